@@ -1,24 +1,124 @@
 use anyhow::Result;
 use async_trait::async_trait;
+use cognitive_kernel::audit::{AuditAction, AuditActor, AuditEvent, AuditOutcome, Auditor};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use tokio::sync::RwLock;
-use tracing::{info, error};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use talkpp_errors::versioning::load_versioned;
+use talkpp_errors::{ErrorKind, Validate, Violations};
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::{Mutex, RwLock};
+use tracing::{info, warn, Instrument};
 use uuid::Uuid;
 
-/// MCP Server Configuration
+use crate::migrations::SERVER_REGISTRY;
+
+mod local;
+pub mod migrations;
+mod validation;
+
+pub use local::{LocalMcpServer, ToolHandler};
+pub use validation::schema_violations;
+
+/// MCP Server Configuration. Build one with [`McpServerConfig::new`]; the hub checks it
+/// with [`Validate`] when it is registered.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct McpServerConfig {
+    /// Generated when left out
+    #[serde(default = "Uuid::new_v4")]
     pub id: Uuid,
     pub name: String,
+    #[serde(default)]
     pub description: String,
     pub server_type: McpServerType,
     pub connection: McpConnection,
     pub capabilities: Vec<McpCapability>,
     pub enabled: bool,
+    /// The time of registration when left out
+    #[serde(default = "chrono::Utc::now")]
     pub created_at: chrono::DateTime<chrono::Utc>,
 }
 
+impl McpServerConfig {
+    /// An enabled server offering tools, local for stdio and Unix socket connections and
+    /// remote otherwise
+    pub fn new(name: impl Into<String>, connection: McpConnection) -> Self {
+        let server_type = match connection {
+            McpConnection::Stdio { .. } | McpConnection::Unix { .. } => McpServerType::Local,
+            McpConnection::Http { .. } | McpConnection::WebSocket { .. } => McpServerType::Remote,
+        };
+        Self {
+            id: Uuid::new_v4(),
+            name: name.into(),
+            description: String::new(),
+            server_type,
+            connection,
+            capabilities: vec![McpCapability::Tools],
+            enabled: true,
+            created_at: chrono::Utc::now(),
+        }
+    }
+
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    pub fn with_server_type(mut self, server_type: McpServerType) -> Self {
+        self.server_type = server_type;
+        self
+    }
+
+    pub fn with_capabilities(mut self, capabilities: Vec<McpCapability>) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Registered without being connected
+    pub fn disabled(mut self) -> Self {
+        self.enabled = false;
+        self
+    }
+}
+
+impl Validate for McpServerConfig {
+    const NAME: &'static str = "MCP server config";
+
+    fn violations(&self) -> Violations {
+        let mut violations = Violations::new();
+        if self.name.trim().is_empty() {
+            violations.push("name", "must not be empty");
+        } else {
+            violations.check(self.name.trim() == self.name, "name", "must not start or end with whitespace");
+        }
+        match &self.connection {
+            McpConnection::Http { url, .. } => violations.check(
+                url.starts_with("http://") || url.starts_with("https://"),
+                "connection.url",
+                "must be an http:// or https:// URL",
+            ),
+            McpConnection::WebSocket { url } => violations.check(
+                url.starts_with("ws://") || url.starts_with("wss://"),
+                "connection.url",
+                "must be a ws:// or wss:// URL",
+            ),
+            McpConnection::Stdio { command, .. } => {
+                violations.check(!command.trim().is_empty(), "connection.command", "must not be empty")
+            }
+            McpConnection::Unix { socket_path } => {
+                violations.check(!socket_path.is_empty(), "connection.socket_path", "must not be empty")
+            }
+        }
+        violations.check(!self.capabilities.is_empty(), "capabilities", "must list at least one capability");
+        violations
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum McpServerType {
     Local,
@@ -35,6 +135,18 @@ pub enum McpConnection {
     Unix { socket_path: String },
 }
 
+impl McpConnection {
+    /// Short transport name, as accepted by `mcp add --transport`
+    pub fn transport(&self) -> &'static str {
+        match self {
+            McpConnection::Http { .. } => "http",
+            McpConnection::WebSocket { .. } => "ws",
+            McpConnection::Stdio { .. } => "stdio",
+            McpConnection::Unix { .. } => "unix",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum McpCapability {
     Tools,
@@ -53,15 +165,64 @@ pub struct McpTool {
     pub server_id: Uuid,
 }
 
+impl McpTool {
+    /// Check call parameters against the tool's input schema before anything is sent
+    pub fn validate_params(&self, params: &serde_json::Value) -> Result<(), McpError> {
+        let violations = schema_violations(&self.input_schema, params);
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(McpError::InvalidParams { tool: self.name.clone(), violations })
+        }
+    }
+}
+
+/// Failures callers may want to tell apart from transport errors
+#[derive(Debug, Error)]
+pub enum McpError {
+    #[error("Server not found: {0}")]
+    ServerNotFound(String),
+
+    #[error("Tool not found: {0}")]
+    ToolNotFound(String),
+
+    #[error("A server named '{0}' is already registered")]
+    DuplicateServer(String),
+
+    #[error("Invalid params for tool '{tool}': {}", violations.join("; "))]
+    InvalidParams { tool: String, violations: Vec<String> },
+}
+
+impl From<McpError> for talkpp_errors::Error {
+    fn from(error: McpError) -> Self {
+        let kind = match &error {
+            McpError::ServerNotFound(_) | McpError::ToolNotFound(_) => ErrorKind::NotFound,
+            McpError::DuplicateServer(_) => ErrorKind::Conflict,
+            McpError::InvalidParams { .. } => ErrorKind::InvalidInput,
+        };
+        Self::transparent(kind, error)
+    }
+}
+
+/// The registry file kept by [`McpHub::with_registry`]; see [`migrations::SERVER_REGISTRY`]
+#[derive(Debug, Serialize, Deserialize)]
+struct RegistryFile {
+    schema_version: u32,
+    servers: Vec<McpServerConfig>,
+}
+
 /// MCP Hub Manager
 pub struct McpHub {
     servers: RwLock<HashMap<Uuid, McpServerConfig>>,
-    connections: RwLock<HashMap<Uuid, Box<dyn McpConnection + Send + Sync>>>,
+    connections: RwLock<HashMap<Uuid, Arc<dyn McpClient + Send + Sync>>>,
     tools: RwLock<HashMap<String, McpTool>>,
+    connect_errors: RwLock<HashMap<Uuid, String>>,
+    registry_path: Option<PathBuf>,
+    auditor: Option<Auditor>,
 }
 
 #[async_trait]
-pub trait McpConnection {
+pub trait McpClient {
     async fn connect(&mut self) -> Result<()>;
     async fn disconnect(&mut self) -> Result<()>;
     async fn call_tool(&self, tool_name: &str, params: serde_json::Value) -> Result<serde_json::Value>;
@@ -69,46 +230,131 @@ pub trait McpConnection {
     async fn is_connected(&self) -> bool;
 }
 
+impl Default for McpHub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl McpHub {
     pub fn new() -> Self {
         Self {
             servers: RwLock::new(HashMap::new()),
             connections: RwLock::new(HashMap::new()),
             tools: RwLock::new(HashMap::new()),
+            connect_errors: RwLock::new(HashMap::new()),
+            registry_path: None,
+            auditor: None,
         }
     }
 
+    /// Audit every tool call
+    pub fn with_auditor(mut self, auditor: Auditor) -> Self {
+        self.auditor = Some(auditor);
+        self
+    }
+
+    /// A hub whose server registrations are persisted as JSON at `path`. Servers already
+    /// in the registry are loaded but not connected.
+    pub fn with_registry(path: impl AsRef<Path>) -> talkpp_errors::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let servers = match std::fs::read_to_string(&path) {
+            Ok(json) => {
+                let invalid = |e: talkpp_errors::Error| e.context(format!("Invalid MCP registry {}", path.display()));
+                let document = serde_json::from_str(&json)
+                    .map_err(|e| talkpp_errors::Error::invalid_input(e.to_string()).with_source(e))
+                    .map_err(invalid)?;
+                load_versioned::<RegistryFile>(document, &SERVER_REGISTRY).map_err(invalid)?.servers
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(anyhow::Error::from(e).into()),
+        };
+
+        let mut hub = Self { registry_path: Some(path), ..Self::new() };
+        *hub.servers.get_mut() = servers.into_iter().map(|config| (config.id, config)).collect();
+        Ok(hub)
+    }
+
     /// Register a new MCP server
-    pub async fn register_server(&self, config: McpServerConfig) -> Result<()> {
-        info!("Registering MCP server: {}", config.name);
-        
+    pub async fn register_server(&self, config: McpServerConfig) -> talkpp_errors::Result<()> {
         let server_id = config.id;
-        
-        // Store the server configuration
-        {
-            let mut servers = self.servers.write().await;
-            servers.insert(server_id, config.clone());
-        }
+        let enabled = config.enabled;
+        self.add_server(config).await?;
 
         // Initialize connection based on server type
-        if config.enabled {
+        if enabled {
             self.connect_server(server_id).await?;
         }
 
         Ok(())
     }
 
+    /// Store a server configuration, persisting the registry, without connecting to it.
+    /// Fails with every violation if the configuration is invalid.
+    pub async fn add_server(&self, config: McpServerConfig) -> talkpp_errors::Result<()> {
+        config.validate()?;
+        info!("Registering MCP server: {}", config.name);
+
+        {
+            let mut servers = self.servers.write().await;
+            if servers.values().any(|s| s.name == config.name && s.id != config.id) {
+                return Err(McpError::DuplicateServer(config.name).into());
+            }
+            servers.insert(config.id, config);
+        }
+
+        Ok(self.save_registry().await?)
+    }
+
+    /// All registered servers, oldest first
+    pub async fn servers(&self) -> Vec<McpServerConfig> {
+        let mut servers: Vec<McpServerConfig> = self.servers.read().await.values().cloned().collect();
+        servers.sort_by_key(|s| s.created_at);
+        servers
+    }
+
+    /// Look a server up by name or id
+    pub async fn find_server(&self, name_or_id: &str) -> Option<McpServerConfig> {
+        let servers = self.servers.read().await;
+        servers.values()
+            .find(|s| s.name == name_or_id || s.id.to_string() == name_or_id)
+            .cloned()
+    }
+
+    async fn save_registry(&self) -> Result<()> {
+        let Some(path) = &self.registry_path else {
+            return Ok(());
+        };
+        let registry = RegistryFile { schema_version: SERVER_REGISTRY.current(), servers: self.servers().await };
+        let json = serde_json::to_string_pretty(&registry)?;
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(path, json).await?;
+        Ok(())
+    }
+
     /// Connect to an MCP server
-    pub async fn connect_server(&self, server_id: Uuid) -> Result<()> {
+    pub async fn connect_server(&self, server_id: Uuid) -> talkpp_errors::Result<()> {
         let config = {
             let servers = self.servers.read().await;
             servers.get(&server_id).cloned()
-                .ok_or_else(|| anyhow::anyhow!("Server not found: {}", server_id))?
+                .ok_or_else(|| McpError::ServerNotFound(server_id.to_string()))?
         };
 
         info!("Connecting to MCP server: {}", config.name);
 
-        let connection: Box<dyn McpConnection + Send + Sync> = match config.connection {
+        let result = self.open_connection(server_id, config.connection).await;
+        let mut connect_errors = self.connect_errors.write().await;
+        match &result {
+            Ok(()) => connect_errors.remove(&server_id),
+            Err(e) => connect_errors.insert(server_id, e.to_string()),
+        };
+        Ok(result?)
+    }
+
+    async fn open_connection(&self, server_id: Uuid, connection: McpConnection) -> Result<()> {
+        let mut client: Box<dyn McpClient + Send + Sync> = match connection {
             McpConnection::Http { url, headers } => {
                 Box::new(HttpMcpConnection::new(url, headers)?)
             },
@@ -122,17 +368,44 @@ impl McpHub {
                 Box::new(UnixMcpConnection::new(socket_path)?)
             },
         };
+        client.connect().await?;
 
         // Store the connection
         {
             let mut connections = self.connections.write().await;
-            connections.insert(server_id, connection);
+            connections.insert(server_id, Arc::from(client));
         }
 
         // Discover and register tools from this server
-        self.discover_tools(server_id).await?;
+        self.discover_tools(server_id).await
+    }
 
-        Ok(())
+    /// Connect every enabled server that is not connected yet. Failures are logged and
+    /// reported through `get_server_status` rather than returned.
+    pub async fn connect_enabled(&self) {
+        for server in self.servers().await {
+            if !server.enabled || self.connections.read().await.contains_key(&server.id) {
+                continue;
+            }
+            if let Err(e) = self.connect_server(server.id).await {
+                warn!("Could not connect to MCP server {}: {}", server.name, e);
+            }
+        }
+    }
+
+    /// Disconnect every server, stopping the processes of stdio servers. Registrations
+    /// stay in the registry.
+    pub async fn shutdown(&self) {
+        let connections: Vec<_> = self.connections.write().await.drain().collect();
+        for (server_id, mut connection) in connections {
+            // A connection still borrowed by an in-flight call is closed when that call drops it
+            if let Some(connection) = Arc::get_mut(&mut connection) {
+                if let Err(e) = connection.disconnect().await {
+                    warn!("Error disconnecting MCP server {}: {}", server_id, e);
+                }
+            }
+        }
+        self.tools.write().await.clear();
     }
 
     /// Discover tools from a connected MCP server
@@ -144,54 +417,89 @@ impl McpHub {
 
         if let Some(conn) = connection {
             let discovered_tools = conn.list_tools().await?;
-            
+            let count = discovered_tools.len();
+
             let mut tools = self.tools.write().await;
-            for tool in discovered_tools {
+            tools.retain(|_, tool| tool.server_id != server_id);
+            for mut tool in discovered_tools {
+                tool.server_id = server_id;
                 let tool_key = format!("{}::{}", server_id, tool.name);
                 tools.insert(tool_key, tool);
             }
-            
-            info!("Discovered {} tools from server {}", tools.len(), server_id);
+
+            info!("Discovered {} tools from server {}", count, server_id);
         }
 
         Ok(())
     }
 
-    /// Execute a tool call
-    pub async fn call_tool(&self, tool_name: &str, params: serde_json::Value) -> Result<serde_json::Value> {
+    /// Execute a tool call. Parameters are checked against the tool's input schema first.
+    pub async fn call_tool(&self, tool_name: &str, params: serde_json::Value) -> talkpp_errors::Result<serde_json::Value> {
+        self.call_tool_as(&AuditActor::system(), tool_name, params).await
+    }
+
+    /// Call a tool on behalf of `actor`, who is recorded in the audit trail
+    pub async fn call_tool_as(&self, actor: &AuditActor, tool_name: &str, params: serde_json::Value) -> talkpp_errors::Result<serde_json::Value> {
+        let audited = self.auditor.as_ref().map(|_| params.clone());
+        let span = tracing::info_span!("mcp_tool_call", tool = %tool_name);
+        let result = self.dispatch_tool(tool_name, params).instrument(span).await;
+        if let (Some(auditor), Some(params)) = (&self.auditor, audited) {
+            auditor.record(AuditEvent::new(
+                actor.clone(),
+                AuditAction::ToolCall,
+                format!("mcp-tool:{}", tool_name),
+                &params,
+                AuditOutcome::of(&result),
+            ));
+        }
+        result
+    }
+
+    async fn dispatch_tool(&self, tool_name: &str, params: serde_json::Value) -> talkpp_errors::Result<serde_json::Value> {
         // Find the tool and its server
-        let (server_id, tool) = {
-            let tools = self.tools.read().await;
-            let tool_entry = tools.iter()
-                .find(|(key, tool)| tool.name == tool_name || key.ends_with(&format!("::{}", tool_name)))
-                .ok_or_else(|| anyhow::anyhow!("Tool not found: {}", tool_name))?;
-            
-            (tool_entry.1.server_id, tool_entry.1.clone())
-        };
+        let tool = self.find_tool(tool_name).await
+            .ok_or_else(|| McpError::ToolNotFound(tool_name.to_string()))?;
+        tool.validate_params(&params)?;
 
         // Get the connection for this server
         let connection = {
             let connections = self.connections.read().await;
-            connections.get(&server_id).cloned()
-                .ok_or_else(|| anyhow::anyhow!("No connection for server: {}", server_id))?
+            connections.get(&tool.server_id).cloned()
+                .ok_or_else(|| talkpp_errors::Error::upstream_unavailable(format!("No connection for server: {}", tool.server_id)))?
         };
 
         // Execute the tool call
-        connection.call_tool(&tool.name, params).await
+        Ok(connection.call_tool(&tool.name, params).await?)
+    }
+
+    /// Find a discovered tool by name
+    pub async fn find_tool(&self, tool_name: &str) -> Option<McpTool> {
+        let tools = self.tools.read().await;
+        tools.iter()
+            .find(|(key, tool)| tool.name == tool_name || key.ends_with(&format!("::{}", tool_name)))
+            .map(|(_, tool)| tool.clone())
     }
 
     /// List all available tools
-    pub async fn list_tools(&self) -> Result<Vec<McpTool>> {
+    pub async fn list_tools(&self) -> talkpp_errors::Result<Vec<McpTool>> {
         let tools = self.tools.read().await;
         Ok(tools.values().cloned().collect())
     }
 
+    /// Tools discovered on one server, by name
+    pub async fn server_tools(&self, server_id: Uuid) -> Vec<McpTool> {
+        let tools = self.tools.read().await;
+        let mut server_tools: Vec<McpTool> = tools.values().filter(|tool| tool.server_id == server_id).cloned().collect();
+        server_tools.sort_by(|a, b| a.name.cmp(&b.name));
+        server_tools
+    }
+
     /// Get server status
-    pub async fn get_server_status(&self, server_id: Uuid) -> Result<McpServerStatus> {
+    pub async fn get_server_status(&self, server_id: Uuid) -> talkpp_errors::Result<McpServerStatus> {
         let config = {
             let servers = self.servers.read().await;
             servers.get(&server_id).cloned()
-                .ok_or_else(|| anyhow::anyhow!("Server not found: {}", server_id))?
+                .ok_or_else(|| McpError::ServerNotFound(server_id.to_string()))?
         };
 
         let is_connected = {
@@ -206,8 +514,11 @@ impl McpHub {
         Ok(McpServerStatus {
             id: server_id,
             name: config.name,
+            transport: config.connection.transport().to_string(),
+            enabled: config.enabled,
             connected: is_connected,
             tools_count: self.get_server_tools_count(server_id).await,
+            last_error: self.connect_errors.read().await.get(&server_id).cloned(),
         })
     }
 
@@ -217,12 +528,61 @@ impl McpHub {
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct McpServerStatus {
     pub id: Uuid,
     pub name: String,
+    pub transport: String,
+    pub enabled: bool,
     pub connected: bool,
     pub tools_count: usize,
+    /// Why the most recent connection attempt failed
+    pub last_error: Option<String>,
+}
+
+/// The `result` of a JSON-RPC response, or its `error` as a failure
+fn rpc_result(response: serde_json::Value) -> Result<serde_json::Value> {
+    if let Some(error) = response.get("error") {
+        let kind = match error.get("code").and_then(|code| code.as_i64()) {
+            Some(local::INVALID_PARAMS) => ErrorKind::InvalidInput,
+            Some(local::METHOD_NOT_FOUND) => ErrorKind::NotFound,
+            _ => ErrorKind::Internal,
+        };
+        return Err(talkpp_errors::Error::new(kind, format!("MCP error: {}", error)).into());
+    }
+
+    Ok(response.get("result").unwrap_or(&serde_json::Value::Null).clone())
+}
+
+/// Parse a `tools/list` result; `server_id` is filled in by the hub
+fn parse_tools(result: &serde_json::Value) -> Result<Vec<McpTool>> {
+    let tools_array = result.get("tools")
+        .and_then(|t| t.as_array())
+        .ok_or_else(|| anyhow::anyhow!("Invalid tools response"))?;
+
+    let tools = tools_array.iter()
+        .filter_map(|tool_json| {
+            let name = tool_json.get("name")?.as_str()?.to_string();
+            let description = tool_json.get("description")?.as_str()?.to_string();
+            let input_schema = tool_json.get("inputSchema")?.clone();
+
+            Some(McpTool {
+                name,
+                description,
+                input_schema,
+                server_id: Uuid::nil(),
+            })
+        })
+        .collect();
+
+    Ok(tools)
+}
+
+fn tool_call_params(tool_name: &str, params: serde_json::Value) -> serde_json::Value {
+    serde_json::json!({
+        "name": tool_name,
+        "arguments": params
+    })
 }
 
 // Connection implementations
@@ -237,10 +597,29 @@ impl HttpMcpConnection {
         let client = reqwest::Client::new();
         Ok(Self { url, client, headers })
     }
+
+    #[tracing::instrument(name = "mcp_http_request", skip(self, params), fields(url = %self.url))]
+    async fn request(&self, method: &str, params: serde_json::Value) -> Result<serde_json::Value> {
+        let request_body = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params
+        });
+
+        let mut request = self.client.post(&self.url).json(&request_body);
+
+        for (key, value) in self.headers.iter().chain(&cognitive_kernel::telemetry::trace_headers()) {
+            request = request.header(key, value);
+        }
+
+        let response = request.send().await?.error_for_status()?;
+        rpc_result(response.json().await?)
+    }
 }
 
 #[async_trait]
-impl McpConnection for HttpMcpConnection {
+impl McpClient for HttpMcpConnection {
     async fn connect(&mut self) -> Result<()> {
         // HTTP connections are stateless
         Ok(())
@@ -252,73 +631,11 @@ impl McpConnection for HttpMcpConnection {
     }
 
     async fn call_tool(&self, tool_name: &str, params: serde_json::Value) -> Result<serde_json::Value> {
-        let request_body = serde_json::json!({
-            "jsonrpc": "2.0",
-            "id": 1,
-            "method": "tools/call",
-            "params": {
-                "name": tool_name,
-                "arguments": params
-            }
-        });
-
-        let mut request = self.client.post(&self.url).json(&request_body);
-        
-        for (key, value) in &self.headers {
-            request = request.header(key, value);
-        }
-
-        let response = request.send().await?;
-        let result: serde_json::Value = response.json().await?;
-        
-        if let Some(error) = result.get("error") {
-            return Err(anyhow::anyhow!("MCP error: {}", error));
-        }
-
-        Ok(result.get("result").unwrap_or(&serde_json::Value::Null).clone())
+        self.request("tools/call", tool_call_params(tool_name, params)).await
     }
 
     async fn list_tools(&self) -> Result<Vec<McpTool>> {
-        let request_body = serde_json::json!({
-            "jsonrpc": "2.0",
-            "id": 1,
-            "method": "tools/list"
-        });
-
-        let mut request = self.client.post(&self.url).json(&request_body);
-        
-        for (key, value) in &self.headers {
-            request = request.header(key, value);
-        }
-
-        let response = request.send().await?;
-        let result: serde_json::Value = response.json().await?;
-        
-        if let Some(error) = result.get("error") {
-            return Err(anyhow::anyhow!("MCP error: {}", error));
-        }
-
-        // Parse tools from response
-        let tools_array = result.get("result").and_then(|r| r.get("tools"))
-            .and_then(|t| t.as_array())
-            .ok_or_else(|| anyhow::anyhow!("Invalid tools response"))?;
-
-        let tools = tools_array.iter()
-            .filter_map(|tool_json| {
-                let name = tool_json.get("name")?.as_str()?.to_string();
-                let description = tool_json.get("description")?.as_str()?.to_string();
-                let input_schema = tool_json.get("inputSchema")?.clone();
-                
-                Some(McpTool {
-                    name,
-                    description,
-                    input_schema,
-                    server_id: Uuid::new_v4(), // This should be set by the hub
-                })
-            })
-            .collect();
-
-        Ok(tools)
+        parse_tools(&self.request("tools/list", serde_json::json!({})).await?)
     }
 
     async fn is_connected(&self) -> bool {
@@ -339,10 +656,9 @@ impl WebSocketMcpConnection {
 }
 
 #[async_trait]
-impl McpConnection for WebSocketMcpConnection {
+impl McpClient for WebSocketMcpConnection {
     async fn connect(&mut self) -> Result<()> {
-        // WebSocket connection implementation
-        todo!("WebSocket MCP connection implementation")
+        Err(anyhow::anyhow!("WebSocket MCP transport is not supported yet ({})", self.url))
     }
 
     async fn disconnect(&mut self) -> Result<()> {
@@ -362,38 +678,101 @@ impl McpConnection for WebSocketMcpConnection {
     }
 }
 
+/// Speaks newline-delimited JSON-RPC with a child process over its stdin and stdout
 pub struct StdioMcpConnection {
     command: String,
     args: Vec<String>,
+    process: Mutex<Option<StdioProcess>>,
+    next_id: AtomicU64,
+}
+
+struct StdioProcess {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
 }
 
 impl StdioMcpConnection {
     pub fn new(command: String, args: Vec<String>) -> Result<Self> {
-        Ok(Self { command, args })
+        Ok(Self { command, args, process: Mutex::new(None), next_id: AtomicU64::new(1) })
+    }
+
+    async fn request(&self, method: &str, params: serde_json::Value) -> Result<serde_json::Value> {
+        let mut process = self.process.lock().await;
+        let process = process.as_mut()
+            .ok_or_else(|| anyhow::anyhow!("Not connected to {}", self.command))?;
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params
+        });
+        process.stdin.write_all(format!("{}\n", request).as_bytes()).await?;
+        process.stdin.flush().await?;
+
+        // Skip notifications and anything else that is not our response
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if process.stdout.read_line(&mut line).await? == 0 {
+                return Err(anyhow::anyhow!("MCP server {} closed its output", self.command));
+            }
+            let Ok(message) = serde_json::from_str::<serde_json::Value>(&line) else {
+                continue;
+            };
+            if message.get("id").and_then(|i| i.as_u64()) == Some(id) {
+                return rpc_result(message);
+            }
+        }
     }
 }
 
 #[async_trait]
-impl McpConnection for StdioMcpConnection {
+impl McpClient for StdioMcpConnection {
     async fn connect(&mut self) -> Result<()> {
-        // Stdio connection implementation
-        todo!("Stdio MCP connection implementation")
+        let mut child = Command::new(&self.command)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| anyhow::anyhow!("Failed to start {}: {}", self.command, e))?;
+
+        let stdin = child.stdin.take().ok_or_else(|| anyhow::anyhow!("No stdin for {}", self.command))?;
+        let stdout = child.stdout.take().ok_or_else(|| anyhow::anyhow!("No stdout for {}", self.command))?;
+        *self.process.get_mut() = Some(StdioProcess { child, stdin, stdout: BufReader::new(stdout) });
+
+        self.request("initialize", serde_json::json!({
+            "protocolVersion": local::PROTOCOL_VERSION,
+            "clientInfo": { "name": "talkpp-mcp-hub", "version": env!("CARGO_PKG_VERSION") },
+            "capabilities": {}
+        })).await?;
+        Ok(())
     }
 
     async fn disconnect(&mut self) -> Result<()> {
-        todo!("Stdio disconnect implementation")
+        if let Some(mut process) = self.process.get_mut().take() {
+            process.child.kill().await?;
+        }
+        Ok(())
     }
 
-    async fn call_tool(&self, _tool_name: &str, _params: serde_json::Value) -> Result<serde_json::Value> {
-        todo!("Stdio tool call implementation")
+    async fn call_tool(&self, tool_name: &str, params: serde_json::Value) -> Result<serde_json::Value> {
+        self.request("tools/call", tool_call_params(tool_name, params)).await
     }
 
     async fn list_tools(&self) -> Result<Vec<McpTool>> {
-        todo!("Stdio list tools implementation")
+        parse_tools(&self.request("tools/list", serde_json::json!({})).await?)
     }
 
     async fn is_connected(&self) -> bool {
-        false // TODO: Implement actual connection status
+        match self.process.lock().await.as_mut() {
+            Some(process) => matches!(process.child.try_wait(), Ok(None)),
+            None => false,
+        }
     }
 }
 
@@ -408,10 +787,9 @@ impl UnixMcpConnection {
 }
 
 #[async_trait]
-impl McpConnection for UnixMcpConnection {
+impl McpClient for UnixMcpConnection {
     async fn connect(&mut self) -> Result<()> {
-        // Unix socket connection implementation
-        todo!("Unix socket MCP connection implementation")
+        Err(anyhow::anyhow!("Unix socket MCP transport is not supported yet ({})", self.socket_path))
     }
 
     async fn disconnect(&mut self) -> Result<()> {
@@ -429,4 +807,168 @@ impl McpConnection for UnixMcpConnection {
     async fn is_connected(&self) -> bool {
         false // TODO: Implement actual connection status
     }
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn http_server(name: &str, url: String) -> McpServerConfig {
+        McpServerConfig::new(name, McpConnection::Http { url, headers: HashMap::new() })
+    }
+
+    #[tokio::test]
+    async fn test_registry_persists_and_tools_are_validated() {
+        let server = LocalMcpServer::new("calc").with_tool(
+            "add",
+            "Add two numbers",
+            json!({"type": "object", "properties": {"a": {"type": "number"}, "b": {"type": "number"}}, "required": ["a", "b"]}),
+            |params| async move { Ok(json!(params["a"].as_f64().unwrap_or(0.0) + params["b"].as_f64().unwrap_or(0.0))) },
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(server.serve(listener));
+
+        let dir = std::env::temp_dir().join(format!("mcp-hub-{}", Uuid::new_v4()));
+        let registry = dir.join("servers.json");
+        let hub = McpHub::with_registry(&registry).unwrap();
+        let config = http_server("calc", url.clone());
+        let server_id = config.id;
+        hub.register_server(config).await.unwrap();
+        let duplicate = hub.add_server(http_server("calc", url)).await.unwrap_err();
+        assert_eq!(duplicate.kind(), ErrorKind::Conflict);
+        assert!(matches!(duplicate.downcast_ref::<McpError>(), Some(McpError::DuplicateServer(_))));
+
+        let reloaded = McpHub::with_registry(&registry).unwrap();
+        assert_eq!(reloaded.find_server("calc").await.map(|s| s.id), Some(server_id));
+        assert_eq!(reloaded.get_server_status(server_id).await.unwrap().tools_count, 0);
+        reloaded.connect_enabled().await;
+
+        let status = reloaded.get_server_status(server_id).await.unwrap();
+        assert!(status.connected && status.last_error.is_none());
+        assert_eq!(status.tools_count, 1);
+        assert_eq!(reloaded.server_tools(server_id).await[0].server_id, server_id);
+        assert_eq!(reloaded.call_tool("add", json!({"a": 2, "b": 3})).await.unwrap(), json!(5.0));
+
+        let invalid = reloaded.call_tool("add", json!({"a": "2"})).await.unwrap_err();
+        assert_eq!(invalid.kind(), ErrorKind::InvalidInput);
+        match invalid.downcast_ref::<McpError>() {
+            Some(McpError::InvalidParams { violations, .. }) => assert_eq!(violations.len(), 2),
+            other => panic!("expected invalid params, got {:?}", other),
+        }
+
+        assert_eq!(reloaded.call_tool("subtract", json!({})).await.unwrap_err().kind(), ErrorKind::NotFound);
+        assert_eq!(reloaded.get_server_status(Uuid::new_v4()).await.unwrap_err().kind(), ErrorKind::NotFound);
+
+        reloaded.shutdown().await;
+        let status = reloaded.get_server_status(server_id).await.unwrap();
+        assert!(!status.connected);
+        assert_eq!(status.tools_count, 0);
+        assert!(reloaded.find_server("calc").await.is_some());
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_tool_calls_are_audited_with_credentials_redacted() {
+        let server = LocalMcpServer::new("mail").with_tool(
+            "send",
+            "Send an email",
+            json!({"type": "object", "properties": {"to": {"type": "string"}, "smtp_password": {"type": "string"}}}),
+            |_| async move { Ok(json!("sent")) },
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(server.serve(listener));
+
+        let path = std::env::temp_dir().join(format!("mcp-audit-{}.jsonl", Uuid::new_v4()));
+        let sink = Arc::new(cognitive_kernel::JsonlAuditSink::new(&path));
+        let auditor = Auditor::spawn(sink, 16);
+        let hub = McpHub::new().with_auditor(auditor.clone());
+        hub.register_server(http_server("mail", url)).await.unwrap();
+
+        let actor = AuditActor::user("alice", "session-1");
+        let params = json!({"to": "ops@example.com", "smtp_password": "hunter2"});
+        hub.call_tool_as(&actor, "send", params).await.unwrap();
+        assert!(hub.call_tool_as(&actor, "missing", json!({})).await.is_err());
+        auditor.flush().await;
+
+        let mut events = auditor.query(&Default::default()).await.unwrap();
+        events.sort_by_key(|event| event.timestamp);
+        assert_eq!(events.len(), 2);
+        assert_eq!((events[0].actor.clone(), events[0].target.as_str()), (actor, "mcp-tool:send"));
+        assert_eq!(events[0].parameters_digest, json!({"to": "ops@example.com", "smtp_password": "[REDACTED]"}));
+        assert_eq!(events[0].outcome, AuditOutcome::Success);
+        assert!(matches!(&events[1].outcome, AuditOutcome::Failure { error } if error.contains("missing")));
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("hunter2"));
+        std::fs::remove_file(path).ok();
+    }
+
+    #[tokio::test]
+    async fn test_failed_connections_are_reported_in_status() {
+        let hub = McpHub::new();
+        let mut config = http_server("later", String::new());
+        config.connection = McpConnection::WebSocket { url: "ws://localhost:1".to_string() };
+        let server_id = config.id;
+        hub.add_server(config).await.unwrap();
+        hub.connect_enabled().await;
+
+        let status = hub.get_server_status(server_id).await.unwrap();
+        assert_eq!(status.transport, "ws");
+        assert!(!status.connected);
+        assert!(status.last_error.unwrap().contains("not supported"));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_configs_are_rejected_with_every_violation() {
+        let http = |url: &str| McpConnection::Http { url: url.to_string(), headers: HashMap::new() };
+        let cases: Vec<(McpServerConfig, McpServerConfig, &str)> = vec![
+            (McpServerConfig::new("", http("http://a")), McpServerConfig::new("calc", http("http://a")), "name"),
+            (McpServerConfig::new(" calc", http("http://a")), McpServerConfig::new("calc", http("http://a")), "name"),
+            (McpServerConfig::new("calc", http("localhost:80")), McpServerConfig::new("calc", http("https://a")), "connection.url"),
+            (
+                McpServerConfig::new("calc", McpConnection::WebSocket { url: "http://a".to_string() }),
+                McpServerConfig::new("calc", McpConnection::WebSocket { url: "wss://a".to_string() }),
+                "connection.url",
+            ),
+            (
+                McpServerConfig::new("calc", McpConnection::Stdio { command: " ".to_string(), args: vec![] }),
+                McpServerConfig::new("calc", McpConnection::Stdio { command: "calc-mcp".to_string(), args: vec![] }),
+                "connection.command",
+            ),
+            (
+                McpServerConfig::new("calc", McpConnection::Unix { socket_path: String::new() }),
+                McpServerConfig::new("calc", McpConnection::Unix { socket_path: "/run/calc.sock".to_string() }),
+                "connection.socket_path",
+            ),
+            (
+                McpServerConfig::new("calc", http("http://a")).with_capabilities(vec![]),
+                McpServerConfig::new("calc", http("http://a")).with_capabilities(vec![McpCapability::Resources]),
+                "capabilities",
+            ),
+        ];
+        for (failing, passing, field) in cases {
+            assert_eq!(failing.violations().fields(), [field], "{:?}", failing);
+            assert!(passing.validate().is_ok(), "{:?}", passing);
+        }
+
+        // Every violation at once, before anything is stored
+        let hub = McpHub::new();
+        let err = hub.add_server(McpServerConfig::new("", http("ftp://a")).with_capabilities(vec![])).await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        let invalid = err.downcast_ref::<talkpp_errors::InvalidConfig>().unwrap();
+        let fields: Vec<_> = invalid.violations.iter().map(|v| v.field.as_str()).collect();
+        assert_eq!(fields, ["name", "connection.url", "capabilities"]);
+        assert!(hub.servers().await.is_empty());
+
+        // Ids and timestamps are generated when a client leaves them out
+        let config: McpServerConfig = serde_json::from_value(json!({
+            "name": "calc",
+            "server_type": "Remote",
+            "connection": {"Http": {"url": "http://a", "headers": {}}},
+            "capabilities": ["Tools"],
+            "enabled": true,
+        })).unwrap();
+        assert!(!config.id.is_nil() && config.description.is_empty());
+    }
+}
@@ -0,0 +1,147 @@
+use anyhow::Result;
+use axum::{http::HeaderMap, routing::post, Json, Router};
+use cognitive_kernel::telemetry::{self, TRACEPARENT};
+use futures::future::BoxFuture;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tracing::Instrument;
+use uuid::Uuid;
+
+use crate::McpTool;
+
+/// MCP protocol revision spoken by `LocalMcpServer` and requested by the stdio client
+pub(crate) const PROTOCOL_VERSION: &str = "2024-11-05";
+
+pub(crate) const INVALID_PARAMS: i64 = -32602;
+pub(crate) const METHOD_NOT_FOUND: i64 = -32601;
+const TOOL_FAILED: i64 = -32000;
+
+pub type ToolHandler = Arc<dyn Fn(Value) -> BoxFuture<'static, Result<Value>> + Send + Sync>;
+
+/// An MCP server whose tools are async functions in the current process.
+///
+/// `handle` answers a single JSON-RPC request, and `serve` exposes the same over HTTP so
+/// an `HttpMcpConnection` (or the CLI) can reach it without any external server.
+#[derive(Clone)]
+pub struct LocalMcpServer {
+    name: String,
+    tools: BTreeMap<String, (McpTool, ToolHandler)>,
+}
+
+impl LocalMcpServer {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into(), tools: BTreeMap::new() }
+    }
+
+    pub fn with_tool<F, Fut>(mut self, name: &str, description: &str, input_schema: Value, handler: F) -> Self
+    where
+        F: Fn(Value) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Value>> + Send + 'static,
+    {
+        let tool = McpTool {
+            name: name.to_string(),
+            description: description.to_string(),
+            input_schema,
+            server_id: Uuid::nil(),
+        };
+        let handler: ToolHandler = Arc::new(move |params| Box::pin(handler(params)));
+        self.tools.insert(name.to_string(), (tool, handler));
+        self
+    }
+
+    pub fn tools(&self) -> Vec<McpTool> {
+        self.tools.values().map(|(tool, _)| tool.clone()).collect()
+    }
+
+    /// Answer one JSON-RPC request
+    pub async fn handle(&self, request: Value) -> Value {
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let params = request.get("params").cloned().unwrap_or(Value::Null);
+        let outcome = match request.get("method").and_then(Value::as_str).unwrap_or_default() {
+            "initialize" => Ok(json!({
+                "protocolVersion": PROTOCOL_VERSION,
+                "serverInfo": { "name": self.name, "version": env!("CARGO_PKG_VERSION") },
+                "capabilities": { "tools": {} }
+            })),
+            "tools/list" => Ok(json!({
+                "tools": self.tools.values().map(|(tool, _)| json!({
+                    "name": tool.name,
+                    "description": tool.description,
+                    "inputSchema": tool.input_schema
+                })).collect::<Vec<_>>()
+            })),
+            "tools/call" => self.call(params).await,
+            method => Err((METHOD_NOT_FOUND, format!("Method not found: {}", method))),
+        };
+
+        match outcome {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } }),
+        }
+    }
+
+    async fn call(&self, params: Value) -> Result<Value, (i64, String)> {
+        let name = params.get("name").and_then(Value::as_str).unwrap_or_default();
+        let (tool, handler) = self.tools.get(name)
+            .ok_or_else(|| (INVALID_PARAMS, format!("Unknown tool: {}", name)))?;
+        let arguments = params.get("arguments").cloned().unwrap_or_else(|| json!({}));
+        tool.validate_params(&arguments).map_err(|e| (INVALID_PARAMS, e.to_string()))?;
+        handler(arguments).await.map_err(|e| (TOOL_FAILED, e.to_string()))
+    }
+
+    /// HTTP endpoint accepting JSON-RPC requests at `/`, each handled in a span parented
+    /// on the request's `traceparent` header
+    pub fn router(self) -> Router {
+        let server = Arc::new(self);
+        Router::new().route("/", post(move |headers: HeaderMap, Json(request): Json<Value>| async move {
+            let method = request.get("method").and_then(Value::as_str).unwrap_or_default();
+            let span = tracing::info_span!("mcp_serve", server = %server.name, method = %method);
+            telemetry::set_parent(&span, headers.get(TRACEPARENT).and_then(|value| value.to_str().ok()));
+            Json(server.handle(request).instrument(span).await)
+        }))
+    }
+
+    /// Serve on `listener` until the task is dropped
+    pub async fn serve(self, listener: TcpListener) -> Result<()> {
+        axum::serve(listener, self.router()).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_handles_tool_calls_and_rejects_bad_requests() {
+        let server = LocalMcpServer::new("echo").with_tool(
+            "echo",
+            "Return the message",
+            json!({"type": "object", "properties": {"message": {"type": "string"}}, "required": ["message"]}),
+            |params| async move {
+                match params["message"].as_str() {
+                    Some("fail") => Err(anyhow::anyhow!("asked to fail")),
+                    _ => Ok(params["message"].clone()),
+                }
+            },
+        );
+
+        let list = server.handle(json!({"jsonrpc": "2.0", "id": 1, "method": "tools/list"})).await;
+        assert_eq!(list["result"]["tools"][0]["name"], "echo");
+        assert_eq!(list["result"]["tools"][0]["inputSchema"]["required"][0], "message");
+
+        let call = |arguments: Value| server.handle(json!({
+            "jsonrpc": "2.0", "id": 2, "method": "tools/call", "params": {"name": "echo", "arguments": arguments}
+        }));
+        assert_eq!(call(json!({"message": "hi"})).await["result"], "hi");
+        assert_eq!(call(json!({})).await["error"]["code"], INVALID_PARAMS);
+        assert_eq!(call(json!({"message": "fail"})).await["error"]["message"], "asked to fail");
+
+        let unknown = server.handle(json!({"jsonrpc": "2.0", "id": 3, "method": "resources/list"})).await;
+        assert_eq!(unknown["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(unknown["id"], 3);
+    }
+}
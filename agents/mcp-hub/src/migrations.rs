@@ -0,0 +1,60 @@
+//! Upgrades of the documents this crate persists; see [`talkpp_errors::versioning`]
+//!
+//! Changing the serialized form of a type listed here means appending a step to its
+//! list. The frozen payloads in `tests/fixtures/schemas` must keep loading.
+
+use serde_json::{json, Value};
+use talkpp_errors::versioning::Migrations;
+use talkpp_errors::Result;
+
+/// The registry file written by [`crate::McpHub::with_registry`]
+pub const SERVER_REGISTRY: Migrations = Migrations::new("MCP server registry", &[wrap_servers]);
+
+/// Version 2 moved the bare array of servers under `servers`, making room for the version
+fn wrap_servers(registry: Value) -> Result<Value> {
+    match registry {
+        Value::Array(servers) => Ok(json!({ "servers": servers })),
+        _ => Err(talkpp_errors::Error::invalid_input("expected an array of servers")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{McpConnection, McpHub, McpServerConfig, RegistryFile};
+    use std::path::Path;
+    use talkpp_errors::versioning::check_round_trip;
+    use uuid::Uuid;
+
+    fn fixture(name: &str) -> Value {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/schemas").join(name);
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_frozen_payloads_still_load() {
+        check_round_trip::<RegistryFile>(fixture("server_registry.v1.json"), &SERVER_REGISTRY).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_old_registry_is_read_and_rewritten_at_the_current_version() {
+        let dir = std::env::temp_dir().join(format!("mcp-hub-versions-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("servers.json");
+        std::fs::write(&path, fixture("server_registry.v1.json").to_string()).unwrap();
+
+        let hub = McpHub::with_registry(&path).unwrap();
+        assert!(!hub.find_server("calc").await.unwrap().enabled);
+        let search = McpServerConfig::new("search", McpConnection::WebSocket { url: "ws://127.0.0.1:9/".to_string() });
+        hub.add_server(search).await.unwrap();
+
+        let written: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written["schema_version"], SERVER_REGISTRY.current());
+        assert_eq!(written["servers"].as_array().map(Vec::len), Some(3));
+
+        std::fs::write(&path, json!({"schema_version": 9, "servers": []}).to_string()).unwrap();
+        let err = McpHub::with_registry(&path).err().expect("a registry from a newer build is refused");
+        assert!(err.to_string().contains("unknown future schema version 9"), "{}", err);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,152 @@
+use serde_json::Value;
+
+/// Check `value` against the subset of JSON Schema MCP tools use in practice: `type`,
+/// `properties`, `required`, `additionalProperties: false`, `enum`, `items`, and the
+/// numeric and length bounds. Returns one message per violation, prefixed with the
+/// JSON pointer of the offending value; unknown keywords are ignored.
+pub fn schema_violations(schema: &Value, value: &Value) -> Vec<String> {
+    let mut violations = Vec::new();
+    check(schema, value, "", &mut violations);
+    violations
+}
+
+fn check(schema: &Value, value: &Value, path: &str, violations: &mut Vec<String>) {
+    let Some(schema) = schema.as_object() else {
+        return;
+    };
+    let at = if path.is_empty() { "/" } else { path };
+
+    if let Some(expected) = schema.get("type") {
+        let allowed: Vec<&str> = match expected {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !allowed.is_empty() && !allowed.iter().any(|t| has_type(value, t)) {
+            violations.push(format!("{}: expected {}, found {}", at, allowed.join(" or "), type_name(value)));
+            return;
+        }
+    }
+
+    if let Some(options) = schema.get("enum").and_then(Value::as_array) {
+        if !options.contains(value) {
+            let options: Vec<String> = options.iter().map(Value::to_string).collect();
+            violations.push(format!("{}: must be one of {}", at, options.join(", ")));
+        }
+    }
+
+    match value {
+        Value::Object(fields) => {
+            for name in schema.get("required").and_then(Value::as_array).into_iter().flatten().filter_map(Value::as_str) {
+                if !fields.contains_key(name) {
+                    violations.push(format!("{}: missing required property '{}'", at, name));
+                }
+            }
+            let properties = schema.get("properties").and_then(Value::as_object);
+            for (name, field) in fields {
+                match properties.and_then(|p| p.get(name)) {
+                    Some(field_schema) => check(field_schema, field, &format!("{}/{}", path, name), violations),
+                    None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                        violations.push(format!("{}: unexpected property '{}'", at, name));
+                    }
+                    None => {}
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    check(item_schema, item, &format!("{}/{}", path, i), violations);
+                }
+            }
+            check_bounds(schema, items.len() as f64, "minItems", "maxItems", "items", at, violations);
+        }
+        Value::String(s) => {
+            check_bounds(schema, s.chars().count() as f64, "minLength", "maxLength", "characters", at, violations);
+        }
+        Value::Number(n) => {
+            let n = n.as_f64().unwrap_or_default();
+            if let Some(min) = schema.get("minimum").and_then(Value::as_f64).filter(|min| n < *min) {
+                violations.push(format!("{}: must be at least {}", at, min));
+            }
+            if let Some(max) = schema.get("maximum").and_then(Value::as_f64).filter(|max| n > *max) {
+                violations.push(format!("{}: must be at most {}", at, max));
+            }
+        }
+        _ => {}
+    }
+}
+
+fn check_bounds(
+    schema: &serde_json::Map<String, Value>,
+    len: f64,
+    min_key: &str,
+    max_key: &str,
+    unit: &str,
+    at: &str,
+    violations: &mut Vec<String>,
+) {
+    if let Some(min) = schema.get(min_key).and_then(Value::as_f64).filter(|min| len < *min) {
+        violations.push(format!("{}: must have at least {} {}", at, min, unit));
+    }
+    if let Some(max) = schema.get(max_key).and_then(Value::as_f64).filter(|max| len > *max) {
+        violations.push(format!("{}: must have at most {} {}", at, max, unit));
+    }
+}
+
+fn has_type(value: &Value, expected: &str) -> bool {
+    match expected {
+        "integer" => value.as_i64().is_some() || value.as_u64().is_some(),
+        "number" => value.is_number(),
+        other => type_name(value) == other,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_reports_each_violation_with_its_path() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "query": {"type": "string", "minLength": 1},
+                "limit": {"type": "integer", "minimum": 1, "maximum": 50},
+                "sort": {"enum": ["asc", "desc"]},
+                "tags": {"type": "array", "items": {"type": "string"}}
+            },
+            "required": ["query"],
+            "additionalProperties": false
+        });
+
+        assert!(schema_violations(&schema, &json!({"query": "rust", "limit": 10, "tags": ["a"]})).is_empty());
+        assert_eq!(
+            schema_violations(&schema, &json!({"limit": 2.5, "sort": "up", "tags": ["a", 3], "extra": true})),
+            vec![
+                "/: missing required property 'query'",
+                "/: unexpected property 'extra'",
+                "/limit: expected integer, found number",
+                "/sort: must be one of \"asc\", \"desc\"",
+                "/tags/1: expected string, found number",
+            ]
+        );
+        assert_eq!(schema_violations(&schema, &json!({"query": "", "limit": 99})), vec![
+            "/limit: must be at most 50",
+            "/query: must have at least 1 characters",
+        ]);
+        assert_eq!(schema_violations(&schema, &json!([])), vec!["/: expected object, found array"]);
+    }
+}
@@ -0,0 +1,226 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde::Deserialize;
+use talkpp_model_traits::LanguageModel;
+use tokio::sync::mpsc;
+use tracing::warn;
+
+/// Prefix of model paths that name an Ollama model, as in `ollama:llama3`
+pub const OLLAMA_MODEL_PREFIX: &str = "ollama:";
+
+/// Generated pieces buffered ahead of a slow stream reader
+const STREAM_BUFFER: usize = 100;
+
+/// One model served by Ollama, behind the interface the CUDA processor loads models by
+pub struct OllamaLanguageModel {
+    http: reqwest::Client,
+    base_url: String,
+    model: String,
+}
+
+/// A line of Ollama's `/api/generate` response
+#[derive(Debug, Deserialize)]
+struct GenerateChunk {
+    #[serde(default)]
+    response: String,
+    #[serde(default)]
+    done: bool,
+    error: Option<String>,
+}
+
+impl OllamaLanguageModel {
+    /// Generate with `model` on the Ollama server at `base_url`, or at
+    /// `http://localhost:11434` without one
+    pub fn new(model: impl Into<String>, base_url: Option<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: base_url.unwrap_or_else(|| "http://localhost:11434".to_string()),
+            model: model.into(),
+        }
+    }
+
+    /// The model a path such as `ollama:llama3` names, or `None` for other paths
+    pub fn from_model_path(model_path: &str, base_url: Option<String>) -> Option<Self> {
+        model_path.strip_prefix(OLLAMA_MODEL_PREFIX).map(|model| Self::new(model, base_url))
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn request(&self, prompt: &str, max_tokens: usize, stream: bool) -> Result<reqwest::Response> {
+        let response = self.http
+            .post(format!("{}/api/generate", self.base_url.trim_end_matches('/')))
+            .json(&serde_json::json!({
+                "model": self.model,
+                "prompt": prompt,
+                "stream": stream,
+                "options": { "num_predict": max_tokens },
+            }))
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("Ollama generation failed: {}", e))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            anyhow::bail!("Ollama generation with {} failed ({}): {}", self.model, status, body);
+        }
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl LanguageModel for OllamaLanguageModel {
+    async fn generate(&self, prompt: &str, max_tokens: usize) -> Result<String> {
+        let chunk: GenerateChunk = self.request(prompt, max_tokens, false).await?.json().await?;
+        match chunk.error {
+            Some(error) => Err(anyhow::anyhow!("Ollama generation with {} failed: {}", self.model, error)),
+            None => Ok(chunk.response),
+        }
+    }
+
+    /// Each piece Ollama streams is passed on as it arrives. Dropping the receiver closes
+    /// the connection, which stops Ollama generating.
+    async fn generate_stream(&self, prompt: &str, max_tokens: usize) -> Result<mpsc::Receiver<String>> {
+        let response = self.request(prompt, max_tokens, true).await?;
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+        let model = self.model.clone();
+
+        tokio::spawn(async move {
+            let mut body = response.bytes_stream();
+            let mut buffer = Vec::new();
+            loop {
+                let bytes = tokio::select! {
+                    bytes = body.next() => bytes,
+                    _ = tx.closed() => return,
+                };
+                let bytes = match bytes {
+                    Some(Ok(bytes)) => bytes,
+                    Some(Err(e)) => {
+                        warn!("Ollama stream from {} broke off: {}", model, e);
+                        return;
+                    }
+                    None => return,
+                };
+
+                buffer.extend_from_slice(&bytes);
+                while let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+                    let line: Vec<u8> = buffer.drain(..=end).collect();
+                    if line.iter().all(u8::is_ascii_whitespace) {
+                        continue;
+                    }
+                    let chunk: GenerateChunk = match serde_json::from_slice(&line) {
+                        Ok(chunk) => chunk,
+                        Err(e) => {
+                            warn!("Unreadable line in Ollama stream from {}: {}", model, e);
+                            return;
+                        }
+                    };
+                    if let Some(error) = chunk.error {
+                        warn!("Ollama generation with {} failed: {}", model, error);
+                        return;
+                    }
+                    if !chunk.response.is_empty() && tx.send(chunk.response).await.is_err() {
+                        return;
+                    }
+                    if chunk.done {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(rx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::testing::read_request;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+    use tokio::sync::oneshot;
+
+    /// An Ollama stand-in that streams `pieces` one per `interval` for a single request.
+    /// Reports the request body, then whether every piece was written before the client
+    /// went away.
+    async fn mock_ollama(
+        pieces: Vec<&'static str>,
+        interval: Duration,
+    ) -> (String, oneshot::Receiver<serde_json::Value>, oneshot::Receiver<bool>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (request_tx, request_rx) = oneshot::channel();
+        let (finished_tx, finished_rx) = oneshot::channel();
+
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            request_tx.send(read_request(&mut socket).await.unwrap()).unwrap();
+            let head = "HTTP/1.1 200 OK\r\ncontent-type: application/x-ndjson\r\ntransfer-encoding: chunked\r\n\r\n";
+            socket.write_all(head.as_bytes()).await.unwrap();
+
+            let lines = pieces.iter()
+                .map(|piece| serde_json::json!({"model": "llama3", "response": piece, "done": false}))
+                .chain([serde_json::json!({"model": "llama3", "response": "", "done": true})]);
+            for line in lines {
+                let line = format!("{}\n", line);
+                let chunk = format!("{:x}\r\n{}\r\n", line.len(), line);
+                if socket.write_all(chunk.as_bytes()).await.is_err() || socket.flush().await.is_err() {
+                    finished_tx.send(false).ok();
+                    return;
+                }
+                tokio::time::sleep(interval).await;
+            }
+            let finished = socket.write_all(b"0\r\n\r\n").await.is_ok();
+            finished_tx.send(finished).ok();
+        });
+
+        (url, request_rx, finished_rx)
+    }
+
+    #[tokio::test]
+    async fn test_stream_passes_chunks_through() {
+        let (url, request, finished) = mock_ollama(vec!["Hello", ", ", "world"], Duration::from_millis(5)).await;
+        let model = OllamaLanguageModel::from_model_path("ollama:llama3", Some(url)).unwrap();
+        assert_eq!(model.model(), "llama3");
+
+        let mut rx = model.generate_stream("Say hello", 64).await.unwrap();
+        let mut pieces = Vec::new();
+        while let Some(piece) = rx.recv().await {
+            pieces.push(piece);
+        }
+        assert_eq!(pieces, vec!["Hello", ", ", "world"]);
+        assert!(finished.await.unwrap());
+
+        let request = request.await.unwrap();
+        assert_eq!(request["model"], "llama3");
+        assert_eq!(request["prompt"], "Say hello");
+        assert_eq!(request["stream"], true);
+        assert_eq!(request["options"]["num_predict"], 64);
+    }
+
+    #[tokio::test]
+    async fn test_dropping_the_receiver_cancels_generation() {
+        let pieces = vec!["token "; 500];
+        let (url, _request, finished) = mock_ollama(pieces, Duration::from_millis(10)).await;
+        let model = OllamaLanguageModel::new("llama3", Some(url));
+
+        let mut rx = model.generate_stream("Count forever", 500).await.unwrap();
+        assert_eq!(rx.recv().await.unwrap(), "token ");
+        drop(rx);
+
+        // The connection is closed long before the 5 seconds all pieces would take
+        let finished = tokio::time::timeout(Duration::from_secs(2), finished).await.unwrap().unwrap();
+        assert!(!finished);
+    }
+
+    #[test]
+    fn test_only_ollama_paths_are_adapted() {
+        assert!(OllamaLanguageModel::from_model_path("models/llama-7b", None).is_none());
+        assert_eq!(OllamaLanguageModel::from_model_path("ollama:mistral:7b", None).unwrap().model(), "mistral:7b");
+    }
+}
@@ -0,0 +1,50 @@
+//! Upgrades of the documents this crate persists; see [`talkpp_errors::versioning`]
+//!
+//! Changing the serialized form of a type listed here means appending a step to its
+//! list. The frozen payloads in `tests/fixtures/schemas` must keep loading.
+
+use talkpp_errors::versioning::Migrations;
+
+/// Chat sessions saved by a [`crate::FileSessionStore`]
+pub const CHAT_SESSION: Migrations = Migrations::new("chat session", &[]);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChatSession, FileSessionStore, SessionStore};
+    use std::path::Path;
+    use talkpp_errors::versioning::check_round_trip;
+    use uuid::Uuid;
+
+    fn fixture(name: &str) -> serde_json::Value {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/schemas").join(name);
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_frozen_payloads_still_load() {
+        check_round_trip::<ChatSession>(fixture("chat_session.v1.json"), &CHAT_SESSION).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_file_store_upgrades_old_sessions_and_refuses_newer_ones() {
+        let dir = std::env::temp_dir().join(format!("talkpp-session-versions-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut session = fixture("chat_session.v1.json");
+        let id: Uuid = session["id"].as_str().unwrap().parse().unwrap();
+        let reply = r#"{"role":"Assistant","content":"The search rollout.","timestamp":"2026-09-14T08:02:00Z"}"#;
+        std::fs::write(dir.join(format!("{}.json", id)), session.to_string()).unwrap();
+        std::fs::write(dir.join(format!("{}.jsonl", id)), format!("{}\n", reply)).unwrap();
+
+        let store = FileSessionStore::new(&dir);
+        let loaded = store.load(id).await.unwrap().unwrap();
+        assert_eq!(loaded.schema_version, CHAT_SESSION.current());
+        assert_eq!(loaded.messages.len(), 3);
+
+        session["schema_version"] = (CHAT_SESSION.current() + 1).into();
+        std::fs::write(dir.join(format!("{}.json", id)), session.to_string()).unwrap();
+        let err = store.load(id).await.unwrap_err();
+        assert!(err.to_string().contains("unknown future schema version"), "{}", err);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
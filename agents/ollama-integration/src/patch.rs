@@ -0,0 +1,423 @@
+//! Patches applied to files by `FilePatch` task actions
+//!
+//! A patch is either a unified diff or a JSON merge patch for a JSON, YAML or TOML file.
+//! Applying one computes the whole new file before anything is written, so a hunk that
+//! does not apply leaves the file as it was. The new file replaces the old one with a
+//! rename, and the old contents are kept as a [`PreImage`] the file can be restored from.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+use thiserror::Error;
+use yaml_rust2::{Yaml, YamlEmitter, YamlLoader};
+
+use cognitive_kernel::artifacts::ArtifactRef;
+
+/// How a `FilePatch` action's patch is written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PatchFormat {
+    /// `diff -u` output: hunks of context, removed and added lines
+    #[default]
+    UnifiedDiff,
+    /// A JSON document merged into a JSON, YAML or TOML file: objects merge key by key,
+    /// `null` deletes a key and anything else replaces what was there. Keys of JSON and
+    /// TOML files are written back sorted, and YAML comments are not kept.
+    JsonMerge,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum PatchError {
+    #[error("Patch line {line}: {reason}")]
+    Malformed { line: usize, reason: String },
+
+    #[error("Hunk {hunk} (@@ -{old_start} @@) does not apply: line {line} should be {expected:?} but is {}", found.as_deref().map_or("past the end of the file".to_string(), |found| format!("{:?}", found)))]
+    ContextMismatch { hunk: usize, old_start: usize, line: usize, expected: String, found: Option<String> },
+
+    #[error("Hunk {hunk} starts at line {line}, inside hunk {previous} which ends at line {previous_end}")]
+    ConflictingHunks { hunk: usize, line: usize, previous: usize, previous_end: usize },
+
+    #[error("Cannot merge into {path}: {reason}")]
+    Merge { path: String, reason: String },
+
+    #[error("Path {0} is outside the file root")]
+    OutsideRoot(String),
+}
+
+/// The contents a file had before a patch was written over them
+#[derive(Debug, Clone)]
+pub struct PreImage {
+    pub path: PathBuf,
+    pub contents: Vec<u8>,
+    /// Where the contents were stored, if the manager stores artifacts
+    pub artifact: Option<ArtifactRef>,
+}
+
+impl PreImage {
+    /// Put the file back as it was before the patch
+    pub fn restore(&self) -> std::io::Result<()> {
+        write_atomically(&self.path, &self.contents)
+    }
+}
+
+/// `path` under `root`. Absolute paths and paths leaving `root` through `..` are refused.
+pub fn resolve(root: &Path, path: &str) -> Result<PathBuf, PatchError> {
+    let relative = Path::new(path);
+    let inside = relative.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    if !inside {
+        return Err(PatchError::OutsideRoot(path.to_string()));
+    }
+    Ok(root.join(relative))
+}
+
+/// Replace the file at `path` with `contents` by writing a temporary file beside it and
+/// renaming it over the original, so readers never see half a file
+pub fn write_atomically(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let temporary = path.with_file_name(format!(".{}.{}.tmp", name, uuid::Uuid::new_v4()));
+    std::fs::write(&temporary, contents)?;
+    if let Ok(metadata) = std::fs::metadata(path) {
+        std::fs::set_permissions(&temporary, metadata.permissions())?;
+    }
+    std::fs::rename(&temporary, path).inspect_err(|_| {
+        let _ = std::fs::remove_file(&temporary);
+    })
+}
+
+/// `original` with `patch` applied; `path` picks the structured format of a merge
+pub fn apply(original: &str, patch: &str, format: PatchFormat, path: &Path) -> Result<String, PatchError> {
+    match format {
+        PatchFormat::UnifiedDiff => apply_unified_diff(original, patch),
+        PatchFormat::JsonMerge => apply_json_merge(original, patch, path),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LineKind {
+    Context,
+    Removed,
+    Added,
+}
+
+#[derive(Debug)]
+struct Hunk {
+    /// Counted from 1, in the order the hunks appear
+    number: usize,
+    /// First line of the original the hunk covers, counted from 1
+    old_start: usize,
+    lines: Vec<(LineKind, String)>,
+}
+
+impl Hunk {
+    /// Lines the original must have where the hunk applies
+    fn old_lines(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().filter(|(kind, _)| *kind != LineKind::Added).map(|(_, line)| line.as_str())
+    }
+
+    fn new_lines(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().filter(|(kind, _)| *kind != LineKind::Removed).map(|(_, line)| line.as_str())
+    }
+}
+
+/// Hunks of a unified diff, and whether `\ No newline at end of file` markers say the
+/// old and new files end without a newline
+struct ParsedDiff {
+    hunks: Vec<Hunk>,
+    old_unterminated: bool,
+    new_unterminated: bool,
+}
+
+fn parse_unified_diff(patch: &str) -> Result<ParsedDiff, PatchError> {
+    let mut parsed = ParsedDiff { hunks: Vec::new(), old_unterminated: false, new_unterminated: false };
+    let mut last_kind = None;
+    for (index, line) in patch.lines().enumerate() {
+        let line_number = index + 1;
+        if let Some(header) = line.strip_prefix("@@ ") {
+            let old_start = parse_hunk_header(header).ok_or_else(|| PatchError::Malformed {
+                line: line_number,
+                reason: format!("invalid hunk header {:?}", line),
+            })?;
+            parsed.hunks.push(Hunk { number: parsed.hunks.len() + 1, old_start, lines: Vec::new() });
+            last_kind = None;
+            continue;
+        }
+        let Some(hunk) = parsed.hunks.last_mut() else {
+            // `diff`, `---`, `+++` and `index` lines before the first hunk
+            continue;
+        };
+        let (kind, text) = match line.chars().next() {
+            Some(' ') => (LineKind::Context, &line[1..]),
+            Some('-') => (LineKind::Removed, &line[1..]),
+            Some('+') => (LineKind::Added, &line[1..]),
+            None => (LineKind::Context, ""),
+            Some('\\') => {
+                match last_kind {
+                    Some(LineKind::Removed) => parsed.old_unterminated = true,
+                    Some(LineKind::Added) => parsed.new_unterminated = true,
+                    Some(LineKind::Context) => {
+                        parsed.old_unterminated = true;
+                        parsed.new_unterminated = true;
+                    }
+                    None => {}
+                }
+                continue;
+            }
+            // A header of the next file in a multi-file diff ends this one's hunks
+            _ if line.starts_with("diff ") => break,
+            _ => {
+                return Err(PatchError::Malformed {
+                    line: line_number,
+                    reason: format!("expected a context, removed or added line, found {:?}", line),
+                })
+            }
+        };
+        hunk.lines.push((kind, text.to_string()));
+        last_kind = Some(kind);
+    }
+    if parsed.hunks.is_empty() {
+        return Err(PatchError::Malformed { line: 1, reason: "patch has no hunks".to_string() });
+    }
+    Ok(parsed)
+}
+
+/// Start line of the original from the `-a,b +c,d @@` rest of a hunk header
+fn parse_hunk_header(header: &str) -> Option<usize> {
+    let old = header.split_whitespace().next()?.strip_prefix('-')?;
+    old.split(',').next()?.parse().ok()
+}
+
+/// Apply a unified diff, placing each hunk where it says or, when lines were added or
+/// removed above it, at the nearest place after the previous hunk where its context and
+/// removed lines match
+fn apply_unified_diff(original: &str, patch: &str) -> Result<String, PatchError> {
+    let parsed = parse_unified_diff(patch)?;
+    let lines: Vec<&str> = original.lines().collect();
+    let mut patched: Vec<&str> = Vec::with_capacity(lines.len());
+    // Index of the first original line not yet copied, how far the last hunk was from
+    // where it said it applied, and where that hunk said it ended
+    let mut cursor = 0;
+    let mut offset: isize = 0;
+    let mut previous: Option<(usize, usize)> = None;
+
+    for hunk in &parsed.hunks {
+        let old: Vec<&str> = hunk.old_lines().collect();
+        // A hunk of only added lines is inserted after its start line
+        let stated = if old.is_empty() { hunk.old_start } else { hunk.old_start.saturating_sub(1) };
+        if let Some((previous, previous_end)) = previous {
+            if stated < previous_end {
+                return Err(PatchError::ConflictingHunks { hunk: hunk.number, line: hunk.old_start, previous, previous_end });
+            }
+        }
+        let expected = stated.saturating_add_signed(offset).max(cursor);
+        let matches_at = |at: usize| at + old.len() <= lines.len() && lines[at..at + old.len()] == old[..];
+        let at = if matches_at(expected) {
+            expected
+        } else {
+            (cursor..=lines.len().saturating_sub(old.len()))
+                .filter(|at| matches_at(*at))
+                .min_by_key(|at| at.abs_diff(expected))
+                .ok_or_else(|| mismatch(hunk, &old, &lines, expected))?
+        };
+
+        patched.extend(&lines[cursor..at]);
+        patched.extend(hunk.new_lines());
+        cursor = at + old.len();
+        offset = at as isize - stated as isize;
+        previous = Some((hunk.number, stated + old.len()));
+    }
+    patched.extend(&lines[cursor..]);
+
+    let mut text = patched.join("\n");
+    let terminated = if parsed.new_unterminated {
+        false
+    } else if parsed.old_unterminated {
+        true
+    } else {
+        original.ends_with('\n') || original.is_empty()
+    };
+    if terminated && !patched.is_empty() {
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+/// The first line where `hunk` disagrees with the original at its stated position
+fn mismatch(hunk: &Hunk, old: &[&str], lines: &[&str], at: usize) -> PatchError {
+    let offset = old.iter()
+        .enumerate()
+        .position(|(i, expected)| lines.get(at + i) != Some(expected))
+        .unwrap_or(0);
+    PatchError::ContextMismatch {
+        hunk: hunk.number,
+        old_start: hunk.old_start,
+        line: at + offset + 1,
+        expected: old.get(offset).unwrap_or(&"").to_string(),
+        found: lines.get(at + offset).map(|line| line.to_string()),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Structured {
+    Json,
+    Yaml,
+    Toml,
+}
+
+fn apply_json_merge(original: &str, patch: &str, path: &Path) -> Result<String, PatchError> {
+    let fail = |reason: String| PatchError::Merge { path: path.display().to_string(), reason };
+    let format = match path.extension().and_then(|ext| ext.to_str()).map(str::to_ascii_lowercase).as_deref() {
+        Some("json") => Structured::Json,
+        Some("yaml" | "yml") => Structured::Yaml,
+        Some("toml") => Structured::Toml,
+        _ => return Err(fail("only .json, .yaml, .yml and .toml files take merge patches".to_string())),
+    };
+    let patch: serde_json::Value = serde_json::from_str(patch).map_err(|e| fail(format!("patch is not JSON: {}", e)))?;
+
+    let mut document = match format {
+        _ if original.trim().is_empty() => serde_json::Value::Object(Default::default()),
+        Structured::Json => serde_json::from_str(original).map_err(|e| fail(e.to_string()))?,
+        Structured::Toml => {
+            let value: toml::Value = toml::from_str(original).map_err(|e| fail(e.to_string()))?;
+            serde_json::to_value(value).map_err(|e| fail(e.to_string()))?
+        }
+        Structured::Yaml => {
+            let documents = YamlLoader::load_from_str(original).map_err(|e| fail(e.to_string()))?;
+            documents.first().map(yaml_to_json).unwrap_or(serde_json::Value::Null)
+        }
+    };
+    merge(&mut document, patch);
+
+    match format {
+        Structured::Json => {
+            let mut text = serde_json::to_string_pretty(&document).map_err(|e| fail(e.to_string()))?;
+            text.push('\n');
+            Ok(text)
+        }
+        Structured::Toml => toml::to_string_pretty(&document).map_err(|e| fail(e.to_string())),
+        Structured::Yaml => {
+            let mut text = String::new();
+            YamlEmitter::new(&mut text).dump(&json_to_yaml(&document)).map_err(|e| fail(e.to_string()))?;
+            let mut text = text.strip_prefix("---\n").unwrap_or(&text).to_string();
+            text.push('\n');
+            Ok(text)
+        }
+    }
+}
+
+/// Merge `patch` into `target` as RFC 7386 describes
+fn merge(target: &mut serde_json::Value, patch: serde_json::Value) {
+    let serde_json::Value::Object(patch) = patch else {
+        *target = patch;
+        return;
+    };
+    if !target.is_object() {
+        *target = serde_json::Value::Object(Default::default());
+    }
+    let serde_json::Value::Object(fields) = target else {
+        unreachable!()
+    };
+    for (key, value) in patch {
+        if value.is_null() {
+            fields.remove(&key);
+        } else {
+            merge(fields.entry(key).or_insert(serde_json::Value::Null), value);
+        }
+    }
+}
+
+fn yaml_to_json(yaml: &Yaml) -> serde_json::Value {
+    match yaml {
+        Yaml::Real(real) => real.parse::<f64>().map(serde_json::Value::from).unwrap_or_else(|_| real.clone().into()),
+        Yaml::Integer(i) => serde_json::Value::from(*i),
+        Yaml::String(text) => serde_json::Value::String(text.clone()),
+        Yaml::Boolean(b) => serde_json::Value::Bool(*b),
+        Yaml::Array(items) => items.iter().map(yaml_to_json).collect(),
+        Yaml::Hash(entries) => entries.iter()
+            .map(|(key, value)| {
+                let key = match yaml_to_json(key) {
+                    serde_json::Value::String(key) => key,
+                    key => key.to_string(),
+                };
+                (key, yaml_to_json(value))
+            })
+            .collect(),
+        Yaml::Null | Yaml::Alias(_) | Yaml::BadValue => serde_json::Value::Null,
+    }
+}
+
+fn json_to_yaml(value: &serde_json::Value) -> Yaml {
+    match value {
+        serde_json::Value::Null => Yaml::Null,
+        serde_json::Value::Bool(b) => Yaml::Boolean(*b),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => Yaml::Integer(i),
+            None => Yaml::Real(n.to_string()),
+        },
+        serde_json::Value::String(text) => Yaml::String(text.clone()),
+        serde_json::Value::Array(items) => Yaml::Array(items.iter().map(json_to_yaml).collect()),
+        serde_json::Value::Object(fields) => Yaml::Hash(
+            fields.iter().map(|(key, value)| (Yaml::String(key.clone()), json_to_yaml(value))).collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "host = \"localhost\"\nport = 8080\nworkers = 4\ndebug = false\ntimeout = 30\n";
+
+    #[test]
+    fn test_unified_diff_applies_cleanly_and_follows_shifted_lines() {
+        let patch = "--- a/app.conf\n+++ b/app.conf\n@@ -2,3 +2,3 @@\n port = 8080\n-workers = 4\n+workers = 8\n debug = false\n";
+        assert_eq!(
+            apply(CONFIG, patch, PatchFormat::UnifiedDiff, Path::new("app.conf")).unwrap(),
+            "host = \"localhost\"\nport = 8080\nworkers = 8\ndebug = false\ntimeout = 30\n",
+        );
+
+        // A line added above moves the hunk down by one
+        let shifted = format!("# managed\n{}", CONFIG);
+        assert!(apply(&shifted, patch, PatchFormat::UnifiedDiff, Path::new("app.conf")).unwrap().contains("workers = 8\n"));
+    }
+
+    #[test]
+    fn test_conflicting_hunk_reports_where_it_failed() {
+        let patch = "@@ -3,2 +3,2 @@\n-workers = 2\n+workers = 8\n debug = false\n";
+        let error = apply(CONFIG, patch, PatchFormat::UnifiedDiff, Path::new("app.conf")).unwrap_err();
+        assert_eq!(error, PatchError::ContextMismatch {
+            hunk: 1,
+            old_start: 3,
+            line: 3,
+            expected: "workers = 2".to_string(),
+            found: Some("workers = 4".to_string()),
+        });
+        assert!(error.to_string().contains("line 3"));
+
+        let overlapping = "@@ -2,2 +2,2 @@\n port = 8080\n-workers = 4\n+workers = 8\n@@ -3,1 +3,1 @@\n-workers = 4\n+workers = 16\n";
+        assert!(matches!(
+            apply(CONFIG, overlapping, PatchFormat::UnifiedDiff, Path::new("app.conf")).unwrap_err(),
+            PatchError::ConflictingHunks { hunk: 2, previous: 1, .. },
+        ));
+    }
+
+    #[test]
+    fn test_yaml_deep_merge_deletes_null_keys() {
+        let original = "service:\n  name: api\n  replicas: 2\n  debug: true\nlabels:\n  - web\n";
+        let patch = r#"{"service": {"replicas": 5, "debug": null, "resources": {"cpu": "500m"}}, "labels": ["web", "public"]}"#;
+        let merged = apply(original, patch, PatchFormat::JsonMerge, Path::new("deploy.yaml")).unwrap();
+
+        let documents = YamlLoader::load_from_str(&merged).unwrap();
+        assert_eq!(yaml_to_json(&documents[0]), serde_json::json!({
+            "service": { "name": "api", "replicas": 5, "resources": { "cpu": "500m" } },
+            "labels": ["web", "public"],
+        }));
+        assert!(!merged.starts_with("---"), "{}", merged);
+    }
+
+    #[test]
+    fn test_paths_stay_under_the_root() {
+        let root = Path::new("/srv/files");
+        assert_eq!(resolve(root, "conf/app.toml").unwrap(), root.join("conf/app.toml"));
+        assert_eq!(resolve(root, "../etc/passwd").unwrap_err(), PatchError::OutsideRoot("../etc/passwd".to_string()));
+        assert!(resolve(root, "/etc/passwd").is_err());
+    }
+}
@@ -0,0 +1,390 @@
+//! Which models Ollama keeps loaded, and for how long
+//!
+//! Ollama unloads a model once it has gone unused for its `keep_alive`, which a request
+//! sets and which defaults to five minutes. Models in the warm set are loaded when the
+//! manager initializes and warmed again shortly before their keep-alive runs out, so the
+//! first message to them is not held up by a load. Other models stay loaded for the
+//! default keep-alive after their last use, unless VRAM runs short: then those idle for
+//! longer than the idle window are released early.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::OllamaManager;
+
+/// How long Ollama keeps a model loaded after a request that does not say
+pub const DEFAULT_KEEP_ALIVE: Duration = Duration::from_secs(5 * 60);
+
+/// How long before its keep-alive runs out a warm-set model is warmed again
+pub const DEFAULT_REWARM_MARGIN: Duration = Duration::from_secs(60);
+
+/// How long a model may go unused before it may be released under VRAM pressure
+pub const DEFAULT_IDLE_WINDOW: Duration = Duration::from_secs(2 * 60);
+
+/// How often the residency loop checks the warm set and VRAM
+pub const DEFAULT_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Models to keep loaded, and when to let others go
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResidencyConfig {
+    /// Loaded at initialize and kept loaded
+    pub warm_set: Vec<String>,
+    /// Keep-alive the warm set is loaded with
+    pub keep_alive: Duration,
+    pub rewarm_margin: Duration,
+    pub idle_window: Duration,
+    /// Free VRAM, in bytes, below which idle models are released
+    pub min_free_vram: u64,
+    pub check_interval: Duration,
+}
+
+impl Default for ResidencyConfig {
+    fn default() -> Self {
+        Self::new(Vec::<String>::new())
+    }
+}
+
+impl ResidencyConfig {
+    pub fn new(warm_set: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            warm_set: warm_set.into_iter().map(Into::into).collect(),
+            keep_alive: DEFAULT_KEEP_ALIVE,
+            rewarm_margin: DEFAULT_REWARM_MARGIN,
+            idle_window: DEFAULT_IDLE_WINDOW,
+            min_free_vram: 0,
+            check_interval: DEFAULT_CHECK_INTERVAL,
+        }
+    }
+
+    pub fn with_keep_alive(mut self, keep_alive: Duration) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    pub fn with_rewarm_margin(mut self, margin: Duration) -> Self {
+        self.rewarm_margin = margin;
+        self
+    }
+
+    /// Release models idle for longer than `idle_window` once free VRAM drops below
+    /// `min_free_vram` bytes
+    pub fn with_eviction(mut self, idle_window: Duration, min_free_vram: u64) -> Self {
+        self.idle_window = idle_window;
+        self.min_free_vram = min_free_vram;
+        self
+    }
+
+    pub fn with_check_interval(mut self, interval: Duration) -> Self {
+        self.check_interval = interval;
+        self
+    }
+}
+
+/// Reports free memory on the GPU Ollama runs on, when it runs on this host
+#[async_trait]
+pub trait VramProbe: Send + Sync {
+    /// Free VRAM in bytes
+    async fn free_vram(&self) -> Result<u64>;
+}
+
+/// A model Ollama holds in memory, as far as this manager knows
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ModelResidency {
+    pub model: String,
+    /// Whether the model is in the warm set
+    pub warm: bool,
+    /// When a message, research or code generation request last used it
+    pub last_used: Option<chrono::DateTime<chrono::Utc>>,
+    /// When Ollama will unload it unless it is used or warmed again
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// What one pass of [`OllamaManager::maintain_residency`] did
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ResidencyReport {
+    pub rewarmed: Vec<String>,
+    pub released: Vec<String>,
+}
+
+/// `keep_alive` as Ollama parses it. Whole milliseconds keep short keep-alives from
+/// rounding down to zero, which would unload the model instead.
+fn keep_alive_value(keep_alive: Duration) -> serde_json::Value {
+    if keep_alive.is_zero() {
+        serde_json::json!(0)
+    } else {
+        serde_json::json!(format!("{}ms", keep_alive.as_millis().max(1)))
+    }
+}
+
+/// `duration` as a chrono duration, capped at a year
+fn span(duration: Duration) -> chrono::Duration {
+    chrono::Duration::from_std(duration.min(Duration::from_secs(365 * 24 * 60 * 60))).unwrap_or_default()
+}
+
+fn expiry(keep_alive: Duration) -> chrono::DateTime<chrono::Utc> {
+    chrono::Utc::now() + span(keep_alive)
+}
+
+impl OllamaManager {
+    /// Load `model` and keep it loaded for `keep_alive` after this request, by asking for
+    /// a generation with an empty prompt
+    pub async fn warm_model(&self, model: &str, keep_alive: Duration) -> talkpp_errors::Result<()> {
+        self.post_keep_alive(model, keep_alive).await?;
+        let warm = self.residency_config.warm_set.iter().any(|m| m == model);
+        let mut residency = self.residency.lock().unwrap();
+        let entry = residency.entry(model.to_string()).or_insert_with(|| ModelResidency {
+            model: model.to_string(),
+            warm,
+            last_used: None,
+            expires_at: expiry(keep_alive),
+        });
+        entry.expires_at = expiry(keep_alive);
+        info!("Warmed model {} for {:?}", model, keep_alive);
+        Ok(())
+    }
+
+    /// Unload `model` now
+    pub async fn release_model(&self, model: &str) -> talkpp_errors::Result<()> {
+        self.post_keep_alive(model, Duration::ZERO).await?;
+        self.residency.lock().unwrap().remove(model);
+        info!("Released model {}", model);
+        Ok(())
+    }
+
+    /// Models still loaded, by name
+    pub fn model_residency(&self) -> Vec<ModelResidency> {
+        let now = chrono::Utc::now();
+        let mut models: Vec<ModelResidency> = self.residency.lock().unwrap().values()
+            .filter(|m| m.expires_at > now)
+            .cloned()
+            .collect();
+        models.sort_by(|a, b| a.model.cmp(&b.model));
+        models
+    }
+
+    /// Warm the warm-set models that are unloaded or close to their expiry, and release
+    /// models idle past the idle window if the VRAM probe reports too little free memory
+    pub async fn maintain_residency(&self) -> talkpp_errors::Result<ResidencyReport> {
+        let mut report = ResidencyReport::default();
+        let config = &self.residency_config;
+
+        let rewarm_by = expiry(config.rewarm_margin);
+        let due: Vec<String> = {
+            let residency = self.residency.lock().unwrap();
+            config.warm_set.iter()
+                .filter(|model| residency.get(*model).is_none_or(|m| m.expires_at <= rewarm_by))
+                .cloned()
+                .collect()
+        };
+        for model in due {
+            self.warm_model(&model, config.keep_alive).await?;
+            report.rewarmed.push(model);
+        }
+
+        let Some(probe) = &self.vram_probe else {
+            return Ok(report);
+        };
+        let free = probe.free_vram().await?;
+        if free >= config.min_free_vram {
+            return Ok(report);
+        }
+
+        let idle_since = chrono::Utc::now() - span(config.idle_window);
+        let idle: Vec<String> = self.model_residency().into_iter()
+            .filter(|m| !m.warm && m.last_used.is_none_or(|used| used <= idle_since))
+            .map(|m| m.model)
+            .collect();
+        if !idle.is_empty() {
+            warn!("{} bytes of VRAM free, below {}; releasing idle models {:?}", free, config.min_free_vram, idle);
+        }
+        for model in idle {
+            self.release_model(&model).await?;
+            report.released.push(model);
+        }
+        Ok(report)
+    }
+
+    /// Run [`Self::maintain_residency`] every check interval until the manager is dropped
+    pub fn start_residency_loop(self: &Arc<Self>) -> tokio::task::JoinHandle<()> {
+        let manager = Arc::downgrade(self);
+        let interval = self.residency_config.check_interval;
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticks.tick().await;
+                let Some(manager) = manager.upgrade() else {
+                    return;
+                };
+                if let Err(e) = manager.maintain_residency().await {
+                    warn!("Model residency check failed: {}", e);
+                }
+            }
+        })
+    }
+
+    /// Record a request to `model`, which Ollama keeps it loaded the default keep-alive for
+    pub(crate) fn touch_model(&self, model: &str) {
+        let now = chrono::Utc::now();
+        let warm = self.residency_config.warm_set.iter().any(|m| m == model);
+        let mut residency = self.residency.lock().unwrap();
+        let entry = residency.entry(model.to_string()).or_insert_with(|| ModelResidency {
+            model: model.to_string(),
+            warm,
+            last_used: None,
+            expires_at: now,
+        });
+        entry.last_used = Some(now);
+        entry.expires_at = expiry(DEFAULT_KEEP_ALIVE);
+    }
+
+    /// Warm every model in the warm set, logging those that fail
+    pub(crate) async fn warm_set(&self) {
+        for model in &self.residency_config.warm_set {
+            if let Err(e) = self.warm_model(model, self.residency_config.keep_alive).await {
+                warn!("Failed to warm model {}: {}", model, e);
+            }
+        }
+    }
+
+    async fn post_keep_alive(&self, model: &str, keep_alive: Duration) -> talkpp_errors::Result<()> {
+        let response = self.http
+            .post(format!("{}/api/generate", self.base_url.trim_end_matches('/')))
+            .json(&serde_json::json!({
+                "model": model,
+                "prompt": "",
+                "stream": false,
+                "keep_alive": keep_alive_value(keep_alive),
+            }))
+            .send()
+            .await
+            .map_err(|e| crate::ollama_error("Ollama keep-alive request failed", e))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(crate::ollama_error(
+                "Ollama keep-alive request failed",
+                format!("{} for {}: {}", status, model, body),
+            ));
+        }
+        Ok(())
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// An Ollama stand-in that answers every request and records its body
+    async fn recording_ollama() -> (String, Arc<Mutex<Vec<serde_json::Value>>>) {
+        use tokio::io::AsyncWriteExt;
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let recorded = recorded.clone();
+                tokio::spawn(async move {
+                    while let Some(request) = crate::testing::read_request(&mut socket).await {
+                        let body = serde_json::json!({
+                            "model": request["model"],
+                            "created_at": chrono::Utc::now().to_rfc3339(),
+                            "response": "ok",
+                            "done": true,
+                        }).to_string();
+                        recorded.lock().unwrap().push(request);
+                        let response = format!(
+                            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+                            body.len(), body
+                        );
+                        if socket.write_all(response.as_bytes()).await.is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+        (url, requests)
+    }
+
+    struct FixedVram(u64);
+
+    #[async_trait]
+    impl VramProbe for FixedVram {
+        async fn free_vram(&self) -> Result<u64> {
+            Ok(self.0)
+        }
+    }
+
+    fn keep_alives(requests: &Mutex<Vec<serde_json::Value>>, model: &str) -> Vec<serde_json::Value> {
+        requests.lock().unwrap().iter()
+            .filter(|r| r["model"] == model && r.get("keep_alive").is_some())
+            .map(|r| r["keep_alive"].clone())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_warm_set_is_rewarmed_before_keep_alive_expires() {
+        let (url, requests) = recording_ollama().await;
+        let config = ResidencyConfig::new(["llama3"])
+            .with_keep_alive(Duration::from_millis(300))
+            .with_rewarm_margin(Duration::from_millis(200))
+            .with_check_interval(Duration::from_millis(25));
+        let manager = Arc::new(OllamaManager::new(Some(url)).with_residency(config));
+
+        manager.warm_model("llama3", Duration::from_millis(300)).await.unwrap();
+        assert_eq!(keep_alives(&requests, "llama3"), vec![serde_json::json!("300ms")]);
+        // Not yet within the margin of its expiry
+        assert_eq!(manager.maintain_residency().await.unwrap(), ResidencyReport::default());
+
+        let residency = manager.model_residency();
+        assert_eq!(residency.len(), 1);
+        assert!(residency[0].warm);
+
+        let task = manager.start_residency_loop();
+        tokio::time::sleep(Duration::from_millis(450)).await;
+        task.abort();
+        let sent = keep_alives(&requests, "llama3");
+        assert!(sent.len() >= 3, "{:?}", sent);
+        assert!(sent.iter().all(|k| k == "300ms"));
+        assert_eq!(manager.model_residency().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_idle_models_are_released_under_vram_pressure() {
+        let (url, requests) = recording_ollama().await;
+        let config = ResidencyConfig::new(["llama3"]).with_eviction(Duration::ZERO, 2 << 30);
+        let manager = OllamaManager::new(Some(url.clone()))
+            .with_residency(config.clone())
+            .with_vram_probe(Arc::new(FixedVram(1 << 30)));
+
+        manager.warm_model("llama3", DEFAULT_KEEP_ALIVE).await.unwrap();
+        manager.research_assistant("tides", "mistral").await.unwrap();
+        let used = manager.model_residency();
+        assert_eq!(used.iter().map(|m| m.model.as_str()).collect::<Vec<_>>(), ["llama3", "mistral"]);
+        assert!(used[1].last_used.is_some());
+
+        let report = manager.maintain_residency().await.unwrap();
+        assert_eq!(report.released, ["mistral"]);
+        assert_eq!(keep_alives(&requests, "mistral"), vec![serde_json::json!(0)]);
+        assert_eq!(manager.model_residency().iter().map(|m| m.model.as_str()).collect::<Vec<_>>(), ["llama3"]);
+
+        // With VRAM to spare nothing is released
+        let relaxed = OllamaManager::new(Some(url))
+            .with_residency(config)
+            .with_vram_probe(Arc::new(FixedVram(4 << 30)));
+        relaxed.research_assistant("tides", "mistral").await.unwrap();
+        let report = relaxed.maintain_residency().await.unwrap();
+        assert!(report.released.is_empty());
+        assert_eq!(report.rewarmed, ["llama3"]);
+    }
+}
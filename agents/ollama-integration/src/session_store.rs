@@ -0,0 +1,139 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use serde_json::Value;
+use std::path::PathBuf;
+use talkpp_errors::versioning::load_versioned;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::migrations::CHAT_SESSION;
+use crate::{ChatMessage, ChatSession};
+
+/// Durable storage for chat sessions
+#[async_trait]
+pub trait SessionStore: Send + Sync {
+    /// Replace everything stored for the session
+    async fn save(&self, session: &ChatSession) -> Result<()>;
+
+    /// Add messages to the end of the session's stored history without rewriting it
+    async fn append(&self, session_id: Uuid, messages: &[ChatMessage]) -> Result<()>;
+
+    async fn load(&self, session_id: Uuid) -> Result<Option<ChatSession>>;
+}
+
+/// Keeps sessions in memory; for tests and single-process use
+#[derive(Debug, Default)]
+pub struct MemorySessionStore {
+    sessions: RwLock<HashMap<Uuid, ChatSession>>,
+}
+
+impl MemorySessionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl SessionStore for MemorySessionStore {
+    async fn save(&self, session: &ChatSession) -> Result<()> {
+        self.sessions.write().await.insert(session.id, session.clone());
+        Ok(())
+    }
+
+    async fn append(&self, session_id: Uuid, messages: &[ChatMessage]) -> Result<()> {
+        let mut sessions = self.sessions.write().await;
+        let session = sessions.get_mut(&session_id)
+            .ok_or_else(|| anyhow::anyhow!("Chat session not stored: {}", session_id))?;
+        session.messages.extend_from_slice(messages);
+        if let Some(last) = messages.last() {
+            session.last_activity = last.timestamp;
+        }
+        Ok(())
+    }
+
+    async fn load(&self, session_id: Uuid) -> Result<Option<ChatSession>> {
+        Ok(self.sessions.read().await.get(&session_id).cloned())
+    }
+}
+
+/// Stores each session as `<id>.json`, with messages appended after the last full save
+/// going to `<id>.jsonl`, one message per line. Sessions saved by older builds are
+/// upgraded as they load; see [`crate::migrations`].
+#[derive(Debug, Clone)]
+pub struct FileSessionStore {
+    dir: PathBuf,
+}
+
+impl FileSessionStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn session_path(&self, session_id: Uuid) -> PathBuf {
+        self.dir.join(format!("{}.json", session_id))
+    }
+
+    fn log_path(&self, session_id: Uuid) -> PathBuf {
+        self.dir.join(format!("{}.jsonl", session_id))
+    }
+}
+
+#[async_trait]
+impl SessionStore for FileSessionStore {
+    async fn save(&self, session: &ChatSession) -> Result<()> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        tokio::fs::write(self.session_path(session.id), serde_json::to_vec_pretty(session)?).await?;
+        // The full save already holds every appended message
+        match tokio::fs::remove_file(self.log_path(session.id)).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn append(&self, session_id: Uuid, messages: &[ChatMessage]) -> Result<()> {
+        if !tokio::fs::try_exists(self.session_path(session_id)).await? {
+            return Err(anyhow::anyhow!("Chat session not stored: {}", session_id));
+        }
+        let mut lines = Vec::new();
+        for message in messages {
+            serde_json::to_writer(&mut lines, message)?;
+            lines.push(b'\n');
+        }
+        let mut log = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.log_path(session_id))
+            .await?;
+        log.write_all(&lines).await?;
+        log.flush().await?;
+        Ok(())
+    }
+
+    async fn load(&self, session_id: Uuid) -> Result<Option<ChatSession>> {
+        let session = match tokio::fs::read(self.session_path(session_id)).await {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let mut session: Value = serde_json::from_slice(&session)?;
+
+        let log = match tokio::fs::read_to_string(self.log_path(session_id)).await {
+            Ok(log) => log,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        // Appended messages are upgraded along with the session they follow
+        if let Some(messages) = session.get_mut("messages").and_then(Value::as_array_mut) {
+            for line in log.lines().filter(|l| !l.trim().is_empty()) {
+                messages.push(serde_json::from_str(line)?);
+            }
+        }
+        let mut session: ChatSession = load_versioned(session, &CHAT_SESSION)?;
+        if let Some(last) = session.messages.last() {
+            session.last_activity = session.last_activity.max(last.timestamp);
+        }
+        Ok(Some(session))
+    }
+}
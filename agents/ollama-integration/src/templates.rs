@@ -0,0 +1,62 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::OllamaParameters;
+
+/// Role a chat session can be started in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionTemplate {
+    pub system_prompt: String,
+    /// Parameters for sessions started from this template; unset fields take the defaults
+    #[serde(default)]
+    pub parameters: OllamaParameters,
+}
+
+/// Session templates by name, loaded from a TOML file with one table per template:
+///
+/// ```toml
+/// [research_assistant]
+/// system_prompt = "You are a careful research assistant."
+///
+/// [research_assistant.parameters]
+/// temperature = 0.2
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TemplateRegistry {
+    templates: HashMap<String, SessionTemplate>,
+}
+
+impl TemplateRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_toml_str(source: &str) -> Result<Self> {
+        toml::from_str(source).map_err(|e| anyhow::anyhow!("Invalid session templates: {}", e))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read session templates {}: {}", path.display(), e))?;
+        Self::from_toml_str(&source)
+    }
+
+    pub fn insert(&mut self, name: impl Into<String>, template: SessionTemplate) {
+        self.templates.insert(name.into(), template);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&SessionTemplate> {
+        self.templates.get(name)
+    }
+
+    /// Template names, sorted
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.templates.keys().map(String::as_str).collect();
+        names.sort();
+        names
+    }
+}
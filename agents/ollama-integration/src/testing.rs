@@ -0,0 +1,32 @@
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+
+/// Read one HTTP request, returning its JSON body, or `None` once the client has closed
+/// the connection
+pub(crate) async fn read_request(socket: &mut TcpStream) -> Option<serde_json::Value> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    let header_end = loop {
+        let n = socket.read(&mut buf).await.ok()?;
+        if n == 0 {
+            return None;
+        }
+        request.extend_from_slice(&buf[..n]);
+        if let Some(i) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+            break i + 4;
+        }
+    };
+    let headers = String::from_utf8_lossy(&request[..header_end]).to_lowercase();
+    let length: usize = headers.lines()
+        .find_map(|line| line.strip_prefix("content-length:"))
+        .map(|len| len.trim().parse().unwrap())
+        .unwrap_or(0);
+    while request.len() < header_end + length {
+        let n = socket.read(&mut buf).await.ok()?;
+        if n == 0 {
+            return None;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+    Some(serde_json::from_slice(&request[header_end..]).unwrap())
+}
@@ -0,0 +1,157 @@
+//! MCP tool use in chat sessions
+//!
+//! A session created with a [`ToolUseConfig`] has the tools it may call described in its
+//! system prompt, as function schemas. The model calls one by replying with a fenced
+//! `tool_call` block holding the tool's name and arguments:
+//!
+//! ````text
+//! ```tool_call
+//! {"tool": "calendar_events", "arguments": {"date": "2026-10-17"}}
+//! ```
+//! ````
+//!
+//! The arguments are checked against the tool's input schema, the call is run through
+//! the `McpHub`, and its outcome is added to the session as a Tool message before the
+//! model is asked again. A turn ends when the model answers without a call, and fails
+//! once it has made `max_iterations` calls and asks for another.
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use talkpp_mcp_hub::{McpError, McpHub, McpTool};
+
+/// Tool calls a turn may make unless configured otherwise
+pub const DEFAULT_MAX_TOOL_ITERATIONS: usize = 5;
+
+/// How long a tool call may take unless configured otherwise
+pub const DEFAULT_TOOL_CALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+const TOOL_CALL_FENCE: &str = "```tool_call";
+
+/// Tools a chat session may call, and the limits it calls them within
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolUseConfig {
+    /// Names of the hub's tools the session is offered; calls to any other are refused
+    pub allowed_tools: Vec<String>,
+    /// Tool calls one turn may make
+    pub max_iterations: usize,
+    pub call_timeout: Duration,
+    /// Record the calls the model asks for without running them
+    pub dry_run: bool,
+}
+
+impl ToolUseConfig {
+    pub fn new(allowed_tools: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            allowed_tools: allowed_tools.into_iter().map(Into::into).collect(),
+            max_iterations: DEFAULT_MAX_TOOL_ITERATIONS,
+            call_timeout: DEFAULT_TOOL_CALL_TIMEOUT,
+            dry_run: false,
+        }
+    }
+
+    pub fn with_max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    pub fn with_call_timeout(mut self, timeout: Duration) -> Self {
+        self.call_timeout = timeout;
+        self
+    }
+
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+}
+
+/// A tool call the model asked for
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub tool: String,
+    #[serde(default = "no_arguments")]
+    pub arguments: serde_json::Value,
+}
+
+fn no_arguments() -> serde_json::Value {
+    serde_json::json!({})
+}
+
+/// What came of a tool call
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolOutcome {
+    Result(serde_json::Value),
+    /// The call was refused, failed or timed out; the model is shown why
+    Error(String),
+    /// The call was valid but not run, as the session is a dry run
+    DryRun,
+}
+
+/// A tool call and its outcome, as the content of a Tool message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCallRecord {
+    #[serde(flatten)]
+    pub call: ToolCall,
+    pub outcome: ToolOutcome,
+}
+
+/// System prompt section offering `tools` to the model, each as a function schema
+pub fn tools_prompt(tools: &[McpTool]) -> String {
+    let mut prompt = format!(
+        "You can call the tools below. To call one, reply with only a tool_call block holding \
+         the tool's name and arguments that match its parameters:\n\n\
+         {}\n{{\"tool\": \"<name>\", \"arguments\": {{}}}}\n```\n\n\
+         The outcome is sent back to you as a Tool message. Once you have what you need, \
+         answer without a tool_call block.\n\nTools:\n",
+        TOOL_CALL_FENCE
+    );
+    for tool in tools {
+        let function = serde_json::json!({
+            "name": tool.name,
+            "description": tool.description,
+            "parameters": tool.input_schema,
+        });
+        prompt.push_str(&format!("- {}\n", function));
+    }
+    prompt
+}
+
+/// The call in a model reply: its first `tool_call` block, or `None` without one. A block
+/// that isn't a call gives the reason, so the model can be told.
+pub fn parse_tool_call(reply: &str) -> Option<Result<ToolCall, String>> {
+    let start = reply.find(TOOL_CALL_FENCE)? + TOOL_CALL_FENCE.len();
+    let block = &reply[start..];
+    let block = block.find("```").map_or(block, |end| &block[..end]);
+    Some(serde_json::from_str(block.trim()).map_err(|e| format!("Invalid tool_call block: {}", e)))
+}
+
+/// Run `call` as `config` allows, or only check it in a dry run
+pub(crate) async fn run(hub: Option<&McpHub>, config: &ToolUseConfig, call: ToolCall) -> ToolCallRecord {
+    let outcome = outcome(hub, config, &call).await;
+    ToolCallRecord { call, outcome }
+}
+
+async fn outcome(hub: Option<&McpHub>, config: &ToolUseConfig, call: &ToolCall) -> ToolOutcome {
+    if !config.allowed_tools.contains(&call.tool) {
+        return ToolOutcome::Error(format!("Tool '{}' is not available in this session", call.tool));
+    }
+    let Some(hub) = hub else {
+        return ToolOutcome::Error("No MCP hub is configured to run tools".to_string());
+    };
+    let Some(tool) = hub.find_tool(&call.tool).await else {
+        return ToolOutcome::Error(McpError::ToolNotFound(call.tool.clone()).to_string());
+    };
+    if let Err(e) = tool.validate_params(&call.arguments) {
+        return ToolOutcome::Error(e.to_string());
+    }
+    if config.dry_run {
+        return ToolOutcome::DryRun;
+    }
+
+    match tokio::time::timeout(config.call_timeout, hub.call_tool(&call.tool, call.arguments.clone())).await {
+        Ok(Ok(result)) => ToolOutcome::Result(result),
+        Ok(Err(e)) => ToolOutcome::Error(e.to_string()),
+        Err(_) => ToolOutcome::Error(format!("Tool '{}' timed out after {:?}", call.tool, config.call_timeout)),
+    }
+}
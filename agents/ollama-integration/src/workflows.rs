@@ -0,0 +1,786 @@
+//! Declarative workflow definitions: automated tasks described in YAML files.
+//!
+//! A file holds a `tasks` list. Each task is keyed by its `name`, which must be unique
+//! across everything loaded together:
+//!
+//! ```yaml
+//! tasks:
+//!   - name: morning-digest
+//!     description: Summarise the overnight feed
+//!     enabled: true                  # optional, defaults to true
+//!     schedule:
+//!       cron: "30 7 * * 1-5"         # five fields, or six with seconds first
+//!     actions:
+//!       - id: feed                   # optional; names the step for later references
+//!         type: api_call
+//!         url: https://example.com/feed.json
+//!         method: GET                # optional, defaults to GET
+//!       - id: digest
+//!         type: llm_query
+//!         model: llama3
+//!         prompt: "Summarise this feed: {{ feed.status }}"
+//!         store_result: true         # optional, defaults to false
+//!       - type: notification
+//!         channel: slack
+//!         message: "{{ digest.response }}"
+//! ```
+//!
+//! Schedules are one of `cron: <expression>`, `interval: { seconds }`,
+//! `daily: { hour, minute }` and `weekly: { day, hour, minute }`, with `day` running
+//! from 1 for Monday to 7 for Sunday. Daily and weekly schedules may also set a
+//! `timezone` such as `Europe/Berlin` for their hour and minute, which are otherwise in
+//! UTC, a list of `skip_dates` such as `2026-12-25`, and a list of `skip_weekdays`
+//! numbered like `day`. A task without a
+//! `trigger` runs on its schedule; otherwise `trigger` has a `type` of `schedule`,
+//! `data_change` (`source`, `pattern`), `api_call` (`endpoint`), `file_change` (`path`)
+//! or `custom` (`condition`).
+//!
+//! Actions have a `type` of `llm_query` (`model`, `prompt`, `store_result`),
+//! `prompt_query` (`model`, `template`, `variables`, `store_result`), which renders
+//! its prompt from the manager's prompt library, `data_extraction` (`source`,
+//! `format`), `api_call` (`url`, `method`, `headers`, `body`), `file_operation`
+//! (`operation`, `path`, `content`), `file_patch` (`path`, `patch`, `format` of
+//! `unified_diff` or `json_merge`, `dry_run`) or `notification` (`channel`, `message`). Any string in an action may refer to a field of an earlier
+//! step's result as `{{ step.field }}`.
+
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use thiserror::Error;
+use uuid::Uuid;
+use yaml_rust2::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust2::scanner::{Marker, TScalarStyle};
+use yaml_rust2::Yaml;
+
+use crate::{AutomatedTask, PatchFormat, ScheduleCalendar, TaskAction, TaskSchedule, TaskTrigger};
+
+/// Action types a workflow may use, as written in YAML
+const ACTION_TYPES: [&str; 7] = ["llm_query", "prompt_query", "data_extraction", "api_call", "file_operation", "file_patch", "notification"];
+
+/// Id of the task named `name`, the same every time the task is loaded
+pub fn task_id(name: &str) -> Uuid {
+    Uuid::new_v5(&Uuid::NAMESPACE_OID, format!("talkpp.workflow.{}", name).as_bytes())
+}
+
+/// Parse a cron expression. Standard five-field expressions are accepted alongside the
+/// six- and seven-field forms whose first field is seconds.
+pub fn parse_cron(expression: &str) -> Result<cron::Schedule> {
+    let expression = expression.trim();
+    let expression = if expression.split_whitespace().count() == 5 {
+        format!("0 {}", expression)
+    } else {
+        expression.to_string()
+    };
+    cron::Schedule::from_str(&expression).map_err(|e| anyhow::anyhow!("{}", e))
+}
+
+/// A problem with a workflow definition, at the line it was found on
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkflowIssue {
+    pub path: PathBuf,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for WorkflowIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.path.display(), self.line, self.message)
+    }
+}
+
+/// Every problem found in a set of workflow definitions; none of them is loaded
+#[derive(Debug, Error)]
+#[error("Invalid workflow definitions:\n{}", .issues.iter().map(|issue| format!("  {}", issue)).collect::<Vec<_>>().join("\n"))]
+pub struct WorkflowError {
+    pub issues: Vec<WorkflowIssue>,
+}
+
+impl From<WorkflowError> for talkpp_errors::Error {
+    fn from(error: WorkflowError) -> Self {
+        Self::transparent(talkpp_errors::ErrorKind::InvalidInput, error)
+    }
+}
+
+/// Tasks defined in a workflow file
+#[derive(Debug, Clone)]
+pub struct WorkflowFile {
+    pub path: PathBuf,
+    pub tasks: Vec<AutomatedTask>,
+}
+
+/// What loading workflow definitions changed, by task name
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WorkflowLoadReport {
+    pub created: Vec<String>,
+    pub updated: Vec<String>,
+    /// Tasks loaded from the same files before that the files no longer define
+    pub disabled: Vec<String>,
+}
+
+/// Read and validate the workflow definitions in `path`
+pub fn read_file(path: &Path) -> talkpp_errors::Result<WorkflowFile> {
+    let source = std::fs::read_to_string(path).map_err(|e| read_error("file", path, e))?;
+    Ok(parse(&source, path)?)
+}
+
+/// Read and validate every `.yaml` and `.yml` file in `dir`, checking task names are
+/// unique across all of them
+pub fn read_dir(dir: &Path) -> talkpp_errors::Result<Vec<WorkflowFile>> {
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(dir).map_err(|e| read_error("directory", dir, e))? {
+        let path = entry.map_err(|e| read_error("directory", dir, e))?.path();
+        let is_yaml = path.extension().is_some_and(|ext| ext == "yaml" || ext == "yml");
+        if is_yaml && path.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let mut files = Vec::new();
+    let mut issues = Vec::new();
+    let mut names: HashMap<String, (PathBuf, usize)> = HashMap::new();
+    for path in paths {
+        let source = std::fs::read_to_string(&path).map_err(|e| read_error("file", &path, e))?;
+        let mut checker = Checker::new(&path);
+        let tasks = checker.tasks(&source);
+        for (name, line) in std::mem::take(&mut checker.names) {
+            match names.get(&name) {
+                Some((first_path, first_line)) => checker.issue(line, format!(
+                    "duplicate task name \"{}\" (first defined at {}:{})",
+                    name,
+                    first_path.display(),
+                    first_line
+                )),
+                None => {
+                    names.insert(name, (path.clone(), line));
+                }
+            }
+        }
+        issues.append(&mut checker.issues);
+        files.push(WorkflowFile { path, tasks });
+    }
+
+    if !issues.is_empty() {
+        return Err(WorkflowError { issues }.into());
+    }
+    Ok(files)
+}
+
+fn read_error(what: &str, path: &Path, error: std::io::Error) -> talkpp_errors::Error {
+    let kind = match error.kind() {
+        std::io::ErrorKind::NotFound => talkpp_errors::ErrorKind::NotFound,
+        _ => talkpp_errors::ErrorKind::Internal,
+    };
+    let message = format!("Failed to read workflow {} {}: {}", what, path.display(), error);
+    talkpp_errors::Error::new(kind, message).with_source(error)
+}
+
+/// Parse and validate workflow definitions, attributing problems to `path`
+pub fn parse(source: &str, path: &Path) -> Result<WorkflowFile, WorkflowError> {
+    let mut checker = Checker::new(path);
+    let tasks = checker.tasks(source);
+    if !checker.issues.is_empty() {
+        return Err(WorkflowError { issues: checker.issues });
+    }
+    Ok(WorkflowFile { path: path.to_path_buf(), tasks })
+}
+
+/// `{{ step.field }}` references in `text`, as written between the braces
+fn references(text: &str) -> Vec<&str> {
+    let mut found = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}") else {
+            break;
+        };
+        found.push(rest[start + 2..start + end].trim());
+        rest = &rest[start + end + 2..];
+    }
+    found
+}
+
+/// Replace each `{{ step.field }}` in `text` with that field of the step's result
+fn render(text: &str, outputs: &HashMap<String, serde_json::Value>) -> Result<String> {
+    let mut rendered = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}") else {
+            break;
+        };
+        let reference = rest[start + 2..start + end].trim();
+        let (step, field) = reference.split_once('.')
+            .ok_or_else(|| anyhow::anyhow!("Malformed template reference: {{{{ {} }}}}", reference))?;
+        let value = outputs.get(step)
+            .ok_or_else(|| anyhow::anyhow!("No result from step {} for {{{{ {} }}}}", step, reference))?
+            .get(field)
+            .ok_or_else(|| anyhow::anyhow!("Step {} has no {} result", step, field))?;
+        rendered.push_str(&rest[..start]);
+        match value {
+            serde_json::Value::String(value) => rendered.push_str(value),
+            value => rendered.push_str(&value.to_string()),
+        }
+        rest = &rest[start + end + 2..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+/// `action` with the results of earlier steps substituted for its template references
+pub(crate) fn render_action(action: &TaskAction, outputs: &HashMap<String, serde_json::Value>) -> Result<TaskAction> {
+    fn render_value(value: &mut serde_json::Value, outputs: &HashMap<String, serde_json::Value>) -> Result<()> {
+        match value {
+            serde_json::Value::String(text) => *text = render(text, outputs)?,
+            serde_json::Value::Array(items) => {
+                for item in items {
+                    render_value(item, outputs)?;
+                }
+            }
+            serde_json::Value::Object(fields) => {
+                for field in fields.values_mut() {
+                    render_value(field, outputs)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    let mut value = serde_json::to_value(action)?;
+    render_value(&mut value, outputs)?;
+    Ok(serde_json::from_value(value)?)
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ScheduleSpec {
+    Cron(String),
+    Interval { seconds: u64 },
+    Daily {
+        hour: u8,
+        minute: u8,
+        #[serde(flatten)]
+        calendar: ScheduleCalendar,
+    },
+    Weekly {
+        day: u8,
+        hour: u8,
+        minute: u8,
+        #[serde(flatten)]
+        calendar: ScheduleCalendar,
+    },
+}
+
+impl From<ScheduleSpec> for TaskSchedule {
+    fn from(spec: ScheduleSpec) -> Self {
+        match spec {
+            ScheduleSpec::Cron(expression) => TaskSchedule::Cron(expression),
+            ScheduleSpec::Interval { seconds } => TaskSchedule::Interval { seconds },
+            ScheduleSpec::Daily { hour, minute, calendar } => TaskSchedule::Daily { hour, minute, calendar },
+            ScheduleSpec::Weekly { day, hour, minute, calendar } => TaskSchedule::Weekly { day, hour, minute, calendar },
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum TriggerSpec {
+    Schedule,
+    DataChange { source: String, pattern: String },
+    ApiCall { endpoint: String },
+    FileChange { path: String },
+    Custom { condition: String },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ActionSpec {
+    LlmQuery {
+        model: String,
+        prompt: String,
+        #[serde(default)]
+        store_result: bool,
+    },
+    PromptQuery {
+        model: String,
+        template: String,
+        #[serde(default)]
+        variables: HashMap<String, String>,
+        #[serde(default)]
+        store_result: bool,
+    },
+    DataExtraction {
+        source: String,
+        format: String,
+    },
+    ApiCall {
+        url: String,
+        #[serde(default = "default_method")]
+        method: String,
+        #[serde(default)]
+        headers: HashMap<String, String>,
+        body: Option<String>,
+    },
+    FileOperation {
+        operation: String,
+        path: String,
+        content: Option<String>,
+    },
+    FilePatch {
+        path: String,
+        patch: String,
+        #[serde(default)]
+        format: PatchFormat,
+        #[serde(default)]
+        dry_run: bool,
+    },
+    Notification {
+        channel: String,
+        message: String,
+    },
+}
+
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+impl From<ActionSpec> for TaskAction {
+    fn from(spec: ActionSpec) -> Self {
+        match spec {
+            ActionSpec::LlmQuery { model, prompt, store_result } => TaskAction::LlmQuery { model, prompt, store_result },
+            ActionSpec::PromptQuery { model, template, variables, store_result } => {
+                TaskAction::PromptQuery { model, template, variables, store_result }
+            }
+            ActionSpec::DataExtraction { source, format } => TaskAction::DataExtraction { source, format },
+            ActionSpec::ApiCall { url, method, headers, body } => TaskAction::ApiCall { url, method, headers, body },
+            ActionSpec::FileOperation { operation, path, content } => {
+                TaskAction::FileOperation { operation, path, content }
+            }
+            ActionSpec::FilePatch { path, patch, format, dry_run } => TaskAction::FilePatch { path, patch, format, dry_run },
+            ActionSpec::Notification { channel, message } => TaskAction::Notification { channel, message },
+        }
+    }
+}
+
+/// A YAML value with the line it starts on
+#[derive(Debug)]
+struct Node {
+    line: usize,
+    value: NodeValue,
+}
+
+#[derive(Debug)]
+enum NodeValue {
+    Scalar(serde_json::Value),
+    Sequence(Vec<Node>),
+    Mapping(Vec<(String, Node)>),
+}
+
+impl Node {
+    fn get(&self, key: &str) -> Option<&Node> {
+        match &self.value {
+            NodeValue::Mapping(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, node)| node),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match &self.value {
+            NodeValue::Scalar(serde_json::Value::String(text)) => Some(text),
+            _ => None,
+        }
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        match &self.value {
+            NodeValue::Scalar(value) => value.clone(),
+            NodeValue::Sequence(items) => items.iter().map(Node::to_json).collect(),
+            NodeValue::Mapping(entries) => {
+                entries.iter().map(|(key, node)| (key.clone(), node.to_json())).collect()
+            }
+        }
+    }
+
+    /// `self` as JSON without the mapping entries named in `skip`
+    fn to_json_without(&self, skip: &[&str]) -> serde_json::Value {
+        let mut value = self.to_json();
+        if let serde_json::Value::Object(fields) = &mut value {
+            for key in skip {
+                fields.remove(*key);
+            }
+        }
+        value
+    }
+
+    /// Every string in `self`, with its line
+    fn strings(&self) -> Vec<(&str, usize)> {
+        match &self.value {
+            NodeValue::Scalar(serde_json::Value::String(text)) => vec![(text.as_str(), self.line)],
+            NodeValue::Scalar(_) => Vec::new(),
+            NodeValue::Sequence(items) => items.iter().flat_map(Node::strings).collect(),
+            NodeValue::Mapping(entries) => entries.iter().flat_map(|(_, node)| node.strings()).collect(),
+        }
+    }
+}
+
+/// Builds a `Node` tree from parser events, keeping the line each value starts on
+#[derive(Default)]
+struct TreeBuilder {
+    open: Vec<(Node, Option<String>)>,
+    root: Option<Node>,
+}
+
+impl TreeBuilder {
+    fn add(&mut self, node: Node) {
+        let Some((parent, pending_key)) = self.open.last_mut() else {
+            self.root.get_or_insert(node);
+            return;
+        };
+        match &mut parent.value {
+            NodeValue::Sequence(items) => items.push(node),
+            NodeValue::Mapping(entries) => match pending_key.take() {
+                Some(key) => entries.push((key, node)),
+                None => {
+                    let key = match node.to_json() {
+                        serde_json::Value::String(key) => key,
+                        key => key.to_string(),
+                    };
+                    *pending_key = Some(key);
+                }
+            },
+            NodeValue::Scalar(_) => {}
+        }
+    }
+}
+
+impl MarkedEventReceiver for TreeBuilder {
+    fn on_event(&mut self, event: Event, mark: Marker) {
+        let line = mark.line();
+        match event {
+            Event::SequenceStart(..) => self.open.push((Node { line, value: NodeValue::Sequence(Vec::new()) }, None)),
+            Event::MappingStart(..) => self.open.push((Node { line, value: NodeValue::Mapping(Vec::new()) }, None)),
+            Event::SequenceEnd | Event::MappingEnd => {
+                if let Some((node, _)) = self.open.pop() {
+                    self.add(node);
+                }
+            }
+            Event::Scalar(text, style, ..) => {
+                let value = if style == TScalarStyle::Plain {
+                    match Yaml::from_str(&text) {
+                        Yaml::Integer(i) => serde_json::Value::from(i),
+                        Yaml::Real(real) => real.parse::<f64>().map(serde_json::Value::from)
+                            .unwrap_or(serde_json::Value::String(real)),
+                        Yaml::Boolean(b) => serde_json::Value::Bool(b),
+                        Yaml::Null => serde_json::Value::Null,
+                        _ => serde_json::Value::String(text),
+                    }
+                } else {
+                    serde_json::Value::String(text)
+                };
+                self.add(Node { line, value: NodeValue::Scalar(value) });
+            }
+            // Anchors are not part of the schema
+            Event::Alias(_) => self.add(Node { line, value: NodeValue::Scalar(serde_json::Value::Null) }),
+            _ => {}
+        }
+    }
+}
+
+/// Validates one file's definitions, collecting every problem rather than stopping at
+/// the first
+struct Checker {
+    path: PathBuf,
+    issues: Vec<WorkflowIssue>,
+    /// Task names defined so far, with the line of each
+    names: Vec<(String, usize)>,
+}
+
+impl Checker {
+    fn new(path: &Path) -> Self {
+        Self { path: path.to_path_buf(), issues: Vec::new(), names: Vec::new() }
+    }
+
+    fn issue(&mut self, line: usize, message: impl Into<String>) {
+        self.issues.push(WorkflowIssue { path: self.path.clone(), line, message: message.into() });
+    }
+
+    fn deserialize<T: DeserializeOwned>(&mut self, line: usize, what: &str, value: serde_json::Value) -> Option<T> {
+        match serde_json::from_value(value) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                self.issue(line, format!("invalid {}: {}", what, e));
+                None
+            }
+        }
+    }
+
+    /// The valid tasks in `source`; any problems are left in `issues`
+    fn tasks(&mut self, source: &str) -> Vec<AutomatedTask> {
+        let mut builder = TreeBuilder::default();
+        if let Err(e) = Parser::new_from_str(source).load(&mut builder, false) {
+            self.issue(e.marker().line(), format!("invalid YAML: {}", e.info()));
+            return Vec::new();
+        }
+        let Some(root) = builder.root else {
+            return Vec::new();
+        };
+        let Some(tasks) = root.get("tasks") else {
+            self.issue(root.line, "expected a top-level `tasks` list");
+            return Vec::new();
+        };
+        let NodeValue::Sequence(items) = &tasks.value else {
+            self.issue(tasks.line, "`tasks` must be a list");
+            return Vec::new();
+        };
+        items.iter().filter_map(|item| self.task(item)).collect()
+    }
+
+    fn task(&mut self, node: &Node) -> Option<AutomatedTask> {
+        if !matches!(node.value, NodeValue::Mapping(_)) {
+            self.issue(node.line, "a task must be a mapping");
+            return None;
+        }
+        let name = match node.get("name").and_then(Node::as_str) {
+            Some(name) if !name.trim().is_empty() => name.to_string(),
+            _ => {
+                self.issue(node.line, "a task needs a `name`");
+                return None;
+            }
+        };
+        let name_line = node.get("name").map_or(node.line, |n| n.line);
+        if let Some((_, first_line)) = self.names.iter().find(|(seen, _)| *seen == name) {
+            let message = format!("duplicate task name \"{}\" (first defined on line {})", name, first_line);
+            self.issue(name_line, message);
+        }
+        self.names.push((name.clone(), name_line));
+
+        let issues_before = self.issues.len();
+        let description = match node.get("description") {
+            Some(n) => self.deserialize(n.line, "description", n.to_json()).unwrap_or_default(),
+            None => String::new(),
+        };
+        let enabled = match node.get("enabled") {
+            Some(n) => self.deserialize(n.line, "enabled flag", n.to_json()).unwrap_or(true),
+            None => true,
+        };
+        let schedule = node.get("schedule").and_then(|n| self.schedule(n));
+        let trigger = match node.get("trigger") {
+            Some(n) => self.deserialize::<TriggerSpec>(n.line, "trigger", n.to_json()),
+            None => Some(TriggerSpec::Schedule),
+        };
+        let trigger = match trigger {
+            Some(TriggerSpec::Schedule) => match &schedule {
+                Some(schedule) => Some(TaskTrigger::Schedule(schedule.clone())),
+                None => {
+                    if node.get("schedule").is_none() {
+                        self.issue(node.line, format!("task \"{}\" needs a `schedule` or a `trigger`", name));
+                    }
+                    None
+                }
+            },
+            Some(TriggerSpec::DataChange { source, pattern }) => Some(TaskTrigger::DataChange { source, pattern }),
+            Some(TriggerSpec::ApiCall { endpoint }) => Some(TaskTrigger::ApiCall { endpoint }),
+            Some(TriggerSpec::FileChange { path }) => Some(TaskTrigger::FileChange { path }),
+            Some(TriggerSpec::Custom { condition }) => Some(TaskTrigger::Custom { condition }),
+            None => None,
+        };
+        let (actions, step_ids) = self.actions(node, &name);
+
+        if self.issues.len() > issues_before {
+            return None;
+        }
+        Some(AutomatedTask {
+            id: task_id(&name),
+            name,
+            description,
+            trigger: trigger?,
+            actions,
+            schedule,
+            enabled,
+            last_run: None,
+            next_run: None,
+            step_ids,
+            source: Some(self.path.clone()),
+        })
+    }
+
+    fn schedule(&mut self, node: &Node) -> Option<TaskSchedule> {
+        let spec: ScheduleSpec = self.deserialize(node.line, "schedule", node.to_json())?;
+        if let ScheduleSpec::Cron(expression) = &spec {
+            if let Err(e) = parse_cron(expression) {
+                let line = node.get("cron").map_or(node.line, |n| n.line);
+                self.issue(line, format!("invalid cron expression \"{}\": {}", expression, e));
+                return None;
+            }
+        }
+        Some(spec.into())
+    }
+
+    fn actions(&mut self, task: &Node, task_name: &str) -> (Vec<TaskAction>, HashMap<usize, String>) {
+        let mut actions = Vec::new();
+        let mut step_ids = HashMap::new();
+        let items = match task.get("actions") {
+            Some(Node { value: NodeValue::Sequence(items), .. }) if !items.is_empty() => items,
+            Some(node) => {
+                self.issue(node.line, "`actions` must be a non-empty list");
+                return (actions, step_ids);
+            }
+            None => {
+                self.issue(task.line, format!("task \"{}\" needs `actions`", task_name));
+                return (actions, step_ids);
+            }
+        };
+
+        // Step ids in file order, so a reference can only reach steps run before it
+        let declared: Vec<Option<&str>> = items.iter().map(|item| item.get("id").and_then(Node::as_str)).collect();
+        for (index, item) in items.iter().enumerate() {
+            let id = declared[index];
+            if let Some(id) = id {
+                if declared[..index].contains(&Some(id)) {
+                    self.issue(item.get("id").map_or(item.line, |n| n.line), format!("duplicate step id \"{}\"", id));
+                }
+                step_ids.insert(index, id.to_string());
+            }
+
+            for (text, line) in item.strings() {
+                for reference in references(text) {
+                    let Some((step, _field)) = reference.split_once('.') else {
+                        self.issue(line, format!(
+                            "malformed template reference {{{{ {} }}}}; expected {{{{ step.field }}}}",
+                            reference
+                        ));
+                        continue;
+                    };
+                    if !declared[..index].contains(&Some(step)) {
+                        let message = if declared[index..].contains(&Some(step)) {
+                            format!("template reference {{{{ {} }}}} refers to step \"{}\", which has not run yet", reference, step)
+                        } else {
+                            format!("template reference {{{{ {} }}}} refers to unknown step \"{}\"", reference, step)
+                        };
+                        self.issue(line, message);
+                    }
+                }
+            }
+
+            match item.get("type").and_then(Node::as_str) {
+                Some(kind) if ACTION_TYPES.contains(&kind) => {
+                    let spec = self.deserialize::<ActionSpec>(item.line, "action", item.to_json_without(&["id"]));
+                    if let Some(spec) = spec {
+                        actions.push(spec.into());
+                    }
+                }
+                Some(kind) => {
+                    let line = item.get("type").map_or(item.line, |n| n.line);
+                    let message = format!("unknown action type \"{}\" (expected one of {})", kind, ACTION_TYPES.join(", "));
+                    self.issue(line, message);
+                }
+                None => self.issue(item.line, "an action needs a `type`"),
+            }
+        }
+        (actions, step_ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/workflows").join(name)
+    }
+
+    fn issues(name: &str) -> Vec<(usize, String)> {
+        let error = read_file(&fixture(name)).unwrap_err();
+        assert_eq!(error.kind(), talkpp_errors::ErrorKind::InvalidInput);
+        let error = error.downcast_ref::<WorkflowError>().unwrap();
+        error.issues.iter().map(|issue| (issue.line, issue.message.clone())).collect()
+    }
+
+    #[test]
+    fn test_valid_file_parses_into_tasks() {
+        let file = read_file(&fixture("digest.yaml")).unwrap();
+        assert_eq!(file.tasks.len(), 2);
+
+        let digest = &file.tasks[0];
+        assert_eq!(digest.name, "morning-digest");
+        assert_eq!(digest.id, task_id("morning-digest"));
+        assert!(digest.enabled);
+        assert!(matches!(&digest.schedule, Some(TaskSchedule::Cron(cron)) if cron == "30 7 * * 1-5"));
+        assert!(matches!(digest.trigger, TaskTrigger::Schedule(TaskSchedule::Cron(_))));
+        assert!(matches!(&digest.actions[0], TaskAction::ApiCall { method, .. } if method == "GET"));
+        assert!(matches!(&digest.actions[1], TaskAction::LlmQuery { store_result: true, .. }));
+        assert_eq!(digest.step_ids.get(&1).map(String::as_str), Some("digest"));
+        assert!(!digest.step_ids.contains_key(&2));
+
+        let watcher = &file.tasks[1];
+        assert!(!watcher.enabled);
+        assert!(matches!(&watcher.trigger, TaskTrigger::FileChange { path } if path == "/var/reports"));
+    }
+
+    #[test]
+    fn test_unknown_action_types_are_reported() {
+        let issues = issues("unknown_action.yaml");
+        assert_eq!(issues.len(), 2, "{:?}", issues);
+        assert_eq!(issues[0].0, 7);
+        assert!(issues[0].1.contains("unknown action type \"send_fax\""));
+        assert_eq!(issues[1].0, 14);
+        assert!(issues[1].1.contains("unknown action type \"shell\""));
+    }
+
+    #[test]
+    fn test_invalid_cron_is_reported() {
+        let issues = issues("invalid_cron.yaml");
+        assert_eq!(issues.len(), 1, "{:?}", issues);
+        assert_eq!(issues[0].0, 4);
+        assert!(issues[0].1.contains("invalid cron expression \"61 * * * *\""));
+    }
+
+    #[test]
+    fn test_dangling_references_are_reported() {
+        let issues = issues("dangling_reference.yaml");
+        assert_eq!(issues.len(), 2, "{:?}", issues);
+        assert_eq!(issues[0].0, 8);
+        assert!(issues[0].1.contains("unknown step \"fetch\""));
+        assert_eq!(issues[1].0, 12);
+        assert!(issues[1].1.contains("\"notify\", which has not run yet"));
+    }
+
+    #[test]
+    fn test_duplicate_names_are_reported() {
+        let issues = issues("duplicate_names.yaml");
+        assert_eq!(issues, vec![(12, "duplicate task name \"nightly\" (first defined on line 2)".to_string())]);
+    }
+
+    #[test]
+    fn test_every_problem_in_a_file_is_reported() {
+        let issues = issues("many_errors.yaml");
+        let lines: Vec<usize> = issues.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, vec![4, 7, 10, 13, 21], "{:?}", issues);
+    }
+
+    #[test]
+    fn test_cron_accepts_five_and_six_fields() {
+        assert!(parse_cron("*/15 * * * *").is_ok());
+        assert!(parse_cron("0 */15 * * * *").is_ok());
+        assert!(parse_cron("every day").is_err());
+    }
+
+    #[test]
+    fn test_actions_render_earlier_results() {
+        let outputs = HashMap::from([("digest".to_string(), serde_json::json!({"response": "All quiet", "count": 3}))]);
+        let action = TaskAction::Notification {
+            channel: "slack".to_string(),
+            message: "{{ digest.response }} ({{digest.count}} items)".to_string(),
+        };
+        match render_action(&action, &outputs).unwrap() {
+            TaskAction::Notification { message, .. } => assert_eq!(message, "All quiet (3 items)"),
+            other => panic!("unexpected action {:?}", other),
+        }
+
+        let missing = TaskAction::Notification { channel: "slack".to_string(), message: "{{ digest.summary }}".to_string() };
+        assert!(render_action(&missing, &outputs).is_err());
+    }
+}
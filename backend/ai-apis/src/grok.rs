@@ -0,0 +1,355 @@
+//! Grok through the x.ai chat completions API, which follows the OpenAI schema
+//!
+//! Requests go to `{base}/v1/chat/completions`, where the base is the API's own
+//! `base_url` or else the client's (`services.grok_api_url`). Server errors are retried
+//! a bounded number of times with exponential backoff; every other failure is returned
+//! at once as a `talkpp_errors::Error`, rate limits carrying the server's `Retry-After`.
+
+use crate::openai_compat::{self, ChatCompletionRequest, ChatCompletionResponse, StreamEvent};
+use crate::{ApiConfig, ApiRequest, ApiResponse};
+use anyhow::Result;
+use futures::StreamExt;
+use std::time::{Duration, Instant};
+use talkpp_errors::ErrorKind;
+use tokio::sync::mpsc;
+use tracing::warn;
+
+pub const DEFAULT_BASE_URL: &str = "https://api.x.ai";
+
+/// Deltas buffered between the stream and a slow reader
+const STREAM_BUFFER: usize = 64;
+
+pub struct GrokClient {
+    http: reqwest::Client,
+    base_url: String,
+    max_retries: u32,
+    retry_backoff: Duration,
+}
+
+impl GrokClient {
+    pub fn new() -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: DEFAULT_BASE_URL.to_string(),
+            max_retries: 2,
+            retry_backoff: Duration::from_millis(500),
+        }
+    }
+
+    /// Send requests to `base_url` unless an API sets its own
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// Retry server errors up to `max_retries` times, waiting `backoff` before the first
+    /// retry and twice as long before each one after it
+    pub fn with_retries(mut self, max_retries: u32, backoff: Duration) -> Self {
+        self.max_retries = max_retries;
+        self.retry_backoff = backoff;
+        self
+    }
+
+    pub async fn initialize(&self, config: &ApiConfig) -> Result<()> {
+        if config.api_key.trim().is_empty() {
+            return Err(talkpp_errors::Error::unauthorized(format!("Grok API '{}' has no API key", config.name)).into());
+        }
+        reqwest::Url::parse(&self.endpoint(config))
+            .map_err(|e| talkpp_errors::Error::invalid_input(format!("Invalid Grok base URL: {}", e)))?;
+        Ok(())
+    }
+
+    pub async fn execute_request(&self, config: &ApiConfig, request: ApiRequest) -> Result<ApiResponse> {
+        let started = Instant::now();
+        let body = ChatCompletionRequest::from_api_request(&request)?;
+        let completion: ChatCompletionResponse = self.send(config, &body).await?
+            .json()
+            .await
+            .map_err(|e| talkpp_errors::Error::upstream_unavailable("Unreadable Grok completion").with_source(e))?;
+
+        Ok(ApiResponse {
+            success: true,
+            data: serde_json::json!({
+                "id": completion.id,
+                "model": completion.model,
+                "content": completion.content(),
+                "finish_reason": completion.finish_reason(),
+            }),
+            usage: completion.usage,
+            cost_usd: None,
+            error: None,
+            latency_ms: started.elapsed().as_millis() as u64,
+        })
+    }
+
+    /// Stream the completion for `request`, passing on each piece of text as it arrives.
+    /// Failures before the first piece are returned like those of `execute_request`;
+    /// the channel just closes if the stream breaks off later. Dropping the receiver
+    /// closes the connection.
+    pub async fn stream_request(&self, config: &ApiConfig, request: ApiRequest) -> Result<mpsc::Receiver<String>> {
+        let body = ChatCompletionRequest::from_api_request(&request)?.streaming();
+        let response = self.send(config, &body).await?;
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+
+        tokio::spawn(async move {
+            let mut body = response.bytes_stream();
+            let mut buffer = Vec::new();
+            loop {
+                let bytes = tokio::select! {
+                    bytes = body.next() => bytes,
+                    _ = tx.closed() => return,
+                };
+                let bytes = match bytes {
+                    Some(Ok(bytes)) => bytes,
+                    Some(Err(e)) => {
+                        warn!("Grok stream broke off: {}", e);
+                        return;
+                    }
+                    None => return,
+                };
+
+                buffer.extend_from_slice(&bytes);
+                while let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+                    let line: Vec<u8> = buffer.drain(..=end).collect();
+                    let event = match openai_compat::parse_stream_line(&String::from_utf8_lossy(&line)) {
+                        None => continue,
+                        Some(Ok(event)) => event,
+                        Some(Err(e)) => {
+                            warn!("Unreadable event in Grok stream: {}", e);
+                            return;
+                        }
+                    };
+                    match event {
+                        StreamEvent::Chunk(chunk) => {
+                            if let Some(delta) = chunk.delta() {
+                                if tx.send(delta.to_string()).await.is_err() {
+                                    return;
+                                }
+                            }
+                        }
+                        StreamEvent::Done => return,
+                    }
+                }
+            }
+        });
+
+        Ok(rx)
+    }
+
+    fn endpoint(&self, config: &ApiConfig) -> String {
+        let base = config.base_url.as_deref().unwrap_or(&self.base_url);
+        format!("{}/v1/chat/completions", base.trim_end_matches('/').trim_end_matches("/v1"))
+    }
+
+    /// Post `body`, retrying server errors, and return the first successful response
+    async fn send(&self, config: &ApiConfig, body: &ChatCompletionRequest) -> talkpp_errors::Result<reqwest::Response> {
+        let endpoint = self.endpoint(config);
+        let mut retries = 0;
+        loop {
+            let response = self.http.post(&endpoint)
+                .bearer_auth(&config.api_key)
+                .json(body)
+                .send()
+                .await?;
+            let status = response.status();
+            if status.is_success() {
+                return Ok(response);
+            }
+
+            let error = status_error(response).await;
+            if !status.is_server_error() || retries == self.max_retries {
+                return Err(error);
+            }
+            let backoff = self.retry_backoff * 2u32.pow(retries);
+            retries += 1;
+            warn!("{}; retry {} of {} in {:?}", error, retries, self.max_retries, backoff);
+            tokio::time::sleep(backoff).await;
+        }
+    }
+}
+
+impl Default for GrokClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The error for an unsuccessful response, classified by its status
+async fn status_error(response: reqwest::Response) -> talkpp_errors::Error {
+    let status = response.status();
+    let retry_after = response.headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<f64>().ok())
+        .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+        .map(Duration::from_secs_f64);
+    let message = openai_compat::error_message(&response.text().await.unwrap_or_default());
+
+    let kind = ErrorKind::from_upstream_status(status.as_u16()).unwrap_or(ErrorKind::Internal);
+    let error = match kind {
+        ErrorKind::Unauthorized => talkpp_errors::Error::unauthorized(format!("Grok rejected the API key: {}", message)),
+        ErrorKind::RateLimited => talkpp_errors::Error::rate_limited(format!("Grok rate limit reached: {}", message)),
+        _ => talkpp_errors::Error::new(kind, format!("Grok request failed ({}): {}", status, message)),
+    };
+    match retry_after {
+        Some(retry_after) if kind == ErrorKind::RateLimited => error.with_retry_after(retry_after),
+        _ => error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ApiProvider, ChatMessage, RateLimitConfig};
+    use uuid::Uuid;
+    use wiremock::matchers::{body_partial_json, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn config(base_url: String) -> ApiConfig {
+        ApiConfig {
+            id: Uuid::new_v4(),
+            provider: ApiProvider::Grok3,
+            name: "grok".to_string(),
+            api_key: "xai-test".to_string(),
+            base_url: Some(base_url),
+            rate_limit: RateLimitConfig { requests_per_minute: 60, requests_per_hour: 1000, tokens_per_minute: None },
+            pricing: None,
+            enabled: true,
+            created_at: chrono::Utc::now(),
+        }
+    }
+
+    fn chat(content: &str) -> ApiRequest {
+        ApiRequest::ChatCompletion {
+            messages: vec![ChatMessage { role: "user".to_string(), content: content.to_string() }],
+            model: "grok-3".to_string(),
+            temperature: Some(0.2),
+            max_tokens: None,
+        }
+    }
+
+    fn client() -> GrokClient {
+        GrokClient::new().with_retries(2, Duration::from_millis(1))
+    }
+
+    async fn error_for(server: &MockServer) -> talkpp_errors::Error {
+        let error = client().execute_request(&config(server.uri()), chat("Hi")).await.unwrap_err();
+        talkpp_errors::Error::from(error)
+    }
+
+    #[tokio::test]
+    async fn test_completions_are_parsed_with_usage() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .and(header("authorization", "Bearer xai-test"))
+            .and(body_partial_json(serde_json::json!({
+                "model": "grok-3",
+                "messages": [{"role": "user", "content": "Hi"}],
+                "temperature": 0.2,
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "id": "cmpl-1",
+                "model": "grok-3",
+                "choices": [{"index": 0, "message": {"role": "assistant", "content": "Hello!"}, "finish_reason": "stop"}],
+                "usage": {"prompt_tokens": 3, "completion_tokens": 2, "total_tokens": 5},
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        // A base URL that already ends in /v1 is not doubled up
+        let response = client().execute_request(&config(format!("{}/v1/", server.uri())), chat("Hi")).await.unwrap();
+        assert!(response.success);
+        assert_eq!(response.data["content"], "Hello!");
+        assert_eq!(response.data["finish_reason"], "stop");
+        let usage = response.usage.unwrap();
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens, usage.total_tokens), (3, 2, 5));
+    }
+
+    #[tokio::test]
+    async fn test_streams_pass_deltas_through() {
+        let server = MockServer::start().await;
+        let events = [
+            r#"{"choices":[{"index":0,"delta":{"role":"assistant"},"finish_reason":null}]}"#,
+            r#"{"choices":[{"index":0,"delta":{"content":"Hel"},"finish_reason":null}]}"#,
+            r#"{"choices":[{"index":0,"delta":{"content":"lo!"},"finish_reason":"stop"}]}"#,
+            "[DONE]",
+        ];
+        let body: String = events.iter().map(|event| format!("data: {}\n\n", event)).collect();
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .and(body_partial_json(serde_json::json!({"stream": true})))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(body, "text/event-stream"))
+            .mount(&server)
+            .await;
+
+        let mut rx = client().stream_request(&config(server.uri()), chat("Hi")).await.unwrap();
+        let mut deltas = Vec::new();
+        while let Some(delta) = rx.recv().await {
+            deltas.push(delta);
+        }
+        assert_eq!(deltas, vec!["Hel", "lo!"]);
+    }
+
+    #[tokio::test]
+    async fn test_bad_keys_are_unauthorized() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(401).set_body_json(serde_json::json!({"error": "Incorrect API key provided"})))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let error = error_for(&server).await;
+        assert_eq!(error.kind(), ErrorKind::Unauthorized);
+        assert!(error.message().contains("Incorrect API key provided"));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limits_carry_retry_after() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(429)
+                .insert_header("retry-after", "7")
+                .set_body_json(serde_json::json!({"error": {"message": "Too many requests"}})))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let error = error_for(&server).await;
+        assert_eq!(error.kind(), ErrorKind::RateLimited);
+        assert_eq!(error.retry_after(), Some(Duration::from_secs(7)));
+    }
+
+    #[tokio::test]
+    async fn test_server_errors_are_retried_a_bounded_number_of_times() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503).set_body_string("overloaded"))
+            .expect(3)
+            .mount(&server)
+            .await;
+
+        let error = error_for(&server).await;
+        assert_eq!(error.kind(), ErrorKind::UpstreamUnavailable);
+        assert!(error.message().contains("overloaded"));
+        server.verify().await;
+
+        // A retry that succeeds hides the server error
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(502))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "choices": [{"message": {"role": "assistant", "content": "Recovered"}, "finish_reason": "stop"}],
+            })))
+            .mount(&server)
+            .await;
+        let response = client().execute_request(&config(server.uri()), chat("Hi")).await.unwrap();
+        assert_eq!(response.data["content"], "Recovered");
+    }
+}
@@ -1,13 +1,17 @@
 use anyhow::Result;
 use async_trait::async_trait;
+use cognitive_kernel::audit::{AuditAction, AuditActor, AuditEvent, AuditOutcome, Auditor};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use tracing::{info, error};
+use std::sync::Arc;
+use talkpp_auth::secrets::SecretResolver;
+use tracing::{info, error, Instrument};
 use uuid::Uuid;
 
 pub mod anthropic;
 pub mod grok;
 pub mod monday;
+pub mod openai_compat;
 
 /// AI API Configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -15,9 +19,13 @@ pub struct ApiConfig {
     pub id: Uuid,
     pub provider: ApiProvider,
     pub name: String,
+    /// Literal key, or a reference such as `vault:ai/anthropic#api_key` resolved on registration
     pub api_key: String,
     pub base_url: Option<String>,
     pub rate_limit: RateLimitConfig,
+    /// Token prices; without them responses carry no cost
+    #[serde(default)]
+    pub pricing: Option<TokenPricing>,
     pub enabled: bool,
     pub created_at: chrono::DateTime<chrono::Utc>,
 }
@@ -37,12 +45,21 @@ pub struct RateLimitConfig {
     pub tokens_per_minute: Option<u32>,
 }
 
+/// What a provider charges for tokens
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenPricing {
+    pub prompt_usd_per_1k: f64,
+    pub completion_usd_per_1k: f64,
+}
+
 /// AI API Manager
 pub struct AiApiManager {
     configs: tokio::sync::RwLock<HashMap<Uuid, ApiConfig>>,
     anthropic_client: anthropic::AnthropicClient,
     grok_client: grok::GrokClient,
     monday_client: monday::MondayClient,
+    resolver: Option<Arc<SecretResolver>>,
+    auditor: Option<Auditor>,
 }
 
 impl AiApiManager {
@@ -52,15 +69,39 @@ impl AiApiManager {
             anthropic_client: anthropic::AnthropicClient::new(),
             grok_client: grok::GrokClient::new(),
             monday_client: monday::MondayClient::new(),
+            resolver: None,
+            auditor: None,
         }
     }
 
+    /// Resolve secret references in API keys with the given resolver
+    pub fn with_secrets(mut self, resolver: Arc<SecretResolver>) -> Self {
+        self.resolver = Some(resolver);
+        self
+    }
+
+    /// Send Grok requests with `client`, such as one pointed at `services.grok_api_url`
+    pub fn with_grok_client(mut self, client: grok::GrokClient) -> Self {
+        self.grok_client = client;
+        self
+    }
+
+    /// Audit every request
+    pub fn with_auditor(mut self, auditor: Auditor) -> Self {
+        self.auditor = Some(auditor);
+        self
+    }
+
     pub async fn register_api(&self, mut config: ApiConfig) -> Result<Uuid> {
         config.id = Uuid::new_v4();
         config.created_at = chrono::Utc::now();
 
         let api_id = config.id;
 
+        if let Some(resolver) = &self.resolver {
+            resolver.resolve_in_place(&mut config.api_key).await?;
+        }
+
         // Initialize client based on provider
         match config.provider {
             ApiProvider::Anthropic => {
@@ -85,6 +126,27 @@ impl AiApiManager {
     }
 
     pub async fn execute_request(&self, api_id: Uuid, request: ApiRequest) -> Result<ApiResponse> {
+        self.execute_request_as(&AuditActor::system(), api_id, request).await
+    }
+
+    /// Execute a request on behalf of `actor`, who is recorded in the audit trail
+    pub async fn execute_request_as(&self, actor: &AuditActor, api_id: Uuid, request: ApiRequest) -> Result<ApiResponse> {
+        let audited = self.auditor.as_ref().map(|_| serde_json::to_value(&request).unwrap_or_default());
+        let span = tracing::info_span!("ai_request", api_id = %api_id, provider = tracing::field::Empty);
+        let result = self.dispatch_request(api_id, request).instrument(span).await;
+        if let (Some(auditor), Some(params)) = (&self.auditor, audited) {
+            auditor.record(AuditEvent::new(
+                actor.clone(),
+                AuditAction::AiRequest,
+                format!("ai-api:{}", api_id),
+                &params,
+                AuditOutcome::of(&result),
+            ));
+        }
+        result
+    }
+
+    async fn dispatch_request(&self, api_id: Uuid, mut request: ApiRequest) -> Result<ApiResponse> {
         let config = {
             let configs = self.configs.read().await;
             configs.get(&api_id).cloned()
@@ -94,8 +156,12 @@ impl AiApiManager {
         if !config.enabled {
             return Err(anyhow::anyhow!("API is disabled: {}", api_id));
         }
+        tracing::Span::current().record("provider", tracing::field::debug(&config.provider));
+        if let ApiRequest::Custom { headers, .. } = &mut request {
+            headers.extend(cognitive_kernel::telemetry::trace_headers());
+        }
 
-        match config.provider {
+        let mut response = match config.provider {
             ApiProvider::Anthropic => {
                 self.anthropic_client.execute_request(&config, request).await
             }
@@ -108,7 +174,12 @@ impl AiApiManager {
             _ => {
                 Err(anyhow::anyhow!("Provider not supported: {:?}", config.provider))
             }
-        }
+        }?;
+
+        response.cost_usd = response.usage.as_ref()
+            .zip(config.pricing.as_ref())
+            .map(|(usage, pricing)| usage.cost_usd(pricing));
+        Ok(response)
     }
 }
 
@@ -149,13 +220,23 @@ pub struct ApiResponse {
     pub success: bool,
     pub data: serde_json::Value,
     pub usage: Option<UsageInfo>,
+    /// Cost of `usage` at the API's pricing, when both are known
+    #[serde(default)]
+    pub cost_usd: Option<f64>,
     pub error: Option<String>,
     pub latency_ms: u64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UsageInfo {
     pub prompt_tokens: u32,
     pub completion_tokens: u32,
     pub total_tokens: u32,
-} 
\ No newline at end of file
+} 
+
+impl UsageInfo {
+    pub fn cost_usd(&self, pricing: &TokenPricing) -> f64 {
+        (self.prompt_tokens as f64 * pricing.prompt_usd_per_1k
+            + self.completion_tokens as f64 * pricing.completion_usd_per_1k) / 1000.0
+    }
+}
@@ -0,0 +1,195 @@
+//! Request and response bodies of the OpenAI-compatible chat completions API
+//!
+//! Grok speaks this schema, as do OpenAI and most hosted models, so providers build
+//! their requests and read their responses with these types and only differ in base
+//! URL and authentication.
+
+use crate::{ApiRequest, ChatMessage, UsageInfo};
+use serde::{Deserialize, Serialize};
+
+/// Body of `POST /v1/chat/completions`
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatCompletionRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub stream: bool,
+}
+
+impl ChatCompletionRequest {
+    /// The chat completion asked for by `request`. A text completion becomes a single
+    /// user message, since the legacy completions endpoint is not offered everywhere.
+    /// Embedding and custom requests have no chat form.
+    pub fn from_api_request(request: &ApiRequest) -> talkpp_errors::Result<Self> {
+        let (model, messages, temperature, max_tokens) = match request {
+            ApiRequest::ChatCompletion { messages, model, temperature, max_tokens } => {
+                (model, messages.clone(), *temperature, *max_tokens)
+            }
+            ApiRequest::TextCompletion { prompt, model, temperature, max_tokens } => {
+                let message = ChatMessage { role: "user".to_string(), content: prompt.clone() };
+                (model, vec![message], *temperature, *max_tokens)
+            }
+            ApiRequest::Embedding { .. } | ApiRequest::Custom { .. } => {
+                return Err(talkpp_errors::Error::invalid_input(
+                    "Only chat and text completions can be sent as chat completions",
+                ));
+            }
+        };
+        Ok(Self { model: model.clone(), messages, temperature, max_tokens, stream: false })
+    }
+
+    /// Ask for the completion as server-sent events
+    pub fn streaming(mut self) -> Self {
+        self.stream = true;
+        self
+    }
+}
+
+/// A complete, non-streamed chat completion
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChatCompletionResponse {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub model: String,
+    pub choices: Vec<Choice>,
+    pub usage: Option<UsageInfo>,
+}
+
+impl ChatCompletionResponse {
+    /// Text of the first choice, empty when the model sent none
+    pub fn content(&self) -> &str {
+        self.choices.first()
+            .and_then(|choice| choice.message.content.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// Why the first choice stopped, such as `stop` or `length`
+    pub fn finish_reason(&self) -> Option<&str> {
+        self.choices.first().and_then(|choice| choice.finish_reason.as_deref())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Choice {
+    #[serde(default)]
+    pub index: u32,
+    pub message: ResponseMessage,
+    pub finish_reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ResponseMessage {
+    pub role: String,
+    /// Missing when the model only called tools
+    pub content: Option<String>,
+}
+
+/// One server-sent event of a streamed chat completion
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChatCompletionChunk {
+    #[serde(default)]
+    pub choices: Vec<ChunkChoice>,
+    /// Only on the last chunk, when the provider reports usage for streams
+    pub usage: Option<UsageInfo>,
+}
+
+impl ChatCompletionChunk {
+    /// Text added to the first choice by this chunk, if any
+    pub fn delta(&self) -> Option<&str> {
+        self.choices.first()
+            .and_then(|choice| choice.delta.content.as_deref())
+            .filter(|content| !content.is_empty())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChunkChoice {
+    #[serde(default)]
+    pub index: u32,
+    pub delta: Delta,
+    pub finish_reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Delta {
+    pub role: Option<String>,
+    pub content: Option<String>,
+}
+
+/// What one line of a completion stream carries
+#[derive(Debug)]
+pub enum StreamEvent {
+    Chunk(ChatCompletionChunk),
+    /// The `[DONE]` sentinel after the last chunk
+    Done,
+}
+
+/// Read one line of a completion stream. Blank lines, comments and fields other than
+/// `data` carry nothing and give `None`.
+pub fn parse_stream_line(line: &str) -> Option<serde_json::Result<StreamEvent>> {
+    let data = line.trim_end_matches(['\r', '\n']).strip_prefix("data:")?.trim_start();
+    if data == "[DONE]" {
+        return Some(Ok(StreamEvent::Done));
+    }
+    Some(serde_json::from_str(data).map(StreamEvent::Chunk))
+}
+
+/// The message of an error response. OpenAI nests it as `{"error": {"message": ..}}`,
+/// others send `{"error": ".."}`; anything else is passed on as it came.
+pub fn error_message(body: &str) -> String {
+    let message = serde_json::from_str::<serde_json::Value>(body).ok().and_then(|value| {
+        let error = value.get("error")?;
+        error.get("message").unwrap_or(error).as_str().map(str::to_string)
+    });
+    message.unwrap_or_else(|| body.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_completions_become_a_user_message() {
+        let request = ApiRequest::TextCompletion {
+            prompt: "Name a moon of Jupiter".to_string(),
+            model: "grok-3".to_string(),
+            temperature: None,
+            max_tokens: Some(16),
+        };
+        let body = serde_json::to_value(ChatCompletionRequest::from_api_request(&request).unwrap()).unwrap();
+        assert_eq!(body, serde_json::json!({
+            "model": "grok-3",
+            "messages": [{"role": "user", "content": "Name a moon of Jupiter"}],
+            "max_tokens": 16,
+        }));
+
+        let embedding = ApiRequest::Embedding { text: "moon".to_string(), model: "grok-3".to_string() };
+        let error = ChatCompletionRequest::from_api_request(&embedding).unwrap_err();
+        assert_eq!(error.kind(), talkpp_errors::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_stream_lines() {
+        let chunk = r#"data: {"choices":[{"index":0,"delta":{"content":"Io"},"finish_reason":null}]}"#;
+        match parse_stream_line(chunk) {
+            Some(Ok(StreamEvent::Chunk(chunk))) => assert_eq!(chunk.delta(), Some("Io")),
+            other => panic!("expected a chunk, got {:?}", other),
+        }
+        assert!(matches!(parse_stream_line("data: [DONE]\r\n"), Some(Ok(StreamEvent::Done))));
+        assert!(parse_stream_line(": keep-alive").is_none());
+        assert!(parse_stream_line("").is_none());
+        assert!(matches!(parse_stream_line("data: {"), Some(Err(_))));
+    }
+
+    #[test]
+    fn test_error_messages() {
+        assert_eq!(error_message(r#"{"error":{"message":"Incorrect API key","type":"invalid_request_error"}}"#), "Incorrect API key");
+        assert_eq!(error_message(r#"{"code":"unauthorized","error":"Incorrect API key"}"#), "Incorrect API key");
+        assert_eq!(error_message("upstream connect error\n"), "upstream connect error");
+    }
+}
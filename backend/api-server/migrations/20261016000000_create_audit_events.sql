@@ -0,0 +1,16 @@
+-- Structured audit log of side-effecting operations
+CREATE TABLE IF NOT EXISTS audit_events (
+    id UUID PRIMARY KEY,
+    occurred_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    user_id VARCHAR(255),
+    session_id VARCHAR(255),
+    agent_id VARCHAR(255),
+    action VARCHAR(50) NOT NULL,
+    target TEXT NOT NULL,
+    parameters_digest JSONB NOT NULL,
+    outcome JSONB NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_events_occurred_at ON audit_events(occurred_at);
+CREATE INDEX IF NOT EXISTS idx_audit_events_user_id ON audit_events(user_id);
+CREATE INDEX IF NOT EXISTS idx_audit_events_action ON audit_events(action);
@@ -0,0 +1,9 @@
+-- Per-user autonomy and approval preferences, enforced on every plan
+CREATE TABLE IF NOT EXISTS user_preferences (
+    user_id UUID PRIMARY KEY,
+    max_autonomy_tier SMALLINT,
+    require_approval_for_risks TEXT[] NOT NULL DEFAULT '{}',
+    preferred_execution_mode VARCHAR(50),
+    notification_preferences JSONB,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
@@ -0,0 +1,175 @@
+use jarvis_core::approval::{ApprovalError, ApprovalLedger, ApprovalStatus, PendingApproval};
+use jarvis_core::audit::{AuditAction, AuditOutcome, Auditor};
+use uuid::Uuid;
+
+use crate::audit::record_task_decision;
+use crate::error::{ApiError, ApiResult};
+use crate::UserSession;
+
+/// Prefix of the session permissions naming the approver groups a user belongs to,
+/// e.g. `group:sre`
+pub const GROUP_PERMISSION_PREFIX: &str = "group:";
+
+/// What an approver decided about a task
+#[derive(Debug, Clone)]
+pub enum TaskDecision {
+    Approve,
+    Reject { reason: Option<String> },
+}
+
+/// The approver groups the session's user is a member of
+pub fn approver_groups(session: &UserSession) -> Vec<String> {
+    session.permissions.iter()
+        .filter_map(|permission| permission.strip_prefix(GROUP_PERMISSION_PREFIX))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Apply the session user's decision to a task awaiting approval, auditing the attempt
+/// whether or not it counted. Returns the task's approval as it now stands; a task is
+/// only released once enough distinct members of its approver groups approved it.
+pub fn decide_task(
+    ledger: &ApprovalLedger,
+    auditor: &Auditor,
+    session: Option<&UserSession>,
+    task_id: Uuid,
+    decision: TaskDecision,
+) -> ApiResult<(PendingApproval, ApprovalStatus)> {
+    let (action, reason) = match &decision {
+        TaskDecision::Approve => (AuditAction::TaskApproval, None),
+        TaskDecision::Reject { reason } => (AuditAction::TaskRejection, reason.clone()),
+    };
+    let result = match session {
+        Some(session) => {
+            let approver = session.user_id.to_string();
+            let groups = approver_groups(session);
+            match decision {
+                TaskDecision::Approve => ledger.approve(task_id, &approver, &groups),
+                TaskDecision::Reject { reason } => ledger.reject(task_id, &approver, &groups, reason),
+            }
+            .map_err(ApiError::from)
+        }
+        None => Err(ApiError::Unauthorized("No active session".to_string())),
+    };
+    record_task_decision(auditor, session, action, task_id, reason.as_deref(), AuditOutcome::of(&result));
+
+    let status = result?;
+    let pending = ledger.get(task_id).ok_or_else(|| ApiError::from(ApprovalError::NotPending(task_id)))?;
+    Ok((pending, status))
+}
+
+impl From<ApprovalError> for ApiError {
+    fn from(error: ApprovalError) -> Self {
+        let message = error.to_string();
+        match error {
+            ApprovalError::NotPending(_) => ApiError::NotFound(message),
+            ApprovalError::NotAnApprover { .. } => ApiError::Forbidden(message),
+            ApprovalError::AlreadyRejected(_) => ApiError::Conflict(message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use jarvis_core::audit::{AuditFilter, JsonlAuditSink};
+    use jarvis_core::{ApprovalPolicy, ExecutionTask, IntentExecutionPlan, PlanBudget, TaskStatus, TaskType};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    fn session(permissions: &[&str]) -> UserSession {
+        UserSession {
+            user_id: Uuid::new_v4(),
+            session_id: Uuid::new_v4(),
+            created_at: Utc::now(),
+            last_activity: Utc::now(),
+            permissions: permissions.iter().map(|p| p.to_string()).collect(),
+            tenant_id: talkpp_tenancy::default_tenant_id(),
+        }
+    }
+
+    /// A ledger holding one deploy task that two members of `sre` must approve, as an
+    /// executor pausing on it would leave it
+    async fn awaiting_deploy() -> (ApprovalLedger, Uuid) {
+        struct Idle;
+
+        #[axum::async_trait]
+        impl jarvis_core::TaskRunner for Idle {
+            async fn validate(&self, _task: &ExecutionTask) -> anyhow::Result<()> {
+                Ok(())
+            }
+
+            async fn run(&self, _task: &ExecutionTask) -> anyhow::Result<jarvis_core::TaskOutput> {
+                unreachable!("the task is held for approval")
+            }
+        }
+
+        let task = ExecutionTask {
+            id: Uuid::new_v4(),
+            name: "deploy".to_string(),
+            description: String::new(),
+            task_type: TaskType::Execute,
+            agent_type: "deployer".to_string(),
+            inputs: HashMap::new(),
+            expected_outputs: Vec::new(),
+            estimated_duration: chrono::Duration::minutes(1),
+            status: TaskStatus::Pending,
+            dry_run_first: false,
+            tags: Vec::new(),
+            output_schemas: HashMap::new(),
+        };
+        let mut plan = IntentExecutionPlan {
+            id: Uuid::new_v4(),
+            intent_id: Uuid::new_v4(),
+            tasks: vec![task],
+            dependencies: Vec::new(),
+            estimated_duration: chrono::Duration::minutes(1),
+            autonomy_tier: 2,
+            checkpoints: Vec::new(),
+            rollback_plan: None,
+            budget: PlanBudget::default(),
+            domain: None,
+            risk_level: None,
+            environment: None,
+            created_at: Utc::now(),
+        };
+        let policy = ApprovalPolicy::from_json(
+            r#"{ "default": { "effect": "require_approval", "approver_groups": ["sre"], "min_approvers": 2 } }"#,
+        ).unwrap();
+        let ledger = ApprovalLedger::new();
+        jarvis_core::PlanExecutor::new(Idle)
+            .with_approval_policy(policy, ledger.clone())
+            .execute(&mut plan)
+            .await
+            .unwrap();
+        (ledger, plan.tasks[0].id)
+    }
+
+    #[tokio::test]
+    async fn test_only_distinct_group_members_count_towards_release() {
+        let path = std::env::temp_dir().join(format!("api-approvals-{}.jsonl", Uuid::new_v4()));
+        let auditor = Auditor::spawn(Arc::new(JsonlAuditSink::new(&path)), 16);
+        let (ledger, task_id) = awaiting_deploy().await;
+        let (ada, bob, eve) = (session(&["group:sre"]), session(&["group:sre", "audit:read"]), session(&["sre"]));
+        let approve = |session: Option<&UserSession>| decide_task(&ledger, &auditor, session, task_id, TaskDecision::Approve);
+
+        assert!(matches!(approve(None), Err(ApiError::Unauthorized(_))));
+        // `sre` alone is a permission, not membership of the group
+        assert!(matches!(approve(Some(&eve)), Err(ApiError::Forbidden(_))));
+        let (pending, status) = approve(Some(&ada)).unwrap();
+        assert_eq!(status, ApprovalStatus::Pending { approvals: 1, required: 2 });
+        assert_eq!(pending.task.name, "deploy");
+        assert_eq!(approve(Some(&ada)).unwrap().1, ApprovalStatus::Pending { approvals: 1, required: 2 });
+        assert_eq!(approve(Some(&bob)).unwrap().1, ApprovalStatus::Released);
+
+        let reject = decide_task(&ledger, &auditor, Some(&eve), Uuid::new_v4(), TaskDecision::Reject { reason: None });
+        assert!(matches!(reject, Err(ApiError::NotFound(_))));
+
+        auditor.flush().await;
+        let events = auditor.query(&AuditFilter::default()).await.unwrap();
+        std::fs::remove_file(path).ok();
+        assert_eq!(events.len(), 6);
+        assert_eq!(events.iter().filter(|event| event.outcome == AuditOutcome::Success).count(), 3);
+    }
+}
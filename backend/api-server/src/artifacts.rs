@@ -0,0 +1,239 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{FromRef, Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+    routing::get,
+    Router,
+};
+use jarvis_core::artifacts::{ArtifactError, ArtifactInfo, ArtifactStore};
+use serde::Serialize;
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::error::{ApiError, ApiResult};
+use crate::UserSession;
+
+/// Permission a session needs to list and download artifacts
+pub const ARTIFACT_READ_PERMISSION: &str = "artifacts:read";
+
+/// Artifact routes, merged into `/api/v1`
+pub fn routes<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+    Arc<dyn ArtifactStore>: FromRef<S>,
+{
+    Router::new()
+        .route("/plans/:plan_id/artifacts", get(list_plan_artifacts))
+        .route("/artifacts/:artifact_id", get(download_artifact))
+}
+
+fn authorize(session: Option<axum::Extension<UserSession>>) -> ApiResult<UserSession> {
+    let axum::Extension(session) = session
+        .ok_or_else(|| ApiError::Unauthorized("No active session".to_string()))?;
+    if !session.permissions.iter().any(|p| p == ARTIFACT_READ_PERMISSION) {
+        return Err(ApiError::Forbidden(format!("Reading artifacts requires '{}'", ARTIFACT_READ_PERMISSION)));
+    }
+    Ok(session)
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlanArtifactsResponse {
+    pub plan_id: Uuid,
+    pub artifacts: Vec<ArtifactInfo>,
+}
+
+/// Artifacts the plan's tasks produced, oldest first
+#[instrument(skip(store, session))]
+async fn list_plan_artifacts(
+    State(store): State<Arc<dyn ArtifactStore>>,
+    session: Option<axum::Extension<UserSession>>,
+    Path(plan_id): Path<Uuid>,
+) -> ApiResult<Json<PlanArtifactsResponse>> {
+    authorize(session)?;
+    let artifacts = store.list_by_plan(plan_id).await?;
+    Ok(Json(PlanArtifactsResponse { plan_id, artifacts }))
+}
+
+/// An artifact's bytes, or the part of them a `Range: bytes=...` header asks for
+#[instrument(skip(store, session, headers))]
+async fn download_artifact(
+    State(store): State<Arc<dyn ArtifactStore>>,
+    session: Option<axum::Extension<UserSession>>,
+    Path(artifact_id): Path<Uuid>,
+    headers: HeaderMap,
+) -> ApiResult<Response> {
+    authorize(session)?;
+    let artifact = store.get(artifact_id).await.map_err(|e| match e.downcast_ref::<ArtifactError>() {
+        Some(ArtifactError::NotFound(_)) => ApiError::NotFound(format!("Artifact {} not found", artifact_id)),
+        _ => ApiError::from(e),
+    })?;
+    let reference = &artifact.info.reference;
+    let len = artifact.bytes.len() as u64;
+
+    let range = headers.get(header::RANGE).and_then(|value| value.to_str().ok()).map(|value| byte_range(value, len));
+    let (status, body, content_range) = match range {
+        Some(Err(RangeNotSatisfiable)) => {
+            return Ok((
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [(header::CONTENT_RANGE, format!("bytes */{}", len))],
+            ).into_response());
+        }
+        Some(Ok(Some((start, end)))) => (
+            StatusCode::PARTIAL_CONTENT,
+            artifact.bytes[start as usize..=end as usize].to_vec(),
+            Some(format!("bytes {}-{}/{}", start, end, len)),
+        ),
+        Some(Ok(None)) | None => (StatusCode::OK, artifact.bytes.clone(), None),
+    };
+
+    let mut response = Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, &reference.content_type)
+        .header(header::CONTENT_LENGTH, body.len())
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::ETAG, format!("\"{}\"", reference.checksum))
+        .header(header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", reference.name.replace('"', "")));
+    if let Some(content_range) = content_range {
+        response = response.header(header::CONTENT_RANGE, content_range);
+    }
+    response.body(Body::from(body)).map_err(|e| ApiError::InternalError(e.to_string()))
+}
+
+/// A single byte range no byte of a `len`-byte artifact falls in
+#[derive(Debug, PartialEq)]
+struct RangeNotSatisfiable;
+
+/// The inclusive bounds a `Range` header selects from `len` bytes. Headers this server
+/// does not serve partially, such as several ranges or another unit, give `None` and
+/// the whole artifact is sent.
+fn byte_range(header: &str, len: u64) -> Result<Option<(u64, u64)>, RangeNotSatisfiable> {
+    let Some(spec) = header.trim().strip_prefix("bytes=") else {
+        return Ok(None);
+    };
+    if spec.contains(',') {
+        return Ok(None);
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return Ok(None);
+    };
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => match suffix.parse::<u64>() {
+            Ok(0) => return Err(RangeNotSatisfiable),
+            Ok(suffix) => (len.saturating_sub(suffix), len.saturating_sub(1)),
+            Err(_) => return Ok(None),
+        },
+        (start, "") => match start.parse::<u64>() {
+            Ok(start) => (start, len.saturating_sub(1)),
+            Err(_) => return Ok(None),
+        },
+        (start, end) => match (start.parse::<u64>(), end.parse::<u64>()) {
+            (Ok(start), Ok(end)) if start <= end => (start, end.min(len.saturating_sub(1))),
+            _ => return Ok(None),
+        },
+    };
+    if len == 0 || start >= len {
+        return Err(RangeNotSatisfiable);
+    }
+    Ok(Some((start, end)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::Request;
+    use chrono::Utc;
+    use jarvis_core::artifacts::{ArtifactMetadata, FsArtifactStore};
+    use tower::ServiceExt;
+
+    fn session(permissions: &[&str]) -> UserSession {
+        UserSession {
+            user_id: Uuid::new_v4(),
+            session_id: Uuid::new_v4(),
+            created_at: Utc::now(),
+            last_activity: Utc::now(),
+            permissions: permissions.iter().map(|p| p.to_string()).collect(),
+            tenant_id: talkpp_tenancy::default_tenant_id(),
+        }
+    }
+
+    async fn get(app: &Router, uri: &str, range: Option<&str>, session: Option<UserSession>) -> Response {
+        let mut request = Request::builder().uri(uri);
+        if let Some(range) = range {
+            request = request.header(header::RANGE, range);
+        }
+        let mut request = request.body(Body::empty()).unwrap();
+        if let Some(session) = session {
+            request.extensions_mut().insert(session);
+        }
+        app.clone().oneshot(request).await.unwrap()
+    }
+
+    async fn body(response: Response) -> Vec<u8> {
+        axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap().to_vec()
+    }
+
+    #[tokio::test]
+    async fn test_artifacts_are_listed_and_downloaded_in_ranges() {
+        let root = std::env::temp_dir().join(format!("api-artifacts-{}", Uuid::new_v4()));
+        let store: Arc<dyn ArtifactStore> = Arc::new(FsArtifactStore::new(&root));
+        let app = Router::new().merge(routes()).with_state(store.clone());
+        let plan_id = Uuid::new_v4();
+        let plan = store.put(
+            b"0123456789abcdef".to_vec(),
+            ArtifactMetadata::new("deployment-plan.yaml", "application/yaml").with_plan(plan_id),
+        ).await.unwrap();
+        store.put(b"elsewhere".to_vec(), ArtifactMetadata::new("other.txt", "text/plain")).await.unwrap();
+
+        let listing = format!("/plans/{}/artifacts", plan_id);
+        assert_eq!(get(&app, &listing, None, None).await.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(get(&app, &listing, None, Some(session(&[]))).await.status(), StatusCode::FORBIDDEN);
+        let reader = || Some(session(&[ARTIFACT_READ_PERMISSION]));
+        let response = get(&app, &listing, None, reader()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_slice(&body(response).await).unwrap();
+        assert_eq!(json["artifacts"].as_array().unwrap().len(), 1);
+        assert_eq!(json["artifacts"][0]["name"], "deployment-plan.yaml");
+        assert_eq!(json["artifacts"][0]["size"], 16);
+
+        let download = format!("/artifacts/{}", plan.id);
+        assert_eq!(get(&app, &download, None, Some(session(&[]))).await.status(), StatusCode::FORBIDDEN);
+        let response = get(&app, &download, None, reader()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/yaml");
+        assert_eq!(response.headers()[header::ACCEPT_RANGES], "bytes");
+        assert_eq!(body(response).await, b"0123456789abcdef");
+
+        let response = get(&app, &download, Some("bytes=4-7"), reader()).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes 4-7/16");
+        assert_eq!(body(response).await, b"4567");
+        assert_eq!(body(get(&app, &download, Some("bytes=-3"), reader()).await).await, b"def");
+        assert_eq!(body(get(&app, &download, Some("bytes=10-"), reader()).await).await, b"abcdef");
+        let response = get(&app, &download, Some("bytes=16-20"), reader()).await;
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes */16");
+
+        let missing = format!("/artifacts/{}", Uuid::new_v4());
+        assert_eq!(get(&app, &missing, None, reader()).await.status(), StatusCode::NOT_FOUND);
+
+        // A corrupted artifact is never served
+        std::fs::write(root.join(format!("{}.bin", plan.id)), b"0123456789abcdeX").unwrap();
+        assert_eq!(get(&app, &download, None, reader()).await.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        std::fs::remove_dir_all(root).ok();
+    }
+
+    #[test]
+    fn test_byte_ranges() {
+        assert_eq!(byte_range("bytes=0-0", 10), Ok(Some((0, 0))));
+        assert_eq!(byte_range("bytes=5-100", 10), Ok(Some((5, 9))));
+        assert_eq!(byte_range("bytes=-20", 10), Ok(Some((0, 9))));
+        assert_eq!(byte_range("bytes=-0", 10), Err(RangeNotSatisfiable));
+        assert_eq!(byte_range("bytes=0-", 0), Err(RangeNotSatisfiable));
+        assert_eq!(byte_range("bytes=0-1,4-5", 10), Ok(None));
+        assert_eq!(byte_range("items=0-1", 10), Ok(None));
+        assert_eq!(byte_range("bytes=7-3", 10), Ok(None));
+    }
+}
@@ -0,0 +1,243 @@
+use axum::{
+    async_trait,
+    extract::{FromRef, Query, State},
+    response::Json,
+    routing::get,
+    Router,
+};
+use chrono::{DateTime, Utc};
+use jarvis_core::audit::{AuditAction, AuditActor, AuditEvent, AuditFilter, AuditOutcome, AuditSink, Auditor};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, QueryBuilder, Row};
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::error::{ApiError, ApiResult};
+use crate::UserSession;
+
+/// Permission a session needs to read the audit log
+pub const AUDIT_READ_PERMISSION: &str = "audit:read";
+
+const DEFAULT_QUERY_LIMIT: usize = 100;
+const MAX_QUERY_LIMIT: usize = 1000;
+
+/// Audit log routes, mounted under `/api/v1/audit`
+pub fn routes<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+    Auditor: FromRef<S>,
+{
+    Router::new().route("/", get(list_events))
+}
+
+/// Record an approval or rejection attempt on `task_id`, and whether it counted
+pub fn record_task_decision(
+    auditor: &Auditor,
+    session: Option<&UserSession>,
+    action: AuditAction,
+    task_id: Uuid,
+    reason: Option<&str>,
+    outcome: AuditOutcome,
+) {
+    let actor = session.map_or_else(AuditActor::system, UserSession::audit_actor);
+    auditor.record(AuditEvent::new(
+        actor,
+        action,
+        format!("task:{}", task_id),
+        &serde_json::json!({ "task_id": task_id, "reason": reason }),
+        outcome,
+    ));
+}
+
+/// Stores events in the `audit_events` table
+pub struct PostgresAuditSink {
+    pool: PgPool,
+}
+
+impl PostgresAuditSink {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl AuditSink for PostgresAuditSink {
+    async fn write(&self, events: &[AuditEvent]) -> anyhow::Result<()> {
+        let mut insert = QueryBuilder::<Postgres>::new(
+            "INSERT INTO audit_events (id, occurred_at, user_id, session_id, agent_id, action, target, parameters_digest, outcome) ",
+        );
+        insert.push_values(events, |mut row, event| {
+            row.push_bind(event.id)
+                .push_bind(event.timestamp)
+                .push_bind(&event.actor.user_id)
+                .push_bind(&event.actor.session_id)
+                .push_bind(&event.actor.agent_id)
+                .push_bind(event.action.as_str())
+                .push_bind(&event.target)
+                .push_bind(&event.parameters_digest)
+                .push_bind(serde_json::to_value(&event.outcome).unwrap_or_default());
+        });
+        insert.build().execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn query(&self, filter: &AuditFilter) -> anyhow::Result<Vec<AuditEvent>> {
+        let mut select = QueryBuilder::<Postgres>::new(
+            "SELECT id, occurred_at, user_id, session_id, agent_id, action, target, parameters_digest, outcome FROM audit_events WHERE TRUE",
+        );
+        if let Some(actor) = &filter.actor {
+            select.push(" AND (user_id = ").push_bind(actor.clone())
+                .push(" OR session_id = ").push_bind(actor.clone())
+                .push(" OR agent_id = ").push_bind(actor.clone())
+                .push(")");
+        }
+        if let Some(action) = filter.action {
+            select.push(" AND action = ").push_bind(action.as_str());
+        }
+        if let Some(since) = filter.since {
+            select.push(" AND occurred_at >= ").push_bind(since);
+        }
+        if let Some(until) = filter.until {
+            select.push(" AND occurred_at < ").push_bind(until);
+        }
+        select.push(" ORDER BY occurred_at DESC");
+        if let Some(limit) = filter.limit {
+            select.push(" LIMIT ").push_bind(limit as i64);
+        }
+
+        let rows = select.build().fetch_all(&self.pool).await?;
+        rows.into_iter()
+            .map(|row| {
+                Ok(AuditEvent {
+                    id: row.try_get("id")?,
+                    timestamp: row.try_get("occurred_at")?,
+                    actor: AuditActor {
+                        user_id: row.try_get("user_id")?,
+                        session_id: row.try_get("session_id")?,
+                        agent_id: row.try_get("agent_id")?,
+                    },
+                    action: row.try_get::<String, _>("action")?.parse()?,
+                    target: row.try_get("target")?,
+                    parameters_digest: row.try_get("parameters_digest")?,
+                    outcome: serde_json::from_value(row.try_get("outcome")?)?,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Audit log query; times are RFC 3339
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditQuery {
+    /// A user, session or agent id
+    pub actor: Option<String>,
+    pub action: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditEventsResponse {
+    pub events: Vec<AuditEvent>,
+    /// Events this server has lost since it started, because the log could not keep up
+    pub dropped: u64,
+}
+
+/// Audit events matching the query, newest first
+#[instrument(skip(auditor, session))]
+async fn list_events(
+    State(auditor): State<Auditor>,
+    session: Option<axum::Extension<UserSession>>,
+    Query(query): Query<AuditQuery>,
+) -> ApiResult<Json<AuditEventsResponse>> {
+    let axum::Extension(session) = session
+        .ok_or_else(|| ApiError::Unauthorized("No active session".to_string()))?;
+    if !session.permissions.iter().any(|p| p == AUDIT_READ_PERMISSION) {
+        return Err(ApiError::Forbidden(format!("Reading the audit log requires '{}'", AUDIT_READ_PERMISSION)));
+    }
+
+    let action = query.action.as_deref()
+        .map(str::parse)
+        .transpose()
+        .map_err(|e: anyhow::Error| ApiError::BadRequest(e.to_string()))?;
+    let filter = AuditFilter {
+        actor: query.actor,
+        action,
+        since: query.since,
+        until: query.until,
+        limit: Some(query.limit.unwrap_or(DEFAULT_QUERY_LIMIT).min(MAX_QUERY_LIMIT)),
+    };
+
+    let events = auditor.query(&filter).await?;
+    Ok(Json(AuditEventsResponse { events, dropped: auditor.dropped() }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use jarvis_core::audit::JsonlAuditSink;
+    use tower::ServiceExt;
+
+    fn session(permissions: &[&str]) -> UserSession {
+        UserSession {
+            user_id: Uuid::new_v4(),
+            session_id: Uuid::new_v4(),
+            created_at: Utc::now(),
+            last_activity: Utc::now(),
+            permissions: permissions.iter().map(|p| p.to_string()).collect(),
+            tenant_id: talkpp_tenancy::default_tenant_id(),
+        }
+    }
+
+    async fn get(app: &Router, uri: &str, session: Option<UserSession>) -> (StatusCode, serde_json::Value) {
+        let mut request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        if let Some(session) = session {
+            request.extensions_mut().insert(session);
+        }
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap_or(serde_json::Value::Null))
+    }
+
+    #[tokio::test]
+    async fn test_audit_log_is_permission_gated_and_filtered() {
+        let path = std::env::temp_dir().join(format!("api-audit-{}.jsonl", Uuid::new_v4()));
+        let auditor = Auditor::spawn(Arc::new(JsonlAuditSink::new(&path)), 16);
+        let app = Router::new().nest("/audit", routes()).with_state(auditor.clone());
+
+        let alice = session(&[]);
+        for (action, target) in [(AuditAction::ToolCall, "mcp-tool:send"), (AuditAction::TaskApproval, "task:1")] {
+            auditor.record(AuditEvent::new(
+                alice.audit_actor(),
+                action,
+                target,
+                &serde_json::json!({"to": "ops@example.com", "password": "hunter2"}),
+                AuditOutcome::Success,
+            ));
+        }
+        auditor.flush().await;
+
+        assert_eq!(get(&app, "/audit", None).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(get(&app, "/audit", Some(alice.clone())).await.0, StatusCode::FORBIDDEN);
+
+        let auditor_session = || Some(session(&[AUDIT_READ_PERMISSION]));
+        let uri = format!("/audit?actor={}&action=tool_call", alice.user_id);
+        let (status, json) = get(&app, &uri, auditor_session()).await;
+        assert_eq!(status, StatusCode::OK, "{}", json);
+        assert_eq!(json["events"].as_array().unwrap().len(), 1);
+        assert_eq!(json["events"][0]["target"], "mcp-tool:send");
+        assert_eq!(json["events"][0]["parameters_digest"]["password"], "[REDACTED]");
+        assert_eq!(json["dropped"], 0);
+
+        let (_, json) = get(&app, "/audit?since=2999-01-01T00:00:00Z", auditor_session()).await;
+        assert!(json["events"].as_array().unwrap().is_empty());
+        assert_eq!(get(&app, "/audit?action=delete_everything", auditor_session()).await.0, StatusCode::BAD_REQUEST);
+        std::fs::remove_file(path).ok();
+    }
+}
@@ -0,0 +1,1063 @@
+//! Bulk intent processing
+//!
+//! `POST /intents/batch` stores a batch of intents and queues one job per intent,
+//! answering with the batch id straight away. Workers started with [`worker_loop`] take
+//! jobs off the queue and plan each intent the way `POST /intents` does, executing the
+//! plan as well when the processor is configured to and the plan needs no approval.
+//!
+//! A worker holds a job under a lease that expires after the visibility timeout. Should
+//! the worker die, the job goes back on the queue once the lease expires and another
+//! worker picks it up; a worker whose lease expired can no longer record a result, so
+//! every item ends with exactly one result. Items whose workers died too often are
+//! failed rather than retried forever.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use axum::{
+    extract::{Extension, FromRef, Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Json},
+    routing::{get, post},
+    Router,
+};
+use chrono::{DateTime, Utc};
+use jarvis_core::{AuditActor, CognitiveKernel, Environment, ExecutionContext, ExecutionTask, PlanExecutor, TaskOutput, TaskRunner};
+use redis::aio::ConnectionManager;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, watch};
+use tokio::time::Instant;
+use tracing::{error, instrument, warn};
+use uuid::Uuid;
+
+use crate::error::{ApiError, ApiResult};
+use crate::notifications::Notifier;
+use crate::preferences::{PolicyOutcome, Preferences};
+use crate::{ProcessIntentResponse, UserSession};
+
+/// Leases an item may be taken under before it is failed instead of queued again
+pub const MAX_ATTEMPTS: u32 = 3;
+
+/// Items returned per page when the request doesn't say
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 500;
+
+/// How long an idle worker waits before looking at the queue again
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemStatus {
+    Queued,
+    Processing,
+    Succeeded,
+    Failed,
+    /// Still queued when its batch was cancelled
+    Skipped,
+}
+
+impl ItemStatus {
+    pub fn is_finished(self) -> bool {
+        matches!(self, ItemStatus::Succeeded | ItemStatus::Failed | ItemStatus::Skipped)
+    }
+}
+
+/// A submitted batch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchInfo {
+    pub id: Uuid,
+    /// User who submitted the batch; only they can see or cancel it
+    pub owner: Option<Uuid>,
+    pub total: usize,
+    pub created_at: DateTime<Utc>,
+    pub cancelled_at: Option<DateTime<Utc>>,
+}
+
+/// One intent of a batch and what became of it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchItem {
+    pub index: usize,
+    pub intent: String,
+    pub status: ItemStatus,
+    /// Leases the item was taken under, including the current one
+    pub attempts: u32,
+    /// The plan, as `POST /intents` would have returned it
+    pub plan: Option<serde_json::Value>,
+    /// Outcome of executing the plan, for plans that were executed
+    pub execution: Option<serde_json::Value>,
+    pub error: Option<String>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+impl BatchItem {
+    fn queued(index: usize, intent: String) -> Self {
+        Self {
+            index,
+            intent,
+            status: ItemStatus::Queued,
+            attempts: 0,
+            plan: None,
+            execution: None,
+            error: None,
+            finished_at: None,
+        }
+    }
+
+    fn succeeded(mut self, output: ItemOutput) -> Self {
+        self.status = ItemStatus::Succeeded;
+        self.plan = Some(output.plan);
+        self.execution = output.execution;
+        self.finished_at = Some(Utc::now());
+        self
+    }
+
+    fn failed(mut self, error: String) -> Self {
+        self.status = ItemStatus::Failed;
+        self.error = Some(error);
+        self.finished_at = Some(Utc::now());
+        self
+    }
+}
+
+/// Item counts of a batch by status
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchProgress {
+    pub total: usize,
+    pub queued: usize,
+    pub processing: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    /// Every item has finished
+    pub done: bool,
+}
+
+impl BatchProgress {
+    fn of<'a>(items: impl IntoIterator<Item = &'a BatchItem>) -> Self {
+        let mut progress = Self::default();
+        for item in items {
+            progress.total += 1;
+            match item.status {
+                ItemStatus::Queued => progress.queued += 1,
+                ItemStatus::Processing => progress.processing += 1,
+                ItemStatus::Succeeded => progress.succeeded += 1,
+                ItemStatus::Failed => progress.failed += 1,
+                ItemStatus::Skipped => progress.skipped += 1,
+            }
+        }
+        progress.done = progress.queued + progress.processing == 0;
+        progress
+    }
+}
+
+/// An item taken off the queue by a worker
+#[derive(Debug, Clone)]
+pub struct Lease {
+    pub batch_id: Uuid,
+    pub owner: Option<Uuid>,
+    /// The item, already marked `Processing` with this lease counted in its attempts
+    pub item: BatchItem,
+    /// Identifies this lease among all leases of the item
+    token: u64,
+}
+
+#[async_trait]
+pub trait BatchQueue: Send + Sync {
+    /// Store a new batch and queue each of its intents
+    async fn submit(&self, batch: &BatchInfo, intents: Vec<String>) -> Result<()>;
+
+    /// Take the next queued item, hiding it from other workers for `visibility`. Items of
+    /// leases that expired are queued again first.
+    async fn lease(&self, visibility: Duration) -> Result<Option<Lease>>;
+
+    /// Record the final state of a leased item. Returns false, recording nothing, when
+    /// the lease expired in the meantime.
+    async fn finish(&self, lease: &Lease, item: BatchItem) -> Result<bool>;
+
+    async fn batch(&self, batch_id: Uuid) -> Result<Option<BatchInfo>>;
+
+    async fn progress(&self, batch_id: Uuid) -> Result<BatchProgress>;
+
+    /// Up to `limit` items starting at index `offset`
+    async fn items(&self, batch_id: Uuid, offset: usize, limit: usize) -> Result<Vec<BatchItem>>;
+
+    /// Mark the batch cancelled and its queued items skipped. Items being processed
+    /// still finish. Returns how many items were skipped.
+    async fn cancel(&self, batch_id: Uuid) -> Result<usize>;
+}
+
+/// Checks run on every leased item before it is handed to a worker. Returns the item to
+/// record instead when it should not be processed.
+fn vet_lease(item: &BatchItem) -> Option<BatchItem> {
+    (item.attempts > MAX_ATTEMPTS).then(|| {
+        item.clone().failed(format!("Abandoned after {} attempts", MAX_ATTEMPTS))
+    })
+}
+
+/// Queue and batches in Redis, shared by every api-server replica and kept across restarts.
+///
+/// Jobs wait in a list and move to a sorted set, scored by lease expiry, while leased;
+/// Lua scripts make taking and finishing a lease atomic. Each batch is a JSON record
+/// plus a hash of its items by index, both expiring `retention` after submission.
+pub struct RedisBatchQueue {
+    connection: ConnectionManager,
+    namespace: String,
+    retention: Duration,
+}
+
+/// Requeue expired leases at the head of the queue, then lease the next job.
+/// KEYS: queue, leases. ARGV: now, lease expiry (unix ms).
+const LEASE_SCRIPT: &str = r#"
+local expired = redis.call('ZRANGEBYSCORE', KEYS[2], '-inf', ARGV[1])
+for _, job in ipairs(expired) do
+    redis.call('ZREM', KEYS[2], job)
+    redis.call('LPUSH', KEYS[1], job)
+end
+local job = redis.call('LPOP', KEYS[1])
+if not job then
+    return false
+end
+redis.call('ZADD', KEYS[2], ARGV[2], job)
+return job
+"#;
+
+/// Store an item if its lease is still the one held.
+/// KEYS: leases, items. ARGV: job, lease expiry, item index, item.
+const FINISH_SCRIPT: &str = r#"
+if tonumber(redis.call('ZSCORE', KEYS[1], ARGV[1])) ~= tonumber(ARGV[2]) then
+    return 0
+end
+redis.call('ZREM', KEYS[1], ARGV[1])
+redis.call('HSET', KEYS[2], ARGV[3], ARGV[4])
+return 1
+"#;
+
+impl RedisBatchQueue {
+    pub async fn new(client: &redis::Client, retention: Duration) -> Result<Self> {
+        Ok(Self {
+            connection: client.get_connection_manager().await?,
+            namespace: "batch".to_string(),
+            retention,
+        })
+    }
+
+    /// Keep keys under `namespace` instead of `batch`
+    pub fn with_namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = namespace.into();
+        self
+    }
+
+    fn queue_key(&self) -> String {
+        format!("{}:queue", self.namespace)
+    }
+
+    fn leases_key(&self) -> String {
+        format!("{}:leases", self.namespace)
+    }
+
+    fn batch_key(&self, batch_id: Uuid) -> String {
+        format!("{}:{}", self.namespace, batch_id)
+    }
+
+    fn items_key(&self, batch_id: Uuid) -> String {
+        format!("{}:{}:items", self.namespace, batch_id)
+    }
+
+    fn job(batch_id: Uuid, index: usize) -> String {
+        format!("{}:{}", batch_id, index)
+    }
+
+    fn parse_job(job: &str) -> Option<(Uuid, usize)> {
+        let (batch_id, index) = job.rsplit_once(':')?;
+        Some((batch_id.parse().ok()?, index.parse().ok()?))
+    }
+
+    async fn item(&self, batch_id: Uuid, index: usize) -> Result<Option<BatchItem>> {
+        let mut connection = self.connection.clone();
+        let item: Option<String> = redis::cmd("HGET")
+            .arg(self.items_key(batch_id))
+            .arg(index)
+            .query_async(&mut connection)
+            .await?;
+        item.map(|item| serde_json::from_str(&item)).transpose().map_err(Into::into)
+    }
+
+    async fn put_item(&self, batch_id: Uuid, item: &BatchItem) -> Result<()> {
+        let mut connection = self.connection.clone();
+        redis::cmd("HSET")
+            .arg(self.items_key(batch_id))
+            .arg(item.index)
+            .arg(serde_json::to_string(item)?)
+            .query_async::<_, ()>(&mut connection)
+            .await?;
+        Ok(())
+    }
+
+    async fn drop_lease(&self, job: &str) -> Result<()> {
+        let mut connection = self.connection.clone();
+        redis::cmd("ZREM").arg(self.leases_key()).arg(job).query_async::<_, ()>(&mut connection).await?;
+        Ok(())
+    }
+
+    async fn all_items(&self, batch_id: Uuid) -> Result<Vec<BatchItem>> {
+        let mut connection = self.connection.clone();
+        let items: Vec<String> = redis::cmd("HVALS").arg(self.items_key(batch_id)).query_async(&mut connection).await?;
+        items.iter().map(|item| serde_json::from_str(item).map_err(Into::into)).collect()
+    }
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or_default()
+}
+
+#[async_trait]
+impl BatchQueue for RedisBatchQueue {
+    async fn submit(&self, batch: &BatchInfo, intents: Vec<String>) -> Result<()> {
+        let mut connection = self.connection.clone();
+        let ttl = self.retention.as_secs().max(1);
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        pipe.cmd("SET").arg(self.batch_key(batch.id)).arg(serde_json::to_string(batch)?).arg("EX").arg(ttl).ignore();
+        for (index, intent) in intents.into_iter().enumerate() {
+            let item = serde_json::to_string(&BatchItem::queued(index, intent))?;
+            pipe.cmd("HSET").arg(self.items_key(batch.id)).arg(index).arg(item).ignore();
+        }
+        pipe.cmd("EXPIRE").arg(self.items_key(batch.id)).arg(ttl).ignore();
+        let jobs: Vec<String> = (0..batch.total).map(|index| Self::job(batch.id, index)).collect();
+        if !jobs.is_empty() {
+            pipe.cmd("RPUSH").arg(self.queue_key()).arg(jobs).ignore();
+        }
+        pipe.query_async::<_, ()>(&mut connection).await?;
+        Ok(())
+    }
+
+    async fn lease(&self, visibility: Duration) -> Result<Option<Lease>> {
+        let script = redis::Script::new(LEASE_SCRIPT);
+        loop {
+            let mut connection = self.connection.clone();
+            let now = unix_millis();
+            let token = now + visibility.as_millis() as u64;
+            let job: Option<String> = script
+                .key(self.queue_key())
+                .key(self.leases_key())
+                .arg(now)
+                .arg(token)
+                .invoke_async(&mut connection)
+                .await?;
+            let Some(job) = job else {
+                return Ok(None);
+            };
+
+            // Jobs of expired batches and items finished since they were queued are dropped
+            let Some((batch_id, index)) = Self::parse_job(&job) else {
+                warn!("Dropping malformed batch job '{}'", job);
+                self.drop_lease(&job).await?;
+                continue;
+            };
+            let (Some(batch), Some(mut item)) = (self.batch(batch_id).await?, self.item(batch_id, index).await?) else {
+                self.drop_lease(&job).await?;
+                continue;
+            };
+            if item.status.is_finished() {
+                self.drop_lease(&job).await?;
+                continue;
+            }
+
+            item.status = ItemStatus::Processing;
+            item.attempts += 1;
+            self.put_item(batch_id, &item).await?;
+            let lease = Lease { batch_id, owner: batch.owner, item, token };
+            if let Some(abandoned) = vet_lease(&lease.item) {
+                self.finish(&lease, abandoned).await?;
+                continue;
+            }
+            return Ok(Some(lease));
+        }
+    }
+
+    async fn finish(&self, lease: &Lease, item: BatchItem) -> Result<bool> {
+        let mut connection = self.connection.clone();
+        let stored: i32 = redis::Script::new(FINISH_SCRIPT)
+            .key(self.leases_key())
+            .key(self.items_key(lease.batch_id))
+            .arg(Self::job(lease.batch_id, item.index))
+            .arg(lease.token)
+            .arg(item.index)
+            .arg(serde_json::to_string(&item)?)
+            .invoke_async(&mut connection)
+            .await?;
+        Ok(stored == 1)
+    }
+
+    async fn batch(&self, batch_id: Uuid) -> Result<Option<BatchInfo>> {
+        let mut connection = self.connection.clone();
+        let batch: Option<String> = redis::cmd("GET").arg(self.batch_key(batch_id)).query_async(&mut connection).await?;
+        batch.map(|batch| serde_json::from_str(&batch)).transpose().map_err(Into::into)
+    }
+
+    async fn progress(&self, batch_id: Uuid) -> Result<BatchProgress> {
+        Ok(BatchProgress::of(&self.all_items(batch_id).await?))
+    }
+
+    async fn items(&self, batch_id: Uuid, offset: usize, limit: usize) -> Result<Vec<BatchItem>> {
+        let Some(batch) = self.batch(batch_id).await? else {
+            return Ok(Vec::new());
+        };
+        let indices: Vec<usize> = (offset..batch.total.min(offset.saturating_add(limit))).collect();
+        if indices.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut connection = self.connection.clone();
+        let items: Vec<Option<String>> = redis::cmd("HMGET")
+            .arg(self.items_key(batch_id))
+            .arg(indices)
+            .query_async(&mut connection)
+            .await?;
+        items.into_iter().flatten().map(|item| serde_json::from_str(&item).map_err(Into::into)).collect()
+    }
+
+    async fn cancel(&self, batch_id: Uuid) -> Result<usize> {
+        let Some(mut batch) = self.batch(batch_id).await? else {
+            return Ok(0);
+        };
+        if batch.cancelled_at.is_none() {
+            batch.cancelled_at = Some(Utc::now());
+            let mut connection = self.connection.clone();
+            redis::cmd("SET")
+                .arg(self.batch_key(batch_id))
+                .arg(serde_json::to_string(&batch)?)
+                .arg("KEEPTTL")
+                .query_async::<_, ()>(&mut connection)
+                .await?;
+        }
+
+        // Their jobs stay queued and are dropped when leased
+        let mut skipped = 0;
+        for mut item in self.all_items(batch_id).await? {
+            if item.status == ItemStatus::Queued {
+                item.status = ItemStatus::Skipped;
+                item.finished_at = Some(Utc::now());
+                self.put_item(batch_id, &item).await?;
+                skipped += 1;
+            }
+        }
+        Ok(skipped)
+    }
+}
+
+#[derive(Default)]
+struct MemoryQueueState {
+    batches: HashMap<Uuid, (BatchInfo, Vec<BatchItem>)>,
+    queue: VecDeque<(Uuid, usize)>,
+    /// Leased jobs with their token and expiry
+    leases: HashMap<(Uuid, usize), (u64, Instant)>,
+    next_token: u64,
+}
+
+/// Process-local queue, for tests and single-instance deployments. Batches are lost on restart.
+#[derive(Default)]
+pub struct MemoryBatchQueue {
+    state: Mutex<MemoryQueueState>,
+}
+
+impl MemoryBatchQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl BatchQueue for MemoryBatchQueue {
+    async fn submit(&self, batch: &BatchInfo, intents: Vec<String>) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let items: Vec<BatchItem> = intents.into_iter().enumerate().map(|(index, intent)| BatchItem::queued(index, intent)).collect();
+        state.queue.extend((0..items.len()).map(|index| (batch.id, index)));
+        state.batches.insert(batch.id, (batch.clone(), items));
+        Ok(())
+    }
+
+    async fn lease(&self, visibility: Duration) -> Result<Option<Lease>> {
+        loop {
+            let (lease, abandoned) = {
+                let mut state = self.state.lock().unwrap();
+                let now = Instant::now();
+                let expired: Vec<_> = state.leases.iter().filter(|(_, (_, expiry))| *expiry <= now).map(|(job, _)| *job).collect();
+                for job in expired {
+                    state.leases.remove(&job);
+                    state.queue.push_front(job);
+                }
+
+                let Some((batch_id, index)) = state.queue.pop_front() else {
+                    return Ok(None);
+                };
+                state.next_token += 1;
+                let token = state.next_token;
+                let Some((batch, items)) = state.batches.get_mut(&batch_id) else {
+                    continue;
+                };
+                let owner = batch.owner;
+                let item = &mut items[index];
+                if item.status.is_finished() {
+                    continue;
+                }
+                item.status = ItemStatus::Processing;
+                item.attempts += 1;
+                let lease = Lease { batch_id, owner, item: item.clone(), token };
+                state.leases.insert((batch_id, index), (token, now + visibility));
+                let abandoned = vet_lease(&lease.item);
+                (lease, abandoned)
+            };
+            match abandoned {
+                Some(abandoned) => {
+                    self.finish(&lease, abandoned).await?;
+                }
+                None => return Ok(Some(lease)),
+            }
+        }
+    }
+
+    async fn finish(&self, lease: &Lease, item: BatchItem) -> Result<bool> {
+        let mut state = self.state.lock().unwrap();
+        let job = (lease.batch_id, item.index);
+        if !matches!(state.leases.get(&job), Some((token, _)) if *token == lease.token) {
+            return Ok(false);
+        }
+        state.leases.remove(&job);
+        if let Some((_, items)) = state.batches.get_mut(&lease.batch_id) {
+            let index = item.index;
+            items[index] = item;
+        }
+        Ok(true)
+    }
+
+    async fn batch(&self, batch_id: Uuid) -> Result<Option<BatchInfo>> {
+        Ok(self.state.lock().unwrap().batches.get(&batch_id).map(|(batch, _)| batch.clone()))
+    }
+
+    async fn progress(&self, batch_id: Uuid) -> Result<BatchProgress> {
+        let state = self.state.lock().unwrap();
+        Ok(state.batches.get(&batch_id).map(|(_, items)| BatchProgress::of(items)).unwrap_or_default())
+    }
+
+    async fn items(&self, batch_id: Uuid, offset: usize, limit: usize) -> Result<Vec<BatchItem>> {
+        let state = self.state.lock().unwrap();
+        Ok(state.batches.get(&batch_id)
+            .map(|(_, items)| items.iter().skip(offset).take(limit).cloned().collect())
+            .unwrap_or_default())
+    }
+
+    async fn cancel(&self, batch_id: Uuid) -> Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        let Some((batch, items)) = state.batches.get_mut(&batch_id) else {
+            return Ok(0);
+        };
+        batch.cancelled_at.get_or_insert_with(Utc::now);
+        let mut skipped = 0;
+        for item in items.iter_mut().filter(|item| item.status == ItemStatus::Queued) {
+            item.status = ItemStatus::Skipped;
+            item.finished_at = Some(Utc::now());
+            skipped += 1;
+        }
+        Ok(skipped)
+    }
+}
+
+/// What processing an intent produced
+#[derive(Debug, Clone)]
+pub struct ItemOutput {
+    pub plan: serde_json::Value,
+    pub execution: Option<serde_json::Value>,
+}
+
+/// Turns one intent of a batch into its result
+#[async_trait]
+pub trait IntentProcessor: Send + Sync {
+    async fn process(&self, intent: &str, owner: Option<Uuid>) -> Result<ItemOutput>;
+}
+
+/// Plans intents with the cognitive kernel and, with `with_execution`, executes the
+/// plans that need no approval
+pub struct KernelProcessor {
+    kernel: Arc<CognitiveKernel>,
+    runner: Option<Arc<dyn TaskRunner>>,
+    preferences: Option<Preferences>,
+    notifier: Notifier,
+    /// Environments plans run here may target; `None` allows any
+    allowed_environments: Option<Vec<Environment>>,
+}
+
+impl KernelProcessor {
+    pub fn new(kernel: Arc<CognitiveKernel>) -> Self {
+        Self { kernel, runner: None, preferences: None, notifier: Notifier::disabled(), allowed_environments: None }
+    }
+
+    /// Email owners when their plans wait for approval, complete or fail
+    pub fn with_notifier(mut self, notifier: Notifier) -> Self {
+        self.notifier = notifier;
+        self
+    }
+
+    /// Hold plans to their owner's preferences, as `POST /intents` does, before deciding
+    /// whether they need approval
+    pub fn with_preferences(mut self, preferences: Preferences) -> Self {
+        self.preferences = Some(preferences);
+        self
+    }
+
+    /// Run the tasks of low-risk plans, those `POST /intents` reports as not requiring
+    /// approval, with `runner`
+    pub fn with_execution(mut self, runner: Arc<dyn TaskRunner>) -> Self {
+        self.runner = Some(runner);
+        self
+    }
+
+    /// Refuse to run plans aimed at environments other than `environments`
+    pub fn with_allowed_environments(mut self, environments: Option<Vec<Environment>>) -> Self {
+        self.allowed_environments = environments;
+        self
+    }
+}
+
+/// Lets a shared runner drive a `PlanExecutor`
+pub(crate) struct SharedRunner(pub(crate) Arc<dyn TaskRunner>);
+
+#[async_trait]
+impl TaskRunner for SharedRunner {
+    async fn validate(&self, task: &ExecutionTask) -> Result<()> {
+        self.0.validate(task).await
+    }
+
+    async fn run(&self, task: &ExecutionTask) -> Result<TaskOutput> {
+        self.0.run(task).await
+    }
+
+    async fn run_streaming(&self, task: &ExecutionTask, deltas: mpsc::UnboundedSender<String>) -> Result<TaskOutput> {
+        self.0.run_streaming(task, deltas).await
+    }
+}
+
+#[async_trait]
+impl IntentProcessor for KernelProcessor {
+    async fn process(&self, intent: &str, owner: Option<Uuid>) -> Result<ItemOutput> {
+        let context = owner.map(|owner| ExecutionContext::new(Uuid::nil()).with_user(owner.to_string()));
+        let (intent, mut plan) = self.kernel.plan_intent(intent, context).await?;
+        let policy = match &self.preferences {
+            Some(preferences) => {
+                // Batch items carry no session, so decisions are audited against the owner alone
+                let owner = owner.map(|owner| (owner, AuditActor { user_id: Some(owner.to_string()), ..AuditActor::system() }));
+                preferences.enforce(owner, None, &intent, &mut plan).await?
+            }
+            None => PolicyOutcome::default(),
+        };
+        let response = ProcessIntentResponse::new(&intent, &plan).with_policy(policy);
+
+        if response.requires_approval {
+            self.notifier.approval_requested(owner, &intent, &plan);
+        }
+        let execution = match &self.runner {
+            Some(runner) if !response.requires_approval => {
+                let mut executor = PlanExecutor::new(SharedRunner(runner.clone()));
+                if let Some(allowed) = &self.allowed_environments {
+                    executor = executor.with_allowed_environments(allowed.clone());
+                }
+                let outcome = executor.execute(&mut plan).await?;
+                self.notifier.plan_finished(owner, &intent, &plan, &outcome);
+                Some(serde_json::to_value(outcome)?)
+            }
+            _ => None,
+        };
+        Ok(ItemOutput { plan: serde_json::to_value(response)?, execution })
+    }
+}
+
+/// Process one leased item. Returns false when the queue was empty.
+async fn work_once(queue: &dyn BatchQueue, processor: &dyn IntentProcessor, visibility: Duration) -> Result<bool> {
+    let Some(lease) = queue.lease(visibility).await? else {
+        return Ok(false);
+    };
+
+    // Past the visibility timeout another worker may take the item, so give up before then
+    let item = lease.item.clone();
+    let finished = match tokio::time::timeout(visibility, processor.process(&item.intent, lease.owner)).await {
+        Ok(Ok(output)) => item.succeeded(output),
+        Ok(Err(e)) => item.failed(e.to_string()),
+        Err(_) => item.failed(format!("Timed out after {:?}", visibility)),
+    };
+    if !queue.finish(&lease, finished).await? {
+        warn!("Lease on item {} of batch {} expired before it finished", lease.item.index, lease.batch_id);
+    }
+    Ok(true)
+}
+
+/// Process queued items until told to stop. Start as many as items should run in parallel.
+pub async fn worker_loop(
+    queue: Arc<dyn BatchQueue>,
+    processor: Arc<dyn IntentProcessor>,
+    visibility: Duration,
+    mut stop: watch::Receiver<bool>,
+) {
+    while !*stop.borrow() {
+        let idle = match work_once(queue.as_ref(), processor.as_ref(), visibility).await {
+            Ok(worked) => !worked,
+            Err(e) => {
+                error!("Batch worker failed: {}", e);
+                true
+            }
+        };
+        if idle {
+            tokio::select! {
+                _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                _ = stop.wait_for(|s| *s) => break,
+            }
+        }
+    }
+}
+
+/// Shared handle to the batch queue, used as route state
+#[derive(Clone)]
+pub struct Batches {
+    queue: Arc<dyn BatchQueue>,
+    max_items: usize,
+}
+
+impl Batches {
+    pub fn new(queue: Arc<dyn BatchQueue>, max_items: usize) -> Self {
+        Self { queue, max_items }
+    }
+
+    pub fn queue(&self) -> &Arc<dyn BatchQueue> {
+        &self.queue
+    }
+
+    /// The batch, if it exists and belongs to the session's user
+    async fn owned(&self, batch_id: Uuid, session: Option<&UserSession>) -> ApiResult<BatchInfo> {
+        self.queue.batch(batch_id).await?
+            .filter(|batch| batch.owner.is_none() || batch.owner == session.map(|s| s.user_id))
+            .ok_or_else(|| ApiError::NotFound(format!("Batch {} not found", batch_id)))
+    }
+}
+
+/// Batch routes, merged into `/api/v1`
+pub fn routes<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+    Batches: FromRef<S>,
+{
+    Router::new()
+        .route("/intents/batch", post(submit_batch))
+        .route("/intents/batch/:batch_id", get(get_batch))
+        .route("/intents/batch/:batch_id/cancel", post(cancel_batch))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SubmitBatchRequest {
+    pub intents: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SubmitBatchResponse {
+    pub batch_id: Uuid,
+    pub total: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PageQuery {
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchResponse {
+    pub batch_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub cancelled_at: Option<DateTime<Utc>>,
+    pub progress: BatchProgress,
+    pub offset: usize,
+    pub limit: usize,
+    pub items: Vec<BatchItem>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CancelBatchResponse {
+    pub batch_id: Uuid,
+    pub skipped: usize,
+    pub progress: BatchProgress,
+}
+
+/// Queue a batch of intents for the workers
+#[instrument(skip(batches, session, request), fields(intents = request.intents.len()))]
+async fn submit_batch(
+    State(batches): State<Batches>,
+    session: Option<Extension<UserSession>>,
+    Json(request): Json<SubmitBatchRequest>,
+) -> ApiResult<impl IntoResponse> {
+    if request.intents.is_empty() {
+        return Err(ApiError::BadRequest("A batch needs at least one intent".to_string()));
+    }
+    if request.intents.len() > batches.max_items {
+        return Err(ApiError::BadRequest(format!("A batch holds at most {} intents", batches.max_items)));
+    }
+    if let Some(index) = request.intents.iter().position(|intent| intent.trim().is_empty()) {
+        return Err(ApiError::BadRequest(format!("Intent {} is empty", index)));
+    }
+
+    let batch = BatchInfo {
+        id: Uuid::new_v4(),
+        owner: session.map(|Extension(session)| session.user_id),
+        total: request.intents.len(),
+        created_at: Utc::now(),
+        cancelled_at: None,
+    };
+    batches.queue.submit(&batch, request.intents).await?;
+    Ok((StatusCode::ACCEPTED, Json(SubmitBatchResponse { batch_id: batch.id, total: batch.total })))
+}
+
+/// Progress of a batch and a page of its items, in submission order
+#[instrument(skip(batches, session))]
+async fn get_batch(
+    State(batches): State<Batches>,
+    session: Option<Extension<UserSession>>,
+    Path(batch_id): Path<Uuid>,
+    Query(page): Query<PageQuery>,
+) -> ApiResult<Json<BatchResponse>> {
+    let batch = batches.owned(batch_id, session.as_deref()).await?;
+    let offset = page.offset.unwrap_or(0);
+    let limit = page.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    Ok(Json(BatchResponse {
+        batch_id,
+        created_at: batch.created_at,
+        cancelled_at: batch.cancelled_at,
+        progress: batches.queue.progress(batch_id).await?,
+        offset,
+        limit,
+        items: batches.queue.items(batch_id, offset, limit).await?,
+    }))
+}
+
+/// Skip the batch's items that have not been taken by a worker yet
+#[instrument(skip(batches, session))]
+async fn cancel_batch(
+    State(batches): State<Batches>,
+    session: Option<Extension<UserSession>>,
+    Path(batch_id): Path<Uuid>,
+) -> ApiResult<Json<CancelBatchResponse>> {
+    batches.owned(batch_id, session.as_deref()).await?;
+    let skipped = batches.queue.cancel(batch_id).await?;
+    Ok(Json(CancelBatchResponse { batch_id, skipped, progress: batches.queue.progress(batch_id).await? }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::{Method, Request}};
+    use jarvis_core::TaskStatus;
+    use tower::ServiceExt;
+
+    const VISIBILITY: Duration = Duration::from_millis(300);
+
+    /// Answers with the intent, failing intents that mention "fail", and counts calls per intent
+    #[derive(Default)]
+    struct EchoProcessor {
+        calls: Mutex<HashMap<String, usize>>,
+    }
+
+    #[async_trait]
+    impl IntentProcessor for EchoProcessor {
+        async fn process(&self, intent: &str, _owner: Option<Uuid>) -> Result<ItemOutput> {
+            *self.calls.lock().unwrap().entry(intent.to_string()).or_default() += 1;
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            if intent.contains("fail") {
+                anyhow::bail!("could not plan '{}'", intent);
+            }
+            Ok(ItemOutput { plan: serde_json::json!({ "intent": intent }), execution: None })
+        }
+    }
+
+    fn session() -> UserSession {
+        UserSession {
+            user_id: Uuid::new_v4(),
+            session_id: Uuid::new_v4(),
+            created_at: Utc::now(),
+            last_activity: Utc::now(),
+            permissions: Vec::new(),
+            tenant_id: talkpp_tenancy::default_tenant_id(),
+        }
+    }
+
+    async fn call(app: &Router, method: Method, uri: &str, body: Option<serde_json::Value>, session: &UserSession) -> (StatusCode, serde_json::Value) {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(body.map(|b| Body::from(b.to_string())).unwrap_or_default())
+            .unwrap();
+        request.extensions_mut().insert(session.clone());
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    async fn submit(app: &Router, intents: &[&str], session: &UserSession) -> Uuid {
+        let (status, body) = call(app, Method::POST, "/intents/batch", Some(serde_json::json!({ "intents": intents })), session).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(body["total"], intents.len());
+        body["batch_id"].as_str().unwrap().parse().unwrap()
+    }
+
+    /// Wait for the batch to finish and return its final state
+    async fn finished(app: &Router, batch_id: Uuid, session: &UserSession) -> serde_json::Value {
+        for _ in 0..200 {
+            let (_, body) = call(app, Method::GET, &format!("/intents/batch/{}", batch_id), None, session).await;
+            if body["progress"]["done"] == true {
+                return body;
+            }
+            tokio::time::sleep(Duration::from_millis(25)).await;
+        }
+        panic!("batch {} did not finish", batch_id);
+    }
+
+    /// Submits a batch, lets one worker "crash" holding a lease, and checks that restarted
+    /// workers report progress accurately and process every item exactly once
+    async fn assert_progress_and_restart_recovery(queue: Arc<dyn BatchQueue>) {
+        let app = Router::new().merge(routes()).with_state(Batches::new(queue.clone(), 10));
+        let user = session();
+        let intents = ["read the config", "list my repos", "fail on purpose", "summarise the logs", "read the docs"];
+        let batch_id = submit(&app, &intents, &user).await;
+
+        // A worker takes the first item and dies with it
+        let crashed = queue.lease(VISIBILITY).await.unwrap().unwrap();
+        assert_eq!((crashed.batch_id, crashed.item.index, crashed.item.attempts), (batch_id, 0, 1));
+        let (status, body) = call(&app, Method::GET, &format!("/intents/batch/{}", batch_id), None, &user).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["progress"]["queued"], 4);
+        assert_eq!(body["progress"]["processing"], 1);
+        assert_eq!(body["progress"]["done"], false);
+
+        // Workers of the restarted server
+        let processor = Arc::new(EchoProcessor::default());
+        let (stop, signal) = watch::channel(false);
+        let workers: Vec<_> = (0..2)
+            .map(|_| tokio::spawn(worker_loop(queue.clone(), processor.clone(), VISIBILITY, signal.clone())))
+            .collect();
+
+        let body = finished(&app, batch_id, &user).await;
+        assert_eq!(body["progress"], serde_json::json!({
+            "total": 5, "queued": 0, "processing": 0, "succeeded": 4, "failed": 1, "skipped": 0, "done": true,
+        }));
+        let items = body["items"].as_array().unwrap();
+        assert_eq!(items[0]["attempts"], 2);
+        assert_eq!(items[0]["plan"]["intent"], "read the config");
+        assert_eq!(items[2]["status"], "failed");
+        assert_eq!(items[2]["error"], "could not plan 'fail on purpose'");
+        assert!(processor.calls.lock().unwrap().values().all(|calls| *calls == 1));
+
+        // The crashed worker comes back too late to record anything
+        assert!(!queue.finish(&crashed, crashed.item.clone().failed("late".to_string())).await.unwrap());
+
+        let (_, page) = call(&app, Method::GET, &format!("/intents/batch/{}?offset=3&limit=5", batch_id), None, &user).await;
+        let indices: Vec<_> = page["items"].as_array().unwrap().iter().map(|item| item["index"].as_u64().unwrap()).collect();
+        assert_eq!(indices, [3, 4]);
+
+        stop.send(true).unwrap();
+        for worker in workers {
+            worker.await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_progress_and_restart_recovery() {
+        assert_progress_and_restart_recovery(Arc::new(MemoryBatchQueue::new())).await;
+    }
+
+    /// Run with `TEST_REDIS_URL=redis://localhost:6379 cargo test -- --ignored`
+    #[tokio::test]
+    #[ignore = "needs a Redis server at TEST_REDIS_URL"]
+    async fn test_redis_progress_and_restart_recovery() {
+        let url = std::env::var("TEST_REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379".to_string());
+        let client = redis::Client::open(url.as_str()).unwrap();
+        let namespace = format!("test-batch-{}", Uuid::new_v4());
+        let queue = RedisBatchQueue::new(&client, Duration::from_secs(60)).await.unwrap().with_namespace(&namespace);
+        assert_progress_and_restart_recovery(Arc::new(queue)).await;
+    }
+
+    #[tokio::test]
+    async fn test_cancel_skips_items_not_yet_taken() {
+        let queue: Arc<dyn BatchQueue> = Arc::new(MemoryBatchQueue::new());
+        let app = Router::new().merge(routes()).with_state(Batches::new(queue.clone(), 3));
+        let (owner, stranger) = (session(), session());
+
+        let (status, _) = call(&app, Method::POST, "/intents/batch", Some(serde_json::json!({ "intents": ["a", "b", "c", "d"] })), &owner).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = call(&app, Method::POST, "/intents/batch", Some(serde_json::json!({ "intents": ["a", " "] })), &owner).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let batch_id = submit(&app, &["a", "b", "c"], &owner).await;
+        let lease = queue.lease(VISIBILITY).await.unwrap().unwrap();
+
+        let cancel = format!("/intents/batch/{}/cancel", batch_id);
+        assert_eq!(call(&app, Method::POST, &cancel, None, &stranger).await.0, StatusCode::NOT_FOUND);
+        let (status, body) = call(&app, Method::POST, &cancel, None, &owner).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["skipped"], 2);
+        assert_eq!((body["progress"]["processing"].as_u64(), body["progress"]["done"].as_bool()), (Some(1), Some(false)));
+
+        // Skipped items are never handed out; the item in progress still finishes
+        assert!(queue.lease(VISIBILITY).await.unwrap().is_none());
+        let output = ItemOutput { plan: serde_json::json!({}), execution: None };
+        assert!(queue.finish(&lease, lease.item.clone().succeeded(output)).await.unwrap());
+        let (_, body) = call(&app, Method::GET, &format!("/intents/batch/{}", batch_id), None, &owner).await;
+        assert_eq!(body["progress"]["skipped"], 2);
+        assert_eq!(body["progress"]["succeeded"], 1);
+        assert_eq!(body["progress"]["done"], true);
+        assert!(body["cancelled_at"].is_string());
+        assert_eq!(call(&app, Method::GET, &format!("/intents/batch/{}", batch_id), None, &stranger).await.0, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_items_whose_workers_keep_dying_are_failed() {
+        let queue = MemoryBatchQueue::new();
+        let batch = BatchInfo { id: Uuid::new_v4(), owner: None, total: 1, created_at: Utc::now(), cancelled_at: None };
+        queue.submit(&batch, vec!["crash the worker".to_string()]).await.unwrap();
+
+        for attempt in 1..=MAX_ATTEMPTS {
+            let lease = queue.lease(Duration::ZERO).await.unwrap().unwrap();
+            assert_eq!(lease.item.attempts, attempt);
+        }
+        assert!(queue.lease(Duration::ZERO).await.unwrap().is_none());
+        let item = &queue.items(batch.id, 0, 1).await.unwrap()[0];
+        assert_eq!(item.status, ItemStatus::Failed);
+        assert_eq!(item.error.as_deref(), Some("Abandoned after 3 attempts"));
+    }
+
+    /// Completes every task it is given, with an empty document for each expected output
+    struct CompletingRunner;
+
+    #[async_trait]
+    impl TaskRunner for CompletingRunner {
+        async fn validate(&self, _task: &ExecutionTask) -> Result<()> {
+            Ok(())
+        }
+
+        async fn run(&self, task: &ExecutionTask) -> Result<TaskOutput> {
+            let outputs = task.expected_outputs.iter().map(|name| (name.clone(), serde_json::json!({}))).collect();
+            Ok(TaskOutput { status: TaskStatus::Completed, outputs, usage: Default::default() })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_only_plans_needing_no_approval_are_executed() {
+        let processor = KernelProcessor::new(Arc::new(CognitiveKernel::new())).with_execution(Arc::new(CompletingRunner));
+
+        let low = processor.process("read the configuration", Some(Uuid::new_v4())).await.unwrap();
+        assert_eq!(low.plan["requires_approval"], false);
+        assert_eq!(low.execution.unwrap()["state"], "Completed");
+
+        let critical = processor.process("delete the production database", None).await.unwrap();
+        assert_eq!(critical.plan["requires_approval"], true);
+        assert!(critical.execution.is_none());
+    }
+}
@@ -0,0 +1,620 @@
+//! What this deployment can do
+//!
+//! Deployments differ in which subsystems they have: a CUDA device, a Qdrant server, an
+//! Ollama server, MCP servers, external services. A [`CapabilityRegistry`] holds one
+//! [`CapabilityProbe`] per subsystem and remembers what each last found. A capability older
+//! than the refresh interval is probed again the next time it is asked for, so
+//! `GET /api/v1/capabilities` follows a subsystem coming or going without a restart.
+//!
+//! Routes that need a subsystem are wrapped in [`enforce`], which answers
+//! [`ApiError::CapabilityUnavailable`] while the subsystem is unavailable instead of
+//! running the handler.
+
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use async_graphql::{Enum, SimpleObject};
+use axum::{
+    async_trait,
+    extract::{FromRef, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+    routing::get,
+    Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use talkpp_mcp_hub::McpHub;
+use tokio::{sync::Mutex, task::JoinSet, time::Instant};
+use tracing::{info, instrument, warn};
+
+use crate::error::{ApiError, ApiResult};
+
+pub const CUDA: &str = "cuda";
+pub const VECTOR_DB: &str = "vector_db";
+pub const OLLAMA: &str = "ollama";
+pub const MCP: &str = "mcp";
+pub const EXTERNAL_SERVICES: &str = "external_services";
+
+pub fn routes<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+    CapabilityRegistry: FromRef<S>,
+{
+    Router::new().route("/capabilities", get(list_capabilities))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Enum)]
+#[serde(rename_all = "snake_case")]
+pub enum CapabilityState {
+    Available,
+    /// Usable, but short of something, such as a GPU or one of several servers
+    Degraded,
+    Unavailable,
+}
+
+/// A subsystem as of its last probe
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SimpleObject)]
+pub struct Capability {
+    pub subsystem: String,
+    pub state: CapabilityState,
+    /// Whether requests needing the subsystem are served; they are while it is degraded
+    pub available: bool,
+    pub version: Option<String>,
+    /// What the probe found, such as devices, models or servers
+    pub details: Vec<String>,
+    /// Why the subsystem is degraded or unavailable
+    pub reasons: Vec<String>,
+    pub checked_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CapabilitiesResponse {
+    pub capabilities: Vec<Capability>,
+}
+
+/// What a [`CapabilityProbe`] found
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProbeResult {
+    pub state: CapabilityState,
+    pub version: Option<String>,
+    pub details: Vec<String>,
+    pub reasons: Vec<String>,
+}
+
+impl ProbeResult {
+    pub fn available() -> Self {
+        Self { state: CapabilityState::Available, version: None, details: Vec::new(), reasons: Vec::new() }
+    }
+
+    pub fn degraded(reason: impl Into<String>) -> Self {
+        Self::available().with_degradation(reason)
+    }
+
+    pub fn unavailable(reason: impl Into<String>) -> Self {
+        Self { state: CapabilityState::Unavailable, reasons: vec![reason.into()], ..Self::available() }
+    }
+
+    pub fn with_version(mut self, version: impl Into<String>) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.details.push(detail.into());
+        self
+    }
+
+    /// Degraded for `reason` as well; an unavailable subsystem stays unavailable
+    pub fn with_degradation(mut self, reason: impl Into<String>) -> Self {
+        if self.state == CapabilityState::Available {
+            self.state = CapabilityState::Degraded;
+        }
+        self.reasons.push(reason.into());
+        self
+    }
+
+    fn into_capability(self, subsystem: &str) -> Capability {
+        Capability {
+            subsystem: subsystem.to_string(),
+            available: self.state != CapabilityState::Unavailable,
+            state: self.state,
+            version: self.version,
+            details: self.details,
+            reasons: self.reasons,
+            checked_at: Utc::now(),
+        }
+    }
+}
+
+/// Checks whether one subsystem is there and working
+#[async_trait]
+pub trait CapabilityProbe: Send + Sync {
+    fn subsystem(&self) -> &str;
+
+    async fn probe(&self) -> ProbeResult;
+}
+
+struct Entry {
+    probe: Arc<dyn CapabilityProbe>,
+    last: Mutex<Option<(Instant, Capability)>>,
+}
+
+/// The probes of this deployment's subsystems and what each last found
+#[derive(Clone)]
+pub struct CapabilityRegistry {
+    entries: Arc<RwLock<Vec<Arc<Entry>>>>,
+    refresh: Duration,
+    probe_timeout: Duration,
+}
+
+impl CapabilityRegistry {
+    /// A registry probing a subsystem again when asked for it `refresh` after the last probe
+    pub fn new(refresh: Duration) -> Self {
+        Self { entries: Arc::new(RwLock::new(Vec::new())), refresh, probe_timeout: Duration::from_secs(5) }
+    }
+
+    /// Report a subsystem unavailable when its probe takes longer than `timeout`
+    pub fn with_probe_timeout(mut self, timeout: Duration) -> Self {
+        self.probe_timeout = timeout;
+        self
+    }
+
+    /// Probe a subsystem with `probe` from now on, replacing any probe registered for it
+    pub fn register(&self, probe: impl CapabilityProbe + 'static) {
+        let entry = Arc::new(Entry { probe: Arc::new(probe), last: Mutex::new(None) });
+        let mut entries = self.entries.write().expect("capability registry lock poisoned");
+        entries.retain(|existing| existing.probe.subsystem() != entry.probe.subsystem());
+        entries.push(entry);
+    }
+
+    /// `subsystem` as of its last probe, probing it first if that is stale; `None` if
+    /// nothing in this deployment probes it
+    pub async fn get(&self, subsystem: &str) -> Option<Capability> {
+        let entry = self.entries.read().expect("capability registry lock poisoned")
+            .iter()
+            .find(|entry| entry.probe.subsystem() == subsystem)
+            .cloned()?;
+        Some(self.current(&entry).await)
+    }
+
+    /// Every registered subsystem in registration order, stale ones probed concurrently
+    pub async fn all(&self) -> Vec<Capability> {
+        let entries = self.entries.read().expect("capability registry lock poisoned").clone();
+        let mut probes = JoinSet::new();
+        for (position, entry) in entries.into_iter().enumerate() {
+            let registry = self.clone();
+            probes.spawn(async move { (position, registry.current(&entry).await) });
+        }
+        let mut capabilities = Vec::new();
+        while let Some(probed) = probes.join_next().await {
+            capabilities.push(probed.expect("probe panics are caught in current()"));
+        }
+        capabilities.sort_by_key(|(position, _)| *position);
+        capabilities.into_iter().map(|(_, capability)| capability).collect()
+    }
+
+    /// `subsystem` if requests needing it can be served, otherwise the error to answer
+    /// them with
+    pub async fn require(&self, subsystem: &str) -> ApiResult<Capability> {
+        match self.get(subsystem).await {
+            Some(capability) if capability.available => Ok(capability),
+            Some(capability) => Err(ApiError::CapabilityUnavailable {
+                subsystem: subsystem.to_string(),
+                reasons: capability.reasons,
+            }),
+            None => Err(ApiError::CapabilityUnavailable {
+                subsystem: subsystem.to_string(),
+                reasons: vec!["This deployment does not provide it".to_string()],
+            }),
+        }
+    }
+
+    /// State for an [`enforce`] layer on routes needing `subsystem`
+    pub fn requirement(&self, subsystem: &'static str) -> Requirement {
+        Requirement { registry: self.clone(), subsystem }
+    }
+
+    /// The entry's last capability, or a fresh one if that is stale. Concurrent callers
+    /// wait for a single probe rather than each starting their own.
+    async fn current(&self, entry: &Entry) -> Capability {
+        let mut last = entry.last.lock().await;
+        if let Some((probed_at, capability)) = last.as_ref() {
+            if probed_at.elapsed() < self.refresh {
+                return capability.clone();
+            }
+        }
+
+        // Spawned so a panicking probe reports its subsystem unavailable like a failing one
+        let probe = Arc::clone(&entry.probe);
+        let result = match tokio::time::timeout(self.probe_timeout, tokio::spawn(async move { probe.probe().await })).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(e)) => ProbeResult::unavailable(format!("Probe failed: {}", e)),
+            Err(_) => ProbeResult::unavailable(format!("Probe timed out after {:?}", self.probe_timeout)),
+        };
+        let capability = result.into_capability(entry.probe.subsystem());
+
+        let previous = last.as_ref().map(|(_, capability)| capability.state);
+        if previous != Some(capability.state) {
+            match capability.state {
+                CapabilityState::Available => info!("Capability {} is available", capability.subsystem),
+                state => warn!("Capability {} is {:?}: {}", capability.subsystem, state, capability.reasons.join("; ")),
+            }
+        }
+        *last = Some((Instant::now(), capability.clone()));
+        capability
+    }
+}
+
+/// What an [`enforce`] layer requires
+#[derive(Clone)]
+pub struct Requirement {
+    registry: CapabilityRegistry,
+    subsystem: &'static str,
+}
+
+/// Answer 503 without running the handler while the required subsystem is unavailable
+pub async fn enforce(State(requirement): State<Requirement>, request: Request, next: Next) -> Response {
+    match requirement.registry.require(requirement.subsystem).await {
+        Ok(_) => next.run(request).await,
+        Err(e) => e.into_response(),
+    }
+}
+
+/// What this deployment can do, probing subsystems whose last probe is stale
+#[instrument(skip(registry))]
+async fn list_capabilities(State(registry): State<CapabilityRegistry>) -> Json<CapabilitiesResponse> {
+    Json(CapabilitiesResponse { capabilities: registry.all().await })
+}
+
+/// CUDA devices, available while the self-test passes on all of them
+#[cfg(feature = "cuda")]
+pub struct CudaProbe {
+    processor: Mutex<talkpp_cuda_processor::CandleCudaProcessor>,
+}
+
+#[cfg(feature = "cuda")]
+impl CudaProbe {
+    pub fn new() -> Self {
+        Self { processor: Mutex::new(talkpp_cuda_processor::CandleCudaProcessor::new()) }
+    }
+}
+
+#[cfg(feature = "cuda")]
+impl Default for CudaProbe {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "cuda")]
+#[async_trait]
+impl CapabilityProbe for CudaProbe {
+    fn subsystem(&self) -> &str {
+        CUDA
+    }
+
+    async fn probe(&self) -> ProbeResult {
+        let report = match self.processor.lock().await.self_test().await {
+            Ok(report) => report,
+            Err(e) => return ProbeResult::unavailable(format!("CUDA self-test failed: {}", e)),
+        };
+        if !report.cuda {
+            return ProbeResult::unavailable("No CUDA device found; models run on the CPU");
+        }
+        let mut result = report.devices.iter()
+            .fold(ProbeResult::available(), |result, device| result.with_detail(&device.name));
+        if report.failures.len() == report.devices.len() {
+            result.state = CapabilityState::Unavailable;
+        }
+        report.failures.into_iter().fold(result, ProbeResult::with_degradation)
+    }
+}
+
+/// CUDA in a server built without the `cuda` feature, which is never available
+#[cfg(not(feature = "cuda"))]
+#[derive(Default)]
+pub struct CudaProbe;
+
+#[cfg(not(feature = "cuda"))]
+impl CudaProbe {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[cfg(not(feature = "cuda"))]
+#[async_trait]
+impl CapabilityProbe for CudaProbe {
+    fn subsystem(&self) -> &str {
+        CUDA
+    }
+
+    async fn probe(&self) -> ProbeResult {
+        ProbeResult::unavailable("This server was built without the `cuda` feature")
+    }
+}
+
+/// The Qdrant vector database, available while it answers health checks
+pub struct QdrantProbe {
+    url: String,
+}
+
+impl QdrantProbe {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self { url: url.into() }
+    }
+}
+
+#[async_trait]
+impl CapabilityProbe for QdrantProbe {
+    fn subsystem(&self) -> &str {
+        VECTOR_DB
+    }
+
+    async fn probe(&self) -> ProbeResult {
+        let client = match qdrant_client::client::QdrantClient::from_url(&self.url).build() {
+            Ok(client) => client,
+            Err(e) => return ProbeResult::unavailable(format!("Invalid Qdrant URL {}: {}", self.url, e)),
+        };
+        match client.health_check().await {
+            Ok(reply) => ProbeResult::available().with_version(reply.version).with_detail(format!("qdrant at {}", self.url)),
+            Err(e) => ProbeResult::unavailable(format!("Qdrant at {} is unreachable: {}", self.url, e)),
+        }
+    }
+}
+
+/// An Ollama server, available while it answers and degraded while it has no models
+pub struct OllamaProbe {
+    url: String,
+    client: reqwest::Client,
+}
+
+#[derive(Deserialize)]
+struct OllamaTags {
+    models: Vec<OllamaModel>,
+}
+
+#[derive(Deserialize)]
+struct OllamaModel {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct OllamaVersion {
+    version: String,
+}
+
+impl OllamaProbe {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self { url: url.into().trim_end_matches('/').to_string(), client: reqwest::Client::new() }
+    }
+
+    async fn fetch<T: serde::de::DeserializeOwned>(&self, path: &str) -> reqwest::Result<T> {
+        self.client.get(format!("{}{}", self.url, path)).send().await?.error_for_status()?.json().await
+    }
+}
+
+#[async_trait]
+impl CapabilityProbe for OllamaProbe {
+    fn subsystem(&self) -> &str {
+        OLLAMA
+    }
+
+    async fn probe(&self) -> ProbeResult {
+        let tags: OllamaTags = match self.fetch("/api/tags").await {
+            Ok(tags) => tags,
+            Err(e) => return ProbeResult::unavailable(format!("Ollama at {} is unreachable: {}", self.url, e)),
+        };
+        let mut result = match tags.models.is_empty() {
+            true => ProbeResult::degraded("Ollama has no models pulled"),
+            false => tags.models.into_iter().fold(ProbeResult::available(), |result, model| result.with_detail(model.name)),
+        };
+        if let Ok(version) = self.fetch::<OllamaVersion>("/api/version").await {
+            result = result.with_version(version.version);
+        }
+        result
+    }
+}
+
+/// MCP tools, available while any enabled server is connected and degraded while some are not
+pub struct McpProbe {
+    hub: Arc<McpHub>,
+}
+
+impl McpProbe {
+    pub fn new(hub: Arc<McpHub>) -> Self {
+        Self { hub }
+    }
+}
+
+#[async_trait]
+impl CapabilityProbe for McpProbe {
+    fn subsystem(&self) -> &str {
+        MCP
+    }
+
+    async fn probe(&self) -> ProbeResult {
+        let servers = self.hub.servers().await;
+        if servers.is_empty() {
+            return ProbeResult::unavailable("No MCP servers are registered");
+        }
+
+        let mut result = ProbeResult::available();
+        let mut connected = 0;
+        for server in servers.iter().filter(|server| server.enabled) {
+            result = match self.hub.get_server_status(server.id).await {
+                Ok(status) if status.connected => {
+                    connected += 1;
+                    result.with_detail(format!("{} ({} tools)", status.name, status.tools_count))
+                }
+                Ok(status) => result.with_degradation(match status.last_error {
+                    Some(error) => format!("MCP server {} is not connected: {}", status.name, error),
+                    None => format!("MCP server {} is not connected", status.name),
+                }),
+                Err(e) => result.with_degradation(format!("MCP server {} has no status: {}", server.name, e)),
+            };
+        }
+        if connected == 0 {
+            result.state = CapabilityState::Unavailable;
+            if result.reasons.is_empty() {
+                result.reasons.push("No MCP server is enabled".to_string());
+            }
+        }
+        result
+    }
+}
+
+/// The external services this deployment is configured to use, which are not contacted
+pub struct ExternalServicesProbe {
+    services: Vec<String>,
+}
+
+impl ExternalServicesProbe {
+    pub fn new(services: Vec<String>) -> Self {
+        Self { services }
+    }
+}
+
+#[async_trait]
+impl CapabilityProbe for ExternalServicesProbe {
+    fn subsystem(&self) -> &str {
+        EXTERNAL_SERVICES
+    }
+
+    async fn probe(&self) -> ProbeResult {
+        if self.services.is_empty() {
+            return ProbeResult::unavailable("No external services are configured");
+        }
+        self.services.iter().fold(ProbeResult::available(), |result, service| result.with_detail(service))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use axum::{body::Body, http::StatusCode, middleware, routing::post};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    /// Answers with whatever result it is set to and counts its probes
+    struct MockProbe {
+        subsystem: &'static str,
+        result: Arc<std::sync::Mutex<ProbeResult>>,
+        probes: Arc<AtomicUsize>,
+        delay: Duration,
+    }
+
+    impl MockProbe {
+        fn new(subsystem: &'static str, result: ProbeResult) -> Self {
+            Self {
+                subsystem,
+                result: Arc::new(std::sync::Mutex::new(result)),
+                probes: Arc::new(AtomicUsize::new(0)),
+                delay: Duration::ZERO,
+            }
+        }
+    }
+
+    #[async_trait]
+    impl CapabilityProbe for MockProbe {
+        fn subsystem(&self) -> &str {
+            self.subsystem
+        }
+
+        async fn probe(&self) -> ProbeResult {
+            self.probes.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(self.delay).await;
+            self.result.lock().unwrap().clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_states() {
+        let registry = CapabilityRegistry::new(Duration::from_secs(60));
+        registry.register(MockProbe::new(VECTOR_DB, ProbeResult::available().with_version("1.7.0")));
+        registry.register(MockProbe::new(OLLAMA, ProbeResult::degraded("Ollama has no models pulled")));
+        registry.register(MockProbe::new(CUDA, ProbeResult::unavailable("No CUDA device found")));
+
+        let capabilities = registry.all().await;
+        let states: Vec<_> = capabilities.iter().map(|c| (c.subsystem.as_str(), c.state, c.available)).collect();
+        assert_eq!(states, [
+            (VECTOR_DB, CapabilityState::Available, true),
+            (OLLAMA, CapabilityState::Degraded, true),
+            (CUDA, CapabilityState::Unavailable, false),
+        ]);
+        assert_eq!(capabilities[0].version.as_deref(), Some("1.7.0"));
+
+        // Degraded subsystems still serve requests; unavailable and missing ones do not
+        assert!(registry.require(OLLAMA).await.is_ok());
+        assert!(matches!(
+            registry.require(CUDA).await,
+            Err(ApiError::CapabilityUnavailable { reasons, .. }) if reasons == ["No CUDA device found"]
+        ));
+        assert!(registry.get(MCP).await.is_none());
+        assert!(matches!(registry.require(MCP).await, Err(ApiError::CapabilityUnavailable { subsystem, .. }) if subsystem == MCP));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_probes_refresh_when_stale() {
+        let registry = CapabilityRegistry::new(Duration::from_secs(60));
+        let probe = MockProbe::new(VECTOR_DB, ProbeResult::unavailable("Qdrant is unreachable"));
+        let (result, probes) = (Arc::clone(&probe.result), Arc::clone(&probe.probes));
+        registry.register(probe);
+
+        assert!(!registry.get(VECTOR_DB).await.unwrap().available);
+        *result.lock().unwrap() = ProbeResult::available();
+        tokio::time::advance(Duration::from_secs(30)).await;
+        assert!(!registry.get(VECTOR_DB).await.unwrap().available);
+        assert_eq!(probes.load(Ordering::SeqCst), 1);
+
+        tokio::time::advance(Duration::from_secs(31)).await;
+        assert!(registry.get(VECTOR_DB).await.unwrap().available);
+        assert_eq!(probes.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_slow_probes_time_out() {
+        let registry = CapabilityRegistry::new(Duration::from_secs(60)).with_probe_timeout(Duration::from_secs(1));
+        registry.register(MockProbe { delay: Duration::from_secs(10), ..MockProbe::new(OLLAMA, ProbeResult::available()) });
+
+        let capability = registry.get(OLLAMA).await.unwrap();
+        assert_eq!(capability.state, CapabilityState::Unavailable);
+        assert_eq!(capability.reasons, ["Probe timed out after 1s"]);
+    }
+
+    #[tokio::test]
+    async fn test_enforce_short_circuits_unavailable_subsystems() {
+        let registry = CapabilityRegistry::new(Duration::from_secs(60));
+        registry.register(MockProbe::new(VECTOR_DB, ProbeResult::unavailable("Qdrant at http://qdrant:6334 is unreachable")));
+        let handled = Arc::new(AtomicUsize::new(0));
+        let app = Router::new()
+            .route("/vectors/search", post({
+                let handled = Arc::clone(&handled);
+                move || async move {
+                    handled.fetch_add(1, Ordering::SeqCst);
+                    "results"
+                }
+            }))
+            .route_layer(middleware::from_fn_with_state(registry.requirement(VECTOR_DB), enforce));
+
+        let response = app.clone()
+            .oneshot(Request::post("/vectors/search").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["capability"], VECTOR_DB);
+        assert_eq!(body["reasons"], serde_json::json!(["Qdrant at http://qdrant:6334 is unreachable"]));
+        assert_eq!(body["hint"], "/api/v1/capabilities");
+        assert_eq!(handled.load(Ordering::SeqCst), 0);
+
+        registry.register(MockProbe::new(VECTOR_DB, ProbeResult::available()));
+        let response = app.oneshot(Request::post("/vectors/search").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(handled.load(Ordering::SeqCst), 1);
+    }
+}
@@ -1,6 +1,16 @@
 use anyhow::Result;
+use jarvis_core::Environment;
+use memory_continuum::MemoryConfig;
+use talkpp_sanitizer::{SanitizerConfig, TextSanitizer};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+use talkpp_auth::secrets::{
+    CachedSecrets, EncryptedFileSecrets, EnvSecrets, SecretResolver, SecretString, VaultAuth, VaultSecrets,
+};
+use talkpp_external_services::{ServiceConfig, ServiceCredentials, ServiceType};
 
 /// Application configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -9,12 +19,30 @@ pub struct Config {
     pub port: u16,
     pub database_url: Option<String>,
     pub redis_url: Option<String>,
+    /// Literal secret, or an `env:`/`file:`/`vault:` reference resolved by `resolve_secrets`
     pub jwt_secret: String,
     pub cors_origins: Vec<String>,
     pub rate_limit: RateLimitConfig,
     pub auth: AuthConfig,
     pub observability: ObservabilityConfig,
     pub services: ServicesConfig,
+    pub memory: MemorySettings,
+    pub mcp: McpSettings,
+    pub intent: IntentSettings,
+    pub shutdown: ShutdownSettings,
+    pub idempotency: IdempotencySettings,
+    pub audit: AuditSettings,
+    pub artifacts: ArtifactSettings,
+    pub batch: BatchSettings,
+    pub jobs: JobSettings,
+    pub intent_stream: IntentStreamSettings,
+    pub event_bus: EventBusSettings,
+    pub preferences: PreferenceSettings,
+    pub kernel_state: KernelStateSettings,
+    pub notifications: NotificationSettings,
+    pub capabilities: CapabilitySettings,
+    pub locks: LockSettings,
+    pub cache: CacheSettings,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -47,6 +75,282 @@ pub struct ServicesConfig {
     pub monday_api_url: String,
     pub vault_addr: Option<String>,
     pub vault_role: String,
+    /// KV v2 mount that `vault:` references are read from
+    pub vault_mount: String,
+    pub vault_secret_ttl_secs: u64,
+    /// Encrypted secrets file backing `file:` references
+    pub secrets_file: Option<String>,
+}
+
+impl ServicesConfig {
+    /// Resolver for `env:` references, plus `file:` and `vault:` when those stores are configured.
+    ///
+    /// Vault logs in with `VAULT_TOKEN` when set, otherwise with AppRole using `vault_role`
+    /// as the role id and `VAULT_SECRET_ID` as the secret id.
+    pub fn secrets_resolver(&self) -> Result<SecretResolver> {
+        let mut resolver = SecretResolver::new().with_provider("env", Arc::new(EnvSecrets));
+
+        if let Some(path) = &self.secrets_file {
+            resolver = resolver.with_provider("file", Arc::new(EncryptedFileSecrets::from_env(path)?));
+        }
+
+        if let Some(addr) = &self.vault_addr {
+            let auth = match env::var("VAULT_TOKEN") {
+                Ok(token) => VaultAuth::Token(SecretString::new(token)),
+                Err(_) => VaultAuth::AppRole {
+                    role_id: self.vault_role.clone(),
+                    secret_id: SecretString::new(env::var("VAULT_SECRET_ID").map_err(|_| {
+                        anyhow::anyhow!("VAULT_TOKEN or VAULT_SECRET_ID is required when VAULT_ADDR is set")
+                    })?),
+                },
+            };
+            let vault = VaultSecrets::new(addr, &self.vault_mount, auth);
+            let ttl = Duration::from_secs(self.vault_secret_ttl_secs);
+            resolver = resolver.with_provider("vault", Arc::new(CachedSecrets::new(vault, ttl)));
+        }
+
+        Ok(resolver)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemorySettings {
+    pub stm_capacity: usize,
+    pub consolidation_threshold: f64,
+    pub consolidation_interval_secs: u64,
+    /// Mask personal data in memories before they are stored
+    pub scrub_pii: bool,
+    /// Secret mixed into the tokens that replace personal data. Literal secret, or an
+    /// `env:`/`file:`/`vault:` reference resolved by `resolve_secrets`
+    pub pii_hash_key: String,
+}
+
+impl MemorySettings {
+    /// Memory continuum configuration, with defaults for everything not set here
+    pub fn continuum_config(&self) -> MemoryConfig {
+        MemoryConfig::default()
+            .with_stm_capacity(self.stm_capacity)
+            .with_consolidation_threshold(self.consolidation_threshold)
+            .with_consolidation_interval(Duration::from_secs(self.consolidation_interval_secs))
+    }
+
+    /// The sanitizer memories pass through before they are stored, if scrubbing is on
+    pub fn sanitizer(&self) -> Option<TextSanitizer> {
+        self.scrub_pii.then(|| TextSanitizer::new(SanitizerConfig {
+            hash_key: self.pii_hash_key.clone(),
+            ..SanitizerConfig::default()
+        }))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpSettings {
+    /// JSON file the MCP hub persists registered servers to
+    pub registry_path: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IntentSettings {
+    /// JSON file of extra domain and risk patterns for the intent classifier
+    pub patterns_path: Option<String>,
+    /// Environments this deployment may execute plans against; `None` allows any
+    pub allowed_environments: Option<Vec<Environment>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShutdownSettings {
+    /// Time allowed for the whole drain sequence before remaining work is aborted
+    pub deadline_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdempotencySettings {
+    /// How long a completed response is replayed for a repeated Idempotency-Key
+    pub ttl_secs: u64,
+    /// How long an unfinished request holds its key, should its server die mid-request
+    pub lock_ttl_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditSettings {
+    /// Where audit events are written: `postgres` or `jsonl`
+    pub sink: String,
+    /// File the `jsonl` sink appends to
+    pub jsonl_path: String,
+    /// Events buffered for the writer; once full, further events are dropped and counted
+    pub channel_capacity: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArtifactSettings {
+    /// Where task outputs too large to inline are stored: `filesystem` or `s3`
+    pub backend: String,
+    /// Directory of the `filesystem` backend
+    pub path: String,
+    /// Bucket of the `s3` backend, which takes its credentials from the AWS environment
+    pub s3_bucket: Option<String>,
+    pub s3_prefix: String,
+    /// Serialized size above which a task output is stored as an artifact
+    pub inline_threshold_bytes: usize,
+    /// Artifacts are deleted this long after they were stored, if their plan was not
+    /// deleted first
+    pub retention_days: u64,
+    pub gc_interval_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchSettings {
+    /// Most intents accepted in one batch
+    pub max_items: usize,
+    /// Batch items processed in parallel by this server
+    pub workers: usize,
+    /// How long a worker holds an item before it is handed to another worker, should
+    /// the first have died
+    pub visibility_timeout_secs: u64,
+    /// How long a batch and its results are kept after submission
+    pub retention_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobSettings {
+    /// Generation jobs run in parallel by this server; more wait queued
+    pub max_running: usize,
+    /// Model jobs generate with: an Ollama model such as `ollama:llama3`, or
+    /// `function:<id>` for a function deployed to the runtime at `function_store_path`
+    pub model: String,
+    /// Where the runtime keeps the functions jobs can run
+    pub function_store_path: String,
+    /// Most tokens a job generates when its request doesn't say
+    pub max_tokens: usize,
+    /// How often running jobs store their output and look for cancellations made on
+    /// other replicas; cancelling takes at most this long to stop generation
+    pub flush_interval_ms: u64,
+    /// How long a job and its output are kept after it last changed
+    pub retention_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IntentStreamSettings {
+    /// Latest events kept per intent for clients resuming with `Last-Event-ID`
+    pub buffered_events: usize,
+    /// How long an intent's events are kept after its last one
+    pub retention_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventBusSettings {
+    /// Prepended to each topic to form its Redis pub/sub channel
+    pub redis_prefix: String,
+    /// Events held for publishing while Redis is unavailable, beyond which the oldest
+    /// are dropped
+    pub max_buffered: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreferenceSettings {
+    /// Highest autonomy tier for users who have not set their own
+    pub default_max_autonomy_tier: Option<u8>,
+    /// Risk levels needing approval for users who have not set their own
+    pub default_require_approval_for_risks: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KernelStateSettings {
+    /// Kernel state namespaces kept in Redis, so they survive restarts and are shared
+    /// across replicas
+    pub persisted_namespaces: Vec<String>,
+    /// Prepended to each namespace to form its Redis key
+    pub redis_prefix: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationSettings {
+    /// How notification emails are sent: `smtp`, or `none` to send none
+    pub email_provider: String,
+    /// Web UI the emails link to
+    pub web_base_url: String,
+    pub smtp_host: Option<String>,
+    pub smtp_port: u16,
+    pub smtp_username: String,
+    /// SMTP password, or a reference to it such as `env:SMTP_PASSWORD`
+    pub smtp_password: String,
+    /// Sender of notification emails
+    pub from_address: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CapabilitySettings {
+    /// A subsystem is probed again when its capability is asked for this long after the
+    /// last probe
+    pub refresh_secs: u64,
+    /// Probes that take longer report their subsystem unavailable
+    pub probe_timeout_secs: u64,
+    /// gRPC endpoint of the Qdrant vector database
+    pub qdrant_url: String,
+    pub ollama_url: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheSettings {
+    /// Prepended to each store's namespace to form its Redis keys
+    pub key_prefix: String,
+    /// Values whose JSON is longer than this many bytes are stored zstd-compressed
+    pub compress_above_bytes: usize,
+    pub compression_level: i32,
+    /// Default TTL of each namespace's values; namespaces not listed keep values until
+    /// they are deleted
+    pub ttl_secs: HashMap<String, u64>,
+}
+
+impl CacheSettings {
+    /// Default TTL of `namespace`'s values
+    pub fn ttl(&self, namespace: &str) -> Option<Duration> {
+        self.ttl_secs.get(namespace).map(|secs| Duration::from_secs(*secs))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockSettings {
+    /// Names this replica as the holder of the locks background jobs run under
+    pub replica_id: String,
+    /// How long a job's lock outlives its holder, should the holder die
+    pub lease_secs: u64,
+    /// Prepended to each lock name to form its Redis key
+    pub redis_prefix: String,
+}
+
+impl NotificationSettings {
+    /// The SMTP service notification emails are sent through
+    pub fn smtp_service(&self) -> ServiceConfig {
+        ServiceConfig {
+            id: uuid::Uuid::nil(),
+            service_type: ServiceType::Smtp,
+            name: "notifications".to_string(),
+            enabled: true,
+            credentials: ServiceCredentials::BasicAuth {
+                username: self.smtp_username.clone(),
+                password: self.smtp_password.clone(),
+            },
+            settings: std::collections::HashMap::from([
+                ("host".to_string(), serde_json::json!(self.smtp_host)),
+                ("port".to_string(), serde_json::json!(self.smtp_port)),
+                ("from".to_string(), serde_json::json!(self.from_address)),
+            ]),
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        }
+    }
+}
+
+impl PreferenceSettings {
+    /// Preferences of users who have stored none
+    pub fn defaults(&self) -> crate::UserPreferences {
+        crate::UserPreferences {
+            max_autonomy_tier: self.default_max_autonomy_tier,
+            require_approval_for_risks: self.default_require_approval_for_risks.clone(),
+            ..Default::default()
+        }
+    }
 }
 
 impl Config {
@@ -54,7 +358,7 @@ impl Config {
     pub fn load() -> Result<Self> {
         dotenvy::dotenv().ok(); // Load .env file if present
 
-        let config = Config {
+        let mut config = Config {
             host: env::var("HOST").unwrap_or_else(|_| "0.0.0.0".to_string()),
             port: env::var("PORT")
                 .unwrap_or_else(|_| "8080".to_string())
@@ -129,15 +433,280 @@ impl Config {
                 vault_addr: env::var("VAULT_ADDR").ok(),
                 vault_role: env::var("VAULT_ROLE")
                     .unwrap_or_else(|_| "talk-plus-plus".to_string()),
+                vault_mount: env::var("VAULT_KV_MOUNT")
+                    .unwrap_or_else(|_| "secret".to_string()),
+                vault_secret_ttl_secs: env::var("VAULT_SECRET_TTL_SECS")
+                    .unwrap_or_else(|_| "300".to_string())
+                    .parse()
+                    .unwrap_or(300),
+                secrets_file: env::var("SECRETS_FILE").ok(),
+            },
+            
+            memory: MemorySettings {
+                stm_capacity: env::var("MEMORY_STM_CAPACITY")
+                    .unwrap_or_else(|_| "1000".to_string())
+                    .parse()
+                    .unwrap_or(1000),
+                consolidation_threshold: env::var("MEMORY_CONSOLIDATION_THRESHOLD")
+                    .unwrap_or_else(|_| "0.7".to_string())
+                    .parse()
+                    .unwrap_or(0.7),
+                consolidation_interval_secs: env::var("MEMORY_CONSOLIDATION_INTERVAL_SECS")
+                    .unwrap_or_else(|_| "300".to_string())
+                    .parse()
+                    .unwrap_or(300),
+                scrub_pii: env::var("MEMORY_SCRUB_PII")
+                    .unwrap_or_else(|_| "false".to_string())
+                    .parse()
+                    .unwrap_or(false),
+                pii_hash_key: env::var("PII_HASH_KEY").unwrap_or_default(),
+            },
+            
+            mcp: McpSettings {
+                registry_path: env::var("MCP_REGISTRY_PATH")
+                    .unwrap_or_else(|_| ".talkpp/mcp-servers.json".to_string()),
+            },
+
+            intent: IntentSettings {
+                patterns_path: env::var("INTENT_PATTERNS_PATH").ok(),
+                allowed_environments: env::var("PLAN_ENVIRONMENTS")
+                    .ok()
+                    .map(|environments| environments
+                        .split(',')
+                        .filter_map(|s| s.parse().ok())
+                        .collect::<Vec<Environment>>())
+                    .filter(|environments| !environments.is_empty()),
+            },
+
+            shutdown: ShutdownSettings {
+                deadline_secs: env::var("SHUTDOWN_DEADLINE_SECS")
+                    .unwrap_or_else(|_| "30".to_string())
+                    .parse()
+                    .unwrap_or(30),
+            },
+
+            idempotency: IdempotencySettings {
+                ttl_secs: env::var("IDEMPOTENCY_TTL_SECS")
+                    .unwrap_or_else(|_| "86400".to_string())
+                    .parse()
+                    .unwrap_or(86400),
+                lock_ttl_secs: env::var("IDEMPOTENCY_LOCK_TTL_SECS")
+                    .unwrap_or_else(|_| "60".to_string())
+                    .parse()
+                    .unwrap_or(60),
+            },
+
+            audit: AuditSettings {
+                sink: env::var("AUDIT_SINK")
+                    .unwrap_or_else(|_| "postgres".to_string()),
+                jsonl_path: env::var("AUDIT_LOG_PATH")
+                    .unwrap_or_else(|_| ".talkpp/audit.jsonl".to_string()),
+                channel_capacity: env::var("AUDIT_CHANNEL_CAPACITY")
+                    .unwrap_or_else(|_| "1024".to_string())
+                    .parse()
+                    .unwrap_or(1024),
+            },
+
+            artifacts: ArtifactSettings {
+                backend: env::var("ARTIFACT_BACKEND")
+                    .unwrap_or_else(|_| "filesystem".to_string()),
+                path: env::var("ARTIFACT_PATH")
+                    .unwrap_or_else(|_| ".talkpp/artifacts".to_string()),
+                s3_bucket: env::var("ARTIFACT_S3_BUCKET").ok(),
+                s3_prefix: env::var("ARTIFACT_S3_PREFIX")
+                    .unwrap_or_else(|_| "artifacts".to_string()),
+                inline_threshold_bytes: env::var("ARTIFACT_INLINE_THRESHOLD_BYTES")
+                    .unwrap_or_else(|_| "65536".to_string())
+                    .parse()
+                    .unwrap_or(65536),
+                retention_days: env::var("ARTIFACT_RETENTION_DAYS")
+                    .unwrap_or_else(|_| "30".to_string())
+                    .parse()
+                    .unwrap_or(30),
+                gc_interval_secs: env::var("ARTIFACT_GC_INTERVAL_SECS")
+                    .unwrap_or_else(|_| "3600".to_string())
+                    .parse()
+                    .unwrap_or(3600),
+            },
+
+            batch: BatchSettings {
+                max_items: env::var("BATCH_MAX_ITEMS")
+                    .unwrap_or_else(|_| "500".to_string())
+                    .parse()
+                    .unwrap_or(500),
+                workers: env::var("BATCH_WORKERS")
+                    .unwrap_or_else(|_| "4".to_string())
+                    .parse()
+                    .unwrap_or(4),
+                visibility_timeout_secs: env::var("BATCH_VISIBILITY_TIMEOUT_SECS")
+                    .unwrap_or_else(|_| "300".to_string())
+                    .parse()
+                    .unwrap_or(300),
+                retention_secs: env::var("BATCH_RETENTION_SECS")
+                    .unwrap_or_else(|_| "604800".to_string())
+                    .parse()
+                    .unwrap_or(604800),
+            },
+
+            jobs: JobSettings {
+                max_running: env::var("JOB_MAX_RUNNING")
+                    .unwrap_or_else(|_| "4".to_string())
+                    .parse()
+                    .unwrap_or(4),
+                model: env::var("JOB_GENERATION_MODEL")
+                    .unwrap_or_else(|_| "ollama:llama3".to_string()),
+                function_store_path: env::var("JOB_FUNCTION_STORE_PATH")
+                    .unwrap_or_else(|_| "./data/functions".to_string()),
+                max_tokens: env::var("JOB_MAX_TOKENS")
+                    .unwrap_or_else(|_| "2048".to_string())
+                    .parse()
+                    .unwrap_or(2048),
+                flush_interval_ms: env::var("JOB_FLUSH_INTERVAL_MS")
+                    .unwrap_or_else(|_| "250".to_string())
+                    .parse()
+                    .unwrap_or(250),
+                retention_secs: env::var("JOB_RETENTION_SECS")
+                    .unwrap_or_else(|_| "86400".to_string())
+                    .parse()
+                    .unwrap_or(86400),
+            },
+
+            intent_stream: IntentStreamSettings {
+                buffered_events: env::var("INTENT_STREAM_BUFFERED_EVENTS")
+                    .unwrap_or_else(|_| "512".to_string())
+                    .parse()
+                    .unwrap_or(512),
+                retention_secs: env::var("INTENT_STREAM_RETENTION_SECS")
+                    .unwrap_or_else(|_| "900".to_string())
+                    .parse()
+                    .unwrap_or(900),
+            },
+
+            event_bus: EventBusSettings {
+                redis_prefix: env::var("EVENT_BUS_REDIS_PREFIX")
+                    .unwrap_or_else(|_| "talkpp:events:".to_string()),
+                max_buffered: env::var("EVENT_BUS_MAX_BUFFERED")
+                    .unwrap_or_else(|_| "1024".to_string())
+                    .parse()
+                    .unwrap_or(1024),
+            },
+
+            preferences: PreferenceSettings {
+                default_max_autonomy_tier: env::var("DEFAULT_MAX_AUTONOMY_TIER")
+                    .ok()
+                    .and_then(|tier| tier.parse().ok()),
+                default_require_approval_for_risks: env::var("DEFAULT_REQUIRE_APPROVAL_FOR_RISKS")
+                    .unwrap_or_else(|_| "critical".to_string())
+                    .split(',')
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+                    .collect(),
+            },
+
+            kernel_state: KernelStateSettings {
+                persisted_namespaces: env::var("KERNEL_PERSISTED_STATE")
+                    .unwrap_or_else(|_| jarvis_core::namespaces::PLANNING.to_string())
+                    .split(',')
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+                    .collect(),
+                redis_prefix: env::var("KERNEL_STATE_REDIS_PREFIX")
+                    .unwrap_or_else(|_| "talkpp:kernel-state:".to_string()),
+            },
+
+            notifications: NotificationSettings {
+                email_provider: env::var("NOTIFICATION_EMAIL_PROVIDER").unwrap_or_else(|_| "none".to_string()),
+                web_base_url: env::var("WEB_BASE_URL").unwrap_or_else(|_| "http://localhost:3000".to_string()),
+                smtp_host: env::var("SMTP_HOST").ok(),
+                smtp_port: env::var("SMTP_PORT")
+                    .unwrap_or_else(|_| "587".to_string())
+                    .parse()
+                    .unwrap_or(587),
+                smtp_username: env::var("SMTP_USERNAME").unwrap_or_default(),
+                smtp_password: env::var("SMTP_PASSWORD").unwrap_or_default(),
+                from_address: env::var("NOTIFICATION_FROM_ADDRESS")
+                    .unwrap_or_else(|_| "talkpp@localhost".to_string()),
+            },
+
+            capabilities: CapabilitySettings {
+                refresh_secs: env::var("CAPABILITY_REFRESH_SECS")
+                    .unwrap_or_else(|_| "60".to_string())
+                    .parse()
+                    .unwrap_or(60),
+                probe_timeout_secs: env::var("CAPABILITY_PROBE_TIMEOUT_SECS")
+                    .unwrap_or_else(|_| "5".to_string())
+                    .parse()
+                    .unwrap_or(5),
+                qdrant_url: env::var("QDRANT_URL").unwrap_or_else(|_| "http://localhost:6334".to_string()),
+                ollama_url: env::var("OLLAMA_URL").unwrap_or_else(|_| "http://localhost:11434".to_string()),
+            },
+
+            locks: LockSettings {
+                replica_id: env::var("REPLICA_ID")
+                    .or_else(|_| env::var("HOSTNAME"))
+                    .unwrap_or_else(|_| uuid::Uuid::new_v4().to_string()),
+                lease_secs: env::var("JOB_LOCK_LEASE_SECS")
+                    .unwrap_or_else(|_| "30".to_string())
+                    .parse()
+                    .unwrap_or(30),
+                redis_prefix: env::var("JOB_LOCK_REDIS_PREFIX")
+                    .unwrap_or_else(|_| "talkpp:lock:".to_string()),
+            },
+
+            cache: CacheSettings {
+                key_prefix: env::var("CACHE_KEY_PREFIX")
+                    .unwrap_or_else(|_| "talkpp:".to_string()),
+                compress_above_bytes: env::var("CACHE_COMPRESS_ABOVE_BYTES")
+                    .unwrap_or_else(|_| "1024".to_string())
+                    .parse()
+                    .unwrap_or(1024),
+                compression_level: env::var("CACHE_COMPRESSION_LEVEL")
+                    .unwrap_or_else(|_| "3".to_string())
+                    .parse()
+                    .unwrap_or(3),
+                // As in `sessions=86400,idempotency=3600`
+                ttl_secs: env::var("CACHE_TTLS")
+                    .unwrap_or_default()
+                    .split(',')
+                    .filter_map(|entry| entry.split_once('='))
+                    .filter_map(|(namespace, secs)| Some((namespace.trim().to_string(), secs.trim().parse().ok()?)))
+                    .collect(),
             },
         };
 
+        // Namespaces CACHE_TTLS leaves out expire as the settings that predate it say
+        let session_ttl = config.auth.session_timeout_hours * 3600;
+        config.cache.ttl_secs.entry(crate::SESSION_NAMESPACE.to_string()).or_insert(session_ttl);
+        config.cache.ttl_secs.entry(crate::idempotency::CACHE_NAMESPACE.to_string()).or_insert(config.idempotency.ttl_secs);
+
         // Validate required configuration
         config.validate()?;
 
         Ok(config)
     }
 
+    /// The external services this deployment is configured to use, for the capabilities
+    /// document
+    pub fn external_services(&self) -> Vec<String> {
+        let mut services = Vec::new();
+        if self.notifications.email_provider != "none" {
+            services.push(format!("email via {}", self.notifications.email_provider));
+        }
+        if self.artifacts.backend == "s3" {
+            services.push("artifact storage on s3".to_string());
+        }
+        if self.services.vault_addr.is_some() {
+            services.push("secrets from vault".to_string());
+        }
+        services
+    }
+
+    /// Replace secret references in the configuration with their values
+    pub async fn resolve_secrets(&mut self, resolver: &SecretResolver) -> Result<()> {
+        resolver.resolve_in_place(&mut self.jwt_secret).await?;
+        resolver.resolve_in_place(&mut self.memory.pii_hash_key).await
+    }
+
     /// Validate configuration
     fn validate(&self) -> Result<()> {
         if self.database_url.is_none() && env::var("APP_ENV").unwrap_or_default() != "test" {
@@ -148,6 +717,41 @@ impl Config {
             return Err(anyhow::anyhow!("REDIS_URL is required"));
         }
 
+        if !matches!(self.audit.sink.as_str(), "postgres" | "jsonl") {
+            return Err(anyhow::anyhow!("AUDIT_SINK must be 'postgres' or 'jsonl'"));
+        }
+
+        match self.artifacts.backend.as_str() {
+            "filesystem" => {}
+            "s3" if self.artifacts.s3_bucket.is_some() => {}
+            "s3" => return Err(anyhow::anyhow!("ARTIFACT_S3_BUCKET is required with ARTIFACT_BACKEND=s3")),
+            _ => return Err(anyhow::anyhow!("ARTIFACT_BACKEND must be 'filesystem' or 's3'")),
+        }
+
+        match self.notifications.email_provider.as_str() {
+            "none" => {}
+            "smtp" if self.notifications.smtp_host.is_some() => {}
+            "smtp" => return Err(anyhow::anyhow!("SMTP_HOST is required with NOTIFICATION_EMAIL_PROVIDER=smtp")),
+            _ => return Err(anyhow::anyhow!("NOTIFICATION_EMAIL_PROVIDER must be 'smtp' or 'none'")),
+        }
+
+        match self.jobs.model.strip_prefix(crate::jobs::FUNCTION_MODEL_PREFIX) {
+            Some(function_id) if function_id.parse::<uuid::Uuid>().is_err() => {
+                return Err(anyhow::anyhow!("JOB_GENERATION_MODEL must name a function by its id, as in 'function:<uuid>'"));
+            }
+            Some(_) => {}
+            None if !self.jobs.model.starts_with(talkpp_ollama_integration::OLLAMA_MODEL_PREFIX) => {
+                return Err(anyhow::anyhow!(
+                    "JOB_GENERATION_MODEL must name an Ollama model, as in 'ollama:llama3', or a deployed function, as in 'function:<uuid>'"
+                ));
+            }
+            None => {}
+        }
+
+        if self.locks.lease_secs == 0 {
+            return Err(anyhow::anyhow!("JOB_LOCK_LEASE_SECS must be at least 1"));
+        }
+
         if self.jwt_secret == "dev-secret-change-in-production" 
             && env::var("APP_ENV").unwrap_or_default() == "production" {
             return Err(anyhow::anyhow!("JWT_SECRET must be set in production"));
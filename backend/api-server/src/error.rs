@@ -0,0 +1,219 @@
+use std::time::Duration;
+
+use async_graphql::ErrorExtensions;
+use axum::{
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use talkpp_errors::{ErrorKind, InvalidConfig};
+use thiserror::Error;
+use tracing::error;
+
+pub type ApiResult<T> = Result<T, ApiError>;
+
+/// Errors returned by API handlers, rendered as `{"error": "..."}` with a matching status
+#[derive(Debug, Error)]
+pub enum ApiError {
+    #[error("Bad request: {0}")]
+    BadRequest(String),
+
+    /// A configuration in the request broke constraints, each listed in the response
+    #[error("Bad request: {0}")]
+    InvalidConfig(InvalidConfig),
+
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    #[error("Unprocessable entity: {0}")]
+    UnprocessableEntity(String),
+
+    #[error("Too many requests: {message}")]
+    RateLimited { message: String, retry_after: Option<Duration> },
+
+    #[error("Internal error: {0}")]
+    InternalError(String),
+
+    /// A service the request depends on could not be reached or failed
+    #[error("Service unavailable: {0}")]
+    ServiceUnavailable(String),
+
+    /// A subsystem the request needs is unavailable in this deployment, as reported at
+    /// `GET /api/v1/capabilities`
+    #[error("Service unavailable: {subsystem} is unavailable")]
+    CapabilityUnavailable { subsystem: String, reasons: Vec<String> },
+
+    /// A service the request depends on did not answer in time
+    #[error("Gateway timeout: {0}")]
+    GatewayTimeout(String),
+
+    #[error("Redis error: {0}")]
+    Redis(#[from] redis::RedisError),
+}
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) | ApiError::InvalidConfig(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::UnprocessableEntity(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::InternalError(_) | ApiError::Redis(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::ServiceUnavailable(_) | ApiError::CapabilityUnavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::GatewayTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+        }
+    }
+
+    /// The `code` extension of GraphQL errors
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) | ApiError::InvalidConfig(_) => "BAD_REQUEST",
+            ApiError::Unauthorized(_) => "UNAUTHORIZED",
+            ApiError::Forbidden(_) => "FORBIDDEN",
+            ApiError::NotFound(_) => "NOT_FOUND",
+            ApiError::Conflict(_) => "CONFLICT",
+            ApiError::UnprocessableEntity(_) => "UNPROCESSABLE_ENTITY",
+            ApiError::RateLimited { .. } => "RATE_LIMITED",
+            ApiError::InternalError(_) | ApiError::Redis(_) => "INTERNAL",
+            ApiError::ServiceUnavailable(_) => "SERVICE_UNAVAILABLE",
+            ApiError::CapabilityUnavailable { .. } => "CAPABILITY_UNAVAILABLE",
+            ApiError::GatewayTimeout(_) => "GATEWAY_TIMEOUT",
+        }
+    }
+
+    /// Whole seconds to send as `Retry-After`, rounded up
+    fn retry_after_secs(&self) -> Option<u64> {
+        match self {
+            ApiError::RateLimited { retry_after: Some(retry_after), .. } => Some(retry_after.as_secs_f64().ceil() as u64),
+            _ => None,
+        }
+    }
+}
+
+impl From<talkpp_errors::Error> for ApiError {
+    fn from(error: talkpp_errors::Error) -> Self {
+        let message = error.to_string();
+        match error.kind() {
+            ErrorKind::InvalidInput => match error.downcast_ref::<InvalidConfig>() {
+                Some(invalid) => ApiError::InvalidConfig(invalid.clone()),
+                None => ApiError::BadRequest(message),
+            },
+            ErrorKind::NotFound => ApiError::NotFound(message),
+            ErrorKind::Unauthorized => ApiError::Unauthorized(message),
+            ErrorKind::RateLimited => ApiError::RateLimited { message, retry_after: error.retry_after() },
+            ErrorKind::UpstreamTimeout => ApiError::GatewayTimeout(message),
+            ErrorKind::UpstreamUnavailable => ApiError::ServiceUnavailable(message),
+            ErrorKind::Conflict => ApiError::Conflict(message),
+            ErrorKind::Internal => ApiError::InternalError(message),
+        }
+    }
+}
+
+/// Typed library errors anywhere in the chain keep their status; anything else is a 500
+impl From<anyhow::Error> for ApiError {
+    fn from(error: anyhow::Error) -> Self {
+        talkpp_errors::Error::from(error).into()
+    }
+}
+
+/// Adds `code` and `status` extensions, `retryAfter` in seconds when there is one,
+/// `capability` when a subsystem is unavailable and `violations` for invalid configs
+impl ErrorExtensions for ApiError {
+    fn extend(&self) -> async_graphql::Error {
+        async_graphql::Error::new(self.to_string()).extend_with(|_, extensions| {
+            extensions.set("code", self.code());
+            extensions.set("status", self.status().as_u16());
+            if let Some(seconds) = self.retry_after_secs() {
+                extensions.set("retryAfter", seconds);
+            }
+            if let ApiError::CapabilityUnavailable { subsystem, .. } = self {
+                extensions.set("capability", subsystem.as_str());
+            }
+            if let ApiError::InvalidConfig(invalid) = self {
+                extensions.set("violations", async_graphql::to_value(&invalid.violations).unwrap_or_default());
+            }
+        })
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        if status.is_server_error() {
+            error!("{}", self);
+        }
+        let body = match &self {
+            ApiError::CapabilityUnavailable { subsystem, reasons } => serde_json::json!({
+                "error": self.to_string(),
+                "capability": subsystem,
+                "reasons": reasons,
+                "hint": "/api/v1/capabilities",
+            }),
+            ApiError::InvalidConfig(invalid) => serde_json::json!({
+                "error": self.to_string(),
+                "violations": invalid.violations,
+            }),
+            _ => serde_json::json!({ "error": self.to_string() }),
+        };
+        let mut response = (status, Json(body)).into_response();
+        if let Some(seconds) = self.retry_after_secs() {
+            response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_kinds_map_to_statuses() {
+        let statuses: Vec<_> = ErrorKind::ALL.into_iter()
+            .map(|kind| ApiError::from(talkpp_errors::Error::new(kind, "x")).status().as_u16())
+            .collect();
+        assert_eq!(statuses, [400, 404, 401, 429, 504, 503, 409, 500]);
+
+        // Beneath anyhow context, a typed error keeps its status
+        let missing = anyhow::Error::new(talkpp_errors::Error::not_found("No session 's1'")).context("Failed to resume chat");
+        assert!(matches!(ApiError::from(missing), ApiError::NotFound(message) if message == "Failed to resume chat"));
+        assert!(matches!(ApiError::from(anyhow::anyhow!("boom")), ApiError::InternalError(_)));
+    }
+
+    #[test]
+    fn test_rate_limits_report_retry_after() {
+        let limited = ApiError::from(talkpp_errors::Error::rate_limited("Slow down").with_retry_after(Duration::from_millis(1500)));
+
+        let graphql = limited.extend();
+        let extensions = serde_json::to_value(graphql.extensions.as_ref().unwrap()).unwrap();
+        assert_eq!(extensions, serde_json::json!({"code": "RATE_LIMITED", "status": 429, "retryAfter": 2}));
+
+        let response = limited.into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "2");
+    }
+
+    #[test]
+    fn test_invalid_configs_list_every_violation() {
+        let mut violations = talkpp_errors::Violations::new();
+        violations.push("name", "must not be empty");
+        violations.push("connection.url", "must be an http:// or https:// URL");
+        let invalid = ApiError::from(anyhow::Error::new(violations.into_result("MCP server config").unwrap_err()));
+        assert!(matches!(invalid, ApiError::InvalidConfig(_)));
+
+        let extensions = serde_json::to_value(invalid.extend().extensions.as_ref().unwrap()).unwrap();
+        assert_eq!(extensions["violations"][1]["field"], "connection.url");
+        assert_eq!(invalid.into_response().status(), StatusCode::BAD_REQUEST);
+    }
+}
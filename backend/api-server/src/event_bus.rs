@@ -0,0 +1,415 @@
+//! Events fanned out to every api-server replica
+//!
+//! Plan, task and kernel state events are published on a bus with one channel per topic.
+//! [`MemoryEventBus`] delivers them within this process only; [`RedisEventBus`] also
+//! publishes them over Redis pub/sub, so a client following a plan on one replica sees
+//! what happens to it on another.
+//!
+//! Delivery is at least once: an event can reach a subscriber twice, for instance when a
+//! publish is retried after Redis failed to acknowledge it. Subscriptions drop events
+//! whose id they have already seen.
+
+use std::collections::{HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use redis::aio::ConnectionManager;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+use tokio_stream::StreamExt;
+use tracing::warn;
+use uuid::Uuid;
+
+/// Events a subscriber may fall behind by before it misses some
+const SUBSCRIBER_CAPACITY: usize = 1024;
+
+/// Event ids a subscription remembers to recognise duplicates
+const DEDUP_WINDOW: usize = 1024;
+
+/// How long to wait before publishing or subscribing again after Redis failed
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Counter of events dropped because Redis stayed unavailable for too long
+pub const DROPPED_EVENTS_METRIC: &str = "talkpp_event_bus_dropped_total";
+
+/// What an event is about; each topic has its own channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Topic {
+    /// Events appended to an intent's stream
+    Plan,
+    /// Progress of plan tasks and approval decisions
+    Task,
+    /// Changes to the kernel's global state
+    KernelState,
+}
+
+impl Topic {
+    pub const ALL: [Topic; 3] = [Topic::Plan, Topic::Task, Topic::KernelState];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Topic::Plan => "plan",
+            Topic::Task => "task",
+            Topic::KernelState => "kernel_state",
+        }
+    }
+}
+
+/// An event as sent between replicas, with short field names to keep messages small
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BusEvent {
+    #[serde(rename = "t")]
+    pub topic: Topic,
+    #[serde(rename = "i")]
+    pub id: Uuid,
+    /// Replica that published the event
+    #[serde(rename = "o")]
+    pub origin: String,
+    #[serde(rename = "p")]
+    pub payload: serde_json::Value,
+}
+
+pub trait EventBus: Send + Sync {
+    /// Deliver `payload` to the subscribers of `topic` on every replica, returning the
+    /// event's id. Never waits on other replicas.
+    fn publish(&self, topic: Topic, payload: serde_json::Value) -> Uuid;
+
+    /// Events on `topic` from now on, from every replica including this one
+    fn subscribe(&self, topic: Topic) -> BusSubscription;
+
+    /// Names this replica as the origin of the events it publishes
+    fn replica_id(&self) -> &str;
+}
+
+/// Events on one topic, without duplicates
+pub struct BusSubscription {
+    receiver: broadcast::Receiver<BusEvent>,
+    seen: HashSet<Uuid>,
+    order: VecDeque<Uuid>,
+}
+
+impl BusSubscription {
+    fn new(receiver: broadcast::Receiver<BusEvent>) -> Self {
+        Self { receiver, seen: HashSet::new(), order: VecDeque::new() }
+    }
+
+    /// The next event not received before, or `None` once the bus is gone. Events missed
+    /// by falling behind are skipped.
+    pub async fn recv(&mut self) -> Option<BusEvent> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(event) => {
+                    if !self.seen.insert(event.id) {
+                        continue;
+                    }
+                    self.order.push_back(event.id);
+                    if self.order.len() > DEDUP_WINDOW {
+                        if let Some(oldest) = self.order.pop_front() {
+                            self.seen.remove(&oldest);
+                        }
+                    }
+                    return Some(event);
+                }
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    warn!("Event bus subscriber fell behind and missed {} events", missed);
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+/// A broadcast channel per topic, delivering to this process's subscribers
+#[derive(Clone)]
+struct LocalTopics {
+    plan: broadcast::Sender<BusEvent>,
+    task: broadcast::Sender<BusEvent>,
+    kernel_state: broadcast::Sender<BusEvent>,
+}
+
+impl LocalTopics {
+    fn new() -> Self {
+        Self {
+            plan: broadcast::channel(SUBSCRIBER_CAPACITY).0,
+            task: broadcast::channel(SUBSCRIBER_CAPACITY).0,
+            kernel_state: broadcast::channel(SUBSCRIBER_CAPACITY).0,
+        }
+    }
+
+    fn sender(&self, topic: Topic) -> &broadcast::Sender<BusEvent> {
+        match topic {
+            Topic::Plan => &self.plan,
+            Topic::Task => &self.task,
+            Topic::KernelState => &self.kernel_state,
+        }
+    }
+
+    fn deliver(&self, event: BusEvent) {
+        // No subscribers on this replica is fine
+        let _ = self.sender(event.topic).send(event);
+    }
+}
+
+/// Process-local bus, for tests and single-instance deployments
+pub struct MemoryEventBus {
+    replica_id: String,
+    local: LocalTopics,
+}
+
+impl MemoryEventBus {
+    pub fn new() -> Self {
+        Self { replica_id: "local".to_string(), local: LocalTopics::new() }
+    }
+}
+
+impl Default for MemoryEventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus for MemoryEventBus {
+    fn publish(&self, topic: Topic, payload: serde_json::Value) -> Uuid {
+        let id = Uuid::new_v4();
+        self.local.deliver(BusEvent { topic, id, origin: self.replica_id.clone(), payload });
+        id
+    }
+
+    fn subscribe(&self, topic: Topic) -> BusSubscription {
+        BusSubscription::new(self.local.sender(topic).subscribe())
+    }
+
+    fn replica_id(&self) -> &str {
+        &self.replica_id
+    }
+}
+
+/// Bus shared by every replica through Redis pub/sub.
+///
+/// Events are delivered to this replica's subscribers straight away and published to
+/// Redis in the background, in order. While Redis is unavailable up to `max_buffered`
+/// events wait to be published, retried every second; past that the oldest are dropped
+/// and counted in [`DROPPED_EVENTS_METRIC`]. The subscription to Redis is likewise
+/// re-established after it fails, and events published meanwhile are not received.
+pub struct RedisEventBus {
+    replica_id: String,
+    local: LocalTopics,
+    outbox: mpsc::UnboundedSender<(String, String)>,
+    prefix: String,
+    dropped: Arc<AtomicU64>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl RedisEventBus {
+    /// Bus publishing as `replica_id` on the channels `<prefix><topic>`. Connects in the
+    /// background, so Redis need not be up yet.
+    pub fn new(client: &redis::Client, replica_id: impl Into<String>, prefix: impl Into<String>, max_buffered: usize) -> Self {
+        let replica_id = replica_id.into();
+        let prefix = prefix.into();
+        let local = LocalTopics::new();
+        let dropped = Arc::new(AtomicU64::new(0));
+        let (outbox, pending) = mpsc::unbounded_channel();
+        let channels = Topic::ALL.iter().map(|topic| format!("{}{}", prefix, topic.name())).collect();
+
+        let tasks = vec![
+            tokio::spawn(publish_loop(client.clone(), pending, max_buffered.max(1), dropped.clone())),
+            tokio::spawn(listen_loop(client.clone(), channels, replica_id.clone(), local.clone())),
+        ];
+        Self { replica_id, local, outbox, prefix, dropped, tasks }
+    }
+
+    /// Events dropped since this bus started because Redis was unavailable
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl EventBus for RedisEventBus {
+    fn publish(&self, topic: Topic, payload: serde_json::Value) -> Uuid {
+        let event = BusEvent { topic, id: Uuid::new_v4(), origin: self.replica_id.clone(), payload };
+        let id = event.id;
+        match serde_json::to_string(&event) {
+            Ok(message) => {
+                let _ = self.outbox.send((format!("{}{}", self.prefix, topic.name()), message));
+            }
+            Err(e) => warn!("Failed to serialize a {} event: {}", topic.name(), e),
+        }
+        self.local.deliver(event);
+        id
+    }
+
+    fn subscribe(&self, topic: Topic) -> BusSubscription {
+        BusSubscription::new(self.local.sender(topic).subscribe())
+    }
+
+    fn replica_id(&self) -> &str {
+        &self.replica_id
+    }
+}
+
+impl Drop for RedisEventBus {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+/// Publish queued events to Redis in order, buffering at most `max_buffered` while it is
+/// unavailable
+async fn publish_loop(
+    client: redis::Client,
+    mut outbox: mpsc::UnboundedReceiver<(String, String)>,
+    max_buffered: usize,
+    dropped: Arc<AtomicU64>,
+) {
+    let mut pending = VecDeque::new();
+    let mut connection: Option<ConnectionManager> = None;
+    loop {
+        if pending.is_empty() {
+            match outbox.recv().await {
+                Some(message) => pending.push_back(message),
+                None => return,
+            }
+        }
+        while let Ok(message) = outbox.try_recv() {
+            pending.push_back(message);
+        }
+        while pending.len() > max_buffered {
+            pending.pop_front();
+            dropped.fetch_add(1, Ordering::Relaxed);
+            metrics::counter!(DROPPED_EVENTS_METRIC).increment(1);
+        }
+
+        let published = async {
+            // The manager reconnects by itself once it has connected
+            let mut manager = match connection.clone() {
+                Some(manager) => manager,
+                None => connection.insert(client.get_connection_manager().await?).clone(),
+            };
+            while let Some((channel, message)) = pending.front() {
+                redis::cmd("PUBLISH").arg(channel).arg(message).query_async::<_, ()>(&mut manager).await?;
+                pending.pop_front();
+            }
+            Ok::<_, redis::RedisError>(())
+        };
+        if let Err(e) = published.await {
+            warn!("Failed to publish {} events to Redis, retrying: {}", pending.len(), e);
+            tokio::time::sleep(RETRY_INTERVAL).await;
+        }
+    }
+}
+
+/// Deliver events other replicas publish to this replica's subscribers, subscribing
+/// again whenever the subscription fails
+async fn listen_loop(client: redis::Client, channels: Vec<String>, replica_id: String, local: LocalTopics) {
+    loop {
+        let subscribed = async {
+            let mut pubsub = client.get_async_connection().await?.into_pubsub();
+            pubsub.subscribe(&channels).await?;
+            Ok::<_, redis::RedisError>(pubsub)
+        };
+        match subscribed.await {
+            Ok(mut pubsub) => {
+                let mut messages = pubsub.on_message();
+                while let Some(message) = messages.next().await {
+                    let payload: Vec<u8> = match message.get_payload() {
+                        Ok(payload) => payload,
+                        Err(e) => {
+                            warn!("Unreadable event bus message on {}: {}", message.get_channel_name(), e);
+                            continue;
+                        }
+                    };
+                    match serde_json::from_slice::<BusEvent>(&payload) {
+                        // Events from this replica were delivered when published
+                        Ok(event) if event.origin == replica_id => {}
+                        Ok(event) => local.deliver(event),
+                        Err(e) => warn!("Malformed event bus message on {}: {}", message.get_channel_name(), e),
+                    }
+                }
+                warn!("Event bus subscription to Redis closed, subscribing again");
+            }
+            Err(e) => warn!("Failed to subscribe to the event bus in Redis: {}", e),
+        }
+        tokio::time::sleep(RETRY_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_subscriptions_drop_duplicate_deliveries() {
+        let bus = MemoryEventBus::new();
+        let mut subscription = bus.subscribe(Topic::Task);
+        let event = BusEvent { topic: Topic::Task, id: Uuid::new_v4(), origin: "a".to_string(), payload: json!(1) };
+        bus.local.deliver(event.clone());
+        bus.local.deliver(event.clone());
+        let second = bus.publish(Topic::Task, json!(2));
+        bus.publish(Topic::Plan, json!(3));
+
+        assert_eq!(subscription.recv().await, Some(event));
+        assert_eq!(subscription.recv().await.unwrap().id, second);
+        drop(bus);
+        assert_eq!(subscription.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_events_past_the_buffer_are_dropped_while_redis_is_down() {
+        // Nothing listens on port 1, so every publish fails
+        let client = redis::Client::open("redis://127.0.0.1:1").unwrap();
+        let bus = RedisEventBus::new(&client, "a", "test-events:", 2);
+        let mut subscription = bus.subscribe(Topic::Plan);
+        for n in 0..5 {
+            bus.publish(Topic::Plan, json!(n));
+        }
+
+        // Still delivered on this replica
+        assert_eq!(subscription.recv().await.unwrap().payload, json!(0));
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while bus.dropped() < 3 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    async fn next(subscription: &mut BusSubscription) -> BusEvent {
+        tokio::time::timeout(Duration::from_secs(5), subscription.recv()).await.unwrap().unwrap()
+    }
+
+    /// Run with `TEST_REDIS_URL=redis://localhost:6379 cargo test -- --ignored`
+    #[tokio::test]
+    #[ignore = "needs a Redis server at TEST_REDIS_URL"]
+    async fn test_redis_bus_delivers_across_replicas_once() {
+        let url = std::env::var("TEST_REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379".to_string());
+        let client = redis::Client::open(url.as_str()).unwrap();
+        let prefix = format!("test-events-{}:", Uuid::new_v4());
+        let a = RedisEventBus::new(&client, "replica-a", prefix.clone(), 64);
+        let b = RedisEventBus::new(&client, "replica-b", prefix, 64);
+        let (mut on_a, mut on_b) = (a.subscribe(Topic::Task), b.subscribe(Topic::Task));
+        // Let both replicas subscribe in Redis
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        let from_a = a.publish(Topic::Task, json!({"task": "deploy"}));
+        let from_b = b.publish(Topic::Task, json!({"task": "verify"}));
+        let mut received_by_a = vec![next(&mut on_a).await.id, next(&mut on_a).await.id];
+        let mut received_by_b = vec![next(&mut on_b).await.id, next(&mut on_b).await.id];
+        received_by_a.sort();
+        received_by_b.sort();
+        let mut expected = vec![from_a, from_b];
+        expected.sort();
+        assert_eq!(received_by_a, expected);
+        assert_eq!(received_by_b, expected);
+
+        // Each event arrives once, though a replica also hears its own events back
+        let again = tokio::time::timeout(Duration::from_millis(300), on_a.recv()).await;
+        assert!(again.is_err(), "a duplicate event was delivered: {:?}", again);
+    }
+}
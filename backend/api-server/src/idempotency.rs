@@ -0,0 +1,407 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use axum::{
+    async_trait,
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+use tracing::warn;
+
+use crate::error::ApiError;
+use crate::services::redis_store::{Caches, RedisStore};
+use crate::UserSession;
+
+/// Cache namespace idempotency records are kept in
+pub const CACHE_NAMESPACE: &str = "idempotency";
+
+/// Request header carrying the client's idempotency key
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Response header marking a response replayed from an earlier request
+pub const REPLAYED_HEADER: &str = "idempotent-replayed";
+
+/// Largest request or response body the middleware will buffer
+const MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// A response kept for replay
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredResponse {
+    pub status: u16,
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+}
+
+/// What is stored against a key: the request it was claimed for and, once done, its response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Record {
+    request_hash: String,
+    response: Option<StoredResponse>,
+}
+
+/// Result of claiming a key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Claim {
+    /// The key is new; the caller must `complete` or `release` it
+    Acquired,
+    /// The same request is still being processed
+    InFlight,
+    /// The same request already finished with this response
+    Completed(StoredResponse),
+    /// The key was used for a different request
+    Mismatch,
+}
+
+#[async_trait]
+pub trait IdempotencyStore: Send + Sync {
+    /// Claim `key` for the request hashing to `request_hash`, or report how it is already used
+    async fn claim(&self, key: &str, request_hash: &str) -> Result<Claim>;
+
+    /// Keep the response to a claimed request for replay
+    async fn complete(&self, key: &str, request_hash: &str, response: StoredResponse) -> Result<()>;
+
+    /// Drop a claim so the request can be retried
+    async fn release(&self, key: &str) -> Result<()>;
+}
+
+impl Record {
+    fn claim(&self, request_hash: &str) -> Claim {
+        if self.request_hash != request_hash {
+            return Claim::Mismatch;
+        }
+        match &self.response {
+            Some(response) => Claim::Completed(response.clone()),
+            None => Claim::InFlight,
+        }
+    }
+}
+
+/// Idempotency records in the [`CACHE_NAMESPACE`] store, shared by every api-server
+/// replica; completed responses are replayed for the namespace's TTL
+pub struct RedisIdempotencyStore {
+    records: RedisStore<Record>,
+    /// How long an unfinished claim blocks retries, in case its process died
+    lock_ttl: Duration,
+}
+
+impl RedisIdempotencyStore {
+    pub fn new(caches: &Caches, lock_ttl: Duration) -> Self {
+        Self { records: caches.store(CACHE_NAMESPACE), lock_ttl }
+    }
+}
+
+#[async_trait]
+impl IdempotencyStore for RedisIdempotencyStore {
+    async fn claim(&self, key: &str, request_hash: &str) -> Result<Claim> {
+        let pending = Record { request_hash: request_hash.to_string(), response: None };
+
+        // The record can expire, or be evicted as unreadable, between a failed insert and
+        // the read, in which case claim again
+        loop {
+            if self.records.insert_if_absent(key, &pending, Some(self.lock_ttl)).await? {
+                return Ok(Claim::Acquired);
+            }
+            if let Some(record) = self.records.get(key).await? {
+                return Ok(record.claim(request_hash));
+            }
+        }
+    }
+
+    async fn complete(&self, key: &str, request_hash: &str, response: StoredResponse) -> Result<()> {
+        let record = Record { request_hash: request_hash.to_string(), response: Some(response) };
+        self.records.set(key, &record).await
+    }
+
+    async fn release(&self, key: &str) -> Result<()> {
+        self.records.delete(key).await?;
+        Ok(())
+    }
+}
+
+/// Process-local idempotency records, for tests and single-instance deployments
+pub struct MemoryIdempotencyStore {
+    records: Mutex<HashMap<String, (Record, Instant)>>,
+    ttl: Duration,
+    lock_ttl: Duration,
+}
+
+impl MemoryIdempotencyStore {
+    pub fn new(ttl: Duration, lock_ttl: Duration) -> Self {
+        Self { records: Mutex::new(HashMap::new()), ttl, lock_ttl }
+    }
+}
+
+#[async_trait]
+impl IdempotencyStore for MemoryIdempotencyStore {
+    async fn claim(&self, key: &str, request_hash: &str) -> Result<Claim> {
+        let mut records = self.records.lock().unwrap();
+        let now = Instant::now();
+        records.retain(|_, (_, expires_at)| *expires_at > now);
+
+        if let Some((record, _)) = records.get(key) {
+            return Ok(record.claim(request_hash));
+        }
+        let record = Record { request_hash: request_hash.to_string(), response: None };
+        records.insert(key.to_string(), (record, now + self.lock_ttl));
+        Ok(Claim::Acquired)
+    }
+
+    async fn complete(&self, key: &str, request_hash: &str, response: StoredResponse) -> Result<()> {
+        let record = Record { request_hash: request_hash.to_string(), response: Some(response) };
+        self.records.lock().unwrap().insert(key.to_string(), (record, Instant::now() + self.ttl));
+        Ok(())
+    }
+
+    async fn release(&self, key: &str) -> Result<()> {
+        self.records.lock().unwrap().remove(key);
+        Ok(())
+    }
+}
+
+/// Shared handle to the idempotency store, used as middleware state
+#[derive(Clone)]
+pub struct Idempotency {
+    store: Arc<dyn IdempotencyStore>,
+}
+
+impl Idempotency {
+    pub fn new(store: Arc<dyn IdempotencyStore>) -> Self {
+        Self { store }
+    }
+
+    pub fn store(&self) -> &dyn IdempotencyStore {
+        self.store.as_ref()
+    }
+}
+
+/// Hex SHA-256 of a request, used to tell a retry from a different request under the same key
+pub fn request_hash(bytes: &[u8]) -> String {
+    ring::digest::digest(&ring::digest::SHA256, bytes)
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Key scoped to the caller and the operation, so clients cannot collide with each other
+pub fn scoped_key(session: Option<&UserSession>, operation: &str, key: &str) -> String {
+    let owner = session.map(|s| s.user_id.to_string()).unwrap_or_else(|| "anonymous".to_string());
+    format!("{}:{}:{}", owner, operation, key)
+}
+
+/// Middleware making a route idempotent for requests that send an `Idempotency-Key`.
+///
+/// Opt a route in with
+/// `post(handler).route_layer(middleware::from_fn_with_state(idempotency, idempotency::enforce))`.
+/// A repeat of a completed request gets the stored response with `Idempotent-Replayed: true`;
+/// a repeat while the first is still running gets 409, and the same key with a different
+/// body gets 422. Only successful responses are kept, so failed requests can be retried.
+pub async fn enforce(State(idempotency): State<Idempotency>, request: Request, next: Next) -> Response {
+    let Some(key) = request.headers().get(IDEMPOTENCY_KEY_HEADER) else {
+        return next.run(request).await;
+    };
+    let key = match key.to_str() {
+        Ok(key) if !key.is_empty() => key.to_string(),
+        _ => return ApiError::BadRequest("Invalid Idempotency-Key header".to_string()).into_response(),
+    };
+
+    let (parts, body) = request.into_parts();
+    let body = match to_bytes(body, MAX_BODY_BYTES).await {
+        Ok(body) => body,
+        Err(e) => return ApiError::BadRequest(format!("Failed to read request body: {}", e)).into_response(),
+    };
+    let operation = format!("{} {}", parts.method, parts.uri.path());
+    let key = scoped_key(parts.extensions.get::<UserSession>(), &operation, &key);
+    let hash = request_hash(&body);
+
+    let store = idempotency.store();
+    match store.claim(&key, &hash).await {
+        Ok(Claim::Acquired) => {}
+        Ok(Claim::Completed(stored)) => return replay(stored),
+        Ok(Claim::InFlight) => {
+            return ApiError::Conflict("A request with this Idempotency-Key is still in progress".to_string())
+                .into_response()
+        }
+        Ok(Claim::Mismatch) => {
+            return ApiError::UnprocessableEntity("Idempotency-Key was already used for a different request".to_string())
+                .into_response()
+        }
+        Err(e) => return ApiError::from(e).into_response(),
+    }
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+    if !response.status().is_success() {
+        if let Err(e) = store.release(&key).await {
+            warn!("Failed to release idempotency key: {}", e);
+        }
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = match to_bytes(body, MAX_BODY_BYTES).await {
+        Ok(body) => body,
+        Err(e) => {
+            if let Err(e) = store.release(&key).await {
+                warn!("Failed to release idempotency key: {}", e);
+            }
+            return ApiError::InternalError(format!("Failed to read response body: {}", e)).into_response();
+        }
+    };
+    let stored = StoredResponse {
+        status: parts.status.as_u16(),
+        content_type: parts.headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).map(str::to_string),
+        body: body.to_vec(),
+    };
+    if let Err(e) = store.complete(&key, &hash, stored).await {
+        warn!("Failed to store idempotent response: {}", e);
+    }
+    Response::from_parts(parts, Body::from(body))
+}
+
+fn replay(stored: StoredResponse) -> Response {
+    let status = StatusCode::from_u16(stored.status).unwrap_or(StatusCode::OK);
+    let mut response = (status, stored.body).into_response();
+    let headers = response.headers_mut();
+    if let Some(content_type) = stored.content_type.and_then(|v| HeaderValue::from_str(&v).ok()) {
+        headers.insert(header::CONTENT_TYPE, content_type);
+    }
+    headers.insert(REPLAYED_HEADER, HeaderValue::from_static("true"));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use axum::{http::Method, middleware, response::Json, routing::post, Router};
+    use tower::ServiceExt;
+    use crate::config::CacheSettings;
+    use crate::services::redis_store::{KeyValueBackend, MemoryBackend};
+
+    const TTL: Duration = Duration::from_secs(60);
+    const LOCK_TTL: Duration = Duration::from_secs(10);
+
+    /// Router whose handler counts calls and takes `delay` to answer
+    fn app(calls: Arc<AtomicUsize>, delay: Duration) -> Router {
+        app_with(Arc::new(MemoryIdempotencyStore::new(TTL, LOCK_TTL)), calls, delay)
+    }
+
+    fn app_with(store: Arc<dyn IdempotencyStore>, calls: Arc<AtomicUsize>, delay: Duration) -> Router {
+        let idempotency = Idempotency::new(store);
+        let handler = move |Json(body): Json<serde_json::Value>| async move {
+            let call = calls.fetch_add(1, Ordering::SeqCst) + 1;
+            tokio::time::sleep(delay).await;
+            Json(serde_json::json!({ "call": call, "echo": body }))
+        };
+        Router::new().route("/intents", post(handler).route_layer(middleware::from_fn_with_state(idempotency, enforce)))
+    }
+
+    async fn send(app: &Router, key: Option<&str>, body: serde_json::Value) -> (StatusCode, bool, serde_json::Value) {
+        let mut request = Request::builder()
+            .method(Method::POST)
+            .uri("/intents")
+            .header("content-type", "application/json");
+        if let Some(key) = key {
+            request = request.header(IDEMPOTENCY_KEY_HEADER, key);
+        }
+        let response = app.clone().oneshot(request.body(Body::from(body.to_string())).unwrap()).await.unwrap();
+
+        let status = response.status();
+        let replayed = response.headers().contains_key(REPLAYED_HEADER);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, replayed, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn test_repeat_request_replays_the_original_response() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = app(calls.clone(), Duration::ZERO);
+        let body = serde_json::json!({ "intent": "deploy the site" });
+
+        let (status, replayed, first) = send(&app, Some("abc"), body.clone()).await;
+        assert_eq!((status, replayed), (StatusCode::OK, false));
+
+        let (status, replayed, second) = send(&app, Some("abc"), body.clone()).await;
+        assert_eq!((status, replayed), (StatusCode::OK, true));
+        assert_eq!(first, second);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Without a key, or with another key, requests are processed as usual
+        send(&app, None, body.clone()).await;
+        send(&app, Some("def"), body).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_conflicting_requests_are_rejected() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = app(calls.clone(), Duration::from_secs(1));
+        let body = serde_json::json!({ "intent": "deploy the site" });
+
+        let first = tokio::spawn({
+            let (app, body) = (app.clone(), body.clone());
+            async move { send(&app, Some("abc"), body).await }
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let (status, _, _) = send(&app, Some("abc"), body.clone()).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(first.await.unwrap().0, StatusCode::OK);
+
+        let (status, _, _) = send(&app, Some("abc"), serde_json::json!({ "intent": "something else" })).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_keys_expire_after_the_ttl() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = app(calls.clone(), Duration::ZERO);
+        let body = serde_json::json!({ "intent": "deploy the site" });
+
+        send(&app, Some("abc"), body.clone()).await;
+        tokio::time::advance(TTL - Duration::from_secs(1)).await;
+        assert!(send(&app, Some("abc"), body.clone()).await.1);
+
+        tokio::time::advance(Duration::from_secs(2)).await;
+        let (status, replayed, response) = send(&app, Some("abc"), body).await;
+        assert_eq!((status, replayed), (StatusCode::OK, false));
+        assert_eq!(response["call"], 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cached_records_replay_until_unreadable() {
+        let backend = Arc::new(MemoryBackend::new());
+        let settings = CacheSettings {
+            key_prefix: "talkpp:".to_string(),
+            compress_above_bytes: 64,
+            compression_level: 3,
+            ttl_secs: HashMap::from([(CACHE_NAMESPACE.to_string(), TTL.as_secs())]),
+        };
+        let store = RedisIdempotencyStore::new(&Caches::new(backend.clone(), settings), LOCK_TTL);
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = app_with(Arc::new(store), calls.clone(), Duration::ZERO);
+        let body = serde_json::json!({ "intent": "deploy the site" });
+
+        send(&app, Some("abc"), body.clone()).await;
+        let (status, replayed, response) = send(&app, Some("abc"), body.clone()).await;
+        assert_eq!((status, replayed, &response["call"]), (StatusCode::OK, true, &serde_json::json!(1)));
+
+        // A record this build cannot read is dropped and the request handled afresh
+        let key = backend.scan("talkpp:idempotency:").await.unwrap().remove(0);
+        backend.set(&key, b"{\"request_hash\":".to_vec(), None).await.unwrap();
+        let (status, replayed, response) = send(&app, Some("abc"), body.clone()).await;
+        assert_eq!((status, replayed, &response["call"]), (StatusCode::OK, false, &serde_json::json!(2)));
+
+        tokio::time::advance(TTL + Duration::from_secs(1)).await;
+        assert!(!send(&app, Some("abc"), body).await.1);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+}
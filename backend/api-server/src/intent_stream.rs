@@ -0,0 +1,700 @@
+//! Server-sent events following an intent through processing
+//!
+//! `POST /intents?stream=true` answers with `text/event-stream` instead of JSON, and
+//! `GET /intents/:intent_id/stream` follows an intent already submitted. Each event is
+//! named after its `type` and carries it as JSON data:
+//!
+//! - `parsing_complete`: the intent's id, classified domain and risk level
+//! - `plan_created`: the plan, as `POST /intents` returns it
+//! - `task_status`: a task started or finished
+//! - `token`: text a task generated, such as LLM output, as it is produced
+//! - `done` or `error`: the last event of the stream
+//!
+//! Plans are executed only when a runner is configured with `with_execution` and the plan
+//! needs no approval; otherwise `done` follows the plan straight away.
+//!
+//! Event ids increase by one per intent. Events are kept in a short buffer, in Redis so any
+//! replica can serve them, and a client reconnecting with `Last-Event-ID` is sent the
+//! buffered events after that id before live ones. Appends are announced on the event
+//! bus, so followers on every replica read new events as soon as they are buffered. A comment is sent every 15 seconds so
+//! proxies do not close idle streams. Only the user who submitted the intent can follow it.
+
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use axum::{
+    extract::{Extension, FromRef, Path, State},
+    http::HeaderMap,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
+    routing::get,
+    Router,
+};
+use chrono::{DateTime, Utc};
+use jarvis_core::{Environment, ExecutionState, Intent, IntentExecutionPlan, PlanEvent, PlanExecutor, TaskRunner};
+use redis::aio::ConnectionManager;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{instrument, warn};
+use uuid::Uuid;
+
+use crate::batch::SharedRunner;
+use crate::error::{ApiError, ApiResult};
+use crate::event_bus::{EventBus, MemoryEventBus, Topic};
+use crate::notifications::Notifier;
+use crate::{ProcessIntentResponse, UserSession};
+
+/// Request header a reconnecting client names the last event it received in
+pub const LAST_EVENT_ID_HEADER: &str = "last-event-id";
+
+/// How often a comment is sent on an otherwise idle stream
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// How often a follower looks at the buffer for appends it was not told about
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Events a follower may fall behind by before it is sent them
+const FOLLOWER_CAPACITY: usize = 64;
+
+/// Plan events an execution may queue before its publisher catches up
+const EXECUTION_EVENT_CAPACITY: usize = 4096;
+
+/// Something that happened to an intent
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum IntentEvent {
+    ParsingComplete { intent_id: Uuid, domain: String, risk_level: String },
+    PlanCreated { plan: serde_json::Value },
+    TaskStatus { task_id: Uuid, status: String },
+    Token { task_id: Uuid, text: String },
+    Done { plan_id: Uuid, execution: Option<serde_json::Value> },
+    Error { message: String },
+}
+
+impl IntentEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            IntentEvent::ParsingComplete { .. } => "parsing_complete",
+            IntentEvent::PlanCreated { .. } => "plan_created",
+            IntentEvent::TaskStatus { .. } => "task_status",
+            IntentEvent::Token { .. } => "token",
+            IntentEvent::Done { .. } => "done",
+            IntentEvent::Error { .. } => "error",
+        }
+    }
+
+    /// Nothing follows this event
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, IntentEvent::Done { .. } | IntentEvent::Error { .. })
+    }
+
+    /// The event a plan event is streamed as, if any
+    fn from_plan_event(event: PlanEvent) -> Option<Self> {
+        match event {
+            PlanEvent::TaskStarted { task_id, .. } => Some(IntentEvent::TaskStatus { task_id, status: "InProgress".to_string() }),
+            PlanEvent::TaskOutputDelta { task_id, delta, .. } => Some(IntentEvent::Token { task_id, text: delta }),
+            PlanEvent::TaskFinished { task_id, status, .. } => Some(IntentEvent::TaskStatus { task_id, status: format!("{:?}", status) }),
+            // The execution in the `done` event says where the plan paused
+            PlanEvent::BudgetExceeded { .. } => None,
+        }
+    }
+}
+
+/// An event as buffered, with its id in the intent's stream
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StreamEvent {
+    pub id: u64,
+    pub event: IntentEvent,
+}
+
+impl StreamEvent {
+    fn to_sse(&self) -> Event {
+        Event::default()
+            .id(self.id.to_string())
+            .event(self.event.name())
+            .json_data(&self.event)
+            .unwrap_or_else(|_| Event::default().id(self.id.to_string()).event(self.event.name()))
+    }
+}
+
+/// Who may follow an intent's stream
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StreamInfo {
+    /// User who submitted the intent; anyone may follow it when `None`
+    pub owner: Option<Uuid>,
+    pub opened_at: DateTime<Utc>,
+}
+
+#[async_trait]
+pub trait EventBuffer: Send + Sync {
+    /// Start the stream of `intent_id`, with no events yet
+    async fn open(&self, intent_id: Uuid, info: &StreamInfo) -> Result<()>;
+
+    async fn info(&self, intent_id: Uuid) -> Result<Option<StreamInfo>>;
+
+    /// Append an event to an open stream, returning its id
+    async fn append(&self, intent_id: Uuid, event: &IntentEvent) -> Result<u64>;
+
+    /// Buffered events with ids above `after`, oldest first, or `None` when the stream is
+    /// unknown or expired. Events pushed out of the buffer are not returned.
+    async fn since(&self, intent_id: Uuid, after: u64) -> Result<Option<Vec<StreamEvent>>>;
+}
+
+/// Streams in Redis, shared by every api-server replica.
+///
+/// Each stream is a JSON record, a counter handing out event ids, and a sorted set of
+/// events scored by id that keeps the latest `max_events`. All three expire `retention`
+/// after the last event.
+pub struct RedisEventBuffer {
+    connection: ConnectionManager,
+    namespace: String,
+    max_events: usize,
+    retention: Duration,
+}
+
+/// Hand out the next id and buffer the event under it, trimming the oldest.
+/// KEYS: info, seq, events. ARGV: event, max events, ttl (s).
+const APPEND_SCRIPT: &str = r#"
+if redis.call('EXISTS', KEYS[1]) == 0 then
+    return redis.error_reply('unknown stream')
+end
+local id = redis.call('INCR', KEYS[2])
+redis.call('ZADD', KEYS[3], id, id .. ' ' .. ARGV[1])
+redis.call('ZREMRANGEBYRANK', KEYS[3], 0, -(tonumber(ARGV[2]) + 1))
+for _, key in ipairs(KEYS) do
+    redis.call('EXPIRE', key, ARGV[3])
+end
+return id
+"#;
+
+impl RedisEventBuffer {
+    pub async fn new(client: &redis::Client, max_events: usize, retention: Duration) -> Result<Self> {
+        Ok(Self {
+            connection: client.get_connection_manager().await?,
+            namespace: "intent-stream".to_string(),
+            max_events: max_events.max(1),
+            retention,
+        })
+    }
+
+    /// Keep keys under `namespace` instead of `intent-stream`
+    pub fn with_namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = namespace.into();
+        self
+    }
+
+    fn info_key(&self, intent_id: Uuid) -> String {
+        format!("{}:{}", self.namespace, intent_id)
+    }
+
+    fn seq_key(&self, intent_id: Uuid) -> String {
+        format!("{}:{}:seq", self.namespace, intent_id)
+    }
+
+    fn events_key(&self, intent_id: Uuid) -> String {
+        format!("{}:{}:events", self.namespace, intent_id)
+    }
+}
+
+#[async_trait]
+impl EventBuffer for RedisEventBuffer {
+    async fn open(&self, intent_id: Uuid, info: &StreamInfo) -> Result<()> {
+        let mut connection = self.connection.clone();
+        redis::cmd("SET")
+            .arg(self.info_key(intent_id))
+            .arg(serde_json::to_string(info)?)
+            .arg("EX")
+            .arg(self.retention.as_secs().max(1))
+            .query_async::<_, ()>(&mut connection)
+            .await?;
+        Ok(())
+    }
+
+    async fn info(&self, intent_id: Uuid) -> Result<Option<StreamInfo>> {
+        let mut connection = self.connection.clone();
+        let info: Option<String> = redis::cmd("GET").arg(self.info_key(intent_id)).query_async(&mut connection).await?;
+        info.map(|info| serde_json::from_str(&info)).transpose().map_err(Into::into)
+    }
+
+    async fn append(&self, intent_id: Uuid, event: &IntentEvent) -> Result<u64> {
+        let mut connection = self.connection.clone();
+        let id: u64 = redis::Script::new(APPEND_SCRIPT)
+            .key(self.info_key(intent_id))
+            .key(self.seq_key(intent_id))
+            .key(self.events_key(intent_id))
+            .arg(serde_json::to_string(event)?)
+            .arg(self.max_events)
+            .arg(self.retention.as_secs().max(1))
+            .invoke_async(&mut connection)
+            .await?;
+        Ok(id)
+    }
+
+    async fn since(&self, intent_id: Uuid, after: u64) -> Result<Option<Vec<StreamEvent>>> {
+        let mut connection = self.connection.clone();
+        let (exists, members): (bool, Vec<String>) = redis::pipe()
+            .cmd("EXISTS").arg(self.info_key(intent_id))
+            .cmd("ZRANGEBYSCORE").arg(self.events_key(intent_id)).arg(format!("({}", after)).arg("+inf")
+            .query_async(&mut connection)
+            .await?;
+        if !exists {
+            return Ok(None);
+        }
+        let events = members.iter()
+            .map(|member| {
+                let (id, event) = member.split_once(' ').ok_or_else(|| anyhow!("Malformed stream event '{}'", member))?;
+                Ok(StreamEvent { id: id.parse()?, event: serde_json::from_str(event)? })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Some(events))
+    }
+}
+
+/// Process-local streams, for tests and single-instance deployments. Streams are lost on
+/// restart and never expire.
+pub struct MemoryEventBuffer {
+    max_events: usize,
+    streams: Mutex<HashMap<Uuid, MemoryStream>>,
+}
+
+struct MemoryStream {
+    info: StreamInfo,
+    last_id: u64,
+    events: VecDeque<StreamEvent>,
+}
+
+impl MemoryEventBuffer {
+    pub fn new(max_events: usize) -> Self {
+        Self { max_events: max_events.max(1), streams: Mutex::new(HashMap::new()) }
+    }
+}
+
+#[async_trait]
+impl EventBuffer for MemoryEventBuffer {
+    async fn open(&self, intent_id: Uuid, info: &StreamInfo) -> Result<()> {
+        let stream = MemoryStream { info: info.clone(), last_id: 0, events: VecDeque::new() };
+        self.streams.lock().unwrap().insert(intent_id, stream);
+        Ok(())
+    }
+
+    async fn info(&self, intent_id: Uuid) -> Result<Option<StreamInfo>> {
+        Ok(self.streams.lock().unwrap().get(&intent_id).map(|stream| stream.info.clone()))
+    }
+
+    async fn append(&self, intent_id: Uuid, event: &IntentEvent) -> Result<u64> {
+        let mut streams = self.streams.lock().unwrap();
+        let stream = streams.get_mut(&intent_id).ok_or_else(|| anyhow!("unknown stream"))?;
+        stream.last_id += 1;
+        stream.events.push_back(StreamEvent { id: stream.last_id, event: event.clone() });
+        if stream.events.len() > self.max_events {
+            stream.events.pop_front();
+        }
+        Ok(stream.last_id)
+    }
+
+    async fn since(&self, intent_id: Uuid, after: u64) -> Result<Option<Vec<StreamEvent>>> {
+        let streams = self.streams.lock().unwrap();
+        Ok(streams.get(&intent_id).map(|stream| stream.events.iter().filter(|e| e.id > after).cloned().collect()))
+    }
+}
+
+/// Publishes intent events to the buffer and serves them as server-sent events, used as
+/// route state
+#[derive(Clone)]
+pub struct IntentStreams {
+    buffer: Arc<dyn EventBuffer>,
+    /// Announces appends on the plan topic and plan events on the task topic
+    events: Arc<dyn EventBus>,
+    runner: Option<Arc<dyn TaskRunner>>,
+    notifier: Notifier,
+    /// Environments plans run here may target; `None` allows any
+    allowed_environments: Option<Vec<Environment>>,
+}
+
+impl IntentStreams {
+    pub fn new(buffer: Arc<dyn EventBuffer>) -> Self {
+        Self {
+            buffer,
+            events: Arc::new(MemoryEventBus::new()),
+            runner: None,
+            notifier: Notifier::disabled(),
+            allowed_environments: None,
+        }
+    }
+
+    /// Announce appends and plan events on `events` rather than within this process only
+    pub fn with_event_bus(mut self, events: Arc<dyn EventBus>) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Run the tasks of plans needing no approval with `runner`, streaming their progress
+    pub fn with_execution(mut self, runner: Arc<dyn TaskRunner>) -> Self {
+        self.runner = Some(runner);
+        self
+    }
+
+    /// Email owners when the plans run here complete or fail
+    pub fn with_notifier(mut self, notifier: Notifier) -> Self {
+        self.notifier = notifier;
+        self
+    }
+
+    /// Refuse to run plans aimed at environments other than `environments`
+    pub fn with_allowed_environments(mut self, environments: Option<Vec<Environment>>) -> Self {
+        self.allowed_environments = environments;
+        self
+    }
+
+    pub async fn publish(&self, intent_id: Uuid, event: IntentEvent) -> Result<u64> {
+        let id = self.buffer.append(intent_id, &event).await?;
+        self.events.publish(Topic::Plan, serde_json::json!({ "intent_id": intent_id, "event_id": id }));
+        Ok(id)
+    }
+
+    /// Open the stream of a planned intent and publish its parse and plan, then execute the
+    /// plan in the background when it may be executed
+    pub async fn start(
+        &self,
+        owner: Option<Uuid>,
+        intent: &Intent,
+        plan: IntentExecutionPlan,
+        response: &ProcessIntentResponse,
+    ) -> Result<()> {
+        self.buffer.open(intent.id, &StreamInfo { owner, opened_at: Utc::now() }).await?;
+        self.publish(intent.id, IntentEvent::ParsingComplete {
+            intent_id: intent.id,
+            domain: intent.domain.clone(),
+            risk_level: format!("{:?}", intent.risk_level),
+        }).await?;
+        self.publish(intent.id, IntentEvent::PlanCreated { plan: serde_json::to_value(response)? }).await?;
+
+        match &self.runner {
+            Some(runner) if !response.requires_approval => {
+                tokio::spawn(self.clone().execute(owner, intent.clone(), plan, runner.clone()));
+            }
+            _ => {
+                self.publish(intent.id, IntentEvent::Done { plan_id: plan.id, execution: None }).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Execute `plan`, publishing task events as they happen and `done` or `error` at the end
+    async fn execute(self, owner: Option<Uuid>, intent: Intent, mut plan: IntentExecutionPlan, runner: Arc<dyn TaskRunner>) {
+        let intent_id = intent.id;
+        let (events, mut received) = broadcast::channel(EXECUTION_EVENT_CAPACITY);
+        let mut executor = PlanExecutor::new(SharedRunner(runner)).with_events(events);
+        if let Some(allowed) = &self.allowed_environments {
+            executor = executor.with_allowed_environments(allowed.clone());
+        }
+        let plan_id = plan.id;
+
+        let execute = async {
+            let outcome = executor.execute(&mut plan).await;
+            // Closing the channel lets the publisher finish once it has caught up
+            drop(executor);
+            outcome
+        };
+        let publish = async {
+            loop {
+                match received.recv().await {
+                    Ok(event) => {
+                        match serde_json::to_value(&event) {
+                            Ok(payload) => {
+                                self.events.publish(Topic::Task, payload);
+                            }
+                            Err(e) => warn!("Failed to serialize an event of plan {}: {}", plan_id, e),
+                        }
+                        let Some(event) = IntentEvent::from_plan_event(event) else { continue };
+                        if let Err(e) = self.publish(intent_id, event).await {
+                            warn!("Failed to publish an event of intent {}: {}", intent_id, e);
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        warn!("Stream of intent {} dropped {} execution events", intent_id, missed);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        };
+        let (outcome, ()) = tokio::join!(execute, publish);
+
+        let last = match outcome {
+            Ok(outcome) => {
+                self.notifier.plan_finished(owner, &intent, &plan, &outcome);
+                match &outcome.state {
+                    ExecutionState::Failed { error } => IntentEvent::Error { message: error.clone() },
+                    _ => IntentEvent::Done { plan_id, execution: serde_json::to_value(&outcome).ok() },
+                }
+            }
+            Err(e) => IntentEvent::Error { message: e.to_string() },
+        };
+        if let Err(e) = self.publish(intent_id, last).await {
+            warn!("Failed to finish the stream of intent {}: {}", intent_id, e);
+        }
+    }
+
+    /// The stream, if it exists and belongs to the session's user
+    async fn owned(&self, intent_id: Uuid, session: Option<&UserSession>) -> ApiResult<StreamInfo> {
+        self.buffer.info(intent_id).await?
+            .filter(|info| info.owner.is_none() || info.owner == session.map(|s| s.user_id))
+            .ok_or_else(|| ApiError::NotFound(format!("No event stream for intent {}", intent_id)))
+    }
+
+    /// The intent's events after `after` as a server-sent event response, ending after the
+    /// terminal event
+    pub fn respond(&self, intent_id: Uuid, after: u64) -> impl IntoResponse {
+        Sse::new(self.follow(intent_id, after)).keep_alive(KeepAlive::new().interval(HEARTBEAT_INTERVAL))
+    }
+
+    fn follow(&self, intent_id: Uuid, mut after: u64) -> ReceiverStream<Result<Event, Infallible>> {
+        let (sender, receiver) = mpsc::channel(FOLLOWER_CAPACITY);
+        let buffer = self.buffer.clone();
+        let mut appended = self.events.subscribe(Topic::Plan);
+        let followed = serde_json::json!(intent_id);
+
+        tokio::spawn(async move {
+            loop {
+                let events = match buffer.since(intent_id, after).await {
+                    Ok(Some(events)) => events,
+                    // The stream expired, so nothing more will come
+                    Ok(None) => return,
+                    Err(e) => {
+                        warn!("Failed to read the stream of intent {}: {}", intent_id, e);
+                        Vec::new()
+                    }
+                };
+                for event in events {
+                    after = event.id;
+                    if sender.send(Ok(event.to_sse())).await.is_err() || event.event.is_terminal() {
+                        return;
+                    }
+                }
+
+                // Woken by an append on any replica, or by polling in case it went unannounced
+                let woken = async {
+                    loop {
+                        match appended.recv().await {
+                            Some(event) if event.payload["intent_id"] == followed => break,
+                            Some(_) => {}
+                            None => std::future::pending::<()>().await,
+                        }
+                    }
+                };
+                tokio::select! {
+                    _ = woken => {}
+                    _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                    _ = sender.closed() => return,
+                }
+            }
+        });
+        ReceiverStream::new(receiver)
+    }
+}
+
+/// Event-stream routes, merged into `/api/v1`; `POST /intents?stream=true` is served by
+/// `process_intent`
+pub fn routes<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+    IntentStreams: FromRef<S>,
+{
+    Router::new().route("/intents/:intent_id/stream", get(stream_intent))
+}
+
+/// The id in the request's `Last-Event-ID` header, or 0 to start from the first event
+pub fn last_event_id(headers: &HeaderMap) -> u64 {
+    headers.get(LAST_EVENT_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// Follow an intent's processing, resuming after `Last-Event-ID` if given
+#[instrument(skip(streams, session, headers))]
+async fn stream_intent(
+    State(streams): State<IntentStreams>,
+    session: Option<Extension<UserSession>>,
+    Path(intent_id): Path<Uuid>,
+    headers: HeaderMap,
+) -> ApiResult<impl IntoResponse> {
+    streams.owned(intent_id, session.as_deref()).await?;
+    Ok(streams.respond(intent_id, last_event_id(&headers)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::{Request, StatusCode}};
+    use jarvis_core::{CognitiveKernel, ExecutionTask, TaskOutput, TaskStatus};
+    use tower::ServiceExt;
+
+    /// Streams three tokens per task, pausing between them so followers see them live
+    struct TokenRunner;
+
+    #[async_trait]
+    impl TaskRunner for TokenRunner {
+        async fn validate(&self, _task: &ExecutionTask) -> Result<()> {
+            Ok(())
+        }
+
+        async fn run(&self, task: &ExecutionTask) -> Result<TaskOutput> {
+            let outputs = task.expected_outputs.iter().map(|name| (name.clone(), serde_json::json!({}))).collect();
+            Ok(TaskOutput { status: TaskStatus::Completed, outputs, usage: Default::default() })
+        }
+
+        async fn run_streaming(&self, task: &ExecutionTask, deltas: mpsc::UnboundedSender<String>) -> Result<TaskOutput> {
+            for token in ["one ", "two ", "three"] {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                deltas.send(token.to_string())?;
+            }
+            self.run(task).await
+        }
+    }
+
+    fn session() -> UserSession {
+        UserSession {
+            user_id: Uuid::new_v4(),
+            session_id: Uuid::new_v4(),
+            created_at: Utc::now(),
+            last_activity: Utc::now(),
+            permissions: Vec::new(),
+            tenant_id: talkpp_tenancy::default_tenant_id(),
+        }
+    }
+
+    /// Events read off an SSE response, as (id, name, data), up to `limit` of them
+    async fn read_events(mut response: reqwest::Response, limit: usize) -> Vec<(u64, String, serde_json::Value)> {
+        let mut text = String::new();
+        let mut events = Vec::new();
+        while events.len() < limit {
+            let Some(chunk) = response.chunk().await.unwrap() else { break };
+            text.push_str(std::str::from_utf8(&chunk).unwrap());
+            while let Some(end) = text.find("\n\n") {
+                let block: String = text.drain(..end + 2).collect();
+                let field = |name: &str| block.lines().find_map(|line| line.strip_prefix(name)).map(str::to_string);
+                // Heartbeat comments carry no id
+                if let (Some(id), Some(name), Some(data)) = (field("id:"), field("event:"), field("data:")) {
+                    events.push((id.trim().parse().unwrap(), name.trim().to_string(), serde_json::from_str(data.trim()).unwrap()));
+                }
+            }
+        }
+        events.truncate(limit);
+        events
+    }
+
+    #[tokio::test]
+    async fn test_stream_is_ordered_and_resumes_after_a_dropped_connection() {
+        let streams = IntentStreams::new(Arc::new(MemoryEventBuffer::new(256))).with_execution(Arc::new(TokenRunner));
+        let (intent, plan) = CognitiveKernel::new().plan_intent("read the configuration", None).await.unwrap();
+        let response = ProcessIntentResponse::new(&intent, &plan);
+        assert!(!response.requires_approval);
+        let tasks = plan.tasks.len();
+
+        let owner = session();
+        let app = Router::new().merge(routes()).with_state(streams.clone()).layer(Extension(owner.clone()));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/intents/{}/stream", listener.local_addr().unwrap(), intent.id);
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        streams.start(Some(owner.user_id), &intent, plan, &response).await.unwrap();
+        let client = reqwest::Client::new();
+
+        // Read a few events, then drop the connection mid-execution
+        let first = client.get(&url).send().await.unwrap();
+        assert_eq!(first.headers()["content-type"], "text/event-stream");
+        let mut events = read_events(first, 4).await;
+        let last_id = events.last().unwrap().0;
+
+        let resumed = client.get(&url).header(LAST_EVENT_ID_HEADER, last_id.to_string()).send().await.unwrap();
+        events.extend(read_events(resumed, usize::MAX).await);
+
+        let ids: Vec<u64> = events.iter().map(|(id, _, _)| *id).collect();
+        assert_eq!(ids, (1..=ids.len() as u64).collect::<Vec<_>>());
+
+        let names: Vec<&str> = events.iter().map(|(_, name, _)| name.as_str()).collect();
+        let mut expected = vec!["parsing_complete", "plan_created"];
+        for _ in 0..tasks {
+            expected.extend(["task_status", "token", "token", "token", "task_status"]);
+        }
+        expected.push("done");
+        assert_eq!(names, expected);
+
+        assert_eq!(events[0].2["intent_id"], intent.id.to_string());
+        assert_eq!(events[1].2["plan"]["intent_id"], intent.id.to_string());
+        assert_eq!(events[2].2["status"], "InProgress");
+        assert_eq!(events[3].2["text"], "one ");
+        assert_eq!(events[6].2["status"], "Completed");
+        assert_eq!(events.last().unwrap().2["execution"]["state"], "Completed");
+    }
+
+    /// Appends more events than the buffer keeps and reads them back from the middle
+    async fn assert_buffer_keeps_the_latest_events(buffer: &dyn EventBuffer) {
+        let intent_id = Uuid::new_v4();
+        assert!(buffer.since(intent_id, 0).await.unwrap().is_none());
+        assert!(buffer.append(intent_id, &IntentEvent::Error { message: "early".to_string() }).await.is_err());
+
+        let info = StreamInfo { owner: Some(Uuid::new_v4()), opened_at: Utc::now() };
+        buffer.open(intent_id, &info).await.unwrap();
+        assert_eq!(buffer.info(intent_id).await.unwrap(), Some(info));
+        for i in 0..5 {
+            let event = IntentEvent::Token { task_id: intent_id, text: i.to_string() };
+            assert_eq!(buffer.append(intent_id, &event).await.unwrap(), i + 1);
+        }
+
+        // Three are kept: events 3 to 5
+        let ids: Vec<u64> = buffer.since(intent_id, 0).await.unwrap().unwrap().iter().map(|e| e.id).collect();
+        assert_eq!(ids, [3, 4, 5]);
+        let after_four = buffer.since(intent_id, 4).await.unwrap().unwrap();
+        assert_eq!(after_four, [StreamEvent { id: 5, event: IntentEvent::Token { task_id: intent_id, text: "4".to_string() } }]);
+    }
+
+    #[tokio::test]
+    async fn test_buffer_keeps_the_latest_events() {
+        assert_buffer_keeps_the_latest_events(&MemoryEventBuffer::new(3)).await;
+    }
+
+    /// Run with `TEST_REDIS_URL=redis://localhost:6379 cargo test -- --ignored`
+    #[tokio::test]
+    #[ignore = "needs a Redis server at TEST_REDIS_URL"]
+    async fn test_redis_buffer_keeps_the_latest_events() {
+        let url = std::env::var("TEST_REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379".to_string());
+        let client = redis::Client::open(url.as_str()).unwrap();
+        let buffer = RedisEventBuffer::new(&client, 3, Duration::from_secs(60)).await.unwrap()
+            .with_namespace(format!("test-intent-stream-{}", Uuid::new_v4()));
+        assert_buffer_keeps_the_latest_events(&buffer).await;
+    }
+
+    #[tokio::test]
+    async fn test_other_users_cannot_follow_a_stream() {
+        let streams = IntentStreams::new(Arc::new(MemoryEventBuffer::new(16)));
+        let (intent, plan) = CognitiveKernel::new().plan_intent("read the configuration", None).await.unwrap();
+        let response = ProcessIntentResponse::new(&intent, &plan);
+        let owner = session();
+        streams.start(Some(owner.user_id), &intent, plan, &response).await.unwrap();
+
+        let status = |user: UserSession, intent_id: Uuid| {
+            let app = Router::new().merge(routes()).with_state(streams.clone()).layer(Extension(user));
+            async move {
+                let request = Request::get(format!("/intents/{}/stream", intent_id)).body(Body::empty()).unwrap();
+                app.oneshot(request).await.unwrap().status()
+            }
+        };
+        assert_eq!(status(owner, intent.id).await, StatusCode::OK);
+        assert_eq!(status(session(), intent.id).await, StatusCode::NOT_FOUND);
+        assert_eq!(status(session(), Uuid::new_v4()).await, StatusCode::NOT_FOUND);
+
+        // Without a runner the plan is not executed and the stream ends after it
+        let events = streams.buffer.since(intent.id, 0).await.unwrap().unwrap();
+        let names: Vec<&str> = events.iter().map(|e| e.event.name()).collect();
+        assert_eq!(names, ["parsing_complete", "plan_created", "done"]);
+    }
+}
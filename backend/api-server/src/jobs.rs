@@ -0,0 +1,921 @@
+//! Long-running generation jobs
+//!
+//! `POST /jobs/generation` records a job and answers with its id straight away; the
+//! replica that accepted it generates on a background task, at most `max_running` jobs
+//! at a time, appending the output to the job store as it is produced. Clients poll
+//! `GET /jobs/:id` for the status and read the output incrementally from
+//! `GET /jobs/:id/output?offset=N`, passing back the `next_offset` of the previous read.
+//!
+//! `POST /jobs/:id/cancel` records a cancellation request in the store and fires the
+//! job's `CancellationToken` when this replica runs it. The generator stops the model
+//! when the token fires, so generation, not only the job record, stops. A job running on
+//! another replica sees the request the next time it flushes its output, so either way
+//! generation stops within the flush interval. The output generated so far is kept.
+//!
+//! Jobs can also run a function deployed to the Talk++ runtime instead of a model (see
+//! [`FunctionGenerator`]); cancelling one stops its invocation through
+//! [`Runtime::cancel_execution`], killing the function's process.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use axum::{
+    extract::{Extension, FromRef, Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Json},
+    routing::{get, post},
+    Router,
+};
+use chrono::{DateTime, Utc};
+use redis::aio::ConnectionManager;
+use serde::{Deserialize, Serialize};
+use talkpp_errors::versioning::load_versioned;
+use talkpp_model_traits::{generate_cancellable, LanguageModel};
+use talkpp_runtime::{event::Event, LogSink, LogStream, Runtime};
+use tokio::sync::{mpsc, Semaphore};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, instrument, warn};
+use uuid::Uuid;
+
+use crate::error::{ApiError, ApiResult};
+use crate::migrations::JOB;
+use crate::UserSession;
+
+/// Prefix of a job model naming a function deployed to the runtime, as in
+/// `function:<function id>`
+pub const FUNCTION_MODEL_PREFIX: &str = "function:";
+
+/// Output bytes returned per read when the request doesn't say
+const DEFAULT_READ_BYTES: usize = 64 * 1024;
+const MAX_READ_BYTES: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    /// Waiting for one of the replica's generation slots
+    Queued,
+    Running,
+    Cancelled,
+    Failed,
+    Completed,
+}
+
+impl JobStatus {
+    pub fn is_finished(self) -> bool {
+        matches!(self, JobStatus::Cancelled | JobStatus::Failed | JobStatus::Completed)
+    }
+}
+
+/// What to generate
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenerationRequest {
+    pub prompt: String,
+    /// Most tokens to generate, instead of the configured default
+    pub max_tokens: Option<usize>,
+}
+
+/// A submitted job, without its output
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobInfo {
+    /// Version of the stored form; see [`crate::migrations::JOB`]
+    pub schema_version: u32,
+    pub id: Uuid,
+    /// User who submitted the job; only they can see or cancel it
+    pub owner: Option<Uuid>,
+    pub status: JobStatus,
+    pub request: GenerationRequest,
+    pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub error: Option<String>,
+}
+
+impl JobInfo {
+    fn queued(owner: Option<Uuid>, request: GenerationRequest) -> Self {
+        Self {
+            schema_version: JOB.current(),
+            id: Uuid::new_v4(),
+            owner,
+            status: JobStatus::Queued,
+            request,
+            created_at: Utc::now(),
+            started_at: None,
+            finished_at: None,
+            error: None,
+        }
+    }
+
+    fn finished(mut self, status: JobStatus, error: Option<String>) -> Self {
+        self.status = status;
+        self.error = error;
+        self.finished_at = Some(Utc::now());
+        self
+    }
+}
+
+#[async_trait]
+pub trait JobStore: Send + Sync {
+    /// Store a new job, or replace the record of one
+    async fn put(&self, job: &JobInfo) -> Result<()>;
+
+    async fn job(&self, job_id: Uuid) -> Result<Option<JobInfo>>;
+
+    /// Append to the job's output
+    async fn append_output(&self, job_id: Uuid, text: &str) -> Result<()>;
+
+    /// Up to `limit` bytes of the output starting at byte `offset`, and the output's
+    /// length so far
+    async fn output(&self, job_id: Uuid, offset: usize, limit: usize) -> Result<(Vec<u8>, usize)>;
+
+    /// Ask whichever replica runs the job to stop it
+    async fn request_cancel(&self, job_id: Uuid) -> Result<()>;
+
+    async fn cancel_requested(&self, job_id: Uuid) -> Result<bool>;
+}
+
+/// Jobs in Redis, visible to every api-server replica. Each job is a JSON record, its
+/// output a string appended to, and a cancellation request a flag; all three expire
+/// `retention` after the job last wrote to them.
+pub struct RedisJobStore {
+    connection: ConnectionManager,
+    namespace: String,
+    retention: Duration,
+}
+
+impl RedisJobStore {
+    pub async fn new(client: &redis::Client, retention: Duration) -> Result<Self> {
+        Ok(Self {
+            connection: client.get_connection_manager().await?,
+            namespace: "jobs".to_string(),
+            retention,
+        })
+    }
+
+    /// Keep keys under `namespace` instead of `jobs`
+    pub fn with_namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = namespace.into();
+        self
+    }
+
+    fn job_key(&self, job_id: Uuid) -> String {
+        format!("{}:{}", self.namespace, job_id)
+    }
+
+    fn output_key(&self, job_id: Uuid) -> String {
+        format!("{}:{}:output", self.namespace, job_id)
+    }
+
+    fn cancel_key(&self, job_id: Uuid) -> String {
+        format!("{}:{}:cancel", self.namespace, job_id)
+    }
+}
+
+#[async_trait]
+impl JobStore for RedisJobStore {
+    async fn put(&self, job: &JobInfo) -> Result<()> {
+        let mut connection = self.connection.clone();
+        redis::cmd("SET")
+            .arg(self.job_key(job.id))
+            .arg(serde_json::to_string(job)?)
+            .arg("EX")
+            .arg(self.retention.as_secs().max(1))
+            .query_async::<_, ()>(&mut connection)
+            .await?;
+        Ok(())
+    }
+
+    async fn job(&self, job_id: Uuid) -> Result<Option<JobInfo>> {
+        let mut connection = self.connection.clone();
+        let job: Option<String> = redis::cmd("GET").arg(self.job_key(job_id)).query_async(&mut connection).await?;
+        let Some(job) = job else {
+            return Ok(None);
+        };
+        Ok(Some(load_versioned(serde_json::from_str(&job)?, &JOB)?))
+    }
+
+    async fn append_output(&self, job_id: Uuid, text: &str) -> Result<()> {
+        let mut connection = self.connection.clone();
+        redis::pipe()
+            .atomic()
+            .cmd("APPEND").arg(self.output_key(job_id)).arg(text).ignore()
+            .cmd("EXPIRE").arg(self.output_key(job_id)).arg(self.retention.as_secs().max(1)).ignore()
+            .query_async::<_, ()>(&mut connection)
+            .await?;
+        Ok(())
+    }
+
+    async fn output(&self, job_id: Uuid, offset: usize, limit: usize) -> Result<(Vec<u8>, usize)> {
+        let mut connection = self.connection.clone();
+        let key = self.output_key(job_id);
+        let (chunk, total): (Vec<u8>, usize) = if limit == 0 {
+            (Vec::new(), redis::cmd("STRLEN").arg(&key).query_async(&mut connection).await?)
+        } else {
+            redis::pipe()
+                .cmd("GETRANGE").arg(&key).arg(offset).arg(offset + limit - 1)
+                .cmd("STRLEN").arg(&key)
+                .query_async(&mut connection)
+                .await?
+        };
+        Ok((chunk, total))
+    }
+
+    async fn request_cancel(&self, job_id: Uuid) -> Result<()> {
+        let mut connection = self.connection.clone();
+        redis::cmd("SET")
+            .arg(self.cancel_key(job_id))
+            .arg(1)
+            .arg("EX")
+            .arg(self.retention.as_secs().max(1))
+            .query_async::<_, ()>(&mut connection)
+            .await?;
+        Ok(())
+    }
+
+    async fn cancel_requested(&self, job_id: Uuid) -> Result<bool> {
+        let mut connection = self.connection.clone();
+        Ok(redis::cmd("EXISTS").arg(self.cancel_key(job_id)).query_async(&mut connection).await?)
+    }
+}
+
+#[derive(Default)]
+struct MemoryJob {
+    info: Option<JobInfo>,
+    output: String,
+    cancel_requested: bool,
+}
+
+/// Process-local jobs, for tests and single-instance deployments. Jobs are lost on
+/// restart; finished jobs are purged `retention` after they finished, whenever a job is
+/// stored.
+pub struct MemoryJobStore {
+    jobs: Mutex<HashMap<Uuid, MemoryJob>>,
+    retention: Duration,
+}
+
+impl MemoryJobStore {
+    pub fn new(retention: Duration) -> Self {
+        Self { jobs: Mutex::new(HashMap::new()), retention }
+    }
+
+    fn purge(&self, jobs: &mut HashMap<Uuid, MemoryJob>) {
+        let Ok(retention) = chrono::Duration::from_std(self.retention) else {
+            return;
+        };
+        let cutoff = Utc::now() - retention;
+        jobs.retain(|_, job| !matches!(&job.info, Some(JobInfo { finished_at: Some(at), .. }) if *at < cutoff));
+    }
+}
+
+#[async_trait]
+impl JobStore for MemoryJobStore {
+    async fn put(&self, job: &JobInfo) -> Result<()> {
+        let mut jobs = self.jobs.lock().unwrap();
+        self.purge(&mut jobs);
+        jobs.entry(job.id).or_default().info = Some(job.clone());
+        Ok(())
+    }
+
+    async fn job(&self, job_id: Uuid) -> Result<Option<JobInfo>> {
+        Ok(self.jobs.lock().unwrap().get(&job_id).and_then(|job| job.info.clone()))
+    }
+
+    async fn append_output(&self, job_id: Uuid, text: &str) -> Result<()> {
+        self.jobs.lock().unwrap().entry(job_id).or_default().output.push_str(text);
+        Ok(())
+    }
+
+    async fn output(&self, job_id: Uuid, offset: usize, limit: usize) -> Result<(Vec<u8>, usize)> {
+        let jobs = self.jobs.lock().unwrap();
+        let output = jobs.get(&job_id).map(|job| job.output.as_bytes()).unwrap_or_default();
+        let start = offset.min(output.len());
+        let end = start.saturating_add(limit).min(output.len());
+        Ok((output[start..end].to_vec(), output.len()))
+    }
+
+    async fn request_cancel(&self, job_id: Uuid) -> Result<()> {
+        self.jobs.lock().unwrap().entry(job_id).or_default().cancel_requested = true;
+        Ok(())
+    }
+
+    async fn cancel_requested(&self, job_id: Uuid) -> Result<bool> {
+        Ok(self.jobs.lock().unwrap().get(&job_id).is_some_and(|job| job.cancel_requested))
+    }
+}
+
+/// Runs the generation of a job
+#[async_trait]
+pub trait Generator: Send + Sync {
+    /// Generate for `request`, sending each piece to `pieces` as it is produced, until
+    /// done or `cancel` fires. Generation must stop promptly once it does.
+    async fn generate(
+        &self,
+        request: &GenerationRequest,
+        pieces: mpsc::UnboundedSender<String>,
+        cancel: CancellationToken,
+    ) -> Result<()>;
+}
+
+/// Generates with a language model, such as one served by Ollama
+pub struct ModelGenerator {
+    model: Arc<dyn LanguageModel + Send + Sync>,
+    max_tokens: usize,
+}
+
+impl ModelGenerator {
+    pub fn new(model: Arc<dyn LanguageModel + Send + Sync>, max_tokens: usize) -> Self {
+        Self { model, max_tokens }
+    }
+}
+
+#[async_trait]
+impl Generator for ModelGenerator {
+    async fn generate(
+        &self,
+        request: &GenerationRequest,
+        pieces: mpsc::UnboundedSender<String>,
+        cancel: CancellationToken,
+    ) -> Result<()> {
+        let max_tokens = request.max_tokens.unwrap_or(self.max_tokens);
+        generate_cancellable(self.model.as_ref(), &request.prompt, max_tokens, Some(&pieces), &cancel).await?;
+        Ok(())
+    }
+}
+
+/// Runs a function deployed to the Talk++ runtime, with the request as its event data
+/// (`{"prompt": ..., "max_tokens": ...}`); what the function prints is the output
+pub struct FunctionGenerator {
+    runtime: Arc<Runtime>,
+    function_id: Uuid,
+}
+
+impl FunctionGenerator {
+    pub fn new(runtime: Arc<Runtime>, function_id: Uuid) -> Self {
+        Self { runtime, function_id }
+    }
+}
+
+#[async_trait]
+impl Generator for FunctionGenerator {
+    async fn generate(
+        &self,
+        request: &GenerationRequest,
+        pieces: mpsc::UnboundedSender<String>,
+        cancel: CancellationToken,
+    ) -> Result<()> {
+        let event = Event::manual(serde_json::json!({ "prompt": request.prompt, "max_tokens": request.max_tokens }));
+        let invocation_id = event.id;
+        // WASM functions don't stream; their output arrives with the response
+        let streamed = Arc::new(AtomicBool::new(false));
+        let sink = {
+            let (pieces, streamed) = (pieces.clone(), streamed.clone());
+            LogSink::new(move |stream, line| {
+                if stream == LogStream::Stdout {
+                    streamed.store(true, Ordering::SeqCst);
+                    let _ = pieces.send(format!("{}\n", line));
+                }
+            })
+        };
+
+        let invocation = self.runtime.execute_with_logs(self.function_id, event, sink);
+        tokio::pin!(invocation);
+        // Polling the invocation first registers it with the runtime before it can be
+        // cancelled
+        let response = tokio::select! {
+            biased;
+            response = &mut invocation => response?,
+            _ = cancel.cancelled() => {
+                self.runtime.cancel_execution(invocation_id);
+                invocation.await?
+            }
+        };
+
+        if !streamed.load(Ordering::SeqCst) && !response.output.is_empty() {
+            let _ = pieces.send(response.output.clone());
+        }
+        if !response.success && !response.cancelled {
+            anyhow::bail!(response.error.unwrap_or(response.message));
+        }
+        Ok(())
+    }
+}
+
+/// Submits jobs, runs them on background tasks and cancels them; used as route state
+#[derive(Clone)]
+pub struct JobManager {
+    store: Arc<dyn JobStore>,
+    generator: Arc<dyn Generator>,
+    /// Tokens of the jobs this replica has accepted and not finished
+    running: Arc<Mutex<HashMap<Uuid, CancellationToken>>>,
+    slots: Arc<Semaphore>,
+    flush_interval: Duration,
+}
+
+impl JobManager {
+    pub fn new(store: Arc<dyn JobStore>, generator: Arc<dyn Generator>, max_running: usize) -> Self {
+        Self {
+            store,
+            generator,
+            running: Arc::new(Mutex::new(HashMap::new())),
+            slots: Arc::new(Semaphore::new(max_running.max(1))),
+            flush_interval: Duration::from_millis(250),
+        }
+    }
+
+    /// How often output is written to the store and cancellation requests from other
+    /// replicas are looked for, instead of every 250ms
+    pub fn with_flush_interval(mut self, flush_interval: Duration) -> Self {
+        self.flush_interval = flush_interval;
+        self
+    }
+
+    pub fn store(&self) -> &Arc<dyn JobStore> {
+        &self.store
+    }
+
+    /// Record a job and start generating for it in the background
+    pub async fn submit(&self, owner: Option<Uuid>, request: GenerationRequest) -> Result<JobInfo> {
+        let job = JobInfo::queued(owner, request);
+        self.store.put(&job).await?;
+        let cancel = CancellationToken::new();
+        self.running.lock().unwrap().insert(job.id, cancel.clone());
+
+        let manager = self.clone();
+        let queued = job.clone();
+        tokio::spawn(async move {
+            let job_id = queued.id;
+            if let Err(e) = manager.run(queued, cancel).await {
+                warn!("Job {} could not be recorded: {}", job_id, e);
+            }
+            manager.running.lock().unwrap().remove(&job_id);
+        });
+        Ok(job)
+    }
+
+    /// Stop the job, wherever it runs. Returns false when it had already finished.
+    pub async fn cancel(&self, job: &JobInfo) -> Result<bool> {
+        if job.status.is_finished() {
+            return Ok(false);
+        }
+        self.store.request_cancel(job.id).await?;
+        if let Some(cancel) = self.running.lock().unwrap().get(&job.id) {
+            cancel.cancel();
+        }
+        Ok(true)
+    }
+
+    async fn run(&self, mut job: JobInfo, cancel: CancellationToken) -> Result<()> {
+        let _slot = tokio::select! {
+            slot = self.slots.clone().acquire_owned() => slot?,
+            _ = cancel.cancelled() => {
+                return self.store.put(&job.finished(JobStatus::Cancelled, None)).await;
+            }
+        };
+        if self.store.cancel_requested(job.id).await? {
+            return self.store.put(&job.finished(JobStatus::Cancelled, None)).await;
+        }
+        job.status = JobStatus::Running;
+        job.started_at = Some(Utc::now());
+        self.store.put(&job).await?;
+        info!("Job {} started generating", job.id);
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        // The generation borrows its own copy, so the job can be finished while it's pinned
+        let request = job.request.clone();
+        let generation = self.generator.generate(&request, tx, cancel.clone());
+        tokio::pin!(generation);
+        // A generator that ignores its token is dropped, stopping it, a flush later
+        let abandon = {
+            let cancel = cancel.clone();
+            let grace = self.flush_interval;
+            async move {
+                cancel.cancelled().await;
+                tokio::time::sleep(grace).await;
+            }
+        };
+        tokio::pin!(abandon);
+
+        let mut pending = String::new();
+        let mut ticks = tokio::time::interval(self.flush_interval);
+        let outcome = loop {
+            tokio::select! {
+                outcome = &mut generation => break outcome,
+                _ = &mut abandon => break Ok(()),
+                Some(piece) = rx.recv() => pending.push_str(&piece),
+                _ = ticks.tick() => {
+                    self.flush(job.id, &mut pending).await;
+                    if !cancel.is_cancelled() && self.store.cancel_requested(job.id).await.unwrap_or(false) {
+                        cancel.cancel();
+                    }
+                }
+            }
+        };
+        while let Ok(piece) = rx.try_recv() {
+            pending.push_str(&piece);
+        }
+        self.flush(job.id, &mut pending).await;
+
+        let job = match outcome {
+            _ if cancel.is_cancelled() => job.finished(JobStatus::Cancelled, None),
+            Ok(()) => job.finished(JobStatus::Completed, None),
+            Err(e) => job.finished(JobStatus::Failed, Some(e.to_string())),
+        };
+        info!("Job {} finished {:?}", job.id, job.status);
+        self.store.put(&job).await
+    }
+
+    /// Append the output generated since the last flush, keeping it for the next flush
+    /// should the store be unreachable
+    async fn flush(&self, job_id: Uuid, pending: &mut String) {
+        if pending.is_empty() {
+            return;
+        }
+        match self.store.append_output(job_id, pending).await {
+            Ok(()) => pending.clear(),
+            Err(e) => warn!("Could not store output of job {}: {}", job_id, e),
+        }
+    }
+
+    /// The job, if it exists and belongs to the session's user
+    async fn owned(&self, job_id: Uuid, session: Option<&UserSession>) -> ApiResult<JobInfo> {
+        self.store.job(job_id).await?
+            .filter(|job| job.owner.is_none() || job.owner == session.map(|s| s.user_id))
+            .ok_or_else(|| ApiError::NotFound(format!("Job {} not found", job_id)))
+    }
+}
+
+/// Job routes, merged into `/api/v1`
+pub fn routes<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+    JobManager: FromRef<S>,
+{
+    Router::new()
+        .route("/jobs/generation", post(submit_generation))
+        .route("/jobs/:job_id", get(get_job))
+        .route("/jobs/:job_id/output", get(get_output))
+        .route("/jobs/:job_id/cancel", post(cancel_job))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OutputQuery {
+    /// Byte offset to read from, the `next_offset` of the previous read
+    pub offset: Option<usize>,
+    /// Most bytes to read
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OutputResponse {
+    pub job_id: Uuid,
+    pub status: JobStatus,
+    pub offset: usize,
+    /// Where the next read should start
+    pub next_offset: usize,
+    /// Bytes of output so far
+    pub total: usize,
+    pub output: String,
+    /// The job finished and every byte of its output has been read
+    pub done: bool,
+}
+
+/// Start generating in the background
+#[instrument(skip(jobs, session, request), fields(prompt_len = request.prompt.len()))]
+async fn submit_generation(
+    State(jobs): State<JobManager>,
+    session: Option<Extension<UserSession>>,
+    Json(request): Json<GenerationRequest>,
+) -> ApiResult<impl IntoResponse> {
+    if request.prompt.trim().is_empty() {
+        return Err(ApiError::BadRequest("A generation needs a prompt".to_string()));
+    }
+    if request.max_tokens == Some(0) {
+        return Err(ApiError::BadRequest("max_tokens must be at least 1".to_string()));
+    }
+    let job = jobs.submit(session.map(|Extension(session)| session.user_id), request).await?;
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+/// Status of a job
+#[instrument(skip(jobs, session))]
+async fn get_job(
+    State(jobs): State<JobManager>,
+    session: Option<Extension<UserSession>>,
+    Path(job_id): Path<Uuid>,
+) -> ApiResult<Json<JobInfo>> {
+    Ok(Json(jobs.owned(job_id, session.as_deref()).await?))
+}
+
+/// Output of a job from a byte offset; reads end on a character boundary, so the next
+/// read starts where this one left off
+#[instrument(skip(jobs, session))]
+async fn get_output(
+    State(jobs): State<JobManager>,
+    session: Option<Extension<UserSession>>,
+    Path(job_id): Path<Uuid>,
+    Query(query): Query<OutputQuery>,
+) -> ApiResult<Json<OutputResponse>> {
+    let job = jobs.owned(job_id, session.as_deref()).await?;
+    let offset = query.offset.unwrap_or(0);
+    let limit = query.limit.unwrap_or(DEFAULT_READ_BYTES).clamp(1, MAX_READ_BYTES);
+    let (chunk, total) = jobs.store.output(job_id, offset, limit).await?;
+    let output = complete_utf8(chunk);
+    let next_offset = offset.min(total) + output.len();
+    Ok(Json(OutputResponse {
+        job_id,
+        status: job.status,
+        offset,
+        next_offset,
+        total,
+        output,
+        done: job.status.is_finished() && next_offset >= total,
+    }))
+}
+
+/// Stop a job; its output so far is kept
+#[instrument(skip(jobs, session))]
+async fn cancel_job(
+    State(jobs): State<JobManager>,
+    session: Option<Extension<UserSession>>,
+    Path(job_id): Path<Uuid>,
+) -> ApiResult<impl IntoResponse> {
+    let job = jobs.owned(job_id, session.as_deref()).await?;
+    if !jobs.cancel(&job).await? {
+        return Err(ApiError::Conflict(format!("Job {} has already finished", job_id)));
+    }
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+/// `bytes` as text, leaving out a character cut off at the end
+fn complete_utf8(mut bytes: Vec<u8>) -> String {
+    if let Err(e) = std::str::from_utf8(&bytes) {
+        if e.error_len().is_none() {
+            bytes.truncate(e.valid_up_to());
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::{Method, Request}};
+    use std::sync::atomic::AtomicUsize;
+    use tower::ServiceExt;
+
+    const FLUSH: Duration = Duration::from_millis(20);
+
+    /// Sends a numbered token every 10ms, up to `max_tokens`, until cancelled; records
+    /// how many it sent and whether it saw the cancellation
+    #[derive(Default)]
+    struct SlowGenerator {
+        sent: AtomicUsize,
+        observed_cancel: AtomicBool,
+    }
+
+    #[async_trait]
+    impl Generator for SlowGenerator {
+        async fn generate(
+            &self,
+            request: &GenerationRequest,
+            pieces: mpsc::UnboundedSender<String>,
+            cancel: CancellationToken,
+        ) -> Result<()> {
+            if request.prompt.contains("fail") {
+                anyhow::bail!("model unavailable");
+            }
+            for i in 0..request.max_tokens.unwrap_or(1000) {
+                tokio::select! {
+                    _ = cancel.cancelled() => {
+                        self.observed_cancel.store(true, Ordering::SeqCst);
+                        return Ok(());
+                    }
+                    _ = tokio::time::sleep(Duration::from_millis(10)) => {}
+                }
+                let _ = pieces.send(format!("token{} ", i));
+                self.sent.fetch_add(1, Ordering::SeqCst);
+            }
+            Ok(())
+        }
+    }
+
+    fn session() -> UserSession {
+        UserSession {
+            user_id: Uuid::new_v4(),
+            session_id: Uuid::new_v4(),
+            created_at: Utc::now(),
+            last_activity: Utc::now(),
+            permissions: Vec::new(),
+            tenant_id: talkpp_tenancy::default_tenant_id(),
+        }
+    }
+
+    async fn call(app: &Router, method: Method, uri: &str, body: Option<serde_json::Value>, session: &UserSession) -> (StatusCode, serde_json::Value) {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(body.map(|b| Body::from(b.to_string())).unwrap_or_default())
+            .unwrap();
+        request.extensions_mut().insert(session.clone());
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    async fn submit(app: &Router, request: serde_json::Value, session: &UserSession) -> Uuid {
+        let (status, body) = call(app, Method::POST, "/jobs/generation", Some(request), session).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(body["status"], "queued");
+        body["id"].as_str().unwrap().parse().unwrap()
+    }
+
+    /// Poll the job until `holds` is true of it, returning it
+    async fn wait_for(app: &Router, job_id: Uuid, session: &UserSession, holds: impl Fn(&serde_json::Value) -> bool) -> serde_json::Value {
+        for _ in 0..200 {
+            let (_, job) = call(app, Method::GET, &format!("/jobs/{}", job_id), None, session).await;
+            if holds(&job) {
+                return job;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("job {} never got there", job_id);
+    }
+
+    async fn read_all(app: &Router, job_id: Uuid, session: &UserSession, limit: usize) -> (String, usize) {
+        let (mut output, mut offset, mut reads) = (String::new(), 0, 0);
+        loop {
+            let uri = format!("/jobs/{}/output?offset={}&limit={}", job_id, offset, limit);
+            let (status, body) = call(app, Method::GET, &uri, None, session).await;
+            assert_eq!(status, StatusCode::OK);
+            let chunk = body["output"].as_str().unwrap();
+            output.push_str(chunk);
+            offset = body["next_offset"].as_u64().unwrap() as usize;
+            reads += 1;
+            if body["done"] == true {
+                return (output, reads);
+            }
+            if chunk.is_empty() {
+                // Caught up with a running job; let it produce more
+                tokio::time::sleep(FLUSH).await;
+            }
+        }
+    }
+
+    fn app(manager: JobManager) -> Router {
+        Router::new().merge(routes()).with_state(manager)
+    }
+
+    #[tokio::test]
+    async fn test_cancelling_stops_generation_and_keeps_partial_output() {
+        let generator = Arc::new(SlowGenerator::default());
+        let manager = JobManager::new(Arc::new(MemoryJobStore::new(Duration::from_secs(60))), generator.clone(), 2)
+            .with_flush_interval(FLUSH);
+        let app = app(manager);
+        let (owner, stranger) = (session(), session());
+
+        let job_id = submit(&app, serde_json::json!({ "prompt": "count slowly" }), &owner).await;
+        wait_for(&app, job_id, &owner, |job| job["status"] == "running").await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let cancel = format!("/jobs/{}/cancel", job_id);
+        assert_eq!(call(&app, Method::POST, &cancel, None, &stranger).await.0, StatusCode::NOT_FOUND);
+        assert_eq!(call(&app, Method::POST, &cancel, None, &owner).await.0, StatusCode::ACCEPTED);
+        let job = wait_for(&app, job_id, &owner, |job| job["status"] == "cancelled").await;
+        assert!(job["finished_at"].is_string());
+        assert!(generator.observed_cancel.load(Ordering::SeqCst));
+
+        // Generation stopped: nothing is produced after the cancellation
+        let sent = generator.sent.load(Ordering::SeqCst);
+        assert!(sent > 0 && sent < 100, "{} tokens sent", sent);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(generator.sent.load(Ordering::SeqCst), sent);
+
+        let (output, _) = read_all(&app, job_id, &owner, 1024).await;
+        let expected: String = (0..sent).map(|i| format!("token{} ", i)).collect();
+        assert_eq!(output, expected);
+
+        assert_eq!(call(&app, Method::POST, &cancel, None, &owner).await.0, StatusCode::CONFLICT);
+        assert_eq!(call(&app, Method::GET, &format!("/jobs/{}", job_id), None, &stranger).await.0, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_cancellation_reaches_jobs_running_on_another_replica() {
+        let store: Arc<dyn JobStore> = Arc::new(MemoryJobStore::new(Duration::from_secs(60)));
+        let generator = Arc::new(SlowGenerator::default());
+        let running = app(JobManager::new(store.clone(), generator.clone(), 1).with_flush_interval(FLUSH));
+        let other = app(JobManager::new(store, Arc::new(SlowGenerator::default()), 1).with_flush_interval(FLUSH));
+        let owner = session();
+
+        let job_id = submit(&running, serde_json::json!({ "prompt": "count slowly" }), &owner).await;
+        wait_for(&running, job_id, &owner, |job| job["status"] == "running").await;
+        let (status, _) = call(&other, Method::POST, &format!("/jobs/{}/cancel", job_id), None, &owner).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+
+        wait_for(&other, job_id, &owner, |job| job["status"] == "cancelled").await;
+        assert!(generator.observed_cancel.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_cancelling_a_function_job_cancels_its_runtime_invocation() {
+        let runtime = Arc::new(Runtime::new().unwrap());
+        let function = talkpp_runtime::FunctionMetadata::new("counter", "bash");
+        let function_id = runtime
+            .deploy("for i in $(seq 1 100); do echo \"line $i\"; sleep 0.05; done", function)
+            .await
+            .unwrap();
+        let generator = Arc::new(FunctionGenerator::new(runtime.clone(), function_id));
+        let manager = JobManager::new(Arc::new(MemoryJobStore::new(Duration::from_secs(60))), generator, 1)
+            .with_flush_interval(FLUSH);
+        let app = app(manager);
+        let owner = session();
+
+        let job_id = submit(&app, serde_json::json!({ "prompt": "count" }), &owner).await;
+        wait_for(&app, job_id, &owner, |job| job["status"] == "running").await;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(runtime.in_flight().len(), 1);
+
+        let (status, _) = call(&app, Method::POST, &format!("/jobs/{}/cancel", job_id), None, &owner).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        wait_for(&app, job_id, &owner, |job| job["status"] == "cancelled").await;
+        assert!(runtime.in_flight().is_empty());
+
+        // The function's process was killed, so its output stopped growing
+        let (output, _) = read_all(&app, job_id, &owner, 1024).await;
+        assert!(output.starts_with("line 1\n"), "{:?}", output);
+        assert!(!output.contains("line 100\n"), "{:?}", output);
+    }
+
+    #[tokio::test]
+    async fn test_output_is_read_incrementally_and_failures_are_reported() {
+        let manager = JobManager::new(Arc::new(MemoryJobStore::new(Duration::from_secs(60))), Arc::new(SlowGenerator::default()), 1)
+            .with_flush_interval(FLUSH);
+        let app = app(manager);
+        let owner = session();
+
+        let (status, _) = call(&app, Method::POST, "/jobs/generation", Some(serde_json::json!({ "prompt": " " })), &owner).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // The second job waits for the only slot
+        let first = submit(&app, serde_json::json!({ "prompt": "count", "max_tokens": 12 }), &owner).await;
+        let failing = submit(&app, serde_json::json!({ "prompt": "fail please" }), &owner).await;
+        let (_, job) = call(&app, Method::GET, &format!("/jobs/{}", failing), None, &owner).await;
+        assert_eq!(job["status"], "queued");
+
+        let (output, reads) = read_all(&app, first, &owner, 16).await;
+        assert_eq!(output, (0..12).map(|i| format!("token{} ", i)).collect::<String>());
+        assert!(reads > 1);
+        let (_, job) = call(&app, Method::GET, &format!("/jobs/{}", first), None, &owner).await;
+        assert_eq!(job["status"], "completed");
+
+        let job = wait_for(&app, failing, &owner, |job| job["status"] == "failed").await;
+        assert_eq!(job["error"], "model unavailable");
+    }
+
+    #[tokio::test]
+    async fn test_finished_jobs_are_purged_after_retention() {
+        let store = MemoryJobStore::new(Duration::from_millis(50));
+        let old = JobInfo::queued(None, GenerationRequest { prompt: "old".to_string(), max_tokens: None })
+            .finished(JobStatus::Completed, None);
+        let running = JobInfo { status: JobStatus::Running, ..JobInfo::queued(None, old.request.clone()) };
+        store.put(&old).await.unwrap();
+        store.put(&running).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(80)).await;
+
+        let fresh = JobInfo::queued(None, old.request.clone());
+        store.put(&fresh).await.unwrap();
+        assert!(store.job(old.id).await.unwrap().is_none());
+        assert!(store.job(running.id).await.unwrap().is_some());
+        assert!(store.job(fresh.id).await.unwrap().is_some());
+    }
+
+    #[test]
+    fn test_reads_end_on_character_boundaries() {
+        let text = "héllo".as_bytes();
+        assert_eq!(complete_utf8(text[..2].to_vec()), "h");
+        assert_eq!(complete_utf8(text[..3].to_vec()), "hé");
+    }
+
+    /// Run with `TEST_REDIS_URL=redis://localhost:6379 cargo test -- --ignored`
+    #[tokio::test]
+    #[ignore = "needs a Redis server at TEST_REDIS_URL"]
+    async fn test_redis_store_keeps_output_and_cancellation() {
+        let url = std::env::var("TEST_REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379".to_string());
+        let client = redis::Client::open(url.as_str()).unwrap();
+        let store = RedisJobStore::new(&client, Duration::from_secs(60)).await.unwrap()
+            .with_namespace(format!("test-jobs-{}", Uuid::new_v4()));
+        let job = JobInfo::queued(None, GenerationRequest { prompt: "count".to_string(), max_tokens: None });
+        store.put(&job).await.unwrap();
+        store.append_output(job.id, "hello ").await.unwrap();
+        store.append_output(job.id, "world").await.unwrap();
+        assert_eq!(store.output(job.id, 6, 100).await.unwrap(), (b"world".to_vec(), 11));
+        assert!(!store.cancel_requested(job.id).await.unwrap());
+        store.request_cancel(job.id).await.unwrap();
+        assert!(store.cancel_requested(job.id).await.unwrap());
+        assert_eq!(store.job(job.id).await.unwrap().unwrap().status, JobStatus::Queued);
+    }
+}
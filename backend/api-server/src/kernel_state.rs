@@ -0,0 +1,139 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    response::IntoResponse,
+};
+use jarvis_core::{CognitiveKernel, StateChange};
+use serde::Deserialize;
+use tokio::sync::broadcast;
+use tracing::{debug, warn};
+
+use crate::event_bus::{BusSubscription, EventBus, Topic};
+
+#[derive(Debug, Deserialize)]
+pub struct StateSubscription {
+    pub namespace: String,
+}
+
+/// Publish changes to `namespaces` on the event bus, so other replicas' subscribers see
+/// them. Namespaces shared between replicas are the persisted ones.
+pub fn publish_changes(kernel: &CognitiveKernel, events: Arc<dyn EventBus>, namespaces: &[String]) {
+    for namespace in namespaces {
+        let mut changes = kernel.subscribe_state(namespace);
+        let events = events.clone();
+        tokio::spawn(async move {
+            loop {
+                match changes.recv().await {
+                    Ok(change) => match serde_json::to_value(&change) {
+                        Ok(payload) => {
+                            events.publish(Topic::KernelState, payload);
+                        }
+                        Err(e) => warn!("Failed to serialize a change to {}: {}", change.namespace, e),
+                    },
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        warn!("Missed publishing {} kernel state changes", missed);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+            }
+        });
+    }
+}
+
+/// WebSocket at `/ws?namespace=<namespace>` that sends the namespace's current value,
+/// then every change to it made on any replica, as JSON `StateChange` messages
+pub async fn websocket_handler(
+    ws: WebSocketUpgrade,
+    Query(subscription): Query<StateSubscription>,
+    State(kernel): State<Arc<CognitiveKernel>>,
+    State(events): State<Arc<dyn EventBus>>,
+) -> impl IntoResponse {
+    // Subscribe before reading the current value so no change falls between the two
+    let changes = kernel.subscribe_state(&subscription.namespace);
+    let remote = RemoteChanges {
+        namespace: subscription.namespace.clone(),
+        replica_id: events.replica_id().to_string(),
+        events: events.subscribe(Topic::KernelState),
+    };
+    let current = kernel.get_global_state(&subscription.namespace).map(|value| StateChange {
+        namespace: subscription.namespace.clone(),
+        value,
+        changed_at: chrono::Utc::now(),
+    });
+    ws.on_upgrade(move |socket| push_changes(socket, current, changes, remote))
+}
+
+/// Changes to a namespace published on the event bus by other replicas; this replica's
+/// own changes come straight from the kernel
+struct RemoteChanges {
+    namespace: String,
+    replica_id: String,
+    events: BusSubscription,
+}
+
+impl RemoteChanges {
+    /// The next change, or never once the bus is gone
+    async fn recv(&mut self) -> StateChange {
+        while let Some(event) = self.events.recv().await {
+            if event.origin == self.replica_id {
+                continue;
+            }
+            match serde_json::from_value::<StateChange>(event.payload) {
+                Ok(change) if change.namespace == self.namespace => return change,
+                Ok(_) => {}
+                Err(e) => warn!("Malformed kernel state change from {}: {}", event.origin, e),
+            }
+        }
+        std::future::pending().await
+    }
+}
+
+async fn push_changes(
+    mut socket: WebSocket,
+    current: Option<StateChange>,
+    mut changes: broadcast::Receiver<StateChange>,
+    mut remote: RemoteChanges,
+) {
+    if let Some(change) = current {
+        if send(&mut socket, &change).await.is_err() {
+            return;
+        }
+    }
+    loop {
+        tokio::select! {
+            change = changes.recv() => match change {
+                Ok(change) => {
+                    if send(&mut socket, &change).await.is_err() {
+                        return;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    warn!("State subscriber fell behind and missed {} changes", missed);
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+            change = remote.recv() => {
+                if send(&mut socket, &change).await.is_err() {
+                    return;
+                }
+            }
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => {
+                    debug!("State subscriber disconnected");
+                    return;
+                }
+                // Subscribers only listen; anything they send is ignored
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+async fn send(socket: &mut WebSocket, change: &StateChange) -> Result<(), axum::Error> {
+    let text = serde_json::to_string(change).map_err(axum::Error::new)?;
+    socket.send(Message::Text(text)).await
+}
@@ -3,17 +3,16 @@ use std::time::Duration;
 
 use anyhow::Result;
 use axum::{
-    extract::{Extension, Path, Query, State},
-    http::{header, Method, StatusCode},
+    extract::{Extension, FromRef, Path, Query, State},
+    http::{header, HeaderMap, Method, StatusCode},
     middleware,
     response::{IntoResponse, Json},
-    routing::{get, post, put, delete},
+    routing::{get, post, delete},
     Router,
 };
 use async_graphql::{Context, EmptySubscription, Object, Schema, SimpleObject};
 use async_graphql_axum::{GraphQLRequest, GraphQLResponse};
 use chrono::{DateTime, Utc};
-use dashmap::DashMap;
 use serde::{Deserialize, Serialize};
 use sqlx::{PgPool, Pool, Postgres};
 use tokio::net::TcpListener;
@@ -24,24 +23,70 @@ use tower_http::{
     timeout::TimeoutLayer,
     trace::TraceLayer,
 };
-use tracing::{info, instrument, Level};
+use tracing::{error, info, instrument, warn};
 use uuid::Uuid;
 
-use jarvis_core::{CognitiveKernel, Intent, IntentExecutionPlan, RiskLevel};
-
+use jarvis_core::{
+    ApprovalLedger, ArtifactStore, AuditActor, Auditor, CognitiveKernel, DistributedLock, Environment, ExecutionContext, Externalizer,
+    FsArtifactStore, Intent, IntentClassifier, IntentExecutionPlan, JsonlAuditSink, RedisLockStore, RedisStatePersistence,
+    RiskLevel, SingletonJob,
+};
+use memory_continuum::MemoryContinuum;
+use metrics_exporter_prometheus::PrometheusHandle;
+use talkpp_external_services::notifications::{EmailComposer, NotificationDispatcher, ServiceEmailSender};
+use talkpp_external_services::secrets::{AesGcmCipher, MASTER_KEY_ENV};
+use talkpp_external_services::storage::S3ArtifactStore;
+use talkpp_external_services::ExternalServicesManager;
+use talkpp_mcp_hub::McpHub;
+use talkpp_tenancy::TenantContext;
+
+mod approvals;
+mod artifacts;
+mod audit;
+mod batch;
 mod auth;
+mod capabilities;
 mod config;
 mod error;
+mod event_bus;
 mod handlers;
+mod idempotency;
+mod intent_stream;
+mod jobs;
+mod kernel_state;
+mod mcp;
+mod memory;
 mod middleware as custom_middleware;
+mod migrations;
 mod models;
+mod notifications;
+mod plans;
+mod preferences;
 mod schema;
 mod services;
-
+mod shutdown;
+mod telemetry;
+
+use approvals::TaskDecision;
+use audit::PostgresAuditSink;
+use batch::{Batches, KernelProcessor, RedisBatchQueue};
+use jobs::{FunctionGenerator, Generator, JobManager, ModelGenerator, RedisJobStore};
+use capabilities::{CapabilityRegistry, CudaProbe, ExternalServicesProbe, McpProbe, OllamaProbe, QdrantProbe};
 use config::Config;
 use error::{ApiError, ApiResult};
+use event_bus::{EventBus, RedisEventBus, Topic};
+use idempotency::{Idempotency, RedisIdempotencyStore};
+use intent_stream::{IntentStreams, RedisEventBuffer};
+use notifications::{Notifier, PreferenceRecipients};
+use plans::RecentPlans;
+use preferences::{PolicyDecision, PolicyOutcome, PostgresPreferencesStore, Preferences};
+use services::redis_store::{Caches, RedisBackend, RedisStore};
 use models::*;
-use schema::{MutationRoot, QueryRoot};
+use schema::{MutationRoot, PlanBudgetGQL, PlanBudgetInput, QueryRoot};
+use shutdown::{ShutdownHandle, ShutdownStage};
+
+/// Name of the lock memory consolidation runs under
+const CONSOLIDATION_JOB: &str = "memory-consolidation";
 
 /// Main application state
 #[derive(Clone)]
@@ -49,10 +94,114 @@ pub struct AppState {
     pub db: PgPool,
     pub redis: redis::Client,
     pub cognitive_kernel: Arc<CognitiveKernel>,
-    pub active_sessions: Arc<DashMap<Uuid, UserSession>>,
+    pub memory: Arc<MemoryContinuum>,
+    pub mcp: Arc<McpHub>,
+    /// Signed-in sessions by session id, shared with the other replicas
+    pub sessions: RedisStore<UserSession>,
+    pub idempotency: Idempotency,
+    pub auditor: Auditor,
+    /// Tasks paused by an approval policy, waiting on approvers
+    pub approvals: ApprovalLedger,
+    pub artifacts: Externalizer,
+    pub batches: Batches,
+    /// Generation jobs clients poll and cancel
+    pub jobs: JobManager,
+    pub preferences: Preferences,
+    pub intent_streams: IntentStreams,
+    /// Plans made here lately, for drawing their graphs
+    pub plans: RecentPlans,
+    /// Plan, task and kernel state events shared with the other replicas
+    pub events: Arc<dyn EventBus>,
+    pub notifier: Notifier,
+    /// What this deployment can do, probed lazily
+    pub capabilities: CapabilityRegistry,
+    /// Locks that keep background jobs to one replica at a time
+    pub job_locks: DistributedLock,
     pub config: Arc<Config>,
+    /// Renders the metrics recorded so far for `GET /metrics`
+    pub metrics: PrometheusHandle,
+}
+
+impl FromRef<AppState> for Arc<CognitiveKernel> {
+    fn from_ref(state: &AppState) -> Self {
+        Arc::clone(&state.cognitive_kernel)
+    }
+}
+
+impl FromRef<AppState> for Arc<MemoryContinuum> {
+    fn from_ref(state: &AppState) -> Self {
+        Arc::clone(&state.memory)
+    }
+}
+
+impl FromRef<AppState> for Arc<McpHub> {
+    fn from_ref(state: &AppState) -> Self {
+        Arc::clone(&state.mcp)
+    }
+}
+
+impl FromRef<AppState> for Arc<dyn ArtifactStore> {
+    fn from_ref(state: &AppState) -> Self {
+        Arc::clone(state.artifacts.store())
+    }
+}
+
+impl FromRef<AppState> for Arc<dyn EventBus> {
+    fn from_ref(state: &AppState) -> Self {
+        Arc::clone(&state.events)
+    }
+}
+
+impl FromRef<AppState> for Batches {
+    fn from_ref(state: &AppState) -> Self {
+        state.batches.clone()
+    }
+}
+
+impl FromRef<AppState> for JobManager {
+    fn from_ref(state: &AppState) -> Self {
+        state.jobs.clone()
+    }
 }
 
+impl FromRef<AppState> for Preferences {
+    fn from_ref(state: &AppState) -> Self {
+        state.preferences.clone()
+    }
+}
+
+impl FromRef<AppState> for IntentStreams {
+    fn from_ref(state: &AppState) -> Self {
+        state.intent_streams.clone()
+    }
+}
+
+impl FromRef<AppState> for RecentPlans {
+    fn from_ref(state: &AppState) -> Self {
+        state.plans.clone()
+    }
+}
+
+impl FromRef<AppState> for Auditor {
+    fn from_ref(state: &AppState) -> Self {
+        state.auditor.clone()
+    }
+}
+
+impl FromRef<AppState> for CapabilityRegistry {
+    fn from_ref(state: &AppState) -> Self {
+        state.capabilities.clone()
+    }
+}
+
+/// Permission that lets a session's production plans keep the autonomy tier their risk
+/// allows, rather than being held to `PRODUCTION_MAX_AUTONOMY_TIER`
+pub const PRODUCTION_AUTONOMY_PERMISSION: &str = "plans:production-autonomy";
+
+/// Cache namespace sessions are kept in, expiring `SESSION_TIMEOUT_HOURS` after they
+/// are stored unless `CACHE_TTLS` says otherwise
+pub const SESSION_NAMESPACE: &str = "sessions";
+
 /// User session information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserSession {
@@ -61,6 +210,33 @@ pub struct UserSession {
     pub created_at: DateTime<Utc>,
     pub last_activity: DateTime<Utc>,
     pub permissions: Vec<String>,
+    /// Tenant the user belongs to; sessions from before tenants existed are in the default one
+    #[serde(default = "talkpp_tenancy::default_tenant_id")]
+    pub tenant_id: String,
+}
+
+impl UserSession {
+    /// Who the session's requests act for, as the tenant-scoped libraries take it
+    pub fn tenant(&self) -> TenantContext {
+        TenantContext { tenant_id: self.tenant_id.clone(), user_id: self.user_id.to_string() }
+    }
+
+    /// Context that grounds the session user's intents in their own memories, traced as
+    /// part of the current request
+    pub fn intent_context(&self) -> ExecutionContext {
+        let context = ExecutionContext::new(Uuid::nil())
+            .with_user(self.user_id.to_string())
+            .with_production_autonomy(self.permissions.iter().any(|p| p == PRODUCTION_AUTONOMY_PERMISSION));
+        match jarvis_core::telemetry::current_traceparent() {
+            Some(traceparent) => context.with_trace_parent(traceparent),
+            None => context,
+        }
+    }
+
+    /// The session user as the audit log records them
+    pub fn audit_actor(&self) -> AuditActor {
+        AuditActor::user(self.user_id.to_string(), self.session_id.to_string())
+    }
 }
 
 /// Health check response
@@ -80,6 +256,18 @@ pub struct ProcessIntentRequest {
     pub intent: String,
     pub context: Option<serde_json::Value>,
     pub user_preferences: Option<UserPreferences>,
+    /// Overrides of the plan's tier-default budget
+    pub budget: Option<PlanBudgetInput>,
+    /// Environment to target, such as `staging`, instead of the one the intent names
+    pub environment: Option<String>,
+}
+
+/// Query of `POST /intents`
+#[derive(Debug, Default, Deserialize)]
+pub struct ProcessIntentQuery {
+    /// Answer with server-sent events following the intent instead of the plan as JSON
+    #[serde(default)]
+    pub stream: bool,
 }
 
 /// Intent processing response
@@ -92,6 +280,46 @@ pub struct ProcessIntentResponse {
     pub tasks: Vec<TaskSummary>,
     pub risk_level: String,
     pub requires_approval: bool,
+    pub budget: PlanBudgetGQL,
+    /// Environment the plan targets, if the request or intent named one
+    pub environment: Option<String>,
+    /// Changes the user's preferences made to the plan
+    pub policy_decisions: Vec<PolicyDecision>,
+}
+
+impl ProcessIntentResponse {
+    pub fn new(intent: &Intent, plan: &IntentExecutionPlan) -> Self {
+        // Convert tasks to API format
+        let tasks: Vec<TaskSummary> = plan.tasks.iter().map(|task| TaskSummary {
+            id: task.id,
+            name: task.name.clone(),
+            description: task.description.clone(),
+            task_type: format!("{:?}", task.task_type),
+            estimated_duration: task.estimated_duration.num_minutes(),
+            status: format!("{:?}", task.status),
+            dry_run_first: task.dry_run_first,
+        }).collect();
+
+        Self {
+            plan_id: plan.id,
+            intent_id: plan.intent_id,
+            estimated_duration: plan.estimated_duration.num_minutes(),
+            autonomy_tier: plan.autonomy_tier,
+            tasks,
+            risk_level: format!("{:?}", intent.risk_level),
+            requires_approval: plan.autonomy_tier <= 2,
+            budget: (&plan.budget).into(),
+            environment: plan.environment.as_ref().map(Environment::to_string),
+            policy_decisions: Vec::new(),
+        }
+    }
+
+    /// The response once the user's preferences were enforced on its plan
+    pub fn with_policy(mut self, outcome: PolicyOutcome) -> Self {
+        self.requires_approval |= outcome.requires_approval;
+        self.policy_decisions = outcome.decisions;
+        self
+    }
 }
 
 /// Task summary for API responses
@@ -107,9 +335,12 @@ pub struct TaskSummary {
 }
 
 /// User preferences
-#[derive(Debug, Serialize, Deserialize, SimpleObject)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, SimpleObject)]
 pub struct UserPreferences {
+    /// Highest autonomy tier the user's plans may run at
     pub max_autonomy_tier: Option<u8>,
+    /// Risk levels whose plans always need the user's approval
+    #[serde(default)]
     pub require_approval_for_risks: Vec<String>,
     pub preferred_execution_mode: Option<String>,
     pub notification_preferences: Option<serde_json::Value>,
@@ -117,20 +348,16 @@ pub struct UserPreferences {
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize tracing
-    tracing_subscriber::fmt()
-        .with_max_level(Level::INFO)
-        .with_target(false)
-        .with_thread_ids(true)
-        .with_file(true)
-        .with_line_number(true)
-        .json()
-        .init();
+    // Load configuration, then initialize tracing as it configures
+    let mut config = Config::load()?;
+    telemetry::init(&config.observability)?;
+    let metrics = telemetry::init_metrics()?;
 
     info!("🚀 Starting Talk++ API Server");
 
-    // Load configuration
-    let config = Arc::new(Config::load()?);
+    let secrets = config.services.secrets_resolver()?;
+    config.resolve_secrets(&secrets).await?;
+    let config = Arc::new(config);
     info!("✅ Configuration loaded");
 
     // Initialize database
@@ -148,17 +375,242 @@ async fn main() -> Result<()> {
     let redis_client = redis::Client::open(redis_url.as_str())?;
     info!("✅ Redis connection established");
 
+    let caches = Caches::new(Arc::new(RedisBackend::new(&redis_client).await?), config.cache.clone());
+    let idempotency = Idempotency::new(Arc::new(RedisIdempotencyStore::new(
+        &caches,
+        Duration::from_secs(config.idempotency.lock_ttl_secs),
+    )));
+
+    let auditor = match config.audit.sink.as_str() {
+        "jsonl" => Auditor::spawn(Arc::new(JsonlAuditSink::new(&config.audit.jsonl_path)), config.audit.channel_capacity),
+        _ => Auditor::spawn(Arc::new(PostgresAuditSink::new(db.clone())), config.audit.channel_capacity),
+    };
+    info!("✅ Audit log writing to {}", config.audit.sink);
+
+    let artifact_store: Arc<dyn ArtifactStore> = match config.artifacts.backend.as_str() {
+        "s3" => {
+            let bucket = config.artifacts.s3_bucket.clone().expect("validated with the config");
+            Arc::new(S3ArtifactStore::from_env(bucket).await.with_prefix(&config.artifacts.s3_prefix))
+        }
+        _ => Arc::new(FsArtifactStore::new(&config.artifacts.path)),
+    };
+    let artifacts = Externalizer::new(artifact_store).with_inline_threshold(config.artifacts.inline_threshold_bytes);
+    info!("✅ Artifact storage on {}", config.artifacts.backend);
+
+    let preferences = Preferences::new(Arc::new(PostgresPreferencesStore::new(db.clone())), config.preferences.defaults())
+        .with_auditor(auditor.clone());
+
     // Initialize JARVIS Cognitive Kernel
-    let cognitive_kernel = Arc::new(CognitiveKernel::new());
-    info!("✅ JARVIS Cognitive Kernel initialized");
+    let classifier = Arc::new(IntentClassifier::new());
+    if let Some(path) = &config.intent.patterns_path {
+        classifier.load_patterns(path)?;
+    }
+
+    // Initialize Memory Continuum
+    let mut memory = MemoryContinuum::new(config.memory.continuum_config()).await?;
+    if let Some(sanitizer) = config.memory.sanitizer() {
+        memory = memory.with_sanitizer(sanitizer);
+        info!("Personal data in memories is masked before they are stored");
+    }
+    let memory = Arc::new(memory);
+    info!("✅ Memory Continuum initialized");
+
+    // Selected kernel state namespaces live in Redis, shared across replicas
+    let state_persistence = RedisStatePersistence::new(
+        redis::aio::ConnectionManager::new(redis_client.clone()).await?,
+        config.kernel_state.redis_prefix.clone(),
+    );
+    let cognitive_kernel = Arc::new(
+        CognitiveKernel::new()
+            .with_classifier(classifier)
+            .with_memory(memory.clone())
+            .with_state_persistence(Arc::new(state_persistence), config.kernel_state.persisted_namespaces.clone()),
+    );
+    let restored = cognitive_kernel.restore_state().await?;
+    info!("✅ JARVIS Cognitive Kernel initialized, {} state namespaces restored", restored);
+
+    // Background jobs run on one replica at a time, under locks kept in Redis
+    let job_locks = DistributedLock::new(
+        Arc::new(RedisLockStore::new(
+            redis::aio::ConnectionManager::new(redis_client.clone()).await?,
+            config.locks.redis_prefix.clone(),
+        )),
+        config.locks.replica_id.clone(),
+    )
+    .with_lease(Duration::from_secs(config.locks.lease_secs));
+    info!("✅ Job locks held as replica {}", config.locks.replica_id);
+
+    // Email plan owners through the configured service, if any
+    let notifier = match config.notifications.email_provider.as_str() {
+        "smtp" => {
+            let services = ExternalServicesManager::new().with_secrets(Arc::new(secrets));
+            // The SMTP credentials never leave this process, so without a master key they stay in plaintext
+            let services = Arc::new(if std::env::var_os(MASTER_KEY_ENV).is_some() {
+                services.with_cipher(Arc::new(AesGcmCipher::from_env()?))
+            } else {
+                services.with_plaintext_credentials()
+            });
+            let service_id = services.register_service(config.notifications.smtp_service()).await?;
+            Notifier::spawn(NotificationDispatcher::new(
+                EmailComposer::new(&config.notifications.web_base_url),
+                Arc::new(PreferenceRecipients(preferences.clone())),
+                Arc::new(ServiceEmailSender::new(services, service_id)),
+            ))
+        }
+        _ => Notifier::disabled(),
+    };
+    info!("✅ Notification emails via {}", config.notifications.email_provider);
+
+    // Fan plan, task and kernel state events out to every replica through Redis pub/sub
+    let events: Arc<dyn EventBus> = Arc::new(RedisEventBus::new(
+        &redis_client,
+        config.locks.replica_id.clone(),
+        config.event_bus.redis_prefix.clone(),
+        config.event_bus.max_buffered,
+    ));
+    kernel_state::publish_changes(&cognitive_kernel, events.clone(), &config.kernel_state.persisted_namespaces);
+    info!("✅ Event bus on Redis channels {}*", config.event_bus.redis_prefix);
+
+    // Buffer intent events in Redis so any replica can resume a client's stream
+    let intent_streams = IntentStreams::new(Arc::new(RedisEventBuffer::new(
+        &redis_client,
+        config.intent_stream.buffered_events,
+        Duration::from_secs(config.intent_stream.retention_secs),
+    ).await?))
+    .with_event_bus(events.clone())
+    .with_notifier(notifier.clone())
+    .with_allowed_environments(config.intent.allowed_environments.clone());
+
+    // Queue batches in Redis so they survive restarts
+    let batches = Batches::new(
+        Arc::new(RedisBatchQueue::new(&redis_client, Duration::from_secs(config.batch.retention_secs)).await?),
+        config.batch.max_items,
+    );
+
+    // Keep generation jobs in Redis so any replica can report on or cancel them
+    let generator: Arc<dyn Generator> = match config.jobs.model.strip_prefix(jobs::FUNCTION_MODEL_PREFIX) {
+        Some(function_id) => {
+            let runtime = talkpp_runtime::Runtime::with_persistence(&config.jobs.function_store_path)?;
+            Arc::new(FunctionGenerator::new(Arc::new(runtime), function_id.parse()?))
+        }
+        None => {
+            let generation_model = talkpp_ollama_integration::OllamaLanguageModel::from_model_path(
+                &config.jobs.model,
+                Some(config.capabilities.ollama_url.clone()),
+            )
+            .ok_or_else(|| anyhow::anyhow!("Unsupported generation model: {}", config.jobs.model))?;
+            Arc::new(ModelGenerator::new(Arc::new(generation_model), config.jobs.max_tokens))
+        }
+    };
+    let jobs = JobManager::new(
+        Arc::new(RedisJobStore::new(&redis_client, Duration::from_secs(config.jobs.retention_secs)).await?),
+        generator,
+        config.jobs.max_running,
+    )
+    .with_flush_interval(Duration::from_millis(config.jobs.flush_interval_ms));
+
+    // Initialize MCP Hub from its persisted registry
+    let mcp = Arc::new(McpHub::with_registry(&config.mcp.registry_path)?.with_auditor(auditor.clone()));
+    mcp.connect_enabled().await;
+    info!("✅ MCP Hub initialized");
+
+    // Probe what this deployment can do; later probes happen when a capability is stale
+    let capabilities = CapabilityRegistry::new(Duration::from_secs(config.capabilities.refresh_secs))
+        .with_probe_timeout(Duration::from_secs(config.capabilities.probe_timeout_secs));
+    capabilities.register(CudaProbe::new());
+    capabilities.register(QdrantProbe::new(&config.capabilities.qdrant_url));
+    capabilities.register(OllamaProbe::new(&config.capabilities.ollama_url));
+    capabilities.register(McpProbe::new(mcp.clone()));
+    capabilities.register(ExternalServicesProbe::new(config.external_services()));
+    let available = capabilities.all().await.into_iter()
+        .filter(|capability| capability.available)
+        .map(|capability| capability.subsystem)
+        .collect::<Vec<_>>();
+    info!("✅ Capabilities probed, available: {}", available.join(", "));
+
+    // Register background work and shutdown hooks in drain order
+    let shutdown = ShutdownHandle::new(Duration::from_secs(config.shutdown.deadline_secs));
+    shutdown.on_shutdown(ShutdownStage::FlushState, "memory-continuum", {
+        let memory = memory.clone();
+        move || async move { memory.shutdown().await.map(|_| ()) }
+    });
+    shutdown.spawn(ShutdownStage::Schedulers, CONSOLIDATION_JOB, {
+        let memory = memory.clone();
+        let interval = Duration::from_secs(config.memory.consolidation_interval_secs);
+        let job = SingletonJob::new(CONSOLIDATION_JOB, job_locks.clone());
+        move |stop| async move {
+            job.run(stop, |halted| consolidation_loop(memory.clone(), interval, halted)).await
+        }
+    });
+    shutdown.spawn(ShutdownStage::Schedulers, "artifact-gc", {
+        let store = artifacts.store().clone();
+        let retention = chrono::Duration::days(config.artifacts.retention_days as i64);
+        let interval = Duration::from_secs(config.artifacts.gc_interval_secs);
+        move |stop| artifact_gc_loop(store, retention, interval, stop)
+    });
+    let processor: Arc<dyn batch::IntentProcessor> = Arc::new(
+        KernelProcessor::new(cognitive_kernel.clone())
+            .with_preferences(preferences.clone())
+            .with_notifier(notifier.clone())
+            .with_allowed_environments(config.intent.allowed_environments.clone()),
+    );
+    for worker in 0..config.batch.workers {
+        let queue = batches.queue().clone();
+        let processor = processor.clone();
+        let visibility = Duration::from_secs(config.batch.visibility_timeout_secs);
+        shutdown.spawn(ShutdownStage::Schedulers, format!("batch-worker-{}", worker), move |stop| {
+            batch::worker_loop(queue, processor, visibility, stop)
+        });
+    }
+    shutdown.on_shutdown(ShutdownStage::Schedulers, "mcp-hub", {
+        let mcp = mcp.clone();
+        move || async move {
+            mcp.shutdown().await;
+            Ok(())
+        }
+    });
+    shutdown.on_shutdown(ShutdownStage::FlushState, "audit-log", {
+        let auditor = auditor.clone();
+        move || async move {
+            auditor.flush().await;
+            Ok(())
+        }
+    });
+    shutdown.on_shutdown(ShutdownStage::FlushState, "tracing", || async {
+        telemetry::flush().await;
+        Ok(())
+    });
+    shutdown.on_shutdown(ShutdownStage::Pools, "postgres", {
+        let db = db.clone();
+        move || async move {
+            db.close().await;
+            Ok(())
+        }
+    });
 
     // Initialize application state
     let app_state = AppState {
         db,
         redis: redis_client,
         cognitive_kernel,
-        active_sessions: Arc::new(DashMap::new()),
+        memory,
+        mcp,
+        sessions: caches.store(SESSION_NAMESPACE),
+        idempotency: idempotency.clone(),
+        auditor,
+        approvals: ApprovalLedger::new(),
+        artifacts,
+        batches,
+        jobs,
+        preferences,
+        intent_streams,
+        plans: RecentPlans::default(),
+        events,
+        notifier,
+        capabilities: capabilities.clone(),
+        job_locks,
         config: config.clone(),
+        metrics,
     };
 
     // Create GraphQL schema
@@ -173,7 +625,7 @@ async fn main() -> Result<()> {
         .route("/ready", get(readiness_check))
         
         // API v1 routes
-        .nest("/api/v1", api_v1_routes())
+        .nest("/api/v1", api_v1_routes(idempotency, capabilities))
         
         // GraphQL endpoint
         .route("/graphql", post(graphql_handler))
@@ -182,15 +634,15 @@ async fn main() -> Result<()> {
         // Metrics endpoint (for Prometheus)
         .route("/metrics", get(metrics_handler))
         
-        // WebSocket for real-time updates
-        .route("/ws", get(websocket_handler))
+        // WebSocket pushing kernel state changes
+        .route("/ws", get(kernel_state::websocket_handler))
         
         // State and middleware
         .layer(Extension(schema))
         .with_state(app_state)
         .layer(
             ServiceBuilder::new()
-                .layer(TraceLayer::new_for_http())
+                .layer(TraceLayer::new_for_http().make_span_with(telemetry::request_span))
                 .layer(CompressionLayer::new())
                 .layer(TimeoutLayer::new(Duration::from_secs(30)))
                 .layer(
@@ -212,24 +664,87 @@ async fn main() -> Result<()> {
     info!("📊 GraphQL Playground available at http://{}/graphql/playground", bind_addr);
     info!("📈 Metrics available at http://{}/metrics", bind_addr);
 
-    axum::serve(listener, app).await?;
+    shutdown.spawn(ShutdownStage::Requests, "http-server", {
+        let shutdown = shutdown.clone();
+        let drain = shutdown.triggered(ShutdownStage::Requests);
+        move |_| async move {
+            if let Err(e) = axum::serve(listener, app).with_graceful_shutdown(drain).await {
+                error!("Server error: {}", e);
+                shutdown.request();
+            }
+        }
+    });
+
+    tokio::select! {
+        _ = shutdown::termination_signal() => {}
+        _ = shutdown.requested() => warn!("Shutdown requested after a fatal error"),
+    }
+
+    let report = shutdown.shutdown().await;
+    if !report.aborted.is_empty() {
+        warn!("Shutdown deadline aborted: {}", report.aborted.join(", "));
+    }
 
     Ok(())
 }
 
-/// API v1 routes
-fn api_v1_routes() -> Router<AppState> {
+/// Run memory consolidation every `interval` until told to stop
+async fn consolidation_loop(memory: Arc<MemoryContinuum>, interval: Duration, mut stop: tokio::sync::watch::Receiver<bool>) {
+    let mut ticks = tokio::time::interval(interval);
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => {
+                if let Err(e) = memory.run_consolidation().await {
+                    error!("Memory consolidation failed: {}", e);
+                }
+            }
+            _ = stop.wait_for(|s| *s) => break,
+        }
+    }
+}
+
+/// Delete artifacts past their retention every `interval` until told to stop
+async fn artifact_gc_loop(
+    store: Arc<dyn ArtifactStore>,
+    retention: chrono::Duration,
+    interval: Duration,
+    mut stop: tokio::sync::watch::Receiver<bool>,
+) {
+    let mut ticks = tokio::time::interval(interval);
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => {
+                if let Err(e) = jarvis_core::artifacts::collect_garbage(store.as_ref(), retention).await {
+                    error!("Artifact garbage collection failed: {}", e);
+                }
+            }
+            _ = stop.wait_for(|s| *s) => break,
+        }
+    }
+}
+
+/// API v1 routes; routes with side effects honour an `Idempotency-Key` header, and routes
+/// needing a subsystem answer 503 while it is unavailable
+fn api_v1_routes(idempotency: Idempotency, capabilities: CapabilityRegistry) -> Router<AppState> {
+    let idempotent = middleware::from_fn_with_state(idempotency, idempotency::enforce);
+    let needs_vector_db = middleware::from_fn_with_state(
+        capabilities.requirement(capabilities::VECTOR_DB),
+        capabilities::enforce,
+    );
+
     Router::new()
         // Intent processing
-        .route("/intents", post(process_intent))
+        .route("/intents", post(process_intent).route_layer(idempotent.clone()))
         .route("/intents/:intent_id", get(get_intent))
         .route("/intents/:intent_id/status", get(get_intent_status))
+        .merge(intent_stream::routes())
         
         // Execution plans
         .route("/plans", get(list_execution_plans))
-        .route("/plans/:plan_id", get(get_execution_plan))
-        .route("/plans/:plan_id/execute", post(execute_plan))
+        .route("/plans/:plan_id", get(get_execution_plan).delete(delete_execution_plan))
+        .route("/plans/:plan_id/execute", post(execute_plan).route_layer(idempotent))
         .route("/plans/:plan_id/cancel", post(cancel_plan))
+        .merge(plans::routes())
         
         // Tasks
         .route("/tasks", get(list_tasks))
@@ -239,21 +754,36 @@ fn api_v1_routes() -> Router<AppState> {
         
         // User management
         .route("/users/me", get(get_current_user))
-        .route("/users/me/preferences", get(get_user_preferences))
-        .route("/users/me/preferences", put(update_user_preferences))
+        .merge(preferences::routes())
         
         // Cognitive kernel status
         .route("/kernel/status", get(get_kernel_status))
         .route("/kernel/metrics", get(get_kernel_metrics))
         
         // Vector database operations
-        .route("/vectors/search", post(vector_search))
-        .route("/vectors/embed", post(embed_text))
+        .route("/vectors/search", post(vector_search).route_layer(needs_vector_db.clone()))
+        .route("/vectors/embed", post(embed_text).route_layer(needs_vector_db))
+        
+        // Memory continuum
+        .nest("/memory", memory::routes())
         
         // MCP operations
-        .route("/mcp/servers", get(list_mcp_servers))
-        .route("/mcp/servers/:server_id/tools", get(list_mcp_tools))
-        .route("/mcp/tools/:tool_id/execute", post(execute_mcp_tool))
+        .nest("/mcp", mcp::routes())
+
+        // Audit log
+        .nest("/audit", audit::routes())
+
+        // Plan artifacts
+        .merge(artifacts::routes())
+
+        // Batch intent processing
+        .merge(batch::routes())
+
+        // Long-running generation jobs
+        .merge(jobs::routes())
+
+        // What this deployment can do
+        .merge(capabilities::routes())
 }
 
 /// Health check endpoint
@@ -285,53 +815,73 @@ async fn readiness_check() -> impl IntoResponse {
     (StatusCode::OK, "ready")
 }
 
-/// Process intent endpoint
-#[instrument(skip(state))]
+/// Process intent endpoint; with `?stream=true` the answer is a stream of the intent's
+/// events, see [`intent_stream`]
+#[instrument(skip(state, session))]
 async fn process_intent(
     State(state): State<AppState>,
+    session: Option<Extension<UserSession>>,
+    Query(query): Query<ProcessIntentQuery>,
     Json(request): Json<ProcessIntentRequest>,
-) -> ApiResult<Json<ProcessIntentResponse>> {
+) -> ApiResult<axum::response::Response> {
     info!("Processing intent: {}", request.intent);
 
     // Process intent through cognitive kernel
-    let plan = state.cognitive_kernel
-        .process_intent(&request.intent, None)
+    let session = session.map(|Extension(session)| session);
+    let environment = request.environment.as_deref()
+        .map(str::parse::<Environment>)
+        .transpose()
+        .map_err(|e| ApiError::BadRequest(format!("Invalid environment: {}", e)))?;
+    let context = match (session.as_ref().map(UserSession::intent_context), environment) {
+        (context, Some(environment)) => Some(context.unwrap_or_else(|| ExecutionContext::new(Uuid::nil())).with_environment(environment)),
+        (context, None) => context,
+    };
+    let (intent, mut plan) = state.cognitive_kernel
+        .plan_intent(&request.intent, context)
         .await
         .map_err(|e| ApiError::InternalError(format!("Failed to process intent: {}", e)))?;
-
-    // Convert tasks to API format
-    let tasks: Vec<TaskSummary> = plan.tasks.iter().map(|task| TaskSummary {
-        id: task.id,
-        name: task.name.clone(),
-        description: task.description.clone(),
-        task_type: format!("{:?}", task.task_type),
-        estimated_duration: task.estimated_duration.num_minutes(),
-        status: format!("{:?}", task.status),
-        dry_run_first: task.dry_run_first,
-    }).collect();
-
-    let response = ProcessIntentResponse {
-        plan_id: plan.id,
-        intent_id: plan.intent_id,
-        estimated_duration: plan.estimated_duration.num_minutes(),
-        autonomy_tier: plan.autonomy_tier,
-        tasks,
-        risk_level: format!("{:?}", state.cognitive_kernel.assess_risk(&request.intent)),
-        requires_approval: plan.autonomy_tier <= 2,
-    };
+    if let Some(budget) = &request.budget {
+        budget.apply(&mut plan.budget);
+    }
+
+    // Hold the plan to the user's autonomy and approval preferences
+    let owner = session.as_ref().map(|session| (session.user_id, session.audit_actor()));
+    let policy = state.preferences
+        .enforce(owner, request.user_preferences.as_ref(), &intent, &mut plan)
+        .await?;
+
+    let response = ProcessIntentResponse::new(&intent, &plan).with_policy(policy);
+    let owner = session.as_ref().map(|session| session.user_id);
+    if response.requires_approval {
+        state.notifier.approval_requested(owner, &intent, &plan);
+    }
 
     // Store plan in database
     // TODO: Implement database storage
+    state.plans.record(owner, &plan);
 
-    Ok(Json(response))
+    if query.stream {
+        state.intent_streams.start(owner, &intent, plan, &response).await?;
+        return Ok(state.intent_streams.respond(intent.id, 0).into_response());
+    }
+    Ok(Json(response).into_response())
 }
 
-/// GraphQL handler
+/// GraphQL handler; the request's session and idempotency key, if any, are passed on to resolvers
 async fn graphql_handler(
     schema: Extension<Schema<QueryRoot, MutationRoot, EmptySubscription>>,
+    session: Option<Extension<UserSession>>,
+    headers: HeaderMap,
     req: GraphQLRequest,
 ) -> GraphQLResponse {
-    schema.execute(req.into_inner()).await.into()
+    let mut req = req.into_inner();
+    if let Some(Extension(session)) = session {
+        req = req.data(session);
+    }
+    if let Some(key) = headers.get(idempotency::IDEMPOTENCY_KEY_HEADER).and_then(|v| v.to_str().ok()) {
+        req = req.data(schema::IdempotencyKey(key.to_string()));
+    }
+    schema.execute(req).await.into()
 }
 
 /// GraphQL playground
@@ -342,15 +892,8 @@ async fn graphql_playground() -> impl IntoResponse {
 }
 
 /// Metrics endpoint for Prometheus
-async fn metrics_handler() -> impl IntoResponse {
-    // TODO: Implement metrics collection
-    "# Talk++ API Server Metrics\n"
-}
-
-/// WebSocket handler for real-time updates
-async fn websocket_handler() -> impl IntoResponse {
-    // TODO: Implement WebSocket handler
-    (StatusCode::NOT_IMPLEMENTED, "WebSocket endpoint not yet implemented")
+async fn metrics_handler(State(state): State<AppState>) -> impl IntoResponse {
+    state.metrics.render()
 }
 
 // Placeholder handlers - these would be implemented in separate handler modules
@@ -370,6 +913,12 @@ async fn get_execution_plan(Path(_plan_id): Path<Uuid>) -> ApiResult<Json<serde_
     Ok(Json(serde_json::json!({"status": "not_implemented"})))
 }
 
+/// Delete a plan, and with it the artifacts its tasks produced
+async fn delete_execution_plan(State(state): State<AppState>, Path(plan_id): Path<Uuid>) -> ApiResult<Json<serde_json::Value>> {
+    let deleted = jarvis_core::artifacts::delete_plan_artifacts(state.artifacts.store().as_ref(), plan_id).await?;
+    Ok(Json(serde_json::json!({ "plan_id": plan_id, "artifacts_deleted": deleted })))
+}
+
 async fn execute_plan(Path(_plan_id): Path<Uuid>) -> ApiResult<Json<serde_json::Value>> {
     Ok(Json(serde_json::json!({"status": "not_implemented"})))
 }
@@ -386,28 +935,51 @@ async fn get_task(Path(_task_id): Path<Uuid>) -> ApiResult<Json<serde_json::Valu
     Ok(Json(serde_json::json!({"status": "not_implemented"})))
 }
 
-async fn approve_task(Path(_task_id): Path<Uuid>) -> ApiResult<Json<serde_json::Value>> {
-    Ok(Json(serde_json::json!({"status": "not_implemented"})))
-}
-
-async fn reject_task(Path(_task_id): Path<Uuid>) -> ApiResult<Json<serde_json::Value>> {
-    Ok(Json(serde_json::json!({"status": "not_implemented"})))
+/// Approve a task awaiting approval as the session user, who must be in one of its
+/// approver groups. Answers with how many approvals the task has and still needs.
+async fn approve_task(
+    State(state): State<AppState>,
+    session: Option<Extension<UserSession>>,
+    Path(task_id): Path<Uuid>,
+) -> ApiResult<Json<serde_json::Value>> {
+    let session = session.map(|Extension(session)| session);
+    let (pending, status) = approvals::decide_task(&state.approvals, &state.auditor, session.as_ref(), task_id, TaskDecision::Approve)?;
+    let decision = serde_json::json!({ "task_id": task_id, "plan_id": pending.plan_id, "approval": status });
+    state.events.publish(Topic::Task, decision.clone());
+    Ok(Json(decision))
 }
 
-async fn get_current_user() -> ApiResult<Json<serde_json::Value>> {
-    Ok(Json(serde_json::json!({"status": "not_implemented"})))
+#[derive(Debug, Default, Deserialize)]
+struct RejectTaskRequest {
+    reason: Option<String>,
 }
 
-async fn get_user_preferences() -> ApiResult<Json<serde_json::Value>> {
-    Ok(Json(serde_json::json!({"status": "not_implemented"})))
+/// Reject a task awaiting approval, failing its plan when it is resumed
+async fn reject_task(
+    State(state): State<AppState>,
+    session: Option<Extension<UserSession>>,
+    Path(task_id): Path<Uuid>,
+    request: Option<Json<RejectTaskRequest>>,
+) -> ApiResult<Json<serde_json::Value>> {
+    let session = session.map(|Extension(session)| session);
+    let reason = request.and_then(|Json(request)| request.reason);
+    let (pending, status) = approvals::decide_task(&state.approvals, &state.auditor, session.as_ref(), task_id, TaskDecision::Reject { reason })?;
+    let decision = serde_json::json!({ "task_id": task_id, "plan_id": pending.plan_id, "approval": status });
+    state.events.publish(Topic::Task, decision.clone());
+    Ok(Json(decision))
 }
 
-async fn update_user_preferences(Json(_prefs): Json<UserPreferences>) -> ApiResult<Json<serde_json::Value>> {
+async fn get_current_user() -> ApiResult<Json<serde_json::Value>> {
     Ok(Json(serde_json::json!({"status": "not_implemented"})))
 }
 
-async fn get_kernel_status() -> ApiResult<Json<serde_json::Value>> {
-    Ok(Json(serde_json::json!({"status": "operational"})))
+/// Kernel status, with which replica holds the lock of each singleton background job
+async fn get_kernel_status(State(state): State<AppState>) -> ApiResult<Json<serde_json::Value>> {
+    Ok(Json(serde_json::json!({
+        "status": "operational",
+        "replica_id": state.job_locks.holder(),
+        "singleton_jobs": state.job_locks.status().await?,
+    })))
 }
 
 async fn get_kernel_metrics() -> ApiResult<Json<serde_json::Value>> {
@@ -421,15 +993,3 @@ async fn vector_search(Json(_query): Json<serde_json::Value>) -> ApiResult<Json<
 async fn embed_text(Json(_text): Json<serde_json::Value>) -> ApiResult<Json<serde_json::Value>> {
     Ok(Json(serde_json::json!({"embedding": []})))
 }
-
-async fn list_mcp_servers() -> ApiResult<Json<serde_json::Value>> {
-    Ok(Json(serde_json::json!({"servers": []})))
-}
-
-async fn list_mcp_tools(Path(_server_id): Path<Uuid>) -> ApiResult<Json<serde_json::Value>> {
-    Ok(Json(serde_json::json!({"tools": []})))
-}
-
-async fn execute_mcp_tool(Path(_tool_id): Path<Uuid>) -> ApiResult<Json<serde_json::Value>> {
-    Ok(Json(serde_json::json!({"result": "not_implemented"})))
-}
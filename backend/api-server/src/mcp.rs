@@ -0,0 +1,177 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Extension, FromRef, Path, State},
+    response::Json,
+    routing::{get, post},
+    Router,
+};
+use jarvis_core::AuditActor;
+use serde::Serialize;
+use talkpp_mcp_hub::{McpHub, McpServerConfig, McpServerStatus, McpTool};
+use tracing::{info, instrument};
+
+use crate::error::{ApiError, ApiResult};
+use crate::UserSession;
+
+/// MCP hub routes, mounted under `/api/v1/mcp`
+pub fn routes<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+    Arc<McpHub>: FromRef<S>,
+{
+    Router::new()
+        .route("/servers", get(list_servers).post(add_server))
+        .route("/servers/:server/tools", get(list_server_tools))
+        .route("/tools", get(list_tools))
+        .route("/tools/:tool_name/execute", post(execute_tool))
+}
+
+#[derive(Debug, Serialize)]
+pub struct ServersResponse {
+    pub servers: Vec<McpServerStatus>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ToolsResponse {
+    pub tools: Vec<McpTool>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExecuteToolResponse {
+    pub result: serde_json::Value,
+}
+
+#[instrument(skip(hub))]
+async fn list_servers(State(hub): State<Arc<McpHub>>) -> ApiResult<Json<ServersResponse>> {
+    hub.connect_enabled().await;
+    let mut servers = Vec::new();
+    for server in hub.servers().await {
+        servers.push(hub.get_server_status(server.id).await?);
+    }
+    Ok(Json(ServersResponse { servers }))
+}
+
+/// Register a server; it is kept even when the first connection attempt fails, which
+/// shows up as `last_error` in the returned status
+#[instrument(skip(hub, config), fields(name = %config.name))]
+async fn add_server(
+    State(hub): State<Arc<McpHub>>,
+    Json(config): Json<McpServerConfig>,
+) -> ApiResult<Json<McpServerStatus>> {
+    let server_id = config.id;
+    hub.add_server(config).await?;
+    let _ = hub.connect_server(server_id).await;
+    info!("Registered MCP server {}", server_id);
+    Ok(Json(hub.get_server_status(server_id).await?))
+}
+
+#[instrument(skip(hub))]
+async fn list_server_tools(
+    State(hub): State<Arc<McpHub>>,
+    Path(server): Path<String>,
+) -> ApiResult<Json<ToolsResponse>> {
+    let config = hub.find_server(&server).await
+        .ok_or_else(|| ApiError::NotFound(format!("Server not found: {}", server)))?;
+    Ok(Json(ToolsResponse { tools: hub.server_tools(config.id).await }))
+}
+
+#[instrument(skip(hub))]
+async fn list_tools(State(hub): State<Arc<McpHub>>) -> ApiResult<Json<ToolsResponse>> {
+    let mut tools = hub.list_tools().await?;
+    tools.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(Json(ToolsResponse { tools }))
+}
+
+#[instrument(skip(hub, session, params))]
+async fn execute_tool(
+    State(hub): State<Arc<McpHub>>,
+    session: Option<Extension<UserSession>>,
+    Path(tool_name): Path<String>,
+    Json(params): Json<serde_json::Value>,
+) -> ApiResult<Json<ExecuteToolResponse>> {
+    let actor = session.map_or_else(AuditActor::system, |Extension(session)| session.audit_actor());
+    let result = hub.call_tool_as(&actor, &tool_name, params).await?;
+    Ok(Json(ExecuteToolResponse { result }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use serde_json::json;
+    use talkpp_mcp_hub::LocalMcpServer;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_register_server_and_execute_tool() {
+        let server = LocalMcpServer::new("echo").with_tool(
+            "echo",
+            "Return the message",
+            json!({"type": "object", "properties": {"message": {"type": "string"}}, "required": ["message"]}),
+            |params| async move { Ok(params["message"].clone()) },
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(server.serve(listener));
+        let app = routes().with_state(Arc::new(McpHub::new()));
+
+        let request = |method: &str, uri: &str, body: serde_json::Value| {
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+        let config = json!({
+            "id": uuid::Uuid::new_v4(),
+            "name": "echo",
+            "description": "",
+            "server_type": "Remote",
+            "connection": {"Http": {"url": url, "headers": {}}},
+            "capabilities": ["Tools"],
+            "enabled": true,
+            "created_at": chrono::Utc::now()
+        });
+        let added = app.clone().oneshot(request("POST", "/servers", config.clone())).await.unwrap();
+        assert_eq!(added.status(), StatusCode::OK);
+        let mut same_name = config;
+        same_name["id"] = json!(uuid::Uuid::new_v4());
+        let duplicate = app.clone().oneshot(request("POST", "/servers", same_name)).await.unwrap();
+        assert_eq!(duplicate.status(), StatusCode::CONFLICT);
+
+        let executed = app.clone()
+            .oneshot(request("POST", "/tools/echo/execute", json!({"message": "hi"})))
+            .await
+            .unwrap();
+        assert_eq!(executed.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(executed.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&body).unwrap(), json!({"result": "hi"}));
+
+        let invalid = app.clone().oneshot(request("POST", "/tools/echo/execute", json!({}))).await.unwrap();
+        assert_eq!(invalid.status(), StatusCode::BAD_REQUEST);
+
+        // Configs go through the hub's validation, which lists every bad field
+        let bad_config = json!({
+            "name": " ",
+            "server_type": "Remote",
+            "connection": {"Http": {"url": "localhost", "headers": {}}},
+            "capabilities": [],
+            "enabled": true
+        });
+        let rejected = app.clone().oneshot(request("POST", "/servers", bad_config)).await.unwrap();
+        assert_eq!(rejected.status(), StatusCode::BAD_REQUEST);
+        let body = axum::body::to_bytes(rejected.into_body(), usize::MAX).await.unwrap();
+        let fields: Vec<_> = serde_json::from_slice::<serde_json::Value>(&body).unwrap()["violations"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|violation| violation["field"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(fields, ["name", "connection.url", "capabilities"]);
+        let missing = app.oneshot(request("GET", "/servers/nope/tools", json!(null))).await.unwrap();
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,542 @@
+use std::sync::Arc;
+
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts, Path, Query, State},
+    http::{request::Parts, StatusCode},
+    response::{IntoResponse, Json, Response},
+    routing::{get, post},
+    Router,
+};
+use chrono::{DateTime, Utc};
+use jarvis_core::GraphFormat;
+use memory_continuum::{
+    AccessPattern, AssociationGraph, GraphFilter, MemoryContinuum, MemoryItem, MemoryMetadata, MemoryStatistics, MemoryType,
+};
+use serde::{Deserialize, Serialize};
+use talkpp_tenancy::TenantContext;
+use tracing::{info, instrument};
+use uuid::Uuid;
+
+use crate::error::{ApiError, ApiResult};
+use crate::plans::{graph_response, GraphQuery};
+use crate::UserSession;
+
+/// Prefix of the tag recording which user a memory belongs to
+pub const USER_TAG_PREFIX: &str = "user:";
+
+const DEFAULT_SEARCH_LIMIT: usize = 10;
+const MAX_SEARCH_LIMIT: usize = 100;
+
+/// Memory continuum routes, mounted under `/api/v1/memory`
+pub fn routes<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+    Arc<MemoryContinuum>: FromRef<S>,
+{
+    Router::new()
+        .route("/memories", post(store_memory))
+        .route("/memories/search", get(search_memories))
+        .route("/memories/:memory_id", get(get_memory).delete(delete_memory))
+        .route("/memories/:memory_id/associations", get(get_associations))
+        .route("/graph", get(get_graph))
+        .route("/statistics", get(get_statistics))
+}
+
+/// The tenant and user of the session the auth middleware attached to the request
+pub struct CurrentUser(pub TenantContext);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for CurrentUser {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<UserSession>()
+            .map(|session| CurrentUser(session.tenant()))
+            .ok_or_else(|| ApiError::Unauthorized("No active session".to_string()))
+    }
+}
+
+/// Store memory request
+#[derive(Debug, Deserialize)]
+pub struct StoreMemoryRequest {
+    pub content: serde_json::Value,
+    #[serde(default = "default_memory_type")]
+    pub memory_type: MemoryType,
+    #[serde(default)]
+    pub metadata: MemoryMetadataInput,
+}
+
+fn default_memory_type() -> MemoryType {
+    MemoryType::ShortTerm
+}
+
+/// Caller-supplied memory metadata; unset fields take defaults
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct MemoryMetadataInput {
+    pub importance: Option<f64>,
+    pub confidence: Option<f64>,
+    pub source: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub associations: Vec<Uuid>,
+    /// Content fields to keep as given when the server masks personal data
+    #[serde(default)]
+    pub pii_allowlist: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StoreMemoryResponse {
+    pub id: Uuid,
+}
+
+/// A memory as returned to its owner
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemoryResponse {
+    pub id: Uuid,
+    pub content: serde_json::Value,
+    pub memory_type: MemoryType,
+    pub importance: f64,
+    pub confidence: f64,
+    pub source: String,
+    /// Tags as stored, without the owner tag
+    pub tags: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_accessed: DateTime<Utc>,
+}
+
+impl From<MemoryItem> for MemoryResponse {
+    fn from(item: MemoryItem) -> Self {
+        Self {
+            id: item.id,
+            content: item.content,
+            memory_type: item.memory_type,
+            importance: item.metadata.importance,
+            confidence: item.metadata.confidence,
+            source: item.metadata.source,
+            tags: item.metadata.tags.into_iter().filter(|tag| !tag.starts_with(USER_TAG_PREFIX)).collect(),
+            created_at: item.created_at,
+            last_accessed: item.last_accessed,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchMemoriesResponse {
+    pub memories: Vec<MemoryResponse>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AssociationsResponse {
+    pub memory_id: Uuid,
+    pub associations: Vec<Uuid>,
+}
+
+/// Memory continuum operations on behalf of one user. Memories are stored as the user's
+/// tenant's and carry a `user:<id>` tag; those of other tenants or users are reported as
+/// not found.
+pub struct UserMemories<'a> {
+    memory: &'a MemoryContinuum,
+    tenant: TenantContext,
+    user_tag: String,
+}
+
+impl<'a> UserMemories<'a> {
+    pub fn new(memory: &'a MemoryContinuum, tenant: TenantContext) -> Self {
+        Self {
+            memory,
+            user_tag: format!("{}{}", USER_TAG_PREFIX, tenant.user_id),
+            tenant,
+        }
+    }
+
+    pub async fn store(
+        &self,
+        content: serde_json::Value,
+        memory_type: MemoryType,
+        input: MemoryMetadataInput,
+    ) -> ApiResult<Uuid> {
+        let importance = input.importance.unwrap_or(0.5);
+        let confidence = input.confidence.unwrap_or(1.0);
+        if !(0.0..=1.0).contains(&importance) || !(0.0..=1.0).contains(&confidence) {
+            return Err(ApiError::BadRequest("importance and confidence must be between 0 and 1".to_string()));
+        }
+        if input.tags.iter().any(|tag| tag.starts_with(USER_TAG_PREFIX)) {
+            return Err(ApiError::BadRequest(format!("Tags starting with '{}' are reserved", USER_TAG_PREFIX)));
+        }
+        for associated in &input.associations {
+            self.get(*associated)?;
+        }
+
+        let mut tags = input.tags;
+        tags.push(self.user_tag.clone());
+        let metadata = MemoryMetadata {
+            importance,
+            confidence,
+            source: input.source.unwrap_or_else(|| "api".to_string()),
+            tags,
+            associations: input.associations,
+            consolidation_level: 0,
+            access_pattern: AccessPattern {
+                frequency: 1.0,
+                recency: 1.0,
+                context_relevance: 1.0,
+                emotional_valence: 0.0,
+            },
+            pii_allowlist: input.pii_allowlist,
+            redactions: None,
+        };
+
+        self.memory.for_tenant(&self.tenant).store_memory(content, memory_type, metadata).await
+            .map_err(|e| ApiError::BadRequest(format!("Failed to store memory: {}", e)))
+    }
+
+    pub async fn search(&self, query: &str, memory_types: Vec<MemoryType>, limit: usize) -> ApiResult<Vec<MemoryItem>> {
+        Ok(self.memory
+            .for_tenant(&self.tenant)
+            .retrieve_memories_matching(query, memory_types, limit, |item| self.owns(item))
+            .await?)
+    }
+
+    pub fn get(&self, memory_id: Uuid) -> ApiResult<MemoryItem> {
+        self.memory.for_tenant(&self.tenant).get_memory(memory_id)
+            .filter(|item| self.owns(item))
+            .ok_or_else(|| ApiError::NotFound(format!("Memory {}", memory_id)))
+    }
+
+    pub async fn forget(&self, memory_id: Uuid) -> ApiResult<()> {
+        self.get(memory_id)?;
+        self.memory.for_tenant(&self.tenant).forget_memory(memory_id).await?;
+        Ok(())
+    }
+
+    /// Associated memories, limited to those the user owns
+    pub async fn associations(&self, memory_id: Uuid) -> ApiResult<Vec<Uuid>> {
+        self.get(memory_id)?;
+        let associations = self.memory.for_tenant(&self.tenant).get_associations(memory_id).await?;
+        Ok(associations.into_iter().filter(|id| self.get(*id).is_ok()).collect())
+    }
+
+    /// The association graph of the user's memories, without their owner tags
+    pub async fn graph(&self, filter: &GraphFilter) -> ApiResult<AssociationGraph> {
+        if filter.tags.iter().any(|tag| tag.starts_with(USER_TAG_PREFIX)) {
+            return Err(ApiError::BadRequest(format!("Tags starting with '{}' are reserved", USER_TAG_PREFIX)));
+        }
+        if let Some(around) = filter.around {
+            self.get(around)?;
+        }
+        let mut graph = self.memory.for_tenant(&self.tenant)
+            .association_graph_matching(filter, |item| self.owns(item))
+            .await;
+        for node in &mut graph.nodes {
+            node.tags.retain(|tag| !tag.starts_with(USER_TAG_PREFIX));
+        }
+        Ok(graph)
+    }
+
+    fn owns(&self, item: &MemoryItem) -> bool {
+        item.metadata.tags.contains(&self.user_tag)
+    }
+}
+
+/// Store a memory for the current user
+#[instrument(skip(memory, request))]
+async fn store_memory(
+    State(memory): State<Arc<MemoryContinuum>>,
+    CurrentUser(tenant): CurrentUser,
+    Json(request): Json<StoreMemoryRequest>,
+) -> ApiResult<impl IntoResponse> {
+    let id = UserMemories::new(&memory, tenant.clone())
+        .store(request.content, request.memory_type, request.metadata)
+        .await?;
+    info!("Stored memory {} for {}", id, tenant);
+    Ok((StatusCode::CREATED, Json(StoreMemoryResponse { id })))
+}
+
+/// Search the current user's memories. Takes `query`, `limit` and any number of `types`
+/// (or `types[]`) parameters, each a memory type or a comma-separated list of them;
+/// short- and long-term memories are searched by default.
+async fn search_memories(
+    State(memory): State<Arc<MemoryContinuum>>,
+    CurrentUser(tenant): CurrentUser,
+    Query(params): Query<Vec<(String, String)>>,
+) -> ApiResult<Json<SearchMemoriesResponse>> {
+    let mut query = String::new();
+    let mut memory_types = Vec::new();
+    let mut limit = DEFAULT_SEARCH_LIMIT;
+    for (key, value) in params {
+        match key.as_str() {
+            "query" => query = value,
+            "limit" => {
+                limit = value.parse()
+                    .map_err(|_| ApiError::BadRequest(format!("Invalid limit: {}", value)))?;
+            }
+            "types" | "types[]" => {
+                for name in value.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+                    memory_types.push(parse_memory_type(name)?);
+                }
+            }
+            _ => {}
+        }
+    }
+    if memory_types.is_empty() {
+        memory_types = vec![MemoryType::ShortTerm, MemoryType::LongTerm];
+    }
+
+    let memories = UserMemories::new(&memory, tenant)
+        .search(&query, memory_types, limit.min(MAX_SEARCH_LIMIT))
+        .await?;
+    Ok(Json(SearchMemoriesResponse {
+        memories: memories.into_iter().map(MemoryResponse::from).collect(),
+    }))
+}
+
+async fn get_memory(
+    State(memory): State<Arc<MemoryContinuum>>,
+    CurrentUser(tenant): CurrentUser,
+    Path(memory_id): Path<Uuid>,
+) -> ApiResult<Json<MemoryResponse>> {
+    let item = UserMemories::new(&memory, tenant).get(memory_id)?;
+    Ok(Json(item.into()))
+}
+
+async fn delete_memory(
+    State(memory): State<Arc<MemoryContinuum>>,
+    CurrentUser(tenant): CurrentUser,
+    Path(memory_id): Path<Uuid>,
+) -> ApiResult<StatusCode> {
+    UserMemories::new(&memory, tenant).forget(memory_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_associations(
+    State(memory): State<Arc<MemoryContinuum>>,
+    CurrentUser(tenant): CurrentUser,
+    Path(memory_id): Path<Uuid>,
+) -> ApiResult<Json<AssociationsResponse>> {
+    let associations = UserMemories::new(&memory, tenant).associations(memory_id).await?;
+    Ok(Json(AssociationsResponse { memory_id, associations }))
+}
+
+/// The current user's memory associations, as JSON for the web UI or as `format=dot`.
+/// Takes any number of `tag` parameters, keeping memories with one of them, and
+/// `min_strength`, `around` with `depth`, and `max_nodes`; see [`GraphFilter`].
+async fn get_graph(
+    State(memory): State<Arc<MemoryContinuum>>,
+    CurrentUser(tenant): CurrentUser,
+    Query(params): Query<Vec<(String, String)>>,
+) -> ApiResult<Response> {
+    fn parse<T: std::str::FromStr>(key: &str, value: &str) -> ApiResult<T> {
+        value.parse().map_err(|_| ApiError::BadRequest(format!("Invalid {}: {}", key, value)))
+    }
+
+    let mut filter = GraphFilter::default();
+    let mut query = GraphQuery::default();
+    for (key, value) in params {
+        match key.as_str() {
+            "format" => query.format = value.parse::<GraphFormat>().map_err(ApiError::BadRequest)?,
+            "tag" | "tag[]" => filter.tags.push(value),
+            "min_strength" => filter.min_strength = parse(&key, &value)?,
+            "around" => filter.around = Some(parse(&key, &value)?),
+            "depth" => filter.depth = parse(&key, &value)?,
+            "max_nodes" => query.max_nodes = Some(parse(&key, &value)?),
+            _ => {}
+        }
+    }
+    filter.max_nodes = query.max_nodes();
+
+    let graph = UserMemories::new(&memory, tenant).graph(&filter).await?;
+    Ok(graph_response(query.format, graph.render(query.format)))
+}
+
+/// Statistics for the whole continuum; they hold counts only, never memory content
+async fn get_statistics(
+    State(memory): State<Arc<MemoryContinuum>>,
+    CurrentUser(_tenant): CurrentUser,
+) -> ApiResult<Json<MemoryStatistics>> {
+    Ok(Json(memory.get_statistics().await?))
+}
+
+fn parse_memory_type(name: &str) -> ApiResult<MemoryType> {
+    serde_json::from_value(serde_json::Value::String(name.to_string()))
+        .map_err(|_| ApiError::BadRequest(format!("Unknown memory type: {}", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Method, Request};
+    use memory_continuum::MemoryConfig;
+    use tower::ServiceExt;
+
+    async fn app() -> Router {
+        let memory = Arc::new(MemoryContinuum::new(MemoryConfig::default()).await.unwrap());
+        routes().with_state(memory)
+    }
+
+    fn session(user_id: Uuid) -> UserSession {
+        UserSession {
+            user_id,
+            session_id: Uuid::new_v4(),
+            created_at: Utc::now(),
+            last_activity: Utc::now(),
+            permissions: Vec::new(),
+            tenant_id: talkpp_tenancy::default_tenant_id(),
+        }
+    }
+
+    async fn call(
+        app: &Router,
+        user_id: Option<Uuid>,
+        method: Method,
+        uri: &str,
+        body: Option<serde_json::Value>,
+    ) -> (StatusCode, serde_json::Value) {
+        let mut request = Request::builder().method(method).uri(uri);
+        if body.is_some() {
+            request = request.header("content-type", "application/json");
+        }
+        let mut request = request
+            .body(body.map(|b| Body::from(b.to_string())).unwrap_or_else(Body::empty))
+            .unwrap();
+        if let Some(user_id) = user_id {
+            request.extensions_mut().insert(session(user_id));
+        }
+
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let json = serde_json::from_slice(&bytes).unwrap_or(serde_json::Value::Null);
+        (status, json)
+    }
+
+    async fn store(app: &Router, user_id: Uuid, body: serde_json::Value) -> Uuid {
+        let (status, json) = call(app, Some(user_id), Method::POST, "/memories", Some(body)).await;
+        assert_eq!(status, StatusCode::CREATED, "{}", json);
+        serde_json::from_value(json["id"].clone()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_memories_are_isolated_per_user() {
+        let app = app().await;
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let note = store(&app, alice, serde_json::json!({
+            "content": "alice prefers morning standups",
+            "memory_type": "LongTerm",
+            "metadata": {"tags": ["preference"]},
+        })).await;
+        let related = store(&app, alice, serde_json::json!({
+            "content": "standup moved to 9am",
+            "metadata": {"associations": [note]},
+        })).await;
+        store(&app, bob, serde_json::json!({"content": "bob skips standups"})).await;
+
+        let (status, json) = call(&app, Some(alice), Method::GET, "/memories/search?query=standups&types[]=ShortTerm&types[]=LongTerm", None).await;
+        assert_eq!(status, StatusCode::OK);
+        let found: Vec<MemoryResponse> = serde_json::from_value(json["memories"].clone()).unwrap();
+        assert_eq!(found.iter().map(|m| m.id).collect::<Vec<_>>(), vec![note]);
+        assert_eq!(found[0].tags, vec!["preference".to_string()]);
+
+        let (_, json) = call(&app, Some(bob), Method::GET, "/memories/search?query=standups&types=ShortTerm,LongTerm", None).await;
+        assert_eq!(json["memories"].as_array().unwrap().len(), 1);
+        assert_eq!(json["memories"][0]["content"], "bob skips standups");
+
+        let (status, json) = call(&app, Some(alice), Method::GET, &format!("/memories/{}/associations", note), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json["associations"], serde_json::json!([related]));
+
+        for uri in [format!("/memories/{}", note), format!("/memories/{}/associations", note)] {
+            let (status, _) = call(&app, Some(bob), Method::GET, &uri, None).await;
+            assert_eq!(status, StatusCode::NOT_FOUND);
+        }
+        let (status, _) = call(&app, Some(bob), Method::DELETE, &format!("/memories/{}", note), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, json) = call(&app, Some(alice), Method::GET, &format!("/memories/{}", note), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json["memory_type"], "LongTerm");
+
+        let (status, _) = call(&app, Some(alice), Method::DELETE, &format!("/memories/{}", note), None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) = call(&app, Some(alice), Method::GET, &format!("/memories/{}", note), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, json) = call(&app, Some(bob), Method::GET, "/statistics", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json["total_memories"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_graphs_hold_only_the_users_memories() {
+        let app = app().await;
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+        let note = store(&app, alice, serde_json::json!({"content": "standups at nine", "metadata": {"tags": ["team"]}})).await;
+        let moved = store(&app, alice, serde_json::json!({"content": "standup moved", "metadata": {"tags": ["team"], "associations": [note]}})).await;
+        store(&app, alice, serde_json::json!({"content": "dentist on friday", "metadata": {"tags": ["home"]}})).await;
+        let theirs = store(&app, bob, serde_json::json!({"content": "bob skips standups"})).await;
+
+        let (status, json) = call(&app, Some(alice), Method::GET, "/graph", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json["nodes"].as_array().unwrap().len(), 3);
+        assert_eq!(json["links"].as_array().unwrap().len(), 1);
+        assert!(json["nodes"].as_array().unwrap().iter().all(|node| !node["tags"].to_string().contains(USER_TAG_PREFIX)));
+
+        let (_, json) = call(&app, Some(alice), Method::GET, "/graph?tag=team&min_strength=0.5", None).await;
+        let mut ids: Vec<Uuid> = serde_json::from_value(json["nodes"].as_array().unwrap().iter().map(|n| n["memory_id"].clone()).collect()).unwrap();
+        ids.sort();
+        let mut expected = vec![note, moved];
+        expected.sort();
+        assert_eq!(ids, expected);
+
+        let (_, json) = call(&app, Some(alice), Method::GET, "/graph?max_nodes=1", None).await;
+        assert_eq!(json["truncated"], serde_json::json!({"total_nodes": 3, "shown_nodes": 1}));
+
+        let (status, _) = call(&app, Some(alice), Method::GET, &format!("/graph?around={}", theirs), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (_, json) = call(&app, Some(bob), Method::GET, "/graph", None).await;
+        assert_eq!(json["nodes"][0]["memory_id"], theirs.to_string());
+        let (status, _) = call(&app, Some(alice), Method::GET, "/graph?format=svg", None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_cross_user_references_and_missing_sessions() {
+        let app = app().await;
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+        let private = store(&app, alice, serde_json::json!({"content": "private"})).await;
+
+        // Neither associating with nor tagging into another user's memories is allowed
+        let (status, _) = call(&app, Some(bob), Method::POST, "/memories", Some(serde_json::json!({
+            "content": "link",
+            "metadata": {"associations": [private]},
+        }))).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = call(&app, Some(bob), Method::POST, "/memories", Some(serde_json::json!({
+            "content": "planted",
+            "metadata": {"tags": [format!("user:{}", alice)]},
+        }))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, _) = call(&app, Some(alice), Method::GET, "/memories/search?types=Dreams", None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = call(&app, None, Method::GET, "/memories/search?query=private", None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_same_user_id_in_another_tenant_sees_nothing() {
+        let memory = MemoryContinuum::new(MemoryConfig::default()).await.unwrap();
+        let user_id = Uuid::new_v4().to_string();
+        let ours = UserMemories::new(&memory, TenantContext::new("acme", user_id.clone()).unwrap());
+        let theirs = UserMemories::new(&memory, TenantContext::new("globex", user_id).unwrap());
+
+        let note = ours.store(serde_json::json!("quarterly numbers"), MemoryType::ShortTerm, MemoryMetadataInput::default()).await.unwrap();
+        assert!(matches!(theirs.get(note), Err(ApiError::NotFound(_))));
+        assert!(theirs.search("quarterly", vec![MemoryType::ShortTerm], 10).await.unwrap().is_empty());
+        assert!(matches!(theirs.forget(note).await, Err(ApiError::NotFound(_))));
+        assert_eq!(ours.get(note).unwrap().id, note);
+    }
+}
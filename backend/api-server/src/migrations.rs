@@ -0,0 +1,36 @@
+//! Upgrades of the JSON documents this server keeps outside Postgres; see
+//! [`talkpp_errors::versioning`]. Postgres tables are migrated by the SQL files in
+//! `migrations/` instead.
+//!
+//! Replicas of two builds share these documents during a rolling deploy, so a step is
+//! only added once every replica reads the version it produces. The frozen payloads in
+//! `tests/fixtures/schemas` must keep loading.
+
+use talkpp_errors::versioning::Migrations;
+
+/// Job records in a [`crate::jobs::RedisJobStore`]
+pub const JOB: Migrations = Migrations::new("generation job", &[]);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jobs::{JobInfo, JobStatus};
+    use std::path::Path;
+    use talkpp_errors::versioning::{check_round_trip, load_versioned};
+
+    fn fixture(name: &str) -> serde_json::Value {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/schemas").join(name);
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_frozen_payloads_still_load() {
+        check_round_trip::<JobInfo>(fixture("job.v1.json"), &JOB).unwrap();
+
+        let job: JobInfo = load_versioned(fixture("job.v1.json"), &JOB).unwrap();
+        assert_eq!((job.schema_version, job.status), (JOB.current(), JobStatus::Cancelled));
+        let mut newer = fixture("job.v1.json");
+        newer["schema_version"] = (JOB.current() + 1).into();
+        assert!(load_versioned::<JobInfo>(newer, &JOB).is_err());
+    }
+}
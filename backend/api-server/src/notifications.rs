@@ -0,0 +1,149 @@
+//! Emailing plan owners when their plans finish or wait for approval
+//!
+//! Events go to a `NotificationDispatcher` from the external services, which looks the
+//! owner's recipients up in their notification preferences:
+//!
+//! ```json
+//! { "email": { "to": ["ada@example.com"], "events": ["plan_failed", "approval_requested"] } }
+//! ```
+//!
+//! Without `events`, the addresses in `to` are emailed about every kind of event.
+
+use anyhow::Result;
+use axum::async_trait;
+use jarvis_core::{Intent, IntentExecutionPlan, PlanOutcome};
+use serde::Deserialize;
+use talkpp_external_services::notifications::{
+    NotificationDispatcher, NotificationEvent, NotificationKind, PlanSummary, RecipientDirectory,
+};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::preferences::Preferences;
+use crate::UserPreferences;
+
+/// The `email` entry of a user's notification preferences
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EmailPreferences {
+    #[serde(default)]
+    to: Vec<String>,
+    events: Option<Vec<NotificationKind>>,
+}
+
+impl EmailPreferences {
+    /// The `email` entry of `preferences`, `None` when there is none
+    fn of(preferences: &UserPreferences) -> Result<Option<Self>, String> {
+        let Some(email) = preferences.notification_preferences.as_ref().and_then(|n| n.get("email")) else {
+            return Ok(None);
+        };
+        let email: Self = serde_json::from_value(email.clone())
+            .map_err(|e| format!("Invalid email notification preferences: {}", e))?;
+        if let Some(bad) = email.to.iter().find(|address| !address.contains('@') || address.contains(['\r', '\n'])) {
+            return Err(format!("Invalid email address {:?} in notification preferences", bad));
+        }
+        Ok(Some(email))
+    }
+
+    fn wants(&self, kind: NotificationKind) -> bool {
+        self.events.as_ref().is_none_or(|events| events.contains(&kind))
+    }
+}
+
+/// Check the `email` entry of `preferences`, if any, before they are stored
+pub fn validate(preferences: &UserPreferences) -> Result<(), String> {
+    EmailPreferences::of(preferences).map(|_| ())
+}
+
+/// Recipients from their owner's notification preferences
+pub struct PreferenceRecipients(pub Preferences);
+
+#[async_trait]
+impl RecipientDirectory for PreferenceRecipients {
+    async fn recipients(&self, user_id: Uuid, kind: NotificationKind) -> Result<Vec<String>> {
+        let preferences = self.0.for_user(user_id).await?;
+        Ok(match EmailPreferences::of(&preferences).map_err(anyhow::Error::msg)? {
+            Some(email) if email.wants(kind) => email.to,
+            _ => Vec::new(),
+        })
+    }
+}
+
+/// Publishes notification events to a background dispatcher; publishing does nothing
+/// when notifications are off or the plan has no owner to notify
+#[derive(Clone, Default)]
+pub struct Notifier {
+    events: Option<mpsc::UnboundedSender<NotificationEvent>>,
+}
+
+impl Notifier {
+    /// A notifier that sends nothing
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// Dispatch published events with `dispatcher` in the background
+    pub fn spawn(dispatcher: NotificationDispatcher) -> Self {
+        let (events, received) = mpsc::unbounded_channel();
+        tokio::spawn(dispatcher.run(received));
+        Self { events: Some(events) }
+    }
+
+    pub fn publish(&self, event: NotificationEvent) {
+        if let Some(events) = &self.events {
+            // The dispatcher only stops once every notifier is dropped
+            let _ = events.send(event);
+        }
+    }
+
+    /// Tell `owner` that the plan for `intent` completed or failed
+    pub fn plan_finished(&self, owner: Option<Uuid>, intent: &Intent, plan: &IntentExecutionPlan, outcome: &PlanOutcome) {
+        if let Some(event) = owner.and_then(|owner| NotificationEvent::plan_finished(owner, &intent.raw_text, plan, outcome)) {
+            self.publish(event);
+        }
+    }
+
+    /// Tell `owner` that the plan for `intent` waits for their approval
+    pub fn approval_requested(&self, owner: Option<Uuid>, intent: &Intent, plan: &IntentExecutionPlan) {
+        if let Some(owner) = owner {
+            self.publish(NotificationEvent::ApprovalRequested { owner, plan: PlanSummary::new(&intent.raw_text, plan) });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preferences::MemoryPreferencesStore;
+    use std::sync::Arc;
+
+    fn with_email(email: serde_json::Value) -> UserPreferences {
+        UserPreferences {
+            notification_preferences: Some(serde_json::json!({ "email": email })),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recipients_follow_the_owners_email_preferences() {
+        let store = Arc::new(MemoryPreferencesStore::new());
+        let preferences = Preferences::new(store, UserPreferences::default());
+        let (owner, other) = (Uuid::new_v4(), Uuid::new_v4());
+        preferences.update(owner, with_email(serde_json::json!({ "to": ["ada@example.com"], "events": ["plan_failed"] })))
+            .await
+            .unwrap();
+        let recipients = PreferenceRecipients(preferences.clone());
+
+        assert_eq!(recipients.recipients(owner, NotificationKind::PlanFailed).await.unwrap(), vec!["ada@example.com"]);
+        assert!(recipients.recipients(owner, NotificationKind::PlanCompleted).await.unwrap().is_empty());
+        assert!(recipients.recipients(other, NotificationKind::PlanFailed).await.unwrap().is_empty());
+
+        for invalid in [
+            serde_json::json!({ "to": ["not-an-address"] }),
+            serde_json::json!({ "to": ["ada@example.com\r\nBcc: all@example.com"] }),
+            serde_json::json!({ "to": ["ada@example.com"], "events": ["plan_exploded"] }),
+        ] {
+            assert!(preferences.update(owner, with_email(invalid)).await.is_err());
+        }
+    }
+}
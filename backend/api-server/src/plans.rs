@@ -0,0 +1,185 @@
+//! Plans this replica made recently, kept so their dependency graphs can be drawn
+//!
+//! Plans aren't stored in the database yet, so `GET /plans/:plan_id/graph` knows only
+//! the last [`RECENT_PLANS`] plans made through `POST /intents` on this replica and
+//! answers 404 for others, as it does for plans owned by another user.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    extract::{FromRef, Path, Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+    routing::get,
+    Extension, Router,
+};
+use jarvis_core::{GraphFormat, IntentExecutionPlan, DEFAULT_MAX_NODES};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::error::{ApiError, ApiResult};
+use crate::UserSession;
+
+/// Plans kept for their graphs, the oldest dropped first
+pub const RECENT_PLANS: usize = 1000;
+
+/// Most nodes a graph request may ask for
+pub const MAX_GRAPH_NODES: usize = 5000;
+
+/// Plan routes, merged into `/api/v1`
+pub fn routes<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+    RecentPlans: FromRef<S>,
+{
+    Router::new().route("/plans/:plan_id/graph", get(plan_graph))
+}
+
+/// The latest plans, each with the user it was made for
+#[derive(Clone)]
+pub struct RecentPlans {
+    inner: Arc<Mutex<Inner>>,
+    capacity: usize,
+}
+
+#[derive(Default)]
+struct Inner {
+    order: VecDeque<Uuid>,
+    plans: HashMap<Uuid, (Option<Uuid>, IntentExecutionPlan)>,
+}
+
+impl Default for RecentPlans {
+    fn default() -> Self {
+        Self::new(RECENT_PLANS)
+    }
+}
+
+impl RecentPlans {
+    pub fn new(capacity: usize) -> Self {
+        Self { inner: Arc::default(), capacity }
+    }
+
+    pub fn record(&self, owner: Option<Uuid>, plan: &IntentExecutionPlan) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.plans.insert(plan.id, (owner, plan.clone())).is_none() {
+            inner.order.push_back(plan.id);
+        }
+        while inner.order.len() > self.capacity {
+            if let Some(oldest) = inner.order.pop_front() {
+                inner.plans.remove(&oldest);
+            }
+        }
+    }
+
+    /// The plan, if `owner` may see it: plans made without a session are anyone's
+    pub fn get(&self, owner: Option<Uuid>, plan_id: Uuid) -> Option<IntentExecutionPlan> {
+        let inner = self.inner.lock().unwrap();
+        inner.plans.get(&plan_id)
+            .filter(|(plan_owner, _)| plan_owner.is_none() || *plan_owner == owner)
+            .map(|(_, plan)| plan.clone())
+    }
+}
+
+/// Query parameters of graph routes
+#[derive(Debug, Default, Deserialize)]
+pub struct GraphQuery {
+    #[serde(default)]
+    pub format: GraphFormat,
+    pub max_nodes: Option<usize>,
+}
+
+impl GraphQuery {
+    pub fn max_nodes(&self) -> usize {
+        self.max_nodes.unwrap_or(DEFAULT_MAX_NODES).min(MAX_GRAPH_NODES)
+    }
+}
+
+/// A graph rendered as `format`, with the matching content type
+pub fn graph_response(format: GraphFormat, body: String) -> Response {
+    let content_type = match format {
+        GraphFormat::Dot => "text/vnd.graphviz",
+        GraphFormat::Json => "application/json",
+    };
+    ([(header::CONTENT_TYPE, content_type)], body).into_response()
+}
+
+/// The plan's tasks and dependencies, as JSON for the web UI or as `format=dot`
+async fn plan_graph(
+    State(plans): State<RecentPlans>,
+    session: Option<Extension<UserSession>>,
+    Path(plan_id): Path<Uuid>,
+    Query(query): Query<GraphQuery>,
+) -> ApiResult<Response> {
+    let owner = session.map(|Extension(session)| session.user_id);
+    let plan = plans.get(owner, plan_id).ok_or_else(|| ApiError::NotFound(format!("Plan {}", plan_id)))?;
+    Ok(graph_response(query.format, plan.graph(query.max_nodes()).render(query.format)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use chrono::Utc;
+    use jarvis_core::CognitiveKernel;
+    use tower::ServiceExt;
+
+    fn session(user_id: Uuid) -> UserSession {
+        UserSession {
+            user_id,
+            session_id: Uuid::new_v4(),
+            created_at: Utc::now(),
+            last_activity: Utc::now(),
+            permissions: Vec::new(),
+            tenant_id: talkpp_tenancy::default_tenant_id(),
+        }
+    }
+
+    async fn get(app: &Router, user_id: Option<Uuid>, uri: &str) -> (StatusCode, String, String) {
+        let mut request = Request::get(uri).body(Body::empty()).unwrap();
+        if let Some(user_id) = user_id {
+            request.extensions_mut().insert(session(user_id));
+        }
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let content_type = response.headers().get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, content_type, String::from_utf8(bytes.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_owners_get_their_plans_graph_as_json_or_dot() {
+        let plan = CognitiveKernel::new().process_intent("summarise my unread email", None).await.unwrap();
+        let (owner, other) = (Uuid::new_v4(), Uuid::new_v4());
+        let plans = RecentPlans::new(2);
+        plans.record(Some(owner), &plan);
+        let app = routes().with_state(plans.clone());
+        let uri = format!("/plans/{}/graph", plan.id);
+
+        let (status, content_type, body) = get(&app, Some(owner), &uri).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(content_type, "application/json");
+        let graph: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(graph["nodes"].as_array().unwrap().len(), plan.tasks.len());
+        assert_eq!(graph["links"].as_array().unwrap().len(), plan.dependencies.len());
+
+        let (status, content_type, body) = get(&app, Some(owner), &format!("{}?format=dot&max_nodes=1", uri)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(content_type, "text/vnd.graphviz");
+        assert_eq!(jarvis_core::graph::check_dot(&body).unwrap().nodes, 1);
+
+        for user in [Some(other), None] {
+            assert_eq!(get(&app, user, &uri).await.0, StatusCode::NOT_FOUND);
+        }
+
+        // The oldest plans make way for new ones
+        for _ in 0..2 {
+            plans.record(None, &CognitiveKernel::new().process_intent("check the weather", None).await.unwrap());
+        }
+        assert_eq!(get(&app, Some(owner), &uri).await.0, StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,401 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use axum::{
+    async_trait,
+    extract::{FromRef, State},
+    response::Json,
+    routing::get,
+    Extension, Router,
+};
+use async_graphql::SimpleObject;
+use jarvis_core::audit::{AuditAction, AuditActor, AuditEvent, AuditOutcome, Auditor};
+use jarvis_core::{Intent, IntentExecutionPlan};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Row};
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::error::{ApiError, ApiResult};
+use crate::notifications;
+use crate::{UserPreferences, UserSession};
+
+/// Autonomy tiers plans are planned at, from most to least supervised
+const AUTONOMY_TIERS: std::ops::RangeInclusive<u8> = 1..=3;
+
+/// Risk levels `require_approval_for_risks` may name, matched without regard to case
+const RISK_LEVELS: [&str; 4] = ["low", "medium", "high", "critical"];
+
+/// Preferences routes, mounted under `/api/v1`
+pub fn routes<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+    Preferences: FromRef<S>,
+{
+    Router::new().route("/users/me/preferences", get(get_preferences).put(update_preferences))
+}
+
+#[async_trait]
+pub trait PreferencesStore: Send + Sync {
+    /// Preferences `user_id` has stored, or `None` if they never set any
+    async fn get(&self, user_id: Uuid) -> Result<Option<UserPreferences>>;
+
+    async fn put(&self, user_id: Uuid, preferences: &UserPreferences) -> Result<()>;
+}
+
+/// Preferences in the `user_preferences` table, one row per user
+pub struct PostgresPreferencesStore {
+    pool: PgPool,
+}
+
+impl PostgresPreferencesStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl PreferencesStore for PostgresPreferencesStore {
+    async fn get(&self, user_id: Uuid) -> Result<Option<UserPreferences>> {
+        let row = sqlx::query(
+            "SELECT max_autonomy_tier, require_approval_for_risks, preferred_execution_mode, notification_preferences \
+             FROM user_preferences WHERE user_id = $1",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|row| {
+            Ok(UserPreferences {
+                max_autonomy_tier: row.try_get::<Option<i16>, _>("max_autonomy_tier")?.map(|tier| tier as u8),
+                require_approval_for_risks: row.try_get("require_approval_for_risks")?,
+                preferred_execution_mode: row.try_get("preferred_execution_mode")?,
+                notification_preferences: row.try_get("notification_preferences")?,
+            })
+        })
+        .transpose()
+    }
+
+    async fn put(&self, user_id: Uuid, preferences: &UserPreferences) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO user_preferences \
+             (user_id, max_autonomy_tier, require_approval_for_risks, preferred_execution_mode, notification_preferences, updated_at) \
+             VALUES ($1, $2, $3, $4, $5, NOW()) \
+             ON CONFLICT (user_id) DO UPDATE SET \
+             max_autonomy_tier = EXCLUDED.max_autonomy_tier, \
+             require_approval_for_risks = EXCLUDED.require_approval_for_risks, \
+             preferred_execution_mode = EXCLUDED.preferred_execution_mode, \
+             notification_preferences = EXCLUDED.notification_preferences, \
+             updated_at = EXCLUDED.updated_at",
+        )
+        .bind(user_id)
+        .bind(preferences.max_autonomy_tier.map(i16::from))
+        .bind(&preferences.require_approval_for_risks)
+        .bind(&preferences.preferred_execution_mode)
+        .bind(&preferences.notification_preferences)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+/// Process-local preferences, for tests and single-instance deployments
+#[derive(Default)]
+pub struct MemoryPreferencesStore {
+    preferences: Mutex<HashMap<Uuid, UserPreferences>>,
+}
+
+impl MemoryPreferencesStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl PreferencesStore for MemoryPreferencesStore {
+    async fn get(&self, user_id: Uuid) -> Result<Option<UserPreferences>> {
+        Ok(self.preferences.lock().unwrap().get(&user_id).cloned())
+    }
+
+    async fn put(&self, user_id: Uuid, preferences: &UserPreferences) -> Result<()> {
+        self.preferences.lock().unwrap().insert(user_id, preferences.clone());
+        Ok(())
+    }
+}
+
+/// A change a user's preferences made to a plan
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, SimpleObject)]
+pub struct PolicyDecision {
+    /// The preference that applied: `max_autonomy_tier` or `require_approval_for_risks`
+    pub preference: String,
+    pub effect: String,
+}
+
+/// What enforcing preferences on a plan decided
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PolicyOutcome {
+    /// Whether the plan must be approved whatever its autonomy tier
+    pub requires_approval: bool,
+    pub decisions: Vec<PolicyDecision>,
+}
+
+/// Shared handle to stored preferences and the defaults for users without any
+#[derive(Clone)]
+pub struct Preferences {
+    store: Arc<dyn PreferencesStore>,
+    defaults: UserPreferences,
+    auditor: Option<Auditor>,
+}
+
+impl Preferences {
+    pub fn new(store: Arc<dyn PreferencesStore>, defaults: UserPreferences) -> Self {
+        Self { store, defaults, auditor: None }
+    }
+
+    /// Record every plan the preferences change in `auditor`
+    pub fn with_auditor(mut self, auditor: Auditor) -> Self {
+        self.auditor = Some(auditor);
+        self
+    }
+
+    /// Preferences `user_id` has stored, or the defaults
+    pub async fn for_user(&self, user_id: Uuid) -> Result<UserPreferences> {
+        Ok(self.store.get(user_id).await?.unwrap_or_else(|| self.defaults.clone()))
+    }
+
+    /// Store `preferences` for `user_id`, once they are known to be valid
+    pub async fn update(&self, user_id: Uuid, preferences: UserPreferences) -> ApiResult<UserPreferences> {
+        validate(&preferences)?;
+        self.store.put(user_id, &preferences).await?;
+        Ok(preferences)
+    }
+
+    /// Apply the preferences of the plan's owner to `plan`, tightened by any sent with the
+    /// request: the autonomy tier is clamped to the owner's maximum, and approval is required
+    /// for the risk levels they listed. Plans without an owner get the defaults.
+    pub async fn enforce(
+        &self,
+        owner: Option<(Uuid, AuditActor)>,
+        requested: Option<&UserPreferences>,
+        intent: &Intent,
+        plan: &mut IntentExecutionPlan,
+    ) -> Result<PolicyOutcome> {
+        let (preferences, actor) = match owner {
+            Some((user_id, actor)) => (self.for_user(user_id).await?, actor),
+            None => (self.defaults.clone(), AuditActor::system()),
+        };
+        let preferences = match requested {
+            Some(requested) => tightened(preferences, requested),
+            None => preferences,
+        };
+
+        let outcome = apply_policy(&preferences, intent, plan);
+        if let (Some(auditor), false) = (&self.auditor, outcome.decisions.is_empty()) {
+            auditor.record(AuditEvent::new(
+                actor,
+                AuditAction::PolicyDecision,
+                format!("plan:{}", plan.id),
+                &serde_json::json!({ "intent_id": intent.id, "decisions": outcome.decisions }),
+                AuditOutcome::Success,
+            ));
+        }
+        Ok(outcome)
+    }
+}
+
+/// Clamp `plan` to `preferences`, reporting each change made
+pub fn apply_policy(preferences: &UserPreferences, intent: &Intent, plan: &mut IntentExecutionPlan) -> PolicyOutcome {
+    let mut outcome = PolicyOutcome::default();
+
+    if let Some(max) = preferences.max_autonomy_tier {
+        if plan.autonomy_tier > max {
+            outcome.decisions.push(PolicyDecision {
+                preference: "max_autonomy_tier".to_string(),
+                effect: format!("autonomy tier {} clamped to {}", plan.autonomy_tier, max),
+            });
+            plan.autonomy_tier = max;
+        }
+    }
+
+    let risk = format!("{:?}", intent.risk_level);
+    if preferences.require_approval_for_risks.iter().any(|listed| listed.eq_ignore_ascii_case(&risk)) {
+        outcome.requires_approval = true;
+        outcome.decisions.push(PolicyDecision {
+            preference: "require_approval_for_risks".to_string(),
+            effect: format!("approval required for {} risk", risk.to_lowercase()),
+        });
+    }
+    outcome
+}
+
+/// `stored` with `requested` able to lower the tier and add risks, but not to loosen either
+fn tightened(mut stored: UserPreferences, requested: &UserPreferences) -> UserPreferences {
+    stored.max_autonomy_tier = match (stored.max_autonomy_tier, requested.max_autonomy_tier) {
+        (Some(stored), Some(requested)) => Some(stored.min(requested)),
+        (stored, requested) => stored.or(requested),
+    };
+    for risk in &requested.require_approval_for_risks {
+        if !stored.require_approval_for_risks.iter().any(|listed| listed.eq_ignore_ascii_case(risk)) {
+            stored.require_approval_for_risks.push(risk.clone());
+        }
+    }
+    stored
+}
+
+fn validate(preferences: &UserPreferences) -> ApiResult<()> {
+    if let Some(tier) = preferences.max_autonomy_tier {
+        if !AUTONOMY_TIERS.contains(&tier) {
+            return Err(ApiError::BadRequest(format!(
+                "max_autonomy_tier must be between {} and {}",
+                AUTONOMY_TIERS.start(),
+                AUTONOMY_TIERS.end()
+            )));
+        }
+    }
+    if let Some(risk) = preferences.require_approval_for_risks.iter()
+        .find(|risk| !RISK_LEVELS.iter().any(|level| level.eq_ignore_ascii_case(risk)))
+    {
+        return Err(ApiError::BadRequest(format!(
+            "Unknown risk level '{}'; expected one of {}",
+            risk,
+            RISK_LEVELS.join(", ")
+        )));
+    }
+    notifications::validate(preferences).map_err(ApiError::BadRequest)
+}
+
+/// The session user's preferences, or the defaults if they have stored none
+#[instrument(skip(preferences, session))]
+async fn get_preferences(
+    State(preferences): State<Preferences>,
+    session: Option<Extension<UserSession>>,
+) -> ApiResult<Json<UserPreferences>> {
+    let Extension(session) = session
+        .ok_or_else(|| ApiError::Unauthorized("No active session".to_string()))?;
+    Ok(Json(preferences.for_user(session.user_id).await?))
+}
+
+/// Replace the session user's preferences
+#[instrument(skip(preferences, session))]
+async fn update_preferences(
+    State(preferences): State<Preferences>,
+    session: Option<Extension<UserSession>>,
+    Json(update): Json<UserPreferences>,
+) -> ApiResult<Json<UserPreferences>> {
+    let Extension(session) = session
+        .ok_or_else(|| ApiError::Unauthorized("No active session".to_string()))?;
+    Ok(Json(preferences.update(session.user_id, update).await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Method, Request, StatusCode};
+    use chrono::Utc;
+    use jarvis_core::audit::{AuditFilter, JsonlAuditSink};
+    use jarvis_core::CognitiveKernel;
+    use tower::ServiceExt;
+
+    fn session() -> UserSession {
+        UserSession {
+            user_id: Uuid::new_v4(),
+            session_id: Uuid::new_v4(),
+            created_at: Utc::now(),
+            last_activity: Utc::now(),
+            permissions: Vec::new(),
+            tenant_id: talkpp_tenancy::default_tenant_id(),
+        }
+    }
+
+    fn preferences(max_autonomy_tier: Option<u8>, risks: &[&str]) -> UserPreferences {
+        UserPreferences {
+            max_autonomy_tier,
+            require_approval_for_risks: risks.iter().map(|r| r.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// A low-risk intent, which the kernel plans at tier 3
+    async fn low_risk_plan() -> (Intent, IntentExecutionPlan) {
+        let (intent, plan) = CognitiveKernel::new().plan_intent("Summarise my unread email", None).await.unwrap();
+        assert_eq!(plan.autonomy_tier, 3);
+        (intent, plan)
+    }
+
+    #[tokio::test]
+    async fn test_tier_is_clamped_and_approval_forced() {
+        let path = std::env::temp_dir().join(format!("api-policy-{}.jsonl", Uuid::new_v4()));
+        let auditor = Auditor::spawn(Arc::new(JsonlAuditSink::new(&path)), 16);
+        let store = Arc::new(MemoryPreferencesStore::new());
+        let policy = Preferences::new(store.clone(), UserPreferences::default()).with_auditor(auditor.clone());
+
+        let user = session();
+        store.put(user.user_id, &preferences(Some(1), &["low"])).await.unwrap();
+        let (intent, mut plan) = low_risk_plan().await;
+        let outcome = policy.enforce(Some((user.user_id, user.audit_actor())), None, &intent, &mut plan).await.unwrap();
+
+        assert_eq!(plan.autonomy_tier, 1);
+        assert!(outcome.requires_approval);
+        let applied: Vec<&str> = outcome.decisions.iter().map(|d| d.preference.as_str()).collect();
+        assert_eq!(applied, ["max_autonomy_tier", "require_approval_for_risks"]);
+        assert_eq!(outcome.decisions[0].effect, "autonomy tier 3 clamped to 1");
+
+        // A request can tighten the stored preferences but never loosen them
+        let (intent, mut plan) = low_risk_plan().await;
+        let loosened = preferences(Some(3), &[]);
+        policy.enforce(Some((user.user_id, user.audit_actor())), Some(&loosened), &intent, &mut plan).await.unwrap();
+        assert_eq!(plan.autonomy_tier, 1);
+
+        auditor.flush().await;
+        let events = auditor.query(&AuditFilter { action: Some(AuditAction::PolicyDecision), ..Default::default() }).await.unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].actor.user_id, Some(user.user_id.to_string()));
+        std::fs::remove_file(path).ok();
+    }
+
+    #[tokio::test]
+    async fn test_users_without_preferences_get_the_defaults() {
+        let policy = Preferences::new(Arc::new(MemoryPreferencesStore::new()), preferences(Some(2), &["critical"]));
+        let app = Router::new().merge(routes()).with_state(policy.clone());
+        let user = session();
+
+        let call = |method: Method, body: Option<serde_json::Value>| {
+            let mut request = Request::builder()
+                .method(method)
+                .uri("/users/me/preferences")
+                .header("content-type", "application/json")
+                .body(body.map_or_else(Body::empty, |b| Body::from(b.to_string())))
+                .unwrap();
+            request.extensions_mut().insert(user.clone());
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                let status = response.status();
+                let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                (status, serde_json::from_slice::<serde_json::Value>(&bytes).unwrap_or_default())
+            }
+        };
+
+        let (status, body) = call(Method::GET, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["max_autonomy_tier"], 2);
+        assert_eq!(body["require_approval_for_risks"], serde_json::json!(["critical"]));
+
+        let (intent, mut plan) = low_risk_plan().await;
+        let outcome = policy.enforce(Some((user.user_id, user.audit_actor())), None, &intent, &mut plan).await.unwrap();
+        assert_eq!(plan.autonomy_tier, 2);
+        assert!(!outcome.requires_approval);
+
+        let update = serde_json::json!({ "max_autonomy_tier": 3, "require_approval_for_risks": ["High"] });
+        assert_eq!(call(Method::PUT, Some(update)).await.0, StatusCode::OK);
+        let (_, body) = call(Method::GET, None).await;
+        assert_eq!(body["max_autonomy_tier"], 3);
+
+        let invalid = serde_json::json!({ "max_autonomy_tier": 7, "require_approval_for_risks": [] });
+        assert_eq!(call(Method::PUT, Some(invalid)).await.0, StatusCode::BAD_REQUEST);
+        let unknown = serde_json::json!({ "require_approval_for_risks": ["spicy"] });
+        assert_eq!(call(Method::PUT, Some(unknown)).await.0, StatusCode::BAD_REQUEST);
+    }
+}
@@ -1,9 +1,21 @@
-use async_graphql::{Context, Object, Result, SimpleObject, Enum, ID};
+use async_graphql::{Context, ErrorExtensions, Object, Result, ResultExt, SimpleObject, Enum, ID};
 use chrono::{DateTime, Utc};
 use uuid::Uuid;
 use serde::{Deserialize, Serialize};
 
-use crate::{AppState, ProcessIntentRequest, UserPreferences};
+use jarvis_core::{ExecutionTask, PlanBudget, RiskLevel};
+use memory_continuum::MemoryType;
+
+use crate::error::ApiError;
+use crate::idempotency::{request_hash, scoped_key, Claim, StoredResponse, REPLAYED_HEADER};
+use crate::memory::{MemoryMetadataInput, MemoryResponse, UserMemories};
+use crate::approvals::{decide_task, TaskDecision};
+use crate::capabilities::{Capability, VECTOR_DB};
+use crate::preferences::PolicyDecision;
+use crate::{AppState, ProcessIntentRequest, UserPreferences, UserSession};
+
+/// `Idempotency-Key` header of the GraphQL request, if it had one
+pub struct IdempotencyKey(pub String);
 
 /// GraphQL Query Root
 pub struct QueryRoot;
@@ -31,11 +43,36 @@ pub struct ExecutionPlanGQL {
     pub intent_id: ID,
     pub estimated_duration: i32, // minutes
     pub autonomy_tier: i32,
+    pub risk_level: RiskLevelGQL,
     pub tasks: Vec<TaskGQL>,
+    pub budget: PlanBudgetGQL,
+    pub requires_approval: bool,
+    /// Changes the user's preferences made to the plan
+    pub policy_decisions: Vec<PolicyDecision>,
     pub created_at: DateTime<Utc>,
     pub status: ExecutionStatusGQL,
 }
 
+/// Ceilings enforced while a plan executes; absent means no ceiling
+#[derive(SimpleObject, Debug, Clone, Serialize, Deserialize)]
+pub struct PlanBudgetGQL {
+    pub max_wall_time_minutes: Option<i64>,
+    pub max_llm_tokens: Option<u64>,
+    pub max_cost_usd: Option<f64>,
+    pub max_task_retries: u32,
+}
+
+impl From<&PlanBudget> for PlanBudgetGQL {
+    fn from(budget: &PlanBudget) -> Self {
+        PlanBudgetGQL {
+            max_wall_time_minutes: budget.max_wall_time.map(|d| d.num_minutes()),
+            max_llm_tokens: budget.max_llm_tokens,
+            max_cost_usd: budget.max_cost_usd,
+            max_task_retries: budget.max_task_retries,
+        }
+    }
+}
+
 /// Task representation for GraphQL
 #[derive(SimpleObject, Debug, Clone, Serialize, Deserialize)]
 pub struct TaskGQL {
@@ -49,6 +86,34 @@ pub struct TaskGQL {
     pub dry_run_first: bool,
 }
 
+impl From<&ExecutionTask> for TaskGQL {
+    fn from(task: &ExecutionTask) -> Self {
+        TaskGQL {
+            id: ID::from(task.id.to_string()),
+            name: task.name.clone(),
+            description: task.description.clone(),
+            task_type: match task.task_type {
+                jarvis_core::TaskType::Sense => TaskTypeGQL::Sense,
+                jarvis_core::TaskType::Plan => TaskTypeGQL::Plan,
+                jarvis_core::TaskType::Execute => TaskTypeGQL::Execute,
+                jarvis_core::TaskType::Verify => TaskTypeGQL::Verify,
+                jarvis_core::TaskType::Reflect => TaskTypeGQL::Reflect,
+            },
+            agent_type: task.agent_type.clone(),
+            estimated_duration: task.estimated_duration.num_minutes() as i32,
+            status: match task.status {
+                jarvis_core::TaskStatus::Pending => TaskStatusGQL::Pending,
+                jarvis_core::TaskStatus::InProgress => TaskStatusGQL::InProgress,
+                jarvis_core::TaskStatus::Completed => TaskStatusGQL::Completed,
+                jarvis_core::TaskStatus::Failed => TaskStatusGQL::Failed,
+                jarvis_core::TaskStatus::Cancelled => TaskStatusGQL::Cancelled,
+                jarvis_core::TaskStatus::WaitingApproval => TaskStatusGQL::WaitingApproval,
+            },
+            dry_run_first: task.dry_run_first,
+        }
+    }
+}
+
 /// Risk level enum for GraphQL
 #[derive(Enum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum RiskLevelGQL {
@@ -58,6 +123,17 @@ pub enum RiskLevelGQL {
     Critical,
 }
 
+impl From<RiskLevel> for RiskLevelGQL {
+    fn from(risk_level: RiskLevel) -> Self {
+        match risk_level {
+            RiskLevel::Low => RiskLevelGQL::Low,
+            RiskLevel::Medium => RiskLevelGQL::Medium,
+            RiskLevel::High => RiskLevelGQL::High,
+            RiskLevel::Critical => RiskLevelGQL::Critical,
+        }
+    }
+}
+
 /// Task type enum for GraphQL
 #[derive(Enum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TaskTypeGQL {
@@ -87,6 +163,8 @@ pub enum ExecutionStatusGQL {
     Completed,
     Failed,
     Cancelled,
+    BudgetExceeded,
+    AwaitingApproval,
 }
 
 /// User information for GraphQL
@@ -108,6 +186,25 @@ pub struct UserPreferencesGQL {
     pub preferred_execution_mode: Option<String>,
 }
 
+impl From<&UserPreferences> for UserPreferencesGQL {
+    fn from(preferences: &UserPreferences) -> Self {
+        Self {
+            max_autonomy_tier: preferences.max_autonomy_tier.map(i32::from),
+            // Stored preferences only name known risk levels
+            require_approval_for_risks: preferences.require_approval_for_risks.iter()
+                .filter_map(|risk| match risk.to_ascii_lowercase().as_str() {
+                    "low" => Some(RiskLevelGQL::Low),
+                    "medium" => Some(RiskLevelGQL::Medium),
+                    "high" => Some(RiskLevelGQL::High),
+                    "critical" => Some(RiskLevelGQL::Critical),
+                    _ => None,
+                })
+                .collect(),
+            preferred_execution_mode: preferences.preferred_execution_mode.clone(),
+        }
+    }
+}
+
 /// Cognitive kernel status for GraphQL
 #[derive(SimpleObject, Debug, Clone, Serialize, Deserialize)]
 pub struct KernelStatusGQL {
@@ -118,6 +215,136 @@ pub struct KernelStatusGQL {
     pub memory_usage_mb: f64,
 }
 
+/// Memory type enum for GraphQL
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MemoryTypeGQL {
+    ShortTerm,
+    LongTerm,
+    Procedural,
+    Episodic,
+    Spatial,
+}
+
+impl From<MemoryTypeGQL> for MemoryType {
+    fn from(memory_type: MemoryTypeGQL) -> Self {
+        match memory_type {
+            MemoryTypeGQL::ShortTerm => MemoryType::ShortTerm,
+            MemoryTypeGQL::LongTerm => MemoryType::LongTerm,
+            MemoryTypeGQL::Procedural => MemoryType::Procedural,
+            MemoryTypeGQL::Episodic => MemoryType::Episodic,
+            MemoryTypeGQL::Spatial => MemoryType::Spatial,
+        }
+    }
+}
+
+impl From<MemoryType> for MemoryTypeGQL {
+    fn from(memory_type: MemoryType) -> Self {
+        match memory_type {
+            MemoryType::ShortTerm => MemoryTypeGQL::ShortTerm,
+            MemoryType::LongTerm => MemoryTypeGQL::LongTerm,
+            MemoryType::Procedural => MemoryTypeGQL::Procedural,
+            MemoryType::Episodic => MemoryTypeGQL::Episodic,
+            MemoryType::Spatial => MemoryTypeGQL::Spatial,
+        }
+    }
+}
+
+/// Memory for GraphQL
+#[derive(SimpleObject, Debug, Clone, Serialize, Deserialize)]
+pub struct MemoryGQL {
+    pub id: ID,
+    pub content: String, // JSON content
+    pub memory_type: MemoryTypeGQL,
+    pub importance: f64,
+    pub confidence: f64,
+    pub source: String,
+    pub tags: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_accessed: DateTime<Utc>,
+}
+
+impl From<MemoryResponse> for MemoryGQL {
+    fn from(memory: MemoryResponse) -> Self {
+        Self {
+            id: ID::from(memory.id.to_string()),
+            content: memory.content.to_string(),
+            memory_type: memory.memory_type.into(),
+            importance: memory.importance,
+            confidence: memory.confidence,
+            source: memory.source,
+            tags: memory.tags,
+            created_at: memory.created_at,
+            last_accessed: memory.last_accessed,
+        }
+    }
+}
+
+/// Memory continuum statistics for GraphQL
+#[derive(SimpleObject, Debug, Clone, Serialize, Deserialize)]
+pub struct MemoryStatisticsGQL {
+    pub total_memories: i32,
+    pub short_term_count: i32,
+    pub long_term_count: i32,
+    pub procedural_count: i32,
+    pub episodic_count: i32,
+    pub spatial_count: i32,
+    pub associations_count: i32,
+    pub consolidation_queue_depth: i32,
+    pub consolidation_max_wait_ms: f64,
+}
+
+/// Memory operations for the session's user
+fn user_memories<'a>(ctx: &Context<'a>) -> Result<UserMemories<'a>> {
+    let state = ctx.data::<AppState>()?;
+    let session = ctx.data_opt::<UserSession>()
+        .ok_or_else(|| ApiError::Unauthorized("No active session".to_string()).extend())?;
+    Ok(UserMemories::new(&state.memory, session.tenant()))
+}
+
+/// Plan an intent through the cognitive kernel, adjusting its tier-default budget
+async fn plan_intent(
+    state: &AppState,
+    session: Option<&UserSession>,
+    intent: &str,
+    budget: Option<&PlanBudgetInput>,
+) -> Result<ExecutionPlanGQL> {
+    // Process through cognitive kernel, grounded in the session user's memories
+    let context = session.map(UserSession::intent_context);
+    let (parsed, mut plan) = state.cognitive_kernel.plan_intent(intent, context).await
+        .map_err(|e| async_graphql::Error::new(format!("Failed to process intent: {}", e)))?;
+    if let Some(budget) = budget {
+        budget.apply(&mut plan.budget);
+    }
+    let owner = session.map(|session| (session.user_id, session.audit_actor()));
+    let policy = state.preferences.enforce(owner, None, &parsed, &mut plan).await
+        .map_err(|e| ApiError::from(e).extend())?;
+    let requires_approval = plan.autonomy_tier <= 2 || policy.requires_approval;
+    if requires_approval {
+        state.notifier.approval_requested(session.map(|session| session.user_id), &parsed, &plan);
+    }
+
+    // Convert to GraphQL format
+    let tasks: Vec<TaskGQL> = plan.tasks.iter().map(TaskGQL::from).collect();
+
+    let gql_plan = ExecutionPlanGQL {
+        id: ID::from(plan.id.to_string()),
+        intent_id: ID::from(plan.intent_id.to_string()),
+        estimated_duration: plan.estimated_duration.num_minutes() as i32,
+        autonomy_tier: plan.autonomy_tier as i32,
+        risk_level: parsed.risk_level.into(),
+        tasks,
+        budget: (&plan.budget).into(),
+        requires_approval,
+        policy_decisions: policy.decisions,
+        created_at: plan.created_at,
+        status: ExecutionStatusGQL::Planning,
+    };
+
+    // TODO: Store in database
+
+    Ok(gql_plan)
+}
+
 #[Object]
 impl QueryRoot {
     /// Get system health status
@@ -209,14 +436,20 @@ impl QueryRoot {
         
         Ok(KernelStatusGQL {
             status: "operational".to_string(),
-            active_contexts: state.active_sessions.len() as i32,
+            active_contexts: state.sessions.keys().await?.len() as i32,
             processed_intents_today: 0, // TODO: Implement counter
             average_processing_time_ms: 150.0, // TODO: Calculate from metrics
             memory_usage_mb: 0.0, // TODO: Get actual memory usage
         })
     }
 
-    /// Search vectors
+    /// What this deployment can do, probing subsystems whose last probe is stale
+    async fn capabilities(&self, ctx: &Context<'_>) -> Result<Vec<Capability>> {
+        Ok(ctx.data::<AppState>()?.capabilities.all().await)
+    }
+
+    /// Search vectors. `threshold` is the lowest similarity to return, in 0..=1 whatever
+    /// the collection's distance metric, as in `SearchResult::score`.
     async fn vector_search(
         &self,
         ctx: &Context<'_>,
@@ -224,13 +457,68 @@ impl QueryRoot {
         limit: Option<i32>,
         threshold: Option<f64>,
     ) -> Result<Vec<VectorSearchResult>> {
-        let _state = ctx.data::<AppState>()?;
+        let state = ctx.data::<AppState>()?;
+        state.capabilities.require(VECTOR_DB).await.extend()?;
         let _search_limit = limit.unwrap_or(10).min(100);
         let _similarity_threshold = threshold.unwrap_or(0.7);
         
         // TODO: Implement vector search
         Ok(vec![])
     }
+
+    /// Get one of the current user's memories by ID
+    async fn memory(&self, ctx: &Context<'_>, id: ID) -> Result<Option<MemoryGQL>> {
+        let memory_id = Uuid::parse_str(&id)?;
+        match user_memories(ctx)?.get(memory_id) {
+            Ok(item) => Ok(Some(MemoryResponse::from(item).into())),
+            Err(ApiError::NotFound(_)) => Ok(None),
+            Err(e) => Err(e.extend()),
+        }
+    }
+
+    /// Search the current user's memories; short- and long-term memories by default
+    async fn search_memories(
+        &self,
+        ctx: &Context<'_>,
+        query: String,
+        types: Option<Vec<MemoryTypeGQL>>,
+        limit: Option<i32>,
+    ) -> Result<Vec<MemoryGQL>> {
+        let memory_types = match types {
+            Some(types) if !types.is_empty() => types.into_iter().map(MemoryType::from).collect(),
+            _ => vec![MemoryType::ShortTerm, MemoryType::LongTerm],
+        };
+        let search_limit = limit.unwrap_or(10).clamp(0, 100) as usize;
+
+        let memories = user_memories(ctx)?.search(&query, memory_types, search_limit).await.extend()?;
+        Ok(memories.into_iter().map(|item| MemoryResponse::from(item).into()).collect())
+    }
+
+    /// IDs of the current user's memories associated with a memory
+    async fn memory_associations(&self, ctx: &Context<'_>, id: ID) -> Result<Vec<ID>> {
+        let memory_id = Uuid::parse_str(&id)?;
+        let associations = user_memories(ctx)?.associations(memory_id).await.extend()?;
+        Ok(associations.into_iter().map(|id| ID::from(id.to_string())).collect())
+    }
+
+    /// Get memory continuum statistics
+    async fn memory_statistics(&self, ctx: &Context<'_>) -> Result<MemoryStatisticsGQL> {
+        user_memories(ctx)?;
+        let state = ctx.data::<AppState>()?;
+        let statistics = state.memory.get_statistics().await?;
+
+        Ok(MemoryStatisticsGQL {
+            total_memories: statistics.total_memories as i32,
+            short_term_count: statistics.short_term_count as i32,
+            long_term_count: statistics.long_term_count as i32,
+            procedural_count: statistics.procedural_count as i32,
+            episodic_count: statistics.episodic_count as i32,
+            spatial_count: statistics.spatial_count as i32,
+            associations_count: statistics.associations_count as i32,
+            consolidation_queue_depth: statistics.consolidation_queue_depth as i32,
+            consolidation_max_wait_ms: statistics.consolidation_max_wait.as_secs_f64() * 1000.0,
+        })
+    }
 }
 
 /// Vector search result for GraphQL
@@ -244,57 +532,57 @@ pub struct VectorSearchResult {
 
 #[Object]
 impl MutationRoot {
-    /// Process a new intent
+    /// Process a new intent. With an `Idempotency-Key` header, a repeated request returns
+    /// the plan from the first one instead of creating another.
     async fn process_intent(
         &self,
         ctx: &Context<'_>,
         intent: String,
         context: Option<String>,
+        budget: Option<PlanBudgetInput>,
     ) -> Result<ExecutionPlanGQL> {
         let state = ctx.data::<AppState>()?;
-        
-        // Process through cognitive kernel
-        let plan = state.cognitive_kernel.process_intent(&intent, None).await
-            .map_err(|e| async_graphql::Error::new(format!("Failed to process intent: {}", e)))?;
-
-        // Convert to GraphQL format
-        let tasks: Vec<TaskGQL> = plan.tasks.iter().map(|task| TaskGQL {
-            id: ID::from(task.id.to_string()),
-            name: task.name.clone(),
-            description: task.description.clone(),
-            task_type: match task.task_type {
-                jarvis_core::TaskType::Sense => TaskTypeGQL::Sense,
-                jarvis_core::TaskType::Plan => TaskTypeGQL::Plan,
-                jarvis_core::TaskType::Execute => TaskTypeGQL::Execute,
-                jarvis_core::TaskType::Verify => TaskTypeGQL::Verify,
-                jarvis_core::TaskType::Reflect => TaskTypeGQL::Reflect,
-            },
-            agent_type: task.agent_type.clone(),
-            estimated_duration: task.estimated_duration.num_minutes() as i32,
-            status: match task.status {
-                jarvis_core::TaskStatus::Pending => TaskStatusGQL::Pending,
-                jarvis_core::TaskStatus::InProgress => TaskStatusGQL::InProgress,
-                jarvis_core::TaskStatus::Completed => TaskStatusGQL::Completed,
-                jarvis_core::TaskStatus::Failed => TaskStatusGQL::Failed,
-                jarvis_core::TaskStatus::Cancelled => TaskStatusGQL::Cancelled,
-                jarvis_core::TaskStatus::WaitingApproval => TaskStatusGQL::WaitingApproval,
-            },
-            dry_run_first: task.dry_run_first,
-        }).collect();
-
-        let gql_plan = ExecutionPlanGQL {
-            id: ID::from(plan.id.to_string()),
-            intent_id: ID::from(plan.intent_id.to_string()),
-            estimated_duration: plan.estimated_duration.num_minutes() as i32,
-            autonomy_tier: plan.autonomy_tier as i32,
-            tasks,
-            created_at: plan.created_at,
-            status: ExecutionStatusGQL::Planning,
+        let session = ctx.data_opt::<UserSession>();
+        let Some(IdempotencyKey(key)) = ctx.data_opt::<IdempotencyKey>() else {
+            return plan_intent(state, session, &intent, budget.as_ref()).await;
         };
 
-        // TODO: Store in database
-
-        Ok(gql_plan)
+        let key = scoped_key(session, "graphql processIntent", key);
+        let hash = request_hash(serde_json::json!({ "intent": intent, "context": context, "budget": budget }).to_string().as_bytes());
+        let store = state.idempotency.store();
+        match store.claim(&key, &hash).await? {
+            Claim::Acquired => {}
+            Claim::Completed(stored) => {
+                ctx.insert_http_header(REPLAYED_HEADER, "true");
+                return Ok(serde_json::from_slice(&stored.body)?);
+            }
+            Claim::InFlight => {
+                return Err(ApiError::Conflict("A request with this Idempotency-Key is still in progress".to_string()).extend())
+            }
+            Claim::Mismatch => {
+                return Err(ApiError::UnprocessableEntity("Idempotency-Key was already used for a different request".to_string()).extend())
+            }
+        }
+
+        match plan_intent(state, session, &intent, budget.as_ref()).await {
+            Ok(plan) => {
+                let stored = StoredResponse {
+                    status: 200,
+                    content_type: Some("application/json".to_string()),
+                    body: serde_json::to_vec(&plan)?,
+                };
+                if let Err(e) = store.complete(&key, &hash, stored).await {
+                    tracing::warn!("Failed to store idempotent response: {}", e);
+                }
+                Ok(plan)
+            }
+            Err(e) => {
+                if let Err(e) = store.release(&key).await {
+                    tracing::warn!("Failed to release idempotency key: {}", e);
+                }
+                Err(e)
+            }
+        }
     }
 
     /// Execute a plan
@@ -315,40 +603,78 @@ impl MutationRoot {
         Ok(false)
     }
 
-    /// Approve a task
+    /// Approve a task awaiting approval; it stays waiting until enough distinct members of
+    /// its approver groups have approved it
     async fn approve_task(&self, ctx: &Context<'_>, task_id: ID) -> Result<TaskGQL> {
-        let _state = ctx.data::<AppState>()?;
-        let _id = Uuid::parse_str(&task_id)?;
-        
-        // TODO: Implement task approval
-        Err(async_graphql::Error::new("Task approval not yet implemented"))
+        let state = ctx.data::<AppState>()?;
+        let id = Uuid::parse_str(&task_id)?;
+        let (pending, _) = decide_task(&state.approvals, &state.auditor, ctx.data_opt::<UserSession>(), id, TaskDecision::Approve)
+            .extend()?;
+        Ok((&pending.task).into())
     }
 
-    /// Reject a task
+    /// Reject a task awaiting approval, failing its plan when it is resumed
     async fn reject_task(
         &self,
         ctx: &Context<'_>,
         task_id: ID,
         reason: Option<String>,
     ) -> Result<TaskGQL> {
-        let _state = ctx.data::<AppState>()?;
-        let _id = Uuid::parse_str(&task_id)?;
-        let _rejection_reason = reason.unwrap_or_else(|| "No reason provided".to_string());
-        
-        // TODO: Implement task rejection
-        Err(async_graphql::Error::new("Task rejection not yet implemented"))
+        let state = ctx.data::<AppState>()?;
+        let id = Uuid::parse_str(&task_id)?;
+        let decision = TaskDecision::Reject { reason };
+        let (pending, _) = decide_task(&state.approvals, &state.auditor, ctx.data_opt::<UserSession>(), id, decision).extend()?;
+        Ok((&pending.task).into())
     }
 
-    /// Update user preferences
+    /// Update the current user's preferences; fields left out keep their current value
     async fn update_preferences(
         &self,
         ctx: &Context<'_>,
         preferences: UserPreferencesInput,
-    ) -> Result<UserGQL> {
-        let _state = ctx.data::<AppState>()?;
-        
-        // TODO: Implement preferences update
-        Err(async_graphql::Error::new("Preferences update not yet implemented"))
+    ) -> Result<UserPreferencesGQL> {
+        let state = ctx.data::<AppState>()?;
+        let session = ctx.data_opt::<UserSession>()
+            .ok_or_else(|| ApiError::Unauthorized("No active session".to_string()).extend())?;
+
+        let mut updated = state.preferences.for_user(session.user_id).await
+            .map_err(|e| ApiError::from(e).extend())?;
+        preferences.apply(&mut updated)?;
+        let stored = state.preferences.update(session.user_id, updated).await.extend()?;
+        Ok((&stored).into())
+    }
+
+    /// Store a memory for the current user, returning its ID
+    async fn store_memory(&self, ctx: &Context<'_>, input: StoreMemoryInput) -> Result<ID> {
+        // Content that isn't JSON is stored as a string
+        let content = serde_json::from_str(&input.content)
+            .unwrap_or(serde_json::Value::String(input.content));
+        let associations = input.associations.unwrap_or_default()
+            .iter()
+            .map(|id| Uuid::parse_str(id))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let metadata = MemoryMetadataInput {
+            importance: input.importance,
+            confidence: input.confidence,
+            source: input.source,
+            tags: input.tags.unwrap_or_default(),
+            associations,
+            pii_allowlist: input.pii_allowlist.unwrap_or_default(),
+        };
+
+        let memory_type = input.memory_type.unwrap_or(MemoryTypeGQL::ShortTerm).into();
+        let id = user_memories(ctx)?.store(content, memory_type, metadata).await.extend()?;
+        Ok(ID::from(id.to_string()))
+    }
+
+    /// Delete one of the current user's memories; false if there was no such memory
+    async fn delete_memory(&self, ctx: &Context<'_>, id: ID) -> Result<bool> {
+        let memory_id = Uuid::parse_str(&id)?;
+        match user_memories(ctx)?.forget(memory_id).await {
+            Ok(()) => Ok(true),
+            Err(ApiError::NotFound(_)) => Ok(false),
+            Err(e) => Err(e.extend()),
+        }
     }
 }
 
@@ -358,4 +684,61 @@ pub struct UserPreferencesInput {
     pub max_autonomy_tier: Option<i32>,
     pub require_approval_for_risks: Option<Vec<RiskLevelGQL>>,
     pub preferred_execution_mode: Option<String>,
-} 
\ No newline at end of file
+}
+
+impl UserPreferencesInput {
+    pub fn apply(&self, preferences: &mut UserPreferences) -> Result<()> {
+        if let Some(tier) = self.max_autonomy_tier {
+            let tier = u8::try_from(tier)
+                .map_err(|_| ApiError::BadRequest("max_autonomy_tier is out of range".to_string()).extend())?;
+            preferences.max_autonomy_tier = Some(tier);
+        }
+        if let Some(risks) = &self.require_approval_for_risks {
+            preferences.require_approval_for_risks = risks.iter().map(|risk| format!("{:?}", risk).to_lowercase()).collect();
+        }
+        if let Some(mode) = &self.preferred_execution_mode {
+            preferences.preferred_execution_mode = Some(mode.clone());
+        }
+        Ok(())
+    }
+}
+
+/// Input type for storing a memory
+#[derive(async_graphql::InputObject, Debug, Clone, Serialize, Deserialize)]
+pub struct StoreMemoryInput {
+    pub content: String, // JSON content, or plain text
+    pub memory_type: Option<MemoryTypeGQL>,
+    pub importance: Option<f64>,
+    pub confidence: Option<f64>,
+    pub source: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub associations: Option<Vec<ID>>,
+    /// Content fields to keep as given when the server masks personal data
+    pub pii_allowlist: Option<Vec<String>>,
+}
+
+/// Changes to a plan's tier-default budget; unset fields keep the default
+#[derive(async_graphql::InputObject, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PlanBudgetInput {
+    pub max_wall_time_minutes: Option<i64>,
+    pub max_llm_tokens: Option<u64>,
+    pub max_cost_usd: Option<f64>,
+    pub max_task_retries: Option<u32>,
+}
+
+impl PlanBudgetInput {
+    pub fn apply(&self, budget: &mut PlanBudget) {
+        if let Some(minutes) = self.max_wall_time_minutes {
+            budget.max_wall_time = Some(chrono::Duration::minutes(minutes));
+        }
+        if let Some(tokens) = self.max_llm_tokens {
+            budget.max_llm_tokens = Some(tokens);
+        }
+        if let Some(cost) = self.max_cost_usd {
+            budget.max_cost_usd = Some(cost);
+        }
+        if let Some(retries) = self.max_task_retries {
+            budget.max_task_retries = retries;
+        }
+    }
+}
@@ -0,0 +1,3 @@
+//! Building blocks shared by the api-server's features
+
+pub mod redis_store;
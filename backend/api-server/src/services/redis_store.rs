@@ -0,0 +1,543 @@
+//! Typed values in Redis under one naming, TTL and compression scheme
+//!
+//! A [`RedisStore`] keeps values of one type in a namespace, at keys of the form
+//! `{key_prefix}{namespace}:{key}`. Values are stored as JSON behind a one-byte header,
+//! zstd-compressed once the JSON passes the store's threshold, and expire after the
+//! namespace's default TTL unless a write names another.
+//!
+//! A stored value that no longer decompresses or deserializes, say one written by an
+//! older build, reads as a miss and is deleted, so callers rebuild it rather than fail.
+//!
+//! Every store reports its hits, misses, evicted corrupt values, bytes written and
+//! compression ratio to the metrics recorder, labelled with its namespace.
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use axum::async_trait;
+use redis::aio::ConnectionManager;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::time::Instant;
+use tracing::warn;
+
+use crate::config::CacheSettings;
+
+/// Counter of reads that found a value
+pub const HITS_METRIC: &str = "talkpp_cache_hits_total";
+/// Counter of reads that found nothing, or a value that could not be read
+pub const MISSES_METRIC: &str = "talkpp_cache_misses_total";
+/// Counter of values deleted because they could not be decompressed or deserialized
+pub const CORRUPT_METRIC: &str = "talkpp_cache_corrupt_total";
+/// Counter of bytes written, after compression
+pub const STORED_BYTES_METRIC: &str = "talkpp_cache_stored_bytes_total";
+/// Histogram of compressed size over JSON size, for values that were compressed
+pub const COMPRESSION_RATIO_METRIC: &str = "talkpp_cache_compression_ratio";
+
+/// Header byte of a value stored as plain JSON
+const PLAIN: u8 = b'j';
+/// Header byte of a value stored as zstd-compressed JSON
+const ZSTD: u8 = b'z';
+
+/// Keys a SCAN asks Redis for per round trip
+const SCAN_BATCH: usize = 100;
+
+/// Raw byte storage under string keys, which stores give their values to
+#[async_trait]
+pub trait KeyValueBackend: Send + Sync {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+
+    /// Store `value` at `key`, expiring after `ttl` if given
+    async fn set(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>) -> Result<()>;
+
+    /// Store `value` at `key` unless it holds a value, returning whether it was stored
+    async fn set_if_absent(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>) -> Result<bool>;
+
+    /// Delete `key`, returning whether it held a value
+    async fn delete(&self, key: &str) -> Result<bool>;
+
+    /// Every key starting with `prefix`
+    async fn scan(&self, prefix: &str) -> Result<Vec<String>>;
+}
+
+/// The Redis server the api-server replicas share
+pub struct RedisBackend {
+    connection: ConnectionManager,
+}
+
+impl RedisBackend {
+    pub async fn new(client: &redis::Client) -> Result<Self> {
+        Ok(Self { connection: client.get_connection_manager().await? })
+    }
+}
+
+fn with_expiry(command: &mut redis::Cmd, ttl: Option<Duration>) {
+    if let Some(ttl) = ttl {
+        command.arg("PX").arg((ttl.as_millis() as u64).max(1));
+    }
+}
+
+/// `prefix` as a SCAN pattern matching the keys that start with it
+fn scan_pattern(prefix: &str) -> String {
+    let mut pattern: String = prefix.chars()
+        .flat_map(|c| match c {
+            '*' | '?' | '[' | ']' | '\\' => vec!['\\', c],
+            c => vec![c],
+        })
+        .collect();
+    pattern.push('*');
+    pattern
+}
+
+#[async_trait]
+impl KeyValueBackend for RedisBackend {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let mut connection = self.connection.clone();
+        Ok(redis::cmd("GET").arg(key).query_async(&mut connection).await?)
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>) -> Result<()> {
+        let mut connection = self.connection.clone();
+        let mut command = redis::cmd("SET");
+        command.arg(key).arg(value);
+        with_expiry(&mut command, ttl);
+        command.query_async::<_, ()>(&mut connection).await?;
+        Ok(())
+    }
+
+    async fn set_if_absent(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>) -> Result<bool> {
+        let mut connection = self.connection.clone();
+        let mut command = redis::cmd("SET");
+        command.arg(key).arg(value).arg("NX");
+        with_expiry(&mut command, ttl);
+        let set: Option<String> = command.query_async(&mut connection).await?;
+        Ok(set.is_some())
+    }
+
+    async fn delete(&self, key: &str) -> Result<bool> {
+        let mut connection = self.connection.clone();
+        let deleted: u64 = redis::cmd("DEL").arg(key).query_async(&mut connection).await?;
+        Ok(deleted > 0)
+    }
+
+    async fn scan(&self, prefix: &str) -> Result<Vec<String>> {
+        let mut connection = self.connection.clone();
+        let pattern = scan_pattern(prefix);
+        let mut keys = Vec::new();
+        let mut cursor = 0u64;
+        loop {
+            let (next, batch): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(&pattern)
+                .arg("COUNT")
+                .arg(SCAN_BATCH)
+                .query_async(&mut connection)
+                .await?;
+            keys.extend(batch);
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        // SCAN may return a key more than once
+        keys.sort();
+        keys.dedup();
+        Ok(keys)
+    }
+}
+
+/// Values by key, with when they expire
+type Entries = HashMap<String, (Vec<u8>, Option<Instant>)>;
+
+/// Process-local storage, for tests and single-instance deployments
+#[derive(Default)]
+pub struct MemoryBackend {
+    entries: Mutex<Entries>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The entries, once those that expired are dropped
+    fn live(&self) -> MutexGuard<'_, Entries> {
+        let mut entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        entries.retain(|_, (_, expires_at)| !expires_at.is_some_and(|expires_at| expires_at <= now));
+        entries
+    }
+}
+
+#[async_trait]
+impl KeyValueBackend for MemoryBackend {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.live().get(key).map(|(value, _)| value.clone()))
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>) -> Result<()> {
+        self.live().insert(key.to_string(), (value, ttl.map(|ttl| Instant::now() + ttl)));
+        Ok(())
+    }
+
+    async fn set_if_absent(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>) -> Result<bool> {
+        let mut entries = self.live();
+        if entries.contains_key(key) {
+            return Ok(false);
+        }
+        entries.insert(key.to_string(), (value, ttl.map(|ttl| Instant::now() + ttl)));
+        Ok(true)
+    }
+
+    async fn delete(&self, key: &str) -> Result<bool> {
+        Ok(self.live().remove(key).is_some())
+    }
+
+    async fn scan(&self, prefix: &str) -> Result<Vec<String>> {
+        let mut keys: Vec<String> = self.live().keys().filter(|key| key.starts_with(prefix)).cloned().collect();
+        keys.sort();
+        Ok(keys)
+    }
+}
+
+/// The backend stores are made on, and the settings they take their namespace's
+/// defaults from
+#[derive(Clone)]
+pub struct Caches {
+    backend: Arc<dyn KeyValueBackend>,
+    settings: CacheSettings,
+}
+
+impl Caches {
+    pub fn new(backend: Arc<dyn KeyValueBackend>, settings: CacheSettings) -> Self {
+        Self { backend, settings }
+    }
+
+    pub fn backend(&self) -> &Arc<dyn KeyValueBackend> {
+        &self.backend
+    }
+
+    /// Store for `namespace`, with its configured TTL and the configured compression
+    pub fn store<T>(&self, namespace: &str) -> RedisStore<T> {
+        let store = RedisStore::new(self.backend.clone(), namespace)
+            .with_key_prefix(&self.settings.key_prefix)
+            .with_compression(self.settings.compress_above_bytes, self.settings.compression_level);
+        match self.settings.ttl(namespace) {
+            Some(ttl) => store.with_ttl(ttl),
+            None => store,
+        }
+    }
+}
+
+/// Values of type `T` in one namespace; see the [module docs](self)
+pub struct RedisStore<T> {
+    backend: Arc<dyn KeyValueBackend>,
+    namespace: String,
+    /// Prepended to the namespace to form the keys' common prefix
+    key_prefix: String,
+    /// TTL of writes that don't name one; `None` keeps values until deleted
+    ttl: Option<Duration>,
+    /// Size in bytes past which JSON is compressed; `None` never compresses
+    compress_above: Option<usize>,
+    compression_level: i32,
+    values: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for RedisStore<T> {
+    fn clone(&self) -> Self {
+        Self {
+            backend: self.backend.clone(),
+            namespace: self.namespace.clone(),
+            key_prefix: self.key_prefix.clone(),
+            ttl: self.ttl,
+            compress_above: self.compress_above,
+            compression_level: self.compression_level,
+            values: PhantomData,
+        }
+    }
+}
+
+impl<T> RedisStore<T> {
+    /// Store in `namespace` keeping values uncompressed until deleted
+    pub fn new(backend: Arc<dyn KeyValueBackend>, namespace: impl Into<String>) -> Self {
+        Self {
+            backend,
+            namespace: namespace.into(),
+            key_prefix: String::new(),
+            ttl: None,
+            compress_above: None,
+            compression_level: zstd::DEFAULT_COMPRESSION_LEVEL,
+            values: PhantomData,
+        }
+    }
+
+    pub fn with_key_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.key_prefix = prefix.into();
+        self
+    }
+
+    /// Expire values `ttl` after they are written, unless the write names its own TTL
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Compress values whose JSON is longer than `above_bytes` at zstd `level`
+    pub fn with_compression(mut self, above_bytes: usize, level: i32) -> Self {
+        self.compress_above = Some(above_bytes);
+        self.compression_level = level;
+        self
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    pub fn ttl(&self) -> Option<Duration> {
+        self.ttl
+    }
+
+    fn prefix(&self) -> String {
+        format!("{}{}:", self.key_prefix, self.namespace)
+    }
+
+    /// The Redis key `key` is stored at
+    pub fn key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix(), key)
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> RedisStore<T> {
+    pub async fn get(&self, key: &str) -> Result<Option<T>> {
+        let redis_key = self.key(key);
+        let Some(stored) = self.backend.get(&redis_key).await? else {
+            metrics::counter!(MISSES_METRIC, "namespace" => self.namespace.clone()).increment(1);
+            return Ok(None);
+        };
+        let value = self.decode(&redis_key, &stored).await;
+        let metric = if value.is_some() { HITS_METRIC } else { MISSES_METRIC };
+        metrics::counter!(metric, "namespace" => self.namespace.clone()).increment(1);
+        Ok(value)
+    }
+
+    /// Store `value` for the store's TTL
+    pub async fn set(&self, key: &str, value: &T) -> Result<()> {
+        self.set_with_ttl(key, value, self.ttl).await
+    }
+
+    /// Store `value` for `ttl`, or until deleted with `None`
+    pub async fn set_with_ttl(&self, key: &str, value: &T, ttl: Option<Duration>) -> Result<()> {
+        let stored = self.encode(value)?;
+        self.backend.set(&self.key(key), stored, ttl).await
+    }
+
+    /// Store `value` for `ttl` unless `key` holds a value, returning whether it was stored.
+    /// A corrupt value held at `key` still counts until a `get` evicts it.
+    pub async fn insert_if_absent(&self, key: &str, value: &T, ttl: Option<Duration>) -> Result<bool> {
+        let stored = self.encode(value)?;
+        self.backend.set_if_absent(&self.key(key), stored, ttl).await
+    }
+
+    /// Delete `key`, returning whether it held a value
+    pub async fn delete(&self, key: &str) -> Result<bool> {
+        self.backend.delete(&self.key(key)).await
+    }
+
+    /// Keys in the namespace, without its prefix
+    pub async fn keys(&self) -> Result<Vec<String>> {
+        let prefix = self.prefix();
+        let keys = self.backend.scan(&prefix).await?;
+        Ok(keys.into_iter().filter_map(|key| key.strip_prefix(&prefix).map(str::to_string)).collect())
+    }
+
+    /// Every value in the namespace with its key; corrupt values are evicted and skipped,
+    /// as are values that expire while being read
+    pub async fn scan(&self) -> Result<Vec<(String, T)>> {
+        let mut entries = Vec::new();
+        for key in self.keys().await? {
+            let redis_key = self.key(&key);
+            let Some(stored) = self.backend.get(&redis_key).await? else {
+                continue;
+            };
+            if let Some(value) = self.decode(&redis_key, &stored).await {
+                entries.push((key, value));
+            }
+        }
+        Ok(entries)
+    }
+
+    fn encode(&self, value: &T) -> Result<Vec<u8>> {
+        let json = serde_json::to_vec(value)
+            .with_context(|| format!("Failed to serialize a value for the {} store", self.namespace))?;
+        let stored = match self.compress_above {
+            Some(threshold) if json.len() > threshold => {
+                let mut stored = vec![ZSTD];
+                stored.extend(zstd::encode_all(json.as_slice(), self.compression_level)?);
+                let ratio = stored.len() as f64 / json.len() as f64;
+                metrics::histogram!(COMPRESSION_RATIO_METRIC, "namespace" => self.namespace.clone()).record(ratio);
+                stored
+            }
+            _ => {
+                let mut stored = Vec::with_capacity(json.len() + 1);
+                stored.push(PLAIN);
+                stored.extend(json);
+                stored
+            }
+        };
+        metrics::counter!(STORED_BYTES_METRIC, "namespace" => self.namespace.clone()).increment(stored.len() as u64);
+        Ok(stored)
+    }
+
+    /// The value `stored` at `redis_key` holds, or `None` after deleting it when it
+    /// cannot be read
+    async fn decode(&self, redis_key: &str, stored: &[u8]) -> Option<T> {
+        let json = match stored.split_first() {
+            Some((&PLAIN, json)) => Ok(json.to_vec()),
+            Some((&ZSTD, compressed)) => zstd::decode_all(compressed).map_err(|e| e.to_string()),
+            _ => Err("unknown encoding".to_string()),
+        };
+        let error = match json.map(|json| serde_json::from_slice(&json)) {
+            Ok(Ok(value)) => return Some(value),
+            Ok(Err(e)) => e.to_string(),
+            Err(e) => e,
+        };
+
+        warn!("Evicting unreadable value at {}: {}", redis_key, error);
+        metrics::counter!(CORRUPT_METRIC, "namespace" => self.namespace.clone()).increment(1);
+        if let Err(e) = self.backend.delete(redis_key).await {
+            warn!("Failed to evict unreadable value at {}: {}", redis_key, e);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Entry {
+        name: String,
+        body: String,
+    }
+
+    fn entry(name: &str, body_len: usize) -> Entry {
+        Entry { name: name.to_string(), body: "plan ".repeat(body_len / 5) }
+    }
+
+    fn store(backend: &Arc<MemoryBackend>) -> RedisStore<Entry> {
+        RedisStore::new(backend.clone(), "entries").with_key_prefix("talkpp:").with_compression(256, 3)
+    }
+
+    #[tokio::test]
+    async fn test_values_past_the_threshold_are_compressed() {
+        let backend = Arc::new(MemoryBackend::new());
+        let store = store(&backend);
+        let (small, large) = (entry("small", 100), entry("large", 10_000));
+        store.set("small", &small).await.unwrap();
+        store.set("large", &large).await.unwrap();
+
+        let raw = |key: &str| {
+            let backend = backend.clone();
+            let key = store.key(key);
+            async move { backend.get(&key).await.unwrap().unwrap() }
+        };
+        assert_eq!(store.key("small"), "talkpp:entries:small");
+        let stored = raw("small").await;
+        assert_eq!(stored[0], PLAIN);
+        assert_eq!(&stored[1..], serde_json::to_vec(&small).unwrap().as_slice());
+        let stored = raw("large").await;
+        assert_eq!(stored[0], ZSTD);
+        assert!(stored.len() < serde_json::to_vec(&large).unwrap().len() / 10, "{} bytes", stored.len());
+
+        assert_eq!(store.get("small").await.unwrap(), Some(small));
+        assert_eq!(store.get("large").await.unwrap(), Some(large.clone()));
+        assert_eq!(store.get("missing").await.unwrap(), None);
+
+        // Without compression the same value is stored as it is
+        let uncompressed = RedisStore::<Entry>::new(backend.clone(), "plain");
+        uncompressed.set("large", &large).await.unwrap();
+        assert_eq!(backend.get(&uncompressed.key("large")).await.unwrap().unwrap()[0], PLAIN);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_values_expire_after_their_ttl() {
+        let backend = Arc::new(MemoryBackend::new());
+        let store = store(&backend).with_ttl(Duration::from_secs(60));
+        store.set("default", &entry("default", 10)).await.unwrap();
+        store.set_with_ttl("short", &entry("short", 10), Some(Duration::from_secs(5))).await.unwrap();
+        store.set_with_ttl("forever", &entry("forever", 10), None).await.unwrap();
+        assert!(!store.insert_if_absent("short", &entry("again", 10), None).await.unwrap());
+
+        tokio::time::advance(Duration::from_secs(6)).await;
+        assert_eq!(store.get("short").await.unwrap(), None);
+        assert!(store.get("default").await.unwrap().is_some());
+        assert!(store.insert_if_absent("short", &entry("again", 10), Some(Duration::from_secs(5))).await.unwrap());
+
+        tokio::time::advance(Duration::from_secs(60)).await;
+        let keys: Vec<String> = store.scan().await.unwrap().into_iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, ["forever"]);
+        assert!(store.delete("forever").await.unwrap());
+        assert!(store.keys().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_values_read_as_misses_and_are_evicted() {
+        let backend = Arc::new(MemoryBackend::new());
+        let store = store(&backend);
+        store.set("good", &entry("good", 10)).await.unwrap();
+        let corrupt: [(&str, Vec<u8>); 4] = [
+            ("truncated", vec![ZSTD, 0x28, 0xb5, 0x2f]),
+            ("unknown-header", b"{\"name\":\"x\",\"body\":\"y\"}".to_vec()),
+            ("other-shape", [&[PLAIN][..], br#"{"title":"renamed"}"#].concat()),
+            ("empty", Vec::new()),
+        ];
+        for (key, value) in &corrupt {
+            backend.set(&store.key(key), value.clone(), None).await.unwrap();
+        }
+
+        for (key, _) in &corrupt[..2] {
+            assert_eq!(store.get(key).await.unwrap(), None, "{}", key);
+            assert_eq!(backend.get(&store.key(key)).await.unwrap(), None, "{}", key);
+        }
+        // Scanning evicts the rest and returns only what still reads
+        let entries = store.scan().await.unwrap();
+        assert_eq!(entries, [("good".to_string(), entry("good", 10))]);
+        assert_eq!(backend.scan("talkpp:").await.unwrap(), ["talkpp:entries:good"]);
+    }
+
+    #[test]
+    fn test_scan_patterns_match_the_prefix_literally() {
+        assert_eq!(scan_pattern("talkpp:sessions:"), "talkpp:sessions:*");
+        assert_eq!(scan_pattern("a*b?[c]\\"), "a\\*b\\?\\[c\\]\\\\*");
+    }
+
+    /// Run with `TEST_REDIS_URL=redis://localhost:6379 cargo test -- --ignored`
+    #[tokio::test]
+    #[ignore = "needs a Redis server at TEST_REDIS_URL"]
+    async fn test_redis_round_trip_and_eviction() {
+        let url = std::env::var("TEST_REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379".to_string());
+        let backend: Arc<dyn KeyValueBackend> = Arc::new(RedisBackend::new(&redis::Client::open(url.as_str()).unwrap()).await.unwrap());
+        let store = RedisStore::<Entry>::new(backend.clone(), format!("test-cache-{}", uuid::Uuid::new_v4()))
+            .with_compression(256, 3)
+            .with_ttl(Duration::from_secs(60));
+
+        let large = entry("large", 10_000);
+        store.set("large", &large).await.unwrap();
+        assert!(store.insert_if_absent("new", &entry("new", 10), Some(Duration::from_millis(50))).await.unwrap());
+        assert!(!store.insert_if_absent("new", &entry("new", 10), None).await.unwrap());
+        backend.set(&store.key("corrupt"), vec![ZSTD, 1, 2, 3], None).await.unwrap();
+
+        assert_eq!(store.get("large").await.unwrap(), Some(large));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let keys: Vec<String> = store.scan().await.unwrap().into_iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, ["large"]);
+        assert_eq!(backend.get(&store.key("corrupt")).await.unwrap(), None);
+        store.delete("large").await.unwrap();
+    }
+}
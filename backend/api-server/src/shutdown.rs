@@ -0,0 +1,339 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::{timeout_at, Instant};
+use tracing::{error, info, warn};
+
+/// Phases of a shutdown, run in declaration order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ShutdownStage {
+    /// Stop accepting requests and let in-flight ones finish
+    Requests,
+    /// Persist in-flight plan, task and memory state
+    FlushState,
+    /// Stop schedulers, monitors and other background loops
+    Schedulers,
+    /// Close database and cache pools
+    Pools,
+}
+
+const STAGES: [ShutdownStage; 4] = [
+    ShutdownStage::Requests,
+    ShutdownStage::FlushState,
+    ShutdownStage::Schedulers,
+    ShutdownStage::Pools,
+];
+
+/// What happened to each registered task and hook, by name
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// Work that finished, in the order it did
+    pub completed: Vec<String>,
+    /// Work that panicked or returned an error, with the reason
+    pub failed: Vec<(String, String)>,
+    /// Work still running or not yet started when the deadline passed
+    pub aborted: Vec<String>,
+}
+
+type Hook = Box<dyn FnOnce() -> Pin<Box<dyn Future<Output = Result<()>> + Send>> + Send>;
+
+enum Work {
+    /// A spawned task that exits once its stage is signalled
+    Task(JoinHandle<()>),
+    /// A future run when its stage is reached, e.g. a component's `shutdown()`
+    Hook(Hook),
+}
+
+struct Registered {
+    stage: ShutdownStage,
+    name: String,
+    work: Work,
+}
+
+struct Inner {
+    stages: Vec<watch::Sender<bool>>,
+    requested: watch::Sender<bool>,
+    registered: Mutex<Vec<Registered>>,
+    deadline: Duration,
+}
+
+/// Coordinates an ordered shutdown of long-running components.
+///
+/// Components either run as tasks spawned through the handle, which receive a signal
+/// that turns `true` when their stage begins, or register a hook to run at their stage.
+/// `shutdown` walks the stages in order, waiting for each stage's tasks and then its
+/// hooks before moving on. Once the deadline passes, remaining tasks are aborted and
+/// remaining hooks skipped.
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    inner: Arc<Inner>,
+}
+
+impl ShutdownHandle {
+    pub fn new(deadline: Duration) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                stages: STAGES.iter().map(|_| watch::channel(false).0).collect(),
+                requested: watch::channel(false).0,
+                registered: Mutex::new(Vec::new()),
+                deadline,
+            }),
+        }
+    }
+
+    /// Receiver that turns `true` when `stage` begins
+    pub fn signal(&self, stage: ShutdownStage) -> watch::Receiver<bool> {
+        self.inner.stages[stage as usize].subscribe()
+    }
+
+    /// Resolves when `stage` begins
+    pub fn triggered(&self, stage: ShutdownStage) -> impl Future<Output = ()> + Send + 'static {
+        wait_for_signal(self.signal(stage))
+    }
+
+    /// Spawn a task that must stop once `stage` is signalled
+    pub fn spawn<F, Fut>(&self, stage: ShutdownStage, name: impl Into<String>, task: F)
+    where
+        F: FnOnce(watch::Receiver<bool>) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let handle = tokio::spawn(task(self.signal(stage)));
+        self.register(stage, name.into(), Work::Task(handle));
+    }
+
+    /// Run `hook` when `stage` is reached
+    pub fn on_shutdown<F, Fut>(&self, stage: ShutdownStage, name: impl Into<String>, hook: F)
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let hook: Hook = Box::new(move || Box::pin(hook()));
+        self.register(stage, name.into(), Work::Hook(hook));
+    }
+
+    /// Ask for a shutdown from inside the process, e.g. after a fatal component error
+    pub fn request(&self) {
+        self.inner.requested.send_replace(true);
+    }
+
+    /// Resolves once `request` has been called
+    pub async fn requested(&self) {
+        wait_for_signal(self.inner.requested.subscribe()).await
+    }
+
+    /// Run every stage in order, bounded by the deadline
+    pub async fn shutdown(&self) -> ShutdownReport {
+        let deadline = Instant::now() + self.inner.deadline;
+        let mut pending = std::mem::take(&mut *self.inner.registered.lock().unwrap());
+        let mut report = ShutdownReport::default();
+        let mut expired = false;
+
+        for stage in STAGES {
+            info!("Shutdown stage {:?}", stage);
+            self.inner.stages[stage as usize].send_replace(true);
+
+            let (current, rest): (Vec<_>, Vec<_>) = pending.into_iter().partition(|r| r.stage == stage);
+            pending = rest;
+
+            // Tasks were signalled together and drain concurrently, so wait on them before the hooks
+            let (tasks, hooks): (Vec<_>, Vec<_>) = current.into_iter().partition(|r| matches!(r.work, Work::Task(_)));
+            for Registered { name, work, .. } in tasks.into_iter().chain(hooks) {
+                if expired {
+                    abort(&mut report, name, work);
+                    continue;
+                }
+                match work {
+                    Work::Task(mut handle) => match timeout_at(deadline, &mut handle).await {
+                        Ok(Ok(())) => complete(&mut report, name),
+                        Ok(Err(e)) => fail(&mut report, name, e.to_string()),
+                        Err(_) => {
+                            expired = true;
+                            abort(&mut report, name, Work::Task(handle));
+                        }
+                    },
+                    Work::Hook(hook) => match timeout_at(deadline, hook()).await {
+                        Ok(Ok(())) => complete(&mut report, name),
+                        Ok(Err(e)) => fail(&mut report, name, e.to_string()),
+                        Err(_) => {
+                            expired = true;
+                            warn!("Shutdown deadline passed while running '{}'", name);
+                            report.aborted.push(name);
+                        }
+                    },
+                }
+            }
+        }
+
+        info!(
+            "Shutdown finished: {} completed, {} failed, {} aborted",
+            report.completed.len(),
+            report.failed.len(),
+            report.aborted.len()
+        );
+        report
+    }
+
+    fn register(&self, stage: ShutdownStage, name: String, work: Work) {
+        self.inner.registered.lock().unwrap().push(Registered { stage, name, work });
+    }
+}
+
+async fn wait_for_signal(mut signal: watch::Receiver<bool>) {
+    // A dropped sender means the handle is gone, which is as good as a signal
+    let _ = signal.wait_for(|signalled| *signalled).await;
+}
+
+fn complete(report: &mut ShutdownReport, name: String) {
+    info!("'{}' shut down", name);
+    report.completed.push(name);
+}
+
+fn fail(report: &mut ShutdownReport, name: String, reason: String) {
+    error!("'{}' failed to shut down: {}", name, reason);
+    report.failed.push((name, reason));
+}
+
+fn abort(report: &mut ShutdownReport, name: String, work: Work) {
+    match work {
+        Work::Task(handle) => {
+            handle.abort();
+            warn!("Aborted '{}' at the shutdown deadline", name);
+        }
+        Work::Hook(_) => warn!("Skipped '{}' at the shutdown deadline", name),
+    }
+    report.aborted.push(name);
+}
+
+/// Resolves on SIGINT, or SIGTERM on unix
+pub async fn termination_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.expect("Failed to listen for SIGINT");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => info!("Received SIGINT"),
+        _ = terminate => info!("Received SIGTERM"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Log = Arc<Mutex<Vec<String>>>;
+
+    fn push(log: &Log, entry: &str) {
+        log.lock().unwrap().push(entry.to_string());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stages_drain_in_order() {
+        let shutdown = ShutdownHandle::new(Duration::from_secs(30));
+        let log = Log::default();
+
+        // Mock server: drains in-flight requests after the signal, before schedulers are told to stop
+        let scheduler_signal = shutdown.signal(ShutdownStage::Schedulers);
+        shutdown.spawn(ShutdownStage::Requests, "server", {
+            let log = log.clone();
+            |stop| async move {
+                wait_for_signal(stop).await;
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                assert!(!*scheduler_signal.borrow());
+                push(&log, "server drained");
+            }
+        });
+        shutdown.spawn(ShutdownStage::Schedulers, "scheduler", {
+            let log = log.clone();
+            |mut stop| async move {
+                let mut ticks = tokio::time::interval(Duration::from_secs(1));
+                loop {
+                    tokio::select! {
+                        _ = ticks.tick() => {}
+                        _ = stop.wait_for(|s| *s) => break,
+                    }
+                }
+                push(&log, "scheduler stopped");
+            }
+        });
+        shutdown.on_shutdown(ShutdownStage::Pools, "pools", {
+            let log = log.clone();
+            move || async move {
+                push(&log, "pools closed");
+                Ok(())
+            }
+        });
+        shutdown.on_shutdown(ShutdownStage::FlushState, "memory", {
+            let log = log.clone();
+            move || async move {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                push(&log, "state flushed");
+                Ok(())
+            }
+        });
+        shutdown.on_shutdown(ShutdownStage::FlushState, "broken", || async { Err(anyhow::anyhow!("disk full")) });
+
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        let report = shutdown.shutdown().await;
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["server drained", "state flushed", "scheduler stopped", "pools closed"]
+        );
+        assert_eq!(report.completed, vec!["server", "memory", "scheduler", "pools"]);
+        assert_eq!(report.failed, vec![("broken".to_string(), "disk full".to_string())]);
+        assert!(report.aborted.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_deadline_aborts_remaining_work() {
+        let shutdown = ShutdownHandle::new(Duration::from_secs(2));
+        let log = Log::default();
+
+        shutdown.spawn(ShutdownStage::Requests, "server", wait_for_signal);
+        // Ignores its signal entirely
+        shutdown.spawn(ShutdownStage::Schedulers, "stuck", |_| std::future::pending());
+        shutdown.on_shutdown(ShutdownStage::Pools, "pools", {
+            let log = log.clone();
+            move || async move {
+                push(&log, "pools closed");
+                Ok(())
+            }
+        });
+
+        let started = Instant::now();
+        let report = shutdown.shutdown().await;
+
+        assert_eq!(started.elapsed(), Duration::from_secs(2));
+        assert_eq!(report.completed, vec!["server"]);
+        assert_eq!(report.aborted, vec!["stuck", "pools"]);
+        assert!(log.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_request_resolves_requested() {
+        let shutdown = ShutdownHandle::new(Duration::from_secs(1));
+        let waiter = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.requested().await }
+        });
+
+        shutdown.request();
+        tokio::time::timeout(Duration::from_secs(1), waiter).await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,192 @@
+use anyhow::Result;
+use axum::http::Request;
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use jarvis_core::telemetry::{self, TRACEPARENT};
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{trace as sdktrace, Resource};
+use tracing::Span;
+use tracing_subscriber::{filter::LevelFilter, layer::SubscriberExt, util::SubscriberInitExt};
+
+use crate::config::ObservabilityConfig;
+
+/// Service name spans are exported under
+const SERVICE_NAME: &str = "talkpp-api-server";
+
+/// Install JSON logging and, with `jaeger_endpoint` set, export spans there over OTLP.
+/// Jaeger accepts OTLP directly, normally on port 4317.
+pub fn init(config: &ObservabilityConfig) -> Result<()> {
+    let otel = match &config.jaeger_endpoint {
+        Some(endpoint) => Some(tracing_opentelemetry::layer().with_tracer(otlp_tracer(endpoint)?)),
+        None => None,
+    };
+    tracing_subscriber::registry()
+        .with(LevelFilter::INFO)
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_target(false)
+                .with_thread_ids(true)
+                .with_file(true)
+                .with_line_number(true)
+                .json(),
+        )
+        .with(otel)
+        .try_init()?;
+    Ok(())
+}
+
+/// Record metrics for `GET /metrics` to render in Prometheus' text format
+pub fn init_metrics() -> Result<PrometheusHandle> {
+    Ok(PrometheusBuilder::new().install_recorder()?)
+}
+
+fn otlp_tracer(endpoint: &str) -> Result<sdktrace::Tracer> {
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(opentelemetry_otlp::new_exporter().tonic().with_endpoint(endpoint))
+        .with_trace_config(sdktrace::config().with_resource(Resource::new([KeyValue::new("service.name", SERVICE_NAME)])))
+        .install_batch(opentelemetry_sdk::runtime::Tokio)?;
+    Ok(tracer)
+}
+
+/// Export the spans still buffered, e.g. on shutdown
+pub async fn flush() {
+    let _ = tokio::task::spawn_blocking(opentelemetry::global::shutdown_tracer_provider).await;
+}
+
+/// Span an HTTP request is served in, continuing the caller's trace when the request has
+/// a `traceparent` header and starting a new one otherwise
+pub fn request_span<B>(request: &Request<B>) -> Span {
+    let span = tracing::info_span!("http_request", method = %request.method(), uri = %request.uri());
+    telemetry::set_parent(&span, request.headers().get(TRACEPARENT).and_then(|value| value.to_str().ok()));
+    span
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use super::*;
+    use async_trait::async_trait;
+    use axum::{body::Body, extract::State, routing::post, Json, Router};
+    use chrono::Utc;
+    use jarvis_core::{
+        CognitiveKernel, ExecutionTask, PlanExecutor, TaskOutput, TaskRunner, TaskStatus, TaskUsage,
+    };
+    use opentelemetry::trace::{SpanId, TraceId, TracerProvider as _};
+    use opentelemetry_sdk::export::trace::SpanData;
+    use opentelemetry_sdk::testing::trace::InMemorySpanExporter;
+    use serde_json::json;
+    use talkpp_mcp_hub::{LocalMcpServer, McpConnection, McpHub, McpServerConfig};
+    use tower::ServiceExt;
+    use tower_http::trace::TraceLayer;
+    use uuid::Uuid;
+
+    use crate::UserSession;
+
+    const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+    const CALLER_SPAN_ID: &str = "00f067aa0ba902b7";
+
+    /// Runs every task as a call to the hub's `remember` tool
+    struct ToolRunner(Arc<McpHub>);
+
+    #[async_trait]
+    impl TaskRunner for ToolRunner {
+        async fn validate(&self, _task: &ExecutionTask) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        async fn run(&self, task: &ExecutionTask) -> anyhow::Result<TaskOutput> {
+            self.0.call_tool("remember", json!({ "note": task.name })).await?;
+            let outputs = task.expected_outputs.iter().map(|name| (name.clone(), json!({}))).collect();
+            Ok(TaskOutput { status: TaskStatus::Completed, outputs, usage: TaskUsage::default() })
+        }
+    }
+
+    /// Plan the intent for a session user and execute the plan straight away
+    async fn run_intent(State(hub): State<Arc<McpHub>>, Json(intent): Json<String>) -> Json<String> {
+        let session = UserSession {
+            user_id: Uuid::new_v4(),
+            session_id: Uuid::new_v4(),
+            created_at: Utc::now(),
+            last_activity: Utc::now(),
+            permissions: Vec::new(),
+            tenant_id: talkpp_tenancy::default_tenant_id(),
+        };
+        let kernel = CognitiveKernel::new();
+        let (_, mut plan) = kernel.plan_intent(&intent, Some(session.intent_context())).await.unwrap();
+        let outcome = PlanExecutor::new(ToolRunner(hub)).execute(&mut plan).await.unwrap();
+        Json(format!("{:?}", outcome.state))
+    }
+
+    fn span<'a>(spans: &'a [SpanData], name: &str) -> &'a SpanData {
+        spans.iter().find(|span| span.name == name).unwrap_or_else(|| panic!("no {} span", name))
+    }
+
+    fn attribute(span: &SpanData, key: &str) -> Option<String> {
+        span.attributes.iter().find(|kv| kv.key.as_str() == key).map(|kv| kv.value.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_one_intent_is_one_trace_across_kernel_and_mcp() {
+        let server = LocalMcpServer::new("notes").with_tool(
+            "remember",
+            "Store a note",
+            json!({"type": "object", "properties": {"note": {"type": "string"}}, "required": ["note"]}),
+            |_| async { Ok(json!("stored")) },
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(server.serve(listener));
+        let hub = Arc::new(McpHub::new());
+        hub.register_server(McpServerConfig::new("notes", McpConnection::Http { url, headers: HashMap::new() })).await.unwrap();
+
+        // Traced from here on, on this thread and so across the test's whole runtime
+        let exporter = InMemorySpanExporter::default();
+        let provider = sdktrace::TracerProvider::builder().with_simple_exporter(exporter.clone()).build();
+        let subscriber = tracing_subscriber::registry()
+            .with(LevelFilter::INFO)
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        let traced = tracing::subscriber::set_default(subscriber);
+
+        let app = Router::new()
+            .route("/intents", post(run_intent))
+            .with_state(hub)
+            .layer(TraceLayer::new_for_http().make_span_with(request_span));
+        let request = axum::http::Request::post("/intents")
+            .header("content-type", "application/json")
+            .header(TRACEPARENT, format!("00-{}-{}-01", TRACE_ID, CALLER_SPAN_ID))
+            .body(Body::from(r#""Deploy the marketing website to staging""#))
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body.as_ref(), br#""Completed""#);
+        drop(traced);
+        provider.force_flush();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let trace_id = TraceId::from_hex(TRACE_ID).unwrap();
+        assert!(spans.iter().all(|span| span.span_context.trace_id() == trace_id));
+
+        let parent_of = |child: &str, parent: &str| {
+            assert_eq!(span(&spans, child).parent_span_id, span(&spans, parent).span_context.span_id(), "{} under {}", child, parent);
+        };
+        assert_eq!(span(&spans, "http_request").parent_span_id, SpanId::from_hex(CALLER_SPAN_ID).unwrap());
+        parent_of("plan_intent", "http_request");
+        parent_of("execute_plan", "http_request");
+        parent_of("run_task", "execute_plan");
+        parent_of("mcp_tool_call", "run_task");
+        parent_of("mcp_http_request", "mcp_tool_call");
+        // The tool server only learns of the trace from the request's headers
+        parent_of("mcp_serve", "mcp_http_request");
+
+        let plan_id = attribute(span(&spans, "execute_plan"), "plan_id");
+        assert!(plan_id.is_some());
+        assert_eq!(attribute(span(&spans, "plan_intent"), "plan_id"), plan_id);
+        assert_eq!(attribute(span(&spans, "plan_intent"), "intent_id"), attribute(span(&spans, "execute_plan"), "intent_id"));
+        let task = span(&spans, "run_task");
+        assert_eq!(attribute(task, "plan_id"), plan_id);
+        assert!(attribute(task, "task_id").is_some() && attribute(task, "agent_id").is_some());
+    }
+}
@@ -5,6 +5,7 @@
 pub mod jwt;
 pub mod oauth;
 pub mod secrets;
+pub mod session;
 pub mod user;
 
 use anyhow::Result;
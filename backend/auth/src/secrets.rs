@@ -0,0 +1,453 @@
+//! Secret resolution for service configuration.
+//!
+//! Configs may hold references such as `env:ANTHROPIC_API_KEY` or
+//! `vault:ai/anthropic#api_key` instead of literal secrets; a [`SecretResolver`] turns
+//! them into [`SecretString`]s using the provider registered for the prefix.
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Key, Nonce,
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+/// Environment variable holding the base64-encoded 256-bit key of the encrypted file store
+pub const MASTER_KEY_ENV: &str = "TALKPP_MASTER_KEY";
+
+/// Field read from a Vault secret when the reference names none
+pub const DEFAULT_VAULT_FIELD: &str = "value";
+
+/// A secret value whose `Debug` output never shows the value itself
+#[derive(Clone, PartialEq, Eq)]
+pub struct SecretString(String);
+
+impl SecretString {
+    pub fn new(value: impl Into<String>) -> Self {
+        Self(value.into())
+    }
+
+    /// The plaintext, for handing to the client that needs it
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretString(********)")
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+/// A source of secrets addressed by key
+#[async_trait]
+pub trait SecretsProvider: Send + Sync {
+    async fn get(&self, key: &str) -> Result<SecretString>;
+}
+
+#[async_trait]
+impl<T: SecretsProvider + ?Sized> SecretsProvider for Arc<T> {
+    async fn get(&self, key: &str) -> Result<SecretString> {
+        (**self).get(key).await
+    }
+}
+
+/// Wraps a provider so each key is fetched at most once per `ttl`
+pub struct CachedSecrets<P> {
+    inner: P,
+    ttl: Duration,
+    cache: Mutex<HashMap<String, (SecretString, Instant)>>,
+}
+
+impl<P: SecretsProvider> CachedSecrets<P> {
+    pub fn new(inner: P, ttl: Duration) -> Self {
+        Self { inner, ttl, cache: Mutex::new(HashMap::new()) }
+    }
+
+    /// Drop every cached value, e.g. after a rotation
+    pub async fn invalidate(&self) {
+        self.cache.lock().await.clear();
+    }
+}
+
+#[async_trait]
+impl<P: SecretsProvider> SecretsProvider for CachedSecrets<P> {
+    async fn get(&self, key: &str) -> Result<SecretString> {
+        if let Some((value, fetched_at)) = self.cache.lock().await.get(key) {
+            if fetched_at.elapsed() < self.ttl {
+                return Ok(value.clone());
+            }
+        }
+
+        let value = self.inner.get(key).await?;
+        self.cache.lock().await.insert(key.to_string(), (value.clone(), Instant::now()));
+        Ok(value)
+    }
+}
+
+/// Resolves `<scheme>:<key>` references through the provider registered for the scheme.
+/// Values without a registered scheme are literals and pass through unchanged.
+#[derive(Default, Clone)]
+pub struct SecretResolver {
+    providers: HashMap<String, Arc<dyn SecretsProvider>>,
+}
+
+impl SecretResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_provider(mut self, scheme: impl Into<String>, provider: Arc<dyn SecretsProvider>) -> Self {
+        self.providers.insert(scheme.into(), provider);
+        self
+    }
+
+    /// Whether `value` names a secret rather than being one
+    pub fn is_reference(&self, value: &str) -> bool {
+        self.split(value).is_some()
+    }
+
+    pub async fn resolve(&self, value: &str) -> Result<SecretString> {
+        match self.split(value) {
+            Some((scheme, key, provider)) => provider.get(key).await
+                .map_err(|e| anyhow::anyhow!("Failed to resolve {}:{}: {}", scheme, key, e)),
+            None => Ok(SecretString::new(value)),
+        }
+    }
+
+    /// Replace a reference held in a config field with the secret it names
+    pub async fn resolve_in_place(&self, value: &mut String) -> Result<()> {
+        if self.is_reference(value) {
+            *value = self.resolve(value).await?.expose().to_string();
+        }
+        Ok(())
+    }
+
+    fn split<'a>(&'a self, value: &'a str) -> Option<(&'a str, &'a str, &'a Arc<dyn SecretsProvider>)> {
+        let (scheme, key) = value.split_once(':')?;
+        self.providers.get(scheme).map(|provider| (scheme, key, provider))
+    }
+}
+
+/// Reads secrets from environment variables named by the key
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EnvSecrets;
+
+#[async_trait]
+impl SecretsProvider for EnvSecrets {
+    async fn get(&self, key: &str) -> Result<SecretString> {
+        std::env::var(key)
+            .map(SecretString::from)
+            .map_err(|_| anyhow::anyhow!("Environment variable {} is not set", key))
+    }
+}
+
+/// The base64-encoded 256-bit key in `TALKPP_MASTER_KEY`
+pub fn master_key_from_env() -> Result<[u8; 32]> {
+    let encoded = std::env::var(MASTER_KEY_ENV)
+        .map_err(|_| anyhow::anyhow!("{} is not set", MASTER_KEY_ENV))?;
+    let bytes = BASE64.decode(encoded.trim())
+        .map_err(|e| anyhow::anyhow!("{} is not valid base64: {}", MASTER_KEY_ENV, e))?;
+    bytes.try_into()
+        .map_err(|_| anyhow::anyhow!("{} must decode to 32 bytes", MASTER_KEY_ENV))
+}
+
+/// A nonce and the ciphertext sealed with it, both base64-encoded
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sealed {
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+/// AES-256-GCM with a fresh random 96-bit nonce for every message. Everything sealed at
+/// rest with the master key goes through this.
+pub struct SealingKey {
+    cipher: Aes256Gcm,
+}
+
+impl SealingKey {
+    pub fn new(key: &[u8; 32]) -> Self {
+        Self { cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)) }
+    }
+
+    /// The key from `TALKPP_MASTER_KEY`
+    pub fn from_env() -> Result<Self> {
+        Ok(Self::new(&master_key_from_env()?))
+    }
+
+    pub fn seal(&self, plaintext: &[u8]) -> Result<Sealed> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self.cipher.encrypt(&nonce, plaintext)
+            .map_err(|_| anyhow::anyhow!("Encryption failed"))?;
+        Ok(Sealed { nonce: BASE64.encode(nonce), ciphertext: BASE64.encode(ciphertext) })
+    }
+
+    /// The plaintext of `sealed`. GCM authenticates the ciphertext, so tampering or the
+    /// wrong key surfaces as an error here.
+    pub fn open(&self, sealed: &Sealed) -> Result<Vec<u8>> {
+        let nonce = BASE64.decode(&sealed.nonce)?;
+        if nonce.len() != 12 {
+            return Err(anyhow::anyhow!("Invalid nonce length"));
+        }
+        let ciphertext = BASE64.decode(&sealed.ciphertext)?;
+        self.cipher.decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+            .map_err(|_| anyhow::anyhow!("Ciphertext failed authentication"))
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SecretsFile {
+    secrets: BTreeMap<String, Sealed>,
+}
+
+/// Secrets sealed with AES-256-GCM in a JSON file, one entry per key
+pub struct EncryptedFileSecrets {
+    path: PathBuf,
+    key: SealingKey,
+    // Serializes read-modify-write cycles of `put`
+    lock: Mutex<()>,
+}
+
+impl EncryptedFileSecrets {
+    pub fn new(path: impl Into<PathBuf>, key: &[u8; 32]) -> Self {
+        Self {
+            path: path.into(),
+            key: SealingKey::new(key),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Open the store at `path` with the key from `TALKPP_MASTER_KEY`
+    pub fn from_env(path: impl Into<PathBuf>) -> Result<Self> {
+        Ok(Self::new(path, &master_key_from_env()?))
+    }
+
+    /// Seal `value` under `key`, replacing any previous value
+    pub async fn put(&self, key: &str, value: &SecretString) -> Result<()> {
+        let _guard = self.lock.lock().await;
+        let mut file = self.read().await?;
+        file.secrets.insert(key.to_string(), self.key.seal(value.expose().as_bytes())?);
+
+        if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&self.path, serde_json::to_vec_pretty(&file)?).await?;
+        Ok(())
+    }
+
+    async fn read(&self) -> Result<SecretsFile> {
+        match tokio::fs::read(&self.path).await {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(SecretsFile::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[async_trait]
+impl SecretsProvider for EncryptedFileSecrets {
+    async fn get(&self, key: &str) -> Result<SecretString> {
+        let file = self.read().await?;
+        let sealed = file.secrets.get(key)
+            .ok_or_else(|| anyhow::anyhow!("No secret named {} in {}", key, self.path.display()))?;
+
+        let plaintext = self.key.open(sealed)
+            .map_err(|e| anyhow::anyhow!("Secret {}: {}", key, e))?;
+        Ok(SecretString::new(String::from_utf8(plaintext)?))
+    }
+}
+
+/// How `VaultSecrets` logs in
+#[derive(Clone)]
+pub enum VaultAuth {
+    Token(SecretString),
+    AppRole { role_id: String, secret_id: SecretString },
+}
+
+/// HashiCorp Vault KV v2. Keys are `<path>#<field>`, read from `<mount>/data/<path>`;
+/// the field defaults to `value`.
+pub struct VaultSecrets {
+    addr: String,
+    mount: String,
+    auth: VaultAuth,
+    client: reqwest::Client,
+    // AppRole login token and when it stops being usable
+    token: Mutex<Option<(SecretString, Instant)>>,
+}
+
+impl VaultSecrets {
+    pub fn new(addr: impl Into<String>, mount: impl Into<String>, auth: VaultAuth) -> Self {
+        Self {
+            addr: addr.into().trim_end_matches('/').to_string(),
+            mount: mount.into().trim_matches('/').to_string(),
+            auth,
+            client: reqwest::Client::new(),
+            token: Mutex::new(None),
+        }
+    }
+
+    async fn token(&self) -> Result<SecretString> {
+        let (role_id, secret_id) = match &self.auth {
+            VaultAuth::Token(token) => return Ok(token.clone()),
+            VaultAuth::AppRole { role_id, secret_id } => (role_id, secret_id),
+        };
+
+        let mut cached = self.token.lock().await;
+        if let Some((token, expires_at)) = cached.as_ref() {
+            if Instant::now() < *expires_at {
+                return Ok(token.clone());
+            }
+        }
+
+        let response: serde_json::Value = self.client
+            .post(format!("{}/v1/auth/approle/login", self.addr))
+            .json(&serde_json::json!({ "role_id": role_id, "secret_id": secret_id.expose() }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let token = response["auth"]["client_token"].as_str()
+            .ok_or_else(|| anyhow::anyhow!("Vault AppRole login returned no token"))?;
+        // Log in again a little before the lease runs out
+        let lease = response["auth"]["lease_duration"].as_u64().unwrap_or(0);
+        let expires_at = Instant::now() + Duration::from_secs(lease.saturating_sub(30));
+
+        let token = SecretString::new(token);
+        *cached = Some((token.clone(), expires_at));
+        Ok(token)
+    }
+}
+
+#[async_trait]
+impl SecretsProvider for VaultSecrets {
+    async fn get(&self, key: &str) -> Result<SecretString> {
+        let (path, field) = key.split_once('#').unwrap_or((key, DEFAULT_VAULT_FIELD));
+        let response = self.client
+            .get(format!("{}/v1/{}/data/{}", self.addr, self.mount, path.trim_start_matches('/')))
+            .header("X-Vault-Token", self.token().await?.expose())
+            .send()
+            .await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(anyhow::anyhow!("No Vault secret at {}/{}", self.mount, path));
+        }
+
+        let mut payload: serde_json::Value = response.error_for_status()?.json().await?;
+        match payload["data"]["data"][field].take() {
+            serde_json::Value::String(value) => Ok(SecretString::new(value)),
+            serde_json::Value::Null => Err(anyhow::anyhow!("Vault secret {}/{} has no field {}", self.mount, path, field)),
+            other => Ok(SecretString::new(other.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Returns `<key>-<n>` where n counts fetches
+    #[derive(Default)]
+    struct CountingSecrets {
+        fetches: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl SecretsProvider for CountingSecrets {
+        async fn get(&self, key: &str) -> Result<SecretString> {
+            let n = self.fetches.fetch_add(1, Ordering::SeqCst) + 1;
+            Ok(SecretString::new(format!("{}-{}", key, n)))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolves_references_and_passes_literals_through() {
+        std::env::set_var("TALKPP_TEST_SECRET_REF", "from-env");
+        let resolver = SecretResolver::new()
+            .with_provider("env", Arc::new(EnvSecrets))
+            .with_provider("test", Arc::new(CountingSecrets::default()));
+
+        assert_eq!(resolver.resolve("env:TALKPP_TEST_SECRET_REF").await.unwrap().expose(), "from-env");
+        assert_eq!(resolver.resolve("test:api_key").await.unwrap().expose(), "api_key-1");
+        assert_eq!(resolver.resolve("sk-literal").await.unwrap().expose(), "sk-literal");
+        // An unregistered scheme is not a reference
+        assert_eq!(resolver.resolve("vault:ai#key").await.unwrap().expose(), "vault:ai#key");
+
+        let mut field = "env:TALKPP_TEST_SECRET_REF".to_string();
+        resolver.resolve_in_place(&mut field).await.unwrap();
+        assert_eq!(field, "from-env");
+
+        let missing = resolver.resolve("env:TALKPP_TEST_SECRET_UNSET").await.unwrap_err();
+        assert!(missing.to_string().contains("TALKPP_TEST_SECRET_UNSET is not set"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cache_expires_after_ttl() {
+        let counting = Arc::new(CountingSecrets::default());
+        let cached = CachedSecrets::new(counting.clone(), Duration::from_secs(60));
+
+        assert_eq!(cached.get("db").await.unwrap().expose(), "db-1");
+        tokio::time::advance(Duration::from_secs(59)).await;
+        assert_eq!(cached.get("db").await.unwrap().expose(), "db-1");
+        tokio::time::advance(Duration::from_secs(2)).await;
+        assert_eq!(cached.get("db").await.unwrap().expose(), "db-2");
+
+        cached.invalidate().await;
+        assert_eq!(cached.get("db").await.unwrap().expose(), "db-3");
+        assert_eq!(counting.fetches.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_file_round_trip_and_redacted_debug() {
+        let path = std::env::temp_dir().join(format!("talkpp-secrets-{}.json", uuid::Uuid::new_v4()));
+        let store = EncryptedFileSecrets::new(&path, &[5u8; 32]);
+        store.put("smtp_password", &SecretString::new("hunter2")).await.unwrap();
+
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("hunter2"));
+        let secret = store.get("smtp_password").await.unwrap();
+        assert_eq!(secret.expose(), "hunter2");
+        assert_eq!(format!("{:?}", secret), "SecretString(********)");
+
+        let wrong_key = EncryptedFileSecrets::new(&path, &[6u8; 32]);
+        assert!(wrong_key.get("smtp_password").await.is_err());
+        assert!(store.get("missing").await.is_err());
+        std::fs::remove_file(path).ok();
+    }
+
+    /// Against `vault server -dev -dev-root-token-id=root` on VAULT_ADDR
+    /// (default http://127.0.0.1:8200)
+    #[cfg(feature = "vault-tests")]
+    #[tokio::test]
+    async fn test_vault_kv_v2_against_dev_server() {
+        let addr = std::env::var("VAULT_ADDR").unwrap_or_else(|_| "http://127.0.0.1:8200".to_string());
+        let client = reqwest::Client::new();
+        client.post(format!("{}/v1/secret/data/talkpp-test", addr))
+            .header("X-Vault-Token", "root")
+            .json(&serde_json::json!({ "data": { "value": "s3cret", "api_key": "sk-123" } }))
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+
+        let vault = VaultSecrets::new(addr, "secret", VaultAuth::Token(SecretString::new("root")));
+        assert_eq!(vault.get("talkpp-test").await.unwrap().expose(), "s3cret");
+        assert_eq!(vault.get("talkpp-test#api_key").await.unwrap().expose(), "sk-123");
+        assert!(vault.get("talkpp-test#missing").await.is_err());
+        assert!(vault.get("talkpp-absent").await.is_err());
+    }
+}
@@ -3,6 +3,23 @@ use anyhow::Result;
 use serde_json::json;
 use std::collections::HashMap;
 use tracing::{info, error};
+use uuid::Uuid;
+
+/// Recipients of an outgoing email given as `{"to": [...], "subject": ..., "html": ..., "text": ...}`,
+/// refusing addresses that could smuggle in extra headers
+pub(crate) fn outgoing_recipients(data: &serde_json::Value) -> Result<Vec<String>> {
+    let recipients: Vec<String> = data.get("to")
+        .and_then(|to| to.as_array())
+        .map(|to| to.iter().filter_map(|address| address.as_str().map(str::to_string)).collect())
+        .unwrap_or_default();
+    if recipients.is_empty() {
+        anyhow::bail!("Email has no recipients");
+    }
+    if let Some(bad) = recipients.iter().find(|address| !address.contains('@') || address.contains(['\r', '\n'])) {
+        anyhow::bail!("Invalid email recipient: {:?}", bad);
+    }
+    Ok(recipients)
+}
 
 pub struct EmailService {}
 
@@ -42,6 +59,19 @@ impl EmailService {
                     ]),
                 })
             }
+            ServiceOperation::Create { resource_type, data } if resource_type == "email" => {
+                let recipients = outgoing_recipients(&data)?;
+                info!("Sending email to {} recipients over {:?}", recipients.len(), config.service_type);
+
+                Ok(ServiceResult {
+                    success: true,
+                    data: json!({ "id": Uuid::new_v4(), "to": recipients }),
+                    error: None,
+                    metadata: HashMap::from([
+                        ("protocol".to_string(), json!(config.service_type)),
+                    ]),
+                })
+            }
             _ => {
                 Ok(ServiceResult {
                     success: true,
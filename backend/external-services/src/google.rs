@@ -203,6 +203,19 @@ impl GoogleService {
                     ]),
                 })
             }
+            ServiceOperation::Create { resource_type, data } if resource_type == "email" => {
+                let recipients = crate::email::outgoing_recipients(&data)?;
+                info!("Sending email through Gmail to {} recipients", recipients.len());
+
+                Ok(ServiceResult {
+                    success: true,
+                    data: json!({ "id": uuid::Uuid::new_v4(), "to": recipients }),
+                    error: None,
+                    metadata: HashMap::from([
+                        ("service".to_string(), json!("gmail")),
+                    ]),
+                })
+            }
             _ => {
                 Err(anyhow::anyhow!("Operation not supported for Gmail"))
             }
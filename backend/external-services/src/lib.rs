@@ -1,15 +1,27 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use async_trait::async_trait;
+use cognitive_kernel::audit::{AuditAction, AuditActor, AuditEvent, AuditOutcome, Auditor};
+use cognitive_kernel::lock::{DistributedLock, SingletonJob};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use tracing::{info, error, warn};
+use std::sync::Arc;
+use talkpp_auth::secrets::SecretResolver;
+use tracing::{info, error, warn, Instrument};
 use uuid::Uuid;
 
 pub mod google;
 pub mod microsoft;
 pub mod email;
 pub mod calendar;
+pub mod notifications;
+pub mod oauth;
 pub mod storage;
+pub mod rest;
+pub mod secrets;
+pub mod webhooks;
+
+/// Name of the lock the sync loop runs under, when it has one
+pub const SYNC_JOB: &str = "external-services-sync";
 
 /// External Service Configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -66,6 +78,8 @@ pub enum ServiceCredentials {
         key_path: String,
         password: Option<String>,
     },
+    /// Any of the above, sealed by a `SecretsCipher`
+    Encrypted(secrets::EncryptedBlob),
 }
 
 /// External Services Manager
@@ -76,6 +90,15 @@ pub struct ExternalServicesManager {
     email_service: email::EmailService,
     calendar_service: calendar::CalendarService,
     storage_service: storage::StorageService,
+    rest_service: rest::RestAdapter,
+    cipher: Option<Arc<dyn secrets::SecretsCipher>>,
+    /// Keep credentials in plaintext when there is no cipher, instead of refusing them
+    allow_plaintext: bool,
+    resolver: Option<Arc<SecretResolver>>,
+    token_refresher: oauth::TokenRefresher,
+    auditor: Option<Auditor>,
+    /// Shared with other replicas, so only one syncs services
+    job_lock: Option<DistributedLock>,
 }
 
 impl ExternalServicesManager {
@@ -87,17 +110,75 @@ impl ExternalServicesManager {
             email_service: email::EmailService::new(),
             calendar_service: calendar::CalendarService::new(),
             storage_service: storage::StorageService::new(),
+            rest_service: rest::RestAdapter::new(),
+            cipher: None,
+            allow_plaintext: false,
+            resolver: None,
+            token_refresher: oauth::TokenRefresher::new(),
+            auditor: None,
+            job_lock: None,
         }
     }
 
+    /// Audit every service operation
+    pub fn with_auditor(mut self, auditor: Auditor) -> Self {
+        self.auditor = Some(auditor);
+        self
+    }
+
+    /// Encrypt credentials at rest with the given cipher
+    pub fn with_cipher(mut self, cipher: Arc<dyn secrets::SecretsCipher>) -> Self {
+        self.cipher = Some(cipher);
+        self
+    }
+
+    /// Accept services without a cipher, keeping their credentials in plaintext. Only for
+    /// tests and managers whose services never leave the process
+    pub fn with_plaintext_credentials(mut self) -> Self {
+        self.allow_plaintext = true;
+        self
+    }
+
+    /// Resolve secret references in credentials when services are registered
+    pub fn with_secrets(mut self, resolver: Arc<SecretResolver>) -> Self {
+        self.resolver = Some(resolver);
+        self
+    }
+
+    /// Refresh OAuth2 access tokens through other token endpoints
+    pub fn with_token_refresher(mut self, refresher: oauth::TokenRefresher) -> Self {
+        self.token_refresher = refresher;
+        self
+    }
+
+    /// Sync services from `sync_loop` only while holding the `SYNC_JOB` lock, so that
+    /// one replica at a time of those sharing the lock's store does
+    pub fn with_job_lock(mut self, lock: DistributedLock) -> Self {
+        self.job_lock = Some(lock);
+        self
+    }
+
     /// Register a new external service
     pub async fn register_service(&self, mut config: ServiceConfig) -> Result<Uuid> {
+        if self.cipher.is_none() {
+            if !self.allow_plaintext {
+                return Err(anyhow::anyhow!(
+                    "No cipher configured to encrypt credentials for service {}", config.name
+                ));
+            }
+            warn!("Storing credentials for service {} in plaintext", config.name);
+        }
+
         config.id = Uuid::new_v4();
         config.created_at = chrono::Utc::now();
         config.updated_at = chrono::Utc::now();
 
         let service_id = config.id;
-        
+
+        if let Some(resolver) = &self.resolver {
+            config.credentials.resolve_references(resolver).await?;
+        }
+
         // Initialize the service based on type
         match config.service_type {
             ServiceType::GoogleDrive | ServiceType::GoogleCalendar | 
@@ -111,11 +192,18 @@ impl ExternalServicesManager {
             ServiceType::Imap | ServiceType::Pop3 | ServiceType::Smtp => {
                 self.email_service.register_service(&config).await?;
             }
+            ServiceType::Custom { .. } => {
+                self.rest_service.register_service(&config).await?;
+            }
             _ => {
                 info!("Service type {:?} registered without specific initialization", config.service_type);
             }
         }
 
+        if let Some(cipher) = &self.cipher {
+            config.credentials = config.credentials.encrypt(cipher.as_ref()).await?;
+        }
+
         {
             let mut services = self.services.write().await;
             services.insert(service_id, config);
@@ -125,19 +213,99 @@ impl ExternalServicesManager {
         Ok(service_id)
     }
 
-    /// List all registered services
+    /// List all registered services with credentials redacted
     pub async fn list_services(&self) -> Result<Vec<ServiceConfig>> {
         let services = self.services.read().await;
-        Ok(services.values().cloned().collect())
+        Ok(services.values().cloned().map(|mut config| {
+            config.credentials = config.credentials.redacted();
+            config
+        }).collect())
+    }
+
+    /// List all registered services with decrypted credentials
+    pub async fn list_services_with_secrets(&self, access: &secrets::SecretsAccess) -> Result<Vec<ServiceConfig>> {
+        info!("Listing services with decrypted credentials for user {}", access.user_id());
+        let services = {
+            let services = self.services.read().await;
+            services.values().cloned().collect::<Vec<_>>()
+        };
+
+        let mut decrypted = Vec::with_capacity(services.len());
+        for config in services {
+            decrypted.push(self.decrypt_config(config).await?);
+        }
+        Ok(decrypted)
+    }
+
+    /// A service's config with decrypted credentials. An OAuth2 access token about to
+    /// expire is refreshed first, and the new one kept for later calls.
+    pub(crate) async fn service_config(&self, service_id: Uuid) -> Result<ServiceConfig> {
+        let config = {
+            let services = self.services.read().await;
+            services.get(&service_id).cloned()
+                .ok_or_else(|| anyhow::anyhow!("Service not found: {}", service_id))?
+        };
+        let mut config = self.decrypt_config(config).await?;
+        if !self.token_refresher.is_expiring(&config.credentials) {
+            return Ok(config);
+        }
+
+        config.credentials = self.token_refresher.refresh(&config).await
+            .with_context(|| format!("Refreshing credentials of service {}", service_id))?;
+        config.updated_at = chrono::Utc::now();
+
+        let mut stored = config.clone();
+        if let Some(cipher) = &self.cipher {
+            stored.credentials = stored.credentials.encrypt(cipher.as_ref()).await?;
+        }
+        self.services.write().await.insert(service_id, stored);
+        info!("Refreshed the access token of service {}", service_id);
+        Ok(config)
+    }
+
+    async fn decrypt_config(&self, mut config: ServiceConfig) -> Result<ServiceConfig> {
+        if let ServiceCredentials::Encrypted(_) = config.credentials {
+            let cipher = self.cipher.as_ref()
+                .ok_or_else(|| anyhow::anyhow!("No cipher configured to decrypt credentials for {}", config.id))?;
+            config.credentials = config.credentials.decrypt(cipher.as_ref()).await?;
+        }
+        Ok(config)
     }
 
     /// Execute service operation
     pub async fn execute_operation(&self, service_id: Uuid, operation: ServiceOperation) -> Result<ServiceResult> {
+        self.execute_operation_as(&AuditActor::system(), service_id, operation).await
+    }
+
+    /// Execute an operation on behalf of `actor`, who is recorded in the audit trail
+    pub async fn execute_operation_as(
+        &self,
+        actor: &AuditActor,
+        service_id: Uuid,
+        operation: ServiceOperation,
+    ) -> Result<ServiceResult> {
+        let audited = self.auditor.as_ref().map(|_| serde_json::to_value(&operation).unwrap_or_default());
+        let span = tracing::info_span!("external_operation", service_id = %service_id);
+        let result = self.dispatch_operation(service_id, operation).instrument(span).await;
+        if let (Some(auditor), Some(params)) = (&self.auditor, audited) {
+            auditor.record(AuditEvent::new(
+                actor.clone(),
+                AuditAction::ExternalOperation,
+                format!("service:{}", service_id),
+                &params,
+                AuditOutcome::of(&result),
+            ));
+        }
+        result
+    }
+
+    async fn dispatch_operation(&self, service_id: Uuid, operation: ServiceOperation) -> Result<ServiceResult> {
         let config = {
             let services = self.services.read().await;
             services.get(&service_id).cloned()
                 .ok_or_else(|| anyhow::anyhow!("Service not found: {}", service_id))?
         };
+        let config = self.decrypt_config(config).await?;
 
         if !config.enabled {
             return Err(anyhow::anyhow!("Service is disabled: {}", service_id));
@@ -162,6 +330,9 @@ impl ExternalServicesManager {
             ServiceType::Imap | ServiceType::Pop3 | ServiceType::Smtp => {
                 self.email_service.execute_operation(&config, operation).await
             }
+            ServiceType::Custom { .. } => {
+                self.rest_service.execute_operation(&config, operation).await
+            }
             _ => {
                 Err(anyhow::anyhow!("Operation not supported for service type: {:?}", config.service_type))
             }
@@ -198,6 +369,33 @@ impl ExternalServicesManager {
         Ok(results)
     }
 
+    /// Sync all enabled services every `interval` until told to stop. With a job lock,
+    /// only the replica holding `SYNC_JOB` does, and another takes over should it die.
+    pub async fn sync_loop(&self, interval: std::time::Duration, stop: tokio::sync::watch::Receiver<bool>) {
+        match &self.job_lock {
+            Some(lock) => {
+                SingletonJob::new(SYNC_JOB, lock.clone())
+                    .run(stop, |halted| self.sync_every(interval, halted))
+                    .await
+            }
+            None => self.sync_every(interval, stop).await,
+        }
+    }
+
+    async fn sync_every(&self, interval: std::time::Duration, mut stop: tokio::sync::watch::Receiver<bool>) {
+        let mut ticks = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                _ = ticks.tick() => {
+                    if let Err(e) = self.sync_all_services().await {
+                        error!("Syncing external services failed: {}", e);
+                    }
+                }
+                _ = stop.wait_for(|s| *s) => break,
+            }
+        }
+    }
+
     async fn sync_service(&self, service_id: Uuid) -> Result<SyncResult> {
         let start_time = std::time::Instant::now();
         
@@ -285,4 +483,78 @@ pub struct SyncResult {
     pub errors: Vec<String>,
     pub duration_ms: u64,
     pub last_sync: chrono::DateTime<chrono::Utc>,
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use talkpp_auth::session::SessionKeys;
+
+    fn secrets_access() -> secrets::SecretsAccess {
+        let keys = SessionKeys::new(b"session-secret");
+        let token = keys.issue(Uuid::new_v4(), vec![secrets::READ_SECRETS_PERMISSION.to_string()], std::time::Duration::from_secs(60)).unwrap();
+        secrets::SecretsAccess::for_session(&keys.verify(&token).unwrap()).unwrap()
+    }
+
+    fn api_key_service() -> ServiceConfig {
+        ServiceConfig {
+            id: Uuid::nil(),
+            service_type: ServiceType::Custom { provider: "crm".to_string() },
+            name: "crm".to_string(),
+            enabled: true,
+            credentials: ServiceCredentials::ApiKey { key: "live-key".to_string(), secret: None },
+            settings: HashMap::from([(rest::REST_MAPPING_SETTING.to_string(), serde_json::json!({
+                "base_url": "https://crm.example.com",
+                "auth": { "type": "bearer" },
+                "resources": { "contacts": { "list": { "method": "GET", "path": "/contacts" } } }
+            }))]),
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_services_redacts_by_default() {
+        let cipher = Arc::new(secrets::AesGcmCipher::new("test-key", &[3u8; 32]));
+        let manager = ExternalServicesManager::new().with_cipher(cipher);
+        manager.register_service(api_key_service()).await.unwrap();
+
+        let listed = manager.list_services().await.unwrap();
+        let serialized = serde_json::to_string(&listed).unwrap();
+        assert!(!serialized.contains("live-key"));
+
+        let with_secrets = manager.list_services_with_secrets(&secrets_access()).await.unwrap();
+        match &with_secrets[0].credentials {
+            ServiceCredentials::ApiKey { key, .. } => assert_eq!(key, "live-key"),
+            other => panic!("unexpected credentials: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_register_service_resolves_env_references() {
+        std::env::set_var("TALKPP_TEST_CRM_KEY", "resolved-key");
+        let resolver = SecretResolver::new()
+            .with_provider("env", Arc::new(talkpp_auth::secrets::EnvSecrets));
+        let manager = ExternalServicesManager::new()
+            .with_plaintext_credentials()
+            .with_secrets(Arc::new(resolver));
+        let mut config = api_key_service();
+        config.credentials = ServiceCredentials::ApiKey { key: "env:TALKPP_TEST_CRM_KEY".to_string(), secret: None };
+        manager.register_service(config).await.unwrap();
+
+        match &manager.list_services_with_secrets(&secrets_access()).await.unwrap()[0].credentials {
+            ServiceCredentials::ApiKey { key, .. } => assert_eq!(key, "resolved-key"),
+            other => panic!("unexpected credentials: {:?}", other),
+        }
+
+        let mut missing = api_key_service();
+        missing.credentials = ServiceCredentials::ApiKey { key: "env:TALKPP_TEST_UNSET_KEY".to_string(), secret: None };
+        assert!(manager.register_service(missing).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_register_service_requires_cipher() {
+        let err = ExternalServicesManager::new().register_service(api_key_service()).await.unwrap_err();
+        assert!(err.to_string().contains("No cipher configured"));
+    }
+}
@@ -0,0 +1,505 @@
+//! Email notifications of plan outcomes, approval requests and sync reports
+//!
+//! Code that finishes a plan or holds one for approval publishes a `NotificationEvent`.
+//! The `NotificationDispatcher` asks a `RecipientDirectory` who wants that kind of event,
+//! renders it once with the `EmailComposer` and sends it to each recipient separately, so
+//! one bad address does not stop the others getting theirs.
+//!
+//! Every email has an HTML and a plain text body, rendered from the templates in this
+//! crate's `templates/email` directory. HTML templates escape whatever they insert, since
+//! plan titles, task names and errors come from users and the services they connect.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use cognitive_kernel::executor::PlanOutcome;
+use cognitive_kernel::{ExecutionState, IntentExecutionPlan, TaskStatus};
+use minijinja::Environment;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tracing::{error, warn};
+use uuid::Uuid;
+
+use crate::{ExternalServicesManager, ServiceOperation, SyncResult};
+
+/// Templates compiled into the library; each kind of event has a `.html` and a `.txt`
+const TEMPLATES: &[(&str, &str)] = &[
+    ("layout.html", include_str!("../templates/email/layout.html")),
+    ("tasks.html", include_str!("../templates/email/tasks.html")),
+    ("tasks.txt", include_str!("../templates/email/tasks.txt")),
+    ("plan_completed.html", include_str!("../templates/email/plan_completed.html")),
+    ("plan_completed.txt", include_str!("../templates/email/plan_completed.txt")),
+    ("plan_failed.html", include_str!("../templates/email/plan_failed.html")),
+    ("plan_failed.txt", include_str!("../templates/email/plan_failed.txt")),
+    ("approval_requested.html", include_str!("../templates/email/approval_requested.html")),
+    ("approval_requested.txt", include_str!("../templates/email/approval_requested.txt")),
+    ("sync_report.html", include_str!("../templates/email/sync_report.html")),
+    ("sync_report.txt", include_str!("../templates/email/sync_report.txt")),
+];
+
+/// Longest plan title put in a subject line, in characters
+const SUBJECT_TITLE_CHARS: usize = 80;
+
+/// Kinds of event users can choose to be emailed about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    PlanCompleted,
+    PlanFailed,
+    ApprovalRequested,
+    SyncReport,
+}
+
+impl NotificationKind {
+    /// Name of the kind's templates, without the extension
+    fn template(self) -> &'static str {
+        match self {
+            NotificationKind::PlanCompleted => "plan_completed",
+            NotificationKind::PlanFailed => "plan_failed",
+            NotificationKind::ApprovalRequested => "approval_requested",
+            NotificationKind::SyncReport => "sync_report",
+        }
+    }
+}
+
+/// One row of a plan's task table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskRow {
+    pub name: String,
+    pub agent: String,
+    pub status: String,
+}
+
+/// What an email says about a plan
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanSummary {
+    pub plan_id: Uuid,
+    pub intent_id: Uuid,
+    /// Usually the intent the plan was made for
+    pub title: String,
+    pub autonomy_tier: u8,
+    pub tasks: Vec<TaskRow>,
+}
+
+impl PlanSummary {
+    pub fn new(title: impl Into<String>, plan: &IntentExecutionPlan) -> Self {
+        Self {
+            plan_id: plan.id,
+            intent_id: plan.intent_id,
+            title: title.into(),
+            autonomy_tier: plan.autonomy_tier,
+            tasks: plan.tasks.iter()
+                .map(|task| TaskRow {
+                    name: task.name.clone(),
+                    agent: task.agent_type.clone(),
+                    status: format!("{:?}", task.status),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Why a plan failed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorDetails {
+    pub message: String,
+    pub failed_task: Option<String>,
+}
+
+/// One service's line in a sync report
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncRow {
+    pub name: String,
+    pub success: bool,
+    pub synced_items: usize,
+    pub errors: Vec<String>,
+    pub duration_ms: u64,
+}
+
+/// Outcome of syncing a user's services
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncReport {
+    pub services: Vec<SyncRow>,
+    pub synced_items: usize,
+    pub failed: usize,
+}
+
+impl SyncReport {
+    pub fn new(results: &[SyncResult]) -> Self {
+        Self {
+            services: results.iter()
+                .map(|result| SyncRow {
+                    name: result.service_name.clone(),
+                    success: result.success,
+                    synced_items: result.synced_items,
+                    errors: result.errors.clone(),
+                    duration_ms: result.duration_ms,
+                })
+                .collect(),
+            synced_items: results.iter().map(|result| result.synced_items).sum(),
+            failed: results.iter().filter(|result| !result.success).count(),
+        }
+    }
+}
+
+/// Something a user may want an email about, with the user it concerns
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NotificationEvent {
+    PlanCompleted { owner: Uuid, plan: PlanSummary },
+    PlanFailed { owner: Uuid, plan: PlanSummary, error: ErrorDetails },
+    ApprovalRequested { owner: Uuid, plan: PlanSummary },
+    SyncReport { owner: Uuid, report: SyncReport },
+}
+
+impl NotificationEvent {
+    /// The event for a plan that finished, or paused for approval, as `outcome`; `None`
+    /// when it stopped for another reason, such as being cancelled or pausing on its budget
+    pub fn plan_finished(owner: Uuid, title: impl Into<String>, plan: &IntentExecutionPlan, outcome: &PlanOutcome) -> Option<Self> {
+        let summary = PlanSummary::new(title, plan);
+        match &outcome.state {
+            ExecutionState::Completed => Some(Self::PlanCompleted { owner, plan: summary }),
+            ExecutionState::AwaitingApproval { .. } => Some(Self::ApprovalRequested { owner, plan: summary }),
+            ExecutionState::Failed { error } => {
+                let failed_task = plan.tasks.iter()
+                    .find(|task| matches!(task.status, TaskStatus::Failed))
+                    .map(|task| task.name.clone());
+                Some(Self::PlanFailed {
+                    owner,
+                    plan: summary,
+                    error: ErrorDetails { message: error.clone(), failed_task },
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn kind(&self) -> NotificationKind {
+        match self {
+            Self::PlanCompleted { .. } => NotificationKind::PlanCompleted,
+            Self::PlanFailed { .. } => NotificationKind::PlanFailed,
+            Self::ApprovalRequested { .. } => NotificationKind::ApprovalRequested,
+            Self::SyncReport { .. } => NotificationKind::SyncReport,
+        }
+    }
+
+    pub fn owner(&self) -> Uuid {
+        match self {
+            Self::PlanCompleted { owner, .. }
+            | Self::PlanFailed { owner, .. }
+            | Self::ApprovalRequested { owner, .. }
+            | Self::SyncReport { owner, .. } => *owner,
+        }
+    }
+}
+
+/// A rendered email, ready to send to any recipient
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ComposedEmail {
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+/// Links into the web UI
+#[derive(Debug, Serialize)]
+struct Links {
+    plan: Option<String>,
+    approve: Option<String>,
+    services: Option<String>,
+}
+
+/// Renders notification events as emails, linking to the web UI at `base_url`
+pub struct EmailComposer {
+    env: Environment<'static>,
+    base_url: String,
+}
+
+impl EmailComposer {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        let mut env = Environment::new();
+        env.set_trim_blocks(true);
+        env.set_lstrip_blocks(true);
+        env.set_keep_trailing_newline(true);
+        // `.html` templates are escaped for HTML by default; `.txt` ones are not
+        for (name, source) in TEMPLATES {
+            env.add_template(name, source).expect("built-in email templates parse");
+        }
+        Self { env, base_url: base_url.into().trim_end_matches('/').to_string() }
+    }
+
+    pub fn compose(&self, event: &NotificationEvent) -> Result<ComposedEmail> {
+        let (subject, context) = match event {
+            NotificationEvent::PlanCompleted { plan, .. } => (
+                format!("Plan completed: {}", subject_title(&plan.title)),
+                minijinja::context! { plan, links => self.plan_links(plan) },
+            ),
+            NotificationEvent::PlanFailed { plan, error, .. } => (
+                format!("Plan failed: {}", subject_title(&plan.title)),
+                minijinja::context! { plan, error, links => self.plan_links(plan) },
+            ),
+            NotificationEvent::ApprovalRequested { plan, .. } => (
+                format!("Approval needed: {}", subject_title(&plan.title)),
+                minijinja::context! { plan, links => self.plan_links(plan) },
+            ),
+            NotificationEvent::SyncReport { report, .. } => (
+                format!("Sync report: {} of {} services failed", report.failed, report.services.len()),
+                minijinja::context! { report, links => Links {
+                    plan: None,
+                    approve: None,
+                    services: Some(format!("{}/settings/services", self.base_url)),
+                } },
+            ),
+        };
+
+        let template = event.kind().template();
+        let render = |extension: &str| -> Result<String> {
+            let name = format!("{}.{}", template, extension);
+            self.env.get_template(&name)?
+                .render(&context)
+                .with_context(|| format!("Failed to render email template {}", name))
+        };
+        Ok(ComposedEmail { subject, html: render("html")?, text: render("txt")? })
+    }
+
+    fn plan_links(&self, plan: &PlanSummary) -> Links {
+        let url = format!("{}/plans/{}", self.base_url, plan.plan_id);
+        Links { approve: Some(format!("{}/approve", url)), plan: Some(url), services: None }
+    }
+}
+
+/// `title` cut to `SUBJECT_TITLE_CHARS` on one line, as a header must be
+fn subject_title(title: &str) -> String {
+    let line: String = title.chars().map(|c| if c.is_control() { ' ' } else { c }).collect();
+    let line = line.trim();
+    if line.chars().count() <= SUBJECT_TITLE_CHARS {
+        return line.to_string();
+    }
+    let cut: String = line.chars().take(SUBJECT_TITLE_CHARS - 1).collect();
+    format!("{}…", cut.trim_end())
+}
+
+/// Who gets emailed about events
+#[async_trait]
+pub trait RecipientDirectory: Send + Sync {
+    /// Addresses to email about an event of `kind` concerning `user_id`; empty when they
+    /// do not want such emails
+    async fn recipients(&self, user_id: Uuid, kind: NotificationKind) -> Result<Vec<String>>;
+}
+
+/// Delivers composed emails
+#[async_trait]
+pub trait EmailSender: Send + Sync {
+    async fn send(&self, to: &str, email: &ComposedEmail) -> Result<()>;
+}
+
+/// Sends through a registered SMTP or Gmail service
+pub struct ServiceEmailSender {
+    manager: Arc<ExternalServicesManager>,
+    service_id: Uuid,
+}
+
+impl ServiceEmailSender {
+    pub fn new(manager: Arc<ExternalServicesManager>, service_id: Uuid) -> Self {
+        Self { manager, service_id }
+    }
+}
+
+#[async_trait]
+impl EmailSender for ServiceEmailSender {
+    async fn send(&self, to: &str, email: &ComposedEmail) -> Result<()> {
+        let operation = ServiceOperation::Create {
+            resource_type: "email".to_string(),
+            data: serde_json::json!({
+                "to": [to],
+                "subject": email.subject,
+                "html": email.html,
+                "text": email.text,
+            }),
+        };
+        let result = self.manager.execute_operation(self.service_id, operation).await?;
+        if !result.success {
+            anyhow::bail!("Email service {} did not send: {}", self.service_id, result.error.unwrap_or_default());
+        }
+        Ok(())
+    }
+}
+
+/// What became of one event's emails
+#[derive(Debug, Default)]
+pub struct DispatchReport {
+    pub sent: Vec<String>,
+    /// Recipients whose email could not be sent, with the reason
+    pub failed: Vec<(String, String)>,
+}
+
+/// Emails each notification event to the recipients that want it
+pub struct NotificationDispatcher {
+    composer: EmailComposer,
+    recipients: Arc<dyn RecipientDirectory>,
+    sender: Arc<dyn EmailSender>,
+}
+
+impl NotificationDispatcher {
+    pub fn new(composer: EmailComposer, recipients: Arc<dyn RecipientDirectory>, sender: Arc<dyn EmailSender>) -> Self {
+        Self { composer, recipients, sender }
+    }
+
+    /// Email `event` to its recipients. Fails only when the recipients cannot be looked up
+    /// or the email cannot be rendered; a failed send is reported and the rest still go.
+    pub async fn dispatch(&self, event: &NotificationEvent) -> Result<DispatchReport> {
+        let recipients = self.recipients.recipients(event.owner(), event.kind()).await?;
+        if recipients.is_empty() {
+            return Ok(DispatchReport::default());
+        }
+        let email = self.composer.compose(event)?;
+
+        let sends = recipients.iter().map(|to| self.sender.send(to, &email));
+        let mut report = DispatchReport::default();
+        for (to, result) in recipients.iter().zip(futures::future::join_all(sends).await) {
+            match result {
+                Ok(()) => report.sent.push(to.clone()),
+                Err(e) => report.failed.push((to.clone(), e.to_string())),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Dispatch `events` until every sender is dropped
+    pub async fn run(self, mut events: mpsc::UnboundedReceiver<NotificationEvent>) {
+        while let Some(event) = events.recv().await {
+            match self.dispatch(&event).await {
+                Ok(report) => {
+                    for (to, reason) in report.failed {
+                        warn!("Failed to email {} about {:?}: {}", to, event.kind(), reason);
+                    }
+                }
+                Err(e) => error!("Failed to notify {} of {:?}: {}", event.owner(), event.kind(), e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    fn plan_summary(title: &str, task_name: &str, status: &str) -> PlanSummary {
+        PlanSummary {
+            plan_id: Uuid::from_u128(0x5eed),
+            intent_id: Uuid::from_u128(0x1d),
+            title: title.to_string(),
+            autonomy_tier: 2,
+            tasks: vec![
+                TaskRow { name: "Gather invoices".to_string(), agent: "finance".to_string(), status: "Completed".to_string() },
+                TaskRow { name: task_name.to_string(), agent: "mailer".to_string(), status: status.to_string() },
+            ],
+        }
+    }
+
+    fn composer() -> EmailComposer {
+        EmailComposer::new("https://talkpp.example/")
+    }
+
+    #[test]
+    fn test_rendered_emails_match_snapshots() {
+        let owner = Uuid::nil();
+        let events = [
+            NotificationEvent::PlanCompleted { owner, plan: plan_summary("Send the March invoices", "Email customers", "Completed") },
+            NotificationEvent::PlanFailed {
+                owner,
+                plan: plan_summary("Send the March invoices", "Email customers", "Failed"),
+                error: ErrorDetails { message: "SMTP relay refused the connection".to_string(), failed_task: Some("Email customers".to_string()) },
+            },
+            NotificationEvent::ApprovalRequested { owner, plan: plan_summary("Send the March invoices", "Email customers", "Pending") },
+            NotificationEvent::SyncReport {
+                owner,
+                report: SyncReport {
+                    services: vec![
+                        SyncRow { name: "Work calendar".to_string(), success: true, synced_items: 12, errors: vec![], duration_ms: 840 },
+                        SyncRow { name: "Dropbox".to_string(), success: false, synced_items: 0, errors: vec!["Token expired".to_string()], duration_ms: 95 },
+                    ],
+                    synced_items: 12,
+                    failed: 1,
+                },
+            },
+        ];
+
+        for event in &events {
+            let email = composer().compose(event).unwrap();
+            let name = event.kind().template();
+            insta::assert_snapshot!(format!("{}_subject", name), email.subject);
+            insta::assert_snapshot!(format!("{}_html", name), email.html);
+            insta::assert_snapshot!(format!("{}_text", name), email.text);
+        }
+    }
+
+    #[test]
+    fn test_user_strings_are_escaped_in_html() {
+        let attack = r#"<script>alert("pwned")</script><a href="https://evil.example">click</a>"#;
+        let event = NotificationEvent::PlanFailed {
+            owner: Uuid::nil(),
+            plan: plan_summary(attack, attack, "Failed"),
+            error: ErrorDetails { message: attack.to_string(), failed_task: Some(attack.to_string()) },
+        };
+
+        let email = composer().compose(&event).unwrap();
+        assert!(!email.html.contains("<script>"));
+        assert!(!email.html.contains("evil.example\">"));
+        assert!(email.html.contains("&lt;script&gt;alert(&quot;pwned&quot;)&lt;&#x2f;script&gt;"));
+
+        let newline = NotificationEvent::ApprovalRequested {
+            owner: Uuid::nil(),
+            plan: plan_summary("Pay rent\r\nBcc: everyone@example.com", "Pay", "Pending"),
+        };
+        assert_eq!(composer().compose(&newline).unwrap().subject, "Approval needed: Pay rent  Bcc: everyone@example.com");
+    }
+
+    struct Directory(HashMap<NotificationKind, Vec<String>>);
+
+    #[async_trait]
+    impl RecipientDirectory for Directory {
+        async fn recipients(&self, _user_id: Uuid, kind: NotificationKind) -> Result<Vec<String>> {
+            Ok(self.0.get(&kind).cloned().unwrap_or_default())
+        }
+    }
+
+    /// Fails for addresses at `bounce.example`, records the rest
+    #[derive(Default)]
+    struct Outbox(Mutex<Vec<(String, String)>>);
+
+    #[async_trait]
+    impl EmailSender for Outbox {
+        async fn send(&self, to: &str, email: &ComposedEmail) -> Result<()> {
+            if to.ends_with("@bounce.example") {
+                anyhow::bail!("mailbox unavailable");
+            }
+            self.0.lock().unwrap().push((to.to_string(), email.subject.clone()));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_one_failed_recipient_does_not_stop_the_others() {
+        let directory = Directory(HashMap::from([(
+            NotificationKind::PlanCompleted,
+            vec!["ada@example.com".to_string(), "gone@bounce.example".to_string(), "grace@example.com".to_string()],
+        )]));
+        let outbox = Arc::new(Outbox::default());
+        let dispatcher = NotificationDispatcher::new(composer(), Arc::new(directory), outbox.clone());
+
+        let completed = NotificationEvent::PlanCompleted { owner: Uuid::nil(), plan: plan_summary("Tidy inbox", "Archive", "Completed") };
+        let report = dispatcher.dispatch(&completed).await.unwrap();
+        assert_eq!(report.sent, vec!["ada@example.com", "grace@example.com"]);
+        assert_eq!(report.failed, vec![("gone@bounce.example".to_string(), "mailbox unavailable".to_string())]);
+        assert_eq!(outbox.0.lock().unwrap()[0], ("ada@example.com".to_string(), "Plan completed: Tidy inbox".to_string()));
+
+        // Nobody asked to hear about approvals
+        let approval = NotificationEvent::ApprovalRequested { owner: Uuid::nil(), plan: plan_summary("Tidy inbox", "Archive", "Pending") };
+        let report = dispatcher.dispatch(&approval).await.unwrap();
+        assert!(report.sent.is_empty() && report.failed.is_empty());
+        assert_eq!(outbox.0.lock().unwrap().len(), 2);
+    }
+}
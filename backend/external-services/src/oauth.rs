@@ -0,0 +1,175 @@
+//! OAuth2 access token refresh for Google and Microsoft services
+//!
+//! Access tokens last about an hour. Anything that calls a provider on a service's behalf
+//! long after it was registered, such as webhook renewals, goes through
+//! `ExternalServicesManager`, which swaps a token about to expire for a new one with the
+//! service's refresh token and keeps the result.
+
+use super::{ServiceConfig, ServiceCredentials, ServiceType};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+/// Google's OAuth2 token endpoint
+pub const GOOGLE_TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+/// Microsoft identity platform token endpoint for work, school and personal accounts
+pub const MICROSOFT_TOKEN_URL: &str = "https://login.microsoftonline.com/common/oauth2/v2.0/token";
+
+/// Refreshes OAuth2 access tokens that expire within a margin
+pub struct TokenRefresher {
+    client: reqwest::Client,
+    google_token_url: String,
+    microsoft_token_url: String,
+    margin: chrono::Duration,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: i64,
+    /// Microsoft rotates refresh tokens; Google keeps the old one valid and sends none
+    refresh_token: Option<String>,
+}
+
+impl TokenRefresher {
+    pub fn new() -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            google_token_url: GOOGLE_TOKEN_URL.to_string(),
+            microsoft_token_url: MICROSOFT_TOKEN_URL.to_string(),
+            margin: chrono::Duration::minutes(5),
+        }
+    }
+
+    pub fn with_google_token_url(mut self, url: impl Into<String>) -> Self {
+        self.google_token_url = url.into();
+        self
+    }
+
+    /// Use another token endpoint, such as a single tenant's
+    pub fn with_microsoft_token_url(mut self, url: impl Into<String>) -> Self {
+        self.microsoft_token_url = url.into();
+        self
+    }
+
+    /// Whether `credentials` hold an access token that expires within the margin
+    pub fn is_expiring(&self, credentials: &ServiceCredentials) -> bool {
+        match credentials {
+            ServiceCredentials::OAuth2 { expires_at, .. } => *expires_at <= chrono::Utc::now() + self.margin,
+            _ => false,
+        }
+    }
+
+    /// The service's credentials with a new access token
+    pub async fn refresh(&self, config: &ServiceConfig) -> Result<ServiceCredentials> {
+        let ServiceCredentials::OAuth2 { client_id, client_secret, refresh_token, .. } = &config.credentials else {
+            anyhow::bail!("Service {} has no OAuth2 credentials to refresh", config.name);
+        };
+
+        let response = self.client
+            .post(self.token_url(&config.service_type)?)
+            .form(&[
+                ("grant_type", "refresh_token"),
+                ("client_id", client_id.as_str()),
+                ("client_secret", client_secret.as_str()),
+                ("refresh_token", refresh_token.as_str()),
+            ])
+            .send()
+            .await
+            .with_context(|| format!("Refreshing the access token of service {}", config.name))?;
+        let status = response.status();
+        if !status.is_success() {
+            anyhow::bail!("Token endpoint returned {} for service {}: {}", status, config.name, response.text().await?);
+        }
+        let token: TokenResponse = response.json().await.context("Unexpected token endpoint response")?;
+
+        Ok(ServiceCredentials::OAuth2 {
+            client_id: client_id.clone(),
+            client_secret: client_secret.clone(),
+            access_token: token.access_token,
+            refresh_token: token.refresh_token.unwrap_or_else(|| refresh_token.clone()),
+            expires_at: chrono::Utc::now() + chrono::Duration::seconds(token.expires_in),
+        })
+    }
+
+    fn token_url(&self, service_type: &ServiceType) -> Result<&str> {
+        match service_type {
+            ServiceType::GoogleDrive | ServiceType::GoogleCalendar |
+            ServiceType::GoogleContacts | ServiceType::Gmail => Ok(&self.google_token_url),
+            ServiceType::OneDrive | ServiceType::OutlookCalendar |
+            ServiceType::OutlookContacts | ServiceType::Exchange => Ok(&self.microsoft_token_url),
+            _ => Err(anyhow::anyhow!("No OAuth2 token endpoint for service type: {:?}", service_type)),
+        }
+    }
+}
+
+impl Default for TokenRefresher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use uuid::Uuid;
+    use wiremock::matchers::{body_string_contains, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn gmail(expires_at: chrono::DateTime<chrono::Utc>) -> ServiceConfig {
+        ServiceConfig {
+            id: Uuid::new_v4(),
+            service_type: ServiceType::Gmail,
+            name: "mail".to_string(),
+            enabled: true,
+            credentials: ServiceCredentials::OAuth2 {
+                client_id: "client".to_string(),
+                client_secret: "secret".to_string(),
+                access_token: "stale-token".to_string(),
+                refresh_token: "refresh-token".to_string(),
+                expires_at,
+            },
+            settings: HashMap::new(),
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_refresh_keeps_refresh_token_google_does_not_rotate() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/token"))
+            .and(body_string_contains("grant_type=refresh_token"))
+            .and(body_string_contains("refresh_token=refresh-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "access_token": "fresh-token",
+                "expires_in": 3599,
+                "token_type": "Bearer"
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let refresher = TokenRefresher::new().with_google_token_url(format!("{}/token", server.uri()));
+        let config = gmail(chrono::Utc::now());
+        assert!(refresher.is_expiring(&config.credentials));
+
+        let refreshed = refresher.refresh(&config).await.unwrap();
+        assert!(!refresher.is_expiring(&refreshed));
+        match refreshed {
+            ServiceCredentials::OAuth2 { access_token, refresh_token, .. } => {
+                assert_eq!(access_token, "fresh-token");
+                assert_eq!(refresh_token, "refresh-token");
+            }
+            other => panic!("unexpected credentials: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_only_tokens_near_expiry_are_refreshed() {
+        let refresher = TokenRefresher::new();
+        assert!(!refresher.is_expiring(&gmail(chrono::Utc::now() + chrono::Duration::hours(1)).credentials));
+        assert!(!refresher.is_expiring(&ServiceCredentials::ApiKey { key: "k".to_string(), secret: None }));
+    }
+}
@@ -0,0 +1,775 @@
+//! Generic REST adapter for `ServiceType::Custom` services
+//!
+//! A custom service describes its API in a `rest_mapping` setting instead of code: the
+//! base URL, how its credentials are attached to requests, and per resource type a
+//! template for each operation it supports. A template names the HTTP method, a path and
+//! query parameters with `{placeholders}` filled from the `ServiceOperation`, an optional
+//! JSON body, and JSON pointers picking the fields of the response that make up
+//! `ServiceResult.data`.
+//!
+//! ```json
+//! {
+//!   "base_url": "https://crm.example.com/api",
+//!   "auth": { "type": "header", "name": "X-Api-Key" },
+//!   "resources": {
+//!     "contacts": {
+//!       "list": { "method": "GET", "path": "/contacts", "query": { "limit": "{limit}" },
+//!                 "extract": { "items": "/results", "total": "/meta/total" } },
+//!       "get": { "method": "GET", "path": "/contacts/{resource_id}" },
+//!       "create": { "method": "POST", "path": "/contacts", "body": { "properties": "{data}" } }
+//!     }
+//!   }
+//! }
+//! ```
+//!
+//! Every operation may use `{resource_type}`, and besides:
+//! - list: `{limit}` and `{filters.<name>}`
+//! - get and delete: `{resource_id}`
+//! - create and update: `{data}` and `{data.<field>}`, update also `{resource_id}`
+//! - search: `{query}` and `{limit}`
+//! - sync, mapped once for the whole service: `{full_sync}` and `{since}`
+//!
+//! A query parameter whose placeholders have no value, such as `{limit}` when none was
+//! given, is left out. A body string that is a single placeholder becomes its value as is,
+//! so `"{data}"` embeds the whole object. Create and update without a body template send
+//! `data` itself.
+
+use super::{ServiceConfig, ServiceCredentials, ServiceOperation, ServiceResult};
+use anyhow::{Context, Result};
+use reqwest::{Method, RequestBuilder, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use talkpp_errors::{Validate, Violations};
+use tracing::info;
+
+/// Setting of a custom service that holds its [`RestMapping`]
+pub const REST_MAPPING_SETTING: &str = "rest_mapping";
+
+const METHODS: &[&str] = &["GET", "POST", "PUT", "PATCH", "DELETE"];
+
+/// How a custom service's REST API is called
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RestMapping {
+    pub base_url: String,
+    #[serde(default)]
+    pub auth: AuthInjection,
+    /// Sent with every request
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// Operations of each resource type
+    #[serde(default)]
+    pub resources: BTreeMap<String, ResourceOperations>,
+    #[serde(default)]
+    pub sync: Option<OperationTemplate>,
+}
+
+/// Templates of the operations a resource type supports
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResourceOperations {
+    pub list: Option<OperationTemplate>,
+    pub get: Option<OperationTemplate>,
+    pub create: Option<OperationTemplate>,
+    pub update: Option<OperationTemplate>,
+    pub delete: Option<OperationTemplate>,
+    pub search: Option<OperationTemplate>,
+}
+
+impl ResourceOperations {
+    fn templates(&self) -> impl Iterator<Item = (&'static str, &OperationTemplate)> {
+        [
+            ("list", &self.list),
+            ("get", &self.get),
+            ("create", &self.create),
+            ("update", &self.update),
+            ("delete", &self.delete),
+            ("search", &self.search),
+        ]
+        .into_iter()
+        .filter_map(|(kind, template)| template.as_ref().map(|template| (kind, template)))
+    }
+}
+
+/// Where the service's credentials go in each request
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AuthInjection {
+    #[default]
+    None,
+    /// `Authorization: Bearer` with the OAuth2 access token or API key
+    Bearer,
+    /// A header holding the API key or access token after `prefix`
+    Header {
+        name: String,
+        #[serde(default)]
+        prefix: String,
+    },
+    /// A query parameter holding the API key or access token
+    Query { name: String },
+    /// HTTP basic auth with the username and password, or the API key and its secret
+    Basic,
+}
+
+impl AuthInjection {
+    /// Fails unless `credentials` are of a kind this injection can send
+    pub fn check(&self, credentials: &ServiceCredentials) -> Result<()> {
+        match self {
+            Self::None => Ok(()),
+            Self::Bearer | Self::Header { .. } | Self::Query { .. } => token(credentials).map(|_| ()),
+            Self::Basic => basic(credentials).map(|_| ()),
+        }
+    }
+
+    fn apply(&self, request: RequestBuilder, credentials: &ServiceCredentials) -> Result<RequestBuilder> {
+        Ok(match self {
+            Self::None => request,
+            Self::Bearer => request.bearer_auth(token(credentials)?),
+            Self::Header { name, prefix } => request.header(name.as_str(), format!("{}{}", prefix, token(credentials)?)),
+            Self::Query { name } => request.query(&[(name.as_str(), token(credentials)?)]),
+            Self::Basic => {
+                let (username, password) = basic(credentials)?;
+                request.basic_auth(username, password)
+            }
+        })
+    }
+}
+
+fn token(credentials: &ServiceCredentials) -> Result<&str> {
+    match credentials {
+        ServiceCredentials::OAuth2 { access_token, .. } => Ok(access_token),
+        ServiceCredentials::ApiKey { key, .. } => Ok(key),
+        other => Err(anyhow::anyhow!("Token auth needs API key or OAuth2 credentials, not {}", credentials_kind(other))),
+    }
+}
+
+fn basic(credentials: &ServiceCredentials) -> Result<(&str, Option<&str>)> {
+    match credentials {
+        ServiceCredentials::BasicAuth { username, password } => Ok((username, Some(password))),
+        ServiceCredentials::ApiKey { key, secret } => Ok((key, secret.as_deref())),
+        other => Err(anyhow::anyhow!("Basic auth needs basic or API key credentials, not {}", credentials_kind(other))),
+    }
+}
+
+fn credentials_kind(credentials: &ServiceCredentials) -> &'static str {
+    match credentials {
+        ServiceCredentials::OAuth2 { .. } => "OAuth2",
+        ServiceCredentials::ApiKey { .. } => "API key",
+        ServiceCredentials::BasicAuth { .. } => "basic auth",
+        ServiceCredentials::Certificate { .. } => "certificate",
+        ServiceCredentials::Encrypted(_) => "encrypted",
+    }
+}
+
+/// One operation as an HTTP request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OperationTemplate {
+    pub method: String,
+    /// Appended to the base URL, each segment's placeholders filled and escaped
+    pub path: String,
+    #[serde(default)]
+    pub query: BTreeMap<String, String>,
+    #[serde(default)]
+    pub body: Option<Value>,
+    /// Fields of `ServiceResult.data`, each the part of the response at a JSON pointer.
+    /// Without any, the data is the whole response.
+    #[serde(default)]
+    pub extract: BTreeMap<String, String>,
+}
+
+impl OperationTemplate {
+    /// Everything wrong with the template of an operation of the given kind
+    fn violations(&self, kind: &str) -> Violations {
+        let mut violations = Violations::new();
+        violations.check(
+            METHODS.contains(&self.method.to_uppercase().as_str()),
+            "method",
+            format!("must be one of {}", METHODS.join(", ")),
+        );
+        violations.check(self.path.starts_with('/'), "path", "must start with '/'");
+        check_template(&mut violations, "path", &self.path, kind);
+        for (name, template) in &self.query {
+            check_template(&mut violations, &format!("query.{}", name), template, kind);
+        }
+        if let Some(body) = &self.body {
+            let mut strings = Vec::new();
+            collect_strings(body, &mut strings);
+            for template in strings {
+                check_template(&mut violations, "body", template, kind);
+            }
+        }
+        for (name, pointer) in &self.extract {
+            violations.check(
+                pointer.is_empty() || pointer.starts_with('/'),
+                &format!("extract.{}", name),
+                "must be a JSON pointer, empty or starting with '/'",
+            );
+        }
+        violations
+    }
+
+    fn extract(&self, response: Value) -> Value {
+        if self.extract.is_empty() {
+            return response;
+        }
+        Value::Object(
+            self.extract.iter()
+                .map(|(name, pointer)| (name.clone(), response.pointer(pointer).cloned().unwrap_or(Value::Null)))
+                .collect(),
+        )
+    }
+}
+
+impl Validate for RestMapping {
+    const NAME: &'static str = "REST mapping";
+
+    fn violations(&self) -> Violations {
+        let mut violations = Violations::new();
+        match Url::parse(&self.base_url) {
+            Ok(url) => violations.check(
+                matches!(url.scheme(), "http" | "https") && !url.cannot_be_a_base(),
+                "base_url",
+                "must be an http or https URL",
+            ),
+            Err(e) => violations.push("base_url", e.to_string()),
+        }
+        if let AuthInjection::Header { name, .. } | AuthInjection::Query { name } = &self.auth {
+            violations.check(!name.trim().is_empty(), "auth.name", "must not be empty");
+        }
+        for name in self.headers.keys() {
+            violations.check(
+                reqwest::header::HeaderName::from_bytes(name.as_bytes()).is_ok(),
+                &format!("headers.{}", name),
+                "is not a valid header name",
+            );
+        }
+        violations.check(
+            self.sync.is_some() || self.resources.values().any(|operations| operations.templates().next().is_some()),
+            "resources",
+            "must define at least one operation",
+        );
+        for (resource, operations) in &self.resources {
+            for (kind, template) in operations.templates() {
+                violations.nest(&format!("resources.{}.{}", resource, kind), template.violations(kind));
+            }
+        }
+        if let Some(sync) = &self.sync {
+            violations.nest("sync", sync.violations("sync"));
+        }
+        violations
+    }
+}
+
+impl RestMapping {
+    /// The validated mapping in a custom service's settings
+    pub fn from_config(config: &ServiceConfig) -> Result<Self> {
+        let mapping = config.settings.get(REST_MAPPING_SETTING)
+            .ok_or_else(|| anyhow::anyhow!("Custom service {} has no `{}` setting", config.name, REST_MAPPING_SETTING))?;
+        let mapping: Self = serde_json::from_value(mapping.clone())
+            .with_context(|| format!("Malformed `{}` of service {}", REST_MAPPING_SETTING, config.name))?;
+        mapping.validate()?;
+        Ok(mapping)
+    }
+
+    fn template(&self, kind: &str, resource_type: Option<&str>) -> Option<&OperationTemplate> {
+        match resource_type {
+            None => self.sync.as_ref(),
+            Some(resource_type) => self.resources.get(resource_type)?
+                .templates()
+                .find(|(defined, _)| *defined == kind)
+                .map(|(_, template)| template),
+        }
+    }
+
+    /// The operations defined, such as `contacts.list`, for error messages
+    fn defined(&self) -> String {
+        let mut defined: Vec<String> = self.resources.iter()
+            .flat_map(|(resource, operations)| {
+                operations.templates().map(move |(kind, _)| format!("{}.{}", resource, kind))
+            })
+            .collect();
+        if self.sync.is_some() {
+            defined.push("sync".to_string());
+        }
+        defined.join(", ")
+    }
+
+    fn url(&self, path: &str, values: &HashMap<String, Value>) -> Result<Url> {
+        let mut url = Url::parse(&self.base_url)?;
+        {
+            let mut segments = url.path_segments_mut()
+                .map_err(|_| anyhow::anyhow!("Base URL {} cannot take a path", self.base_url))?;
+            segments.pop_if_empty();
+            for segment in path.trim_start_matches('/').split('/') {
+                let segment = render(segment, values)
+                    .map_err(|name| anyhow::anyhow!("No value for {{{}}} in path {}", name, path))?;
+                segments.push(&segment);
+            }
+        }
+        Ok(url)
+    }
+}
+
+/// Whether an operation of the given kind can fill the placeholder `name`
+fn allows(kind: &str, name: &str) -> bool {
+    match name.split_once('.') {
+        Some(("filters", field)) => kind == "list" && !field.is_empty(),
+        Some(("data", field)) => matches!(kind, "create" | "update") && !field.is_empty(),
+        Some(_) => false,
+        None => match name {
+            "resource_type" => kind != "sync",
+            "resource_id" => matches!(kind, "get" | "update" | "delete"),
+            "limit" => matches!(kind, "list" | "search"),
+            "query" => kind == "search",
+            "data" => matches!(kind, "create" | "update"),
+            "full_sync" | "since" => kind == "sync",
+            _ => false,
+        },
+    }
+}
+
+fn check_template(violations: &mut Violations, field: &str, template: &str, kind: &str) {
+    match placeholders(template) {
+        Ok(names) => {
+            for name in names.into_iter().filter(|name| !allows(kind, name)) {
+                violations.push(field, format!("{} operations have no {{{}}}", kind, name));
+            }
+        }
+        Err(problem) => violations.push(field, problem),
+    }
+}
+
+fn collect_strings<'a>(value: &'a Value, strings: &mut Vec<&'a str>) {
+    match value {
+        Value::String(string) => strings.push(string),
+        Value::Array(items) => items.iter().for_each(|item| collect_strings(item, strings)),
+        Value::Object(fields) => fields.values().for_each(|field| collect_strings(field, strings)),
+        _ => {}
+    }
+}
+
+/// Names of the `{placeholders}` in `template`, or what is wrong with its braces
+fn placeholders(template: &str) -> Result<Vec<&str>, String> {
+    let mut names = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find(['{', '}']) {
+        if rest[start..].starts_with('}') {
+            return Err(format!("unmatched '}}' in {:?}", template));
+        }
+        let end = start + rest[start..].find('}').ok_or_else(|| format!("unclosed '{{' in {:?}", template))?;
+        let name = &rest[start + 1..end];
+        if name.is_empty() || name.contains('{') {
+            return Err(format!("malformed placeholder in {:?}", template));
+        }
+        names.push(name);
+        rest = &rest[end + 1..];
+    }
+    Ok(names)
+}
+
+/// `template` with its placeholders filled, or the name of one without a value
+fn render(template: &str, values: &HashMap<String, Value>) -> Result<String, String> {
+    let mut rendered = String::new();
+    let mut rest = template;
+    while let (Some(start), Some(end)) = (rest.find('{'), rest.find('}')) {
+        let name = &rest[start + 1..end];
+        match values.get(name).ok_or_else(|| name.to_string())? {
+            Value::String(value) => rendered.push_str(&format!("{}{}", &rest[..start], value)),
+            value => rendered.push_str(&format!("{}{}", &rest[..start], value)),
+        }
+        rest = &rest[end + 1..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+/// A body template with its placeholders filled; strings that are one placeholder become
+/// its value
+fn render_body(template: &Value, values: &HashMap<String, Value>) -> Result<Value, String> {
+    Ok(match template {
+        Value::String(string) => match placeholders(string).as_deref() {
+            Ok([name]) if string.len() == name.len() + 2 => values.get(*name).cloned().ok_or_else(|| name.to_string())?,
+            _ => Value::String(render(string, values)?),
+        },
+        Value::Array(items) => Value::Array(items.iter().map(|item| render_body(item, values)).collect::<Result<_, _>>()?),
+        Value::Object(fields) => Value::Object(
+            fields.iter()
+                .map(|(name, field)| Ok((name.clone(), render_body(field, values)?)))
+                .collect::<Result<_, String>>()?,
+        ),
+        other => other.clone(),
+    })
+}
+
+/// The kind of an operation, its resource type unless it is a sync, and the values of
+/// the placeholders it fills
+fn operation_values(operation: ServiceOperation) -> (&'static str, Option<String>, HashMap<String, Value>) {
+    let mut values = HashMap::new();
+    let (kind, resource_type) = match operation {
+        ServiceOperation::List { resource_type, limit, filters } => {
+            if let Some(limit) = limit {
+                values.insert("limit".to_string(), json!(limit));
+            }
+            for (name, value) in filters {
+                values.insert(format!("filters.{}", name), Value::String(value));
+            }
+            ("list", resource_type)
+        }
+        ServiceOperation::Get { resource_type, resource_id } => {
+            values.insert("resource_id".to_string(), Value::String(resource_id));
+            ("get", resource_type)
+        }
+        ServiceOperation::Create { resource_type, data } => {
+            insert_data(&mut values, data);
+            ("create", resource_type)
+        }
+        ServiceOperation::Update { resource_type, resource_id, data } => {
+            values.insert("resource_id".to_string(), Value::String(resource_id));
+            insert_data(&mut values, data);
+            ("update", resource_type)
+        }
+        ServiceOperation::Delete { resource_type, resource_id } => {
+            values.insert("resource_id".to_string(), Value::String(resource_id));
+            ("delete", resource_type)
+        }
+        ServiceOperation::Search { resource_type, query, limit } => {
+            values.insert("query".to_string(), Value::String(query));
+            if let Some(limit) = limit {
+                values.insert("limit".to_string(), json!(limit));
+            }
+            ("search", resource_type)
+        }
+        ServiceOperation::Sync { full_sync, since } => {
+            values.insert("full_sync".to_string(), json!(full_sync));
+            if let Some(since) = since {
+                values.insert("since".to_string(), Value::String(since.to_rfc3339()));
+            }
+            return ("sync", None, values);
+        }
+    };
+    values.insert("resource_type".to_string(), Value::String(resource_type.clone()));
+    (kind, Some(resource_type), values)
+}
+
+fn insert_data(values: &mut HashMap<String, Value>, data: Value) {
+    if let Value::Object(fields) = &data {
+        for (name, field) in fields {
+            values.insert(format!("data.{}", name), field.clone());
+        }
+    }
+    values.insert("data".to_string(), data);
+}
+
+/// Calls custom services' REST APIs as their mappings describe
+pub struct RestAdapter {
+    client: reqwest::Client,
+}
+
+impl RestAdapter {
+    pub fn new() -> Self {
+        Self { client: reqwest::Client::new() }
+    }
+
+    /// Check the service's mapping, and that its credentials suit the mapping's auth
+    pub async fn register_service(&self, config: &ServiceConfig) -> Result<()> {
+        let mapping = RestMapping::from_config(config)?;
+        mapping.auth.check(&config.credentials)
+            .with_context(|| format!("Credentials of service {} do not suit its REST mapping", config.name))?;
+        info!("Registering REST service {} at {}: {}", config.name, mapping.base_url, mapping.defined());
+        Ok(())
+    }
+
+    pub async fn execute_operation(&self, config: &ServiceConfig, operation: ServiceOperation) -> Result<ServiceResult> {
+        let mapping = RestMapping::from_config(config)?;
+        let (kind, resource_type, values) = operation_values(operation);
+        let template = mapping.template(kind, resource_type.as_deref()).ok_or_else(|| {
+            let operation = match &resource_type {
+                Some(resource_type) => format!("{} on {}", kind, resource_type),
+                None => kind.to_string(),
+            };
+            anyhow::anyhow!("Service {} maps no {} operation (it maps: {})", config.name, operation, mapping.defined())
+        })?;
+
+        let method = Method::from_bytes(template.method.to_uppercase().as_bytes())?;
+        let url = mapping.url(&template.path, &values)?;
+        info!("{} {} for {} of service {}", method, url.path(), kind, config.name);
+
+        let mut request = self.client.request(method.clone(), url);
+        for (name, value) in &mapping.headers {
+            request = request.header(name.as_str(), value.as_str());
+        }
+        request = mapping.auth.apply(request, &config.credentials)?;
+        let query: Vec<(&str, String)> = template.query.iter()
+            .filter_map(|(name, template)| render(template, &values).ok().map(|value| (name.as_str(), value)))
+            .collect();
+        if !query.is_empty() {
+            request = request.query(&query);
+        }
+        let body = match &template.body {
+            Some(body) => Some(render_body(body, &values)
+                .map_err(|name| anyhow::anyhow!("No value for {{{}}} in the body of {}", name, kind))?),
+            None => values.get("data").cloned(),
+        };
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+
+        let response = request.send().await
+            .with_context(|| format!("Calling {} of service {}", kind, config.name))?;
+        let status = response.status();
+        let text = response.text().await?;
+        let body = if text.trim().is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_str(&text).unwrap_or(Value::String(text))
+        };
+        let metadata = HashMap::from([
+            ("service".to_string(), json!(config.name)),
+            ("status".to_string(), json!(status.as_u16())),
+        ]);
+
+        if !status.is_success() {
+            return Ok(ServiceResult {
+                success: false,
+                data: body,
+                error: Some(format!("{} {} returned {}", method, template.path, status)),
+                metadata,
+            });
+        }
+        Ok(ServiceResult { success: true, data: template.extract(body), error: None, metadata })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ExternalServicesManager, ServiceType};
+    use uuid::Uuid;
+    use wiremock::matchers::{body_json, header, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn service(mapping: Value, credentials: ServiceCredentials) -> ServiceConfig {
+        ServiceConfig {
+            id: Uuid::nil(),
+            service_type: ServiceType::Custom { provider: "rest".to_string() },
+            name: "rest".to_string(),
+            enabled: true,
+            credentials,
+            settings: HashMap::from([(REST_MAPPING_SETTING.to_string(), mapping)]),
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        }
+    }
+
+    fn api_key(key: &str) -> ServiceCredentials {
+        ServiceCredentials::ApiKey { key: key.to_string(), secret: None }
+    }
+
+    fn crm_mapping(base_url: String) -> Value {
+        json!({
+            "base_url": base_url,
+            "auth": { "type": "header", "name": "X-Api-Key" },
+            "headers": { "Accept": "application/json" },
+            "resources": {
+                "contacts": {
+                    "list": {
+                        "method": "GET",
+                        "path": "/v2/contacts",
+                        "query": { "limit": "{limit}", "status": "{filters.status}" },
+                        "extract": { "items": "/results", "total": "/meta/total" }
+                    },
+                    "get": {
+                        "method": "GET",
+                        "path": "/v2/contacts/{resource_id}",
+                        "extract": { "id": "/id", "name": "/properties/name" }
+                    },
+                    "create": {
+                        "method": "POST",
+                        "path": "/v2/contacts",
+                        "body": { "properties": "{data}", "source": "talkpp" },
+                        "extract": { "id": "/id" }
+                    }
+                }
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn test_crm_operations_are_mapped_to_requests() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/crm/v2/contacts"))
+            .and(header("x-api-key", "crm-key"))
+            .and(header("accept", "application/json"))
+            .and(query_param("limit", "2"))
+            .and(query_param("status", "active"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "results": [{ "id": "1" }, { "id": "2" }],
+                "meta": { "total": 7 }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/crm/v2/contacts/42"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "42",
+                "properties": { "name": "Ada" }
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/crm/v2/contacts/404"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(json!({ "message": "no such contact" })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/crm/v2/contacts"))
+            .and(body_json(json!({ "properties": { "name": "Grace", "email": "grace@example.com" }, "source": "talkpp" })))
+            .respond_with(ResponseTemplate::new(201).set_body_json(json!({ "id": "43", "createdAt": "2024-01-01" })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let manager = ExternalServicesManager::new().with_plaintext_credentials();
+        let id = manager
+            .register_service(service(crm_mapping(format!("{}/crm/", server.uri())), api_key("crm-key")))
+            .await
+            .unwrap();
+
+        let listed = manager.execute_operation(id, ServiceOperation::List {
+            resource_type: "contacts".to_string(),
+            limit: Some(2),
+            filters: HashMap::from([("status".to_string(), "active".to_string())]),
+        }).await.unwrap();
+        assert!(listed.success);
+        assert_eq!(listed.data, json!({ "items": [{ "id": "1" }, { "id": "2" }], "total": 7 }));
+
+        let get = |resource_id: &str| ServiceOperation::Get {
+            resource_type: "contacts".to_string(),
+            resource_id: resource_id.to_string(),
+        };
+        let fetched = manager.execute_operation(id, get("42")).await.unwrap();
+        assert_eq!(fetched.data, json!({ "id": "42", "name": "Ada" }));
+
+        let missing = manager.execute_operation(id, get("404")).await.unwrap();
+        assert!(!missing.success);
+        assert_eq!(missing.metadata["status"], json!(404));
+        assert_eq!(missing.data, json!({ "message": "no such contact" }));
+
+        let created = manager.execute_operation(id, ServiceOperation::Create {
+            resource_type: "contacts".to_string(),
+            data: json!({ "name": "Grace", "email": "grace@example.com" }),
+        }).await.unwrap();
+        assert_eq!(created.data, json!({ "id": "43" }));
+
+        let err = manager.execute_operation(id, ServiceOperation::Delete {
+            resource_type: "contacts".to_string(),
+            resource_id: "42".to_string(),
+        }).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Service rest maps no delete on contacts operation (it maps: contacts.list, contacts.get, contacts.create)"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_webhook_creates_fill_path_and_body_from_data() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/hooks/deploys%20and%20alerts"))
+            .and(query_param("token", "hook-token"))
+            .and(body_json(json!({ "text": "Build 12 finished", "attempt": 2 })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "ok": true })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mapping = json!({
+            "base_url": server.uri(),
+            "auth": { "type": "query", "name": "token" },
+            "resources": {
+                "messages": {
+                    "create": {
+                        "method": "post",
+                        "path": "/hooks/{data.channel}",
+                        "body": { "text": "Build {data.build} finished", "attempt": "{data.attempt}" }
+                    }
+                }
+            }
+        });
+        let manager = ExternalServicesManager::new().with_plaintext_credentials();
+        let id = manager.register_service(service(mapping, api_key("hook-token"))).await.unwrap();
+
+        let sent = manager.execute_operation(id, ServiceOperation::Create {
+            resource_type: "messages".to_string(),
+            data: json!({ "channel": "deploys and alerts", "build": 12, "attempt": 2 }),
+        }).await.unwrap();
+        assert!(sent.success);
+        assert_eq!(sent.data, json!({ "ok": true }));
+
+        let err = manager.execute_operation(id, ServiceOperation::List {
+            resource_type: "messages".to_string(),
+            limit: None,
+            filters: HashMap::new(),
+        }).await.unwrap_err();
+        assert!(err.to_string().contains("maps no list on messages operation"), "{}", err);
+
+        let err = manager.execute_operation(id, ServiceOperation::Create {
+            resource_type: "messages".to_string(),
+            data: json!({ "build": 13 }),
+        }).await.unwrap_err();
+        assert_eq!(err.to_string(), "No value for {data.channel} in path /hooks/{data.channel}");
+    }
+
+    #[tokio::test]
+    async fn test_register_rejects_invalid_mappings_with_every_violation() {
+        let manager = ExternalServicesManager::new().with_plaintext_credentials();
+        let mapping = json!({
+            "base_url": "ftp://crm.example.com",
+            "auth": { "type": "header", "name": " " },
+            "resources": {
+                "contacts": {
+                    "list": { "method": "FETCH", "path": "/contacts/{resource_id}", "extract": { "items": "results" } },
+                    "create": { "method": "POST", "path": "contacts", "body": { "name": "{data.name" } }
+                }
+            }
+        });
+        let err = manager.register_service(service(mapping, api_key("key"))).await.unwrap_err();
+        let violations = err.downcast_ref::<talkpp_errors::Error>()
+            .and_then(|err| err.downcast_ref::<talkpp_errors::InvalidConfig>())
+            .map(|invalid| invalid.violations.iter().map(|v| v.field.as_str()).collect::<Vec<_>>())
+            .unwrap_or_else(|| panic!("not an invalid config: {}", err));
+        assert_eq!(violations, [
+            "base_url",
+            "auth.name",
+            "resources.contacts.list.method",
+            "resources.contacts.list.path",
+            "resources.contacts.list.extract.items",
+            "resources.contacts.create.path",
+            "resources.contacts.create.body",
+        ]);
+
+        let err = manager.register_service(service(json!({ "base_url": "https://crm.example.com" }), api_key("key")))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("resources: must define at least one operation"), "{}", err);
+
+        let mut unmapped = service(json!(null), api_key("key"));
+        unmapped.settings.clear();
+        let err = manager.register_service(unmapped).await.unwrap_err();
+        assert_eq!(err.to_string(), "Custom service rest has no `rest_mapping` setting");
+
+        let certificate = ServiceCredentials::Certificate {
+            cert_path: "crm.pem".to_string(),
+            key_path: "crm.key".to_string(),
+            password: None,
+        };
+        let err = manager.register_service(service(crm_mapping("https://crm.example.com".to_string()), certificate))
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("not certificate"), "{:#}", err);
+    }
+}
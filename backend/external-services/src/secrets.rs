@@ -0,0 +1,314 @@
+use super::ServiceCredentials;
+use anyhow::Result;
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Deserialize, Serialize};
+use talkpp_auth::secrets::{Sealed, SealingKey, SecretResolver};
+use talkpp_auth::session::Session;
+use uuid::Uuid;
+
+pub use talkpp_auth::secrets::MASTER_KEY_ENV;
+
+/// Permission required to read decrypted service credentials
+pub const READ_SECRETS_PERMISSION: &str = "services:read_secrets";
+
+const REDACTED: &str = "********";
+
+/// Ciphertext envelope stored in place of plaintext credentials
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncryptedBlob {
+    pub key_id: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+/// Envelope encryption for service credentials
+#[async_trait]
+pub trait SecretsCipher: Send + Sync {
+    fn key_id(&self) -> &str;
+    async fn encrypt(&self, plaintext: &[u8]) -> Result<EncryptedBlob>;
+    async fn decrypt(&self, blob: &EncryptedBlob) -> Result<Vec<u8>>;
+}
+
+/// AES-256-GCM cipher keyed from a locally provided master key, sealing with
+/// [`SealingKey`] like the auth crate's encrypted secrets file
+pub struct AesGcmCipher {
+    key_id: String,
+    key: SealingKey,
+}
+
+impl AesGcmCipher {
+    pub fn new(key_id: impl Into<String>, key: &[u8; 32]) -> Self {
+        Self {
+            key_id: key_id.into(),
+            key: SealingKey::new(key),
+        }
+    }
+
+    /// Load the master key from `TALKPP_MASTER_KEY`
+    pub fn from_env() -> Result<Self> {
+        Ok(Self { key_id: "env-master-key".to_string(), key: SealingKey::from_env()? })
+    }
+}
+
+#[async_trait]
+impl SecretsCipher for AesGcmCipher {
+    fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    async fn encrypt(&self, plaintext: &[u8]) -> Result<EncryptedBlob> {
+        let Sealed { nonce, ciphertext } = self.key.seal(plaintext)?;
+        Ok(EncryptedBlob { key_id: self.key_id.clone(), nonce, ciphertext })
+    }
+
+    async fn decrypt(&self, blob: &EncryptedBlob) -> Result<Vec<u8>> {
+        if blob.key_id != self.key_id {
+            return Err(anyhow::anyhow!("Credentials were encrypted with unknown key: {}", blob.key_id));
+        }
+        let sealed = Sealed { nonce: blob.nonce.clone(), ciphertext: blob.ciphertext.clone() };
+        self.key.open(&sealed).map_err(|e| anyhow::anyhow!("Credentials failed to decrypt: {}", e))
+    }
+}
+
+/// HashiCorp Vault transit engine cipher, configured from `services.vault_addr`
+pub struct VaultTransitCipher {
+    vault_addr: String,
+    key_name: String,
+    token: String,
+    client: reqwest::Client,
+}
+
+impl VaultTransitCipher {
+    pub fn new(vault_addr: impl Into<String>, key_name: impl Into<String>, token: impl Into<String>) -> Self {
+        Self {
+            vault_addr: vault_addr.into().trim_end_matches('/').to_string(),
+            key_name: key_name.into(),
+            token: token.into(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    #[tracing::instrument(name = "vault_transit", skip(self, body))]
+    async fn transit(&self, operation: &str, body: serde_json::Value) -> Result<serde_json::Value> {
+        let url = format!("{}/v1/transit/{}/{}", self.vault_addr, operation, self.key_name);
+        let mut request = self.client.post(url).header("X-Vault-Token", &self.token);
+        for (key, value) in cognitive_kernel::telemetry::trace_headers() {
+            request = request.header(key, value);
+        }
+        let response = request.json(&body).send().await?.error_for_status()?;
+
+        let mut payload: serde_json::Value = response.json().await?;
+        Ok(payload["data"].take())
+    }
+}
+
+#[async_trait]
+impl SecretsCipher for VaultTransitCipher {
+    fn key_id(&self) -> &str {
+        &self.key_name
+    }
+
+    async fn encrypt(&self, plaintext: &[u8]) -> Result<EncryptedBlob> {
+        let data = self.transit("encrypt", serde_json::json!({ "plaintext": BASE64.encode(plaintext) })).await?;
+        let ciphertext = data["ciphertext"].as_str()
+            .ok_or_else(|| anyhow::anyhow!("Vault transit response missing ciphertext"))?;
+
+        Ok(EncryptedBlob {
+            key_id: self.key_name.clone(),
+            nonce: String::new(),
+            ciphertext: ciphertext.to_string(),
+        })
+    }
+
+    async fn decrypt(&self, blob: &EncryptedBlob) -> Result<Vec<u8>> {
+        let data = self.transit("decrypt", serde_json::json!({ "ciphertext": blob.ciphertext })).await?;
+        let plaintext = data["plaintext"].as_str()
+            .ok_or_else(|| anyhow::anyhow!("Vault transit response missing plaintext"))?;
+        Ok(BASE64.decode(plaintext)?)
+    }
+}
+
+/// Proof that the caller holds `services:read_secrets`, taken from a verified session
+#[derive(Debug, Clone, Copy)]
+pub struct SecretsAccess {
+    user_id: Uuid,
+}
+
+impl SecretsAccess {
+    pub fn for_session(session: &Session) -> Result<Self> {
+        if session.has_permission(READ_SECRETS_PERMISSION) {
+            Ok(Self { user_id: session.user_id() })
+        } else {
+            Err(anyhow::anyhow!("Permission denied: {} required", READ_SECRETS_PERMISSION))
+        }
+    }
+
+    /// The user access was granted to
+    pub fn user_id(&self) -> Uuid {
+        self.user_id
+    }
+}
+
+impl ServiceCredentials {
+    pub async fn encrypt(&self, cipher: &dyn SecretsCipher) -> Result<ServiceCredentials> {
+        if let ServiceCredentials::Encrypted(_) = self {
+            return Ok(self.clone());
+        }
+        let plaintext = serde_json::to_vec(self)?;
+        Ok(ServiceCredentials::Encrypted(cipher.encrypt(&plaintext).await?))
+    }
+
+    pub async fn decrypt(&self, cipher: &dyn SecretsCipher) -> Result<ServiceCredentials> {
+        match self {
+            ServiceCredentials::Encrypted(blob) => {
+                let plaintext = cipher.decrypt(blob).await?;
+                Ok(serde_json::from_slice(&plaintext)?)
+            }
+            other => Ok(other.clone()),
+        }
+    }
+
+    /// Replace `env:`/`vault:` style references with the secrets they name. Literal values
+    /// and already encrypted credentials are left alone.
+    pub async fn resolve_references(&mut self, resolver: &SecretResolver) -> Result<()> {
+        match self {
+            ServiceCredentials::OAuth2 { client_id, client_secret, access_token, refresh_token, .. } => {
+                for value in [client_id, client_secret, access_token, refresh_token] {
+                    resolver.resolve_in_place(value).await?;
+                }
+            }
+            ServiceCredentials::ApiKey { key, secret } => {
+                resolver.resolve_in_place(key).await?;
+                if let Some(secret) = secret {
+                    resolver.resolve_in_place(secret).await?;
+                }
+            }
+            ServiceCredentials::BasicAuth { username, password } => {
+                resolver.resolve_in_place(username).await?;
+                resolver.resolve_in_place(password).await?;
+            }
+            ServiceCredentials::Certificate { password, .. } => {
+                if let Some(password) = password {
+                    resolver.resolve_in_place(password).await?;
+                }
+            }
+            ServiceCredentials::Encrypted(_) => {}
+        }
+        Ok(())
+    }
+
+    /// Copy with every secret value masked
+    pub fn redacted(&self) -> ServiceCredentials {
+        let mask = || REDACTED.to_string();
+        match self {
+            ServiceCredentials::OAuth2 { client_id, expires_at, .. } => ServiceCredentials::OAuth2 {
+                client_id: client_id.clone(),
+                client_secret: mask(),
+                access_token: mask(),
+                refresh_token: mask(),
+                expires_at: *expires_at,
+            },
+            ServiceCredentials::ApiKey { secret, .. } => ServiceCredentials::ApiKey {
+                key: mask(),
+                secret: secret.as_ref().map(|_| mask()),
+            },
+            ServiceCredentials::BasicAuth { username, .. } => ServiceCredentials::BasicAuth {
+                username: username.clone(),
+                password: mask(),
+            },
+            ServiceCredentials::Certificate { cert_path, key_path, password } => ServiceCredentials::Certificate {
+                cert_path: cert_path.clone(),
+                key_path: key_path.clone(),
+                password: password.as_ref().map(|_| mask()),
+            },
+            ServiceCredentials::Encrypted(blob) => ServiceCredentials::Encrypted(EncryptedBlob {
+                key_id: blob.key_id.clone(),
+                nonce: String::new(),
+                ciphertext: mask(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use talkpp_auth::session::SessionKeys;
+
+    fn cipher() -> AesGcmCipher {
+        AesGcmCipher::new("test-key", &[7u8; 32])
+    }
+
+    fn oauth() -> ServiceCredentials {
+        ServiceCredentials::OAuth2 {
+            client_id: "client".to_string(),
+            client_secret: "shh".to_string(),
+            access_token: "access".to_string(),
+            refresh_token: "refresh".to_string(),
+            expires_at: chrono::Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_credentials_round_trip() {
+        let cipher = cipher();
+        let encrypted = oauth().encrypt(&cipher).await.unwrap();
+
+        let serialized = serde_json::to_string(&encrypted).unwrap();
+        assert!(!serialized.contains("shh"));
+        assert!(!serialized.contains("refresh"));
+
+        let decrypted = encrypted.decrypt(&cipher).await.unwrap();
+        match decrypted {
+            ServiceCredentials::OAuth2 { client_secret, refresh_token, .. } => {
+                assert_eq!(client_secret, "shh");
+                assert_eq!(refresh_token, "refresh");
+            }
+            other => panic!("unexpected credentials: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tampered_ciphertext_fails_authentication() {
+        let cipher = cipher();
+        let ServiceCredentials::Encrypted(mut blob) = oauth().encrypt(&cipher).await.unwrap() else {
+            panic!("expected encrypted credentials");
+        };
+
+        let mut bytes = BASE64.decode(&blob.ciphertext).unwrap();
+        bytes[0] ^= 0x01;
+        blob.ciphertext = BASE64.encode(bytes);
+
+        let err = ServiceCredentials::Encrypted(blob).decrypt(&cipher).await.unwrap_err();
+        assert!(err.to_string().contains("failed authentication"));
+    }
+
+    #[tokio::test]
+    async fn test_wrong_key_rejected() {
+        let encrypted = oauth().encrypt(&cipher()).await.unwrap();
+        let other = AesGcmCipher::new("test-key", &[9u8; 32]);
+        assert!(encrypted.decrypt(&other).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sealed_like_the_auth_secrets_file() {
+        let blob = cipher().encrypt(b"shared format").await.unwrap();
+        let sealed = Sealed { nonce: blob.nonce, ciphertext: blob.ciphertext };
+        assert_eq!(SealingKey::new(&[7u8; 32]).open(&sealed).unwrap(), b"shared format");
+    }
+
+    #[test]
+    fn test_secrets_access_requires_permission() {
+        let keys = SessionKeys::new(b"session-secret");
+        let session = |permission: &str| {
+            let token = keys.issue(Uuid::new_v4(), vec![permission.to_string()], Duration::from_secs(60)).unwrap();
+            keys.verify(&token).unwrap()
+        };
+
+        assert!(SecretsAccess::for_session(&session("services:read")).is_err());
+        let session = session(READ_SECRETS_PERMISSION);
+        assert_eq!(SecretsAccess::for_session(&session).unwrap().user_id(), session.user_id());
+    }
+}
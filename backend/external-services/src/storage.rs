@@ -1,8 +1,14 @@
 use super::{ServiceConfig, ServiceOperation, ServiceResult};  
 use anyhow::Result;
+use async_trait::async_trait;
+use aws_sdk_s3::primitives::ByteStream;
+use cognitive_kernel::artifacts::{
+    Artifact, ArtifactError, ArtifactInfo, ArtifactMetadata, ArtifactRef, ArtifactStore,
+};
 use serde_json::json;
 use std::collections::HashMap;
-use tracing::{info, error};
+use tracing::{info, error, warn};
+use uuid::Uuid;
 
 pub struct StorageService {}
 
@@ -24,4 +30,131 @@ impl StorageService {
             metadata: HashMap::new(),
         })
     }
-} 
\ No newline at end of file
+} 
+/// Keeps artifacts in an S3 bucket, each as `<prefix>/<id>` beside a `<prefix>/<id>.json`
+/// description
+pub struct S3ArtifactStore {
+    client: aws_sdk_s3::Client,
+    bucket: String,
+    prefix: String,
+}
+
+impl S3ArtifactStore {
+    pub fn new(client: aws_sdk_s3::Client, bucket: impl Into<String>) -> Self {
+        Self { client, bucket: bucket.into(), prefix: "artifacts".to_string() }
+    }
+
+    /// Store in `bucket` with credentials and region from the AWS environment: variables,
+    /// profiles or the instance role
+    pub async fn from_env(bucket: impl Into<String>) -> Self {
+        let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+        Self::new(aws_sdk_s3::Client::new(&config), bucket)
+    }
+
+    /// Key prefix artifacts are stored under, `artifacts` by default
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    fn data_key(&self, id: Uuid) -> String {
+        format!("{}/{}", self.prefix, id)
+    }
+
+    fn info_key(&self, id: Uuid) -> String {
+        format!("{}/{}.json", self.prefix, id)
+    }
+
+    /// The object at `key`, or `None` if there is none
+    async fn read(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match self.client.get_object().bucket(&self.bucket).key(key).send().await {
+            Ok(object) => Ok(Some(object.body.collect().await?.into_bytes().to_vec())),
+            Err(e) if e.as_service_error().is_some_and(|e| e.is_no_such_key()) => Ok(None),
+            Err(e) => Err(anyhow::anyhow!("Failed to read s3://{}/{}: {}", self.bucket, key, e)),
+        }
+    }
+
+    async fn write(&self, key: &str, bytes: Vec<u8>, content_type: &str) -> Result<()> {
+        self.client.put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .content_type(content_type)
+            .body(ByteStream::from(bytes))
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to write s3://{}/{}: {}", self.bucket, key, e))?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ArtifactStore for S3ArtifactStore {
+    async fn put(&self, bytes: Vec<u8>, metadata: ArtifactMetadata) -> Result<ArtifactRef> {
+        let info = ArtifactInfo::describe(&bytes, metadata);
+        let id = info.reference.id;
+        self.write(&self.data_key(id), bytes, &info.reference.content_type).await?;
+        // The description goes last, so a listed artifact always has its bytes
+        self.write(&self.info_key(id), serde_json::to_vec(&info)?, "application/json").await?;
+        Ok(info.reference)
+    }
+
+    async fn get(&self, id: Uuid) -> Result<Artifact> {
+        let info = self.read(&self.info_key(id)).await?.ok_or(ArtifactError::NotFound(id))?;
+        let info: ArtifactInfo = serde_json::from_slice(&info)?;
+        let bytes = self.read(&self.data_key(id)).await?.ok_or(ArtifactError::NotFound(id))?;
+        Ok(Artifact::verified(info, bytes)?)
+    }
+
+    async fn list_by_plan(&self, plan_id: Uuid) -> Result<Vec<ArtifactInfo>> {
+        let mut artifacts = self.list().await?;
+        artifacts.retain(|artifact| artifact.plan_id == Some(plan_id));
+        Ok(artifacts)
+    }
+
+    async fn list(&self) -> Result<Vec<ArtifactInfo>> {
+        let mut keys = Vec::new();
+        let mut continuation = None;
+        loop {
+            let page = self.client.list_objects_v2()
+                .bucket(&self.bucket)
+                .prefix(format!("{}/", self.prefix))
+                .set_continuation_token(continuation)
+                .send()
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to list s3://{}/{}: {}", self.bucket, self.prefix, e))?;
+            let descriptions = page.contents().iter().filter_map(|object| object.key()).filter(|key| key.ends_with(".json"));
+            keys.extend(descriptions.map(String::from));
+            continuation = page.next_continuation_token().map(String::from);
+            if continuation.is_none() {
+                break;
+            }
+        }
+
+        let mut artifacts = Vec::new();
+        for key in keys {
+            // Deleted since it was listed
+            let Some(json) = self.read(&key).await? else {
+                continue;
+            };
+            match serde_json::from_slice::<ArtifactInfo>(&json) {
+                Ok(info) => artifacts.push(info),
+                Err(e) => warn!("Skipping unreadable artifact description s3://{}/{}: {}", self.bucket, key, e),
+            }
+        }
+        artifacts.sort_by_key(|artifact| artifact.created_at);
+        Ok(artifacts)
+    }
+
+    async fn delete(&self, id: Uuid) -> Result<()> {
+        // S3 deletes of missing keys succeed
+        for key in [self.info_key(id), self.data_key(id)] {
+            self.client.delete_object()
+                .bucket(&self.bucket)
+                .key(&key)
+                .send()
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to delete s3://{}/{}: {}", self.bucket, key, e))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,1199 @@
+use super::{ExternalServicesManager, ServiceConfig, ServiceCredentials, ServiceType};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use hmac::{Hmac, Mac};
+use reqwest::RequestBuilder;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Maximum subscription lifetime accepted by Microsoft Graph for mail/calendar resources
+const MICROSOFT_MAX_LIFETIME_MINUTES: i64 = 4230;
+/// Gmail `users.watch` and Calendar channels expire after seven days
+const GOOGLE_MAX_LIFETIME_MINUTES: i64 = 7 * 24 * 60;
+
+/// Microsoft Graph endpoint
+pub const GRAPH_API: &str = "https://graph.microsoft.com/v1.0";
+/// Google APIs endpoint
+pub const GOOGLE_API: &str = "https://www.googleapis.com";
+/// Setting of a Gmail service naming the Pub/Sub topic `users.watch` publishes to
+pub const GMAIL_TOPIC_SETTING: &str = "pubsub_topic";
+
+/// Changes Graph subscriptions are created for
+const GRAPH_CHANGE_TYPES: &str = "created,updated,deleted";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WebhookProvider {
+    Google,
+    Microsoft,
+}
+
+impl WebhookProvider {
+    pub fn for_service_type(service_type: &ServiceType) -> Option<Self> {
+        match service_type {
+            ServiceType::Gmail | ServiceType::GoogleCalendar | ServiceType::GoogleDrive => Some(Self::Google),
+            ServiceType::OutlookCalendar | ServiceType::OutlookContacts |
+            ServiceType::Exchange | ServiceType::OneDrive => Some(Self::Microsoft),
+            _ => None,
+        }
+    }
+
+    fn max_lifetime(&self) -> chrono::Duration {
+        match self {
+            Self::Google => chrono::Duration::minutes(GOOGLE_MAX_LIFETIME_MINUTES),
+            Self::Microsoft => chrono::Duration::minutes(MICROSOFT_MAX_LIFETIME_MINUTES),
+        }
+    }
+}
+
+/// Push subscription registered with a provider on behalf of a service
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookSubscription {
+    pub id: Uuid,
+    pub service_id: Uuid,
+    pub provider: WebhookProvider,
+    pub resource: String,
+    /// The provider's id for the subscription: Graph's subscription id, the resource id
+    /// of a Google watch channel, or the address of a watched Gmail mailbox
+    #[serde(default)]
+    pub external_id: Option<String>,
+    /// Secret echoed back by the provider with every notification
+    pub client_state: String,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub renewed_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Normalized inbound notification
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookNotification {
+    pub subscription_id: Uuid,
+    pub service_id: Uuid,
+    pub provider: WebhookProvider,
+    pub resource: String,
+    pub change_type: String,
+    pub received_at: chrono::DateTime<chrono::Utc>,
+    pub payload: serde_json::Value,
+}
+
+/// Handler invoked for each validated notification, e.g. to run an incremental sync
+#[async_trait]
+pub trait WebhookHandler: Send + Sync {
+    async fn handle(&self, notification: WebhookNotification) -> Result<()>;
+}
+
+/// A subscription as the provider registered it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProviderSubscription {
+    pub external_id: Option<String>,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Registers subscriptions with the provider
+#[async_trait]
+pub trait SubscriptionApi: Send + Sync {
+    /// Register `subscription`, asking for it to last until its `expires_at`
+    async fn subscribe(&self, config: &ServiceConfig, subscription: &WebhookSubscription) -> Result<ProviderSubscription>;
+
+    /// Extend `subscription` until `expires_at`
+    async fn renew(
+        &self,
+        config: &ServiceConfig,
+        subscription: &WebhookSubscription,
+        expires_at: chrono::DateTime<chrono::Utc>,
+    ) -> Result<ProviderSubscription>;
+
+    /// Stop `subscription`; one the provider no longer knows counts as stopped
+    async fn unsubscribe(&self, config: &ServiceConfig, subscription: &WebhookSubscription) -> Result<()>;
+}
+
+/// [`SubscriptionApi`] calling Graph `/subscriptions`, Gmail `users.watch` and the
+/// Calendar and Drive watch channels with the service's OAuth2 access token
+pub struct HttpSubscriptionApi {
+    client: reqwest::Client,
+    /// Public URL the webhook [`router`] is mounted at, e.g. `https://example.com/webhooks`
+    callback_url: String,
+    graph_api: String,
+    google_api: String,
+}
+
+impl HttpSubscriptionApi {
+    pub fn new(callback_url: impl Into<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            callback_url: callback_url.into().trim_end_matches('/').to_string(),
+            graph_api: GRAPH_API.to_string(),
+            google_api: GOOGLE_API.to_string(),
+        }
+    }
+
+    /// Call another Graph endpoint, such as a national cloud's
+    pub fn with_graph_api(mut self, url: impl Into<String>) -> Self {
+        self.graph_api = url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn with_google_api(mut self, url: impl Into<String>) -> Self {
+        self.google_api = url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    async fn graph_subscribe(&self, config: &ServiceConfig, subscription: &WebhookSubscription) -> Result<ProviderSubscription> {
+        let body = json!({
+            "changeType": GRAPH_CHANGE_TYPES,
+            "notificationUrl": format!("{}/microsoft", self.callback_url),
+            "resource": subscription.resource,
+            "expirationDateTime": subscription.expires_at.to_rfc3339(),
+            "clientState": subscription.client_state,
+        });
+        let request = self.client.post(format!("{}/subscriptions", self.graph_api)).json(&body);
+        graph_subscription(send(request, config).await?)
+    }
+
+    async fn graph_renew(
+        &self,
+        config: &ServiceConfig,
+        subscription: &WebhookSubscription,
+        expires_at: chrono::DateTime<chrono::Utc>,
+    ) -> Result<ProviderSubscription> {
+        let request = self.client
+            .patch(format!("{}/subscriptions/{}", self.graph_api, graph_id(subscription)?))
+            .json(&json!({ "expirationDateTime": expires_at.to_rfc3339() }));
+        graph_subscription(send(request, config).await?)
+    }
+
+    async fn graph_unsubscribe(&self, config: &ServiceConfig, subscription: &WebhookSubscription) -> Result<()> {
+        let request = self.client.delete(format!("{}/subscriptions/{}", self.graph_api, graph_id(subscription)?));
+        send(request, config).await.map(|_| ())
+    }
+
+    /// Gmail watches the whole mailbox and publishes to the service's Pub/Sub topic;
+    /// watching again renews it. Pushes name the mailbox by address, so that is kept too.
+    async fn gmail_watch(&self, config: &ServiceConfig) -> Result<ProviderSubscription> {
+        let topic = config.settings.get(GMAIL_TOPIC_SETTING).and_then(Value::as_str)
+            .ok_or_else(|| anyhow::anyhow!("Gmail service {} has no {} setting", config.name, GMAIL_TOPIC_SETTING))?;
+        let request = self.client
+            .post(format!("{}/gmail/v1/users/me/watch", self.google_api))
+            .json(&json!({ "topicName": topic }));
+        let watch = send(request, config).await?
+            .ok_or_else(|| anyhow::anyhow!("Gmail mailbox of service {} not found", config.name))?;
+
+        let request = self.client.get(format!("{}/gmail/v1/users/me/profile", self.google_api));
+        let profile = send(request, config).await?
+            .ok_or_else(|| anyhow::anyhow!("Gmail mailbox of service {} not found", config.name))?;
+        let address = profile["emailAddress"].as_str()
+            .ok_or_else(|| anyhow::anyhow!("Gmail profile of service {} has no address", config.name))?;
+
+        Ok(ProviderSubscription { external_id: Some(address.to_string()), expires_at: google_expiration(&watch)? })
+    }
+
+    async fn gmail_stop(&self, config: &ServiceConfig) -> Result<()> {
+        let request = self.client.post(format!("{}/gmail/v1/users/me/stop", self.google_api));
+        send(request, config).await.map(|_| ())
+    }
+
+    /// Calendar and Drive notify a channel whose id is the subscription's and whose token is
+    /// its client state
+    async fn channel_watch(&self, config: &ServiceConfig, subscription: &WebhookSubscription) -> Result<ProviderSubscription> {
+        let body = json!({
+            "id": subscription.id,
+            "type": "web_hook",
+            "address": format!("{}/google", self.callback_url),
+            "token": subscription.client_state,
+            "expiration": subscription.expires_at.timestamp_millis().to_string(),
+        });
+        let request = self.client
+            .post(format!("{}/{}/{}/watch", self.google_api, channel_api(config)?, subscription.resource.trim_matches('/')))
+            .json(&body);
+        let response = send(request, config).await?
+            .ok_or_else(|| anyhow::anyhow!("Resource {} of service {} not found", subscription.resource, config.name))?;
+        Ok(ProviderSubscription {
+            external_id: response["resourceId"].as_str().map(str::to_string),
+            expires_at: google_expiration(&response)?,
+        })
+    }
+
+    async fn channel_stop(&self, config: &ServiceConfig, subscription: &WebhookSubscription) -> Result<()> {
+        let request = self.client
+            .post(format!("{}/{}/channels/stop", self.google_api, channel_api(config)?))
+            .json(&json!({ "id": subscription.id, "resourceId": subscription.external_id }));
+        send(request, config).await.map(|_| ())
+    }
+}
+
+#[async_trait]
+impl SubscriptionApi for HttpSubscriptionApi {
+    async fn subscribe(&self, config: &ServiceConfig, subscription: &WebhookSubscription) -> Result<ProviderSubscription> {
+        match (subscription.provider, &config.service_type) {
+            (WebhookProvider::Microsoft, _) => self.graph_subscribe(config, subscription).await,
+            (WebhookProvider::Google, ServiceType::Gmail) => self.gmail_watch(config).await,
+            (WebhookProvider::Google, _) => self.channel_watch(config, subscription).await,
+        }
+    }
+
+    async fn renew(
+        &self,
+        config: &ServiceConfig,
+        subscription: &WebhookSubscription,
+        expires_at: chrono::DateTime<chrono::Utc>,
+    ) -> Result<ProviderSubscription> {
+        match (subscription.provider, &config.service_type) {
+            (WebhookProvider::Microsoft, _) => self.graph_renew(config, subscription, expires_at).await,
+            (WebhookProvider::Google, ServiceType::Gmail) => self.gmail_watch(config).await,
+            // Channels can't be extended, so the channel is replaced by a new one
+            (WebhookProvider::Google, _) => {
+                self.channel_stop(config, subscription).await?;
+                let renewed = WebhookSubscription { expires_at, ..subscription.clone() };
+                self.channel_watch(config, &renewed).await
+            }
+        }
+    }
+
+    async fn unsubscribe(&self, config: &ServiceConfig, subscription: &WebhookSubscription) -> Result<()> {
+        match (subscription.provider, &config.service_type) {
+            (WebhookProvider::Microsoft, _) => self.graph_unsubscribe(config, subscription).await,
+            (WebhookProvider::Google, ServiceType::Gmail) => self.gmail_stop(config).await,
+            (WebhookProvider::Google, _) => self.channel_stop(config, subscription).await,
+        }
+    }
+}
+
+/// Send `request` with the service's access token. A 404 gives `None`.
+async fn send(request: RequestBuilder, config: &ServiceConfig) -> Result<Option<Value>> {
+    let ServiceCredentials::OAuth2 { access_token, .. } = &config.credentials else {
+        anyhow::bail!("Push notifications for service {} need OAuth2 credentials", config.name);
+    };
+    let response = request.bearer_auth(access_token).send().await
+        .with_context(|| format!("Calling the provider of service {}", config.name))?;
+    let status = response.status();
+    if status == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let text = response.text().await?;
+    if !status.is_success() {
+        anyhow::bail!("Provider of service {} returned {}: {}", config.name, status, text);
+    }
+    Ok(Some(if text.trim().is_empty() { Value::Null } else { serde_json::from_str(&text)? }))
+}
+
+fn graph_id(subscription: &WebhookSubscription) -> Result<&str> {
+    subscription.external_id.as_deref()
+        .ok_or_else(|| anyhow::anyhow!("Webhook subscription {} has no Graph subscription id", subscription.id))
+}
+
+fn graph_subscription(response: Option<Value>) -> Result<ProviderSubscription> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct GraphSubscription {
+        id: String,
+        expiration_date_time: chrono::DateTime<chrono::Utc>,
+    }
+
+    let response = response.ok_or_else(|| anyhow::anyhow!("Graph subscription not found"))?;
+    let subscription: GraphSubscription = serde_json::from_value(response)
+        .context("Unexpected Graph subscription response")?;
+    Ok(ProviderSubscription {
+        external_id: Some(subscription.id),
+        expires_at: subscription.expiration_date_time,
+    })
+}
+
+/// Google reports expirations as a string of milliseconds since the epoch
+fn google_expiration(response: &Value) -> Result<chrono::DateTime<chrono::Utc>> {
+    response["expiration"].as_str()
+        .and_then(|ms| ms.parse().ok())
+        .and_then(chrono::DateTime::from_timestamp_millis)
+        .ok_or_else(|| anyhow::anyhow!("Google watch response has no expiration: {}", response))
+}
+
+fn channel_api(config: &ServiceConfig) -> Result<&'static str> {
+    match config.service_type {
+        ServiceType::GoogleCalendar => Ok("calendar/v3"),
+        ServiceType::GoogleDrive => Ok("drive/v3"),
+        _ => Err(anyhow::anyhow!("No Google watch channels for service type: {:?}", config.service_type)),
+    }
+}
+
+/// Webhook subscription manager
+pub struct WebhookManager {
+    subscriptions: tokio::sync::RwLock<HashMap<Uuid, WebhookSubscription>>,
+    /// Where the services with subscriptions are registered. Their credentials are looked
+    /// up for every call to the provider, so refreshed access tokens are used.
+    services: Arc<ExternalServicesManager>,
+    handler: tokio::sync::RwLock<Option<Arc<dyn WebhookHandler>>>,
+    api: Arc<dyn SubscriptionApi>,
+    signing_secret: Vec<u8>,
+    renewal_margin: chrono::Duration,
+}
+
+impl WebhookManager {
+    pub fn new(
+        signing_secret: impl Into<Vec<u8>>,
+        api: Arc<dyn SubscriptionApi>,
+        services: Arc<ExternalServicesManager>,
+    ) -> Self {
+        Self {
+            subscriptions: tokio::sync::RwLock::new(HashMap::new()),
+            services,
+            handler: tokio::sync::RwLock::new(None),
+            api,
+            signing_secret: signing_secret.into(),
+            renewal_margin: chrono::Duration::hours(12),
+        }
+    }
+
+    pub fn with_renewal_margin(mut self, margin: chrono::Duration) -> Self {
+        self.renewal_margin = margin;
+        self
+    }
+
+    pub async fn set_handler(&self, handler: Arc<dyn WebhookHandler>) {
+        *self.handler.write().await = Some(handler);
+    }
+
+    /// Token Gmail's Pub/Sub push subscription must send, as the `token` query parameter of
+    /// its endpoint `<callback url>/google/pubsub?token=<token>`
+    pub fn pubsub_push_token(&self) -> String {
+        self.sign(PUBSUB_TOKEN_LABEL)
+    }
+
+    /// Create a push subscription for a resource of a service registered with the
+    /// manager's `ExternalServicesManager`
+    pub async fn create_subscription(&self, service_id: Uuid, resource: &str) -> Result<WebhookSubscription> {
+        let config = self.services.service_config(service_id).await?;
+        let provider = WebhookProvider::for_service_type(&config.service_type)
+            .ok_or_else(|| anyhow::anyhow!("Push notifications not supported for service type: {:?}", config.service_type))?;
+
+        let id = Uuid::new_v4();
+        let now = chrono::Utc::now();
+        let mut subscription = WebhookSubscription {
+            id,
+            service_id: config.id,
+            provider,
+            resource: resource.to_string(),
+            external_id: None,
+            client_state: self.sign(id.to_string().as_bytes()),
+            expires_at: now + provider.max_lifetime(),
+            created_at: now,
+            renewed_at: None,
+        };
+
+        let registered = self.api.subscribe(&config, &subscription).await
+            .with_context(|| format!("Subscribing to {} of service {}", resource, config.name))?;
+        subscription.external_id = registered.external_id;
+        subscription.expires_at = registered.expires_at;
+        info!("Created {:?} webhook subscription {} for {} ({})", provider, id, config.name, resource);
+
+        self.subscriptions.write().await.insert(id, subscription.clone());
+        Ok(subscription)
+    }
+
+    /// Extend a subscription to the provider's maximum lifetime
+    pub async fn renew_subscription(&self, subscription_id: Uuid) -> Result<WebhookSubscription> {
+        let (subscription, config) = self.registered(subscription_id).await?;
+
+        let now = chrono::Utc::now();
+        let registered = self.api.renew(&config, &subscription, now + subscription.provider.max_lifetime()).await
+            .with_context(|| format!("Renewing webhook subscription {}", subscription_id))?;
+
+        let mut subscriptions = self.subscriptions.write().await;
+        let subscription = subscriptions.get_mut(&subscription_id)
+            .ok_or_else(|| anyhow::anyhow!("Webhook subscription not found: {}", subscription_id))?;
+        subscription.external_id = registered.external_id;
+        subscription.expires_at = registered.expires_at;
+        subscription.renewed_at = Some(now);
+
+        info!("Renewed webhook subscription {} until {}", subscription_id, subscription.expires_at);
+        Ok(subscription.clone())
+    }
+
+    /// Stop a subscription at the provider and forget it. It is kept when the provider
+    /// fails, so that removing it can be retried.
+    ///
+    /// A Gmail watch covers the whole mailbox, so it is only stopped with the last
+    /// subscription of its service.
+    pub async fn remove_subscription(&self, subscription_id: Uuid) -> Result<()> {
+        let (subscription, config) = self.registered(subscription_id).await?;
+        // Taken out before the provider is called, so that of two Gmail subscriptions removed
+        // at once, the last one out stops the watch
+        let watch_shared = {
+            let mut subscriptions = self.subscriptions.write().await;
+            if subscriptions.remove(&subscription_id).is_none() {
+                anyhow::bail!("Webhook subscription not found: {}", subscription_id);
+            }
+            matches!(config.service_type, ServiceType::Gmail)
+                && subscriptions.values().any(|s| s.service_id == subscription.service_id)
+        };
+        if watch_shared {
+            return Ok(());
+        }
+
+        if let Err(e) = self.api.unsubscribe(&config, &subscription).await {
+            self.subscriptions.write().await.insert(subscription_id, subscription);
+            return Err(e.context(format!("Removing webhook subscription {}", subscription_id)));
+        }
+        Ok(())
+    }
+
+    async fn registered(&self, subscription_id: Uuid) -> Result<(WebhookSubscription, ServiceConfig)> {
+        let subscription = self.subscriptions.read().await.get(&subscription_id).cloned()
+            .ok_or_else(|| anyhow::anyhow!("Webhook subscription not found: {}", subscription_id))?;
+        let config = self.services.service_config(subscription.service_id).await
+            .with_context(|| format!("Service of webhook subscription {}", subscription_id))?;
+        Ok((subscription, config))
+    }
+
+    pub async fn list_subscriptions(&self) -> Vec<WebhookSubscription> {
+        self.subscriptions.read().await.values().cloned().collect()
+    }
+
+    /// Renew every subscription expiring within the renewal margin
+    pub async fn renew_expiring(&self) -> Vec<Uuid> {
+        let deadline = chrono::Utc::now() + self.renewal_margin;
+        let due: Vec<Uuid> = {
+            let subscriptions = self.subscriptions.read().await;
+            subscriptions.values()
+                .filter(|s| s.expires_at <= deadline)
+                .map(|s| s.id)
+                .collect()
+        };
+
+        let mut renewed = Vec::new();
+        for id in due {
+            match self.renew_subscription(id).await {
+                Ok(_) => renewed.push(id),
+                Err(e) => error!("Failed to renew webhook subscription {}: {}", id, e),
+            }
+        }
+        renewed
+    }
+
+    /// Spawn the background task that keeps subscriptions alive
+    pub fn spawn_renewal_task(self: Arc<Self>, interval: std::time::Duration) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let renewed = self.renew_expiring().await;
+                if !renewed.is_empty() {
+                    info!("Renewed {} webhook subscriptions", renewed.len());
+                }
+            }
+        })
+    }
+
+    fn sign(&self, data: &[u8]) -> String {
+        let mut mac = HmacSha256::new_from_slice(&self.signing_secret)
+            .expect("HMAC accepts keys of any length");
+        mac.update(data);
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    fn verify_signature(&self, data: &[u8], signature: &str) -> bool {
+        let Ok(expected) = hex::decode(signature) else {
+            return false;
+        };
+        let mut mac = HmacSha256::new_from_slice(&self.signing_secret)
+            .expect("HMAC accepts keys of any length");
+        mac.update(data);
+        mac.verify_slice(&expected).is_ok()
+    }
+
+    async fn dispatch(&self, notification: WebhookNotification) {
+        let handler = self.handler.read().await.clone();
+        match handler {
+            Some(handler) => {
+                if let Err(e) = handler.handle(notification).await {
+                    error!("Webhook handler failed: {}", e);
+                }
+            }
+            None => warn!("Dropping webhook notification for {}: no handler registered", notification.service_id),
+        }
+    }
+
+    /// Validate a Calendar or Drive channel notification. Google echoes the channel token
+    /// supplied when creating the watch, which is an HMAC of the channel id.
+    async fn handle_google(&self, headers: &HeaderMap, body: &[u8]) -> Result<WebhookNotification, StatusCode> {
+        let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+
+        let channel_id = header("x-goog-channel-id")
+            .and_then(|v| Uuid::parse_str(v).ok())
+            .ok_or(StatusCode::BAD_REQUEST)?;
+        let token = header("x-goog-channel-token").ok_or(StatusCode::UNAUTHORIZED)?;
+
+        let subscription = self.subscriptions.read().await.get(&channel_id).cloned()
+            .ok_or(StatusCode::NOT_FOUND)?;
+
+        if subscription.provider != WebhookProvider::Google || !self.verify_signature(channel_id.to_string().as_bytes(), token) {
+            warn!("Rejected Google notification with invalid channel token for {}", channel_id);
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+
+        let payload = if body.is_empty() {
+            serde_json::Value::Null
+        } else {
+            serde_json::from_slice(body).map_err(|_| StatusCode::BAD_REQUEST)?
+        };
+
+        Ok(WebhookNotification {
+            subscription_id: subscription.id,
+            service_id: subscription.service_id,
+            provider: WebhookProvider::Google,
+            resource: header("x-goog-resource-uri").unwrap_or(&subscription.resource).to_string(),
+            change_type: header("x-goog-resource-state").unwrap_or("exists").to_string(),
+            received_at: chrono::Utc::now(),
+            payload,
+        })
+    }
+
+    /// Validate a Pub/Sub push of a Gmail change and turn it into a notification for every
+    /// subscription of the mailbox it names
+    async fn handle_gmail_push(&self, token: Option<&str>, body: &[u8]) -> Result<Vec<WebhookNotification>, StatusCode> {
+        if !token.is_some_and(|token| self.verify_signature(PUBSUB_TOKEN_LABEL, token)) {
+            warn!("Rejected Pub/Sub push with invalid token");
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+
+        let push: PubSubPush = serde_json::from_slice(body).map_err(|_| StatusCode::BAD_REQUEST)?;
+        let data = BASE64.decode(&push.message.data).map_err(|_| StatusCode::BAD_REQUEST)?;
+        let change: GmailChange = serde_json::from_slice(&data).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+        let subscriptions = self.subscriptions.read().await;
+        let notifications: Vec<_> = subscriptions.values()
+            .filter(|s| s.provider == WebhookProvider::Google && s.external_id.as_deref() == Some(change.email_address.as_str()))
+            .map(|s| WebhookNotification {
+                subscription_id: s.id,
+                service_id: s.service_id,
+                provider: WebhookProvider::Google,
+                resource: s.resource.clone(),
+                change_type: "updated".to_string(),
+                received_at: chrono::Utc::now(),
+                payload: json!({
+                    "emailAddress": change.email_address,
+                    "historyId": change.history_id,
+                    "messageId": push.message.message_id,
+                }),
+            })
+            .collect();
+
+        if notifications.is_empty() {
+            warn!("Dropping Pub/Sub push for unwatched mailbox {}", change.email_address);
+        }
+        Ok(notifications)
+    }
+
+    /// Validate a batch of Microsoft Graph change notifications against their client state.
+    /// Notifications carry Graph's subscription id, not ours.
+    async fn handle_microsoft(&self, batch: MicrosoftNotificationBatch) -> Result<Vec<WebhookNotification>, StatusCode> {
+        let subscriptions = self.subscriptions.read().await;
+        let mut notifications = Vec::with_capacity(batch.value.len());
+
+        for item in batch.value {
+            let subscription = subscriptions.values()
+                .find(|s| s.provider == WebhookProvider::Microsoft && s.external_id.as_deref() == Some(item.subscription_id.as_str()))
+                .ok_or(StatusCode::NOT_FOUND)?;
+
+            if item.client_state.as_deref() != Some(subscription.client_state.as_str()) {
+                warn!("Rejected Microsoft notification with invalid client state for {}", item.subscription_id);
+                return Err(StatusCode::UNAUTHORIZED);
+            }
+
+            notifications.push(WebhookNotification {
+                subscription_id: subscription.id,
+                service_id: subscription.service_id,
+                provider: WebhookProvider::Microsoft,
+                resource: item.resource.clone(),
+                change_type: item.change_type.clone(),
+                received_at: chrono::Utc::now(),
+                payload: serde_json::to_value(&item).unwrap_or_default(),
+            });
+        }
+
+        Ok(notifications)
+    }
+}
+
+/// What the Pub/Sub push token is an HMAC of
+const PUBSUB_TOKEN_LABEL: &[u8] = b"gmail-pubsub-push";
+
+#[derive(Debug, Deserialize)]
+struct PubSubQuery {
+    token: Option<String>,
+}
+
+/// Envelope Pub/Sub posts to push endpoints
+#[derive(Debug, Deserialize)]
+struct PubSubPush {
+    message: PubSubMessage,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PubSubMessage {
+    /// Base64 of the published JSON
+    data: String,
+    #[serde(default)]
+    message_id: Option<String>,
+}
+
+/// What Gmail publishes when a watched mailbox changes
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GmailChange {
+    email_address: String,
+    /// A number, sent as one by Gmail but as a string in its documentation
+    history_id: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct MicrosoftValidationQuery {
+    #[serde(rename = "validationToken")]
+    validation_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MicrosoftNotificationBatch {
+    value: Vec<MicrosoftNotification>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MicrosoftNotification {
+    subscription_id: String,
+    client_state: Option<String>,
+    change_type: String,
+    resource: String,
+    #[serde(default)]
+    resource_data: Option<serde_json::Value>,
+}
+
+/// Router fragment to be mounted by the api-server under `/webhooks`
+pub fn router(manager: Arc<WebhookManager>) -> Router {
+    Router::new()
+        .route("/google", post(google_notification))
+        .route("/google/pubsub", post(gmail_push))
+        .route("/microsoft", post(microsoft_notification))
+        .with_state(manager)
+}
+
+async fn google_notification(
+    State(manager): State<Arc<WebhookManager>>,
+    headers: HeaderMap,
+    body: axum::body::Bytes,
+) -> StatusCode {
+    match manager.handle_google(&headers, &body).await {
+        Ok(notification) => {
+            // The initial "sync" message only confirms the channel was created
+            if notification.change_type != "sync" {
+                manager.dispatch(notification).await;
+            }
+            StatusCode::OK
+        }
+        Err(status) => status,
+    }
+}
+
+async fn gmail_push(
+    State(manager): State<Arc<WebhookManager>>,
+    Query(query): Query<PubSubQuery>,
+    body: axum::body::Bytes,
+) -> StatusCode {
+    // Pub/Sub redelivers every push that isn't acknowledged, even for mailboxes no longer
+    // watched, so only invalid pushes are refused
+    match manager.handle_gmail_push(query.token.as_deref(), &body).await {
+        Ok(notifications) => {
+            for notification in notifications {
+                manager.dispatch(notification).await;
+            }
+            StatusCode::NO_CONTENT
+        }
+        Err(status) => status,
+    }
+}
+
+async fn microsoft_notification(
+    State(manager): State<Arc<WebhookManager>>,
+    Query(query): Query<MicrosoftValidationQuery>,
+    body: axum::body::Bytes,
+) -> Response {
+    // Subscription creation handshake: echo the token back as plain text
+    if let Some(token) = query.validation_token {
+        return (StatusCode::OK, [("content-type", "text/plain")], token).into_response();
+    }
+
+    let batch: MicrosoftNotificationBatch = match serde_json::from_slice(&body) {
+        Ok(batch) => batch,
+        Err(_) => return StatusCode::BAD_REQUEST.into_response(),
+    };
+
+    match manager.handle_microsoft(batch).await {
+        Ok(notifications) => {
+            for notification in notifications {
+                manager.dispatch(notification).await;
+            }
+            (StatusCode::ACCEPTED, Json(serde_json::json!({ "status": "accepted" }))).into_response()
+        }
+        Err(status) => status.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ServiceCredentials;
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+    use wiremock::matchers::{body_json, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    /// Address of the mailbox every Gmail service watches
+    const MAILBOX: &str = "user@example.com";
+
+    /// Provider that records its calls and grants subscriptions an hour shorter than asked
+    #[derive(Default)]
+    struct MockApi {
+        calls: std::sync::Mutex<Vec<String>>,
+        fail_unsubscribe: std::sync::atomic::AtomicBool,
+    }
+
+    impl MockApi {
+        fn calls(&self) -> Vec<String> {
+            self.calls.lock().unwrap().clone()
+        }
+
+        fn granted(&self, call: String, expires_at: chrono::DateTime<chrono::Utc>) -> ProviderSubscription {
+            let mut calls = self.calls.lock().unwrap();
+            calls.push(call);
+            ProviderSubscription {
+                external_id: Some(format!("external-{}", calls.len())),
+                expires_at: expires_at - chrono::Duration::hours(1),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl SubscriptionApi for MockApi {
+        async fn subscribe(&self, config: &ServiceConfig, subscription: &WebhookSubscription) -> Result<ProviderSubscription> {
+            let mut granted = self.granted(format!("subscribe {}", subscription.resource), subscription.expires_at);
+            if matches!(config.service_type, ServiceType::Gmail) {
+                granted.external_id = Some(MAILBOX.to_string());
+            }
+            Ok(granted)
+        }
+
+        async fn renew(
+            &self,
+            _: &ServiceConfig,
+            subscription: &WebhookSubscription,
+            expires_at: chrono::DateTime<chrono::Utc>,
+        ) -> Result<ProviderSubscription> {
+            Ok(self.granted(format!("renew {}", subscription.external_id.as_deref().unwrap()), expires_at))
+        }
+
+        async fn unsubscribe(&self, _: &ServiceConfig, subscription: &WebhookSubscription) -> Result<()> {
+            if self.fail_unsubscribe.load(std::sync::atomic::Ordering::SeqCst) {
+                anyhow::bail!("provider unavailable");
+            }
+            self.calls.lock().unwrap().push(format!("unsubscribe {}", subscription.external_id.as_deref().unwrap()));
+            Ok(())
+        }
+    }
+
+    struct RecordingHandler {
+        received: tokio::sync::Mutex<Vec<WebhookNotification>>,
+    }
+
+    #[async_trait]
+    impl WebhookHandler for RecordingHandler {
+        async fn handle(&self, notification: WebhookNotification) -> Result<()> {
+            self.received.lock().await.push(notification);
+            Ok(())
+        }
+    }
+
+    fn service(service_type: ServiceType) -> ServiceConfig {
+        ServiceConfig {
+            id: Uuid::new_v4(),
+            service_type,
+            name: "test".to_string(),
+            enabled: true,
+            credentials: ServiceCredentials::ApiKey { key: "k".to_string(), secret: None },
+            settings: HashMap::new(),
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        }
+    }
+
+    fn oauth2_service(service_type: ServiceType) -> ServiceConfig {
+        ServiceConfig {
+            credentials: ServiceCredentials::OAuth2 {
+                client_id: "client".to_string(),
+                client_secret: "secret".to_string(),
+                access_token: "access-token".to_string(),
+                refresh_token: "refresh-token".to_string(),
+                expires_at: chrono::Utc::now() + chrono::Duration::hours(1),
+            },
+            ..service(service_type)
+        }
+    }
+
+    fn services() -> Arc<ExternalServicesManager> {
+        Arc::new(ExternalServicesManager::new().with_plaintext_credentials())
+    }
+
+    async fn register(manager: &WebhookManager, config: ServiceConfig) -> Uuid {
+        manager.services.register_service(config).await.unwrap()
+    }
+
+    async fn setup() -> (Arc<WebhookManager>, Arc<RecordingHandler>) {
+        let manager = Arc::new(WebhookManager::new("test-secret", Arc::new(MockApi::default()), services()));
+        let handler = Arc::new(RecordingHandler { received: tokio::sync::Mutex::new(Vec::new()) });
+        manager.set_handler(handler.clone()).await;
+        (manager, handler)
+    }
+
+    #[tokio::test]
+    async fn test_microsoft_validation_handshake() {
+        let (manager, _) = setup().await;
+        let response = router(manager)
+            .oneshot(
+                Request::post("/microsoft?validationToken=abc%20123")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], b"abc 123");
+    }
+
+    #[tokio::test]
+    async fn test_microsoft_notification_requires_client_state() {
+        let (manager, handler) = setup().await;
+        let service_id = register(&manager, service(ServiceType::OutlookCalendar)).await;
+        let subscription = manager.create_subscription(service_id, "me/events").await.unwrap();
+
+        let request = |client_state: &str| {
+            let body = serde_json::json!({
+                "value": [{
+                    "subscriptionId": subscription.external_id,
+                    "clientState": client_state,
+                    "changeType": "updated",
+                    "resource": "me/events/1"
+                }]
+            });
+            Request::post("/microsoft").body(Body::from(body.to_string())).unwrap()
+        };
+
+        let response = router(manager.clone()).oneshot(request("forged")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(handler.received.lock().await.is_empty());
+
+        let response = router(manager).oneshot(request(&subscription.client_state)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let received = handler.received.lock().await;
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].subscription_id, subscription.id);
+    }
+
+    #[tokio::test]
+    async fn test_google_channel_notification_requires_channel_token() {
+        let (manager, handler) = setup().await;
+        let service_id = register(&manager, service(ServiceType::GoogleCalendar)).await;
+        let subscription = manager.create_subscription(service_id, "calendars/primary/events").await.unwrap();
+
+        let request = |token: &str| {
+            Request::post("/google")
+                .header("x-goog-channel-id", subscription.id.to_string())
+                .header("x-goog-channel-token", token)
+                .header("x-goog-resource-state", "exists")
+                .header("x-goog-resource-uri", "https://www.googleapis.com/calendar/v3/calendars/primary/events")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = router(manager.clone()).oneshot(request("forged")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = router(manager).oneshot(request(&subscription.client_state)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let received = handler.received.lock().await;
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].service_id, service_id);
+        assert_eq!(received[0].change_type, "exists");
+    }
+
+    #[tokio::test]
+    async fn test_gmail_pubsub_push() {
+        let (manager, handler) = setup().await;
+        let service_id = register(&manager, service(ServiceType::Gmail)).await;
+        let subscription = manager.create_subscription(service_id, "users/me/messages").await.unwrap();
+
+        // As Pub/Sub delivers it, with the Gmail notification base64 encoded in `data`
+        let push = |address: &str| json!({
+            "message": {
+                "data": BASE64.encode(json!({ "emailAddress": address, "historyId": 9876543210u64 }).to_string()),
+                "messageId": "2070443601311540",
+                "message_id": "2070443601311540",
+                "publishTime": "2021-02-26T19:13:55.749Z",
+                "publish_time": "2021-02-26T19:13:55.749Z"
+            },
+            "subscription": "projects/talkpp/subscriptions/gmail-push"
+        });
+        let request = |uri: String, address: &str| {
+            Request::post(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(push(address).to_string()))
+                .unwrap()
+        };
+
+        let response = router(manager.clone()).oneshot(request("/google/pubsub".to_string(), MAILBOX)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = router(manager.clone()).oneshot(request("/google/pubsub?token=forged".to_string(), MAILBOX)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(handler.received.lock().await.is_empty());
+
+        let endpoint = format!("/google/pubsub?token={}", manager.pubsub_push_token());
+        let response = router(manager.clone()).oneshot(request(endpoint.clone(), MAILBOX)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        // Pushes for other mailboxes are acknowledged so Pub/Sub stops redelivering them
+        let response = router(manager).oneshot(request(endpoint, "other@example.com")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        let received = handler.received.lock().await;
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].subscription_id, subscription.id);
+        assert_eq!(received[0].service_id, service_id);
+        assert_eq!(received[0].resource, "users/me/messages");
+        assert_eq!(received[0].payload["historyId"], 9876543210u64);
+        assert_eq!(received[0].payload["emailAddress"], MAILBOX);
+    }
+
+    #[tokio::test]
+    async fn test_gmail_watch_stopped_with_last_subscription() {
+        let api = Arc::new(MockApi::default());
+        let manager = WebhookManager::new("test-secret", api.clone(), services());
+        let service_id = register(&manager, service(ServiceType::Gmail)).await;
+
+        let inbox = manager.create_subscription(service_id, "users/me/messages").await.unwrap();
+        let labels = manager.create_subscription(service_id, "users/me/labels").await.unwrap();
+
+        manager.remove_subscription(inbox.id).await.unwrap();
+        assert_eq!(api.calls(), vec!["subscribe users/me/messages", "subscribe users/me/labels"]);
+
+        manager.remove_subscription(labels.id).await.unwrap();
+        assert_eq!(api.calls().last().unwrap(), &format!("unsubscribe {}", MAILBOX));
+        assert!(manager.list_subscriptions().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_renew_expiring_subscriptions() {
+        let api = Arc::new(MockApi::default());
+        let manager = WebhookManager::new("test-secret", api.clone(), services())
+            .with_renewal_margin(chrono::Duration::minutes(GOOGLE_MAX_LIFETIME_MINUTES + 1));
+        let service_id = register(&manager, service(ServiceType::GoogleCalendar)).await;
+        let subscription = manager.create_subscription(service_id, "calendars/primary/events").await.unwrap();
+
+        let renewed = manager.renew_expiring().await;
+        assert_eq!(renewed, vec![subscription.id]);
+        assert!(manager.list_subscriptions().await[0].renewed_at.is_some());
+        assert_eq!(api.calls(), vec!["subscribe calendars/primary/events", "renew external-1"]);
+    }
+
+    #[tokio::test]
+    async fn test_subscriptions_registered_renewed_and_removed_at_the_provider() {
+        let api = Arc::new(MockApi::default());
+        let manager = WebhookManager::new("test-secret", api.clone(), services());
+        let service_id = register(&manager, service(ServiceType::OutlookCalendar)).await;
+
+        let created = manager.create_subscription(service_id, "me/events").await.unwrap();
+        assert_eq!(created.external_id.as_deref(), Some("external-1"));
+        // The provider's expiry wins over the one asked for
+        assert!(created.expires_at < created.created_at + WebhookProvider::Microsoft.max_lifetime() - chrono::Duration::minutes(59));
+
+        let renewed = manager.renew_subscription(created.id).await.unwrap();
+        assert_eq!(renewed.external_id.as_deref(), Some("external-2"));
+        assert!(renewed.expires_at > created.expires_at);
+
+        api.fail_unsubscribe.store(true, std::sync::atomic::Ordering::SeqCst);
+        assert!(manager.remove_subscription(created.id).await.is_err());
+        assert_eq!(manager.list_subscriptions().await.len(), 1);
+
+        api.fail_unsubscribe.store(false, std::sync::atomic::Ordering::SeqCst);
+        manager.remove_subscription(created.id).await.unwrap();
+        assert!(manager.list_subscriptions().await.is_empty());
+        assert_eq!(api.calls(), vec!["subscribe me/events", "renew external-1", "unsubscribe external-2"]);
+    }
+
+    #[tokio::test]
+    async fn test_graph_subscription_calls() {
+        let server = MockServer::start().await;
+        let expires = "2030-01-01T00:00:00Z";
+        Mock::given(method("POST"))
+            .and(path("/graph/subscriptions"))
+            .and(header("authorization", "Bearer access-token"))
+            .respond_with(ResponseTemplate::new(201).set_body_json(json!({
+                "id": "graph-sub-1",
+                "expirationDateTime": expires
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path("/graph/subscriptions/graph-sub-1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "graph-sub-1",
+                "expirationDateTime": "2030-01-02T00:00:00Z"
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/graph/subscriptions/graph-sub-1"))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let api = HttpSubscriptionApi::new("https://talkpp.example.com/webhooks/")
+            .with_graph_api(format!("{}/graph", server.uri()));
+        let manager = WebhookManager::new("test-secret", Arc::new(api), services());
+        let service_id = register(&manager, oauth2_service(ServiceType::OutlookCalendar)).await;
+
+        let created = manager.create_subscription(service_id, "me/events").await.unwrap();
+        assert_eq!(created.external_id.as_deref(), Some("graph-sub-1"));
+        assert_eq!(created.expires_at.to_rfc3339(), "2030-01-01T00:00:00+00:00");
+
+        let requests = server.received_requests().await.unwrap();
+        let body: Value = serde_json::from_slice(&requests[0].body).unwrap();
+        assert_eq!(body["notificationUrl"], "https://talkpp.example.com/webhooks/microsoft");
+        assert_eq!(body["resource"], "me/events");
+        assert_eq!(body["clientState"], created.client_state);
+
+        let renewed = manager.renew_subscription(created.id).await.unwrap();
+        assert_eq!(renewed.expires_at.to_rfc3339(), "2030-01-02T00:00:00+00:00");
+        manager.remove_subscription(created.id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_renewal_uses_refreshed_access_token() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "access_token": "fresh-token",
+                "refresh_token": "rotated-refresh-token",
+                "expires_in": 3600
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/graph/subscriptions"))
+            .and(header("authorization", "Bearer fresh-token"))
+            .respond_with(ResponseTemplate::new(201).set_body_json(json!({
+                "id": "graph-sub-1",
+                "expirationDateTime": "2030-01-01T00:00:00Z"
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path("/graph/subscriptions/graph-sub-1"))
+            .and(header("authorization", "Bearer fresh-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "graph-sub-1",
+                "expirationDateTime": "2030-01-02T00:00:00Z"
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let services = Arc::new(
+            ExternalServicesManager::new()
+                .with_plaintext_credentials()
+                .with_token_refresher(crate::oauth::TokenRefresher::new().with_microsoft_token_url(format!("{}/token", server.uri()))),
+        );
+        let api = HttpSubscriptionApi::new("https://talkpp.example.com/webhooks")
+            .with_graph_api(format!("{}/graph", server.uri()));
+        let manager = WebhookManager::new("test-secret", Arc::new(api), services);
+
+        // Registered with an access token that has already expired
+        let mut config = oauth2_service(ServiceType::OutlookCalendar);
+        if let ServiceCredentials::OAuth2 { expires_at, .. } = &mut config.credentials {
+            *expires_at = chrono::Utc::now() - chrono::Duration::minutes(1);
+        }
+        let service_id = register(&manager, config).await;
+
+        let created = manager.create_subscription(service_id, "me/events").await.unwrap();
+        // The refreshed token was kept, so renewing doesn't refresh again
+        manager.renew_subscription(created.id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_gmail_watch_and_stop() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/gmail/v1/users/me/watch"))
+            .and(header("authorization", "Bearer access-token"))
+            .and(body_json(json!({ "topicName": "projects/talkpp/topics/gmail" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "historyId": "1234",
+                "expiration": "1893456000000"
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/gmail/v1/users/me/profile"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "emailAddress": MAILBOX,
+                "messagesTotal": 10,
+                "threadsTotal": 8,
+                "historyId": "1234"
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/gmail/v1/users/me/stop"))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let api = HttpSubscriptionApi::new("https://talkpp.example.com/webhooks").with_google_api(server.uri());
+        let manager = WebhookManager::new("test-secret", Arc::new(api), services());
+        let mut config = oauth2_service(ServiceType::Gmail);
+        config.settings.insert(GMAIL_TOPIC_SETTING.to_string(), json!("projects/talkpp/topics/gmail"));
+        let settings = config.settings.clone();
+        let service_id = register(&manager, config).await;
+
+        let created = manager.create_subscription(service_id, "users/me/messages").await.unwrap();
+        assert_eq!(created.external_id.as_deref(), Some(MAILBOX));
+        assert_eq!(created.expires_at.timestamp_millis(), 1_893_456_000_000);
+        manager.remove_subscription(created.id).await.unwrap();
+
+        // Without OAuth2 credentials nothing can be subscribed
+        let api_key = register(&manager, ServiceConfig { settings, ..service(ServiceType::Gmail) }).await;
+        assert!(manager.create_subscription(api_key, "users/me/messages").await.is_err());
+    }
+}
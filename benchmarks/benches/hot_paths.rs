@@ -0,0 +1,103 @@
+//! Hot path benchmarks; see the `talkpp_benchmarks` crate docs for the code path each
+//! one guards
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use std::sync::Arc;
+use talkpp_benchmarks::*;
+use talkpp_compiler::{codegen, lexer, parser, CompilerConfig};
+use talkpp_vector_db::{search_results, DistanceMetric, EmbeddingPoolConfig, EmbeddingWorkerPool};
+
+/// Documents each embedding pool iteration submits
+const POOL_DOCUMENTS: usize = 1000;
+
+fn runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_multi_thread().enable_all().build().expect("benchmark runtime starts")
+}
+
+fn compiler(c: &mut Criterion) {
+    let config = CompilerConfig::default();
+    let mut group = c.benchmark_group("compiler");
+    for (size, statements) in DSL_SIZES {
+        let source = dsl_fixture(statements);
+        let tokens = lexer::tokenize(&source).expect("fixture tokenizes");
+        let program = parser::parse(tokens.clone()).expect("fixture parses");
+        group.throughput(Throughput::Elements(statements as u64));
+
+        group.bench_with_input(BenchmarkId::new("tokenize", size), &source, |b, source| {
+            b.iter(|| lexer::tokenize(black_box(source)))
+        });
+        group.bench_with_input(BenchmarkId::new("parse", size), &tokens, |b, tokens| {
+            b.iter_batched(|| tokens.clone(), parser::parse, BatchSize::SmallInput)
+        });
+        group.bench_with_input(BenchmarkId::new("codegen", size), &program, |b, program| {
+            b.iter(|| codegen::generate(black_box(program), &config))
+        });
+    }
+    group.finish();
+}
+
+fn chunking(c: &mut Criterion) {
+    let document = prose(20_000);
+    let mut group = c.benchmark_group("rag");
+    group.throughput(Throughput::Bytes(document.len() as u64));
+    for (strategy, rag) in chunking_strategies() {
+        group.bench_function(BenchmarkId::new("chunk_text", strategy), |b| b.iter(|| rag.chunk_text(black_box(&document))));
+    }
+    group.finish();
+}
+
+fn qdrant(c: &mut Criterion) {
+    let mut group = c.benchmark_group("qdrant");
+    for count in [10, 100, 1000] {
+        let points = scored_points(count);
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(BenchmarkId::new("search_results", count), &points, |b, points| {
+            b.iter_batched(|| points.clone(), |points| search_results(points, DistanceMetric::Cosine), BatchSize::SmallInput)
+        });
+    }
+    group.finish();
+}
+
+fn memory(c: &mut Criterion) {
+    let runtime = runtime();
+    let continuum = runtime.block_on(seeded_continuum(SEEDED_MEMORIES)).expect("memories seed");
+    let mut group = c.benchmark_group("memory");
+    let mut next = SEEDED_MEMORIES;
+    group.bench_function("store", |b| {
+        b.to_async(&runtime).iter(|| {
+            next += 1;
+            store_memory(&continuum, next)
+        })
+    });
+    group.bench_function("retrieve", |b| {
+        b.to_async(&runtime).iter(|| {
+            continuum.retrieve_memories(memory_query(), vec![memory_continuum::MemoryType::ShortTerm], 10)
+        })
+    });
+    group.finish();
+}
+
+fn embedding_pool(c: &mut Criterion) {
+    let runtime = runtime();
+    let mut group = c.benchmark_group("embedding_pool");
+    group.throughput(Throughput::Elements(POOL_DOCUMENTS as u64));
+    group.bench_function("throughput", |b| {
+        b.to_async(&runtime).iter_batched(
+            || documents(POOL_DOCUMENTS),
+            |documents| async move {
+                let pool = EmbeddingWorkerPool::new(
+                    EmbeddingPoolConfig::default(),
+                    Arc::new(NoopEmbedder::default()),
+                    Arc::new(NullVectorDb::default()),
+                );
+                pool.submit(documents).await.expect("pool accepts documents");
+                pool.shutdown(true).await
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, compiler, chunking, qdrant, memory, embedding_pool);
+criterion_main!(benches);
@@ -0,0 +1,37 @@
+//! Print criterion's estimates as one JSON document
+//!
+//! ```text
+//! bench-summary [--criterion-dir <dir>] [--compare <baseline.json>]
+//! ```
+//!
+//! Without `--compare` the summary is printed, ready to be saved as a baseline. With it,
+//! each benchmark in both is printed with its change against the baseline.
+
+use anyhow::{bail, Context, Result};
+use std::path::PathBuf;
+use talkpp_benchmarks::summary::{self, Summary};
+
+fn main() -> Result<()> {
+    let mut criterion_dir = PathBuf::from("target/criterion");
+    let mut baseline = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--criterion-dir" => criterion_dir = args.next().context("--criterion-dir needs a directory")?.into(),
+            "--compare" => baseline = Some(PathBuf::from(args.next().context("--compare needs a summary file")?)),
+            other => bail!("Unknown argument '{}'", other),
+        }
+    }
+
+    let current = summary::collect(&criterion_dir)?;
+    let output = match baseline {
+        Some(path) => {
+            let text = std::fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+            let baseline: Summary = serde_json::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))?;
+            serde_json::to_string_pretty(&summary::compare(&baseline, &current))?
+        }
+        None => serde_json::to_string_pretty(&current)?,
+    };
+    println!("{}", output);
+    Ok(())
+}
@@ -0,0 +1,310 @@
+//! Benchmark fixtures for the hot paths performance claims are made about
+//!
+//! The benchmarks live in `benches/hot_paths.rs`; this crate holds their setup so the
+//! smoke tests below can check that each one reaches the code it is named after. Nothing
+//! here needs a running service: Qdrant responses are built in memory and embedding runs
+//! through [`NoopEmbedder`].
+//!
+//! | Benchmark | Code path it guards |
+//! |---|---|
+//! | `compiler/tokenize/{small,medium,large}` | `talkpp_compiler::lexer::tokenize` |
+//! | `compiler/parse/{small,medium,large}` | `talkpp_compiler::parser::parse` |
+//! | `compiler/codegen/{small,medium,large}` | `talkpp_compiler::codegen::generate`, Rust target |
+//! | `rag/chunk_text/{fine,default,coarse}` | `RagSystem::chunk_text` at each [`CHUNKING_STRATEGIES`] entry |
+//! | `qdrant/search_results/{10,100,1000}` | `talkpp_vector_db::search_results`, turning a Qdrant search response into ranked results |
+//! | `memory/store` | `MemoryContinuum::store_memory` with [`SEEDED_MEMORIES`] already stored |
+//! | `memory/retrieve` | `MemoryContinuum::retrieve_memories` over [`SEEDED_MEMORIES`] |
+//! | `embedding_pool/throughput` | `EmbeddingWorkerPool` submit to drained shutdown, embedder and upserts doing no work |
+//!
+//! Run them with `cargo bench -p talkpp-benchmarks`. Criterion keeps its estimates as
+//! JSON under `target/criterion`; `cargo run -p talkpp-benchmarks --bin bench-summary`
+//! gathers them into one document, and `--compare <summary.json>` reports the change
+//! against a summary saved on another branch.
+
+use async_trait::async_trait;
+use memory_continuum::{AccessPattern, MemoryConfig, MemoryContinuum, MemoryMetadata, MemoryType};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use talkpp_vector_db::{
+    CollectionInfo, DocumentPage, EmbeddingModel, RagSystem, SearchResult, VectorDatabase, VectorDocument,
+};
+use uuid::Uuid;
+
+pub mod summary;
+
+/// Statements in the small, medium and large compiler fixtures
+pub const DSL_SIZES: [(&str, usize); 3] = [("small", 10), ("medium", 100), ("large", 1000)];
+
+/// Chunk size and overlap, in characters, of each chunking strategy benchmarked
+pub const CHUNKING_STRATEGIES: [(&str, usize, usize); 3] = [
+    ("fine", 256, 32),
+    ("default", 1000, 200),
+    ("coarse", 4000, 400),
+];
+
+/// Memories stored before the memory continuum is benchmarked
+pub const SEEDED_MEMORIES: usize = 10_000;
+
+/// Topics seeded memories are spread over, so a retrieval matches a fraction of them
+const MEMORY_TOPICS: usize = 100;
+
+/// A Talk++ program of `statements` statements, alternating assignments with the
+/// conditionals that read them
+pub fn dsl_fixture(statements: usize) -> String {
+    (0..statements)
+        .map(|i| match i % 2 {
+            0 => format!("total_{}: {}", i / 2, i),
+            _ => format!("if order_{} placed then send total_{} to \"555-0100\" using Twilio end", i / 2, i / 2),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// A document of `words` words of prose
+pub fn prose(words: usize) -> String {
+    const VOCABULARY: [&str; 12] = [
+        "tidal", "turbines", "spin", "in", "both", "directions", "while", "offshore", "wind", "farms", "store", "energy",
+    ];
+    (0..words).map(|i| VOCABULARY[i * 7 % VOCABULARY.len()]).collect::<Vec<_>>().join(" ")
+}
+
+/// A RAG system per [`CHUNKING_STRATEGIES`] entry, over a database that stores nothing
+pub fn chunking_strategies() -> Vec<(&'static str, RagSystem)> {
+    CHUNKING_STRATEGIES
+        .iter()
+        .map(|&(name, size, overlap)| {
+            let rag = RagSystem::new(Box::new(NullVectorDb::default()))
+                .with_chunking(size, overlap)
+                .expect("benchmark chunking strategies are valid");
+            (name, rag)
+        })
+        .collect()
+}
+
+/// The points of a Qdrant search response holding `count` matches, each with a content
+/// payload and a few metadata fields
+pub fn scored_points(count: usize) -> Vec<qdrant_client::qdrant::ScoredPoint> {
+    use qdrant_client::qdrant::{point_id::PointIdOptions, PointId, ScoredPoint, Value};
+
+    (0..count)
+        .map(|i| ScoredPoint {
+            id: Some(PointId { point_id_options: Some(PointIdOptions::Uuid(Uuid::new_v4().to_string())) }),
+            payload: HashMap::from([
+                ("content".to_string(), Value::from(prose(40))),
+                ("source".to_string(), Value::from(format!("handbook-{}.md", i))),
+                ("chunk_index".to_string(), Value::from(i as i64)),
+                ("tenant_id".to_string(), Value::from("bench".to_string())),
+            ]),
+            score: 1.0 - i as f32 / count as f32,
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn metadata(topic: usize) -> MemoryMetadata {
+    MemoryMetadata {
+        importance: 0.5,
+        confidence: 0.9,
+        source: "benchmark".to_string(),
+        tags: vec![format!("topic-{}", topic)],
+        associations: Vec::new(),
+        consolidation_level: 0,
+        access_pattern: AccessPattern {
+            frequency: 1.0,
+            recency: 1.0,
+            context_relevance: 0.5,
+            emotional_valence: 0.0,
+        },
+        pii_allowlist: Vec::new(),
+        redactions: None,
+    }
+}
+
+/// Store memory `i` of a benchmark in `continuum`
+pub async fn store_memory(continuum: &MemoryContinuum, i: usize) -> anyhow::Result<Uuid> {
+    let topic = i % MEMORY_TOPICS;
+    let content = serde_json::json!({"text": format!("Note {} about benchmark topic {}", i, topic)});
+    continuum.store_memory(content, MemoryType::ShortTerm, metadata(topic)).await
+}
+
+/// A memory continuum holding `count` short-term memories, with room for as many again
+pub async fn seeded_continuum(count: usize) -> anyhow::Result<MemoryContinuum> {
+    let continuum = MemoryContinuum::new(MemoryConfig::default().with_stm_capacity(count * 2)).await?;
+    for i in 0..count {
+        store_memory(&continuum, i).await?;
+    }
+    Ok(continuum)
+}
+
+/// Query matching the memories of one topic
+pub fn memory_query() -> &'static str {
+    "benchmark topic 7"
+}
+
+/// `count` documents without vectors, for the embedding pool to embed
+pub fn documents(count: usize) -> Vec<VectorDocument> {
+    (0..count)
+        .map(|i| VectorDocument {
+            id: Uuid::new_v4(),
+            content: format!("document {} {}", i, prose(20)),
+            metadata: HashMap::new(),
+            vector: None,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        })
+        .collect()
+}
+
+/// Embeds every text as the same small vector, so only the pool's own overhead is measured
+#[derive(Debug, Default)]
+pub struct NoopEmbedder {
+    pub batches: AtomicU64,
+}
+
+#[async_trait]
+impl EmbeddingModel for NoopEmbedder {
+    async fn embed(&self, _text: &str) -> anyhow::Result<Vec<f32>> {
+        Ok(vec![0.0; 4])
+    }
+
+    async fn embed_batch(&self, texts: Vec<&str>) -> anyhow::Result<Vec<Vec<f32>>> {
+        self.batches.fetch_add(1, Ordering::Relaxed);
+        Ok(vec![vec![0.0; 4]; texts.len()])
+    }
+
+    fn dimension(&self) -> usize {
+        4
+    }
+
+    fn model_id(&self) -> &str {
+        "bench/noop"
+    }
+}
+
+/// Accepts every write, counting upserted documents, and finds nothing
+#[derive(Debug, Default, Clone)]
+pub struct NullVectorDb {
+    pub upserted: Arc<AtomicU64>,
+}
+
+#[async_trait]
+impl VectorDatabase for NullVectorDb {
+    async fn initialize(&mut self) -> talkpp_errors::Result<()> {
+        Ok(())
+    }
+
+    async fn create_collection(&self, _name: &str, _vector_size: u64) -> talkpp_errors::Result<()> {
+        Ok(())
+    }
+
+    async fn upsert_document(&self, _document: VectorDocument) -> talkpp_errors::Result<()> {
+        self.upserted.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    async fn upsert_documents(&self, documents: Vec<VectorDocument>) -> talkpp_errors::Result<()> {
+        self.upserted.fetch_add(documents.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+
+    async fn search(
+        &self,
+        _query_vector: Vec<f32>,
+        _limit: usize,
+        _filter: Option<HashMap<String, serde_json::Value>>,
+    ) -> talkpp_errors::Result<Vec<SearchResult>> {
+        Ok(Vec::new())
+    }
+
+    async fn search_by_text(
+        &self,
+        _query: &str,
+        _limit: usize,
+        _filter: Option<HashMap<String, serde_json::Value>>,
+    ) -> talkpp_errors::Result<Vec<SearchResult>> {
+        Ok(Vec::new())
+    }
+
+    async fn delete_document(&self, _id: Uuid) -> talkpp_errors::Result<()> {
+        Ok(())
+    }
+
+    async fn get_document(&self, _id: Uuid) -> talkpp_errors::Result<Option<VectorDocument>> {
+        Ok(None)
+    }
+
+    async fn get_collection_info(&self) -> talkpp_errors::Result<CollectionInfo> {
+        Ok(CollectionInfo {
+            name: "bench".to_string(),
+            vector_size: 4,
+            distance_metric: None,
+            points_count: self.upserted.load(Ordering::Relaxed),
+            indexed: true,
+        })
+    }
+
+    async fn scroll_documents(&self, _offset: Option<Uuid>, _limit: usize) -> talkpp_errors::Result<DocumentPage> {
+        Ok(DocumentPage::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    //! Each benchmark's setup must reach the function it is named after, or the
+    //! benchmark would time an early return
+
+    use super::*;
+    use talkpp_compiler::{codegen, lexer, parser, CompilerConfig};
+    use talkpp_vector_db::{search_results, DistanceMetric, EmbeddingPoolConfig, EmbeddingWorkerPool};
+
+    #[test]
+    fn test_compiler_fixtures_compile_every_statement() {
+        for (name, statements) in DSL_SIZES {
+            let source = dsl_fixture(statements);
+            let tokens = lexer::tokenize(&source).unwrap_or_else(|e| panic!("{} fixture: {}", name, e));
+            let program = parser::parse(tokens).unwrap_or_else(|e| panic!("{} fixture: {}", name, e));
+            assert_eq!(program.statements.len(), statements, "{} fixture", name);
+            let code = codegen::generate(&program, &CompilerConfig::default()).unwrap();
+            assert!(code.contains(&format!("total_{}", statements / 2 - 1)), "{} fixture", name);
+        }
+    }
+
+    #[test]
+    fn test_chunking_strategies_split_the_document() {
+        let document = prose(5000);
+        let counts: Vec<usize> = chunking_strategies().iter().map(|(_, rag)| rag.chunk_text(&document).len()).collect();
+        assert!(counts.iter().all(|&count| count > 1), "{:?}", counts);
+        assert!(counts.windows(2).all(|pair| pair[0] > pair[1]), "{:?}", counts);
+    }
+
+    #[test]
+    fn test_search_results_keep_every_point() {
+        let results = search_results(scored_points(100), DistanceMetric::Cosine);
+        assert_eq!(results.len(), 100);
+        assert!(results.iter().all(|result| !result.document.content.is_empty()));
+        assert_eq!(results[99].rank, 99);
+    }
+
+    #[tokio::test]
+    async fn test_seeded_continuum_finds_one_topic() {
+        let continuum = seeded_continuum(1000).await.unwrap();
+        assert_eq!(continuum.stm.count().await.unwrap(), 1000);
+        let memories = continuum.retrieve_memories(memory_query(), vec![MemoryType::ShortTerm], 10).await.unwrap();
+        assert!(!memories.is_empty());
+        store_memory(&continuum, 1000).await.unwrap();
+        assert_eq!(continuum.stm.count().await.unwrap(), 1001);
+    }
+
+    #[tokio::test]
+    async fn test_embedding_pool_embeds_and_upserts_every_document() {
+        let embedder = Arc::new(NoopEmbedder::default());
+        let db = NullVectorDb::default();
+        let pool = EmbeddingWorkerPool::new(EmbeddingPoolConfig::default(), embedder.clone(), Arc::new(db.clone()));
+        pool.submit(documents(100)).await.unwrap();
+        let stats = pool.shutdown(true).await;
+        assert_eq!((stats.embedded_documents, stats.upserted_documents), (100, 100));
+        assert!(embedder.batches.load(Ordering::Relaxed) > 0);
+        assert_eq!(db.upserted.load(Ordering::Relaxed), 100);
+    }
+}
@@ -0,0 +1,129 @@
+//! One JSON document of criterion's estimates, for comparing runs across branches
+//!
+//! Criterion writes each benchmark's latest estimates to
+//! `<criterion dir>/<benchmark>/new/estimates.json`, next to a `benchmark.json` naming
+//! it. A [`Summary`] gathers the point estimates from all of them, keyed by the
+//! benchmark's full id such as `compiler/parse/large`.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Point estimates of one benchmark, in nanoseconds per iteration
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Estimate {
+    pub mean_ns: f64,
+    pub median_ns: f64,
+    pub std_dev_ns: f64,
+}
+
+/// Estimates by benchmark id
+pub type Summary = BTreeMap<String, Estimate>;
+
+/// How a benchmark's mean moved against a baseline
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Change {
+    pub baseline_mean_ns: f64,
+    pub mean_ns: f64,
+    /// Positive when the benchmark got slower
+    pub change_percent: f64,
+}
+
+#[derive(Deserialize)]
+struct BenchmarkId {
+    full_id: String,
+}
+
+#[derive(Deserialize)]
+struct PointEstimate {
+    point_estimate: f64,
+}
+
+#[derive(Deserialize)]
+struct Estimates {
+    mean: PointEstimate,
+    median: PointEstimate,
+    std_dev: PointEstimate,
+}
+
+/// Every benchmark criterion has recorded under `criterion_dir`
+pub fn collect(criterion_dir: &Path) -> Result<Summary> {
+    let mut summary = Summary::new();
+    visit(criterion_dir, &mut summary)?;
+    Ok(summary)
+}
+
+fn visit(dir: &Path, summary: &mut Summary) -> Result<()> {
+    let estimates = dir.join("new").join("estimates.json");
+    if estimates.is_file() {
+        let id: BenchmarkId = read(&dir.join("new").join("benchmark.json"))?;
+        let estimates: Estimates = read(&estimates)?;
+        summary.insert(id.full_id, Estimate {
+            mean_ns: estimates.mean.point_estimate,
+            median_ns: estimates.median.point_estimate,
+            std_dev_ns: estimates.std_dev.point_estimate,
+        });
+    }
+    for entry in std::fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let path = entry?.path();
+        // `report` holds criterion's HTML; `base` and `new` hold a benchmark's runs
+        let skipped = path.file_name().is_some_and(|name| name == "report" || name == "base" || name == "new");
+        if path.is_dir() && !skipped {
+            visit(&path, summary)?;
+        }
+    }
+    Ok(())
+}
+
+fn read<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_json::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Change of each benchmark in both `baseline` and `current`
+pub fn compare(baseline: &Summary, current: &Summary) -> BTreeMap<String, Change> {
+    current
+        .iter()
+        .filter_map(|(id, estimate)| {
+            let baseline = baseline.get(id)?;
+            Some((id.clone(), Change {
+                baseline_mean_ns: baseline.mean_ns,
+                mean_ns: estimate.mean_ns,
+                change_percent: (estimate.mean_ns - baseline.mean_ns) / baseline.mean_ns * 100.0,
+            }))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn record(dir: &Path, full_id: &str, mean: f64) {
+        let new = dir.join("new");
+        std::fs::create_dir_all(&new).unwrap();
+        std::fs::write(new.join("benchmark.json"), json!({"full_id": full_id, "group_id": "g"}).to_string()).unwrap();
+        let estimate = |value: f64| json!({"point_estimate": value, "standard_error": 1.0});
+        let estimates = json!({"mean": estimate(mean), "median": estimate(mean - 1.0), "std_dev": estimate(2.0)});
+        std::fs::write(new.join("estimates.json"), estimates.to_string()).unwrap();
+    }
+
+    #[test]
+    fn test_estimates_are_collected_by_full_id_and_compared() {
+        let dir = tempfile::tempdir().unwrap();
+        record(&dir.path().join("compiler").join("parse").join("large"), "compiler/parse/large", 200.0);
+        record(&dir.path().join("memory").join("store"), "memory/store", 50.0);
+        std::fs::create_dir_all(dir.path().join("report")).unwrap();
+
+        let current = collect(dir.path()).unwrap();
+        assert_eq!(current.keys().collect::<Vec<_>>(), ["compiler/parse/large", "memory/store"]);
+        assert_eq!(current["memory/store"], Estimate { mean_ns: 50.0, median_ns: 49.0, std_dev_ns: 2.0 });
+
+        let baseline = Summary::from([("compiler/parse/large".to_string(), Estimate { mean_ns: 160.0, median_ns: 160.0, std_dev_ns: 1.0 })]);
+        let changes = compare(&baseline, &current);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes["compiler/parse/large"].change_percent, 25.0);
+    }
+}
@@ -0,0 +1,158 @@
+//! `talkpprun memory graph` and `talkpprun plan graph`: fetch a running api-server's
+//! memory associations or a plan's task dependencies as DOT, ready for Graphviz
+//! (`talkpprun plan graph --id <id> | dot -Tsvg > plan.svg`), or as JSON.
+
+use anyhow::{Context, Result};
+use clap::{Args, Subcommand, ValueEnum};
+use colored::*;
+use serde_json::Value;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+#[derive(Args)]
+struct ApiArgs {
+    /// Base URL of the api-server
+    #[arg(long, global = true, env = "TALKPP_API_URL", default_value = "http://localhost:8080")]
+    remote: String,
+
+    /// Bearer token sent with requests
+    #[arg(long, global = true, env = "TALKPP_API_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum GraphFormat {
+    Dot,
+    Json,
+}
+
+impl GraphFormat {
+    fn as_str(&self) -> &'static str {
+        match self {
+            GraphFormat::Dot => "dot",
+            GraphFormat::Json => "json",
+        }
+    }
+}
+
+#[derive(Args)]
+struct OutputArgs {
+    #[arg(long, value_enum, default_value = "dot")]
+    format: GraphFormat,
+
+    /// Most nodes to include; the server notes how many it left out
+    #[arg(long)]
+    max_nodes: Option<usize>,
+
+    /// File to write the graph to, instead of stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct MemoryArgs {
+    #[command(flatten)]
+    api: ApiArgs,
+
+    #[command(subcommand)]
+    command: MemoryCommand,
+}
+
+#[derive(Subcommand)]
+enum MemoryCommand {
+    /// Export how your memories are associated
+    Graph {
+        /// Keep memories with this tag (repeatable)
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+
+        /// Leave out associations weaker than this, and memories left without any
+        #[arg(long)]
+        min_strength: Option<f64>,
+
+        /// Keep only memories near this one
+        #[arg(long)]
+        around: Option<Uuid>,
+
+        /// Associations away from --around a memory may be
+        #[arg(long, requires = "around", default_value = "1")]
+        depth: usize,
+
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+}
+
+#[derive(Args)]
+pub struct PlanArgs {
+    #[command(flatten)]
+    api: ApiArgs,
+
+    #[command(subcommand)]
+    command: PlanCommand,
+}
+
+#[derive(Subcommand)]
+enum PlanCommand {
+    /// Export a plan's tasks and their dependencies
+    Graph {
+        /// Plan id
+        #[arg(long)]
+        id: Uuid,
+
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+}
+
+pub async fn memory_command(args: MemoryArgs) -> Result<()> {
+    match args.command {
+        MemoryCommand::Graph { tags, min_strength, around, depth, output } => {
+            let mut query: Vec<(&str, String)> = tags.into_iter().map(|tag| ("tag", tag)).collect();
+            if let Some(min_strength) = min_strength {
+                query.push(("min_strength", min_strength.to_string()));
+            }
+            if let Some(around) = around {
+                query.push(("around", around.to_string()));
+                query.push(("depth", depth.to_string()));
+            }
+            export(&args.api, "memory/graph", query, &output).await
+        }
+    }
+}
+
+pub async fn plan_command(args: PlanArgs) -> Result<()> {
+    match args.command {
+        PlanCommand::Graph { id, output } => export(&args.api, &format!("plans/{}/graph", id), Vec::new(), &output).await,
+    }
+}
+
+async fn export(api: &ApiArgs, path: &str, mut query: Vec<(&str, String)>, output: &OutputArgs) -> Result<()> {
+    query.push(("format", output.format.as_str().to_string()));
+    if let Some(max_nodes) = output.max_nodes {
+        query.push(("max_nodes", max_nodes.to_string()));
+    }
+    let url = format!("{}/api/v1/{}", api.remote.trim_end_matches('/'), path);
+    let mut request = reqwest::Client::new().get(&url).query(&query);
+    if let Some(token) = &api.token {
+        request = request.bearer_auth(token);
+    }
+    let response = request.send().await.with_context(|| format!("Failed to reach {}", url))?;
+    let status = response.status();
+    let body = response.text().await?;
+    if !status.is_success() {
+        let message = serde_json::from_str::<Value>(&body).ok()
+            .and_then(|body| body.get("error").and_then(Value::as_str).map(str::to_string))
+            .unwrap_or_else(|| status.to_string());
+        anyhow::bail!("{}", message);
+    }
+
+    match &output.output {
+        Some(path) => {
+            std::fs::write(path, &body).with_context(|| format!("Failed to write {}", path.display()))?;
+            eprintln!("{} {}", "Wrote".green().bold(), path.display());
+        }
+        None => print!("{}", body),
+    }
+    Ok(())
+}
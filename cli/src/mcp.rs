@@ -0,0 +1,321 @@
+//! `talkpprun mcp`: manage MCP servers and call their tools, either through a hub
+//! embedded in the CLI or through a running api-server.
+
+use anyhow::Result;
+use clap::{Args, Subcommand, ValueEnum};
+use colored::*;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use talkpp_mcp_hub::{McpConnection, McpError, McpHub, McpServerConfig, McpServerStatus, McpTool};
+
+use crate::render_table;
+
+#[derive(Args)]
+pub struct McpArgs {
+    /// Base URL of an api-server to manage instead of the local registry
+    #[arg(long, global = true, env = "TALKPP_API_URL")]
+    remote: Option<String>,
+
+    /// Bearer token sent with --remote requests
+    #[arg(long, global = true, env = "TALKPP_API_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+
+    /// Registry of MCP servers used without --remote
+    #[arg(long, global = true, env = "TALKPP_MCP_REGISTRY", default_value = ".talkpp/mcp-servers.json")]
+    registry: PathBuf,
+
+    #[command(subcommand)]
+    command: McpCommand,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Transport {
+    Stdio,
+    Http,
+    Ws,
+}
+
+#[derive(Subcommand)]
+enum McpCommand {
+    /// Register an MCP server
+    Add {
+        /// Unique name for the server
+        #[arg(long)]
+        name: String,
+
+        /// How to reach the server
+        #[arg(long, value_enum)]
+        transport: Transport,
+
+        /// Command starting a stdio server
+        #[arg(long, required_if_eq("transport", "stdio"), conflicts_with = "url")]
+        command: Option<String>,
+
+        /// Arguments for --command (repeatable)
+        #[arg(long = "arg", requires = "command", allow_hyphen_values = true)]
+        args: Vec<String>,
+
+        /// URL of an http or ws server
+        #[arg(long, required_if_eq_any([("transport", "http"), ("transport", "ws")]))]
+        url: Option<String>,
+
+        /// HTTP header as NAME=VALUE (repeatable)
+        #[arg(long = "header", value_parser = parse_header, requires = "url")]
+        headers: Vec<(String, String)>,
+
+        /// What the server provides
+        #[arg(long, default_value = "")]
+        description: String,
+    },
+
+    /// List registered servers with their connection status
+    List,
+
+    /// List the tools a server provides
+    Tools {
+        /// Server name or id
+        server: String,
+    },
+
+    /// Call a tool, checking the parameters against its schema first
+    Call {
+        /// Tool name
+        tool: String,
+
+        /// Tool parameters (JSON object)
+        #[arg(long, default_value = "{}")]
+        params: String,
+    },
+}
+
+fn parse_header(header: &str) -> Result<(String, String), String> {
+    header.split_once('=')
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .ok_or_else(|| format!("expected NAME=VALUE, got '{}'", header))
+}
+
+/// Exit code for calls rejected by local schema validation, before reaching the server
+const EXIT_INVALID_PARAMS: i32 = 2;
+
+pub async fn mcp_command(args: McpArgs) -> Result<()> {
+    let backend = match &args.remote {
+        Some(url) => Backend::Remote(RemoteHub::new(url, args.token.as_deref())),
+        None => Backend::Local(Box::new(McpHub::with_registry(&args.registry)?)),
+    };
+
+    match args.command {
+        McpCommand::Add { name, transport, command, args, url, headers, description } => {
+            let connection = match transport {
+                Transport::Stdio => McpConnection::Stdio { command: command.unwrap_or_default(), args },
+                Transport::Http => McpConnection::Http {
+                    url: url.unwrap_or_default(),
+                    headers: headers.into_iter().collect(),
+                },
+                Transport::Ws => McpConnection::WebSocket { url: url.unwrap_or_default() },
+            };
+            let config = McpServerConfig::new(name, connection).with_description(description);
+            add_command(&backend, config).await
+        }
+        McpCommand::List => list_command(&backend).await,
+        McpCommand::Tools { server } => tools_command(&backend, &server).await,
+        McpCommand::Call { tool, params } => call_command(&backend, &tool, &params).await,
+    }
+}
+
+async fn add_command(backend: &Backend, config: McpServerConfig) -> Result<()> {
+    let name = config.name.clone();
+    let status = backend.add(config).await?;
+    println!("{} MCP server {} ({})", "Registered".green().bold(), name, status.id);
+    match status.last_error {
+        Some(error) => println!("{} Could not connect yet: {}", "Warning".yellow(), error),
+        None => println!("Connected, {} tools available", status.tools_count),
+    }
+    Ok(())
+}
+
+async fn list_command(backend: &Backend) -> Result<()> {
+    let servers = backend.list().await?;
+    if servers.is_empty() {
+        println!("{} No MCP servers registered", "Listing".blue().bold());
+        return Ok(());
+    }
+
+    let rows: Vec<Vec<String>> = servers.iter()
+        .map(|s| {
+            let status = match (s.enabled, s.connected) {
+                (false, _) => "disabled",
+                (true, true) => "connected",
+                (true, false) => "disconnected",
+            };
+            vec![s.name.clone(), s.transport.clone(), status.to_string(), s.tools_count.to_string(), s.id.to_string()]
+        })
+        .collect();
+    print!("{}", render_table(&["NAME", "TRANSPORT", "STATUS", "TOOLS", "ID"], &rows));
+
+    for server in servers.iter().filter(|s| s.last_error.is_some()) {
+        println!("{} {}: {}", "Warning".yellow(), server.name, server.last_error.as_deref().unwrap_or_default());
+    }
+    Ok(())
+}
+
+async fn tools_command(backend: &Backend, server: &str) -> Result<()> {
+    let tools = backend.server_tools(server).await?;
+    if tools.is_empty() {
+        println!("{} {} has no tools", "Listing".blue().bold(), server);
+        return Ok(());
+    }
+
+    let rows: Vec<Vec<String>> = tools.iter().map(|t| vec![t.name.clone(), t.description.clone()]).collect();
+    print!("{}", render_table(&["NAME", "DESCRIPTION"], &rows));
+    Ok(())
+}
+
+async fn call_command(backend: &Backend, tool: &str, params: &str) -> Result<()> {
+    let params: Value = serde_json::from_str(params).map_err(|e| {
+        anyhow::anyhow!("Invalid --params JSON at line {}, column {}: {}", e.line(), e.column(), e)
+    })?;
+
+    let tool = backend.find_tool(tool).await?;
+    if let Err(McpError::InvalidParams { violations, .. }) = tool.validate_params(&params) {
+        eprintln!("{} Invalid params for {}:", "Error".red().bold(), tool.name);
+        for violation in violations {
+            eprintln!("  • {}", violation);
+        }
+        std::process::exit(EXIT_INVALID_PARAMS);
+    }
+
+    match backend.call(&tool.name, params).await {
+        Ok(result) => {
+            println!("{}", serde_json::to_string_pretty(&result)?);
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("{} {} failed: {}", "Error".red().bold(), tool.name, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Where `mcp` subcommands are carried out
+enum Backend {
+    Local(Box<McpHub>),
+    Remote(RemoteHub),
+}
+
+impl Backend {
+    async fn add(&self, config: McpServerConfig) -> Result<McpServerStatus> {
+        match self {
+            Backend::Local(hub) => {
+                let server_id = config.id;
+                hub.add_server(config).await?;
+                // A server that is down now is still worth registering
+                let _ = hub.connect_server(server_id).await;
+                Ok(hub.get_server_status(server_id).await?)
+            }
+            Backend::Remote(remote) => remote.post("servers", &config).await,
+        }
+    }
+
+    async fn list(&self) -> Result<Vec<McpServerStatus>> {
+        match self {
+            Backend::Local(hub) => {
+                hub.connect_enabled().await;
+                let mut statuses = Vec::new();
+                for server in hub.servers().await {
+                    statuses.push(hub.get_server_status(server.id).await?);
+                }
+                Ok(statuses)
+            }
+            Backend::Remote(remote) => Ok(remote.get::<ServersResponse>("servers").await?.servers),
+        }
+    }
+
+    async fn server_tools(&self, server: &str) -> Result<Vec<McpTool>> {
+        match self {
+            Backend::Local(hub) => {
+                let config = hub.find_server(server).await
+                    .ok_or_else(|| McpError::ServerNotFound(server.to_string()))?;
+                hub.connect_server(config.id).await?;
+                Ok(hub.server_tools(config.id).await)
+            }
+            Backend::Remote(remote) => Ok(remote.get::<ToolsResponse>(&format!("servers/{}/tools", server)).await?.tools),
+        }
+    }
+
+    async fn find_tool(&self, tool: &str) -> Result<McpTool> {
+        let found = match self {
+            Backend::Local(hub) => {
+                hub.connect_enabled().await;
+                hub.find_tool(tool).await
+            }
+            Backend::Remote(remote) => remote.get::<ToolsResponse>("tools").await?.tools.into_iter().find(|t| t.name == tool),
+        };
+        found.ok_or_else(|| McpError::ToolNotFound(tool.to_string()).into())
+    }
+
+    async fn call(&self, tool: &str, params: Value) -> Result<Value> {
+        match self {
+            Backend::Local(hub) => Ok(hub.call_tool(tool, params).await?),
+            Backend::Remote(remote) => Ok(remote.post::<CallResponse>(&format!("tools/{}/execute", tool), &params).await?.result),
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct ServersResponse {
+    servers: Vec<McpServerStatus>,
+}
+
+#[derive(serde::Deserialize)]
+struct ToolsResponse {
+    tools: Vec<McpTool>,
+}
+
+#[derive(serde::Deserialize)]
+struct CallResponse {
+    result: Value,
+}
+
+/// The api-server's `/api/v1/mcp` routes
+struct RemoteHub {
+    base_url: String,
+    client: reqwest::Client,
+    headers: HashMap<String, String>,
+}
+
+impl RemoteHub {
+    fn new(url: &str, token: Option<&str>) -> Self {
+        let headers = token
+            .map(|token| HashMap::from([("Authorization".to_string(), format!("Bearer {}", token))]))
+            .unwrap_or_default();
+        Self {
+            base_url: format!("{}/api/v1/mcp", url.trim_end_matches('/')),
+            client: reqwest::Client::new(),
+            headers,
+        }
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        self.send(self.client.get(format!("{}/{}", self.base_url, path))).await
+    }
+
+    async fn post<T: DeserializeOwned>(&self, path: &str, body: &impl serde::Serialize) -> Result<T> {
+        self.send(self.client.post(format!("{}/{}", self.base_url, path)).json(body)).await
+    }
+
+    async fn send<T: DeserializeOwned>(&self, mut request: reqwest::RequestBuilder) -> Result<T> {
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        let body: Value = response.json().await.unwrap_or(Value::Null);
+        if !status.is_success() {
+            let message = body.get("error").and_then(Value::as_str).map(str::to_string).unwrap_or_else(|| status.to_string());
+            return Err(anyhow::anyhow!("{}", message));
+        }
+        Ok(serde_json::from_value(body)?)
+    }
+}
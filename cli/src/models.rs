@@ -0,0 +1,110 @@
+//! `talkpprun models`: download models into the local model cache, and list and remove
+//! cached ones, so processors can load them without network access.
+
+use anyhow::Result;
+use clap::{Args, Subcommand};
+use colored::*;
+use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
+use std::path::PathBuf;
+use talkpp_model_traits::repository::ModelRepository;
+
+use crate::render_table;
+
+#[derive(Args)]
+pub struct ModelsArgs {
+    /// Directory models are cached in, instead of TALKPP_MODEL_CACHE or ~/.cache/talkpp/models
+    #[arg(long, global = true)]
+    cache_dir: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: ModelsCommand,
+}
+
+#[derive(Subcommand)]
+enum ModelsCommand {
+    /// Download models into the cache, resuming interrupted downloads
+    Pull {
+        /// Hub model ids, e.g. sentence-transformers/all-MiniLM-L6-v2
+        #[arg(required = true)]
+        ids: Vec<String>,
+
+        /// Hub to download from, instead of HF_ENDPOINT or https://huggingface.co
+        #[arg(long)]
+        endpoint: Option<String>,
+
+        /// Branch, tag or commit to download
+        #[arg(long)]
+        revision: Option<String>,
+
+        /// Evict the least recently used models once the cache holds more than this many bytes
+        #[arg(long)]
+        max_cache_bytes: Option<u64>,
+    },
+
+    /// List cached models
+    List,
+
+    /// Remove a model from the cache
+    Rm {
+        /// Model id
+        id: String,
+    },
+}
+
+pub async fn models_command(args: ModelsArgs) -> Result<()> {
+    let mut models = ModelRepository::from_env();
+    if let Some(cache_dir) = args.cache_dir {
+        models = models.with_cache_dir(cache_dir);
+    }
+
+    match args.command {
+        ModelsCommand::Pull { ids, endpoint, revision, max_cache_bytes } => {
+            if let Some(endpoint) = endpoint {
+                models = models.with_endpoint(endpoint);
+            }
+            if let Some(revision) = revision {
+                models = models.with_revision(revision);
+            }
+            if let Some(max_cache_bytes) = max_cache_bytes {
+                models = models.with_max_cache_bytes(max_cache_bytes);
+            }
+            let bar = ProgressBar::new(0).with_style(
+                ProgressStyle::with_template("{msg} {bar:40} {bytes}/{total_bytes} ({eta} left)")?,
+            );
+            let dirs = models.prefetch(&ids, |progress| {
+                bar.set_message(format!("{} {}", progress.model_id, progress.file));
+                bar.set_length(progress.total.unwrap_or(progress.downloaded));
+                bar.set_position(progress.downloaded);
+            }).await;
+            bar.finish_and_clear();
+            for (id, dir) in ids.iter().zip(dirs?) {
+                println!("{} {} into {}", "Pulled".green().bold(), id, dir.display());
+            }
+        }
+        ModelsCommand::List => {
+            let cached = models.list_cached()?;
+            if cached.is_empty() {
+                println!("{} No models cached in {}", "Listing".blue().bold(), models.cache_dir().display());
+                return Ok(());
+            }
+            println!("{} Models cached in {}:", "Listing".blue().bold(), models.cache_dir().display());
+            let rows: Vec<Vec<String>> = cached.iter()
+                .map(|model| vec![
+                    model.id.clone(),
+                    model.revision.clone(),
+                    model.files.len().to_string(),
+                    HumanBytes(model.size_bytes()).to_string(),
+                    model.last_used.format("%Y-%m-%d %H:%M:%S").to_string(),
+                ])
+                .collect();
+            print!("{}", render_table(&["ID", "REVISION", "FILES", "SIZE", "LAST USED"], &rows));
+        }
+        ModelsCommand::Rm { id } => {
+            if !models.evict(&id)? {
+                anyhow::bail!("Model '{}' is not cached in {}", id, models.cache_dir().display());
+            }
+            println!("{} {}", "Removed".green().bold(), id);
+        }
+    }
+    Ok(())
+}
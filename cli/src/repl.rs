@@ -0,0 +1,367 @@
+//! Interactive Talk++ sessions (`talkppc repl`)
+//!
+//! Each entry is parsed on its own and its statements are appended to a program that
+//! persists for the session, along with the variables it has assigned. After every entry
+//! the whole program is generated for the current target, so code generation errors show
+//! up against the entry that caused them, which is then left out of the program.
+//!
+//! An entry keeps reading while a conditional is open or the last line ends in a word
+//! that needs something after it, such as `then` or `using`. A blank line submits it
+//! anyway, which is how a conditional without `end` is entered. Lines starting with `:`
+//! are meta-commands; `:help` lists them.
+
+use anyhow::Result;
+use colored::*;
+use std::collections::BTreeMap;
+use talkpp_compiler::ast::{Expression, Program, Statement};
+use talkpp_compiler::error::{render_snippet, CompilerError};
+use talkpp_compiler::lexer::{self, Token, TokenWithSpan};
+use talkpp_compiler::{codegen, parser, CompilerConfig, TargetLanguage};
+use talkpp_simulator::{mock::MockRegistry, SimulationConfig, Simulator};
+
+const HELP: &str = "\
+:ast              show the program's syntax tree as JSON
+:code             show the program generated for the current target
+:target <name>    generate for rust, python, js, ts, bash or go
+:simulate         run the program in the simulator and print its trace
+:event <json>     set the event :simulate passes to the program
+:vars             show the variables assigned so far
+:reset            start over with an empty program
+:help             show this help
+:quit             leave the REPL";
+
+/// What a line of input produced
+#[derive(Debug, PartialEq)]
+pub enum Reply {
+    /// The entry continues on the next line
+    More,
+    Output(String),
+    Error(String),
+    Quit,
+}
+
+/// Everything a REPL session has accumulated, independent of the terminal
+pub struct ReplSession {
+    program: Program,
+    variables: BTreeMap<String, Expression>,
+    config: CompilerConfig,
+    mocks: MockRegistry,
+    event: serde_json::Value,
+    /// Lines of the entry being read
+    pending: Vec<String>,
+}
+
+impl ReplSession {
+    pub fn new(target: TargetLanguage) -> Self {
+        Self {
+            program: Program::new(),
+            variables: BTreeMap::new(),
+            config: CompilerConfig { target_language: target, ..CompilerConfig::default() },
+            mocks: MockRegistry::default(),
+            event: serde_json::Value::Null,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Answer `:simulate` from `mocks` instead of the default mock responses
+    pub fn with_mocks(mut self, mocks: MockRegistry) -> Self {
+        self.mocks = mocks;
+        self
+    }
+
+    /// Event `:simulate` passes to the program
+    pub fn with_event(mut self, event: serde_json::Value) -> Self {
+        self.event = event;
+        self
+    }
+
+    /// Whether an entry is waiting for more lines
+    pub fn is_continuing(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Drop the entry being read
+    pub fn cancel(&mut self) {
+        self.pending.clear();
+    }
+
+    /// Handle one line of input
+    pub async fn feed(&mut self, line: &str) -> Reply {
+        if self.pending.is_empty() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                return Reply::Output(String::new());
+            }
+            if let Some(command) = trimmed.strip_prefix(':') {
+                return self.command(command).await;
+            }
+        } else if line.trim().is_empty() {
+            return self.submit();
+        }
+
+        self.pending.push(line.to_string());
+        match lexer::tokenize(&self.pending.join("\n")) {
+            Ok(tokens) if needs_more(&tokens) => Reply::More,
+            _ => self.submit(),
+        }
+    }
+
+    /// Parse the pending entry and add its statements to the program
+    fn submit(&mut self) -> Reply {
+        let entry = std::mem::take(&mut self.pending).join("\n");
+        let parsed = lexer::tokenize(&entry).and_then(parser::parse);
+        let statements = match parsed {
+            Ok(entry_program) => entry_program.statements,
+            Err(e) => return Reply::Error(diagnostic(&entry, &e)),
+        };
+        if statements.is_empty() {
+            return Reply::Output(String::new());
+        }
+
+        let mut candidate = self.program.clone();
+        candidate.statements.extend(statements.iter().cloned());
+        if let Err(e) = codegen::generate(&candidate, &self.config) {
+            return Reply::Error(diagnostic(&entry, &e));
+        }
+
+        self.program = candidate;
+        for statement in &statements {
+            if let Statement::Assignment(assign) = statement {
+                self.variables.insert(assign.variable.clone(), assign.value.clone());
+            }
+        }
+        let added = statements.iter().map(|statement| format!("+ {}", statement.summary())).collect::<Vec<_>>();
+        Reply::Output(added.join("\n"))
+    }
+
+    async fn command(&mut self, command: &str) -> Reply {
+        let (name, argument) = match command.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, argument.trim()),
+            None => (command, ""),
+        };
+        let result = match name {
+            "ast" => serde_json::to_string_pretty(&self.program).map_err(Into::into),
+            "code" => codegen::generate(&self.program, &self.config).map_err(Into::into),
+            "target" => self.set_target(argument),
+            "simulate" => self.simulate().await,
+            "event" => self.set_event(argument),
+            "vars" => Ok(self.variables.iter().map(|(name, value)| format!("{} = {}", name, value)).collect::<Vec<_>>().join("\n")),
+            "reset" => {
+                self.program = Program::new();
+                self.variables.clear();
+                Ok("Program cleared".to_string())
+            }
+            "help" => Ok(HELP.to_string()),
+            "quit" | "q" | "exit" => return Reply::Quit,
+            _ => Err(anyhow::anyhow!("Unknown command ':{}'; :help lists them", name)),
+        };
+        match result {
+            Ok(output) => Reply::Output(output),
+            Err(e) => Reply::Error(e.to_string()),
+        }
+    }
+
+    fn set_target(&mut self, name: &str) -> Result<String> {
+        if name.is_empty() {
+            return Ok(format!("Target is {:?}", self.config.target_language));
+        }
+        let target = super::parse_target(name)?;
+        let config = CompilerConfig { target_language: target.clone(), ..self.config.clone() };
+        // The program must still generate, or later entries would be blamed for it
+        codegen::generate(&self.program, &config)?;
+        self.config = config;
+        Ok(format!("Target is {:?}", target))
+    }
+
+    fn set_event(&mut self, json: &str) -> Result<String> {
+        if json.is_empty() {
+            return Ok(self.event.to_string());
+        }
+        self.event = serde_json::from_str(json)?;
+        Ok(format!("Event is {}", self.event))
+    }
+
+    async fn simulate(&self) -> Result<String> {
+        let config = SimulationConfig { input: self.event.clone(), ..SimulationConfig::default() };
+        let result = Simulator::new().with_mocks(self.mocks.clone()).simulate_program(&self.program, config).await?;
+
+        let mut out = result.trace.map(|trace| trace.render_tree()).unwrap_or_default();
+        if result.success {
+            out.push_str(&format!("succeeded in {}ms", result.execution_time_ms));
+        } else {
+            out.push_str(&format!("failed: {}", result.errors.join("; ")));
+        }
+        Ok(out)
+    }
+}
+
+/// Whether an entry ending in `tokens` has more to come: a conditional without its `end`,
+/// or a last word that cannot end a statement
+fn needs_more(tokens: &[TokenWithSpan]) -> bool {
+    let mut open = 0usize;
+    for token in tokens {
+        match token.token {
+            Token::If | Token::When => open += 1,
+            Token::End => open = open.saturating_sub(1),
+            _ => {}
+        }
+    }
+    open > 0 || matches!(
+        tokens.last().map(|token| &token.token),
+        Some(Token::Then | Token::Else | Token::And | Token::Or | Token::Using | Token::With | Token::To | Token::In | Token::From | Token::Colon)
+    )
+}
+
+/// `error` with the part of `entry` it points at, when it has a location
+fn diagnostic(entry: &str, error: &CompilerError) -> String {
+    match error.span() {
+        Some(span) => format!("{}\n{}", error, render_snippet(entry, span).trim_end()),
+        None => error.to_string(),
+    }
+}
+
+/// Read entries from the terminal until `:quit` or end of input
+pub async fn run(mut session: ReplSession) -> Result<()> {
+    use rustyline::error::ReadlineError;
+
+    let mut editor = rustyline::DefaultEditor::new()?;
+    let history = std::env::var_os("HOME").map(|home| std::path::Path::new(&home).join(".talkpp_history"));
+    if let Some(history) = &history {
+        let _ = editor.load_history(history);
+    }
+    println!("{} (:help for commands, :quit to leave)", "Talk++ REPL".blue().bold());
+
+    loop {
+        let prompt = if session.is_continuing() { "   ...> " } else { "talk++> " };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => {
+                session.cancel();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        if !line.trim().is_empty() {
+            let _ = editor.add_history_entry(line.as_str());
+        }
+
+        match session.feed(&line).await {
+            Reply::More => {}
+            Reply::Output(output) if output.is_empty() => {}
+            Reply::Output(output) => println!("{}", output),
+            Reply::Error(error) => eprintln!("{} {}", "error:".red().bold(), error),
+            Reply::Quit => break,
+        }
+    }
+
+    if let Some(history) = &history {
+        let _ = editor.save_history(history);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn feed_all(session: &mut ReplSession, lines: &[&str]) -> Vec<Reply> {
+        let mut replies = Vec::new();
+        for line in lines {
+            replies.push(session.feed(line).await);
+        }
+        replies
+    }
+
+    fn output(reply: Reply) -> String {
+        match reply {
+            Reply::Output(output) => output,
+            other => panic!("expected output, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_entries_accumulate_into_one_program() {
+        let mut session = ReplSession::new(TargetLanguage::Rust);
+        let replies = feed_all(&mut session, &["greeting: \"hello\"", "send greeting using Twilio"]).await;
+        assert_eq!(replies, vec![
+            Reply::Output("+ greeting: \"hello\"".to_string()),
+            Reply::Output("+ send greeting using Twilio".to_string()),
+        ]);
+
+        let ast: serde_json::Value = serde_json::from_str(&output(session.feed(":ast").await)).unwrap();
+        assert_eq!(ast["statements"].as_array().unwrap().len(), 2);
+        assert_eq!(output(session.feed(":vars").await), "greeting = \"hello\"");
+
+        let rust = output(session.feed(":code").await);
+        assert!(rust.contains("let greeting = "), "{}", rust);
+        assert_eq!(output(session.feed(":target python").await), "Target is Python");
+        let python = output(session.feed(":code").await);
+        assert!(python.contains("def "), "{}", python);
+
+        assert_eq!(output(session.feed(":reset").await), "Program cleared");
+        assert_eq!(output(session.feed(":vars").await), "");
+    }
+
+    #[tokio::test]
+    async fn test_open_conditionals_keep_reading() {
+        let mut session = ReplSession::new(TargetLanguage::Rust);
+        let replies = feed_all(&mut session, &[
+            "if new user registers then",
+            "  validate email using",
+            "  SendGrid",
+            "end",
+        ]).await;
+        assert_eq!(&replies[..3], &[Reply::More, Reply::More, Reply::More]);
+        assert_eq!(replies[3], Reply::Output("+ if new user registers".to_string()));
+
+        // Without `end`, a blank line submits the entry
+        let replies = feed_all(&mut session, &["if order placed then process order", ""]).await;
+        assert_eq!(replies, vec![Reply::More, Reply::Output("+ if order placed".to_string())]);
+        assert!(!session.is_continuing());
+    }
+
+    #[tokio::test]
+    async fn test_errors_point_into_the_entry_and_leave_the_program_alone() {
+        let mut session = ReplSession::new(TargetLanguage::Rust);
+        session.feed("send alert using Slack").await;
+
+        match session.feed("send alert using 42").await {
+            Reply::Error(error) => {
+                assert!(error.starts_with("Parse error at line 1, column 18: Expected service name"), "{}", error);
+                assert!(error.ends_with("1 | send alert using 42\n  |                  ^^"), "{}", error);
+            }
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+        match session.feed("match: 1").await {
+            Reply::Error(error) => assert!(error.contains("'match' is a reserved word in Rust"), "{}", error),
+            other => panic!("expected a code generation error, got {:?}", other),
+        }
+        assert!(matches!(session.feed(":nope").await, Reply::Error(e) if e.contains("Unknown command")));
+        assert!(matches!(session.feed(":target cobol").await, Reply::Error(_)));
+
+        let ast: serde_json::Value = serde_json::from_str(&output(session.feed(":ast").await)).unwrap();
+        assert_eq!(ast["statements"].as_array().unwrap().len(), 1);
+        assert_eq!(session.feed(":quit").await, Reply::Quit);
+    }
+
+    #[tokio::test]
+    async fn test_simulate_runs_the_accumulated_program() {
+        let mut session = ReplSession::new(TargetLanguage::Rust)
+            .with_event(serde_json::json!({"type": "new_user_registers", "email": "ada@example.com"}));
+        feed_all(&mut session, &["if new user registers then validate email using SendGrid", ""]).await;
+        // Entered separately, so it runs after the conditional rather than inside it
+        session.feed("process signup").await;
+
+        let trace = output(session.feed(":simulate").await);
+        assert!(trace.contains("├─ if new user registers → true"), "{}", trace);
+        assert!(trace.contains("  ├─ call SendGrid [mock]"), "{}", trace);
+        assert!(trace.contains("\n├─ process signup"), "{}", trace);
+        assert!(trace.ends_with("ms"), "{}", trace);
+
+        output(session.feed(":event {\"type\": \"order_placed\"}").await);
+        let trace = output(session.feed(":simulate").await);
+        assert!(trace.contains("→ false"), "{}", trace);
+        assert!(!trace.contains("SendGrid"), "{}", trace);
+    }
+}
@@ -5,8 +5,13 @@
 use anyhow::Result;
 use clap::{Parser, Subcommand};
 use colored::*;
-use std::path::PathBuf;
-use talkpp_compiler::{Compiler, CompilerConfig, TargetLanguage, OptimizationLevel};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use talkpp_compiler::error::{render_snippet, CompilerError};
+use talkpp_compiler::{CacheStats, Compiler, CompilerConfig, TargetLanguage, OptimizationLevel};
+use talkpp_simulator::mock::MockRegistry;
+
+mod repl;
 
 #[derive(Parser)]
 #[command(name = "talkppc")]
@@ -42,6 +47,46 @@ enum Commands {
         debug: bool,
     },
     
+    /// Compile a source file, optionally recompiling whenever it changes.
+    ///
+    /// Exits with 2 for parse errors, 3 for validation errors and 4 for IO errors.
+    Compile {
+        /// Input Talk++ source file
+        input: PathBuf,
+        
+        /// Target language (rust, python, js, ts, bash, go)
+        #[arg(short, long, default_value = "rust")]
+        target: String,
+        
+        /// Output file, or a directory to write `<input stem>.<ext>` into
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+        
+        /// Generate debug builds with logging (the default)
+        #[arg(long, conflicts_with = "release")]
+        debug: bool,
+        
+        /// Generate release builds without debug logging
+        #[arg(long)]
+        release: bool,
+        
+        /// Recompile whenever the input changes
+        #[arg(short, long)]
+        watch: bool,
+        
+        /// Quiet period after a change before recompiling, in milliseconds
+        #[arg(long, default_value = "200", requires = "watch")]
+        debounce_ms: u64,
+        
+        /// Directory caching generated code per statement between compiles
+        #[arg(long, env = "TALKPP_CACHE_DIR", default_value = ".talkpp/cache")]
+        cache_dir: PathBuf,
+        
+        /// Regenerate every statement instead of reusing cached code
+        #[arg(long)]
+        no_cache: bool,
+    },
+    
     /// Validate Talk++ syntax
     Check {
         /// Input Talk++ source file
@@ -49,13 +94,29 @@ enum Commands {
         input: PathBuf,
     },
     
+    /// Enter statements interactively, inspecting the AST and generated code as the
+    /// program grows and simulating it at any point
+    Repl {
+        /// Target language to generate for until changed with `:target`
+        #[arg(short, long, default_value = "rust")]
+        target: String,
+        
+        /// Mock definitions file (JSON or YAML) answering service calls in `:simulate`
+        #[arg(long)]
+        mocks: Option<PathBuf>,
+        
+        /// Event data (JSON) `:simulate` passes to the program
+        #[arg(short, long)]
+        event: Option<String>,
+    },
+    
     /// Show compiler version and supported languages
     Info,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    tracing_subscriber::init();
+    tracing_subscriber::fmt::init();
     
     let cli = Cli::parse();
     
@@ -63,9 +124,40 @@ async fn main() -> Result<()> {
         Commands::Build { input, output, target, optimization, debug } => {
             build_command(input, output, target, optimization, debug).await
         }
+        Commands::Compile { input, target, out, debug: _, release, watch, debounce_ms, cache_dir, no_cache } => {
+            let config = CompilerConfig {
+                target_language: parse_target(&target)?,
+                optimization_level: if release { OptimizationLevel::Release } else { OptimizationLevel::Debug },
+                debug_mode: !release,
+                ..CompilerConfig::default()
+            };
+            let job = CompileJob {
+                output: output_path(&input, out.as_deref(), &config.target_language),
+                input,
+                config,
+                cache_dir: (!no_cache).then_some(cache_dir),
+            };
+            if watch {
+                return watch_command(&job, Duration::from_millis(debounce_ms)).await;
+            }
+            if let Err(e) = job.run() {
+                std::process::exit(exit_code(&e));
+            }
+            Ok(())
+        }
         Commands::Check { input } => {
             check_command(input).await
         }
+        Commands::Repl { target, mocks, event } => {
+            let mut session = repl::ReplSession::new(parse_target(&target)?);
+            if let Some(path) = mocks {
+                session = session.with_mocks(MockRegistry::from_file(path)?);
+            }
+            if let Some(event) = event {
+                session = session.with_event(serde_json::from_str(&event)?);
+            }
+            repl::run(session).await
+        }
         Commands::Info => {
             info_command()
         }
@@ -85,14 +177,7 @@ async fn build_command(
     let source = std::fs::read_to_string(&input)?;
     
     // Parse target language
-    let target_language = match target.to_lowercase().as_str() {
-        "rust" => TargetLanguage::Rust,
-        "python" => TargetLanguage::Python,
-        "javascript" | "js" => TargetLanguage::JavaScript,
-        "typescript" | "ts" => TargetLanguage::TypeScript,
-        "bash" => TargetLanguage::Bash,
-        _ => return Err(anyhow::anyhow!("Unsupported target language: {}", target)),
-    };
+    let target_language = parse_target(&target)?;
     
     // Parse optimization level
     let optimization_level = match optimization.to_lowercase().as_str() {
@@ -107,22 +192,18 @@ async fn build_command(
         target_language,
         optimization_level,
         debug_mode: debug,
+        ..CompilerConfig::default()
     };
     
     // Compile the source
+    let extension = extension(&config.target_language);
     let compiler = Compiler::with_config(config);
-    let compiled_code = compiler.compile(&source)?;
+    let compiled_code = compiler.compile_to_code(&source).inspect_err(|e| print_source_snippet(&source, e))?;
     
     // Determine output path
     let output_path = output.unwrap_or_else(|| {
         let mut path = input.clone();
-        path.set_extension(match target_language {
-            TargetLanguage::Rust => "rs",
-            TargetLanguage::Python => "py",
-            TargetLanguage::JavaScript => "js",
-            TargetLanguage::TypeScript => "ts",
-            TargetLanguage::Bash => "sh",
-        });
+        path.set_extension(extension);
         path
     });
     
@@ -146,6 +227,7 @@ async fn check_command(input: PathBuf) -> Result<()> {
         }
         Err(e) => {
             println!("{} Syntax error: {}", "Error".red().bold(), e);
+            print_source_snippet(&source, &e);
             return Err(e);
         }
     }
@@ -153,6 +235,154 @@ async fn check_command(input: PathBuf) -> Result<()> {
     Ok(())
 }
 
+/// Show the offending source for compiler errors that carry a location, with the
+/// underline highlighted
+fn print_source_snippet(source: &str, error: &anyhow::Error) {
+    if let Some(span) = error.downcast_ref::<CompilerError>().and_then(CompilerError::span) {
+        for line in render_snippet(source, span).lines() {
+            if line.ends_with('^') {
+                eprintln!("{}", line.red().bold());
+            } else {
+                eprintln!("{}", line.blue());
+            }
+        }
+    }
+}
+
+fn parse_target(target: &str) -> Result<TargetLanguage> {
+    Ok(match target.to_lowercase().as_str() {
+        "rust" => TargetLanguage::Rust,
+        "python" => TargetLanguage::Python,
+        "javascript" | "js" => TargetLanguage::JavaScript,
+        "typescript" | "ts" => TargetLanguage::TypeScript,
+        "bash" => TargetLanguage::Bash,
+        "go" | "golang" => TargetLanguage::Go,
+        _ => return Err(anyhow::anyhow!("Unsupported target language: {}", target)),
+    })
+}
+
+fn extension(target: &TargetLanguage) -> &'static str {
+    match target {
+        TargetLanguage::Rust => "rs",
+        TargetLanguage::Python => "py",
+        TargetLanguage::JavaScript => "js",
+        TargetLanguage::TypeScript => "ts",
+        TargetLanguage::Bash => "sh",
+        TargetLanguage::Go => "go",
+    }
+}
+
+/// Where `compile` writes its output: `out` itself, a file inside `out` when it is a
+/// directory, or next to the input
+fn output_path(input: &Path, out: Option<&Path>, target: &TargetLanguage) -> PathBuf {
+    let file_name = Path::new(input.file_name().unwrap_or_default()).with_extension(extension(target));
+    match out {
+        Some(dir) if dir.is_dir() || dir.to_string_lossy().ends_with(std::path::MAIN_SEPARATOR) => dir.join(file_name),
+        Some(path) => path.to_path_buf(),
+        None => input.with_file_name(file_name),
+    }
+}
+
+const EXIT_PARSE: i32 = 2;
+const EXIT_VALIDATION: i32 = 3;
+const EXIT_IO: i32 = 4;
+
+/// Exit code for a failed compile, so CI can tell bad syntax from bad programs
+fn exit_code(error: &anyhow::Error) -> i32 {
+    if error.downcast_ref::<std::io::Error>().is_some() {
+        return EXIT_IO;
+    }
+    match error.downcast_ref::<CompilerError>() {
+        Some(CompilerError::LexicalError { .. } | CompilerError::ParseError { .. }) => EXIT_PARSE,
+        Some(CompilerError::IoError { .. }) => EXIT_IO,
+        Some(_) => EXIT_VALIDATION,
+        None => 1,
+    }
+}
+
+/// A single input compiled to a single output
+struct CompileJob {
+    input: PathBuf,
+    output: PathBuf,
+    config: CompilerConfig,
+    /// Fragment cache directory, unless compiling with `--no-cache`
+    cache_dir: Option<PathBuf>,
+}
+
+impl CompileJob {
+    /// Compile once, printing one status line followed by any source snippet
+    fn run(&self) -> Result<()> {
+        let started = Instant::now();
+        let (source, result) = match std::fs::read_to_string(&self.input) {
+            Ok(source) => {
+                let result = self.compile(&source);
+                (source, result)
+            }
+            Err(e) => (String::new(), Err(e.into())),
+        };
+        match &result {
+            Ok(cache) => println!(
+                "{} {} -> {} ({}{}ms)",
+                "ok".green().bold(),
+                self.input.display(),
+                self.output.display(),
+                cache
+                    .map(|stats| format!("{}/{} statements cached, ", stats.hits, stats.hits + stats.misses))
+                    .unwrap_or_default(),
+                started.elapsed().as_millis()
+            ),
+            Err(e) => {
+                eprintln!("{} {}: {}", "error:".red().bold(), self.input.display(), e);
+                print_source_snippet(&source, e);
+            }
+        }
+        result.map(|_| ())
+    }
+
+    fn compile(&self, source: &str) -> Result<Option<CacheStats>> {
+        let mut compiler = Compiler::with_config(self.config.clone());
+        if let Some(dir) = &self.cache_dir {
+            compiler = compiler.with_cache_dir(dir);
+        }
+        let artifact = compiler.compile(source)?;
+        if let Some(parent) = self.output.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.output, &artifact.code)?;
+        Ok(artifact.provenance.cache)
+    }
+}
+
+/// Recompile whenever the input's contents change, waiting for `debounce` without
+/// further changes so editors that write in several steps trigger one rebuild
+async fn watch_command(job: &CompileJob, debounce: Duration) -> Result<()> {
+    println!("{} {} (Ctrl-C to stop)", "Watching".cyan().bold(), job.input.display());
+    let read = || std::fs::read(&job.input).ok();
+
+    let mut compiled = read();
+    let _ = job.run();
+    let mut pending: Option<(Option<Vec<u8>>, Instant)> = None;
+    loop {
+        tokio::time::sleep(WATCH_POLL_INTERVAL).await;
+        let current = read();
+        match &pending {
+            Some((contents, _)) if *contents != current => pending = Some((current, Instant::now())),
+            Some((_, changed_at)) if changed_at.elapsed() >= debounce => {
+                pending = None;
+                if current != compiled {
+                    let _ = job.run();
+                    compiled = current;
+                }
+            }
+            Some(_) => {}
+            None if current != compiled => pending = Some((current, Instant::now())),
+            None => {}
+        }
+    }
+}
+
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
 fn info_command() -> Result<()> {
     println!("{}", "Talk++ Compiler Information".blue().bold());
     println!("Version: 0.2.0");
@@ -162,6 +392,7 @@ fn info_command() -> Result<()> {
     println!("  • JavaScript");
     println!("  • TypeScript");
     println!("  • Bash");
+    println!("  • Go");
     
     Ok(())
 } 
\ No newline at end of file
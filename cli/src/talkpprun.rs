@@ -3,20 +3,36 @@
 //! Command-line interface for executing and simulating Talk++ functions.
 
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{ArgGroup, Parser, Subcommand, ValueEnum};
 use colored::*;
-use std::path::PathBuf;
-use talkpp_simulator::{Simulator, SimulationConfig};
+use std::path::{Path, PathBuf};
+use talkpp_runtime::{event::Event, response::Response, FunctionMetadata, LogSink, LogStream, Runtime};
+use talkpp_simulator::{mock::MockRegistry, validation::ValidationSpec, Simulator, SimulationConfig};
+
+mod graph;
+mod mcp;
+mod models;
+mod vectors;
 
 #[derive(Parser)]
 #[command(name = "talkpprun")]
 #[command(about = "Talk++ Runtime - Execute and simulate Talk++ functions")]
 #[command(version = "0.2.0")]
 struct Cli {
+    /// Directory holding the runtime's deployed functions
+    #[arg(long, global = true, env = "TALKPP_STORE", default_value = ".talkpp/functions")]
+    store: PathBuf,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+#[derive(Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    Json,
+    Pretty,
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// Simulate function execution with dry-run
@@ -40,25 +56,69 @@ enum Commands {
         /// Timeout in seconds
         #[arg(long, default_value = "30")]
         timeout: u64,
+        
+        /// Input event data (JSON)
+        #[arg(short, long)]
+        event: Option<String>,
+        
+        /// Mock definitions file (JSON or YAML); implies --mock
+        #[arg(long)]
+        mocks: Option<PathBuf>,
+        
+        /// Assertions file (JSON or YAML); exits nonzero when any assertion fails
+        #[arg(long = "assert")]
+        assertions: Option<PathBuf>,
+        
+        /// How to print the execution trace
+        #[arg(long, value_enum, default_value = "pretty")]
+        trace_format: OutputFormat,
+        
+        /// How to print the assertion report
+        #[arg(long, value_enum, default_value = "pretty")]
+        report_format: OutputFormat,
     },
     
     /// Execute a deployed function
+    #[command(group(ArgGroup::new("function").required(true).args(["function_id", "name"])))]
     Execute {
         /// Function ID to execute
         #[arg(short, long)]
-        function_id: String,
+        function_id: Option<String>,
         
-        /// Input event data (JSON)
+        /// Name of the function to execute
         #[arg(short, long)]
+        name: Option<String>,
+        
+        /// Input event data (JSON)
+        #[arg(short, long, conflicts_with = "event_file")]
         event: Option<String>,
         
         /// Event file path
         #[arg(long)]
         event_file: Option<PathBuf>,
+        
+        /// Stream the function's stdout and stderr while it runs
+        #[arg(long)]
+        follow_logs: bool,
     },
     
     /// List deployed functions
     List,
+    
+    /// Manage MCP servers and call their tools
+    Mcp(mcp::McpArgs),
+
+    /// Back up, restore and migrate vector database collections
+    Vectors(vectors::VectorsArgs),
+
+    /// Download, list and remove cached models
+    Models(models::ModelsArgs),
+
+    /// Inspect memories held by an api-server
+    Memory(graph::MemoryArgs),
+
+    /// Inspect execution plans made by an api-server
+    Plan(graph::PlanArgs),
 }
 
 #[tokio::main]
@@ -68,6 +128,10 @@ async fn main() -> Result<()> {
     // Initialize tracing based on log level
     let level = match cli.command {
         Commands::Simulate { ref loglevel, .. } => loglevel.clone(),
+        // Connection problems are reported in the command's own output
+        Commands::Mcp(_) | Commands::Memory(_) | Commands::Plan(_) => "error".to_string(),
+        // Progress is drawn as a bar; fallbacks such as a skipped snapshot still show
+        Commands::Vectors(_) | Commands::Models(_) => "warn".to_string(),
         _ => "info".to_string(),
     };
     
@@ -77,23 +141,44 @@ async fn main() -> Result<()> {
     tracing::subscriber::set_global_default(subscriber)?;
     
     match cli.command {
-        Commands::Simulate { input, secrets, loglevel, mock, timeout } => {
-            simulate_command(input, secrets, mock, timeout).await
+        Commands::Simulate { input, secrets, loglevel, mock, timeout, event, mocks, assertions, trace_format, report_format } => {
+            simulate_command(input, secrets, mock, timeout, event, mocks, assertions, trace_format, report_format).await
         }
-        Commands::Execute { function_id, event, event_file } => {
-            execute_command(function_id, event, event_file).await
+        Commands::Execute { function_id, name, event, event_file, follow_logs } => {
+            execute_command(&cli.store, function_id, name, event, event_file, follow_logs).await
         }
         Commands::List => {
-            list_command().await
+            list_command(&cli.store).await
+        }
+        Commands::Mcp(args) => {
+            mcp::mcp_command(args).await
+        }
+        Commands::Vectors(args) => {
+            vectors::vectors_command(args).await
+        }
+        Commands::Models(args) => {
+            models::models_command(args).await
+        }
+        Commands::Memory(args) => {
+            graph::memory_command(args).await
+        }
+        Commands::Plan(args) => {
+            graph::plan_command(args).await
         }
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn simulate_command(
     input: PathBuf,
     secrets: Option<PathBuf>,
     mock: bool,
     timeout: u64,
+    event: Option<String>,
+    mocks: Option<PathBuf>,
+    assertions: Option<PathBuf>,
+    trace_format: OutputFormat,
+    report_format: OutputFormat,
 ) -> Result<()> {
     println!("{} Starting simulation: {}", "Simulating".yellow().bold(), input.display());
     
@@ -108,16 +193,28 @@ async fn simulate_command(
         }
     }
     
+    let registry = match &mocks {
+        Some(path) => MockRegistry::from_file(path)?,
+        None => MockRegistry::default(),
+    };
+    
     // Create simulation config
     let config = SimulationConfig {
-        mock_external_calls: mock,
+        mock_external_calls: mock || mocks.is_some(),
         trace_execution: true,
         validate_outputs: true,
         timeout_seconds: timeout,
+        input: match event {
+            Some(event) => serde_json::from_str(&event)?,
+            None => serde_json::Value::Null,
+        },
     };
     
     // Run simulation
-    let simulator = Simulator::new();
+    let mut simulator = Simulator::new().with_mocks(registry);
+    if let Some(path) = &assertions {
+        simulator = simulator.with_assertions(ValidationSpec::from_file(path)?);
+    }
     let result = simulator.simulate(&code, config).await?;
     
     // Display results
@@ -125,52 +222,191 @@ async fn simulate_command(
         println!("{} Simulation completed successfully", "Success".green().bold());
         println!("Execution time: {}ms", result.execution_time_ms);
         println!("Output: {}", serde_json::to_string_pretty(&result.output)?);
-        
-        if let Some(trace) = result.trace {
-            println!("\n{} Execution trace available", "Info".blue());
-        }
     } else {
         println!("{} Simulation failed", "Error".red().bold());
-        for error in result.errors {
+        for error in &result.errors {
             println!("  • {}", error);
         }
     }
     
+    if !result.calls.is_empty() {
+        println!("\n{} Service calls:", "Info".blue());
+        for call in &result.calls {
+            let status = if call.success { "ok".green() } else { "failed".red() };
+            println!("  • {}.{} {} {}", call.service, call.operation, call.params, status);
+        }
+    }
+    
+    if let Some(trace) = &result.trace {
+        println!("\n{} Execution trace:", "Info".blue());
+        match trace_format {
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(trace)?),
+            OutputFormat::Pretty => print!("{}", trace.render_tree()),
+        }
+    }
+    
+    if let Some(report) = &result.validation {
+        println!("\n{} Assertions:", "Info".blue());
+        match report_format {
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(report)?),
+            OutputFormat::Pretty => print!("{}", report.render_pretty()),
+        }
+        
+        if !report.passed {
+            println!("{} Assertions failed", "Error".red().bold());
+            std::process::exit(1);
+        }
+    }
+    
     Ok(())
 }
 
 async fn execute_command(
-    function_id: String,
+    store: &Path,
+    function_id: Option<String>,
+    name: Option<String>,
     event: Option<String>,
     event_file: Option<PathBuf>,
+    follow_logs: bool,
 ) -> Result<()> {
-    println!("{} Executing function: {}", "Executing".green().bold(), function_id);
+    let runtime = Runtime::with_persistence(store)?;
+    let function = resolve_function(&runtime, function_id.as_deref(), name.as_deref())?;
+    println!("{} Executing function: {} ({})", "Executing".green().bold(), function.name, function.id);
     
     // Load event data
-    let event_data = if let Some(event_str) = event {
-        event_str
-    } else if let Some(event_path) = event_file {
-        std::fs::read_to_string(event_path)?
+    let event = match (event, event_file) {
+        (Some(json), _) => parse_event(&json, "--event")?,
+        (None, Some(path)) => parse_event(&std::fs::read_to_string(&path)?, &path.display().to_string())?,
+        (None, None) => Event::default(),
+    };
+    
+    let response = if follow_logs {
+        let sink = LogSink::new(|stream, line| match stream {
+            LogStream::Stdout => println!("{} {}", "stdout".dimmed(), line),
+            LogStream::Stderr => eprintln!("{} {}", "stderr".yellow(), line),
+        });
+        runtime.execute_with_logs(function.id, event, sink).await?
     } else {
-        "{}".to_string() // Empty event
+        runtime.execute(function.id, event).await?
     };
     
-    // TODO: Implement actual function execution
-    println!("{} Function execution not yet implemented", "Warning".yellow());
-    println!("Event data: {}", event_data);
+    print_response(&response, follow_logs)?;
+    if !response.success {
+        std::process::exit(1);
+    }
     
     Ok(())
 }
 
-async fn list_command() -> Result<()> {
-    println!("{} Deployed functions:", "Listing".blue().bold());
+/// Find a function by id, or by name when no id is given
+fn resolve_function(runtime: &Runtime, function_id: Option<&str>, name: Option<&str>) -> Result<FunctionMetadata> {
+    let functions = runtime.list_functions();
+    if let Some(id) = function_id {
+        return functions.into_iter()
+            .find(|f| f.id.to_string() == id)
+            .ok_or_else(|| anyhow::anyhow!("Function not found: {}", id));
+    }
     
-    // TODO: Implement function listing
-    println!("{} Function listing not yet implemented", "Warning".yellow());
+    let name = name.unwrap_or_default();
+    let mut matches: Vec<FunctionMetadata> = functions.into_iter().filter(|f| f.name == name).collect();
+    match matches.len() {
+        0 => Err(anyhow::anyhow!("No function named '{}'", name)),
+        1 => Ok(matches.remove(0)),
+        _ => Err(anyhow::anyhow!(
+            "Several functions are named '{}'; pass --function-id with one of: {}",
+            name,
+            matches.iter().map(|f| f.id.to_string()).collect::<Vec<_>>().join(", ")
+        )),
+    }
+}
+
+/// Parse event data, reporting where invalid JSON went wrong
+fn parse_event(json: &str, source: &str) -> Result<Event> {
+    let data: serde_json::Value = serde_json::from_str(json).map_err(|e| {
+        anyhow::anyhow!("Invalid event JSON in {} at line {}, column {}: {}", source, e.line(), e.column(), e)
+    })?;
+    Ok(Event::manual(data))
+}
+
+fn print_response(response: &Response, follow_logs: bool) -> Result<()> {
+    if response.success {
+        println!("{} {}", "Success".green().bold(), response.message);
+    } else {
+        println!("{} {}", "Failed".red().bold(), response.message);
+        if let Some(error) = response.error.as_deref().filter(|e| !e.trim().is_empty()) {
+            println!("  {}", error.trim());
+        }
+    }
+    println!("Duration: {}ms", response.execution_time_ms);
+    
+    // Streamed output has already been shown line by line
+    if !follow_logs {
+        println!("Output: {}", serde_json::to_string_pretty(&response.data)?);
+    }
+    Ok(())
+}
+
+async fn list_command(store: &Path) -> Result<()> {
+    let runtime = Runtime::with_persistence(store)?;
+    let functions = runtime.list_functions();
+    if functions.is_empty() {
+        println!("{} No functions deployed", "Listing".blue().bold());
+        return Ok(());
+    }
+    
+    println!("{} Deployed functions:", "Listing".blue().bold());
+    let rows: Vec<Vec<String>> = functions.iter()
+        .map(|f| {
+            let runtime_type = runtime.get_function(f.id)
+                .map(|d| format!("{:?}", d.runtime_type).to_lowercase())
+                .unwrap_or_default();
+            // Functions deployed from plain code have no provenance
+            let (source_hash, compiler) = match &f.provenance {
+                Some(p) => (p.source_hash.chars().take(12).collect(), p.compiler_version.clone()),
+                None => ("-".to_string(), "-".to_string()),
+            };
+            vec![
+                f.id.to_string(),
+                f.name.clone(),
+                f.language.clone(),
+                runtime_type,
+                source_hash,
+                compiler,
+                f.created_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+            ]
+        })
+        .collect();
+    print!("{}", render_table(&["ID", "NAME", "LANGUAGE", "RUNTIME", "SOURCE HASH", "COMPILER", "DEPLOYED AT"], &rows));
     
     Ok(())
 }
 
+/// Left-aligned columns separated by two spaces
+fn render_table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.len()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    
+    let format_row = |cells: Vec<String>| {
+        let line = cells.iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        format!("{}\n", line.trim_end())
+    };
+    
+    let header = format_row(headers.iter().map(|h| h.to_string()).collect());
+    let mut table = format!("{}\n", header.trim_end().bold());
+    for row in rows {
+        table.push_str(&format_row(row.clone()));
+    }
+    table
+}
+
 fn parse_log_level(level: &str) -> Result<tracing::Level> {
     match level.to_lowercase().as_str() {
         "trace" => Ok(tracing::Level::TRACE),
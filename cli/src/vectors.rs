@@ -0,0 +1,363 @@
+//! `talkpprun vectors`: back up, restore and migrate Qdrant collections while the service
+//! keeps running, assign documents from before tenants existed to a tenant, and measure
+//! how well collections serve retrieval.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use clap::{Args, Subcommand, ValueEnum};
+use colored::*;
+use indicatif::{ProgressBar, ProgressStyle};
+use std::path::PathBuf;
+use std::sync::Arc;
+use talkpp_tenancy::{TenantContext, DEFAULT_TENANT};
+use talkpp_vector_db::{
+    backfill_tenant, evaluate, ArchiveReport, BackupKind, CollectionParams, DistanceMetric, EmbeddingModel, EvalDataset,
+    FastEmbedModel, MigrationTarget, QdrantVectorDb, RagSystem, ResilienceConfig, RetrievalConfig,
+    SharedEmbeddingModel, VectorDbConfig,
+};
+
+#[derive(Args)]
+pub struct VectorsArgs {
+    /// Qdrant gRPC endpoint
+    #[arg(long, global = true, env = "QDRANT_URL", default_value = "http://localhost:6334")]
+    qdrant_url: String,
+
+    /// Qdrant REST endpoint; without it, backups export points instead of taking a snapshot
+    #[arg(long, global = true, env = "QDRANT_REST_URL")]
+    qdrant_rest_url: Option<String>,
+
+    #[arg(long, global = true, env = "QDRANT_API_KEY", hide_env_values = true)]
+    api_key: Option<String>,
+
+    /// Vector size a restored collection is expected to have
+    #[arg(long, global = true, default_value = "384")]
+    vector_size: u64,
+
+    /// Distance metric a restored collection is expected to use, and a migrated one is created with
+    #[arg(long, global = true, value_enum, default_value = "cosine")]
+    distance: Distance,
+
+    #[command(subcommand)]
+    command: VectorsCommand,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Distance {
+    Cosine,
+    Euclidean,
+    Dot,
+}
+
+#[derive(Subcommand)]
+enum VectorsCommand {
+    /// Back up a collection to an archive
+    Backup {
+        /// Collection name
+        collection: String,
+
+        /// Archive to write
+        dest: PathBuf,
+    },
+
+    /// Restore a collection from an archive, creating it if missing
+    Restore {
+        /// Collection name
+        collection: String,
+
+        /// Archive to read
+        src: PathBuf,
+    },
+
+    /// Copy a collection into a new one, re-embedding every document with another model
+    Migrate {
+        /// Collection to read
+        source: String,
+
+        /// Collection to create for the new vectors
+        dest: String,
+
+        /// Ollama embedding model to re-embed with, e.g. nomic-embed-text; the built-in
+        /// FastEmbed model is used without it
+        #[arg(long, requires = "dimension")]
+        ollama_model: Option<String>,
+
+        /// Length of the vectors the Ollama model produces
+        #[arg(long)]
+        dimension: Option<usize>,
+
+        #[arg(long, env = "OLLAMA_URL", default_value = "http://localhost:11434")]
+        ollama_url: String,
+
+        /// Alias to point at the new collection once it is verified
+        #[arg(long)]
+        alias: Option<String>,
+
+        /// Points re-embedded and written per batch
+        #[arg(long, default_value = "64")]
+        batch_size: u32,
+
+        /// Share of sampled documents that must be found by their own content
+        #[arg(long, default_value = "0.8")]
+        min_recall: f32,
+    },
+
+    /// Assign documents stored before tenants existed to a tenant, keeping their vectors
+    BackfillTenant {
+        /// Collection name
+        collection: String,
+
+        /// Tenant to assign them to
+        #[arg(long, default_value = DEFAULT_TENANT)]
+        tenant: String,
+    },
+
+    /// Score dense, hybrid and MMR retrieval from a collection against labeled queries
+    Eval {
+        /// Collection to search
+        collection: String,
+
+        /// JSONL of queries, each with relevant_document_ids or relevant_text_spans
+        #[arg(long)]
+        dataset: PathBuf,
+
+        /// Results scored per query
+        #[arg(long, default_value = "5")]
+        k: usize,
+
+        /// Write the full report, with per-query scores, as JSON here
+        #[arg(long)]
+        report: Option<PathBuf>,
+
+        /// Tenant whose documents are searched
+        #[arg(long, default_value = DEFAULT_TENANT)]
+        tenant: String,
+
+        /// Ollama embedding model the collection was embedded with; the built-in FastEmbed
+        /// model is used without it
+        #[arg(long, requires = "dimension")]
+        ollama_model: Option<String>,
+
+        /// Length of the vectors the Ollama model produces
+        #[arg(long)]
+        dimension: Option<usize>,
+
+        #[arg(long, env = "OLLAMA_URL", default_value = "http://localhost:11434")]
+        ollama_url: String,
+    },
+}
+
+pub async fn vectors_command(args: VectorsArgs) -> Result<()> {
+    let collection = match &args.command {
+        VectorsCommand::Backup { collection, .. } | VectorsCommand::Restore { collection, .. } => collection.clone(),
+        VectorsCommand::Migrate { source, .. } => source.clone(),
+        VectorsCommand::BackfillTenant { collection, .. } | VectorsCommand::Eval { collection, .. } => collection.clone(),
+    };
+    let distance = match args.distance {
+        Distance::Cosine => DistanceMetric::Cosine,
+        Distance::Euclidean => DistanceMetric::Euclidean,
+        Distance::Dot => DistanceMetric::Dot,
+    };
+    let config = VectorDbConfig {
+        qdrant_url: args.qdrant_url,
+        qdrant_api_key: args.api_key,
+        qdrant_rest_url: args.qdrant_rest_url,
+        collection_name: collection,
+        vector_size: args.vector_size,
+        distance_metric: distance,
+        embedding_model: NoEmbeddings::MODEL_ID.to_string(),
+        // One command at a time needs no more than one connection
+        resilience: ResilienceConfig { pool_size: 1, ..Default::default() },
+    };
+    let model = Arc::new(NoEmbeddings { dimension: args.vector_size as usize });
+    let db = QdrantVectorDb::connect_unverified(config.clone(), model)?;
+
+    match args.command {
+        VectorsCommand::Backup { collection, dest } => {
+            let report = db.backup_collection(&collection, &dest).await?;
+            print_report("Backed up", &collection, &report);
+            println!("Archive written to {}", dest.display());
+        }
+        VectorsCommand::Restore { collection, src } => {
+            let bar = ProgressBar::new(0).with_style(
+                ProgressStyle::with_template("{bar:40} {pos}/{len} points ({eta} left)")?,
+            );
+            let report = db.restore_collection_with_progress(&collection, &src, |progress| {
+                bar.set_length(progress.points_total);
+                bar.set_position(progress.points_done);
+            }).await?;
+            bar.finish_and_clear();
+            print_report("Restored", &collection, &report);
+        }
+        VectorsCommand::Migrate { source, dest, ollama_model, dimension, ollama_url, alias, batch_size, min_recall } => {
+            let re_embedder = embedding_model(ollama_model, dimension, ollama_url).await?;
+            let params = CollectionParams { vector_size: re_embedder.dimension() as u64, distance };
+            let mut target = MigrationTarget::new(&dest, params);
+            target.min_recall = min_recall;
+            if let Some(alias) = alias {
+                target = target.with_alias(alias);
+            }
+
+            let bar = ProgressBar::new_spinner().with_style(ProgressStyle::with_template("{spinner} {msg}")?);
+            let report = db.migrate_collection(&source, &target, re_embedder.as_ref(), batch_size, |progress| {
+                bar.set_message(format!(
+                    "{} points read, {} migrated, {} skipped ({:.0} points/s)",
+                    progress.points_scanned,
+                    progress.migrated,
+                    progress.skipped,
+                    progress.points_per_second()
+                ));
+                bar.tick();
+            }).await?;
+            bar.finish_and_clear();
+
+            println!(
+                "{} {} to {} with {}: {} points in {:.1}s",
+                "Migrated".green().bold(),
+                source,
+                dest,
+                re_embedder.model_id(),
+                report.migrated,
+                report.elapsed.as_secs_f64()
+            );
+            if let Some(recall) = report.recall {
+                println!("Recall on {} sampled documents: {:.0}%", target.recall_samples, recall * 100.0);
+            }
+            if !report.skipped.is_empty() {
+                println!(
+                    "{} {} points without content: {}",
+                    "Skipped".yellow().bold(),
+                    report.skipped.len(),
+                    report.skipped.join(", ")
+                );
+            }
+            match &report.alias {
+                Some(alias) => println!("Alias {} now points at {}", alias, dest),
+                None => println!("Point collection_name at {} to start serving from it", dest),
+            }
+        }
+        VectorsCommand::BackfillTenant { collection, tenant } => {
+            let backfilled = backfill_tenant(&db, &tenant).await?;
+            println!("{} {} documents of {} to tenant '{}'", "Assigned".green().bold(), backfilled, collection, tenant);
+        }
+        VectorsCommand::Eval { collection, dataset, k, report, tenant, ollama_model, dimension, ollama_url } => {
+            let tenant = TenantContext::new(tenant, "talkpprun")?;
+            let dataset = EvalDataset::load(&dataset)?;
+            let embeddings = embedding_model(ollama_model, dimension, ollama_url).await?;
+            let config = VectorDbConfig { vector_size: embeddings.dimension() as u64, ..config };
+            let rag = RagSystem::verified(Box::new(QdrantVectorDb::connect_unverified(config, embeddings)?)).await?;
+
+            let results = evaluate(&rag, &tenant, &dataset, &RetrievalConfig::defaults(k)).await?;
+            println!("{} {} queries against {}\n", "Evaluated".green().bold(), results.dataset_queries, collection);
+            print!("{}", results.to_table());
+            if let Some(path) = report {
+                std::fs::write(&path, results.to_json())?;
+                println!("\nReport written to {}", path.display());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The Ollama model named, or the built-in FastEmbed model without one
+async fn embedding_model(
+    ollama_model: Option<String>,
+    dimension: Option<usize>,
+    ollama_url: String,
+) -> Result<SharedEmbeddingModel> {
+    Ok(match (ollama_model, dimension) {
+        (Some(model), Some(dimension)) => Arc::new(OllamaEmbeddings::new(ollama_url, model, dimension)),
+        _ => Arc::new(FastEmbedModel::new().await?),
+    })
+}
+
+fn print_report(verb: &str, collection: &str, report: &ArchiveReport) {
+    match report.kind {
+        BackupKind::Snapshot => println!("{} {} from a server snapshot", verb.green().bold(), collection),
+        BackupKind::Scroll => println!(
+            "{} {}: {} points in {} chunks",
+            verb.green().bold(),
+            collection,
+            report.points,
+            report.chunks
+        ),
+    }
+}
+
+/// An embedding model served by Ollama, which migrations re-embed documents with and
+/// evaluations embed queries with
+struct OllamaEmbeddings {
+    client: reqwest::Client,
+    url: String,
+    model: String,
+    dimension: usize,
+}
+
+impl OllamaEmbeddings {
+    fn new(url: String, model: String, dimension: usize) -> Self {
+        Self { client: reqwest::Client::new(), url, model, dimension }
+    }
+}
+
+#[async_trait]
+impl EmbeddingModel for OllamaEmbeddings {
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        #[derive(serde::Deserialize)]
+        struct Embedding {
+            embedding: Vec<f32>,
+        }
+
+        let response: Embedding = self.client
+            .post(format!("{}/api/embeddings", self.url.trim_end_matches('/')))
+            .json(&serde_json::json!({ "model": self.model, "prompt": text }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response.embedding)
+    }
+
+    async fn embed_batch(&self, texts: Vec<&str>) -> Result<Vec<Vec<f32>>> {
+        let mut vectors = Vec::with_capacity(texts.len());
+        for text in texts {
+            vectors.push(self.embed(text).await?);
+        }
+        Ok(vectors)
+    }
+
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    fn model_id(&self) -> &str {
+        &self.model
+    }
+}
+
+/// Backups move stored vectors as they are, so no embedding model is loaded
+struct NoEmbeddings {
+    dimension: usize,
+}
+
+impl NoEmbeddings {
+    const MODEL_ID: &'static str = "talkpprun/no-embeddings";
+}
+
+#[async_trait]
+impl EmbeddingModel for NoEmbeddings {
+    async fn embed(&self, _text: &str) -> Result<Vec<f32>> {
+        anyhow::bail!("talkpprun vectors does not embed text")
+    }
+
+    async fn embed_batch(&self, _texts: Vec<&str>) -> Result<Vec<Vec<f32>>> {
+        anyhow::bail!("talkpprun vectors does not embed text")
+    }
+
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    fn model_id(&self) -> &str {
+        Self::MODEL_ID
+    }
+}
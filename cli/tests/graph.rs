@@ -0,0 +1,76 @@
+//! End-to-end tests for `talkpprun memory graph` and `talkpprun plan graph` against a
+//! canned api-server on a loopback port
+
+use assert_cmd::Command;
+use predicates::prelude::*;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// Answer every request with `status` and `body`, returning the server's URL and the
+/// request lines it received
+async fn api_server(status: &'static str, body: &'static str) -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let seen = requests.clone();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let mut buffer = vec![0; 8192];
+            let read = stream.read(&mut buffer).await.unwrap();
+            let request = String::from_utf8_lossy(&buffer[..read]).to_string();
+            seen.lock().unwrap().push(request.lines().next().unwrap_or_default().to_string());
+            let response = format!(
+                "HTTP/1.1 {}\r\ncontent-type: text/plain\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body,
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        }
+    });
+    (url, requests)
+}
+
+fn talkpprun(url: &str) -> Command {
+    let mut command = Command::cargo_bin("talkpprun").unwrap();
+    command.env("NO_COLOR", "1").env("TALKPP_API_URL", url).env_remove("TALKPP_API_TOKEN");
+    command
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_graphs_are_fetched_with_their_filters() {
+    let dot = "digraph \"plan\" {\n  \"a\" -> \"b\";\n}\n";
+    let (url, requests) = api_server("200 OK", dot).await;
+    let plan_id = "6f1c2e4a-8d3b-4c5e-9f7a-1b2c3d4e5f60";
+
+    talkpprun(&url).args(["plan", "graph", "--id", plan_id]).assert().success().stdout(dot);
+    let around = "0d9e8f7a-6b5c-4d3e-8f1a-2b3c4d5e6f70";
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("memory.json");
+    talkpprun(&url)
+        .args(["memory", "graph", "--tag", "team", "--around", around, "--depth", "2", "--format", "json", "--max-nodes", "50", "-o"])
+        .arg(&output)
+        .assert()
+        .success()
+        .stdout("");
+    assert_eq!(std::fs::read_to_string(&output).unwrap(), dot);
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests[0], format!("GET /api/v1/plans/{}/graph?format=dot HTTP/1.1", plan_id));
+    assert_eq!(
+        requests[1],
+        format!("GET /api/v1/memory/graph?tag=team&around={}&depth=2&format=json&max_nodes=50 HTTP/1.1", around),
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_server_errors_are_reported() {
+    let (url, _) = api_server("404 Not Found", r#"{"error": "Not found: Plan 6f1c2e4a"}"#).await;
+    talkpprun(&url)
+        .args(["plan", "graph", "--id", "6f1c2e4a-8d3b-4c5e-9f7a-1b2c3d4e5f60"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Not found: Plan 6f1c2e4a"));
+
+    talkpprun(&url).args(["plan", "graph", "--id", "not-a-uuid"]).assert().failure();
+}
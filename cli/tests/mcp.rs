@@ -0,0 +1,145 @@
+//! End-to-end tests for `talkpprun mcp` against a `LocalMcpServer` hosted by the test
+//! process on a loopback port
+
+use assert_cmd::Command;
+use predicates::prelude::*;
+use serde_json::json;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use talkpp_mcp_hub::LocalMcpServer;
+
+/// Serve a calculator with an `add` tool and an always-failing `divide_by_zero` tool,
+/// returning its URL and a count of calls that reached `add`
+async fn calculator() -> (String, Arc<AtomicUsize>) {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    let server = LocalMcpServer::new("calculator")
+        .with_tool(
+            "add",
+            "Add two numbers",
+            json!({
+                "type": "object",
+                "properties": {"a": {"type": "number"}, "b": {"type": "number"}},
+                "required": ["a", "b"]
+            }),
+            move |params| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                async move { Ok(json!({"sum": params["a"].as_f64().unwrap() + params["b"].as_f64().unwrap()})) }
+            },
+        )
+        .with_tool("divide_by_zero", "Always fails", json!({"type": "object"}), |_| async {
+            Err(anyhow::anyhow!("division by zero"))
+        });
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    tokio::spawn(server.serve(listener));
+    (url, calls)
+}
+
+fn talkpprun(registry: &Path) -> Command {
+    let mut command = Command::cargo_bin("talkpprun").unwrap();
+    command.env("NO_COLOR", "1").env("TALKPP_MCP_REGISTRY", registry).env_remove("TALKPP_API_URL");
+    command
+}
+
+fn add_calculator(registry: &Path, url: &str) {
+    talkpprun(registry)
+        .args(["mcp", "add", "--name", "calc", "--transport", "http", "--url", url])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Registered MCP server calc"))
+        .stdout(predicate::str::contains("2 tools available"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_add_list_and_tools() {
+    let (url, _) = calculator().await;
+    let dir = tempfile::tempdir().unwrap();
+    let registry = dir.path().join("mcp.json");
+
+    talkpprun(&registry).args(["mcp", "list"]).assert().success().stdout(predicate::str::contains("No MCP servers registered"));
+    add_calculator(&registry, &url);
+    talkpprun(&registry)
+        .args(["mcp", "add", "--name", "calc", "--transport", "http", "--url", &url])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("already registered"));
+    talkpprun(&registry)
+        .args(["mcp", "add", "--name", "files", "--transport", "ws", "--url", "ws://127.0.0.1:9/"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Could not connect yet"));
+
+    talkpprun(&registry)
+        .args(["mcp", "list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(r"NAME\s+TRANSPORT\s+STATUS\s+TOOLS\s+ID").unwrap())
+        .stdout(predicate::str::is_match(r"calc\s+http\s+connected\s+2\s").unwrap())
+        .stdout(predicate::str::is_match(r"files\s+ws\s+disconnected\s+0\s").unwrap())
+        .stdout(predicate::str::contains("Warning files: WebSocket MCP transport is not supported yet"));
+
+    talkpprun(&registry)
+        .args(["mcp", "tools", "calc"])
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(r"add\s+Add two numbers").unwrap())
+        .stdout(predicate::str::is_match(r"divide_by_zero\s+Always fails").unwrap());
+    talkpprun(&registry)
+        .args(["mcp", "tools", "nope"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Server not found: nope"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_call_validates_params_before_sending() {
+    let (url, calls) = calculator().await;
+    let dir = tempfile::tempdir().unwrap();
+    let registry = dir.path().join("mcp.json");
+    add_calculator(&registry, &url);
+
+    talkpprun(&registry)
+        .args(["mcp", "call", "add", "--params", r#"{"a": 2, "b": 3}"#])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""sum": 5.0"#));
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    talkpprun(&registry)
+        .args(["mcp", "call", "add", "--params", r#"{"a": "2"}"#])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("Invalid params for add"))
+        .stderr(predicate::str::contains("/a: expected number, found string"))
+        .stderr(predicate::str::contains("/: missing required property 'b'"));
+    assert_eq!(calls.load(Ordering::SeqCst), 1, "invalid params must not reach the server");
+
+    talkpprun(&registry)
+        .args(["mcp", "call", "add", "--params", "{a: 2}"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid --params JSON at line 1, column 2"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_call_reports_tool_errors() {
+    let (url, _) = calculator().await;
+    let dir = tempfile::tempdir().unwrap();
+    let registry = dir.path().join("mcp.json");
+    add_calculator(&registry, &url);
+
+    talkpprun(&registry)
+        .args(["mcp", "call", "divide_by_zero"])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("Error divide_by_zero failed"))
+        .stderr(predicate::str::contains("division by zero"));
+    talkpprun(&registry)
+        .args(["mcp", "call", "multiply"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Tool not found: multiply"));
+}
@@ -0,0 +1,36 @@
+//! End-to-end tests for `talkpprun models` against a model cache in a temporary directory
+
+use assert_cmd::Command;
+use predicates::prelude::*;
+use std::path::Path;
+
+fn talkpprun(cache: &Path) -> Command {
+    let mut command = Command::cargo_bin("talkpprun").unwrap();
+    command.env("NO_COLOR", "1").env("TALKPP_MODEL_CACHE", cache).env("HF_HUB_OFFLINE", "1");
+    command
+}
+
+#[test]
+fn test_models_in_an_empty_cache() {
+    let cache = tempfile::tempdir().unwrap();
+
+    talkpprun(cache.path())
+        .args(["models", "list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No models cached"));
+
+    // Offline, a model that isn't cached fails at once and says where it was looked for
+    talkpprun(cache.path())
+        .args(["models", "pull", "acme/tiny-llama"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("offline mode"))
+        .stderr(predicate::str::contains(cache.path().join("acme--tiny-llama").display().to_string()));
+
+    talkpprun(cache.path())
+        .args(["models", "rm", "acme/tiny-llama"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Model 'acme/tiny-llama' is not cached"));
+}
@@ -0,0 +1,169 @@
+//! End-to-end tests for `talkppc compile` over the programs in `tests/fixtures`
+
+use assert_cmd::Command;
+use predicates::prelude::*;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::mpsc;
+use std::time::Duration;
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)
+}
+
+fn talkppc() -> Command {
+    let mut command = Command::cargo_bin("talkppc").unwrap();
+    command.env("NO_COLOR", "1");
+    command
+}
+
+#[test]
+fn test_compile_fixture_for_each_target() {
+    let out = tempfile::tempdir().unwrap();
+    let targets = [
+        ("rust", "welcome.rs", "async fn handler"),
+        ("python", "welcome.py", "def handler"),
+        ("js", "welcome.js", "handler"),
+        ("ts", "welcome.ts", "handler"),
+        ("bash", "welcome.sh", "#!/bin/bash"),
+        ("go", "welcome.go", "package main"),
+    ];
+
+    for (target, file, marker) in targets {
+        talkppc()
+            .args(["compile", "--target", target, "--out"])
+            .arg(out.path())
+            .arg(fixture("welcome.talk"))
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("ok"))
+            .stdout(predicate::str::contains(file));
+
+        let code = std::fs::read_to_string(out.path().join(file)).unwrap();
+        assert!(code.contains(marker), "{} output is missing {:?}:\n{}", target, marker, code);
+    }
+}
+
+#[test]
+fn test_release_writes_to_explicit_file() {
+    let out = tempfile::tempdir().unwrap();
+    let path = out.path().join("nested/handler.rs");
+
+    talkppc()
+        .args(["compile", "--release", "--out"])
+        .arg(&path)
+        .arg(fixture("welcome.talk"))
+        .assert()
+        .success();
+
+    let code = std::fs::read_to_string(&path).unwrap();
+    assert!(!code.contains("fn main"), "release build kept the debug entry point:\n{}", code);
+
+    talkppc()
+        .args(["compile", "--debug", "--release"])
+        .arg(fixture("welcome.talk"))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn test_recompiling_reuses_cached_statements_unless_disabled() {
+    let out = tempfile::tempdir().unwrap();
+    let cache = tempfile::tempdir().unwrap();
+    let compile = |extra: &[&str]| {
+        talkppc()
+            .args(["compile", "--cache-dir"])
+            .arg(cache.path())
+            .args(extra)
+            .arg("--out")
+            .arg(out.path())
+            .arg(fixture("welcome.talk"))
+            .assert()
+            .success()
+    };
+
+    compile(&[]).stdout(predicate::str::contains("(0/1 statements cached"));
+    compile(&[]).stdout(predicate::str::contains("(1/1 statements cached"));
+    compile(&["--no-cache"]).stdout(predicate::str::contains("cached").not());
+}
+
+#[test]
+fn test_exit_codes_distinguish_failures() {
+    let out = tempfile::tempdir().unwrap();
+
+    talkppc()
+        .args(["compile", "--out"])
+        .arg(out.path())
+        .arg(fixture("parse_error.talk"))
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("error:"))
+        .stderr(predicate::str::contains("--> line 2"));
+
+    talkppc()
+        .args(["compile", "--out"])
+        .arg(out.path())
+        .arg(fixture("reserved_name.talk"))
+        .assert()
+        .code(3)
+        .stderr(predicate::str::contains("type"));
+
+    talkppc()
+        .args(["compile", "--out"])
+        .arg(out.path())
+        .arg(fixture("missing.talk"))
+        .assert()
+        .code(4);
+
+    assert_eq!(std::fs::read_dir(out.path()).unwrap().count(), 0);
+}
+
+fn forward(stream: impl std::io::Read, lines: mpsc::Sender<String>) {
+    for line in BufReader::new(stream).lines().map_while(Result::ok) {
+        let _ = lines.send(line);
+    }
+}
+
+#[test]
+fn test_watch_recompiles_on_change() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("watched.talk");
+    std::fs::copy(fixture("welcome.talk"), &input).unwrap();
+
+    let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("talkppc"))
+        .args(["compile", "--watch", "--debounce-ms", "50"])
+        .arg(&input)
+        .env("NO_COLOR", "1")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // Merge both streams so each rebuild shows up as one line, whichever it went to
+    let (lines, received) = mpsc::channel();
+    let stdout = child.stdout.take().unwrap();
+    let stderr = child.stderr.take().unwrap();
+    let out_lines = lines.clone();
+    std::thread::spawn(move || forward(stdout, out_lines));
+    std::thread::spawn(move || forward(stderr, lines));
+
+    let wait_for = |prefix: &str| loop {
+        let line = received.recv_timeout(Duration::from_secs(10)).expect("watcher stopped reporting");
+        if line.starts_with(prefix) {
+            return line;
+        }
+    };
+
+    assert!(wait_for("ok ").ends_with("ms)"));
+
+    std::fs::write(&input, std::fs::read(fixture("parse_error.talk")).unwrap()).unwrap();
+    assert!(wait_for("error: ").contains("watched.talk"));
+
+    std::fs::write(&input, std::fs::read(fixture("welcome.talk")).unwrap()).unwrap();
+    assert!(wait_for("ok ").contains("watched.rs"));
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
@@ -0,0 +1,129 @@
+//! End-to-end tests for `talkpprun execute` and `talkpprun list` against a runtime store
+//! populated in-process
+
+use assert_cmd::Command;
+use predicates::prelude::*;
+use std::path::Path;
+use talkpp_runtime::{FunctionMetadata, Runtime};
+
+/// Deploy bash functions into a fresh store, closing it before the CLI opens it
+async fn store_with(functions: &[(&str, &str)]) -> (tempfile::TempDir, Vec<String>) {
+    let dir = tempfile::tempdir().unwrap();
+    let runtime = Runtime::with_persistence(dir.path().join("functions")).unwrap();
+    let mut ids = Vec::new();
+    for (name, code) in functions {
+        ids.push(runtime.deploy(code, FunctionMetadata::new(*name, "bash")).await.unwrap().to_string());
+    }
+    (dir, ids)
+}
+
+fn talkpprun(store: &Path) -> Command {
+    let mut command = Command::cargo_bin("talkpprun").unwrap();
+    command.env("NO_COLOR", "1").env("TALKPP_STORE", store.join("functions"));
+    command
+}
+
+#[tokio::test]
+async fn test_list_renders_deployed_functions() {
+    let (dir, ids) = store_with(&[("greet", "echo hi"), ("farewell", "echo bye")]).await;
+
+    talkpprun(dir.path())
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ID"))
+        .stdout(predicate::str::contains("DEPLOYED AT"))
+        .stdout(predicate::str::is_match(format!(r"{}\s+greet\s+bash\s+process\s+-\s+-\s+\d{{4}}-", ids[0])).unwrap())
+        .stdout(predicate::str::contains(ids[1].as_str()));
+
+    let empty = tempfile::tempdir().unwrap();
+    talkpprun(empty.path()).arg("list").assert().success().stdout(predicate::str::contains("No functions deployed"));
+}
+
+#[tokio::test]
+async fn test_list_shows_provenance_of_compiled_functions() {
+    let dir = tempfile::tempdir().unwrap();
+    let artifact = talkpp_compiler::Compiler::with_config(talkpp_compiler::CompilerConfig {
+        target_language: talkpp_compiler::TargetLanguage::Bash,
+        ..talkpp_compiler::CompilerConfig::default()
+    }).compile("send welcome message").unwrap();
+    let runtime = Runtime::with_persistence(dir.path().join("functions")).unwrap();
+    runtime.deploy_artifact(&artifact, FunctionMetadata::new("welcome", "")).await.unwrap();
+    drop(runtime);
+
+    talkpprun(dir.path())
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("SOURCE HASH"))
+        .stdout(predicate::str::is_match(format!(
+            r"welcome\s+bash\s+process\s+{}\s+{}\s",
+            &artifact.provenance.source_hash[..12],
+            talkpp_compiler::COMPILER_VERSION.replace('.', r"\."),
+        )).unwrap());
+}
+
+#[tokio::test]
+async fn test_execute_by_name_and_id() {
+    let (dir, ids) = store_with(&[("echo-event", r#"echo "$TALKPP_EVENT""#)]).await;
+
+    talkpprun(dir.path())
+        .args(["execute", "--name", "echo-event", "--event", r#"{"name": "talk"}"#])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Success"))
+        .stdout(predicate::str::contains("Duration:"))
+        .stdout(predicate::str::contains(r#""name": "talk""#));
+
+    let event_file = dir.path().join("event.json");
+    std::fs::write(&event_file, r#"{"count": 3}"#).unwrap();
+    talkpprun(dir.path())
+        .args(["execute", "--function-id", &ids[0], "--event-file"])
+        .arg(&event_file)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""count": 3"#));
+}
+
+#[tokio::test]
+async fn test_execute_reports_bad_input_and_failures() {
+    let (dir, _) = store_with(&[("broken", "echo 'went wrong' >&2; exit 3"), ("twin", "echo a"), ("twin", "echo b")]).await;
+
+    talkpprun(dir.path())
+        .args(["execute", "--name", "broken", "--event", "{\n  \"name\": }"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid event JSON in --event at line 2, column 11"));
+
+    talkpprun(dir.path())
+        .args(["execute", "--name", "broken"])
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("Failed"))
+        .stdout(predicate::str::contains("went wrong"));
+
+    talkpprun(dir.path())
+        .args(["execute", "--name", "twin"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Several functions are named 'twin'"));
+
+    talkpprun(dir.path())
+        .args(["execute", "--name", "missing"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("No function named 'missing'"));
+}
+
+#[tokio::test]
+async fn test_execute_follow_logs_streams_output() {
+    let (dir, _) = store_with(&[("chatty", "echo starting; echo careful >&2; echo done")]).await;
+
+    talkpprun(dir.path())
+        .args(["execute", "--name", "chatty", "--follow-logs"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("stdout starting\nstdout done\n"))
+        .stderr(predicate::str::contains("stderr careful"))
+        .stdout(predicate::str::contains("Output:").not());
+}
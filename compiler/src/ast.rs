@@ -2,6 +2,17 @@
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
+
+/// Source region covered by a node. Lines and columns are 1-based and `end_col` is
+/// exclusive, so a single-character token at column 5 spans columns 5..6.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+    pub start_line: usize,
+    pub start_col: usize,
+    pub end_line: usize,
+    pub end_col: usize,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Program {
@@ -19,8 +30,10 @@ pub enum Statement {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConditionalStatement {
     pub condition: Condition,
-    pub then_actions: Vec<ActionStatement>,
-    pub else_actions: Option<Vec<ActionStatement>>,
+    pub then_body: Vec<Statement>,
+    pub else_body: Option<Vec<Statement>>,
+    #[serde(default)]
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -35,6 +48,8 @@ pub struct EventCondition {
     pub subject: String,
     pub action: String,
     pub context: Option<String>,
+    #[serde(default)]
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -42,6 +57,8 @@ pub struct ComparisonCondition {
     pub left: Expression,
     pub operator: ComparisonOperator,
     pub right: Expression,
+    #[serde(default)]
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -49,6 +66,8 @@ pub struct LogicalCondition {
     pub left: Box<Condition>,
     pub operator: LogicalOperator,
     pub right: Box<Condition>,
+    #[serde(default)]
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -73,6 +92,8 @@ pub struct ActionStatement {
     pub target: Option<Expression>,
     pub service: Option<ServiceCall>,
     pub parameters: HashMap<String, Expression>,
+    #[serde(default)]
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -91,12 +112,16 @@ pub struct ServiceCall {
     pub name: String,
     pub method: Option<String>,
     pub config: HashMap<String, Expression>,
+    #[serde(default)]
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AssignmentStatement {
     pub variable: String,
     pub value: Expression,
+    #[serde(default)]
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -114,12 +139,147 @@ pub enum Expression {
 pub struct PropertyAccess {
     pub object: Box<Expression>,
     pub property: String,
+    #[serde(default)]
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FunctionCall {
     pub name: String,
     pub arguments: Vec<Expression>,
+    #[serde(default)]
+    pub span: Span,
+}
+
+impl Span {
+    pub fn new(start_line: usize, start_col: usize, end_line: usize, end_col: usize) -> Self {
+        Self {
+            start_line,
+            start_col,
+            end_line,
+            end_col,
+        }
+    }
+
+    /// Span from the start of `self` to the end of `other`
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            end_line: other.end_line,
+            end_col: other.end_col,
+            ..self
+        }
+    }
+}
+
+/// `with_span` builders, so nodes can be constructed first and located afterwards
+macro_rules! impl_with_span {
+    ($($node:ty),* $(,)?) => {
+        $(
+            impl $node {
+                pub fn with_span(mut self, span: Span) -> Self {
+                    self.span = span;
+                    self
+                }
+            }
+        )*
+    };
+}
+
+impl_with_span!(
+    ConditionalStatement,
+    EventCondition,
+    ComparisonCondition,
+    LogicalCondition,
+    ActionStatement,
+    ServiceCall,
+    AssignmentStatement,
+    PropertyAccess,
+    FunctionCall,
+);
+
+impl Statement {
+    /// Source span of the statement; comments are not located
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Statement::Conditional(cond) => Some(cond.span),
+            Statement::Action(action) => Some(action.span),
+            Statement::Assignment(assign) => Some(assign.span),
+            Statement::Comment(_) => None,
+        }
+    }
+
+    /// One-line source-like rendering, leaving out nested bodies and parameters
+    pub fn summary(&self) -> String {
+        match self {
+            Statement::Conditional(cond) => format!("if {}", cond.condition),
+            Statement::Action(action) => {
+                let mut summary = action.action.to_string();
+                if let Some(target) = &action.target {
+                    summary.push_str(&format!(" {}", target));
+                }
+                if let Some(service) = &action.service {
+                    summary.push_str(&format!(" using {}", service.name));
+                }
+                summary
+            }
+            Statement::Assignment(assign) => format!("{}: {}", assign.variable, assign.value),
+            Statement::Comment(comment) => format!("// {}", comment),
+        }
+    }
+}
+
+impl Condition {
+    pub fn span(&self) -> Span {
+        match self {
+            Condition::Event(event) => event.span,
+            Condition::Comparison(comp) => comp.span,
+            Condition::Logical(logical) => logical.span,
+        }
+    }
+}
+
+impl ConditionalStatement {
+    pub fn new(condition: Condition, then_body: Vec<Statement>, else_body: Option<Vec<Statement>>) -> Self {
+        Self {
+            condition,
+            then_body,
+            else_body,
+            span: Span::default(),
+        }
+    }
+}
+
+impl ActionStatement {
+    pub fn new(action: Action) -> Self {
+        Self {
+            action,
+            target: None,
+            service: None,
+            parameters: HashMap::new(),
+            span: Span::default(),
+        }
+    }
+}
+
+impl ServiceCall {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            method: None,
+            config: HashMap::new(),
+            span: Span::default(),
+        }
+    }
+}
+
+impl AssignmentStatement {
+    pub fn new(variable: impl Into<String>, value: Expression) -> Self {
+        Self {
+            variable: variable.into(),
+            value,
+            span: Span::default(),
+        }
+    }
 }
 
 impl Program {
@@ -186,4 +346,50 @@ impl Expression {
     pub fn boolean(value: bool) -> Self {
         Expression::Boolean(value)
     }
-} 
\ No newline at end of file
+} 
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Condition::Event(event) => match &event.context {
+                Some(context) => write!(f, "{} {} in {}", event.subject, event.action, context),
+                None => write!(f, "{} {}", event.subject, event.action),
+            },
+            Condition::Comparison(comp) => {
+                let op = match comp.operator {
+                    ComparisonOperator::Equal => "==",
+                    ComparisonOperator::NotEqual => "!=",
+                    ComparisonOperator::GreaterThan => ">",
+                    ComparisonOperator::LessThan => "<",
+                    ComparisonOperator::GreaterEqual => ">=",
+                    ComparisonOperator::LessEqual => "<=",
+                };
+                write!(f, "{} {} {}", comp.left, op, comp.right)
+            }
+            Condition::Logical(logical) => {
+                let op = match logical.operator {
+                    LogicalOperator::And => "and",
+                    LogicalOperator::Or => "or",
+                };
+                write!(f, "({}) {} ({})", logical.left, op, logical.right)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expression::Identifier(name) => write!(f, "{}", name),
+            Expression::String(value) => write!(f, "\"{}\"", value),
+            Expression::Integer(value) => write!(f, "{}", value),
+            Expression::Float(value) => write!(f, "{}", value),
+            Expression::Boolean(value) => write!(f, "{}", value),
+            Expression::Property(prop) => write!(f, "{}.{}", prop.object, prop.property),
+            Expression::FunctionCall(call) => {
+                let args = call.arguments.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+                write!(f, "{}({})", call.name, args.join(", "))
+            }
+        }
+    }
+}
@@ -1,5 +1,7 @@
 //! Compiler error types and handling
 
+use crate::ast::Span;
+use std::fmt::Write;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -7,18 +9,14 @@ pub enum CompilerError {
     #[error("Lexical error at position {position}: {message}")]
     LexicalError { position: usize, message: String },
 
-    #[error("Parse error at line {line}, column {column}: {message}")]
-    ParseError {
-        line: usize,
-        column: usize,
-        message: String,
-    },
+    #[error("Parse error at line {}, column {}: {message}", .span.start_line, .span.start_col)]
+    ParseError { span: Span, message: String },
 
     #[error("Semantic error: {message}")]
     SemanticError { message: String },
 
     #[error("Code generation error: {message}")]
-    CodeGenError { message: String },
+    CodeGenError { message: String, span: Option<Span> },
 
     #[error("Unsupported feature: {feature}")]
     UnsupportedFeature { feature: String },
@@ -41,10 +39,9 @@ impl CompilerError {
         }
     }
 
-    pub fn parse(line: usize, column: usize, message: impl Into<String>) -> Self {
+    pub fn parse(span: Span, message: impl Into<String>) -> Self {
         Self::ParseError {
-            line,
-            column,
+            span,
             message: message.into(),
         }
     }
@@ -58,6 +55,15 @@ impl CompilerError {
     pub fn codegen(message: impl Into<String>) -> Self {
         Self::CodeGenError {
             message: message.into(),
+            span: None,
+        }
+    }
+
+    /// Code generation error attributed to the node at `span`
+    pub fn codegen_at(span: Span, message: impl Into<String>) -> Self {
+        Self::CodeGenError {
+            message: message.into(),
+            span: Some(span),
         }
     }
 
@@ -72,4 +78,74 @@ impl CompilerError {
             message: message.into(),
         }
     }
-} 
\ No newline at end of file
+
+    /// Source location of the error, when known
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::ParseError { span, .. } => Some(*span),
+            Self::CodeGenError { span, .. } => *span,
+            _ => None,
+        }
+    }
+}
+
+/// Render the source line(s) covered by `span` with a caret underline:
+///
+/// ```text
+///   --> line 1, column 6
+///    |
+///  1 | send to
+///    |      ^^
+/// ```
+///
+/// Spans over several lines underline the first line to its end.
+pub fn render_snippet(source: &str, span: Span) -> String {
+    let mut out = String::new();
+    let gutter = " ".repeat(span.start_line.to_string().len());
+    let Some(line) = source.lines().nth(span.start_line.saturating_sub(1)) else {
+        let _ = writeln!(out, "{}--> line {}, column {}", gutter, span.start_line, span.start_col);
+        return out;
+    };
+
+    let width = line.chars().count();
+    let start = span.start_col.max(1).min(width + 1);
+    let end = if span.end_line == span.start_line { span.end_col.min(width + 1) } else { width + 1 };
+    let carets = end.saturating_sub(start).max(1);
+
+    let _ = writeln!(out, "{}--> line {}, column {}", gutter, span.start_line, span.start_col);
+    let _ = writeln!(out, "{} |", gutter);
+    let _ = writeln!(out, "{} | {}", span.start_line, line);
+    let _ = writeln!(out, "{} | {}{}", gutter, " ".repeat(start - 1), "^".repeat(carets));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_snippet_underlines_span() {
+        let source = "if order placed then\n  send receipt to using SendGrid\nend";
+        let snippet = render_snippet(source, Span::new(2, 3, 2, 18));
+        assert_eq!(
+            snippet,
+            " --> line 2, column 3\n  |\n2 |   send receipt to using SendGrid\n  |   ^^^^^^^^^^^^^^^\n"
+        );
+    }
+
+    #[test]
+    fn test_render_snippet_multi_line_and_out_of_range() {
+        let snippet = render_snippet("store user\nin table", Span::new(1, 7, 2, 9));
+        assert!(snippet.ends_with("1 | store user\n  |       ^^^^\n"));
+
+        assert_eq!(render_snippet("", Span::new(3, 1, 3, 2)), " --> line 3, column 1\n");
+    }
+
+    #[test]
+    fn test_parse_error_display_uses_span() {
+        let error = CompilerError::parse(Span::new(4, 2, 4, 6), "Expected condition");
+        assert_eq!(error.to_string(), "Parse error at line 4, column 2: Expected condition");
+        assert_eq!(error.span(), Some(Span::new(4, 2, 4, 6)));
+        assert_eq!(CompilerError::codegen("boom").span(), None);
+    }
+}
@@ -0,0 +1,205 @@
+//! Incremental compilation
+//!
+//! Generated code is cached on disk per top-level statement, keyed by the statement's
+//! tokens, the compiler settings and the variables in scope before it. Lexing, parsing
+//! and the scope analysis always run over the whole program, so only codegen is reused
+//! and a stale or corrupt cache can cost time but never change the output.
+
+use crate::ast::Program;
+use crate::codegen;
+use crate::error::CompilerError;
+use crate::lexer::TokenWithSpan;
+use crate::{CompilerConfig, InstrumentationMode, COMPILER_VERSION};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// How many statement fragments a compilation reused from the cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheStats {
+    /// Fragments read from the cache
+    pub hits: usize,
+    /// Fragments generated because they weren't cached
+    pub misses: usize,
+}
+
+/// On-disk cache of generated code fragments, one file per fragment
+#[derive(Debug)]
+pub struct FragmentCache {
+    dir: PathBuf,
+    regenerated: AtomicUsize,
+}
+
+impl FragmentCache {
+    /// Cache in `dir`, which is created on first write
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            regenerated: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Fragments generated rather than read from the cache over this cache's lifetime
+    pub fn regenerated(&self) -> usize {
+        self.regenerated.load(Ordering::Relaxed)
+    }
+
+    /// Generate `program`, parsed from `tokens`, reusing cached fragments for statements
+    /// that haven't changed
+    pub fn generate(
+        &self,
+        tokens: &[TokenWithSpan],
+        program: &Program,
+        config: &CompilerConfig,
+    ) -> Result<(String, CacheStats), CompilerError> {
+        let config_hash = config_hash(config);
+        let declared = codegen::declared_variables(program, &config.target_language);
+        let mut stats = CacheStats::default();
+        let mut fragments = Vec::with_capacity(program.statements.len());
+
+        for ((statement, statement_hash), declared) in
+            program.statements.iter().zip(statement_hashes(tokens, program)).zip(declared)
+        {
+            let mut key = Sha256::new();
+            key.update(&config_hash);
+            key.update(statement_hash);
+            for name in &declared {
+                key.update(name.as_bytes());
+                key.update([0]);
+            }
+            // Traced code embeds the statement's position, so moving it must miss
+            if config.instrumentation == InstrumentationMode::Trace {
+                if let Some(span) = statement.span() {
+                    key.update(format!("{:?}", span).as_bytes());
+                }
+            }
+            let path = self.dir.join(format!("{:x}", key.finalize()));
+
+            match std::fs::read_to_string(&path) {
+                Ok(fragment) => {
+                    stats.hits += 1;
+                    fragments.push(fragment);
+                }
+                Err(_) => {
+                    let fragment = codegen::generate_fragment(statement, &declared, config)?;
+                    self.regenerated.fetch_add(1, Ordering::Relaxed);
+                    stats.misses += 1;
+                    if let Err(e) = self.write(&path, &fragment) {
+                        tracing::warn!("Failed to cache fragment in {}: {}", self.dir.display(), e);
+                    }
+                    fragments.push(fragment);
+                }
+            }
+        }
+
+        Ok((codegen::assemble(program, &fragments, config)?, stats))
+    }
+
+    /// Write through a temporary file so a concurrent compile never reads half a fragment
+    fn write(&self, path: &Path, fragment: &str) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        let temp = path.with_extension(format!("tmp{}", std::process::id()));
+        std::fs::write(&temp, fragment)?;
+        std::fs::rename(&temp, path)
+    }
+}
+
+/// Hash of everything besides the statement that codegen depends on
+fn config_hash(config: &CompilerConfig) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(COMPILER_VERSION.as_bytes());
+    hasher.update(serde_json::to_vec(config).expect("configs always serialize"));
+    hasher.finalize().to_vec()
+}
+
+/// Hash of each top-level statement's tokens. Tokens rather than source text are hashed,
+/// so whitespace, comments and the statement's position don't invalidate its fragment.
+fn statement_hashes(tokens: &[TokenWithSpan], program: &Program) -> Vec<Vec<u8>> {
+    let mut rest = tokens;
+    program
+        .statements
+        .iter()
+        .map(|statement| {
+            let mut hasher = Sha256::new();
+            match statement.span() {
+                Some(span) => {
+                    let start = rest
+                        .iter()
+                        .position(|t| (t.line, t.column) >= (span.start_line, span.start_col))
+                        .unwrap_or(rest.len());
+                    let len = rest[start..]
+                        .iter()
+                        .take_while(|t| (t.end_line, t.end_column) <= (span.end_line, span.end_col))
+                        .count();
+                    for token in &rest[start..start + len] {
+                        hasher.update(serde_json::to_vec(&token.token).expect("tokens always serialize"));
+                    }
+                    rest = &rest[start + len..];
+                }
+                None => hasher.update(serde_json::to_vec(statement).expect("statements always serialize")),
+            }
+            hasher.finalize().to_vec()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Compiler, CompilerConfig, TargetLanguage};
+
+    /// 50 statements, half of them assignments that the other half read
+    fn fixture(changed: Option<usize>) -> String {
+        (0..25)
+            .flat_map(|i| {
+                let value = if changed == Some(i) { 1000 + i } else { i };
+                [
+                    format!("total_{}: {}", i, value),
+                    format!("if order_{} placed then send total_{} to \"555-0100\" using Twilio end", i, i),
+                ]
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_only_the_changed_statement_is_regenerated() {
+        for target_language in [TargetLanguage::Rust, TargetLanguage::Go, TargetLanguage::Python] {
+            let config = CompilerConfig { target_language, ..CompilerConfig::default() };
+            let dir = tempfile::tempdir().unwrap();
+            let compiler = Compiler::with_config(config.clone()).with_cache_dir(dir.path());
+            let cache = compiler.cache().unwrap();
+
+            let first = compiler.compile(&fixture(None)).unwrap();
+            assert_eq!(cache.regenerated(), 50);
+            assert_eq!(first.provenance.cache.unwrap().misses, 50);
+
+            let second = compiler.compile(&fixture(Some(7))).unwrap();
+            assert_eq!(cache.regenerated(), 51);
+            let stats = second.provenance.cache.unwrap();
+            assert_eq!((stats.hits, stats.misses), (49, 1));
+
+            let from_scratch = Compiler::with_config(config).compile(&fixture(Some(7))).unwrap();
+            assert_eq!(second.code, from_scratch.code);
+            assert_eq!(from_scratch.provenance.cache, None);
+        }
+    }
+
+    #[test]
+    fn test_statements_reading_a_removed_assignment_are_regenerated() {
+        let dir = tempfile::tempdir().unwrap();
+        let compiler = Compiler::new().with_cache_dir(dir.path());
+        compiler.compile("count: 1\nsend count to \"555-0100\" using Twilio").unwrap();
+
+        // Without the assignment `count` becomes an event field
+        let source = "send count to \"555-0100\" using Twilio";
+        let artifact = compiler.compile(source).unwrap();
+        assert_eq!(artifact.provenance.cache.unwrap().misses, 1);
+        assert_eq!(artifact.code, Compiler::new().compile_to_code(source).unwrap());
+        assert!(artifact.code.contains(r#"event.data.get("count")"#));
+    }
+}
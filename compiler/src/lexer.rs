@@ -2,11 +2,15 @@
 //! 
 //! Tokenizes Talk++ natural language input into structured tokens
 
+use crate::ast::Span;
 use crate::error::CompilerError;
 use logos::Logos;
 use serde::{Deserialize, Serialize};
 
 #[derive(Logos, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[logos(skip r"[ \t\n\f]+")]
+#[logos(skip r"//[^\n]*")]
+#[logos(skip r"/\*([^*]|\*[^/])*\*/")]
 pub enum Token {
     // Keywords
     #[token("if")]
@@ -18,6 +22,9 @@ pub enum Token {
     #[token("else")]
     Else,
     
+    #[token("end")]
+    End,
+    
     #[token("when")]
     When,
     
@@ -80,6 +87,10 @@ pub enum Token {
         let s = lex.slice();
         s[1..s.len()-1].to_owned() // Remove quotes
     })]
+    #[regex(r#"'([^'\\]|\\.)*'"#, |lex| {
+        let s = lex.slice();
+        s[1..s.len()-1].to_owned() // Remove single quotes
+    })]
     #[regex(r#"`([^`\\]|\\.)*`"#, |lex| {
         let s = lex.slice();
         s[1..s.len()-1].to_owned() // Remove backticks
@@ -105,13 +116,6 @@ pub enum Token {
     
     #[token(";")]
     Semicolon,
-
-    // Skip whitespace and comments
-    #[regex(r"[ \t\n\f]+", logos::skip)]
-    #[regex(r"//[^\n]*", logos::skip)]
-    #[regex(r"/\*([^*]|\*[^/])*\*/", logos::skip)]
-    #[error]
-    Error,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -120,6 +124,15 @@ pub struct TokenWithSpan {
     pub span: std::ops::Range<usize>,
     pub line: usize,
     pub column: usize,
+    /// Position just past the token's last character
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+impl TokenWithSpan {
+    pub fn location(&self) -> Span {
+        Span::new(self.line, self.column, self.end_line, self.end_column)
+    }
 }
 
 pub fn tokenize(input: &str) -> Result<Vec<TokenWithSpan>, CompilerError> {
@@ -145,18 +158,29 @@ pub fn tokenize(input: &str) -> Result<Vec<TokenWithSpan>, CompilerError> {
         last_pos = span.start;
 
         match token {
-            Token::Error => {
+            Err(()) => {
                 return Err(CompilerError::lexical(
                     span.start,
                     format!("Invalid token: '{}'", &input[span.clone()]),
                 ));
             }
-            _ => {
+            Ok(token) => {
+                let (mut end_line, mut end_column) = (line, column);
+                for c in input[span.clone()].chars() {
+                    if c == '\n' {
+                        end_line += 1;
+                        end_column = 1;
+                    } else {
+                        end_column += 1;
+                    }
+                }
                 tokens.push(TokenWithSpan {
                     token,
                     span,
                     line,
                     column,
+                    end_line,
+                    end_column,
                 });
             }
         }
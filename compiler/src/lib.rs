@@ -8,13 +8,22 @@ pub mod parser;
 pub mod ast;
 pub mod codegen;
 pub mod error;
+pub mod incremental;
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+
+pub use incremental::{CacheStats, FragmentCache};
+
+/// Version of this compiler, recorded in the provenance of everything it generates
+pub const COMPILER_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 /// Main compiler interface
 pub struct Compiler {
     config: CompilerConfig,
+    cache: Option<FragmentCache>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,30 +31,84 @@ pub struct CompilerConfig {
     pub target_language: TargetLanguage,
     pub optimization_level: OptimizationLevel,
     pub debug_mode: bool,
+    #[serde(default)]
+    pub instrumentation: InstrumentationMode,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TargetLanguage {
     Rust,
     Python,
     JavaScript,
     TypeScript,
     Bash,
+    Go,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// How generated Rust functions reach external services. Instrumented handlers take a
+/// `talkpp_support::ServiceBroker` and make every service call through it, so a simulator
+/// can intercept them; other targets ignore this setting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InstrumentationMode {
+    /// Call the generated service clients directly
+    #[default]
+    None,
+    /// Route service calls through the broker
+    DryRun,
+    /// Route service calls through the broker and report each statement to it
+    Trace,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum OptimizationLevel {
     Debug,
     Release,
     Size,
 }
 
+/// What produced a piece of generated code, so a deployed function can be traced back
+/// to the source and settings it was compiled from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    /// Hex SHA-256 of the DSL source, exactly as given to the compiler
+    pub source_hash: String,
+    pub compiler_version: String,
+    pub target_language: TargetLanguage,
+    pub optimization_level: OptimizationLevel,
+    pub instrumentation: InstrumentationMode,
+    pub compiled_at: chrono::DateTime<chrono::Utc>,
+    /// Fragment cache use, when compiled incrementally
+    #[serde(default)]
+    pub cache: Option<CacheStats>,
+}
+
+impl Provenance {
+    /// Hex SHA-256 of `source`
+    pub fn hash_source(source: &str) -> String {
+        format!("{:x}", Sha256::digest(source.as_bytes()))
+    }
+}
+
+/// Generated code with the provenance it was compiled with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompilationArtifact {
+    pub code: String,
+    pub provenance: Provenance,
+}
+
+impl CompilationArtifact {
+    pub fn into_code(self) -> String {
+        self.code
+    }
+}
+
 impl Default for CompilerConfig {
     fn default() -> Self {
         Self {
             target_language: TargetLanguage::Rust,
             optimization_level: OptimizationLevel::Debug,
             debug_mode: true,
+            instrumentation: InstrumentationMode::None,
         }
     }
 }
@@ -53,33 +116,63 @@ impl Default for CompilerConfig {
 impl Compiler {
     /// Create a new compiler instance with default configuration
     pub fn new() -> Self {
-        Self {
-            config: CompilerConfig::default(),
-        }
+        Self::with_config(CompilerConfig::default())
     }
 
     /// Create a new compiler instance with custom configuration
     pub fn with_config(config: CompilerConfig) -> Self {
-        Self { config }
+        Self { config, cache: None }
+    }
+
+    /// Compile incrementally, caching generated code per statement in `dir`
+    pub fn with_cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cache = Some(FragmentCache::new(dir));
+        self
     }
 
-    /// Compile Talk++ DSL source code to target language
-    pub fn compile(&self, source: &str) -> Result<String> {
+    pub fn cache(&self) -> Option<&FragmentCache> {
+        self.cache.as_ref()
+    }
+
+    /// Compile Talk++ DSL source code to target language, recording its provenance
+    pub fn compile(&self, source: &str) -> Result<CompilationArtifact> {
         // Parse the source into tokens
         let tokens = lexer::tokenize(source)?;
         
         // Parse tokens into AST
-        let ast = parser::parse(tokens)?;
+        let ast = parser::parse(tokens.clone())?;
         
-        // Generate code from AST
-        let code = codegen::generate(&ast, &self.config)?;
+        // Generate code from AST, reusing cached fragments of unchanged statements
+        let (code, cache) = match &self.cache {
+            Some(cache) => {
+                let (code, stats) = cache.generate(&tokens, &ast, &self.config)?;
+                (code, Some(stats))
+            }
+            None => (codegen::generate(&ast, &self.config)?, None),
+        };
         
-        Ok(code)
+        Ok(CompilationArtifact {
+            code,
+            provenance: Provenance {
+                source_hash: Provenance::hash_source(source),
+                compiler_version: COMPILER_VERSION.to_string(),
+                target_language: self.config.target_language.clone(),
+                optimization_level: self.config.optimization_level.clone(),
+                instrumentation: self.config.instrumentation,
+                compiled_at: chrono::Utc::now(),
+                cache,
+            },
+        })
+    }
+
+    /// Compile Talk++ DSL source code to target language, returning just the code
+    pub fn compile_to_code(&self, source: &str) -> Result<String> {
+        Ok(self.compile(source)?.into_code())
     }
 
     /// Compile and validate the generated code
     pub fn compile_and_validate(&self, source: &str) -> Result<String> {
-        let code = self.compile(source)?;
+        let code = self.compile_to_code(source)?;
         
         // TODO: Add validation logic
         
@@ -91,4 +184,32 @@ impl Default for Compiler {
     fn default() -> Self {
         Self::new()
     }
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recompiling_identical_source_yields_identical_provenance() {
+        let source = "if new user registers then validate email using SendGrid";
+        let compiler = Compiler::with_config(CompilerConfig {
+            target_language: TargetLanguage::Python,
+            optimization_level: OptimizationLevel::Release,
+            ..CompilerConfig::default()
+        });
+
+        let first = compiler.compile(source).unwrap();
+        let second = compiler.compile(source).unwrap();
+        assert_eq!(first.code, second.code);
+        assert_eq!(first.provenance.source_hash, second.provenance.source_hash);
+        assert_eq!(first.provenance.source_hash.len(), 64);
+        assert_eq!(first.provenance.compiler_version, COMPILER_VERSION);
+        assert_eq!(first.provenance.target_language, TargetLanguage::Python);
+        assert_eq!(first.provenance.optimization_level, OptimizationLevel::Release);
+        assert_eq!(compiler.compile_to_code(source).unwrap(), first.code);
+
+        let changed = compiler.compile("if new user registers then validate email using Twilio").unwrap();
+        assert_ne!(changed.provenance.source_hash, first.provenance.source_hash);
+    }
+}
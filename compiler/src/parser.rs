@@ -61,6 +61,8 @@ impl Parser {
     }
 
     fn parse_conditional(&mut self) -> Result<ConditionalStatement, CompilerError> {
+        let start = self.current;
+
         // Consume 'if' or 'when'
         self.advance();
 
@@ -72,42 +74,40 @@ impl Parser {
         }
         self.advance();
 
-        let mut then_actions = Vec::new();
-        
-        // Parse actions until we hit 'else' or end
-        while !self.is_at_end() && !self.check(&Token::Else) {
-            if let Some(Statement::Action(action)) = self.parse_statement()? {
-                then_actions.push(action);
-            } else {
-                break;
-            }
-        }
+        let then_body = self.parse_block()?;
 
-        let else_actions = if self.check(&Token::Else) {
+        let else_body = if self.check(&Token::Else) {
             self.advance(); // consume 'else'
-            let mut actions = Vec::new();
-            
-            while !self.is_at_end() {
-                if let Some(Statement::Action(action)) = self.parse_statement()? {
-                    actions.push(action);
-                } else {
-                    break;
-                }
-            }
-            
-            Some(actions)
+            Some(self.parse_block()?)
         } else {
             None
         };
 
-        Ok(ConditionalStatement {
-            condition,
-            then_actions,
-            else_actions,
-        })
+        // An optional 'end' closes the conditional, so later statements (or an 'else')
+        // belong to the enclosing block. Without it a dangling 'else' binds to the
+        // innermost conditional.
+        if self.check(&Token::End) {
+            self.advance();
+        }
+
+        Ok(ConditionalStatement::new(condition, then_body, else_body).with_span(self.span_from(start)))
+    }
+
+    /// Parse statements until 'else', 'end' or the end of input
+    fn parse_block(&mut self) -> Result<Vec<Statement>, CompilerError> {
+        let mut statements = Vec::new();
+
+        while !self.is_at_end() && !self.check(&Token::Else) && !self.check(&Token::End) {
+            if let Some(statement) = self.parse_statement()? {
+                statements.push(statement);
+            }
+        }
+
+        Ok(statements)
     }
 
     fn parse_condition(&mut self) -> Result<Condition, CompilerError> {
+        let start = self.current;
         let mut condition = self.parse_primary_condition()?;
 
         while self.check(&Token::And) || self.check(&Token::Or) {
@@ -123,6 +123,7 @@ impl Parser {
                 left: Box::new(condition),
                 operator,
                 right: Box::new(right),
+                span: self.span_from(start),
             });
         }
 
@@ -130,6 +131,8 @@ impl Parser {
     }
 
     fn parse_primary_condition(&mut self) -> Result<Condition, CompilerError> {
+        let start = self.current;
+
         // Parse event conditions like "new user registers"
         if self.check_identifier() {
             let mut parts = Vec::new();
@@ -168,6 +171,7 @@ impl Parser {
                     subject,
                     action,
                     context,
+                    span: self.span_from(start),
                 }));
             }
         }
@@ -176,6 +180,7 @@ impl Parser {
     }
 
     fn parse_action(&mut self) -> Result<ActionStatement, CompilerError> {
+        let start = self.current;
         let action = if let Token::Identifier(verb) = &self.peek().token {
             let action = Action::from_str(verb);
             self.advance();
@@ -192,41 +197,120 @@ impl Parser {
             }
         };
 
-        // Parse target (what to act on)
-        let target = if self.check_identifier() || self.check(&Token::String("".to_string())) {
-            Some(self.parse_expression()?)
+        let mut parameters = HashMap::new();
+
+        // Direct object, e.g. "welcome email" or a quoted literal
+        let target = if self.check_identifier() || self.check_literal() {
+            let object = self.parse_phrase()?;
+            parameters.insert("object".to_string(), object.clone());
+            Some(object)
         } else {
             None
         };
 
-        // Parse service call (using X, with Y)
-        let service = if self.check(&Token::Using) || self.check(&Token::With) {
-            self.advance(); // consume 'using' or 'with'
-            
-            if let Token::Service(name) = &self.peek().token {
-                let name = name.clone();
-                self.advance();
-                Some(ServiceCall {
-                    name,
-                    method: None,
-                    config: HashMap::new(),
-                })
-            } else {
-                None
+        // Service and prepositional arguments, in any order:
+        // "to new_user", "with subject 'Hi'", "in table 'users'", "using SendGrid"
+        let mut service = None;
+        while !self.is_at_end() {
+            match &self.peek().token {
+                Token::Using => {
+                    self.advance();
+                    service = Some(self.parse_service()?);
+                }
+                Token::With if matches!(self.peek_ahead(1).map(|t| &t.token), Some(Token::Service(_))) => {
+                    self.advance();
+                    service = Some(self.parse_service()?);
+                }
+                Token::With | Token::To | Token::From | Token::In => {
+                    let preposition = match self.advance().token {
+                        Token::With => "with",
+                        Token::To => "to",
+                        Token::From => "from",
+                        _ => "in",
+                    };
+                    let (key, value) = self.parse_argument()?;
+                    parameters.insert(key.unwrap_or_else(|| preposition.to_string()), value);
+                }
+                _ => break,
             }
-        } else {
-            None
-        };
+        }
 
         Ok(ActionStatement {
             action,
             target,
             service,
-            parameters: HashMap::new(),
+            parameters,
+            span: self.span_from(start),
         })
     }
 
+    fn parse_service(&mut self) -> Result<ServiceCall, CompilerError> {
+        if self.is_at_end() {
+            return Err(self.error("Expected service name"));
+        }
+        if let Token::Service(name) = &self.peek().token {
+            let name = name.clone();
+            self.advance();
+            Ok(ServiceCall::new(name).with_span(self.previous().location()))
+        } else {
+            Err(self.error("Expected service name"))
+        }
+    }
+
+    /// Parse a literal, a dotted path or a noun phrase. A single word is an identifier;
+    /// several words ("welcome email") are descriptive text and become a string.
+    fn parse_phrase(&mut self) -> Result<Expression, CompilerError> {
+        if !self.check_identifier() || matches!(self.peek_ahead(1).map(|t| &t.token), Some(Token::Dot)) {
+            return self.parse_expression();
+        }
+
+        let words = self.parse_words();
+        if words.len() == 1 {
+            Ok(Expression::identifier(words[0].clone()))
+        } else {
+            Ok(Expression::string(words.join(" ")))
+        }
+    }
+
+    /// Parse an argument value. A noun phrase directly followed by a literal names the
+    /// argument, as in `subject "Hi"` or `table "users"`.
+    fn parse_argument(&mut self) -> Result<(Option<String>, Expression), CompilerError> {
+        if self.is_at_end() {
+            return Err(self.error("Expected argument"));
+        }
+
+        let followed_by_literal = |parser: &Self| {
+            let mut offset = 0;
+            while matches!(parser.peek_ahead(offset).map(|t| &t.token), Some(Token::Identifier(_))) {
+                offset += 1;
+            }
+            offset > 0 && matches!(
+                parser.peek_ahead(offset).map(|t| &t.token),
+                Some(Token::String(_) | Token::Integer(_) | Token::Float(_))
+            )
+        };
+
+        if followed_by_literal(self) {
+            let key = self.parse_words().join("_");
+            return Ok((Some(key), self.parse_expression()?));
+        }
+
+        Ok((None, self.parse_phrase()?))
+    }
+
+    fn parse_words(&mut self) -> Vec<String> {
+        let mut words = Vec::new();
+        while self.check_identifier() {
+            if let Token::Identifier(word) = &self.peek().token {
+                words.push(word.clone());
+            }
+            self.advance();
+        }
+        words
+    }
+
     fn parse_assignment(&mut self) -> Result<AssignmentStatement, CompilerError> {
+        let start = self.current;
         let variable = if let Token::Identifier(name) = &self.peek().token {
             let name = name.clone();
             self.advance();
@@ -243,15 +327,34 @@ impl Parser {
 
         let value = self.parse_expression()?;
 
-        Ok(AssignmentStatement { variable, value })
+        Ok(AssignmentStatement::new(variable, value).with_span(self.span_from(start)))
     }
 
     fn parse_expression(&mut self) -> Result<Expression, CompilerError> {
         match &self.peek().token {
             Token::Identifier(name) => {
-                let name = name.clone();
+                let start = self.current;
+                let mut expr = Expression::identifier(name.clone());
                 self.advance();
-                Ok(Expression::identifier(name))
+
+                // Dotted property access, e.g. user.email
+                while self.check(&Token::Dot) {
+                    match self.peek_ahead(1).map(|t| &t.token) {
+                        Some(Token::Identifier(property)) => {
+                            let property = property.clone();
+                            self.advance();
+                            self.advance();
+                            expr = Expression::Property(PropertyAccess {
+                                object: Box::new(expr),
+                                property,
+                                span: self.span_from(start),
+                            });
+                        }
+                        _ => break,
+                    }
+                }
+
+                Ok(expr)
             }
             Token::String(value) => {
                 let value = value.clone();
@@ -310,12 +413,32 @@ impl Parser {
     }
 
     fn check_identifier(&self) -> bool {
-        matches!(self.peek().token, Token::Identifier(_))
+        !self.is_at_end() && matches!(self.peek().token, Token::Identifier(_))
+    }
+
+    fn check_literal(&self) -> bool {
+        !self.is_at_end() && matches!(self.peek().token, Token::String(_) | Token::Integer(_) | Token::Float(_))
+    }
+
+    /// Span from the token at `start` through the last consumed token
+    fn span_from(&self, start: usize) -> Span {
+        let first = match self.tokens.get(start) {
+            Some(token) => token.location(),
+            None => return Span::default(),
+        };
+        if self.current > start {
+            first.to(self.previous().location())
+        } else {
+            first
+        }
     }
 
     fn error(&self, message: &str) -> CompilerError {
-        let token = self.peek();
-        CompilerError::parse(token.line, token.column, message)
+        // Errors at the end of input point at the last token
+        match self.tokens.get(self.current).or(self.tokens.last()) {
+            Some(token) => CompilerError::parse(token.location(), message),
+            None => CompilerError::parse(Span::new(1, 1, 1, 2), message),
+        }
     }
 }
 
@@ -338,7 +461,7 @@ mod tests {
         assert_eq!(ast.statements.len(), 1);
         if let Statement::Conditional(cond) = &ast.statements[0] {
             assert!(matches!(cond.condition, Condition::Event(_)));
-            assert_eq!(cond.then_actions.len(), 1);
+            assert_eq!(cond.then_body.len(), 1);
         } else {
             panic!("Expected conditional statement");
         }
@@ -358,4 +481,123 @@ mod tests {
             panic!("Expected action statement");
         }
     }
-} 
\ No newline at end of file
+
+    fn conditional(statement: &Statement) -> &ConditionalStatement {
+        match statement {
+            Statement::Conditional(cond) => cond,
+            other => panic!("Expected conditional statement, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_two_level_nesting() {
+        let input = "if new user registers then if user verified then send welcome email else send reminder";
+        let ast = parse(tokenize(input).unwrap()).unwrap();
+
+        assert_eq!(ast.statements.len(), 1);
+        let outer = conditional(&ast.statements[0]);
+        assert!(outer.else_body.is_none());
+        assert_eq!(outer.then_body.len(), 1);
+
+        // The dangling else binds to the inner conditional
+        let inner = conditional(&outer.then_body[0]);
+        assert_eq!(inner.then_body.len(), 1);
+        assert_eq!(inner.else_body.as_ref().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_else_containing_conditional() {
+        let input = "if order placed then process order else if order cancelled then send refund using Stripe else store order end end";
+        let ast = parse(tokenize(input).unwrap()).unwrap();
+
+        assert_eq!(ast.statements.len(), 1);
+        let outer = conditional(&ast.statements[0]);
+        let else_body = outer.else_body.as_ref().unwrap();
+        assert_eq!(else_body.len(), 1);
+
+        let inner = conditional(&else_body[0]);
+        assert!(matches!(&inner.then_body[0], Statement::Action(a) if a.service.is_some()));
+        assert_eq!(inner.else_body.as_ref().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_end_closes_branches() {
+        let input = "if user signs then if user verified then send badge end else send reminder end count: 1";
+        let ast = parse(tokenize(input).unwrap()).unwrap();
+
+        assert_eq!(ast.statements.len(), 2);
+        let outer = conditional(&ast.statements[0]);
+        assert!(conditional(&outer.then_body[0]).else_body.is_none());
+        assert_eq!(outer.else_body.as_ref().unwrap().len(), 1);
+        assert!(matches!(ast.statements[1], Statement::Assignment(_)));
+    }
+
+    #[test]
+    fn test_multi_statement_branches() {
+        let input = "if new user registers then status: \"new\" validate email using SendGrid store user else send alert end";
+        let ast = parse(tokenize(input).unwrap()).unwrap();
+
+        let cond = conditional(&ast.statements[0]);
+        assert_eq!(cond.then_body.len(), 3);
+        assert!(matches!(cond.then_body[0], Statement::Assignment(_)));
+    }
+    fn action(statement: &Statement) -> &ActionStatement {
+        match statement {
+            Statement::Action(action) => action,
+            other => panic!("Expected action statement, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_action_parameters() {
+        let input = "send welcome email to \"ada@example.com\" with subject 'Hi' using SendGrid";
+        let ast = parse(tokenize(input).unwrap()).unwrap();
+
+        let send = action(&ast.statements[0]);
+        assert!(matches!(&send.parameters["object"], Expression::String(v) if v == "welcome email"));
+        assert!(matches!(&send.parameters["to"], Expression::String(v) if v == "ada@example.com"));
+        assert!(matches!(&send.parameters["subject"], Expression::String(v) if v == "Hi"));
+        assert_eq!(send.service.as_ref().unwrap().name, "SendGrid");
+    }
+
+    #[test]
+    fn test_action_parameters_with_identifiers_and_properties() {
+        let input = "store user in table \"users\" using Postgres send alert to user.phone";
+        let ast = parse(tokenize(input).unwrap()).unwrap();
+
+        let store = action(&ast.statements[0]);
+        assert!(matches!(&store.parameters["object"], Expression::Identifier(v) if v == "user"));
+        assert!(matches!(&store.parameters["table"], Expression::String(v) if v == "users"));
+
+        let send = action(&ast.statements[1]);
+        assert!(send.service.is_none());
+        assert!(matches!(&send.parameters["to"], Expression::Property(p) if p.property == "phone"));
+    }
+    #[test]
+    fn test_spans_cover_whole_statements() {
+        let input = "if new user registers then\n  send welcome email to \"ada@example.com\" using SendGrid\n  status: user.state\nend";
+        let ast = parse(tokenize(input).unwrap()).unwrap();
+
+        let cond = conditional(&ast.statements[0]);
+        assert_eq!(cond.span, Span::new(1, 1, 4, 4));
+        assert_eq!(cond.condition.span(), Span::new(1, 4, 1, 22));
+
+        let send = action(&cond.then_body[0]);
+        assert_eq!(send.span, Span::new(2, 3, 2, 57));
+        assert_eq!(send.service.as_ref().unwrap().span, Span::new(2, 49, 2, 57));
+
+        let assign = match &cond.then_body[1] {
+            Statement::Assignment(assign) => assign,
+            other => panic!("Expected assignment, got {:?}", other),
+        };
+        assert_eq!(assign.span, Span::new(3, 3, 3, 21));
+        assert!(matches!(&assign.value, Expression::Property(p) if p.span == Span::new(3, 11, 3, 21)));
+    }
+
+    #[test]
+    fn test_parse_error_span() {
+        let error = parse(tokenize("send alert using").unwrap()).unwrap_err();
+        assert_eq!(error.span(), Some(Span::new(1, 12, 1, 17)));
+        assert_eq!(error.to_string(), "Parse error at line 1, column 12: Expected service name");
+    }
+}
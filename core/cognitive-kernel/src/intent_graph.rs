@@ -1,33 +1,30 @@
 use std::collections::HashMap;
+use std::sync::Arc;
 use anyhow::Result;
 use uuid::Uuid;
 use chrono::Utc;
 use serde::{Serialize, Deserialize};
 
-use crate::{Intent, RiskLevel, ExecutionTask, TaskType, TaskStatus};
+use crate::{Classification, Intent, IntentClassifier, RiskLevel, ExecutionTask, TaskType, TaskStatus};
 
 /// Builds hierarchical intent graphs from natural language
 #[derive(Debug)]
 pub struct IntentGraphBuilder {
-    domain_patterns: HashMap<String, Vec<String>>,
-    risk_patterns: HashMap<RiskLevel, Vec<String>>,
+    classifier: Arc<IntentClassifier>,
 }
 
 impl IntentGraphBuilder {
     pub fn new() -> Self {
-        let mut builder = Self {
-            domain_patterns: HashMap::new(),
-            risk_patterns: HashMap::new(),
-        };
-        
-        builder.init_patterns();
-        builder
+        Self::with_classifier(Arc::new(IntentClassifier::new()))
+    }
+
+    pub fn with_classifier(classifier: Arc<IntentClassifier>) -> Self {
+        Self { classifier }
     }
 
     /// Parse natural language into structured intent
     pub async fn parse_intent(&self, raw_text: &str) -> Result<Intent> {
-        let domain = self.classify_domain(raw_text);
-        let risk_level = self.assess_risk(raw_text);
+        let Classification { domain, risk_level } = self.classifier.classify(raw_text);
         let (constraints, success_criteria) = self.extract_constraints(raw_text);
         
         Ok(Intent {
@@ -45,72 +42,11 @@ impl IntentGraphBuilder {
     }
 
     pub fn classify_domain(&self, text: &str) -> String {
-        let text_lower = text.to_lowercase();
-        let mut scores = HashMap::new();
-        
-        for (domain, patterns) in &self.domain_patterns {
-            let score = patterns.iter()
-                .filter(|pattern| text_lower.contains(*pattern))
-                .count();
-            if score > 0 {
-                scores.insert(domain.clone(), score);
-            }
-        }
-        
-        scores.into_iter()
-            .max_by_key(|(_, score)| *score)
-            .map(|(domain, _)| domain)
-            .unwrap_or_else(|| "general".to_string())
+        self.classifier.classify_domain(text)
     }
 
     pub fn assess_risk(&self, text: &str) -> RiskLevel {
-        let text_lower = text.to_lowercase();
-        
-        // Check in order of severity
-        for (risk_level, patterns) in &self.risk_patterns {
-            for pattern in patterns {
-                if text_lower.contains(pattern) {
-                    return risk_level.clone();
-                }
-            }
-        }
-        
-        RiskLevel::Low
-    }
-
-    fn init_patterns(&mut self) {
-        // Domain patterns
-        self.domain_patterns.insert("infra_deployment".to_string(), vec![
-            "deploy".to_string(), "kubernetes".to_string(), "docker".to_string(),
-            "container".to_string(), "server".to_string(), "staging".to_string(),
-            "production".to_string(), "infrastructure".to_string(),
-        ]);
-        
-        self.domain_patterns.insert("database_admin".to_string(), vec![
-            "database".to_string(), "postgres".to_string(), "sql".to_string(),
-            "migration".to_string(), "schema".to_string(), "backup".to_string(),
-        ]);
-        
-        self.domain_patterns.insert("marketing_content".to_string(), vec![
-            "marketing".to_string(), "content".to_string(), "blog".to_string(),
-            "social".to_string(), "email".to_string(), "campaign".to_string(),
-        ]);
-
-        // Risk patterns (in order of severity)
-        self.risk_patterns.insert(RiskLevel::Critical, vec![
-            "production".to_string(), "delete".to_string(), "drop".to_string(),
-            "destroy".to_string(), "rm -rf".to_string(),
-        ]);
-        
-        self.risk_patterns.insert(RiskLevel::High, vec![
-            "modify".to_string(), "update".to_string(), "migrate".to_string(),
-            "change".to_string(),
-        ]);
-        
-        self.risk_patterns.insert(RiskLevel::Medium, vec![
-            "create".to_string(), "add".to_string(), "install".to_string(),
-            "deploy".to_string(),
-        ]);
+        self.classifier.assess_risk(text)
     }
 
     fn extract_constraints(&self, text: &str) -> (Vec<String>, Vec<String>) {
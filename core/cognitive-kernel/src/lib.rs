@@ -11,6 +11,7 @@ pub mod adaptive_planner;
 
 pub use intent_graph::IntentGraphBuilder;
 pub use adaptive_planner::AdaptivePlanner;
+pub use intent_classifier::{Classification, IntentClassifier, PatternSet, RiskLevel};
 
 /// Core cognitive kernel that orchestrates all JARVIS thinking processes
 #[derive(Debug)]
@@ -23,8 +24,13 @@ pub struct CognitiveKernel {
 
 impl CognitiveKernel {
     pub fn new() -> Self {
+        Self::with_classifier(Arc::new(IntentClassifier::new()))
+    }
+
+    /// Kernel whose intent graph classifies with `classifier`, shared with other consumers
+    pub fn with_classifier(classifier: Arc<IntentClassifier>) -> Self {
         Self {
-            intent_graph: Arc::new(IntentGraphBuilder::new()),
+            intent_graph: Arc::new(IntentGraphBuilder::with_classifier(classifier)),
             planner: Arc::new(AdaptivePlanner::new()),
             active_contexts: Arc::new(DashMap::new()),
             global_state: Arc::new(DashMap::new()),
@@ -88,14 +94,6 @@ pub struct Intent {
     pub created_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum RiskLevel {
-    Low,
-    Medium, 
-    High,
-    Critical,
-}
-
 /// Execution plan with hierarchical tasks and dependencies
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IntentExecutionPlan {
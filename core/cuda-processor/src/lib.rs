@@ -3,8 +3,24 @@ use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use tracing::{info, error, warn};
 use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
+pub use talkpp_model_traits::LanguageModel;
+use talkpp_model_traits::generate_cancellable;
+use talkpp_model_traits::repository::ModelRepository;
+use talkpp_errors::{Validate, Violations};
+
+pub mod multimodal;
+pub use multimodal::{EmbeddingInput, Modality, MultimodalEmbedding, MultimodalEmbeddingModel};
+pub mod scheduler;
+pub use scheduler::{DeviceLease, DeviceMemoryProbe, DeviceScheduler, Placement, SchedulerConfig, SchedulerError};
+pub mod vision;
+pub use vision::{
+    BoundingBox, Detection, ObjectDetectionModel, ObjectDetectionResult, OcrModel, OcrPipeline, OcrResult, TextLine,
+};
+
 /// CUDA Device Information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CudaDeviceInfo {
@@ -22,22 +38,92 @@ pub struct CudaDeviceInfo {
 pub enum MlTaskType {
     TextEmbedding,
     ImageProcessing,
+    /// Find labeled objects; `model_path` naming DETR selects it over YOLOv8
+    ObjectDetection,
+    /// Read lines of text; an `.onnx` or `ort:` `model_path` runs through ONNX Runtime
+    /// instead of candle's TrOCR
+    Ocr,
     LanguageGeneration,
     VectorSearch,
     DataAnalysis,
     Custom { name: String, description: String },
 }
 
-/// ML Task Configuration
+/// ML Task Configuration. Build one with [`MlTaskConfig::new`].
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MlTaskConfig {
+    #[serde(default = "Uuid::new_v4")]
     pub id: Uuid,
     pub task_type: MlTaskType,
     pub model_path: Option<String>,
     pub batch_size: usize,
     pub precision: ModelPrecision,
     pub use_cuda: bool,
+    /// Run on this device instead of the one the scheduler picks, failing if it lacks
+    /// room for the task
     pub device_id: Option<u32>,
+    /// Report items of a batch that fail on their own instead of failing the batch
+    #[serde(default)]
+    pub best_effort: bool,
+}
+
+impl MlTaskConfig {
+    /// A task on the default CUDA device, in batches of 32 at full precision
+    pub fn new(task_type: MlTaskType) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            task_type,
+            model_path: None,
+            batch_size: 32,
+            precision: ModelPrecision::Float32,
+            use_cuda: true,
+            device_id: None,
+            best_effort: false,
+        }
+    }
+
+    pub fn with_model_path(mut self, model_path: impl Into<String>) -> Self {
+        self.model_path = Some(model_path.into());
+        self
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    pub fn with_precision(mut self, precision: ModelPrecision) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Pin the task to `device_id`, bypassing the device scheduler
+    pub fn with_device(mut self, device_id: u32) -> Self {
+        self.device_id = Some(device_id);
+        self
+    }
+
+    pub fn with_best_effort(mut self, best_effort: bool) -> Self {
+        self.best_effort = best_effort;
+        self
+    }
+}
+
+impl Validate for MlTaskConfig {
+    const NAME: &'static str = "ML task config";
+
+    fn violations(&self) -> Violations {
+        let mut violations = Violations::new();
+        if let MlTaskType::Custom { name, .. } = &self.task_type {
+            violations.check(!name.trim().is_empty(), "task_type.name", "must not be empty");
+        }
+        if let Some(model_path) = &self.model_path {
+            violations.check(!model_path.trim().is_empty(), "model_path", "must not be empty when set");
+        }
+        violations.check(self.batch_size > 0, "batch_size", "must be at least 1");
+        violations.check(self.device_id.is_none() || self.use_cuda, "device_id", "requires use_cuda");
+        violations
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -57,8 +143,15 @@ pub struct MlTaskResult {
     pub execution_time_ms: u64,
     pub memory_used_mb: u64,
     pub error: Option<String>,
+    /// Device the scheduler ran the task on, with its memory estimated and measured;
+    /// `None` on the CPU
+    #[serde(default)]
+    pub placement: Option<Placement>,
 }
 
+/// Most tokens a language generation produces
+const MAX_GENERATED_TOKENS: usize = 100;
+
 /// CUDA Processor Interface
 #[async_trait]
 pub trait CudaProcessor {
@@ -66,7 +159,20 @@ pub trait CudaProcessor {
     async fn get_device_info(&self) -> Result<Vec<CudaDeviceInfo>>;
     async fn process_embedding(&self, texts: Vec<String>, config: MlTaskConfig) -> Result<MlTaskResult>;
     async fn process_image(&self, image_data: Vec<u8>, config: MlTaskConfig) -> Result<MlTaskResult>;
+    /// Embed text and images into one vector space. The result lists a
+    /// [`MultimodalEmbedding`] per item, in input order.
+    async fn process_multimodal_embedding(&self, items: Vec<EmbeddingInput>, config: MlTaskConfig) -> Result<MlTaskResult>;
     async fn process_language_generation(&self, prompt: String, config: MlTaskConfig) -> Result<MlTaskResult>;
+    /// Generate as `process_language_generation` does, passing each piece to `pieces` as
+    /// it is produced, until the model finishes or `cancel` fires. Cancelling stops the
+    /// model; the result keeps the text generated so far and reports `"cancelled": true`.
+    async fn process_language_generation_stream(
+        &self,
+        prompt: String,
+        config: MlTaskConfig,
+        pieces: mpsc::UnboundedSender<String>,
+        cancel: CancellationToken,
+    ) -> Result<MlTaskResult>;
     async fn cleanup(&mut self) -> Result<()>;
 }
 
@@ -75,6 +181,13 @@ pub struct CandleCudaProcessor {
     devices: Vec<CudaDeviceInfo>,
     candle_devices: Vec<candle_core::Device>,
     initialized: bool,
+    models: ModelRepository,
+    /// Set up by `initialize` when there are CUDA devices to place tasks on
+    scheduler: Option<DeviceScheduler>,
+    scheduling: SchedulerConfig,
+    memory_probe: Option<Arc<dyn DeviceMemoryProbe>>,
+    #[cfg(feature = "ollama")]
+    ollama_url: Option<String>,
 }
 
 impl CandleCudaProcessor {
@@ -83,11 +196,86 @@ impl CandleCudaProcessor {
             devices: Vec::new(),
             candle_devices: Vec::new(),
             initialized: false,
+            models: ModelRepository::from_env(),
+            scheduler: None,
+            scheduling: SchedulerConfig::default(),
+            memory_probe: None,
+            #[cfg(feature = "ollama")]
+            ollama_url: None,
         }
     }
 
+    /// Cache that hub model ids are downloaded into, instead of one configured from
+    /// the environment
+    pub fn with_model_repository(mut self, models: ModelRepository) -> Self {
+        self.models = models;
+        self
+    }
+
+    /// How many tasks may wait for device memory, and for how long
+    pub fn with_scheduling(mut self, scheduling: SchedulerConfig) -> Self {
+        self.scheduling = scheduling;
+        self
+    }
+
+    /// Measure the device memory each task takes, reported in its result's placement
+    pub fn with_memory_probe(mut self, probe: Arc<dyn DeviceMemoryProbe>) -> Self {
+        self.memory_probe = Some(probe);
+        self
+    }
+
+    /// Ollama server that `ollama:<model>` model paths are generated with, instead of
+    /// `http://localhost:11434`
+    #[cfg(feature = "ollama")]
+    pub fn with_ollama_url(mut self, url: impl Into<String>) -> Self {
+        self.ollama_url = Some(url.into());
+        self
+    }
+
+    /// Local directory of `model_path`: the path itself when it exists, otherwise a hub
+    /// model id, downloaded into the model cache unless it is there already
+    async fn resolve_model_path(&self, model_path: &str) -> Result<String> {
+        if std::path::Path::new(model_path).exists() {
+            return Ok(model_path.to_string());
+        }
+        let dir = self.models.ensure_model(model_path).await?;
+        Ok(dir.to_string_lossy().into_owned())
+    }
+
+    /// Device to run a task with `model_path` on: the pinned one or the one the scheduler
+    /// picks, whose lease holds the task's memory until it is dropped. Without CUDA
+    /// devices there is nothing to schedule.
+    async fn place(&self, config: &MlTaskConfig, model_path: &str) -> Result<(&candle_core::Device, Option<DeviceLease>)> {
+        let Some(scheduler) = &self.scheduler else {
+            let device_id = config.device_id.unwrap_or(0) as usize;
+            let device = self.candle_devices.get(device_id)
+                .ok_or_else(|| anyhow::anyhow!("Device {} not available", device_id))?;
+            return Ok((device, None));
+        };
+        let required = scheduler::estimate_memory(self.model_size(model_path), &config.precision, config.batch_size);
+        let lease = scheduler.acquire(required, config.device_id).await?;
+        let device = self.candle_devices.get(lease.device_id() as usize)
+            .ok_or_else(|| anyhow::anyhow!("Device {} not available", lease.device_id()))?;
+        info!("Placed task {} on device {} (estimated {} bytes)", config.id, lease.device_id(), required);
+        Ok((device, Some(lease)))
+    }
+
+    /// Bytes of `model_path` on disk, as a local directory or a cached hub model; 0 for a
+    /// model not downloaded yet
+    fn model_size(&self, model_path: &str) -> u64 {
+        let path = std::path::Path::new(model_path);
+        if path.exists() {
+            return disk_size(path);
+        }
+        self.models.list_cached()
+            .ok()
+            .and_then(|models| models.into_iter().find(|model| model.id == model_path))
+            .map_or(0, |model| model.size_bytes())
+    }
+
     /// Load embedding model
     async fn load_embedding_model(&self, model_path: &str, device: &candle_core::Device) -> Result<Box<dyn EmbeddingModel + Send + Sync>> {
+        let model_path = &self.resolve_model_path(model_path).await?;
         info!("Loading embedding model from: {}", model_path);
         
         // Load different model types based on path
@@ -100,10 +288,26 @@ impl CandleCudaProcessor {
         }
     }
 
+    /// The language model `config` generates with, loaded onto the device it was placed on
+    async fn generation_model(&self, config: &MlTaskConfig) -> Result<(Box<dyn LanguageModel + Send + Sync>, Option<DeviceLease>)> {
+        let model_path = config.model_path.as_deref()
+            .ok_or_else(|| anyhow::anyhow!("Model path required for language generation"))?;
+        let (device, lease) = self.place(config, model_path).await?;
+
+        Ok((self.load_language_model(model_path, device).await?, lease))
+    }
+
     /// Load language model
     async fn load_language_model(&self, model_path: &str, device: &candle_core::Device) -> Result<Box<dyn LanguageModel + Send + Sync>> {
         info!("Loading language model from: {}", model_path);
-        
+
+        // Served by Ollama rather than loaded onto the device
+        #[cfg(feature = "ollama")]
+        if let Some(model) = talkpp_ollama_integration::OllamaLanguageModel::from_model_path(model_path, self.ollama_url.clone()) {
+            return Ok(Box::new(model));
+        }
+
+        let model_path = &self.resolve_model_path(model_path).await?;
         // Load different model architectures
         if model_path.contains("llama") {
             Ok(Box::new(LlamaModel::load(model_path, device.clone()).await?))
@@ -136,6 +340,11 @@ impl CudaProcessor for CandleCudaProcessor {
             }
             
             info!("Found {} CUDA devices", device_count);
+            let mut scheduler = DeviceScheduler::new(&self.devices, self.scheduling.clone());
+            if let Some(probe) = &self.memory_probe {
+                scheduler = scheduler.with_probe(probe.clone());
+            }
+            self.scheduler = Some(scheduler);
         } else {
             warn!("CUDA not available, falling back to CPU");
             let cpu_device = candle_core::Device::Cpu;
@@ -165,19 +374,16 @@ impl CudaProcessor for CandleCudaProcessor {
     }
 
     async fn process_embedding(&self, texts: Vec<String>, config: MlTaskConfig) -> Result<MlTaskResult> {
+        config.validate()?;
         let start_time = std::time::Instant::now();
         let task_id = config.id;
         
         info!("Processing embeddings for {} texts", texts.len());
         
-        // Select device
-        let device_id = config.device_id.unwrap_or(0) as usize;
-        let device = self.candle_devices.get(device_id)
-            .ok_or_else(|| anyhow::anyhow!("Device {} not available", device_id))?;
-        
         // Load or get cached embedding model
-        let model_path = config.model_path
+        let model_path = config.model_path.clone()
             .unwrap_or_else(|| "sentence-transformers/all-MiniLM-L6-v2".to_string());
+        let (device, lease) = self.place(&config, &model_path).await?;
         
         let model = self.load_embedding_model(&model_path, device).await?;
         
@@ -191,69 +397,108 @@ impl CudaProcessor for CandleCudaProcessor {
         }
         
         let execution_time = start_time.elapsed().as_millis() as u64;
+        let placement = placement(lease.as_ref()).await;
         
         Ok(MlTaskResult {
             task_id,
             success: true,
             result: serde_json::to_value(&all_embeddings)?,
             execution_time_ms: execution_time,
-            memory_used_mb: 0, // TODO: Implement memory tracking
+            memory_used_mb: memory_used_mb(placement.as_ref()),
             error: None,
+            placement,
         })
     }
 
     async fn process_image(&self, image_data: Vec<u8>, config: MlTaskConfig) -> Result<MlTaskResult> {
+        config.validate()?;
         let start_time = std::time::Instant::now();
         let task_id = config.id;
         
         info!("Processing image of {} bytes", image_data.len());
         
-        // Select device
-        let device_id = config.device_id.unwrap_or(0) as usize;
-        let device = self.candle_devices.get(device_id)
-            .ok_or_else(|| anyhow::anyhow!("Device {} not available", device_id))?;
-        
-        // Load image processing model
-        let model_path = config.model_path
-            .unwrap_or_else(|| "clip-vit-base-patch32".to_string());
+        let default_model = match config.task_type {
+            MlTaskType::ObjectDetection => "lmz/candle-yolo-v8",
+            MlTaskType::Ocr => "microsoft/trocr-base-printed",
+            _ => "openai/clip-vit-base-patch32",
+        };
+        let model_path = config.model_path.clone().unwrap_or_else(|| default_model.to_string());
+        let (device, lease) = self.place(&config, &model_path).await?;
         
-        let model = self.load_image_model(&model_path, device).await?;
-        
-        // Process image
-        let result = model.process_image(image_data).await?;
+        // Load the task's model and process the image
+        let result = match config.task_type {
+            MlTaskType::ObjectDetection => {
+                let model = self.load_detection_model(&model_path, device).await?;
+                serde_json::to_value(model.detect(image_data).await?)?
+            }
+            MlTaskType::Ocr => {
+                let model = self.load_ocr_model(&model_path, device).await?;
+                serde_json::to_value(model.read_text(image_data).await?)?
+            }
+            _ => {
+                let model = self.load_image_model(&model_path, device).await?;
+                serde_json::to_value(model.process_image(image_data).await?)?
+            }
+        };
         
         let execution_time = start_time.elapsed().as_millis() as u64;
+        let placement = placement(lease.as_ref()).await;
         
         Ok(MlTaskResult {
             task_id,
             success: true,
-            result: serde_json::to_value(&result)?,
+            result,
             execution_time_ms: execution_time,
-            memory_used_mb: 0,
+            memory_used_mb: memory_used_mb(placement.as_ref()),
             error: None,
+            placement,
         })
     }
 
-    async fn process_language_generation(&self, prompt: String, config: MlTaskConfig) -> Result<MlTaskResult> {
+    async fn process_multimodal_embedding(&self, items: Vec<EmbeddingInput>, config: MlTaskConfig) -> Result<MlTaskResult> {
+        config.validate()?;
         let start_time = std::time::Instant::now();
         let task_id = config.id;
         
-        info!("Processing language generation for prompt length: {}", prompt.len());
+        info!("Processing multimodal embeddings for {} items", items.len());
         
-        // Select device
-        let device_id = config.device_id.unwrap_or(0) as usize;
-        let device = self.candle_devices.get(device_id)
-            .ok_or_else(|| anyhow::anyhow!("Device {} not available", device_id))?;
+        // Both towers come from the same model, so text and images share a space
+        let model_path = config.model_path.clone()
+            .unwrap_or_else(|| "openai/clip-vit-base-patch32".to_string());
+        let (device, lease) = self.place(&config, &model_path).await?;
         
-        let model_path = config.model_path
-            .ok_or_else(|| anyhow::anyhow!("Model path required for language generation"))?;
+        let model = self.load_multimodal_model(&model_path, device).await?;
+        let embeddings = multimodal::embed_multimodal(model.as_ref(), items, config.batch_size, config.best_effort).await?;
+        let failed = embeddings.iter().filter(|embedding| embedding.error.is_some()).count();
         
-        let model = self.load_language_model(&model_path, device).await?;
+        let execution_time = start_time.elapsed().as_millis() as u64;
+        let placement = placement(lease.as_ref()).await;
+        
+        Ok(MlTaskResult {
+            task_id,
+            success: failed == 0,
+            result: serde_json::to_value(&embeddings)?,
+            execution_time_ms: execution_time,
+            memory_used_mb: memory_used_mb(placement.as_ref()),
+            error: (failed > 0).then(|| format!("{} of {} items could not be embedded", failed, embeddings.len())),
+            placement,
+        })
+    }
+
+    async fn process_language_generation(&self, prompt: String, config: MlTaskConfig) -> Result<MlTaskResult> {
+        config.validate()?;
+        let start_time = std::time::Instant::now();
+        let task_id = config.id;
+        
+        info!("Processing language generation for prompt length: {}", prompt.len());
+        
+        let (model, lease) = self.generation_model(&config).await?;
         
         // Generate text
-        let generated_text = model.generate(&prompt, 100).await?; // Max 100 tokens
+        let generated_text = model.generate(&prompt, MAX_GENERATED_TOKENS).await?;
         
         let execution_time = start_time.elapsed().as_millis() as u64;
+        let placement = placement(lease.as_ref()).await;
         
         Ok(MlTaskResult {
             task_id,
@@ -263,8 +508,44 @@ impl CudaProcessor for CandleCudaProcessor {
                 "prompt": prompt
             }),
             execution_time_ms: execution_time,
-            memory_used_mb: 0,
+            memory_used_mb: memory_used_mb(placement.as_ref()),
             error: None,
+            placement,
+        })
+    }
+
+    async fn process_language_generation_stream(
+        &self,
+        prompt: String,
+        config: MlTaskConfig,
+        pieces: mpsc::UnboundedSender<String>,
+        cancel: CancellationToken,
+    ) -> Result<MlTaskResult> {
+        config.validate()?;
+        let start_time = std::time::Instant::now();
+        let task_id = config.id;
+
+        info!("Streaming language generation for prompt length: {}", prompt.len());
+
+        let (model, lease) = self.generation_model(&config).await?;
+        let generation = generate_cancellable(model.as_ref(), &prompt, MAX_GENERATED_TOKENS, Some(&pieces), &cancel).await?;
+        if generation.cancelled {
+            info!("Language generation {} cancelled after {} bytes", task_id, generation.text.len());
+        }
+        let placement = placement(lease.as_ref()).await;
+
+        Ok(MlTaskResult {
+            task_id,
+            success: !generation.cancelled,
+            result: serde_json::json!({
+                "generated_text": generation.text,
+                "prompt": prompt,
+                "cancelled": generation.cancelled,
+            }),
+            execution_time_ms: start_time.elapsed().as_millis() as u64,
+            memory_used_mb: memory_used_mb(placement.as_ref()),
+            error: generation.cancelled.then(|| "Generation cancelled".to_string()),
+            placement,
         })
     }
 
@@ -272,12 +553,40 @@ impl CudaProcessor for CandleCudaProcessor {
         info!("Cleaning up CUDA processor");
         self.devices.clear();
         self.candle_devices.clear();
+        self.scheduler = None;
         self.initialized = false;
         Ok(())
     }
 }
 
+/// What [`CandleCudaProcessor::self_test`] found
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SelfTestReport {
+    /// Whether the test ran on a CUDA device, rather than on the CPU fallback
+    pub cuda: bool,
+    pub devices: Vec<CudaDeviceInfo>,
+    /// Devices whose test failed, with why
+    pub failures: Vec<String>,
+}
+
 impl CandleCudaProcessor {
+    /// Initialize if needed, then multiply a small matrix on every device and check the
+    /// product, so a broken driver or device shows up before a real task runs into it
+    pub async fn self_test(&mut self) -> Result<SelfTestReport> {
+        if !self.initialized {
+            self.initialize().await?;
+        }
+        let failures = self.devices.iter()
+            .zip(&self.candle_devices)
+            .filter_map(|(info, device)| check_matmul(device).err().map(|e| format!("{}: {}", info.name, e)))
+            .collect();
+        Ok(SelfTestReport {
+            cuda: self.candle_devices.iter().any(|device| device.is_cuda()),
+            devices: self.devices.clone(),
+            failures,
+        })
+    }
+
     fn get_cuda_device_info(&self, device_id: u32) -> Result<CudaDeviceInfo> {
         // This would use cudarc or similar to get actual device properties
         // For now, returning placeholder data
@@ -293,9 +602,74 @@ impl CandleCudaProcessor {
     }
 
     async fn load_image_model(&self, model_path: &str, device: &candle_core::Device) -> Result<Box<dyn ImageModel + Send + Sync>> {
+        let model_path = &self.resolve_model_path(model_path).await?;
         info!("Loading image model from: {}", model_path);
         Ok(Box::new(ClipModel::load(model_path, device.clone()).await?))
     }
+
+    async fn load_detection_model(&self, model_path: &str, device: &candle_core::Device) -> Result<Box<dyn ObjectDetectionModel + Send + Sync>> {
+        let detr = model_path.to_lowercase().contains("detr");
+        let model_path = &self.resolve_model_path(model_path).await?;
+        info!("Loading object detection model from: {}", model_path);
+        if detr {
+            Ok(Box::new(vision::DetrDetector::load(model_path, device.clone()).await?))
+        } else {
+            Ok(Box::new(vision::YoloV8Detector::load(model_path, device.clone()).await?))
+        }
+    }
+
+    async fn load_ocr_model(&self, model_path: &str, device: &candle_core::Device) -> Result<Box<dyn OcrModel + Send + Sync>> {
+        let detector = Box::new(vision::ProjectionTextDetector::default());
+        if model_path.starts_with("ort:") || model_path.ends_with(".onnx") {
+            info!("Loading ONNX text recognizer from: {}", model_path);
+            let recognizer = vision::OrtTextRecognizer::load(model_path).await?;
+            return Ok(Box::new(OcrPipeline::new(detector, Box::new(recognizer))));
+        }
+        let model_path = &self.resolve_model_path(model_path).await?;
+        info!("Loading TrOCR text recognizer from: {}", model_path);
+        let recognizer = vision::TrOcrRecognizer::load(model_path, device.clone()).await?;
+        Ok(Box::new(OcrPipeline::new(detector, Box::new(recognizer))))
+    }
+
+    async fn load_multimodal_model(&self, model_path: &str, device: &candle_core::Device) -> Result<Box<dyn MultimodalEmbeddingModel + Send + Sync>> {
+        let model_path = &self.resolve_model_path(model_path).await?;
+        info!("Loading multimodal model from: {}", model_path);
+        Ok(Box::new(ClipModel::load(model_path, device.clone()).await?))
+    }
+}
+
+/// Where a task ran, read while its model is still loaded
+async fn placement(lease: Option<&DeviceLease>) -> Option<Placement> {
+    match lease {
+        Some(lease) => Some(lease.placement().await),
+        None => None,
+    }
+}
+
+fn memory_used_mb(placement: Option<&Placement>) -> u64 {
+    placement.and_then(|placement| placement.actual_memory_mb).unwrap_or(0)
+}
+
+/// Total size of the files under `path`
+fn disk_size(path: &std::path::Path) -> u64 {
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    std::fs::read_dir(path)
+        .map(|entries| entries.flatten().map(|entry| disk_size(&entry.path())).sum())
+        .unwrap_or(0)
+}
+
+fn check_matmul(device: &candle_core::Device) -> Result<()> {
+    let matrix = candle_core::Tensor::new(&[[1f32, 2.], [3., 4.]], device)?;
+    let product = matrix.matmul(&matrix)?.to_vec2::<f32>()?;
+    if product != [[7., 10.], [15., 22.]] {
+        return Err(anyhow::anyhow!("2x2 matrix product came back as {:?}", product));
+    }
+    Ok(())
 }
 
 // Model interfaces and implementations
@@ -305,12 +679,6 @@ pub trait EmbeddingModel {
     async fn embed_batch(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>>;
 }
 
-#[async_trait]
-pub trait LanguageModel {
-    async fn generate(&self, prompt: &str, max_tokens: usize) -> Result<String>;
-    async fn generate_stream(&self, prompt: &str, max_tokens: usize) -> Result<tokio::sync::mpsc::Receiver<String>>;
-}
-
 #[async_trait]
 pub trait ImageModel {
     async fn process_image(&self, image_data: Vec<u8>) -> Result<ImageProcessingResult>;
@@ -563,6 +931,29 @@ impl ImageModel for ClipModel {
     }
 }
 
+/// Encoded image formats the vision tower decodes, by their leading bytes
+const IMAGE_SIGNATURES: &[&[u8]] = &[b"\x89PNG\r\n\x1a\n", b"\xff\xd8\xff", b"GIF87a", b"GIF89a", b"BM"];
+
+#[async_trait]
+impl MultimodalEmbeddingModel for ClipModel {
+    async fn embed_texts(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        info!("CLIP text tower embedding {} texts", texts.len());
+        Ok(texts.iter().map(|_| vec![0.1; 512]).collect()) // Placeholder projection
+    }
+
+    async fn embed_images(&self, images: Vec<Vec<u8>>) -> Result<Vec<Vec<f32>>> {
+        info!("CLIP vision tower embedding {} images", images.len());
+        images.iter()
+            .map(|image| {
+                if !IMAGE_SIGNATURES.iter().any(|signature| image.starts_with(signature)) {
+                    return Err(anyhow::anyhow!("Unrecognized or corrupt image ({} bytes)", image.len()));
+                }
+                Ok(vec![0.5; 512]) // Placeholder projection
+            })
+            .collect()
+    }
+}
+
 /// CUDA Memory Manager
 pub struct CudaMemoryManager {
     device_id: u32,
@@ -611,6 +1002,15 @@ impl CudaMemoryManager {
     }
 }
 
+/// Lets an Ollama server on this host release idle models when the device runs short
+#[cfg(feature = "ollama")]
+#[async_trait]
+impl talkpp_ollama_integration::VramProbe for CudaMemoryManager {
+    async fn free_vram(&self) -> Result<u64> {
+        Ok(self.get_memory_info().await?.free_memory)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CudaMemoryInfo {
     pub device_id: u32,
@@ -627,4 +1027,29 @@ pub struct CudaMemoryBlock {
     pub device_id: u32,
     pub size: u64,
     pub allocated_at: chrono::DateTime<chrono::Utc>,
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_configs_are_rejected_with_every_violation() {
+        let config = || MlTaskConfig::new(MlTaskType::TextEmbedding);
+        let custom = |name: &str| MlTaskConfig::new(MlTaskType::Custom { name: name.to_string(), description: String::new() });
+        let cases = [
+            (custom(" "), custom("rerank"), "task_type.name"),
+            (config().with_model_path(""), config().with_model_path("sentence-transformers/all-MiniLM-L6-v2"), "model_path"),
+            (config().with_batch_size(0), config().with_batch_size(1), "batch_size"),
+            (MlTaskConfig { use_cuda: false, ..config().with_device(1) }, config().with_device(1), "device_id"),
+        ];
+        for (failing, passing, field) in cases {
+            assert_eq!(failing.violations().fields(), [field]);
+            assert!(passing.validate().is_ok(), "{}", field);
+        }
+
+        let err = config().with_batch_size(0).with_model_path(" ").validate().unwrap_err();
+        assert_eq!(err.kind(), talkpp_errors::ErrorKind::InvalidInput);
+        assert_eq!(err.to_string(), "Invalid ML task config: model_path: must not be empty when set; batch_size: must be at least 1");
+    }
+}
@@ -0,0 +1,212 @@
+//! Text and image embeddings in one shared vector space
+//!
+//! A multimodal model such as CLIP embeds text with its text tower and images with its
+//! vision tower, so a caption and a matching photo land close together. Batches may
+//! interleave both: [`embed_multimodal`] groups the items by modality, runs each group
+//! through its tower in chunks of the configured batch size, and puts the vectors back in
+//! input order, each tagged with its modality and index.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// One item of a multimodal embedding batch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum EmbeddingInput {
+    Text(String),
+    /// Encoded image bytes, such as a PNG or JPEG file
+    Image(Vec<u8>),
+}
+
+impl EmbeddingInput {
+    pub fn modality(&self) -> Modality {
+        match self {
+            EmbeddingInput::Text(_) => Modality::Text,
+            EmbeddingInput::Image(_) => Modality::Image,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Modality {
+    Text,
+    Image,
+}
+
+/// The embedding of the input at `index`, or why it could not be embedded
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MultimodalEmbedding {
+    pub index: usize,
+    pub modality: Modality,
+    pub vector: Option<Vec<f32>>,
+    pub error: Option<String>,
+}
+
+/// A model with a text tower and a vision tower projecting into the same space
+#[async_trait]
+pub trait MultimodalEmbeddingModel {
+    async fn embed_texts(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>>;
+    async fn embed_images(&self, images: Vec<Vec<u8>>) -> Result<Vec<Vec<f32>>>;
+}
+
+/// Embed `items` with `model`, `batch_size` items of a modality at a time, returning one
+/// entry per item in input order.
+///
+/// Without `best_effort` the first failure fails the whole call. With it, a chunk that
+/// fails is retried one item at a time, so only the items that fail on their own, such
+/// as a corrupt image, are reported with an error.
+pub async fn embed_multimodal(
+    model: &(dyn MultimodalEmbeddingModel + Send + Sync),
+    items: Vec<EmbeddingInput>,
+    batch_size: usize,
+    best_effort: bool,
+) -> Result<Vec<MultimodalEmbedding>> {
+    let (texts, images): (Vec<_>, Vec<_>) = items.into_iter()
+        .enumerate()
+        .partition(|(_, item)| item.modality() == Modality::Text);
+
+    let mut embeddings = Vec::with_capacity(texts.len() + images.len());
+    for group in [texts, images] {
+        embeddings.extend(embed_group(model, group, batch_size, best_effort).await?);
+    }
+    embeddings.sort_by_key(|embedding| embedding.index);
+    Ok(embeddings)
+}
+
+/// Embed `(index, input)` pairs of a single modality in chunks of `batch_size`
+async fn embed_group(
+    model: &(dyn MultimodalEmbeddingModel + Send + Sync),
+    inputs: Vec<(usize, EmbeddingInput)>,
+    batch_size: usize,
+    best_effort: bool,
+) -> Result<Vec<MultimodalEmbedding>> {
+    let mut embeddings = Vec::with_capacity(inputs.len());
+    for chunk in inputs.chunks(batch_size.max(1)) {
+        let batch = chunk.iter().map(|(_, input)| input.clone()).collect();
+        let error = match embed_batch(model, batch).await {
+            Ok(vectors) => {
+                embeddings.extend(chunk.iter().zip(vectors).map(|((index, input), vector)| embedded(*index, input, vector)));
+                continue;
+            }
+            Err(e) => e,
+        };
+        if !best_effort {
+            return Err(error);
+        }
+
+        warn!("Embedding a batch of {} inputs failed, retrying them one at a time: {}", chunk.len(), error);
+        for (index, input) in chunk {
+            let embedding = match embed_batch(model, vec![input.clone()]).await {
+                Ok(mut vectors) => embedded(*index, input, vectors.remove(0)),
+                Err(e) => failed(*index, input.modality(), e.to_string()),
+            };
+            embeddings.push(embedding);
+        }
+    }
+    Ok(embeddings)
+}
+
+/// Embed inputs of a single modality with that modality's tower, checking that every
+/// input got a vector
+async fn embed_batch(model: &(dyn MultimodalEmbeddingModel + Send + Sync), inputs: Vec<EmbeddingInput>) -> Result<Vec<Vec<f32>>> {
+    let count = inputs.len();
+    let (texts, images): (Vec<_>, Vec<_>) = inputs.into_iter().partition(|input| input.modality() == Modality::Text);
+    let vectors = if images.is_empty() {
+        let texts = texts.into_iter().filter_map(|input| match input {
+            EmbeddingInput::Text(text) => Some(text),
+            EmbeddingInput::Image(_) => None,
+        });
+        model.embed_texts(texts.collect()).await?
+    } else if texts.is_empty() {
+        let images = images.into_iter().filter_map(|input| match input {
+            EmbeddingInput::Image(image) => Some(image),
+            EmbeddingInput::Text(_) => None,
+        });
+        model.embed_images(images.collect()).await?
+    } else {
+        anyhow::bail!("A batch sent to one tower must hold a single modality");
+    };
+    if vectors.len() != count {
+        anyhow::bail!("Model returned {} embeddings for {} inputs", vectors.len(), count);
+    }
+    Ok(vectors)
+}
+
+fn embedded(index: usize, input: &EmbeddingInput, vector: Vec<f32>) -> MultimodalEmbedding {
+    MultimodalEmbedding { index, modality: input.modality(), vector: Some(vector), error: None }
+}
+
+fn failed(index: usize, modality: Modality, error: String) -> MultimodalEmbedding {
+    MultimodalEmbedding { index, modality, vector: None, error: Some(error) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Text embeds to its length, images to their first byte; empty images are corrupt
+    #[derive(Default)]
+    struct Towers {
+        batches: Mutex<Vec<(Modality, usize)>>,
+    }
+
+    #[async_trait]
+    impl MultimodalEmbeddingModel for Towers {
+        async fn embed_texts(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+            self.batches.lock().unwrap().push((Modality::Text, texts.len()));
+            Ok(texts.iter().map(|text| vec![text.len() as f32]).collect())
+        }
+
+        async fn embed_images(&self, images: Vec<Vec<u8>>) -> Result<Vec<Vec<f32>>> {
+            self.batches.lock().unwrap().push((Modality::Image, images.len()));
+            images.iter()
+                .map(|image| image.first().map(|byte| vec![*byte as f32]).ok_or_else(|| anyhow::anyhow!("corrupt image")))
+                .collect()
+        }
+    }
+
+    fn interleaved() -> Vec<EmbeddingInput> {
+        vec![
+            EmbeddingInput::Image(vec![7]),
+            EmbeddingInput::Text("a cat".to_string()),
+            EmbeddingInput::Image(vec![9]),
+            EmbeddingInput::Text("dog".to_string()),
+            EmbeddingInput::Text("a red bicycle".to_string()),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_interleaved_inputs_come_back_in_order() {
+        let model = Towers::default();
+        let embeddings = embed_multimodal(&model, interleaved(), 2, false).await.unwrap();
+
+        let tagged: Vec<_> = embeddings.iter().map(|e| (e.index, e.modality, e.vector.clone().unwrap()[0])).collect();
+        assert_eq!(tagged, vec![
+            (0, Modality::Image, 7.0),
+            (1, Modality::Text, 5.0),
+            (2, Modality::Image, 9.0),
+            (3, Modality::Text, 3.0),
+            (4, Modality::Text, 13.0),
+        ]);
+        // Each tower saw only its own modality, in chunks of the batch size
+        assert_eq!(*model.batches.lock().unwrap(), vec![(Modality::Text, 2), (Modality::Text, 1), (Modality::Image, 2)]);
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_items_fail_alone_when_best_effort() {
+        let mut items = interleaved();
+        items.insert(3, EmbeddingInput::Image(Vec::new()));
+
+        let err = embed_multimodal(&Towers::default(), items.clone(), 4, false).await.unwrap_err();
+        assert_eq!(err.to_string(), "corrupt image");
+
+        let embeddings = embed_multimodal(&Towers::default(), items, 4, true).await.unwrap();
+        assert_eq!(embeddings.iter().map(|e| e.index).collect::<Vec<_>>(), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(embeddings[3], failed(3, Modality::Image, "corrupt image".to_string()));
+        assert_eq!(embeddings[2].vector, Some(vec![9.0]));
+        assert!(embeddings.iter().enumerate().all(|(i, e)| (i == 3) == e.vector.is_none()));
+    }
+}
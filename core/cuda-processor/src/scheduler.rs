@@ -0,0 +1,403 @@
+//! Placement of ML tasks on devices by the memory they need
+//!
+//! Each task's memory is estimated from the size of its model on disk, its precision
+//! and its batch size. The scheduler reserves that much on the device with room for it
+//! that is running the fewest tasks, and gives it back when the task's lease is dropped.
+//! A task no device has room for waits for one to free up, within a bounded queue and
+//! a timeout. A task pinned to a device runs there or fails straight away.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::Notify;
+
+use crate::{CudaDeviceInfo, ModelPrecision};
+
+/// Tasks that may wait for memory at once before more are turned away
+pub const DEFAULT_MAX_QUEUED: usize = 16;
+
+/// How long a task waits for memory before it fails
+pub const DEFAULT_QUEUE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Activations and buffers per item of a batch, on top of the weights
+const BATCH_ITEM_OVERHEAD: u64 = 16 * 1024 * 1024;
+
+/// Runtime overhead on the weights: CUDA context, allocator slack and the like
+const WEIGHT_OVERHEAD: f64 = 1.2;
+
+/// Bytes a task needs on its device: weights stored as 32-bit floats scaled to the
+/// task's precision, plus the runtime's overhead and that of each batch item
+pub fn estimate_memory(model_bytes: u64, precision: &ModelPrecision, batch_size: usize) -> u64 {
+    let factor = match precision {
+        ModelPrecision::Float32 => 1.0,
+        ModelPrecision::Float16 => 0.5,
+        ModelPrecision::Int8 => 0.25,
+        ModelPrecision::Int4 => 0.125,
+    };
+    (model_bytes as f64 * factor * WEIGHT_OVERHEAD) as u64 + batch_size as u64 * BATCH_ITEM_OVERHEAD
+}
+
+/// Reads how much memory is free on a device, to measure what a task really used
+#[async_trait]
+pub trait DeviceMemoryProbe: Send + Sync {
+    async fn free_memory(&self, device_id: u32) -> Result<u64>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum SchedulerError {
+    #[error("Device {0} is not available")]
+    UnknownDevice(u32),
+
+    #[error("Task is pinned to device {device_id}, which has {available} bytes free but the task needs an estimated {required}")]
+    PinnedDeviceFull { device_id: u32, required: u64, available: u64 },
+
+    #[error("No device can hold the estimated {required} bytes the task needs; the largest has {largest}")]
+    TooLarge { required: u64, largest: u64 },
+
+    #[error("{queued} tasks are already waiting for device memory")]
+    QueueFull { queued: usize },
+
+    #[error("No device freed the estimated {required} bytes the task needs within {waited:?}")]
+    Timeout { required: u64, waited: Duration },
+}
+
+/// Limits on tasks waiting for memory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SchedulerConfig {
+    pub max_queued: usize,
+    pub queue_timeout: Duration,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self { max_queued: DEFAULT_MAX_QUEUED, queue_timeout: DEFAULT_QUEUE_TIMEOUT }
+    }
+}
+
+/// Where a task ran and what it was expected to need, reported in its result so the
+/// estimates can be tuned
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Placement {
+    pub device_id: u32,
+    /// The task named the device rather than letting the scheduler choose
+    pub pinned: bool,
+    pub estimated_memory_mb: u64,
+    /// Free memory the task took, when the scheduler has a probe to measure it with
+    pub actual_memory_mb: Option<u64>,
+    /// Time spent waiting for a device with room
+    pub queued_ms: u64,
+}
+
+#[derive(Debug)]
+struct DeviceSlot {
+    device_id: u32,
+    /// Free memory less what running tasks reserved
+    available: u64,
+    /// Free memory with nothing reserved, the most a task can ever get
+    capacity: u64,
+    running: usize,
+}
+
+struct Inner {
+    slots: Mutex<Vec<DeviceSlot>>,
+    released: Notify,
+    queued: AtomicUsize,
+    config: SchedulerConfig,
+    probe: Option<Arc<dyn DeviceMemoryProbe>>,
+}
+
+/// Shares devices' memory among the tasks placed on them. Clones share the same
+/// reservations.
+#[derive(Clone)]
+pub struct DeviceScheduler {
+    inner: Arc<Inner>,
+}
+
+impl std::fmt::Debug for DeviceScheduler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DeviceScheduler")
+            .field("devices", &self.inner.slots.lock().unwrap())
+            .field("config", &self.inner.config)
+            .finish()
+    }
+}
+
+impl DeviceScheduler {
+    /// Schedule onto `devices`, each starting with its reported free memory
+    pub fn new(devices: &[CudaDeviceInfo], config: SchedulerConfig) -> Self {
+        let slots = devices.iter()
+            .map(|device| DeviceSlot {
+                device_id: device.device_id,
+                available: device.memory_free,
+                capacity: device.memory_free,
+                running: 0,
+            })
+            .collect();
+        Self {
+            inner: Arc::new(Inner {
+                slots: Mutex::new(slots),
+                released: Notify::new(),
+                queued: AtomicUsize::new(0),
+                config,
+                probe: None,
+            }),
+        }
+    }
+
+    /// Measure the memory each task really takes with `probe`. Set it before the
+    /// scheduler is cloned.
+    pub fn with_probe(mut self, probe: Arc<dyn DeviceMemoryProbe>) -> Self {
+        if let Some(inner) = Arc::get_mut(&mut self.inner) {
+            inner.probe = Some(probe);
+        }
+        self
+    }
+
+    /// Reserve `required` bytes on `pin`, or otherwise on the least busy device with
+    /// room, waiting for one when none has room yet
+    pub async fn acquire(&self, required: u64, pin: Option<u32>) -> Result<DeviceLease, SchedulerError> {
+        let started = Instant::now();
+        if let Some(device_id) = pin {
+            self.reserve_on(device_id, required)?;
+            return Ok(self.lease(device_id, required, true, started).await);
+        }
+
+        let largest = self.inner.slots.lock().unwrap().iter().map(|slot| slot.capacity).max().unwrap_or(0);
+        if required > largest {
+            return Err(SchedulerError::TooLarge { required, largest });
+        }
+
+        let deadline = tokio::time::Instant::from_std(started + self.inner.config.queue_timeout);
+        let mut waiting: Option<QueueSlot> = None;
+        loop {
+            // Registered before looking, so a release in between is not missed
+            let released = self.inner.released.notified();
+            tokio::pin!(released);
+            released.as_mut().enable();
+
+            if let Some(device_id) = self.place(required) {
+                drop(waiting);
+                return Ok(self.lease(device_id, required, false, started).await);
+            }
+            if waiting.is_none() {
+                waiting = Some(QueueSlot::join(&self.inner)?);
+                tracing::debug!("Waiting for a device with {} bytes free", required);
+            }
+            if tokio::time::timeout_at(deadline, released).await.is_err() {
+                return Err(SchedulerError::Timeout { required, waited: started.elapsed() });
+            }
+        }
+    }
+
+    /// Tasks running on each device, by device id
+    pub fn running(&self) -> Vec<(u32, usize)> {
+        self.inner.slots.lock().unwrap().iter().map(|slot| (slot.device_id, slot.running)).collect()
+    }
+
+    fn reserve_on(&self, device_id: u32, required: u64) -> Result<(), SchedulerError> {
+        let mut slots = self.inner.slots.lock().unwrap();
+        let slot = slots.iter_mut()
+            .find(|slot| slot.device_id == device_id)
+            .ok_or(SchedulerError::UnknownDevice(device_id))?;
+        if slot.available < required {
+            return Err(SchedulerError::PinnedDeviceFull { device_id, required, available: slot.available });
+        }
+        slot.available -= required;
+        slot.running += 1;
+        Ok(())
+    }
+
+    /// Reserve `required` on the device with room running the fewest tasks, the one with
+    /// the most memory left among those
+    fn place(&self, required: u64) -> Option<u32> {
+        let mut slots = self.inner.slots.lock().unwrap();
+        let slot = slots.iter_mut()
+            .filter(|slot| slot.available >= required)
+            .min_by_key(|slot| (slot.running, std::cmp::Reverse(slot.available)))?;
+        slot.available -= required;
+        slot.running += 1;
+        Some(slot.device_id)
+    }
+
+    /// Lease for memory already reserved on the device
+    async fn lease(&self, device_id: u32, required: u64, pinned: bool, started: Instant) -> DeviceLease {
+        let free_before = match &self.inner.probe {
+            Some(probe) => probe.free_memory(device_id).await.ok(),
+            None => None,
+        };
+        DeviceLease {
+            inner: self.inner.clone(),
+            device_id,
+            reserved: required,
+            pinned,
+            queued: started.elapsed(),
+            free_before,
+        }
+    }
+}
+
+/// Counts a task as waiting for as long as it is held
+struct QueueSlot(Arc<Inner>);
+
+impl QueueSlot {
+    fn join(inner: &Arc<Inner>) -> Result<Self, SchedulerError> {
+        let queued = inner.queued.fetch_add(1, Ordering::SeqCst);
+        if queued >= inner.config.max_queued {
+            inner.queued.fetch_sub(1, Ordering::SeqCst);
+            return Err(SchedulerError::QueueFull { queued });
+        }
+        Ok(Self(inner.clone()))
+    }
+}
+
+impl Drop for QueueSlot {
+    fn drop(&mut self) {
+        self.0.queued.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Memory reserved on a device for one task, given back when dropped
+pub struct DeviceLease {
+    inner: Arc<Inner>,
+    device_id: u32,
+    reserved: u64,
+    pinned: bool,
+    queued: Duration,
+    free_before: Option<u64>,
+}
+
+impl DeviceLease {
+    pub fn device_id(&self) -> u32 {
+        self.device_id
+    }
+
+    /// The decision behind the lease. Call it while the task's model is still loaded, so
+    /// the probe sees the memory the task took.
+    pub async fn placement(&self) -> Placement {
+        let actual = match (&self.inner.probe, self.free_before) {
+            (Some(probe), Some(before)) => probe.free_memory(self.device_id).await.ok().map(|now| before.saturating_sub(now)),
+            _ => None,
+        };
+        Placement {
+            device_id: self.device_id,
+            pinned: self.pinned,
+            estimated_memory_mb: self.reserved / (1024 * 1024),
+            actual_memory_mb: actual.map(|bytes| bytes / (1024 * 1024)),
+            queued_ms: self.queued.as_millis() as u64,
+        }
+    }
+}
+
+impl Drop for DeviceLease {
+    fn drop(&mut self) {
+        let mut slots = self.inner.slots.lock().unwrap();
+        if let Some(slot) = slots.iter_mut().find(|slot| slot.device_id == self.device_id) {
+            slot.available = (slot.available + self.reserved).min(slot.capacity);
+            slot.running -= 1;
+        }
+        drop(slots);
+        self.inner.released.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GIB: u64 = 1024 * 1024 * 1024;
+
+    fn device(device_id: u32, free: u64) -> CudaDeviceInfo {
+        CudaDeviceInfo {
+            device_id,
+            name: format!("Fake {}", device_id),
+            memory_total: free,
+            memory_free: free,
+            compute_capability: (8, 6),
+            multiprocessor_count: 1,
+            max_threads_per_block: 1024,
+        }
+    }
+
+    /// A 24GB card and a 4GB one
+    fn scheduler(config: SchedulerConfig) -> DeviceScheduler {
+        DeviceScheduler::new(&[device(0, 4 * GIB), device(1, 24 * GIB)], config)
+    }
+
+    #[test]
+    fn test_estimate_scales_weights_by_precision_and_adds_batches() {
+        let seven_b = 28 * GIB;
+        assert!(estimate_memory(seven_b, &ModelPrecision::Float16, 1) > 16 * GIB);
+        assert!(estimate_memory(seven_b, &ModelPrecision::Int4, 1) < 5 * GIB);
+        assert_eq!(estimate_memory(0, &ModelPrecision::Float32, 4), 4 * BATCH_ITEM_OVERHEAD);
+    }
+
+    #[tokio::test]
+    async fn test_large_tasks_go_to_the_big_card_and_small_ones_to_the_idle_card() {
+        let scheduler = scheduler(SchedulerConfig::default());
+        let language_model = scheduler.acquire(14 * GIB, None).await.unwrap();
+        assert_eq!(language_model.device_id(), 1);
+
+        // The big card is busy, so embeddings go to the small one
+        let embeddings = scheduler.acquire(GIB, None).await.unwrap();
+        assert_eq!(embeddings.device_id(), 0);
+        assert_eq!(scheduler.running(), vec![(0, 1), (1, 1)]);
+
+        let placement = language_model.placement().await;
+        assert_eq!((placement.device_id, placement.pinned, placement.estimated_memory_mb), (1, false, 14 * 1024));
+        assert_eq!(placement.actual_memory_mb, None);
+
+        assert!(matches!(scheduler.acquire(30 * GIB, None).await, Err(SchedulerError::TooLarge { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_tasks_wait_for_memory_within_the_queue_limits() {
+        let scheduler = scheduler(SchedulerConfig { max_queued: 1, queue_timeout: Duration::from_secs(5) });
+        let first = scheduler.acquire(20 * GIB, None).await.unwrap();
+
+        let waiting = tokio::spawn({
+            let scheduler = scheduler.clone();
+            async move { scheduler.acquire(20 * GIB, None).await.map(|lease| lease.device_id()) }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(matches!(scheduler.acquire(20 * GIB, None).await, Err(SchedulerError::QueueFull { queued: 1 })));
+
+        drop(first);
+        assert_eq!(waiting.await.unwrap().unwrap(), 1);
+
+        let scheduler = self::scheduler(SchedulerConfig { max_queued: 1, queue_timeout: Duration::from_millis(30) });
+        let _held = scheduler.acquire(20 * GIB, None).await.unwrap();
+        assert!(matches!(scheduler.acquire(20 * GIB, None).await, Err(SchedulerError::Timeout { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_pinned_tasks_bypass_the_scheduler_and_fail_when_they_do_not_fit() {
+        struct Probe;
+
+        #[async_trait]
+        impl DeviceMemoryProbe for Probe {
+            async fn free_memory(&self, _device_id: u32) -> Result<u64> {
+                Ok(3 * GIB)
+            }
+        }
+
+        let scheduler = scheduler(SchedulerConfig::default());
+        // Would have gone to the big card
+        let pinned = scheduler.acquire(GIB, Some(0)).await.unwrap();
+        assert_eq!(pinned.device_id(), 0);
+        assert!(pinned.placement().await.pinned);
+
+        assert_eq!(
+            scheduler.acquire(8 * GIB, Some(0)).await.unwrap_err(),
+            SchedulerError::PinnedDeviceFull { device_id: 0, required: 8 * GIB, available: 3 * GIB },
+        );
+        assert_eq!(scheduler.acquire(GIB, Some(7)).await.unwrap_err(), SchedulerError::UnknownDevice(7));
+
+        let probed = DeviceScheduler::new(&[device(0, 4 * GIB)], SchedulerConfig::default()).with_probe(Arc::new(Probe));
+        let lease = probed.acquire(GIB, None).await.unwrap();
+        assert_eq!(lease.placement().await.actual_memory_mb, Some(0));
+    }
+}
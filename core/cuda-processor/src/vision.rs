@@ -0,0 +1,719 @@
+//! Object detection and OCR on single images
+//!
+//! Detection models report labeled boxes in the pixel coordinates of the image they were
+//! given, whatever size they run at: a YOLOv8 head is decoded from its letterboxed square
+//! input and thinned out with non-maximum suppression, a DETR head from its normalized
+//! boxes. OCR runs in two stages: a [`TextDetector`] finds the lines of text, then a
+//! [`TextRecognizer`] such as TrOCR reads each line's crop. Lines come back in reading
+//! order, top to bottom and left to right.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use image::{DynamicImage, GenericImageView, GrayImage};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+/// Side of the square input YOLOv8 runs at
+pub const YOLO_INPUT_SIZE: u32 = 640;
+
+/// Boxes a YOLOv8 head proposes for a 640×640 input, over its three strides
+const YOLO_ANCHORS: usize = 8400;
+
+/// Queries a DETR decoder answers, each with one box
+const DETR_QUERIES: usize = 100;
+
+/// Detections below this confidence are dropped
+pub const DEFAULT_CONFIDENCE_THRESHOLD: f32 = 0.25;
+
+/// Of two boxes of a class overlapping more than this, only the more confident is kept
+pub const DEFAULT_IOU_THRESHOLD: f32 = 0.45;
+
+/// Classes of the COCO dataset, in the order detection heads trained on it score them
+pub const COCO_LABELS: &[&str] = &[
+    "person", "bicycle", "car", "motorcycle", "airplane", "bus", "train", "truck", "boat", "traffic light",
+    "fire hydrant", "stop sign", "parking meter", "bench", "bird", "cat", "dog", "horse", "sheep", "cow",
+    "elephant", "bear", "zebra", "giraffe", "backpack", "umbrella", "handbag", "tie", "suitcase", "frisbee",
+    "skis", "snowboard", "sports ball", "kite", "baseball bat", "baseball glove", "skateboard", "surfboard",
+    "tennis racket", "bottle", "wine glass", "cup", "fork", "knife", "spoon", "bowl", "banana", "apple",
+    "sandwich", "orange", "broccoli", "carrot", "hot dog", "pizza", "donut", "cake", "chair", "couch",
+    "potted plant", "bed", "dining table", "toilet", "tv", "laptop", "mouse", "remote", "keyboard", "cell phone",
+    "microwave", "oven", "toaster", "sink", "refrigerator", "book", "clock", "vase", "scissors", "teddy bear",
+    "hair drier", "toothbrush",
+];
+
+/// Axis-aligned box in pixels, measured from the image's top-left corner
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BoundingBox {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl BoundingBox {
+    /// The box of the given size centered on `(cx, cy)`
+    pub fn from_center(cx: f32, cy: f32, width: f32, height: f32) -> Self {
+        Self { x: cx - width / 2.0, y: cy - height / 2.0, width, height }
+    }
+
+    pub fn area(&self) -> f32 {
+        self.width.max(0.0) * self.height.max(0.0)
+    }
+
+    /// Intersection over union with `other`: 0 for disjoint boxes, 1 for equal ones
+    pub fn iou(&self, other: &Self) -> f32 {
+        let width = (self.x + self.width).min(other.x + other.width) - self.x.max(other.x);
+        let height = (self.y + self.height).min(other.y + other.height) - self.y.max(other.y);
+        let intersection = width.max(0.0) * height.max(0.0);
+        let union = self.area() + other.area() - intersection;
+        if union > 0.0 { intersection / union } else { 0.0 }
+    }
+
+    /// The part of the box inside a `width`×`height` image
+    pub fn clamp_to(&self, width: u32, height: u32) -> Self {
+        let (right, bottom) = (width as f32, height as f32);
+        let x = self.x.clamp(0.0, right);
+        let y = self.y.clamp(0.0, bottom);
+        Self {
+            x,
+            y,
+            width: (self.x + self.width).clamp(0.0, right) - x,
+            height: (self.y + self.height).clamp(0.0, bottom) - y,
+        }
+    }
+
+    /// Whether the box lies within a `width`×`height` image
+    pub fn is_within(&self, width: u32, height: u32) -> bool {
+        self.x >= 0.0 && self.y >= 0.0 && self.x + self.width <= width as f32 && self.y + self.height <= height as f32
+    }
+}
+
+/// An object found in an image
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Detection {
+    pub label: String,
+    /// From 0 to 1
+    pub confidence: f32,
+    pub bbox: BoundingBox,
+}
+
+/// Objects found in a `width`×`height` image, most confident first
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ObjectDetectionResult {
+    pub width: u32,
+    pub height: u32,
+    pub detections: Vec<Detection>,
+}
+
+/// One line of text read from an image
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TextLine {
+    pub text: String,
+    /// From 0 to 1
+    pub confidence: f32,
+    pub bbox: BoundingBox,
+}
+
+/// Text read from a `width`×`height` image, line by line in reading order
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OcrResult {
+    pub width: u32,
+    pub height: u32,
+    pub lines: Vec<TextLine>,
+    /// The lines' text joined by newlines
+    pub text: String,
+}
+
+impl OcrResult {
+    pub fn new(width: u32, height: u32, lines: Vec<TextLine>) -> Self {
+        let text = lines.iter().map(|line| line.text.as_str()).collect::<Vec<_>>().join("\n");
+        Self { width, height, lines, text }
+    }
+}
+
+#[async_trait]
+pub trait ObjectDetectionModel {
+    async fn detect(&self, image_data: Vec<u8>) -> Result<ObjectDetectionResult>;
+}
+
+#[async_trait]
+pub trait OcrModel {
+    async fn read_text(&self, image_data: Vec<u8>) -> Result<OcrResult>;
+}
+
+/// Finds the lines of text in a grayscale image
+pub trait TextDetector: Send + Sync {
+    fn detect_lines(&self, image: &GrayImage) -> Result<Vec<BoundingBox>>;
+}
+
+/// Reads the single line of text an image crop holds, with its confidence
+#[async_trait]
+pub trait TextRecognizer: Send + Sync {
+    async fn recognize(&self, line: &DynamicImage) -> Result<(String, f32)>;
+}
+
+/// Decode an encoded image such as a PNG or JPEG file
+pub fn decode_image(image_data: &[u8]) -> Result<DynamicImage> {
+    image::load_from_memory(image_data)
+        .with_context(|| format!("Unrecognized or corrupt image ({} bytes)", image_data.len()))
+}
+
+/// Labels a detection model scores: one per line of `labels.txt` in its directory, or
+/// the COCO classes without one
+pub fn model_labels(model_path: &str) -> Result<Vec<String>> {
+    let path = Path::new(model_path).join("labels.txt");
+    if !path.exists() {
+        return Ok(COCO_LABELS.iter().map(|label| label.to_string()).collect());
+    }
+    let labels = std::fs::read_to_string(&path).with_context(|| format!("Reading {}", path.display()))?;
+    Ok(labels.lines().map(str::trim).filter(|label| !label.is_empty()).map(str::to_string).collect())
+}
+
+/// How an image was scaled and padded into a square model input
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Letterbox {
+    pub scale: f32,
+    pub pad_x: f32,
+    pub pad_y: f32,
+}
+
+impl Letterbox {
+    /// Fit a `width`×`height` image into a `size`×`size` square, keeping its aspect ratio
+    /// and centering it
+    pub fn fit(width: u32, height: u32, size: u32) -> Self {
+        let scale = (size as f32 / width as f32).min(size as f32 / height as f32);
+        Self {
+            scale,
+            pad_x: (size as f32 - width as f32 * scale) / 2.0,
+            pad_y: (size as f32 - height as f32 * scale) / 2.0,
+        }
+    }
+
+    /// A box in model input coordinates, in the coordinates of the original image
+    pub fn unmap(&self, bbox: BoundingBox) -> BoundingBox {
+        BoundingBox {
+            x: (bbox.x - self.pad_x) / self.scale,
+            y: (bbox.y - self.pad_y) / self.scale,
+            width: bbox.width / self.scale,
+            height: bbox.height / self.scale,
+        }
+    }
+
+    /// `image` as a `size`×`size` RGB input in channel-major order, scaled to 0..1 and
+    /// padded with grey
+    pub fn apply(&self, image: &DynamicImage, size: u32) -> Vec<f32> {
+        let (width, height) = image.dimensions();
+        let scaled = image.resize_exact(
+            ((width as f32 * self.scale).round() as u32).max(1),
+            ((height as f32 * self.scale).round() as u32).max(1),
+            image::imageops::FilterType::Triangle,
+        ).to_rgb8();
+        let (left, top) = (self.pad_x.floor() as u32, self.pad_y.floor() as u32);
+        let plane = (size * size) as usize;
+        let mut input = vec![0.5; 3 * plane];
+        for (x, y, pixel) in scaled.enumerate_pixels() {
+            let (x, y) = (x + left, y + top);
+            if x >= size || y >= size {
+                continue;
+            }
+            let offset = (y * size + x) as usize;
+            for (channel, value) in pixel.0.iter().enumerate() {
+                input[channel * plane + offset] = *value as f32 / 255.0;
+            }
+        }
+        input
+    }
+}
+
+/// Detections in a YOLOv8 head's output: `4 + labels` rows of `anchors` values, the box
+/// center and size in input pixels followed by each class's score
+pub fn decode_yolo(
+    head: &[f32],
+    anchors: usize,
+    labels: &[String],
+    letterbox: Letterbox,
+    (width, height): (u32, u32),
+    confidence_threshold: f32,
+    iou_threshold: f32,
+) -> Result<Vec<Detection>> {
+    let rows = 4 + labels.len();
+    if head.len() != rows * anchors {
+        anyhow::bail!("YOLOv8 head has {} values, expected {} rows of {} anchors", head.len(), rows, anchors);
+    }
+    let at = |row: usize, anchor: usize| head[row * anchors + anchor];
+
+    let mut candidates = Vec::new();
+    for anchor in 0..anchors {
+        let Some((class, score)) = (0..labels.len())
+            .map(|class| (class, at(4 + class, anchor)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+        else {
+            continue;
+        };
+        if score < confidence_threshold {
+            continue;
+        }
+        let bbox = BoundingBox::from_center(at(0, anchor), at(1, anchor), at(2, anchor), at(3, anchor));
+        let bbox = letterbox.unmap(bbox).clamp_to(width, height);
+        if bbox.area() > 0.0 {
+            candidates.push(Detection { label: labels[class].clone(), confidence: score, bbox });
+        }
+    }
+    Ok(non_max_suppression(candidates, iou_threshold))
+}
+
+/// Detections in a DETR head's output: per query, logits for each label followed by one
+/// for "no object", and a box as normalized center and size
+pub fn decode_detr(
+    logits: &[f32],
+    boxes: &[f32],
+    labels: &[String],
+    (width, height): (u32, u32),
+    confidence_threshold: f32,
+) -> Result<Vec<Detection>> {
+    let classes = labels.len() + 1;
+    let queries = boxes.len() / 4;
+    if boxes.len() != queries * 4 || logits.len() != queries * classes {
+        anyhow::bail!("DETR head has {} logits and {} box values for {} labels", logits.len(), boxes.len(), labels.len());
+    }
+
+    let mut detections = Vec::new();
+    for (query, scores) in logits.chunks(classes).enumerate() {
+        let max = scores.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let exps: Vec<f32> = scores.iter().map(|score| (score - max).exp()).collect();
+        let total: f32 = exps.iter().sum();
+        // The last class is "no object", which is never reported
+        let Some((class, probability)) = exps[..labels.len()].iter()
+            .map(|exp| exp / total)
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+        else {
+            continue;
+        };
+        if probability < confidence_threshold {
+            continue;
+        }
+        let [cx, cy, w, h] = [0, 1, 2, 3].map(|i| boxes[query * 4 + i]);
+        let bbox = BoundingBox::from_center(cx * width as f32, cy * height as f32, w * width as f32, h * height as f32)
+            .clamp_to(width, height);
+        if bbox.area() > 0.0 {
+            detections.push(Detection { label: labels[class].clone(), confidence: probability, bbox });
+        }
+    }
+    detections.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+    Ok(detections)
+}
+
+/// The most confident of `detections`, dropping any that overlaps a more confident one of
+/// its label by more than `iou_threshold`
+pub fn non_max_suppression(mut detections: Vec<Detection>, iou_threshold: f32) -> Vec<Detection> {
+    detections.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+    let mut kept: Vec<Detection> = Vec::new();
+    for detection in detections {
+        let suppressed = kept.iter()
+            .any(|other| other.label == detection.label && other.bbox.iou(&detection.bbox) > iou_threshold);
+        if !suppressed {
+            kept.push(detection);
+        }
+    }
+    kept
+}
+
+/// YOLOv8 detector, run through candle
+pub struct YoloV8Detector {
+    device: candle_core::Device,
+    model_path: String,
+    labels: Vec<String>,
+    confidence_threshold: f32,
+    iou_threshold: f32,
+}
+
+impl YoloV8Detector {
+    pub async fn load(model_path: &str, device: candle_core::Device) -> Result<Self> {
+        Ok(Self {
+            device,
+            model_path: model_path.to_string(),
+            labels: model_labels(model_path)?,
+            confidence_threshold: DEFAULT_CONFIDENCE_THRESHOLD,
+            iou_threshold: DEFAULT_IOU_THRESHOLD,
+        })
+    }
+
+    /// The head's output for a letterboxed input
+    fn forward(&self, input: &[f32]) -> Result<Vec<f32>> {
+        info!("YOLOv8 {} running on {:?} over {} input values", self.model_path, self.device, input.len());
+        // Placeholder head: one confident box of the first class over the input's center
+        let center = YOLO_INPUT_SIZE as f32 / 2.0;
+        let mut head = vec![0.0; (4 + self.labels.len()) * YOLO_ANCHORS];
+        for (row, value) in [center, center, center, center, 0.9].into_iter().enumerate() {
+            head[row * YOLO_ANCHORS] = value;
+        }
+        Ok(head)
+    }
+}
+
+#[async_trait]
+impl ObjectDetectionModel for YoloV8Detector {
+    async fn detect(&self, image_data: Vec<u8>) -> Result<ObjectDetectionResult> {
+        let image = decode_image(&image_data)?;
+        let (width, height) = image.dimensions();
+        let letterbox = Letterbox::fit(width, height, YOLO_INPUT_SIZE);
+        let head = self.forward(&letterbox.apply(&image, YOLO_INPUT_SIZE))?;
+        let detections = decode_yolo(
+            &head,
+            YOLO_ANCHORS,
+            &self.labels,
+            letterbox,
+            (width, height),
+            self.confidence_threshold,
+            self.iou_threshold,
+        )?;
+        Ok(ObjectDetectionResult { width, height, detections })
+    }
+}
+
+/// DETR detector, run through candle
+pub struct DetrDetector {
+    device: candle_core::Device,
+    model_path: String,
+    labels: Vec<String>,
+    confidence_threshold: f32,
+}
+
+impl DetrDetector {
+    pub async fn load(model_path: &str, device: candle_core::Device) -> Result<Self> {
+        Ok(Self {
+            device,
+            model_path: model_path.to_string(),
+            labels: model_labels(model_path)?,
+            confidence_threshold: DEFAULT_CONFIDENCE_THRESHOLD,
+        })
+    }
+
+    /// Class logits and normalized boxes of every query
+    fn forward(&self, image: &DynamicImage) -> Result<(Vec<f32>, Vec<f32>)> {
+        info!("DETR {} running on {:?} over a {:?} image", self.model_path, self.device, image.dimensions());
+        // Placeholder head: the first query finds the first class in the middle of the
+        // image, every other query finds no object
+        let classes = self.labels.len() + 1;
+        let mut logits = vec![0.0; DETR_QUERIES * classes];
+        for query in 0..DETR_QUERIES {
+            logits[query * classes + classes - 1] = 10.0;
+        }
+        logits[0] = 20.0;
+        let mut boxes = vec![0.5; DETR_QUERIES * 4];
+        boxes[2] = 0.25;
+        boxes[3] = 0.5;
+        Ok((logits, boxes))
+    }
+}
+
+#[async_trait]
+impl ObjectDetectionModel for DetrDetector {
+    async fn detect(&self, image_data: Vec<u8>) -> Result<ObjectDetectionResult> {
+        let image = decode_image(&image_data)?;
+        let (width, height) = image.dimensions();
+        let (logits, boxes) = self.forward(&image)?;
+        let detections = decode_detr(&logits, &boxes, &self.labels, (width, height), self.confidence_threshold)?;
+        Ok(ObjectDetectionResult { width, height, detections })
+    }
+}
+
+/// Finds lines of text as bands of rows holding ink, which suits screenshots and scans
+/// of horizontal text on a plain background
+#[derive(Debug, Clone)]
+pub struct ProjectionTextDetector {
+    /// Pixels this far from the background's brightness are ink
+    pub contrast: u8,
+    /// Bands of ink closer than this many rows are one line
+    pub line_gap: u32,
+    /// Pixels added around each line, so the recognizer sees its edges
+    pub padding: u32,
+}
+
+impl Default for ProjectionTextDetector {
+    fn default() -> Self {
+        Self { contrast: 64, line_gap: 2, padding: 2 }
+    }
+}
+
+impl TextDetector for ProjectionTextDetector {
+    fn detect_lines(&self, image: &GrayImage) -> Result<Vec<BoundingBox>> {
+        let (width, height) = image.dimensions();
+        if width == 0 || height == 0 {
+            return Ok(Vec::new());
+        }
+        // The background is whatever brightness most pixels have
+        let mut histogram = [0usize; 256];
+        for pixel in image.pixels() {
+            histogram[pixel.0[0] as usize] += 1;
+        }
+        let background = (0..256).max_by_key(|&value| histogram[value]).unwrap_or(255) as i16;
+        let is_ink = |x: u32, y: u32| (image.get_pixel(x, y).0[0] as i16 - background).unsigned_abs() >= self.contrast as u16;
+
+        // Bands of inked rows, merged across gaps narrower than `line_gap`
+        let mut bands: Vec<(u32, u32)> = Vec::new();
+        for y in (0..height).filter(|&y| (0..width).any(|x| is_ink(x, y))) {
+            match bands.last_mut() {
+                Some((_, end)) if y <= *end + self.line_gap => *end = y,
+                _ => bands.push((y, y)),
+            }
+        }
+
+        let lines = bands.into_iter()
+            .filter_map(|(top, bottom)| {
+                let inked = |x: &u32| (top..=bottom).any(|y| is_ink(*x, y));
+                let left = (0..width).find(inked)?;
+                let right = (0..width).rev().find(inked)?;
+                let bbox = BoundingBox {
+                    x: left.saturating_sub(self.padding) as f32,
+                    y: top.saturating_sub(self.padding) as f32,
+                    width: (right + 1 + self.padding - left.saturating_sub(self.padding)) as f32,
+                    height: (bottom + 1 + self.padding - top.saturating_sub(self.padding)) as f32,
+                };
+                Some(bbox.clamp_to(width, height))
+            })
+            .collect();
+        Ok(lines)
+    }
+}
+
+/// Two-stage OCR: detect the lines, then recognize each line's crop
+pub struct OcrPipeline {
+    detector: Box<dyn TextDetector>,
+    recognizer: Box<dyn TextRecognizer>,
+}
+
+impl OcrPipeline {
+    pub fn new(detector: Box<dyn TextDetector>, recognizer: Box<dyn TextRecognizer>) -> Self {
+        Self { detector, recognizer }
+    }
+}
+
+#[async_trait]
+impl OcrModel for OcrPipeline {
+    async fn read_text(&self, image_data: Vec<u8>) -> Result<OcrResult> {
+        let image = decode_image(&image_data)?;
+        let (width, height) = image.dimensions();
+        let mut boxes: Vec<BoundingBox> = self.detector.detect_lines(&image.to_luma8())?
+            .into_iter()
+            .map(|bbox| bbox.clamp_to(width, height))
+            .filter(|bbox| bbox.width >= 1.0 && bbox.height >= 1.0)
+            .collect();
+        boxes.sort_by(|a, b| a.y.total_cmp(&b.y).then(a.x.total_cmp(&b.x)));
+
+        let mut lines = Vec::with_capacity(boxes.len());
+        for bbox in boxes {
+            let crop = image.crop_imm(bbox.x as u32, bbox.y as u32, bbox.width as u32, bbox.height as u32);
+            let (text, confidence) = self.recognizer.recognize(&crop).await?;
+            let text = text.trim();
+            if !text.is_empty() {
+                lines.push(TextLine { text: text.to_string(), confidence, bbox });
+            }
+        }
+        Ok(OcrResult::new(width, height, lines))
+    }
+}
+
+/// Side of the square crop TrOCR's vision encoder reads a line from
+const TROCR_INPUT_SIZE: u32 = 384;
+
+/// TrOCR line recognizer, run through candle
+pub struct TrOcrRecognizer {
+    device: candle_core::Device,
+    model_path: String,
+}
+
+impl TrOcrRecognizer {
+    pub async fn load(model_path: &str, device: candle_core::Device) -> Result<Self> {
+        Ok(Self { device, model_path: model_path.to_string() })
+    }
+}
+
+#[async_trait]
+impl TextRecognizer for TrOcrRecognizer {
+    async fn recognize(&self, line: &DynamicImage) -> Result<(String, f32)> {
+        let input = line.resize_exact(TROCR_INPUT_SIZE, TROCR_INPUT_SIZE, image::imageops::FilterType::Triangle);
+        info!("TrOCR {} reading a {:?} line on {:?}", self.model_path, input.dimensions(), self.device);
+        Ok(("placeholder text".to_string(), 0.5)) // Placeholder decoding
+    }
+}
+
+/// Line recognizer exported to ONNX, run through ONNX Runtime where candle has no port
+pub struct OrtTextRecognizer {
+    model_path: String,
+}
+
+impl OrtTextRecognizer {
+    pub async fn load(model_path: &str) -> Result<Self> {
+        let model_path = model_path.strip_prefix("ort:").unwrap_or(model_path);
+        Ok(Self { model_path: model_path.to_string() })
+    }
+}
+
+#[async_trait]
+impl TextRecognizer for OrtTextRecognizer {
+    async fn recognize(&self, line: &DynamicImage) -> Result<(String, f32)> {
+        info!("ONNX recognizer {} reading a {:?} line", self.model_path, line.dimensions());
+        Ok(("placeholder text".to_string(), 0.5)) // Placeholder decoding
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: &[u8] = include_bytes!("../fixtures/frame.png");
+    const SCREENSHOT: &[u8] = include_bytes!("../fixtures/screenshot.png");
+
+    fn labels(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn assert_within(result: &ObjectDetectionResult) {
+        for detection in &result.detections {
+            assert!(detection.bbox.is_within(result.width, result.height), "{:?}", detection);
+            assert!(detection.bbox.area() > 0.0, "{:?}", detection);
+            assert!((0.0..=1.0).contains(&detection.confidence), "{:?}", detection);
+        }
+    }
+
+    #[test]
+    fn test_yolo_boxes_are_mapped_back_through_the_letterbox_and_suppressed() {
+        // A 64×48 image scales by 10 into 640×480, centered with 80 rows of padding above
+        let letterbox = Letterbox::fit(64, 48, YOLO_INPUT_SIZE);
+        assert_eq!(letterbox, Letterbox { scale: 10.0, pad_x: 0.0, pad_y: 80.0 });
+
+        let labels = labels(&["person", "dog"]);
+        let anchors = 4;
+        let mut head = vec![0.0; 6 * anchors];
+        let mut propose = |anchor: usize, values: [f32; 6]| {
+            for (row, value) in values.into_iter().enumerate() {
+                head[row * anchors + anchor] = value;
+            }
+        };
+        propose(0, [320.0, 320.0, 100.0, 200.0, 0.9, 0.1]);
+        propose(1, [322.0, 318.0, 100.0, 200.0, 0.7, 0.0]); // Overlaps anchor 0 as the same class
+        propose(2, [322.0, 318.0, 100.0, 200.0, 0.0, 0.6]); // Overlaps it as another class
+        propose(3, [620.0, 90.0, 80.0, 40.0, 0.1, 0.2]); // Below the threshold
+
+        let detections = decode_yolo(&head, anchors, &labels, letterbox, (64, 48), 0.25, 0.45).unwrap();
+        let found: Vec<(&str, f32)> = detections.iter().map(|d| (d.label.as_str(), d.confidence)).collect();
+        assert_eq!(found, [("person", 0.9), ("dog", 0.6)]);
+        assert_eq!(detections[0].bbox, BoundingBox { x: 27.0, y: 14.0, width: 10.0, height: 20.0 });
+
+        assert!(decode_yolo(&head[1..], anchors, &labels, letterbox, (64, 48), 0.25, 0.45).is_err());
+    }
+
+    #[test]
+    fn test_detr_skips_queries_without_an_object() {
+        let labels = labels(&["person", "car"]);
+        let logits = [
+            5.0, 0.0, 0.0, // person
+            0.0, 0.0, 5.0, // no object
+            0.0, 4.0, 0.0, // car, partly outside the image
+        ];
+        let boxes = [0.5, 0.5, 0.2, 0.4, 0.1, 0.1, 0.1, 0.1, 0.95, 0.5, 0.2, 0.2];
+        let detections = decode_detr(&logits, &boxes, &labels, (100, 50), 0.5).unwrap();
+
+        assert_eq!(detections.iter().map(|d| d.label.as_str()).collect::<Vec<_>>(), ["person", "car"]);
+        assert_eq!(detections[0].bbox, BoundingBox { x: 40.0, y: 15.0, width: 20.0, height: 20.0 });
+        assert_eq!(detections[1].bbox, BoundingBox { x: 85.0, y: 20.0, width: 15.0, height: 10.0 });
+        assert!(detections[0].confidence > 0.9);
+    }
+
+    #[tokio::test]
+    async fn test_detectors_report_boxes_inside_the_frame() {
+        for detector in [
+            Box::new(YoloV8Detector::load("yolov8n", candle_core::Device::Cpu).await.unwrap()) as Box<dyn ObjectDetectionModel + Send + Sync>,
+            Box::new(DetrDetector::load("facebook/detr-resnet-50", candle_core::Device::Cpu).await.unwrap()),
+        ] {
+            let result = detector.detect(FRAME.to_vec()).await.unwrap();
+            assert_eq!((result.width, result.height), (64, 48));
+            assert!(!result.detections.is_empty());
+            assert_within(&result);
+            assert!(result.detections.iter().all(|d| COCO_LABELS.contains(&d.label.as_str())));
+        }
+
+        let err = YoloV8Detector::load("yolov8n", candle_core::Device::Cpu).await.unwrap()
+            .detect(b"not an image".to_vec()).await.unwrap_err();
+        assert!(err.to_string().contains("Unrecognized or corrupt image"), "{}", err);
+    }
+
+    #[test]
+    fn test_projection_detector_finds_each_line_of_the_screenshot() {
+        let image = decode_image(SCREENSHOT).unwrap().to_luma8();
+        let detector = ProjectionTextDetector { padding: 0, ..ProjectionTextDetector::default() };
+        assert_eq!(detector.detect_lines(&image).unwrap(), [
+            BoundingBox { x: 10.0, y: 8.0, width: 72.0, height: 6.0 },
+            BoundingBox { x: 10.0, y: 24.0, width: 40.0, height: 6.0 },
+        ]);
+
+        // Padding never reaches past the image
+        let padded = ProjectionTextDetector { padding: 12, ..ProjectionTextDetector::default() }.detect_lines(&image).unwrap();
+        assert!(padded.iter().all(|bbox| bbox.is_within(96, 40)));
+    }
+
+    /// Reads each line as its crop's size
+    struct SizeReader;
+
+    #[async_trait]
+    impl TextRecognizer for SizeReader {
+        async fn recognize(&self, line: &DynamicImage) -> Result<(String, f32)> {
+            let (width, height) = line.dimensions();
+            Ok((format!("{}x{}", width, height), 0.8))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ocr_reads_lines_in_order_with_their_geometry() {
+        let pipeline = OcrPipeline::new(Box::new(ProjectionTextDetector::default()), Box::new(SizeReader));
+        let result = pipeline.read_text(SCREENSHOT.to_vec()).await.unwrap();
+
+        assert_eq!((result.width, result.height), (96, 40));
+        assert_eq!(result.text, "76x10\n44x10");
+        assert_eq!(result.lines[0].bbox, BoundingBox { x: 8.0, y: 6.0, width: 76.0, height: 10.0 });
+        assert!(result.lines.iter().all(|line| line.bbox.is_within(96, 40) && line.confidence == 0.8));
+
+        let trocr = TrOcrRecognizer::load("microsoft/trocr-base-printed", candle_core::Device::Cpu).await.unwrap();
+        let result = OcrPipeline::new(Box::new(ProjectionTextDetector::default()), Box::new(trocr))
+            .read_text(SCREENSHOT.to_vec())
+            .await
+            .unwrap();
+        assert_eq!(result.lines.len(), 2);
+        assert!(result.lines.iter().all(|line| !line.text.is_empty()));
+    }
+}
+
+/// Runs the real detection and OCR models, downloaded into the model cache on first use
+#[cfg(all(test, feature = "vision-models"))]
+mod model_tests {
+    use super::*;
+    use crate::{CandleCudaProcessor, CudaProcessor, MlTaskConfig, MlTaskType};
+
+    async fn process(task_type: MlTaskType, image: &[u8]) -> serde_json::Value {
+        let mut processor = CandleCudaProcessor::new();
+        processor.initialize().await.unwrap();
+        let result = processor.process_image(image.to_vec(), MlTaskConfig::new(task_type)).await.unwrap();
+        assert!(result.success, "{:?}", result.error);
+        result.result
+    }
+
+    #[tokio::test]
+    async fn test_yolo_weights_find_the_figure() {
+        let result: ObjectDetectionResult =
+            serde_json::from_value(process(MlTaskType::ObjectDetection, include_bytes!("../fixtures/frame.png")).await).unwrap();
+        assert_eq!((result.width, result.height), (64, 48));
+        assert!(result.detections.iter().all(|d| d.bbox.is_within(64, 48) && (0.0..=1.0).contains(&d.confidence)));
+    }
+
+    #[tokio::test]
+    async fn test_trocr_weights_read_both_lines() {
+        let result: OcrResult =
+            serde_json::from_value(process(MlTaskType::Ocr, include_bytes!("../fixtures/screenshot.png")).await).unwrap();
+        assert_eq!(result.lines.len(), 2);
+        assert!(result.lines[0].bbox.y < result.lines[1].bbox.y);
+    }
+}
@@ -0,0 +1,414 @@
+//! Error kinds shared by the Talk++ libraries
+//!
+//! Public library APIs return [`Error`]: an [`ErrorKind`] callers can match on, a
+//! message, and optionally the error that caused it. The kind decides how a failure is
+//! reported — the API server maps each kind to an HTTP status and a GraphQL error code —
+//! and whether trying again could help ([`ErrorKind::is_retryable`]).
+//!
+//! Libraries keep using `anyhow` internally and convert at their public boundary. `?`
+//! turns an `anyhow::Error` into an [`ErrorKind::Internal`] error unless a typed
+//! [`Error`] is somewhere in its chain, in which case that error's kind is kept.
+//! [`ResultExt`] classifies other errors explicitly:
+//!
+//! ```
+//! use talkpp_errors::{ErrorKind, ResultExt};
+//!
+//! fn parse_port(value: &str) -> talkpp_errors::Result<u16> {
+//!     value.parse().or_else_error(ErrorKind::InvalidInput, || format!("'{}' is not a port", value))
+//! }
+//!
+//! let err = parse_port("http").unwrap_err();
+//! assert_eq!(err.kind(), ErrorKind::InvalidInput);
+//! assert_eq!(err.to_string(), "'http' is not a port");
+//! ```
+//!
+//! Configurations implement [`Validate`], which reports every broken constraint at once
+//! as an [`ErrorKind::InvalidInput`] error; see [`validation`]. Persisted data written
+//! by older builds is upgraded as it is read; see [`versioning`].
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+pub mod validation;
+pub mod versioning;
+
+pub use validation::{InvalidConfig, Validate, Violation, Violations};
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// What went wrong, in terms a caller can act on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// The request was malformed or failed validation
+    InvalidInput,
+    NotFound,
+    /// Credentials were missing, invalid, or don't allow the operation
+    Unauthorized,
+    /// Too many requests; see [`Error::retry_after`]
+    RateLimited,
+    /// A service this one depends on did not answer in time
+    UpstreamTimeout,
+    /// A service this one depends on could not be reached or failed
+    UpstreamUnavailable,
+    /// The request conflicts with the current state, e.g. a duplicate or a finished session
+    Conflict,
+    Internal,
+}
+
+impl ErrorKind {
+    pub const ALL: [ErrorKind; 8] = [
+        ErrorKind::InvalidInput,
+        ErrorKind::NotFound,
+        ErrorKind::Unauthorized,
+        ErrorKind::RateLimited,
+        ErrorKind::UpstreamTimeout,
+        ErrorKind::UpstreamUnavailable,
+        ErrorKind::Conflict,
+        ErrorKind::Internal,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorKind::InvalidInput => "invalid_input",
+            ErrorKind::NotFound => "not_found",
+            ErrorKind::Unauthorized => "unauthorized",
+            ErrorKind::RateLimited => "rate_limited",
+            ErrorKind::UpstreamTimeout => "upstream_timeout",
+            ErrorKind::UpstreamUnavailable => "upstream_unavailable",
+            ErrorKind::Conflict => "conflict",
+            ErrorKind::Internal => "internal",
+        }
+    }
+
+    /// Whether the same request could succeed if tried again later
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, ErrorKind::RateLimited | ErrorKind::UpstreamTimeout | ErrorKind::UpstreamUnavailable)
+    }
+
+    /// The kind of failure an upstream HTTP service reported with `status`, or `None`
+    /// for statuses that aren't errors
+    pub fn from_upstream_status(status: u16) -> Option<Self> {
+        Some(match status {
+            400 | 422 => ErrorKind::InvalidInput,
+            401 | 403 => ErrorKind::Unauthorized,
+            404 | 410 => ErrorKind::NotFound,
+            409 => ErrorKind::Conflict,
+            429 => ErrorKind::RateLimited,
+            408 | 504 => ErrorKind::UpstreamTimeout,
+            500..=599 => ErrorKind::UpstreamUnavailable,
+            400..=499 => ErrorKind::Internal,
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A failure of a given [`ErrorKind`]
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    message: String,
+    retry_after: Option<Duration>,
+    source: Option<anyhow::Error>,
+    /// The message is the source's own, so the source chain continues below it
+    transparent: bool,
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            retry_after: None,
+            source: None,
+            transparent: false,
+        }
+    }
+
+    /// An error of `kind` that `source` describes: the message is the source's own, and
+    /// the source chain continues below it. Crate error types convert this way.
+    pub fn transparent(kind: ErrorKind, source: impl Into<anyhow::Error>) -> Self {
+        let source = source.into();
+        Self {
+            kind,
+            message: source.to_string(),
+            retry_after: None,
+            source: Some(source),
+            transparent: true,
+        }
+    }
+
+    pub fn invalid_input(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::InvalidInput, message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::NotFound, message)
+    }
+
+    pub fn unauthorized(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Unauthorized, message)
+    }
+
+    pub fn rate_limited(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::RateLimited, message)
+    }
+
+    pub fn upstream_timeout(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::UpstreamTimeout, message)
+    }
+
+    pub fn upstream_unavailable(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::UpstreamUnavailable, message)
+    }
+
+    pub fn conflict(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Conflict, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Internal, message)
+    }
+
+    /// Record the error that caused this one
+    pub fn with_source(mut self, source: impl Into<anyhow::Error>) -> Self {
+        self.source = Some(source.into());
+        self.transparent = false;
+        self
+    }
+
+    /// How long the caller should wait before trying again
+    pub fn with_retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = Some(retry_after);
+        self
+    }
+
+    /// Prefix the message with what was being done when the error happened
+    pub fn context(mut self, context: impl fmt::Display) -> Self {
+        self.message = format!("{}: {}", context, self.message);
+        self
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.retry_after
+    }
+
+    /// The first error of type `E` among this error's causes
+    pub fn downcast_ref<E>(&self) -> Option<&E>
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        let source = self.source.as_ref()?;
+        source.downcast_ref::<E>().or_else(|| source.chain().find_map(|cause| cause.downcast_ref::<E>()))
+    }
+
+    /// Whether an error of type `E` is among this error's causes
+    pub fn is<E>(&self) -> bool
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        self.downcast_ref::<E>().is_some()
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        let source: &(dyn std::error::Error + 'static) = &**self.source.as_ref()?;
+        if self.transparent {
+            source.source()
+        } else {
+            Some(source)
+        }
+    }
+}
+
+/// Keeps the kind of a typed error anywhere in the chain, and otherwise treats the
+/// error as [`ErrorKind::Internal`]. The message is the outermost context's.
+impl From<anyhow::Error> for Error {
+    fn from(error: anyhow::Error) -> Self {
+        // `downcast` would also see through context, which would drop it
+        if error.chain().next().is_some_and(|top| top.is::<Error>()) {
+            return error.downcast::<Error>().expect("the outermost error is a typed Error");
+        }
+        let (kind, retry_after) = classify(&error);
+        Self { retry_after, ..Self::transparent(kind, error) }
+    }
+}
+
+fn classify(error: &anyhow::Error) -> (ErrorKind, Option<Duration>) {
+    for cause in error.chain() {
+        if let Some(typed) = cause.downcast_ref::<Error>() {
+            return (typed.kind, typed.retry_after);
+        }
+        #[cfg(feature = "reqwest")]
+        if let Some(error) = cause.downcast_ref::<reqwest::Error>() {
+            return (reqwest_kind(error), None);
+        }
+    }
+    (ErrorKind::Internal, None)
+}
+
+#[cfg(feature = "reqwest")]
+fn reqwest_kind(error: &reqwest::Error) -> ErrorKind {
+    if error.is_timeout() {
+        return ErrorKind::UpstreamTimeout;
+    }
+    if let Some(kind) = error.status().and_then(|status| ErrorKind::from_upstream_status(status.as_u16())) {
+        return kind;
+    }
+    if error.is_connect() || error.is_request() || error.is_body() {
+        ErrorKind::UpstreamUnavailable
+    } else {
+        ErrorKind::Internal
+    }
+}
+
+#[cfg(feature = "reqwest")]
+impl From<reqwest::Error> for Error {
+    fn from(error: reqwest::Error) -> Self {
+        Self::transparent(reqwest_kind(&error), error)
+    }
+}
+
+/// Classify the error of a `Result` at a public API boundary
+pub trait ResultExt<T> {
+    /// Replace the error with one of `kind`, keeping the original as its source
+    fn or_error(self, kind: ErrorKind, message: impl Into<String>) -> Result<T>;
+
+    /// Like [`or_error`](Self::or_error), building the message only on failure
+    fn or_else_error<M: Into<String>>(self, kind: ErrorKind, message: impl FnOnce() -> M) -> Result<T>;
+
+    /// Convert the error as `?` would and prefix its message with `context`
+    fn error_context(self, context: impl fmt::Display) -> Result<T>;
+}
+
+impl<T, E: Into<anyhow::Error>> ResultExt<T> for std::result::Result<T, E> {
+    fn or_error(self, kind: ErrorKind, message: impl Into<String>) -> Result<T> {
+        self.map_err(|e| Error::new(kind, message).with_source(e))
+    }
+
+    fn or_else_error<M: Into<String>>(self, kind: ErrorKind, message: impl FnOnce() -> M) -> Result<T> {
+        self.map_err(|e| Error::new(kind, message()).with_source(e))
+    }
+
+    fn error_context(self, context: impl fmt::Display) -> Result<T> {
+        self.map_err(|e| Error::from(e.into()).context(context))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[derive(Debug, PartialEq)]
+    struct SessionEnded(&'static str);
+
+    impl fmt::Display for SessionEnded {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "Session {} has ended", self.0)
+        }
+    }
+
+    impl std::error::Error for SessionEnded {}
+
+    fn lookup(id: &str) -> Result<u32> {
+        match id {
+            "a" => Ok(1),
+            "ended" => Err(Error::transparent(ErrorKind::Conflict, SessionEnded("ended"))),
+            "slow" => Err(Error::rate_limited("Slow down").with_retry_after(Duration::from_secs(3))),
+            _ => Err(Error::not_found(format!("No session '{}'", id))),
+        }
+    }
+
+    #[test]
+    fn test_callers_match_on_kind() {
+        let describe = |id| match lookup(id) {
+            Ok(_) => "found",
+            Err(e) => match e.kind() {
+                ErrorKind::NotFound => "missing",
+                ErrorKind::Conflict => "conflict",
+                ErrorKind::RateLimited => "later",
+                ErrorKind::InvalidInput
+                | ErrorKind::Unauthorized
+                | ErrorKind::UpstreamTimeout
+                | ErrorKind::UpstreamUnavailable
+                | ErrorKind::Internal => "failed",
+            },
+        };
+        assert_eq!(["a", "b", "ended", "slow"].map(describe), ["found", "missing", "conflict", "later"]);
+
+        let ended = lookup("ended").unwrap_err();
+        assert_eq!(ended.downcast_ref::<SessionEnded>(), Some(&SessionEnded("ended")));
+        assert_eq!(ended.to_string(), "Session ended has ended");
+        assert!(std::error::Error::source(&ended).is_none());
+        assert_eq!(lookup("slow").unwrap_err().retry_after(), Some(Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn test_anyhow_errors_keep_typed_kinds() {
+        // Straight back out of anyhow
+        let err = Error::from(anyhow::Error::new(lookup("b").unwrap_err()));
+        assert_eq!((err.kind(), err.message()), (ErrorKind::NotFound, "No session 'b'"));
+
+        // Beneath context, the kind survives and the message is the outermost context
+        let err = Error::from(lookup("slow").context("Failed to load history").unwrap_err());
+        assert_eq!(err.kind(), ErrorKind::RateLimited);
+        assert_eq!(err.retry_after(), Some(Duration::from_secs(3)));
+        assert_eq!(err.to_string(), "Failed to load history");
+        assert_eq!(std::error::Error::source(&err).unwrap().to_string(), "Slow down");
+
+        // Anything else is internal, with the original still reachable
+        let err = Error::from(anyhow::Error::new(SessionEnded("x")).context("Failed to send"));
+        assert_eq!(err.kind(), ErrorKind::Internal);
+        assert!(err.is::<SessionEnded>());
+    }
+
+    #[test]
+    fn test_result_ext_classifies_and_adds_context() {
+        let parsed: Result<u16> = "http".parse::<u16>().or_error(ErrorKind::InvalidInput, "Bad port");
+        let err = parsed.unwrap_err();
+        assert_eq!((err.kind(), err.message()), (ErrorKind::InvalidInput, "Bad port"));
+        assert!(err.is::<std::num::ParseIntError>());
+
+        let err = lookup("b").error_context("Resuming chat").unwrap_err();
+        assert_eq!((err.kind(), err.message()), (ErrorKind::NotFound, "Resuming chat: No session 'b'"));
+
+        let err = Err::<(), _>(anyhow::anyhow!("disk full")).error_context("Saving").unwrap_err();
+        assert_eq!((err.kind(), err.message()), (ErrorKind::Internal, "Saving: disk full"));
+    }
+
+    #[test]
+    fn test_kinds() {
+        let retryable: Vec<_> = ErrorKind::ALL.into_iter().filter(ErrorKind::is_retryable).collect();
+        assert_eq!(retryable, [ErrorKind::RateLimited, ErrorKind::UpstreamTimeout, ErrorKind::UpstreamUnavailable]);
+        assert_eq!(serde_json::to_string(&ErrorKind::UpstreamTimeout).unwrap(), "\"upstream_timeout\"");
+
+        assert_eq!(ErrorKind::from_upstream_status(200), None);
+        assert_eq!(ErrorKind::from_upstream_status(404), Some(ErrorKind::NotFound));
+        assert_eq!(ErrorKind::from_upstream_status(429), Some(ErrorKind::RateLimited));
+        assert_eq!(ErrorKind::from_upstream_status(418), Some(ErrorKind::Internal));
+        assert_eq!(ErrorKind::from_upstream_status(503), Some(ErrorKind::UpstreamUnavailable));
+    }
+}
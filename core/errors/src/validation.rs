@@ -0,0 +1,147 @@
+//! Checking every constraint of a configuration at once
+//!
+//! A [`Validate`] type lists everything wrong with a value as [`Violations`], each naming
+//! the field it concerns by its path, such as `resilience.pool_size`. Validation does not
+//! stop at the first violation, so whoever wrote the value can fix it in one pass.
+//! [`Validate::validate`] reports them as an [`ErrorKind::InvalidInput`] error whose
+//! source is an [`InvalidConfig`] holding the list, which the API server renders as a 400
+//! listing every violation.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::{Error, ErrorKind, Result};
+
+/// One broken constraint
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Violation {
+    /// Path of the field, with nested fields joined by `.`
+    pub field: String,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+/// The violations found so far while validating a value
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Violations(Vec<Violation>);
+
+impl Violations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record `message` against `field` unless `holds`
+    pub fn check(&mut self, holds: bool, field: &str, message: impl Into<String>) {
+        if !holds {
+            self.push(field, message);
+        }
+    }
+
+    pub fn push(&mut self, field: &str, message: impl Into<String>) {
+        self.0.push(Violation { field: field.to_string(), message: message.into() });
+    }
+
+    /// Record the violations of a nested value, under `field`
+    pub fn nest(&mut self, field: &str, nested: Violations) {
+        self.0.extend(nested.0.into_iter().map(|violation| Violation {
+            field: format!("{}.{}", field, violation.field),
+            ..violation
+        }));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Paths of the violated fields, in the order they were found
+    pub fn fields(&self) -> Vec<&str> {
+        self.0.iter().map(|violation| violation.field.as_str()).collect()
+    }
+
+    /// `Ok` if nothing was recorded, otherwise an invalid input error listing every
+    /// violation of the value called `name`
+    pub fn into_result(self, name: &str) -> Result<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        Err(Error::transparent(ErrorKind::InvalidInput, InvalidConfig { name: name.to_string(), violations: self.0 }))
+    }
+}
+
+/// A value that broke constraints, the source of the error [`Validate::validate`] returns
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InvalidConfig {
+    /// What the value is, such as "MCP server config"
+    pub name: String,
+    pub violations: Vec<Violation>,
+}
+
+impl fmt::Display for InvalidConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid {}: ", self.name)?;
+        for (i, violation) in self.violations.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{}", violation)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for InvalidConfig {}
+
+/// A value with constraints its type cannot express
+pub trait Validate {
+    /// What the value is called in error messages, such as "MCP server config"
+    const NAME: &'static str;
+
+    /// Every constraint the value breaks
+    fn violations(&self) -> Violations;
+
+    /// Check every constraint, failing with all violations at once
+    fn validate(&self) -> Result<()> {
+        self.violations().into_result(Self::NAME)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Pool {
+        size: usize,
+        timeout_secs: u64,
+    }
+
+    impl Validate for Pool {
+        const NAME: &'static str = "pool config";
+
+        fn violations(&self) -> Violations {
+            let mut violations = Violations::new();
+            violations.check(self.size > 0, "size", "must be at least 1");
+            violations.check(self.timeout_secs > 0, "timeout_secs", "must be at least 1");
+            violations
+        }
+    }
+
+    #[test]
+    fn test_every_violation_is_reported_with_its_path() {
+        assert!(Pool { size: 1, timeout_secs: 1 }.validate().is_ok());
+
+        let err = Pool { size: 0, timeout_secs: 0 }.validate().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert_eq!(err.to_string(), "Invalid pool config: size: must be at least 1; timeout_secs: must be at least 1");
+        assert_eq!(err.downcast_ref::<InvalidConfig>().unwrap().violations.len(), 2);
+
+        let mut outer = Violations::new();
+        outer.check(false, "name", "must not be empty");
+        outer.nest("pool", Pool { size: 0, timeout_secs: 1 }.violations());
+        assert_eq!(outer.fields(), ["name", "pool.size"]);
+    }
+}
@@ -0,0 +1,251 @@
+//! Reading persisted data written by older builds
+//!
+//! Persisted structs carry a `schema_version` field, and each crate keeps the upgrades
+//! of its persisted types in a `migrations` module as one [`Migrations`] list per type.
+//! The first step turns a version 1 document into version 2, the next turns 2 into 3,
+//! and so on; steps work on the JSON, before it is deserialized. Data written before a
+//! type was versioned has no `schema_version` and is read as version 1.
+//!
+//! [`load_versioned`] applies the steps a document is missing and deserializes the
+//! result. A document written by a newer build fails with an [`UnknownVersion`] instead
+//! of being misread. Renaming, removing or adding a persisted field therefore comes with
+//! a step; [`check_round_trip`] over frozen payloads of every old version catches a
+//! change that doesn't:
+//!
+//! ```
+//! use serde::{Deserialize, Serialize};
+//! use serde_json::{json, Value};
+//! use talkpp_errors::versioning::{load_versioned, Migrations};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Note {
+//!     schema_version: u32,
+//!     title: String,
+//! }
+//!
+//! /// Version 2 renamed `name` to `title`
+//! fn rename_name(mut note: Value) -> talkpp_errors::Result<Value> {
+//!     if let Some(name) = note.as_object_mut().and_then(|note| note.remove("name")) {
+//!         note["title"] = name;
+//!     }
+//!     Ok(note)
+//! }
+//!
+//! const NOTE: Migrations = Migrations::new("note", &[rename_name]);
+//!
+//! let note: Note = load_versioned(json!({"name": "groceries"}), &NOTE).unwrap();
+//! assert_eq!((note.schema_version, note.title.as_str()), (2, "groceries"));
+//! assert!(load_versioned::<Note>(json!({"schema_version": 3, "title": "later"}), &NOTE).is_err());
+//! ```
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+
+use crate::{Error, ErrorKind, Result};
+
+/// Field holding a document's version
+pub const VERSION_FIELD: &str = "schema_version";
+
+/// Upgrades a document by one version
+pub type Migration = fn(Value) -> Result<Value>;
+
+/// The ordered upgrades of one persisted type
+#[derive(Clone, Copy)]
+pub struct Migrations {
+    name: &'static str,
+    steps: &'static [Migration],
+}
+
+impl Migrations {
+    /// Upgrades of the type called `name` in errors; `steps[0]` upgrades version 1
+    pub const fn new(name: &'static str, steps: &'static [Migration]) -> Self {
+        Self { name, steps }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Version this build writes: one past the last upgrade
+    pub const fn current(&self) -> u32 {
+        self.steps.len() as u32 + 1
+    }
+
+    /// Version `document` was written at
+    pub fn version_of(&self, document: &Value) -> Result<u32> {
+        let Some(version) = document.get(VERSION_FIELD) else {
+            return Ok(1);
+        };
+        let version = version.as_u64().filter(|version| *version >= 1).ok_or_else(|| {
+            Error::invalid_input(format!("{} has an invalid {}: {}", self.name, VERSION_FIELD, version))
+        })?;
+        if version > u64::from(self.current()) {
+            return Err(Error::transparent(
+                ErrorKind::InvalidInput,
+                UnknownVersion { name: self.name, found: version, supported: self.current() },
+            ));
+        }
+        Ok(version as u32)
+    }
+
+    /// Bring `document` up to the current version, stamping it with that version
+    pub fn upgrade(&self, mut document: Value) -> Result<Value> {
+        let version = self.version_of(&document)?;
+        for (from, step) in self.steps.iter().enumerate().skip(version as usize - 1) {
+            document = step(document).map_err(|e| e.context(format!("Upgrading {} from version {}", self.name, from + 1)))?;
+        }
+        if let Value::Object(fields) = &mut document {
+            fields.insert(VERSION_FIELD.to_string(), self.current().into());
+        }
+        Ok(document)
+    }
+}
+
+impl fmt::Debug for Migrations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Migrations").field("name", &self.name).field("current", &self.current()).finish()
+    }
+}
+
+/// A document written by a newer build than this one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownVersion {
+    pub name: &'static str,
+    pub found: u64,
+    /// Newest version this build reads
+    pub supported: u32,
+}
+
+impl fmt::Display for UnknownVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} has unknown future schema version {}; this build reads versions up to {}",
+            self.name, self.found, self.supported
+        )
+    }
+}
+
+impl std::error::Error for UnknownVersion {}
+
+/// Upgrade `document` with `migrations` and deserialize it
+pub fn load_versioned<T: DeserializeOwned>(document: Value, migrations: &Migrations) -> Result<T> {
+    let document = migrations.upgrade(document)?;
+    serde_json::from_value(document).map_err(|e| {
+        Error::invalid_input(format!("Invalid {}: {}", migrations.name, e)).with_source(e)
+    })
+}
+
+/// Check that a frozen payload of an old version still loads as `T`, and that `T`
+/// writes back exactly what the migrations upgrade it to. A field added, renamed or
+/// removed without a migration step makes the two differ, naming the first field that
+/// does.
+pub fn check_round_trip<T: Serialize + DeserializeOwned>(document: Value, migrations: &Migrations) -> std::result::Result<(), String> {
+    let upgraded = migrations.upgrade(document).map_err(|e| e.to_string())?;
+    let loaded: T = load_versioned(upgraded.clone(), migrations).map_err(|e| e.to_string())?;
+    let written = serde_json::to_value(&loaded).map_err(|e| e.to_string())?;
+    match first_difference("", &upgraded, &written) {
+        None => Ok(()),
+        Some(path) => Err(format!(
+            "{} at {} reads differently than it is written; add a migration step",
+            migrations.name,
+            if path.is_empty() { "/" } else { &path },
+        )),
+    }
+}
+
+/// JSON pointer to the first place `a` and `b` differ
+fn first_difference(path: &str, a: &Value, b: &Value) -> Option<String> {
+    match (a, b) {
+        (Value::Object(a), Value::Object(b)) => {
+            let mut keys: Vec<&String> = a.keys().chain(b.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            keys.into_iter().find_map(|key| {
+                let path = format!("{}/{}", path, key);
+                match (a.get(key), b.get(key)) {
+                    (Some(a), Some(b)) => first_difference(&path, a, b),
+                    _ => Some(path),
+                }
+            })
+        }
+        (Value::Array(a), Value::Array(b)) if a.len() == b.len() => {
+            a.iter().zip(b).enumerate().find_map(|(i, (a, b))| first_difference(&format!("{}/{}", path, i), a, b))
+        }
+        _ if a == b => None,
+        _ => Some(path.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Profile {
+        schema_version: u32,
+        display_name: String,
+        #[serde(default)]
+        tags: Vec<String>,
+    }
+
+    /// Version 2 renamed `name` to `display_name`
+    fn rename_name(mut profile: Value) -> Result<Value> {
+        let fields = profile.as_object_mut().ok_or_else(|| Error::invalid_input("profile is not an object"))?;
+        if let Some(name) = fields.remove("name") {
+            fields.insert("display_name".to_string(), name);
+        }
+        Ok(profile)
+    }
+
+    /// Version 3 added `tags`
+    fn add_tags(mut profile: Value) -> Result<Value> {
+        profile["tags"] = json!([]);
+        Ok(profile)
+    }
+
+    const PROFILE: Migrations = Migrations::new("profile", &[rename_name, add_tags]);
+
+    #[test]
+    fn test_old_documents_are_upgraded_and_future_ones_refused() {
+        assert_eq!(PROFILE.current(), 3);
+        for document in [
+            json!({"name": "Ada"}),
+            json!({"schema_version": 2, "display_name": "Ada"}),
+            json!({"schema_version": 3, "display_name": "Ada", "tags": []}),
+        ] {
+            let profile: Profile = load_versioned(document, &PROFILE).unwrap();
+            assert_eq!((profile.schema_version, profile.display_name.as_str()), (3, "Ada"));
+        }
+
+        let err = load_versioned::<Profile>(json!({"schema_version": 4, "display_name": "Ada"}), &PROFILE).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert_eq!(err.downcast_ref::<UnknownVersion>(), Some(&UnknownVersion { name: "profile", found: 4, supported: 3 }));
+        assert_eq!(err.to_string(), "profile has unknown future schema version 4; this build reads versions up to 3");
+
+        for version in [json!(0), json!("2"), json!(1.5)] {
+            let err = load_versioned::<Profile>(json!({"schema_version": version, "name": "Ada"}), &PROFILE).unwrap_err();
+            assert!(err.to_string().contains("invalid schema_version"), "{}", err);
+        }
+        let err = load_versioned::<Profile>(json!(["Ada"]), &PROFILE).unwrap_err();
+        assert_eq!(err.to_string(), "Upgrading profile from version 1: profile is not an object");
+    }
+
+    #[test]
+    fn test_round_trip_catches_fields_changed_without_a_migration() {
+        assert_eq!(check_round_trip::<Profile>(json!({"name": "Ada"}), &PROFILE), Ok(()));
+
+        // As if `add_tags` had been forgotten: `tags` is written but never migrated in
+        let forgetful = Migrations::new("profile", &[rename_name]);
+        let err = check_round_trip::<Profile>(json!({"name": "Ada"}), &forgetful).unwrap_err();
+        assert_eq!(err, "profile at /tags reads differently than it is written; add a migration step");
+
+        // A field the struct no longer has is dropped on load
+        let err = check_round_trip::<Profile>(json!({"name": "Ada", "avatar": "ada.png"}), &PROFILE).unwrap_err();
+        assert!(err.contains("/avatar"), "{}", err);
+    }
+}
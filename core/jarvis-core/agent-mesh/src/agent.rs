@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+
+/// Role an agent plays in the mesh
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AgentType {
+    Analyzer,
+    Planner,
+    Executor,
+    Reviewer,
+    Generic,
+}
+
+/// What an agent can do, advertised to the mesh when it is deployed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentCapabilities {
+    pub skills: Vec<String>,
+    /// Tasks the agent accepts at once; further dispatch is rejected until one finishes
+    pub max_concurrent_tasks: usize,
+    /// Relative cost of using this agent; scores are divided by it, so cheaper agents win ties
+    pub cost_weight: f64,
+    /// Where the agent runs, matched against a task's locality hint
+    pub locality: Option<String>,
+}
+
+impl Default for AgentCapabilities {
+    fn default() -> Self {
+        Self {
+            skills: Vec::new(),
+            max_concurrent_tasks: 1,
+            cost_weight: 1.0,
+            locality: None,
+        }
+    }
+}
+
+impl AgentCapabilities {
+    pub fn new(skills: &[&str]) -> Self {
+        Self {
+            skills: skills.iter().map(|s| s.to_string()).collect(),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_max_concurrent_tasks(mut self, max_concurrent_tasks: usize) -> Self {
+        self.max_concurrent_tasks = max_concurrent_tasks;
+        self
+    }
+
+    pub fn with_cost_weight(mut self, cost_weight: f64) -> Self {
+        self.cost_weight = cost_weight;
+        self
+    }
+
+    pub fn with_locality(mut self, locality: impl Into<String>) -> Self {
+        self.locality = Some(locality.into());
+        self
+    }
+
+    /// Whether every required capability is among this agent's skills
+    pub fn covers(&self, required: &[String]) -> bool {
+        required.iter().all(|r| self.skills.contains(r))
+    }
+}
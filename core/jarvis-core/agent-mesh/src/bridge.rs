@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use anyhow::Result;
+use async_trait::async_trait;
+use cognitive_kernel::{ExecutionTask, TaskOutput, TaskRunner, TaskStatus, TaskUsage};
+
+use crate::error::MeshError;
+use crate::mesh::{Task, TaskResult};
+use crate::AgentMeshFabric;
+
+/// Suffix of kernel agent types that map to a capability by name, as in `planner-agent`
+const AGENT_SUFFIX: &str = "-agent";
+
+/// Translates between the cognitive kernel's plan tasks and mesh tasks.
+///
+/// A task's `agent_type` becomes the capabilities an agent needs: an explicit mapping
+/// when one is registered, otherwise `<role>` for `<role>-agent`.
+#[derive(Debug, Clone, Default)]
+pub struct KernelMeshBridge {
+    mappings: HashMap<String, Vec<String>>,
+}
+
+impl KernelMeshBridge {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Require `capabilities` for tasks of `agent_type` instead of the default mapping
+    pub fn with_mapping(mut self, agent_type: impl Into<String>, capabilities: &[&str]) -> Self {
+        self.mappings.insert(agent_type.into(), capabilities.iter().map(|c| c.to_string()).collect());
+        self
+    }
+
+    pub fn capabilities_for(&self, agent_type: &str) -> Result<Vec<String>, MeshError> {
+        if let Some(capabilities) = self.mappings.get(agent_type) {
+            return Ok(capabilities.clone());
+        }
+        match agent_type.strip_suffix(AGENT_SUFFIX) {
+            Some(role) if !role.is_empty() => Ok(vec![role.to_string()]),
+            _ => Err(MeshError::UnmappableAgentType { agent_type: agent_type.to_string() }),
+        }
+    }
+
+    /// Mesh task carrying the plan task's id and the current trace, with its name, inputs
+    /// and expected outputs as the payload
+    pub fn to_mesh_task(&self, task: &ExecutionTask) -> Result<Task, MeshError> {
+        let mut mesh_task = Task::new(task.description.clone())
+            .with_task_type(format!("{:?}", task.task_type).to_lowercase())
+            .with_payload(serde_json::json!({
+                "name": task.name,
+                "inputs": task.inputs,
+                "expected_outputs": task.expected_outputs,
+                "dry_run_first": task.dry_run_first,
+            }));
+        mesh_task.id = task.id;
+        mesh_task.trace_parent = cognitive_kernel::telemetry::current_traceparent();
+        for capability in self.capabilities_for(&task.agent_type)? {
+            mesh_task = mesh_task.with_capability(capability);
+        }
+        Ok(mesh_task)
+    }
+
+    /// Artifacts for each of the task's expected outputs: the field of that name in the
+    /// agent's outcome, or the whole outcome when the task expects a single output
+    pub fn outputs(task: &ExecutionTask, result: &TaskResult) -> HashMap<String, serde_json::Value> {
+        let outcome = &result.result.outcome;
+        task.expected_outputs.iter()
+            .filter_map(|name| {
+                let value = match outcome.get(name) {
+                    Some(value) => value.clone(),
+                    None if task.expected_outputs.len() == 1 => outcome.clone(),
+                    None => return None,
+                };
+                Some((name.clone(), value))
+            })
+            .collect()
+    }
+}
+
+impl TryFrom<&ExecutionTask> for Task {
+    type Error = MeshError;
+
+    fn try_from(task: &ExecutionTask) -> Result<Self, Self::Error> {
+        KernelMeshBridge::default().to_mesh_task(task)
+    }
+}
+
+impl From<&TaskResult> for TaskStatus {
+    fn from(result: &TaskResult) -> Self {
+        if result.result.success {
+            TaskStatus::Completed
+        } else {
+            TaskStatus::Failed
+        }
+    }
+}
+
+/// Runs kernel plans on the agent mesh, one `execute_task` per plan task
+pub struct MeshTaskRunner {
+    fabric: Arc<AgentMeshFabric>,
+    bridge: KernelMeshBridge,
+}
+
+impl MeshTaskRunner {
+    pub fn new(fabric: Arc<AgentMeshFabric>) -> Self {
+        Self {
+            fabric,
+            bridge: KernelMeshBridge::default(),
+        }
+    }
+
+    pub fn with_bridge(mut self, bridge: KernelMeshBridge) -> Self {
+        self.bridge = bridge;
+        self
+    }
+}
+
+#[async_trait]
+impl TaskRunner for MeshTaskRunner {
+    /// The agent type must map to capabilities some deployed agent has; whether that
+    /// agent is free is only known at run time
+    async fn validate(&self, task: &ExecutionTask) -> Result<()> {
+        let mesh_task = self.bridge.to_mesh_task(task)?;
+        if !self.fabric.mesh.has_capable_agent(&mesh_task).await {
+            return Err(MeshError::NoSuitableAgent {
+                task_id: task.id,
+                required: mesh_task.required_capabilities,
+                available: self.fabric.mesh.available_capabilities().await,
+            }.into());
+        }
+        Ok(())
+    }
+
+    async fn run(&self, task: &ExecutionTask) -> Result<TaskOutput> {
+        let result = self.fabric.execute_task(self.bridge.to_mesh_task(task)?).await?;
+        Ok(TaskOutput {
+            status: TaskStatus::from(&result),
+            outputs: KernelMeshBridge::outputs(task, &result),
+            usage: TaskUsage::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::MockAgent;
+    use cognitive_kernel::{CognitiveKernel, ExecutionState, PlanExecutor, TaskType};
+
+    #[test]
+    fn test_agent_types_map_to_capabilities() {
+        let bridge = KernelMeshBridge::new().with_mapping("generic-agent", &["shell", "http"]);
+        assert_eq!(bridge.capabilities_for("planner-agent").unwrap(), vec!["planner"]);
+        assert_eq!(bridge.capabilities_for("generic-agent").unwrap(), vec!["shell", "http"]);
+        assert!(matches!(
+            bridge.capabilities_for("-agent"),
+            Err(MeshError::UnmappableAgentType { agent_type }) if agent_type == "-agent"
+        ));
+        assert!(bridge.capabilities_for("oracle").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_kernel_plan_runs_on_mesh_agents() {
+        let kernel = CognitiveKernel::new();
+        let mut plan = kernel.process_intent("Deploy the marketing website to staging", None).await.unwrap();
+        assert_eq!(plan.tasks.len(), 2);
+
+        let fabric = Arc::new(AgentMeshFabric::new().await.unwrap());
+        let analyzer = fabric.deploy_agent(Arc::new(MockAgent::new(vec!["analyzer"]))).await.unwrap();
+        let planner = fabric.deploy_agent(Arc::new(MockAgent::new(vec!["planner"]))).await.unwrap();
+
+        let executor = PlanExecutor::new(MeshTaskRunner::new(fabric.clone()));
+        let outcome = executor.execute(&mut plan).await.unwrap();
+
+        assert_eq!(outcome.state, ExecutionState::Completed);
+        assert!(plan.tasks.iter().all(|t| matches!(t.status, TaskStatus::Completed)));
+        let requirements = &outcome.outputs[&plan.tasks[0].id]["requirements.json"];
+        assert_eq!(requirements["handled_by"], serde_json::json!(analyzer));
+        let deployment_plan = &outcome.outputs[&plan.tasks[1].id]["deployment-plan.yaml"];
+        assert_eq!(deployment_plan["handled_by"], serde_json::json!(planner));
+        assert_eq!(fabric.lifecycle.stats(planner).by_task_type["plan"], (1, 1));
+    }
+
+    #[tokio::test]
+    async fn test_unmappable_plans_fail_validation_before_any_task_runs() {
+        let kernel = CognitiveKernel::new();
+        let fabric = Arc::new(AgentMeshFabric::new().await.unwrap());
+        let analyzer = fabric.deploy_agent(Arc::new(MockAgent::new(vec!["analyzer"]))).await.unwrap();
+        let executor = PlanExecutor::new(MeshTaskRunner::new(fabric.clone()));
+
+        let mut missing_planner = kernel.process_intent("Deploy the marketing website to staging", None).await.unwrap();
+        let err = executor.execute(&mut missing_planner).await.unwrap_err();
+        assert!(err.to_string().contains("requires [planner]"), "{}", err);
+
+        let mut unmappable = kernel.process_intent("Write a blog post", None).await.unwrap();
+        unmappable.tasks[0].agent_type = "oracle".to_string();
+        let err = executor.execute(&mut unmappable).await.unwrap_err();
+        assert!(err.to_string().contains("'oracle' does not map"), "{}", err);
+
+        assert_eq!(fabric.lifecycle.stats(analyzer).succeeded, 0);
+        assert!(missing_planner.tasks.iter().all(|t| matches!(t.status, TaskStatus::Pending)));
+    }
+
+    #[test]
+    fn test_outputs_pick_named_fields() {
+        let kernel_task = ExecutionTask {
+            id: uuid::Uuid::new_v4(),
+            name: "report".to_string(),
+            description: String::new(),
+            task_type: TaskType::Verify,
+            agent_type: "reviewer-agent".to_string(),
+            inputs: HashMap::new(),
+            expected_outputs: vec!["summary".to_string(), "score".to_string()],
+            estimated_duration: chrono::Duration::minutes(1),
+            status: TaskStatus::Pending,
+            dry_run_first: false,
+            tags: Vec::new(),
+            output_schemas: HashMap::new(),
+        };
+        let mesh_task = Task::try_from(&kernel_task).unwrap();
+        assert_eq!(mesh_task.id, kernel_task.id);
+        assert_eq!(mesh_task.required_capabilities, vec!["reviewer"]);
+        assert_eq!(mesh_task.task_type.as_deref(), Some("verify"));
+
+        let result = TaskResult {
+            task_id: kernel_task.id,
+            agent_id: uuid::Uuid::new_v4(),
+            result: crate::ActResult {
+                executed_steps: vec![],
+                outcome: serde_json::json!({ "summary": "ok", "extra": true }),
+                success: true,
+            },
+            metadata: crate::ReflectResult {
+                performance_analysis: String::new(),
+                lessons_learned: vec![],
+                improvement_suggestions: vec![],
+            },
+            routing: Default::default(),
+            selection: None,
+            completed_at: chrono::Utc::now(),
+        };
+        let outputs = KernelMeshBridge::outputs(&kernel_task, &result);
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs["summary"], "ok");
+    }
+}
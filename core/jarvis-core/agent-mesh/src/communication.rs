@@ -0,0 +1,296 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, mpsc, oneshot};
+use uuid::Uuid;
+
+use crate::error::MessageError;
+use crate::lifecycle::{AgentState, LifecycleManager};
+
+/// Number of events a slow subscriber can fall behind before missing some
+const EVENT_CAPACITY: usize = 256;
+
+/// Messages an agent can have waiting before further sends are rejected
+const MAILBOX_CAPACITY: usize = 64;
+
+/// Undeliverable messages kept for inspection; the oldest are dropped first
+const DEAD_LETTER_CAPACITY: usize = 1024;
+
+/// Stage of the Sense-Reason-Act-Reflect-Teach cycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Stage {
+    Sense,
+    Reason,
+    Act,
+    Reflect,
+    Teach,
+}
+
+impl std::fmt::Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Stage::Sense => "sense",
+            Stage::Reason => "reason",
+            Stage::Act => "act",
+            Stage::Reflect => "reflect",
+            Stage::Teach => "teach",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Progress of a task through an agent, published for observers
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MeshEvent {
+    TaskStarted { task_id: Uuid, agent_id: Uuid },
+    StageCompleted { task_id: Uuid, agent_id: Uuid, stage: Stage },
+    TaskFailed { task_id: Uuid, agent_id: Uuid, stage: Stage, reason: String },
+    TaskSucceeded { task_id: Uuid, agent_id: Uuid },
+    AgentStateChanged { agent_id: Uuid, state: AgentState },
+}
+
+/// Message exchanged between agents
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Message {
+    pub id: Uuid,
+    /// Sending agent, filled in by its [`AgentHandle`]
+    pub from: Option<Uuid>,
+    /// Id of the request this message answers
+    pub correlation_id: Option<Uuid>,
+    /// Topic the message was broadcast on
+    pub topic: Option<String>,
+    pub payload: serde_json::Value,
+    pub sent_at: DateTime<Utc>,
+}
+
+impl Message {
+    pub fn new(payload: serde_json::Value) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            from: None,
+            correlation_id: None,
+            topic: None,
+            payload,
+            sent_at: Utc::now(),
+        }
+    }
+
+    /// Response to this message; send it back to `self.from` to complete a request
+    pub fn reply(&self, payload: serde_json::Value) -> Self {
+        Self {
+            correlation_id: Some(self.id),
+            ..Self::new(payload)
+        }
+    }
+}
+
+/// Message that could not be delivered, and why
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeadLetter {
+    pub to: Uuid,
+    pub message: Message,
+    pub reason: MessageError,
+}
+
+/// Channel between the mesh and anything following its progress, and between agents
+#[derive(Debug)]
+pub struct CommunicationLayer {
+    events: broadcast::Sender<MeshEvent>,
+    mailboxes: DashMap<Uuid, mpsc::Sender<Message>>,
+    /// Requests awaiting a reply, keyed by request message id
+    pending: DashMap<Uuid, oneshot::Sender<Message>>,
+    topics: DashMap<String, Vec<Uuid>>,
+    dead_letters: Mutex<VecDeque<DeadLetter>>,
+}
+
+impl CommunicationLayer {
+    pub async fn new() -> Result<Self> {
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+        Ok(Self {
+            events,
+            mailboxes: DashMap::new(),
+            pending: DashMap::new(),
+            topics: DashMap::new(),
+            dead_letters: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    /// Receive every event published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<MeshEvent> {
+        self.events.subscribe()
+    }
+
+    /// Publish an event; it is dropped when nobody is subscribed
+    pub fn publish(&self, event: MeshEvent) {
+        let _ = self.events.send(event);
+    }
+
+    /// Create the agent's mailbox, replacing any previous one
+    pub fn open_mailbox(&self, agent_id: Uuid) -> mpsc::Receiver<Message> {
+        let (sender, receiver) = mpsc::channel(MAILBOX_CAPACITY);
+        self.mailboxes.insert(agent_id, sender);
+        receiver
+    }
+
+    /// Remove the agent's mailbox and topic subscriptions
+    pub fn close_mailbox(&self, agent_id: Uuid) {
+        self.mailboxes.remove(&agent_id);
+        for mut subscribers in self.topics.iter_mut() {
+            subscribers.retain(|id| *id != agent_id);
+        }
+    }
+
+    /// Deliver a message to an agent's mailbox, or to the request waiting for it when it
+    /// is a reply
+    pub fn send(&self, to: Uuid, message: Message) -> Result<(), MessageError> {
+        if let Some((_, waiting)) = message.correlation_id.and_then(|id| self.pending.remove(&id)) {
+            // A requester that gave up has already been told it timed out
+            let _ = waiting.send(message);
+            return Ok(());
+        }
+
+        let result = match self.mailboxes.get(&to) {
+            None => Err(MessageError::UnknownAgent { agent_id: to }),
+            Some(mailbox) => mailbox.try_send(message.clone()).map_err(|e| match e {
+                mpsc::error::TrySendError::Full(_) => MessageError::MailboxFull { agent_id: to },
+                mpsc::error::TrySendError::Closed(_) => MessageError::MailboxClosed { agent_id: to },
+            }),
+        };
+        if let Err(reason) = &result {
+            self.dead_letter(to, message, reason.clone());
+        }
+        result
+    }
+
+    /// Send a message and wait for the reply carrying its id as `correlation_id`
+    pub async fn request(&self, to: Uuid, message: Message, timeout: Duration) -> Result<Message, MessageError> {
+        let (sender, receiver) = oneshot::channel();
+        let request_id = message.id;
+        self.pending.insert(request_id, sender);
+
+        if let Err(e) = self.send(to, message) {
+            self.pending.remove(&request_id);
+            return Err(e);
+        }
+
+        match tokio::time::timeout(timeout, receiver).await {
+            Ok(Ok(reply)) => Ok(reply),
+            _ => {
+                self.pending.remove(&request_id);
+                Err(MessageError::Timeout { agent_id: to, after: timeout })
+            }
+        }
+    }
+
+    pub fn subscribe_topic(&self, agent_id: Uuid, topic: &str) {
+        let mut subscribers = self.topics.entry(topic.to_string()).or_default();
+        if !subscribers.contains(&agent_id) {
+            subscribers.push(agent_id);
+        }
+    }
+
+    pub fn unsubscribe_topic(&self, agent_id: Uuid, topic: &str) {
+        if let Some(mut subscribers) = self.topics.get_mut(topic) {
+            subscribers.retain(|id| *id != agent_id);
+        }
+    }
+
+    /// Send a message to every subscriber of `topic` other than its sender, returning how
+    /// many received it. Subscribers that can't take it are dead-lettered rather than
+    /// failing the rest.
+    pub fn broadcast(&self, topic: &str, mut message: Message) -> usize {
+        message.topic = Some(topic.to_string());
+        let subscribers = self.topics.get(topic).map(|s| s.clone()).unwrap_or_default();
+        subscribers.into_iter()
+            .filter(|id| Some(*id) != message.from)
+            .filter(|id| self.send(*id, message.clone()).is_ok())
+            .count()
+    }
+
+    /// Undeliverable messages, oldest first
+    pub fn dead_letters(&self) -> Vec<DeadLetter> {
+        self.dead_letters.lock().unwrap().iter().cloned().collect()
+    }
+
+    fn dead_letter(&self, to: Uuid, message: Message, reason: MessageError) {
+        tracing::warn!("Dead-lettering message {} to {}: {}", message.id, to, reason);
+        let mut dead_letters = self.dead_letters.lock().unwrap();
+        if dead_letters.len() == DEAD_LETTER_CAPACITY {
+            dead_letters.pop_front();
+        }
+        dead_letters.push_back(DeadLetter { to, message, reason });
+    }
+}
+
+/// An agent's connection to the mesh, handed to it at deploy time
+#[derive(Debug)]
+pub struct AgentHandle {
+    agent_id: Uuid,
+    communication: Arc<CommunicationLayer>,
+    lifecycle: Arc<LifecycleManager>,
+    mailbox: tokio::sync::Mutex<mpsc::Receiver<Message>>,
+}
+
+impl AgentHandle {
+    pub fn new(
+        agent_id: Uuid,
+        communication: Arc<CommunicationLayer>,
+        lifecycle: Arc<LifecycleManager>,
+        mailbox: mpsc::Receiver<Message>,
+    ) -> Self {
+        Self {
+            agent_id,
+            communication,
+            lifecycle,
+            mailbox: tokio::sync::Mutex::new(mailbox),
+        }
+    }
+
+    /// Report that the agent is alive; agents should call this at least once per
+    /// supervision heartbeat interval
+    pub fn heartbeat(&self) {
+        if let Some(state) = self.lifecycle.heartbeat(self.agent_id) {
+            self.communication.publish(MeshEvent::AgentStateChanged { agent_id: self.agent_id, state });
+        }
+    }
+
+    pub fn agent_id(&self) -> Uuid {
+        self.agent_id
+    }
+
+    pub fn send(&self, to: Uuid, message: Message) -> Result<(), MessageError> {
+        self.communication.send(to, self.stamp(message))
+    }
+
+    pub async fn request(&self, to: Uuid, message: Message, timeout: Duration) -> Result<Message, MessageError> {
+        self.communication.request(to, self.stamp(message), timeout).await
+    }
+
+    /// Answer a request received in the mailbox
+    pub fn reply(&self, request: &Message, payload: serde_json::Value) -> Result<(), MessageError> {
+        let to = request.from.unwrap_or_default();
+        self.send(to, request.reply(payload))
+    }
+
+    pub fn broadcast(&self, topic: &str, message: Message) -> usize {
+        self.communication.broadcast(topic, self.stamp(message))
+    }
+
+    pub fn subscribe(&self, topic: &str) {
+        self.communication.subscribe_topic(self.agent_id, topic);
+    }
+
+    /// Next message in the mailbox; `None` once the agent is removed from the mesh
+    pub async fn recv(&self) -> Option<Message> {
+        self.mailbox.lock().await.recv().await
+    }
+
+    fn stamp(&self, mut message: Message) -> Message {
+        message.from = Some(self.agent_id);
+        message
+    }
+}
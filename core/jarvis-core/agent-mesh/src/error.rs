@@ -0,0 +1,93 @@
+use std::time::Duration;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::communication::Stage;
+use crate::lifecycle::AgentState;
+
+/// Why one agent could not complete a task
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgentFailure {
+    pub agent_id: Uuid,
+    pub stage: Stage,
+    pub reason: String,
+}
+
+impl std::fmt::Display for AgentFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "agent {} failed at {}: {}", self.agent_id, self.stage, self.reason)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum MeshError {
+    #[error("No suitable agent for task {task_id}: requires [{}], mesh offers [{}]", required.join(", "), available.join(", "))]
+    NoSuitableAgent {
+        task_id: Uuid,
+        required: Vec<String>,
+        available: Vec<String>,
+    },
+
+    #[error("Task {task_id} (requires [{}]) failed on {} agent(s): {}", required.join(", "), failures.len(), join_failures(failures))]
+    AllAgentsFailed {
+        task_id: Uuid,
+        required: Vec<String>,
+        failures: Vec<AgentFailure>,
+    },
+
+    #[error("Every agent able to run task {task_id} (requires [{}]) is at capacity or unavailable", required.join(", "))]
+    AllAgentsBusy {
+        task_id: Uuid,
+        required: Vec<String>,
+    },
+
+    #[error("Agent {agent_id} is already running {max_concurrent_tasks} task(s)")]
+    AgentAtCapacity {
+        agent_id: Uuid,
+        max_concurrent_tasks: usize,
+    },
+
+    #[error("Agent {agent_id} is {state:?} and not accepting tasks")]
+    AgentUnavailable {
+        agent_id: Uuid,
+        state: AgentState,
+    },
+
+    #[error("Agent type '{agent_type}' does not map to any mesh capability")]
+    UnmappableAgentType { agent_type: String },
+
+    #[error("Agent {agent_id} is not deployed")]
+    AgentNotFound { agent_id: Uuid },
+
+    #[error("Task {task_id} rejected by routing rule '{rule}'")]
+    PolicyViolation { task_id: Uuid, rule: String },
+
+    #[error("Routing rule '{rule}' sends task {task_id} to pool '{pool}', which has no agent able to take it")]
+    NoAgentInPool { task_id: Uuid, rule: String, pool: String },
+
+    #[error("Invalid routing policy: {reason}")]
+    InvalidPolicy { reason: String },
+
+    #[error("The mesh has no skill registry; agents can only advertise fixed capabilities")]
+    NoSkillRegistry,
+}
+
+/// Why a message between agents was not delivered
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum MessageError {
+    #[error("No mailbox for agent {agent_id}")]
+    UnknownAgent { agent_id: Uuid },
+
+    #[error("Mailbox of agent {agent_id} is full")]
+    MailboxFull { agent_id: Uuid },
+
+    #[error("Mailbox of agent {agent_id} is closed")]
+    MailboxClosed { agent_id: Uuid },
+
+    #[error("Agent {agent_id} did not reply within {after:?}")]
+    Timeout { agent_id: Uuid, after: Duration },
+}
+
+fn join_failures(failures: &[AgentFailure]) -> String {
+    failures.iter().map(AgentFailure::to_string).collect::<Vec<_>>().join("; ")
+}
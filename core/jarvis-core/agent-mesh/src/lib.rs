@@ -1,27 +1,51 @@
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
 use anyhow::Result;
-use chrono::{DateTime, Utc};
+use chrono::Utc;
 use dashmap::DashMap;
 use serde::{Deserialize, Serialize};
-use tokio::sync::{mpsc, RwLock};
+use tracing::Instrument;
 use uuid::Uuid;
 
 pub mod agent;
 pub mod mesh;
 pub mod communication;
 pub mod lifecycle;
+pub mod error;
+pub mod bridge;
+pub mod policy;
+pub mod skills;
 
-pub use agent::{Agent, AgentType, AgentCapabilities};
-pub use mesh::{AgentMesh, MeshTopology};
+pub use agent::{AgentType, AgentCapabilities};
+pub use mesh::{AgentMesh, AgentSelection, MeshStats, MeshTopology};
+pub use lifecycle::{AgentState, SupervisionPolicy};
+pub use communication::{AgentHandle, DeadLetter, MeshEvent, Message, Stage};
+pub use error::{AgentFailure, MeshError, MessageError};
+pub use bridge::{KernelMeshBridge, MeshTaskRunner};
+pub use policy::{AgentPool, RoutingDecision, RoutingPolicy, RoutingRule, RuleAction, TaskMatcher};
+pub use skills::{MemorySkillStore, PostgresSkillStore, RegisteredSkill, Skill, SkillMatch, SkillRegistry, SkillStore};
+
+/// Agents tried for a task before giving up
+const DEFAULT_MAX_ATTEMPTS: usize = 3;
 
 /// Agent mesh implementing Sense-Reason-Act-Reflect-Teach pattern
-#[derive(Debug)]
 pub struct AgentMeshFabric {
     pub agents: Arc<DashMap<Uuid, Arc<dyn Agent>>>,
     pub mesh: Arc<AgentMesh>,
     pub communication: Arc<communication::CommunicationLayer>,
     pub lifecycle: Arc<lifecycle::LifecycleManager>,
+    max_attempts: usize,
+    supervision: SupervisionPolicy,
+}
+
+impl std::fmt::Debug for AgentMeshFabric {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AgentMeshFabric")
+            .field("agents", &self.agents.len())
+            .field("mesh", &self.mesh)
+            .field("max_attempts", &self.max_attempts)
+            .field("supervision", &self.supervision)
+            .finish()
+    }
 }
 
 impl AgentMeshFabric {
@@ -35,45 +59,289 @@ impl AgentMeshFabric {
             mesh,
             communication,
             lifecycle,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            supervision: SupervisionPolicy::default(),
         })
     }
 
-    /// Deploy an agent to the mesh
+    /// Limit how many suitable agents `execute_task` tries before reporting failure
+    pub fn with_max_attempts(mut self, max_attempts: usize) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    pub fn with_supervision(mut self, supervision: SupervisionPolicy) -> Self {
+        self.supervision = supervision;
+        self
+    }
+
+    /// Deploy an agent to the mesh. Deployment counts as its first heartbeat.
     pub async fn deploy_agent(&self, agent: Arc<dyn Agent>) -> Result<Uuid> {
         let agent_id = agent.id();
+        self.lifecycle.start_agent(agent_id).await?;
+        let mailbox = self.communication.open_mailbox(agent_id);
+        let handle = AgentHandle::new(agent_id, self.communication.clone(), self.lifecycle.clone(), mailbox);
+        agent.connect(handle);
         self.agents.insert(agent_id, agent.clone());
         self.mesh.register_agent(agent_id, agent.capabilities()).await?;
-        self.lifecycle.start_agent(agent_id).await?;
+        self.heartbeat(agent_id);
         Ok(agent_id)
     }
 
-    /// Execute task through agent mesh
+    pub fn agent_state(&self, agent_id: Uuid) -> Option<AgentState> {
+        self.lifecycle.state(agent_id)
+    }
+
+    /// Stop routing new tasks to the agent and wait for its in-flight tasks to finish,
+    /// leaving it Stopped
+    pub async fn drain_agent(&self, agent_id: Uuid) -> Result<()> {
+        if self.lifecycle.state(agent_id).is_none() {
+            return Err(MeshError::AgentNotFound { agent_id }.into());
+        }
+        self.transition(agent_id, AgentState::Draining);
+        self.lifecycle.wait_idle(agent_id).await;
+        self.transition(agent_id, AgentState::Stopped);
+        Ok(())
+    }
+
+    /// Register a skill for a deployed agent, so tasks whose description reads like it
+    /// can be routed to the agent without redeploying it
+    pub async fn register_skill(&self, agent_id: Uuid, skill: skills::Skill) -> Result<skills::RegisteredSkill> {
+        if !self.agents.contains_key(&agent_id) {
+            return Err(MeshError::AgentNotFound { agent_id }.into());
+        }
+        self.mesh.register_skill(agent_id, skill).await
+    }
+
+    pub async fn unregister_skill(&self, agent_id: Uuid, name: &str) -> Result<bool> {
+        self.mesh.unregister_skill(agent_id, name).await
+    }
+
+    /// Drain the agent, then take it out of the mesh along with its mailbox and skills
+    pub async fn remove_agent(&self, agent_id: Uuid) -> Result<()> {
+        self.drain_agent(agent_id).await?;
+        self.mesh.unregister_agent(agent_id).await?;
+        self.communication.close_mailbox(agent_id);
+        self.agents.remove(&agent_id);
+        self.lifecycle.remove_agent(agent_id);
+        Ok(())
+    }
+
+    /// Check every agent once: mark those that missed too many heartbeats Unhealthy,
+    /// restart unhealthy ones whose backoff has passed, and fail those out of restarts
+    pub async fn supervise(&self) {
+        for agent_id in self.lifecycle.overdue(&self.supervision) {
+            tracing::warn!("Agent {} missed its heartbeats", agent_id);
+            self.transition(agent_id, AgentState::Unhealthy);
+        }
+
+        for agent_id in self.lifecycle.agents_in(AgentState::Unhealthy) {
+            match self.lifecycle.restart_decision(agent_id, &self.supervision) {
+                lifecycle::RestartDecision::Wait => {}
+                lifecycle::RestartDecision::GiveUp => {
+                    tracing::error!("Agent {} failed after {} restarts", agent_id, self.lifecycle.restarts(agent_id));
+                    self.transition(agent_id, AgentState::Failed);
+                }
+                lifecycle::RestartDecision::Restart => {
+                    let Some(agent) = self.agents.get(&agent_id).map(|a| a.clone()) else {
+                        continue;
+                    };
+                    self.lifecycle.record_restart(agent_id);
+                    self.publish_state(agent_id, AgentState::Starting);
+                    match agent.restart().await {
+                        Ok(()) => self.heartbeat(agent_id),
+                        Err(e) => {
+                            tracing::warn!("Restarting agent {} failed: {}", agent_id, e);
+                            self.transition(agent_id, AgentState::Unhealthy);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Run `supervise` every heartbeat interval until the fabric is dropped
+    pub fn start_supervisor(self: &Arc<Self>) -> tokio::task::JoinHandle<()> {
+        let fabric: Weak<Self> = Arc::downgrade(self);
+        let mut interval = tokio::time::interval(self.supervision.heartbeat_interval);
+        tokio::spawn(async move {
+            loop {
+                interval.tick().await;
+                let Some(fabric) = fabric.upgrade() else {
+                    return;
+                };
+                fabric.supervise().await;
+            }
+        })
+    }
+
+    fn heartbeat(&self, agent_id: Uuid) {
+        if let Some(state) = self.lifecycle.heartbeat(agent_id) {
+            self.publish_state(agent_id, state);
+        }
+    }
+
+    fn transition(&self, agent_id: Uuid, state: AgentState) {
+        if self.lifecycle.set_state(agent_id, state).is_some_and(|previous| previous != state) {
+            self.publish_state(agent_id, state);
+        }
+    }
+
+    fn publish_state(&self, agent_id: Uuid, state: AgentState) {
+        self.communication.publish(MeshEvent::AgentStateChanged { agent_id, state });
+    }
+
+    /// Execute task through agent mesh, moving on to the next suitable agent the routing
+    /// policy allows when one fails until the attempt budget runs out
     pub async fn execute_task(&self, task: mesh::Task) -> Result<mesh::TaskResult> {
-        let suitable_agents = self.mesh.find_suitable_agents(&task).await?;
+        let span = task_span(&task);
+        self.place_task(task).instrument(span).await
+    }
+
+    async fn place_task(&self, task: mesh::Task) -> Result<mesh::TaskResult> {
+        let suitable_agents = self.mesh.find_suitable_agents(&task, &self.lifecycle).await?;
+        if suitable_agents.is_empty() && !self.mesh.has_capable_agent(&task).await {
+            return Err(MeshError::NoSuitableAgent {
+                task_id: task.id,
+                required: task.required_capabilities.clone(),
+                available: self.mesh.available_capabilities().await,
+            }.into());
+        }
+        let candidates: Vec<Uuid> = suitable_agents.iter().map(|s| s.agent_id).collect();
+        let (candidates, routing) = self.mesh.route(&task, &candidates).await?;
         
-        for agent_id in suitable_agents {
-            if let Some(agent) = self.agents.get(&agent_id) {
-                // Execute SRART pattern
-                let sense_result = agent.sense(&task).await?;
-                let reason_result = agent.reason(&sense_result).await?;
-                let act_result = agent.act(&reason_result).await?;
-                let reflect_result = agent.reflect(&act_result).await?;
-                let _teach_result = agent.teach(&reflect_result).await?;
-                
-                return Ok(mesh::TaskResult {
-                    task_id: task.id,
-                    agent_id,
-                    result: act_result,
-                    metadata: reflect_result,
-                    completed_at: Utc::now(),
-                });
+        let mut failures = Vec::new();
+        for agent_id in candidates {
+            if failures.len() == self.max_attempts {
+                break;
+            }
+            let Some(agent) = self.agents.get(&agent_id).map(|a| a.clone()) else {
+                continue;
+            };
+            match self.dispatch(agent_id, agent.as_ref(), &task).await {
+                Some(Ok(result)) => {
+                    let selection = suitable_agents.into_iter().find(|s| s.agent_id == agent_id);
+                    return Ok(mesh::TaskResult { routing, selection, ..result });
+                }
+                Some(Err(failure)) => {
+                    tracing::warn!("Task {} {}", task.id, failure);
+                    failures.push(failure);
+                }
+                None => tracing::debug!("Agent {} is at capacity, skipping", agent_id),
             }
         }
         
-        Err(anyhow::anyhow!("No suitable agent found"))
+        if failures.is_empty() {
+            return Err(MeshError::AllAgentsBusy {
+                task_id: task.id,
+                required: task.required_capabilities.clone(),
+            }.into());
+        }
+        Err(MeshError::AllAgentsFailed {
+            task_id: task.id,
+            required: task.required_capabilities.clone(),
+            failures,
+        }.into())
+    }
+
+    /// Execute task on a specific agent, bypassing capability matching but not the
+    /// routing policy
+    pub async fn execute_task_on(&self, agent_id: Uuid, task: mesh::Task) -> Result<mesh::TaskResult> {
+        let span = task_span(&task);
+        self.place_task_on(agent_id, task).instrument(span).await
+    }
+
+    async fn place_task_on(&self, agent_id: Uuid, task: mesh::Task) -> Result<mesh::TaskResult> {
+        let agent = self.agents.get(&agent_id)
+            .map(|a| a.clone())
+            .ok_or(MeshError::AgentNotFound { agent_id })?;
+        let (_, routing) = self.mesh.route(&task, &[agent_id]).await?;
+        
+        let state = self.lifecycle.state(agent_id).unwrap_or(AgentState::Stopped);
+        if !state.accepts_tasks() {
+            return Err(MeshError::AgentUnavailable { agent_id, state }.into());
+        }
+        let max_concurrent_tasks = agent.capabilities().max_concurrent_tasks;
+        match self.dispatch(agent_id, agent.as_ref(), &task).await {
+            Some(outcome) => outcome.map(|result| mesh::TaskResult { routing, ..result }).map_err(|failure| {
+                MeshError::AllAgentsFailed {
+                    task_id: task.id,
+                    required: task.required_capabilities.clone(),
+                    failures: vec![failure],
+                }.into()
+            }),
+            None => Err(MeshError::AgentAtCapacity { agent_id, max_concurrent_tasks }.into()),
+        }
+    }
+
+    /// Messages between agents that could not be delivered, oldest first
+    pub fn dead_letters(&self) -> Vec<DeadLetter> {
+        self.communication.dead_letters()
+    }
+
+    /// Per-agent state, load and success figures, with agent counts per state
+    pub async fn mesh_stats(&self) -> MeshStats {
+        self.mesh.stats(&self.lifecycle).await
+    }
+
+    /// Run the task on the agent if it has a free slot, recording the outcome in its
+    /// stats; `None` when the agent is at capacity
+    async fn dispatch(&self, agent_id: Uuid, agent: &dyn Agent, task: &mesh::Task) -> Option<std::result::Result<mesh::TaskResult, AgentFailure>> {
+        if !self.lifecycle.try_begin_task(agent_id, agent.capabilities().max_concurrent_tasks) {
+            return None;
+        }
+        let span = tracing::info_span!("agent_cycle", task_id = %task.id, agent_id = %agent_id);
+        let outcome = self.run_cycle(agent_id, agent, task).instrument(span).await;
+        self.lifecycle.finish_task(agent_id, task.task_type.as_deref(), outcome.is_ok());
+        Some(outcome)
+    }
+
+    /// Run the SRART cycle, publishing progress and stopping at the first failing stage
+    async fn run_cycle(&self, agent_id: Uuid, agent: &dyn Agent, task: &mesh::Task) -> std::result::Result<mesh::TaskResult, AgentFailure> {
+        let events = &self.communication;
+        let task_id = task.id;
+        let fail = |stage: Stage, reason: String| {
+            events.publish(MeshEvent::TaskFailed { task_id, agent_id, stage, reason: reason.clone() });
+            AgentFailure { agent_id, stage, reason }
+        };
+        let completed = |stage: Stage| events.publish(MeshEvent::StageCompleted { task_id, agent_id, stage });
+        
+        events.publish(MeshEvent::TaskStarted { task_id, agent_id });
+        
+        let sense_result = agent.sense(task).await.map_err(|e| fail(Stage::Sense, e.to_string()))?;
+        completed(Stage::Sense);
+        let reason_result = agent.reason(&sense_result).await.map_err(|e| fail(Stage::Reason, e.to_string()))?;
+        completed(Stage::Reason);
+        let act_result = agent.act(&reason_result).await.map_err(|e| fail(Stage::Act, e.to_string()))?;
+        if !act_result.success {
+            return Err(fail(Stage::Act, format!("action reported failure: {}", act_result.outcome)));
+        }
+        completed(Stage::Act);
+        let reflect_result = agent.reflect(&act_result).await.map_err(|e| fail(Stage::Reflect, e.to_string()))?;
+        completed(Stage::Reflect);
+        let _teach_result = agent.teach(&reflect_result).await.map_err(|e| fail(Stage::Teach, e.to_string()))?;
+        completed(Stage::Teach);
+        
+        events.publish(MeshEvent::TaskSucceeded { task_id, agent_id });
+        Ok(mesh::TaskResult {
+            task_id,
+            agent_id,
+            result: act_result,
+            metadata: reflect_result,
+            routing: Default::default(),
+            selection: None,
+            completed_at: Utc::now(),
+        })
     }
 }
 
+/// Span a task is placed in, parented on the span that submitted it
+fn task_span(task: &mesh::Task) -> tracing::Span {
+    let span = tracing::info_span!("execute_task", task_id = %task.id);
+    cognitive_kernel::telemetry::set_parent(&span, task.trace_parent.as_deref());
+    span
+}
+
 /// Agent trait with SRART pattern
 #[async_trait::async_trait]
 pub trait Agent: Send + Sync {
@@ -81,6 +349,10 @@ pub trait Agent: Send + Sync {
     fn agent_type(&self) -> AgentType;
     fn capabilities(&self) -> AgentCapabilities;
     
+    /// Receive the agent's messaging handle when it is deployed; agents that talk to
+    /// their peers, for instance to deliver what they `teach`, keep it
+    fn connect(&self, _handle: AgentHandle) {}
+    
     /// Sense: Gather information and context
     async fn sense(&self, task: &mesh::Task) -> Result<SenseResult>;
     
@@ -95,6 +367,11 @@ pub trait Agent: Send + Sync {
     
     /// Teach: Share learnings with other agents
     async fn teach(&self, reflect_result: &ReflectResult) -> Result<TeachResult>;
+    
+    /// Recover after missing heartbeats; called by the fabric's supervisor
+    async fn restart(&self) -> Result<()> {
+        Ok(())
+    }
 }
 
 /// Agent execution results
@@ -140,4 +417,396 @@ pub struct ActionStep {
     pub parameters: serde_json::Value,
     pub executed: bool,
     pub result: Option<serde_json::Value>,
-} 
\ No newline at end of file
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::sync::broadcast::error::TryRecvError;
+
+    pub(crate) struct MockAgent {
+        pub(crate) id: Uuid,
+        skills: Vec<&'static str>,
+        /// Stage that returns an error
+        fail_at: Option<Stage>,
+        /// Whether `act` reports success when it doesn't error
+        act_success: bool,
+        /// Agents `teach` sends its lesson to
+        teach_to: Vec<Uuid>,
+        handle: std::sync::OnceLock<AgentHandle>,
+        restarts: std::sync::atomic::AtomicUsize,
+    }
+
+    impl MockAgent {
+        pub(crate) fn new(skills: Vec<&'static str>) -> Self {
+            Self {
+                id: Uuid::new_v4(),
+                skills,
+                fail_at: None,
+                act_success: true,
+                teach_to: vec![],
+                handle: std::sync::OnceLock::new(),
+                restarts: Default::default(),
+            }
+        }
+
+        fn handle(&self) -> &AgentHandle {
+            self.handle.get().expect("agent not deployed")
+        }
+
+        fn check(&self, stage: Stage) -> Result<()> {
+            match self.fail_at {
+                Some(failing) if failing == stage => Err(anyhow::anyhow!("{} unavailable", stage)),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Agent for MockAgent {
+        fn id(&self) -> Uuid {
+            self.id
+        }
+
+        fn agent_type(&self) -> AgentType {
+            AgentType::Generic
+        }
+
+        fn capabilities(&self) -> AgentCapabilities {
+            AgentCapabilities::new(&self.skills)
+        }
+
+        fn connect(&self, handle: AgentHandle) {
+            let _ = self.handle.set(handle);
+        }
+
+        async fn sense(&self, task: &mesh::Task) -> Result<SenseResult> {
+            self.check(Stage::Sense)?;
+            Ok(SenseResult { context: task.payload.clone(), observations: vec![], relevance_score: 1.0 })
+        }
+
+        async fn reason(&self, _sense_result: &SenseResult) -> Result<ReasonResult> {
+            self.check(Stage::Reason)?;
+            Ok(ReasonResult { analysis: String::new(), plan: vec![], confidence: 1.0 })
+        }
+
+        async fn act(&self, _reason_result: &ReasonResult) -> Result<ActResult> {
+            self.check(Stage::Act)?;
+            Ok(ActResult {
+                executed_steps: vec![],
+                outcome: serde_json::json!({ "handled_by": self.id }),
+                success: self.act_success,
+            })
+        }
+
+        async fn reflect(&self, _act_result: &ActResult) -> Result<ReflectResult> {
+            self.check(Stage::Reflect)?;
+            Ok(ReflectResult { performance_analysis: String::new(), lessons_learned: vec![], improvement_suggestions: vec![] })
+        }
+
+        async fn teach(&self, _reflect_result: &ReflectResult) -> Result<TeachResult> {
+            self.check(Stage::Teach)?;
+            let lesson = "retry after timeouts".to_string();
+            for recipient in &self.teach_to {
+                self.handle().send(*recipient, Message::new(serde_json::json!({ "lesson": lesson })))?;
+            }
+            Ok(TeachResult { knowledge_shared: vec![lesson], recipients: self.teach_to.clone(), effectiveness: 1.0 })
+        }
+
+        async fn restart(&self) -> Result<()> {
+            self.restarts.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    fn drain(events: &mut tokio::sync::broadcast::Receiver<MeshEvent>) -> Vec<MeshEvent> {
+        let mut drained = Vec::new();
+        loop {
+            match events.try_recv() {
+                Ok(event) => drained.push(event),
+                Err(TryRecvError::Empty) => return drained,
+                Err(e) => panic!("event stream broken: {}", e),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_to_next_agent_after_act_failure() {
+        let fabric = AgentMeshFabric::new().await.unwrap();
+        let failing = fabric.deploy_agent(Arc::new(MockAgent { fail_at: Some(Stage::Act), ..MockAgent::new(vec!["deploy"]) })).await.unwrap();
+        let working = fabric.deploy_agent(Arc::new(MockAgent::new(vec!["deploy", "review"]))).await.unwrap();
+        let mut events = fabric.communication.subscribe();
+
+        let task = mesh::Task::new("deploy the site").with_capability("deploy");
+        let task_id = task.id;
+        let result = fabric.execute_task(task).await.unwrap();
+
+        assert_eq!(result.agent_id, working);
+        assert_eq!(result.result.outcome["handled_by"], serde_json::json!(working));
+
+        let events = drain(&mut events);
+        assert_eq!(events[0], MeshEvent::TaskStarted { task_id, agent_id: failing });
+        assert!(events.contains(&MeshEvent::TaskFailed {
+            task_id,
+            agent_id: failing,
+            stage: Stage::Act,
+            reason: "act unavailable".to_string(),
+        }));
+        let completed = events.iter().filter(|e| matches!(e, MeshEvent::StageCompleted { agent_id, .. } if *agent_id == working)).count();
+        assert_eq!(completed, 5);
+        assert_eq!(events.last(), Some(&MeshEvent::TaskSucceeded { task_id, agent_id: working }));
+    }
+
+    #[tokio::test]
+    async fn test_reports_every_failure_and_requirements() {
+        let fabric = AgentMeshFabric::new().await.unwrap().with_max_attempts(2);
+        let erroring = fabric.deploy_agent(Arc::new(MockAgent { fail_at: Some(Stage::Sense), ..MockAgent::new(vec!["sql"]) })).await.unwrap();
+        let unsuccessful = fabric.deploy_agent(Arc::new(MockAgent { act_success: false, ..MockAgent::new(vec!["sql"]) })).await.unwrap();
+        fabric.deploy_agent(Arc::new(MockAgent::new(vec!["sql"]))).await.unwrap();
+
+        let err = fabric.execute_task(mesh::Task::new("migrate").with_capability("sql")).await.unwrap_err();
+        match err.downcast_ref::<MeshError>() {
+            Some(MeshError::AllAgentsFailed { required, failures, .. }) => {
+                assert_eq!(required, &vec!["sql".to_string()]);
+                assert_eq!(failures.len(), 2);
+                assert_eq!((failures[0].agent_id, failures[0].stage), (erroring, Stage::Sense));
+                assert_eq!((failures[1].agent_id, failures[1].stage), (unsuccessful, Stage::Act));
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert!(err.to_string().contains("action reported failure"));
+    }
+
+    #[tokio::test]
+    async fn test_no_suitable_agent_lists_capabilities() {
+        let fabric = AgentMeshFabric::new().await.unwrap();
+        fabric.deploy_agent(Arc::new(MockAgent::new(vec!["review", "deploy"]))).await.unwrap();
+
+        let err = fabric.execute_task(mesh::Task::new("train").with_capability("gpu")).await.unwrap_err();
+        assert_eq!(
+            err.to_string().split_once(": ").unwrap().1,
+            "requires [gpu], mesh offers [deploy, review]"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rejects_dispatch_to_agents_at_capacity() {
+        let fabric = AgentMeshFabric::new().await.unwrap();
+        let busy = fabric.deploy_agent(Arc::new(MockAgent::new(vec!["deploy"]))).await.unwrap();
+        let idle = fabric.deploy_agent(Arc::new(MockAgent::new(vec!["deploy"]))).await.unwrap();
+        assert!(fabric.lifecycle.try_begin_task(busy, 1));
+
+        let err = fabric.execute_task_on(busy, mesh::Task::new("deploy")).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<MeshError>(), Some(MeshError::AgentAtCapacity { max_concurrent_tasks: 1, .. })));
+
+        let result = fabric.execute_task(mesh::Task::new("deploy").with_capability("deploy").with_task_type("release")).await.unwrap();
+        assert_eq!(result.agent_id, idle);
+
+        assert!(fabric.lifecycle.try_begin_task(idle, 1));
+        let err = fabric.execute_task(mesh::Task::new("deploy").with_capability("deploy")).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<MeshError>(), Some(MeshError::AllAgentsBusy { .. })));
+
+        let stats = fabric.mesh_stats().await.agents;
+        assert_eq!(stats.len(), 2);
+        assert_eq!((stats[0].agent_id, stats[0].in_flight, stats[0].succeeded), (busy, 1, 0));
+        assert_eq!((stats[1].agent_id, stats[1].in_flight, stats[1].succeeded), (idle, 1, 1));
+        assert_eq!(fabric.lifecycle.stats(idle).by_task_type["release"], (1, 1));
+    }
+
+    #[tokio::test]
+    async fn test_execute_task_on_routes_to_named_agent() {
+        let fabric = AgentMeshFabric::new().await.unwrap();
+        fabric.deploy_agent(Arc::new(MockAgent::new(vec!["deploy"]))).await.unwrap();
+        let chosen = fabric.deploy_agent(Arc::new(MockAgent::new(vec![]))).await.unwrap();
+
+        let result = fabric.execute_task_on(chosen, mesh::Task::new("deploy").with_capability("deploy")).await.unwrap();
+        assert_eq!(result.agent_id, chosen);
+
+        let missing = Uuid::new_v4();
+        let err = fabric.execute_task_on(missing, mesh::Task::new("deploy")).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<MeshError>(), Some(MeshError::AgentNotFound { agent_id }) if *agent_id == missing));
+    }
+
+    #[tokio::test]
+    async fn test_routing_policy_is_applied_and_reported() {
+        let fabric = AgentMeshFabric::new().await.unwrap();
+        fabric.deploy_agent(Arc::new(MockAgent::new(vec!["deploy"]))).await.unwrap();
+        let db = fabric.deploy_agent(Arc::new(MockAgent::new(vec!["deploy", "sql"]))).await.unwrap();
+        fabric.mesh.load_policies(r#"{
+            "pools": {"db": {"capabilities": ["sql"]}},
+            "rules": [
+                {"name": "database-tasks", "action": "route", "pool": "db", "match": {"task_type": "database"}},
+                {"name": "no-critical", "action": "deny", "match": {"risk_levels": ["critical"]}}
+            ]
+        }"#).await.unwrap();
+
+        let result = fabric.execute_task(mesh::Task::new("migrate").with_capability("deploy").with_task_type("database")).await.unwrap();
+        assert_eq!(result.agent_id, db);
+        assert_eq!(result.routing.rule.as_deref(), Some("database-tasks"));
+
+        let critical = mesh::Task::new("drop tables").with_risk_level("critical");
+        let err = fabric.execute_task_on(db, critical).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<MeshError>(), Some(MeshError::PolicyViolation { rule, .. }) if rule == "no-critical"));
+
+        // An invalid reload keeps the policy in force
+        assert!(fabric.mesh.load_policies("[[rules]]\nname = \"broken\"\naction = \"route\"").await.is_err());
+        assert_eq!(fabric.mesh.policy().await.rules.len(), 2);
+        fabric.mesh.load_policies("").await.unwrap();
+        let result = fabric.execute_task(mesh::Task::new("deploy").with_risk_level("critical")).await.unwrap();
+        assert_eq!(result.routing, RoutingDecision::default());
+    }
+
+    #[tokio::test]
+    async fn test_request_reply_between_agents() {
+        let fabric = AgentMeshFabric::new().await.unwrap();
+        let asker = Arc::new(MockAgent::new(vec![]));
+        let responder = Arc::new(MockAgent::new(vec![]));
+        fabric.deploy_agent(asker.clone()).await.unwrap();
+        fabric.deploy_agent(responder.clone()).await.unwrap();
+
+        let serving = responder.clone();
+        tokio::spawn(async move {
+            let request = serving.handle().recv().await.unwrap();
+            serving.handle().reply(&request, serde_json::json!({ "pong": request.payload["ping"] })).unwrap();
+        });
+
+        let request = Message::new(serde_json::json!({ "ping": 7 }));
+        let request_id = request.id;
+        let reply = asker.handle().request(responder.id, request, Duration::from_secs(5)).await.unwrap();
+        assert_eq!(reply.payload, serde_json::json!({ "pong": 7 }));
+        assert_eq!(reply.correlation_id, Some(request_id));
+        assert_eq!(reply.from, Some(responder.id));
+
+        let silent = asker.handle().request(responder.id, Message::new(serde_json::json!({})), Duration::from_millis(20)).await;
+        assert!(matches!(silent, Err(MessageError::Timeout { agent_id, .. }) if agent_id == responder.id));
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_reaches_every_subscriber() {
+        let fabric = AgentMeshFabric::new().await.unwrap();
+        let agents: Vec<Arc<MockAgent>> = (0..4).map(|_| Arc::new(MockAgent::new(vec![]))).collect();
+        for agent in &agents {
+            fabric.deploy_agent(agent.clone()).await.unwrap();
+        }
+        for agent in &agents[..3] {
+            agent.handle().subscribe("alerts");
+        }
+
+        let delivered = agents[0].handle().broadcast("alerts", Message::new(serde_json::json!("disk full")));
+        assert_eq!(delivered, 2);
+        for agent in &agents[1..3] {
+            let message = agent.handle().recv().await.unwrap();
+            assert_eq!(message.topic.as_deref(), Some("alerts"));
+            assert_eq!(message.from, Some(agents[0].id));
+        }
+        let unsubscribed = tokio::time::timeout(Duration::from_millis(20), agents[3].handle().recv()).await;
+        assert!(unsubscribed.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_teach_delivers_and_undeliverable_messages_are_dead_lettered() {
+        let fabric = AgentMeshFabric::new().await.unwrap();
+        let student = Arc::new(MockAgent::new(vec![]));
+        let teacher = MockAgent { teach_to: vec![student.id], ..MockAgent::new(vec!["deploy"]) };
+        fabric.deploy_agent(student.clone()).await.unwrap();
+        fabric.deploy_agent(Arc::new(teacher)).await.unwrap();
+
+        fabric.execute_task(mesh::Task::new("deploy").with_capability("deploy")).await.unwrap();
+        let lesson = student.handle().recv().await.unwrap();
+        assert_eq!(lesson.payload["lesson"], "retry after timeouts");
+
+        let stranger = Uuid::new_v4();
+        let err = student.handle().send(stranger, Message::new(serde_json::json!(1))).unwrap_err();
+        assert_eq!(err, MessageError::UnknownAgent { agent_id: stranger });
+
+        let full = loop {
+            if let Err(e) = fabric.communication.send(student.id, Message::new(serde_json::json!(2))) {
+                break e;
+            }
+        };
+        assert_eq!(full, MessageError::MailboxFull { agent_id: student.id });
+
+        let dead_letters = fabric.dead_letters();
+        assert_eq!(dead_letters.len(), 2);
+        assert_eq!((dead_letters[0].to, dead_letters[0].message.from), (stranger, Some(student.id)));
+        assert_eq!(dead_letters[1].reason, full);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stuck_agent_is_taken_out_of_rotation_and_restarted() {
+        let policy = SupervisionPolicy {
+            heartbeat_interval: Duration::from_secs(1),
+            missed_heartbeats: 2,
+            max_restarts: 1,
+            restart_backoff: Duration::from_secs(1),
+        };
+        let fabric = AgentMeshFabric::new().await.unwrap().with_supervision(policy);
+        let stuck = Arc::new(MockAgent::new(vec!["deploy"]));
+        let live = Arc::new(MockAgent::new(vec!["deploy"]));
+        fabric.deploy_agent(stuck.clone()).await.unwrap();
+        fabric.deploy_agent(live.clone()).await.unwrap();
+        let restarts = || stuck.restarts.load(std::sync::atomic::Ordering::SeqCst);
+
+        tokio::time::advance(Duration::from_millis(1500)).await;
+        live.handle().heartbeat();
+        tokio::time::advance(Duration::from_secs(1)).await;
+        fabric.supervise().await;
+
+        assert_eq!(fabric.agent_state(stuck.id), Some(AgentState::Unhealthy));
+        assert_eq!(fabric.agent_state(live.id), Some(AgentState::Healthy));
+        assert_eq!(restarts(), 0);
+        let result = fabric.execute_task(mesh::Task::new("deploy").with_capability("deploy")).await.unwrap();
+        assert_eq!(result.agent_id, live.id);
+        let err = fabric.execute_task_on(stuck.id, mesh::Task::new("deploy")).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<MeshError>(), Some(MeshError::AgentUnavailable { state: AgentState::Unhealthy, .. })));
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        fabric.supervise().await;
+        assert_eq!(restarts(), 1);
+        assert_eq!(fabric.agent_state(stuck.id), Some(AgentState::Healthy));
+
+        // Still stuck after the restart, with no restarts left
+        tokio::time::advance(Duration::from_millis(2500)).await;
+        live.handle().heartbeat();
+        let mut events = fabric.communication.subscribe();
+        fabric.supervise().await;
+        assert_eq!(fabric.agent_state(stuck.id), Some(AgentState::Failed));
+        assert_eq!(drain(&mut events), vec![
+            MeshEvent::AgentStateChanged { agent_id: stuck.id, state: AgentState::Unhealthy },
+            MeshEvent::AgentStateChanged { agent_id: stuck.id, state: AgentState::Failed },
+        ]);
+        assert_eq!(restarts(), 1);
+
+        let stats = fabric.mesh_stats().await;
+        assert_eq!(stats.by_state[&AgentState::Failed], 1);
+        assert_eq!(stats.by_state[&AgentState::Healthy], 1);
+    }
+
+    #[tokio::test]
+    async fn test_drain_lets_in_flight_tasks_finish_before_removal() {
+        let fabric = Arc::new(AgentMeshFabric::new().await.unwrap());
+        let draining = fabric.deploy_agent(Arc::new(MockAgent::new(vec!["deploy"]))).await.unwrap();
+        let other = fabric.deploy_agent(Arc::new(MockAgent::new(vec!["deploy"]))).await.unwrap();
+        assert!(fabric.lifecycle.try_begin_task(draining, 1));
+
+        let removal = tokio::spawn({
+            let fabric = fabric.clone();
+            async move { fabric.remove_agent(draining).await }
+        });
+        while fabric.agent_state(draining) != Some(AgentState::Draining) {
+            tokio::task::yield_now().await;
+        }
+
+        let result = fabric.execute_task(mesh::Task::new("deploy").with_capability("deploy")).await.unwrap();
+        assert_eq!(result.agent_id, other);
+        assert!(!removal.is_finished());
+
+        fabric.lifecycle.finish_task(draining, None, true);
+        removal.await.unwrap().unwrap();
+
+        assert_eq!(fabric.agent_state(draining), None);
+        assert_eq!(fabric.mesh_stats().await.agents.len(), 1);
+        let err = fabric.communication.send(draining, Message::new(serde_json::json!({}))).unwrap_err();
+        assert_eq!(err, MessageError::UnknownAgent { agent_id: draining });
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use anyhow::Result;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+use tokio::time::Instant;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AgentState {
+    /// Deployed or restarted, not yet confirmed alive
+    Starting,
+    Healthy,
+    /// Missed too many heartbeats; waiting to be restarted
+    Unhealthy,
+    /// Finishing in-flight tasks before stopping
+    Draining,
+    Stopped,
+    /// Out of restarts
+    Failed,
+}
+
+impl AgentState {
+    /// Whether new tasks may be routed to an agent in this state
+    pub fn accepts_tasks(self) -> bool {
+        self == AgentState::Healthy
+    }
+}
+
+/// How the fabric watches over deployed agents
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SupervisionPolicy {
+    /// How often agents are expected to report liveness
+    pub heartbeat_interval: Duration,
+    /// Intervals without a heartbeat before an agent is marked Unhealthy
+    pub missed_heartbeats: u32,
+    /// Restarts attempted over an agent's lifetime before it is marked Failed
+    pub max_restarts: u32,
+    /// Wait before the first restart; doubled for each restart after it
+    pub restart_backoff: Duration,
+}
+
+impl Default for SupervisionPolicy {
+    fn default() -> Self {
+        Self {
+            heartbeat_interval: Duration::from_secs(5),
+            missed_heartbeats: 3,
+            max_restarts: 3,
+            restart_backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+/// What the supervisor should do about an unhealthy agent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartDecision {
+    Wait,
+    Restart,
+    GiveUp,
+}
+
+#[derive(Debug, Clone)]
+struct Health {
+    state: AgentState,
+    last_heartbeat: Instant,
+    unhealthy_since: Option<Instant>,
+    restarts: u32,
+}
+
+/// Work an agent is doing and how its finished tasks went
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AgentStats {
+    pub in_flight: usize,
+    pub succeeded: u64,
+    pub failed: u64,
+    /// `(succeeded, attempted)` per task type
+    pub by_task_type: HashMap<String, (u64, u64)>,
+}
+
+impl AgentStats {
+    /// Success rate for a task type, or overall when `task_type` is `None`. Smoothed so an
+    /// agent with no history scores 0.5 rather than 0 or 1.
+    pub fn success_rate(&self, task_type: Option<&str>) -> f64 {
+        let (succeeded, attempted) = match task_type {
+            Some(task_type) => self.by_task_type.get(task_type).copied().unwrap_or_default(),
+            None => (self.succeeded, self.succeeded + self.failed),
+        };
+        (succeeded as f64 + 1.0) / (attempted as f64 + 2.0)
+    }
+}
+
+/// Tracks each deployed agent's health, load and track record.
+///
+/// The health entry is always locked before the stats entry, so a state change and a
+/// task claim can't interleave.
+#[derive(Debug, Default)]
+pub struct LifecycleManager {
+    health: DashMap<Uuid, Health>,
+    stats: DashMap<Uuid, AgentStats>,
+    /// Woken whenever a task finishes, for drains waiting on in-flight work
+    task_finished: Notify,
+}
+
+impl LifecycleManager {
+    pub async fn new() -> Result<Self> {
+        Ok(Self::default())
+    }
+
+    pub async fn start_agent(&self, agent_id: Uuid) -> Result<()> {
+        self.health.insert(agent_id, Health {
+            state: AgentState::Starting,
+            last_heartbeat: Instant::now(),
+            unhealthy_since: None,
+            restarts: 0,
+        });
+        self.stats.entry(agent_id).or_default();
+        Ok(())
+    }
+
+    pub async fn stop_agent(&self, agent_id: Uuid) -> Result<()> {
+        self.set_state(agent_id, AgentState::Stopped);
+        Ok(())
+    }
+
+    /// Forget the agent entirely
+    pub fn remove_agent(&self, agent_id: Uuid) {
+        self.health.remove(&agent_id);
+        self.stats.remove(&agent_id);
+    }
+
+    pub fn state(&self, agent_id: Uuid) -> Option<AgentState> {
+        self.health.get(&agent_id).map(|h| h.state)
+    }
+
+    /// Set the agent's state, returning the previous one
+    pub fn set_state(&self, agent_id: Uuid, state: AgentState) -> Option<AgentState> {
+        let mut health = self.health.get_mut(&agent_id)?;
+        let previous = health.state;
+        health.state = state;
+        health.unhealthy_since = (state == AgentState::Unhealthy).then(Instant::now);
+        Some(previous)
+    }
+
+    /// Record that the agent is alive. A starting or unhealthy agent becomes Healthy;
+    /// returns the new state when it changed.
+    pub fn heartbeat(&self, agent_id: Uuid) -> Option<AgentState> {
+        let mut health = self.health.get_mut(&agent_id)?;
+        health.last_heartbeat = Instant::now();
+        if matches!(health.state, AgentState::Starting | AgentState::Unhealthy) {
+            health.state = AgentState::Healthy;
+            health.unhealthy_since = None;
+            return Some(AgentState::Healthy);
+        }
+        None
+    }
+
+    /// Live agents that have gone longer than the policy allows without a heartbeat
+    pub fn overdue(&self, policy: &SupervisionPolicy) -> Vec<Uuid> {
+        let allowed = policy.heartbeat_interval * policy.missed_heartbeats;
+        self.health.iter()
+            .filter(|h| matches!(h.state, AgentState::Starting | AgentState::Healthy))
+            .filter(|h| h.last_heartbeat.elapsed() > allowed)
+            .map(|h| *h.key())
+            .collect()
+    }
+
+    /// Whether an unhealthy agent is due a restart under the policy's backoff
+    pub fn restart_decision(&self, agent_id: Uuid, policy: &SupervisionPolicy) -> RestartDecision {
+        let Some(health) = self.health.get(&agent_id) else {
+            return RestartDecision::Wait;
+        };
+        let Some(since) = health.unhealthy_since.filter(|_| health.state == AgentState::Unhealthy) else {
+            return RestartDecision::Wait;
+        };
+        if health.restarts >= policy.max_restarts {
+            return RestartDecision::GiveUp;
+        }
+        let backoff = policy.restart_backoff * 2u32.saturating_pow(health.restarts);
+        if since.elapsed() >= backoff {
+            RestartDecision::Restart
+        } else {
+            RestartDecision::Wait
+        }
+    }
+
+    /// Count a restart attempt and start the agent's heartbeat clock over
+    pub fn record_restart(&self, agent_id: Uuid) {
+        if let Some(mut health) = self.health.get_mut(&agent_id) {
+            health.restarts += 1;
+            health.state = AgentState::Starting;
+            health.last_heartbeat = Instant::now();
+            health.unhealthy_since = None;
+        }
+    }
+
+    pub fn restarts(&self, agent_id: Uuid) -> u32 {
+        self.health.get(&agent_id).map(|h| h.restarts).unwrap_or_default()
+    }
+
+    pub fn agents_in(&self, state: AgentState) -> Vec<Uuid> {
+        self.health.iter().filter(|h| h.state == state).map(|h| *h.key()).collect()
+    }
+
+    pub fn stats(&self, agent_id: Uuid) -> AgentStats {
+        self.stats.get(&agent_id).map(|s| s.clone()).unwrap_or_default()
+    }
+
+    /// Claim a task slot on the agent; false when it isn't accepting tasks or already
+    /// runs `capacity` of them
+    pub fn try_begin_task(&self, agent_id: Uuid, capacity: usize) -> bool {
+        let Some(health) = self.health.get(&agent_id) else {
+            return false;
+        };
+        if !health.state.accepts_tasks() {
+            return false;
+        }
+        let mut stats = self.stats.entry(agent_id).or_default();
+        if stats.in_flight >= capacity {
+            return false;
+        }
+        stats.in_flight += 1;
+        true
+    }
+
+    /// Release a slot claimed with `try_begin_task` and record how the task went
+    pub fn finish_task(&self, agent_id: Uuid, task_type: Option<&str>, succeeded: bool) {
+        if let Some(mut stats) = self.stats.get_mut(&agent_id) {
+            stats.in_flight = stats.in_flight.saturating_sub(1);
+            if succeeded {
+                stats.succeeded += 1;
+            } else {
+                stats.failed += 1;
+            }
+            if let Some(task_type) = task_type {
+                let (task_succeeded, attempted) = stats.by_task_type.entry(task_type.to_string()).or_default();
+                *task_succeeded += u64::from(succeeded);
+                *attempted += 1;
+            }
+        }
+        self.task_finished.notify_waiters();
+    }
+
+    /// Wait until the agent has no tasks in flight
+    pub async fn wait_idle(&self, agent_id: Uuid) {
+        loop {
+            let finished = self.task_finished.notified();
+            tokio::pin!(finished);
+            finished.as_mut().enable();
+            if self.stats(agent_id).in_flight == 0 {
+                return;
+            }
+            finished.await;
+        }
+    }
+}
@@ -0,0 +1,516 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, SystemTime};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::agent::AgentCapabilities;
+use crate::error::MeshError;
+use crate::lifecycle::{AgentState, AgentStats, LifecycleManager};
+use crate::policy::{RoutingDecision, RoutingPolicy};
+use crate::skills::{RegisteredSkill, Skill, SkillMatch, SkillRegistry};
+use crate::{ActResult, ReflectResult};
+
+/// How agents in the mesh are connected
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MeshTopology {
+    #[default]
+    FullMesh,
+    Hierarchical,
+}
+
+/// Unit of work submitted to the mesh
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Task {
+    pub id: Uuid,
+    pub description: String,
+    /// Skills an agent must have to be given this task
+    pub required_capabilities: Vec<String>,
+    /// Skills that make an agent a better fit without being required
+    pub optional_capabilities: Vec<String>,
+    /// Kind of work, used to look up each agent's success rate on similar tasks
+    pub task_type: Option<String>,
+    /// Preferred agent locality, such as a region or host
+    pub locality: Option<String>,
+    /// Labels routing policies match on
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Risk of the work, such as `low` or `critical`, for routing policies
+    #[serde(default)]
+    pub risk_level: Option<String>,
+    pub payload: serde_json::Value,
+    /// W3C `traceparent` of the span that submitted the task, which the mesh's spans for
+    /// it are parented on
+    #[serde(default)]
+    pub trace_parent: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Task {
+    pub fn new(description: impl Into<String>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            description: description.into(),
+            required_capabilities: Vec::new(),
+            optional_capabilities: Vec::new(),
+            task_type: None,
+            locality: None,
+            tags: Vec::new(),
+            risk_level: None,
+            payload: serde_json::Value::Null,
+            trace_parent: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    pub fn with_capability(mut self, capability: impl Into<String>) -> Self {
+        self.required_capabilities.push(capability.into());
+        self
+    }
+
+    pub fn with_optional_capability(mut self, capability: impl Into<String>) -> Self {
+        self.optional_capabilities.push(capability.into());
+        self
+    }
+
+    pub fn with_task_type(mut self, task_type: impl Into<String>) -> Self {
+        self.task_type = Some(task_type.into());
+        self
+    }
+
+    pub fn with_locality(mut self, locality: impl Into<String>) -> Self {
+        self.locality = Some(locality.into());
+        self
+    }
+
+    pub fn with_tag(mut self, tag: impl Into<String>) -> Self {
+        self.tags.push(tag.into());
+        self
+    }
+
+    pub fn with_risk_level(mut self, risk_level: impl Into<String>) -> Self {
+        self.risk_level = Some(risk_level.into());
+        self
+    }
+
+    pub fn with_payload(mut self, payload: serde_json::Value) -> Self {
+        self.payload = payload;
+        self
+    }
+
+    pub fn with_trace_parent(mut self, traceparent: impl Into<String>) -> Self {
+        self.trace_parent = Some(traceparent.into());
+        self
+    }
+}
+
+/// Outcome of a task that an agent carried through the full SRART cycle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskResult {
+    pub task_id: Uuid,
+    pub agent_id: Uuid,
+    pub result: ActResult,
+    pub metadata: ReflectResult,
+    /// Which routing rule placed the task, for auditing
+    #[serde(default)]
+    pub routing: RoutingDecision,
+    /// How the agent was matched to the task; `None` when it was named by the caller
+    #[serde(default)]
+    pub selection: Option<AgentSelection>,
+    pub completed_at: DateTime<Utc>,
+}
+
+/// An agent found suitable for a task, and why
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AgentSelection {
+    pub agent_id: Uuid,
+    pub score: f64,
+    /// The agent's registered skill that matched the task description, if any did
+    #[serde(default)]
+    pub skill_match: Option<SkillMatch>,
+}
+
+/// Per-agent figures reported by `AgentMeshFabric::mesh_stats`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AgentLoad {
+    pub agent_id: Uuid,
+    pub state: AgentState,
+    pub in_flight: usize,
+    pub max_concurrent_tasks: usize,
+    pub succeeded: u64,
+    pub failed: u64,
+    pub success_rate: f64,
+}
+
+/// Snapshot of the whole mesh
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MeshStats {
+    pub agents: Vec<AgentLoad>,
+    /// Number of agents in each state
+    pub by_state: HashMap<AgentState, usize>,
+}
+
+/// Registry of deployed agents and the capabilities they advertise
+#[derive(Debug, Default)]
+pub struct AgentMesh {
+    pub topology: MeshTopology,
+    /// Kept in registration order so agent selection is deterministic
+    agents: RwLock<Vec<(Uuid, AgentCapabilities)>>,
+    /// Replaced wholesale on reload; routing works on a snapshot
+    policy: RwLock<Arc<RoutingPolicy>>,
+    /// Skills agents registered at runtime; without one only capability names match
+    skills: RwLock<Option<Arc<SkillRegistry>>>,
+}
+
+impl AgentMesh {
+    pub async fn new() -> Result<Self> {
+        Ok(Self::default())
+    }
+
+    pub async fn register_agent(&self, agent_id: Uuid, capabilities: AgentCapabilities) -> Result<()> {
+        let mut agents = self.agents.write().await;
+        agents.retain(|(id, _)| *id != agent_id);
+        agents.push((agent_id, capabilities));
+        Ok(())
+    }
+
+    /// Take the agent out of the mesh along with any skills it registered
+    pub async fn unregister_agent(&self, agent_id: Uuid) -> Result<()> {
+        self.agents.write().await.retain(|(id, _)| *id != agent_id);
+        if let Some(registry) = self.skill_registry().await {
+            registry.unregister_agent(agent_id).await?;
+        }
+        Ok(())
+    }
+
+    /// Healthy agents with spare capacity that either cover everything the task requires
+    /// or have a registered skill matching its description, best scored first; ties keep
+    /// registration order. A skill match adds its similarity to the agent's score.
+    pub async fn find_suitable_agents(&self, task: &Task, lifecycle: &LifecycleManager) -> Result<Vec<AgentSelection>> {
+        let mut skill_matches = self.skill_matches(task).await;
+        let mut selected: Vec<AgentSelection> = self.agents.read().await
+            .iter()
+            .filter_map(|(id, capabilities)| {
+                let stats = lifecycle.stats(*id);
+                let available = lifecycle.state(*id).is_some_and(AgentState::accepts_tasks);
+                if !available || stats.in_flight >= capabilities.max_concurrent_tasks {
+                    return None;
+                }
+                let skill_match = skill_matches.remove(id);
+                if skill_match.is_none() && !capabilities.covers(&task.required_capabilities) {
+                    return None;
+                }
+                let similarity = skill_match.as_ref().map_or(0.0, |m| f64::from(m.score));
+                let score = (fit(capabilities, task, &stats) + SKILL_WEIGHT * similarity) / capabilities.cost_weight.max(f64::EPSILON);
+                Some(AgentSelection { agent_id: *id, score, skill_match })
+            })
+            .collect();
+        selected.sort_by(|a, b| b.score.total_cmp(&a.score));
+        Ok(selected)
+    }
+
+    /// Whether any registered agent covers the task's requirements or has a skill
+    /// matching it, busy or not
+    pub async fn has_capable_agent(&self, task: &Task) -> bool {
+        if self.agents.read().await.iter().any(|(_, capabilities)| capabilities.covers(&task.required_capabilities)) {
+            return true;
+        }
+        let skill_matches = self.skill_matches(task).await;
+        self.agents.read().await.iter().any(|(id, _)| skill_matches.contains_key(id))
+    }
+
+    /// Per agent, its registered skill matching the task description. Matching is
+    /// best effort: when embedding fails, only capability names are matched.
+    async fn skill_matches(&self, task: &Task) -> HashMap<Uuid, SkillMatch> {
+        let Some(registry) = self.skill_registry().await else {
+            return HashMap::new();
+        };
+        registry.matches(&task.description).await.unwrap_or_else(|e| {
+            tracing::warn!("Matching task {} against agent skills failed: {}", task.id, e);
+            HashMap::new()
+        })
+    }
+
+    pub async fn set_skill_registry(&self, registry: SkillRegistry) {
+        *self.skills.write().await = Some(Arc::new(registry));
+    }
+
+    pub async fn skill_registry(&self) -> Option<Arc<SkillRegistry>> {
+        self.skills.read().await.clone()
+    }
+
+    /// Register a skill for the agent, replacing its skill of the same name
+    pub async fn register_skill(&self, agent_id: Uuid, skill: Skill) -> Result<RegisteredSkill> {
+        let registry = self.skill_registry().await.ok_or(MeshError::NoSkillRegistry)?;
+        registry.register(agent_id, skill).await
+    }
+
+    /// Returns whether the agent had a skill of that name
+    pub async fn unregister_skill(&self, agent_id: Uuid, name: &str) -> Result<bool> {
+        let registry = self.skill_registry().await.ok_or(MeshError::NoSkillRegistry)?;
+        registry.unregister(agent_id, name).await
+    }
+
+    pub async fn capabilities(&self, agent_id: Uuid) -> Option<AgentCapabilities> {
+        self.agents.read().await.iter().find(|(id, _)| *id == agent_id).map(|(_, c)| c.clone())
+    }
+
+    /// State, load and track record of every registered agent, in registration order
+    pub async fn stats(&self, lifecycle: &LifecycleManager) -> MeshStats {
+        let agents: Vec<AgentLoad> = self.agents.read().await
+            .iter()
+            .map(|(id, capabilities)| {
+                let stats = lifecycle.stats(*id);
+                AgentLoad {
+                    agent_id: *id,
+                    state: lifecycle.state(*id).unwrap_or(AgentState::Stopped),
+                    in_flight: stats.in_flight,
+                    max_concurrent_tasks: capabilities.max_concurrent_tasks,
+                    succeeded: stats.succeeded,
+                    failed: stats.failed,
+                    success_rate: stats.success_rate(None),
+                }
+            })
+            .collect();
+
+        let mut by_state = HashMap::new();
+        for agent in &agents {
+            *by_state.entry(agent.state).or_insert(0) += 1;
+        }
+        MeshStats { agents, by_state }
+    }
+
+    /// Replace the routing policy with one parsed from a TOML or JSON document. An
+    /// invalid document leaves the current policy in place.
+    pub async fn load_policies(&self, document: &str) -> Result<(), MeshError> {
+        self.set_policy(RoutingPolicy::parse(document)?).await;
+        Ok(())
+    }
+
+    pub async fn load_policy_file(&self, path: impl Into<PathBuf>) -> Result<(), MeshError> {
+        self.set_policy(RoutingPolicy::load(path.into())?).await;
+        Ok(())
+    }
+
+    pub async fn set_policy(&self, policy: RoutingPolicy) {
+        *self.policy.write().await = Arc::new(policy);
+    }
+
+    pub async fn policy(&self) -> Arc<RoutingPolicy> {
+        self.policy.read().await.clone()
+    }
+
+    /// Reload the policy file whenever its modification time changes, checking every
+    /// `interval` until the mesh is dropped. Failed reloads are logged and keep the
+    /// previous policy.
+    pub fn watch_policy_file(self: &Arc<Self>, path: impl Into<PathBuf>, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let mesh: Weak<Self> = Arc::downgrade(self);
+        let path = path.into();
+        let mut interval = tokio::time::interval(interval);
+        tokio::spawn(async move {
+            let mut loaded: Option<SystemTime> = None;
+            loop {
+                interval.tick().await;
+                let Some(mesh) = mesh.upgrade() else {
+                    return;
+                };
+                let modified = match std::fs::metadata(&path).and_then(|m| m.modified()) {
+                    Ok(modified) => modified,
+                    Err(e) => {
+                        tracing::warn!("Cannot read routing policy {}: {}", path.display(), e);
+                        continue;
+                    }
+                };
+                if loaded == Some(modified) {
+                    continue;
+                }
+                loaded = Some(modified);
+                match mesh.load_policy_file(path.clone()).await {
+                    Ok(()) => tracing::info!("Loaded routing policy from {}", path.display()),
+                    Err(e) => tracing::warn!("Keeping previous routing policy: {}", e),
+                }
+            }
+        })
+    }
+
+    /// Apply the routing policy to `candidates`, keeping their order
+    pub async fn route(&self, task: &Task, candidates: &[Uuid]) -> Result<(Vec<Uuid>, RoutingDecision), MeshError> {
+        let policy = self.policy().await;
+        let agents = self.agents.read().await;
+        let candidates: Vec<(Uuid, AgentCapabilities)> = candidates.iter()
+            .filter_map(|id| agents.iter().find(|(agent, _)| agent == id).cloned())
+            .collect();
+        drop(agents);
+        policy.route(task, &candidates)
+    }
+
+    /// Every skill advertised by a registered agent, sorted and deduplicated
+    pub async fn available_capabilities(&self) -> Vec<String> {
+        let mut skills: Vec<String> = self.agents.read().await
+            .iter()
+            .flat_map(|(_, capabilities)| capabilities.skills.iter().cloned())
+            .collect();
+        skills.sort();
+        skills.dedup();
+        skills
+    }
+}
+
+/// Weights of the factors in `score_agent`; they sum to 1
+const OPTIONAL_WEIGHT: f64 = 0.4;
+const LOAD_WEIGHT: f64 = 0.3;
+const HISTORY_WEIGHT: f64 = 0.2;
+const LOCALITY_WEIGHT: f64 = 0.1;
+/// Weight of a skill match's similarity, added on top of `score_agent`'s factors so an
+/// agent whose skill reads like the task outranks one that only has the capability names
+const SKILL_WEIGHT: f64 = 1.0;
+
+/// How well an agent fits a task, or `None` when it lacks a required capability.
+///
+/// Combines the share of optional capabilities it has, its free capacity, its success
+/// rate on tasks of the same type and whether it matches the locality hint, then divides
+/// by the agent's cost weight.
+pub fn score_agent(capabilities: &AgentCapabilities, task: &Task, stats: &AgentStats) -> Option<f64> {
+    if !capabilities.covers(&task.required_capabilities) {
+        return None;
+    }
+    Some(fit(capabilities, task, stats) / capabilities.cost_weight.max(f64::EPSILON))
+}
+
+/// `score_agent` before dividing by cost
+fn fit(capabilities: &AgentCapabilities, task: &Task, stats: &AgentStats) -> f64 {
+    let optional = if task.optional_capabilities.is_empty() {
+        1.0
+    } else {
+        let matched = task.optional_capabilities.iter().filter(|c| capabilities.skills.contains(c)).count();
+        matched as f64 / task.optional_capabilities.len() as f64
+    };
+    let load = if capabilities.max_concurrent_tasks == 0 {
+        0.0
+    } else {
+        1.0 - stats.in_flight as f64 / capabilities.max_concurrent_tasks as f64
+    };
+    let history = stats.success_rate(task.task_type.as_deref());
+    let locality = match (&task.locality, &capabilities.locality) {
+        (None, _) => 0.5,
+        (Some(wanted), Some(actual)) if wanted == actual => 1.0,
+        (Some(_), _) => 0.0,
+    };
+
+    OPTIONAL_WEIGHT * optional + LOAD_WEIGHT * load + HISTORY_WEIGHT * history + LOCALITY_WEIGHT * locality
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn agent_ids(selected: Vec<AgentSelection>) -> Vec<Uuid> {
+        selected.into_iter().map(|s| s.agent_id).collect()
+    }
+
+    async fn mesh_with(agents: Vec<AgentCapabilities>) -> (AgentMesh, LifecycleManager, Vec<Uuid>) {
+        let mesh = AgentMesh::new().await.unwrap();
+        let lifecycle = LifecycleManager::new().await.unwrap();
+        let mut ids = Vec::new();
+        for capabilities in agents {
+            let id = Uuid::new_v4();
+            mesh.register_agent(id, capabilities).await.unwrap();
+            lifecycle.start_agent(id).await.unwrap();
+            lifecycle.heartbeat(id);
+            ids.push(id);
+        }
+        (mesh, lifecycle, ids)
+    }
+
+    #[tokio::test]
+    async fn test_ranks_by_optional_match_locality_and_cost() {
+        let (mesh, lifecycle, ids) = mesh_with(vec![
+            AgentCapabilities::new(&["deploy"]),
+            AgentCapabilities::new(&["deploy", "k8s"]),
+            AgentCapabilities::new(&["deploy", "k8s"]).with_cost_weight(2.0),
+            AgentCapabilities::new(&["deploy", "k8s"]).with_locality("eu-west"),
+            AgentCapabilities::new(&["review"]),
+        ]).await;
+
+        let task = Task::new("roll out").with_capability("deploy").with_optional_capability("k8s").with_locality("eu-west");
+        let ranked = agent_ids(mesh.find_suitable_agents(&task, &lifecycle).await.unwrap());
+        assert_eq!(ranked, vec![ids[3], ids[1], ids[0], ids[2]]);
+    }
+
+    #[tokio::test]
+    async fn test_prefers_idle_and_proven_agents() {
+        let capabilities = AgentCapabilities::new(&["sql"]).with_max_concurrent_tasks(4);
+        let (mesh, lifecycle, ids) = mesh_with(vec![capabilities.clone(), capabilities.clone(), capabilities]).await;
+
+        assert!(lifecycle.try_begin_task(ids[0], 4));
+        lifecycle.finish_task(ids[1], Some("migration"), false);
+        lifecycle.finish_task(ids[2], Some("migration"), true);
+
+        let task = Task::new("migrate").with_capability("sql").with_task_type("migration");
+        let ranked = agent_ids(mesh.find_suitable_agents(&task, &lifecycle).await.unwrap());
+        assert_eq!(ranked, vec![ids[2], ids[1], ids[0]]);
+
+        let load = mesh.stats(&lifecycle).await.agents;
+        assert_eq!((load[0].in_flight, load[0].max_concurrent_tasks), (1, 4));
+        assert_eq!((load[1].failed, load[2].succeeded), (1, 1));
+        assert!(load[2].success_rate > load[1].success_rate);
+    }
+
+    #[tokio::test]
+    async fn test_skips_agents_at_capacity() {
+        let (mesh, lifecycle, ids) = mesh_with(vec![
+            AgentCapabilities::new(&["deploy"]),
+            AgentCapabilities::new(&["deploy"]).with_max_concurrent_tasks(2),
+        ]).await;
+        let task = Task::new("deploy").with_capability("deploy");
+
+        assert!(lifecycle.try_begin_task(ids[0], 1));
+        assert!(!lifecycle.try_begin_task(ids[0], 1));
+        assert_eq!(agent_ids(mesh.find_suitable_agents(&task, &lifecycle).await.unwrap()), vec![ids[1]]);
+
+        assert!(lifecycle.try_begin_task(ids[1], 2));
+        assert!(lifecycle.try_begin_task(ids[1], 2));
+        assert!(mesh.find_suitable_agents(&task, &lifecycle).await.unwrap().is_empty());
+        assert!(mesh.has_capable_agent(&task).await);
+    }
+
+    #[tokio::test]
+    async fn test_paraphrased_task_routes_to_agent_with_closest_skill() {
+        let (mesh, lifecycle, ids) = mesh_with(vec![AgentCapabilities::default(); 3]).await;
+        mesh.set_skill_registry(crate::skills::tests::registry()).await;
+        mesh.register_skill(ids[0], Skill::new("query-tuning", "Optimize slow SQL queries and database indexes")).await.unwrap();
+        mesh.register_skill(ids[1], Skill::new("rollout", "Deploy a release to the production cluster")).await.unwrap();
+        mesh.register_skill(ids[2], Skill::new("copywriting", "Draft blog posts and documentation")).await.unwrap();
+
+        // Every agent covers a task with no required capabilities; the skill decides
+        let task = Task::new("Ship the new version to the kubernetes servers");
+        let selected = mesh.find_suitable_agents(&task, &lifecycle).await.unwrap();
+        assert_eq!(selected[0].agent_id, ids[1]);
+        let skill_match = selected[0].skill_match.as_ref().unwrap();
+        assert_eq!(skill_match.skill, "rollout");
+        assert!(skill_match.score > 0.9);
+        assert!(selected[1..].iter().all(|s| s.skill_match.is_none()));
+
+        // A skill match stands in for capability names the agent lacks
+        let required = task.clone().with_capability("deploy");
+        mesh.register_agent(ids[2], AgentCapabilities::new(&["deploy"])).await.unwrap();
+        let selected = mesh.find_suitable_agents(&required, &lifecycle).await.unwrap();
+        assert_eq!(agent_ids(selected), vec![ids[1], ids[2]]);
+
+        mesh.unregister_agent(ids[1]).await.unwrap();
+        assert!(!mesh.unregister_skill(ids[1], "rollout").await.unwrap());
+        let selected = mesh.find_suitable_agents(&required, &lifecycle).await.unwrap();
+        assert_eq!(agent_ids(selected), vec![ids[2]]);
+    }
+
+    #[tokio::test]
+    async fn test_skills_need_a_registry() {
+        let (mesh, _lifecycle, ids) = mesh_with(vec![AgentCapabilities::default()]).await;
+        let error = mesh.register_skill(ids[0], Skill::new("rollout", "Deploy a release")).await.unwrap_err();
+        assert!(matches!(error.downcast_ref(), Some(MeshError::NoSkillRegistry)));
+    }
+}
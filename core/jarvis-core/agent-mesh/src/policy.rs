@@ -0,0 +1,343 @@
+use std::collections::HashMap;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::agent::AgentCapabilities;
+use crate::error::MeshError;
+use crate::mesh::Task;
+
+/// Where tasks may go, loaded from a TOML or JSON document:
+///
+/// ```toml
+/// [pools.db]
+/// capabilities = ["sql"]
+///
+/// [pools.autonomous]
+/// agents = ["6f1c0e2a-..."]
+///
+/// # Agents of a pool named on a deny rule are never given matching tasks;
+/// # a deny rule without a pool rejects matching tasks outright
+/// [[rules]]
+/// name = "no-critical-autonomy"
+/// action = "deny"
+/// pool = "autonomous"
+/// match = { risk_levels = ["critical"] }
+///
+/// [[rules]]
+/// name = "database-tasks"
+/// action = "route"
+/// pool = "db"
+/// fallback = "general"
+/// match = { task_type = "database" }
+/// ```
+///
+/// Rules are evaluated in order. Deny rules apply as they match; the first matching
+/// route rule decides the pool and ends evaluation. Without one, any agent may run the task.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RoutingPolicy {
+    #[serde(default)]
+    pub pools: HashMap<String, AgentPool>,
+    #[serde(default)]
+    pub rules: Vec<RoutingRule>,
+}
+
+/// A named set of agents: those listed by id plus those with every selector capability
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AgentPool {
+    #[serde(default)]
+    pub agents: Vec<Uuid>,
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+}
+
+impl AgentPool {
+    pub fn contains(&self, agent_id: Uuid, capabilities: &AgentCapabilities) -> bool {
+        self.agents.contains(&agent_id)
+            || (!self.capabilities.is_empty() && capabilities.covers(&self.capabilities))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleAction {
+    Route,
+    Deny,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoutingRule {
+    pub name: String,
+    pub action: RuleAction,
+    #[serde(rename = "match", default)]
+    pub matcher: TaskMatcher,
+    /// Pool tasks are routed to, or for deny rules the pool they are kept from
+    pub pool: Option<String>,
+    /// Pool a route rule uses when none of its pool's agents can take the task
+    pub fallback: Option<String>,
+}
+
+/// Task attributes a rule applies to; a matcher with no criteria matches every task
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TaskMatcher {
+    pub task_type: Option<String>,
+    /// Tags the task must all carry
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Risk levels of which the task must have one, compared case-insensitively
+    #[serde(default)]
+    pub risk_levels: Vec<String>,
+    /// Capabilities the task must all require
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+}
+
+impl TaskMatcher {
+    pub fn matches(&self, task: &Task) -> bool {
+        let task_type = self.task_type.as_ref().is_none_or(|wanted| task.task_type.as_ref() == Some(wanted));
+        let risk = self.risk_levels.is_empty()
+            || task.risk_level.as_ref().is_some_and(|risk| self.risk_levels.iter().any(|r| r.eq_ignore_ascii_case(risk)));
+        task_type
+            && risk
+            && self.tags.iter().all(|tag| task.tags.contains(tag))
+            && self.capabilities.iter().all(|c| task.required_capabilities.contains(c))
+    }
+}
+
+/// How the policy placed a task, reported with its result for auditing
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RoutingDecision {
+    /// Route rule that matched, if any
+    pub rule: Option<String>,
+    /// Pool the task was restricted to
+    pub pool: Option<String>,
+    /// Whether the rule's fallback pool was used
+    pub used_fallback: bool,
+    /// Deny rules that took agents out of consideration
+    pub excluded_by: Vec<String>,
+}
+
+impl RoutingPolicy {
+    pub fn from_toml_str(document: &str) -> Result<Self, MeshError> {
+        let policy: Self = toml::from_str(document).map_err(|e| MeshError::InvalidPolicy { reason: e.to_string() })?;
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    pub fn from_json_str(document: &str) -> Result<Self, MeshError> {
+        let policy: Self = serde_json::from_str(document).map_err(|e| MeshError::InvalidPolicy { reason: e.to_string() })?;
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    /// Parse a JSON document if it is a JSON object, TOML otherwise
+    pub fn parse(document: &str) -> Result<Self, MeshError> {
+        if document.trim_start().starts_with('{') {
+            Self::from_json_str(document)
+        } else {
+            Self::from_toml_str(document)
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, MeshError> {
+        let path = path.as_ref();
+        let document = std::fs::read_to_string(path)
+            .map_err(|e| MeshError::InvalidPolicy { reason: format!("reading {}: {}", path.display(), e) })?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => Self::from_json_str(&document),
+            Some("toml") => Self::from_toml_str(&document),
+            _ => Self::parse(&document),
+        }
+    }
+
+    /// Check that route rules name a pool and that every named pool is defined
+    fn validate(&self) -> Result<(), MeshError> {
+        for rule in &self.rules {
+            let invalid = |reason: &str| MeshError::InvalidPolicy { reason: format!("rule '{}' {}", rule.name, reason) };
+            if rule.action == RuleAction::Route && rule.pool.is_none() {
+                return Err(invalid("routes to no pool"));
+            }
+            if rule.action == RuleAction::Deny && rule.fallback.is_some() {
+                return Err(invalid("is a deny rule with a fallback"));
+            }
+            for pool in rule.pool.iter().chain(&rule.fallback) {
+                if !self.pools.contains_key(pool) {
+                    return Err(invalid(&format!("names undefined pool '{}'", pool)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Narrow `candidates`, best first, to the agents the policy lets run `task`,
+    /// keeping their order
+    pub fn route(&self, task: &Task, candidates: &[(Uuid, AgentCapabilities)]) -> Result<(Vec<Uuid>, RoutingDecision), MeshError> {
+        let mut decision = RoutingDecision::default();
+        let mut allowed: Vec<&(Uuid, AgentCapabilities)> = candidates.iter().collect();
+        let members = |allowed: &[&(Uuid, AgentCapabilities)], pool: &str| -> Vec<Uuid> {
+            let pool = &self.pools[pool];
+            allowed.iter().filter(|(id, c)| pool.contains(*id, c)).map(|(id, _)| *id).collect()
+        };
+
+        for rule in self.rules.iter().filter(|rule| rule.matcher.matches(task)) {
+            let pool = rule.pool.as_deref();
+            match (rule.action, pool) {
+                (RuleAction::Deny, None) => {
+                    return Err(MeshError::PolicyViolation { task_id: task.id, rule: rule.name.clone() });
+                }
+                (RuleAction::Deny, Some(pool)) => {
+                    let pool = &self.pools[pool];
+                    let before = allowed.len();
+                    allowed.retain(|(id, c)| !pool.contains(*id, c));
+                    if allowed.len() < before {
+                        decision.excluded_by.push(rule.name.clone());
+                    }
+                }
+                (RuleAction::Route, None) => {}
+                (RuleAction::Route, Some(pool)) => {
+                    decision.rule = Some(rule.name.clone());
+                    decision.pool = Some(pool.to_string());
+                    let mut routed = members(&allowed, pool);
+                    if routed.is_empty() {
+                        if let Some(fallback) = &rule.fallback {
+                            decision.pool = Some(fallback.clone());
+                            decision.used_fallback = true;
+                            routed = members(&allowed, fallback);
+                        }
+                    }
+                    if routed.is_empty() && !allowed.is_empty() {
+                        return Err(MeshError::NoAgentInPool {
+                            task_id: task.id,
+                            rule: rule.name.clone(),
+                            pool: decision.pool.unwrap_or_default(),
+                        });
+                    }
+                    return Ok((routed, decision));
+                }
+            }
+        }
+
+        if allowed.is_empty() && !decision.excluded_by.is_empty() {
+            return Err(MeshError::PolicyViolation {
+                task_id: task.id,
+                rule: decision.excluded_by.join(", "),
+            });
+        }
+        Ok((allowed.into_iter().map(|(id, _)| *id).collect(), decision))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn agents() -> Vec<(Uuid, AgentCapabilities)> {
+        vec![
+            (Uuid::new_v4(), AgentCapabilities::new(&["deploy"])),
+            (Uuid::new_v4(), AgentCapabilities::new(&["deploy", "sql"])),
+            (Uuid::new_v4(), AgentCapabilities::new(&["deploy", "autonomous"])),
+        ]
+    }
+
+    fn policy(agents: &[(Uuid, AgentCapabilities)]) -> RoutingPolicy {
+        RoutingPolicy::parse(&format!(r#"
+            [pools.db]
+            capabilities = ["sql"]
+
+            [pools.autonomous]
+            capabilities = ["autonomous"]
+
+            [pools.primary]
+            agents = ["{primary}"]
+
+            [pools.reserve]
+            agents = ["{reserve}"]
+
+            [[rules]]
+            name = "no-critical-autonomy"
+            action = "deny"
+            pool = "autonomous"
+            match = {{ risk_levels = ["critical"] }}
+
+            [[rules]]
+            name = "freeze"
+            action = "deny"
+            match = {{ tags = ["frozen"] }}
+
+            [[rules]]
+            name = "database-tasks"
+            action = "route"
+            pool = "db"
+            match = {{ task_type = "database" }}
+
+            [[rules]]
+            name = "releases"
+            action = "route"
+            pool = "primary"
+            fallback = "reserve"
+            match = {{ tags = ["release"] }}
+        "#, primary = agents[0].0, reserve = agents[2].0)).unwrap()
+    }
+
+    #[test]
+    fn test_routes_matching_tasks_to_their_pool() {
+        let agents = agents();
+        let policy = policy(&agents);
+
+        let task = Task::new("migrate").with_capability("deploy").with_task_type("database");
+        let (routed, decision) = policy.route(&task, &agents).unwrap();
+        assert_eq!(routed, vec![agents[1].0]);
+        assert_eq!((decision.rule.as_deref(), decision.pool.as_deref()), (Some("database-tasks"), Some("db")));
+
+        // Tasks no route rule matches may go to any agent
+        let (routed, decision) = policy.route(&Task::new("build"), &agents).unwrap();
+        assert_eq!(routed.len(), 3);
+        assert_eq!(decision, RoutingDecision::default());
+    }
+
+    #[test]
+    fn test_falls_back_when_the_pool_has_no_candidate() {
+        let agents = agents();
+        let policy = policy(&agents);
+        let task = Task::new("ship").with_tag("release");
+
+        let (routed, decision) = policy.route(&task, &agents).unwrap();
+        assert_eq!((routed, decision.used_fallback), (vec![agents[0].0], false));
+
+        // The primary agent is busy, so it is not a candidate
+        let (routed, decision) = policy.route(&task, &agents[1..]).unwrap();
+        assert_eq!(routed, vec![agents[2].0]);
+        assert_eq!((decision.pool.as_deref(), decision.used_fallback), (Some("reserve"), true));
+
+        let err = policy.route(&task, &agents[1..2]).unwrap_err();
+        assert!(matches!(err, MeshError::NoAgentInPool { ref pool, .. } if pool == "reserve"));
+    }
+
+    #[test]
+    fn test_deny_rules_exclude_pools_or_reject_tasks() {
+        let agents = agents();
+        let policy = policy(&agents);
+
+        let critical = Task::new("rotate keys").with_risk_level("Critical");
+        let (routed, decision) = policy.route(&critical, &agents).unwrap();
+        assert_eq!(routed, vec![agents[0].0, agents[1].0]);
+        assert_eq!(decision.excluded_by, vec!["no-critical-autonomy".to_string()]);
+
+        let err = policy.route(&critical, &agents[2..]).unwrap_err();
+        assert!(matches!(err, MeshError::PolicyViolation { ref rule, .. } if rule == "no-critical-autonomy"));
+
+        // A critical release can't fall back to the autonomous reserve agent
+        let err = policy.route(&critical.clone().with_tag("release"), &agents[1..]).unwrap_err();
+        assert!(matches!(err, MeshError::NoAgentInPool { .. }));
+
+        let err = policy.route(&Task::new("hotfix").with_tag("frozen"), &agents).unwrap_err();
+        assert!(matches!(err, MeshError::PolicyViolation { ref rule, .. } if rule == "freeze"));
+    }
+
+    #[test]
+    fn test_rejects_rules_naming_undefined_pools() {
+        let json = r#"{"rules": [{"name": "r", "action": "route", "pool": "gpu"}]}"#;
+        assert!(matches!(RoutingPolicy::parse(json), Err(MeshError::InvalidPolicy { .. })));
+        assert!(RoutingPolicy::parse(r#"{"pools": {"gpu": {"capabilities": ["cuda"]}}, "rules": [{"name": "r", "action": "route", "pool": "gpu"}]}"#).is_ok());
+    }
+}
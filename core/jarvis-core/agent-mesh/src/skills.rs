@@ -0,0 +1,428 @@
+//! Skills agents register at runtime, matched to tasks by meaning
+//!
+//! A skill describes something an agent can do in prose. Its description is embedded
+//! when it is registered, and a task's description is embedded when agents are found
+//! for it, so a task phrased differently from every skill name can still be matched to
+//! the agent whose skill reads most like it. Skills live in Postgres when the mesh has
+//! a database, so they outlive restarts, and in memory otherwise.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Row};
+use talkpp_vector_db::{validate_vector, SharedEmbeddingModel};
+use uuid::Uuid;
+
+/// Similarity a skill must reach to match a task when the registry isn't told otherwise
+pub const DEFAULT_SIMILARITY_THRESHOLD: f32 = 0.6;
+
+/// Something an agent can do, described for matching against task descriptions
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Skill {
+    /// Unique per agent; registering a skill of the same name replaces it
+    pub name: String,
+    pub description: String,
+    /// What the skill expects as input, such as "a SQL dialect and a schema"
+    #[serde(default)]
+    pub input_hint: Option<String>,
+    /// What the skill produces
+    #[serde(default)]
+    pub output_hint: Option<String>,
+}
+
+impl Skill {
+    pub fn new(name: impl Into<String>, description: impl Into<String>) -> Self {
+        Self { name: name.into(), description: description.into(), input_hint: None, output_hint: None }
+    }
+
+    pub fn with_input_hint(mut self, hint: impl Into<String>) -> Self {
+        self.input_hint = Some(hint.into());
+        self
+    }
+
+    pub fn with_output_hint(mut self, hint: impl Into<String>) -> Self {
+        self.output_hint = Some(hint.into());
+        self
+    }
+}
+
+/// A skill as stored, with the embedding of its description
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegisteredSkill {
+    pub agent_id: Uuid,
+    pub skill: Skill,
+    /// Id of the model that embedded the description
+    pub model_id: String,
+    pub embedding: Vec<f32>,
+    pub registered_at: DateTime<Utc>,
+}
+
+/// Why an agent was matched to a task: its skill that reads most like the task
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SkillMatch {
+    pub skill: String,
+    /// Cosine similarity of the skill and task descriptions
+    pub score: f32,
+}
+
+#[async_trait]
+pub trait SkillStore: Send + Sync {
+    /// Store a skill, replacing the agent's skill of the same name
+    async fn put(&self, skill: &RegisteredSkill) -> Result<()>;
+
+    /// Returns whether the agent had a skill of that name
+    async fn remove(&self, agent_id: Uuid, name: &str) -> Result<bool>;
+
+    async fn remove_agent(&self, agent_id: Uuid) -> Result<()>;
+
+    async fn all(&self) -> Result<Vec<RegisteredSkill>>;
+}
+
+/// Skills in the `agent_skills` table, one row per agent and skill name
+pub struct PostgresSkillStore {
+    pool: PgPool,
+}
+
+impl PostgresSkillStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Create the `agent_skills` table if it does not exist yet
+    pub async fn ensure_schema(&self) -> Result<()> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS agent_skills ( \
+             agent_id UUID NOT NULL, \
+             name TEXT NOT NULL, \
+             description TEXT NOT NULL, \
+             input_hint TEXT, \
+             output_hint TEXT, \
+             model_id TEXT NOT NULL, \
+             embedding REAL[] NOT NULL, \
+             registered_at TIMESTAMP WITH TIME ZONE NOT NULL, \
+             PRIMARY KEY (agent_id, name))",
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SkillStore for PostgresSkillStore {
+    async fn put(&self, skill: &RegisteredSkill) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO agent_skills \
+             (agent_id, name, description, input_hint, output_hint, model_id, embedding, registered_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8) \
+             ON CONFLICT (agent_id, name) DO UPDATE SET \
+             description = EXCLUDED.description, \
+             input_hint = EXCLUDED.input_hint, \
+             output_hint = EXCLUDED.output_hint, \
+             model_id = EXCLUDED.model_id, \
+             embedding = EXCLUDED.embedding, \
+             registered_at = EXCLUDED.registered_at",
+        )
+        .bind(skill.agent_id)
+        .bind(&skill.skill.name)
+        .bind(&skill.skill.description)
+        .bind(&skill.skill.input_hint)
+        .bind(&skill.skill.output_hint)
+        .bind(&skill.model_id)
+        .bind(&skill.embedding)
+        .bind(skill.registered_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn remove(&self, agent_id: Uuid, name: &str) -> Result<bool> {
+        let deleted = sqlx::query("DELETE FROM agent_skills WHERE agent_id = $1 AND name = $2")
+            .bind(agent_id)
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        Ok(deleted.rows_affected() > 0)
+    }
+
+    async fn remove_agent(&self, agent_id: Uuid) -> Result<()> {
+        sqlx::query("DELETE FROM agent_skills WHERE agent_id = $1")
+            .bind(agent_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn all(&self) -> Result<Vec<RegisteredSkill>> {
+        let rows = sqlx::query(
+            "SELECT agent_id, name, description, input_hint, output_hint, model_id, embedding, registered_at \
+             FROM agent_skills ORDER BY registered_at, agent_id, name",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(RegisteredSkill {
+                    agent_id: row.try_get("agent_id")?,
+                    skill: Skill {
+                        name: row.try_get("name")?,
+                        description: row.try_get("description")?,
+                        input_hint: row.try_get("input_hint")?,
+                        output_hint: row.try_get("output_hint")?,
+                    },
+                    model_id: row.try_get("model_id")?,
+                    embedding: row.try_get("embedding")?,
+                    registered_at: row.try_get("registered_at")?,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Process-local skills, for tests and meshes without a database. Skills are lost on restart.
+#[derive(Default)]
+pub struct MemorySkillStore {
+    /// Kept in registration order so matching is deterministic
+    skills: Mutex<Vec<RegisteredSkill>>,
+}
+
+impl MemorySkillStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl SkillStore for MemorySkillStore {
+    async fn put(&self, skill: &RegisteredSkill) -> Result<()> {
+        let mut skills = self.skills.lock().unwrap();
+        match skills.iter_mut().find(|s| s.agent_id == skill.agent_id && s.skill.name == skill.skill.name) {
+            Some(existing) => *existing = skill.clone(),
+            None => skills.push(skill.clone()),
+        }
+        Ok(())
+    }
+
+    async fn remove(&self, agent_id: Uuid, name: &str) -> Result<bool> {
+        let mut skills = self.skills.lock().unwrap();
+        let before = skills.len();
+        skills.retain(|s| !(s.agent_id == agent_id && s.skill.name == name));
+        Ok(skills.len() < before)
+    }
+
+    async fn remove_agent(&self, agent_id: Uuid) -> Result<()> {
+        self.skills.lock().unwrap().retain(|s| s.agent_id != agent_id);
+        Ok(())
+    }
+
+    async fn all(&self) -> Result<Vec<RegisteredSkill>> {
+        Ok(self.skills.lock().unwrap().clone())
+    }
+}
+
+/// Registers agents' skills with embeddings of their descriptions, and finds the skills
+/// that read like a task
+pub struct SkillRegistry {
+    store: Arc<dyn SkillStore>,
+    embedder: SharedEmbeddingModel,
+    threshold: f32,
+}
+
+impl std::fmt::Debug for SkillRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SkillRegistry")
+            .field("model", &self.embedder.model_id())
+            .field("threshold", &self.threshold)
+            .finish()
+    }
+}
+
+impl SkillRegistry {
+    pub fn new(store: Arc<dyn SkillStore>, embedder: SharedEmbeddingModel) -> Self {
+        Self { store, embedder, threshold: DEFAULT_SIMILARITY_THRESHOLD }
+    }
+
+    /// Registry kept in Postgres at `database_url`, or in memory when there is no database
+    /// or it cannot be reached
+    pub async fn open(database_url: Option<&str>, embedder: SharedEmbeddingModel) -> Self {
+        let Some(database_url) = database_url else {
+            return Self::new(Arc::new(MemorySkillStore::new()), embedder);
+        };
+        let store = async {
+            let store = PostgresSkillStore::new(PgPool::connect(database_url).await?);
+            store.ensure_schema().await?;
+            anyhow::Ok(store)
+        };
+        match store.await {
+            Ok(store) => Self::new(Arc::new(store), embedder),
+            Err(e) => {
+                tracing::warn!("Keeping agent skills in memory, Postgres is unavailable: {}", e);
+                Self::new(Arc::new(MemorySkillStore::new()), embedder)
+            }
+        }
+    }
+
+    /// Similarity a skill must reach to match a task, instead of
+    /// [`DEFAULT_SIMILARITY_THRESHOLD`]
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Embed the skill's description and store it for the agent
+    pub async fn register(&self, agent_id: Uuid, skill: Skill) -> Result<RegisteredSkill> {
+        let embedding = self.embedder.embed(&skill.description).await?;
+        validate_vector(self.embedder.as_ref(), &embedding)?;
+        let registered = RegisteredSkill {
+            agent_id,
+            skill,
+            model_id: self.embedder.model_id().to_string(),
+            embedding,
+            registered_at: Utc::now(),
+        };
+        self.store.put(&registered).await?;
+        Ok(registered)
+    }
+
+    pub async fn unregister(&self, agent_id: Uuid, name: &str) -> Result<bool> {
+        self.store.remove(agent_id, name).await
+    }
+
+    pub async fn unregister_agent(&self, agent_id: Uuid) -> Result<()> {
+        self.store.remove_agent(agent_id).await
+    }
+
+    /// Skills of the agent, in registration order
+    pub async fn skills(&self, agent_id: Uuid) -> Result<Vec<Skill>> {
+        Ok(self.store.all().await?
+            .into_iter()
+            .filter(|registered| registered.agent_id == agent_id)
+            .map(|registered| registered.skill)
+            .collect())
+    }
+
+    /// Per agent, its skill most similar to `description` among those reaching the
+    /// threshold. Skills embedded by another model than the registry's are skipped.
+    pub async fn matches(&self, description: &str) -> Result<HashMap<Uuid, SkillMatch>> {
+        let skills = self.store.all().await?;
+        if skills.is_empty() || description.trim().is_empty() {
+            return Ok(HashMap::new());
+        }
+        let task = self.embedder.embed(description).await?;
+
+        let mut best: HashMap<Uuid, SkillMatch> = HashMap::new();
+        for registered in skills.iter().filter(|s| s.model_id == self.embedder.model_id()) {
+            let score = cosine_similarity(&task, &registered.embedding);
+            if score < self.threshold {
+                continue;
+            }
+            let current = best.get(&registered.agent_id).map(|m| m.score);
+            if current.map_or(true, |current| score > current) {
+                best.insert(registered.agent_id, SkillMatch { skill: registered.skill.name.clone(), score });
+            }
+        }
+        Ok(best)
+    }
+}
+
+/// Cosine similarity of two vectors, 0 for vectors of different lengths or no length
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norms = a.iter().map(|x| x * x).sum::<f32>().sqrt() * b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot / norms
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use talkpp_vector_db::EmbeddingModel;
+
+    /// Words that mean the same thing land on the same axis, so paraphrases embed alike
+    pub(crate) struct ConceptEmbedder;
+
+    const CONCEPTS: [&[&str]; 4] = [
+        &["sql", "database", "query", "queries", "table", "tables", "rows", "index", "indexes", "schema"],
+        &["deploy", "deployment", "release", "ship", "rollout", "cluster", "kubernetes", "production", "servers"],
+        &["write", "writing", "draft", "essay", "prose", "article", "blog", "copy", "post", "documentation"],
+        &["slow", "speed", "fast", "faster", "performance", "tune", "optimize", "latency"],
+    ];
+
+    #[async_trait]
+    impl EmbeddingModel for ConceptEmbedder {
+        async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+            let text = text.to_lowercase();
+            let words: Vec<&str> = text.split(|c: char| !c.is_alphanumeric()).collect();
+            Ok(CONCEPTS.iter()
+                .map(|concept| words.iter().filter(|word| concept.contains(word)).count() as f32)
+                .collect())
+        }
+
+        async fn embed_batch(&self, texts: Vec<&str>) -> Result<Vec<Vec<f32>>> {
+            let mut vectors = Vec::with_capacity(texts.len());
+            for text in texts {
+                vectors.push(self.embed(text).await?);
+            }
+            Ok(vectors)
+        }
+
+        fn dimension(&self) -> usize {
+            CONCEPTS.len()
+        }
+
+        fn model_id(&self) -> &str {
+            "test/concepts"
+        }
+    }
+
+    pub(crate) fn registry() -> SkillRegistry {
+        SkillRegistry::new(Arc::new(MemorySkillStore::new()), Arc::new(ConceptEmbedder))
+    }
+
+    #[tokio::test]
+    async fn test_reregistering_replaces_and_unregistering_removes() {
+        let registry = registry();
+        let agent = Uuid::new_v4();
+        registry.register(agent, Skill::new("tuning", "Tune slow SQL queries")).await.unwrap();
+        let replaced = Skill::new("tuning", "Optimize database indexes").with_input_hint("a schema");
+        registry.register(agent, replaced.clone()).await.unwrap();
+        assert_eq!(registry.skills(agent).await.unwrap(), vec![replaced]);
+
+        assert!(registry.unregister(agent, "tuning").await.unwrap());
+        assert!(!registry.unregister(agent, "tuning").await.unwrap());
+        assert!(registry.matches("optimize the database").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_matches_keep_each_agents_best_skill_above_threshold() {
+        let registry = registry().with_threshold(0.5);
+        let (db, writer) = (Uuid::new_v4(), Uuid::new_v4());
+        registry.register(db, Skill::new("schema-design", "Design database tables and indexes")).await.unwrap();
+        registry.register(db, Skill::new("query-tuning", "Make slow SQL queries fast")).await.unwrap();
+        registry.register(writer, Skill::new("blogging", "Draft a blog post")).await.unwrap();
+
+        let matches = registry.matches("the queries are slow, please tune them").await.unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[&db].skill, "query-tuning");
+        assert!(matches[&db].score > 0.9);
+    }
+
+    #[test]
+    fn test_cosine_similarity() {
+        assert!((cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]), 0.0);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 1.0]), 0.0);
+        assert_eq!(cosine_similarity(&[1.0], &[1.0, 1.0]), 0.0);
+    }
+}
@@ -0,0 +1,372 @@
+//! Declarative approval policies and the approvals they wait on
+//!
+//! An [`ApprovalPolicy`] is an ordered list of rules, loaded from a config document:
+//!
+//! ```json
+//! {
+//!   "rules": [
+//!     { "name": "no-prod-wipes", "when": { "tags": ["destructive"], "domain": ["infra_deployment"] }, "effect": "deny" },
+//!     { "name": "risky-deploys", "when": { "risk": ["High", "Critical"] },
+//!       "effect": "require_approval", "approver_groups": ["sre"], "min_approvers": 2 },
+//!     { "name": "reads", "when": { "task_type": ["Sense"] }, "effect": "auto_approve" }
+//!   ],
+//!   "default": { "effect": "auto_approve" }
+//! }
+//! ```
+//!
+//! A rule matches a task when every attribute it names matches: the task's agent type,
+//! task type or one of its tags is among those listed, and the plan's domain, risk
+//! level and `environment` (such as `"production"`) likewise. The first matching rule decides; with none, the policy's default does.
+//!
+//! Tasks that need approval wait in an [`ApprovalLedger`] until enough distinct members of
+//! the approver groups have approved them, or until one of them rejects it.
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::{Environment, ExecutionTask, IntentExecutionPlan, RiskLevel, TaskStatus, TaskType};
+
+/// What happens to a task a rule matches
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "effect", rename_all = "snake_case")]
+pub enum ApprovalEffect {
+    #[default]
+    AutoApprove,
+    /// Wait for `min_approvers` distinct approvers, each in one of `approver_groups`; no
+    /// groups means anyone may approve
+    RequireApproval {
+        #[serde(default)]
+        approver_groups: Vec<String>,
+        #[serde(default = "default_min_approvers")]
+        min_approvers: usize,
+    },
+    /// Abort the plan before the task runs
+    Deny,
+}
+
+fn default_min_approvers() -> usize {
+    1
+}
+
+/// Task attributes a rule matches on; an empty list matches anything
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApprovalMatch {
+    pub agent_type: Vec<String>,
+    pub task_type: Vec<TaskType>,
+    pub risk: Vec<RiskLevel>,
+    pub domain: Vec<String>,
+    pub environment: Vec<Environment>,
+    pub tags: Vec<String>,
+}
+
+impl ApprovalMatch {
+    pub fn matches(&self, plan: &IntentExecutionPlan, task: &ExecutionTask) -> bool {
+        fn any<T: PartialEq>(allowed: &[T], value: Option<&T>) -> bool {
+            allowed.is_empty() || value.is_some_and(|value| allowed.contains(value))
+        }
+
+        any(&self.agent_type, Some(&task.agent_type))
+            && any(&self.task_type, Some(&task.task_type))
+            && any(&self.risk, plan.risk_level.as_ref())
+            && any(&self.domain, plan.domain.as_ref())
+            && any(&self.environment, plan.environment.as_ref())
+            && (self.tags.is_empty() || task.tags.iter().any(|tag| self.tags.contains(tag)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApprovalRule {
+    pub name: String,
+    #[serde(default)]
+    pub when: ApprovalMatch,
+    #[serde(flatten)]
+    pub effect: ApprovalEffect,
+}
+
+/// Ordered approval rules; see the [module docs](self)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ApprovalPolicy {
+    #[serde(default)]
+    pub rules: Vec<ApprovalRule>,
+    /// Effect on tasks no rule matches
+    #[serde(default)]
+    pub default: ApprovalEffect,
+}
+
+/// The effect a policy has on one task, and the rule it came from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApprovalDecision {
+    /// `None` when no rule matched and the policy's default applied
+    pub rule: Option<String>,
+    pub effect: ApprovalEffect,
+}
+
+impl ApprovalPolicy {
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read approval policy from {}", path.display()))?;
+        Self::from_json(&content).with_context(|| format!("Invalid approval policy in {}", path.display()))
+    }
+
+    /// The effect of the first rule matching `task`, or the default
+    pub fn evaluate(&self, plan: &IntentExecutionPlan, task: &ExecutionTask) -> ApprovalDecision {
+        match self.rules.iter().find(|rule| rule.when.matches(plan, task)) {
+            Some(rule) => ApprovalDecision { rule: Some(rule.name.clone()), effect: rule.effect.clone() },
+            None => ApprovalDecision { rule: None, effect: self.default.clone() },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ApprovalStatus {
+    Pending { approvals: usize, required: usize },
+    /// Enough approvers agreed; the task runs when its plan is resumed
+    Released,
+    Rejected { by: String, reason: Option<String> },
+}
+
+/// A task waiting on approvers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingApproval {
+    pub plan_id: Uuid,
+    pub task: ExecutionTask,
+    /// Rule that required the approval, `None` for the policy's default
+    pub rule: Option<String>,
+    pub approver_groups: Vec<String>,
+    pub min_approvers: usize,
+    /// Distinct approvers so far
+    pub approvers: BTreeSet<String>,
+    pub rejection: Option<Rejection>,
+    pub requested_at: DateTime<Utc>,
+}
+
+impl PendingApproval {
+    pub fn status(&self) -> ApprovalStatus {
+        match &self.rejection {
+            Some(rejection) => ApprovalStatus::Rejected { by: rejection.by.clone(), reason: rejection.reason.clone() },
+            None if self.approvers.len() >= self.min_approvers => ApprovalStatus::Released,
+            None => ApprovalStatus::Pending { approvals: self.approvers.len(), required: self.min_approvers },
+        }
+    }
+
+    fn may_decide(&self, groups: &[String]) -> bool {
+        self.approver_groups.is_empty() || groups.iter().any(|group| self.approver_groups.contains(group))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rejection {
+    pub by: String,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ApprovalError {
+    #[error("Task {0} is not awaiting approval")]
+    NotPending(Uuid),
+
+    #[error("Approving task {task_id} requires membership of one of: {}", groups.join(", "))]
+    NotAnApprover { task_id: Uuid, groups: Vec<String> },
+
+    #[error("Task {0} was already rejected")]
+    AlreadyRejected(Uuid),
+}
+
+/// Tasks awaiting approval, shared between the executors that pause on them and the
+/// endpoints approvers use. Cloning shares the ledger.
+#[derive(Debug, Clone, Default)]
+pub struct ApprovalLedger {
+    pending: Arc<Mutex<HashMap<Uuid, PendingApproval>>>,
+}
+
+impl ApprovalLedger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, task_id: Uuid) -> Option<PendingApproval> {
+        self.pending.lock().unwrap().get(&task_id).cloned()
+    }
+
+    /// Every task still waiting, oldest request first
+    pub fn list(&self) -> Vec<PendingApproval> {
+        let mut pending: Vec<_> = self.pending.lock().unwrap().values().cloned().collect();
+        pending.sort_by_key(|approval| approval.requested_at);
+        pending
+    }
+
+    /// Record `approver`, a member of `groups`, as approving the task. Approving twice
+    /// counts once.
+    pub fn approve(&self, task_id: Uuid, approver: &str, groups: &[String]) -> Result<ApprovalStatus, ApprovalError> {
+        self.decide(task_id, groups, |pending| {
+            pending.approvers.insert(approver.to_string());
+        })
+    }
+
+    /// Record `approver`, a member of `groups`, as rejecting the task, which fails its plan
+    pub fn reject(&self, task_id: Uuid, approver: &str, groups: &[String], reason: Option<String>) -> Result<ApprovalStatus, ApprovalError> {
+        self.decide(task_id, groups, |pending| {
+            pending.rejection = Some(Rejection { by: approver.to_string(), reason });
+        })
+    }
+
+    fn decide(&self, task_id: Uuid, groups: &[String], decide: impl FnOnce(&mut PendingApproval)) -> Result<ApprovalStatus, ApprovalError> {
+        let mut ledger = self.pending.lock().unwrap();
+        let pending = ledger.get_mut(&task_id).ok_or(ApprovalError::NotPending(task_id))?;
+        if !pending.may_decide(groups) {
+            return Err(ApprovalError::NotAnApprover { task_id, groups: pending.approver_groups.clone() });
+        }
+        if pending.rejection.is_some() {
+            return Err(ApprovalError::AlreadyRejected(task_id));
+        }
+        decide(pending);
+        Ok(pending.status())
+    }
+
+    /// Wait for approval of `task`, keeping the approvals of an earlier request for it
+    pub(crate) fn request(&self, plan_id: Uuid, task: &ExecutionTask, rule: Option<String>, approver_groups: Vec<String>, min_approvers: usize) {
+        self.pending.lock().unwrap().entry(task.id).or_insert_with(|| PendingApproval {
+            plan_id,
+            task: ExecutionTask { status: TaskStatus::WaitingApproval, ..task.clone() },
+            rule,
+            approver_groups,
+            min_approvers,
+            approvers: BTreeSet::new(),
+            rejection: None,
+            requested_at: Utc::now(),
+        });
+    }
+
+    pub(crate) fn status(&self, task_id: Uuid) -> Option<ApprovalStatus> {
+        self.pending.lock().unwrap().get(&task_id).map(PendingApproval::status)
+    }
+
+    /// Stop tracking a task once its approval has been acted on
+    pub(crate) fn remove(&self, task_id: Uuid) {
+        self.pending.lock().unwrap().remove(&task_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::budget::PlanBudget;
+    use chrono::Duration;
+
+    fn task(agent_type: &str, task_type: TaskType, tags: &[&str]) -> ExecutionTask {
+        ExecutionTask {
+            id: Uuid::new_v4(),
+            name: "task".to_string(),
+            description: String::new(),
+            task_type,
+            agent_type: agent_type.to_string(),
+            inputs: HashMap::new(),
+            expected_outputs: Vec::new(),
+            estimated_duration: Duration::minutes(1),
+            status: TaskStatus::Pending,
+            dry_run_first: false,
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            output_schemas: HashMap::new(),
+        }
+    }
+
+    fn plan(domain: &str, risk_level: RiskLevel) -> IntentExecutionPlan {
+        IntentExecutionPlan {
+            id: Uuid::new_v4(),
+            intent_id: Uuid::new_v4(),
+            tasks: Vec::new(),
+            dependencies: Vec::new(),
+            estimated_duration: Duration::minutes(1),
+            autonomy_tier: 2,
+            checkpoints: Vec::new(),
+            rollback_plan: None,
+            budget: PlanBudget::default(),
+            domain: Some(domain.to_string()),
+            risk_level: Some(risk_level),
+            environment: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    const POLICY: &str = r#"{
+        "rules": [
+            { "name": "no-wipes", "when": { "tags": ["destructive"], "domain": ["infra_deployment"] }, "effect": "deny" },
+            { "name": "risky", "when": { "risk": ["High", "Critical"] },
+              "effect": "require_approval", "approver_groups": ["sre"], "min_approvers": 2 },
+            { "name": "reads", "when": { "task_type": ["Sense"] }, "effect": "require_approval" },
+            { "name": "everything-else-from-deployer", "when": { "agent_type": ["deployer-agent"] }, "effect": "auto_approve" }
+        ],
+        "default": { "effect": "deny" }
+    }"#;
+
+    #[test]
+    fn test_first_matching_rule_decides() {
+        let policy = ApprovalPolicy::from_json(POLICY).unwrap();
+        let decide = |plan: &IntentExecutionPlan, task: &ExecutionTask| {
+            let decision = policy.evaluate(plan, task);
+            (decision.rule, decision.effect)
+        };
+        let infra = plan("infra_deployment", RiskLevel::High);
+        let wipe = task("deployer-agent", TaskType::Execute, &["destructive"]);
+
+        // The deny comes first, so high risk does not turn it into an approval
+        assert_eq!(decide(&infra, &wipe), (Some("no-wipes".to_string()), ApprovalEffect::Deny));
+        let risky = ApprovalEffect::RequireApproval { approver_groups: vec!["sre".to_string()], min_approvers: 2 };
+        assert_eq!(decide(&infra, &task("deployer-agent", TaskType::Sense, &[])), (Some("risky".to_string()), risky));
+        // Outside infra the same tags are fine, and later rules get their turn
+        let content = plan("marketing_content", RiskLevel::Low);
+        assert_eq!(
+            decide(&content, &task("analyzer-agent", TaskType::Sense, &["destructive"])),
+            (Some("reads".to_string()), ApprovalEffect::RequireApproval { approver_groups: Vec::new(), min_approvers: 1 }),
+        );
+        assert_eq!(decide(&content, &wipe), (Some("everything-else-from-deployer".to_string()), ApprovalEffect::AutoApprove));
+        assert_eq!(decide(&content, &task("writer-agent", TaskType::Execute, &[])), (None, ApprovalEffect::Deny));
+
+        assert_eq!(ApprovalPolicy::default().evaluate(&infra, &wipe).effect, ApprovalEffect::AutoApprove);
+
+        // Environment rules match only plans aimed at the environment
+        let prod_only = ApprovalPolicy::from_json(r#"{ "rules": [{ "name": "prod", "when": { "environment": ["prod"] }, "effect": "deny" }] }"#).unwrap();
+        let mut production = plan("infra_deployment", RiskLevel::Low);
+        production.environment = Some(Environment::Production);
+        assert_eq!(prod_only.evaluate(&production, &wipe).effect, ApprovalEffect::Deny);
+        assert_eq!(prod_only.evaluate(&infra, &wipe).effect, ApprovalEffect::AutoApprove);
+        assert!(ApprovalPolicy::from_json(r#"{ "rules": [{ "name": "x", "effect": "shrug" }] }"#).is_err());
+    }
+
+    #[test]
+    fn test_distinct_members_of_the_approver_groups_release_a_task() {
+        let ledger = ApprovalLedger::new();
+        let task = task("deployer-agent", TaskType::Execute, &[]);
+        let sre = ["sre".to_string()];
+        assert_eq!(ledger.approve(task.id, "ada", &sre), Err(ApprovalError::NotPending(task.id)));
+
+        ledger.request(Uuid::new_v4(), &task, Some("risky".to_string()), vec!["sre".to_string(), "dba".to_string()], 2);
+        let err = ledger.approve(task.id, "eve", &["marketing".to_string()]).unwrap_err();
+        assert_eq!(err.to_string(), format!("Approving task {} requires membership of one of: sre, dba", task.id));
+
+        assert_eq!(ledger.approve(task.id, "ada", &sre), Ok(ApprovalStatus::Pending { approvals: 1, required: 2 }));
+        assert_eq!(ledger.approve(task.id, "ada", &sre), Ok(ApprovalStatus::Pending { approvals: 1, required: 2 }));
+        // Asking again keeps the approvals collected so far
+        ledger.request(Uuid::new_v4(), &task, None, Vec::new(), 1);
+        assert_eq!(ledger.approve(task.id, "bob", &["dba".to_string()]), Ok(ApprovalStatus::Released));
+        assert_eq!(ledger.get(task.id).unwrap().approvers.into_iter().collect::<Vec<_>>(), ["ada", "bob"]);
+
+        let rejected = ledger.reject(task.id, "bob", &sre, Some("change freeze".to_string())).unwrap();
+        assert_eq!(rejected, ApprovalStatus::Rejected { by: "bob".to_string(), reason: Some("change freeze".to_string()) });
+        assert_eq!(ledger.approve(task.id, "cy", &sre), Err(ApprovalError::AlreadyRejected(task.id)));
+    }
+}
@@ -0,0 +1,408 @@
+//! Storage for task outputs too large to carry inline in results.
+//!
+//! An output over the inline threshold is written to an `ArtifactStore` and replaced in
+//! the result by its `ArtifactRef`. Reads check the stored bytes against the checksum
+//! taken when they were written.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Serialized size above which an output is stored as an artifact
+pub const DEFAULT_INLINE_THRESHOLD: usize = 64 * 1024;
+
+/// Stands in for a stored output in task and plan results
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArtifactRef {
+    pub id: Uuid,
+    pub name: String,
+    pub size: u64,
+    pub content_type: String,
+    /// Hex SHA-256 of the stored bytes
+    pub checksum: String,
+}
+
+impl ArtifactRef {
+    /// The reference an output value was replaced by, if it was
+    pub fn from_value(value: &serde_json::Value) -> Option<Self> {
+        serde_json::from_value(value.clone()).ok()
+    }
+}
+
+/// What an artifact is and what produced it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ArtifactMetadata {
+    pub name: String,
+    pub content_type: String,
+    pub plan_id: Option<Uuid>,
+    pub task_id: Option<Uuid>,
+}
+
+impl ArtifactMetadata {
+    pub fn new(name: impl Into<String>, content_type: impl Into<String>) -> Self {
+        Self { name: name.into(), content_type: content_type.into(), ..Self::default() }
+    }
+
+    pub fn with_plan(mut self, plan_id: Uuid) -> Self {
+        self.plan_id = Some(plan_id);
+        self
+    }
+
+    pub fn with_task(mut self, task_id: Uuid) -> Self {
+        self.task_id = Some(task_id);
+        self
+    }
+}
+
+/// A stored artifact as listed by its store
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArtifactInfo {
+    #[serde(flatten)]
+    pub reference: ArtifactRef,
+    pub plan_id: Option<Uuid>,
+    pub task_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl ArtifactInfo {
+    /// Describe `bytes` about to be stored under a new id
+    pub fn describe(bytes: &[u8], metadata: ArtifactMetadata) -> Self {
+        Self {
+            reference: ArtifactRef {
+                id: Uuid::new_v4(),
+                name: metadata.name,
+                size: bytes.len() as u64,
+                content_type: metadata.content_type,
+                checksum: checksum(bytes),
+            },
+            plan_id: metadata.plan_id,
+            task_id: metadata.task_id,
+            created_at: Utc::now(),
+        }
+    }
+}
+
+/// An artifact's bytes, checked against its checksum
+#[derive(Debug, Clone)]
+pub struct Artifact {
+    pub info: ArtifactInfo,
+    pub bytes: Vec<u8>,
+}
+
+impl Artifact {
+    /// Pair `bytes` read back from a store with their description, failing if they are
+    /// not the bytes that were stored
+    pub fn verified(info: ArtifactInfo, bytes: Vec<u8>) -> Result<Self, ArtifactError> {
+        let actual = checksum(&bytes);
+        if actual != info.reference.checksum {
+            return Err(ArtifactError::ChecksumMismatch {
+                id: info.reference.id,
+                expected: info.reference.checksum,
+                actual,
+            });
+        }
+        Ok(Self { info, bytes })
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ArtifactError {
+    #[error("Artifact {0} not found")]
+    NotFound(Uuid),
+
+    #[error("Artifact {id} is corrupt: expected checksum {expected}, found {actual}")]
+    ChecksumMismatch { id: Uuid, expected: String, actual: String },
+}
+
+/// Hex SHA-256 of `bytes`
+pub fn checksum(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+/// Where artifacts are kept
+#[async_trait]
+pub trait ArtifactStore: Send + Sync {
+    async fn put(&self, bytes: Vec<u8>, metadata: ArtifactMetadata) -> Result<ArtifactRef>;
+
+    /// The artifact's bytes, failing with `ArtifactError` if it is missing or corrupt
+    async fn get(&self, id: Uuid) -> Result<Artifact>;
+
+    async fn list_by_plan(&self, plan_id: Uuid) -> Result<Vec<ArtifactInfo>>;
+
+    /// Every stored artifact
+    async fn list(&self) -> Result<Vec<ArtifactInfo>>;
+
+    /// Remove an artifact; removing one that is already gone is not an error
+    async fn delete(&self, id: Uuid) -> Result<()>;
+}
+
+/// Delete a plan's artifacts along with the plan, returning how many there were
+pub async fn delete_plan_artifacts(store: &dyn ArtifactStore, plan_id: Uuid) -> Result<usize> {
+    let artifacts = store.list_by_plan(plan_id).await?;
+    for artifact in &artifacts {
+        store.delete(artifact.reference.id).await?;
+    }
+    Ok(artifacts.len())
+}
+
+/// Delete artifacts older than `retention`, returning how many were deleted
+pub async fn collect_garbage(store: &dyn ArtifactStore, retention: Duration) -> Result<usize> {
+    let cutoff = Utc::now() - retention;
+    let mut deleted = 0;
+    for artifact in store.list().await? {
+        if artifact.created_at < cutoff {
+            store.delete(artifact.reference.id).await?;
+            deleted += 1;
+        }
+    }
+    if deleted > 0 {
+        tracing::info!("Deleted {} artifacts older than {}", deleted, cutoff);
+    }
+    Ok(deleted)
+}
+
+/// Moves outputs larger than the inline threshold into a store
+#[derive(Clone)]
+pub struct Externalizer {
+    store: Arc<dyn ArtifactStore>,
+    inline_threshold: usize,
+}
+
+impl Externalizer {
+    pub fn new(store: Arc<dyn ArtifactStore>) -> Self {
+        Self { store, inline_threshold: DEFAULT_INLINE_THRESHOLD }
+    }
+
+    /// Bytes an output may take before it is stored as an artifact
+    pub fn with_inline_threshold(mut self, bytes: usize) -> Self {
+        self.inline_threshold = bytes;
+        self
+    }
+
+    pub fn store(&self) -> &Arc<dyn ArtifactStore> {
+        &self.store
+    }
+
+    /// `value` itself when small enough, otherwise the reference to it as stored. Strings
+    /// are stored as text, anything else as JSON.
+    pub async fn externalize(
+        &self,
+        name: &str,
+        value: serde_json::Value,
+        plan_id: Option<Uuid>,
+        task_id: Option<Uuid>,
+    ) -> Result<serde_json::Value> {
+        let (bytes, content_type) = match &value {
+            serde_json::Value::String(text) => (text.as_bytes().to_vec(), text_content_type(name)),
+            value => (serde_json::to_vec(value)?, "application/json"),
+        };
+        if bytes.len() <= self.inline_threshold {
+            return Ok(value);
+        }
+        let metadata = ArtifactMetadata { name: name.to_string(), content_type: content_type.to_string(), plan_id, task_id };
+        let reference = self.store.put(bytes, metadata).await?;
+        tracing::debug!("Stored output {} as artifact {} ({} bytes)", name, reference.id, reference.size);
+        Ok(serde_json::to_value(reference)?)
+    }
+
+    /// `outputs` with each one over the threshold replaced by its reference
+    pub async fn externalize_all(
+        &self,
+        outputs: HashMap<String, serde_json::Value>,
+        plan_id: Option<Uuid>,
+        task_id: Option<Uuid>,
+    ) -> Result<HashMap<String, serde_json::Value>> {
+        let mut kept = HashMap::with_capacity(outputs.len());
+        for (name, value) in outputs {
+            let value = self.externalize(&name, value, plan_id, task_id).await?;
+            kept.insert(name, value);
+        }
+        Ok(kept)
+    }
+}
+
+/// Content type of a text output, going by the extension of its name
+fn text_content_type(name: &str) -> &'static str {
+    match name.rsplit_once('.').map(|(_, ext)| ext.to_ascii_lowercase()).as_deref() {
+        Some("json") => "application/json",
+        Some("yaml" | "yml") => "application/yaml",
+        Some("md") => "text/markdown; charset=utf-8",
+        Some("csv") => "text/csv; charset=utf-8",
+        Some("html") => "text/html; charset=utf-8",
+        _ => "text/plain; charset=utf-8",
+    }
+}
+
+/// Keeps each artifact as `<id>.bin` beside a `<id>.json` description in one directory
+pub struct FsArtifactStore {
+    root: PathBuf,
+}
+
+impl FsArtifactStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn data_path(&self, id: Uuid) -> PathBuf {
+        self.root.join(format!("{}.bin", id))
+    }
+
+    fn info_path(&self, id: Uuid) -> PathBuf {
+        self.root.join(format!("{}.json", id))
+    }
+
+    async fn info(&self, id: Uuid) -> Result<ArtifactInfo> {
+        match tokio::fs::read(self.info_path(id)).await {
+            Ok(json) => Ok(serde_json::from_slice(&json)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(ArtifactError::NotFound(id).into()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[async_trait]
+impl ArtifactStore for FsArtifactStore {
+    async fn put(&self, bytes: Vec<u8>, metadata: ArtifactMetadata) -> Result<ArtifactRef> {
+        let info = ArtifactInfo::describe(&bytes, metadata);
+        let id = info.reference.id;
+        tokio::fs::create_dir_all(&self.root).await?;
+        tokio::fs::write(self.data_path(id), &bytes).await?;
+        // The description goes last, so a listed artifact always has its bytes
+        tokio::fs::write(self.info_path(id), serde_json::to_vec(&info)?).await?;
+        Ok(info.reference)
+    }
+
+    async fn get(&self, id: Uuid) -> Result<Artifact> {
+        let info = self.info(id).await?;
+        let bytes = match tokio::fs::read(self.data_path(id)).await {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(ArtifactError::NotFound(id).into()),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Artifact::verified(info, bytes)?)
+    }
+
+    async fn list_by_plan(&self, plan_id: Uuid) -> Result<Vec<ArtifactInfo>> {
+        let mut artifacts = self.list().await?;
+        artifacts.retain(|artifact| artifact.plan_id == Some(plan_id));
+        Ok(artifacts)
+    }
+
+    async fn list(&self) -> Result<Vec<ArtifactInfo>> {
+        let mut entries = match tokio::fs::read_dir(&self.root).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut artifacts = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                match serde_json::from_slice::<ArtifactInfo>(&tokio::fs::read(&path).await?) {
+                    Ok(info) => artifacts.push(info),
+                    Err(e) => tracing::warn!("Skipping unreadable artifact description {}: {}", path.display(), e),
+                }
+            }
+        }
+        artifacts.sort_by_key(|artifact| artifact.created_at);
+        Ok(artifacts)
+    }
+
+    async fn delete(&self, id: Uuid) -> Result<()> {
+        for path in [self.info_path(id), self.data_path(id)] {
+            match tokio::fs::remove_file(path).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> (FsArtifactStore, PathBuf) {
+        let root = std::env::temp_dir().join(format!("artifacts-{}", Uuid::new_v4()));
+        (FsArtifactStore::new(&root), root)
+    }
+
+    #[tokio::test]
+    async fn test_large_outputs_are_externalized() {
+        let (store, root) = store();
+        let store: Arc<dyn ArtifactStore> = Arc::new(store);
+        let externalizer = Externalizer::new(store.clone()).with_inline_threshold(16);
+        let plan_id = Uuid::new_v4();
+        let task_id = Uuid::new_v4();
+
+        let plan_yaml = "steps:\n  - build\n  - deploy\n".to_string();
+        let outputs = HashMap::from([
+            ("status".to_string(), serde_json::json!("ok")),
+            ("deployment-plan.yaml".to_string(), serde_json::json!(plan_yaml)),
+            ("hosts".to_string(), serde_json::json!(["web-1", "web-2", "web-3"])),
+        ]);
+        let outputs = externalizer.externalize_all(outputs, Some(plan_id), Some(task_id)).await.unwrap();
+
+        assert_eq!(outputs["status"], "ok");
+        let plan_ref = ArtifactRef::from_value(&outputs["deployment-plan.yaml"]).unwrap();
+        assert_eq!(plan_ref.size, plan_yaml.len() as u64);
+        assert_eq!(plan_ref.content_type, "application/yaml");
+        assert_eq!(plan_ref.checksum, checksum(plan_yaml.as_bytes()));
+        let hosts_ref = ArtifactRef::from_value(&outputs["hosts"]).unwrap();
+        assert_eq!(hosts_ref.content_type, "application/json");
+
+        let artifact = store.get(plan_ref.id).await.unwrap();
+        assert_eq!(artifact.bytes, plan_yaml.as_bytes());
+        assert_eq!(artifact.info.task_id, Some(task_id));
+        let hosts: serde_json::Value = serde_json::from_slice(&store.get(hosts_ref.id).await.unwrap().bytes).unwrap();
+        assert_eq!(hosts, serde_json::json!(["web-1", "web-2", "web-3"]));
+
+        assert_eq!(store.list_by_plan(plan_id).await.unwrap().len(), 2);
+        assert!(store.list_by_plan(Uuid::new_v4()).await.unwrap().is_empty());
+        assert_eq!(delete_plan_artifacts(store.as_ref(), plan_id).await.unwrap(), 2);
+        assert!(store.list().await.unwrap().is_empty());
+        std::fs::remove_dir_all(root).ok();
+    }
+
+    #[tokio::test]
+    async fn test_reads_verify_the_checksum() {
+        let (store, root) = store();
+        let reference = store.put(b"quarterly numbers".to_vec(), ArtifactMetadata::new("report.txt", "text/plain")).await.unwrap();
+        assert_eq!(store.get(reference.id).await.unwrap().bytes, b"quarterly numbers");
+
+        std::fs::write(root.join(format!("{}.bin", reference.id)), b"doctored numbers").unwrap();
+        let error = store.get(reference.id).await.unwrap_err();
+        assert!(matches!(error.downcast_ref::<ArtifactError>(), Some(ArtifactError::ChecksumMismatch { .. })));
+
+        store.delete(reference.id).await.unwrap();
+        let error = store.get(reference.id).await.unwrap_err();
+        assert!(matches!(error.downcast_ref::<ArtifactError>(), Some(ArtifactError::NotFound(id)) if *id == reference.id));
+        std::fs::remove_dir_all(root).ok();
+    }
+
+    #[tokio::test]
+    async fn test_garbage_collection_keeps_recent_artifacts() {
+        let (store, root) = store();
+        let old = store.put(b"old".to_vec(), ArtifactMetadata::new("old.txt", "text/plain")).await.unwrap();
+        let recent = store.put(b"recent".to_vec(), ArtifactMetadata::new("recent.txt", "text/plain")).await.unwrap();
+
+        let info_path = root.join(format!("{}.json", old.id));
+        let mut info: ArtifactInfo = serde_json::from_slice(&std::fs::read(&info_path).unwrap()).unwrap();
+        info.created_at -= Duration::days(31);
+        std::fs::write(&info_path, serde_json::to_vec(&info).unwrap()).unwrap();
+
+        assert_eq!(collect_garbage(&store, Duration::days(30)).await.unwrap(), 1);
+        let left: Vec<Uuid> = store.list().await.unwrap().iter().map(|a| a.reference.id).collect();
+        assert_eq!(left, vec![recent.id]);
+        std::fs::remove_dir_all(root).ok();
+    }
+}
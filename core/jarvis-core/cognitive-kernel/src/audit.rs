@@ -0,0 +1,444 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{mpsc, oneshot};
+use uuid::Uuid;
+
+/// Replaces the value of every parameter whose name looks credential-bearing
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Parameter names, compared lowercased with `-` and `_` removed, whose values are redacted
+const SECRET_KEYS: &[&str] = &[
+    "password", "passwd", "secret", "token", "apikey", "authorization", "credential",
+    "privatekey", "cookie", "accesskey", "signature",
+];
+
+/// Longest string kept in a parameters digest; longer ones are truncated
+const MAX_DIGEST_STRING: usize = 256;
+
+/// Events written to the sink in one call at most
+const MAX_BATCH: usize = 100;
+
+/// Who performed an audited operation. All `None` is the system itself.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditActor {
+    pub user_id: Option<String>,
+    pub session_id: Option<String>,
+    pub agent_id: Option<String>,
+}
+
+impl AuditActor {
+    pub fn system() -> Self {
+        Self::default()
+    }
+
+    pub fn user(user_id: impl Into<String>, session_id: impl Into<String>) -> Self {
+        Self { user_id: Some(user_id.into()), session_id: Some(session_id.into()), agent_id: None }
+    }
+
+    pub fn agent(agent_id: impl Into<String>) -> Self {
+        Self { agent_id: Some(agent_id.into()), ..Self::default() }
+    }
+
+    /// Whether `id` is this actor's user, session or agent id
+    pub fn matches(&self, id: &str) -> bool {
+        [&self.user_id, &self.session_id, &self.agent_id].iter().any(|own| own.as_deref() == Some(id))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    ToolCall,
+    ExternalOperation,
+    AiRequest,
+    TaskDispatch,
+    TaskApproval,
+    TaskRejection,
+    /// A user's preferences changed a plan before it was returned or run
+    PolicyDecision,
+    /// An approval policy decided whether a task may run
+    ApprovalEvaluation,
+    /// A safety filter rule triggered on text going into or coming out of a model
+    SafetyFilter,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::ToolCall => "tool_call",
+            AuditAction::ExternalOperation => "external_operation",
+            AuditAction::AiRequest => "ai_request",
+            AuditAction::TaskDispatch => "task_dispatch",
+            AuditAction::TaskApproval => "task_approval",
+            AuditAction::TaskRejection => "task_rejection",
+            AuditAction::PolicyDecision => "policy_decision",
+            AuditAction::ApprovalEvaluation => "approval_evaluation",
+            AuditAction::SafetyFilter => "safety_filter",
+        }
+    }
+}
+
+impl std::str::FromStr for AuditAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        serde_json::from_value(serde_json::Value::String(s.to_string()))
+            .map_err(|_| anyhow::anyhow!("Unknown audit action: {}", s))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum AuditOutcome {
+    Success,
+    Failure { error: String },
+}
+
+impl AuditOutcome {
+    /// Outcome of an operation that returned `result`
+    pub fn of<T, E: std::fmt::Display>(result: &std::result::Result<T, E>) -> Self {
+        match result {
+            Ok(_) => AuditOutcome::Success,
+            Err(e) => AuditOutcome::Failure { error: e.to_string() },
+        }
+    }
+}
+
+/// One side-effecting operation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEvent {
+    pub id: Uuid,
+    pub timestamp: DateTime<Utc>,
+    pub actor: AuditActor,
+    pub action: AuditAction,
+    /// What was acted on, e.g. `mcp-tool:search` or `plan:<id>/task:<id>`
+    pub target: String,
+    /// The operation's parameters with credentials redacted and long strings truncated
+    pub parameters_digest: serde_json::Value,
+    pub outcome: AuditOutcome,
+}
+
+impl AuditEvent {
+    pub fn new(
+        actor: AuditActor,
+        action: AuditAction,
+        target: impl Into<String>,
+        parameters: &serde_json::Value,
+        outcome: AuditOutcome,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            timestamp: Utc::now(),
+            actor,
+            action,
+            target: target.into(),
+            parameters_digest: digest(parameters),
+            outcome,
+        }
+    }
+}
+
+/// A copy of `parameters` that is safe to store: values under credential-bearing names
+/// are replaced with `REDACTED`, as are bearer tokens anywhere, and long strings are cut
+pub fn digest(parameters: &serde_json::Value) -> serde_json::Value {
+    match parameters {
+        serde_json::Value::Object(map) => map.iter()
+            .map(|(key, value)| {
+                let value = if is_secret_key(key) { REDACTED.into() } else { digest(value) };
+                (key.clone(), value)
+            })
+            .collect(),
+        serde_json::Value::Array(items) => items.iter().map(digest).collect(),
+        serde_json::Value::String(s) if s.trim_start().to_lowercase().starts_with("bearer ") => REDACTED.into(),
+        serde_json::Value::String(s) if s.chars().count() > MAX_DIGEST_STRING => {
+            format!("{}…", s.chars().take(MAX_DIGEST_STRING).collect::<String>()).into()
+        }
+        other => other.clone(),
+    }
+}
+
+fn is_secret_key(key: &str) -> bool {
+    let normalized: String = key.chars().filter(|c| !matches!(c, '-' | '_')).collect::<String>().to_lowercase();
+    SECRET_KEYS.iter().any(|secret| normalized.contains(secret))
+}
+
+/// Which events an audit query returns
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuditFilter {
+    /// A user, session or agent id
+    pub actor: Option<String>,
+    pub action: Option<AuditAction>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<usize>,
+}
+
+impl AuditFilter {
+    pub fn matches(&self, event: &AuditEvent) -> bool {
+        self.actor.as_deref().is_none_or(|actor| event.actor.matches(actor))
+            && self.action.is_none_or(|action| event.action == action)
+            && self.since.is_none_or(|since| event.timestamp >= since)
+            && self.until.is_none_or(|until| event.timestamp < until)
+    }
+}
+
+/// Durable storage for audit events
+#[async_trait]
+pub trait AuditSink: Send + Sync {
+    async fn write(&self, events: &[AuditEvent]) -> Result<()>;
+
+    /// Events matching `filter`, newest first
+    async fn query(&self, filter: &AuditFilter) -> Result<Vec<AuditEvent>>;
+}
+
+/// Appends events to a file, one JSON object per line
+pub struct JsonlAuditSink {
+    path: PathBuf,
+    lock: tokio::sync::Mutex<()>,
+}
+
+impl JsonlAuditSink {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), lock: tokio::sync::Mutex::new(()) }
+    }
+}
+
+#[async_trait]
+impl AuditSink for JsonlAuditSink {
+    async fn write(&self, events: &[AuditEvent]) -> Result<()> {
+        let mut lines = Vec::new();
+        for event in events {
+            serde_json::to_writer(&mut lines, event)?;
+            lines.push(b'\n');
+        }
+
+        let _guard = self.lock.lock().await;
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let mut file = tokio::fs::OpenOptions::new().create(true).append(true).open(&self.path).await?;
+        file.write_all(&lines).await?;
+        file.flush().await?;
+        Ok(())
+    }
+
+    async fn query(&self, filter: &AuditFilter) -> Result<Vec<AuditEvent>> {
+        let contents = {
+            let _guard = self.lock.lock().await;
+            match tokio::fs::read_to_string(&self.path).await {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+                Err(e) => return Err(e.into()),
+            }
+        };
+
+        let mut events = Vec::new();
+        for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+            match serde_json::from_str::<AuditEvent>(line) {
+                Ok(event) if filter.matches(&event) => events.push(event),
+                Ok(_) => {}
+                Err(e) => tracing::warn!("Skipping unreadable audit line in {}: {}", self.path.display(), e),
+            }
+        }
+        events.sort_by_key(|event| std::cmp::Reverse(event.timestamp));
+        events.truncate(filter.limit.unwrap_or(usize::MAX));
+        Ok(events)
+    }
+}
+
+enum Message {
+    Event(Box<AuditEvent>),
+    Flush(oneshot::Sender<()>),
+}
+
+/// Hands audit events to a background writer through a bounded channel.
+///
+/// `record` never waits: when the channel is full, or the writer has stopped, the event
+/// is dropped, counted and reported as an error so a stalled sink cannot slow down the
+/// operations being audited. Write failures are counted the same way.
+#[derive(Clone)]
+pub struct Auditor {
+    sender: mpsc::Sender<Message>,
+    sink: Arc<dyn AuditSink>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl Auditor {
+    /// Start a writer for `sink` buffering up to `capacity` events
+    pub fn spawn(sink: Arc<dyn AuditSink>, capacity: usize) -> Self {
+        let (sender, receiver) = mpsc::channel(capacity.max(1));
+        let dropped = Arc::new(AtomicU64::new(0));
+        tokio::spawn(write_events(receiver, sink.clone(), dropped.clone()));
+        Self { sender, sink, dropped }
+    }
+
+    pub fn record(&self, event: AuditEvent) {
+        if self.sender.try_send(Message::Event(Box::new(event))).is_err() {
+            count_dropped(&self.dropped, 1);
+        }
+    }
+
+    /// Events lost because the channel was full or the sink failed
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Wait until everything recorded so far has been written, e.g. on shutdown
+    pub async fn flush(&self) {
+        let (done, written) = oneshot::channel();
+        if self.sender.send(Message::Flush(done)).await.is_ok() {
+            let _ = written.await;
+        }
+    }
+
+    pub async fn query(&self, filter: &AuditFilter) -> Result<Vec<AuditEvent>> {
+        self.sink.query(filter).await
+    }
+}
+
+async fn write_events(mut receiver: mpsc::Receiver<Message>, sink: Arc<dyn AuditSink>, dropped: Arc<AtomicU64>) {
+    while let Some(message) = receiver.recv().await {
+        let mut batch = Vec::new();
+        let mut flushes = Vec::new();
+        let mut next = Some(message);
+        while let Some(message) = next {
+            match message {
+                Message::Event(event) => batch.push(*event),
+                Message::Flush(done) => flushes.push(done),
+            }
+            next = if batch.len() < MAX_BATCH { receiver.try_recv().ok() } else { None };
+        }
+
+        if !batch.is_empty() {
+            if let Err(e) = sink.write(&batch).await {
+                tracing::error!("Failed to write {} audit events: {}", batch.len(), e);
+                count_dropped(&dropped, batch.len() as u64);
+            }
+        }
+        for done in flushes {
+            let _ = done.send(());
+        }
+    }
+}
+
+fn count_dropped(dropped: &AtomicU64, count: u64) {
+    let before = dropped.fetch_add(count, Ordering::Relaxed);
+    let total = before + count;
+    // Alarm on the first drop, then at every power of two so a flood stays readable
+    if before == 0 || total.ilog2() > before.ilog2() {
+        tracing::error!("Audit events dropped: {} so far", total);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use tokio::sync::Notify;
+
+    use super::*;
+
+    /// Keeps written events in memory, optionally holding every write until released
+    #[derive(Default)]
+    struct StallingSink {
+        events: Mutex<Vec<AuditEvent>>,
+        stalled: Option<Notify>,
+    }
+
+    #[async_trait]
+    impl AuditSink for StallingSink {
+        async fn write(&self, events: &[AuditEvent]) -> Result<()> {
+            if let Some(release) = &self.stalled {
+                release.notified().await;
+            }
+            self.events.lock().unwrap().extend_from_slice(events);
+            Ok(())
+        }
+
+        async fn query(&self, filter: &AuditFilter) -> Result<Vec<AuditEvent>> {
+            Ok(self.events.lock().unwrap().iter().filter(|e| filter.matches(e)).cloned().collect())
+        }
+    }
+
+    fn tool_call(user: &str) -> AuditEvent {
+        AuditEvent::new(
+            AuditActor::user(user, "session-1"),
+            AuditAction::ToolCall,
+            "mcp-tool:search",
+            &serde_json::json!({"query": "q3 report"}),
+            AuditOutcome::Success,
+        )
+    }
+
+    #[test]
+    fn test_digest_redacts_credentials() {
+        let parameters = serde_json::json!({
+            "to": "ops@example.com",
+            "api_key": "sk-live-123",
+            "auth": {"Client-Secret": "abc", "refreshToken": "def", "scope": "mail"},
+            "headers": [{"name": "x", "value": "Bearer eyJhbGci"}],
+            "body": "x".repeat(300),
+        });
+
+        let digest = digest(&parameters);
+        assert_eq!(digest["to"], "ops@example.com");
+        assert_eq!(digest["api_key"], REDACTED);
+        assert_eq!(digest["auth"], serde_json::json!({"Client-Secret": REDACTED, "refreshToken": REDACTED, "scope": "mail"}));
+        assert_eq!(digest["headers"][0]["value"], REDACTED);
+        assert_eq!(digest["body"].as_str().unwrap().chars().count(), MAX_DIGEST_STRING + 1);
+        assert!(!digest.to_string().contains("sk-live") && !digest.to_string().contains("eyJ"));
+    }
+
+    #[tokio::test]
+    async fn test_record_does_not_block_when_the_sink_stalls() {
+        let sink = Arc::new(StallingSink { stalled: Some(Notify::new()), ..Default::default() });
+        let auditor = Auditor::spawn(sink.clone(), 2);
+
+        // The writer has not run yet, so two events fit and the rest are dropped
+        let started = std::time::Instant::now();
+        for _ in 0..10 {
+            auditor.record(tool_call("alice"));
+        }
+        assert!(started.elapsed() < std::time::Duration::from_millis(100));
+        assert_eq!(auditor.dropped(), 8);
+
+        sink.stalled.as_ref().unwrap().notify_one();
+        auditor.flush().await;
+        assert_eq!(sink.events.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_jsonl_sink_filters_by_actor_action_and_time() {
+        let path = std::env::temp_dir().join(format!("audit-{}.jsonl", Uuid::new_v4()));
+        let sink = JsonlAuditSink::new(&path);
+
+        let mut old = tool_call("alice");
+        old.timestamp -= chrono::Duration::hours(2);
+        let mut approval = AuditEvent::new(
+            AuditActor::user("bob", "session-2"),
+            AuditAction::TaskApproval,
+            "task:1",
+            &serde_json::Value::Null,
+            AuditOutcome::Failure { error: "not found".to_string() },
+        );
+        approval.timestamp += chrono::Duration::seconds(1);
+        sink.write(&[old.clone(), tool_call("alice"), approval.clone()]).await.unwrap();
+
+        let alice = AuditFilter { actor: Some("alice".to_string()), ..Default::default() };
+        assert_eq!(sink.query(&alice).await.unwrap().len(), 2);
+        let recent = AuditFilter { since: Some(Utc::now() - chrono::Duration::hours(1)), ..alice };
+        assert_eq!(sink.query(&recent).await.unwrap().len(), 1);
+        let approvals = AuditFilter { action: Some("task_approval".parse().unwrap()), ..Default::default() };
+        assert_eq!(sink.query(&approvals).await.unwrap(), vec![approval]);
+        assert_eq!(sink.query(&AuditFilter { limit: Some(1), ..Default::default() }).await.unwrap()[0].actor.user_id.as_deref(), Some("bob"));
+
+        tokio::fs::remove_file(&path).await.unwrap();
+    }
+}
@@ -0,0 +1,75 @@
+use chrono::Duration;
+use serde::{Serialize, Deserialize};
+
+/// Ceilings on what executing a plan may consume; `None` means no ceiling. The default
+/// has no ceilings and no retries.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PlanBudget {
+    pub max_wall_time: Option<Duration>,
+    pub max_llm_tokens: Option<u64>,
+    pub max_cost_usd: Option<f64>,
+    /// Extra attempts, across the whole plan, given to tasks that fail
+    pub max_task_retries: u32,
+}
+
+impl PlanBudget {
+    /// Default ceilings for a plan's autonomy tier. Plans that run with less supervision
+    /// get tighter ones.
+    pub fn for_autonomy_tier(tier: u8) -> Self {
+        let (minutes, tokens, cost, retries) = match tier {
+            3 => (15, 100_000, 2.0, 1),
+            2 => (30, 250_000, 5.0, 2),
+            _ => (60, 500_000, 10.0, 2),
+        };
+        Self {
+            max_wall_time: Some(Duration::minutes(minutes)),
+            max_llm_tokens: Some(tokens),
+            max_cost_usd: Some(cost),
+            max_task_retries: retries,
+        }
+    }
+
+    /// The first ceiling `usage` has reached, if any
+    pub fn exceeded_by(&self, usage: &BudgetUsage) -> Option<BudgetLimit> {
+        if self.max_wall_time.is_some_and(|max| usage.wall_time >= max) {
+            return Some(BudgetLimit::WallTime);
+        }
+        if self.max_llm_tokens.is_some_and(|max| usage.llm_tokens >= max) {
+            return Some(BudgetLimit::LlmTokens);
+        }
+        if self.max_cost_usd.is_some_and(|max| usage.cost_usd >= max) {
+            return Some(BudgetLimit::Cost);
+        }
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BudgetLimit {
+    WallTime,
+    LlmTokens,
+    Cost,
+}
+
+/// What a plan has consumed so far
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BudgetUsage {
+    pub wall_time: Duration,
+    pub llm_tokens: u64,
+    pub cost_usd: f64,
+    pub task_retries: u32,
+}
+
+impl BudgetUsage {
+    pub fn add(&mut self, usage: &TaskUsage) {
+        self.llm_tokens += usage.llm_tokens;
+        self.cost_usd += usage.cost_usd;
+    }
+}
+
+/// What one task run consumed, as reported by its runner
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TaskUsage {
+    pub llm_tokens: u64,
+    pub cost_usd: f64,
+}
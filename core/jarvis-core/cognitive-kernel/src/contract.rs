@@ -0,0 +1,226 @@
+//! Output contracts: what a task promises to produce through its `expected_outputs`.
+//!
+//! Once a task completes, every output it expects must be among the outputs its runner
+//! reported. Names with a known extension also fix the content: text given for a `.json`
+//! or `.yaml`/`.yml` output must parse as such, while a structured value already does.
+//! An output with a JSON Schema in the task's `output_schemas` must match it as well.
+//! Outputs the runner stored as artifacts itself count as present; their content is not
+//! read back.
+
+use std::collections::HashMap;
+
+use jsonschema::JSONSchema;
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::artifacts::ArtifactRef;
+use crate::executor::TaskOutput;
+use crate::{ExecutionTask, TaskStatus};
+
+/// One output as a runner reported it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum OutputValue {
+    /// Content held in an artifact store
+    Artifact(ArtifactRef),
+    Inline(Value),
+}
+
+impl From<Value> for OutputValue {
+    fn from(value: Value) -> Self {
+        match ArtifactRef::from_value(&value) {
+            Some(reference) => Self::Artifact(reference),
+            None => Self::Inline(value),
+        }
+    }
+}
+
+/// Content format an output's name declares by its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Json,
+    Yaml,
+}
+
+impl OutputFormat {
+    /// The format of the output called `name`, if its extension is one that is checked
+    pub fn of(name: &str) -> Option<Self> {
+        match name.rsplit_once('.').map(|(_, ext)| ext.to_ascii_lowercase()).as_deref() {
+            Some("json") => Some(Self::Json),
+            Some("yaml" | "yml") => Some(Self::Yaml),
+            _ => None,
+        }
+    }
+
+    fn parse(self, text: &str) -> Result<Value, String> {
+        match self {
+            Self::Json => serde_json::from_str(text).map_err(|e| format!("not valid JSON: {}", e)),
+            Self::Yaml => serde_yaml::from_str(text).map_err(|e| format!("not valid YAML: {}", e)),
+        }
+    }
+}
+
+/// One way a task's outputs fall short of what it declared
+#[derive(Debug, Clone, PartialEq, Eq, Error, Serialize, Deserialize)]
+pub enum ContractViolation {
+    #[error("missing output '{name}'")]
+    Missing { name: String },
+    #[error("invalid output '{name}': {reason}")]
+    Invalid { name: String, reason: String },
+}
+
+/// A completed task broke its output contract
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{}", describe(.violations))]
+pub struct OutputContractError {
+    pub violations: Vec<ContractViolation>,
+}
+
+fn describe(violations: &[ContractViolation]) -> String {
+    violations.iter().map(ToString::to_string).collect::<Vec<_>>().join("; ")
+}
+
+/// Every way `outputs` falls short of the outputs `task` expects, in the order it
+/// declared them
+pub fn check_outputs(task: &ExecutionTask, outputs: &HashMap<String, Value>) -> Vec<ContractViolation> {
+    let mut violations = Vec::new();
+    for name in &task.expected_outputs {
+        let Some(value) = outputs.get(name) else {
+            violations.push(ContractViolation::Missing { name: name.clone() });
+            continue;
+        };
+        let OutputValue::Inline(value) = OutputValue::from(value.clone()) else {
+            continue;
+        };
+        if let Err(reason) = check_content(name, value, task.output_schemas.get(name)) {
+            violations.push(ContractViolation::Invalid { name: name.clone(), reason });
+        }
+    }
+    violations
+}
+
+/// `output` unchanged, unless it is a completed one that breaks `task`'s contract
+pub fn enforce(task: &ExecutionTask, output: TaskOutput) -> Result<TaskOutput, OutputContractError> {
+    if !matches!(output.status, TaskStatus::Completed) {
+        return Ok(output);
+    }
+    let violations = check_outputs(task, &output.outputs);
+    if violations.is_empty() {
+        Ok(output)
+    } else {
+        Err(OutputContractError { violations })
+    }
+}
+
+/// Fail if one of the task's output schemas is no valid JSON Schema or is for an output
+/// the task does not expect
+pub fn validate_schemas(task: &ExecutionTask) -> anyhow::Result<()> {
+    for (name, schema) in &task.output_schemas {
+        if !task.expected_outputs.contains(name) {
+            anyhow::bail!("schema given for output '{}', which the task does not expect", name);
+        }
+        JSONSchema::compile(schema).map_err(|e| anyhow::anyhow!("schema for output '{}' is invalid: {}", name, e))?;
+    }
+    Ok(())
+}
+
+fn check_content(name: &str, value: Value, schema: Option<&Value>) -> Result<(), String> {
+    let content = match (OutputFormat::of(name), value) {
+        (Some(format), Value::String(text)) => format.parse(&text)?,
+        (_, value) => value,
+    };
+    let Some(schema) = schema else {
+        return Ok(());
+    };
+    let compiled = JSONSchema::compile(schema).map_err(|e| format!("schema is invalid: {}", e))?;
+    let result = compiled.validate(&content);
+    if let Err(errors) = result {
+        let errors: Vec<String> = errors.map(|e| format!("{} at '{}'", e, e.instance_path)).collect();
+        return Err(format!("does not match its schema: {}", errors.join("; ")));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TaskType;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    fn task(outputs: &[&str]) -> ExecutionTask {
+        ExecutionTask {
+            id: Uuid::new_v4(),
+            name: "deploy".to_string(),
+            description: String::new(),
+            task_type: TaskType::Execute,
+            agent_type: "deployer".to_string(),
+            inputs: HashMap::new(),
+            expected_outputs: outputs.iter().map(|s| s.to_string()).collect(),
+            estimated_duration: chrono::Duration::minutes(1),
+            status: TaskStatus::Pending,
+            dry_run_first: false,
+            tags: Vec::new(),
+            output_schemas: HashMap::new(),
+        }
+    }
+
+    fn outputs(values: &[(&str, Value)]) -> HashMap<String, Value> {
+        values.iter().map(|(name, value)| (name.to_string(), value.clone())).collect()
+    }
+
+    #[test]
+    fn test_text_must_parse_in_the_format_its_name_declares() {
+        let task = task(&["plan.yaml", "health.json", "notes.md"]);
+        let valid = outputs(&[
+            ("plan.yaml", json!("steps:\n  - build\n  - ship\n")),
+            ("health.json", json!({ "healthy": true })),
+            ("notes.md", json!("# not checked {")),
+        ]);
+        assert!(check_outputs(&task, &valid).is_empty());
+
+        let invalid = outputs(&[
+            ("plan.yaml", json!("steps: [build")),
+            ("health.json", json!("{\"healthy\": tru")),
+        ]);
+        let violations = check_outputs(&task, &invalid);
+        let described: Vec<String> = violations.iter().map(ToString::to_string).collect();
+        assert_eq!(described.len(), 3);
+        assert!(described[0].starts_with("invalid output 'plan.yaml': not valid YAML"), "{}", described[0]);
+        assert!(described[1].starts_with("invalid output 'health.json': not valid JSON"), "{}", described[1]);
+        assert_eq!(described[2], "missing output 'notes.md'");
+    }
+
+    #[test]
+    fn test_outputs_must_match_their_schemas() {
+        let mut task = task(&["health.json"]);
+        task.output_schemas.insert(
+            "health.json".to_string(),
+            json!({ "type": "object", "required": ["healthy"], "properties": { "healthy": { "type": "boolean" } } }),
+        );
+        assert!(validate_schemas(&task).is_ok());
+        assert!(check_outputs(&task, &outputs(&[("health.json", json!("{\"healthy\": false}"))])).is_empty());
+
+        let violations = check_outputs(&task, &outputs(&[("health.json", json!({ "healthy": "yes" }))]));
+        assert!(matches!(
+            &violations[..],
+            [ContractViolation::Invalid { name, reason }] if name == "health.json" && reason.contains("/healthy")
+        ));
+
+        // Stored artifacts are taken as they are
+        let reference = ArtifactRef {
+            id: Uuid::new_v4(),
+            name: "health.json".to_string(),
+            size: 1,
+            content_type: "application/json".to_string(),
+            checksum: String::new(),
+        };
+        assert!(check_outputs(&task, &outputs(&[("health.json", serde_json::to_value(reference).unwrap())])).is_empty());
+
+        task.output_schemas.insert("other.json".to_string(), json!({}));
+        assert!(validate_schemas(&task).unwrap_err().to_string().contains("does not expect"));
+        task.output_schemas = HashMap::from([("health.json".to_string(), json!({ "type": 12 }))]);
+        assert!(validate_schemas(&task).unwrap_err().to_string().contains("is invalid"));
+    }
+}
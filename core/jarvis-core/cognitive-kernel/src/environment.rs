@@ -0,0 +1,199 @@
+//! Deployment environments plans are aimed at
+//!
+//! The kernel reads the environment from an intent's constraints (`env=prod`) or from
+//! how it is phrased ("deploy billing-api to staging"), unless the request names one.
+//! Every task of the plan gets it as its [`ENVIRONMENT_INPUT`], production plans are
+//! held to [`PRODUCTION_MAX_AUTONOMY_TIER`], and an executor can be limited to the
+//! environments its deployment may touch.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+/// Task input naming the environment the task runs against
+pub const ENVIRONMENT_INPUT: &str = "environment";
+
+/// Highest autonomy tier a production plan gets without an explicit override
+pub const PRODUCTION_MAX_AUTONOMY_TIER: u8 = 1;
+
+/// Constraint keys naming an environment, as in `env=prod` or `environment: staging`
+const CONSTRAINT_KEYS: &[&str] = &["env", "environment", "target_env", "target-env"];
+
+/// Words after which an environment is the one a request targets, as in "to staging"
+const TARGETING: &[&str] = &["to", "into", "on", "in", "onto", "against"];
+
+/// Nouns that make the word before them an environment's name, as in "the qa cluster"
+const ENVIRONMENT_NOUNS: &[&str] = &["environment", "env", "cluster"];
+
+/// Words before an environment noun that don't name one, as in "a new environment"
+const NOT_NAMES: &[&str] = &[
+    "the", "a", "an", "new", "same", "this", "that", "my", "our", "your", "their", "each", "every", "other", "target",
+    "whole", "entire", "kubernetes", "k8s",
+];
+
+/// A deployment environment, written as its lowercase name
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum Environment {
+    Dev,
+    Staging,
+    Production,
+    /// Any other named environment, such as `qa` or `eu-west`
+    Custom(String),
+}
+
+impl Environment {
+    /// The well-known environment `word` is a name for, e.g. `prod` for `Production`
+    fn known(word: &str) -> Option<Self> {
+        match word {
+            "dev" | "development" | "sandbox" => Some(Self::Dev),
+            "staging" | "stg" | "preprod" | "pre-prod" => Some(Self::Staging),
+            "prod" | "production" | "prd" => Some(Self::Production),
+            _ => None,
+        }
+    }
+
+    pub fn is_production(&self) -> bool {
+        matches!(self, Self::Production)
+    }
+
+    /// How much harm a mistake in the environment does; of several environments a
+    /// request mentions without targeting one, the most sensitive is assumed
+    fn sensitivity(&self) -> u8 {
+        match self {
+            Self::Dev => 0,
+            Self::Custom(_) => 1,
+            Self::Staging => 2,
+            Self::Production => 3,
+        }
+    }
+}
+
+impl fmt::Display for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Dev => f.write_str("dev"),
+            Self::Staging => f.write_str("staging"),
+            Self::Production => f.write_str("production"),
+            Self::Custom(name) => f.write_str(name),
+        }
+    }
+}
+
+impl FromStr for Environment {
+    type Err = anyhow::Error;
+
+    /// Any non-blank name; aliases such as `prod` or `development` resolve to the
+    /// well-known environments
+    fn from_str(name: &str) -> anyhow::Result<Self> {
+        let name = name.trim().to_lowercase();
+        if name.is_empty() {
+            return Err(anyhow!("Environment name is empty"));
+        }
+        Ok(Self::known(&name).unwrap_or(Self::Custom(name)))
+    }
+}
+
+impl From<Environment> for String {
+    fn from(environment: Environment) -> Self {
+        environment.to_string()
+    }
+}
+
+impl TryFrom<String> for Environment {
+    type Error = anyhow::Error;
+
+    fn try_from(name: String) -> anyhow::Result<Self> {
+        name.parse()
+    }
+}
+
+/// The environment a request targets: the first `env=...` constraint, in `constraints`
+/// or the text itself, then the first environment the text aims at ("to prod", "into
+/// the qa cluster"), then the most sensitive one it mentions at all
+pub fn extract(text: &str, constraints: &[String]) -> Option<Environment> {
+    let constrained = constraints.iter().map(String::as_str)
+        .chain(text.split_whitespace())
+        .find_map(|constraint| {
+            let (key, value) = constraint.split_once(['=', ':'])?;
+            let key = key.trim().to_lowercase();
+            CONSTRAINT_KEYS.contains(&key.as_str()).then(|| value.trim_matches(|c: char| !c.is_alphanumeric()).parse().ok())?
+        });
+    if constrained.is_some() {
+        return constrained;
+    }
+
+    let words: Vec<String> = text
+        .split(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_'))
+        .map(|word| word.trim_matches(|c| c == '-' || c == '_').to_lowercase())
+        .filter(|word| !word.is_empty())
+        .collect();
+
+    let mut mentioned = Vec::new();
+    for (i, word) in words.iter().enumerate() {
+        let named = words.get(i + 1).is_some_and(|next| ENVIRONMENT_NOUNS.contains(&next.as_str()))
+            && !NOT_NAMES.contains(&word.as_str())
+            && !ENVIRONMENT_NOUNS.contains(&word.as_str());
+        let Some(environment) = Environment::known(word).or_else(|| named.then(|| Environment::Custom(word.clone()))) else {
+            continue;
+        };
+        let before = |n: usize| i.checked_sub(n).map(|j| words[j].as_str());
+        let before = match before(1) {
+            Some("the") => before(2),
+            before => before,
+        };
+        if before.is_some_and(|before| TARGETING.contains(&before)) {
+            return Some(environment);
+        }
+        mentioned.push(environment);
+    }
+    mentioned.into_iter().max_by_key(Environment::sensitivity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extracts_environment_from_phrasing_variants() {
+        let env = |text: &str| extract(text, &[]);
+
+        assert_eq!(env("Deploy billing-api to production"), Some(Environment::Production));
+        assert_eq!(env("roll out v2.3 to prod"), Some(Environment::Production));
+        assert_eq!(env("Run the smoke tests against staging."), Some(Environment::Staging));
+        assert_eq!(env("Restart the workers in the eu-west cluster"), Some(Environment::Custom("eu-west".to_string())));
+        assert_eq!(env("Spin up the QA environment"), Some(Environment::Custom("qa".to_string())));
+        // The targeted environment wins over a more sensitive one the request reads from
+        assert_eq!(env("Promote the build from prod into the dev env"), Some(Environment::Dev));
+        assert_eq!(env("Copy prod data into the qa environment"), Some(Environment::Custom("qa".to_string())));
+        // Untargeted mentions resolve to the most sensitive
+        assert_eq!(env("Compare the dev and production configs"), Some(Environment::Production));
+        assert_eq!(env("Deploy the service env=preprod"), Some(Environment::Staging));
+
+        assert_eq!(env("Create a new environment for the team"), None);
+        assert_eq!(env("Write a product launch blog post"), None);
+    }
+
+    #[test]
+    fn test_constraints_win_and_names_round_trip() {
+        let constraints = ["budget: small".to_string(), "Environment: Staging".to_string()];
+        assert_eq!(extract("Deploy billing-api to production", &constraints), Some(Environment::Staging));
+        assert_eq!(extract("Deploy it", &["env=sandbox".to_string()]), Some(Environment::Dev));
+
+        for (name, environment) in [
+            ("dev", Environment::Dev),
+            ("staging", Environment::Staging),
+            ("production", Environment::Production),
+            ("eu-west", Environment::Custom("eu-west".to_string())),
+        ] {
+            let json = serde_json::to_value(&environment).unwrap();
+            assert_eq!(json, name);
+            assert_eq!(serde_json::from_value::<Environment>(json).unwrap(), environment);
+        }
+        assert_eq!(" PRD ".parse::<Environment>().unwrap(), Environment::Production);
+        assert!("  ".parse::<Environment>().is_err());
+        assert!(serde_json::from_str::<Environment>("\"\"").is_err());
+    }
+}
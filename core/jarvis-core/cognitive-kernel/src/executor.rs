@@ -0,0 +1,1083 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use chrono::Duration;
+use serde::{Serialize, Deserialize};
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio::time::Instant;
+use tracing::Instrument;
+use uuid::Uuid;
+
+use crate::approval::{ApprovalEffect, ApprovalLedger, ApprovalPolicy, ApprovalStatus};
+use crate::artifacts::Externalizer;
+use crate::audit::{AuditAction, AuditActor, AuditEvent, AuditOutcome, Auditor};
+use crate::budget::{BudgetLimit, BudgetUsage, TaskUsage};
+use crate::contract;
+use crate::environment::Environment;
+use crate::replan::{AdaptivePlanner, PlanRevision};
+use crate::replay::{Attempts, MismatchPolicy, ReplayBundle, ReplayMismatch, ReplayMode};
+use crate::{ExecutionState, ExecutionTask, IntentExecutionPlan, RollbackStep, TaskStatus};
+
+/// What a runner reports back for one task
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskOutput {
+    pub status: TaskStatus,
+    /// Artifacts keyed by the task's `expected_outputs` names
+    pub outputs: HashMap<String, serde_json::Value>,
+    /// LLM tokens and cost the run consumed, counted against the plan's budget
+    #[serde(default)]
+    pub usage: TaskUsage,
+}
+
+impl TaskOutput {
+    /// The output called `name`, as stored artifact or inline value
+    pub fn output(&self, name: &str) -> Option<contract::OutputValue> {
+        self.outputs.get(name).cloned().map(contract::OutputValue::from)
+    }
+}
+
+/// Carries out the individual tasks of a plan on behalf of a `PlanExecutor`
+#[async_trait]
+pub trait TaskRunner: Send + Sync {
+    /// Reject a task this runner could never execute. Called for every task before any
+    /// of them runs, so a bad plan fails before it has side effects.
+    async fn validate(&self, task: &ExecutionTask) -> Result<()>;
+
+    async fn run(&self, task: &ExecutionTask) -> Result<TaskOutput>;
+
+    /// Like `run`, sending text the task generates to `deltas` as it is produced, such as
+    /// LLM tokens. Runners that do not stream output need not override it.
+    async fn run_streaming(&self, task: &ExecutionTask, deltas: mpsc::UnboundedSender<String>) -> Result<TaskOutput> {
+        drop(deltas);
+        self.run(task).await
+    }
+
+    /// Undo one step of a plan's rollback plan. Runners that cannot roll back need not
+    /// override it; rolling back with them fails.
+    async fn rollback(&self, step: &RollbackStep) -> Result<()> {
+        Err(anyhow!("This runner cannot run rollback step '{}'", step.description))
+    }
+}
+
+/// Result of executing a plan
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanOutcome {
+    pub state: ExecutionState,
+    /// Outputs of each completed task, keyed by task id
+    pub outputs: HashMap<Uuid, HashMap<String, serde_json::Value>>,
+    /// Tasks a replaying executor could not serve from its bundle
+    #[serde(default)]
+    pub mismatches: Vec<ReplayMismatch>,
+    /// What the plan consumed of its budget
+    #[serde(default)]
+    pub usage: BudgetUsage,
+    /// Rollback steps that ran after the plan was denied, in order
+    #[serde(default)]
+    pub rolled_back: Vec<Uuid>,
+}
+
+/// Progress of a plan, published to `with_events` subscribers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum PlanEvent {
+    TaskStarted { plan_id: Uuid, task_id: Uuid },
+    /// Text a running task generated, in the order it was produced
+    TaskOutputDelta { plan_id: Uuid, task_id: Uuid, delta: String },
+    TaskFinished { plan_id: Uuid, task_id: Uuid, status: TaskStatus, usage: BudgetUsage },
+    BudgetExceeded { plan_id: Uuid, limit: BudgetLimit, usage: BudgetUsage },
+}
+
+/// Runs a plan's tasks in dependency order, stopping at the first failure.
+///
+/// Before each task the plan's budget is checked against what the plan has used so far;
+/// once a ceiling is reached the plan pauses as `BudgetExceeded` until `resume`d. Failed
+/// tasks are retried while the budget has retries left.
+///
+/// With `with_recording` every runner call is also written to a replay bundle; with
+/// `with_replay` runner calls are answered from such a bundle instead, so a failed plan
+/// can be stepped through again without repeating its side effects.
+///
+/// With `with_approval_policy` the policy is evaluated before each task. A task it holds
+/// pauses the plan as `AwaitingApproval` until enough approvers release it in the ledger
+/// and the plan is `resume`d; a denied or rejected task fails the plan and runs its
+/// rollback plan.
+///
+/// A task that completes without one of its expected outputs, or with one that breaks
+/// its output contract, fails like a task whose runner errored, naming the output.
+///
+/// A failed plan can be revised with `request_replan` when a planner is configured,
+/// keeping the tasks that completed rather than rolling the whole plan back.
+///
+/// With `with_allowed_environments` plans aimed at any other environment are refused
+/// before anything runs, so a dev deployment never executes a production plan.
+pub struct PlanExecutor<R: TaskRunner> {
+    runner: R,
+    mode: ReplayMode,
+    attempts: Attempts,
+    stop: Option<watch::Receiver<bool>>,
+    events: Option<broadcast::Sender<PlanEvent>>,
+    auditor: Option<Auditor>,
+    artifacts: Option<Externalizer>,
+    planner: Option<AdaptivePlanner>,
+    approvals: Option<(ApprovalPolicy, ApprovalLedger)>,
+    allowed_environments: Option<Vec<Environment>>,
+}
+
+impl<R: TaskRunner> PlanExecutor<R> {
+    pub fn new(runner: R) -> Self {
+        Self {
+            runner,
+            mode: ReplayMode::Live,
+            attempts: Attempts::default(),
+            stop: None,
+            events: None,
+            auditor: None,
+            artifacts: None,
+            planner: None,
+            approvals: None,
+            allowed_environments: None,
+        }
+    }
+
+    /// Only run plans that target one of `environments`, or no environment at all
+    pub fn with_allowed_environments(mut self, environments: Vec<Environment>) -> Self {
+        self.allowed_environments = Some(environments);
+        self
+    }
+
+    /// Revise failed plans with `planner` when `request_replan` is called
+    pub fn with_replanning(mut self, planner: AdaptivePlanner) -> Self {
+        self.planner = Some(planner);
+        self
+    }
+
+    /// Gate every task on `policy`, superseding the plan's checkpoints, with tasks that
+    /// need approval waiting in `ledger`
+    pub fn with_approval_policy(mut self, policy: ApprovalPolicy, ledger: ApprovalLedger) -> Self {
+        self.approvals = Some((policy, ledger));
+        self
+    }
+
+    /// Store task outputs over the externalizer's inline threshold as artifacts of the
+    /// plan, leaving their references in the outcome
+    pub fn with_artifacts(mut self, artifacts: Externalizer) -> Self {
+        self.artifacts = Some(artifacts);
+        self
+    }
+
+    /// Audit every task dispatch, as performed by the task's agent
+    pub fn with_auditor(mut self, auditor: Auditor) -> Self {
+        self.auditor = Some(auditor);
+        self
+    }
+
+    /// Publish task starts, completions, streamed output and budget pauses to `events`
+    pub fn with_events(mut self, events: broadcast::Sender<PlanEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Stop between tasks once `stop` turns true, e.g. on shutdown. The plan then ends as
+    /// `Cancelled` with the remaining tasks still pending, for the caller to persist.
+    pub fn with_stop_signal(mut self, stop: watch::Receiver<bool>) -> Self {
+        self.stop = Some(stop);
+        self
+    }
+
+    /// Record every task run to a replay bundle at `path`, rewritten after each task
+    pub fn with_recording(mut self, path: impl Into<PathBuf>) -> Self {
+        self.mode = ReplayMode::Record { path: path.into(), bundle: Mutex::new(ReplayBundle::default()) };
+        self
+    }
+
+    /// Serve task runs from `bundle`, handling tasks it has no matching run for per `policy`
+    pub fn with_replay(mut self, bundle: ReplayBundle, policy: MismatchPolicy) -> Self {
+        self.mode = ReplayMode::Replay { bundle, policy };
+        self
+    }
+
+    /// Check that the plan's environment is allowed, every task with the runner and its
+    /// output schemas, and that the dependencies form no cycle
+    pub async fn validate(&self, plan: &IntentExecutionPlan) -> Result<()> {
+        if let (Some(allowed), Some(environment)) = (&self.allowed_environments, &plan.environment) {
+            if !allowed.contains(environment) {
+                let allowed: Vec<String> = allowed.iter().map(Environment::to_string).collect();
+                return Err(anyhow!(
+                    "Plan {} targets the {} environment; this executor only runs plans for: {}",
+                    plan.id, environment, allowed.join(", ")
+                ));
+            }
+        }
+        for task in &plan.tasks {
+            self.runner.validate(task).await
+                .and_then(|()| contract::validate_schemas(task))
+                .map_err(|e| anyhow!("Task '{}' cannot be executed: {}", task.name, e))?;
+        }
+        execution_order(plan)?;
+        Ok(())
+    }
+
+    /// Validate the plan, then run its tasks, updating each task's status as it goes
+    pub async fn execute(&self, plan: &mut IntentExecutionPlan) -> Result<PlanOutcome> {
+        self.validate(plan).await?;
+        self.run(plan, PlanOutcome {
+            state: ExecutionState::Executing,
+            outputs: HashMap::new(),
+            mismatches: Vec::new(),
+            usage: BudgetUsage::default(),
+            rolled_back: Vec::new(),
+        }).await
+    }
+
+    /// Continue a plan `paused` as `BudgetExceeded`, normally after raising its budget, or
+    /// as `AwaitingApproval`, normally once the task has been approved. Usage carries over
+    /// from the paused run and completed tasks are not run again.
+    pub async fn resume(&self, plan: &mut IntentExecutionPlan, paused: PlanOutcome) -> Result<PlanOutcome> {
+        if !matches!(paused.state, ExecutionState::BudgetExceeded { .. } | ExecutionState::AwaitingApproval { .. }) {
+            return Err(anyhow!("Plan {} is not paused", plan.id));
+        }
+        self.validate(plan).await?;
+        self.run(plan, paused).await
+    }
+
+    /// A revision of `plan`, which failed as `failed`, that skips its completed tasks.
+    /// Fails without a planner, or when the plan's autonomy tier is too low for the
+    /// planner to revise it, leaving rollback as the way out.
+    pub fn request_replan(&self, plan: &IntentExecutionPlan, failed: &PlanOutcome) -> Result<PlanRevision> {
+        let planner = self.planner.as_ref()
+            .ok_or_else(|| anyhow!("No planner is configured to revise plan {}", plan.id))?;
+        let revision = planner.replan(plan, failed)?;
+        tracing::info!(
+            "Revised plan {} as {}: {} tasks added, {} removed, {} modified",
+            plan.id, revision.plan.id, revision.diff.added.len(), revision.diff.removed.len(), revision.diff.modified.len()
+        );
+        Ok(revision)
+    }
+
+    /// Run the plan in an `execute_plan` span, each task in a `run_task` span within it
+    async fn run(&self, plan: &mut IntentExecutionPlan, outcome: PlanOutcome) -> Result<PlanOutcome> {
+        let span = tracing::info_span!("execute_plan", intent_id = %plan.intent_id, plan_id = %plan.id);
+        self.run_tasks(plan, outcome).instrument(span).await
+    }
+
+    async fn run_tasks(&self, plan: &mut IntentExecutionPlan, mut outcome: PlanOutcome) -> Result<PlanOutcome> {
+        let clock = WallClock::start(&outcome.usage);
+        for index in execution_order(plan)? {
+            if matches!(plan.tasks[index].status, TaskStatus::Completed) {
+                continue;
+            }
+            clock.update(&mut outcome.usage);
+
+            if self.stop.as_ref().is_some_and(|stop| *stop.borrow()) {
+                tracing::info!("Stopping plan {} with {} tasks left", plan.id, plan.tasks.len() - outcome.outputs.len());
+                outcome.state = ExecutionState::Cancelled;
+                return Ok(outcome);
+            }
+            if let Some(limit) = plan.budget.exceeded_by(&outcome.usage) {
+                tracing::warn!("Pausing plan {} before task '{}': {:?} budget reached", plan.id, plan.tasks[index].name, limit);
+                self.publish(PlanEvent::BudgetExceeded { plan_id: plan.id, limit, usage: outcome.usage.clone() });
+                outcome.state = ExecutionState::BudgetExceeded { limit };
+                return Ok(outcome);
+            }
+            match self.approval_gate(plan, index) {
+                Gate::Proceed => {}
+                Gate::Await => {
+                    let task = &mut plan.tasks[index];
+                    tracing::info!("Pausing plan {} until task '{}' is approved", plan.id, task.name);
+                    task.status = TaskStatus::WaitingApproval;
+                    outcome.state = ExecutionState::AwaitingApproval { task_id: task.id };
+                    return Ok(outcome);
+                }
+                Gate::Deny(error) => {
+                    plan.tasks[index].status = TaskStatus::Failed;
+                    let error = self.roll_back(plan, &mut outcome, error).await;
+                    outcome.state = ExecutionState::Failed { error };
+                    return Ok(outcome);
+                }
+            }
+
+            let max_retries = plan.budget.max_task_retries;
+            let task = &mut plan.tasks[index];
+            task.status = TaskStatus::InProgress;
+            tracing::info!("Running task {} ({})", task.name, task.id);
+            let span = tracing::info_span!("run_task", plan_id = %plan.id, task_id = %task.id, agent_id = %task.agent_type);
+            self.publish(PlanEvent::TaskStarted { plan_id: plan.id, task_id: task.id });
+
+            let error = loop {
+                let result = self.run_task(plan.id, task, &mut outcome.mismatches).instrument(span.clone()).await?
+                    .and_then(|output| contract::enforce(task, output).map_err(Into::into));
+                self.audit(plan.id, task, &result);
+                match result {
+                    Ok(output) => {
+                        outcome.usage.add(&output.usage);
+                        task.status = output.status.clone();
+                        if matches!(output.status, TaskStatus::Completed) {
+                            let outputs = match &self.artifacts {
+                                Some(artifacts) => artifacts.externalize_all(output.outputs, Some(plan.id), Some(task.id)).await?,
+                                None => output.outputs,
+                            };
+                            outcome.outputs.insert(task.id, outputs);
+                            break None;
+                        }
+                        break Some(format!("Task '{}' ended as {:?}", task.name, output.status));
+                    }
+                    Err(e) if outcome.usage.task_retries < max_retries => {
+                        outcome.usage.task_retries += 1;
+                        tracing::warn!("Retrying task '{}' after: {}", task.name, e);
+                    }
+                    Err(e) => {
+                        task.status = TaskStatus::Failed;
+                        break Some(format!("Task '{}' failed: {}", task.name, e));
+                    }
+                }
+            };
+
+            clock.update(&mut outcome.usage);
+            self.publish(PlanEvent::TaskFinished {
+                plan_id: plan.id,
+                task_id: task.id,
+                status: task.status.clone(),
+                usage: outcome.usage.clone(),
+            });
+            if let Some(error) = error {
+                outcome.state = ExecutionState::Failed { error };
+                return Ok(outcome);
+            }
+        }
+
+        clock.update(&mut outcome.usage);
+        outcome.state = ExecutionState::Completed;
+        Ok(outcome)
+    }
+
+    /// Whether the task at `index` may run under the approval policy, if there is one
+    fn approval_gate(&self, plan: &IntentExecutionPlan, index: usize) -> Gate {
+        let Some((policy, ledger)) = &self.approvals else {
+            return Gate::Proceed;
+        };
+        let task = &plan.tasks[index];
+        let decision = policy.evaluate(plan, task);
+        let rule = decision.rule.as_deref().unwrap_or("default");
+        if let Some(auditor) = &self.auditor {
+            auditor.record(AuditEvent::new(
+                AuditActor::system(),
+                AuditAction::ApprovalEvaluation,
+                format!("plan:{}/task:{}", plan.id, task.id),
+                &serde_json::json!({ "name": task.name, "decision": decision }),
+                AuditOutcome::Success,
+            ));
+        }
+
+        match decision.effect {
+            ApprovalEffect::AutoApprove => Gate::Proceed,
+            ApprovalEffect::Deny => Gate::Deny(format!("Task '{}' denied by approval rule '{}'", task.name, rule)),
+            ApprovalEffect::RequireApproval { approver_groups, min_approvers } => match ledger.status(task.id) {
+                Some(ApprovalStatus::Released) => {
+                    ledger.remove(task.id);
+                    Gate::Proceed
+                }
+                Some(ApprovalStatus::Rejected { by, reason }) => {
+                    ledger.remove(task.id);
+                    let reason = reason.map(|reason| format!(": {}", reason)).unwrap_or_default();
+                    Gate::Deny(format!("Task '{}' rejected by {}{}", task.name, by, reason))
+                }
+                _ => {
+                    ledger.request(plan.id, task, decision.rule, approver_groups, min_approvers);
+                    Gate::Await
+                }
+            },
+        }
+    }
+
+    /// Run the plan's rollback steps in order, stopping at the first that fails. Returns
+    /// `error` with any rollback failure added.
+    async fn roll_back(&self, plan: &IntentExecutionPlan, outcome: &mut PlanOutcome, error: String) -> String {
+        let Some(rollback) = &plan.rollback_plan else {
+            return error;
+        };
+        tracing::warn!("Rolling back plan {}: {}", plan.id, error);
+        for step in &rollback.steps {
+            if let Err(e) = self.runner.rollback(step).await {
+                return format!("{}; rollback step '{}' failed: {}", error, step.description, e);
+            }
+            outcome.rolled_back.push(step.id);
+        }
+        error
+    }
+
+    fn audit(&self, plan_id: Uuid, task: &ExecutionTask, result: &Result<TaskOutput>) {
+        let Some(auditor) = &self.auditor else {
+            return;
+        };
+        let outcome = match result {
+            Ok(output) if !matches!(output.status, TaskStatus::Completed) => {
+                AuditOutcome::Failure { error: format!("Task ended as {:?}", output.status) }
+            }
+            result => AuditOutcome::of(result),
+        };
+        let parameters = serde_json::json!({ "name": task.name, "task_type": task.task_type, "inputs": task.inputs });
+        auditor.record(AuditEvent::new(
+            AuditActor::agent(&task.agent_type),
+            AuditAction::TaskDispatch,
+            format!("plan:{}/task:{}", plan_id, task.id),
+            &parameters,
+            outcome,
+        ));
+    }
+
+    fn publish(&self, event: PlanEvent) {
+        if let Some(events) = &self.events {
+            // No subscribers is fine
+            let _ = events.send(event);
+        }
+    }
+
+    /// Run one task according to the executor's mode. The outer error is for failures of
+    /// the recording itself; the inner one is the task's own.
+    async fn run_task(&self, plan_id: Uuid, task: &ExecutionTask, mismatches: &mut Vec<ReplayMismatch>) -> Result<Result<TaskOutput>> {
+        let attempt = self.attempts.next(task.id);
+        match &self.mode {
+            ReplayMode::Live => Ok(self.run_live(plan_id, task).await),
+            ReplayMode::Record { path, bundle } => {
+                let result = self.run_live(plan_id, task).await;
+                let snapshot = {
+                    let mut bundle = bundle.lock().unwrap();
+                    bundle.record(task, attempt, &result);
+                    bundle.clone()
+                };
+                snapshot.save(path)?;
+                Ok(result)
+            }
+            ReplayMode::Replay { bundle, policy } => match bundle.lookup(task, attempt) {
+                Ok(recorded) => Ok(recorded),
+                Err(mismatch) => {
+                    tracing::warn!("{}", mismatch);
+                    let result = match policy {
+                        MismatchPolicy::Fail => Err(anyhow!("{}", mismatch)),
+                        MismatchPolicy::RunLive => self.run_live(plan_id, task).await,
+                    };
+                    mismatches.push(mismatch);
+                    Ok(result)
+                }
+            },
+        }
+    }
+}
+
+impl<R: TaskRunner> PlanExecutor<R> {
+    /// Run `task` with the runner, publishing the output it streams when anyone listens
+    async fn run_live(&self, plan_id: Uuid, task: &ExecutionTask) -> Result<TaskOutput> {
+        let Some(events) = &self.events else {
+            return self.runner.run(task).await;
+        };
+        let (deltas, mut streamed) = mpsc::unbounded_channel();
+        let forward = async {
+            while let Some(delta) = streamed.recv().await {
+                let _ = events.send(PlanEvent::TaskOutputDelta { plan_id, task_id: task.id, delta });
+            }
+        };
+        // The runner drops its sender when done, which ends the forwarding
+        let (result, ()) = tokio::join!(self.runner.run_streaming(task, deltas), forward);
+        result
+    }
+}
+
+/// What the approval policy lets the executor do with a task
+enum Gate {
+    Proceed,
+    Await,
+    Deny(String),
+}
+
+/// Wall time of a run, on top of whatever earlier runs of the plan used
+struct WallClock {
+    started: Instant,
+    before: Duration,
+}
+
+impl WallClock {
+    fn start(usage: &BudgetUsage) -> Self {
+        Self { started: Instant::now(), before: usage.wall_time }
+    }
+
+    fn update(&self, usage: &mut BudgetUsage) {
+        usage.wall_time = self.before + Duration::from_std(self.started.elapsed()).unwrap_or(Duration::MAX);
+    }
+}
+
+/// Indices of the plan's tasks with every task after those it depends on, otherwise
+/// keeping plan order
+fn execution_order(plan: &IntentExecutionPlan) -> Result<Vec<usize>> {
+    let position: HashMap<Uuid, usize> = plan.tasks.iter().enumerate().map(|(i, t)| (t.id, i)).collect();
+    let mut blockers = vec![0usize; plan.tasks.len()];
+    for dependency in &plan.dependencies {
+        let to = *position.get(&dependency.to_task)
+            .ok_or_else(|| anyhow!("Dependency refers to unknown task {}", dependency.to_task))?;
+        if !position.contains_key(&dependency.from_task) {
+            return Err(anyhow!("Dependency refers to unknown task {}", dependency.from_task));
+        }
+        blockers[to] += 1;
+    }
+
+    let mut order = Vec::with_capacity(plan.tasks.len());
+    while order.len() < plan.tasks.len() {
+        let next = (0..plan.tasks.len())
+            .find(|i| blockers[*i] == 0 && !order.contains(i))
+            .ok_or_else(|| anyhow!("Plan dependencies contain a cycle"))?;
+        order.push(next);
+        for dependency in plan.dependencies.iter().filter(|d| d.from_task == plan.tasks[next].id) {
+            blockers[position[&dependency.to_task]] -= 1;
+        }
+    }
+    Ok(order)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::artifacts::{ArtifactRef, ArtifactStore, FsArtifactStore};
+    use crate::budget::PlanBudget;
+    use std::sync::Arc;
+    use crate::{DependencyType, TaskDependency, TaskType};
+    use chrono::{Duration, Utc};
+    use std::sync::Mutex;
+
+    /// Records the tasks it runs and the rollback steps; fails tasks named `broken`, and
+    /// `flaky` on its first run. Each expected output is the JSON text `{"task":"<name>"}`.
+    #[derive(Default)]
+    struct RecordingRunner {
+        ran: Mutex<Vec<String>>,
+        rolled_back: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl TaskRunner for RecordingRunner {
+        async fn validate(&self, task: &ExecutionTask) -> Result<()> {
+            match task.agent_type.as_str() {
+                "unknown-agent" => Err(anyhow!("no agent for {}", task.agent_type)),
+                _ => Ok(()),
+            }
+        }
+
+        async fn run(&self, task: &ExecutionTask) -> Result<TaskOutput> {
+            let first_run = {
+                let mut ran = self.ran.lock().unwrap();
+                ran.push(task.name.clone());
+                ran.iter().filter(|name| **name == task.name).count() == 1
+            };
+            if task.name == "broken" || (task.name == "flaky" && first_run) {
+                return Err(anyhow!("exploded"));
+            }
+            let outputs = task.expected_outputs.iter()
+                .map(|name| (name.clone(), serde_json::json!(task_json(&task.name))))
+                .collect();
+            Ok(TaskOutput { status: TaskStatus::Completed, outputs, usage: TaskUsage::default() })
+        }
+
+        async fn rollback(&self, step: &RollbackStep) -> Result<()> {
+            self.rolled_back.lock().unwrap().push(step.command.clone());
+            Ok(())
+        }
+    }
+
+    fn task_json(name: &str) -> String {
+        serde_json::json!({ "task": name }).to_string()
+    }
+
+    fn task(name: &str, agent_type: &str) -> ExecutionTask {
+        ExecutionTask {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            description: String::new(),
+            task_type: TaskType::Execute,
+            agent_type: agent_type.to_string(),
+            inputs: HashMap::new(),
+            expected_outputs: vec![format!("{}.json", name)],
+            estimated_duration: Duration::minutes(1),
+            status: TaskStatus::Pending,
+            dry_run_first: false,
+            tags: Vec::new(),
+            output_schemas: HashMap::new(),
+        }
+    }
+
+    fn plan(tasks: Vec<ExecutionTask>, dependencies: Vec<(usize, usize)>) -> IntentExecutionPlan {
+        let dependencies = dependencies.into_iter()
+            .map(|(from, to)| TaskDependency {
+                from_task: tasks[from].id,
+                to_task: tasks[to].id,
+                dependency_type: DependencyType::Sequential,
+            })
+            .collect();
+        IntentExecutionPlan {
+            id: Uuid::new_v4(),
+            intent_id: Uuid::new_v4(),
+            tasks,
+            dependencies,
+            estimated_duration: Duration::minutes(2),
+            autonomy_tier: 3,
+            checkpoints: Vec::new(),
+            rollback_plan: None,
+            budget: PlanBudget::default(),
+            domain: None,
+            risk_level: None,
+            environment: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_runs_tasks_after_their_dependencies() {
+        let executor = PlanExecutor::new(RecordingRunner::default());
+        let mut plan = plan(vec![task("deploy", "a"), task("build", "a"), task("notify", "a")], vec![(1, 0)]);
+
+        let outcome = executor.execute(&mut plan).await.unwrap();
+        assert_eq!(outcome.state, ExecutionState::Completed);
+        assert_eq!(*executor.runner.ran.lock().unwrap(), vec!["build", "deploy", "notify"]);
+        assert_eq!(outcome.outputs[&plan.tasks[0].id]["deploy.json"], task_json("deploy"));
+        assert!(plan.tasks.iter().all(|t| matches!(t.status, TaskStatus::Completed)));
+    }
+
+    #[tokio::test]
+    async fn test_stops_at_first_failure() {
+        let executor = PlanExecutor::new(RecordingRunner::default());
+        let mut plan = plan(vec![task("build", "a"), task("broken", "a"), task("deploy", "a")], vec![]);
+
+        let outcome = executor.execute(&mut plan).await.unwrap();
+        assert_eq!(outcome.state, ExecutionState::Failed { error: "Task 'broken' failed: exploded".to_string() });
+        assert!(matches!(plan.tasks[1].status, TaskStatus::Failed));
+        assert!(matches!(plan.tasks[2].status, TaskStatus::Pending));
+        assert_eq!(outcome.outputs.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_invalid_plans_before_running_anything() {
+        let executor = PlanExecutor::new(RecordingRunner::default());
+
+        let mut unmappable = plan(vec![task("build", "a"), task("mystery", "unknown-agent")], vec![]);
+        let err = executor.execute(&mut unmappable).await.unwrap_err();
+        assert_eq!(err.to_string(), "Task 'mystery' cannot be executed: no agent for unknown-agent");
+
+        let mut cyclic = plan(vec![task("a", "a"), task("b", "a")], vec![(0, 1), (1, 0)]);
+        assert!(executor.execute(&mut cyclic).await.unwrap_err().to_string().contains("cycle"));
+
+        assert!(executor.runner.ran.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_refuses_plans_for_environments_outside_the_allowlist() {
+        let executor = PlanExecutor::new(RecordingRunner::default())
+            .with_allowed_environments(vec![Environment::Dev, Environment::Custom("qa".to_string())]);
+
+        let mut production = plan(vec![task("deploy", "a")], vec![]);
+        production.environment = Some(Environment::Production);
+        let err = executor.execute(&mut production).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("Plan {} targets the production environment; this executor only runs plans for: dev, qa", production.id)
+        );
+        assert!(executor.runner.ran.lock().unwrap().is_empty());
+        assert!(matches!(production.tasks[0].status, TaskStatus::Pending));
+
+        let mut qa = plan(vec![task("deploy", "a")], vec![]);
+        qa.environment = Some(Environment::Custom("qa".to_string()));
+        assert_eq!(executor.execute(&mut qa).await.unwrap().state, ExecutionState::Completed);
+        let mut untargeted = plan(vec![task("notify", "a")], vec![]);
+        assert_eq!(executor.execute(&mut untargeted).await.unwrap().state, ExecutionState::Completed);
+    }
+
+    #[tokio::test]
+    async fn test_stop_signal_cancels_before_the_next_task() {
+        let (stop, signal) = watch::channel(false);
+        let executor = PlanExecutor::new(RecordingRunner::default()).with_stop_signal(signal);
+        let mut plan = plan(vec![task("build", "a"), task("deploy", "a")], vec![(0, 1)]);
+
+        stop.send(true).unwrap();
+        let outcome = executor.execute(&mut plan).await.unwrap();
+        assert_eq!(outcome.state, ExecutionState::Cancelled);
+        assert!(executor.runner.ran.lock().unwrap().is_empty());
+        assert!(plan.tasks.iter().all(|t| matches!(t.status, TaskStatus::Pending)));
+    }
+
+    /// Reports every task as costing `cost_usd` and 1000 tokens
+    struct CostlyRunner {
+        inner: RecordingRunner,
+        cost_usd: f64,
+    }
+
+    #[async_trait]
+    impl TaskRunner for CostlyRunner {
+        async fn validate(&self, task: &ExecutionTask) -> Result<()> {
+            self.inner.validate(task).await
+        }
+
+        async fn run(&self, task: &ExecutionTask) -> Result<TaskOutput> {
+            let mut output = self.inner.run(task).await?;
+            output.usage = TaskUsage { llm_tokens: 1000, cost_usd: self.cost_usd };
+            Ok(output)
+        }
+    }
+
+    /// Streams each task's name word by word
+    struct StreamingRunner;
+
+    #[async_trait]
+    impl TaskRunner for StreamingRunner {
+        async fn validate(&self, _task: &ExecutionTask) -> Result<()> {
+            Ok(())
+        }
+
+        async fn run(&self, task: &ExecutionTask) -> Result<TaskOutput> {
+            RecordingRunner::default().run(task).await
+        }
+
+        async fn run_streaming(&self, task: &ExecutionTask, deltas: mpsc::UnboundedSender<String>) -> Result<TaskOutput> {
+            for word in task.name.split('-') {
+                deltas.send(word.to_string())?;
+            }
+            self.run(task).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_streamed_output_is_published_between_start_and_finish() {
+        let (events, mut received) = broadcast::channel(16);
+        let executor = PlanExecutor::new(StreamingRunner).with_events(events);
+        let mut plan = plan(vec![task("draft-the-summary", "a")], vec![]);
+        executor.execute(&mut plan).await.unwrap();
+
+        let mut seen = Vec::new();
+        while let Ok(event) = received.try_recv() {
+            seen.push(match event {
+                PlanEvent::TaskStarted { .. } => "started".to_string(),
+                PlanEvent::TaskOutputDelta { delta, .. } => delta,
+                PlanEvent::TaskFinished { .. } => "finished".to_string(),
+                PlanEvent::BudgetExceeded { .. } => "paused".to_string(),
+            });
+        }
+        assert_eq!(seen, ["started", "draft", "the", "summary", "finished"]);
+    }
+
+    #[tokio::test]
+    async fn test_budget_pauses_plan_at_task_boundary() {
+        let (events, mut received) = broadcast::channel(16);
+        let executor = PlanExecutor::new(CostlyRunner { inner: RecordingRunner::default(), cost_usd: 3.0 })
+            .with_events(events);
+        let mut plan = plan(vec![task("a", "a"), task("b", "a"), task("c", "a"), task("d", "a")], vec![]);
+        plan.budget.max_cost_usd = Some(5.0);
+
+        let paused = executor.execute(&mut plan).await.unwrap();
+        assert_eq!(paused.state, ExecutionState::BudgetExceeded { limit: BudgetLimit::Cost });
+        assert_eq!(*executor.runner.inner.ran.lock().unwrap(), vec!["a", "b"]);
+        assert!(matches!(plan.tasks[2].status, TaskStatus::Pending));
+        assert_eq!((paused.usage.cost_usd, paused.usage.llm_tokens), (6.0, 2000));
+
+        let mut seen = Vec::new();
+        while let Ok(event) = received.try_recv() {
+            seen.push(event);
+        }
+        assert_eq!(seen.len(), 5);
+        assert!(matches!(&seen[2], PlanEvent::TaskStarted { task_id, .. } if *task_id == plan.tasks[1].id));
+        assert!(matches!(&seen[3], PlanEvent::TaskFinished { task_id, usage, .. } if *task_id == plan.tasks[1].id && usage.cost_usd == 6.0));
+        assert!(matches!(&seen[4], PlanEvent::BudgetExceeded { limit: BudgetLimit::Cost, .. }));
+
+        // Resuming without approval of a bigger budget pauses straight away
+        let paused = executor.resume(&mut plan, paused).await.unwrap();
+        assert!(matches!(paused.state, ExecutionState::BudgetExceeded { .. }));
+        assert_eq!(executor.runner.inner.ran.lock().unwrap().len(), 2);
+
+        plan.budget.max_cost_usd = Some(20.0);
+        let finished = executor.resume(&mut plan, paused).await.unwrap();
+        assert_eq!(finished.state, ExecutionState::Completed);
+        assert_eq!(*executor.runner.inner.ran.lock().unwrap(), vec!["a", "b", "c", "d"]);
+        assert_eq!((finished.usage.cost_usd, finished.usage.llm_tokens), (12.0, 4000));
+        assert_eq!(finished.outputs.len(), 4);
+
+        assert!(executor.resume(&mut plan, finished).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_failed_tasks_retry_within_budget() {
+        let executor = PlanExecutor::new(RecordingRunner::default());
+        let mut plan = plan(vec![task("flaky", "a"), task("broken", "a")], vec![]);
+        plan.budget.max_task_retries = 2;
+
+        let outcome = executor.execute(&mut plan).await.unwrap();
+        assert_eq!(*executor.runner.ran.lock().unwrap(), vec!["flaky", "flaky", "broken", "broken"]);
+        assert!(matches!(plan.tasks[0].status, TaskStatus::Completed));
+        assert_eq!(outcome.state, ExecutionState::Failed { error: "Task 'broken' failed: exploded".to_string() });
+        assert_eq!(outcome.usage.task_retries, 2);
+    }
+
+    #[tokio::test]
+    async fn test_large_outputs_are_stored_as_plan_artifacts() {
+        let root = std::env::temp_dir().join(format!("plan-artifacts-{}", Uuid::new_v4()));
+        let store: Arc<dyn ArtifactStore> = Arc::new(FsArtifactStore::new(&root));
+        let executor = PlanExecutor::new(RecordingRunner::default())
+            .with_artifacts(Externalizer::new(store.clone()).with_inline_threshold(16));
+        let mut plan = plan(vec![task("build", "a"), task("deploy-everything", "a")], vec![(0, 1)]);
+
+        let outcome = executor.execute(&mut plan).await.unwrap();
+        assert_eq!(outcome.state, ExecutionState::Completed);
+        // "build" fits inline, "deploy-everything" does not
+        assert_eq!(outcome.outputs[&plan.tasks[0].id]["build.json"], task_json("build"));
+        let output = &outcome.outputs[&plan.tasks[1].id]["deploy-everything.json"];
+        let reference = ArtifactRef::from_value(output).unwrap();
+        assert_eq!(reference.name, "deploy-everything.json");
+
+        let stored = store.list_by_plan(plan.id).await.unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].task_id, Some(plan.tasks[1].id));
+        assert_eq!(store.get(reference.id).await.unwrap().bytes, task_json("deploy-everything").as_bytes());
+        std::fs::remove_dir_all(root).ok();
+    }
+
+    fn bundle_path() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("replay-{}.json", Uuid::new_v4()))
+    }
+
+    fn reset(plan: &mut IntentExecutionPlan) {
+        plan.tasks.iter_mut().for_each(|t| t.status = TaskStatus::Pending);
+    }
+
+    #[tokio::test]
+    async fn test_replay_repeats_recorded_runs_without_the_runner() {
+        let path = bundle_path();
+        let mut plan = plan(vec![task("build", "a"), task("deploy", "a"), task("broken", "a")], vec![(0, 1)]);
+        let recorder = PlanExecutor::new(RecordingRunner::default()).with_recording(&path);
+        let recorded = recorder.execute(&mut plan.clone()).await.unwrap();
+
+        let bundle = ReplayBundle::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(bundle.runs.len(), 3);
+
+        let replayer = PlanExecutor::new(RecordingRunner::default()).with_replay(bundle, MismatchPolicy::Fail);
+        let replayed = replayer.execute(&mut plan).await.unwrap();
+        assert!(replayer.runner.ran.lock().unwrap().is_empty());
+        assert_eq!(replayed.state, recorded.state);
+        assert_eq!(replayed.state, ExecutionState::Failed { error: "Task 'broken' failed: exploded".to_string() });
+        assert_eq!(replayed.outputs[&plan.tasks[1].id]["deploy.json"], task_json("deploy"));
+        assert!(replayed.mismatches.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_replay_reports_mutated_task_input() {
+        let path = bundle_path();
+        let mut plan = plan(vec![task("build", "a"), task("deploy", "a")], vec![(0, 1)]);
+        PlanExecutor::new(RecordingRunner::default()).with_recording(&path).execute(&mut plan.clone()).await.unwrap();
+        let bundle = ReplayBundle::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        plan.tasks[1].inputs.insert("version".to_string(), serde_json::json!("2.0"));
+
+        let strict = PlanExecutor::new(RecordingRunner::default()).with_replay(bundle.clone(), MismatchPolicy::Fail);
+        let outcome = strict.execute(&mut plan).await.unwrap();
+        assert!(strict.runner.ran.lock().unwrap().is_empty());
+        assert!(matches!(plan.tasks[0].status, TaskStatus::Completed));
+        assert!(matches!(plan.tasks[1].status, TaskStatus::Failed));
+        assert_eq!(outcome.mismatches.len(), 1);
+        assert!(matches!(&outcome.mismatches[0], ReplayMismatch::InputChanged { task_name, .. } if task_name == "deploy"));
+        assert!(matches!(outcome.state, ExecutionState::Failed { error } if error.contains("input changed since it was recorded")));
+
+        reset(&mut plan);
+        plan.tasks.push(task("notify", "a"));
+        let lenient = PlanExecutor::new(RecordingRunner::default()).with_replay(bundle, MismatchPolicy::RunLive);
+        let outcome = lenient.execute(&mut plan).await.unwrap();
+        assert_eq!(outcome.state, ExecutionState::Completed);
+        assert_eq!(*lenient.runner.ran.lock().unwrap(), vec!["deploy", "notify"]);
+        assert!(matches!(&outcome.mismatches[1], ReplayMismatch::Missing { task_name, attempt: 1, .. } if task_name == "notify"));
+    }
+
+    #[tokio::test]
+    async fn test_failed_plan_is_revised_around_completed_tasks() {
+        let mut rebuild = task("rebuild", "a");
+        rebuild.estimated_duration = Duration::minutes(3);
+        let planner = AdaptivePlanner::new().with_fallbacks("broken", vec![task("repair", "a"), rebuild]);
+        let executor = PlanExecutor::new(RecordingRunner::default()).with_replanning(planner);
+        let mut plan = plan(
+            vec![task("build", "a"), task("broken", "a"), task("deploy", "a"), task("notify", "a")],
+            vec![(0, 1), (1, 2), (2, 3)],
+        );
+        plan.estimated_duration = Duration::minutes(4);
+
+        let outcome = executor.execute(&mut plan).await.unwrap();
+        assert!(matches!(outcome.state, ExecutionState::Failed { .. }));
+        let mut revision = executor.request_replan(&plan, &outcome).unwrap();
+        assert_eq!(revision.replaces, plan.id);
+
+        // Task 1 is done and passes its output on; the fallbacks take task 2's place
+        let names: Vec<&str> = revision.plan.tasks.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["repair", "rebuild", "deploy", "notify"]);
+        assert_eq!(revision.plan.tasks[0].inputs["build.json"], task_json("build"));
+        assert_eq!(revision.plan.tasks[1].inputs["build.json"], task_json("build"));
+        assert_eq!((revision.plan.tasks[2].id, revision.plan.tasks[3].id), (plan.tasks[2].id, plan.tasks[3].id));
+        assert_eq!(revision.plan.estimated_duration, Duration::minutes(6));
+        assert_eq!(
+            revision.diff.to_string(),
+            "- build (completed)\n- broken (replaced)\n+ repair [a, 1m]\n+ rebuild [a, 3m]\n\
+             ~ deploy: now runs after 'rebuild' instead of 'broken'\nEstimated duration: +2m",
+        );
+
+        let outcome = executor.execute(&mut revision.plan).await.unwrap();
+        assert_eq!(outcome.state, ExecutionState::Completed);
+        assert_eq!(*executor.runner.ran.lock().unwrap(), vec!["build", "broken", "repair", "rebuild", "deploy", "notify"]);
+
+        let unplanned = PlanExecutor::new(RecordingRunner::default());
+        assert!(unplanned.request_replan(&plan, &outcome).unwrap_err().to_string().contains("No planner"));
+    }
+
+    /// Completes every task with the same outputs
+    struct FixedRunner(HashMap<String, serde_json::Value>);
+
+    #[async_trait]
+    impl TaskRunner for FixedRunner {
+        async fn validate(&self, _task: &ExecutionTask) -> Result<()> {
+            Ok(())
+        }
+
+        async fn run(&self, _task: &ExecutionTask) -> Result<TaskOutput> {
+            Ok(TaskOutput { status: TaskStatus::Completed, outputs: self.0.clone(), usage: TaskUsage::default() })
+        }
+    }
+
+    fn deploy_task() -> ExecutionTask {
+        let mut deploy = task("deploy", "a");
+        deploy.expected_outputs = vec!["deployment-plan.yaml".to_string(), "health-check.json".to_string()];
+        deploy
+    }
+
+    #[tokio::test]
+    async fn test_missing_outputs_fail_the_task() {
+        let executor = PlanExecutor::new(FixedRunner(HashMap::from([
+            ("deployment-plan.yaml".to_string(), serde_json::json!("replicas: 3\n")),
+        ])));
+        let mut plan = plan(vec![deploy_task(), task("notify", "a")], vec![(0, 1)]);
+
+        let outcome = executor.execute(&mut plan).await.unwrap();
+        assert_eq!(outcome.state, ExecutionState::Failed {
+            error: "Task 'deploy' failed: missing output 'health-check.json'".to_string(),
+        });
+        assert!(matches!(plan.tasks[0].status, TaskStatus::Failed));
+        assert!(matches!(plan.tasks[1].status, TaskStatus::Pending));
+        assert!(outcome.outputs.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_outputs_that_do_not_parse_or_match_their_schema_fail_the_task() {
+        let runner = |health: serde_json::Value| FixedRunner(HashMap::from([
+            ("deployment-plan.yaml".to_string(), serde_json::json!({ "replicas": 3 })),
+            ("health-check.json".to_string(), health),
+        ]));
+
+        let mut unparsable = plan(vec![deploy_task()], vec![]);
+        let outcome = PlanExecutor::new(runner(serde_json::json!("{\"healthy\": tru"))).execute(&mut unparsable).await.unwrap();
+        assert!(matches!(
+            &outcome.state,
+            ExecutionState::Failed { error } if error.starts_with("Task 'deploy' failed: invalid output 'health-check.json': not valid JSON")
+        ), "{:?}", outcome.state);
+        assert!(matches!(unparsable.tasks[0].status, TaskStatus::Failed));
+
+        // A failed contract is retried like any other failure, and the plan can be revised
+        let mut mismatched = plan(vec![deploy_task()], vec![]);
+        mismatched.budget.max_task_retries = 1;
+        mismatched.tasks[0].output_schemas.insert(
+            "health-check.json".to_string(),
+            serde_json::json!({ "type": "object", "required": ["healthy"] }),
+        );
+        let executor = PlanExecutor::new(runner(serde_json::json!("{\"status\": \"ok\"}")))
+            .with_replanning(AdaptivePlanner::new());
+        let outcome = executor.execute(&mut mismatched).await.unwrap();
+        assert_eq!(outcome.usage.task_retries, 1);
+        assert!(matches!(
+            &outcome.state,
+            ExecutionState::Failed { error } if error.contains("invalid output 'health-check.json': does not match its schema")
+        ), "{:?}", outcome.state);
+        assert!(executor.request_replan(&mismatched, &outcome).is_ok());
+
+        let mut valid = plan(vec![deploy_task()], vec![]);
+        valid.tasks[0].output_schemas.insert("health-check.json".to_string(), serde_json::json!({ "type": "object" }));
+        let outcome = PlanExecutor::new(runner(serde_json::json!("{\"healthy\": true}"))).execute(&mut valid).await.unwrap();
+        assert_eq!(outcome.state, ExecutionState::Completed);
+    }
+
+    fn approval_policy() -> ApprovalPolicy {
+        ApprovalPolicy::from_json(r#"{
+            "rules": [
+                { "name": "no-wipes", "when": { "tags": ["destructive"] }, "effect": "deny" },
+                { "name": "deploys", "when": { "agent_type": ["deployer"] },
+                  "effect": "require_approval", "approver_groups": ["sre"], "min_approvers": 2 }
+            ]
+        }"#).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_plan_waits_for_enough_distinct_approvers() {
+        let path = std::env::temp_dir().join(format!("approval-audit-{}.jsonl", Uuid::new_v4()));
+        let auditor = Auditor::spawn(Arc::new(crate::audit::JsonlAuditSink::new(&path)), 16);
+        let ledger = ApprovalLedger::new();
+        let executor = PlanExecutor::new(RecordingRunner::default())
+            .with_approval_policy(approval_policy(), ledger.clone())
+            .with_auditor(auditor.clone());
+        let mut plan = plan(vec![task("build", "builder"), task("deploy", "deployer"), task("notify", "a")], vec![(0, 1)]);
+        let deploy = plan.tasks[1].id;
+
+        let paused = executor.execute(&mut plan).await.unwrap();
+        assert_eq!(paused.state, ExecutionState::AwaitingApproval { task_id: deploy });
+        assert!(matches!(plan.tasks[1].status, TaskStatus::WaitingApproval));
+        assert_eq!(*executor.runner.ran.lock().unwrap(), vec!["build"]);
+        assert_eq!(ledger.get(deploy).unwrap().rule.as_deref(), Some("deploys"));
+
+        // One approval, even given twice, is not enough
+        let sre = ["sre".to_string()];
+        ledger.approve(deploy, "ada", &sre).unwrap();
+        ledger.approve(deploy, "ada", &sre).unwrap();
+        let paused = executor.resume(&mut plan, paused).await.unwrap();
+        assert!(matches!(paused.state, ExecutionState::AwaitingApproval { .. }));
+        assert_eq!(executor.runner.ran.lock().unwrap().len(), 1);
+
+        assert_eq!(ledger.approve(deploy, "bob", &sre), Ok(ApprovalStatus::Released));
+        let finished = executor.resume(&mut plan, paused).await.unwrap();
+        assert_eq!(finished.state, ExecutionState::Completed);
+        assert_eq!(*executor.runner.ran.lock().unwrap(), vec!["build", "deploy", "notify"]);
+        assert!(ledger.get(deploy).is_none());
+
+        // Every evaluation is audited with the rule that decided it
+        auditor.flush().await;
+        let filter = crate::audit::AuditFilter { action: Some(AuditAction::ApprovalEvaluation), ..Default::default() };
+        let evaluations = auditor.query(&filter).await.unwrap();
+        std::fs::remove_file(path).ok();
+        // build, deploy three times, notify; newest first
+        assert_eq!(evaluations.len(), 5);
+        assert_eq!(evaluations[1].target, format!("plan:{}/task:{}", plan.id, deploy));
+        assert_eq!(evaluations[1].parameters_digest["decision"]["rule"], "deploys");
+        assert_eq!(evaluations[0].parameters_digest["decision"]["rule"], serde_json::Value::Null);
+    }
+
+    #[tokio::test]
+    async fn test_denied_task_aborts_the_plan_and_rolls_it_back() {
+        let executor = PlanExecutor::new(RecordingRunner::default())
+            .with_approval_policy(approval_policy(), ApprovalLedger::new());
+        let mut wipe = task("wipe", "deployer");
+        wipe.tags = vec!["destructive".to_string()];
+        let mut plan = plan(vec![task("backup", "a"), wipe, task("notify", "a")], vec![(0, 1)]);
+        let step = |command: &str| RollbackStep {
+            id: Uuid::new_v4(),
+            description: command.to_string(),
+            command: command.to_string(),
+            verification: String::new(),
+        };
+        plan.rollback_plan = Some(crate::RollbackPlan {
+            steps: vec![step("restore backup"), step("unlock writes")],
+            auto_trigger_conditions: Vec::new(),
+        });
+
+        let outcome = executor.execute(&mut plan).await.unwrap();
+        assert_eq!(outcome.state, ExecutionState::Failed { error: "Task 'wipe' denied by approval rule 'no-wipes'".to_string() });
+        assert!(matches!(plan.tasks[1].status, TaskStatus::Failed));
+        assert!(matches!(plan.tasks[2].status, TaskStatus::Pending));
+        assert_eq!(*executor.runner.ran.lock().unwrap(), vec!["backup"]);
+        assert_eq!(*executor.runner.rolled_back.lock().unwrap(), vec!["restore backup", "unlock writes"]);
+        assert_eq!(outcome.rolled_back.len(), 2);
+    }
+}
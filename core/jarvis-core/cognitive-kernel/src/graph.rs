@@ -0,0 +1,468 @@
+//! Plan dependency graphs as DOT or as a JSON node-link document
+//!
+//! [`IntentExecutionPlan::export_graph`] draws a plan's tasks as nodes, filled by
+//! [`TaskType`] and outlined by [`TaskStatus`], and its dependencies as edges from the
+//! task that must finish first, labelled with their [`DependencyType`]. The DOT helpers
+//! here are shared with the memory continuum's association graph export.
+//!
+//! Exports keep at most a node cap of nodes, [`DEFAULT_MAX_NODES`] unless set, and say
+//! how many they left out rather than growing without bound.
+
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{DependencyType, IntentExecutionPlan, TaskStatus, TaskType};
+
+/// Most nodes an export keeps unless told otherwise
+pub const DEFAULT_MAX_NODES: usize = 500;
+
+/// How a graph is written out
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GraphFormat {
+    /// Graphviz DOT
+    Dot,
+    /// A node-link document with `nodes` and `links`
+    #[default]
+    Json,
+}
+
+impl FromStr for GraphFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "dot" => Ok(GraphFormat::Dot),
+            "json" => Ok(GraphFormat::Json),
+            other => Err(format!("Unknown graph format '{}', expected dot or json", other)),
+        }
+    }
+}
+
+/// How many nodes an export left out to stay within its node cap
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Truncation {
+    pub total_nodes: usize,
+    pub shown_nodes: usize,
+}
+
+impl Truncation {
+    /// `None` when all `total_nodes` fit under `max_nodes`
+    pub fn of(total_nodes: usize, max_nodes: usize) -> Option<Self> {
+        (total_nodes > max_nodes).then_some(Self { total_nodes, shown_nodes: max_nodes })
+    }
+
+    pub fn notice(&self) -> String {
+        format!("truncated: showing {} of {} nodes", self.shown_nodes, self.total_nodes)
+    }
+}
+
+/// Builds a DOT graph one statement at a time, quoting every id and attribute value
+#[derive(Debug)]
+pub struct DotWriter {
+    out: String,
+    edge_op: &'static str,
+}
+
+impl DotWriter {
+    pub fn new(name: &str, directed: bool) -> Self {
+        let (keyword, edge_op) = if directed { ("digraph", "->") } else { ("graph", "--") };
+        Self { out: format!("{} {} {{\n", keyword, dot_quote(name)), edge_op }
+    }
+
+    /// An attribute statement such as `node [shape="box"]`, for `graph`, `node` or `edge`
+    pub fn defaults(&mut self, kind: &str, attrs: &[(&str, String)]) {
+        let _ = writeln!(self.out, "  {}{};", kind, attr_list(attrs));
+    }
+
+    pub fn node(&mut self, id: &str, attrs: &[(&str, String)]) {
+        let _ = writeln!(self.out, "  {}{};", dot_quote(id), attr_list(attrs));
+    }
+
+    pub fn edge(&mut self, from: &str, to: &str, attrs: &[(&str, String)]) {
+        let _ = writeln!(self.out, "  {} {} {}{};", dot_quote(from), self.edge_op, dot_quote(to), attr_list(attrs));
+    }
+
+    /// Label the graph with `truncation`'s notice
+    pub fn truncated(&mut self, truncation: &Truncation) {
+        let _ = writeln!(self.out, "  // {}", truncation.notice());
+        self.defaults("graph", &[("label", truncation.notice()), ("labelloc", "t".to_string())]);
+    }
+
+    pub fn finish(mut self) -> String {
+        self.out.push_str("}\n");
+        self.out
+    }
+}
+
+fn attr_list(attrs: &[(&str, String)]) -> String {
+    if attrs.is_empty() {
+        return String::new();
+    }
+    let attrs: Vec<String> = attrs.iter().map(|(name, value)| format!("{}={}", name, dot_quote(value))).collect();
+    format!(" [{}]", attrs.join(", "))
+}
+
+/// `text` as a quoted DOT id
+pub fn dot_quote(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => {}
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Node and edge statements in a DOT document
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DotCounts {
+    pub nodes: usize,
+    pub edges: usize,
+}
+
+/// Check `dot` against the DOT grammar, less subgraphs and ports, counting its node and
+/// edge statements. Exports are checked with it in tests.
+pub fn check_dot(dot: &str) -> Result<DotCounts, String> {
+    let tokens = dot_tokens(dot)?;
+    let mut tokens = tokens.iter().map(String::as_str).peekable();
+    let edge_op = match tokens.next() {
+        Some("digraph") => "->",
+        Some("graph") => "--",
+        _ => return expected("graph or digraph"),
+    };
+    if tokens.peek().is_some_and(|token| is_id(token)) {
+        tokens.next();
+    }
+    if tokens.next() != Some("{") {
+        return expected("{");
+    }
+
+    let mut counts = DotCounts { nodes: 0, edges: 0 };
+    loop {
+        let Some(first) = tokens.next() else {
+            return expected("}");
+        };
+        match first {
+            "}" => break,
+            ";" => continue,
+            "graph" | "node" | "edge" => check_attr_list(&mut tokens)?,
+            id if is_id(id) => match tokens.peek().copied() {
+                Some("=") => {
+                    tokens.next();
+                    if !tokens.next().is_some_and(is_id) {
+                        return expected("a value after =");
+                    }
+                }
+                Some(op) if op == "->" || op == "--" => {
+                    if op != edge_op {
+                        return Err(format!("{} in a graph whose edges are {}", op, edge_op));
+                    }
+                    while tokens.peek() == Some(&edge_op) {
+                        tokens.next();
+                        if !tokens.next().is_some_and(is_id) {
+                            return expected(&format!("a node id after {}", edge_op));
+                        }
+                    }
+                    if tokens.peek() == Some(&"[") {
+                        check_attr_list(&mut tokens)?;
+                    }
+                    counts.edges += 1;
+                }
+                _ => {
+                    if tokens.peek() == Some(&"[") {
+                        check_attr_list(&mut tokens)?;
+                    }
+                    counts.nodes += 1;
+                }
+            },
+            other => return Err(format!("unexpected '{}'", other)),
+        }
+    }
+    match tokens.next() {
+        None => Ok(counts),
+        Some(extra) => Err(format!("unexpected '{}' after the graph", extra)),
+    }
+}
+
+fn expected(what: &str) -> Result<DotCounts, String> {
+    Err(format!("expected {}", what))
+}
+
+fn check_attr_list<'a>(tokens: &mut std::iter::Peekable<impl Iterator<Item = &'a str>>) -> Result<(), String> {
+    if tokens.next() != Some("[") {
+        return Err("expected [".to_string());
+    }
+    loop {
+        match tokens.next() {
+            Some("]") => return Ok(()),
+            Some("," | ";") => continue,
+            Some(name) if is_id(name) => {
+                if tokens.next() != Some("=") || !tokens.next().is_some_and(is_id) {
+                    return Err(format!("expected a value for attribute {}", name));
+                }
+            }
+            Some(other) => return Err(format!("unexpected '{}' in attributes", other)),
+            None => return Err("unclosed attribute list".to_string()),
+        }
+    }
+}
+
+fn is_id(token: &str) -> bool {
+    token.starts_with('"') || token.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+fn dot_tokens(dot: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = dot.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '{' | '}' | '[' | ']' | '=' | ';' | ',' => tokens.push(c.to_string()),
+            '-' if matches!(chars.peek(), Some('>' | '-')) => {
+                let op = chars.next().expect("peeked");
+                tokens.push(format!("-{}", op));
+            }
+            '"' => {
+                let mut token = String::from('"');
+                loop {
+                    match chars.next() {
+                        Some('\\') => {
+                            token.push('\\');
+                            token.push(chars.next().ok_or("unterminated string")?);
+                        }
+                        Some('"') => break,
+                        Some(c) => token.push(c),
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+                token.push('"');
+                tokens.push(token);
+            }
+            c if c.is_ascii_alphanumeric() || c == '_' || c == '.' => {
+                let mut token = c.to_string();
+                while let Some(&c) = chars.peek().filter(|c| c.is_ascii_alphanumeric() || **c == '_' || **c == '.') {
+                    token.push(c);
+                    chars.next();
+                }
+                tokens.push(token);
+            }
+            other => return Err(format!("unexpected character '{}'", other)),
+        }
+    }
+    Ok(tokens)
+}
+
+/// A plan's tasks and dependencies, as [`IntentExecutionPlan::graph`] selects them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanGraph {
+    pub plan_id: Uuid,
+    pub nodes: Vec<PlanGraphNode>,
+    pub links: Vec<PlanGraphLink>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub truncated: Option<Truncation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanGraphNode {
+    pub id: Uuid,
+    pub name: String,
+    pub task_type: TaskType,
+    pub status: TaskStatus,
+}
+
+/// A dependency; `source` must finish before `target` starts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanGraphLink {
+    pub source: Uuid,
+    pub target: Uuid,
+    pub dependency_type: DependencyType,
+}
+
+impl IntentExecutionPlan {
+    /// The plan's first `max_nodes` tasks, in plan order, and the dependencies between them
+    pub fn graph(&self, max_nodes: usize) -> PlanGraph {
+        let shown: Vec<_> = self.tasks.iter().take(max_nodes).collect();
+        let ids: HashSet<Uuid> = shown.iter().map(|task| task.id).collect();
+        PlanGraph {
+            plan_id: self.id,
+            nodes: shown.iter()
+                .map(|task| PlanGraphNode {
+                    id: task.id,
+                    name: task.name.clone(),
+                    task_type: task.task_type.clone(),
+                    status: task.status.clone(),
+                })
+                .collect(),
+            links: self.dependencies.iter()
+                .filter(|dependency| ids.contains(&dependency.from_task) && ids.contains(&dependency.to_task))
+                .map(|dependency| PlanGraphLink {
+                    source: dependency.from_task,
+                    target: dependency.to_task,
+                    dependency_type: dependency.dependency_type.clone(),
+                })
+                .collect(),
+            truncated: Truncation::of(self.tasks.len(), max_nodes),
+        }
+    }
+
+    /// The plan's graph, capped at [`DEFAULT_MAX_NODES`] tasks, written as `format`
+    pub fn export_graph(&self, format: GraphFormat) -> String {
+        self.graph(DEFAULT_MAX_NODES).render(format)
+    }
+}
+
+impl PlanGraph {
+    pub fn render(&self, format: GraphFormat) -> String {
+        match format {
+            GraphFormat::Dot => self.to_dot(),
+            GraphFormat::Json => serde_json::to_string_pretty(self).expect("plan graphs serialize"),
+        }
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut dot = DotWriter::new(&format!("plan {}", self.plan_id), true);
+        dot.defaults("graph", &[("rankdir", "LR".to_string())]);
+        dot.defaults("node", &[("shape", "box".to_string()), ("style", "rounded,filled".to_string())]);
+        if let Some(truncation) = &self.truncated {
+            dot.truncated(truncation);
+        }
+        for node in &self.nodes {
+            dot.node(&node.id.to_string(), &[
+                ("label", format!("{}\n{:?} ({:?})", node.name, node.task_type, node.status)),
+                ("fillcolor", task_type_color(&node.task_type).to_string()),
+                ("color", status_color(&node.status).to_string()),
+                ("penwidth", "2".to_string()),
+            ]);
+        }
+        for link in &self.links {
+            let style = match link.dependency_type {
+                DependencyType::Sequential => "solid",
+                DependencyType::Conditional => "dashed",
+                DependencyType::DataFlow => "bold",
+                DependencyType::Resource => "dotted",
+            };
+            dot.edge(&link.source.to_string(), &link.target.to_string(), &[
+                ("label", format!("{:?}", link.dependency_type)),
+                ("style", style.to_string()),
+            ]);
+        }
+        dot.finish()
+    }
+}
+
+fn task_type_color(task_type: &TaskType) -> &'static str {
+    match task_type {
+        TaskType::Sense => "lightblue",
+        TaskType::Plan => "khaki",
+        TaskType::Execute => "palegreen",
+        TaskType::Verify => "plum",
+        TaskType::Reflect => "lightgray",
+    }
+}
+
+fn status_color(status: &TaskStatus) -> &'static str {
+    match status {
+        TaskStatus::Pending => "gray40",
+        TaskStatus::InProgress => "blue",
+        TaskStatus::Completed => "darkgreen",
+        TaskStatus::Failed => "red",
+        TaskStatus::Cancelled => "gray70",
+        TaskStatus::WaitingApproval => "orange",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ExecutionTask, PlanBudget, TaskDependency};
+    use chrono::{Duration, Utc};
+    use std::collections::HashMap;
+
+    fn plan(tasks: usize) -> IntentExecutionPlan {
+        let tasks: Vec<ExecutionTask> = (0..tasks)
+            .map(|i| ExecutionTask {
+                id: Uuid::new_v4(),
+                name: format!("step \"{}\"", i),
+                description: String::new(),
+                task_type: if i % 2 == 0 { TaskType::Sense } else { TaskType::Execute },
+                agent_type: "agent".to_string(),
+                inputs: HashMap::new(),
+                expected_outputs: Vec::new(),
+                estimated_duration: Duration::minutes(1),
+                status: if i == 0 { TaskStatus::Completed } else { TaskStatus::Pending },
+                dry_run_first: false,
+                tags: Vec::new(),
+                output_schemas: HashMap::new(),
+            })
+            .collect();
+        let dependencies = tasks.windows(2)
+            .map(|pair| TaskDependency { from_task: pair[0].id, to_task: pair[1].id, dependency_type: DependencyType::DataFlow })
+            .collect();
+        IntentExecutionPlan {
+            id: Uuid::new_v4(),
+            intent_id: Uuid::new_v4(),
+            tasks,
+            dependencies,
+            estimated_duration: Duration::minutes(2),
+            autonomy_tier: 3,
+            checkpoints: Vec::new(),
+            rollback_plan: None,
+            budget: PlanBudget::default(),
+            domain: None,
+            risk_level: None,
+            environment: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_plans_export_as_well_formed_dot_and_json() {
+        let plan = plan(3);
+        let dot = plan.export_graph(GraphFormat::Dot);
+        assert_eq!(check_dot(&dot), Ok(DotCounts { nodes: 3, edges: 2 }), "{}", dot);
+        assert!(dot.contains(&format!("{} -> {}", dot_quote(&plan.tasks[0].id.to_string()), dot_quote(&plan.tasks[1].id.to_string()))));
+        assert!(dot.contains("label=\"DataFlow\""));
+        assert!(dot.contains("step \\\"0\\\"\\nSense (Completed)"));
+
+        let json: serde_json::Value = serde_json::from_str(&plan.export_graph(GraphFormat::Json)).unwrap();
+        assert_eq!(json["nodes"].as_array().unwrap().len(), 3);
+        assert_eq!(json["links"][0]["source"], plan.tasks[0].id.to_string());
+        assert_eq!(json["links"][0]["dependency_type"], "DataFlow");
+        assert!(json.get("truncated").is_none());
+
+        for broken in ["digraph { \"a\" -> }", "graph g { a -> b; }", "digraph g { a [label=] }", "digraph g { a"] {
+            assert!(check_dot(broken).is_err(), "{}", broken);
+        }
+    }
+
+    #[test]
+    fn test_large_plans_are_capped_with_a_notice() {
+        let plan = plan(10);
+        let graph = plan.graph(4);
+        assert_eq!(graph.nodes.len(), 4);
+        assert_eq!(graph.links.len(), 3);
+        assert_eq!(graph.truncated, Some(Truncation { total_nodes: 10, shown_nodes: 4 }));
+
+        let dot = graph.to_dot();
+        assert_eq!(check_dot(&dot), Ok(DotCounts { nodes: 4, edges: 3 }), "{}", dot);
+        assert!(dot.contains("truncated: showing 4 of 10 nodes"));
+    }
+}
@@ -0,0 +1,103 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+
+use crate::{Intent, IntentExecutionPlan};
+
+/// Memories recalled to ground an intent, most relevant first
+pub const DEFAULT_GROUNDING_LIMIT: usize = 5;
+
+/// Words and phrases that refer back to earlier conversation
+const REFERENCES: &[&str] = &["it", "that", "this", "them", "those", "same", "again", "last time", "previous"];
+
+/// A memory recalled for an intent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecalledMemory {
+    pub id: Uuid,
+    pub text: String,
+    /// Entities mentioned in the memory, most significant first
+    pub entities: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Conversation history the kernel grounds intents in and records them to
+#[async_trait]
+pub trait ConversationMemory: Send + Sync {
+    /// Up to `limit` of `user_id`'s recent memories most relevant to `query`
+    async fn recall(&self, user_id: Option<&str>, query: &str, limit: usize) -> Result<Vec<RecalledMemory>>;
+
+    /// Remember that `user_id` asked for `intent` and it was planned as `plan`
+    async fn record(&self, user_id: Option<&str>, intent: &Intent, plan: &IntentExecutionPlan) -> Result<Uuid>;
+}
+
+/// Identifier-like tokens and quoted phrases, e.g. `billing-api`, `v2.3` or `"Q3 launch"`
+pub fn extract_entities(text: &str) -> Vec<String> {
+    let mut entities: Vec<String> = text.split('"').skip(1).step_by(2)
+        .map(str::trim)
+        .filter(|quoted| !quoted.is_empty())
+        .map(str::to_string)
+        .collect();
+
+    for token in text.split_whitespace() {
+        let token = token.trim_matches(|c: char| !c.is_alphanumeric());
+        let identifier = token.chars().any(|c| matches!(c, '-' | '_' | '.' | '/' | ':'))
+            || (token.chars().any(|c| c.is_ascii_digit()) && token.chars().any(|c| c.is_alphabetic()));
+        if identifier && !entities.iter().any(|e| e == token) {
+            entities.push(token.to_string());
+        }
+    }
+    entities
+}
+
+/// Whether `text` refers back to something said earlier
+pub fn has_references(text: &str) -> bool {
+    let words: Vec<String> = text.split_whitespace()
+        .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase())
+        .collect();
+    let joined = words.join(" ");
+    REFERENCES.iter().any(|reference| match reference.contains(' ') {
+        true => format!(" {} ", joined).contains(&format!(" {} ", reference)),
+        false => words.iter().any(|w| w == reference),
+    })
+}
+
+/// Heuristic grounding: an intent that refers back to earlier conversation takes on the
+/// entities of the most relevant memory that has any. Returns the ids of the memories
+/// the intent was grounded in.
+pub fn merge_recalled(intent: &mut Intent, recalled: &[RecalledMemory]) -> Vec<Uuid> {
+    if recalled.is_empty() || !has_references(&intent.raw_text) {
+        return Vec::new();
+    }
+
+    if let Some(source) = recalled.iter().find(|m| !m.entities.is_empty()) {
+        let merged: Vec<String> = source.entities.iter()
+            .filter(|e| !intent.entities.contains(e))
+            .cloned()
+            .collect();
+        if !merged.is_empty() {
+            intent.structured_goal = format!("{} (re: {})", intent.structured_goal, merged.join(", "));
+            intent.entities.extend(merged);
+        }
+    }
+    recalled.iter().map(|m| m.id).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_entities_and_references() {
+        assert_eq!(
+            extract_entities("Deploy billing-api v2.3 to production with \"blue green\" rollout"),
+            vec!["blue green", "billing-api", "v2.3"]
+        );
+        assert!(extract_entities("deploy it to staging").is_empty());
+
+        assert!(has_references("Deploy it to staging like last time"));
+        assert!(has_references("Do that again."));
+        assert!(!has_references("Deploy billing-api to its new cluster"));
+    }
+}
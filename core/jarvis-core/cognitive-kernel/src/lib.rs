@@ -5,46 +5,203 @@ use serde::{Serialize, Deserialize};
 use uuid::Uuid;
 use dashmap::DashMap;
 use anyhow::{Result, anyhow};
+use tracing::Instrument;
+
+pub mod approval;
+pub mod artifacts;
+pub mod audit;
+pub mod budget;
+pub mod contract;
+pub mod environment;
+pub mod executor;
+pub mod graph;
+pub mod grounding;
+pub mod lock;
+pub mod replan;
+pub mod replay;
+pub mod state;
+pub mod telemetry;
+
+pub use artifacts::{
+    Artifact, ArtifactError, ArtifactInfo, ArtifactMetadata, ArtifactRef, ArtifactStore, Externalizer, FsArtifactStore,
+};
+pub use approval::{
+    ApprovalDecision, ApprovalEffect, ApprovalError, ApprovalLedger, ApprovalMatch, ApprovalPolicy, ApprovalRule, ApprovalStatus,
+    PendingApproval,
+};
+pub use audit::{AuditAction, AuditActor, AuditEvent, AuditFilter, AuditOutcome, AuditSink, Auditor, JsonlAuditSink};
+pub use budget::{BudgetLimit, BudgetUsage, PlanBudget, TaskUsage};
+pub use contract::{ContractViolation, OutputContractError, OutputFormat, OutputValue};
+pub use environment::{Environment, ENVIRONMENT_INPUT, PRODUCTION_MAX_AUTONOMY_TIER};
+pub use executor::{PlanEvent, PlanExecutor, PlanOutcome, TaskOutput, TaskRunner};
+pub use graph::{GraphFormat, PlanGraph, PlanGraphLink, PlanGraphNode, Truncation, DEFAULT_MAX_NODES};
+pub use grounding::{ConversationMemory, RecalledMemory, DEFAULT_GROUNDING_LIMIT};
+pub use lock::{DistributedLock, LockGuard, LockHolder, LockStatus, LockStore, MemoryLockStore, SingletonJob};
+#[cfg(feature = "redis")]
+pub use lock::RedisLockStore;
+pub use replan::{AdaptivePlanner, ModifiedTask, PlanDiff, PlanRevision, RemovalReason, RemovedTask, TaskSummary};
+pub use replay::{MismatchPolicy, RecordedResult, RecordedRun, ReplayBundle, ReplayMismatch};
+pub use state::{namespaces, KernelState, PlanningStats, StateChange, StateError, StateHandle, StatePersistence};
+#[cfg(feature = "redis")]
+pub use state::RedisStatePersistence;
+pub use intent_classifier::{Classification, IntentClassifier, PatternSet, RiskLevel};
 
 /// Core cognitive kernel that orchestrates all JARVIS thinking processes
-#[derive(Debug)]
 pub struct CognitiveKernel {
+    pub classifier: Arc<IntentClassifier>,
     pub active_contexts: Arc<DashMap<Uuid, ExecutionContext>>,
     pub global_state: Arc<DashMap<String, serde_json::Value>>,
+    /// Namespaced, typed view of `global_state`
+    state: Arc<KernelState>,
+    /// Conversation history intents are grounded in, if any
+    memory: Option<Arc<dyn ConversationMemory>>,
+    grounding_limit: usize,
+}
+
+impl std::fmt::Debug for CognitiveKernel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CognitiveKernel")
+            .field("classifier", &self.classifier)
+            .field("active_contexts", &self.active_contexts)
+            .field("global_state", &self.global_state)
+            .field("state", &self.state)
+            .field("memory", &self.memory.is_some())
+            .field("grounding_limit", &self.grounding_limit)
+            .finish()
+    }
 }
 
 impl CognitiveKernel {
     pub fn new() -> Self {
+        let global_state = Arc::new(DashMap::new());
         Self {
+            classifier: Arc::new(IntentClassifier::new()),
             active_contexts: Arc::new(DashMap::new()),
-            global_state: Arc::new(DashMap::new()),
+            state: Arc::new(KernelState::new(global_state.clone())),
+            global_state,
+            memory: None,
+            grounding_limit: DEFAULT_GROUNDING_LIMIT,
         }
     }
 
+    /// Classify intents with a shared classifier, e.g. one with patterns loaded from config
+    pub fn with_classifier(mut self, classifier: Arc<IntentClassifier>) -> Self {
+        self.classifier = classifier;
+        self
+    }
+
+    /// Ground intents in `memory`'s recent conversation and record each planned intent to it
+    pub fn with_memory(mut self, memory: Arc<dyn ConversationMemory>) -> Self {
+        self.memory = Some(memory);
+        self
+    }
+
+    /// How many recalled memories an intent is grounded in
+    pub fn with_grounding_limit(mut self, limit: usize) -> Self {
+        self.grounding_limit = limit;
+        self
+    }
+
+    /// Write the given state namespaces through to `persistence`; call `restore_state`
+    /// to load them back
+    pub fn with_state_persistence(
+        mut self,
+        persistence: Arc<dyn StatePersistence>,
+        namespaces: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.state = Arc::new(KernelState::new(self.global_state.clone()).with_persistence(persistence, namespaces));
+        self
+    }
+
+    /// Load persisted state namespaces, returning how many had a stored value
+    pub async fn restore_state(&self) -> Result<usize> {
+        self.state.restore().await
+    }
+
+    /// Typed access to one namespace of the global state
+    pub fn state_handle<T: Serialize + serde::de::DeserializeOwned>(&self, namespace: &str) -> StateHandle<T> {
+        self.state.handle(namespace)
+    }
+
+    /// Changes to a namespace of the global state from now on
+    pub fn subscribe_state(&self, namespace: &str) -> tokio::sync::broadcast::Receiver<StateChange> {
+        self.state.subscribe(namespace)
+    }
+
     /// Primary entry point: converts user intent into executable plan
-    pub async fn process_intent(&self, raw_intent: &str, _context: Option<ExecutionContext>) -> Result<IntentExecutionPlan> {
+    pub async fn process_intent(&self, raw_intent: &str, context: Option<ExecutionContext>) -> Result<IntentExecutionPlan> {
+        Ok(self.plan_intent(raw_intent, context).await?.1)
+    }
+
+    /// Like `process_intent`, also returning the parsed intent the plan was built from
+    ///
+    /// Planning runs in a `plan_intent` span, parented on the context's trace if it has one.
+    pub async fn plan_intent(&self, raw_intent: &str, context: Option<ExecutionContext>) -> Result<(Intent, IntentExecutionPlan)> {
+        let span = tracing::info_span!("plan_intent", intent_id = tracing::field::Empty, plan_id = tracing::field::Empty);
+        telemetry::set_parent(&span, context.as_ref().and_then(|ctx| ctx.trace_parent.as_deref()));
+        self.plan_in_span(raw_intent, context).instrument(span).await
+    }
+
+    async fn plan_in_span(&self, raw_intent: &str, context: Option<ExecutionContext>) -> Result<(Intent, IntentExecutionPlan)> {
         tracing::info!("Processing intent: {}", raw_intent);
+        let (user_id, environment, production_autonomy) = match context {
+            Some(ctx) => (ctx.user_id, ctx.environment, ctx.production_autonomy),
+            None => (None, None, false),
+        };
         
-        // Parse and classify the intent
-        let intent = self.parse_intent(raw_intent).await?;
+        // Parse and classify the intent, grounded in what the user said before
+        let recalled = self.recall(user_id.as_deref(), raw_intent).await;
+        let intent = self.parse_intent(raw_intent, &recalled, environment).await?;
+        tracing::Span::current().record("intent_id", tracing::field::display(intent.id));
         
         // Create execution context
         let ctx_id = Uuid::new_v4();
-        let ctx = ExecutionContext::new(intent.id);
+        let mut ctx = ExecutionContext::new(intent.id);
+        ctx.user_id = user_id.clone();
+        ctx.environment = intent.environment.clone();
+        ctx.production_autonomy = production_autonomy;
+        ctx.trace_parent = telemetry::current_traceparent();
         self.active_contexts.insert(ctx_id, ctx);
         
         // Generate execution plan
-        let plan = self.create_execution_plan(&intent).await?;
+        let plan = self.create_execution_plan(&intent, production_autonomy).await?;
+        tracing::Span::current().record("plan_id", tracing::field::display(plan.id));
         
         tracing::info!("Generated execution plan with {} tasks", plan.tasks.len());
-        Ok(plan)
+        let plan_id = plan.id;
+        let planned = self.state_handle::<PlanningStats>(namespaces::PLANNING).update(|stats| {
+            let mut stats = stats.unwrap_or_default();
+            stats.plans_created += 1;
+            stats.last_plan_id = Some(plan_id);
+            stats
+        }).await;
+        if let Err(e) = planned {
+            tracing::warn!("Failed to record plan {} in kernel state: {}", plan_id, e);
+        }
+        if let Some(memory) = &self.memory {
+            if let Err(e) = memory.record(user_id.as_deref(), &intent, &plan).await {
+                tracing::warn!("Failed to record intent {} in memory: {}", intent.id, e);
+            }
+        }
+        Ok((intent, plan))
+    }
+
+    /// Memories to ground `raw_intent` in; a failed recall leaves the intent ungrounded
+    async fn recall(&self, user_id: Option<&str>, raw_intent: &str) -> Vec<RecalledMemory> {
+        let Some(memory) = &self.memory else {
+            return Vec::new();
+        };
+        memory.recall(user_id, raw_intent, self.grounding_limit).await.unwrap_or_else(|e| {
+            tracing::warn!("Failed to recall memories for intent: {}", e);
+            Vec::new()
+        })
     }
 
-    async fn parse_intent(&self, raw_text: &str) -> Result<Intent> {
-        let domain = self.classify_domain(raw_text);
-        let risk_level = self.assess_risk(raw_text);
+    /// `environment`, when the request names one, overrides the one the intent describes
+    async fn parse_intent(&self, raw_text: &str, recalled: &[RecalledMemory], environment: Option<Environment>) -> Result<Intent> {
+        let Classification { domain, risk_level } = self.classifier.classify(raw_text);
         
-        Ok(Intent {
+        let mut intent = Intent {
             id: Uuid::new_v4(),
             raw_text: raw_text.to_string(),
             structured_goal: format!("[{}] {}", domain, raw_text),
@@ -54,40 +211,38 @@ impl CognitiveKernel {
             constraints: Vec::new(),
             success_criteria: vec!["task_completed".to_string()],
             risk_level,
+            entities: grounding::extract_entities(raw_text),
+            grounding: Vec::new(),
+            environment: None,
             created_at: Utc::now(),
-        })
+        };
+        intent.environment = environment.or_else(|| environment::extract(raw_text, &intent.constraints));
+        intent.grounding = grounding::merge_recalled(&mut intent, recalled);
+        Ok(intent)
     }
 
     pub fn classify_domain(&self, text: &str) -> String {
-        let text_lower = text.to_lowercase();
-        
-        if text_lower.contains("deploy") || text_lower.contains("kubernetes") || text_lower.contains("docker") {
-            "infra_deployment".to_string()
-        } else if text_lower.contains("database") || text_lower.contains("postgres") || text_lower.contains("sql") {
-            "database_admin".to_string()
-        } else if text_lower.contains("marketing") || text_lower.contains("content") || text_lower.contains("blog") {
-            "marketing_content".to_string()
-        } else {
-            "general".to_string()
-        }
+        self.classifier.classify_domain(text)
     }
 
     pub fn assess_risk(&self, text: &str) -> RiskLevel {
-        let text_lower = text.to_lowercase();
-        
-        if text_lower.contains("production") || text_lower.contains("delete") || text_lower.contains("drop") {
-            RiskLevel::Critical
-        } else if text_lower.contains("modify") || text_lower.contains("update") || text_lower.contains("migrate") {
-            RiskLevel::High
-        } else if text_lower.contains("create") || text_lower.contains("deploy") {
-            RiskLevel::Medium
-        } else {
-            RiskLevel::Low
-        }
+        self.classifier.assess_risk(text)
     }
 
-    async fn create_execution_plan(&self, intent: &Intent) -> Result<IntentExecutionPlan> {
-        let tasks = self.generate_tasks_for_domain(&intent.domain, intent)?;
+    /// Production plans are held to `PRODUCTION_MAX_AUTONOMY_TIER` unless
+    /// `production_autonomy` allows otherwise
+    async fn create_execution_plan(&self, intent: &Intent, production_autonomy: bool) -> Result<IntentExecutionPlan> {
+        let mut tasks = self.generate_tasks_for_domain(&intent.domain, intent)?;
+        let mut autonomy_tier = self.determine_autonomy_tier(intent);
+        if let Some(environment) = &intent.environment {
+            for task in &mut tasks {
+                task.inputs.insert(ENVIRONMENT_INPUT.to_string(), serde_json::json!(environment));
+            }
+            if environment.is_production() && !production_autonomy && autonomy_tier > PRODUCTION_MAX_AUTONOMY_TIER {
+                tracing::info!("Clamping autonomy tier {} to {} for a production plan", autonomy_tier, PRODUCTION_MAX_AUTONOMY_TIER);
+                autonomy_tier = PRODUCTION_MAX_AUTONOMY_TIER;
+            }
+        }
         
         Ok(IntentExecutionPlan {
             id: Uuid::new_v4(),
@@ -95,9 +250,13 @@ impl CognitiveKernel {
             tasks,
             dependencies: Vec::new(),
             estimated_duration: Duration::minutes(15),
-            autonomy_tier: self.determine_autonomy_tier(intent),
+            autonomy_tier,
             checkpoints: Vec::new(),
             rollback_plan: None,
+            budget: PlanBudget::for_autonomy_tier(autonomy_tier),
+            domain: Some(intent.domain.clone()),
+            risk_level: Some(intent.risk_level),
+            environment: intent.environment.clone(),
             created_at: Utc::now(),
         })
     }
@@ -118,6 +277,8 @@ impl CognitiveKernel {
                     estimated_duration: Duration::minutes(5),
                     status: TaskStatus::Pending,
                     dry_run_first: false,
+                    tags: Vec::new(),
+                    output_schemas: HashMap::new(),
                 });
                 
                 tasks.push(ExecutionTask {
@@ -131,6 +292,8 @@ impl CognitiveKernel {
                     estimated_duration: Duration::minutes(10),
                     status: TaskStatus::Pending,
                     dry_run_first: false,
+                    tags: Vec::new(),
+                    output_schemas: HashMap::new(),
                 });
             },
             _ => {
@@ -145,6 +308,8 @@ impl CognitiveKernel {
                     estimated_duration: Duration::minutes(10),
                     status: TaskStatus::Pending,
                     dry_run_first: true,
+                    tags: Vec::new(),
+                    output_schemas: HashMap::new(),
                 });
             }
         }
@@ -161,14 +326,15 @@ impl CognitiveKernel {
         }
     }
 
-    /// Query global cognitive state
+    /// Query global cognitive state; prefer a typed `state_handle`
     pub fn get_global_state(&self, key: &str) -> Option<serde_json::Value> {
-        self.global_state.get(key).map(|v| v.clone())
+        self.state.get_raw(key)
     }
 
-    /// Update global cognitive state
+    /// Update global cognitive state, notifying subscribers but never persisting; prefer
+    /// a typed `state_handle`
     pub fn set_global_state(&self, key: String, value: serde_json::Value) {
-        self.global_state.insert(key, value);
+        self.state.set_raw(&key, value);
     }
 }
 
@@ -184,17 +350,18 @@ pub struct Intent {
     pub constraints: Vec<String>,
     pub success_criteria: Vec<String>,
     pub risk_level: RiskLevel,
+    /// Entities the intent is about, including ones resolved from earlier conversation
+    #[serde(default)]
+    pub entities: Vec<String>,
+    /// Ids of the memories the intent was grounded in
+    #[serde(default)]
+    pub grounding: Vec<Uuid>,
+    /// Environment the intent targets, named by the request or read from the intent
+    #[serde(default)]
+    pub environment: Option<Environment>,
     pub created_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum RiskLevel {
-    Low,
-    Medium, 
-    High,
-    Critical,
-}
-
 /// Execution plan with hierarchical tasks and dependencies
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IntentExecutionPlan {
@@ -206,6 +373,18 @@ pub struct IntentExecutionPlan {
     pub autonomy_tier: u8,
     pub checkpoints: Vec<Checkpoint>,
     pub rollback_plan: Option<RollbackPlan>,
+    /// Ceilings the executor enforces; tier defaults unless overridden
+    #[serde(default)]
+    pub budget: PlanBudget,
+    /// Domain and risk of the intent the plan serves, for approval policies to match on
+    #[serde(default)]
+    pub domain: Option<String>,
+    #[serde(default)]
+    pub risk_level: Option<RiskLevel>,
+    /// Environment the plan runs against, also given to each task as its
+    /// `ENVIRONMENT_INPUT`
+    #[serde(default)]
+    pub environment: Option<Environment>,
     pub created_at: DateTime<Utc>,
 }
 
@@ -221,9 +400,16 @@ pub struct ExecutionTask {
     pub estimated_duration: Duration,
     pub status: TaskStatus,
     pub dry_run_first: bool,
+    /// Labels approval policies can match on, such as `destructive`
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// JSON Schemas for expected outputs, keyed by output name, that the outputs must
+    /// match on top of parsing in the format their names declare
+    #[serde(default)]
+    pub output_schemas: HashMap<String, serde_json::Value>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TaskType {
     Sense,
     Plan,
@@ -261,6 +447,7 @@ pub enum DependencyType {
 pub struct Checkpoint {
     pub task_id: Uuid,
     pub description: String,
+    /// Ignored by executors with an approval policy, which decides instead
     pub requires_approval: bool,
     pub auto_rollback_on_fail: bool,
 }
@@ -285,6 +472,19 @@ pub struct ExecutionContext {
     pub id: Uuid,
     pub intent_id: Uuid,
     pub execution_state: ExecutionState,
+    /// User the intent came from, whose memories ground it
+    #[serde(default)]
+    pub user_id: Option<String>,
+    /// W3C `traceparent` of the request the intent arrived with
+    #[serde(default)]
+    pub trace_parent: Option<String>,
+    /// Environment the request targets, overriding the one the intent names
+    #[serde(default)]
+    pub environment: Option<Environment>,
+    /// Whether the requester may let production plans run above
+    /// `PRODUCTION_MAX_AUTONOMY_TIER`
+    #[serde(default)]
+    pub production_autonomy: bool,
     pub created_at: DateTime<Utc>,
 }
 
@@ -294,9 +494,36 @@ impl ExecutionContext {
             id: Uuid::new_v4(),
             intent_id,
             execution_state: ExecutionState::Planning,
+            user_id: None,
+            trace_parent: None,
+            environment: None,
+            production_autonomy: false,
             created_at: Utc::now(),
         }
     }
+
+    pub fn with_user(mut self, user_id: impl Into<String>) -> Self {
+        self.user_id = Some(user_id.into());
+        self
+    }
+
+    /// Trace the intent's processing as part of the request `traceparent` names
+    pub fn with_trace_parent(mut self, traceparent: impl Into<String>) -> Self {
+        self.trace_parent = Some(traceparent.into());
+        self
+    }
+
+    /// Target `environment` whatever the intent says
+    pub fn with_environment(mut self, environment: Environment) -> Self {
+        self.environment = Some(environment);
+        self
+    }
+
+    /// Let production plans keep the autonomy tier their risk allows
+    pub fn with_production_autonomy(mut self, allowed: bool) -> Self {
+        self.production_autonomy = allowed;
+        self
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -306,6 +533,10 @@ pub enum ExecutionState {
     Completed,
     Failed { error: String },
     Cancelled,
+    /// Paused before a task because a budget ceiling was reached; needs approval to resume
+    BudgetExceeded { limit: BudgetLimit },
+    /// Paused before a task the approval policy holds until enough approvers release it
+    AwaitingApproval { task_id: Uuid },
 }
 
 #[cfg(test)]
@@ -334,6 +565,93 @@ mod tests {
         assert_eq!(plan.autonomy_tier, 2); // Medium risk = tier 2
     }
 
+    #[tokio::test]
+    async fn test_plan_intent_uses_shared_classifier() {
+        let classifier = Arc::new(IntentClassifier::new());
+        let kernel = CognitiveKernel::new().with_classifier(classifier.clone());
+        classifier.register_risk_patterns(RiskLevel::Critical, ["rotate credentials"]);
+
+        let (intent, plan) = kernel.plan_intent("Rotate credentials for the billing service", None).await.unwrap();
+        assert_eq!(intent.risk_level, RiskLevel::Critical);
+        assert_eq!(plan.intent_id, intent.id);
+        assert_eq!(plan.autonomy_tier, 1);
+    }
+
+    #[tokio::test]
+    async fn test_production_plans_are_clamped_unless_overridden() {
+        let kernel = CognitiveKernel::new();
+
+        let (intent, plan) = kernel.plan_intent("Read the configuration in prod", None).await.unwrap();
+        assert_eq!(intent.risk_level, RiskLevel::Low);
+        assert_eq!(plan.environment, Some(Environment::Production));
+        assert_eq!(plan.autonomy_tier, PRODUCTION_MAX_AUTONOMY_TIER);
+        assert!(plan.tasks.iter().all(|task| task.inputs[ENVIRONMENT_INPUT] == "production"));
+
+        let allowed = ExecutionContext::new(Uuid::nil()).with_production_autonomy(true);
+        let (_, plan) = kernel.plan_intent("Read the configuration in prod", Some(allowed)).await.unwrap();
+        assert_eq!(plan.autonomy_tier, 3);
+
+        // The request's environment overrides the one in the text, and is not clamped
+        let staging = ExecutionContext::new(Uuid::nil()).with_environment(Environment::Staging);
+        let (intent, plan) = kernel.plan_intent("Read the configuration in prod", Some(staging)).await.unwrap();
+        assert_eq!((intent.environment, plan.autonomy_tier), (Some(Environment::Staging), 3));
+
+        let (_, plan) = kernel.plan_intent("Read the configuration", None).await.unwrap();
+        assert!(plan.environment.is_none() && plan.tasks[0].inputs.is_empty());
+    }
+
+    /// Remembers recorded intents as plain entries, recalling the newest first
+    #[derive(Default)]
+    struct ListMemory {
+        entries: std::sync::Mutex<Vec<(Option<String>, RecalledMemory)>>,
+    }
+
+    #[async_trait::async_trait]
+    impl ConversationMemory for ListMemory {
+        async fn recall(&self, user_id: Option<&str>, _query: &str, limit: usize) -> Result<Vec<RecalledMemory>> {
+            let entries = self.entries.lock().unwrap();
+            Ok(entries.iter().rev()
+                .filter(|(user, _)| user.as_deref() == user_id)
+                .take(limit)
+                .map(|(_, memory)| memory.clone())
+                .collect())
+        }
+
+        async fn record(&self, user_id: Option<&str>, intent: &Intent, _plan: &IntentExecutionPlan) -> Result<Uuid> {
+            let memory = RecalledMemory {
+                id: Uuid::new_v4(),
+                text: intent.raw_text.clone(),
+                entities: intent.entities.clone(),
+                created_at: intent.created_at,
+            };
+            self.entries.lock().unwrap().push((user_id.map(str::to_string), memory.clone()));
+            Ok(memory.id)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_follow_up_intent_is_grounded_in_memory() {
+        let memory = Arc::new(ListMemory::default());
+        let kernel = CognitiveKernel::new().with_memory(memory.clone());
+        let alice = || Some(ExecutionContext::new(Uuid::nil()).with_user("alice"));
+
+        let (first, _) = kernel.plan_intent("Deploy billing-api to production", alice()).await.unwrap();
+        assert_eq!(first.entities, vec!["billing-api"]);
+        assert!(first.grounding.is_empty());
+
+        let (follow_up, plan) = kernel.plan_intent("Deploy it to staging like last time", alice()).await.unwrap();
+        let recorded = memory.entries.lock().unwrap()[0].1.id;
+        assert_eq!(follow_up.entities, vec!["billing-api"]);
+        assert_eq!(follow_up.grounding, vec![recorded]);
+        assert!(follow_up.structured_goal.ends_with("(re: billing-api)"));
+        assert_eq!(plan.intent_id, follow_up.id);
+
+        let bob = Some(ExecutionContext::new(Uuid::nil()).with_user("bob"));
+        let (unrelated, _) = kernel.plan_intent("Deploy it to staging", bob).await.unwrap();
+        assert!(unrelated.entities.is_empty() && unrelated.grounding.is_empty());
+        assert_eq!(memory.entries.lock().unwrap().len(), 3);
+    }
+
     #[test]
     fn test_domain_classification() {
         let kernel = CognitiveKernel::new();
@@ -0,0 +1,596 @@
+//! Named locks shared by every replica, so that background jobs run on one at a time.
+//!
+//! A `DistributedLock` takes a lock for a lease that a watchdog keeps extending while
+//! its holder is alive; a holder that dies loses the lock once the lease runs out. Each
+//! acquisition gets a fencing token larger than any earlier one for the same name, so
+//! work done under a lock that was since lost can be told from the current holder's.
+//! `SingletonJob` runs a job's loop only while this replica holds the job's lock, and
+//! otherwise keeps trying to take it over, with jitter so that waiting replicas do not
+//! all try at once.
+
+use std::collections::{BTreeSet, HashMap};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Serialize, Deserialize};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Lease a lock is taken for unless `DistributedLock::with_lease` says otherwise
+pub const DEFAULT_LEASE: Duration = Duration::from_secs(30);
+
+/// Who holds a lock, as its store records it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockHolder {
+    pub holder: String,
+    pub token: u64,
+}
+
+/// Where locks and their fencing tokens are kept
+#[async_trait]
+pub trait LockStore: Send + Sync {
+    /// Take `name` for `holder` for `lease` unless it is held, returning the new fencing token
+    async fn acquire(&self, name: &str, holder: &str, lease: Duration) -> Result<Option<u64>>;
+
+    /// Extend the lease `holder` took with `token`; false if it no longer holds the lock
+    async fn extend(&self, name: &str, holder: &str, token: u64, lease: Duration) -> Result<bool>;
+
+    /// Give the lock up if `holder` still holds it with `token`
+    async fn release(&self, name: &str, holder: &str, token: u64) -> Result<()>;
+
+    /// The holder of `name`, unless its lease ran out
+    async fn holder(&self, name: &str) -> Result<Option<LockHolder>>;
+}
+
+/// Locks of a single process, for tests and single-replica deployments
+#[derive(Default)]
+pub struct MemoryLockStore {
+    locks: Mutex<HashMap<String, (LockHolder, Instant)>>,
+    tokens: Mutex<HashMap<String, u64>>,
+}
+
+impl MemoryLockStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn current(&self, name: &str) -> Option<LockHolder> {
+        let mut locks = self.locks.lock().unwrap();
+        match locks.get(name) {
+            Some((holder, expires)) if *expires > Instant::now() => Some(holder.clone()),
+            Some(_) => {
+                locks.remove(name);
+                None
+            }
+            None => None,
+        }
+    }
+}
+
+#[async_trait]
+impl LockStore for MemoryLockStore {
+    async fn acquire(&self, name: &str, holder: &str, lease: Duration) -> Result<Option<u64>> {
+        if self.current(name).is_some() {
+            return Ok(None);
+        }
+        let token = {
+            let mut tokens = self.tokens.lock().unwrap();
+            let token = tokens.entry(name.to_string()).or_insert(0);
+            *token += 1;
+            *token
+        };
+        let held = LockHolder { holder: holder.to_string(), token };
+        self.locks.lock().unwrap().insert(name.to_string(), (held, Instant::now() + lease));
+        Ok(Some(token))
+    }
+
+    async fn extend(&self, name: &str, holder: &str, token: u64, lease: Duration) -> Result<bool> {
+        if self.current(name) != Some(LockHolder { holder: holder.to_string(), token }) {
+            return Ok(false);
+        }
+        if let Some((_, expires)) = self.locks.lock().unwrap().get_mut(name) {
+            *expires = Instant::now() + lease;
+        }
+        Ok(true)
+    }
+
+    async fn release(&self, name: &str, holder: &str, token: u64) -> Result<()> {
+        if self.current(name) == Some(LockHolder { holder: holder.to_string(), token }) {
+            self.locks.lock().unwrap().remove(name);
+        }
+        Ok(())
+    }
+
+    async fn holder(&self, name: &str) -> Result<Option<LockHolder>> {
+        Ok(self.current(name))
+    }
+}
+
+/// Take the lock unless it is held, with a fencing token from its counter.
+/// KEYS: lock, fencing counter. ARGV: holder, lease (ms).
+#[cfg(feature = "redis")]
+const ACQUIRE_SCRIPT: &str = r#"
+if redis.call('EXISTS', KEYS[1]) == 1 then
+    return false
+end
+local token = redis.call('INCR', KEYS[2])
+redis.call('SET', KEYS[1], token .. ':' .. ARGV[1], 'PX', ARGV[2])
+return token
+"#;
+
+/// Extend the lease if the lock is still the one taken.
+/// KEYS: lock. ARGV: `<token>:<holder>`, lease (ms).
+#[cfg(feature = "redis")]
+const EXTEND_SCRIPT: &str = r#"
+if redis.call('GET', KEYS[1]) ~= ARGV[1] then
+    return 0
+end
+redis.call('PEXPIRE', KEYS[1], ARGV[2])
+return 1
+"#;
+
+/// Delete the lock if it is still the one taken.
+/// KEYS: lock. ARGV: `<token>:<holder>`.
+#[cfg(feature = "redis")]
+const RELEASE_SCRIPT: &str = r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    redis.call('DEL', KEYS[1])
+end
+return 1
+"#;
+
+/// Locks as `<prefix><name>` keys in Redis holding `<token>:<holder>`, with their
+/// fencing counters under `<prefix><name>:fence`
+#[cfg(feature = "redis")]
+pub struct RedisLockStore {
+    connection: redis::aio::ConnectionManager,
+    prefix: String,
+}
+
+#[cfg(feature = "redis")]
+impl RedisLockStore {
+    pub fn new(connection: redis::aio::ConnectionManager, prefix: impl Into<String>) -> Self {
+        Self { connection, prefix: prefix.into() }
+    }
+
+    fn lock_key(&self, name: &str) -> String {
+        format!("{}{}", self.prefix, name)
+    }
+
+    fn value(holder: &str, token: u64) -> String {
+        format!("{}:{}", token, holder)
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl LockStore for RedisLockStore {
+    async fn acquire(&self, name: &str, holder: &str, lease: Duration) -> Result<Option<u64>> {
+        let mut connection = self.connection.clone();
+        let token: Option<u64> = redis::Script::new(ACQUIRE_SCRIPT)
+            .key(self.lock_key(name))
+            .key(format!("{}:fence", self.lock_key(name)))
+            .arg(holder)
+            .arg(lease.as_millis() as u64)
+            .invoke_async(&mut connection)
+            .await?;
+        Ok(token)
+    }
+
+    async fn extend(&self, name: &str, holder: &str, token: u64, lease: Duration) -> Result<bool> {
+        let mut connection = self.connection.clone();
+        let extended: i32 = redis::Script::new(EXTEND_SCRIPT)
+            .key(self.lock_key(name))
+            .arg(Self::value(holder, token))
+            .arg(lease.as_millis() as u64)
+            .invoke_async(&mut connection)
+            .await?;
+        Ok(extended == 1)
+    }
+
+    async fn release(&self, name: &str, holder: &str, token: u64) -> Result<()> {
+        let mut connection = self.connection.clone();
+        redis::Script::new(RELEASE_SCRIPT)
+            .key(self.lock_key(name))
+            .arg(Self::value(holder, token))
+            .invoke_async::<_, ()>(&mut connection)
+            .await?;
+        Ok(())
+    }
+
+    async fn holder(&self, name: &str) -> Result<Option<LockHolder>> {
+        let mut connection = self.connection.clone();
+        let stored: Option<String> = redis::AsyncCommands::get(&mut connection, self.lock_key(name)).await?;
+        Ok(stored.and_then(|value| {
+            let (token, holder) = value.split_once(':')?;
+            Some(LockHolder { holder: holder.to_string(), token: token.parse().ok()? })
+        }))
+    }
+}
+
+/// Who holds one of the locks a `DistributedLock` was used for
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockStatus {
+    pub name: String,
+    /// `None` while nobody holds the lock
+    pub holder: Option<LockHolder>,
+    /// Whether this replica is the holder
+    pub held_here: bool,
+}
+
+/// Takes named locks in a `LockStore` on behalf of one replica
+#[derive(Clone)]
+pub struct DistributedLock {
+    store: Arc<dyn LockStore>,
+    holder: String,
+    lease: Duration,
+    /// Every name locked through this value or its clones, for `status`
+    names: Arc<Mutex<BTreeSet<String>>>,
+}
+
+impl DistributedLock {
+    /// Locks in `store` held as `holder`, which must be unique to this replica
+    pub fn new(store: Arc<dyn LockStore>, holder: impl Into<String>) -> Self {
+        Self { store, holder: holder.into(), lease: DEFAULT_LEASE, names: Arc::default() }
+    }
+
+    /// Hold locks for `lease` past the last time the watchdog extended them
+    pub fn with_lease(mut self, lease: Duration) -> Self {
+        self.lease = lease;
+        self
+    }
+
+    pub fn holder(&self) -> &str {
+        &self.holder
+    }
+
+    pub fn lease(&self) -> Duration {
+        self.lease
+    }
+
+    /// Take `name` unless another holder has it; the returned guard keeps extending the
+    /// lease until it is released or dropped
+    pub async fn try_acquire(&self, name: &str) -> Result<Option<LockGuard>> {
+        self.track(name);
+        let Some(token) = self.store.acquire(name, &self.holder, self.lease).await? else {
+            return Ok(None);
+        };
+        let (lose, lost) = watch::channel(false);
+        let watchdog = tokio::spawn(watchdog(
+            self.store.clone(),
+            name.to_string(),
+            self.holder.clone(),
+            token,
+            self.lease,
+            lose,
+        ));
+        Ok(Some(LockGuard {
+            store: self.store.clone(),
+            name: name.to_string(),
+            holder: self.holder.clone(),
+            token,
+            lost,
+            watchdog,
+        }))
+    }
+
+    /// Who holds each lock taken or tried through this value, by name
+    pub async fn status(&self) -> Result<Vec<LockStatus>> {
+        let names: Vec<String> = self.names.lock().unwrap().iter().cloned().collect();
+        let mut statuses = Vec::with_capacity(names.len());
+        for name in names {
+            let holder = self.store.holder(&name).await?;
+            let held_here = holder.as_ref().is_some_and(|holder| holder.holder == self.holder);
+            statuses.push(LockStatus { name, holder, held_here });
+        }
+        Ok(statuses)
+    }
+
+    fn track(&self, name: &str) {
+        self.names.lock().unwrap().insert(name.to_string());
+    }
+}
+
+/// Extend the lease a third of the way through, until the lock turns out to be lost
+async fn watchdog(
+    store: Arc<dyn LockStore>,
+    name: String,
+    holder: String,
+    token: u64,
+    lease: Duration,
+    lose: watch::Sender<bool>,
+) {
+    let mut extended = Instant::now();
+    loop {
+        tokio::time::sleep(lease / 3).await;
+        match store.extend(&name, &holder, token, lease).await {
+            Ok(true) => extended = Instant::now(),
+            Ok(false) => break,
+            // The lease may still be running; it is only lost once it has run out
+            Err(e) if extended.elapsed() < lease => warn!("Could not extend the lease on lock '{}': {}", name, e),
+            Err(e) => {
+                warn!("Could not extend the lease on lock '{}' before it ran out: {}", name, e);
+                break;
+            }
+        }
+    }
+    let _ = lose.send(true);
+}
+
+/// A lock this replica holds. Dropping it stops extending the lease, so the lock is
+/// free once the lease runs out; `release` frees it at once.
+pub struct LockGuard {
+    store: Arc<dyn LockStore>,
+    name: String,
+    holder: String,
+    token: u64,
+    lost: watch::Receiver<bool>,
+    watchdog: JoinHandle<()>,
+}
+
+impl LockGuard {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Fencing token of this acquisition, larger than that of every earlier one
+    pub fn token(&self) -> u64 {
+        self.token
+    }
+
+    /// Whether the lease could not be extended, so another holder may have the lock
+    pub fn is_lost(&self) -> bool {
+        *self.lost.borrow()
+    }
+
+    /// Wait until the lock is lost
+    pub async fn lost(&mut self) {
+        let _ = self.lost.wait_for(|lost| *lost).await;
+    }
+
+    /// Give the lock up, so another replica can take it without waiting out the lease
+    pub async fn release(self) -> Result<()> {
+        self.watchdog.abort();
+        self.store.release(&self.name, &self.holder, self.token).await
+    }
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        self.watchdog.abort();
+    }
+}
+
+/// A background job that runs on one replica at a time
+#[derive(Clone)]
+pub struct SingletonJob {
+    name: String,
+    lock: DistributedLock,
+    retry: Duration,
+    jitter: Duration,
+}
+
+impl SingletonJob {
+    /// The job called `name`, run under the lock of that name. Replicas not running it
+    /// try to take it over every lease, give or take a quarter of one.
+    pub fn new(name: impl Into<String>, lock: DistributedLock) -> Self {
+        let name = name.into();
+        lock.track(&name);
+        let lease = lock.lease();
+        Self { name, lock, retry: lease * 3 / 4, jitter: lease / 2 }
+    }
+
+    /// Try to take the job over every `retry` plus up to `jitter`
+    pub fn with_retry(mut self, retry: Duration, jitter: Duration) -> Self {
+        self.retry = retry;
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Run `job` whenever this replica holds the job's lock, until `stop` turns true.
+    ///
+    /// `job` is handed a receiver that turns true when it must return: on `stop`, or when
+    /// the lock was lost to another replica, after which this one waits to take it back.
+    /// On `stop` the lock is released for another replica to take over right away.
+    pub async fn run<F, Fut>(&self, mut stop: watch::Receiver<bool>, mut job: F)
+    where
+        F: FnMut(watch::Receiver<bool>) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        loop {
+            match self.lock.try_acquire(&self.name).await {
+                Ok(Some(mut guard)) => {
+                    info!("Running job '{}' here as holder {} with fencing token {}", self.name, self.lock.holder(), guard.token());
+                    let (halt, halted) = watch::channel(false);
+                    let running = job(halted);
+                    tokio::pin!(running);
+                    let lost = tokio::select! {
+                        _ = &mut running => None,
+                        _ = stop.wait_for(|s| *s) => Some(false),
+                        _ = guard.lost() => Some(true),
+                    };
+                    if let Some(lost) = lost {
+                        if lost {
+                            warn!("Lost the lock of job '{}', stopping it here", self.name);
+                        }
+                        let _ = halt.send(true);
+                        running.as_mut().await;
+                    }
+                    let finished = lost != Some(true);
+                    if finished {
+                        if let Err(e) = guard.release().await {
+                            warn!("Could not release the lock of job '{}': {}", self.name, e);
+                        }
+                        return;
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => warn!("Could not take the lock of job '{}': {}", self.name, e),
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(self.retry + jitter(self.jitter)) => {}
+                _ = stop.wait_for(|s| *s) => return,
+            }
+        }
+    }
+}
+
+/// A random duration up to `max`
+fn jitter(max: Duration) -> Duration {
+    let millis = max.as_millis() as u64;
+    if millis == 0 {
+        return Duration::ZERO;
+    }
+    Duration::from_millis(Uuid::new_v4().as_u128() as u64 % (millis + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const LEASE: Duration = Duration::from_millis(300);
+
+    /// A job that counts how many replicas run it at once and which ran it
+    #[derive(Default)]
+    struct Probe {
+        running: AtomicUsize,
+        peak: AtomicUsize,
+        runs: Mutex<Vec<String>>,
+    }
+
+    /// One replica running the job, until dropped with it
+    struct Running(Arc<Probe>);
+
+    impl Running {
+        fn start(probe: Arc<Probe>, replica: String) -> Self {
+            let now = probe.running.fetch_add(1, Ordering::SeqCst) + 1;
+            probe.peak.fetch_max(now, Ordering::SeqCst);
+            probe.runs.lock().unwrap().push(replica);
+            Self(probe)
+        }
+    }
+
+    impl Drop for Running {
+        fn drop(&mut self) {
+            self.0.running.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    fn spawn_replica(
+        store: Arc<dyn LockStore>,
+        replica: &str,
+        probe: Arc<Probe>,
+        stop: watch::Receiver<bool>,
+    ) -> JoinHandle<()> {
+        let lock = DistributedLock::new(store, replica).with_lease(LEASE);
+        let job = SingletonJob::new("consolidation", lock).with_retry(Duration::from_millis(50), Duration::from_millis(50));
+        let replica = replica.to_string();
+        tokio::spawn(async move {
+            job.run(stop, |mut halted| {
+                let (probe, replica) = (probe.clone(), replica.clone());
+                async move {
+                    let _running = Running::start(probe, replica);
+                    let _ = halted.wait_for(|h| *h).await;
+                }
+            })
+            .await
+        })
+    }
+
+    async fn assert_mutual_exclusion_and_takeover(store: Arc<dyn LockStore>) {
+        let probe = Arc::new(Probe::default());
+        let (stop, stopped) = watch::channel(false);
+        let replicas: Vec<JoinHandle<()>> = ["a", "b", "c"]
+            .iter()
+            .map(|replica| spawn_replica(store.clone(), replica, probe.clone(), stopped.clone()))
+            .collect();
+
+        // Outlive several leases: the holder keeps the job, nobody else runs it
+        tokio::time::sleep(LEASE * 4).await;
+        assert_eq!(probe.peak.load(Ordering::SeqCst), 1);
+        let runs = probe.runs.lock().unwrap().clone();
+        assert_eq!(runs.len(), 1, "{:?}", runs);
+        let first = store.holder("consolidation").await.unwrap().unwrap();
+        assert_eq!(first.holder, runs[0]);
+
+        // The holder dies without releasing; another replica takes over once its lease ran out
+        let dead = ["a", "b", "c"].iter().position(|replica| *replica == first.holder).unwrap();
+        replicas[dead].abort();
+        tokio::time::sleep(LEASE * 3).await;
+        let second = store.holder("consolidation").await.unwrap().unwrap();
+        assert_ne!(second.holder, first.holder);
+        assert!(second.token > first.token);
+        assert_eq!(probe.runs.lock().unwrap().len(), 2);
+        assert_eq!(probe.peak.load(Ordering::SeqCst), 1);
+
+        // Stopping releases the lock right away
+        stop.send(true).unwrap();
+        for (index, replica) in replicas.into_iter().enumerate() {
+            if index != dead {
+                replica.await.unwrap();
+            }
+        }
+        assert!(store.holder("consolidation").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_one_replica_runs_a_job_and_another_takes_over_when_it_dies() {
+        assert_mutual_exclusion_and_takeover(Arc::new(MemoryLockStore::new())).await;
+    }
+
+    #[tokio::test]
+    async fn test_stale_holders_cannot_extend_or_release() {
+        let store = Arc::new(MemoryLockStore::new());
+        let first = DistributedLock::new(store.clone(), "a").with_lease(LEASE);
+        let second = DistributedLock::new(store.clone(), "b").with_lease(LEASE);
+
+        let guard = first.try_acquire("sync").await.unwrap().unwrap();
+        assert!(second.try_acquire("sync").await.unwrap().is_none());
+        let token = guard.token();
+        drop(guard);
+
+        tokio::time::sleep(LEASE + Duration::from_millis(50)).await;
+        let mut taken = second.try_acquire("sync").await.unwrap().unwrap();
+        assert!(taken.token() > token);
+        assert!(!store.extend("sync", "a", token, LEASE).await.unwrap());
+        store.release("sync", "a", token).await.unwrap();
+
+        let status = second.status().await.unwrap();
+        assert_eq!(status, vec![LockStatus {
+            name: "sync".to_string(),
+            holder: Some(LockHolder { holder: "b".to_string(), token: taken.token() }),
+            held_here: true,
+        }]);
+        assert!(!first.status().await.unwrap()[0].held_here);
+
+        // Someone else taking the lock over is noticed by the watchdog
+        store.locks.lock().unwrap().clear();
+        store.acquire("sync", "c", LEASE).await.unwrap().unwrap();
+        tokio::time::timeout(LEASE, taken.lost()).await.unwrap();
+        assert!(taken.is_lost());
+    }
+
+    /// Run with `TEST_REDIS_URL=redis://localhost:6379 cargo test --features redis -- --ignored`
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    #[ignore = "needs a Redis server at TEST_REDIS_URL"]
+    async fn test_redis_one_replica_runs_a_job_and_another_takes_over_when_it_dies() {
+        let url = std::env::var("TEST_REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379".to_string());
+        let client = redis::Client::open(url.as_str()).unwrap();
+        let connection = redis::aio::ConnectionManager::new(client).await.unwrap();
+        let store = RedisLockStore::new(connection, format!("test-lock-{}:", Uuid::new_v4()));
+        assert_mutual_exclusion_and_takeover(Arc::new(store)).await;
+    }
+}
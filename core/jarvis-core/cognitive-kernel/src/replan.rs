@@ -0,0 +1,395 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use anyhow::{Result, anyhow};
+use chrono::{Duration, Utc};
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+
+use crate::executor::PlanOutcome;
+use crate::{DependencyType, ExecutionState, ExecutionTask, IntentExecutionPlan, TaskDependency, TaskStatus, ENVIRONMENT_INPUT};
+
+/// Lowest autonomy tier whose failed plans may be revised unless configured otherwise.
+/// Plans below it, those for critical intents, are left for rollback or a person.
+pub const DEFAULT_MIN_REPLAN_TIER: u8 = 2;
+
+/// Revises a plan that failed partway, keeping the work that already completed.
+///
+/// The revision drops completed tasks and copies their outputs into the inputs of the
+/// tasks that depend on them. The failed task is replaced by its fallbacks, run in the
+/// order given, or else retried as it was. In a plan without dependencies, where tasks
+/// run in plan order, every task counts as depending on those listed before it.
+#[derive(Debug, Clone)]
+pub struct AdaptivePlanner {
+    /// Tasks to run in place of a failed task, by the failed task's name
+    fallbacks: HashMap<String, Vec<ExecutionTask>>,
+    min_autonomy_tier: u8,
+}
+
+impl Default for AdaptivePlanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AdaptivePlanner {
+    pub fn new() -> Self {
+        Self {
+            fallbacks: HashMap::new(),
+            min_autonomy_tier: DEFAULT_MIN_REPLAN_TIER,
+        }
+    }
+
+    /// Replace a failed task named `task_name` with `alternatives`, run one after another
+    pub fn with_fallbacks(mut self, task_name: impl Into<String>, alternatives: Vec<ExecutionTask>) -> Self {
+        self.fallbacks.insert(task_name.into(), alternatives);
+        self
+    }
+
+    /// Lowest autonomy tier a plan must have to be revised
+    pub fn with_min_autonomy_tier(mut self, tier: u8) -> Self {
+        self.min_autonomy_tier = tier;
+        self
+    }
+
+    /// Revise `original` after the run that ended in `outcome` failed. The plan's task
+    /// statuses must be those the run left them with.
+    pub fn replan(&self, original: &IntentExecutionPlan, outcome: &PlanOutcome) -> Result<PlanRevision> {
+        if !matches!(outcome.state, ExecutionState::Failed { .. }) {
+            return Err(anyhow!("Plan {} did not fail, so there is nothing to revise", original.id));
+        }
+        if original.autonomy_tier < self.min_autonomy_tier {
+            return Err(anyhow!(
+                "Plan {} is at autonomy tier {}; tier {} is needed to revise it",
+                original.id, original.autonomy_tier, self.min_autonomy_tier
+            ));
+        }
+        let completed: HashSet<Uuid> = original.tasks.iter()
+            .filter(|task| outcome.outputs.contains_key(&task.id))
+            .map(|task| task.id)
+            .collect();
+        // The task the run stopped at: failed, or ended in some other state than completed
+        let failed = original.tasks.iter()
+            .find(|task| !completed.contains(&task.id) && !matches!(task.status, TaskStatus::Pending))
+            .ok_or_else(|| anyhow!("Plan {} has no failed task to revise around", original.id))?;
+
+        // The failed task's replacements, or the task itself to retry it
+        let replacements: Vec<ExecutionTask> = match self.fallbacks.get(&failed.name) {
+            Some(alternatives) if !alternatives.is_empty() => alternatives.iter()
+                .map(|alternative| {
+                    let mut replacement = ExecutionTask { id: Uuid::new_v4(), status: TaskStatus::Pending, ..alternative.clone() };
+                    // Alternatives run against the environment the plan targets
+                    if let Some(environment) = &original.environment {
+                        replacement.inputs.insert(ENVIRONMENT_INPUT.to_string(), serde_json::json!(environment));
+                    }
+                    replacement
+                })
+                .collect(),
+            _ => vec![ExecutionTask { status: TaskStatus::Pending, ..failed.clone() }],
+        };
+        let replaced = replacements[0].id != failed.id;
+
+        let mut tasks = Vec::new();
+        let mut changes: HashMap<Uuid, Vec<String>> = HashMap::new();
+        for task in &original.tasks {
+            if completed.contains(&task.id) {
+                continue;
+            }
+            if task.id != failed.id {
+                tasks.push(ExecutionTask { status: TaskStatus::Pending, ..task.clone() });
+                continue;
+            }
+            tasks.extend(replacements.iter().cloned());
+            if !replaced {
+                changes.entry(failed.id).or_default().push(format!("retried after it failed as {:?}", failed.status));
+            }
+        }
+
+        // Completed work flows into what depended on it
+        for task in tasks.iter_mut() {
+            let is_replacement = replacements.iter().any(|r| r.id == task.id);
+            let original_id = if is_replacement { failed.id } else { task.id };
+            for source in depends_on(original, original_id) {
+                if !completed.contains(&source.id) {
+                    continue;
+                }
+                let mut outputs: Vec<_> = outcome.outputs[&source.id].iter().collect();
+                outputs.sort_by_key(|(name, _)| *name);
+                for (name, value) in outputs {
+                    task.inputs.insert(name.clone(), value.clone());
+                    // Added tasks are listed whole; only tasks kept from the plan are modified
+                    if !(replaced && is_replacement) {
+                        changes.entry(task.id).or_default().push(format!("input '{}' imported from '{}'", name, source.name));
+                    }
+                }
+            }
+        }
+
+        let dependencies = revise_dependencies(original, &completed, failed.id, &replacements, &mut changes);
+
+        let estimated_duration = original.estimated_duration
+            - total_duration(original.tasks.iter().filter(|task| completed.contains(&task.id) || task.id == failed.id))
+            + total_duration(&replacements);
+        let remaining: HashSet<Uuid> = tasks.iter().map(|task| task.id).collect();
+
+        let plan = IntentExecutionPlan {
+            id: Uuid::new_v4(),
+            intent_id: original.intent_id,
+            tasks,
+            dependencies,
+            estimated_duration,
+            autonomy_tier: original.autonomy_tier,
+            checkpoints: original.checkpoints.iter().filter(|c| remaining.contains(&c.task_id)).cloned().collect(),
+            rollback_plan: original.rollback_plan.clone(),
+            budget: original.budget.clone(),
+            domain: original.domain.clone(),
+            risk_level: original.risk_level,
+            environment: original.environment.clone(),
+            created_at: Utc::now(),
+        };
+
+        let mut removed: Vec<RemovedTask> = original.tasks.iter()
+            .filter(|task| completed.contains(&task.id))
+            .map(|task| RemovedTask { task: TaskSummary::of(task), reason: RemovalReason::Completed })
+            .collect();
+        let mut added = Vec::new();
+        if replaced {
+            removed.push(RemovedTask { task: TaskSummary::of(failed), reason: RemovalReason::Replaced });
+            added = replacements.iter().map(TaskSummary::of).collect();
+        }
+        let modified = plan.tasks.iter()
+            .filter_map(|task| Some(ModifiedTask { task: TaskSummary::of(task), changes: changes.remove(&task.id)? }))
+            .collect();
+
+        let diff = PlanDiff {
+            added,
+            removed,
+            modified,
+            duration_delta: plan.estimated_duration - original.estimated_duration,
+        };
+        Ok(PlanRevision { replaces: original.id, plan, diff })
+    }
+}
+
+fn total_duration<'a>(tasks: impl IntoIterator<Item = &'a ExecutionTask>) -> Duration {
+    tasks.into_iter().fold(Duration::zero(), |total, task| total + task.estimated_duration)
+}
+
+/// Tasks of `plan` that the task `task_id` depends on
+fn depends_on(plan: &IntentExecutionPlan, task_id: Uuid) -> Vec<&ExecutionTask> {
+    if plan.dependencies.is_empty() {
+        return plan.tasks.iter().take_while(|task| task.id != task_id).collect();
+    }
+    plan.dependencies.iter()
+        .filter(|dependency| dependency.to_task == task_id)
+        .filter_map(|dependency| plan.tasks.iter().find(|task| task.id == dependency.from_task))
+        .collect()
+}
+
+/// The original dependencies among the remaining tasks, with the failed task's taken
+/// over by its replacements, which run in sequence: the first after what the failed task
+/// came after, and the last before what came after it
+fn revise_dependencies(
+    original: &IntentExecutionPlan,
+    completed: &HashSet<Uuid>,
+    failed: Uuid,
+    replacements: &[ExecutionTask],
+    changes: &mut HashMap<Uuid, Vec<String>>,
+) -> Vec<TaskDependency> {
+    let (first, last) = (replacements[0].id, replacements[replacements.len() - 1].id);
+    let name = |id: Uuid| {
+        original.tasks.iter().chain(replacements).find(|task| task.id == id).map_or(String::new(), |task| task.name.clone())
+    };
+
+    let mut dependencies = Vec::new();
+    for dependency in &original.dependencies {
+        if completed.contains(&dependency.from_task) {
+            continue;
+        }
+        let mut revised = dependency.clone();
+        if revised.to_task == failed {
+            revised.to_task = first;
+        }
+        if revised.from_task == failed {
+            revised.from_task = last;
+            if last != failed {
+                changes.entry(revised.to_task).or_default()
+                    .push(format!("now runs after '{}' instead of '{}'", name(last), name(failed)));
+            }
+        }
+        dependencies.push(revised);
+    }
+    if !original.dependencies.is_empty() {
+        for pair in replacements.windows(2) {
+            dependencies.push(TaskDependency {
+                from_task: pair[0].id,
+                to_task: pair[1].id,
+                dependency_type: DependencyType::Sequential,
+            });
+        }
+    }
+    dependencies
+}
+
+/// A revised plan and how it differs from the one it replaces
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanRevision {
+    /// Id of the plan the revision replaces
+    pub replaces: Uuid,
+    pub plan: IntentExecutionPlan,
+    pub diff: PlanDiff,
+}
+
+/// What a revision changes, for review before it is approved
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PlanDiff {
+    pub added: Vec<TaskSummary>,
+    pub removed: Vec<RemovedTask>,
+    pub modified: Vec<ModifiedTask>,
+    /// Change in the plan's estimated duration; negative when completed work is dropped
+    pub duration_delta: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskSummary {
+    pub id: Uuid,
+    pub name: String,
+    pub agent_type: String,
+    pub estimated_duration: Duration,
+}
+
+impl TaskSummary {
+    fn of(task: &ExecutionTask) -> Self {
+        Self {
+            id: task.id,
+            name: task.name.clone(),
+            agent_type: task.agent_type.clone(),
+            estimated_duration: task.estimated_duration,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RemovedTask {
+    pub task: TaskSummary,
+    pub reason: RemovalReason,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RemovalReason {
+    /// Already done; its outputs are passed on as inputs
+    Completed,
+    /// Failed and replaced by fallback tasks
+    Replaced,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModifiedTask {
+    pub task: TaskSummary,
+    /// What changed, one line each
+    pub changes: Vec<String>,
+}
+
+impl fmt::Display for PlanDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for removed in &self.removed {
+            let reason = match removed.reason {
+                RemovalReason::Completed => "completed",
+                RemovalReason::Replaced => "replaced",
+            };
+            writeln!(f, "- {} ({})", removed.task.name, reason)?;
+        }
+        for added in &self.added {
+            writeln!(f, "+ {} [{}, {}m]", added.name, added.agent_type, added.estimated_duration.num_minutes())?;
+        }
+        for modified in &self.modified {
+            writeln!(f, "~ {}: {}", modified.task.name, modified.changes.join("; "))?;
+        }
+        write!(f, "Estimated duration: {:+}m", self.duration_delta.num_minutes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::budget::{BudgetUsage, PlanBudget};
+    use crate::TaskType;
+
+    fn task(name: &str, status: TaskStatus) -> ExecutionTask {
+        ExecutionTask {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            description: String::new(),
+            task_type: TaskType::Execute,
+            agent_type: "a".to_string(),
+            inputs: HashMap::new(),
+            expected_outputs: vec![format!("{}.json", name)],
+            estimated_duration: Duration::minutes(5),
+            status,
+            dry_run_first: false,
+            tags: Vec::new(),
+            output_schemas: HashMap::new(),
+        }
+    }
+
+    /// A plan without dependencies whose second task failed after the first completed
+    fn failed_run(autonomy_tier: u8) -> (IntentExecutionPlan, PlanOutcome) {
+        let tasks = vec![task("fetch", TaskStatus::Completed), task("broken", TaskStatus::Failed), task("report", TaskStatus::Pending)];
+        let outputs = HashMap::from([(tasks[0].id, HashMap::from([("fetch.json".to_string(), serde_json::json!([1, 2]))]))]);
+        let plan = IntentExecutionPlan {
+            id: Uuid::new_v4(),
+            intent_id: Uuid::new_v4(),
+            tasks,
+            dependencies: Vec::new(),
+            estimated_duration: Duration::minutes(15),
+            autonomy_tier,
+            checkpoints: Vec::new(),
+            rollback_plan: None,
+            budget: PlanBudget::default(),
+            domain: None,
+            risk_level: None,
+            environment: None,
+            created_at: Utc::now(),
+        };
+        let outcome = PlanOutcome {
+            state: ExecutionState::Failed { error: "Task 'broken' failed: exploded".to_string() },
+            outputs,
+            mismatches: Vec::new(),
+            usage: BudgetUsage::default(),
+            rolled_back: Vec::new(),
+        };
+        (plan, outcome)
+    }
+
+    #[test]
+    fn test_failed_task_without_fallbacks_is_retried() {
+        let (plan, outcome) = failed_run(3);
+        let revision = AdaptivePlanner::new().replan(&plan, &outcome).unwrap();
+
+        let tasks = &revision.plan.tasks;
+        assert_eq!((tasks[0].id, tasks[1].id), (plan.tasks[1].id, plan.tasks[2].id));
+        assert!(tasks.iter().all(|t| matches!(t.status, TaskStatus::Pending)));
+        // Without dependencies, later tasks see everything completed before them
+        assert!(tasks.iter().all(|t| t.inputs["fetch.json"] == serde_json::json!([1, 2])));
+
+        assert!(revision.diff.added.is_empty());
+        assert_eq!(revision.diff.removed, vec![RemovedTask { task: TaskSummary::of(&plan.tasks[0]), reason: RemovalReason::Completed }]);
+        let changes: Vec<&[String]> = revision.diff.modified.iter().map(|m| m.changes.as_slice()).collect();
+        assert_eq!(changes, [
+            &["retried after it failed as Failed".to_string(), "input 'fetch.json' imported from 'fetch'".to_string()][..],
+            &["input 'fetch.json' imported from 'fetch'".to_string()][..],
+        ]);
+        assert_eq!(revision.diff.duration_delta, Duration::minutes(-5));
+    }
+
+    #[test]
+    fn test_only_failed_plans_at_a_high_enough_tier_are_revised() {
+        let (plan, outcome) = failed_run(1);
+        let err = AdaptivePlanner::new().replan(&plan, &outcome).unwrap_err();
+        assert_eq!(err.to_string(), format!("Plan {} is at autonomy tier 1; tier 2 is needed to revise it", plan.id));
+        assert!(AdaptivePlanner::new().with_min_autonomy_tier(1).replan(&plan, &outcome).is_ok());
+
+        let (plan, mut outcome) = failed_run(3);
+        outcome.state = ExecutionState::Completed;
+        assert!(AdaptivePlanner::new().replan(&plan, &outcome).unwrap_err().to_string().contains("did not fail"));
+    }
+}
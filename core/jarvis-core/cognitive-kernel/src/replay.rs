@@ -0,0 +1,161 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use anyhow::{Context, Result, anyhow};
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+
+use crate::executor::TaskOutput;
+use crate::ExecutionTask;
+
+/// Task runs recorded from a live execution, keyed by `<task id>#<attempt>`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReplayBundle {
+    pub runs: BTreeMap<String, RecordedRun>,
+}
+
+/// One `TaskRunner::run` call and what it returned
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedRun {
+    pub task_id: Uuid,
+    pub task_name: String,
+    pub attempt: u32,
+    /// Hash of `input`, compared on replay to detect a changed task
+    pub input_hash: String,
+    /// The parts of the task a runner acts on
+    pub input: serde_json::Value,
+    pub result: RecordedResult,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RecordedResult {
+    Output(TaskOutput),
+    Error(String),
+}
+
+/// Why a task could not be served from the bundle
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReplayMismatch {
+    Missing { task_id: Uuid, task_name: String, attempt: u32 },
+    InputChanged { task_id: Uuid, task_name: String, recorded: String, current: String },
+}
+
+impl fmt::Display for ReplayMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayMismatch::Missing { task_name, attempt, .. } =>
+                write!(f, "Task '{}' attempt {} is not in the replay bundle", task_name, attempt),
+            ReplayMismatch::InputChanged { task_name, recorded, current, .. } =>
+                write!(f, "Task '{}' input changed since it was recorded ({} -> {})", task_name, recorded, current),
+        }
+    }
+}
+
+/// What replay does with a task the bundle cannot answer for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MismatchPolicy {
+    /// Fail the task with the mismatch as its error
+    Fail,
+    /// Run just that task with the live runner
+    RunLive,
+}
+
+impl ReplayBundle {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read replay bundle {}", path.display()))?;
+        serde_json::from_str(&content).with_context(|| format!("Invalid replay bundle {}", path.display()))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write replay bundle {}", path.display()))
+    }
+
+    pub fn record(&mut self, task: &ExecutionTask, attempt: u32, result: &Result<TaskOutput>) {
+        let input = task_input(task);
+        let run = RecordedRun {
+            task_id: task.id,
+            task_name: task.name.clone(),
+            attempt,
+            input_hash: input_hash(&input),
+            input,
+            result: match result {
+                Ok(output) => RecordedResult::Output(output.clone()),
+                Err(e) => RecordedResult::Error(e.to_string()),
+            },
+        };
+        self.runs.insert(run_key(task.id, attempt), run);
+    }
+
+    /// The recorded result for this attempt at `task`, provided the task is unchanged
+    pub fn lookup(&self, task: &ExecutionTask, attempt: u32) -> Result<Result<TaskOutput>, ReplayMismatch> {
+        let run = self.runs.get(&run_key(task.id, attempt)).ok_or_else(|| ReplayMismatch::Missing {
+            task_id: task.id,
+            task_name: task.name.clone(),
+            attempt,
+        })?;
+        let current = input_hash(&task_input(task));
+        if run.input_hash != current {
+            return Err(ReplayMismatch::InputChanged {
+                task_id: task.id,
+                task_name: task.name.clone(),
+                recorded: run.input_hash.clone(),
+                current,
+            });
+        }
+        Ok(match &run.result {
+            RecordedResult::Output(output) => Ok(output.clone()),
+            RecordedResult::Error(message) => Err(anyhow!("{}", message)),
+        })
+    }
+}
+
+/// How a `PlanExecutor` treats `TaskRunner` calls
+pub(crate) enum ReplayMode {
+    Live,
+    /// Run live and write every call to the bundle at `path` as it happens
+    Record { path: PathBuf, bundle: Mutex<ReplayBundle> },
+    /// Answer calls from the bundle
+    Replay { bundle: ReplayBundle, policy: MismatchPolicy },
+}
+
+/// Counts runs per task so repeated runs of a task get their own bundle entries
+#[derive(Default)]
+pub(crate) struct Attempts(Mutex<HashMap<Uuid, u32>>);
+
+impl Attempts {
+    pub(crate) fn next(&self, task_id: Uuid) -> u32 {
+        let mut attempts = self.0.lock().unwrap();
+        let attempt = attempts.entry(task_id).or_insert(0);
+        *attempt += 1;
+        *attempt
+    }
+}
+
+fn run_key(task_id: Uuid, attempt: u32) -> String {
+    format!("{}#{}", task_id, attempt)
+}
+
+/// Everything a runner sees of a task except its mutable status
+fn task_input(task: &ExecutionTask) -> serde_json::Value {
+    serde_json::json!({
+        "name": task.name,
+        "task_type": task.task_type,
+        "agent_type": task.agent_type,
+        "inputs": task.inputs,
+        "expected_outputs": task.expected_outputs,
+        "dry_run_first": task.dry_run_first,
+    })
+}
+
+/// FNV-1a over the canonical JSON, which `serde_json::Value` gives by sorting object keys
+fn input_hash(input: &serde_json::Value) -> String {
+    let hash = input.to_string().bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
@@ -0,0 +1,353 @@
+//! Namespaced, typed access to the kernel's global state.
+//!
+//! Each namespace holds one value, read and written through a `StateHandle<T>` that
+//! (de)serializes it as `T`. Updates are read-modify-write under the namespace's map
+//! entry, so concurrent updates never lose each other's changes. Every write is
+//! broadcast to the namespace's subscribers. Namespaces selected for persistence are
+//! also written through to a `StatePersistence`, such as Redis, and loaded back by
+//! `KernelState::restore`; updates are atomic within one process only, and across
+//! replicas the last write wins.
+//!
+//! Namespaces starting with `kernel.` are written by the kernel itself; see
+//! [`namespaces`].
+
+use std::collections::HashSet;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::sync::broadcast;
+
+/// Namespaces the kernel writes itself. Others may read them but should not write them.
+pub mod namespaces {
+    /// Prefix of every reserved namespace
+    pub const RESERVED_PREFIX: &str = "kernel.";
+
+    /// `PlanningStats`: how many plans the kernel has made, and the latest one
+    pub const PLANNING: &str = "kernel.planning";
+
+    /// Whether `namespace` is reserved for the kernel
+    pub fn is_reserved(namespace: &str) -> bool {
+        namespace.starts_with(RESERVED_PREFIX)
+    }
+}
+
+/// Changes a subscriber may fall behind by before it misses some
+const CHANGE_CHANNEL_CAPACITY: usize = 64;
+
+#[derive(Debug, Error)]
+pub enum StateError {
+    #[error("State namespace '{namespace}' does not hold the requested type: {source}")]
+    Type {
+        namespace: String,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+/// A namespace's new value, as broadcast to its subscribers
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateChange {
+    pub namespace: String,
+    pub value: serde_json::Value,
+    pub changed_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Where persisted namespaces are kept between restarts
+#[async_trait]
+pub trait StatePersistence: Send + Sync {
+    async fn load(&self, namespace: &str) -> Result<Option<serde_json::Value>>;
+    async fn save(&self, namespace: &str, value: &serde_json::Value) -> Result<()>;
+}
+
+/// What the kernel records under [`namespaces::PLANNING`]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PlanningStats {
+    pub plans_created: u64,
+    pub last_plan_id: Option<uuid::Uuid>,
+}
+
+/// The kernel's global state, by namespace
+pub struct KernelState {
+    values: Arc<DashMap<String, serde_json::Value>>,
+    channels: DashMap<String, broadcast::Sender<StateChange>>,
+    persistence: Option<Arc<dyn StatePersistence>>,
+    persisted: HashSet<String>,
+}
+
+impl std::fmt::Debug for KernelState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KernelState")
+            .field("namespaces", &self.values.len())
+            .field("persisted", &self.persisted)
+            .finish()
+    }
+}
+
+impl KernelState {
+    /// State kept in `values`, shared with whoever else holds the map
+    pub fn new(values: Arc<DashMap<String, serde_json::Value>>) -> Self {
+        Self {
+            values,
+            channels: DashMap::new(),
+            persistence: None,
+            persisted: HashSet::new(),
+        }
+    }
+
+    /// Write `namespaces` through to `persistence` as they change
+    pub fn with_persistence(
+        mut self,
+        persistence: Arc<dyn StatePersistence>,
+        namespaces: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.persistence = Some(persistence);
+        self.persisted = namespaces.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Load every persisted namespace that has a stored value, returning how many did
+    pub async fn restore(&self) -> Result<usize> {
+        let Some(persistence) = &self.persistence else {
+            return Ok(0);
+        };
+        let mut restored = 0;
+        for namespace in &self.persisted {
+            if let Some(value) = persistence.load(namespace).await? {
+                self.values.insert(namespace.clone(), value.clone());
+                self.notify(namespace, value);
+                restored += 1;
+            }
+        }
+        Ok(restored)
+    }
+
+    pub fn handle<T: Serialize + DeserializeOwned>(self: &Arc<Self>, namespace: &str) -> StateHandle<T> {
+        StateHandle {
+            state: Arc::clone(self),
+            namespace: namespace.to_string(),
+            _type: PhantomData,
+        }
+    }
+
+    /// Changes to `namespace` from now on
+    pub fn subscribe(&self, namespace: &str) -> broadcast::Receiver<StateChange> {
+        self.channels
+            .entry(namespace.to_string())
+            .or_insert_with(|| broadcast::channel(CHANGE_CHANNEL_CAPACITY).0)
+            .subscribe()
+    }
+
+    pub fn get_raw(&self, namespace: &str) -> Option<serde_json::Value> {
+        self.values.get(namespace).map(|v| v.clone())
+    }
+
+    /// Replace a namespace's value without persisting it
+    pub fn set_raw(&self, namespace: &str, value: serde_json::Value) {
+        self.values.insert(namespace.to_string(), value.clone());
+        self.notify(namespace, value);
+    }
+
+    fn notify(&self, namespace: &str, value: serde_json::Value) {
+        if let Some(sender) = self.channels.get(namespace) {
+            // No receivers is not an error: nobody is listening
+            let _ = sender.send(StateChange {
+                namespace: namespace.to_string(),
+                value,
+                changed_at: chrono::Utc::now(),
+            });
+        }
+    }
+
+    async fn persist(&self, namespace: &str, value: &serde_json::Value) -> Result<()> {
+        match &self.persistence {
+            Some(persistence) if self.persisted.contains(namespace) => persistence.save(namespace, value).await,
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Typed access to one namespace
+pub struct StateHandle<T> {
+    state: Arc<KernelState>,
+    namespace: String,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T: Serialize + DeserializeOwned> StateHandle<T> {
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    pub fn get(&self) -> Result<Option<T>> {
+        self.state.get_raw(&self.namespace).map(|value| self.decode(value)).transpose()
+    }
+
+    /// Replace the namespace's value, notify subscribers and persist it if selected
+    pub async fn set(&self, value: T) -> Result<()> {
+        let value = serde_json::to_value(value)?;
+        self.state.set_raw(&self.namespace, value.clone());
+        self.state.persist(&self.namespace, &value).await
+    }
+
+    /// Replace the namespace's value with `f` of the current one, atomically with respect
+    /// to other writers in this process, returning the new value. `f` runs while the
+    /// namespace is locked, so it must not use the kernel's state itself.
+    pub async fn update<F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(Option<T>) -> T,
+    {
+        let (updated, value) = match self.state.values.entry(self.namespace.clone()) {
+            Entry::Occupied(mut entry) => {
+                let current = self.decode(entry.get().clone())?;
+                let updated = f(Some(current));
+                let value = serde_json::to_value(&updated)?;
+                entry.insert(value.clone());
+                (updated, value)
+            }
+            Entry::Vacant(entry) => {
+                let updated = f(None);
+                let value = serde_json::to_value(&updated)?;
+                entry.insert(value.clone());
+                (updated, value)
+            }
+        };
+        self.state.notify(&self.namespace, value.clone());
+        self.state.persist(&self.namespace, &value).await?;
+        Ok(updated)
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<StateChange> {
+        self.state.subscribe(&self.namespace)
+    }
+
+    fn decode(&self, value: serde_json::Value) -> Result<T> {
+        serde_json::from_value(value).map_err(|source| {
+            StateError::Type { namespace: self.namespace.clone(), source }.into()
+        })
+    }
+}
+
+/// Persists namespaces as JSON strings under `<prefix><namespace>` in Redis
+#[cfg(feature = "redis")]
+pub struct RedisStatePersistence {
+    connection: redis::aio::ConnectionManager,
+    prefix: String,
+}
+
+#[cfg(feature = "redis")]
+impl RedisStatePersistence {
+    pub fn new(connection: redis::aio::ConnectionManager, prefix: impl Into<String>) -> Self {
+        Self { connection, prefix: prefix.into() }
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl StatePersistence for RedisStatePersistence {
+    async fn load(&self, namespace: &str) -> Result<Option<serde_json::Value>> {
+        let mut connection = self.connection.clone();
+        let stored: Option<String> = redis::AsyncCommands::get(&mut connection, format!("{}{}", self.prefix, namespace)).await?;
+        Ok(stored.map(|json| serde_json::from_str(&json)).transpose()?)
+    }
+
+    async fn save(&self, namespace: &str, value: &serde_json::Value) -> Result<()> {
+        let mut connection = self.connection.clone();
+        redis::AsyncCommands::set::<_, _, ()>(&mut connection, format!("{}{}", self.prefix, namespace), value.to_string()).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct MemoryPersistence {
+        saved: Mutex<std::collections::HashMap<String, serde_json::Value>>,
+    }
+
+    #[async_trait]
+    impl StatePersistence for MemoryPersistence {
+        async fn load(&self, namespace: &str) -> Result<Option<serde_json::Value>> {
+            Ok(self.saved.lock().unwrap().get(namespace).cloned())
+        }
+
+        async fn save(&self, namespace: &str, value: &serde_json::Value) -> Result<()> {
+            self.saved.lock().unwrap().insert(namespace.to_string(), value.clone());
+            Ok(())
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_updates_lose_no_increments() {
+        let state = Arc::new(KernelState::new(Arc::new(DashMap::new())));
+        let mut tasks = tokio::task::JoinSet::new();
+        for _ in 0..16 {
+            let counter = state.handle::<u64>("counters.requests");
+            tasks.spawn(async move {
+                for _ in 0..250 {
+                    counter.update(|n| n.unwrap_or(0) + 1).await.unwrap();
+                }
+            });
+        }
+        while let Some(result) = tasks.join_next().await {
+            result.unwrap();
+        }
+        assert_eq!(state.handle::<u64>("counters.requests").get().unwrap(), Some(4000));
+
+        // Reading a namespace as the wrong type fails instead of yielding a default
+        let err = state.handle::<String>("counters.requests").get().unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(StateError::Type { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_each_set_notifies_once() {
+        let state = Arc::new(KernelState::new(Arc::new(DashMap::new())));
+        let mode = state.handle::<String>("ui.mode");
+        let mut changes = mode.subscribe();
+        let mut other = state.subscribe("ui.theme");
+
+        mode.set("focus".to_string()).await.unwrap();
+        mode.update(|_| "review".to_string()).await.unwrap();
+
+        assert_eq!(changes.recv().await.unwrap().value, "focus");
+        let change = changes.recv().await.unwrap();
+        assert_eq!((change.namespace.as_str(), change.value), ("ui.mode", serde_json::json!("review")));
+        assert!(matches!(changes.try_recv(), Err(broadcast::error::TryRecvError::Empty)));
+        assert!(matches!(other.try_recv(), Err(broadcast::error::TryRecvError::Empty)));
+    }
+
+    #[tokio::test]
+    async fn test_selected_namespaces_survive_a_restart() {
+        let persistence = Arc::new(MemoryPersistence::default());
+        let first = Arc::new(
+            KernelState::new(Arc::new(DashMap::new())).with_persistence(persistence.clone(), [namespaces::PLANNING]),
+        );
+        first.handle::<PlanningStats>(namespaces::PLANNING)
+            .update(|stats| {
+                let mut stats = stats.unwrap_or_default();
+                stats.plans_created += 1;
+                stats
+            })
+            .await
+            .unwrap();
+        first.handle::<bool>("ui.busy").set(true).await.unwrap();
+        assert_eq!(persistence.saved.lock().unwrap().len(), 1);
+
+        let second = Arc::new(
+            KernelState::new(Arc::new(DashMap::new())).with_persistence(persistence, [namespaces::PLANNING]),
+        );
+        assert_eq!(second.restore().await.unwrap(), 1);
+        let stats = second.handle::<PlanningStats>(namespaces::PLANNING).get().unwrap().unwrap();
+        assert_eq!(stats.plans_created, 1);
+        assert_eq!(second.handle::<bool>("ui.busy").get().unwrap(), None);
+    }
+}
@@ -0,0 +1,72 @@
+//! W3C trace context propagation, so one intent's spans form a single trace from the
+//! api-server through the kernel, the agent mesh and every outgoing HTTP call.
+//!
+//! Spans across crates share the attribute names `intent_id`, `plan_id`, `task_id` and
+//! `agent_id`. Without an OpenTelemetry layer installed the current span has no trace
+//! context, so the helpers here produce no headers and ignore parents.
+
+use std::collections::HashMap;
+
+use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry::trace::TraceContextExt;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Header carrying the trace and parent span ids
+pub const TRACEPARENT: &str = "traceparent";
+
+/// Trace context headers for an outgoing request made within the current span
+pub fn trace_headers() -> HashMap<String, String> {
+    let mut headers = HashMap::new();
+    TraceContextPropagator::new().inject_context(&Span::current().context(), &mut headers);
+    headers
+}
+
+/// `traceparent` of the current span, for work that is handed on beyond it
+pub fn current_traceparent() -> Option<String> {
+    trace_headers().remove(TRACEPARENT)
+}
+
+/// Parent `span` on the span a `traceparent` value names; a missing or malformed value
+/// leaves `span` where it is
+pub fn set_parent(span: &Span, traceparent: Option<&str>) {
+    let Some(traceparent) = traceparent else {
+        return;
+    };
+    let carrier = HashMap::from([(TRACEPARENT.to_string(), traceparent.to_string())]);
+    let context = TraceContextPropagator::new().extract(&carrier);
+    if context.span().span_context().is_valid() {
+        span.set_parent(context);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_sdk::trace::TracerProvider;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    const REMOTE: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn test_headers_carry_the_trace_a_span_was_parented_on() {
+        assert!(trace_headers().is_empty());
+
+        // Tracers only hold on to their provider weakly
+        let provider = TracerProvider::builder().build();
+        let tracer = provider.tracer("test");
+        let subscriber = tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("request");
+            set_parent(&span, Some("not-a-traceparent"));
+            set_parent(&span, Some(REMOTE));
+            let _entered = span.enter();
+
+            let traceparent = current_traceparent().unwrap();
+            assert!(traceparent.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+            assert!(!traceparent.contains("00f067aa0ba902b7"));
+        });
+    }
+}
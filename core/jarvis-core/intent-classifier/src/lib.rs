@@ -0,0 +1,328 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::{Mutex, RwLock};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Domain assigned to text that matches no domain pattern
+pub const GENERAL_DOMAIN: &str = "general";
+
+/// Number of distinct texts whose classification is remembered by default
+pub const DEFAULT_CACHE_CAPACITY: usize = 256;
+
+/// Risk of acting on an intent, ordered from least to most severe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum RiskLevel {
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Classification {
+    pub domain: String,
+    pub risk_level: RiskLevel,
+}
+
+/// Additional patterns, in the shape of a pattern config file:
+///
+/// ```json
+/// { "domains": { "payments": ["invoice", "refund"] }, "risks": { "Critical": ["wire transfer"] } }
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PatternSet {
+    #[serde(default)]
+    pub domains: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    pub risks: BTreeMap<RiskLevel, Vec<String>>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// Keyword classifier for intent domain and risk.
+///
+/// A domain scores one point per pattern found in the text and the highest score wins,
+/// ties going to the domain registered first. Risk is the most severe level with any
+/// matching pattern. Results are cached by normalized text; registering patterns clears
+/// the cache so later calls see them.
+#[derive(Debug)]
+pub struct IntentClassifier {
+    patterns: RwLock<Patterns>,
+    cache: Mutex<ClassificationCache>,
+}
+
+impl IntentClassifier {
+    pub fn new() -> Self {
+        Self::with_cache_capacity(DEFAULT_CACHE_CAPACITY)
+    }
+
+    /// Classifier with the built-in patterns and room for `capacity` cached texts
+    pub fn with_cache_capacity(capacity: usize) -> Self {
+        let classifier = Self {
+            patterns: RwLock::new(Patterns::default()),
+            cache: Mutex::new(ClassificationCache::new(capacity)),
+        };
+
+        classifier.register_domain_patterns("infra_deployment", [
+            "deploy", "kubernetes", "docker", "container", "server", "staging", "production", "infrastructure",
+        ]);
+        classifier.register_domain_patterns("database_admin", [
+            "database", "postgres", "sql", "migration", "schema", "backup",
+        ]);
+        classifier.register_domain_patterns("marketing_content", [
+            "marketing", "content", "blog", "social", "email", "campaign",
+        ]);
+
+        classifier.register_risk_patterns(RiskLevel::Critical, ["production", "delete", "drop", "destroy", "rm -rf"]);
+        classifier.register_risk_patterns(RiskLevel::High, ["modify", "update", "migrate", "change"]);
+        classifier.register_risk_patterns(RiskLevel::Medium, ["create", "add", "install", "deploy"]);
+
+        classifier
+    }
+
+    pub fn classify(&self, text: &str) -> Classification {
+        self.classify_many(&[text]).remove(0)
+    }
+
+    /// Classify a batch under a single lock acquisition, in input order
+    pub fn classify_many<S: AsRef<str>>(&self, texts: &[S]) -> Vec<Classification> {
+        let patterns = self.patterns.read().unwrap();
+        let mut cache = self.cache.lock().unwrap();
+
+        texts.iter()
+            .map(|text| {
+                let key = normalize(text.as_ref());
+                cache.get(&key).unwrap_or_else(|| {
+                    let classification = patterns.classify(&key);
+                    cache.insert(key, classification.clone());
+                    classification
+                })
+            })
+            .collect()
+    }
+
+    pub fn classify_domain(&self, text: &str) -> String {
+        self.classify(text).domain
+    }
+
+    pub fn assess_risk(&self, text: &str) -> RiskLevel {
+        self.classify(text).risk_level
+    }
+
+    /// Add keywords for a domain, creating the domain if it is new
+    pub fn register_domain_patterns<I, S>(&self, domain: &str, patterns: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut all = self.patterns.write().unwrap();
+        let keywords = match all.domains.iter().position(|(name, _)| name == domain) {
+            Some(index) => &mut all.domains[index].1,
+            None => {
+                all.domains.push((domain.to_string(), Vec::new()));
+                &mut all.domains.last_mut().unwrap().1
+            }
+        };
+        keywords.extend(patterns.into_iter().map(|p| normalize(p.as_ref())));
+        self.cache.lock().unwrap().clear();
+    }
+
+    /// Add keywords that raise matching text to `level`
+    pub fn register_risk_patterns<I, S>(&self, level: RiskLevel, patterns: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut all = self.patterns.write().unwrap();
+        all.risks.entry(level).or_default().extend(patterns.into_iter().map(|p| normalize(p.as_ref())));
+        self.cache.lock().unwrap().clear();
+    }
+
+    pub fn register_patterns(&self, patterns: &PatternSet) {
+        for (domain, keywords) in &patterns.domains {
+            self.register_domain_patterns(domain, keywords);
+        }
+        for (level, keywords) in &patterns.risks {
+            self.register_risk_patterns(*level, keywords);
+        }
+    }
+
+    /// Register the patterns in a JSON pattern file
+    pub fn load_patterns(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read intent patterns from {}", path.display()))?;
+        let patterns: PatternSet = serde_json::from_str(&content)
+            .with_context(|| format!("Invalid intent patterns in {}", path.display()))?;
+        self.register_patterns(&patterns);
+        Ok(())
+    }
+
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache.lock().unwrap().stats
+    }
+}
+
+impl Default for IntentClassifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Lowercase with runs of whitespace collapsed, so trivially different texts share a cache entry
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+#[derive(Debug, Default)]
+struct Patterns {
+    domains: Vec<(String, Vec<String>)>,
+    risks: BTreeMap<RiskLevel, Vec<String>>,
+}
+
+impl Patterns {
+    fn classify(&self, text: &str) -> Classification {
+        let mut domain = GENERAL_DOMAIN;
+        let mut best_score = 0;
+        for (name, keywords) in &self.domains {
+            let score = keywords.iter().filter(|k| text.contains(k.as_str())).count();
+            if score > best_score {
+                domain = name;
+                best_score = score;
+            }
+        }
+
+        let risk_level = self.risks.iter()
+            .rev()
+            .find(|(_, keywords)| keywords.iter().any(|k| text.contains(k.as_str())))
+            .map(|(level, _)| *level)
+            .unwrap_or(RiskLevel::Low);
+
+        Classification { domain: domain.to_string(), risk_level }
+    }
+}
+
+/// Least-recently-used cache of classifications keyed by normalized text
+#[derive(Debug)]
+struct ClassificationCache {
+    capacity: usize,
+    entries: HashMap<String, (Classification, u64)>,
+    clock: u64,
+    stats: CacheStats,
+}
+
+impl ClassificationCache {
+    fn new(capacity: usize) -> Self {
+        Self { capacity, entries: HashMap::new(), clock: 0, stats: CacheStats::default() }
+    }
+
+    fn get(&mut self, key: &str) -> Option<Classification> {
+        self.clock += 1;
+        match self.entries.get_mut(key) {
+            Some((classification, last_used)) => {
+                *last_used = self.clock;
+                self.stats.hits += 1;
+                Some(classification.clone())
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn insert(&mut self, key: String, classification: Classification) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            let oldest = self.entries.iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries.insert(key, (classification, self.clock));
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_patterns() {
+        let classifier = IntentClassifier::new();
+
+        assert_eq!(classifier.classify_domain("deploy kubernetes app"), "infra_deployment");
+        assert_eq!(classifier.classify_domain("backup postgres database"), "database_admin");
+        assert_eq!(classifier.classify_domain("create marketing content"), "marketing_content");
+        assert_eq!(classifier.classify_domain("random task"), GENERAL_DOMAIN);
+
+        assert_eq!(classifier.assess_risk("delete production database"), RiskLevel::Critical);
+        assert_eq!(classifier.assess_risk("update user profile"), RiskLevel::High);
+        assert_eq!(classifier.assess_risk("create new deployment"), RiskLevel::Medium);
+        assert_eq!(classifier.assess_risk("read configuration"), RiskLevel::Low);
+    }
+
+    #[test]
+    fn test_repeated_text_hits_cache() {
+        let classifier = IntentClassifier::new();
+
+        let first = classifier.classify("Deploy the API to staging");
+        let second = classifier.classify("  deploy the   api to STAGING ");
+        assert_eq!(first, second);
+        assert_eq!(classifier.cache_stats(), CacheStats { hits: 1, misses: 1 });
+
+        let batch = classifier.classify_many(&["deploy the api to staging", "drop the users table", "drop the users table"]);
+        assert_eq!(batch[0], first);
+        assert_eq!(batch[1].risk_level, RiskLevel::Critical);
+        assert_eq!(batch[1], batch[2]);
+        assert_eq!(classifier.cache_stats(), CacheStats { hits: 3, misses: 2 });
+    }
+
+    #[test]
+    fn test_cache_evicts_least_recently_used() {
+        let classifier = IntentClassifier::with_cache_capacity(2);
+        classifier.classify("a");
+        classifier.classify("b");
+        classifier.classify("a");
+        classifier.classify("c");
+
+        classifier.classify("a");
+        assert_eq!(classifier.cache_stats().hits, 2);
+        classifier.classify("b");
+        assert_eq!(classifier.cache_stats().misses, 4);
+    }
+
+    #[test]
+    fn test_runtime_patterns_affect_classification() {
+        let classifier = IntentClassifier::new();
+        assert_eq!(classifier.classify("issue a refund for invoice 42").domain, GENERAL_DOMAIN);
+        assert_eq!(classifier.assess_risk("issue a refund for invoice 42"), RiskLevel::Low);
+
+        let path = std::env::temp_dir().join(format!("intent-patterns-{}.json", std::process::id()));
+        std::fs::write(&path, r#"{"domains": {"payments": ["invoice", "Refund"]}, "risks": {"High": ["refund"]}}"#).unwrap();
+        classifier.load_patterns(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let classification = classifier.classify("issue a refund for invoice 42");
+        assert_eq!(classification.domain, "payments");
+        assert_eq!(classification.risk_level, RiskLevel::High);
+
+        classifier.register_domain_patterns("payments", ["payroll"]);
+        assert_eq!(classifier.classify_domain("run payroll"), "payments");
+        assert!(classifier.load_patterns("/nonexistent/patterns.json").is_err());
+    }
+}
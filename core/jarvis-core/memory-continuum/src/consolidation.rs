@@ -0,0 +1,81 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::graph::MemoryGraph;
+use crate::long_term::LongTermMemory;
+use crate::short_term::ShortTermMemory;
+use crate::{ConsolidationResult, MemoryType};
+
+/// Weight of the association between memories promoted together that share a tag
+const SHARED_TAG_WEIGHT: f64 = 0.5;
+
+/// Moves important short-term memories into long-term memory
+#[derive(Debug)]
+pub struct MemoryConsolidation {
+    stm: Arc<ShortTermMemory>,
+    ltm: Arc<LongTermMemory>,
+    graph: Arc<RwLock<MemoryGraph>>,
+    threshold: f64,
+}
+
+impl MemoryConsolidation {
+    pub fn new(
+        stm: Arc<ShortTermMemory>,
+        ltm: Arc<LongTermMemory>,
+        graph: Arc<RwLock<MemoryGraph>>,
+        threshold: f64,
+    ) -> Self {
+        Self { stm, ltm, graph, threshold }
+    }
+
+    /// Consolidate every short-term memory
+    pub async fn consolidate(&self) -> Result<ConsolidationResult> {
+        let memory_ids: Vec<Uuid> = self.stm.items().await.iter().map(|item| item.id).collect();
+        self.consolidate_memories(&memory_ids).await
+    }
+
+    /// Promote those of the given short-term memories whose importance reaches the
+    /// threshold, linking promoted memories that share a tag. Memories found in neither
+    /// store have been evicted and count as forgotten.
+    pub async fn consolidate_memories(&self, memory_ids: &[Uuid]) -> Result<ConsolidationResult> {
+        let mut result = ConsolidationResult::default();
+        let mut promoted = Vec::new();
+
+        for memory_id in memory_ids {
+            let Some(item) = self.stm.get(*memory_id) else {
+                if self.ltm.get(*memory_id).is_none() {
+                    result.memories_forgotten += 1;
+                }
+                continue;
+            };
+            result.processed_count += 1;
+            if item.metadata.importance < self.threshold {
+                continue;
+            }
+
+            if let Some(mut item) = self.stm.remove(item.id).await {
+                item.memory_type = MemoryType::LongTerm;
+                item.metadata.consolidation_level = item.metadata.consolidation_level.saturating_add(1);
+                promoted.push((item.id, item.metadata.tags.iter().cloned().collect::<HashSet<_>>()));
+                self.ltm.store(item).await?;
+                result.promoted_to_ltm += 1;
+            }
+        }
+
+        let mut graph = self.graph.write().await;
+        for (i, (a, a_tags)) in promoted.iter().enumerate() {
+            for (b, b_tags) in &promoted[i + 1..] {
+                if !a_tags.is_disjoint(b_tags) {
+                    graph.add_association(*a, *b, SHARED_TAG_WEIGHT).await?;
+                    result.associations_created += 1;
+                }
+            }
+        }
+
+        Ok(result)
+    }
+}
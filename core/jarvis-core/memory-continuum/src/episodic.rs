@@ -0,0 +1,77 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::MemoryItem;
+
+/// Something that happened, with the events that made it up
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Episode {
+    pub description: String,
+    #[serde(default)]
+    pub events: Vec<String>,
+    #[serde(default = "Utc::now")]
+    pub occurred_at: DateTime<Utc>,
+}
+
+impl Episode {
+    pub fn from_json(content: &serde_json::Value) -> Result<Self> {
+        serde_json::from_value(content.clone())
+            .map_err(|e| anyhow::anyhow!("Content is not an episode: {}", e))
+    }
+}
+
+/// Episodes in the order they were stored
+#[derive(Debug, Default)]
+pub struct EpisodicMemory {
+    episodes: RwLock<Vec<Episode>>,
+    /// Memory items the episodes were stored from, for retrieval by query
+    items: DashMap<Uuid, MemoryItem>,
+}
+
+impl EpisodicMemory {
+    pub async fn new() -> Result<Self> {
+        Ok(Self::default())
+    }
+
+    pub async fn store_episode(&self, episode: Episode) -> Result<()> {
+        self.episodes.write().await.push(episode);
+        Ok(())
+    }
+
+    /// Store the episode encoded in `item`, keeping the item retrievable
+    pub async fn store_item(&self, item: MemoryItem) -> Result<()> {
+        let crate::MemoryEncoding::Episode(episode) = &item.encoding else {
+            return Err(anyhow::anyhow!("Memory {} is not an episode", item.id));
+        };
+        self.store_episode(episode.clone()).await?;
+        self.items.insert(item.id, item);
+        Ok(())
+    }
+
+    pub fn get(&self, memory_id: Uuid) -> Option<MemoryItem> {
+        self.items.get(&memory_id).map(|item| item.clone())
+    }
+
+    pub fn items(&self) -> Vec<MemoryItem> {
+        self.items.iter().map(|item| item.clone()).collect()
+    }
+
+    /// Episodes that occurred at or after `since`, oldest first
+    pub async fn episodes_since(&self, since: DateTime<Utc>) -> Vec<Episode> {
+        let mut episodes: Vec<Episode> = self.episodes.read().await
+            .iter()
+            .filter(|e| e.occurred_at >= since)
+            .cloned()
+            .collect();
+        episodes.sort_by_key(|e| e.occurred_at);
+        episodes
+    }
+
+    pub async fn count(&self) -> Result<usize> {
+        Ok(self.episodes.read().await.len())
+    }
+}
@@ -0,0 +1,350 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use anyhow::Result;
+use cognitive_kernel::graph::{DotWriter, GraphFormat, Truncation, DEFAULT_MAX_NODES};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{MemoryMetadata, MemoryType};
+
+/// A memory as a node of the association graph
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GraphNode {
+    pub memory_id: Uuid,
+    /// Unknown for nodes serialized before it was recorded
+    #[serde(default)]
+    pub memory_type: Option<MemoryType>,
+    pub importance: f64,
+    pub tags: Vec<String>,
+}
+
+/// Weighted, undirected associations between memories
+#[derive(Debug, Default)]
+pub struct MemoryGraph {
+    nodes: HashMap<Uuid, GraphNode>,
+    edges: HashMap<Uuid, HashMap<Uuid, f64>>,
+}
+
+impl MemoryGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn add_memory_node(&mut self, memory_id: Uuid, memory_type: MemoryType, metadata: &MemoryMetadata) -> Result<()> {
+        self.nodes.insert(memory_id, GraphNode {
+            memory_id,
+            memory_type: Some(memory_type),
+            importance: metadata.importance,
+            tags: metadata.tags.clone(),
+        });
+        Ok(())
+    }
+
+    /// Link two memories, keeping the stronger weight if they are already linked
+    pub async fn add_association(&mut self, from: Uuid, to: Uuid, weight: f64) -> Result<()> {
+        if from == to {
+            return Ok(());
+        }
+        for (a, b) in [(from, to), (to, from)] {
+            let existing = self.edges.entry(a).or_default().entry(b).or_insert(weight);
+            *existing = existing.max(weight);
+        }
+        Ok(())
+    }
+
+    /// Memories linked to `memory_id`, strongest first
+    pub async fn get_associations(&self, memory_id: Uuid) -> Result<Vec<Uuid>> {
+        let mut linked: Vec<(Uuid, f64)> = self.edges.get(&memory_id)
+            .map(|edges| edges.iter().map(|(id, weight)| (*id, *weight)).collect())
+            .unwrap_or_default();
+        linked.sort_by(|a, b| b.1.total_cmp(&a.1));
+        Ok(linked.into_iter().map(|(id, _)| id).collect())
+    }
+
+    /// Number of distinct associated pairs
+    pub async fn association_count(&self) -> Result<usize> {
+        Ok(self.edges.values().map(HashMap::len).sum::<usize>() / 2)
+    }
+
+    pub fn node(&self, memory_id: Uuid) -> Option<&GraphNode> {
+        self.nodes.get(&memory_id)
+    }
+
+    /// Drop a memory and every association it takes part in
+    pub fn remove_memory_node(&mut self, memory_id: Uuid) {
+        self.nodes.remove(&memory_id);
+        let linked: HashSet<Uuid> = self.edges.remove(&memory_id)
+            .map(|edges| edges.into_keys().collect())
+            .unwrap_or_default();
+        for other in linked {
+            if let Some(edges) = self.edges.get_mut(&other) {
+                edges.remove(&memory_id);
+            }
+        }
+    }
+}
+
+    /// The part of the graph `filter` selects among the nodes `keep` accepts; see
+    /// [`GraphFilter`]
+    pub fn select(&self, filter: &GraphFilter, keep: impl Fn(&GraphNode) -> bool) -> AssociationGraph {
+        let candidate = |id: &Uuid| {
+            self.nodes.get(id).is_some_and(|node| {
+                (filter.tags.is_empty() || node.tags.iter().any(|tag| filter.tags.contains(tag))) && keep(node)
+            })
+        };
+        let links_of = |id: &Uuid| -> Vec<(Uuid, f64)> {
+            self.edges.get(id).into_iter().flatten()
+                .filter(|(other, strength)| **strength >= filter.min_strength && candidate(*other))
+                .map(|(other, strength)| (*other, *strength))
+                .collect()
+        };
+
+        let mut selected: Vec<Uuid> = match filter.around {
+            Some(root) if candidate(&root) => {
+                // Breadth first, so the nodes a cap leaves out are the furthest
+                let mut reached = vec![root];
+                let mut seen = HashSet::from([root]);
+                let mut queue = VecDeque::from([(root, 0)]);
+                while let Some((id, hops)) = queue.pop_front() {
+                    if hops == filter.depth {
+                        continue;
+                    }
+                    let mut next = links_of(&id);
+                    next.retain(|(other, _)| !seen.contains(other));
+                    next.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+                    for (other, _) in next {
+                        seen.insert(other);
+                        reached.push(other);
+                        queue.push_back((other, hops + 1));
+                    }
+                }
+                reached
+            }
+            Some(_) => Vec::new(),
+            None => {
+                let mut ids: Vec<Uuid> = self.nodes.keys()
+                    .filter(|id| candidate(*id))
+                    // A strength threshold drops the memories it leaves unlinked
+                    .filter(|id| filter.min_strength <= 0.0 || !links_of(*id).is_empty())
+                    .copied()
+                    .collect();
+                ids.sort_by(|a, b| self.nodes[b].importance.total_cmp(&self.nodes[a].importance).then(a.cmp(b)));
+                ids
+            }
+        };
+
+        let truncated = Truncation::of(selected.len(), filter.max_nodes);
+        selected.truncate(filter.max_nodes);
+        let shown: HashSet<Uuid> = selected.iter().copied().collect();
+        let mut links: Vec<AssociationLink> = selected.iter()
+            .flat_map(|&id| links_of(&id).into_iter().map(move |(other, strength)| (id, other, strength)))
+            .filter(|(id, other, _)| id < other && shown.contains(other))
+            .map(|(source, target, strength)| AssociationLink { source, target, strength })
+            .collect();
+        links.sort_by(|a, b| (a.source, a.target).cmp(&(b.source, b.target)));
+        AssociationGraph {
+            nodes: selected.iter().map(|id| self.nodes[id].clone()).collect(),
+            links,
+            truncated,
+        }
+    }
+
+    /// The part of the graph `filter` selects, written as `format`
+    pub fn export(&self, format: GraphFormat, filter: &GraphFilter) -> String {
+        self.select(filter, |_| true).render(format)
+    }
+}
+
+/// Which memories and associations an export of the graph holds
+///
+/// Without `around` every memory carrying one of the tags, or any
+/// memory without tags to match, is kept, most important first. With it, only the
+/// memories within `depth` associations of the given one are, nearest first. Either
+/// way associations weaker than `min_strength` are left out, as are memories a
+/// `min_strength` above zero leaves without one, and at most `max_nodes` memories are
+/// kept.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GraphFilter {
+    pub tags: Vec<String>,
+    pub min_strength: f64,
+    pub around: Option<Uuid>,
+    pub depth: usize,
+    pub max_nodes: usize,
+}
+
+impl Default for GraphFilter {
+    fn default() -> Self {
+        Self {
+            tags: Vec::new(),
+            min_strength: 0.0,
+            around: None,
+            depth: 1,
+            max_nodes: DEFAULT_MAX_NODES,
+        }
+    }
+}
+
+impl GraphFilter {
+    pub fn with_tag(mut self, tag: impl Into<String>) -> Self {
+        self.tags.push(tag.into());
+        self
+    }
+
+    pub fn with_min_strength(mut self, min_strength: f64) -> Self {
+        self.min_strength = min_strength;
+        self
+    }
+
+    /// Keep only the memories within `depth` associations of `memory_id`
+    pub fn around(mut self, memory_id: Uuid, depth: usize) -> Self {
+        self.around = Some(memory_id);
+        self.depth = depth;
+        self
+    }
+
+    pub fn with_max_nodes(mut self, max_nodes: usize) -> Self {
+        self.max_nodes = max_nodes;
+        self
+    }
+}
+
+/// An association between two memories; `source` is the lower id
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AssociationLink {
+    pub source: Uuid,
+    pub target: Uuid,
+    pub strength: f64,
+}
+
+/// Memories and their associations as a node-link document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssociationGraph {
+    pub nodes: Vec<GraphNode>,
+    pub links: Vec<AssociationLink>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub truncated: Option<Truncation>,
+}
+
+impl AssociationGraph {
+    pub fn render(&self, format: GraphFormat) -> String {
+        match format {
+            GraphFormat::Dot => self.to_dot(),
+            GraphFormat::Json => serde_json::to_string_pretty(self).expect("association graphs serialize"),
+        }
+    }
+
+    /// Memories filled by type, with their short id, type and importance as the label,
+    /// and associations labelled and weighted by strength
+    pub fn to_dot(&self) -> String {
+        let mut dot = DotWriter::new("memory associations", false);
+        dot.defaults("node", &[("shape", "ellipse".to_string()), ("style", "filled".to_string())]);
+        if let Some(truncation) = &self.truncated {
+            dot.truncated(truncation);
+        }
+        for node in &self.nodes {
+            let memory_type = node.memory_type.as_ref().map(|t| format!("{:?}", t)).unwrap_or_else(|| "Unknown".to_string());
+            let mut label = format!("{}\n{} ({:.2})", &node.memory_id.to_string()[..8], memory_type, node.importance);
+            if !node.tags.is_empty() {
+                label = format!("{}\n{}", label, node.tags.join(", "));
+            }
+            dot.node(&node.memory_id.to_string(), &[
+                ("label", label),
+                ("fillcolor", memory_type_color(node.memory_type.as_ref()).to_string()),
+            ]);
+        }
+        for link in &self.links {
+            dot.edge(&link.source.to_string(), &link.target.to_string(), &[
+                ("label", format!("{:.2}", link.strength)),
+                ("penwidth", format!("{:.2}", 1.0 + 2.0 * link.strength.clamp(0.0, 1.0))),
+            ]);
+        }
+        dot.finish()
+    }
+}
+
+fn memory_type_color(memory_type: Option<&MemoryType>) -> &'static str {
+    match memory_type {
+        Some(MemoryType::ShortTerm) => "lightyellow",
+        Some(MemoryType::LongTerm) => "lightblue",
+        Some(MemoryType::Procedural) => "palegreen",
+        Some(MemoryType::Episodic) => "plum",
+        Some(MemoryType::Spatial) => "lightsalmon",
+        None => "white",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AccessPattern;
+    use cognitive_kernel::graph::{check_dot, DotCounts};
+
+    fn metadata(importance: f64, tag: &str) -> MemoryMetadata {
+        MemoryMetadata {
+            importance,
+            confidence: 1.0,
+            source: "test".to_string(),
+            tags: vec![tag.to_string()],
+            associations: Vec::new(),
+            consolidation_level: 0,
+            access_pattern: AccessPattern { frequency: 1.0, recency: 1.0, context_relevance: 1.0, emotional_valence: 0.0 },
+            pii_allowlist: Vec::new(),
+            redactions: None,
+        }
+    }
+
+    /// a, b and d are tagged `team`, c and e `home`, in falling importance; linked
+    /// a-b 0.9, b-d 0.3, a-c 0.5 and c-e 0.8
+    async fn graph() -> (MemoryGraph, [Uuid; 5]) {
+        let mut graph = MemoryGraph::new();
+        let ids = [Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4()];
+        let [a, b, c, d, e] = ids;
+        for (id, importance, tag) in [(a, 0.9, "team"), (b, 0.5, "team"), (c, 0.4, "home"), (d, 0.3, "team"), (e, 0.2, "home")] {
+            graph.add_memory_node(id, MemoryType::LongTerm, &metadata(importance, tag)).await.unwrap();
+        }
+        for (from, to, strength) in [(a, b, 0.9), (b, d, 0.3), (a, c, 0.5), (c, e, 0.8)] {
+            graph.add_association(from, to, strength).await.unwrap();
+        }
+        (graph, ids)
+    }
+
+    fn dot_counts(graph: &MemoryGraph, filter: GraphFilter) -> DotCounts {
+        let dot = graph.export(GraphFormat::Dot, &filter);
+        check_dot(&dot).unwrap_or_else(|e| panic!("{}: {}", e, dot))
+    }
+
+    #[tokio::test]
+    async fn test_filters_narrow_the_exported_graph() {
+        let (graph, [a, b, c, d, e]) = graph().await;
+        assert_eq!(dot_counts(&graph, GraphFilter::default()), DotCounts { nodes: 5, edges: 4 });
+        assert_eq!(dot_counts(&graph, GraphFilter::default().with_tag("team")), DotCounts { nodes: 3, edges: 2 });
+        assert_eq!(dot_counts(&graph, GraphFilter::default().with_min_strength(0.5)), DotCounts { nodes: 4, edges: 3 });
+        assert_eq!(dot_counts(&graph, GraphFilter::default().around(a, 1)), DotCounts { nodes: 3, edges: 2 });
+        assert_eq!(dot_counts(&graph, GraphFilter::default().around(a, 2)), DotCounts { nodes: 5, edges: 4 });
+        assert_eq!(dot_counts(&graph, GraphFilter::default().around(a, 2).with_min_strength(0.6)), DotCounts { nodes: 2, edges: 1 });
+
+        let nearby = graph.select(&GraphFilter::default().around(a, 1), |_| true);
+        assert_eq!(nearby.nodes.iter().map(|n| n.memory_id).collect::<Vec<_>>(), [a, b, c]);
+        let without_c = graph.select(&GraphFilter::default().around(a, 2), |node| node.memory_id != c);
+        assert_eq!(without_c.nodes.iter().map(|n| n.memory_id).collect::<Vec<_>>(), [a, b, d]);
+        assert!(graph.select(&GraphFilter::default().around(e, 3).with_tag("team"), |_| true).nodes.is_empty());
+
+        let json: serde_json::Value = serde_json::from_str(&graph.export(GraphFormat::Json, &GraphFilter::default().with_tag("home"))).unwrap();
+        assert_eq!(json["nodes"][0]["memory_type"], "LongTerm");
+        assert_eq!(json["links"].as_array().unwrap().len(), 1);
+        assert_eq!(json["links"][0]["strength"], 0.8);
+        assert!([c.to_string(), e.to_string()].contains(&json["links"][0]["source"].as_str().unwrap().to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_exports_over_the_node_cap_are_truncated_most_important_first() {
+        let (graph, [a, b, ..]) = graph().await;
+        let capped = graph.select(&GraphFilter::default().with_max_nodes(2), |_| true);
+        assert_eq!(capped.nodes.iter().map(|n| n.memory_id).collect::<Vec<_>>(), [a, b]);
+        assert_eq!(capped.links.len(), 1);
+        assert_eq!(capped.truncated, Some(Truncation { total_nodes: 5, shown_nodes: 2 }));
+        assert!(capped.to_dot().contains("truncated: showing 2 of 5 nodes"));
+        assert_eq!(dot_counts(&graph, GraphFilter::default().with_max_nodes(2)), DotCounts { nodes: 2, edges: 1 });
+    }
+}
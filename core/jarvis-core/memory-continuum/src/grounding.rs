@@ -0,0 +1,150 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use cognitive_kernel::grounding::extract_entities;
+use cognitive_kernel::{ConversationMemory, Intent, IntentExecutionPlan, RecalledMemory};
+use uuid::Uuid;
+
+use crate::{AccessPattern, MemoryContinuum, MemoryEncoding, MemoryItem, MemoryMetadata, MemoryType};
+
+/// Tag carried by every intent the kernel records
+pub const INTENT_TAG: &str = "intent";
+
+/// Tag identifying the user a memory belongs to
+pub fn user_tag(user_id: &str) -> String {
+    format!("user:{}", user_id)
+}
+
+/// Tag identifying the plan an intent was turned into
+pub fn plan_tag(plan_id: Uuid) -> String {
+    format!("plan:{}", plan_id)
+}
+
+/// Recalls from short-term and episodic memory. A user only sees memories tagged with
+/// their id; without a user, only memories tagged with no user are seen.
+#[async_trait]
+impl ConversationMemory for MemoryContinuum {
+    async fn recall(&self, user_id: Option<&str>, query: &str, limit: usize) -> Result<Vec<RecalledMemory>> {
+        let user = user_id.map(user_tag);
+        let belongs_to_user = |item: &MemoryItem| {
+            let mut users = item.metadata.tags.iter().filter(|tag| tag.starts_with("user:"));
+            match &user {
+                Some(user) => users.any(|tag| tag == user),
+                None => users.next().is_none(),
+            }
+        };
+
+        let memories = self
+            .retrieve_memories_matching(query, vec![MemoryType::ShortTerm, MemoryType::Episodic], limit, belongs_to_user)
+            .await?;
+        Ok(memories.into_iter().map(recalled).collect())
+    }
+
+    async fn record(&self, user_id: Option<&str>, intent: &Intent, plan: &IntentExecutionPlan) -> Result<Uuid> {
+        let content = serde_json::json!({
+            "description": intent.raw_text,
+            "events": plan.tasks.iter().map(|task| task.name.clone()).collect::<Vec<_>>(),
+            "occurred_at": intent.created_at,
+            "intent_id": intent.id,
+            "plan_id": plan.id,
+            "domain": intent.domain,
+            "entities": intent.entities,
+        });
+
+        let mut tags = vec![INTENT_TAG.to_string(), plan_tag(plan.id)];
+        tags.extend(user_id.map(user_tag));
+        let metadata = MemoryMetadata {
+            importance: 0.5,
+            confidence: intent.confidence,
+            source: "cognitive-kernel".to_string(),
+            tags,
+            associations: intent.grounding.clone(),
+            consolidation_level: 0,
+            access_pattern: AccessPattern {
+                frequency: 1.0,
+                recency: 1.0,
+                context_relevance: 1.0,
+                emotional_valence: 0.0,
+            },
+            pii_allowlist: Vec::new(),
+            redactions: None,
+        };
+        self.store_memory(content, MemoryType::Episodic, metadata).await
+    }
+}
+
+/// An item as the kernel sees it, taking recorded entities over re-extracted ones
+fn recalled(item: MemoryItem) -> RecalledMemory {
+    let text = match &item.encoding {
+        MemoryEncoding::Text(text) => text.clone(),
+        MemoryEncoding::Episode(episode) => episode.description.clone(),
+        _ => item.content.to_string(),
+    };
+    let entities = item.content.get("entities")
+        .and_then(|entities| serde_json::from_value(entities.clone()).ok())
+        .unwrap_or_else(|| extract_entities(&text));
+
+    RecalledMemory {
+        id: item.id,
+        text,
+        entities,
+        created_at: item.created_at,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use cognitive_kernel::{CognitiveKernel, ExecutionContext};
+
+    use super::*;
+    use crate::MemoryConfig;
+
+    #[tokio::test]
+    async fn test_follow_up_intent_resolves_entity_from_stored_intent() {
+        let continuum = Arc::new(MemoryContinuum::new(MemoryConfig::default()).await.unwrap());
+        let kernel = CognitiveKernel::new().with_memory(continuum.clone());
+        let context = |user: &str| Some(ExecutionContext::new(Uuid::nil()).with_user(user));
+
+        // Seeded: a short-term note from another user mentioning a different service
+        let note = MemoryMetadata { tags: vec![user_tag("bob")], ..metadata() };
+        continuum.store_memory(serde_json::json!("deploy search-api to staging"), MemoryType::ShortTerm, note).await.unwrap();
+
+        let (first, first_plan) = kernel.plan_intent("Deploy billing-api to production", context("alice")).await.unwrap();
+        assert!(first.grounding.is_empty());
+        let stored = continuum.episodic.items();
+        assert_eq!(stored.len(), 1);
+        assert!(stored[0].metadata.tags.contains(&plan_tag(first_plan.id)));
+
+        let (follow_up, _) = kernel.plan_intent("deploy it to staging like last time", context("alice")).await.unwrap();
+        assert_eq!(follow_up.entities, vec!["billing-api"]);
+        assert_eq!(follow_up.grounding, vec![stored[0].id]);
+        assert!(continuum.get_memory(stored[0].id).is_some());
+
+        // The follow-up is recorded too, linked to what grounded it
+        let recorded = continuum.episodic.items().into_iter().find(|item| item.id != stored[0].id).unwrap();
+        assert_eq!(recorded.metadata.associations, vec![stored[0].id]);
+
+        let (other, _) = kernel.plan_intent("deploy it to production like last time", context("bob")).await.unwrap();
+        assert_eq!(other.entities, vec!["search-api"]);
+    }
+
+    fn metadata() -> MemoryMetadata {
+        MemoryMetadata {
+            importance: 0.5,
+            confidence: 0.9,
+            source: "test".to_string(),
+            tags: vec![],
+            associations: vec![],
+            consolidation_level: 0,
+            access_pattern: AccessPattern {
+                frequency: 1.0,
+                recency: 1.0,
+                context_relevance: 0.8,
+                emotional_valence: 0.0,
+            },
+            pii_allowlist: Vec::new(),
+            redactions: None,
+        }
+    }
+}
@@ -1,12 +1,16 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use anyhow::Result;
 use chrono::{DateTime, Utc};
 use dashmap::DashMap;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use tokio::sync::{RwLock, Semaphore};
+use talkpp_sanitizer::{SanitizationReport, TextSanitizer};
+use talkpp_errors::{Validate, Violations};
+use talkpp_tenancy::DEFAULT_TENANT;
 use tracing::{debug, info, instrument, warn};
 use uuid::Uuid;
 
@@ -18,6 +22,9 @@ pub mod spatial;
 pub mod consolidation;
 pub mod retrieval;
 pub mod graph;
+pub mod grounding;
+pub mod tenancy;
+mod scheduler;
 
 pub use short_term::ShortTermMemory;
 pub use long_term::LongTermMemory;
@@ -26,8 +33,16 @@ pub use episodic::EpisodicMemory;
 pub use spatial::SpatialMemory;
 pub use consolidation::MemoryConsolidation;
 pub use retrieval::MemoryRetrieval;
+pub use graph::{AssociationGraph, AssociationLink, GraphFilter, GraphNode};
+pub use tenancy::TenantMemories;
 
-/// Multi-layer memory continuum that orchestrates all memory types
+use scheduler::ConsolidationScheduler;
+
+/// Prefix of the tag recording the Rust type of content stored with `store_typed`
+pub const TYPE_TAG_PREFIX: &str = "type:";
+
+/// Multi-layer memory continuum that orchestrates all memory types. Its own methods act
+/// for [`DEFAULT_TENANT`]; [`MemoryContinuum::for_tenant`] gives another tenant's view.
 #[derive(Debug)]
 pub struct MemoryContinuum {
     pub stm: Arc<ShortTermMemory>,
@@ -40,11 +55,14 @@ pub struct MemoryContinuum {
     
     // Memory management
     active_memories: Arc<DashMap<Uuid, ActiveMemory>>,
+    /// Ids of the short- and long-term memories carrying each tag
+    tag_index: Arc<DashMap<String, HashSet<Uuid>>>,
     memory_graph: Arc<RwLock<graph::MemoryGraph>>,
     consolidation_scheduler: Arc<tokio::sync::Mutex<ConsolidationScheduler>>,
     
     // Configuration
     config: MemoryConfig,
+    sanitizer: Option<TextSanitizer>,
 }
 
 /// Active memory tracking
@@ -79,6 +97,10 @@ pub struct MemoryItem {
     pub metadata: MemoryMetadata,
     pub created_at: DateTime<Utc>,
     pub last_accessed: DateTime<Utc>,
+    /// Tenant the memory was stored for; memories from before tenants existed belong to
+    /// the default one
+    #[serde(default = "talkpp_tenancy::default_tenant_id")]
+    pub tenant_id: String,
 }
 
 /// Memory encoding formats
@@ -102,6 +124,12 @@ pub struct MemoryMetadata {
     pub associations: Vec<Uuid>,
     pub consolidation_level: u8,
     pub access_pattern: AccessPattern,
+    /// Fields of the content the sanitizer leaves as they are; `content` exempts all of it
+    #[serde(default)]
+    pub pii_allowlist: Vec<String>,
+    /// What the sanitizer masked in the content when it was stored
+    #[serde(default)]
+    pub redactions: Option<SanitizationReport>,
 }
 
 /// Memory access patterns
@@ -124,21 +152,12 @@ pub struct MemoryConfig {
     pub max_associations: usize,
     pub spatial_resolution: f64,
     pub episodic_compression_ratio: f64,
-}
-
-/// Consolidation scheduler for memory management
-#[derive(Debug)]
-struct ConsolidationScheduler {
-    pending_consolidations: Vec<ConsolidationTask>,
-    last_consolidation: Instant,
-    consolidation_interval: Duration,
-}
-
-#[derive(Debug, Clone)]
-struct ConsolidationTask {
-    memory_id: Uuid,
-    scheduled_at: Instant,
-    priority: f64,
+    /// Minimum time between consolidation runs
+    pub consolidation_interval: Duration,
+    /// How long after being scheduled a memory becomes eligible for consolidation
+    pub consolidation_delay: Duration,
+    /// Most memories consolidated in one run, so a large backlog can't stall a run
+    pub max_consolidations_per_run: usize,
 }
 
 impl Default for MemoryConfig {
@@ -152,13 +171,70 @@ impl Default for MemoryConfig {
             max_associations: 50,
             spatial_resolution: 1.0,
             episodic_compression_ratio: 0.3,
+            consolidation_interval: Duration::from_secs(300), // 5 minutes
+            consolidation_delay: Duration::from_secs(60),
+            max_consolidations_per_run: 100,
         }
     }
 }
 
+impl MemoryConfig {
+    pub fn with_stm_capacity(mut self, capacity: usize) -> Self {
+        self.stm_capacity = capacity;
+        self
+    }
+
+    /// Most long-term memories kept, instead of unlimited
+    pub fn with_ltm_capacity(mut self, capacity: usize) -> Self {
+        self.ltm_capacity = Some(capacity);
+        self
+    }
+
+    /// Lowest importance, in 0..=1, at which a short-term memory is consolidated
+    pub fn with_consolidation_threshold(mut self, threshold: f64) -> Self {
+        self.consolidation_threshold = threshold;
+        self
+    }
+
+    pub fn with_consolidation_interval(mut self, interval: Duration) -> Self {
+        self.consolidation_interval = interval;
+        self
+    }
+}
+
+impl Validate for MemoryConfig {
+    const NAME: &'static str = "memory config";
+
+    fn violations(&self) -> Violations {
+        let mut violations = Violations::new();
+        violations.check(self.stm_capacity > 0, "stm_capacity", "must be at least 1");
+        violations.check(self.ltm_capacity != Some(0), "ltm_capacity", "must be at least 1, or unset for unlimited");
+        violations.check((0.0..=1.0).contains(&self.consolidation_threshold), "consolidation_threshold", "must be between 0 and 1");
+        violations.check(
+            self.forgetting_curve_factor >= 0.0 && self.forgetting_curve_factor.is_finite(),
+            "forgetting_curve_factor",
+            "must not be negative",
+        );
+        violations.check((0.0..=1.0).contains(&self.importance_decay_rate), "importance_decay_rate", "must be between 0 and 1");
+        violations.check(
+            self.spatial_resolution > 0.0 && self.spatial_resolution.is_finite(),
+            "spatial_resolution",
+            "must be positive",
+        );
+        violations.check(
+            self.episodic_compression_ratio > 0.0 && self.episodic_compression_ratio <= 1.0,
+            "episodic_compression_ratio",
+            "must be above 0 and at most 1",
+        );
+        violations.check(self.max_consolidations_per_run > 0, "max_consolidations_per_run", "must be at least 1");
+        violations
+    }
+}
+
 impl MemoryContinuum {
-    /// Create a new memory continuum
+    /// Create a new memory continuum. Fails with every violation if `config` is invalid.
     pub async fn new(config: MemoryConfig) -> Result<Self> {
+        config.validate()?;
         info!("🧠 Initializing JARVIS Memory Continuum");
         
         let stm = Arc::new(ShortTermMemory::new(config.stm_capacity).await?);
@@ -185,11 +261,11 @@ impl MemoryContinuum {
             Arc::clone(&memory_graph),
         ));
         
-        let consolidation_scheduler = Arc::new(tokio::sync::Mutex::new(ConsolidationScheduler {
-            pending_consolidations: Vec::new(),
-            last_consolidation: Instant::now(),
-            consolidation_interval: Duration::from_secs(300), // 5 minutes
-        }));
+        let consolidation_scheduler = Arc::new(tokio::sync::Mutex::new(ConsolidationScheduler::new(
+            config.consolidation_interval,
+            config.consolidation_delay,
+            config.max_consolidations_per_run,
+        )));
 
         Ok(Self {
             stm,
@@ -200,24 +276,76 @@ impl MemoryContinuum {
             consolidation,
             retrieval,
             active_memories: Arc::new(DashMap::new()),
+            tag_index: Arc::new(DashMap::new()),
             memory_graph,
             consolidation_scheduler,
             config,
+            sanitizer: None,
         })
     }
 
+    /// Mask personal data in the content of short-term, long-term and episodic memories
+    /// before they are encoded and stored, recording what was masked in their metadata.
+    /// Memories holding a value whose detector drops chunks are refused.
+    pub fn with_sanitizer(mut self, sanitizer: TextSanitizer) -> Self {
+        self.sanitizer = Some(sanitizer);
+        self
+    }
+
+    /// `content` with personal data masked, except in the fields `metadata` allowlists,
+    /// and `metadata` recording what was masked. Procedural and spatial memories are
+    /// structured data rather than text, so they are stored as they are.
+    fn sanitize(
+        &self,
+        mut content: serde_json::Value,
+        mut metadata: MemoryMetadata,
+        memory_type: &MemoryType,
+    ) -> Result<(serde_json::Value, MemoryMetadata)> {
+        let Some(sanitizer) = &self.sanitizer else {
+            return Ok((content, metadata));
+        };
+        if matches!(memory_type, MemoryType::Procedural | MemoryType::Spatial) {
+            return Ok((content, metadata));
+        }
+        let report = sanitizer.sanitize_json("content", &mut content, &metadata.pii_allowlist);
+        if report.dropped {
+            return Err(talkpp_errors::Error::invalid_input("Memory holds personal data that must not be stored").into());
+        }
+        metadata.redactions = (!report.is_empty()).then_some(report);
+        Ok((content, metadata))
+    }
+
     /// Store a memory item in the appropriate memory system
-    #[instrument(skip(self, content))]
     pub async fn store_memory(
         &self,
         content: serde_json::Value,
         memory_type: MemoryType,
         metadata: MemoryMetadata,
     ) -> Result<Uuid> {
+        self.store_memory_for(DEFAULT_TENANT, content, memory_type, metadata).await
+    }
+
+    #[instrument(skip(self, content))]
+    pub(crate) async fn store_memory_for(
+        &self,
+        tenant_id: &str,
+        content: serde_json::Value,
+        memory_type: MemoryType,
+        metadata: MemoryMetadata,
+    ) -> Result<Uuid> {
+        if tenant_id != DEFAULT_TENANT && matches!(memory_type, MemoryType::Procedural | MemoryType::Spatial) {
+            return Err(talkpp_errors::Error::invalid_input(format!(
+                "{:?} memories are shared, so only the default tenant can store them", memory_type
+            )).into());
+        }
+        for associated in &metadata.associations {
+            self.check_owner(tenant_id, *associated)?;
+        }
+        let (content, metadata) = self.sanitize(content, metadata, &memory_type)?;
         let memory_id = Uuid::new_v4();
         let now = Utc::now();
         
-        debug!("Storing memory {} in {:?}", memory_id, memory_type);
+        debug!("Storing memory {} in {:?} for tenant '{}'", memory_id, memory_type, tenant_id);
 
         // Create memory item
         let memory_item = MemoryItem {
@@ -228,14 +356,20 @@ impl MemoryContinuum {
             metadata: metadata.clone(),
             created_at: now,
             last_accessed: now,
+            tenant_id: tenant_id.to_string(),
         };
 
         // Store in appropriate memory system
         match memory_type {
             MemoryType::ShortTerm => {
-                self.stm.store(memory_item).await?;
+                self.index_tags(memory_id, &metadata.tags);
+                if let Some(evicted) = self.stm.store(memory_item).await? {
+                    debug!("Evicted memory {} from short-term memory", evicted.id);
+                    self.unindex_tags(evicted.id, &evicted.metadata.tags);
+                }
             },
             MemoryType::LongTerm => {
+                self.index_tags(memory_id, &metadata.tags);
                 self.ltm.store(memory_item).await?;
             },
             MemoryType::Procedural => {
@@ -244,9 +378,7 @@ impl MemoryContinuum {
                 }
             },
             MemoryType::Episodic => {
-                if let MemoryEncoding::Episode(episode) = &memory_item.encoding {
-                    self.episodic.store_episode(episode.clone()).await?;
-                }
+                self.episodic.store_item(memory_item).await?;
             },
             MemoryType::Spatial => {
                 if let MemoryEncoding::Spatial(spatial_data) = &memory_item.encoding {
@@ -258,7 +390,7 @@ impl MemoryContinuum {
         // Track active memory
         let active_memory = ActiveMemory {
             id: memory_id,
-            memory_type,
+            memory_type: memory_type.clone(),
             created_at: now,
             last_accessed: now,
             access_count: 1,
@@ -271,7 +403,7 @@ impl MemoryContinuum {
         // Update memory graph
         {
             let mut graph = self.memory_graph.write().await;
-            graph.add_memory_node(memory_id, &metadata).await?;
+            graph.add_memory_node(memory_id, memory_type, &metadata).await?;
             
             // Create associations
             for associated_id in &metadata.associations {
@@ -296,9 +428,33 @@ impl MemoryContinuum {
         memory_types: Vec<MemoryType>,
         limit: usize,
     ) -> Result<Vec<MemoryItem>> {
-        debug!("Retrieving memories for query: {}", query);
+        self.retrieve_memories_matching(query, memory_types, limit, |_| true).await
+    }
+
+    /// Like `retrieve_memories`, considering only memories accepted by `filter`
+    pub async fn retrieve_memories_matching(
+        &self,
+        query: &str,
+        memory_types: Vec<MemoryType>,
+        limit: usize,
+        filter: impl Fn(&MemoryItem) -> bool,
+    ) -> Result<Vec<MemoryItem>> {
+        self.retrieve_memories_matching_for(DEFAULT_TENANT, query, memory_types, limit, filter).await
+    }
+
+    pub(crate) async fn retrieve_memories_matching_for(
+        &self,
+        tenant_id: &str,
+        query: &str,
+        memory_types: Vec<MemoryType>,
+        limit: usize,
+        filter: impl Fn(&MemoryItem) -> bool,
+    ) -> Result<Vec<MemoryItem>> {
+        debug!("Retrieving memories of tenant '{}' for query: {}", tenant_id, query);
         
-        let memories = self.retrieval.retrieve(query, memory_types, limit).await?;
+        let memories = self.retrieval
+            .retrieve_matching(query, memory_types, limit, |item| item.tenant_id == tenant_id && filter(item))
+            .await?;
         
         // Update access patterns
         for memory in &memories {
@@ -309,14 +465,216 @@ impl MemoryContinuum {
         Ok(memories)
     }
 
-    /// Get memory associations
+    /// Store serializable content, tagging it with its type so `retrieve_typed` can find it
+    pub async fn store_typed<T: Serialize>(
+        &self,
+        content: &T,
+        memory_type: MemoryType,
+        metadata: MemoryMetadata,
+    ) -> Result<Uuid> {
+        self.store_typed_for(DEFAULT_TENANT, content, memory_type, metadata).await
+    }
+
+    pub(crate) async fn store_typed_for<T: Serialize>(
+        &self,
+        tenant_id: &str,
+        content: &T,
+        memory_type: MemoryType,
+        mut metadata: MemoryMetadata,
+    ) -> Result<Uuid> {
+        metadata.tags.push(type_tag::<T>());
+        self.store_memory_for(tenant_id, serde_json::to_value(content)?, memory_type, metadata).await
+    }
+
+    /// Retrieve memories stored with `store_typed::<T>`, ranked as `retrieve_memories`
+    /// ranks them. Items whose content no longer deserializes are skipped and counted.
+    pub async fn retrieve_typed<T: DeserializeOwned>(
+        &self,
+        query: &str,
+        memory_types: Vec<MemoryType>,
+        limit: usize,
+    ) -> Result<TypedRetrieval<T>> {
+        self.retrieve_typed_for(DEFAULT_TENANT, query, memory_types, limit).await
+    }
+
+    pub(crate) async fn retrieve_typed_for<T: DeserializeOwned>(
+        &self,
+        tenant_id: &str,
+        query: &str,
+        memory_types: Vec<MemoryType>,
+        limit: usize,
+    ) -> Result<TypedRetrieval<T>> {
+        let type_tag = type_tag::<T>();
+        let items = self.retrieval
+            .retrieve_matching(query, memory_types, limit, |item| {
+                item.tenant_id == tenant_id && item.metadata.tags.contains(&type_tag)
+            })
+            .await?;
+
+        let mut retrieved = TypedRetrieval { memories: Vec::new(), skipped: 0 };
+        for item in items {
+            match serde_json::from_value(item.content) {
+                Ok(content) => {
+                    self.update_access_pattern(item.id).await;
+                    retrieved.memories.push(TypedMemory {
+                        id: item.id,
+                        content,
+                        metadata: item.metadata,
+                        created_at: item.created_at,
+                    });
+                }
+                Err(e) => {
+                    warn!("Skipping memory {} tagged {}: {}", item.id, type_tag, e);
+                    retrieved.skipped += 1;
+                }
+            }
+        }
+        Ok(retrieved)
+    }
+
+    /// Short- and long-term memories carrying all (`match_all`) or any of `tags`, created
+    /// at or after `since`, newest first
+    pub async fn retrieve_by_tags(
+        &self,
+        tags: &[String],
+        match_all: bool,
+        since: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> Result<Vec<MemoryItem>> {
+        self.retrieve_by_tags_for(DEFAULT_TENANT, tags, match_all, since, limit).await
+    }
+
+    pub(crate) async fn retrieve_by_tags_for(
+        &self,
+        tenant_id: &str,
+        tags: &[String],
+        match_all: bool,
+        since: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> Result<Vec<MemoryItem>> {
+        let tagged: Vec<HashSet<Uuid>> = tags.iter()
+            .map(|tag| self.tag_index.get(tag).map(|ids| ids.clone()).unwrap_or_default())
+            .collect();
+
+        let ids: HashSet<Uuid> = if match_all {
+            let mut by_size: Vec<&HashSet<Uuid>> = tagged.iter().collect();
+            by_size.sort_by_key(|ids| ids.len());
+            match by_size.split_first() {
+                Some((smallest, rest)) => smallest.iter()
+                    .filter(|id| rest.iter().all(|ids| ids.contains(id)))
+                    .copied()
+                    .collect(),
+                None => HashSet::new(),
+            }
+        } else {
+            tagged.into_iter().flatten().collect()
+        };
+
+        let mut memories: Vec<MemoryItem> = ids.into_iter()
+            .filter_map(|id| self.stm.get(id).or_else(|| self.ltm.get(id)))
+            .filter(|item| item.tenant_id == tenant_id)
+            .filter(|item| since.is_none_or(|since| item.created_at >= since))
+            .collect();
+        memories.sort_by_key(|item| std::cmp::Reverse(item.created_at));
+        memories.truncate(limit);
+
+        for memory in &memories {
+            self.update_access_pattern(memory.id).await;
+        }
+        Ok(memories)
+    }
+
+    /// A short-term, long-term or episodic memory by id
+    pub fn get_memory(&self, memory_id: Uuid) -> Option<MemoryItem> {
+        self.get_memory_for(DEFAULT_TENANT, memory_id)
+    }
+
+    pub(crate) fn get_memory_for(&self, tenant_id: &str, memory_id: Uuid) -> Option<MemoryItem> {
+        self.find_memory(memory_id).filter(|item| item.tenant_id == tenant_id)
+    }
+
+    /// A memory of any tenant's
+    fn find_memory(&self, memory_id: Uuid) -> Option<MemoryItem> {
+        self.stm.get(memory_id)
+            .or_else(|| self.ltm.get(memory_id))
+            .or_else(|| self.episodic.get(memory_id))
+    }
+
+    /// Fail as if `memory_id` did not exist if it is another tenant's memory. Ids that
+    /// aren't short-term, long-term or episodic memories belong to no tenant and pass.
+    fn check_owner(&self, tenant_id: &str, memory_id: Uuid) -> Result<()> {
+        match self.find_memory(memory_id) {
+            Some(item) if item.tenant_id != tenant_id => {
+                Err(talkpp_errors::Error::not_found(format!("Memory {} not found", memory_id)).into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Remove a short- or long-term memory along with its associations, returning it
+    pub async fn forget_memory(&self, memory_id: Uuid) -> Result<Option<MemoryItem>> {
+        self.forget_memory_for(DEFAULT_TENANT, memory_id).await
+    }
+
+    pub(crate) async fn forget_memory_for(&self, tenant_id: &str, memory_id: Uuid) -> Result<Option<MemoryItem>> {
+        if self.check_owner(tenant_id, memory_id).is_err() {
+            return Ok(None);
+        }
+        let removed = match self.stm.remove(memory_id).await {
+            Some(item) => Some(item),
+            None => self.ltm.remove(memory_id),
+        };
+        let Some(item) = removed else {
+            return Ok(None);
+        };
+
+        self.unindex_tags(memory_id, &item.metadata.tags);
+        self.active_memories.remove(&memory_id);
+        self.memory_graph.write().await.remove_memory_node(memory_id);
+        debug!("Forgot memory {}", memory_id);
+        Ok(Some(item))
+    }
+
+    /// Get memory associations, leaving out other tenants' memories
     pub async fn get_associations(&self, memory_id: Uuid) -> Result<Vec<Uuid>> {
+        self.get_associations_for(DEFAULT_TENANT, memory_id).await
+    }
+
+    pub(crate) async fn get_associations_for(&self, tenant_id: &str, memory_id: Uuid) -> Result<Vec<Uuid>> {
+        self.check_owner(tenant_id, memory_id)?;
+        let graph = self.memory_graph.read().await;
+        let associations = graph.get_associations(memory_id).await?;
+        Ok(associations.into_iter().filter(|id| self.check_owner(tenant_id, *id).is_ok()).collect())
+    }
+
+    /// The association graph, as far as `filter` selects it, of the default tenant's
+    /// memories and the shared procedural and spatial ones
+    pub async fn association_graph(&self, filter: &GraphFilter) -> AssociationGraph {
+        let graph = self.memory_graph.read().await;
+        graph.select(filter, |node| self.check_owner(DEFAULT_TENANT, node.memory_id).is_ok())
+    }
+
+    /// The association graph of the short-term, long-term and episodic memories of
+    /// `tenant_id` that `keep` accepts
+    pub(crate) async fn association_graph_for(
+        &self,
+        tenant_id: &str,
+        filter: &GraphFilter,
+        keep: impl Fn(&MemoryItem) -> bool,
+    ) -> AssociationGraph {
         let graph = self.memory_graph.read().await;
-        Ok(graph.get_associations(memory_id).await?)
+        graph.select(filter, |node| {
+            self.find_memory(node.memory_id).is_some_and(|item| item.tenant_id == tenant_id && keep(&item))
+        })
     }
 
     /// Update memory importance
     pub async fn update_importance(&self, memory_id: Uuid, new_importance: f64) -> Result<()> {
+        self.update_importance_for(DEFAULT_TENANT, memory_id, new_importance).await
+    }
+
+    pub(crate) async fn update_importance_for(&self, tenant_id: &str, memory_id: Uuid, new_importance: f64) -> Result<()> {
+        self.check_owner(tenant_id, memory_id)?;
         if let Some(mut active_memory) = self.active_memories.get_mut(&memory_id) {
             active_memory.importance_score = new_importance;
             
@@ -339,6 +697,8 @@ impl MemoryContinuum {
         
         let graph = self.memory_graph.read().await;
         let associations_count = graph.association_count().await?;
+
+        let scheduler = self.consolidation_scheduler.lock().await;
         
         Ok(MemoryStatistics {
             total_memories: stm_count + ltm_count + procedural_count + episodic_count + spatial_count,
@@ -349,26 +709,48 @@ impl MemoryContinuum {
             spatial_count,
             associations_count,
             active_memories_count: self.active_memories.len(),
+            consolidation_queue_depth: scheduler.depth(),
+            consolidation_max_wait: scheduler.max_wait(Instant::now()),
         })
     }
 
-    /// Run consolidation process
+    /// Run consolidation process: consolidate the scheduled memories that are due,
+    /// highest priority first. Does nothing until the consolidation interval has passed
+    /// since the previous run.
     pub async fn run_consolidation(&self) -> Result<ConsolidationResult> {
-        info!("🔄 Running memory consolidation");
-        
-        let result = self.consolidation.consolidate().await?;
-        
-        // Update consolidation scheduler
-        {
+        let due = {
             let mut scheduler = self.consolidation_scheduler.lock().await;
-            scheduler.last_consolidation = Instant::now();
-            scheduler.pending_consolidations.clear();
-        }
+            let now = Instant::now();
+            if !scheduler.is_due(now) {
+                debug!("Skipping consolidation, last run was under {:?} ago", scheduler.consolidation_interval);
+                return Ok(ConsolidationResult::default());
+            }
+            scheduler.last_consolidation = now;
+            scheduler.take_due(now)
+        };
+
+        info!("🔄 Running memory consolidation for {} memories", due.len());
+        
+        let memory_ids: Vec<Uuid> = due.iter().map(|task| task.memory_id).collect();
+        let result = self.consolidation.consolidate_memories(&memory_ids).await?;
         
         info!("Consolidation completed: {} memories processed", result.processed_count);
         Ok(result)
     }
 
+    /// Consolidate every queued memory now, ignoring the interval and delays, so nothing
+    /// scheduled is lost when the process stops
+    pub async fn shutdown(&self) -> Result<ConsolidationResult> {
+        let pending = self.consolidation_scheduler.lock().await.take_all();
+        if pending.is_empty() {
+            return Ok(ConsolidationResult::default());
+        }
+
+        info!("Consolidating {} queued memories before shutdown", pending.len());
+        let memory_ids: Vec<Uuid> = pending.iter().map(|task| task.memory_id).collect();
+        self.consolidation.consolidate_memories(&memory_ids).await
+    }
+
     /// Encode memory content based on type
     async fn encode_memory(&self, content: &serde_json::Value, memory_type: &MemoryType) -> Result<MemoryEncoding> {
         match memory_type {
@@ -397,18 +779,25 @@ impl MemoryContinuum {
         }
     }
 
-    /// Schedule memory consolidation
+    /// Schedule memory consolidation, updating the priority of an already pending memory
     async fn schedule_consolidation(&self, memory_id: Uuid, priority: f64) {
         let mut scheduler = self.consolidation_scheduler.lock().await;
-        
-        let task = ConsolidationTask {
-            memory_id,
-            scheduled_at: Instant::now() + Duration::from_secs(60), // 1 minute delay
-            priority,
-        };
-        
-        scheduler.pending_consolidations.push(task);
-        scheduler.pending_consolidations.sort_by(|a, b| b.priority.partial_cmp(&a.priority).unwrap());
+        scheduler.schedule(memory_id, priority, Instant::now());
+    }
+
+    fn index_tags(&self, memory_id: Uuid, tags: &[String]) {
+        for tag in tags {
+            self.tag_index.entry(tag.clone()).or_default().insert(memory_id);
+        }
+    }
+
+    fn unindex_tags(&self, memory_id: Uuid, tags: &[String]) {
+        for tag in tags {
+            if let Some(mut ids) = self.tag_index.get_mut(tag) {
+                ids.remove(&memory_id);
+            }
+            self.tag_index.remove_if(tag, |_, ids| ids.is_empty());
+        }
     }
 
     /// Update memory access pattern
@@ -426,6 +815,28 @@ impl MemoryContinuum {
     }
 }
 
+/// Tag recording that a memory's content is a serialized `T`
+fn type_tag<T>() -> String {
+    format!("{}{}", TYPE_TAG_PREFIX, std::any::type_name::<T>())
+}
+
+/// A memory whose content was deserialized by `retrieve_typed`
+#[derive(Debug, Clone)]
+pub struct TypedMemory<T> {
+    pub id: Uuid,
+    pub content: T,
+    pub metadata: MemoryMetadata,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Result of `retrieve_typed`
+#[derive(Debug, Clone)]
+pub struct TypedRetrieval<T> {
+    pub memories: Vec<TypedMemory<T>>,
+    /// Matching items whose content failed to deserialize
+    pub skipped: usize,
+}
+
 /// Memory statistics
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MemoryStatistics {
@@ -437,10 +848,14 @@ pub struct MemoryStatistics {
     pub spatial_count: usize,
     pub associations_count: usize,
     pub active_memories_count: usize,
+    /// Memories waiting to be consolidated
+    pub consolidation_queue_depth: usize,
+    /// Longest any of those has been waiting since it was scheduled
+    pub consolidation_max_wait: Duration,
 }
 
 /// Consolidation result
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct ConsolidationResult {
     pub processed_count: usize,
     pub promoted_to_ltm: usize,
@@ -459,6 +874,50 @@ mod tests {
         assert!(continuum.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_invalid_configs_are_rejected_with_every_violation() {
+        let default = MemoryConfig::default;
+        let cases = [
+            (MemoryConfig { stm_capacity: 0, ..default() }, MemoryConfig { stm_capacity: 1, ..default() }, "stm_capacity"),
+            (default().with_ltm_capacity(0), default().with_ltm_capacity(1), "ltm_capacity"),
+            (default().with_consolidation_threshold(1.5), default().with_consolidation_threshold(1.0), "consolidation_threshold"),
+            (
+                MemoryConfig { forgetting_curve_factor: -0.1, ..default() },
+                MemoryConfig { forgetting_curve_factor: 0.0, ..default() },
+                "forgetting_curve_factor",
+            ),
+            (
+                MemoryConfig { importance_decay_rate: f64::NAN, ..default() },
+                MemoryConfig { importance_decay_rate: 0.0, ..default() },
+                "importance_decay_rate",
+            ),
+            (
+                MemoryConfig { spatial_resolution: 0.0, ..default() },
+                MemoryConfig { spatial_resolution: 0.5, ..default() },
+                "spatial_resolution",
+            ),
+            (
+                MemoryConfig { episodic_compression_ratio: 0.0, ..default() },
+                MemoryConfig { episodic_compression_ratio: 1.0, ..default() },
+                "episodic_compression_ratio",
+            ),
+            (
+                MemoryConfig { max_consolidations_per_run: 0, ..default() },
+                MemoryConfig { max_consolidations_per_run: 1, ..default() },
+                "max_consolidations_per_run",
+            ),
+        ];
+        for (failing, passing, field) in cases {
+            assert_eq!(failing.violations().fields(), [field]);
+            assert!(passing.validate().is_ok(), "{}", field);
+        }
+
+        let config = MemoryConfig::default().with_stm_capacity(0).with_consolidation_threshold(-1.0);
+        let err = talkpp_errors::Error::from(MemoryContinuum::new(config).await.err().unwrap());
+        assert_eq!(err.kind(), talkpp_errors::ErrorKind::InvalidInput);
+        assert_eq!(err.downcast_ref::<talkpp_errors::InvalidConfig>().unwrap().violations.len(), 2);
+    }
+
     #[tokio::test]
     async fn test_memory_storage_and_retrieval() {
         let config = MemoryConfig::default();
@@ -478,6 +937,8 @@ mod tests {
                 context_relevance: 0.8,
                 emotional_valence: 0.0,
             },
+            pii_allowlist: Vec::new(),
+            redactions: None,
         };
 
         let memory_id = continuum.store_memory(
@@ -495,4 +956,190 @@ mod tests {
         assert!(!memories.is_empty());
         assert_eq!(memories[0].id, memory_id);
     }
-} 
\ No newline at end of file
+    #[tokio::test]
+    async fn test_consolidation_drains_scheduled_memories() {
+        let config = MemoryConfig {
+            consolidation_interval: Duration::ZERO,
+            consolidation_delay: Duration::ZERO,
+            ..MemoryConfig::default()
+        };
+        let continuum = MemoryContinuum::new(config).await.unwrap();
+        let metadata = |importance: f64| MemoryMetadata {
+            importance,
+            confidence: 0.9,
+            source: "test".to_string(),
+            tags: vec!["deployment".to_string()],
+            associations: vec![],
+            consolidation_level: 0,
+            access_pattern: AccessPattern {
+                frequency: 1.0,
+                recency: 1.0,
+                context_relevance: 0.8,
+                emotional_valence: 0.0,
+            },
+            pii_allowlist: Vec::new(),
+            redactions: None,
+        };
+
+        let first = continuum.store_memory(serde_json::json!("first"), MemoryType::ShortTerm, metadata(0.8)).await.unwrap();
+        let second = continuum.store_memory(serde_json::json!("second"), MemoryType::ShortTerm, metadata(0.9)).await.unwrap();
+        continuum.store_memory(serde_json::json!("minor"), MemoryType::ShortTerm, metadata(0.2)).await.unwrap();
+        continuum.update_importance(first, 0.95).await.unwrap();
+
+        let stats = continuum.get_statistics().await.unwrap();
+        assert_eq!(stats.consolidation_queue_depth, 2);
+
+        let result = continuum.run_consolidation().await.unwrap();
+        assert_eq!((result.processed_count, result.promoted_to_ltm, result.associations_created), (2, 2, 1));
+        assert_eq!(continuum.get_associations(first).await.unwrap(), vec![second]);
+
+        let stats = continuum.get_statistics().await.unwrap();
+        assert_eq!((stats.consolidation_queue_depth, stats.consolidation_max_wait), (0, Duration::ZERO));
+        assert_eq!((stats.short_term_count, stats.long_term_count), (1, 2));
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_consolidates_memories_that_are_not_due_yet() {
+        let continuum = MemoryContinuum::new(MemoryConfig::default()).await.unwrap();
+        let important = MemoryMetadata { importance: 0.9, ..tagged(&["deployment"]) };
+        continuum.store_memory(serde_json::json!("keep me"), MemoryType::ShortTerm, important).await.unwrap();
+
+        assert_eq!(continuum.run_consolidation().await.unwrap().processed_count, 0);
+        assert_eq!(continuum.get_statistics().await.unwrap().consolidation_queue_depth, 1);
+
+        assert_eq!(continuum.shutdown().await.unwrap().processed_count, 1);
+        let stats = continuum.get_statistics().await.unwrap();
+        assert_eq!((stats.consolidation_queue_depth, stats.long_term_count), (0, 1));
+    }
+
+    #[tokio::test]
+    async fn test_sanitized_memories_are_found_by_surrounding_context() {
+        let continuum = MemoryContinuum::new(MemoryConfig::default()).await.unwrap()
+            .with_sanitizer(TextSanitizer::default().with_action("credit_card", talkpp_sanitizer::SanitizeAction::DropChunk));
+        let content = serde_json::json!({
+            "text": "Ada asked for the turbine invoice at ada@example.com",
+            "reply_to": "billing@example.com",
+        });
+        let metadata = MemoryMetadata { pii_allowlist: vec!["reply_to".to_string()], ..tagged(&["billing"]) };
+        continuum.store_memory(content, MemoryType::ShortTerm, metadata).await.unwrap();
+
+        let memories = continuum.retrieve_memories("turbine invoice", vec![MemoryType::ShortTerm], 10).await.unwrap();
+        assert_eq!(memories.len(), 1);
+        let text = memories[0].content["text"].as_str().unwrap();
+        assert!(text.starts_with("Ada asked for the turbine invoice at <EMAIL_") && !text.contains("ada@"));
+        assert_eq!(memories[0].content["reply_to"], "billing@example.com");
+        let redactions = memories[0].metadata.redactions.as_ref().unwrap();
+        assert_eq!(redactions.fields["content/text"][0].offsets, vec![(37, 52)]);
+
+        let card = serde_json::json!("Paid with 4111 1111 1111 1111");
+        let refused = continuum.store_memory(card, MemoryType::ShortTerm, tagged(&["billing"])).await.unwrap_err();
+        assert_eq!(talkpp_errors::Error::from(refused).kind(), talkpp_errors::ErrorKind::InvalidInput);
+    }
+
+    fn tagged(tags: &[&str]) -> MemoryMetadata {
+        MemoryMetadata {
+            importance: 0.5,
+            confidence: 0.9,
+            source: "test".to_string(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            associations: vec![],
+            consolidation_level: 0,
+            access_pattern: AccessPattern {
+                frequency: 1.0,
+                recency: 1.0,
+                context_relevance: 0.8,
+                emotional_valence: 0.0,
+            },
+            pii_allowlist: Vec::new(),
+            redactions: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_by_tags_intersection_and_union() {
+        let config = MemoryConfig { stm_capacity: 3, ..MemoryConfig::default() };
+        let continuum = MemoryContinuum::new(config).await.unwrap();
+        let tags = |t: &[&str]| t.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+
+        let rollout = continuum.store_memory(serde_json::json!("rolled out api"), MemoryType::ShortTerm, tagged(&["deployment", "api"])).await.unwrap();
+        let rollback = continuum.store_memory(serde_json::json!("rolled back web"), MemoryType::LongTerm, tagged(&["deployment", "web"])).await.unwrap();
+        let outage = continuum.store_memory(serde_json::json!("api outage"), MemoryType::ShortTerm, tagged(&["incident", "api"])).await.unwrap();
+
+        let all = continuum.retrieve_by_tags(&tags(&["deployment", "api"]), true, None, 10).await.unwrap();
+        assert_eq!(all.iter().map(|m| m.id).collect::<Vec<_>>(), vec![rollout]);
+
+        let any = continuum.retrieve_by_tags(&tags(&["deployment", "api"]), false, None, 10).await.unwrap();
+        assert_eq!(any.iter().map(|m| m.id).collect::<Vec<_>>(), vec![outage, rollback, rollout]);
+        assert_eq!(continuum.retrieve_by_tags(&tags(&["deployment", "api"]), false, None, 1).await.unwrap()[0].id, outage);
+
+        let since = continuum.ltm.get(rollback).unwrap().created_at;
+        let recent = continuum.retrieve_by_tags(&tags(&["deployment"]), false, Some(since), 10).await.unwrap();
+        assert_eq!(recent.iter().map(|m| m.id).collect::<Vec<_>>(), vec![rollback]);
+        assert!(continuum.retrieve_by_tags(&tags(&["unknown", "api"]), true, None, 10).await.unwrap().is_empty());
+
+        // Evicting a short-term memory drops it from the index
+        for text in ["one", "two", "three"] {
+            continuum.store_memory(serde_json::json!(text), MemoryType::ShortTerm, tagged(&["filler"])).await.unwrap();
+        }
+        let api = continuum.retrieve_by_tags(&tags(&["api"]), false, None, 10).await.unwrap();
+        assert!(api.is_empty());
+        assert!(!continuum.tag_index.contains_key("incident"));
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Deployment {
+        service: String,
+        version: u32,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Incident {
+        service: String,
+        severity: String,
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_typed_filters_on_type_tag() {
+        let continuum = MemoryContinuum::new(MemoryConfig::default()).await.unwrap();
+
+        let deployment = Deployment { service: "api".to_string(), version: 7 };
+        let id = continuum.store_typed(&deployment, MemoryType::ShortTerm, tagged(&["deployment"])).await.unwrap();
+        let incident = Incident { service: "api".to_string(), severity: "high".to_string() };
+        continuum.store_typed(&incident, MemoryType::LongTerm, tagged(&["incident"])).await.unwrap();
+        continuum.store_memory(serde_json::json!({"service": "api"}), MemoryType::ShortTerm, tagged(&[])).await.unwrap();
+
+        let types = vec![MemoryType::ShortTerm, MemoryType::LongTerm];
+        let deployments = continuum.retrieve_typed::<Deployment>("api", types.clone(), 10).await.unwrap();
+        assert_eq!(deployments.memories.len(), 1);
+        assert_eq!(deployments.memories[0].id, id);
+        assert_eq!(deployments.memories[0].content, deployment);
+        assert_eq!(deployments.skipped, 0);
+
+        let incidents = continuum.retrieve_typed::<Incident>("", types.clone(), 10).await.unwrap();
+        assert_eq!(incidents.memories.iter().map(|m| &m.content).collect::<Vec<_>>(), vec![&incident]);
+
+        // Content tagged as a type it no longer deserializes to is skipped, not an error
+        let mut corrupt = tagged(&["deployment"]);
+        corrupt.tags.push(type_tag::<Deployment>());
+        continuum.store_memory(serde_json::json!({"service": "web"}), MemoryType::ShortTerm, corrupt).await.unwrap();
+        let deployments = continuum.retrieve_typed::<Deployment>("", types, 10).await.unwrap();
+        assert_eq!((deployments.memories.len(), deployments.skipped), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn test_forget_memory_drops_item_tags_and_associations() {
+        let continuum = MemoryContinuum::new(MemoryConfig::default()).await.unwrap();
+        let kept = continuum.store_memory(serde_json::json!("kept"), MemoryType::LongTerm, tagged(&["shared"])).await.unwrap();
+        let mut linked = tagged(&["shared", "doomed"]);
+        linked.associations.push(kept);
+        let doomed = continuum.store_memory(serde_json::json!("doomed"), MemoryType::ShortTerm, linked).await.unwrap();
+        assert_eq!(continuum.get_associations(kept).await.unwrap(), vec![doomed]);
+
+        assert_eq!(continuum.forget_memory(doomed).await.unwrap().unwrap().id, doomed);
+        assert!(continuum.get_memory(doomed).is_none());
+        assert!(continuum.get_memory(kept).is_some());
+        assert!(continuum.get_associations(kept).await.unwrap().is_empty());
+        assert!(!continuum.tag_index.contains_key("doomed"));
+        assert!(continuum.forget_memory(doomed).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,38 @@
+use anyhow::Result;
+use dashmap::DashMap;
+use uuid::Uuid;
+
+use crate::MemoryItem;
+
+/// Durable memory for items worth keeping beyond the working set
+#[derive(Debug, Default)]
+pub struct LongTermMemory {
+    items: DashMap<Uuid, MemoryItem>,
+}
+
+impl LongTermMemory {
+    pub async fn new() -> Result<Self> {
+        Ok(Self::default())
+    }
+
+    pub async fn store(&self, item: MemoryItem) -> Result<()> {
+        self.items.insert(item.id, item);
+        Ok(())
+    }
+
+    pub fn get(&self, memory_id: Uuid) -> Option<MemoryItem> {
+        self.items.get(&memory_id).map(|item| item.clone())
+    }
+
+    pub fn remove(&self, memory_id: Uuid) -> Option<MemoryItem> {
+        self.items.remove(&memory_id).map(|(_, item)| item)
+    }
+
+    pub fn items(&self) -> Vec<MemoryItem> {
+        self.items.iter().map(|item| item.clone()).collect()
+    }
+
+    pub async fn count(&self) -> Result<usize> {
+        Ok(self.items.len())
+    }
+}
@@ -0,0 +1,46 @@
+use anyhow::Result;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+
+/// How to carry out a task, learned from past executions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Procedure {
+    pub name: String,
+    pub steps: Vec<String>,
+    #[serde(default)]
+    pub success_rate: f64,
+    #[serde(default)]
+    pub executions: u64,
+}
+
+impl Procedure {
+    pub fn from_json(content: &serde_json::Value) -> Result<Self> {
+        serde_json::from_value(content.clone())
+            .map_err(|e| anyhow::anyhow!("Content is not a procedure: {}", e))
+    }
+}
+
+/// Procedures by name; storing one under an existing name replaces it
+#[derive(Debug, Default)]
+pub struct ProceduralMemory {
+    procedures: DashMap<String, Procedure>,
+}
+
+impl ProceduralMemory {
+    pub async fn new() -> Result<Self> {
+        Ok(Self::default())
+    }
+
+    pub async fn store_procedure(&self, procedure: Procedure) -> Result<()> {
+        self.procedures.insert(procedure.name.clone(), procedure);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<Procedure> {
+        self.procedures.get(name).map(|p| p.clone())
+    }
+
+    pub async fn count(&self) -> Result<usize> {
+        Ok(self.procedures.len())
+    }
+}
@@ -0,0 +1,89 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::sync::RwLock;
+
+use crate::episodic::EpisodicMemory;
+use crate::graph::MemoryGraph;
+use crate::long_term::LongTermMemory;
+use crate::procedural::ProceduralMemory;
+use crate::short_term::ShortTermMemory;
+use crate::spatial::SpatialMemory;
+use crate::{MemoryEncoding, MemoryItem, MemoryType};
+
+/// Finds short-term, long-term and episodic memories matching a free-text query.
+///
+/// Procedural and spatial memories are looked up through their own stores.
+#[derive(Debug)]
+pub struct MemoryRetrieval {
+    stm: Arc<ShortTermMemory>,
+    ltm: Arc<LongTermMemory>,
+    episodic: Arc<EpisodicMemory>,
+}
+
+impl MemoryRetrieval {
+    pub fn new(
+        stm: Arc<ShortTermMemory>,
+        ltm: Arc<LongTermMemory>,
+        _procedural: Arc<ProceduralMemory>,
+        episodic: Arc<EpisodicMemory>,
+        _spatial: Arc<SpatialMemory>,
+        _graph: Arc<RwLock<MemoryGraph>>,
+    ) -> Self {
+        Self { stm, ltm, episodic }
+    }
+
+    /// Memories of the given types containing any of the query's words, those matching
+    /// the most words first, then the most important
+    pub async fn retrieve(&self, query: &str, memory_types: Vec<MemoryType>, limit: usize) -> Result<Vec<MemoryItem>> {
+        self.retrieve_matching(query, memory_types, limit, |_| true).await
+    }
+
+    /// Like `retrieve`, considering only memories accepted by `filter`. A query without
+    /// words matches every such memory.
+    pub async fn retrieve_matching(
+        &self,
+        query: &str,
+        memory_types: Vec<MemoryType>,
+        limit: usize,
+        filter: impl Fn(&MemoryItem) -> bool,
+    ) -> Result<Vec<MemoryItem>> {
+        let terms: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+
+        let mut candidates = Vec::new();
+        if memory_types.contains(&MemoryType::ShortTerm) {
+            candidates.extend(self.stm.items().await);
+        }
+        if memory_types.contains(&MemoryType::LongTerm) {
+            candidates.extend(self.ltm.items());
+        }
+        if memory_types.contains(&MemoryType::Episodic) {
+            candidates.extend(self.episodic.items());
+        }
+
+        let mut scored: Vec<(usize, MemoryItem)> = candidates.into_iter()
+            .filter(|item| filter(item))
+            .filter_map(|item| {
+                let text = searchable_text(&item);
+                let matched = terms.iter().filter(|term| text.contains(term.as_str())).count();
+                (matched > 0 || terms.is_empty()).then_some((matched, item))
+            })
+            .collect();
+        scored.sort_by(|(a_matched, a), (b_matched, b)| {
+            b_matched.cmp(a_matched)
+                .then_with(|| b.metadata.importance.total_cmp(&a.metadata.importance))
+                .then_with(|| b.created_at.cmp(&a.created_at))
+        });
+        Ok(scored.into_iter().take(limit).map(|(_, item)| item).collect())
+    }
+}
+
+/// Lowercased text and tags of a memory
+fn searchable_text(item: &MemoryItem) -> String {
+    let text = match &item.encoding {
+        MemoryEncoding::Text(text) => text.clone(),
+        MemoryEncoding::Episode(episode) => format!("{} {}", episode.description, episode.events.join(" ")),
+        _ => item.content.to_string(),
+    };
+    format!("{} {}", text, item.metadata.tags.join(" ")).to_lowercase()
+}
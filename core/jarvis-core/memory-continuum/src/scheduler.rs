@@ -0,0 +1,191 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::time::{Duration, Instant};
+
+use uuid::Uuid;
+
+/// A memory waiting to be consolidated
+#[derive(Debug, Clone)]
+pub(crate) struct ConsolidationTask {
+    pub memory_id: Uuid,
+    pub scheduled_at: Instant,
+    pub priority: f64,
+    /// When the memory was first queued; re-scheduling keeps it
+    pub enqueued_at: Instant,
+    /// Bumped on every re-schedule so the superseded heap entry can be recognised
+    version: u64,
+}
+
+impl PartialEq for ConsolidationTask {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for ConsolidationTask {}
+
+impl PartialOrd for ConsolidationTask {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ConsolidationTask {
+    /// Highest priority first, then the earliest scheduled
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority.total_cmp(&other.priority)
+            .then_with(|| other.scheduled_at.cmp(&self.scheduled_at))
+            .then_with(|| self.version.cmp(&other.version))
+    }
+}
+
+/// Queue of memories to consolidate, at most one entry per memory.
+///
+/// Re-scheduling a memory pushes a fresh heap entry and leaves the old one behind; stale
+/// entries are recognised by their version and dropped when they surface.
+#[derive(Debug)]
+pub(crate) struct ConsolidationScheduler {
+    queue: BinaryHeap<ConsolidationTask>,
+    /// Live entry of each queued memory
+    pending: HashMap<Uuid, ConsolidationTask>,
+    next_version: u64,
+    pub last_consolidation: Instant,
+    pub consolidation_interval: Duration,
+    /// How long after being scheduled a memory becomes due
+    pub delay: Duration,
+    /// Most memories consolidated in one run
+    pub max_per_run: usize,
+}
+
+impl ConsolidationScheduler {
+    pub fn new(consolidation_interval: Duration, delay: Duration, max_per_run: usize) -> Self {
+        Self {
+            queue: BinaryHeap::new(),
+            pending: HashMap::new(),
+            next_version: 0,
+            last_consolidation: Instant::now(),
+            consolidation_interval,
+            delay,
+            max_per_run,
+        }
+    }
+
+    /// Queue a memory, or update its priority if it is already queued. A re-scheduled
+    /// memory keeps its place in time.
+    pub fn schedule(&mut self, memory_id: Uuid, priority: f64, now: Instant) {
+        self.next_version += 1;
+        let task = match self.pending.get(&memory_id) {
+            Some(queued) => ConsolidationTask {
+                priority,
+                version: self.next_version,
+                ..queued.clone()
+            },
+            None => ConsolidationTask {
+                memory_id,
+                scheduled_at: now + self.delay,
+                priority,
+                enqueued_at: now,
+                version: self.next_version,
+            },
+        };
+        self.pending.insert(memory_id, task.clone());
+        self.queue.push(task);
+    }
+
+    /// Whether the consolidation interval has passed since the last run
+    pub fn is_due(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.last_consolidation) >= self.consolidation_interval
+    }
+
+    /// Remove and return up to `max_per_run` tasks whose time has come, highest
+    /// priority first
+    pub fn take_due(&mut self, now: Instant) -> Vec<ConsolidationTask> {
+        let mut due = Vec::new();
+        let mut waiting = Vec::new();
+        while due.len() < self.max_per_run {
+            let Some(task) = self.queue.pop() else {
+                break;
+            };
+            if self.pending.get(&task.memory_id).map(|t| t.version) != Some(task.version) {
+                continue;
+            }
+            if task.scheduled_at <= now {
+                self.pending.remove(&task.memory_id);
+                due.push(task);
+            } else {
+                waiting.push(task);
+            }
+        }
+        self.queue.extend(waiting);
+        due
+    }
+
+    /// Remove and return every queued task regardless of its time, highest priority first
+    pub fn take_all(&mut self) -> Vec<ConsolidationTask> {
+        self.queue.clear();
+        let mut all: Vec<_> = self.pending.drain().map(|(_, task)| task).collect();
+        all.sort_by(|a, b| b.cmp(a));
+        all
+    }
+
+    /// Number of memories waiting to be consolidated
+    pub fn depth(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Longest any queued memory has been waiting since it was first scheduled
+    pub fn max_wait(&self, now: Instant) -> Duration {
+        self.pending.values()
+            .map(|t| now.saturating_duration_since(t.enqueued_at))
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rescheduling_updates_priority_instead_of_duplicating() {
+        let now = Instant::now();
+        let mut scheduler = ConsolidationScheduler::new(Duration::ZERO, Duration::from_secs(60), 10);
+        let memory = Uuid::new_v4();
+        let other = Uuid::new_v4();
+
+        scheduler.schedule(memory, 0.75, now);
+        scheduler.schedule(other, 0.8, now);
+        scheduler.schedule(memory, 0.9, now + Duration::from_secs(30));
+        assert_eq!(scheduler.depth(), 2);
+        assert_eq!(scheduler.max_wait(now + Duration::from_secs(45)), Duration::from_secs(45));
+
+        // Re-scheduling did not push the memory's due time back
+        let due = scheduler.take_due(now + Duration::from_secs(60));
+        assert_eq!(due.iter().map(|t| t.memory_id).collect::<Vec<_>>(), vec![memory, other]);
+        assert_eq!(due[0].priority, 0.9);
+        assert_eq!(scheduler.depth(), 0);
+        assert!(scheduler.take_due(now + Duration::from_secs(120)).is_empty());
+    }
+
+    #[test]
+    fn test_drains_due_tasks_by_priority_up_to_the_cap() {
+        let now = Instant::now();
+        let mut scheduler = ConsolidationScheduler::new(Duration::from_secs(300), Duration::from_secs(60), 2);
+        let ids: Vec<Uuid> = (0..4).map(|_| Uuid::new_v4()).collect();
+
+        scheduler.schedule(ids[0], 0.75, now);
+        scheduler.schedule(ids[1], 0.95, now + Duration::from_secs(10));
+        scheduler.schedule(ids[2], 0.85, now);
+        // Highest priority, but not due until later
+        scheduler.schedule(ids[3], 0.99, now + Duration::from_secs(120));
+
+        let later = now + Duration::from_secs(90);
+        assert!(!scheduler.is_due(later));
+        let first = scheduler.take_due(later);
+        assert_eq!(first.iter().map(|t| t.memory_id).collect::<Vec<_>>(), vec![ids[1], ids[2]]);
+        let second = scheduler.take_due(later);
+        assert_eq!(second.iter().map(|t| t.memory_id).collect::<Vec<_>>(), vec![ids[0]]);
+        assert_eq!(scheduler.depth(), 1);
+        assert_eq!(scheduler.take_due(now + Duration::from_secs(180))[0].memory_id, ids[3]);
+    }
+}
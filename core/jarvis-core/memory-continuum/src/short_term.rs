@@ -0,0 +1,61 @@
+use std::collections::VecDeque;
+
+use anyhow::Result;
+use dashmap::DashMap;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use crate::MemoryItem;
+
+/// Bounded working memory; the oldest item is evicted to make room for a new one
+#[derive(Debug)]
+pub struct ShortTermMemory {
+    capacity: usize,
+    items: DashMap<Uuid, MemoryItem>,
+    /// Item ids, oldest first
+    order: Mutex<VecDeque<Uuid>>,
+}
+
+impl ShortTermMemory {
+    pub async fn new(capacity: usize) -> Result<Self> {
+        Ok(Self {
+            capacity,
+            items: DashMap::new(),
+            order: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    /// Store an item, returning the one evicted to make room for it, if any
+    pub async fn store(&self, item: MemoryItem) -> Result<Option<MemoryItem>> {
+        let mut order = self.order.lock().await;
+        let mut evicted = None;
+        if !self.items.contains_key(&item.id) {
+            if order.len() >= self.capacity {
+                evicted = order.pop_front().and_then(|id| self.items.remove(&id)).map(|(_, item)| item);
+            }
+            order.push_back(item.id);
+        }
+        self.items.insert(item.id, item);
+        Ok(evicted)
+    }
+
+    pub fn get(&self, memory_id: Uuid) -> Option<MemoryItem> {
+        self.items.get(&memory_id).map(|item| item.clone())
+    }
+
+    pub async fn remove(&self, memory_id: Uuid) -> Option<MemoryItem> {
+        let mut order = self.order.lock().await;
+        order.retain(|id| *id != memory_id);
+        self.items.remove(&memory_id).map(|(_, item)| item)
+    }
+
+    /// Snapshot of every item, oldest first
+    pub async fn items(&self) -> Vec<MemoryItem> {
+        let order = self.order.lock().await;
+        order.iter().filter_map(|id| self.get(*id)).collect()
+    }
+
+    pub async fn count(&self) -> Result<usize> {
+        Ok(self.items.len())
+    }
+}
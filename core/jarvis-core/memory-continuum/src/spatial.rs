@@ -0,0 +1,61 @@
+use anyhow::Result;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+
+/// A labelled point in space
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpatialData {
+    pub x: f64,
+    pub y: f64,
+    #[serde(default)]
+    pub z: f64,
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+impl SpatialData {
+    pub fn from_json(content: &serde_json::Value) -> Result<Self> {
+        serde_json::from_value(content.clone())
+            .map_err(|e| anyhow::anyhow!("Content is not spatial data: {}", e))
+    }
+}
+
+/// Points bucketed into grid cells `resolution` units wide
+#[derive(Debug)]
+pub struct SpatialMemory {
+    resolution: f64,
+    cells: DashMap<(i64, i64, i64), Vec<SpatialData>>,
+}
+
+impl SpatialMemory {
+    pub async fn new(resolution: f64) -> Result<Self> {
+        Ok(Self {
+            resolution,
+            cells: DashMap::new(),
+        })
+    }
+
+    pub async fn store_spatial_data(&self, data: SpatialData) -> Result<()> {
+        let cell = self.cell_of(&data);
+        self.cells.entry(cell).or_default().push(data);
+        Ok(())
+    }
+
+    /// Points in the same grid cell as `point`
+    pub fn nearby(&self, point: &SpatialData) -> Vec<SpatialData> {
+        self.cells.get(&self.cell_of(point)).map(|c| c.clone()).unwrap_or_default()
+    }
+
+    pub async fn count(&self) -> Result<usize> {
+        Ok(self.cells.iter().map(|c| c.len()).sum())
+    }
+
+    fn cell_of(&self, data: &SpatialData) -> (i64, i64, i64) {
+        let resolution = self.resolution.max(f64::EPSILON);
+        (
+            (data.x / resolution).floor() as i64,
+            (data.y / resolution).floor() as i64,
+            (data.z / resolution).floor() as i64,
+        )
+    }
+}
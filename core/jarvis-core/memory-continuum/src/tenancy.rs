@@ -0,0 +1,198 @@
+//! One tenant's view of the continuum
+//!
+//! Every short-term, long-term and episodic memory records the tenant it was stored for.
+//! [`TenantMemories`] stores memories as its tenant's and only finds, changes or forgets
+//! that tenant's: another tenant's memories are treated as missing, and associating with
+//! or updating one fails as not found. The continuum's own methods are the view of
+//! [`DEFAULT_TENANT`](talkpp_tenancy::DEFAULT_TENANT), which memories serialized before
+//! tenants existed also belong to. Procedural and spatial stores are shared, so only the
+//! default tenant stores memories of those types.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use talkpp_tenancy::TenantContext;
+use uuid::Uuid;
+
+use crate::{AssociationGraph, GraphFilter, MemoryContinuum, MemoryItem, MemoryMetadata, MemoryType, TypedRetrieval};
+
+/// The continuum as one tenant sees it; see the [module docs](self)
+pub struct TenantMemories<'a> {
+    continuum: &'a MemoryContinuum,
+    tenant: &'a TenantContext,
+}
+
+impl MemoryContinuum {
+    /// The continuum as `tenant` sees it
+    pub fn for_tenant<'a>(&'a self, tenant: &'a TenantContext) -> TenantMemories<'a> {
+        TenantMemories { continuum: self, tenant }
+    }
+}
+
+impl TenantMemories<'_> {
+    pub fn tenant(&self) -> &TenantContext {
+        self.tenant
+    }
+
+    fn tenant_id(&self) -> &str {
+        &self.tenant.tenant_id
+    }
+
+    pub async fn store_memory(&self, content: serde_json::Value, memory_type: MemoryType, metadata: MemoryMetadata) -> Result<Uuid> {
+        self.continuum.store_memory_for(self.tenant_id(), content, memory_type, metadata).await
+    }
+
+    pub async fn retrieve_memories(&self, query: &str, memory_types: Vec<MemoryType>, limit: usize) -> Result<Vec<MemoryItem>> {
+        self.retrieve_memories_matching(query, memory_types, limit, |_| true).await
+    }
+
+    pub async fn retrieve_memories_matching(
+        &self,
+        query: &str,
+        memory_types: Vec<MemoryType>,
+        limit: usize,
+        filter: impl Fn(&MemoryItem) -> bool,
+    ) -> Result<Vec<MemoryItem>> {
+        self.continuum.retrieve_memories_matching_for(self.tenant_id(), query, memory_types, limit, filter).await
+    }
+
+    pub async fn store_typed<T: Serialize>(&self, content: &T, memory_type: MemoryType, metadata: MemoryMetadata) -> Result<Uuid> {
+        self.continuum.store_typed_for(self.tenant_id(), content, memory_type, metadata).await
+    }
+
+    pub async fn retrieve_typed<T: DeserializeOwned>(
+        &self,
+        query: &str,
+        memory_types: Vec<MemoryType>,
+        limit: usize,
+    ) -> Result<TypedRetrieval<T>> {
+        self.continuum.retrieve_typed_for(self.tenant_id(), query, memory_types, limit).await
+    }
+
+    pub async fn retrieve_by_tags(
+        &self,
+        tags: &[String],
+        match_all: bool,
+        since: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> Result<Vec<MemoryItem>> {
+        self.continuum.retrieve_by_tags_for(self.tenant_id(), tags, match_all, since, limit).await
+    }
+
+    pub fn get_memory(&self, memory_id: Uuid) -> Option<MemoryItem> {
+        self.continuum.get_memory_for(self.tenant_id(), memory_id)
+    }
+
+    pub async fn forget_memory(&self, memory_id: Uuid) -> Result<Option<MemoryItem>> {
+        self.continuum.forget_memory_for(self.tenant_id(), memory_id).await
+    }
+
+    pub async fn get_associations(&self, memory_id: Uuid) -> Result<Vec<Uuid>> {
+        self.continuum.get_associations_for(self.tenant_id(), memory_id).await
+    }
+
+    pub async fn update_importance(&self, memory_id: Uuid, new_importance: f64) -> Result<()> {
+        self.continuum.update_importance_for(self.tenant_id(), memory_id, new_importance).await
+    }
+
+    /// The association graph of the tenant's short-term, long-term and episodic
+    /// memories, as far as `filter` selects it
+    pub async fn association_graph(&self, filter: &GraphFilter) -> AssociationGraph {
+        self.association_graph_matching(filter, |_| true).await
+    }
+
+    pub async fn association_graph_matching(&self, filter: &GraphFilter, keep: impl Fn(&MemoryItem) -> bool) -> AssociationGraph {
+        self.continuum.association_graph_for(self.tenant_id(), filter, keep).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AccessPattern, MemoryConfig};
+
+    fn metadata(tags: &[&str], associations: Vec<Uuid>) -> MemoryMetadata {
+        MemoryMetadata {
+            importance: 0.5,
+            confidence: 0.9,
+            source: "test".to_string(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            associations,
+            consolidation_level: 0,
+            access_pattern: AccessPattern {
+                frequency: 1.0,
+                recency: 1.0,
+                context_relevance: 1.0,
+                emotional_valence: 0.0,
+            },
+            pii_allowlist: Vec::new(),
+            redactions: None,
+        }
+    }
+
+    fn is_not_found(error: anyhow::Error) -> bool {
+        talkpp_errors::Error::from(error).kind() == talkpp_errors::ErrorKind::NotFound
+    }
+
+    #[derive(Serialize, serde::Deserialize)]
+    struct Preference {
+        standup: String,
+    }
+
+    #[tokio::test]
+    async fn test_tenants_cannot_reach_each_others_memories() {
+        let continuum = MemoryContinuum::new(MemoryConfig::default()).await.unwrap();
+        let (acme, globex) = (TenantContext::new("acme", "ada").unwrap(), TenantContext::new("globex", "ada").unwrap());
+        let (ours, theirs) = (continuum.for_tenant(&acme), continuum.for_tenant(&globex));
+
+        let note = ours.store_memory(serde_json::json!("standups at nine"), MemoryType::LongTerm, metadata(&["team"], vec![])).await.unwrap();
+        let linked = ours.store_memory(serde_json::json!("standups moved"), MemoryType::ShortTerm, metadata(&["team"], vec![note])).await.unwrap();
+        ours.store_typed(&Preference { standup: "nine".to_string() }, MemoryType::ShortTerm, metadata(&[], vec![])).await.unwrap();
+        let types = vec![MemoryType::ShortTerm, MemoryType::LongTerm, MemoryType::Episodic];
+
+        // Every read path comes back empty for the other tenant
+        assert!(theirs.retrieve_memories("standups", types.clone(), 10).await.unwrap().is_empty());
+        assert!(theirs.retrieve_memories_matching("", types.clone(), 10, |_| true).await.unwrap().is_empty());
+        assert!(theirs.retrieve_typed::<Preference>("", types.clone(), 10).await.unwrap().memories.is_empty());
+        assert!(theirs.retrieve_by_tags(&["team".to_string()], false, None, 10).await.unwrap().is_empty());
+        assert!(theirs.get_memory(note).is_none());
+        // ...as it does through the continuum's own methods, which act for the default tenant
+        assert!(continuum.retrieve_memories("standups", types.clone(), 10).await.unwrap().is_empty());
+        assert!(continuum.get_memory(note).is_none());
+
+        // Writes touching the other tenant's memories are refused
+        assert!(is_not_found(theirs.get_associations(note).await.unwrap_err()));
+        assert!(is_not_found(theirs.update_importance(note, 1.0).await.unwrap_err()));
+        let err = theirs.store_memory(serde_json::json!("link"), MemoryType::ShortTerm, metadata(&[], vec![note])).await.unwrap_err();
+        assert!(is_not_found(err));
+        assert!(theirs.forget_memory(note).await.unwrap().is_none());
+        assert!(continuum.forget_memory(note).await.unwrap().is_none());
+
+        // The owner still has everything
+        assert_eq!(ours.retrieve_by_tags(&["team".to_string()], true, None, 10).await.unwrap().len(), 2);
+        assert_eq!(ours.get_associations(note).await.unwrap(), vec![linked]);
+        assert_eq!(ours.retrieve_typed::<Preference>("", types, 10).await.unwrap().memories[0].content.standup, "nine");
+        assert_eq!(ours.forget_memory(note).await.unwrap().unwrap().tenant_id, "acme");
+
+        // Shared stores stay with the default tenant
+        let procedure = serde_json::json!({"name": "deploy", "steps": []});
+        let err = theirs.store_memory(procedure, MemoryType::Procedural, metadata(&[], vec![])).await.unwrap_err();
+        assert_eq!(talkpp_errors::Error::from(err).kind(), talkpp_errors::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_memories_from_before_tenants_belong_to_the_default_one() {
+        let item = serde_json::json!({
+            "id": Uuid::new_v4(),
+            "content": "standups at nine",
+            "memory_type": "ShortTerm",
+            "encoding": {"Text": "standups at nine"},
+            "metadata": serde_json::to_value(metadata(&[], vec![])).unwrap(),
+            "created_at": Utc::now(),
+            "last_accessed": Utc::now(),
+        });
+        let item: MemoryItem = serde_json::from_value(item).unwrap();
+        assert_eq!(item.tenant_id, talkpp_tenancy::DEFAULT_TENANT);
+    }
+}
@@ -0,0 +1,141 @@
+//! Generation that can be stopped part way
+//!
+//! Every [`LanguageModel`] stops generating once the receiver of its stream is dropped.
+//! [`generate_cancellable`] reads the stream until the model finishes or a
+//! `CancellationToken` fires, and drops it straight away when the token does, so
+//! cancelling stops token generation rather than only discarding what comes after.
+
+use anyhow::Result;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use crate::LanguageModel;
+
+/// What [`generate_cancellable`] produced
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Generation {
+    pub text: String,
+    /// Generation was stopped by the token before the model finished
+    pub cancelled: bool,
+}
+
+/// Generate with `model`, passing each piece to `pieces` as it arrives, until the model
+/// finishes or `cancel` fires. The text generated before a cancellation is kept.
+pub async fn generate_cancellable(
+    model: &(dyn LanguageModel + Send + Sync),
+    prompt: &str,
+    max_tokens: usize,
+    pieces: Option<&mpsc::UnboundedSender<String>>,
+    cancel: &CancellationToken,
+) -> Result<Generation> {
+    let mut stream = tokio::select! {
+        biased;
+        _ = cancel.cancelled() => return Ok(Generation { text: String::new(), cancelled: true }),
+        stream = model.generate_stream(prompt, max_tokens) => stream?,
+    };
+
+    let mut generation = Generation::default();
+    loop {
+        let piece = tokio::select! {
+            biased;
+            _ = cancel.cancelled() => {
+                generation.cancelled = true;
+                break;
+            }
+            piece = stream.recv() => piece,
+        };
+        let Some(piece) = piece else { break };
+        if let Some(pieces) = pieces {
+            // A reader that went away does not stop generation; only the token does
+            let _ = pieces.send(piece.clone());
+        }
+        generation.text.push_str(&piece);
+    }
+    Ok(generation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// Streams numbered tokens one per `interval`, recording how many it sent and
+    /// whether it saw its receiver go away
+    #[derive(Default)]
+    struct Counter {
+        sent: Arc<AtomicUsize>,
+        stopped: Arc<AtomicBool>,
+    }
+
+    #[async_trait]
+    impl LanguageModel for Counter {
+        async fn generate(&self, _prompt: &str, _max_tokens: usize) -> Result<String> {
+            unreachable!()
+        }
+
+        async fn generate_stream(&self, _prompt: &str, max_tokens: usize) -> Result<mpsc::Receiver<String>> {
+            let (tx, rx) = mpsc::channel(1);
+            let (sent, stopped) = (self.sent.clone(), self.stopped.clone());
+            tokio::spawn(async move {
+                for i in 0..max_tokens {
+                    if tx.send(format!("{} ", i)).await.is_err() {
+                        stopped.store(true, Ordering::SeqCst);
+                        return;
+                    }
+                    sent.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            });
+            Ok(rx)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generation_runs_to_completion_without_cancellation() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let generation = generate_cancellable(&Counter::default(), "count", 3, Some(&tx), &CancellationToken::new())
+            .await
+            .unwrap();
+        assert_eq!(generation, Generation { text: "0 1 2 ".to_string(), cancelled: false });
+        drop(tx);
+        let mut pieces = Vec::new();
+        while let Some(piece) = rx.recv().await {
+            pieces.push(piece);
+        }
+        assert_eq!(pieces, ["0 ", "1 ", "2 "]);
+    }
+
+    #[tokio::test]
+    async fn test_cancelling_keeps_partial_text_and_stops_the_model() {
+        let model = Counter::default();
+        let cancel = CancellationToken::new();
+        tokio::spawn({
+            let cancel = cancel.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(55)).await;
+                cancel.cancel();
+            }
+        });
+
+        let generation = generate_cancellable(&model, "count", 1000, None, &cancel).await.unwrap();
+        assert!(generation.cancelled);
+        assert!(generation.text.starts_with("0 1 2 "), "{}", generation.text);
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(model.stopped.load(Ordering::SeqCst));
+        assert!(model.sent.load(Ordering::SeqCst) < 20);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_token_never_starts_the_stream() {
+        let model = Counter::default();
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let generation = generate_cancellable(&model, "count", 10, None, &cancel).await.unwrap();
+        assert_eq!(generation, Generation { text: String::new(), cancelled: true });
+        assert_eq!(model.sent.load(Ordering::SeqCst), 0);
+    }
+}
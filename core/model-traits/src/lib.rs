@@ -0,0 +1,20 @@
+//! Model interfaces, and the prompt templates models are called with, shared by crates
+//! that run models and crates that call them, so that neither has to depend on the other.
+
+pub mod generation;
+pub mod prompts;
+pub mod repository;
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+pub use generation::{generate_cancellable, Generation};
+
+/// Text generation
+#[async_trait]
+pub trait LanguageModel {
+    async fn generate(&self, prompt: &str, max_tokens: usize) -> Result<String>;
+
+    /// Generated text as it is produced. Dropping the receiver stops generation.
+    async fn generate_stream(&self, prompt: &str, max_tokens: usize) -> Result<tokio::sync::mpsc::Receiver<String>>;
+}
@@ -0,0 +1,283 @@
+//! Versioned prompt templates
+//!
+//! A template is a TOML file whose name is the template id:
+//!
+//! ```toml
+//! version = "2"
+//! description = "Open-ended analysis of a research query"   # optional
+//! template = """Summarise {{ query }} for {{ audience }}."""
+//! ```
+//!
+//! `{{ name }}` is replaced by the variable `name`; values are inserted as they are and
+//! never scanned for further references. The defaults are compiled in from this crate's
+//! `prompts` directory, and a directory of files in the same format can add templates
+//! or replace defaults by id. Every rendered prompt carries the id and version of the
+//! template it came from, so results can be traced back to the prompt that produced them.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use thiserror::Error;
+
+/// Templates compiled into the library
+const BUILTIN: &[(&str, &str)] = &[
+    ("code_generation", include_str!("../prompts/code_generation.toml")),
+    ("rag_answer", include_str!("../prompts/rag_answer.toml")),
+    ("research_assistant", include_str!("../prompts/research_assistant.toml")),
+];
+
+#[derive(Debug, Error)]
+pub enum PromptError {
+    #[error("Unknown prompt template: {0}")]
+    UnknownTemplate(String),
+
+    #[error("Prompt template {template} needs variable '{variable}'")]
+    MissingVariable { template: String, variable: String },
+
+    #[error("Prompt template {template} has an unclosed '{{{{' at byte {offset}")]
+    Unclosed { template: String, offset: usize },
+
+    #[error("Invalid prompt template {template}: {message}")]
+    Invalid { template: String, message: String },
+
+    #[error("Failed to read prompt templates from {path}: {source}")]
+    Io { path: String, source: std::io::Error },
+}
+
+impl From<PromptError> for talkpp_errors::Error {
+    fn from(error: PromptError) -> Self {
+        let kind = match &error {
+            PromptError::UnknownTemplate(_) => talkpp_errors::ErrorKind::NotFound,
+            PromptError::MissingVariable { .. } | PromptError::Unclosed { .. } | PromptError::Invalid { .. } => {
+                talkpp_errors::ErrorKind::InvalidInput
+            }
+            PromptError::Io { .. } => talkpp_errors::ErrorKind::Internal,
+        };
+        Self::transparent(kind, error)
+    }
+}
+
+/// A prompt with `{{ name }}` placeholders
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PromptTemplate {
+    #[serde(skip)]
+    pub id: String,
+    pub version: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub template: String,
+}
+
+/// Text of a rendered template, with the template it was rendered from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RenderedPrompt {
+    pub text: String,
+    pub template_id: String,
+    pub version: String,
+}
+
+impl PromptTemplate {
+    /// Parse a template in the TOML format described in the [module docs](self)
+    pub fn from_toml_str(id: impl Into<String>, source: &str) -> Result<Self, PromptError> {
+        let id = id.into();
+        let mut template: Self = toml::from_str(source)
+            .map_err(|e| PromptError::Invalid { template: id.clone(), message: e.to_string() })?;
+        template.id = id;
+        template.parts()?;
+        Ok(template)
+    }
+
+    /// Names of the variables the template uses, in order of first use
+    pub fn variables(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        for part in self.parts().unwrap_or_default() {
+            if let Part::Variable(name) = part {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+        names
+    }
+
+    /// The template with each placeholder replaced by its value in `vars`
+    pub fn render(&self, vars: &[(&str, &str)]) -> Result<RenderedPrompt, PromptError> {
+        let mut text = String::with_capacity(self.template.len());
+        for part in self.parts()? {
+            match part {
+                Part::Text(literal) => text.push_str(literal),
+                Part::Variable(name) => {
+                    let (_, value) = vars.iter().find(|(var, _)| *var == name)
+                        .ok_or_else(|| PromptError::MissingVariable {
+                            template: self.id.clone(),
+                            variable: name.to_string(),
+                        })?;
+                    text.push_str(value);
+                }
+            }
+        }
+        Ok(RenderedPrompt {
+            text,
+            template_id: self.id.clone(),
+            version: self.version.clone(),
+        })
+    }
+
+    fn parts(&self) -> Result<Vec<Part<'_>>, PromptError> {
+        let mut parts = Vec::new();
+        let mut rest = self.template.as_str();
+        while let Some(start) = rest.find("{{") {
+            let end = rest[start..].find("}}").ok_or_else(|| PromptError::Unclosed {
+                template: self.id.clone(),
+                offset: self.template.len() - rest.len() + start,
+            })?;
+            let name = rest[start + 2..start + end].trim();
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(PromptError::Invalid {
+                    template: self.id.clone(),
+                    message: format!("'{{{{{}}}}}' is not a variable name", &rest[start + 2..start + end]),
+                });
+            }
+            parts.push(Part::Text(&rest[..start]));
+            parts.push(Part::Variable(name));
+            rest = &rest[start + end + 2..];
+        }
+        parts.push(Part::Text(rest));
+        Ok(parts)
+    }
+}
+
+enum Part<'a> {
+    Text(&'a str),
+    Variable(&'a str),
+}
+
+/// Prompt templates by id
+#[derive(Debug, Clone)]
+pub struct PromptLibrary {
+    templates: BTreeMap<String, PromptTemplate>,
+}
+
+impl Default for PromptLibrary {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl PromptLibrary {
+    /// The templates compiled into this crate
+    pub fn builtin() -> Self {
+        let templates = BUILTIN.iter()
+            .map(|(id, source)| {
+                let template = PromptTemplate::from_toml_str(*id, source)
+                    .unwrap_or_else(|e| panic!("Built-in prompt template is invalid: {}", e));
+                (id.to_string(), template)
+            })
+            .collect();
+        Self { templates }
+    }
+
+    /// The built-in templates, with every `*.toml` file in `dir` added or replacing the
+    /// default of the same id
+    pub fn with_overrides(mut self, dir: impl AsRef<Path>) -> Result<Self, PromptError> {
+        let dir = dir.as_ref();
+        let io = |source| PromptError::Io { path: dir.display().to_string(), source };
+        let mut paths: Vec<_> = std::fs::read_dir(dir).map_err(io)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<_, _>>()
+            .map_err(io)?;
+        paths.sort();
+
+        for path in paths.iter().filter(|p| p.extension().is_some_and(|ext| ext == "toml")) {
+            let Some(id) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            let source = std::fs::read_to_string(path)
+                .map_err(|source| PromptError::Io { path: path.display().to_string(), source })?;
+            self.insert(PromptTemplate::from_toml_str(id, &source)?);
+        }
+        Ok(self)
+    }
+
+    pub fn insert(&mut self, template: PromptTemplate) {
+        self.templates.insert(template.id.clone(), template);
+    }
+
+    pub fn get(&self, template_id: &str) -> Option<&PromptTemplate> {
+        self.templates.get(template_id)
+    }
+
+    /// All templates, sorted by id
+    pub fn list_templates(&self) -> Vec<&PromptTemplate> {
+        self.templates.values().collect()
+    }
+
+    /// Render the template `template_id` with `vars`
+    pub fn render(&self, template_id: &str, vars: &[(&str, &str)]) -> Result<RenderedPrompt, PromptError> {
+        self.get(template_id)
+            .ok_or_else(|| PromptError::UnknownTemplate(template_id.to_string()))?
+            .render(vars)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The prompts as they were written inline before they became templates
+    #[test]
+    fn test_builtin_prompts_render_as_before() {
+        let library = PromptLibrary::builtin();
+
+        let research = library.render("research_assistant", &[("query", "tidal energy")]).unwrap();
+        assert_eq!(
+            research.text,
+            "You are a research assistant. Please provide a comprehensive analysis of the following query:\n\ntidal energy\n\nProvide:\n1. Key insights\n2. Relevant facts\n3. Potential implications\n4. Further research directions",
+        );
+        assert_eq!((research.template_id.as_str(), research.version.as_str()), ("research_assistant", "1"));
+
+        let code = library.render("code_generation", &[("language", "Rust"), ("specification", "a stack")]).unwrap();
+        assert_eq!(
+            code.text,
+            "Generate Rust code for the following specification:\n\na stack\n\nProvide:\n1. Clean, well-commented code\n2. Usage examples\n3. Error handling\n4. Testing suggestions",
+        );
+
+        let ids: Vec<&str> = library.list_templates().iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(ids, ["code_generation", "rag_answer", "research_assistant"]);
+        assert_eq!(library.get("rag_answer").unwrap().variables(), ["context", "query"]);
+    }
+
+    #[test]
+    fn test_missing_variables_are_named() {
+        let library = PromptLibrary::builtin();
+
+        let err = library.render("code_generation", &[("language", "Go")]).unwrap_err();
+        assert_eq!(err.to_string(), "Prompt template code_generation needs variable 'specification'");
+        assert!(matches!(library.render("nope", &[]), Err(PromptError::UnknownTemplate(id)) if id == "nope"));
+        assert_eq!(talkpp_errors::Error::from(err).kind(), talkpp_errors::ErrorKind::InvalidInput);
+
+        // Values are inserted literally, never rendered themselves
+        let rendered = library.render("research_assistant", &[("query", "{{ secret }}")]).unwrap();
+        assert!(rendered.text.contains("\n\n{{ secret }}\n\n"));
+
+        let unclosed = PromptTemplate::from_toml_str("bad", "version = \"1\"\ntemplate = \"Hi {{ name\"").unwrap_err();
+        assert!(matches!(unclosed, PromptError::Unclosed { offset: 3, .. }));
+    }
+
+    #[test]
+    fn test_override_directory_replaces_by_id() {
+        let dir = std::env::temp_dir().join(format!("prompt-overrides-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("research_assistant.toml"), "version = \"2-terse\"\ntemplate = \"Briefly: {{ query }}\"\n").unwrap();
+        std::fs::write(dir.join("triage.toml"), "version = \"1\"\ntemplate = \"Triage {{ ticket }}\"\n").unwrap();
+        std::fs::write(dir.join("notes.txt"), "ignored").unwrap();
+
+        let library = PromptLibrary::builtin().with_overrides(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+
+        let research = library.render("research_assistant", &[("query", "tides")]).unwrap();
+        assert_eq!((research.text.as_str(), research.version.as_str()), ("Briefly: tides", "2-terse"));
+        assert_eq!(library.render("triage", &[("ticket", "#4")]).unwrap().text, "Triage #4");
+        assert_eq!(library.list_templates().len(), 4);
+    }
+}
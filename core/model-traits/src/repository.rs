@@ -0,0 +1,796 @@
+//! Local cache of model files downloaded from a Hugging Face compatible hub
+//!
+//! Every model has its own directory under the cache root, named after its id with `/`
+//! replaced by `--`, holding `config.json`, `tokenizer.json` when the model has one, and
+//! its safetensors weights, sharded or not. The manifest is written once every file is
+//! in place, so a directory without one is an interrupted download: files are written to
+//! `<name>.partial` first, and the next attempt continues them with a `Range` request.
+//!
+//! Files the hub reports a SHA-256 for, which it does for the large files in LFS through
+//! the `X-Linked-Etag` header, are checked before they are moved into place.
+
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use reqwest::header::{HeaderMap, CONTENT_RANGE, LOCATION, RANGE};
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
+use tracing::{debug, info, warn};
+
+pub const DEFAULT_ENDPOINT: &str = "https://huggingface.co";
+
+/// Written into a model's directory once all its files are downloaded
+const MANIFEST: &str = ".talkpp-model.json";
+
+/// Redirects followed to reach a file, e.g. from the hub to its CDN
+const MAX_REDIRECTS: usize = 5;
+
+#[derive(Debug, Error)]
+pub enum ModelError {
+    #[error("Invalid model id '{0}': expected <name> or <owner>/<name>")]
+    InvalidId(String),
+
+    #[error("Invalid model hub endpoint '{0}'")]
+    InvalidEndpoint(String),
+
+    #[error("Model '{id}' is not cached: expected it in {} and downloads are disabled (offline mode)", dir.display())]
+    NotCached { id: String, dir: PathBuf },
+
+    #[error("Model '{id}' has no {file}")]
+    MissingFile { id: String, file: String },
+
+    #[error("Downloading {file} of model '{id}' failed: {message}")]
+    Download { id: String, file: String, message: String },
+
+    #[error("The model hub refused {file} of model '{id}' with status {status}")]
+    Rejected { id: String, file: String, status: u16 },
+
+    #[error("{file} of model '{id}' has SHA-256 {actual}, but the hub reported {expected}")]
+    ChecksumMismatch { id: String, file: String, expected: String, actual: String },
+
+    #[error("Model cache I/O failed: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl From<ModelError> for talkpp_errors::Error {
+    fn from(error: ModelError) -> Self {
+        let kind = match &error {
+            ModelError::InvalidId(_) | ModelError::InvalidEndpoint(_) => talkpp_errors::ErrorKind::InvalidInput,
+            ModelError::NotCached { .. } | ModelError::MissingFile { .. } => talkpp_errors::ErrorKind::NotFound,
+            ModelError::Rejected { status: 401 | 403, .. } => talkpp_errors::ErrorKind::Unauthorized,
+            ModelError::Download { .. } | ModelError::Rejected { .. } | ModelError::ChecksumMismatch { .. } => {
+                talkpp_errors::ErrorKind::UpstreamUnavailable
+            }
+            ModelError::Io(_) => talkpp_errors::ErrorKind::Internal,
+        };
+        Self::transparent(kind, error)
+    }
+}
+
+/// How far the download of one file has got
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadProgress {
+    pub model_id: String,
+    pub file: String,
+    /// Bytes of the file on disk, including any resumed from an earlier attempt
+    pub downloaded: u64,
+    /// Size of the file, when the hub sent it
+    pub total: Option<u64>,
+}
+
+/// A model in the cache, as recorded by its manifest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedModel {
+    pub id: String,
+    pub revision: String,
+    pub files: Vec<CachedFile>,
+    pub downloaded_at: DateTime<Utc>,
+    /// Last time the model was ensured; the least recently used models are evicted first
+    pub last_used: DateTime<Utc>,
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+impl CachedModel {
+    pub fn size_bytes(&self) -> u64 {
+        self.files.iter().map(|file| file.size).sum()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedFile {
+    pub name: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// Downloads models on first use and keeps them in a local cache
+#[derive(Debug, Clone)]
+pub struct ModelRepository {
+    cache_dir: PathBuf,
+    endpoint: String,
+    revision: String,
+    token: Option<String>,
+    offline: bool,
+    max_cache_bytes: Option<u64>,
+    max_attempts: u32,
+    http: reqwest::Client,
+    /// Held while a model is downloaded, so two callers never write the same files
+    downloads: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl ModelRepository {
+    pub fn new(cache_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            cache_dir: cache_dir.into(),
+            endpoint: DEFAULT_ENDPOINT.to_string(),
+            revision: "main".to_string(),
+            token: None,
+            offline: false,
+            max_cache_bytes: None,
+            max_attempts: 3,
+            // Redirects are followed by hand to read the checksum off the hub's response
+            http: reqwest::Client::builder()
+                .redirect(reqwest::redirect::Policy::none())
+                .build()
+                .expect("Failed to build HTTP client"),
+            downloads: Arc::default(),
+        }
+    }
+
+    /// A repository configured from the environment:
+    ///
+    /// - `TALKPP_MODEL_CACHE`: cache directory, `~/.cache/talkpp/models` by default
+    /// - `TALKPP_MODEL_CACHE_MAX_BYTES`: evict models beyond this many bytes
+    /// - `HF_ENDPOINT`, `HF_TOKEN`: the hub to download from and its access token
+    /// - `HF_HUB_OFFLINE=1`: never download
+    pub fn from_env() -> Self {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        let cache_dir = var("TALKPP_MODEL_CACHE").map(PathBuf::from).unwrap_or_else(default_cache_dir);
+        let mut repository = Self::new(cache_dir)
+            .with_offline(var("HF_HUB_OFFLINE").is_some_and(|value| matches!(value.as_str(), "1" | "true" | "yes")));
+        if let Some(endpoint) = var("HF_ENDPOINT") {
+            repository = repository.with_endpoint(endpoint);
+        }
+        if let Some(token) = var("HF_TOKEN") {
+            repository = repository.with_token(token);
+        }
+        match var("TALKPP_MODEL_CACHE_MAX_BYTES").map(|value| value.parse::<u64>()) {
+            Some(Ok(max_bytes)) => repository.with_max_cache_bytes(max_bytes),
+            Some(Err(_)) => {
+                warn!("Ignoring TALKPP_MODEL_CACHE_MAX_BYTES, which is not a number of bytes");
+                repository
+            }
+            None => repository,
+        }
+    }
+
+    pub fn with_cache_dir(mut self, cache_dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = cache_dir.into();
+        self
+    }
+
+    /// Hub to download from, instead of `https://huggingface.co`
+    pub fn with_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.endpoint = endpoint.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Branch, tag or commit to download, instead of `main`
+    pub fn with_revision(mut self, revision: impl Into<String>) -> Self {
+        self.revision = revision.into();
+        self
+    }
+
+    /// Token sent to the hub, for gated and private models
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Only use models already in the cache
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Evict the least recently used models once the cache holds more than `max_bytes`.
+    /// The model just ensured is never evicted, even if it alone is larger.
+    pub fn with_max_cache_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_cache_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Times a file is requested, resuming where the last attempt stopped, before its
+    /// download fails
+    pub fn with_max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = attempts.max(1);
+        self
+    }
+
+    pub fn cache_dir(&self) -> &Path {
+        &self.cache_dir
+    }
+
+    /// Directory the model `id` is, or would be, cached in
+    pub fn model_dir(&self, id: &str) -> Result<PathBuf, ModelError> {
+        validate_id(id)?;
+        Ok(self.cache_dir.join(id.replace('/', "--")))
+    }
+
+    /// Directory holding the files of model `id`, downloading them first if needed
+    pub async fn ensure_model(&self, id: &str) -> Result<PathBuf, ModelError> {
+        self.ensure_model_with_progress(id, &|_| {}).await
+    }
+
+    /// Ensure each of `ids` in turn, calling `progress` as their files download
+    pub async fn prefetch<S: AsRef<str>>(
+        &self,
+        ids: &[S],
+        progress: impl Fn(&DownloadProgress) + Send + Sync,
+    ) -> Result<Vec<PathBuf>, ModelError> {
+        let mut dirs = Vec::with_capacity(ids.len());
+        for id in ids {
+            dirs.push(self.ensure_model_with_progress(id.as_ref(), &progress).await?);
+        }
+        Ok(dirs)
+    }
+
+    /// Every model in the cache, by id. Interrupted downloads are not listed.
+    pub fn list_cached(&self) -> Result<Vec<CachedModel>, ModelError> {
+        let entries = match std::fs::read_dir(&self.cache_dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut models = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if let Some(model) = read_manifest(&path)? {
+                models.push(model);
+            }
+        }
+        models.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(models)
+    }
+
+    /// Remove model `id` from the cache, including an interrupted download of it.
+    /// Returns whether there was anything to remove.
+    pub fn evict(&self, id: &str) -> Result<bool, ModelError> {
+        let dir = self.model_dir(id)?;
+        match std::fs::remove_dir_all(&dir) {
+            Ok(()) => {
+                info!("Evicted model {} from {}", id, dir.display());
+                Ok(true)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn ensure_model_with_progress(
+        &self,
+        id: &str,
+        progress: &(dyn Fn(&DownloadProgress) + Send + Sync),
+    ) -> Result<PathBuf, ModelError> {
+        let dir = self.model_dir(id)?;
+        if let Some(model) = cached(&dir)? {
+            touch(model)?;
+            return Ok(dir);
+        }
+        if self.offline {
+            return Err(ModelError::NotCached { id: id.to_string(), dir });
+        }
+
+        let _downloading = self.downloads.lock().await;
+        // Another caller may have downloaded it while this one waited
+        if cached(&dir)?.is_some() {
+            return Ok(dir);
+        }
+        info!("Downloading model {} into {}", id, dir.display());
+        std::fs::create_dir_all(&dir)?;
+        let files = self.download_files(id, &dir, progress).await?;
+        let now = Utc::now();
+        write_manifest(&CachedModel {
+            id: id.to_string(),
+            revision: self.revision.clone(),
+            files,
+            downloaded_at: now,
+            last_used: now,
+            path: dir.clone(),
+        })?;
+        self.enforce_max_size(id)?;
+        Ok(dir)
+    }
+
+    /// The model's config, tokenizer and weights, downloading the shards listed by
+    /// `model.safetensors.index.json` when there is one
+    async fn download_files(
+        &self,
+        id: &str,
+        dir: &Path,
+        progress: &(dyn Fn(&DownloadProgress) + Send + Sync),
+    ) -> Result<Vec<CachedFile>, ModelError> {
+        let mut files = vec![self.fetch(id, dir, "config.json", progress).await?];
+        match self.fetch(id, dir, "tokenizer.json", progress).await {
+            Ok(file) => files.push(file),
+            Err(ModelError::MissingFile { .. }) => debug!("Model {} has no tokenizer.json", id),
+            Err(e) => return Err(e),
+        }
+        match self.fetch(id, dir, "model.safetensors.index.json", progress).await {
+            Ok(index) => {
+                for shard in shards(id, &dir.join(&index.name))? {
+                    files.push(self.fetch(id, dir, &shard, progress).await?);
+                }
+                files.push(index);
+            }
+            Err(ModelError::MissingFile { .. }) => files.push(self.fetch(id, dir, "model.safetensors", progress).await?),
+            Err(e) => return Err(e),
+        }
+        Ok(files)
+    }
+
+    /// Download `file` of model `id` into `dir`, resuming a partial download, and check it
+    async fn fetch(
+        &self,
+        id: &str,
+        dir: &Path,
+        file: &str,
+        progress: &(dyn Fn(&DownloadProgress) + Send + Sync),
+    ) -> Result<CachedFile, ModelError> {
+        let dest = dir.join(file);
+        let partial = dir.join(format!("{}.partial", file));
+        // Files are only moved into place once complete and checked
+        if dest.exists() {
+            let sha256 = hash_file(dest.clone()).await?;
+            return Ok(CachedFile { name: file.to_string(), size: std::fs::metadata(&dest)?.len(), sha256 });
+        }
+        let url = Url::parse(&format!("{}/{}/resolve/{}/{}", self.endpoint, id, self.revision, file))
+            .map_err(|_| ModelError::InvalidEndpoint(self.endpoint.clone()))?;
+
+        let mut attempt = 1;
+        let expected = loop {
+            match self.download(id, file, &url, &partial, progress).await {
+                Ok(expected) => break expected,
+                Err(e @ ModelError::Download { .. }) if attempt < self.max_attempts => {
+                    warn!("{}; resuming (attempt {} of {})", e, attempt + 1, self.max_attempts);
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+
+        let actual = hash_file(partial.clone()).await?;
+        if let Some(expected) = expected.filter(|expected| *expected != actual) {
+            std::fs::remove_file(&partial)?;
+            return Err(ModelError::ChecksumMismatch { id: id.to_string(), file: file.to_string(), expected, actual });
+        }
+        std::fs::rename(&partial, &dest)?;
+        Ok(CachedFile { name: file.to_string(), size: std::fs::metadata(&dest)?.len(), sha256: actual })
+    }
+
+    /// Append the rest of the file at `url` to `partial`, returning the SHA-256 the hub
+    /// reported for it, if any
+    async fn download(
+        &self,
+        id: &str,
+        file: &str,
+        url: &Url,
+        partial: &Path,
+        progress: &(dyn Fn(&DownloadProgress) + Send + Sync),
+    ) -> Result<Option<String>, ModelError> {
+        let failed = |message: String| ModelError::Download { id: id.to_string(), file: file.to_string(), message };
+        let mut offset = tokio::fs::metadata(partial).await.map(|metadata| metadata.len()).unwrap_or(0);
+        let (response, expected) = self.get(url, offset).await.map_err(|e| failed(e.to_string()))?;
+
+        let mut out = match response.status() {
+            StatusCode::PARTIAL_CONTENT if range_start(response.headers()) == Some(offset) => {
+                debug!("Resuming {} of {} from byte {}", file, id, offset);
+                tokio::fs::OpenOptions::new().append(true).open(partial).await?
+            }
+            StatusCode::PARTIAL_CONTENT | StatusCode::RANGE_NOT_SATISFIABLE => {
+                // The partial file does not match what the hub has; start it over
+                tokio::fs::remove_file(partial).await?;
+                return Err(failed(format!("the hub could not resume from byte {}", offset)));
+            }
+            status if status.is_success() => {
+                offset = 0;
+                tokio::fs::File::create(partial).await?
+            }
+            StatusCode::NOT_FOUND => return Err(ModelError::MissingFile { id: id.to_string(), file: file.to_string() }),
+            status if status.is_server_error() => return Err(failed(format!("status {}", status))),
+            status => return Err(ModelError::Rejected { id: id.to_string(), file: file.to_string(), status: status.as_u16() }),
+        };
+
+        let total = response.content_length().map(|length| length + offset);
+        let mut downloaded = offset;
+        let mut body = response.bytes_stream();
+        while let Some(chunk) = body.next().await {
+            // Whatever arrived is kept for the next attempt to resume from
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    out.flush().await?;
+                    return Err(failed(format!("{} after {} bytes", e, downloaded)));
+                }
+            };
+            out.write_all(&chunk).await?;
+            downloaded += chunk.len() as u64;
+            progress(&DownloadProgress { model_id: id.to_string(), file: file.to_string(), downloaded, total });
+        }
+        out.flush().await?;
+        match total {
+            Some(total) if downloaded < total => Err(failed(format!("connection closed after {} of {} bytes", downloaded, total))),
+            _ => Ok(expected),
+        }
+    }
+
+    /// GET `url` from byte `offset`, following redirects. The checksum is taken from the
+    /// first response that has one, which for LFS files is the hub's redirect to its CDN.
+    async fn get(&self, url: &Url, offset: u64) -> reqwest::Result<(reqwest::Response, Option<String>)> {
+        let mut url = url.clone();
+        let mut expected = None;
+        let hub = Url::parse(&self.endpoint).ok().map(|endpoint| endpoint.origin());
+        let mut redirects = 0;
+        loop {
+            let mut request = self.http.get(url.clone());
+            if offset > 0 {
+                request = request.header(RANGE, format!("bytes={}-", offset));
+            }
+            // The token is for the hub only, not wherever it redirects to
+            if let Some(token) = self.token.as_ref().filter(|_| hub.as_ref() == Some(&url.origin())) {
+                request = request.bearer_auth(token);
+            }
+            let response = request.send().await?;
+            expected = expected.or_else(|| sha256_etag(response.headers()));
+
+            // Past the redirect limit the redirect itself is returned, and refused
+            let location = response.headers().get(LOCATION).and_then(|location| location.to_str().ok());
+            match location.and_then(|location| url.join(location).ok()) {
+                Some(next) if response.status().is_redirection() && redirects < MAX_REDIRECTS => {
+                    url = next;
+                    redirects += 1;
+                }
+                _ => return Ok((response, expected)),
+            }
+        }
+    }
+
+    /// Evict the least recently used models, other than `keep`, until the cache fits
+    fn enforce_max_size(&self, keep: &str) -> Result<(), ModelError> {
+        let Some(max_bytes) = self.max_cache_bytes else {
+            return Ok(());
+        };
+        let mut models = self.list_cached()?;
+        let mut total: u64 = models.iter().map(CachedModel::size_bytes).sum();
+        models.sort_by_key(|model| model.last_used);
+        for model in models.iter().filter(|model| model.id != keep) {
+            if total <= max_bytes {
+                break;
+            }
+            self.evict(&model.id)?;
+            total -= model.size_bytes();
+        }
+        if total > max_bytes {
+            warn!("Model cache holds {} bytes, more than its limit of {}", total, max_bytes);
+        }
+        Ok(())
+    }
+}
+
+fn default_cache_dir() -> PathBuf {
+    let cache = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .unwrap_or_else(std::env::temp_dir);
+    cache.join("talkpp").join("models")
+}
+
+/// Ids are `<name>` or `<owner>/<name>`, each part made of letters, digits, `-`, `_`
+/// and `.`, and not starting with `.`
+fn validate_id(id: &str) -> Result<(), ModelError> {
+    let parts: Vec<&str> = id.split('/').collect();
+    let valid_part = |part: &&str| {
+        !part.is_empty()
+            && !part.starts_with('.')
+            && part.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    };
+    if parts.len() > 2 || !parts.iter().all(valid_part) {
+        return Err(ModelError::InvalidId(id.to_string()));
+    }
+    Ok(())
+}
+
+/// The model in `dir`, if its manifest is there and all its files are intact
+fn cached(dir: &Path) -> Result<Option<CachedModel>, ModelError> {
+    let Some(model) = read_manifest(dir)? else {
+        return Ok(None);
+    };
+    let intact = model.files.iter().all(|file| {
+        std::fs::metadata(dir.join(&file.name)).is_ok_and(|metadata| metadata.len() == file.size)
+    });
+    if !intact {
+        warn!("Files of cached model {} are missing or changed; downloading it again", model.id);
+    }
+    Ok(intact.then_some(model))
+}
+
+fn read_manifest(dir: &Path) -> Result<Option<CachedModel>, ModelError> {
+    let json = match std::fs::read(dir.join(MANIFEST)) {
+        Ok(json) => json,
+        Err(e) if matches!(e.kind(), std::io::ErrorKind::NotFound | std::io::ErrorKind::NotADirectory) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    match serde_json::from_slice::<CachedModel>(&json) {
+        Ok(model) => Ok(Some(CachedModel { path: dir.to_path_buf(), ..model })),
+        Err(e) => {
+            warn!("Ignoring the unreadable model manifest in {}: {}", dir.display(), e);
+            Ok(None)
+        }
+    }
+}
+
+/// Write the manifest of `model` into its directory, replacing any in one step
+fn write_manifest(model: &CachedModel) -> Result<(), ModelError> {
+    let tmp = model.path.join(format!("{}.tmp", MANIFEST));
+    std::fs::write(&tmp, serde_json::to_vec_pretty(model).map_err(std::io::Error::other)?)?;
+    std::fs::rename(&tmp, model.path.join(MANIFEST))?;
+    Ok(())
+}
+
+fn touch(model: CachedModel) -> Result<(), ModelError> {
+    write_manifest(&CachedModel { last_used: Utc::now(), ..model })
+}
+
+/// Shard files named by a `model.safetensors.index.json`, each once
+fn shards(id: &str, index: &Path) -> Result<Vec<String>, ModelError> {
+    #[derive(Deserialize)]
+    struct Index {
+        weight_map: std::collections::BTreeMap<String, String>,
+    }
+    let invalid = |message: String| ModelError::Download {
+        id: id.to_string(),
+        file: "model.safetensors.index.json".to_string(),
+        message,
+    };
+    let index: Index = serde_json::from_slice(&std::fs::read(index)?).map_err(|e| invalid(e.to_string()))?;
+    let mut shards: Vec<String> = index.weight_map.into_values().collect();
+    shards.sort();
+    shards.dedup();
+    // Shards are written into the model's directory, so their names must stay in it
+    if let Some(bad) = shards.iter().find(|shard| shard.contains(['/', '\\']) || shard.starts_with('.')) {
+        return Err(invalid(format!("invalid shard name '{}'", bad)));
+    }
+    Ok(shards)
+}
+
+/// A SHA-256 in the `X-Linked-Etag` or `ETag` header. Other etags, such as the git blob
+/// hashes of small files, can't be checked against the file and give `None`.
+fn sha256_etag(headers: &HeaderMap) -> Option<String> {
+    ["x-linked-etag", "etag"].iter().find_map(|name| {
+        let etag = headers.get(*name)?.to_str().ok()?;
+        let etag = etag.trim_start_matches("W/").trim_matches('"');
+        (etag.len() == 64 && etag.chars().all(|c| c.is_ascii_hexdigit())).then(|| etag.to_ascii_lowercase())
+    })
+}
+
+/// First byte of a `Content-Range: bytes <start>-<end>/<size>` response
+fn range_start(headers: &HeaderMap) -> Option<u64> {
+    let range = headers.get(CONTENT_RANGE)?.to_str().ok()?;
+    range.strip_prefix("bytes ")?.split('-').next()?.parse().ok()
+}
+
+/// Hex SHA-256 of the file at `path`, read off the async runtime
+async fn hash_file(path: PathBuf) -> Result<String, ModelError> {
+    let hashed = tokio::task::spawn_blocking(move || {
+        let mut file = std::fs::File::open(path)?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0; 1024 * 1024];
+        loop {
+            match file.read(&mut buffer)? {
+                0 => return Ok::<_, std::io::Error>(hex::encode(hasher.finalize())),
+                read => hasher.update(&buffer[..read]),
+            }
+        }
+    });
+    Ok(hashed.await.map_err(std::io::Error::other)??)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    /// A hub serving files over plain HTTP/1.1. Weights are served like LFS files: the
+    /// hub redirects to a CDN path and reports their checksum on the redirect.
+    #[derive(Default)]
+    struct Hub {
+        files: HashMap<String, Vec<u8>>,
+        /// Files whose first download is cut off half way
+        interrupt: Mutex<Vec<String>>,
+        /// Files whose checksum is reported wrongly
+        corrupt: Vec<String>,
+        /// Path and `Range` start of every request
+        requests: Mutex<Vec<(String, Option<String>)>>,
+    }
+
+    impl Hub {
+        fn with_file(mut self, name: &str, bytes: impl Into<Vec<u8>>) -> Self {
+            self.files.insert(name.to_string(), bytes.into());
+            self
+        }
+
+        async fn serve(self) -> (Arc<Self>, String) {
+            let hub = Arc::new(self);
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            let serving = hub.clone();
+            tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    tokio::spawn(serving.clone().respond(stream));
+                }
+            });
+            (hub, url)
+        }
+
+        fn requests(&self) -> Vec<(String, Option<String>)> {
+            self.requests.lock().unwrap().clone()
+        }
+
+        async fn respond(self: Arc<Self>, mut stream: tokio::net::TcpStream) {
+            let mut head = Vec::new();
+            while !head.ends_with(b"\r\n\r\n") {
+                let mut byte = [0];
+                if stream.read(&mut byte).await.unwrap_or(0) == 0 {
+                    return;
+                }
+                head.push(byte[0]);
+            }
+            let head = String::from_utf8(head).unwrap();
+            let path = head.split(' ').nth(1).unwrap().to_string();
+            let range = head.lines()
+                .find_map(|line| line.to_ascii_lowercase().strip_prefix("range: bytes=").map(str::to_string))
+                .map(|range| range.trim_end_matches('-').to_string());
+            self.requests.lock().unwrap().push((path.clone(), range.clone()));
+
+            let name = path.rsplit('/').next().unwrap().to_string();
+            let Some(bytes) = self.files.get(&name) else {
+                let _ = stream.write_all(b"HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n").await;
+                return;
+            };
+            if name.ends_with(".safetensors") && !path.starts_with("/cdn/") {
+                let sha = match self.corrupt.contains(&name) {
+                    true => "0".repeat(64),
+                    false => hex::encode(Sha256::digest(bytes)),
+                };
+                let redirect = format!(
+                    "HTTP/1.1 302 Found\r\nlocation: /cdn/{}\r\nx-linked-etag: \"{}\"\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                    name, sha,
+                );
+                let _ = stream.write_all(redirect.as_bytes()).await;
+                return;
+            }
+
+            let start: usize = range.map(|start| start.parse().unwrap()).unwrap_or(0);
+            let body = &bytes[start..];
+            let status = match start {
+                0 => "200 OK".to_string(),
+                _ => format!("206 Partial Content\r\ncontent-range: bytes {}-{}/{}", start, bytes.len() - 1, bytes.len()),
+            };
+            let head = format!("HTTP/1.1 {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n", status, body.len());
+            let _ = stream.write_all(head.as_bytes()).await;
+
+            let interrupted = {
+                let mut interrupt = self.interrupt.lock().unwrap();
+                let position = interrupt.iter().position(|file| *file == name);
+                position.map(|position| interrupt.remove(position)).is_some()
+            };
+            let sent = if interrupted { &body[..body.len() / 2] } else { body };
+            let _ = stream.write_all(sent).await;
+        }
+    }
+
+    fn shard(n: u8) -> Vec<u8> {
+        (0..50_000u32).map(|i| (i as u8).wrapping_mul(n)).collect()
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_downloads_resume() {
+        let index = r#"{"metadata":{},"weight_map":{
+            "embed.weight":"model-00001-of-00002.safetensors",
+            "layers.0.weight":"model-00001-of-00002.safetensors",
+            "lm_head.weight":"model-00002-of-00002.safetensors"}}"#;
+        let hub = Hub::default()
+            .with_file("config.json", r#"{"model_type":"llama"}"#)
+            .with_file("tokenizer.json", "{}")
+            .with_file("model.safetensors.index.json", index)
+            .with_file("model-00001-of-00002.safetensors", shard(3))
+            .with_file("model-00002-of-00002.safetensors", shard(7));
+        *hub.interrupt.lock().unwrap() = vec!["model-00002-of-00002.safetensors".to_string()];
+        let (hub, url) = hub.serve().await;
+        let cache = tempfile::tempdir().unwrap();
+
+        // The first attempt is cut off half way through the second shard
+        let repository = ModelRepository::new(cache.path()).with_endpoint(&url).with_max_attempts(1);
+        let error = repository.ensure_model("acme/tiny-llama").await.unwrap_err();
+        assert!(matches!(error, ModelError::Download { ref file, .. } if file == "model-00002-of-00002.safetensors"), "{}", error);
+        assert!(repository.list_cached().unwrap().is_empty());
+
+        // The next one asks for the rest of that shard, and has every other file already
+        let seen = Mutex::new(Vec::new());
+        let dirs = repository.prefetch(&["acme/tiny-llama"], |progress| seen.lock().unwrap().push(progress.clone())).await.unwrap();
+        assert_eq!(dirs, vec![cache.path().join("acme--tiny-llama")]);
+        assert_eq!(std::fs::read(dirs[0].join("model-00001-of-00002.safetensors")).unwrap(), shard(3));
+        assert_eq!(std::fs::read(dirs[0].join("model-00002-of-00002.safetensors")).unwrap(), shard(7));
+        let resumed: Vec<(String, Option<String>)> = hub.requests().into_iter()
+            .filter(|(path, _)| path.ends_with("model-00002-of-00002.safetensors"))
+            .collect();
+        assert_eq!(resumed.len(), 4);
+        assert_eq!(resumed[3], ("/cdn/model-00002-of-00002.safetensors".to_string(), Some("25000".to_string())));
+        let seen = seen.into_inner().unwrap();
+        assert!(seen.iter().all(|progress| progress.file == "model-00002-of-00002.safetensors"));
+        assert_eq!(seen.last().map(|progress| (progress.downloaded, progress.total)), Some((50_000, Some(50_000))));
+
+        let cached = repository.list_cached().unwrap();
+        assert_eq!(cached.len(), 1);
+        assert_eq!(cached[0].id, "acme/tiny-llama");
+        assert_eq!(cached[0].files.len(), 5);
+
+        // Cached models are served without asking the hub again
+        let requests = hub.requests().len();
+        assert_eq!(repository.ensure_model("acme/tiny-llama").await.unwrap(), dirs[0]);
+        assert_eq!(hub.requests().len(), requests);
+    }
+
+    #[tokio::test]
+    async fn test_offline_mode_and_eviction() {
+        let (hub, url) = Hub::default()
+            .with_file("config.json", "{}")
+            .with_file("model.safetensors", shard(1))
+            .serve()
+            .await;
+        let cache = tempfile::tempdir().unwrap();
+
+        let offline = ModelRepository::new(cache.path()).with_endpoint(&url).with_offline(true);
+        let error = offline.ensure_model("acme/small").await.unwrap_err();
+        assert!(error.to_string().contains(&cache.path().join("acme--small").display().to_string()), "{}", error);
+        assert_eq!(talkpp_errors::Error::from(error).kind(), talkpp_errors::ErrorKind::NotFound);
+        assert!(hub.requests().is_empty());
+
+        // Room for one model: ensuring a second evicts the first
+        let repository = ModelRepository::new(cache.path()).with_endpoint(&url).with_max_cache_bytes(60_000);
+        repository.ensure_model("acme/small").await.unwrap();
+        repository.ensure_model("other").await.unwrap();
+        let cached: Vec<String> = repository.list_cached().unwrap().into_iter().map(|model| model.id).collect();
+        assert_eq!(cached, vec!["other"]);
+
+        assert_eq!(offline.ensure_model("other").await.unwrap(), cache.path().join("other"));
+        assert!(repository.evict("other").unwrap());
+        assert!(!repository.evict("other").unwrap());
+        assert!(matches!(repository.evict("../etc"), Err(ModelError::InvalidId(_))));
+    }
+
+    #[tokio::test]
+    async fn test_checksum_mismatches_are_not_kept() {
+        let hub = Hub { corrupt: vec!["model.safetensors".to_string()], ..Hub::default() }
+            .with_file("config.json", "{}")
+            .with_file("model.safetensors", shard(5));
+        let (_hub, url) = hub.serve().await;
+        let cache = tempfile::tempdir().unwrap();
+
+        let repository = ModelRepository::new(cache.path()).with_endpoint(&url);
+        let error = repository.ensure_model("acme/broken").await.unwrap_err();
+        assert!(matches!(error, ModelError::ChecksumMismatch { ref expected, .. } if *expected == "0".repeat(64)), "{}", error);
+        let dir = cache.path().join("acme--broken");
+        assert!(!dir.join("model.safetensors").exists());
+        assert!(!dir.join("model.safetensors.partial").exists());
+    }
+}
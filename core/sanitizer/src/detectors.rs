@@ -0,0 +1,160 @@
+//! The built-in detectors
+//!
+//! Each is a [`RegexDetector`] whose pattern finds candidates and whose scorer rates them,
+//! so a 16-digit run that fails the Luhn check is still found but falls below the default
+//! confidence threshold instead of being masked as a card number.
+
+use regex::Regex;
+
+use crate::{Detection, Detector};
+
+/// Finds candidates with a pattern and rates each with a fixed confidence or a scorer
+pub struct RegexDetector {
+    name: String,
+    pattern: Regex,
+    scorer: Scorer,
+}
+
+enum Scorer {
+    Fixed(f32),
+    Custom(fn(&str) -> f32),
+}
+
+impl RegexDetector {
+    /// A detector rating every match of `pattern` at `confidence`
+    pub fn new(name: impl Into<String>, pattern: &str, confidence: f32) -> Result<Self, regex::Error> {
+        Ok(Self { name: name.into(), pattern: Regex::new(pattern)?, scorer: Scorer::Fixed(confidence) })
+    }
+
+    /// Rate each match with `scorer` instead of a fixed confidence
+    pub fn with_scorer(mut self, scorer: fn(&str) -> f32) -> Self {
+        self.scorer = Scorer::Custom(scorer);
+        self
+    }
+}
+
+impl Detector for RegexDetector {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn detect(&self, text: &str) -> Vec<Detection> {
+        self.pattern.find_iter(text)
+            .map(|found| Detection {
+                start: found.start(),
+                end: found.end(),
+                confidence: match self.scorer {
+                    Scorer::Fixed(confidence) => confidence,
+                    Scorer::Custom(scorer) => scorer(found.as_str()),
+                },
+            })
+            .collect()
+    }
+}
+
+pub const EMAIL: &str = "email";
+pub const PHONE: &str = "phone";
+pub const CREDIT_CARD: &str = "credit_card";
+pub const NATIONAL_ID: &str = "national_id";
+
+/// Email addresses, phone numbers, payment card numbers, US social security numbers and
+/// UK national insurance numbers
+pub fn builtin() -> Vec<RegexDetector> {
+    vec![
+        RegexDetector::new(EMAIL, r"(?i)\b[a-z0-9._%+-]+@[a-z0-9-]+(?:\.[a-z0-9-]+)*\.[a-z]{2,}\b", 0.99),
+        RegexDetector::new(PHONE, r"(?:\+\d{1,3}[ .-]?(?:\(\d{2,4}\)|\d{2,4})|\(\d{2,4}\)|\b\d{2,4})[ .-]?\d{3,4}[ .-]?\d{4}\b", 0.7)
+            .map(|detector| detector.with_scorer(score_phone)),
+        RegexDetector::new(CREDIT_CARD, r"\b\d(?:[ -]?\d){12,18}\b", 0.95)
+            .map(|detector| detector.with_scorer(score_card)),
+        RegexDetector::new(
+            NATIONAL_ID,
+            r"\b\d{3}-\d{2}-\d{4}\b|\b[A-CEGHJ-PR-TW-Z]{2} ?\d{2} ?\d{2} ?\d{2} ?[A-D]\b",
+            0.9,
+        )
+        .map(|detector| detector.with_scorer(score_national_id)),
+    ]
+    .into_iter()
+    .map(|detector| detector.expect("built-in patterns are valid"))
+    .collect()
+}
+
+fn digits(text: &str) -> Vec<u32> {
+    text.chars().filter_map(|c| c.to_digit(10)).collect()
+}
+
+/// Phone numbers have 9 to 15 digits (E.164 allows at most 15)
+fn score_phone(text: &str) -> f32 {
+    match digits(text).len() {
+        9..=15 => 0.7,
+        _ => 0.1,
+    }
+}
+
+/// Card numbers pass the Luhn check; other long digit runs are probably order numbers
+fn score_card(text: &str) -> f32 {
+    let digits = digits(text);
+    let sum: u32 = digits.iter().rev().enumerate()
+        .map(|(i, &digit)| match i % 2 {
+            0 => digit,
+            _ if digit * 2 > 9 => digit * 2 - 9,
+            _ => digit * 2,
+        })
+        .sum();
+    if (13..=19).contains(&digits.len()) && sum.is_multiple_of(10) { 0.95 } else { 0.2 }
+}
+
+/// SSNs never have area 000, 666 or 900-999, group 00 or serial 0000
+fn score_national_id(text: &str) -> f32 {
+    if text.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return 0.9;
+    }
+    let parts: Vec<&str> = text.split('-').collect();
+    let invalid = matches!(parts[0], "000" | "666") || parts[0].starts_with('9') || parts[1] == "00" || parts[2] == "0000";
+    if invalid { 0.2 } else { 0.9 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn found(name: &str, text: &str) -> Vec<(String, f32)> {
+        let detector = builtin().into_iter().find(|detector| detector.name() == name).unwrap();
+        detector.detect(text).into_iter()
+            .map(|detection| (text[detection.start..detection.end].to_string(), detection.confidence))
+            .collect()
+    }
+
+    #[test]
+    fn test_email() {
+        assert_eq!(found(EMAIL, "Write to Ada.Lovelace+ops@mail.example.co.uk today"), vec![
+            ("Ada.Lovelace+ops@mail.example.co.uk".to_string(), 0.99),
+        ]);
+        assert!(found(EMAIL, "ping @ada or see example.com").is_empty());
+    }
+
+    #[test]
+    fn test_phone() {
+        assert_eq!(found(PHONE, "Call +1 (555) 123-4567 or 020 7946 0018."), vec![
+            ("+1 (555) 123-4567".to_string(), 0.7),
+            ("020 7946 0018".to_string(), 0.7),
+        ]);
+        assert_eq!(found(PHONE, "or +15551234567")[0].0, "+15551234567");
+        assert!(found(PHONE, "Released 2024-06-01").is_empty());
+    }
+
+    #[test]
+    fn test_credit_card() {
+        assert_eq!(found(CREDIT_CARD, "Card 4111 1111 1111 1111 expires"), vec![("4111 1111 1111 1111".to_string(), 0.95)]);
+        // Fails the Luhn check, so probably not a card
+        assert_eq!(found(CREDIT_CARD, "Order 4111111111111112"), vec![("4111111111111112".to_string(), 0.2)]);
+    }
+
+    #[test]
+    fn test_national_id() {
+        assert_eq!(found(NATIONAL_ID, "SSN 123-45-6789, NINO AB 12 34 56 C"), vec![
+            ("123-45-6789".to_string(), 0.9),
+            ("AB 12 34 56 C".to_string(), 0.9),
+        ]);
+        assert_eq!(found(NATIONAL_ID, "ticket 666-12-3456")[0].1, 0.2);
+    }
+}
@@ -0,0 +1,395 @@
+//! Masking personal data before text is embedded or stored
+//!
+//! A [`TextSanitizer`] runs a set of [`Detector`]s over a text, keeps the detections at or
+//! above its confidence threshold, and resolves overlaps in favour of the more confident,
+//! then the longer, detection. Each detection is then handled by its detector's
+//! [`SanitizeAction`]:
+//!
+//! - `mask` replaces it with a placeholder such as `<EMAIL>`
+//! - `hash` replaces it with a keyed hash token such as `<EMAIL_3f9a1c20>`. The same value
+//!   always gets the same token, so documents mentioning one address still cluster and a
+//!   search for the token finds them all
+//! - `drop_chunk` masks it like `mask` and marks the text as unfit to store. Callers that
+//!   split a text into chunks after sanitizing it find the chunks to drop with
+//!   [`TextSanitizer::holds_dropped_value`]
+//!
+//! What was found is returned as a [`SanitizationReport`] of detector names, counts and
+//! byte offsets, which callers store alongside the text so audits can check coverage. The
+//! raw values are never part of it.
+//!
+//! Fields named in an allowlist are left untouched, for documents that are meant to carry
+//! a contact address or similar.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+pub mod detectors;
+pub mod safety;
+
+pub use detectors::RegexDetector;
+pub use safety::{
+    Filtered, HeuristicSafetyFilter, SafetyAction, SafetyFilter, SafetyFinding, SafetyPolicy, SafetyRule,
+};
+
+/// A span of text a detector believes is personal data
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Detection {
+    /// Byte offset of the first byte
+    pub start: usize,
+    /// Byte offset just past the last byte
+    pub end: usize,
+    pub confidence: f32,
+}
+
+/// Finds one kind of personal data in text
+pub trait Detector: Send + Sync {
+    /// Names the kind of data in reports and tokens, e.g. `email` gives `<EMAIL_…>`
+    fn name(&self) -> &str;
+
+    fn detect(&self, text: &str) -> Vec<Detection>;
+}
+
+/// What to do with a detection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SanitizeAction {
+    /// Replace with a placeholder naming the detector
+    Mask,
+    /// Replace with a deterministic token derived from the value
+    #[default]
+    Hash,
+    /// Mask, and refuse the chunk holding it
+    DropChunk,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SanitizerConfig {
+    /// Strip control characters and collapse runs of spaces before detection, so
+    /// spacing tricks such as non-breaking spaces inside a number do not hide it
+    pub normalize: bool,
+    /// Detections below this confidence are left alone
+    pub min_confidence: f32,
+    pub default_action: SanitizeAction,
+    /// Actions by detector name, overriding `default_action`
+    pub actions: HashMap<String, SanitizeAction>,
+    /// Secret mixed into hash tokens. Without one, a token can be confirmed by hashing a
+    /// guessed address, so deployments should set it and keep it stable.
+    pub hash_key: String,
+}
+
+impl Default for SanitizerConfig {
+    fn default() -> Self {
+        Self {
+            normalize: true,
+            min_confidence: 0.5,
+            default_action: SanitizeAction::Hash,
+            actions: HashMap::new(),
+            hash_key: String::new(),
+        }
+    }
+}
+
+/// Where one detector matched within one field
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Finding {
+    pub detector: String,
+    pub count: usize,
+    /// `[start, end)` byte offsets into the field's text as normalized, before masking
+    pub offsets: Vec<(usize, usize)>,
+}
+
+/// A text after sanitizing
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sanitized {
+    pub text: String,
+    pub findings: Vec<Finding>,
+    /// A `drop_chunk` detector matched
+    pub dropped: bool,
+}
+
+/// What was masked, by field. Fields are named by their path from the root, such as
+/// `content` or `metadata/from`, with array elements numbered.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SanitizationReport {
+    #[serde(default)]
+    pub fields: BTreeMap<String, Vec<Finding>>,
+    /// A `drop_chunk` detector matched, so the text should not be stored
+    #[serde(default)]
+    pub dropped: bool,
+}
+
+impl SanitizationReport {
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty() && !self.dropped
+    }
+
+    /// Total detections across all fields
+    pub fn count(&self) -> usize {
+        self.fields.values().flatten().map(|finding| finding.count).sum()
+    }
+
+    pub fn merge(&mut self, other: SanitizationReport) {
+        self.fields.extend(other.fields);
+        self.dropped |= other.dropped;
+    }
+}
+
+/// Runs detectors over text and masks what they find. Cheap to clone.
+#[derive(Clone)]
+pub struct TextSanitizer {
+    detectors: Vec<Arc<dyn Detector>>,
+    config: SanitizerConfig,
+}
+
+impl Default for TextSanitizer {
+    fn default() -> Self {
+        Self::new(SanitizerConfig::default())
+    }
+}
+
+impl std::fmt::Debug for TextSanitizer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TextSanitizer")
+            .field("detectors", &self.detectors.iter().map(|detector| detector.name()).collect::<Vec<_>>())
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl TextSanitizer {
+    /// A sanitizer with the [built-in detectors](detectors::builtin)
+    pub fn new(config: SanitizerConfig) -> Self {
+        let detectors = detectors::builtin().into_iter()
+            .map(|detector| Arc::new(detector) as Arc<dyn Detector>)
+            .collect();
+        Self { detectors, config }
+    }
+
+    /// Also run `detector`, e.g. for customer or employee ids
+    pub fn with_detector(mut self, detector: impl Detector + 'static) -> Self {
+        self.detectors.push(Arc::new(detector));
+        self
+    }
+
+    pub fn with_action(mut self, detector: impl Into<String>, action: SanitizeAction) -> Self {
+        self.config.actions.insert(detector.into(), action);
+        self
+    }
+
+    pub fn config(&self) -> &SanitizerConfig {
+        &self.config
+    }
+
+    pub fn sanitize(&self, text: &str) -> Sanitized {
+        let text = if self.config.normalize { normalize(text) } else { text.to_string() };
+
+        let mut detections: Vec<(&str, Detection)> = self.detectors.iter()
+            .flat_map(|detector| detector.detect(&text).into_iter().map(move |detection| (detector.name(), detection)))
+            .filter(|(_, detection)| detection.confidence >= self.config.min_confidence && detection.end > detection.start)
+            .collect();
+        detections.sort_by(|(_, a), (_, b)| {
+            b.confidence.total_cmp(&a.confidence)
+                .then((b.end - b.start).cmp(&(a.end - a.start)))
+                .then(a.start.cmp(&b.start))
+        });
+        let mut accepted: Vec<(&str, Detection)> = Vec::new();
+        for (name, detection) in detections {
+            if accepted.iter().all(|(_, kept)| detection.end <= kept.start || detection.start >= kept.end) {
+                accepted.push((name, detection));
+            }
+        }
+        accepted.sort_by_key(|(_, detection)| detection.start);
+
+        let mut masked = String::with_capacity(text.len());
+        let mut findings: Vec<Finding> = Vec::new();
+        let mut dropped = false;
+        let mut last = 0;
+        for (name, detection) in accepted {
+            let action = self.action(name);
+            dropped |= action == SanitizeAction::DropChunk;
+            masked.push_str(&text[last..detection.start]);
+            masked.push_str(&self.token(name, &text[detection.start..detection.end], action));
+            last = detection.end;
+
+            let offsets = (detection.start, detection.end);
+            match findings.iter_mut().find(|finding| finding.detector == name) {
+                Some(finding) => {
+                    finding.count += 1;
+                    finding.offsets.push(offsets);
+                }
+                None => findings.push(Finding { detector: name.to_string(), count: 1, offsets: vec![offsets] }),
+            }
+        }
+        masked.push_str(&text[last..]);
+
+        Sanitized { text: masked, findings, dropped }
+    }
+
+    /// Whether sanitized `text` holds a value masked by a `drop_chunk` detector
+    pub fn holds_dropped_value(&self, text: &str) -> bool {
+        self.detectors.iter()
+            .filter(|detector| self.action(detector.name()) == SanitizeAction::DropChunk)
+            .any(|detector| text.contains(&format!("<{}>", detector.name().to_uppercase())))
+    }
+
+    fn action(&self, detector: &str) -> SanitizeAction {
+        self.config.actions.get(detector).copied().unwrap_or(self.config.default_action)
+    }
+
+    /// Sanitize every string in `value` in place, reporting them under `field` and their
+    /// path below it. Object members whose key is in `allowlist`, or the whole value if
+    /// `field` is, are left as they are.
+    pub fn sanitize_json(&self, field: &str, value: &mut serde_json::Value, allowlist: &[String]) -> SanitizationReport {
+        let mut report = SanitizationReport::default();
+        if !allowlist.iter().any(|allowed| allowed == field) {
+            self.sanitize_value(field.to_string(), value, allowlist, &mut report);
+        }
+        report
+    }
+
+    fn sanitize_value(&self, path: String, value: &mut serde_json::Value, allowlist: &[String], report: &mut SanitizationReport) {
+        match value {
+            serde_json::Value::String(text) => {
+                let sanitized = self.sanitize(text);
+                *text = sanitized.text;
+                report.dropped |= sanitized.dropped;
+                if !sanitized.findings.is_empty() {
+                    report.fields.insert(path, sanitized.findings);
+                }
+            }
+            serde_json::Value::Array(items) => {
+                for (i, item) in items.iter_mut().enumerate() {
+                    self.sanitize_value(format!("{}/{}", path, i), item, allowlist, report);
+                }
+            }
+            serde_json::Value::Object(members) => {
+                for (key, member) in members.iter_mut() {
+                    if !allowlist.contains(key) {
+                        self.sanitize_value(format!("{}/{}", path, key), member, allowlist, report);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn token(&self, detector: &str, value: &str, action: SanitizeAction) -> String {
+        let label = detector.to_uppercase();
+        match action {
+            SanitizeAction::Mask | SanitizeAction::DropChunk => format!("<{}>", label),
+            SanitizeAction::Hash => {
+                let mut hasher = Sha256::new();
+                for part in [self.config.hash_key.as_str(), detector, &canonical(value)] {
+                    hasher.update(part.as_bytes());
+                    hasher.update([0]);
+                }
+                let digest = format!("{:x}", hasher.finalize());
+                format!("<{}_{}>", label, &digest[..8])
+            }
+        }
+    }
+}
+
+/// `text` without control characters, with each run of spaces collapsed to one and line
+/// breaks kept
+pub fn normalize(text: &str) -> String {
+    let mut normalized = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c == '\n' {
+            normalized.truncate(normalized.trim_end_matches(' ').len());
+            normalized.push('\n');
+        } else if c.is_whitespace() {
+            if !normalized.is_empty() && !normalized.ends_with([' ', '\n']) {
+                normalized.push(' ');
+            }
+        } else if !c.is_control() {
+            normalized.push(c);
+        }
+    }
+    normalized.trim_end().to_string()
+}
+
+/// A detected value as hashed: lowercased, without the separators people vary when
+/// writing the same number, so `+1 (555) 123-4567` and `+15551234567` get one token
+fn canonical(value: &str) -> String {
+    value.chars()
+        .filter(|c| !c.is_whitespace() && !matches!(c, '-' | '(' | ')'))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_hash_tokens_are_deterministic() {
+        let sanitizer = TextSanitizer::default();
+        let first = sanitizer.sanitize("From Ada@Example.com: call +1 (555) 123-4567").text;
+        let second = sanitizer.sanitize("Reply to ada@example.com or +15551234567").text;
+
+        let email = sanitizer.sanitize("ada@example.com").text;
+        assert!(email.starts_with("<EMAIL_") && email.len() == "<EMAIL_>".len() + 8);
+        assert_eq!(first, format!("From {}: call {}", email, sanitizer.sanitize("+15551234567").text));
+        assert!(second.contains(&email));
+        assert_ne!(email, sanitizer.sanitize("bob@example.com").text);
+
+        let keyed = TextSanitizer::new(SanitizerConfig { hash_key: "secret".to_string(), ..Default::default() });
+        assert_ne!(email, keyed.sanitize("ada@example.com").text);
+    }
+
+    #[test]
+    fn test_actions_overlaps_and_findings() {
+        let sanitizer = TextSanitizer::default()
+            .with_action(detectors::EMAIL, SanitizeAction::Mask)
+            .with_detector(RegexDetector::new("employee_id", r"\bEMP-\d{5}\b", 0.8).unwrap());
+        let text = "EMP-12345  paid with 4111 1111 1111 1111,\u{7} mail ada@example.com or bob@example.com";
+        let sanitized = sanitizer.sanitize(text);
+
+        let masked = sanitized.text.clone();
+        // The card number also looks like a phone number, but the card detector is surer
+        assert!(masked.starts_with("<EMPLOYEE_ID_"));
+        assert!(masked.contains(" paid with <CREDIT_CARD_"));
+        assert!(masked.ends_with(", mail <EMAIL> or <EMAIL>"));
+        let detectors: Vec<_> = sanitized.findings.iter().map(|f| (f.detector.as_str(), f.count)).collect();
+        assert_eq!(detectors, vec![("employee_id", 1), ("credit_card", 1), ("email", 2)]);
+        let normalized = normalize(text);
+        let (start, end) = sanitized.findings[2].offsets[1];
+        assert_eq!(&normalized[start..end], "bob@example.com");
+
+        assert!(!sanitized.dropped);
+        let strict = sanitizer.with_action(detectors::CREDIT_CARD, SanitizeAction::DropChunk);
+        let refused = strict.sanitize(text);
+        assert!(refused.dropped);
+        assert!(strict.holds_dropped_value(&refused.text));
+        assert!(!strict.holds_dropped_value(&masked));
+        assert!(!strict.sanitize("no card here").dropped);
+    }
+
+    #[test]
+    fn test_json_fields_and_allowlist() {
+        let sanitizer = TextSanitizer::default();
+        let mut value = json!({
+            "subject": "Call 020 7946 0018",
+            "support_contact": "help@example.com",
+            "thread": [{ "from": "ada@example.com" }, 7],
+        });
+        let report = sanitizer.sanitize_json("content", &mut value, &["support_contact".to_string()]);
+
+        assert_eq!(report.fields.keys().collect::<Vec<_>>(), vec!["content/subject", "content/thread/0/from"]);
+        assert_eq!(report.count(), 2);
+        assert!(!report.dropped);
+        assert_eq!(value["support_contact"], "help@example.com");
+        assert!(!value.to_string().contains("ada@example.com"));
+        // The report holds offsets, never the values
+        assert!(!serde_json::to_string(&report).unwrap().contains("ada@"));
+
+        let mut whole = json!("ada@example.com");
+        assert!(sanitizer.sanitize_json("content", &mut whole, &["content".to_string()]).is_empty());
+        assert_eq!(whole, "ada@example.com");
+    }
+}
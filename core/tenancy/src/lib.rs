@@ -0,0 +1,99 @@
+//! The tenant a request acts for
+//!
+//! The API server derives a [`TenantContext`] from the authenticated session and passes
+//! it to every library call that reads or writes tenant data: RAG documents, memories and
+//! chat sessions. Those libraries record the owning tenant on everything they store and
+//! check it on every access, so isolation does not depend on each handler remembering a
+//! filter.
+//!
+//! Data stored before tenants existed belongs to [`DEFAULT_TENANT`]. Records deserialized
+//! without a tenant get it through [`default_tenant_id`]; stores that cannot rely on that
+//! provide a backfill.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Tenant that data stored before tenants existed is backfilled into
+pub const DEFAULT_TENANT: &str = "default";
+
+/// [`DEFAULT_TENANT`] as an owned id, for `#[serde(default = "...")]` on stored records
+pub fn default_tenant_id() -> String {
+    DEFAULT_TENANT.to_string()
+}
+
+/// Who a request acts for: a tenant, and the user within it
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TenantContext {
+    pub tenant_id: String,
+    pub user_id: String,
+}
+
+impl TenantContext {
+    /// Fails if either id is empty, since an empty tenant would match unscoped data
+    pub fn new(tenant_id: impl Into<String>, user_id: impl Into<String>) -> talkpp_errors::Result<Self> {
+        let (tenant_id, user_id) = (tenant_id.into(), user_id.into());
+        if tenant_id.trim().is_empty() {
+            return Err(talkpp_errors::Error::invalid_input("Tenant id must not be empty"));
+        }
+        if user_id.trim().is_empty() {
+            return Err(talkpp_errors::Error::invalid_input("User id must not be empty"));
+        }
+        Ok(Self { tenant_id, user_id })
+    }
+
+    /// `user_id` in [`DEFAULT_TENANT`], for single-tenant deployments and tools
+    /// working on data from before tenants existed
+    pub fn default_tenant(user_id: impl Into<String>) -> Self {
+        Self { tenant_id: default_tenant_id(), user_id: user_id.into() }
+    }
+
+    /// Whether data owned by `tenant_id` belongs to this context's tenant
+    pub fn owns(&self, tenant_id: &str) -> bool {
+        self.tenant_id == tenant_id
+    }
+
+    /// Reject access to `what`, owned by `owner`, unless it belongs to this context's
+    /// tenant. Another tenant's data is reported as not found, so its existence isn't
+    /// revealed either.
+    pub fn check(&self, owner: &str, what: impl fmt::Display) -> talkpp_errors::Result<()> {
+        if self.owns(owner) {
+            return Ok(());
+        }
+        Err(talkpp_errors::Error::not_found(format!("{} not found", what)))
+    }
+}
+
+impl fmt::Display for TenantContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.tenant_id, self.user_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_other_tenants_data_is_not_found() {
+        let acme = TenantContext::new("acme", "ada").unwrap();
+        assert!(acme.check("acme", "Memory 1").is_ok());
+
+        let err = acme.check("globex", "Memory 1").unwrap_err();
+        assert_eq!(err.kind(), talkpp_errors::ErrorKind::NotFound);
+        assert_eq!(err.to_string(), "Memory 1 not found");
+
+        assert_eq!(TenantContext::new(" ", "ada").unwrap_err().kind(), talkpp_errors::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_records_without_a_tenant_belong_to_the_default_one() {
+        #[derive(Deserialize)]
+        struct Stored {
+            #[serde(default = "default_tenant_id")]
+            tenant_id: String,
+        }
+
+        let stored: Stored = serde_json::from_str("{}").unwrap();
+        assert!(TenantContext::default_tenant("ada").owns(&stored.tenant_id));
+    }
+}
@@ -0,0 +1,525 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use tracing::info;
+
+use crate::DistanceMetric;
+
+/// Points per archive chunk, and per upsert batch on restore
+pub const BACKUP_CHUNK_SIZE: u32 = 256;
+
+/// Bytes of a server snapshot per archive chunk
+const SNAPSHOT_CHUNK_BYTES: usize = 1 << 20;
+
+/// Archives are read back only if they were written in this format
+const ARCHIVE_FORMAT_VERSION: u32 = 1;
+
+/// Frames larger than this are taken as corruption rather than allocated
+const MAX_FRAME_BYTES: usize = 256 << 20;
+
+const MANIFEST_FRAME: u8 = b'M';
+const CHUNK_FRAME: u8 = b'C';
+const TRAILER_FRAME: u8 = b'T';
+
+/// Vector layout of a collection
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CollectionParams {
+    pub vector_size: u64,
+    pub distance: DistanceMetric,
+}
+
+/// How a backup captured its collection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BackupKind {
+    /// A snapshot taken by the server, restored by the server
+    Snapshot,
+    /// Every point read out through the scroll API, restored by upserting
+    Scroll,
+}
+
+/// First frame of an archive
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub format_version: u32,
+    pub collection: String,
+    pub kind: BackupKind,
+    #[serde(flatten)]
+    pub params: CollectionParams,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Last frame of an archive; a missing or disagreeing trailer marks the archive as partial
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BackupTrailer {
+    chunks: u64,
+    points: u64,
+    /// SHA-256 over every chunk frame's body, in order
+    sha256: String,
+}
+
+/// A point as archived: its id, vector and payload, which includes the document content
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackupPoint {
+    /// A UUID, or an unsigned integer for numerically keyed points
+    pub id: String,
+    pub vector: Vec<f32>,
+    pub payload: HashMap<String, serde_json::Value>,
+}
+
+/// One page of a scroll through a collection
+#[derive(Debug, Clone, Default)]
+pub struct ScrollPage {
+    pub points: Vec<BackupPoint>,
+    /// Id of the first point of the next page, `None` after the last page
+    pub next_offset: Option<String>,
+}
+
+/// What a backup wrote, or a restore read
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ArchiveReport {
+    pub kind: BackupKind,
+    pub chunks: u64,
+    pub points: u64,
+}
+
+/// Reported after each batch a restore upserts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RestoreProgress {
+    pub chunks_done: u64,
+    pub chunks_total: u64,
+    pub points_done: u64,
+    pub points_total: u64,
+}
+
+#[derive(Debug, Error)]
+pub enum BackupError {
+    #[error("Collection '{0}' does not exist")]
+    MissingCollection(String),
+
+    #[error("Archive is corrupt or incomplete: {0}")]
+    Corrupt(String),
+
+    #[error("Archive holds {archived:?} vectors but '{collection}' is configured for {target:?}")]
+    IncompatibleParams { collection: String, archived: CollectionParams, target: CollectionParams },
+
+    #[error("Archive holds a server snapshot, but this server cannot restore snapshots")]
+    SnapshotUnsupported,
+}
+
+impl From<BackupError> for talkpp_errors::Error {
+    fn from(error: BackupError) -> Self {
+        let kind = match &error {
+            BackupError::MissingCollection(_) => talkpp_errors::ErrorKind::NotFound,
+            BackupError::Corrupt(_) => talkpp_errors::ErrorKind::InvalidInput,
+            BackupError::IncompatibleParams { .. } | BackupError::SnapshotUnsupported => talkpp_errors::ErrorKind::Conflict,
+        };
+        Self::transparent(kind, error)
+    }
+}
+
+/// Collection operations backups are taken and restored through
+#[async_trait]
+pub trait BackupClient: Send + Sync {
+    /// Layout of collection `name`, or `None` if it does not exist
+    async fn collection_params(&self, name: &str) -> Result<Option<CollectionParams>>;
+
+    async fn create_collection_with(&self, name: &str, params: &CollectionParams) -> Result<()>;
+
+    /// A snapshot of the collection taken by the server, or `None` when the server cannot
+    /// take one
+    async fn snapshot(&self, name: &str) -> Result<Option<Vec<u8>>>;
+
+    /// Have the server restore `snapshot` as collection `name`
+    async fn recover_snapshot(&self, name: &str, snapshot: Vec<u8>) -> Result<()>;
+
+    /// Up to `limit` points starting at `offset`, with vectors and payloads
+    async fn scroll(&self, name: &str, offset: Option<String>, limit: u32) -> Result<ScrollPage>;
+
+    async fn upsert_points(&self, name: &str, points: Vec<BackupPoint>) -> Result<()>;
+}
+
+/// Back up collection `name` to `dest`, from a server snapshot when the server can take
+/// one and by scrolling through every point otherwise. The archive only appears at `dest`
+/// once it is complete.
+pub async fn backup_collection(client: &dyn BackupClient, name: &str, dest: &Path) -> Result<ArchiveReport> {
+    let params = client.collection_params(name).await?
+        .ok_or_else(|| BackupError::MissingCollection(name.to_string()))?;
+    let snapshot = client.snapshot(name).await?;
+    let kind = if snapshot.is_some() { BackupKind::Snapshot } else { BackupKind::Scroll };
+
+    let partial = partial_path(dest);
+    let mut archive = ArchiveWriter::create(&partial)?;
+    archive.frame(MANIFEST_FRAME, &serde_json::to_vec(&BackupManifest {
+        format_version: ARCHIVE_FORMAT_VERSION,
+        collection: name.to_string(),
+        kind,
+        params,
+        created_at: chrono::Utc::now(),
+    })?)?;
+
+    match snapshot {
+        Some(snapshot) => {
+            for chunk in snapshot.chunks(SNAPSHOT_CHUNK_BYTES) {
+                archive.chunk(chunk, 0)?;
+            }
+        }
+        None => {
+            let mut offset = None;
+            loop {
+                let page = client.scroll(name, offset, BACKUP_CHUNK_SIZE).await?;
+                if !page.points.is_empty() {
+                    archive.chunk(&serde_json::to_vec(&page.points)?, page.points.len() as u64)?;
+                }
+                match page.next_offset {
+                    Some(next) => offset = Some(next),
+                    None => break,
+                }
+            }
+        }
+    }
+
+    let report = archive.finish()?;
+    std::fs::rename(&partial, dest)?;
+    info!("Backed up '{}' to {} ({} points in {} chunks)", name, dest.display(), report.points, report.chunks);
+    Ok(ArchiveReport { kind, ..report })
+}
+
+/// Restore collection `name` from the archive at `src`. The whole archive is checked,
+/// and its manifest compared with `target` and any existing collection, before anything
+/// is written. A missing collection is created.
+pub async fn restore_collection(
+    client: &dyn BackupClient,
+    name: &str,
+    src: &Path,
+    target: &CollectionParams,
+    mut on_progress: impl FnMut(RestoreProgress) + Send,
+) -> Result<ArchiveReport> {
+    let (manifest, trailer) = tokio::task::spawn_blocking({
+        let src = src.to_path_buf();
+        move || verify_archive(&src)
+    }).await??;
+
+    let incompatible = |target: &CollectionParams| BackupError::IncompatibleParams {
+        collection: name.to_string(),
+        archived: manifest.params.clone(),
+        target: target.clone(),
+    };
+    if &manifest.params != target {
+        return Err(incompatible(target).into());
+    }
+    let existing = client.collection_params(name).await?;
+    if let Some(existing) = &existing {
+        if existing != target {
+            return Err(incompatible(existing).into());
+        }
+    }
+
+    let mut archive = ArchiveReader::open(src)?;
+    archive.next_frame()?; // manifest, already verified
+    let mut progress = RestoreProgress {
+        chunks_done: 0,
+        chunks_total: trailer.chunks,
+        points_done: 0,
+        points_total: trailer.points,
+    };
+
+    match manifest.kind {
+        BackupKind::Snapshot => {
+            let mut snapshot = Vec::new();
+            while let Some((CHUNK_FRAME, body)) = archive.next_frame()? {
+                snapshot.extend_from_slice(&body);
+            }
+            client.recover_snapshot(name, snapshot).await?;
+            progress.chunks_done = trailer.chunks;
+            progress.points_done = trailer.points;
+            on_progress(progress);
+        }
+        BackupKind::Scroll => {
+            if existing.is_none() {
+                client.create_collection_with(name, target).await?;
+            }
+            while let Some((CHUNK_FRAME, body)) = archive.next_frame()? {
+                let points: Vec<BackupPoint> = serde_json::from_slice(&body)?;
+                progress.chunks_done += 1;
+                progress.points_done += points.len() as u64;
+                client.upsert_points(name, points).await?;
+                on_progress(progress);
+            }
+        }
+    }
+
+    info!("Restored '{}' from {} ({} points)", name, src.display(), trailer.points);
+    Ok(ArchiveReport { kind: manifest.kind, chunks: trailer.chunks, points: trailer.points })
+}
+
+/// Read the whole archive, checking its framing, checksum, counts and vector sizes
+fn verify_archive(src: &Path) -> Result<(BackupManifest, BackupTrailer)> {
+    let mut archive = ArchiveReader::open(src)?;
+    let manifest: BackupManifest = match archive.next_frame()? {
+        Some((MANIFEST_FRAME, body)) => serde_json::from_slice(&body).map_err(corrupt)?,
+        _ => return Err(BackupError::Corrupt("no manifest".to_string()).into()),
+    };
+    if manifest.format_version != ARCHIVE_FORMAT_VERSION {
+        return Err(BackupError::Corrupt(format!("unsupported format version {}", manifest.format_version)).into());
+    }
+
+    let mut digest = Sha256::new();
+    let (mut chunks, mut points) = (0u64, 0u64);
+    let trailer: BackupTrailer = loop {
+        match archive.next_frame()? {
+            Some((CHUNK_FRAME, body)) => {
+                digest.update(&body);
+                chunks += 1;
+                if manifest.kind == BackupKind::Scroll {
+                    let batch: Vec<BackupPoint> = serde_json::from_slice(&body).map_err(corrupt)?;
+                    if let Some(point) = batch.iter().find(|p| p.vector.len() as u64 != manifest.params.vector_size) {
+                        return Err(BackupError::Corrupt(format!(
+                            "point {} has {} dimensions, expected {}",
+                            point.id,
+                            point.vector.len(),
+                            manifest.params.vector_size
+                        )).into());
+                    }
+                    points += batch.len() as u64;
+                }
+            }
+            Some((TRAILER_FRAME, body)) => break serde_json::from_slice(&body).map_err(corrupt)?,
+            Some((tag, _)) => return Err(BackupError::Corrupt(format!("unexpected frame '{}'", tag as char)).into()),
+            None => return Err(BackupError::Corrupt("no trailer".to_string()).into()),
+        }
+    };
+
+    if archive.next_frame()?.is_some() {
+        return Err(BackupError::Corrupt("data after the trailer".to_string()).into());
+    }
+    if trailer.sha256 != hex(&digest.finalize()) {
+        return Err(BackupError::Corrupt("checksum mismatch".to_string()).into());
+    }
+    if trailer.chunks != chunks || (manifest.kind == BackupKind::Scroll && trailer.points != points) {
+        return Err(BackupError::Corrupt(format!(
+            "trailer records {} points in {} chunks, archive holds {} in {}",
+            trailer.points, trailer.chunks, points, chunks
+        )).into());
+    }
+    Ok((manifest, trailer))
+}
+
+fn corrupt(error: impl std::fmt::Display) -> BackupError {
+    BackupError::Corrupt(error.to_string())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn partial_path(dest: &Path) -> PathBuf {
+    let mut partial = dest.as_os_str().to_owned();
+    partial.push(".partial");
+    PathBuf::from(partial)
+}
+
+/// zstd-compressed stream of frames: a tag byte, a little-endian `u32` length, then the body
+struct ArchiveWriter {
+    encoder: zstd::Encoder<'static, BufWriter<File>>,
+    digest: Sha256,
+    chunks: u64,
+    points: u64,
+}
+
+impl ArchiveWriter {
+    fn create(path: &Path) -> Result<Self> {
+        Ok(Self {
+            encoder: zstd::Encoder::new(BufWriter::new(File::create(path)?), 0)?,
+            digest: Sha256::new(),
+            chunks: 0,
+            points: 0,
+        })
+    }
+
+    fn frame(&mut self, tag: u8, body: &[u8]) -> Result<()> {
+        self.encoder.write_all(&[tag])?;
+        self.encoder.write_all(&(body.len() as u32).to_le_bytes())?;
+        self.encoder.write_all(body)?;
+        Ok(())
+    }
+
+    fn chunk(&mut self, body: &[u8], points: u64) -> Result<()> {
+        self.digest.update(body);
+        self.chunks += 1;
+        self.points += points;
+        self.frame(CHUNK_FRAME, body)
+    }
+
+    fn finish(mut self) -> Result<ArchiveReport> {
+        let trailer = BackupTrailer {
+            chunks: self.chunks,
+            points: self.points,
+            sha256: hex(&self.digest.clone().finalize()),
+        };
+        self.frame(TRAILER_FRAME, &serde_json::to_vec(&trailer)?)?;
+        self.encoder.finish()?.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        Ok(ArchiveReport { kind: BackupKind::Scroll, chunks: trailer.chunks, points: trailer.points })
+    }
+}
+
+struct ArchiveReader {
+    decoder: zstd::Decoder<'static, BufReader<File>>,
+}
+
+impl ArchiveReader {
+    fn open(path: &Path) -> Result<Self> {
+        Ok(Self { decoder: zstd::Decoder::new(File::open(path)?)? })
+    }
+
+    /// The next frame, or `None` at the end of the archive
+    fn next_frame(&mut self) -> Result<Option<(u8, Vec<u8>)>> {
+        let mut tag = [0u8; 1];
+        match self.decoder.read_exact(&mut tag) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(corrupt(e).into()),
+        }
+
+        let mut len = [0u8; 4];
+        self.decoder.read_exact(&mut len).map_err(corrupt)?;
+        let len = u32::from_le_bytes(len) as usize;
+        if len > MAX_FRAME_BYTES {
+            return Err(BackupError::Corrupt(format!("{}-byte frame", len)).into());
+        }
+        let mut body = vec![0u8; len];
+        self.decoder.read_exact(&mut body).map_err(corrupt)?;
+        Ok(Some((tag[0], body)))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::sync::Mutex;
+
+    pub(crate) type MockCollection = (CollectionParams, BTreeMap<String, BackupPoint>);
+
+    /// Collections kept in memory, served the way Qdrant pages through them, on a server
+    /// too old to take snapshots
+    #[derive(Default)]
+    pub(crate) struct MockClient {
+        pub(crate) collections: Mutex<HashMap<String, MockCollection>>,
+        pub(crate) upserted_batches: Mutex<Vec<usize>>,
+        /// Collection each alias names
+        pub(crate) aliases: Mutex<HashMap<String, String>>,
+    }
+
+    impl MockClient {
+        pub(crate) fn points(&self, name: &str) -> Vec<BackupPoint> {
+            self.collections.lock().unwrap()[name].1.values().cloned().collect()
+        }
+    }
+
+    #[async_trait]
+    impl BackupClient for MockClient {
+        async fn collection_params(&self, name: &str) -> Result<Option<CollectionParams>> {
+            Ok(self.collections.lock().unwrap().get(name).map(|(params, _)| params.clone()))
+        }
+
+        async fn create_collection_with(&self, name: &str, params: &CollectionParams) -> Result<()> {
+            self.collections.lock().unwrap().insert(name.to_string(), (params.clone(), BTreeMap::new()));
+            Ok(())
+        }
+
+        async fn snapshot(&self, _name: &str) -> Result<Option<Vec<u8>>> {
+            Ok(None)
+        }
+
+        async fn recover_snapshot(&self, _name: &str, _snapshot: Vec<u8>) -> Result<()> {
+            Err(BackupError::SnapshotUnsupported.into())
+        }
+
+        async fn scroll(&self, name: &str, offset: Option<String>, limit: u32) -> Result<ScrollPage> {
+            let collections = self.collections.lock().unwrap();
+            let points = &collections[name].1;
+            let mut page = points.range(offset.unwrap_or_default()..).map(|(_, p)| p.clone());
+            let taken: Vec<BackupPoint> = page.by_ref().take(limit as usize).collect();
+            Ok(ScrollPage { points: taken, next_offset: page.next().map(|p| p.id) })
+        }
+
+        async fn upsert_points(&self, name: &str, points: Vec<BackupPoint>) -> Result<()> {
+            self.upserted_batches.lock().unwrap().push(points.len());
+            let mut collections = self.collections.lock().unwrap();
+            let (_, stored) = collections.get_mut(name).unwrap();
+            stored.extend(points.into_iter().map(|p| (p.id.clone(), p)));
+            Ok(())
+        }
+    }
+
+    pub(crate) fn params(vector_size: u64) -> CollectionParams {
+        CollectionParams { vector_size, distance: DistanceMetric::Cosine }
+    }
+
+    async fn source(count: usize) -> MockClient {
+        let client = MockClient::default();
+        client.create_collection_with("docs", &params(4)).await.unwrap();
+        let points = (0..count)
+            .map(|i| BackupPoint {
+                id: uuid::Uuid::new_v4().to_string(),
+                vector: vec![i as f32, 0.5, -1.0, 2.0],
+                payload: HashMap::from([("content".to_string(), serde_json::json!(format!("document {}", i)))]),
+            })
+            .collect();
+        client.upsert_points("docs", points).await.unwrap();
+        client
+    }
+
+    fn archive_path() -> PathBuf {
+        std::env::temp_dir().join(format!("vector-backup-{}.tpvb", uuid::Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn test_scroll_backup_round_trip() {
+        let source = source(600).await;
+        let path = archive_path();
+
+        let backup = backup_collection(&source, "docs", &path).await.unwrap();
+        assert_eq!(backup, ArchiveReport { kind: BackupKind::Scroll, chunks: 3, points: 600 });
+        assert!(!partial_path(&path).exists());
+
+        let target = MockClient::default();
+        let mut progress = Vec::new();
+        let restored = restore_collection(&target, "restored", &path, &params(4), |p| progress.push(p)).await.unwrap();
+        assert_eq!(restored, backup);
+        assert_eq!(target.collection_params("restored").await.unwrap(), Some(params(4)));
+        assert_eq!(target.points("restored"), source.points("docs"));
+        assert_eq!(*target.upserted_batches.lock().unwrap(), vec![256, 256, 88]);
+        assert_eq!(progress.iter().map(|p| p.points_done).collect::<Vec<_>>(), vec![256, 512, 600]);
+        assert_eq!(progress.last().unwrap().chunks_done, progress.last().unwrap().chunks_total);
+        std::fs::remove_file(path).ok();
+    }
+
+    #[tokio::test]
+    async fn test_bad_archives_fail_before_writing() {
+        let source = source(300).await;
+        let path = archive_path();
+        backup_collection(&source, "docs", &path).await.unwrap();
+        let target = MockClient::default();
+
+        // Configured for a different model
+        let err = restore_collection(&target, "restored", &path, &params(8), |_| {}).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(BackupError::IncompatibleParams { .. })), "{}", err);
+
+        // Cut off partway through
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() * 2 / 3]).unwrap();
+        let err = restore_collection(&target, "restored", &path, &params(4), |_| {}).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(BackupError::Corrupt(_))), "{}", err);
+
+        assert!(target.collections.lock().unwrap().is_empty());
+        assert!(target.upserted_batches.lock().unwrap().is_empty());
+        std::fs::remove_file(path).ok();
+    }
+}
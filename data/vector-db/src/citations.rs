@@ -0,0 +1,455 @@
+//! Tracing the claims of a RAG answer back to the sources it cites
+//!
+//! `RagSystem::generate_with_context` numbers the retrieved chunks `[1]`, `[2]`, ... in
+//! the prompt, and the `rag_answer` template asks the model to cite them with the same
+//! markers. [`cite`] checks an answer against the sources, sentence by sentence:
+//!
+//! - A marker naming a source becomes a [`Citation`], quoting the sentence of the chunk
+//!   that best matches the claim the marker ends.
+//! - A marker naming no source is rewritten to the source that best supports its claim,
+//!   if one holds at least [`REWRITE_MIN_SUPPORT`] of the claim's terms and the sentence
+//!   doesn't cite it already; otherwise it is dropped.
+//! - A sentence stating something without citing anything is flagged with
+//!   [`LOW_CONFIDENCE_MARKER`]. Questions, headings, short transitions and sentences saying
+//!   the context holds no answer are left alone.
+//!
+//! Claims are matched to chunk sentences by the share of their terms, ignoring common
+//! words, that the chunk sentence contains.
+
+use std::collections::HashSet;
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::retrieval::terms;
+use crate::SearchResult;
+
+/// Placed at the end of a sentence that states something without citing a source
+pub const LOW_CONFIDENCE_MARKER: &str = "[unverified]";
+
+/// Share of a claim's terms a source must contain for an invalid marker to be rewritten to it
+pub const REWRITE_MIN_SUPPORT: f32 = 0.5;
+
+/// Fewest words in an uncited sentence that is flagged; shorter ones are taken for
+/// transitions such as "In short:"
+const MIN_CLAIM_WORDS: usize = 5;
+
+/// Sentences saying the context doesn't answer the question, which need no source
+const NO_ANSWER_PHRASES: &[&str] = &[
+    "does not contain",
+    "doesn't contain",
+    "does not say",
+    "doesn't say",
+    "don't know",
+    "do not know",
+    "not sure",
+];
+
+/// Words too common to tell which source a claim came from
+const STOPWORDS: &[&str] = &[
+    "the", "and", "for", "are", "was", "were", "with", "that", "this", "from", "its", "has", "have", "not", "but",
+    "they", "their", "into", "than", "then", "also",
+];
+
+/// A claim in the answer and the source it cites
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Citation {
+    /// `n` of the `[n]` marker in the answer, after rewriting
+    pub marker: usize,
+    /// `rank` of the cited source in the search results
+    pub source_rank: usize,
+    pub document_id: Uuid,
+    /// Position of the chunk in its document, when the chunk records it
+    pub chunk_index: Option<usize>,
+    /// Sentence of the chunk that best matches the claim; empty when none shares a term
+    pub quoted_span: String,
+    /// Share of the claim's terms found in `quoted_span`
+    pub support: f32,
+}
+
+/// A model's answer with its citation markers checked; see [`cite`]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CitedAnswer {
+    /// The answer with invalid markers rewritten or dropped and uncited claims flagged
+    pub text: String,
+    /// One per marker left in `text`, in the order they appear
+    pub citations: Vec<Citation>,
+    /// Markers naming no source that were pointed at the source supporting their claim
+    pub rewritten_markers: usize,
+    /// Markers naming no source that were removed
+    pub dropped_markers: usize,
+    /// Sentences flagged with [`LOW_CONFIDENCE_MARKER`], without their markers
+    pub uncited: Vec<String>,
+}
+
+impl CitedAnswer {
+    /// `text` as Markdown, its markers turned into footnote references and each cited
+    /// source listed as a footnote linking to it, with the spans quoted from it
+    pub fn render_markdown(&self, sources: &[SearchResult]) -> String {
+        let mut rendered = String::with_capacity(self.text.len());
+        let mut at = 0;
+        for marker in markers(&self.text) {
+            rendered.push_str(&self.text[at..marker.range.start]);
+            rendered.push_str(&format!("[^{}]", marker.number));
+            at = marker.range.end;
+        }
+        rendered.push_str(&self.text[at..]);
+
+        let mut cited: Vec<usize> = Vec::new();
+        for citation in &self.citations {
+            if !cited.contains(&citation.marker) {
+                cited.push(citation.marker);
+            }
+        }
+        if !cited.is_empty() {
+            rendered.push_str("\n\n");
+        }
+        for number in cited {
+            let Some(source) = sources.get(number - 1) else {
+                continue;
+            };
+            let mut quotes: Vec<&str> = Vec::new();
+            for citation in self.citations.iter().filter(|c| c.marker == number && !c.quoted_span.is_empty()) {
+                if !quotes.contains(&citation.quoted_span.as_str()) {
+                    quotes.push(&citation.quoted_span);
+                }
+            }
+            rendered.push_str(&format!("[^{}]: {}", number, source_label(source)));
+            if !quotes.is_empty() {
+                rendered.push_str(&format!(": \"{}\"", quotes.join("\" … \"")));
+            }
+            rendered.push('\n');
+        }
+        rendered
+    }
+}
+
+/// Check the citation markers of `answer`, which cites `sources` as `[1]` for the first;
+/// see the [module docs](self)
+pub fn cite(answer: &str, sources: &[SearchResult]) -> CitedAnswer {
+    let mut cited = CitedAnswer::default();
+    let mut edits: Vec<(Range<usize>, String)> = Vec::new();
+    let exists = |number: usize| (1..=sources.len()).contains(&number);
+
+    for sentence in sentences(answer) {
+        let claim = sentence.claim(answer);
+        let claim_terms = key_terms(&claim);
+        let spans: Vec<(f32, String)> = sources.iter()
+            .map(|source| best_span(&claim_terms, &source.document.content))
+            .collect();
+        let mut cited_here: Vec<usize> = sentence.markers.iter().map(|m| m.number).filter(|n| exists(*n)).collect();
+
+        for marker in &sentence.markers {
+            let number = if exists(marker.number) {
+                marker.number
+            } else {
+                // First of equals wins, so ties go to the higher-ranked source
+                let best = (0..sources.len())
+                    .filter(|i| spans[*i].0 >= REWRITE_MIN_SUPPORT && !cited_here.contains(&(i + 1)))
+                    .fold(None, |best: Option<usize>, i| match best {
+                        Some(b) if spans[b].0 >= spans[i].0 => Some(b),
+                        _ => Some(i),
+                    });
+                let Some(best) = best else {
+                    cited.dropped_markers += 1;
+                    edits.push((with_leading_space(answer, &marker.range), String::new()));
+                    continue;
+                };
+                cited.rewritten_markers += 1;
+                cited_here.push(best + 1);
+                edits.push((marker.range.clone(), format!("[{}]", best + 1)));
+                best + 1
+            };
+            let source = &sources[number - 1];
+            let (support, quoted_span) = spans[number - 1].clone();
+            cited.citations.push(Citation {
+                marker: number,
+                source_rank: source.rank,
+                document_id: source.document.id,
+                chunk_index: source.document.metadata.get("chunk_index")
+                    .and_then(serde_json::Value::as_u64)
+                    .map(|index| index as usize),
+                quoted_span,
+                support,
+            });
+        }
+
+        if cited_here.is_empty() && looks_factual(&claim) {
+            let at = sentence.flag_position(answer);
+            edits.push((at..at, format!(" {}", LOW_CONFIDENCE_MARKER)));
+            cited.uncited.push(claim);
+        }
+    }
+
+    // Stable, so a flag stays after a dropped marker ending where the flag goes
+    edits.sort_by_key(|(range, _)| range.start);
+    let mut at = 0;
+    for (range, replacement) in edits {
+        cited.text.push_str(&answer[at..range.start]);
+        cited.text.push_str(&replacement);
+        at = range.end;
+    }
+    cited.text.push_str(&answer[at..]);
+    cited
+}
+
+/// A `[n]` marker in an answer
+struct Marker {
+    range: Range<usize>,
+    number: usize,
+}
+
+/// A sentence of an answer, with the markers that follow its claim
+struct Sentence {
+    range: Range<usize>,
+    markers: Vec<Marker>,
+}
+
+impl Sentence {
+    /// The sentence without its markers, whitespace collapsed
+    fn claim(&self, text: &str) -> String {
+        let mut claim = String::new();
+        let mut at = self.range.start;
+        for marker in &self.markers {
+            claim.push_str(text[at..marker.range.start].trim_end());
+            at = marker.range.end;
+        }
+        claim.push_str(&text[at..self.range.end]);
+        claim.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    /// Where a flag goes: before the closing punctuation of the claim, past any markers
+    fn flag_position(&self, text: &str) -> usize {
+        let mut end = self.range.end;
+        loop {
+            end = self.range.start + text[self.range.start..end].trim_end().len();
+            match self.markers.iter().find(|marker| marker.range.end == end) {
+                Some(marker) => end = marker.range.start,
+                None => break,
+            }
+        }
+        if text[..end].ends_with(['.', '!', '?']) {
+            end - 1
+        } else {
+            end
+        }
+    }
+}
+
+/// The marker starting at byte `at` of `text`, if one does. `[1](url)` is a link and
+/// `[1]: url` a link definition, not markers.
+fn marker_at(text: &str, at: usize) -> Option<Marker> {
+    let rest = text.get(at..)?.strip_prefix('[')?;
+    let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+    if digits == 0 || digits > 3 || rest.as_bytes().get(digits) != Some(&b']') {
+        return None;
+    }
+    let end = at + digits + 2;
+    if matches!(text.as_bytes().get(end), Some(b'(' | b':')) {
+        return None;
+    }
+    Some(Marker { range: at..end, number: rest[..digits].parse().ok()? })
+}
+
+fn markers(text: &str) -> Vec<Marker> {
+    sentences(text).into_iter().flat_map(|sentence| sentence.markers).collect()
+}
+
+/// `text` split into sentences at line breaks and at `.`, `!` or `?` followed by
+/// whitespace. Markers right after the punctuation, such as `spin.[1]` or `spin. [1]`,
+/// belong to the sentence they follow.
+fn sentences(text: &str) -> Vec<Sentence> {
+    let bytes = text.as_bytes();
+    let mut sentences = Vec::new();
+    let mut markers = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        if let Some(marker) = marker_at(text, i) {
+            i = marker.range.end;
+            markers.push(marker);
+            continue;
+        }
+        let end = match bytes[i] {
+            b'\n' => Some(i),
+            b'.' | b'!' | b'?' => {
+                let mut end = i + 1;
+                let mut trailing = false;
+                loop {
+                    let next = if bytes.get(end) == Some(&b' ') { end + 1 } else { end };
+                    let Some(marker) = marker_at(text, next) else {
+                        break;
+                    };
+                    end = marker.range.end;
+                    markers.push(marker);
+                    trailing = true;
+                }
+                (trailing || end == bytes.len() || bytes[end].is_ascii_whitespace()).then_some(end)
+            }
+            _ => None,
+        };
+        match end {
+            Some(end) => {
+                sentences.push(Sentence { range: start..end, markers: std::mem::take(&mut markers) });
+                start = end;
+                i = end.max(i + 1);
+            }
+            None => i += 1,
+        }
+    }
+    sentences.push(Sentence { range: start..bytes.len(), markers });
+    sentences.retain(|sentence| !sentence.markers.is_empty() || !text[sentence.range.clone()].trim().is_empty());
+    sentences
+}
+
+/// Terms of `text` that tell claims apart: no common words, and no one or two letter
+/// words unless they are numbers
+fn key_terms(text: &str) -> HashSet<String> {
+    terms(text)
+        .into_iter()
+        .filter(|term| term.len() > 2 || term.chars().all(|c| c.is_ascii_digit()))
+        .filter(|term| !STOPWORDS.contains(&term.as_str()))
+        .collect()
+}
+
+/// The sentence of `content` holding the largest share of `claim`, and that share
+fn best_span(claim: &HashSet<String>, content: &str) -> (f32, String) {
+    if claim.is_empty() {
+        return (0.0, String::new());
+    }
+    sentences(content)
+        .iter()
+        .map(|sentence| {
+            let span = sentence.claim(content);
+            let found = claim.intersection(&key_terms(&span)).count();
+            (found as f32 / claim.len() as f32, span)
+        })
+        .fold((0.0, String::new()), |best, next| if next.0 > best.0 { next } else { best })
+}
+
+/// Whether an uncited sentence states something a source should back
+fn looks_factual(claim: &str) -> bool {
+    let lowercase = claim.to_lowercase();
+    claim.split_whitespace().count() >= MIN_CLAIM_WORDS
+        && !claim.ends_with('?')
+        && !claim.starts_with('#')
+        && !NO_ANSWER_PHRASES.iter().any(|phrase| lowercase.contains(phrase))
+}
+
+/// `range` widened over one space before it, so removing a marker leaves no gap
+fn with_leading_space(text: &str, range: &Range<usize>) -> Range<usize> {
+    if text[..range.start].ends_with(' ') {
+        range.start - 1..range.end
+    } else {
+        range.clone()
+    }
+}
+
+/// The source's `title`, `source` or `url` metadata, or its id, linked to its `url` if
+/// it has one, and the chunk it is
+fn source_label(source: &SearchResult) -> String {
+    let metadata = &source.document.metadata;
+    let text = |key: &str| metadata.get(key).and_then(serde_json::Value::as_str).map(str::to_string);
+    let name = text("title").or_else(|| text("source")).or_else(|| text("url")).unwrap_or_else(|| source.document.id.to_string());
+    let mut label = match text("url") {
+        Some(url) => format!("[{}]({})", name, url),
+        None => name,
+    };
+    let number = |key: &str| metadata.get(key).and_then(serde_json::Value::as_u64);
+    match (number("chunk_index"), number("total_chunks")) {
+        (Some(index), Some(total)) if total > 1 => label.push_str(&format!(", chunk {} of {}", index + 1, total)),
+        (Some(index), None) => label.push_str(&format!(", chunk {}", index + 1)),
+        _ => {}
+    }
+    label
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::VectorDocument;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    fn source(rank: usize, content: &str, metadata: serde_json::Value) -> SearchResult {
+        SearchResult {
+            document: VectorDocument {
+                id: Uuid::new_v4(),
+                content: content.to_string(),
+                metadata: serde_json::from_value::<HashMap<_, _>>(metadata).unwrap(),
+                vector: None,
+                created_at: chrono::Utc::now(),
+                updated_at: chrono::Utc::now(),
+            },
+            score: 1.0,
+            raw_score: 1.0,
+            rank,
+        }
+    }
+
+    fn sources() -> Vec<SearchResult> {
+        vec![
+            source(0, "Tidal turbines spin in both directions. They sit on the seabed.", json!({"source": "tides.md", "chunk_index": 2, "total_chunks": 5})),
+            source(1, "Most wind farms are built offshore in the North Sea.", json!({"title": "Wind atlas", "url": "https://example.com/wind"})),
+        ]
+    }
+
+    #[test]
+    fn test_valid_citations_quote_the_matching_chunk_sentence() {
+        let sources = sources();
+        let answer = "They sit on the seabed [1]. Wind farms are mostly offshore.[2] [1]";
+        let cited = cite(answer, &sources);
+
+        assert_eq!(cited.text, answer);
+        assert_eq!((cited.rewritten_markers, cited.dropped_markers), (0, 0));
+        assert!(cited.uncited.is_empty());
+        let quoted: Vec<(usize, usize, Option<usize>, &str)> = cited.citations.iter()
+            .map(|c| (c.marker, c.source_rank, c.chunk_index, c.quoted_span.as_str()))
+            .collect();
+        assert_eq!(quoted, [
+            (1, 0, Some(2), "They sit on the seabed."),
+            (2, 1, None, "Most wind farms are built offshore in the North Sea."),
+            (1, 0, Some(2), ""),
+        ]);
+        assert_eq!(cited.citations[0].document_id, sources[0].document.id);
+        assert_eq!(cited.citations[0].support, 1.0);
+    }
+
+    #[test]
+    fn test_invalid_markers_are_rewritten_or_dropped() {
+        let cited = cite(
+            "Wind farms sit offshore in the North Sea [7]. Tidal turbines spin both ways [1][9]. Solar panels are cheaper than ever [4].",
+            &sources(),
+        );
+
+        assert_eq!(
+            cited.text,
+            "Wind farms sit offshore in the North Sea [2]. Tidal turbines spin both ways [1]. Solar panels are cheaper than ever [unverified].",
+        );
+        assert_eq!((cited.rewritten_markers, cited.dropped_markers), (1, 2));
+        assert_eq!(cited.citations.iter().map(|c| c.marker).collect::<Vec<_>>(), [2, 1]);
+        assert_eq!(cited.uncited, ["Solar panels are cheaper than ever."]);
+    }
+
+    #[test]
+    fn test_uncited_claims_are_flagged_and_rendered_as_markdown() {
+        let sources = sources();
+        let answer = "Tidal turbines spin in both directions\nDo they?\nThe context does not say how fast they spin.\nIn short: yes [1].";
+        let cited = cite(answer, &sources);
+
+        assert_eq!(
+            cited.text,
+            "Tidal turbines spin in both directions [unverified]\nDo they?\nThe context does not say how fast they spin.\nIn short: yes [1].",
+        );
+        assert_eq!(cited.uncited, ["Tidal turbines spin in both directions"]);
+
+        let cited = cite("Wind farms are mostly offshore [2]. Turbines spin both ways. [1] See [1](https://example.com).", &sources);
+        assert_eq!(
+            cited.render_markdown(&sources),
+            "Wind farms are mostly offshore [^2]. Turbines spin both ways. [^1] See [1](https://example.com).\n\n\
+             [^2]: [Wind atlas](https://example.com/wind): \"Most wind farms are built offshore in the North Sea.\"\n\
+             [^1]: tides.md, chunk 3 of 5: \"Tidal turbines spin in both directions.\"\n",
+        );
+    }
+}
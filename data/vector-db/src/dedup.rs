@@ -0,0 +1,102 @@
+//! Content-addressed chunk deduplication for `RagSystem`
+//!
+//! Every chunk is stored with a `content_hash` payload: the SHA-256 of its text after
+//! normalization, which lowercases it and collapses each run of whitespace to one space.
+//! Chunks that differ only in casing or spacing, such as a document re-exported with
+//! different line wrapping, therefore hash the same and are stored once.
+//!
+//! `RagSystem::add_document` looks each chunk up in a [`DedupIndex`] of the hashes already
+//! stored. A chunk already stored with the same metadata is skipped; one stored with other
+//! metadata has only its metadata rewritten, under its existing id, text and vector, so it
+//! is not embedded again. The index is built from the collection's payloads on first use and can
+//! be rebuilt at any time, so losing it costs a scroll through the collection rather than
+//! duplicate points.
+//!
+//! Identical chunks of different documents are stored once, under the id of whichever was
+//! added first, and both documents list that id among their chunks.
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use uuid::Uuid;
+
+/// Payload field holding a chunk's normalized content hash
+pub const CONTENT_HASH_KEY: &str = "content_hash";
+
+/// `text` as compared for duplicates: lowercased, with whitespace runs collapsed to one space
+pub fn normalize_chunk(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+/// Hex SHA-256 of the normalized `text`
+pub fn content_hash(text: &str) -> String {
+    format!("{:x}", Sha256::digest(normalize_chunk(text).as_bytes()))
+}
+
+/// Hex SHA-256 of `metadata` with its keys in order, to tell whether it changed. The
+/// chunk's text is left out, so a near-duplicate with the same metadata counts as unchanged.
+pub(crate) fn metadata_hash(metadata: &HashMap<String, serde_json::Value>) -> String {
+    let ordered: BTreeMap<&String, &serde_json::Value> = metadata.iter()
+        .filter(|(key, _)| key.as_str() != "content")
+        .collect();
+    format!("{:x}", Sha256::digest(serde_json::to_vec(&ordered).unwrap_or_default()))
+}
+
+/// A stored chunk as the index knows it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DedupEntry {
+    pub id: Uuid,
+    pub metadata_hash: String,
+}
+
+/// Stored chunks by content hash
+#[derive(Debug, Clone, Default)]
+pub struct DedupIndex {
+    by_hash: HashMap<String, DedupEntry>,
+    hash_by_id: HashMap<Uuid, String>,
+}
+
+impl DedupIndex {
+    pub fn get(&self, content_hash: &str) -> Option<&DedupEntry> {
+        self.by_hash.get(content_hash)
+    }
+
+    /// Record the chunk `entry.id` as holding `content_hash`, replacing whatever it held before
+    pub fn insert(&mut self, content_hash: String, entry: DedupEntry) {
+        self.remove(entry.id);
+        self.hash_by_id.insert(entry.id, content_hash.clone());
+        self.by_hash.insert(content_hash, entry);
+    }
+
+    /// Forget the chunk `id`
+    pub fn remove(&mut self, id: Uuid) {
+        if let Some(hash) = self.hash_by_id.remove(&id) {
+            if self.by_hash.get(&hash).is_some_and(|entry| entry.id == id) {
+                self.by_hash.remove(&hash);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_hash.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_hash.is_empty()
+    }
+}
+
+/// What adding a document stored
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct AddDocumentSummary {
+    /// Ids of the document's chunks in order, including those already stored
+    pub chunk_ids: Vec<Uuid>,
+    /// Chunks embedded and stored for the first time
+    pub new: usize,
+    /// Chunks already stored with the same metadata
+    pub skipped: usize,
+    /// Chunks already stored whose metadata was rewritten
+    pub refreshed: usize,
+    /// Chunks not stored because they held personal data their detector drops chunks for
+    pub dropped: usize,
+}
@@ -0,0 +1,373 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Semaphore};
+use tokio::task::{JoinHandle, JoinSet};
+use tracing::{debug, warn};
+
+use crate::{EmbeddingModel, VectorDatabase, VectorDocument};
+
+/// Embed latencies kept for the p95 estimate
+const LATENCY_SAMPLES: usize = 1024;
+
+/// Sizing of an `EmbeddingWorkerPool`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingPoolConfig {
+    /// Tasks embedding batches concurrently
+    pub workers: usize,
+    /// Batches waiting to be embedded before `submit` waits for room
+    pub channel_depth: usize,
+    /// Documents per `embed_batch` call
+    pub batch_size: usize,
+    /// Upserts in flight at once
+    pub upsert_concurrency: usize,
+}
+
+impl Default for EmbeddingPoolConfig {
+    fn default() -> Self {
+        Self {
+            workers: 4,
+            channel_depth: 16,
+            batch_size: 32,
+            upsert_concurrency: 4,
+        }
+    }
+}
+
+/// Snapshot of an `EmbeddingWorkerPool`'s progress
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EmbeddingPoolStats {
+    /// Batches submitted but not yet picked up by a worker
+    pub queued_batches: usize,
+    pub embedded_documents: u64,
+    pub upserted_documents: u64,
+    /// Documents dropped because embedding or upserting them failed
+    pub failed_documents: u64,
+    /// Documents embedded per second since the pool started
+    pub throughput: f64,
+    /// Over the most recent batches
+    pub p95_embed_latency: Duration,
+}
+
+#[derive(Debug)]
+struct PoolMetrics {
+    started: Instant,
+    embedded: AtomicU64,
+    upserted: AtomicU64,
+    failed: AtomicU64,
+    latencies: Mutex<VecDeque<Duration>>,
+}
+
+impl PoolMetrics {
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            embedded: AtomicU64::new(0),
+            upserted: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+            latencies: Mutex::new(VecDeque::with_capacity(LATENCY_SAMPLES)),
+        }
+    }
+
+    fn record_embed(&self, documents: usize, latency: Duration) {
+        self.embedded.fetch_add(documents as u64, Ordering::Relaxed);
+        let mut latencies = self.latencies.lock().unwrap();
+        if latencies.len() == LATENCY_SAMPLES {
+            latencies.pop_front();
+        }
+        latencies.push_back(latency);
+    }
+
+    fn record_failure(&self, documents: usize) {
+        self.failed.fetch_add(documents as u64, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self, queued_batches: usize) -> EmbeddingPoolStats {
+        let embedded = self.embedded.load(Ordering::Relaxed);
+        let elapsed = self.started.elapsed().as_secs_f64();
+        EmbeddingPoolStats {
+            queued_batches,
+            embedded_documents: embedded,
+            upserted_documents: self.upserted.load(Ordering::Relaxed),
+            failed_documents: self.failed.load(Ordering::Relaxed),
+            throughput: if elapsed > 0.0 { embedded as f64 / elapsed } else { 0.0 },
+            p95_embed_latency: self.p95_latency(),
+        }
+    }
+
+    fn p95_latency(&self) -> Duration {
+        let mut latencies: Vec<Duration> = self.latencies.lock().unwrap().iter().copied().collect();
+        if latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        latencies.sort();
+        latencies[(latencies.len() * 95).div_ceil(100) - 1]
+    }
+}
+
+/// Embeds documents on a fixed set of worker tasks and upserts the results.
+///
+/// Batches wait in a bounded queue, so producers calling `submit` slow down to the pace
+/// the embedder sustains instead of buffering without limit.
+pub struct EmbeddingWorkerPool {
+    jobs: mpsc::Sender<Vec<VectorDocument>>,
+    workers: Vec<JoinHandle<()>>,
+    upserter: JoinHandle<()>,
+    metrics: Arc<PoolMetrics>,
+    batch_size: usize,
+}
+
+impl EmbeddingWorkerPool {
+    /// Start the workers and the upsert stage; must be called within a Tokio runtime
+    pub fn new(
+        config: EmbeddingPoolConfig,
+        embedder: Arc<dyn EmbeddingModel + Send + Sync>,
+        vector_db: Arc<dyn VectorDatabase + Send + Sync>,
+    ) -> Self {
+        let (jobs, job_queue) = mpsc::channel(config.channel_depth.max(1));
+        let (embedded, embedded_queue) = mpsc::channel(config.channel_depth.max(1));
+        let job_queue = Arc::new(tokio::sync::Mutex::new(job_queue));
+        let metrics = Arc::new(PoolMetrics::new());
+
+        let workers = (0..config.workers.max(1))
+            .map(|_| tokio::spawn(embed_worker(job_queue.clone(), embedder.clone(), embedded.clone(), metrics.clone())))
+            .collect();
+        let upserter = tokio::spawn(upsert_stage(embedded_queue, vector_db, config.upsert_concurrency.max(1), metrics.clone()));
+
+        Self {
+            jobs,
+            workers,
+            upserter,
+            metrics,
+            batch_size: config.batch_size.max(1),
+        }
+    }
+
+    /// Queue documents for embedding and upsert, waiting while the queue is full.
+    /// Documents that already carry a vector are upserted as they are.
+    pub async fn submit(&self, documents: Vec<VectorDocument>) -> Result<()> {
+        let mut documents = documents.into_iter().peekable();
+        while documents.peek().is_some() {
+            let batch: Vec<VectorDocument> = documents.by_ref().take(self.batch_size).collect();
+            self.jobs.send(batch).await
+                .map_err(|_| anyhow::anyhow!("Embedding worker pool has shut down"))?;
+        }
+        Ok(())
+    }
+
+    pub fn stats(&self) -> EmbeddingPoolStats {
+        self.metrics.snapshot(self.jobs.max_capacity() - self.jobs.capacity())
+    }
+
+    /// Stop the pool. With `drain`, queued batches are embedded and upserted first;
+    /// otherwise they are dropped and in-flight work is aborted.
+    pub async fn shutdown(self, drain: bool) -> EmbeddingPoolStats {
+        let Self { jobs, workers, upserter, metrics, .. } = self;
+        drop(jobs);
+        if !drain {
+            for worker in &workers {
+                worker.abort();
+            }
+            upserter.abort();
+        }
+        for worker in workers {
+            let _ = worker.await;
+        }
+        // Finishes once every worker has dropped its sender
+        let _ = upserter.await;
+        debug!("Embedding worker pool stopped");
+        metrics.snapshot(0)
+    }
+}
+
+async fn embed_worker(
+    jobs: Arc<tokio::sync::Mutex<mpsc::Receiver<Vec<VectorDocument>>>>,
+    embedder: Arc<dyn EmbeddingModel + Send + Sync>,
+    embedded: mpsc::Sender<Vec<VectorDocument>>,
+    metrics: Arc<PoolMetrics>,
+) {
+    loop {
+        let Some(mut batch) = jobs.lock().await.recv().await else {
+            return;
+        };
+
+        let pending: Vec<usize> = (0..batch.len()).filter(|&i| batch[i].vector.is_none()).collect();
+        if !pending.is_empty() {
+            let texts: Vec<&str> = pending.iter().map(|&i| batch[i].content.as_str()).collect();
+            let started = Instant::now();
+            let result = embedder.embed_batch(texts).await;
+            match result {
+                Ok(vectors) if vectors.len() == pending.len() => {
+                    metrics.record_embed(pending.len(), started.elapsed());
+                    for (i, vector) in pending.into_iter().zip(vectors) {
+                        batch[i].vector = Some(vector);
+                    }
+                }
+                Ok(vectors) => {
+                    warn!("Embedder returned {} vectors for {} documents", vectors.len(), pending.len());
+                    metrics.record_failure(batch.len());
+                    continue;
+                }
+                Err(e) => {
+                    warn!("Failed to embed batch of {} documents: {}", pending.len(), e);
+                    metrics.record_failure(batch.len());
+                    continue;
+                }
+            }
+        }
+
+        if embedded.send(batch).await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn upsert_stage(
+    mut embedded: mpsc::Receiver<Vec<VectorDocument>>,
+    vector_db: Arc<dyn VectorDatabase + Send + Sync>,
+    concurrency: usize,
+    metrics: Arc<PoolMetrics>,
+) {
+    let permits = Arc::new(Semaphore::new(concurrency));
+    let mut running = JoinSet::new();
+    while let Some(batch) = embedded.recv().await {
+        let permit = permits.clone().acquire_owned().await.expect("upsert semaphore is never closed");
+        let vector_db = vector_db.clone();
+        let metrics = metrics.clone();
+        running.spawn(async move {
+            let count = batch.len();
+            match vector_db.upsert_documents(batch).await {
+                Ok(()) => {
+                    metrics.upserted.fetch_add(count as u64, Ordering::Relaxed);
+                }
+                Err(e) => {
+                    warn!("Failed to upsert batch of {} documents: {}", count, e);
+                    metrics.record_failure(count);
+                }
+            }
+            drop(permit);
+        });
+        while running.try_join_next().is_some() {}
+    }
+    while running.join_next().await.is_some() {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeVectorDb;
+    use async_trait::async_trait;
+    use std::collections::HashMap;
+    use uuid::Uuid;
+
+    /// Embeds each text as its length, once `gate` grants a permit per batch
+    struct FakeEmbedder {
+        gate: Arc<Semaphore>,
+        calls: AtomicU64,
+        delay: Duration,
+    }
+
+    impl FakeEmbedder {
+        fn new(open: bool, delay: Duration) -> Arc<Self> {
+            Arc::new(Self {
+                gate: Arc::new(Semaphore::new(if open { Semaphore::MAX_PERMITS } else { 0 })),
+                calls: AtomicU64::new(0),
+                delay,
+            })
+        }
+    }
+
+    #[async_trait]
+    impl EmbeddingModel for FakeEmbedder {
+        async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+            Ok(self.embed_batch(vec![text]).await?.remove(0))
+        }
+
+        async fn embed_batch(&self, texts: Vec<&str>) -> Result<Vec<Vec<f32>>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            self.gate.acquire().await?.forget();
+            tokio::time::sleep(self.delay).await;
+            Ok(texts.iter().map(|t| vec![t.len() as f32]).collect())
+        }
+
+        fn dimension(&self) -> usize {
+            1
+        }
+
+        fn model_id(&self) -> &str {
+            "test/fake"
+        }
+    }
+
+    fn documents(count: usize) -> Vec<VectorDocument> {
+        (0..count).map(|i| VectorDocument {
+            id: Uuid::new_v4(),
+            content: "x".repeat(i % 7 + 1),
+            metadata: HashMap::new(),
+            vector: None,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        }).collect()
+    }
+
+    #[tokio::test]
+    async fn test_bounded_queue_blocks_producers() {
+        let embedder = FakeEmbedder::new(false, Duration::ZERO);
+        let db = FakeVectorDb::default();
+        let config = EmbeddingPoolConfig { workers: 1, channel_depth: 1, batch_size: 2, upsert_concurrency: 1 };
+        let pool = EmbeddingWorkerPool::new(config, embedder.clone(), Arc::new(db.clone()));
+
+        // The worker takes the first batch and waits at the gate; the second fills the queue
+        pool.submit(documents(2)).await.unwrap();
+        while embedder.calls.load(Ordering::SeqCst) == 0 {
+            tokio::task::yield_now().await;
+        }
+        pool.submit(documents(2)).await.unwrap();
+        assert_eq!(pool.stats().queued_batches, 1);
+
+        let blocked = tokio::time::timeout(Duration::from_millis(50), pool.submit(documents(2))).await;
+        assert!(blocked.is_err(), "submit should wait while the queue is full");
+
+        embedder.gate.add_permits(Semaphore::MAX_PERMITS / 2);
+        let stats = pool.shutdown(true).await;
+        assert_eq!((stats.embedded_documents, stats.upserted_documents, stats.failed_documents), (4, 4, 0));
+        let documents = db.documents.lock().unwrap();
+        assert_eq!(documents.len(), 4);
+        assert!(documents.values().all(|d| d.vector == Some(vec![d.content.len() as f32])));
+    }
+
+    #[tokio::test]
+    async fn test_drains_thousands_of_chunks_across_workers() {
+        let embedder = FakeEmbedder::new(true, Duration::from_millis(1));
+        let db = FakeVectorDb::default();
+        let config = EmbeddingPoolConfig { workers: 4, channel_depth: 4, batch_size: 25, upsert_concurrency: 2 };
+        let pool = EmbeddingWorkerPool::new(config, embedder.clone(), Arc::new(db.clone()));
+
+        for _ in 0..10 {
+            pool.submit(documents(200)).await.unwrap();
+        }
+        let stats = pool.shutdown(true).await;
+
+        assert_eq!(stats.upserted_documents, 2000);
+        assert_eq!(embedder.calls.load(Ordering::SeqCst), 80);
+        assert!(stats.throughput > 0.0);
+        assert!(stats.p95_embed_latency >= Duration::from_millis(1));
+        assert_eq!(db.documents.lock().unwrap().len(), 2000);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_without_drain_drops_queued_work() {
+        let embedder = FakeEmbedder::new(false, Duration::ZERO);
+        let db = FakeVectorDb::default();
+        let config = EmbeddingPoolConfig { workers: 1, channel_depth: 4, batch_size: 10, ..Default::default() };
+        let pool = EmbeddingWorkerPool::new(config, embedder, Arc::new(db.clone()));
+
+        pool.submit(documents(30)).await.unwrap();
+        let stats = tokio::time::timeout(Duration::from_secs(1), pool.shutdown(false)).await.unwrap();
+        assert_eq!(stats.upserted_documents, 0);
+        assert!(db.documents.lock().unwrap().is_empty());
+    }
+}
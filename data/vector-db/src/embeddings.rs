@@ -0,0 +1,263 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Embedding model interface shared by every backend
+#[async_trait]
+pub trait EmbeddingModel {
+    async fn embed(&self, text: &str) -> Result<Vec<f32>>;
+    async fn embed_batch(&self, texts: Vec<&str>) -> Result<Vec<Vec<f32>>>;
+    /// Length of every vector the model produces
+    fn dimension(&self) -> usize;
+    /// Identifier the model is registered under, e.g. `BAAI/bge-small-en-v1.5`
+    fn model_id(&self) -> &str;
+}
+
+pub type SharedEmbeddingModel = Arc<dyn EmbeddingModel + Send + Sync>;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum EmbeddingError {
+    #[error("Vector has {actual} dimensions but model '{model_id}' produces {expected}")]
+    DimensionMismatch { model_id: String, expected: usize, actual: usize },
+
+    #[error("Collection '{collection}' stores {collection_size}-dimensional vectors but model '{model_id}' produces {dimension}")]
+    CollectionMismatch { collection: String, collection_size: u64, model_id: String, dimension: usize },
+
+    #[error("No embedding model registered as '{0}'")]
+    UnknownModel(String),
+}
+
+impl From<EmbeddingError> for talkpp_errors::Error {
+    fn from(error: EmbeddingError) -> Self {
+        let kind = match &error {
+            EmbeddingError::UnknownModel(_) => talkpp_errors::ErrorKind::NotFound,
+            EmbeddingError::DimensionMismatch { .. } | EmbeddingError::CollectionMismatch { .. } => {
+                talkpp_errors::ErrorKind::InvalidInput
+            }
+        };
+        Self::transparent(kind, error)
+    }
+}
+
+/// Check that `vector` could have come from `model`
+pub fn validate_vector(model: &dyn EmbeddingModel, vector: &[f32]) -> Result<(), EmbeddingError> {
+    if vector.len() != model.dimension() {
+        return Err(EmbeddingError::DimensionMismatch {
+            model_id: model.model_id().to_string(),
+            expected: model.dimension(),
+            actual: vector.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Check that a collection of `collection_size`-dimensional vectors can hold `model`'s embeddings
+pub fn validate_collection(collection: &str, collection_size: u64, model: &dyn EmbeddingModel) -> Result<(), EmbeddingError> {
+    if collection_size != model.dimension() as u64 {
+        return Err(EmbeddingError::CollectionMismatch {
+            collection: collection.to_string(),
+            collection_size,
+            model_id: model.model_id().to_string(),
+            dimension: model.dimension(),
+        });
+    }
+    Ok(())
+}
+
+/// Embedding models by id
+#[derive(Default, Clone)]
+pub struct EmbeddingRegistry {
+    models: BTreeMap<String, SharedEmbeddingModel>,
+}
+
+impl EmbeddingRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_model(mut self, model: SharedEmbeddingModel) -> Self {
+        self.register(model);
+        self
+    }
+
+    /// Register `model` under its id, replacing any model already there
+    pub fn register(&mut self, model: SharedEmbeddingModel) {
+        self.models.insert(model.model_id().to_string(), model);
+    }
+
+    pub fn get(&self, model_id: &str) -> Result<SharedEmbeddingModel, EmbeddingError> {
+        self.models.get(model_id).cloned().ok_or_else(|| EmbeddingError::UnknownModel(model_id.to_string()))
+    }
+
+    pub fn model_ids(&self) -> impl Iterator<Item = &str> {
+        self.models.keys().map(String::as_str)
+    }
+}
+
+/// An embedding model from `talkpp-cuda-processor`, which reports neither an id nor a
+/// dimension, under the canonical interface. Vectors of the wrong length are rejected.
+#[cfg(feature = "cuda")]
+pub struct CudaEmbeddingAdapter {
+    model: Box<dyn talkpp_cuda_processor::EmbeddingModel + Send + Sync>,
+    model_id: String,
+    dimension: usize,
+}
+
+#[cfg(feature = "cuda")]
+impl CudaEmbeddingAdapter {
+    pub fn new(
+        model: Box<dyn talkpp_cuda_processor::EmbeddingModel + Send + Sync>,
+        model_id: impl Into<String>,
+        dimension: usize,
+    ) -> Self {
+        Self { model, model_id: model_id.into(), dimension }
+    }
+}
+
+#[cfg(feature = "cuda")]
+#[async_trait]
+impl EmbeddingModel for CudaEmbeddingAdapter {
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        let vector = self.model.embed(text).await?;
+        validate_vector(self, &vector)?;
+        Ok(vector)
+    }
+
+    async fn embed_batch(&self, texts: Vec<&str>) -> Result<Vec<Vec<f32>>> {
+        let vectors = self.model.embed_batch(texts.into_iter().map(str::to_string).collect()).await?;
+        for vector in &vectors {
+            validate_vector(self, vector)?;
+        }
+        Ok(vectors)
+    }
+
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    fn model_id(&self) -> &str {
+        &self.model_id
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Deterministic model: each component is derived from the text's bytes
+    pub(crate) struct HashEmbedder {
+        pub(crate) dimension: usize,
+    }
+
+    #[async_trait]
+    impl EmbeddingModel for HashEmbedder {
+        async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+            Ok((0..self.dimension)
+                .map(|i| text.bytes().map(|b| b as f32 * (i + 1) as f32).sum::<f32>() % 97.0)
+                .collect())
+        }
+
+        async fn embed_batch(&self, texts: Vec<&str>) -> Result<Vec<Vec<f32>>> {
+            let mut vectors = Vec::with_capacity(texts.len());
+            for text in texts {
+                vectors.push(self.embed(text).await?);
+            }
+            Ok(vectors)
+        }
+
+        fn dimension(&self) -> usize {
+            self.dimension
+        }
+
+        fn model_id(&self) -> &str {
+            "test/hash"
+        }
+    }
+
+    /// What every backend must honour: vectors of the declared dimension, and a batch
+    /// embedding each text the same way as a single call
+    pub(crate) async fn assert_model_contract(model: &dyn EmbeddingModel) {
+        let texts = vec!["deploy the site", "", "rotate credentials"];
+        let batch = model.embed_batch(texts.clone()).await.unwrap();
+        assert_eq!(batch.len(), texts.len());
+        for (text, vector) in texts.iter().zip(&batch) {
+            assert_eq!(vector.len(), model.dimension(), "{} on {:?}", model.model_id(), text);
+            assert_eq!(&model.embed(text).await.unwrap(), vector, "{} on {:?}", model.model_id(), text);
+        }
+        assert!(!model.model_id().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_registry_and_vector_validation() {
+        let small: SharedEmbeddingModel = Arc::new(HashEmbedder { dimension: 4 });
+        let registry = EmbeddingRegistry::new().with_model(small.clone());
+        assert_eq!(registry.model_ids().collect::<Vec<_>>(), vec!["test/hash"]);
+        assert_eq!(registry.get("test/hash").unwrap().dimension(), 4);
+        assert_eq!(registry.get("bge-large").err(), Some(EmbeddingError::UnknownModel("bge-large".to_string())));
+
+        assert!(validate_vector(small.as_ref(), &[0.0; 4]).is_ok());
+        let err = validate_vector(small.as_ref(), &[0.0; 768]).unwrap_err();
+        assert_eq!(err.to_string(), "Vector has 768 dimensions but model 'test/hash' produces 4");
+
+        let err = validate_collection("docs", 384, small.as_ref()).unwrap_err();
+        assert_eq!(err.to_string(), "Collection 'docs' stores 384-dimensional vectors but model 'test/hash' produces 4");
+        assert!(validate_collection("docs", 4, small.as_ref()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_backends_honour_the_model_contract() {
+        assert_model_contract(&HashEmbedder { dimension: 8 }).await;
+
+        #[cfg(feature = "cuda")]
+        {
+            struct Fixed;
+
+            #[async_trait]
+            impl talkpp_cuda_processor::EmbeddingModel for Fixed {
+                async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+                    Ok(vec![text.len() as f32; 3])
+                }
+
+                async fn embed_batch(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+                    Ok(texts.iter().map(|t| vec![t.len() as f32; 3]).collect())
+                }
+            }
+
+            assert_model_contract(&CudaEmbeddingAdapter::new(Box::new(Fixed), "test/fixed", 3)).await;
+            let misdeclared = CudaEmbeddingAdapter::new(Box::new(Fixed), "test/fixed", 384);
+            assert!(misdeclared.embed("text").await.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rag_system_rejects_mismatched_collection() {
+        let db = crate::testing::FakeVectorDb {
+            vector_size: 384,
+            model: Some(Arc::new(HashEmbedder { dimension: 768 })),
+            ..Default::default()
+        };
+        let err = crate::RagSystem::verified(Box::new(db.clone())).await.err().unwrap();
+        assert_eq!(
+            err.downcast_ref::<EmbeddingError>(),
+            Some(&EmbeddingError::CollectionMismatch {
+                collection: "fake".to_string(),
+                collection_size: 384,
+                model_id: "test/hash".to_string(),
+                dimension: 768,
+            })
+        );
+        assert_eq!(err.kind(), talkpp_errors::ErrorKind::InvalidInput);
+
+        let matching = crate::testing::FakeVectorDb { vector_size: 768, ..db };
+        assert!(crate::RagSystem::verified(Box::new(matching)).await.is_ok());
+    }
+
+    /// Downloads the model weights on first run
+    #[tokio::test]
+    #[ignore]
+    async fn test_fastembed_honours_the_model_contract() {
+        assert_model_contract(&crate::FastEmbedModel::new().await.unwrap()).await;
+    }
+}
@@ -0,0 +1,408 @@
+//! Measuring retrieval quality of a populated `RagSystem`
+//!
+//! A dataset is JSONL, one labeled query per line:
+//!
+//! ```json
+//! {"query": "access code for vault Orion-0001", "relevant_document_ids": ["vault-0001"]}
+//! {"query": "when do tidal turbines reverse", "relevant_text_spans": ["turbines reverse at slack tide"]}
+//! ```
+//!
+//! A retrieved chunk satisfies a relevant document id when the id is the chunk's own, or its
+//! `document_id` or `source_id` metadata; it satisfies a relevant text span when its
+//! normalized content contains the normalized span. A query's relevant items are its ids and
+//! spans together.
+//!
+//! [`evaluate`] runs every query under each [`RetrievalConfig`] and scores the top `k`
+//! chunks:
+//!
+//! - recall@k: share of the relevant items satisfied by some chunk
+//! - MRR: mean over queries of 1 / rank of the first chunk satisfying any item
+//! - nDCG@k: binary gain, where a chunk gains only for items no chunk above it satisfied, so
+//!   several chunks of one relevant document do not count twice
+//!
+//! [`SyntheticCorpus`] generates documents with planted facts and queries about them, so the
+//! harness runs without external data.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::path::Path;
+
+use crate::dedup::normalize_chunk;
+use crate::retrieval::{DEFAULT_KEYWORD_WEIGHT, DEFAULT_MMR_LAMBDA};
+use crate::{RagSystem, SearchResult, TenantContext};
+
+/// Metadata fields a relevant document id is matched against, besides the chunk's own id
+pub const DOCUMENT_ID_KEYS: [&str; 2] = ["document_id", "source_id"];
+
+/// Results scored per query, unless configured otherwise
+pub const DEFAULT_K: usize = 5;
+
+/// A query and what counts as relevant to it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EvalQuery {
+    pub query: String,
+    #[serde(default)]
+    pub relevant_document_ids: Vec<String>,
+    #[serde(default)]
+    pub relevant_text_spans: Vec<String>,
+}
+
+impl EvalQuery {
+    fn relevant_items(&self) -> usize {
+        self.relevant_document_ids.len() + self.relevant_text_spans.len()
+    }
+
+    /// Indices of the relevant items `result` satisfies, ids first, then spans
+    fn satisfied_by(&self, result: &SearchResult) -> Vec<usize> {
+        let document = &result.document;
+        let own_id = document.id.to_string();
+        let metadata_ids: Vec<&str> = DOCUMENT_ID_KEYS.iter()
+            .filter_map(|key| document.metadata.get(*key).and_then(|v| v.as_str()))
+            .collect();
+        let content = normalize_chunk(&document.content);
+
+        let ids = self.relevant_document_ids.iter()
+            .map(|id| *id == own_id || metadata_ids.contains(&id.as_str()));
+        let spans = self.relevant_text_spans.iter()
+            .map(|span| content.contains(&normalize_chunk(span)));
+        ids.chain(spans)
+            .enumerate()
+            .filter_map(|(i, satisfied)| satisfied.then_some(i))
+            .collect()
+    }
+}
+
+/// Labeled queries to evaluate retrieval with
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EvalDataset {
+    pub queries: Vec<EvalQuery>,
+}
+
+impl EvalDataset {
+    /// Parse JSONL, skipping blank lines. Fails on a line that is not a query, or a query
+    /// with nothing relevant to it, since it could only score zero.
+    pub fn from_jsonl(jsonl: &str) -> talkpp_errors::Result<Self> {
+        let mut queries = Vec::new();
+        for (number, line) in jsonl.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            let query: EvalQuery = serde_json::from_str(line).map_err(|e| {
+                talkpp_errors::Error::invalid_input(format!("dataset line {}: {}", number + 1, e))
+            })?;
+            if query.relevant_items() == 0 {
+                return Err(talkpp_errors::Error::invalid_input(format!(
+                    "dataset line {}: no relevant_document_ids or relevant_text_spans",
+                    number + 1
+                )));
+            }
+            queries.push(query);
+        }
+        Ok(Self { queries })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> talkpp_errors::Result<Self> {
+        let path = path.as_ref();
+        let jsonl = std::fs::read_to_string(path).map_err(|e| {
+            talkpp_errors::Error::invalid_input(format!("cannot read dataset {}", path.display())).with_source(e)
+        })?;
+        Self::from_jsonl(&jsonl)
+    }
+
+    pub fn to_jsonl(&self) -> String {
+        self.queries.iter()
+            .filter_map(|query| serde_json::to_string(query).ok())
+            .map(|line| line + "\n")
+            .collect()
+    }
+}
+
+/// How chunks are retrieved for a query
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "strategy", rename_all = "snake_case")]
+pub enum RetrievalStrategy {
+    /// `RagSystem::retrieve_context`
+    Dense,
+    /// `RagSystem::retrieve_hybrid`
+    Hybrid { keyword_weight: f32 },
+    /// `RagSystem::retrieve_mmr`
+    Mmr { lambda: f32 },
+}
+
+/// One retrieval setup to evaluate
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetrievalConfig {
+    /// Row label in the comparison table
+    pub name: String,
+    pub strategy: RetrievalStrategy,
+    /// Chunks retrieved and scored per query
+    pub k: usize,
+}
+
+impl RetrievalConfig {
+    pub fn new(name: impl Into<String>, strategy: RetrievalStrategy) -> Self {
+        Self { name: name.into(), strategy, k: DEFAULT_K }
+    }
+
+    pub fn with_k(mut self, k: usize) -> Self {
+        self.k = k;
+        self
+    }
+
+    /// Dense, hybrid and MMR retrieval with their default settings, each scoring `k` chunks
+    pub fn defaults(k: usize) -> Vec<Self> {
+        vec![
+            Self::new("dense", RetrievalStrategy::Dense).with_k(k),
+            Self::new("hybrid", RetrievalStrategy::Hybrid { keyword_weight: DEFAULT_KEYWORD_WEIGHT }).with_k(k),
+            Self::new("mmr", RetrievalStrategy::Mmr { lambda: DEFAULT_MMR_LAMBDA }).with_k(k),
+        ]
+    }
+
+    async fn retrieve(&self, rag: &RagSystem, tenant: &TenantContext, query: &str) -> talkpp_errors::Result<Vec<SearchResult>> {
+        match self.strategy {
+            RetrievalStrategy::Dense => rag.retrieve_context(tenant, query, self.k).await,
+            RetrievalStrategy::Hybrid { keyword_weight } => rag.retrieve_hybrid(tenant, query, self.k, keyword_weight).await,
+            RetrievalStrategy::Mmr { lambda } => rag.retrieve_mmr(tenant, query, self.k, lambda).await,
+        }
+    }
+}
+
+/// Scores of one query's results
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct QueryMetrics {
+    pub recall: f64,
+    pub reciprocal_rank: f64,
+    pub ndcg: f64,
+}
+
+impl QueryMetrics {
+    /// Scores for results where `satisfied[i]` lists the relevant items the `i`th result
+    /// satisfies, out of `relevant` items, cut off at `k` results
+    pub fn from_judgements(satisfied: &[Vec<usize>], relevant: usize, k: usize) -> Self {
+        if relevant == 0 {
+            return Self::default();
+        }
+        let top = &satisfied[..satisfied.len().min(k)];
+
+        let reciprocal_rank = top.iter()
+            .position(|items| !items.is_empty())
+            .map_or(0.0, |rank| 1.0 / (rank + 1) as f64);
+
+        let mut found = HashSet::new();
+        let mut dcg = 0.0;
+        for (rank, items) in top.iter().enumerate() {
+            let before = found.len();
+            found.extend(items.iter().copied());
+            if found.len() > before {
+                dcg += 1.0 / (rank as f64 + 2.0).log2();
+            }
+        }
+        let ideal: f64 = (0..relevant.min(k)).map(|rank| 1.0 / (rank as f64 + 2.0).log2()).sum();
+
+        Self {
+            recall: found.len() as f64 / relevant as f64,
+            reciprocal_rank,
+            ndcg: if ideal > 0.0 { dcg / ideal } else { 0.0 },
+        }
+    }
+}
+
+/// Scores of one query under one configuration
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryReport {
+    pub query: String,
+    #[serde(flatten)]
+    pub metrics: QueryMetrics,
+}
+
+/// Scores of one configuration, averaged over the dataset
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigReport {
+    pub config: RetrievalConfig,
+    pub recall_at_k: f64,
+    pub mrr: f64,
+    pub ndcg_at_k: f64,
+    pub queries: Vec<QueryReport>,
+}
+
+/// Scores of every configuration evaluated
+#[derive(Debug, Clone, Serialize)]
+pub struct EvalReport {
+    pub dataset_queries: usize,
+    pub configs: Vec<ConfigReport>,
+    pub evaluated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl EvalReport {
+    /// One row per configuration, for comparing them at a glance
+    pub fn to_table(&self) -> String {
+        let width = self.configs.iter().map(|c| c.config.name.len()).max().unwrap_or(0).max("CONFIG".len());
+        let mut table = format!("{:<width$}  {:>3}  {:>8}  {:>6}  {:>6}\n", "CONFIG", "K", "RECALL@K", "MRR", "NDCG@K");
+        for report in &self.configs {
+            let _ = writeln!(
+                table,
+                "{:<width$}  {:>3}  {:>8.3}  {:>6.3}  {:>6.3}",
+                report.config.name, report.config.k, report.recall_at_k, report.mrr, report.ndcg_at_k
+            );
+        }
+        table
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+/// Run every query of `dataset` against `tenant`'s documents in `rag` under each of `configs`
+pub async fn evaluate(
+    rag: &RagSystem,
+    tenant: &TenantContext,
+    dataset: &EvalDataset,
+    configs: &[RetrievalConfig],
+) -> talkpp_errors::Result<EvalReport> {
+    let mut reports = Vec::with_capacity(configs.len());
+    for config in configs {
+        let mut queries = Vec::with_capacity(dataset.queries.len());
+        for query in &dataset.queries {
+            let results = config.retrieve(rag, tenant, &query.query).await?;
+            let satisfied: Vec<Vec<usize>> = results.iter().map(|result| query.satisfied_by(result)).collect();
+            queries.push(QueryReport {
+                query: query.query.clone(),
+                metrics: QueryMetrics::from_judgements(&satisfied, query.relevant_items(), config.k),
+            });
+        }
+
+        let mean = |metric: fn(&QueryMetrics) -> f64| {
+            if queries.is_empty() {
+                0.0
+            } else {
+                queries.iter().map(|q| metric(&q.metrics)).sum::<f64>() / queries.len() as f64
+            }
+        };
+        reports.push(ConfigReport {
+            config: config.clone(),
+            recall_at_k: mean(|m| m.recall),
+            mrr: mean(|m| m.reciprocal_rank),
+            ndcg_at_k: mean(|m| m.ndcg),
+            queries,
+        });
+    }
+    Ok(EvalReport { dataset_queries: dataset.queries.len(), configs: reports, evaluated_at: chrono::Utc::now() })
+}
+
+const VAULT_NAMES: [&str; 8] = ["Orion", "Lyra", "Cygnus", "Draco", "Vela", "Carina", "Hydra", "Pavo"];
+
+const FILLER: [&str; 8] = [
+    "Maintenance crews rotate every second week.",
+    "The corridor lights dim automatically after midnight.",
+    "Visitors must sign the ledger at the front desk.",
+    "Backup generators are tested on the first Monday of each month.",
+    "Temperature in the storage wing is kept below eighteen degrees.",
+    "Inventory audits are filed with the regional office.",
+    "Fire doors close when the alarm panel is triggered.",
+    "Deliveries arrive through the loading bay on the east side.",
+];
+
+/// Documents each holding one planted fact, and a query for each fact
+#[derive(Debug, Clone)]
+pub struct SyntheticCorpus {
+    /// Document text and metadata, with `document_id` set
+    pub documents: Vec<(String, HashMap<String, serde_json::Value>)>,
+    pub dataset: EvalDataset,
+}
+
+impl SyntheticCorpus {
+    /// `documents` documents of filler sentences around a vault's access code, the same
+    /// for the same `seed`
+    pub fn generate(documents: usize, seed: u64) -> Self {
+        // Linear congruential generator, so corpora are reproducible without a rand dependency
+        let mut state = seed;
+        let mut next = move || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 33) as usize
+        };
+
+        let mut corpus = Self { documents: Vec::with_capacity(documents), dataset: EvalDataset::default() };
+        for i in 0..documents {
+            let vault = format!("{}-{:04}", VAULT_NAMES[i % VAULT_NAMES.len()], i);
+            let fact = format!("The access code for vault {} is {:04}.", vault, next() % 10_000);
+            let mut sentences: Vec<&str> = (0..4).map(|_| FILLER[next() % FILLER.len()]).collect();
+            let at = next() % (sentences.len() + 1);
+            sentences.insert(at, &fact);
+
+            let document_id = format!("vault-{:04}", i);
+            let metadata = HashMap::from([("document_id".to_string(), serde_json::json!(document_id))]);
+            corpus.documents.push((sentences.join(" "), metadata));
+            corpus.dataset.queries.push(EvalQuery {
+                query: format!("access code for vault {}", vault),
+                relevant_document_ids: vec![document_id],
+                relevant_text_spans: Vec::new(),
+            });
+        }
+        corpus
+    }
+
+    /// Add every document to `rag` as `tenant`'s, returning the chunks stored
+    pub async fn populate(&self, rag: &RagSystem, tenant: &TenantContext) -> talkpp_errors::Result<usize> {
+        let mut chunks = 0;
+        for (content, metadata) in &self.documents {
+            chunks += rag.add_document(tenant, content, metadata.clone()).await?.chunk_ids.len();
+        }
+        Ok(chunks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeVectorDb;
+
+    #[test]
+    fn test_metrics_match_hand_computed_values() {
+        // Two relevant items; results: miss, item 0, miss, item 1, item 0 again
+        let satisfied = vec![vec![], vec![0], vec![], vec![1], vec![0]];
+
+        let at_3 = QueryMetrics::from_judgements(&satisfied, 2, 3);
+        assert_eq!(at_3.recall, 0.5);
+        assert_eq!(at_3.reciprocal_rank, 0.5);
+        // DCG = 1/log2(3); ideal = 1 + 1/log2(3)
+        let expected = (1.0 / 3f64.log2()) / (1.0 + 1.0 / 3f64.log2());
+        assert!((at_3.ndcg - expected).abs() < 1e-9);
+
+        // The repeat of item 0 at rank 5 gains nothing
+        let at_5 = QueryMetrics::from_judgements(&satisfied, 2, 5);
+        assert_eq!(at_5.recall, 1.0);
+        let expected = (1.0 / 3f64.log2() + 1.0 / 5f64.log2()) / (1.0 + 1.0 / 3f64.log2());
+        assert!((at_5.ndcg - expected).abs() < 1e-9);
+
+        let none = QueryMetrics::from_judgements(&[vec![], vec![]], 1, 2);
+        assert_eq!(none, QueryMetrics::default());
+    }
+
+    #[test]
+    fn test_dataset_lines_need_something_relevant() {
+        let dataset = EvalDataset::from_jsonl(
+            "{\"query\": \"a\", \"relevant_document_ids\": [\"d1\"]}\n\n{\"query\": \"b\", \"relevant_text_spans\": [\"x y\"]}\n",
+        ).unwrap();
+        assert_eq!(dataset.queries.len(), 2);
+        assert_eq!(EvalDataset::from_jsonl(&dataset.to_jsonl()).unwrap(), dataset);
+
+        let err = EvalDataset::from_jsonl("{\"query\": \"c\"}").unwrap_err();
+        assert_eq!(err.kind(), talkpp_errors::ErrorKind::InvalidInput);
+    }
+
+    #[tokio::test]
+    async fn test_synthetic_corpus_is_found_by_every_strategy() {
+        let corpus = SyntheticCorpus::generate(12, 7);
+        assert_eq!(corpus.documents[3].0, SyntheticCorpus::generate(12, 7).documents[3].0);
+
+        let rag = RagSystem::new(Box::new(FakeVectorDb::default()));
+        let tenant = TenantContext::default_tenant("ada");
+        assert_eq!(corpus.populate(&rag, &tenant).await.unwrap(), 12);
+
+        let report = evaluate(&rag, &tenant, &corpus.dataset, &RetrievalConfig::defaults(3)).await.unwrap();
+        assert_eq!(report.dataset_queries, 12);
+        for config in &report.configs {
+            assert_eq!((config.recall_at_k, config.mrr, config.ndcg_at_k), (1.0, 1.0, 1.0), "{}", config.config.name);
+        }
+        assert!(report.to_table().lines().nth(2).unwrap().starts_with("hybrid"));
+    }
+}
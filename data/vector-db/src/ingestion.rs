@@ -0,0 +1,525 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::{RagSystem, TenantContext, VectorDocument};
+
+/// A document as produced by an external-service sync, such as a Gmail message or a
+/// Drive file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SourceDocument {
+    pub source_service: String,
+    pub source_id: String,
+    pub title: String,
+    pub body: String,
+    /// Copied onto every stored chunk. A `mime_type` entry selects how the body is
+    /// turned into text.
+    pub metadata: HashMap<String, serde_json::Value>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl SourceDocument {
+    fn mime_type(&self) -> Option<&str> {
+        self.metadata.get("mime_type").and_then(|v| v.as_str())
+    }
+}
+
+/// What one sync run changed in a service, as recorded in the sync engine's change journal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncChanges {
+    pub source_service: String,
+    pub changes: Vec<SourceChange>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SourceChange {
+    Upserted { source_id: String },
+    Deleted { source_id: String },
+}
+
+/// Loads the current version of a changed document from its service
+#[async_trait]
+pub trait DocumentFetcher: Send + Sync {
+    /// `None` when the document no longer exists
+    async fn fetch(&self, source_service: &str, source_id: &str) -> Result<Option<SourceDocument>>;
+}
+
+/// Turns binary or structured bodies (office documents, PDFs) into plain text
+pub trait TextExtractor: Send + Sync {
+    fn handles(&self, mime_type: &str) -> bool;
+    fn extract(&self, document: &SourceDocument) -> Result<String>;
+}
+
+/// What the ledger remembers about an ingested document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    /// SHA-256 of the normalized text
+    pub content_hash: String,
+    pub chunk_ids: Vec<Uuid>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    pub ingested_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Ingested documents by `(source_service, source_id)`. Serializable so it can be saved
+/// between runs and handed back with `IngestionPipeline::with_ledger`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IngestionLedger {
+    entries: HashMap<String, HashMap<String, LedgerEntry>>,
+}
+
+impl IngestionLedger {
+    pub fn get(&self, source_service: &str, source_id: &str) -> Option<&LedgerEntry> {
+        self.entries.get(source_service)?.get(source_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.values().map(HashMap::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn insert(&mut self, source_service: &str, source_id: &str, entry: LedgerEntry) {
+        self.entries.entry(source_service.to_string()).or_default().insert(source_id.to_string(), entry);
+    }
+
+    fn remove(&mut self, source_service: &str, source_id: &str) -> Option<LedgerEntry> {
+        self.entries.get_mut(source_service)?.remove(source_id)
+    }
+}
+
+/// What happened to one document
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IngestionOutcome {
+    Added,
+    Updated,
+    /// Same content as last time; nothing was re-embedded
+    Unchanged,
+    /// Older than the version already ingested
+    Stale,
+    /// No text left after normalization
+    Empty,
+    Deleted,
+}
+
+/// Totals for a batch of documents
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IngestionReport {
+    pub added: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub skipped: usize,
+    pub deleted: usize,
+    /// `service/id` and error of each document that could not be ingested
+    pub failures: Vec<(String, String)>,
+}
+
+impl IngestionReport {
+    fn record(&mut self, outcome: IngestionOutcome) {
+        match outcome {
+            IngestionOutcome::Added => self.added += 1,
+            IngestionOutcome::Updated => self.updated += 1,
+            IngestionOutcome::Unchanged => self.unchanged += 1,
+            IngestionOutcome::Stale | IngestionOutcome::Empty => self.skipped += 1,
+            IngestionOutcome::Deleted => self.deleted += 1,
+        }
+    }
+
+    fn record_failure(&mut self, source_service: &str, source_id: &str, error: anyhow::Error) {
+        warn!("Failed to ingest {}/{}: {}", source_service, source_id, error);
+        self.failures.push((format!("{}/{}", source_service, source_id), error.to_string()));
+    }
+}
+
+/// Feeds synced documents into a `RagSystem` as one tenant's, re-embedding only those
+/// whose text changed
+pub struct IngestionPipeline {
+    rag: Arc<RagSystem>,
+    tenant: TenantContext,
+    ledger: RwLock<IngestionLedger>,
+    extractors: Vec<Box<dyn TextExtractor>>,
+}
+
+impl IngestionPipeline {
+    pub fn new(rag: Arc<RagSystem>, tenant: TenantContext) -> Self {
+        Self {
+            rag,
+            tenant,
+            ledger: RwLock::new(IngestionLedger::default()),
+            extractors: Vec::new(),
+        }
+    }
+
+    /// Start from a ledger saved by an earlier run
+    pub fn with_ledger(mut self, ledger: IngestionLedger) -> Self {
+        self.ledger = RwLock::new(ledger);
+        self
+    }
+
+    /// Extract text from the mime types `extractor` handles
+    pub fn with_extractor(mut self, extractor: Box<dyn TextExtractor>) -> Self {
+        self.extractors.push(extractor);
+        self
+    }
+
+    /// Snapshot of the ledger, for saving
+    pub async fn ledger(&self) -> IngestionLedger {
+        self.ledger.read().await.clone()
+    }
+
+    /// Ingest one document: add it, update it in place, or skip it when nothing changed
+    pub async fn ingest(&self, document: SourceDocument) -> Result<IngestionOutcome> {
+        let text = self.normalize(&document)?;
+        if text.is_empty() {
+            return Ok(IngestionOutcome::Empty);
+        }
+        let content_hash = format!("{:x}", Sha256::digest(text.as_bytes()));
+
+        let mut ledger = self.ledger.write().await;
+        let previous = ledger.get(&document.source_service, &document.source_id).cloned();
+        if let Some(previous) = &previous {
+            if previous.content_hash == content_hash {
+                return Ok(IngestionOutcome::Unchanged);
+            }
+            if previous.updated_at > document.updated_at {
+                return Ok(IngestionOutcome::Stale);
+            }
+        }
+
+        let metadata = chunk_metadata(&document);
+        let (chunk_ids, outcome) = match &previous {
+            Some(previous) => (
+                self.rag.update_document(&self.tenant, &previous.chunk_ids, &text, metadata).await?,
+                IngestionOutcome::Updated,
+            ),
+            None => (self.rag.add_document(&self.tenant, &text, metadata).await?.chunk_ids, IngestionOutcome::Added),
+        };
+        debug!("{:?} {}/{} as {} chunks", outcome, document.source_service, document.source_id, chunk_ids.len());
+
+        ledger.insert(&document.source_service, &document.source_id, LedgerEntry {
+            content_hash,
+            chunk_ids,
+            updated_at: document.updated_at,
+            ingested_at: chrono::Utc::now(),
+        });
+        Ok(outcome)
+    }
+
+    /// Remove a document and its chunks
+    pub async fn remove(&self, source_service: &str, source_id: &str) -> Result<IngestionOutcome> {
+        let mut ledger = self.ledger.write().await;
+        match ledger.get(source_service, source_id) {
+            Some(entry) => {
+                self.rag.remove_document(&self.tenant, &entry.chunk_ids).await?;
+                ledger.remove(source_service, source_id);
+                Ok(IngestionOutcome::Deleted)
+            }
+            None => Ok(IngestionOutcome::Unchanged),
+        }
+    }
+
+    /// Ingest every document of a stream; a failing document is reported, not fatal
+    pub async fn ingest_stream(&self, documents: impl Stream<Item = SourceDocument>) -> IngestionReport {
+        let mut report = IngestionReport::default();
+        let mut documents = std::pin::pin!(documents);
+        while let Some(document) = documents.next().await {
+            let (service, id) = (document.source_service.clone(), document.source_id.clone());
+            match self.ingest(document).await {
+                Ok(outcome) => report.record(outcome),
+                Err(e) => report.record_failure(&service, &id, e),
+            }
+        }
+        report
+    }
+
+    /// Apply a sync run's changes, fetching each upserted document through `fetcher`
+    pub async fn ingest_sync_result(&self, sync_result: &SyncChanges, fetcher: &dyn DocumentFetcher) -> IngestionReport {
+        let service = sync_result.source_service.as_str();
+        let mut report = IngestionReport::default();
+        for change in &sync_result.changes {
+            let (id, outcome) = match change {
+                SourceChange::Upserted { source_id } => {
+                    let outcome = match fetcher.fetch(service, source_id).await {
+                        Ok(Some(document)) => self.ingest(document).await,
+                        Ok(None) => self.remove(service, source_id).await,
+                        Err(e) => Err(e),
+                    };
+                    (source_id, outcome)
+                }
+                SourceChange::Deleted { source_id } => (source_id, self.remove(service, source_id).await),
+            };
+            match outcome {
+                Ok(outcome) => report.record(outcome),
+                Err(e) => report.record_failure(service, id, e),
+            }
+        }
+        report
+    }
+
+    fn normalize(&self, document: &SourceDocument) -> Result<String> {
+        plain_text(document, &self.extractors)
+    }
+}
+
+/// The documents a sync run upserted, as whole unchunked documents for
+/// `VectorDatabase::upsert_stream`. Each is fetched through `fetcher` only when the
+/// stream is read. Deletions, documents that are gone or empty, and documents that fail
+/// to load are skipped, the failures with a warning.
+pub fn sync_document_stream<'a>(changes: &'a SyncChanges, fetcher: &'a dyn DocumentFetcher) -> BoxStream<'a, VectorDocument> {
+    let service = changes.source_service.as_str();
+    futures::stream::iter(&changes.changes)
+        .filter_map(move |change| async move {
+            let SourceChange::Upserted { source_id } = change else {
+                return None;
+            };
+            let document = match fetcher.fetch(service, source_id).await {
+                Ok(document) => document?,
+                Err(e) => {
+                    warn!("Failed to fetch {}/{} for upsert: {}", service, source_id, e);
+                    return None;
+                }
+            };
+            let content = plain_text(&document, &[]).ok().filter(|text| !text.is_empty())?;
+            Some(VectorDocument {
+                id: source_document_id(service, source_id),
+                content,
+                metadata: chunk_metadata(&document),
+                vector: None,
+                created_at: chrono::Utc::now(),
+                updated_at: document.updated_at,
+            })
+        })
+        .boxed()
+}
+
+/// Stable id of a synced document, so upserting it again replaces the stored copy
+pub fn source_document_id(source_service: &str, source_id: &str) -> Uuid {
+    let digest = Sha256::digest(format!("{}/{}", source_service, source_id).as_bytes());
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    Uuid::from_bytes(bytes)
+}
+
+/// Title and body as plain text with whitespace collapsed
+fn plain_text(document: &SourceDocument, extractors: &[Box<dyn TextExtractor>]) -> Result<String> {
+    let body = match document.mime_type() {
+        Some(mime) if mime.starts_with("text/html") => strip_html(&document.body),
+        Some(mime) => match extractors.iter().find(|e| e.handles(mime)) {
+            Some(extractor) => extractor.extract(document)?,
+            None => document.body.clone(),
+        },
+        None if looks_like_html(&document.body) => strip_html(&document.body),
+        None => document.body.clone(),
+    };
+    let text = format!("{}\n{}", document.title, body);
+    Ok(text.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+fn chunk_metadata(document: &SourceDocument) -> HashMap<String, serde_json::Value> {
+    let mut metadata = document.metadata.clone();
+    metadata.insert("source_service".to_string(), serde_json::json!(document.source_service));
+    metadata.insert("source_id".to_string(), serde_json::json!(document.source_id));
+    metadata.insert("title".to_string(), serde_json::json!(document.title));
+    metadata.insert("updated_at".to_string(), serde_json::json!(document.updated_at));
+    metadata
+}
+
+fn looks_like_html(body: &str) -> bool {
+    let body = body.trim_start().to_ascii_lowercase();
+    body.starts_with("<!doctype html") || body.starts_with("<html") || body.starts_with("<div") || body.starts_with("<p")
+}
+
+/// Text content of an HTML fragment: tags removed, script and style bodies dropped and
+/// the common entities decoded
+fn strip_html(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('>') else {
+            rest = "";
+            break;
+        };
+        let tag = rest[start + 1..start + end].trim().to_ascii_lowercase();
+        rest = &rest[start + end + 1..];
+
+        for skipped in ["script", "style"] {
+            if tag == skipped || tag.starts_with(&format!("{} ", skipped)) {
+                let close = format!("</{}", skipped);
+                rest = match rest.to_ascii_lowercase().find(&close) {
+                    Some(at) => &rest[at..],
+                    None => "",
+                };
+            }
+        }
+        // Keep words on either side of a tag apart
+        text.push(' ');
+    }
+    text.push_str(rest);
+
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeVectorDb;
+
+    fn pipeline() -> (IngestionPipeline, FakeVectorDb) {
+        let db = FakeVectorDb::default();
+        let rag = Arc::new(RagSystem::new(Box::new(db.clone())));
+        (IngestionPipeline::new(rag, TenantContext::default_tenant("ada")), db)
+    }
+
+    fn email(id: &str, body: &str, minutes_ago: i64) -> SourceDocument {
+        SourceDocument {
+            source_service: "gmail".to_string(),
+            source_id: id.to_string(),
+            title: "Release notes".to_string(),
+            body: body.to_string(),
+            metadata: HashMap::from([("mime_type".to_string(), serde_json::json!("text/html"))]),
+            updated_at: chrono::Utc::now() - chrono::Duration::minutes(minutes_ago),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unchanged_documents_are_not_re_embedded() {
+        let (pipeline, db) = pipeline();
+        let html = "<html><style>p { color: red }</style><p>Version&nbsp;2 &amp; friends</p><br>shipped</html>";
+
+        let mut blank = email("m2", "<p> &nbsp; </p>", 5);
+        blank.title.clear();
+
+        let documents = vec![email("m1", html, 10), email("m1", html, 5), blank];
+        let report = pipeline.ingest_stream(futures::stream::iter(documents)).await;
+        assert_eq!((report.added, report.unchanged, report.skipped), (1, 1, 1));
+        assert_eq!(*db.upserts.lock().unwrap(), 1);
+
+        let entry = pipeline.ledger().await.get("gmail", "m1").unwrap().clone();
+        let stored = db.documents.lock().unwrap()[&entry.chunk_ids[0]].clone();
+        assert_eq!(stored.content, "Release notes Version 2 & friends shipped");
+        assert_eq!(stored.metadata["source_id"], "m1");
+        assert_eq!(stored.metadata["mime_type"], "text/html");
+    }
+
+    #[tokio::test]
+    async fn test_changed_documents_are_updated_in_place() {
+        let (pipeline, db) = pipeline();
+        let long_body = (0..400).map(|i| format!("word{}", i)).collect::<Vec<_>>().join(" ");
+
+        assert_eq!(pipeline.ingest(email("m1", &long_body, 10)).await.unwrap(), IngestionOutcome::Added);
+        let before = pipeline.ledger().await.get("gmail", "m1").unwrap().clone();
+        assert!(before.chunk_ids.len() > 1);
+
+        assert_eq!(pipeline.ingest(email("m1", "<p>Short now</p>", 5)).await.unwrap(), IngestionOutcome::Updated);
+        let after = pipeline.ledger().await.get("gmail", "m1").unwrap().clone();
+        assert_eq!(after.chunk_ids, vec![before.chunk_ids[0]]);
+        assert_ne!(after.content_hash, before.content_hash);
+
+        let documents = db.documents.lock().unwrap().clone();
+        assert_eq!(documents.len(), 1);
+        assert_eq!(documents[&before.chunk_ids[0]].content, "Release notes Short now");
+
+        // An older copy arriving late does not overwrite the newer one
+        assert_eq!(pipeline.ingest(email("m1", "<p>Old</p>", 60)).await.unwrap(), IngestionOutcome::Stale);
+    }
+
+    struct FakeFetcher {
+        documents: HashMap<String, SourceDocument>,
+    }
+
+    #[async_trait]
+    impl DocumentFetcher for FakeFetcher {
+        async fn fetch(&self, source_service: &str, source_id: &str) -> Result<Option<SourceDocument>> {
+            if source_id == "broken" {
+                return Err(anyhow::anyhow!("{} unavailable", source_service));
+            }
+            Ok(self.documents.get(source_id).cloned())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sync_result_drives_adds_and_deletes() {
+        let (pipeline, db) = pipeline();
+        pipeline.ingest(email("gone", "<p>Removed upstream</p>", 10)).await.unwrap();
+        pipeline.ingest(email("vanished", "<p>Missing upstream</p>", 10)).await.unwrap();
+
+        let fetcher = FakeFetcher {
+            documents: HashMap::from([("new".to_string(), email("new", "<p>Fresh</p>", 1))]),
+        };
+        let sync = SyncChanges {
+            source_service: "gmail".to_string(),
+            changes: vec![
+                SourceChange::Upserted { source_id: "new".to_string() },
+                SourceChange::Deleted { source_id: "gone".to_string() },
+                SourceChange::Upserted { source_id: "vanished".to_string() },
+                SourceChange::Upserted { source_id: "broken".to_string() },
+            ],
+        };
+
+        let report = pipeline.ingest_sync_result(&sync, &fetcher).await;
+        assert_eq!((report.added, report.deleted), (1, 2));
+        assert_eq!(report.failures, vec![("gmail/broken".to_string(), "gmail unavailable".to_string())]);
+
+        let ledger = pipeline.ledger().await;
+        assert_eq!(ledger.len(), 1);
+        assert!(ledger.get("gmail", "new").is_some());
+        assert_eq!(db.documents.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_sync_document_stream_feeds_upsert_stream() {
+        use crate::{UpsertOptions, VectorDatabase};
+
+        let fetcher = FakeFetcher {
+            documents: HashMap::from([
+                ("a".to_string(), email("a", "<p>First</p>", 2)),
+                ("b".to_string(), email("b", "<p>Second</p>", 1)),
+            ]),
+        };
+        let sync = SyncChanges {
+            source_service: "gmail".to_string(),
+            changes: vec![
+                SourceChange::Upserted { source_id: "a".to_string() },
+                SourceChange::Deleted { source_id: "old".to_string() },
+                SourceChange::Upserted { source_id: "broken".to_string() },
+                SourceChange::Upserted { source_id: "missing".to_string() },
+                SourceChange::Upserted { source_id: "b".to_string() },
+            ],
+        };
+
+        let db = FakeVectorDb::default();
+        for _ in 0..2 {
+            let report = db.upsert_stream(sync_document_stream(&sync, &fetcher), UpsertOptions::default()).await.unwrap();
+            assert_eq!(report.upserted, 2);
+        }
+
+        // Upserting the same changes twice keeps one copy of each document
+        let documents = db.documents.lock().unwrap();
+        assert_eq!(documents.len(), 2);
+        let first = &documents[&source_document_id("gmail", "a")];
+        assert_eq!(first.content, "Release notes First");
+        assert_eq!(first.metadata["source_id"], "a");
+    }
+
+    #[test]
+    fn test_strip_html_drops_markup_and_scripts() {
+        let html = "<div>Hi <b>there</b><script type=\"text/javascript\">alert('x')</script></div>&lt;ok&gt;";
+        let text = strip_html(html);
+        assert_eq!(text.split_whitespace().collect::<Vec<_>>(), vec!["Hi", "there", "<ok>"]);
+    }
+}
@@ -0,0 +1,392 @@
+//! Moving a collection to a new embedding model
+//!
+//! Vectors from one model mean nothing to another, so a collection cannot be switched to a
+//! new model in place. [`migrate_collection`] scrolls through the source collection,
+//! re-embeds each point's `content` payload with the new model, and writes the points, with
+//! their ids and payloads, into a new collection laid out for the new vectors. The source is
+//! left untouched and keeps serving until the switch.
+//!
+//! Before switching, the destination's point count is checked against the source's, and a
+//! sample of migrated documents is searched for by their own content: recall is the share
+//! found among the nearest results. Only then is the target's alias, if any, pointed at the
+//! destination, in one step so searches never see a missing collection.
+//!
+//! Qdrant cannot rename collections. To switch without an alias, point
+//! `VectorDbConfig::collection_name` at the destination; to keep the old name, delete the
+//! source once the destination is serving and create an alias under the source's name.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tracing::{info, warn};
+
+use crate::backup::{BackupClient, BackupPoint, CollectionParams};
+use crate::embeddings::{validate_collection, validate_vector, EmbeddingModel};
+
+/// Payload field holding the text a point was embedded from
+pub const CONTENT_PAYLOAD_KEY: &str = "content";
+
+/// Migrated documents searched for to check recall, unless configured otherwise
+pub const DEFAULT_RECALL_SAMPLES: usize = 10;
+
+/// Nearest results a sampled document must be among, unless configured otherwise
+pub const DEFAULT_RECALL_TOP_K: u64 = 5;
+
+/// Recall below which the switch is refused, unless configured otherwise
+pub const DEFAULT_MIN_RECALL: f32 = 0.8;
+
+/// Collection operations a migration needs beyond those of a backup
+#[async_trait]
+pub trait MigrationClient: BackupClient {
+    /// Ids of the `limit` points of collection `name` nearest `vector`, nearest first
+    async fn search_ids(&self, name: &str, vector: Vec<f32>, limit: u64) -> Result<Vec<String>>;
+
+    async fn count_points(&self, name: &str) -> Result<u64>;
+
+    /// Point `alias` at `collection`, taking it off any collection it named before, in one
+    /// step
+    async fn swap_alias(&self, alias: &str, collection: &str) -> Result<()>;
+}
+
+/// The collection a migration writes, and what must hold before it is switched to
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MigrationTarget {
+    /// Collection to create; it must not exist yet
+    pub collection: String,
+    pub params: CollectionParams,
+    /// Alias to point at the destination once it has been verified
+    pub alias: Option<String>,
+    pub recall_samples: usize,
+    pub recall_top_k: u64,
+    pub min_recall: f32,
+}
+
+impl MigrationTarget {
+    pub fn new(collection: impl Into<String>, params: CollectionParams) -> Self {
+        Self {
+            collection: collection.into(),
+            params,
+            alias: None,
+            recall_samples: DEFAULT_RECALL_SAMPLES,
+            recall_top_k: DEFAULT_RECALL_TOP_K,
+            min_recall: DEFAULT_MIN_RECALL,
+        }
+    }
+
+    pub fn with_alias(mut self, alias: impl Into<String>) -> Self {
+        self.alias = Some(alias.into());
+        self
+    }
+
+    pub fn with_recall_check(mut self, samples: usize, top_k: u64, min_recall: f32) -> Self {
+        self.recall_samples = samples;
+        self.recall_top_k = top_k;
+        self.min_recall = min_recall;
+        self
+    }
+}
+
+/// Reported after each batch a migration writes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MigrationProgress {
+    /// Points read from the source so far
+    pub points_scanned: u64,
+    pub migrated: u64,
+    /// Points left out for having no content to re-embed
+    pub skipped: u64,
+    pub elapsed: Duration,
+}
+
+impl MigrationProgress {
+    /// Source points processed per second so far
+    pub fn points_per_second(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 { self.points_scanned as f64 / seconds } else { 0.0 }
+    }
+}
+
+/// What a migration wrote and how it checked out
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MigrationReport {
+    pub source: String,
+    pub destination: String,
+    pub migrated: u64,
+    /// Ids of source points with no content payload, which are not in the destination
+    pub skipped: Vec<String>,
+    /// Share of sampled documents found by their own content, `None` if none were sampled
+    pub recall: Option<f32>,
+    /// Alias now pointing at the destination
+    pub alias: Option<String>,
+    pub elapsed: Duration,
+}
+
+#[derive(Debug, Error)]
+pub enum MigrationError {
+    #[error("Collection '{0}' does not exist")]
+    MissingCollection(String),
+
+    #[error("Collection '{0}' already exists; migrations write a new collection")]
+    DestinationExists(String),
+
+    #[error("Collection '{collection}' holds {actual} points, expected {expected}")]
+    CountMismatch { collection: String, expected: u64, actual: u64 },
+
+    #[error("Recall on '{collection}' is {recall:.2}, below the required {min_recall:.2}")]
+    RecallTooLow { collection: String, recall: f32, min_recall: f32 },
+}
+
+impl From<MigrationError> for talkpp_errors::Error {
+    fn from(error: MigrationError) -> Self {
+        let kind = match &error {
+            MigrationError::MissingCollection(_) => talkpp_errors::ErrorKind::NotFound,
+            MigrationError::DestinationExists(_) => talkpp_errors::ErrorKind::Conflict,
+            MigrationError::CountMismatch { .. } | MigrationError::RecallTooLow { .. } => {
+                talkpp_errors::ErrorKind::Internal
+            }
+        };
+        Self::transparent(kind, error)
+    }
+}
+
+/// Copy collection `source` into `target.collection`, re-embedding every point's content
+/// with `re_embedder` in batches of `batch_size`, then verify the copy and swap the target's
+/// alias to it. Points without content are skipped and reported, not failed on.
+pub async fn migrate_collection(
+    client: &dyn MigrationClient,
+    source: &str,
+    target: &MigrationTarget,
+    re_embedder: &dyn EmbeddingModel,
+    batch_size: u32,
+    mut on_progress: impl FnMut(MigrationProgress) + Send,
+) -> Result<MigrationReport> {
+    let started = Instant::now();
+    let destination = target.collection.as_str();
+    validate_collection(destination, target.params.vector_size, re_embedder)?;
+    if client.collection_params(source).await?.is_none() {
+        return Err(MigrationError::MissingCollection(source.to_string()).into());
+    }
+    if client.collection_params(destination).await?.is_some() {
+        return Err(MigrationError::DestinationExists(destination.to_string()).into());
+    }
+    client.create_collection_with(destination, &target.params).await?;
+
+    let mut progress = MigrationProgress { points_scanned: 0, migrated: 0, skipped: 0, elapsed: Duration::ZERO };
+    let mut skipped = Vec::new();
+    let mut samples: Vec<(String, String)> = Vec::new();
+    let mut offset = None;
+    loop {
+        let page = client.scroll(source, offset, batch_size.max(1)).await?;
+        progress.points_scanned += page.points.len() as u64;
+
+        let (documents, missing): (Vec<BackupPoint>, Vec<BackupPoint>) = page.points
+            .into_iter()
+            .partition(|point| content(point).is_some());
+        for point in missing {
+            warn!("Point {} in '{}' has no '{}' payload to re-embed; skipping it", point.id, source, CONTENT_PAYLOAD_KEY);
+            skipped.push(point.id);
+        }
+
+        if !documents.is_empty() {
+            let texts: Vec<&str> = documents.iter().filter_map(content).collect();
+            let vectors = re_embedder.embed_batch(texts).await?;
+            for vector in &vectors {
+                validate_vector(re_embedder, vector)?;
+            }
+            let migrated: Vec<BackupPoint> = documents
+                .into_iter()
+                .zip(vectors)
+                .map(|(point, vector)| BackupPoint { vector, ..point })
+                .collect();
+            for point in migrated.iter().take(target.recall_samples.saturating_sub(samples.len())) {
+                samples.push((point.id.clone(), content(point).unwrap_or_default().to_string()));
+            }
+            progress.migrated += migrated.len() as u64;
+            client.upsert_points(destination, migrated).await?;
+        }
+
+        progress.skipped = skipped.len() as u64;
+        progress.elapsed = started.elapsed();
+        on_progress(progress);
+        match page.next_offset {
+            Some(next) => offset = Some(next),
+            None => break,
+        }
+    }
+
+    let actual = client.count_points(destination).await?;
+    if actual != progress.migrated {
+        return Err(MigrationError::CountMismatch {
+            collection: destination.to_string(),
+            expected: progress.migrated,
+            actual,
+        }.into());
+    }
+
+    let recall = spot_check_recall(client, destination, target, re_embedder, &samples).await?;
+    if let Some(recall) = recall {
+        if recall < target.min_recall {
+            return Err(MigrationError::RecallTooLow {
+                collection: destination.to_string(),
+                recall,
+                min_recall: target.min_recall,
+            }.into());
+        }
+    }
+
+    if let Some(alias) = &target.alias {
+        client.swap_alias(alias, destination).await?;
+        info!("Alias '{}' now points at '{}'", alias, destination);
+    }
+
+    info!(
+        "Migrated '{}' to '{}' with {} ({} points, {} skipped)",
+        source, destination, re_embedder.model_id(), progress.migrated, skipped.len()
+    );
+    Ok(MigrationReport {
+        source: source.to_string(),
+        destination: destination.to_string(),
+        migrated: progress.migrated,
+        skipped,
+        recall,
+        alias: target.alias.clone(),
+        elapsed: started.elapsed(),
+    })
+}
+
+fn content(point: &BackupPoint) -> Option<&str> {
+    point.payload.get(CONTENT_PAYLOAD_KEY).and_then(serde_json::Value::as_str)
+}
+
+/// Share of `samples` that appear among the nearest results for their own content
+async fn spot_check_recall(
+    client: &dyn MigrationClient,
+    collection: &str,
+    target: &MigrationTarget,
+    re_embedder: &dyn EmbeddingModel,
+    samples: &[(String, String)],
+) -> Result<Option<f32>> {
+    if samples.is_empty() {
+        return Ok(None);
+    }
+    let mut found = 0;
+    for (id, text) in samples {
+        let vector = re_embedder.embed(text).await?;
+        if client.search_ids(collection, vector, target.recall_top_k).await?.contains(id) {
+            found += 1;
+        }
+    }
+    Ok(Some(found as f32 / samples.len() as f32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backup::tests::{params, MockClient};
+    use crate::embeddings::tests::HashEmbedder;
+    use crate::DistanceMetric;
+    use std::collections::HashMap;
+
+    #[async_trait]
+    impl MigrationClient for MockClient {
+        async fn search_ids(&self, name: &str, vector: Vec<f32>, limit: u64) -> Result<Vec<String>> {
+            let cosine = |a: &[f32], b: &[f32]| {
+                let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+                let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+                dot / (norm(a) * norm(b)).max(f32::EPSILON)
+            };
+            let mut scored: Vec<(f32, String)> = self.points(name)
+                .into_iter()
+                .map(|point| (cosine(&vector, &point.vector), point.id))
+                .collect();
+            scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+            Ok(scored.into_iter().take(limit as usize).map(|(_, id)| id).collect())
+        }
+
+        async fn count_points(&self, name: &str) -> Result<u64> {
+            Ok(self.points(name).len() as u64)
+        }
+
+        async fn swap_alias(&self, alias: &str, collection: &str) -> Result<()> {
+            self.aliases.lock().unwrap().insert(alias.to_string(), collection.to_string());
+            Ok(())
+        }
+    }
+
+    fn document(id: usize, content: Option<&str>) -> BackupPoint {
+        let mut payload = HashMap::new();
+        payload.insert("source".to_string(), serde_json::json!("handbook.md"));
+        if let Some(content) = content {
+            payload.insert(CONTENT_PAYLOAD_KEY.to_string(), serde_json::json!(content));
+        }
+        BackupPoint { id: format!("{:04}", id), vector: vec![0.5; 384], payload }
+    }
+
+    async fn seeded_client() -> MockClient {
+        let client = MockClient::default();
+        client.create_collection_with("docs_v1", &params(384)).await.unwrap();
+        let mut points: Vec<BackupPoint> = (0..25)
+            .map(|i| document(i, Some(&format!("Handbook section {} on {}", i, ["leave", "expenses", "security"][i % 3]))))
+            .collect();
+        points.push(document(25, None));
+        points.push(document(26, None));
+        client.upsert_points("docs_v1", points).await.unwrap();
+        client.upserted_batches.lock().unwrap().clear();
+        client
+    }
+
+    #[tokio::test]
+    async fn test_migrates_to_a_larger_model_and_swaps_the_alias() {
+        let client = seeded_client().await;
+        let model = HashEmbedder { dimension: 768 };
+        let target = MigrationTarget::new("docs_v2", params(768)).with_alias("docs");
+
+        let mut updates = Vec::new();
+        let report = migrate_collection(&client, "docs_v1", &target, &model, 10, |progress| updates.push(progress))
+            .await
+            .unwrap();
+
+        assert_eq!(report.migrated, 25);
+        assert_eq!(report.skipped, vec!["0025".to_string(), "0026".to_string()]);
+        assert_eq!(report.recall, Some(1.0));
+        assert_eq!(report.alias.as_deref(), Some("docs"));
+        assert_eq!(client.aliases.lock().unwrap()["docs"], "docs_v2");
+
+        // Progress after every page of ten, skips counted as they are found
+        let counts: Vec<(u64, u64, u64)> = updates.iter().map(|p| (p.points_scanned, p.migrated, p.skipped)).collect();
+        assert_eq!(counts, vec![(10, 10, 0), (20, 20, 0), (27, 25, 2)]);
+        assert_eq!(*client.upserted_batches.lock().unwrap(), vec![10, 10, 5]);
+
+        let migrated = client.points("docs_v2");
+        let expected = model.embed("Handbook section 7 on expenses").await.unwrap();
+        let section = migrated.iter().find(|p| p.id == "0007").unwrap();
+        assert_eq!(section.vector, expected);
+        assert_eq!(section.payload["source"], "handbook.md");
+        assert!(migrated.iter().all(|p| p.vector.len() == 768));
+
+        // The source keeps serving unchanged until it is dropped
+        assert_eq!(client.points("docs_v1").len(), 27);
+        assert!(client.points("docs_v1").iter().all(|p| p.vector.len() == 384));
+    }
+
+    #[tokio::test]
+    async fn test_refuses_to_overwrite_or_mismatch() {
+        let client = seeded_client().await;
+        let model = HashEmbedder { dimension: 768 };
+
+        let existing = MigrationTarget::new("docs_v1", params(768));
+        let err = migrate_collection(&client, "docs_v1", &existing, &model, 10, |_| {}).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(MigrationError::DestinationExists(name)) if name == "docs_v1"));
+
+        let wrong_size = MigrationTarget::new("docs_v2", params(1024));
+        let err = migrate_collection(&client, "docs_v1", &wrong_size, &model, 10, |_| {}).await.unwrap_err();
+        assert!(err.downcast_ref::<crate::EmbeddingError>().is_some());
+        assert!(client.collections.lock().unwrap().get("docs_v2").is_none());
+
+        let missing = MigrationTarget::new("docs_v2", CollectionParams { vector_size: 768, distance: DistanceMetric::Dot });
+        let err = migrate_collection(&client, "nope", &missing, &model, 10, |_| {}).await.unwrap_err();
+        let err = talkpp_errors::Error::from(err.downcast::<MigrationError>().unwrap());
+        assert_eq!(err.kind(), talkpp_errors::ErrorKind::NotFound);
+    }
+}
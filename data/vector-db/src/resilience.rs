@@ -0,0 +1,573 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write as _;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use talkpp_errors::{Validate, Violations};
+use thiserror::Error;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+/// Request latencies kept per operation for the p95 estimate
+const LATENCY_SAMPLES: usize = 1024;
+
+/// How a `ResilientClient` spreads, bounds and cuts off requests to the vector database
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResilienceConfig {
+    /// Connections requests are round-robined over
+    pub pool_size: usize,
+    pub connect_timeout: Duration,
+    pub search_timeout: Duration,
+    pub upsert_timeout: Duration,
+    /// For collection management, snapshots and health probes
+    pub admin_timeout: Duration,
+    /// Consecutive outage failures that open the circuit breaker
+    pub failure_threshold: u32,
+    /// How long an open breaker fails fast before probing the server's health again
+    pub probe_interval: Duration,
+}
+
+impl Default for ResilienceConfig {
+    fn default() -> Self {
+        Self {
+            pool_size: 4,
+            connect_timeout: Duration::from_secs(2),
+            search_timeout: Duration::from_secs(2),
+            upsert_timeout: Duration::from_secs(10),
+            admin_timeout: Duration::from_secs(60),
+            failure_threshold: 5,
+            probe_interval: Duration::from_secs(5),
+        }
+    }
+}
+
+impl Validate for ResilienceConfig {
+    const NAME: &'static str = "resilience config";
+
+    fn violations(&self) -> Violations {
+        let mut violations = Violations::new();
+        violations.check(self.pool_size > 0, "pool_size", "must be at least 1");
+        for (field, timeout) in [
+            ("connect_timeout", self.connect_timeout),
+            ("search_timeout", self.search_timeout),
+            ("upsert_timeout", self.upsert_timeout),
+            ("admin_timeout", self.admin_timeout),
+            ("probe_interval", self.probe_interval),
+        ] {
+            violations.check(!timeout.is_zero(), field, "must not be zero");
+        }
+        violations.check(self.failure_threshold > 0, "failure_threshold", "must be at least 1");
+        violations
+    }
+}
+
+impl ResilienceConfig {
+    pub fn timeout(&self, operation: Operation) -> Duration {
+        match operation {
+            Operation::Search => self.search_timeout,
+            Operation::Upsert => self.upsert_timeout,
+            Operation::Admin => self.admin_timeout,
+        }
+    }
+
+    /// The longest any request may take, which the underlying client's own deadline must
+    /// not cut short
+    pub fn longest_timeout(&self) -> Duration {
+        self.search_timeout.max(self.upsert_timeout).max(self.admin_timeout)
+    }
+}
+
+/// Kind of request, which picks its timeout and the stats it is counted under
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    /// Searches and point lookups
+    Search,
+    /// Writes and deletes of points
+    Upsert,
+    Admin,
+}
+
+impl Operation {
+    const ALL: [Operation; 3] = [Operation::Search, Operation::Upsert, Operation::Admin];
+
+    fn label(self) -> &'static str {
+        match self {
+            Operation::Search => "search",
+            Operation::Upsert => "upsert",
+            Operation::Admin => "admin",
+        }
+    }
+}
+
+/// Returned without contacting the server while the circuit breaker is open
+#[derive(Debug, Clone, PartialEq, Error)]
+#[error("Vector database unavailable after {consecutive_failures} consecutive failures; next health probe in {retry_in:?}")]
+pub struct VectorDbUnavailable {
+    pub consecutive_failures: u32,
+    pub retry_in: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq, Error)]
+#[error("Vector database {} request timed out after {timeout:?}", operation.label())]
+pub struct OperationTimedOut {
+    pub operation: Operation,
+    pub timeout: Duration,
+}
+
+impl From<VectorDbUnavailable> for talkpp_errors::Error {
+    fn from(error: VectorDbUnavailable) -> Self {
+        let retry_in = error.retry_in;
+        Self::transparent(talkpp_errors::ErrorKind::UpstreamUnavailable, error).with_retry_after(retry_in)
+    }
+}
+
+impl From<OperationTimedOut> for talkpp_errors::Error {
+    fn from(error: OperationTimedOut) -> Self {
+        Self::transparent(talkpp_errors::ErrorKind::UpstreamTimeout, error)
+    }
+}
+
+/// One connection to the vector database
+#[async_trait]
+pub trait Transport: Send + Sync + 'static {
+    async fn health_check(&self) -> Result<()>;
+
+    /// Whether `error` means the server could not be reached or did not answer, as
+    /// opposed to it rejecting the request. Only outages count towards opening the breaker.
+    fn is_outage(&self, _error: &anyhow::Error) -> bool {
+        true
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    Closed,
+    /// Failing fast until the next health probe
+    Open,
+    /// A health probe is deciding whether to close the breaker
+    HalfOpen,
+}
+
+impl BreakerState {
+    fn gauge(self) -> u8 {
+        match self {
+            BreakerState::Closed => 0,
+            BreakerState::Open => 1,
+            BreakerState::HalfOpen => 2,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Breaker {
+    state: BreakerState,
+    consecutive_failures: u32,
+    opened_at: Instant,
+}
+
+/// What the breaker lets a request do
+enum Admission {
+    Proceed,
+    /// Probe the server's health first, on the breaker's behalf
+    Probe,
+}
+
+#[derive(Debug, Default)]
+struct OperationMetrics {
+    requests: AtomicU64,
+    errors: AtomicU64,
+    timeouts: AtomicU64,
+    latencies: Mutex<VecDeque<Duration>>,
+}
+
+impl OperationMetrics {
+    fn record(&self, latency: Duration) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        let mut latencies = self.latencies.lock().unwrap();
+        if latencies.len() == LATENCY_SAMPLES {
+            latencies.pop_front();
+        }
+        latencies.push_back(latency);
+    }
+
+    fn snapshot(&self) -> OperationStats {
+        let mut latencies: Vec<Duration> = self.latencies.lock().unwrap().iter().copied().collect();
+        latencies.sort();
+        let p95_latency = match latencies.len() {
+            0 => Duration::ZERO,
+            len => latencies[(len * 95).div_ceil(100) - 1],
+        };
+        OperationStats {
+            requests: self.requests.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+            p95_latency,
+        }
+    }
+}
+
+/// Requests sent for one kind of operation
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OperationStats {
+    pub requests: u64,
+    /// Requests that failed, timeouts included
+    pub errors: u64,
+    pub timeouts: u64,
+    /// Over the most recent requests
+    pub p95_latency: Duration,
+}
+
+/// Snapshot of a `ResilientClient`'s traffic and breaker
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VectorDbStats {
+    pub breaker: BreakerState,
+    pub consecutive_failures: u32,
+    pub pool_size: usize,
+    /// Requests failed fast by the open breaker, which never reached the server
+    pub rejected: u64,
+    /// Share of the requests sent that failed
+    pub error_rate: f64,
+    pub operations: HashMap<Operation, OperationStats>,
+}
+
+impl VectorDbStats {
+    /// The stats in Prometheus' text exposition format
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        let mut operations: Vec<_> = self.operations.iter().collect();
+        operations.sort_by_key(|(operation, _)| operation.label());
+
+        let mut family = |name: &str, kind: &str, help: &str, value: &dyn Fn(&OperationStats) -> String| {
+            let _ = writeln!(out, "# HELP {} {}\n# TYPE {} {}", name, help, name, kind);
+            for (operation, stats) in &operations {
+                let _ = writeln!(out, "{}{{operation=\"{}\"}} {}", name, operation.label(), value(stats));
+            }
+        };
+        family("vector_db_requests_total", "counter", "Requests sent to the vector database", &|s| s.requests.to_string());
+        family("vector_db_errors_total", "counter", "Vector database requests that failed", &|s| s.errors.to_string());
+        family("vector_db_timeouts_total", "counter", "Vector database requests that timed out", &|s| s.timeouts.to_string());
+        family(
+            "vector_db_request_latency_p95_seconds",
+            "gauge",
+            "95th percentile vector database request latency",
+            &|s| s.p95_latency.as_secs_f64().to_string(),
+        );
+
+        let _ = writeln!(out, "# HELP vector_db_rejected_total Requests failed fast by the open circuit breaker");
+        let _ = writeln!(out, "# TYPE vector_db_rejected_total counter\nvector_db_rejected_total {}", self.rejected);
+        let _ = writeln!(out, "# HELP vector_db_breaker_state Circuit breaker state: 0 closed, 1 open, 2 half-open");
+        let _ = writeln!(out, "# TYPE vector_db_breaker_state gauge\nvector_db_breaker_state {}", self.breaker.gauge());
+        out
+    }
+}
+
+/// A pool of connections to the vector database, round-robined per request, behind
+/// per-operation timeouts and a circuit breaker.
+///
+/// After `failure_threshold` consecutive outages the breaker opens and requests fail fast
+/// with `VectorDbUnavailable` instead of each waiting out its timeout. Once
+/// `probe_interval` has passed, the next request probes the server's health and the
+/// breaker closes again if the server answers.
+pub struct ResilientClient<C> {
+    channels: Vec<Arc<C>>,
+    next: AtomicUsize,
+    config: ResilienceConfig,
+    breaker: Mutex<Breaker>,
+    rejected: AtomicU64,
+    metrics: HashMap<Operation, OperationMetrics>,
+}
+
+impl<C: Transport> ResilientClient<C> {
+    /// Open `config.pool_size` connections with `connect`
+    pub fn connect(config: ResilienceConfig, connect: impl FnMut() -> Result<C>) -> Result<Self> {
+        let channels = std::iter::repeat_with(connect).take(config.pool_size.max(1)).collect::<Result<Vec<_>>>()?;
+        Ok(Self::from_channels(config, channels))
+    }
+
+    pub fn from_channels(config: ResilienceConfig, channels: Vec<C>) -> Self {
+        assert!(!channels.is_empty(), "a resilient client needs at least one connection");
+        Self {
+            channels: channels.into_iter().map(Arc::new).collect(),
+            next: AtomicUsize::new(0),
+            config,
+            breaker: Mutex::new(Breaker {
+                state: BreakerState::Closed,
+                consecutive_failures: 0,
+                opened_at: Instant::now(),
+            }),
+            rejected: AtomicU64::new(0),
+            metrics: Operation::ALL.into_iter().map(|operation| (operation, OperationMetrics::default())).collect(),
+        }
+    }
+
+    /// Run `request` on the next connection in the pool, bounded by the operation's timeout
+    pub async fn call<T, F, Fut>(&self, operation: Operation, request: F) -> Result<T>
+    where
+        F: FnOnce(Arc<C>) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        if let Admission::Probe = self.admit()? {
+            if !self.probe().await {
+                self.rejected.fetch_add(1, Ordering::Relaxed);
+                let breaker = self.breaker.lock().unwrap();
+                return Err(self.unavailable(&breaker).into());
+            }
+        }
+
+        let channel = self.channel();
+        let timeout = self.config.timeout(operation);
+        let metrics = &self.metrics[&operation];
+        let started = Instant::now();
+        let result = match tokio::time::timeout(timeout, request(channel.clone())).await {
+            Ok(result) => result,
+            Err(_) => {
+                metrics.timeouts.fetch_add(1, Ordering::Relaxed);
+                Err(OperationTimedOut { operation, timeout }.into())
+            }
+        };
+        metrics.record(started.elapsed());
+
+        match &result {
+            Ok(_) => self.record_success(),
+            Err(e) => {
+                metrics.errors.fetch_add(1, Ordering::Relaxed);
+                if e.is::<OperationTimedOut>() || channel.is_outage(e) {
+                    self.record_failure();
+                } else {
+                    // The server answered, so it is up
+                    self.record_success();
+                }
+            }
+        }
+        result
+    }
+
+    /// Check the server's health, closing the breaker if it answers and reopening it if not
+    pub async fn probe(&self) -> bool {
+        let channel = self.channel();
+        let healthy = match tokio::time::timeout(self.config.admin_timeout, channel.health_check()).await {
+            Ok(Ok(())) => true,
+            Ok(Err(e)) => {
+                warn!("Vector database health probe failed: {}", e);
+                false
+            }
+            Err(_) => {
+                warn!("Vector database health probe timed out after {:?}", self.config.admin_timeout);
+                false
+            }
+        };
+
+        let mut breaker = self.breaker.lock().unwrap();
+        if healthy {
+            if breaker.state != BreakerState::Closed {
+                info!("Vector database is reachable again; closing circuit breaker");
+            }
+            breaker.state = BreakerState::Closed;
+            breaker.consecutive_failures = 0;
+        } else if breaker.state != BreakerState::Closed {
+            breaker.state = BreakerState::Open;
+            breaker.opened_at = Instant::now();
+        }
+        healthy
+    }
+
+    pub fn stats(&self) -> VectorDbStats {
+        let breaker = self.breaker.lock().unwrap();
+        let operations: HashMap<Operation, OperationStats> =
+            self.metrics.iter().map(|(operation, metrics)| (*operation, metrics.snapshot())).collect();
+        let requests: u64 = operations.values().map(|s| s.requests).sum();
+        let errors: u64 = operations.values().map(|s| s.errors).sum();
+        VectorDbStats {
+            breaker: breaker.state,
+            consecutive_failures: breaker.consecutive_failures,
+            pool_size: self.channels.len(),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            error_rate: if requests > 0 { errors as f64 / requests as f64 } else { 0.0 },
+            operations,
+        }
+    }
+
+    fn channel(&self) -> Arc<C> {
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.channels.len();
+        self.channels[index].clone()
+    }
+
+    /// Let a request through, make it probe first, or fail it fast
+    fn admit(&self) -> Result<Admission, VectorDbUnavailable> {
+        let mut breaker = self.breaker.lock().unwrap();
+        match breaker.state {
+            BreakerState::Closed => Ok(Admission::Proceed),
+            BreakerState::Open if breaker.opened_at.elapsed() >= self.config.probe_interval => {
+                breaker.state = BreakerState::HalfOpen;
+                Ok(Admission::Probe)
+            }
+            // While one request probes, the rest keep failing fast
+            BreakerState::Open | BreakerState::HalfOpen => {
+                self.rejected.fetch_add(1, Ordering::Relaxed);
+                Err(self.unavailable(&breaker))
+            }
+        }
+    }
+
+    fn unavailable(&self, breaker: &Breaker) -> VectorDbUnavailable {
+        VectorDbUnavailable {
+            consecutive_failures: breaker.consecutive_failures,
+            retry_in: self.config.probe_interval.saturating_sub(breaker.opened_at.elapsed()),
+        }
+    }
+
+    fn record_success(&self) {
+        let mut breaker = self.breaker.lock().unwrap();
+        if breaker.state == BreakerState::Closed {
+            breaker.consecutive_failures = 0;
+        }
+    }
+
+    fn record_failure(&self) {
+        let mut breaker = self.breaker.lock().unwrap();
+        breaker.consecutive_failures += 1;
+        if breaker.state == BreakerState::Closed && breaker.consecutive_failures >= self.config.failure_threshold {
+            warn!(
+                "Opening vector database circuit breaker after {} consecutive failures",
+                breaker.consecutive_failures
+            );
+            breaker.state = BreakerState::Open;
+            breaker.opened_at = Instant::now();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+
+    /// A connection to a server that is up, or down and never answering
+    struct MockTransport {
+        up: Arc<AtomicBool>,
+        calls: AtomicUsize,
+    }
+
+    impl MockTransport {
+        async fn search(&self) -> Result<Vec<f32>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if !self.up.load(Ordering::SeqCst) {
+                // What a blackholed connection looks like: nothing until the deadline
+                tokio::time::sleep(Duration::from_secs(3600)).await;
+            }
+            Ok(vec![1.0])
+        }
+
+        async fn reject(&self) -> Result<()> {
+            anyhow::bail!("collection 'missing' does not exist")
+        }
+    }
+
+    #[async_trait]
+    impl Transport for MockTransport {
+        async fn health_check(&self) -> Result<()> {
+            if self.up.load(Ordering::SeqCst) {
+                Ok(())
+            } else {
+                anyhow::bail!("connection refused")
+            }
+        }
+
+        fn is_outage(&self, error: &anyhow::Error) -> bool {
+            !error.to_string().contains("does not exist")
+        }
+    }
+
+    fn client(pool_size: usize) -> (ResilientClient<MockTransport>, Arc<AtomicBool>) {
+        let up = Arc::new(AtomicBool::new(true));
+        let config = ResilienceConfig {
+            pool_size,
+            search_timeout: Duration::from_millis(50),
+            failure_threshold: 3,
+            probe_interval: Duration::from_secs(1),
+            ..Default::default()
+        };
+        let client = ResilientClient::connect(config, || Ok(MockTransport { up: up.clone(), calls: AtomicUsize::new(0) })).unwrap();
+        (client, up)
+    }
+
+    fn calls(client: &ResilientClient<MockTransport>) -> Vec<usize> {
+        client.channels.iter().map(|c| c.calls.load(Ordering::SeqCst)).collect()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_outage_fails_fast_then_recovers() {
+        let (client, up) = client(3);
+        for _ in 0..6 {
+            client.call(Operation::Search, |c| async move { c.search().await }).await.unwrap();
+        }
+        assert_eq!(calls(&client), vec![2, 2, 2]);
+
+        // Each request waits out its timeout until the breaker opens
+        up.store(false, Ordering::SeqCst);
+        for _ in 0..3 {
+            let started = Instant::now();
+            let error = client.call(Operation::Search, |c| async move { c.search().await }).await.unwrap_err();
+            assert!(error.is::<OperationTimedOut>());
+            assert_eq!(started.elapsed(), Duration::from_millis(50));
+        }
+        assert_eq!(client.stats().breaker, BreakerState::Open);
+
+        // Then fail fast without reaching the server
+        let started = Instant::now();
+        let error = client.call(Operation::Search, |c| async move { c.search().await }).await.unwrap_err();
+        assert_eq!(started.elapsed(), Duration::ZERO);
+        let unavailable = error.downcast_ref::<VectorDbUnavailable>().unwrap();
+        assert_eq!(unavailable.consecutive_failures, 3);
+        assert_eq!(calls(&client).iter().sum::<usize>(), 9);
+        let retry_in = unavailable.retry_in;
+        let public = talkpp_errors::Error::from(error.downcast::<VectorDbUnavailable>().unwrap());
+        assert_eq!(public.kind(), talkpp_errors::ErrorKind::UpstreamUnavailable);
+        assert_eq!(public.retry_after(), Some(retry_in));
+
+        // A probe while the server is still down keeps the breaker open
+        tokio::time::advance(Duration::from_secs(1)).await;
+        let error = client.call(Operation::Search, |c| async move { c.search().await }).await.unwrap_err();
+        assert!(error.is::<VectorDbUnavailable>());
+        assert_eq!(client.stats().breaker, BreakerState::Open);
+        assert_eq!(calls(&client).iter().sum::<usize>(), 9);
+
+        up.store(true, Ordering::SeqCst);
+        let error = client.call(Operation::Search, |c| async move { c.search().await }).await.unwrap_err();
+        assert!(error.is::<VectorDbUnavailable>(), "no probe before the interval has passed again");
+        tokio::time::advance(Duration::from_secs(1)).await;
+        client.call(Operation::Search, |c| async move { c.search().await }).await.unwrap();
+
+        let stats = client.stats();
+        assert_eq!((stats.breaker, stats.consecutive_failures, stats.rejected), (BreakerState::Closed, 0, 3));
+        let search = &stats.operations[&Operation::Search];
+        assert_eq!((search.requests, search.errors, search.timeouts), (10, 3, 3));
+        assert_eq!(search.p95_latency, Duration::from_millis(50));
+        assert!((stats.error_rate - 0.3).abs() < 1e-9);
+
+        let exposition = stats.to_prometheus();
+        assert!(exposition.contains("vector_db_requests_total{operation=\"search\"} 10"));
+        assert!(exposition.contains("vector_db_rejected_total 3"));
+        assert!(exposition.contains("vector_db_breaker_state 0"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rejected_requests_do_not_open_the_breaker() {
+        let (client, _) = client(1);
+        for _ in 0..5 {
+            let error = client.call(Operation::Admin, |c| async move { c.reject().await }).await.unwrap_err();
+            assert!(!error.is::<VectorDbUnavailable>());
+        }
+        let stats = client.stats();
+        assert_eq!((stats.breaker, stats.consecutive_failures), (BreakerState::Closed, 0));
+        assert_eq!(stats.operations[&Operation::Admin].errors, 5);
+        assert_eq!(stats.error_rate, 1.0);
+    }
+}
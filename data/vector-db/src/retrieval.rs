@@ -0,0 +1,153 @@
+//! Reranking dense search candidates for `RagSystem`
+//!
+//! Both strategies start from the nearest `limit * CANDIDATE_MULTIPLIER` chunks by vector
+//! search and reorder them:
+//!
+//! - [`hybrid_rerank`] blends each candidate's vector score with the share of the query's
+//!   terms found in its text, so exact names and codes the embedding blurs still count.
+//! - [`mmr_rerank`] picks candidates by maximal marginal relevance: each pick trades its
+//!   relevance against its similarity to the chunks already picked, so near-duplicate chunks
+//!   do not crowd out the rest of the context.
+//!
+//! Vector scores are min-max rescaled over the candidates first, since even normalized
+//! similarities tend to bunch in a narrow band.
+
+use std::collections::HashSet;
+
+use crate::SearchResult;
+
+/// Candidates fetched per result wanted before reranking
+pub const CANDIDATE_MULTIPLIER: usize = 4;
+
+/// Weight of the keyword score in a hybrid score, unless configured otherwise
+pub const DEFAULT_KEYWORD_WEIGHT: f32 = 0.3;
+
+/// Weight of relevance against novelty in MMR, unless configured otherwise
+pub const DEFAULT_MMR_LAMBDA: f32 = 0.7;
+
+/// Lowercased alphanumeric terms of `text`
+pub(crate) fn terms(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Each candidate's score scaled to 0..=1 over the candidates; all 1 when they are equal
+fn normalized_scores(candidates: &[SearchResult]) -> Vec<f32> {
+    let min = candidates.iter().map(|c| c.score).fold(f32::INFINITY, f32::min);
+    let max = candidates.iter().map(|c| c.score).fold(f32::NEG_INFINITY, f32::max);
+    candidates.iter()
+        .map(|c| if max > min { (c.score - min) / (max - min) } else { 1.0 })
+        .collect()
+}
+
+/// Cosine similarity of the chunks' vectors, or the Jaccard similarity of their terms when
+/// either was returned without one
+fn similarity(a: &SearchResult, b: &SearchResult) -> f32 {
+    if let (Some(va), Some(vb)) = (&a.document.vector, &b.document.vector) {
+        let dot: f32 = va.iter().zip(vb).map(|(x, y)| x * y).sum();
+        let norms = va.iter().map(|x| x * x).sum::<f32>().sqrt() * vb.iter().map(|x| x * x).sum::<f32>().sqrt();
+        return if norms > 0.0 { dot / norms } else { 0.0 };
+    }
+    let (ta, tb) = (terms(&a.document.content), terms(&b.document.content));
+    let union = ta.union(&tb).count();
+    if union == 0 {
+        return 0.0;
+    }
+    ta.intersection(&tb).count() as f32 / union as f32
+}
+
+/// `results` in their new order, with `score` and `rank` to match
+fn ranked(results: Vec<(SearchResult, f32)>) -> Vec<SearchResult> {
+    results.into_iter()
+        .enumerate()
+        .map(|(rank, (result, score))| SearchResult { score, rank, ..result })
+        .collect()
+}
+
+/// The best `limit` of `candidates` by `(1 - keyword_weight) * vector score + keyword_weight
+/// * share of query terms in the chunk`
+pub fn hybrid_rerank(query: &str, candidates: Vec<SearchResult>, keyword_weight: f32, limit: usize) -> Vec<SearchResult> {
+    let query_terms = terms(query);
+    let dense = normalized_scores(&candidates);
+    let mut scored: Vec<(SearchResult, f32)> = candidates.into_iter()
+        .zip(dense)
+        .map(|(candidate, dense)| {
+            let keyword = if query_terms.is_empty() {
+                0.0
+            } else {
+                let chunk_terms = terms(&candidate.document.content);
+                query_terms.intersection(&chunk_terms).count() as f32 / query_terms.len() as f32
+            };
+            (candidate, (1.0 - keyword_weight) * dense + keyword_weight * keyword)
+        })
+        .collect();
+    // Stable, so ties keep the vector search's order
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scored.truncate(limit);
+    ranked(scored)
+}
+
+/// `limit` of `candidates` picked one at a time by `lambda * relevance - (1 - lambda) *
+/// highest similarity to a chunk already picked`
+pub fn mmr_rerank(candidates: Vec<SearchResult>, lambda: f32, limit: usize) -> Vec<SearchResult> {
+    let relevance = normalized_scores(&candidates);
+    let mut remaining: Vec<(SearchResult, f32)> = candidates.into_iter().zip(relevance).collect();
+    let mut picked: Vec<(SearchResult, f32)> = Vec::new();
+
+    while picked.len() < limit && !remaining.is_empty() {
+        let (best, score) = remaining.iter()
+            .enumerate()
+            .map(|(i, (candidate, relevance))| {
+                let redundancy = picked.iter()
+                    .map(|(chosen, _)| similarity(candidate, chosen))
+                    .fold(0.0, f32::max);
+                (i, lambda * relevance - (1.0 - lambda) * redundancy)
+            })
+            // First of equals wins, so ties keep the vector search's order
+            .fold((0, f32::NEG_INFINITY), |best, next| if next.1 > best.1 { next } else { best });
+        let (candidate, _) = remaining.remove(best);
+        picked.push((candidate, score));
+    }
+    ranked(picked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::VectorDocument;
+
+    fn result(content: &str, score: f32) -> SearchResult {
+        SearchResult {
+            document: VectorDocument {
+                id: uuid::Uuid::new_v4(),
+                content: content.to_string(),
+                metadata: Default::default(),
+                vector: None,
+                created_at: chrono::Utc::now(),
+                updated_at: chrono::Utc::now(),
+            },
+            score,
+            raw_score: score,
+            rank: 0,
+        }
+    }
+
+    #[test]
+    fn test_hybrid_lifts_exact_term_matches_and_mmr_skips_near_duplicates() {
+        let candidates = vec![
+            result("tidal turbines spin both ways", 0.9),
+            result("tidal turbines spin both ways today", 0.85),
+            result("vault ORION-7 opens at dawn", 0.5),
+        ];
+
+        let hybrid = hybrid_rerank("orion-7 vault", candidates.clone(), 0.6, 2);
+        assert_eq!(hybrid[0].document.content, "vault ORION-7 opens at dawn");
+        assert_eq!((hybrid[0].rank, hybrid[1].rank), (0, 1));
+
+        let mmr = mmr_rerank(candidates, 0.3, 2);
+        assert_eq!(mmr[0].document.content, "tidal turbines spin both ways");
+        assert_eq!(mmr[1].document.content, "vault ORION-7 opens at dawn");
+    }
+}
@@ -0,0 +1,298 @@
+//! Upserting documents as a stream produces them
+//!
+//! Documents are gathered into batches of `batch_size` and at most `max_in_flight`
+//! batches are written at once. The stream is only read while a batch has room, so a
+//! fast producer waits for the database instead of piling documents up in memory.
+//!
+//! Dropping an upsert part way cancels the batches being written. Whether Qdrant stored
+//! any of their points is then unknown, so each one is sent to the progress channel as
+//! interrupted, with the ids to write again.
+
+use futures::stream::BoxStream;
+use futures::{Future, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::VectorDocument;
+
+#[derive(Debug, Clone)]
+pub struct UpsertOptions {
+    /// Documents embedded and written together
+    pub batch_size: usize,
+    /// Batches written at once
+    pub max_in_flight: usize,
+    /// Receives each batch's report as soon as it finishes, including batches cut off by
+    /// dropping the upsert
+    pub progress: Option<mpsc::UnboundedSender<BatchReport>>,
+}
+
+impl Default for UpsertOptions {
+    fn default() -> Self {
+        Self { batch_size: 64, max_in_flight: 4, progress: None }
+    }
+}
+
+impl UpsertOptions {
+    pub fn with_progress(mut self, progress: mpsc::UnboundedSender<BatchReport>) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    pub fn validate(&self) -> talkpp_errors::Result<()> {
+        if self.batch_size == 0 || self.max_in_flight == 0 {
+            return Err(talkpp_errors::Error::invalid_input("Upsert batch size and in-flight limit must be at least 1"));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BatchStatus {
+    Written,
+    Failed(String),
+    /// The upsert was dropped while the batch was being written
+    Interrupted,
+}
+
+/// How one batch went
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchReport {
+    /// Position of the batch in the stream, from 0
+    pub index: usize,
+    pub documents: usize,
+    pub elapsed: Duration,
+    pub status: BatchStatus,
+    /// Ids of the batch's documents unless it was written
+    pub unwritten_ids: Vec<Uuid>,
+}
+
+/// Totals for a whole stream
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpsertReport {
+    pub upserted: usize,
+    pub failed_ids: Vec<Uuid>,
+    /// Every batch, in stream order
+    pub batches: Vec<BatchReport>,
+}
+
+impl UpsertReport {
+    fn record(&mut self, batch: BatchReport) {
+        match batch.status {
+            BatchStatus::Written => self.upserted += batch.documents,
+            _ => self.failed_ids.extend(&batch.unwritten_ids),
+        }
+        self.batches.push(batch);
+    }
+}
+
+/// Write `documents` in batches with `write`, as `options` allow. A failing batch is
+/// reported, not fatal.
+pub async fn upsert_in_batches<W, Fut>(documents: BoxStream<'_, VectorDocument>, options: &UpsertOptions, write: W) -> UpsertReport
+where
+    W: Fn(Vec<VectorDocument>) -> Fut,
+    Fut: Future<Output = talkpp_errors::Result<()>>,
+{
+    let progress = options.progress.as_ref();
+    let mut batches = documents
+        .chunks(options.batch_size.max(1))
+        .enumerate()
+        .map(|(index, batch)| write_batch(index, batch, &write, progress))
+        .buffer_unordered(options.max_in_flight.max(1));
+
+    let mut report = UpsertReport::default();
+    while let Some(batch) = batches.next().await {
+        report.record(batch);
+    }
+    report.batches.sort_by_key(|batch| batch.index);
+    report
+}
+
+async fn write_batch<W, Fut>(
+    index: usize,
+    batch: Vec<VectorDocument>,
+    write: &W,
+    progress: Option<&mpsc::UnboundedSender<BatchReport>>,
+) -> BatchReport
+where
+    W: Fn(Vec<VectorDocument>) -> Fut,
+    Fut: Future<Output = talkpp_errors::Result<()>>,
+{
+    let mut pending = PendingBatch {
+        index,
+        ids: batch.iter().map(|document| document.id).collect(),
+        started: Instant::now(),
+        progress,
+        finished: false,
+    };
+    let result = write(batch).await;
+    pending.finished = true;
+
+    let report = BatchReport {
+        index,
+        documents: pending.ids.len(),
+        elapsed: pending.started.elapsed(),
+        status: match &result {
+            Ok(()) => BatchStatus::Written,
+            Err(e) => BatchStatus::Failed(e.to_string()),
+        },
+        unwritten_ids: if result.is_ok() { Vec::new() } else { std::mem::take(&mut pending.ids) },
+    };
+    if let BatchStatus::Failed(error) = &report.status {
+        warn!("Upsert batch {} of {} documents failed: {}", index, report.documents, error);
+    }
+    if let Some(progress) = progress {
+        let _ = progress.send(report.clone());
+    }
+    report
+}
+
+/// Reports its batch as interrupted if dropped before the write finished
+struct PendingBatch<'a> {
+    index: usize,
+    ids: Vec<Uuid>,
+    started: Instant,
+    progress: Option<&'a mpsc::UnboundedSender<BatchReport>>,
+    finished: bool,
+}
+
+impl Drop for PendingBatch<'_> {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        warn!("Upsert dropped while writing batch {} of {} documents", self.index, self.ids.len());
+        if let Some(progress) = self.progress {
+            let _ = progress.send(BatchReport {
+                index: self.index,
+                documents: self.ids.len(),
+                elapsed: self.started.elapsed(),
+                status: BatchStatus::Interrupted,
+                unwritten_ids: std::mem::take(&mut self.ids),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeVectorDb;
+    use crate::VectorDatabase;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn document(i: usize) -> VectorDocument {
+        VectorDocument {
+            id: Uuid::new_v4(),
+            content: format!("document {}", i),
+            metadata: HashMap::new(),
+            vector: Some(vec![i as f32]),
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        }
+    }
+
+    /// Documents taken from the stream but not yet written, and the most there ever were
+    #[derive(Default)]
+    struct Buffered {
+        now: AtomicUsize,
+        peak: AtomicUsize,
+    }
+
+    impl Buffered {
+        fn taken(&self) {
+            let now = self.now.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(now, Ordering::SeqCst);
+        }
+
+        fn written(&self, documents: usize) {
+            self.now.fetch_sub(documents, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_large_streams_are_written_in_bounded_memory() {
+        let buffered = Arc::new(Buffered::default());
+        let counted = buffered.clone();
+        let documents = futures::stream::iter(0..5000)
+            .map(move |i| {
+                counted.taken();
+                document(i)
+            })
+            .boxed();
+
+        let options = UpsertOptions { batch_size: 50, max_in_flight: 3, progress: None };
+        let batches_at_once = Arc::new(AtomicUsize::new(0));
+        let report = upsert_in_batches(documents, &options, |batch| {
+            let (buffered, batches_at_once) = (buffered.clone(), batches_at_once.clone());
+            async move {
+                assert!(batches_at_once.fetch_add(1, Ordering::SeqCst) < 3);
+                tokio::time::sleep(Duration::from_micros(200)).await;
+                batches_at_once.fetch_sub(1, Ordering::SeqCst);
+                buffered.written(batch.len());
+                match batch.iter().any(|document| document.content == "document 4321") {
+                    true => Err(talkpp_errors::Error::upstream_unavailable("Qdrant went away")),
+                    false => Ok(()),
+                }
+            }
+        })
+        .await;
+
+        assert_eq!(report.upserted, 4950);
+        assert_eq!(report.failed_ids.len(), 50);
+        assert_eq!(report.batches.len(), 100);
+        assert!(report.batches.iter().enumerate().all(|(i, batch)| batch.index == i));
+        assert!(matches!(report.batches[86].status, BatchStatus::Failed(_)));
+
+        let peak = buffered.peak.load(Ordering::SeqCst);
+        assert!(peak <= 50 * 3, "{} documents were buffered at once", peak);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_upserts_report_interrupted_batches() {
+        let (progress, mut reports) = mpsc::unbounded_channel();
+        let documents: Vec<VectorDocument> = (0..5).map(document).collect();
+        let ids: Vec<Uuid> = documents.iter().map(|document| document.id).collect();
+        let options = UpsertOptions { batch_size: 2, max_in_flight: 2, progress: None }.with_progress(progress);
+
+        // The first batch is written, the second never finishes
+        let upsert = upsert_in_batches(futures::stream::iter(documents).boxed(), &options, |batch| async move {
+            if batch.len() == 2 && batch[0].content == "document 2" {
+                futures::future::pending::<()>().await;
+            }
+            Ok(())
+        });
+        assert!(tokio::time::timeout(Duration::from_millis(50), upsert).await.is_err());
+        drop(options);
+
+        let mut received = Vec::new();
+        while let Some(report) = reports.recv().await {
+            received.push((report.index, report.status, report.unwritten_ids));
+        }
+        received.sort_by_key(|(index, ..)| *index);
+        assert_eq!(received, vec![
+            (0, BatchStatus::Written, vec![]),
+            (1, BatchStatus::Interrupted, ids[2..4].to_vec()),
+            (2, BatchStatus::Written, vec![]),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_default_upsert_stream_writes_through_upsert_documents() {
+        let db = FakeVectorDb::default();
+        let options = UpsertOptions { batch_size: 4, ..UpsertOptions::default() };
+        let report = db.upsert_stream(futures::stream::iter((0..10).map(document)).boxed(), options).await.unwrap();
+
+        assert_eq!(report.upserted, 10);
+        assert_eq!(report.batches.iter().map(|batch| batch.documents).collect::<Vec<_>>(), vec![4, 4, 2]);
+        assert_eq!(db.documents.lock().unwrap().len(), 10);
+
+        let invalid = UpsertOptions { batch_size: 0, ..UpsertOptions::default() };
+        let error = db.upsert_stream(futures::stream::empty().boxed(), invalid).await.unwrap_err();
+        assert_eq!(error.kind(), talkpp_errors::ErrorKind::InvalidInput);
+    }
+}
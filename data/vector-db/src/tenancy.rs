@@ -0,0 +1,240 @@
+//! Keeping each tenant's documents apart in a shared collection
+//!
+//! Every document carries its owner in the [`TENANT_PAYLOAD_KEY`] payload field.
+//! [`TenantScopedDb`] is how [`RagSystem`](crate::RagSystem) reaches the database: it
+//! stamps the field on every write, adds it to the filter of every search, and drops
+//! anything of another tenant's that comes back anyway, so a query cannot see across
+//! tenants even against a database that ignores filters.
+//!
+//! Documents stored before tenants existed have no owner and are invisible to every
+//! tenant until [`backfill_tenant`] assigns them one, usually
+//! [`DEFAULT_TENANT`](talkpp_tenancy::DEFAULT_TENANT).
+
+use std::collections::HashMap;
+use talkpp_tenancy::TenantContext;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{DocumentPage, SearchResult, VectorDatabase, VectorDocument};
+
+/// Payload field holding the id of the tenant a document belongs to
+pub const TENANT_PAYLOAD_KEY: &str = "tenant_id";
+
+/// Documents read per page while backfilling
+const BACKFILL_PAGE_SIZE: usize = 256;
+
+/// Tenant owning `document`, if it has one
+pub fn document_tenant(document: &VectorDocument) -> Option<&str> {
+    document.metadata.get(TENANT_PAYLOAD_KEY).and_then(|tenant| tenant.as_str())
+}
+
+/// A database as one tenant sees it
+pub struct TenantScopedDb<'a> {
+    db: &'a (dyn VectorDatabase + Send + Sync),
+    tenant: &'a TenantContext,
+}
+
+impl<'a> TenantScopedDb<'a> {
+    pub fn new(db: &'a (dyn VectorDatabase + Send + Sync), tenant: &'a TenantContext) -> Self {
+        Self { db, tenant }
+    }
+
+    pub fn tenant(&self) -> &TenantContext {
+        self.tenant
+    }
+
+    fn owns(&self, document: &VectorDocument) -> bool {
+        document_tenant(document).is_some_and(|tenant| self.tenant.owns(tenant))
+    }
+
+    /// `filter` narrowed to this tenant, replacing any tenant it named
+    fn scoped_filter(&self, filter: Option<HashMap<String, serde_json::Value>>) -> HashMap<String, serde_json::Value> {
+        let mut filter = filter.unwrap_or_default();
+        filter.insert(TENANT_PAYLOAD_KEY.to_string(), serde_json::Value::String(self.tenant.tenant_id.clone()));
+        filter
+    }
+
+    /// `document` marked as this tenant's. Fails if it is marked as another's.
+    fn stamp(&self, mut document: VectorDocument) -> talkpp_errors::Result<VectorDocument> {
+        if let Some(tenant) = document_tenant(&document).filter(|tenant| !self.tenant.owns(tenant)) {
+            return Err(talkpp_errors::Error::invalid_input(format!(
+                "Document {} is marked as tenant '{}', not '{}'", document.id, tenant, self.tenant.tenant_id
+            )));
+        }
+        document.metadata.insert(TENANT_PAYLOAD_KEY.to_string(), serde_json::Value::String(self.tenant.tenant_id.clone()));
+        Ok(document)
+    }
+
+    /// Write `document` as this tenant's. Overwriting another tenant's document with the
+    /// same id is refused as if there were no such document.
+    pub async fn upsert_document(&self, document: VectorDocument) -> talkpp_errors::Result<()> {
+        if let Some(existing) = self.db.get_document(document.id).await? {
+            if !self.owns(&existing) {
+                return Err(talkpp_errors::Error::not_found(format!("Document {} not found", document.id)));
+            }
+        }
+        self.write(document).await
+    }
+
+    /// Write `document` without looking up who owns its id, for ids just generated or
+    /// already known to be this tenant's
+    pub(crate) async fn write(&self, document: VectorDocument) -> talkpp_errors::Result<()> {
+        self.db.upsert_document(self.stamp(document)?).await
+    }
+
+    pub async fn search(
+        &self,
+        query_vector: Vec<f32>,
+        limit: usize,
+        filter: Option<HashMap<String, serde_json::Value>>,
+    ) -> talkpp_errors::Result<Vec<SearchResult>> {
+        let results = self.db.search(query_vector, limit, Some(self.scoped_filter(filter))).await?;
+        Ok(self.own_results(results))
+    }
+
+    pub async fn search_by_text(
+        &self,
+        query: &str,
+        limit: usize,
+        filter: Option<HashMap<String, serde_json::Value>>,
+    ) -> talkpp_errors::Result<Vec<SearchResult>> {
+        let results = self.db.search_by_text(query, limit, Some(self.scoped_filter(filter))).await?;
+        Ok(self.own_results(results))
+    }
+
+    /// `results` without other tenants' documents, ranked again from 0
+    fn own_results(&self, results: Vec<SearchResult>) -> Vec<SearchResult> {
+        results.into_iter()
+            .filter(|result| self.owns(&result.document))
+            .enumerate()
+            .map(|(rank, result)| SearchResult { rank, ..result })
+            .collect()
+    }
+
+    /// The document, unless it is missing or another tenant's
+    pub async fn get_document(&self, id: Uuid) -> talkpp_errors::Result<Option<VectorDocument>> {
+        Ok(self.db.get_document(id).await?.filter(|document| self.owns(document)))
+    }
+
+    /// Delete the document if it is this tenant's, returning whether it was. Another
+    /// tenant's document is left alone, as if it did not exist.
+    pub async fn delete_document(&self, id: Uuid) -> talkpp_errors::Result<bool> {
+        if self.get_document(id).await?.is_none() {
+            return Ok(false);
+        }
+        self.db.delete_document(id).await?;
+        Ok(true)
+    }
+
+    /// This tenant's documents among the next `limit` of the collection. Pages can hold
+    /// fewer than `limit` documents, or none, before the last one.
+    pub async fn scroll_documents(&self, offset: Option<Uuid>, limit: usize) -> talkpp_errors::Result<DocumentPage> {
+        let mut page = self.db.scroll_documents(offset, limit).await?;
+        page.documents.retain(|document| self.owns(document));
+        Ok(page)
+    }
+}
+
+/// Assign every document without a tenant to `tenant_id`, returning how many were.
+/// Documents are rewritten with their stored vectors, so nothing is re-embedded.
+pub async fn backfill_tenant(db: &(dyn VectorDatabase + Send + Sync), tenant_id: &str) -> talkpp_errors::Result<usize> {
+    if tenant_id.trim().is_empty() {
+        return Err(talkpp_errors::Error::invalid_input("Tenant id must not be empty"));
+    }
+    let mut backfilled = 0;
+    let mut offset = None;
+    loop {
+        let page = db.scroll_documents(offset, BACKFILL_PAGE_SIZE).await?;
+        for document in page.documents.iter().filter(|document| document_tenant(document).is_none()) {
+            // Scrolled documents come without their vectors
+            let Some(mut stored) = db.get_document(document.id).await? else {
+                continue;
+            };
+            stored.metadata.insert(TENANT_PAYLOAD_KEY.to_string(), serde_json::Value::String(tenant_id.to_string()));
+            db.upsert_document(stored).await?;
+            backfilled += 1;
+        }
+        match page.next_offset {
+            Some(next) => offset = Some(next),
+            None => break,
+        }
+    }
+    info!("Assigned {} documents to tenant '{}'", backfilled, tenant_id);
+    Ok(backfilled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeVectorDb;
+    use crate::RagSystem;
+
+    fn tenant(id: &str) -> TenantContext {
+        TenantContext::new(id, "ada").unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_tenants_cannot_reach_each_others_documents() {
+        let db = FakeVectorDb::default();
+        let rag = RagSystem::new(Box::new(db.clone()));
+        let (acme, globex) = (tenant("acme"), tenant("globex"));
+        let added = rag.add_document(&acme, "Tidal turbines spin in both directions.", HashMap::new()).await.unwrap();
+        let chunk_id = added.chunk_ids[0];
+
+        // Every query path
+        assert!(rag.retrieve_context(&globex, "turbines", 5).await.unwrap().is_empty());
+        assert!(rag.retrieve_hybrid(&globex, "turbines", 5, 0.3).await.unwrap().is_empty());
+        assert!(rag.retrieve_mmr(&globex, "turbines", 5, 0.5).await.unwrap().is_empty());
+        assert!(rag.generate_with_context(&globex, "turbines", 5).await.unwrap().sources.is_empty());
+        assert_eq!(rag.retrieve_context(&acme, "turbines", 5).await.unwrap().len(), 1);
+
+        // The scoped view itself, with a filter naming the other tenant
+        let scoped = TenantScopedDb::new(&db, &globex);
+        let sneaky = HashMap::from([(TENANT_PAYLOAD_KEY.to_string(), serde_json::json!("acme"))]);
+        assert!(scoped.search_by_text("turbines", 5, Some(sneaky.clone())).await.unwrap().is_empty());
+        assert!(scoped.search(vec![0.0], 5, Some(sneaky)).await.unwrap().is_empty());
+        assert!(scoped.get_document(chunk_id).await.unwrap().is_none());
+        assert!(scoped.scroll_documents(None, 10).await.unwrap().documents.is_empty());
+        assert!(!scoped.delete_document(chunk_id).await.unwrap());
+
+        // Writes can neither overwrite nor remove another tenant's chunks
+        let err = rag.update_document(&globex, &[chunk_id], "Hijacked", HashMap::new()).await.unwrap_err();
+        assert_eq!(err.kind(), talkpp_errors::ErrorKind::NotFound);
+        rag.remove_document(&globex, &[chunk_id]).await.unwrap();
+        let stored = db.documents.lock().unwrap()[&chunk_id].clone();
+        assert_eq!(stored.content, "Tidal turbines spin in both directions.");
+        assert_eq!(document_tenant(&stored), Some("acme"));
+
+        // The same text is stored separately for each tenant
+        let theirs = rag.add_document(&globex, "Tidal turbines spin in both directions.", HashMap::new()).await.unwrap();
+        assert_eq!(theirs.new, 1);
+        assert_ne!(theirs.chunk_ids, added.chunk_ids);
+    }
+
+    #[tokio::test]
+    async fn test_backfill_assigns_unowned_documents() {
+        let db = FakeVectorDb::default();
+        let legacy = VectorDocument {
+            id: Uuid::new_v4(),
+            content: "Wind farms are offshore.".to_string(),
+            metadata: HashMap::new(),
+            vector: Some(vec![0.5]),
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        };
+        db.upsert_document(legacy.clone()).await.unwrap();
+        let rag = RagSystem::new(Box::new(db.clone()));
+        let default = TenantContext::default_tenant("ada");
+        rag.add_document(&tenant("acme"), "Tidal turbines spin.", HashMap::new()).await.unwrap();
+
+        // Unowned documents are nobody's until backfilled
+        assert!(rag.retrieve_context(&default, "Wind", 5).await.unwrap().is_empty());
+        assert_eq!(backfill_tenant(&db, talkpp_tenancy::DEFAULT_TENANT).await.unwrap(), 1);
+        assert_eq!(backfill_tenant(&db, talkpp_tenancy::DEFAULT_TENANT).await.unwrap(), 0);
+
+        let found = rag.retrieve_context(&default, "Wind", 5).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(db.documents.lock().unwrap()[&legacy.id].vector, legacy.vector);
+        assert!(rag.retrieve_context(&default, "Tidal", 5).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,94 @@
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use talkpp_errors::Result;
+use uuid::Uuid;
+
+use crate::{CollectionInfo, DocumentPage, SearchResult, SharedEmbeddingModel, VectorDatabase, VectorDocument};
+
+/// Keeps documents in memory and counts upserts
+#[derive(Clone, Default)]
+pub(crate) struct FakeVectorDb {
+    pub(crate) documents: Arc<Mutex<HashMap<Uuid, VectorDocument>>>,
+    pub(crate) upserts: Arc<Mutex<usize>>,
+    /// Reported as the collection's vector size
+    pub(crate) vector_size: u64,
+    pub(crate) model: Option<SharedEmbeddingModel>,
+}
+
+#[async_trait]
+impl VectorDatabase for FakeVectorDb {
+    async fn initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn create_collection(&self, _name: &str, _vector_size: u64) -> Result<()> {
+        Ok(())
+    }
+
+    async fn upsert_document(&self, document: VectorDocument) -> Result<()> {
+        *self.upserts.lock().unwrap() += 1;
+        self.documents.lock().unwrap().insert(document.id, document);
+        Ok(())
+    }
+
+    async fn upsert_documents(&self, documents: Vec<VectorDocument>) -> Result<()> {
+        for document in documents {
+            self.upsert_document(document).await?;
+        }
+        Ok(())
+    }
+
+    async fn search(&self, _query_vector: Vec<f32>, _limit: usize, _filter: Option<HashMap<String, serde_json::Value>>) -> Result<Vec<SearchResult>> {
+        Ok(Vec::new())
+    }
+
+    /// Documents containing `query`, in no particular order
+    async fn search_by_text(&self, query: &str, limit: usize, _filter: Option<HashMap<String, serde_json::Value>>) -> Result<Vec<SearchResult>> {
+        let documents = self.documents.lock().unwrap();
+        Ok(documents.values()
+            .filter(|document| document.content.contains(query))
+            .take(limit)
+            .enumerate()
+            .map(|(rank, document)| SearchResult { document: document.clone(), score: 1.0, raw_score: 1.0, rank })
+            .collect())
+    }
+
+    async fn delete_document(&self, id: Uuid) -> Result<()> {
+        self.documents.lock().unwrap().remove(&id);
+        Ok(())
+    }
+
+    async fn get_document(&self, id: Uuid) -> Result<Option<VectorDocument>> {
+        Ok(self.documents.lock().unwrap().get(&id).cloned())
+    }
+
+    async fn get_collection_info(&self) -> Result<CollectionInfo> {
+        Ok(CollectionInfo {
+            name: "fake".to_string(),
+            vector_size: self.vector_size,
+            distance_metric: None,
+            points_count: self.documents.lock().unwrap().len() as u64,
+            indexed: true,
+        })
+    }
+
+    /// Documents in id order
+    async fn scroll_documents(&self, offset: Option<Uuid>, limit: usize) -> Result<DocumentPage> {
+        let documents = self.documents.lock().unwrap();
+        let mut ids: Vec<Uuid> = documents.keys()
+            .filter(|id| offset.is_none_or(|offset| **id >= offset))
+            .copied()
+            .collect();
+        ids.sort();
+        let next_offset = ids.get(limit).copied();
+        Ok(DocumentPage {
+            documents: ids.iter().take(limit).map(|id| documents[id].clone()).collect(),
+            next_offset,
+        })
+    }
+
+    fn embedding_model(&self) -> Option<SharedEmbeddingModel> {
+        self.model.clone()
+    }
+}
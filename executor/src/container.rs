@@ -0,0 +1,452 @@
+//! Container execution through the Docker (or Podman) API
+
+use crate::{ExecutionContext, ExecutionResult, ExecutionStatus};
+use bollard::container::{
+    AttachContainerOptions, Config, CreateContainerOptions, LogOutput, LogsOptions, RemoveContainerOptions,
+    WaitContainerOptions,
+};
+use bollard::image::CreateImageOptions;
+use bollard::models::HostConfig;
+use bollard::Docker;
+use futures::StreamExt;
+use std::collections::HashMap;
+use talkpp_wrappers::platform::normalize_path;
+use talkpp_wrappers::{Language, LogSink, LogStream};
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
+
+const CODE_MOUNT: &str = "/workspace";
+/// Label carrying the function id, so a function's containers can be found
+pub const FUNCTION_LABEL: &str = "talkpp.function_id";
+
+#[derive(Error, Debug)]
+pub enum ContainerError {
+    #[error("Container daemon unreachable: {message}")]
+    DaemonUnreachable { message: String },
+
+    #[error("Image missing: {image}")]
+    ImageMissing { image: String },
+
+    #[error("Container exited with code {exit_code}: {stderr}")]
+    NonZeroExit {
+        exit_code: i64,
+        stdout: String,
+        stderr: String,
+    },
+
+    #[error("Unsupported language for containers: {language:?}")]
+    UnsupportedLanguage { language: Option<Language> },
+
+    #[error("Docker API error: {source}")]
+    Api {
+        #[from]
+        source: bollard::errors::Error,
+    },
+
+    #[error("IO error: {source}")]
+    Io {
+        #[from]
+        source: std::io::Error,
+    },
+}
+
+/// Container resource limits and image selection
+#[derive(Debug, Clone)]
+pub struct ContainerConfig {
+    pub images: HashMap<Language, String>,
+    pub memory_bytes: i64,
+    pub cpu_shares: i64,
+    pub pids_limit: i64,
+    pub network_enabled: bool,
+    /// Pull images that aren't present locally instead of failing
+    pub auto_pull: bool,
+}
+
+impl Default for ContainerConfig {
+    fn default() -> Self {
+        Self {
+            images: HashMap::from([
+                (Language::Python, "python:3.12-slim".to_string()),
+                (Language::JavaScript, "node:20-slim".to_string()),
+                (Language::Bash, "bash:5".to_string()),
+            ]),
+            memory_bytes: 256 * 1024 * 1024,
+            cpu_shares: 512,
+            pids_limit: 64,
+            network_enabled: false,
+            auto_pull: false,
+        }
+    }
+}
+
+impl ContainerConfig {
+    /// Host configuration: no network, read-only rootfs with a writable /tmp, and resource caps
+    pub fn host_config(&self, code_dir: &str) -> HostConfig {
+        HostConfig {
+            memory: Some(self.memory_bytes),
+            cpu_shares: Some(self.cpu_shares),
+            pids_limit: Some(self.pids_limit),
+            network_mode: if self.network_enabled { None } else { Some("none".to_string()) },
+            readonly_rootfs: Some(true),
+            tmpfs: Some(HashMap::from([("/tmp".to_string(), "rw,size=64m".to_string())])),
+            binds: Some(vec![format!("{}:{}:ro", code_dir, CODE_MOUNT)]),
+            cap_drop: Some(vec!["ALL".to_string()]),
+            ..Default::default()
+        }
+    }
+}
+
+fn entrypoint(language: Language) -> Option<(&'static str, Vec<String>)> {
+    let (file, command) = match language {
+        Language::Python => ("main.py", "python"),
+        Language::JavaScript => ("main.js", "node"),
+        Language::Bash => ("main.sh", "bash"),
+        _ => return None,
+    };
+    Some((file, vec![command.to_string(), format!("{}/{}", CODE_MOUNT, file)]))
+}
+
+/// Removes the container when dropped, so timeouts and panics never leak containers
+struct ContainerGuard {
+    docker: Docker,
+    id: Option<String>,
+}
+
+impl ContainerGuard {
+    async fn remove(mut self) {
+        if let Some(id) = self.id.take() {
+            remove_container(&self.docker, &id).await;
+        }
+    }
+}
+
+impl Drop for ContainerGuard {
+    fn drop(&mut self) {
+        if let Some(id) = self.id.take() {
+            let docker = self.docker.clone();
+            if let Ok(handle) = tokio::runtime::Handle::try_current() {
+                handle.spawn(async move { remove_container(&docker, &id).await });
+            }
+        }
+    }
+}
+
+async fn remove_container(docker: &Docker, id: &str) {
+    let options = RemoveContainerOptions { force: true, ..Default::default() };
+    if let Err(e) = docker.remove_container(id, Some(options)).await {
+        warn!("Failed to remove container {}: {}", id, e);
+    }
+}
+
+/// Runs functions in short-lived, locked-down containers
+pub struct ContainerRuntime {
+    docker: Docker,
+    config: ContainerConfig,
+}
+
+impl ContainerRuntime {
+    pub fn new(config: ContainerConfig) -> Result<Self, ContainerError> {
+        let docker = Docker::connect_with_local_defaults()
+            .map_err(|e| ContainerError::DaemonUnreachable { message: e.to_string() })?;
+        Ok(Self { docker, config })
+    }
+
+    pub async fn ping(&self) -> Result<(), ContainerError> {
+        self.docker.ping().await
+            .map(|_| ())
+            .map_err(|e| ContainerError::DaemonUnreachable { message: e.to_string() })
+    }
+
+    async fn ensure_image(&self, image: &str) -> Result<(), ContainerError> {
+        match self.docker.inspect_image(image).await {
+            Ok(_) => Ok(()),
+            Err(bollard::errors::Error::DockerResponseServerError { status_code: 404, .. }) => {
+                if !self.config.auto_pull {
+                    return Err(ContainerError::ImageMissing { image: image.to_string() });
+                }
+                info!("Pulling image {}", image);
+                let options = CreateImageOptions { from_image: image, ..Default::default() };
+                let mut pull = self.docker.create_image(Some(options), None, None);
+                while let Some(progress) = pull.next().await {
+                    progress?;
+                }
+                Ok(())
+            }
+            Err(e) => Err(ContainerError::DaemonUnreachable { message: e.to_string() }),
+        }
+    }
+
+    pub async fn execute(&self, code: &str, context: &ExecutionContext) -> Result<ExecutionResult, ContainerError> {
+        let unsupported = || ContainerError::UnsupportedLanguage { language: context.language };
+        let language = context.language.ok_or_else(unsupported)?;
+        let image = self.config.images.get(&language).ok_or_else(unsupported)?;
+        let (file, cmd) = entrypoint(language).ok_or_else(unsupported)?;
+
+        self.ensure_image(image).await?;
+
+        let code_dir = tempfile::tempdir()?;
+        std::fs::write(code_dir.path().join(file), code)?;
+
+        let config = Config {
+            image: Some(image.clone()),
+            cmd: Some(cmd),
+            env: Some(context.environment.iter().map(|(k, v)| format!("{}={}", k, v)).collect()),
+            working_dir: Some("/tmp".to_string()),
+            labels: Some(HashMap::from([(FUNCTION_LABEL.to_string(), context.function_id.to_string())])),
+            // stdin is closed once the attached writer shuts down
+            attach_stdin: Some(context.stdin.is_some()),
+            open_stdin: Some(context.stdin.is_some()),
+            stdin_once: Some(context.stdin.is_some()),
+            network_disabled: Some(!self.config.network_enabled),
+            host_config: Some(self.config.host_config(&normalize_path(code_dir.path()).to_string_lossy())),
+            ..Default::default()
+        };
+        let name = format!("talkpp-{}", uuid::Uuid::new_v4());
+        let created = self.docker
+            .create_container(Some(CreateContainerOptions { name: name.as_str(), platform: None }), config)
+            .await?;
+        let guard = ContainerGuard { docker: self.docker.clone(), id: Some(created.id.clone()) };
+
+        // Attach before starting so nothing the function reads is missed
+        let stdin = match &context.stdin {
+            Some(data) => {
+                let options = AttachContainerOptions::<String> { stdin: Some(true), stream: Some(true), ..Default::default() };
+                let attached = self.docker.attach_container(&created.id, Some(options)).await?;
+                Some((attached.input, data.clone()))
+            }
+            None => None,
+        };
+
+        let start_time = std::time::Instant::now();
+        self.docker.start_container::<String>(&created.id, None).await?;
+
+        // Written in the background: a function that never reads its stdin must not block us
+        if let Some((mut input, data)) = stdin {
+            let id = created.id.clone();
+            tokio::spawn(async move {
+                if let Err(e) = async { input.write_all(&data).await?; input.shutdown().await }.await {
+                    warn!("Failed to write stdin to container {}: {}", id, e);
+                }
+            });
+        }
+
+        let wait = async {
+            let mut stream = self.docker.wait_container(&created.id, None::<WaitContainerOptions<String>>);
+            match stream.next().await {
+                Some(Ok(response)) => Ok(response.status_code),
+                // bollard reports non-zero exits as an error carrying the code
+                Some(Err(bollard::errors::Error::DockerContainerWaitError { code, .. })) => Ok(code),
+                Some(Err(e)) => Err(ContainerError::from(e)),
+                None => Ok(0),
+            }
+        };
+
+        // With a log sink, logs are followed while the container runs instead of being
+        // fetched once it exits
+        let run = async {
+            match &context.log_sink {
+                Some(sink) => {
+                    let (exit_code, logs) = tokio::join!(wait, self.collect_logs(&created.id, Some(sink)));
+                    Ok::<_, ContainerError>((exit_code?, Some(logs?)))
+                }
+                None => Ok((wait.await?, None)),
+            }
+        };
+
+        let timeout = std::time::Duration::from_secs(context.timeout_seconds);
+        // Timeouts and cancellation are reported as statuses, like the other runtimes do
+        let stopped = tokio::select! {
+            biased;
+            result = run => Ok(result?),
+            _ = tokio::time::sleep(timeout) => Err((
+                ExecutionStatus::TimedOut,
+                format!("Execution timed out after {}s", context.timeout_seconds),
+            )),
+            _ = context.cancelled() => Err((ExecutionStatus::Cancelled, "Execution cancelled".to_string())),
+        };
+        let (exit_code, logs) = match stopped {
+            Ok(finished) => finished,
+            Err((status, error)) => {
+                info!("{}, killing container {}", error, created.id);
+                if let Err(e) = self.docker.kill_container::<String>(&created.id, None).await {
+                    warn!("Failed to kill container {}: {}", created.id, e);
+                }
+                let (stdout, _) = self.collect_logs(&created.id, None).await.unwrap_or_default();
+                guard.remove().await;
+                return Ok(ExecutionResult {
+                    success: false,
+                    status,
+                    output: stdout,
+                    error: Some(error),
+                    execution_time_ms: start_time.elapsed().as_millis() as u64,
+                    exit_code: None,
+                    fuel_consumed: None,
+                    artifacts: Vec::new(),
+                });
+            }
+        };
+        let execution_time_ms = start_time.elapsed().as_millis() as u64;
+
+        let (stdout, stderr) = match logs {
+            Some(logs) => logs,
+            None => self.collect_logs(&created.id, None).await?,
+        };
+        guard.remove().await;
+
+        if exit_code != 0 {
+            return Err(ContainerError::NonZeroExit { exit_code, stdout, stderr });
+        }
+
+        Ok(ExecutionResult {
+            success: true,
+            status: ExecutionStatus::Succeeded,
+            output: stdout,
+            error: None,
+            execution_time_ms,
+            exit_code: Some(exit_code as i32),
+            fuel_consumed: None,
+            artifacts: Vec::new(),
+        })
+    }
+
+    /// Read a container's stdout and stderr, following them until it exits and forwarding
+    /// each line when `sink` is given
+    async fn collect_logs(&self, id: &str, sink: Option<&LogSink>) -> Result<(String, String), ContainerError> {
+        let options = LogsOptions::<String> { stdout: true, stderr: true, follow: sink.is_some(), ..Default::default() };
+        let mut logs = self.docker.logs(id, Some(options));
+        let (mut stdout, mut stderr) = (String::new(), String::new());
+        let mut stdout_lines = sink.map(|s| s.lines(LogStream::Stdout));
+        let mut stderr_lines = sink.map(|s| s.lines(LogStream::Stderr));
+
+        while let Some(chunk) = logs.next().await {
+            match chunk? {
+                LogOutput::StdOut { message } => {
+                    stdout_lines.iter_mut().for_each(|l| l.push(&message));
+                    stdout.push_str(&String::from_utf8_lossy(&message));
+                }
+                LogOutput::StdErr { message } => {
+                    stderr_lines.iter_mut().for_each(|l| l.push(&message));
+                    stderr.push_str(&String::from_utf8_lossy(&message));
+                }
+                _ => {}
+            }
+        }
+        stdout_lines.into_iter().chain(stderr_lines).for_each(|l| l.finish());
+        Ok((stdout, stderr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host_config_locks_down_container() {
+        let host = ContainerConfig::default().host_config("/tmp/code");
+
+        assert_eq!(host.network_mode.as_deref(), Some("none"));
+        assert_eq!(host.readonly_rootfs, Some(true));
+        assert_eq!(host.pids_limit, Some(64));
+        assert!(host.tmpfs.unwrap().contains_key("/tmp"));
+        assert_eq!(host.binds.unwrap(), vec!["/tmp/code:/workspace:ro".to_string()]);
+    }
+
+    #[cfg(feature = "docker")]
+    mod docker {
+        use super::*;
+        use crate::RuntimeType;
+        use bollard::container::ListContainersOptions;
+
+        async fn runtime() -> Option<ContainerRuntime> {
+            let runtime = ContainerRuntime::new(ContainerConfig { auto_pull: true, ..Default::default() }).ok()?;
+            if runtime.ping().await.is_err() {
+                eprintln!("skipping: no container daemon available");
+                return None;
+            }
+            Some(runtime)
+        }
+
+        fn context(timeout_seconds: u64) -> ExecutionContext {
+            ExecutionContext {
+                function_id: uuid::Uuid::new_v4(),
+                runtime_type: RuntimeType::Container,
+                language: Some(Language::Python),
+                environment: HashMap::from([("GREETING".to_string(), "hi".to_string())]),
+                stdin: None,
+                timeout_seconds,
+                log_sink: None,
+                cancel: None,
+            }
+        }
+
+        #[tokio::test]
+        async fn test_python_container_runs() {
+            let Some(runtime) = runtime().await else { return };
+            let result = runtime.execute("import os\nprint(os.environ['GREETING'])\n", &context(60)).await.unwrap();
+            assert_eq!(result.output.trim(), "hi");
+        }
+
+        #[tokio::test]
+        async fn test_non_zero_exit_surfaced() {
+            let Some(runtime) = runtime().await else { return };
+            let err = runtime.execute("import sys\nsys.exit(4)\n", &context(60)).await.unwrap_err();
+            assert!(matches!(err, ContainerError::NonZeroExit { exit_code: 4, .. }));
+        }
+
+        #[tokio::test]
+        async fn test_cancellation_kills_container_and_keeps_output() {
+            let Some(runtime) = runtime().await else { return };
+            let cancel = tokio_util::sync::CancellationToken::new();
+            let token = cancel.clone();
+            let sink = LogSink::new(move |_, line| {
+                if line == "working" {
+                    token.cancel();
+                }
+            });
+            let context = ExecutionContext { log_sink: Some(sink), cancel: Some(cancel), ..context(120) };
+
+            let code = "import time\nprint('working', flush=True)\ntime.sleep(60)\n";
+            let result = runtime.execute(code, &context).await.unwrap();
+            assert_eq!(result.status, ExecutionStatus::Cancelled);
+            assert_eq!(result.output, "working\n");
+            assert!(result.execution_time_ms < 30_000);
+        }
+
+        #[tokio::test]
+        async fn test_stdin_reaches_container() {
+            let Some(runtime) = runtime().await else { return };
+            let context = ExecutionContext { stdin: Some(b"piped input".to_vec()), ..context(60) };
+            let result = runtime.execute("import sys\nprint(sys.stdin.read().upper())\n", &context).await.unwrap();
+            assert_eq!(result.output.trim(), "PIPED INPUT");
+        }
+
+        #[tokio::test]
+        async fn test_timeout_removes_container() {
+            let Some(runtime) = runtime().await else { return };
+            let context = context(2);
+
+            // Find the container while it runs, then check it's gone once the timeout fires
+            let find = async {
+                let label = format!("{}={}", FUNCTION_LABEL, context.function_id);
+                let filters = HashMap::from([("label".to_string(), vec![label])]);
+                loop {
+                    let options = ListContainersOptions { all: true, filters: filters.clone(), ..Default::default() };
+                    let containers = runtime.docker.list_containers(Some(options)).await.unwrap();
+                    if let Some(id) = containers.into_iter().find_map(|c| c.id) {
+                        return id;
+                    }
+                    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                }
+            };
+            let (result, id) = tokio::join!(runtime.execute("import time\ntime.sleep(60)\n", &context), find);
+
+            assert_eq!(result.unwrap().status, ExecutionStatus::TimedOut);
+            let inspected = runtime.docker.inspect_container(&id, None).await;
+            assert!(matches!(
+                inspected,
+                Err(bollard::errors::Error::DockerResponseServerError { status_code: 404, .. })
+            ));
+        }
+    }
+}
@@ -7,13 +7,21 @@ pub mod process;
 pub mod wasm;
 
 use anyhow::Result;
+use cognitive_kernel::ArtifactStore;
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use talkpp_wrappers::workdir::WorkdirConfig;
+use talkpp_wrappers::ResourceLimits;
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
 /// Function executor
 pub struct Executor {
     id: Uuid,
     runtime_type: RuntimeType,
+    workdirs: WorkdirConfig,
+    limits: ResourceLimits,
+    artifacts: Option<Arc<dyn ArtifactStore>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -27,16 +35,61 @@ pub enum RuntimeType {
 pub struct ExecutionContext {
     pub function_id: Uuid,
     pub runtime_type: RuntimeType,
+    /// Source language, required by the process runtime to pick a wrapper
+    #[serde(default)]
+    pub language: Option<talkpp_wrappers::Language>,
     pub environment: std::collections::HashMap<String, String>,
+    /// Data piped to the function's stdin
+    #[serde(default)]
+    pub stdin: Option<Vec<u8>>,
     pub timeout_seconds: u64,
+    /// Receives output as it is produced, for runtimes that can stream it
+    #[serde(skip)]
+    pub log_sink: Option<talkpp_wrappers::LogSink>,
+    /// Stops the execution when cancelled: processes and containers are killed and WASM
+    /// is interrupted. Cancelling after the execution finished does nothing.
+    #[serde(skip)]
+    pub cancel: Option<CancellationToken>,
+}
+
+impl ExecutionContext {
+    /// Resolves when the context's token is cancelled, or never without one
+    pub async fn cancelled(&self) {
+        match &self.cancel {
+            Some(cancel) => cancel.cancelled().await,
+            None => std::future::pending().await,
+        }
+    }
+}
+
+/// How an execution ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecutionStatus {
+    Succeeded,
+    /// Ran to completion but failed, such as by exiting non-zero or trapping
+    Failed,
+    TimedOut,
+    /// Stopped through the context's cancellation token; the output is what was
+    /// captured until then
+    Cancelled,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExecutionResult {
     pub success: bool,
+    pub status: ExecutionStatus,
     pub output: String,
     pub error: Option<String>,
     pub execution_time_ms: u64,
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+    /// Fuel consumed by WASM executions
+    #[serde(default)]
+    pub fuel_consumed: Option<u64>,
+    /// Files the execution left in its working directory, as stored
+    #[serde(default)]
+    pub artifacts: Vec<cognitive_kernel::ArtifactRef>,
 }
 
 impl Executor {
@@ -45,10 +98,33 @@ impl Executor {
         Self {
             id: Uuid::new_v4(),
             runtime_type,
+            workdirs: WorkdirConfig::default(),
+            limits: ResourceLimits::default(),
+            artifacts: None,
         }
     }
 
-    /// Execute a function with the given context
+    /// Memory, CPU and output limits of process executions; their timeout comes from
+    /// each context
+    pub fn with_limits(mut self, limits: ResourceLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Where process executions get their working directories, and how they are limited
+    pub fn with_workdirs(mut self, workdirs: WorkdirConfig) -> Self {
+        self.workdirs = workdirs;
+        self
+    }
+
+    /// Store the files process executions leave behind as artifacts
+    pub fn with_artifact_store(mut self, store: Arc<dyn ArtifactStore>) -> Self {
+        self.artifacts = Some(store);
+        self
+    }
+
+    /// Execute a function with the given context. Cancelling `context.cancel` stops it,
+    /// with a result whose status is [`ExecutionStatus::Cancelled`].
     pub async fn execute(&self, code: &str, context: ExecutionContext) -> Result<ExecutionResult> {
         tracing::info!("Executing function {} with runtime {:?}", context.function_id, context.runtime_type);
         
@@ -62,33 +138,25 @@ impl Executor {
         }
     }
 
-    async fn execute_container(&self, _code: &str, _context: &ExecutionContext) -> Result<ExecutionResult> {
-        // TODO: Implement container execution
-        Ok(ExecutionResult {
-            success: true,
-            output: "Container execution completed".to_string(),
-            error: None,
-            execution_time_ms: 100,
-        })
+    async fn execute_container(&self, code: &str, context: &ExecutionContext) -> Result<ExecutionResult> {
+        let runtime = container::ContainerRuntime::new(container::ContainerConfig::default())?;
+        Ok(runtime.execute(code, context).await?)
     }
 
-    async fn execute_process(&self, _code: &str, _context: &ExecutionContext) -> Result<ExecutionResult> {
-        // TODO: Implement process execution
-        Ok(ExecutionResult {
-            success: true,
-            output: "Process execution completed".to_string(),
-            error: None,
-            execution_time_ms: 50,
-        })
+    async fn execute_process(&self, code: &str, context: &ExecutionContext) -> Result<ExecutionResult> {
+        let language = context.language
+            .ok_or_else(|| anyhow::anyhow!("Process runtime requires a language for function {}", context.function_id))?;
+
+        let mut runtime = process::ProcessRuntime::new(language)?
+            .with_limits(self.limits.clone())
+            .with_workdirs(self.workdirs.clone());
+        if let Some(store) = &self.artifacts {
+            runtime = runtime.with_artifact_store(store.clone());
+        }
+        runtime.execute(code, context).await
     }
 
-    async fn execute_wasm(&self, _code: &str, _context: &ExecutionContext) -> Result<ExecutionResult> {
-        // TODO: Implement WASM execution
-        Ok(ExecutionResult {
-            success: true,
-            output: "WASM execution completed".to_string(),
-            error: None,
-            execution_time_ms: 25,
-        })
+    async fn execute_wasm(&self, code: &str, context: &ExecutionContext) -> Result<ExecutionResult> {
+        wasm::WasmRuntime::new()?.execute(code.as_bytes(), context).await
     }
 } 
\ No newline at end of file
@@ -0,0 +1,349 @@
+//! Process-based execution through the language wrappers
+
+use crate::{ExecutionContext, ExecutionResult, ExecutionStatus};
+use anyhow::Result;
+use cognitive_kernel::{ArtifactMetadata, ArtifactRef, ArtifactStore};
+use std::sync::Arc;
+use talkpp_wrappers::workdir::{ExecutionDir, WorkdirConfig};
+use talkpp_wrappers::{ExecutionRequest, Language, ResourceLimits, WrapperFactory};
+use tracing::warn;
+
+/// Runs functions as local subprocesses of the matching language wrapper.
+///
+/// Limits and cleanup follow the platform: process groups and rlimits on unix, Job
+/// Objects on Windows (see [`talkpp_wrappers::platform`]). Bash functions need bash on
+/// Windows too, unless the wrapper is set to fall back to PowerShell.
+///
+/// Each execution gets a fresh working directory, also named by `OUTPUT_DIR`. The files
+/// a successful execution leaves there are stored as artifacts when there is a store;
+/// see [`talkpp_wrappers::workdir`] for quotas and for keeping failed executions' files.
+///
+/// The wrapper enforces the limits, killing the process group of an execution that runs
+/// past its context's `timeout_seconds`.
+pub struct ProcessRuntime {
+    language: Language,
+    limits: ResourceLimits,
+    workdirs: WorkdirConfig,
+    artifacts: Option<Arc<dyn ArtifactStore>>,
+}
+
+impl ProcessRuntime {
+    /// Fails if the language's interpreter or toolchain is not installed
+    pub fn new(language: Language) -> Result<Self> {
+        WrapperFactory::create_wrapper(language)?;
+        Ok(Self {
+            language,
+            limits: ResourceLimits::default(),
+            workdirs: WorkdirConfig::default(),
+            artifacts: None,
+        })
+    }
+
+    /// Memory, CPU and output limits for executions; the timeout is replaced by each
+    /// context's `timeout_seconds`
+    pub fn with_limits(mut self, limits: ResourceLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn with_workdirs(mut self, workdirs: WorkdirConfig) -> Self {
+        self.workdirs = workdirs;
+        self
+    }
+
+    pub fn with_artifact_store(mut self, store: Arc<dyn ArtifactStore>) -> Self {
+        self.artifacts = Some(store);
+        self
+    }
+
+    /// Build the wrapper request for a function invocation.
+    ///
+    /// Only the variables in `context.environment` are visible to the function.
+    pub fn build_request(code: &str, context: &ExecutionContext) -> ExecutionRequest {
+        let mut request = ExecutionRequest::new(code);
+        request.env = context.environment.clone();
+        request.stdin = context.stdin.clone();
+        request.log_sink = context.log_sink.clone();
+        request.cancel = context.cancel.clone();
+        request
+    }
+
+    /// The runtime's limits, with the context's timeout
+    pub fn limits(&self, context: &ExecutionContext) -> ResourceLimits {
+        ResourceLimits {
+            timeout: std::time::Duration::from_secs(context.timeout_seconds),
+            ..self.limits.clone()
+        }
+    }
+
+    /// Fails with [`talkpp_wrappers::WrapperError::QuotaExceeded`] if the function left
+    /// more in its working directory than the quota allows
+    pub async fn execute(&self, code: &str, context: &ExecutionContext) -> Result<ExecutionResult> {
+        let wrapper = WrapperFactory::create_wrapper_with_limits(self.language, self.limits(context))?;
+        let dir = ExecutionDir::create(&self.workdirs)?;
+        let request = dir.apply(Self::build_request(code, context));
+        let output = wrapper.execute_with(request).await?;
+
+        // The directory is dropped, and so removed, along with an oversized output
+        dir.check_quota()?;
+        let artifacts = if output.success() { self.capture(&dir).await? } else { Vec::new() };
+        finish(dir, !output.success());
+
+        let status = if output.success() {
+            ExecutionStatus::Succeeded
+        } else if output.cancelled {
+            ExecutionStatus::Cancelled
+        } else if output.timed_out {
+            ExecutionStatus::TimedOut
+        } else {
+            ExecutionStatus::Failed
+        };
+        let error = match status {
+            ExecutionStatus::Succeeded => None,
+            ExecutionStatus::Cancelled => Some("Execution cancelled".to_string()),
+            ExecutionStatus::TimedOut => Some(format!("Execution timed out after {}s", context.timeout_seconds)),
+            ExecutionStatus::Failed => Some(output.stderr.clone()),
+        };
+
+        Ok(ExecutionResult {
+            success: output.success(),
+            status,
+            error,
+            output: output.stdout,
+            execution_time_ms: output.duration.as_millis() as u64,
+            exit_code: output.exit_code,
+            fuel_consumed: None,
+            artifacts,
+        })
+    }
+
+    /// Store the files left in `dir`, if there is a store to put them in
+    async fn capture(&self, dir: &ExecutionDir) -> Result<Vec<ArtifactRef>> {
+        let Some(store) = &self.artifacts else {
+            return Ok(Vec::new());
+        };
+        let mut artifacts = Vec::new();
+        for file in dir.outputs()? {
+            let metadata = ArtifactMetadata::new(file.name.as_str(), content_type(&file.name));
+            artifacts.push(store.put(file.bytes, metadata).await?);
+        }
+        Ok(artifacts)
+    }
+}
+
+/// Remove or retain an execution's directory; failing to is not the execution's failure
+fn finish(dir: ExecutionDir, failed: bool) {
+    let path = dir.path().to_path_buf();
+    if let Err(e) = dir.finish(failed) {
+        warn!("Could not clean up execution directory {}: {}", path.display(), e);
+    }
+}
+
+/// Content type of an output file, going by its extension
+fn content_type(name: &str) -> &'static str {
+    match name.rsplit_once('.').map(|(_, ext)| ext.to_ascii_lowercase()).as_deref() {
+        Some("json") => "application/json",
+        Some("yaml" | "yml") => "application/yaml",
+        Some("toml") => "application/toml",
+        Some("txt" | "log") => "text/plain; charset=utf-8",
+        Some("md") => "text/markdown; charset=utf-8",
+        Some("csv") => "text/csv; charset=utf-8",
+        Some("html") => "text/html; charset=utf-8",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("svg") => "image/svg+xml",
+        Some("pdf") => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RuntimeType;
+    use cognitive_kernel::FsArtifactStore;
+    use std::collections::HashMap;
+    use std::path::Path;
+    use std::time::Duration;
+    use talkpp_wrappers::{LogSink, WrapperError};
+    use tokio_util::sync::CancellationToken;
+
+    fn context() -> ExecutionContext {
+        ExecutionContext {
+            function_id: uuid::Uuid::new_v4(),
+            runtime_type: RuntimeType::Process,
+            language: Some(Language::Python),
+            environment: HashMap::new(),
+            stdin: None,
+            timeout_seconds: 30,
+            log_sink: None,
+            cancel: None,
+        }
+    }
+
+    fn workdirs(root: &Path) -> WorkdirConfig {
+        WorkdirConfig { root: root.to_path_buf(), max_bytes: 4096, retain_failed: None }
+    }
+
+    fn entries(root: &Path) -> Vec<String> {
+        std::fs::read_dir(root).unwrap().map(|e| e.unwrap().file_name().to_string_lossy().into_owned()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_output_files_captured_as_artifacts() {
+        let (root, stored) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let store = Arc::new(FsArtifactStore::new(stored.path()));
+        let runtime = ProcessRuntime::new(Language::Python).unwrap()
+            .with_workdirs(workdirs(root.path()))
+            .with_artifact_store(store.clone());
+
+        let code = "import os
+os.makedirs('reports')
+open('reports/summary.md', 'w').write('# Done')
+open(os.path.join(os.environ['OUTPUT_DIR'], 'config.json'), 'w').write('{}')
+";
+        let result = runtime.execute(code, &context()).await.unwrap();
+        assert!(result.success, "{:?}", result.error);
+
+        let names: Vec<(&str, &str)> = result.artifacts.iter().map(|a| (a.name.as_str(), a.content_type.as_str())).collect();
+        assert_eq!(names, vec![("config.json", "application/json"), ("reports/summary.md", "text/markdown; charset=utf-8")]);
+        assert_eq!(store.get(result.artifacts[1].id).await.unwrap().bytes, b"# Done");
+        assert!(entries(root.path()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_quota_violation_is_a_typed_error() {
+        let root = tempfile::tempdir().unwrap();
+        let runtime = ProcessRuntime::new(Language::Python).unwrap().with_workdirs(workdirs(root.path()));
+
+        let error = runtime.execute("open('big.bin', 'wb').write(b'0' * 8192)
+", &context()).await.unwrap_err();
+        match error.downcast_ref::<WrapperError>() {
+            Some(WrapperError::QuotaExceeded { used: 8192, limit: 4096 }) => {}
+            other => panic!("expected a quota violation, got {:?}", other),
+        }
+        assert!(entries(root.path()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_execution_directories_retained_when_configured() {
+        let root = tempfile::tempdir().unwrap();
+        let code = "open('trace.log', 'w').write('step 3 failed')
+raise SystemExit(1)
+";
+
+        let runtime = ProcessRuntime::new(Language::Python).unwrap().with_workdirs(workdirs(root.path()));
+        assert!(!runtime.execute(code, &context()).await.unwrap().success);
+        assert!(entries(root.path()).is_empty());
+
+        let retaining = WorkdirConfig { retain_failed: Some(Duration::from_secs(3600)), ..workdirs(root.path()) };
+        let runtime = ProcessRuntime::new(Language::Python).unwrap().with_workdirs(retaining);
+        let result = runtime.execute(code, &context()).await.unwrap();
+        assert!(!result.success);
+        assert!(result.artifacts.is_empty());
+
+        let retained = entries(root.path());
+        assert_eq!(retained.len(), 1);
+        assert!(retained[0].starts_with("failed-"));
+        let trace = std::fs::read_to_string(root.path().join(&retained[0]).join("trace.log")).unwrap();
+        assert_eq!(trace, "step 3 failed");
+    }
+
+    #[tokio::test]
+    async fn test_timeout_kills_the_process_group() {
+        let (root, scratch) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let runtime = ProcessRuntime::new(Language::Python).unwrap().with_workdirs(workdirs(root.path()));
+        let marker = scratch.path().join("survived");
+        let context = ExecutionContext {
+            timeout_seconds: 1,
+            environment: HashMap::from([("MARKER".to_string(), marker.display().to_string())]),
+            ..context()
+        };
+
+        let code = "import subprocess, time
+subprocess.Popen(['sh', '-c', 'sleep 2 && touch \"$MARKER\"'])
+print('started', flush=True)
+time.sleep(30)
+";
+        let result = runtime.execute(code, &context).await.unwrap();
+        assert_eq!(result.status, ExecutionStatus::TimedOut);
+        assert_eq!(result.error.as_deref(), Some("Execution timed out after 1s"));
+        assert_eq!(result.output, "started\n");
+        assert!(result.execution_time_ms < 10_000);
+
+        // The child the function started was killed with it
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert!(!marker.exists());
+        assert!(entries(root.path()).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_memory_limit_reaches_the_process() {
+        let root = tempfile::tempdir().unwrap();
+        let limits = ResourceLimits { max_memory_bytes: Some(256 * 1024 * 1024), ..ResourceLimits::default() };
+        let runtime = ProcessRuntime::new(Language::Python).unwrap()
+            .with_limits(limits)
+            .with_workdirs(workdirs(root.path()));
+
+        let result = runtime.execute("data = bytearray(1024 * 1024 * 1024)\n", &context()).await.unwrap();
+        assert_eq!(result.status, ExecutionStatus::Failed);
+        assert!(result.error.as_deref().unwrap_or_default().contains("MemoryError"), "{:?}", result.error);
+    }
+
+    /// A context cancelled as soon as the function writes `line`
+    fn cancelled_on(line: &'static str) -> (ExecutionContext, CancellationToken) {
+        let cancel = CancellationToken::new();
+        let token = cancel.clone();
+        let sink = LogSink::new(move |_, written| {
+            if written == line {
+                token.cancel();
+            }
+        });
+        (ExecutionContext { log_sink: Some(sink), cancel: Some(cancel.clone()), ..context() }, cancel)
+    }
+
+    #[tokio::test]
+    async fn test_cancelling_a_sleeping_process_keeps_partial_output() {
+        let root = tempfile::tempdir().unwrap();
+        let runtime = ProcessRuntime::new(Language::Python).unwrap().with_workdirs(workdirs(root.path()));
+        let (context, _) = cancelled_on("working");
+
+        let code = "import time
+print('working', flush=True)
+time.sleep(30)
+print('unreachable')
+";
+        let result = runtime.execute(code, &context).await.unwrap();
+        assert_eq!(result.status, ExecutionStatus::Cancelled);
+        assert!(!result.success);
+        assert_eq!(result.output, "working\n");
+        assert_eq!(result.error.as_deref(), Some("Execution cancelled"));
+        assert!(result.execution_time_ms < 10_000);
+        assert!(entries(root.path()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cancellation_racing_completion_is_harmless() {
+        let root = tempfile::tempdir().unwrap();
+        let runtime = ProcessRuntime::new(Language::Python).unwrap().with_workdirs(workdirs(root.path()));
+
+        // Cancelled as the function prints its last line, so either may come first
+        for _ in 0..5 {
+            let (context, cancel) = cancelled_on("done");
+            let result = runtime.execute("print('done')\n", &context).await.unwrap();
+            assert!(matches!(result.status, ExecutionStatus::Succeeded | ExecutionStatus::Cancelled), "{:?}", result);
+            assert_eq!(result.success, result.status == ExecutionStatus::Succeeded);
+            assert_eq!(result.output, "done\n");
+            cancel.cancel();
+        }
+
+        // Cancelling once the function has exited changes nothing
+        let cancel = CancellationToken::new();
+        let context = ExecutionContext { cancel: Some(cancel.clone()), ..context() };
+        let result = runtime.execute("print('done')\n", &context).await.unwrap();
+        cancel.cancel();
+        assert_eq!(result.status, ExecutionStatus::Succeeded);
+        assert!(entries(root.path()).is_empty());
+    }
+}
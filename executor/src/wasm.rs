@@ -0,0 +1,302 @@
+//! WASM execution with wasmtime and WASI preview1
+
+use crate::{ExecutionContext, ExecutionResult, ExecutionStatus};
+use anyhow::Result;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use wasi_common::pipe::{ReadPipe, WritePipe};
+use wasi_common::sync::WasiCtxBuilder;
+use wasi_common::{I32Exit, WasiCtx};
+use wasmtime::{Config, Engine, Linker, Module, Store, StoreLimits, StoreLimitsBuilder, Trap, UpdateDeadline};
+
+const DEFAULT_MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+const INITIAL_FUEL: u64 = u64::MAX;
+
+struct WasmState {
+    wasi: WasiCtx,
+    limits: StoreLimits,
+}
+
+/// Sandboxed WASM runtime.
+///
+/// Modules may only import WASI preview1; `timeout_seconds` and the context's
+/// cancellation token are enforced with epoch interruption, and linear memory is capped
+/// by store limits.
+///
+/// Executions share the engine and so its epoch. An execution being stopped sets its own
+/// flag before bumping the epoch; every running store then checks its own flag in its
+/// deadline callback, and only the flagged one traps.
+pub struct WasmRuntime {
+    engine: Engine,
+    memory_limit: usize,
+}
+
+impl WasmRuntime {
+    pub fn new() -> Result<Self> {
+        let mut config = Config::new();
+        config.epoch_interruption(true);
+        config.consume_fuel(true);
+
+        Ok(Self {
+            engine: Engine::new(&config)?,
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+        })
+    }
+
+    pub fn with_memory_limit(mut self, bytes: usize) -> Self {
+        self.memory_limit = bytes;
+        self
+    }
+
+    /// Execute a module given as WAT text or binary
+    pub async fn execute(&self, module: &[u8], context: &ExecutionContext) -> Result<ExecutionResult> {
+        let module = Module::new(&self.engine, module)?;
+
+        let mut linker: Linker<WasmState> = Linker::new(&self.engine);
+        wasi_common::sync::add_to_linker(&mut linker, |s: &mut WasmState| &mut s.wasi)?;
+
+        let stdout = WritePipe::new_in_memory();
+        let stderr = WritePipe::new_in_memory();
+        let envs: Vec<(String, String)> = context.environment.clone().into_iter().collect();
+        let wasi = WasiCtxBuilder::new()
+            .stdin(Box::new(ReadPipe::from(context.stdin.clone().unwrap_or_default())))
+            .stdout(Box::new(stdout.clone()))
+            .stderr(Box::new(stderr.clone()))
+            .envs(&envs)?
+            .build();
+
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(self.memory_limit)
+            .trap_on_grow_failure(true)
+            .build();
+        let mut store = Store::new(&self.engine, WasmState { wasi, limits });
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(INITIAL_FUEL)?;
+        store.set_epoch_deadline(1);
+        let interrupted = Arc::new(AtomicBool::new(false));
+        store.epoch_deadline_callback({
+            let interrupted = interrupted.clone();
+            move |_| {
+                if interrupted.load(Ordering::SeqCst) {
+                    return Err(Trap::Interrupt.into());
+                }
+                // Another execution was stopped; keep running until the next bump
+                Ok(UpdateDeadline::Continue(1))
+            }
+        });
+
+        let unsupported: Vec<String> = module.imports()
+            .filter(|import| linker.get_by_import(&mut store, import).is_none())
+            .map(|import| format!("{}::{}", import.module(), import.name()))
+            .collect();
+        if !unsupported.is_empty() {
+            return Err(anyhow::anyhow!("Unsupported imports: {}", unsupported.join(", ")));
+        }
+
+        // Interrupts the module at the timeout or on cancellation, whichever comes first,
+        // noting which it was
+        let engine = self.engine.clone();
+        let timeout = Duration::from_secs(context.timeout_seconds);
+        let cancel = context.cancel.clone().unwrap_or_default();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let watchdog = tokio::spawn({
+            let cancelled = cancelled.clone();
+            async move {
+                tokio::select! {
+                    _ = tokio::time::sleep(timeout) => {}
+                    _ = cancel.cancelled() => cancelled.store(true, Ordering::SeqCst),
+                }
+                interrupted.store(true, Ordering::SeqCst);
+                engine.increment_epoch();
+            }
+        });
+
+        let start_time = Instant::now();
+        let outcome = tokio::task::spawn_blocking(move || {
+            let outcome = linker
+                .instantiate(&mut store, &module)
+                .and_then(|instance| instance.get_typed_func::<(), ()>(&mut store, "_start"))
+                .and_then(|start| start.call(&mut store, ()));
+            let fuel = INITIAL_FUEL - store.get_fuel().unwrap_or(INITIAL_FUEL);
+            (outcome, fuel)
+        })
+        .await;
+        watchdog.abort();
+        let (outcome, fuel_consumed) = outcome?;
+        let execution_time_ms = start_time.elapsed().as_millis() as u64;
+
+        let read_pipe = |pipe: WritePipe<std::io::Cursor<Vec<u8>>>| {
+            pipe.try_into_inner()
+                .map(|cursor| String::from_utf8_lossy(&cursor.into_inner()).into_owned())
+                .unwrap_or_default()
+        };
+        let output = read_pipe(stdout);
+        let stderr = read_pipe(stderr);
+
+        // A module that returned before the interrupt landed finished on its own
+        let (status, exit_code, error) = match outcome {
+            Ok(()) => (ExecutionStatus::Succeeded, Some(0), None),
+            Err(e) => match e.downcast_ref::<I32Exit>() {
+                Some(I32Exit(0)) => (ExecutionStatus::Succeeded, Some(0), None),
+                Some(exit) => (ExecutionStatus::Failed, Some(exit.0), None),
+                None if matches!(e.downcast_ref::<wasmtime::Trap>(), Some(wasmtime::Trap::Interrupt)) => {
+                    if cancelled.load(Ordering::SeqCst) {
+                        (ExecutionStatus::Cancelled, None, Some("Execution cancelled".to_string()))
+                    } else {
+                        (ExecutionStatus::TimedOut, None, Some(format!("Execution timed out after {}s", context.timeout_seconds)))
+                    }
+                }
+                None => (ExecutionStatus::Failed, None, Some(format!("{:#}", e))),
+            },
+        };
+
+        Ok(ExecutionResult {
+            success: status == ExecutionStatus::Succeeded,
+            status,
+            output,
+            error: error.or_else(|| exit_code.filter(|c| *c != 0).map(|_| stderr)),
+            execution_time_ms,
+            exit_code,
+            fuel_consumed: Some(fuel_consumed),
+            artifacts: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RuntimeType;
+    use std::collections::HashMap;
+
+    fn context(timeout_seconds: u64) -> ExecutionContext {
+        ExecutionContext {
+            function_id: uuid::Uuid::new_v4(),
+            runtime_type: RuntimeType::Wasm,
+            language: None,
+            environment: HashMap::new(),
+            stdin: None,
+            timeout_seconds,
+            log_sink: None,
+            cancel: None,
+        }
+    }
+
+    const HELLO: &str = r#"
+        (module
+            (import "wasi_snapshot_preview1" "fd_write"
+                (func $fd_write (param i32 i32 i32 i32) (result i32)))
+            (memory (export "memory") 1)
+            (data (i32.const 16) "hello wasm\n")
+            (func (export "_start")
+                (i32.store (i32.const 0) (i32.const 16))
+                (i32.store (i32.const 4) (i32.const 11))
+                (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))))
+    "#;
+
+    #[tokio::test]
+    async fn test_hello_world_wasi() {
+        let runtime = WasmRuntime::new().unwrap();
+        let result = runtime.execute(HELLO.as_bytes(), &context(5)).await.unwrap();
+
+        assert!(result.success, "{:?}", result.error);
+        assert_eq!(result.output, "hello wasm\n");
+        assert_eq!(result.exit_code, Some(0));
+        assert!(result.fuel_consumed.unwrap() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_infinite_loop_interrupted_by_epoch() {
+        let runtime = WasmRuntime::new().unwrap();
+        let module = r#"(module (func (export "_start") (loop $l (br $l))))"#;
+
+        let result = runtime.execute(module.as_bytes(), &context(1)).await.unwrap();
+        assert!(!result.success);
+        assert_eq!(result.status, ExecutionStatus::TimedOut);
+        assert!(result.error.unwrap().contains("timed out"));
+        assert!(result.execution_time_ms < 5000);
+    }
+
+    #[tokio::test]
+    async fn test_cancellation_interrupts_infinite_loop() {
+        let runtime = WasmRuntime::new().unwrap();
+        let module = r#"(module (func (export "_start") (loop $l (br $l))))"#;
+        let cancel = tokio_util::sync::CancellationToken::new();
+        let cancellable = ExecutionContext { cancel: Some(cancel.clone()), ..context(60) };
+
+        let canceller = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            cancel.cancel();
+        });
+        let result = runtime.execute(module.as_bytes(), &cancellable).await.unwrap();
+        canceller.await.unwrap();
+
+        assert_eq!(result.status, ExecutionStatus::Cancelled);
+        assert_eq!(result.error.as_deref(), Some("Execution cancelled"));
+        assert!(result.execution_time_ms < 5000);
+
+        // The same runtime runs the next module to completion
+        let result = runtime.execute(HELLO.as_bytes(), &context(5)).await.unwrap();
+        assert_eq!(result.status, ExecutionStatus::Succeeded);
+    }
+
+    #[tokio::test]
+    async fn test_stopping_one_execution_leaves_concurrent_ones_running() {
+        let runtime = WasmRuntime::new().unwrap();
+        let module = r#"(module (func (export "_start") (loop $l (br $l))))"#;
+        let cancel = tokio_util::sync::CancellationToken::new();
+        let cancellable = ExecutionContext { cancel: Some(cancel.clone()), ..context(60) };
+
+        // The first times out while the second keeps looping until it is cancelled
+        let (timed_out, cancelled) = tokio::join!(
+            async {
+                let result = runtime.execute(module.as_bytes(), &context(1)).await.unwrap();
+                tokio::time::sleep(Duration::from_millis(500)).await;
+                cancel.cancel();
+                result
+            },
+            runtime.execute(module.as_bytes(), &cancellable),
+        );
+        let cancelled = cancelled.unwrap();
+
+        assert_eq!(timed_out.status, ExecutionStatus::TimedOut);
+        assert_eq!(cancelled.status, ExecutionStatus::Cancelled, "{:?}", cancelled.error);
+        assert!(cancelled.execution_time_ms >= timed_out.execution_time_ms + 400);
+    }
+
+    #[tokio::test]
+    async fn test_memory_growth_bounded() {
+        let runtime = WasmRuntime::new().unwrap().with_memory_limit(2 * 1024 * 1024);
+        // Grow by 1000 pages (~64MB) past the 2MB limit
+        let module = r#"(module (memory 1) (func (export "_start") (drop (memory.grow (i32.const 1000)))))"#;
+
+        let result = runtime.execute(module.as_bytes(), &context(5)).await.unwrap();
+        assert!(!result.success);
+        assert!(result.error.unwrap().contains("memory"));
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_import_rejected() {
+        let runtime = WasmRuntime::new().unwrap();
+        let module = r#"(module (import "env" "host_secret" (func)) (func (export "_start")))"#;
+
+        let err = runtime.execute(module.as_bytes(), &context(5)).await.unwrap_err();
+        assert!(err.to_string().contains("env::host_secret"));
+    }
+
+    #[tokio::test]
+    async fn test_exit_code_reported() {
+        let runtime = WasmRuntime::new().unwrap();
+        let module = r#"
+            (module
+                (import "wasi_snapshot_preview1" "proc_exit" (func $exit (param i32)))
+                (memory (export "memory") 1)
+                (func (export "_start") (call $exit (i32.const 3))))
+        "#;
+
+        let result = runtime.execute(module.as_bytes(), &context(5)).await.unwrap();
+        assert_eq!(result.exit_code, Some(3));
+        assert!(!result.success);
+    }
+}
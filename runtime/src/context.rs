@@ -0,0 +1,21 @@
+//! Runtime-wide execution settings
+
+use anyhow::Result;
+use std::collections::HashMap;
+
+/// Settings shared by every invocation handled by a runtime
+#[derive(Debug, Clone)]
+pub struct RuntimeContext {
+    /// Variables visible to every function in addition to the event
+    pub environment: HashMap<String, String>,
+    pub default_timeout_seconds: u64,
+}
+
+impl RuntimeContext {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            environment: HashMap::new(),
+            default_timeout_seconds: 30,
+        })
+    }
+}
@@ -0,0 +1,56 @@
+//! Dispatch of function invocations to the executor
+//!
+//! Invocation contract: the event is serialized as JSON and written to the function's
+//! stdin, and the same JSON is exposed in the `TALKPP_EVENT` environment variable for
+//! handlers (such as shell scripts) that prefer not to read stdin.
+
+use crate::context::RuntimeContext;
+use crate::event::Event;
+use crate::response::Response;
+use crate::store::DeployedFunction;
+use anyhow::Result;
+use talkpp_executor::{ExecutionContext, Executor};
+use talkpp_wrappers::LogSink;
+use tokio_util::sync::CancellationToken;
+
+/// Environment variable holding the serialized event
+pub const EVENT_ENV: &str = "TALKPP_EVENT";
+
+pub struct ExecutionEngine;
+
+impl ExecutionEngine {
+    pub fn build_context(function: &DeployedFunction, event: &Event, runtime: &RuntimeContext) -> Result<ExecutionContext> {
+        let payload = serde_json::to_string(event)?;
+
+        let mut environment = runtime.environment.clone();
+        environment.insert(EVENT_ENV.to_string(), payload.clone());
+
+        Ok(ExecutionContext {
+            function_id: function.metadata.id,
+            runtime_type: function.runtime_type.clone(),
+            language: Some(function.language),
+            environment,
+            stdin: Some(payload.into_bytes()),
+            timeout_seconds: runtime.default_timeout_seconds,
+            log_sink: None,
+            cancel: None,
+        })
+    }
+
+    /// Run a function, streaming its output to `log_sink` when the runtime supports it
+    /// and stopping it when `cancel` fires
+    pub async fn invoke(
+        function: &DeployedFunction,
+        event: &Event,
+        runtime: &RuntimeContext,
+        log_sink: Option<LogSink>,
+        cancel: CancellationToken,
+    ) -> Result<Response> {
+        let mut context = Self::build_context(function, event, runtime)?;
+        context.log_sink = log_sink;
+        context.cancel = Some(cancel);
+        let executor = Executor::new(function.runtime_type.clone());
+        let result = executor.execute(&function.code, context).await?;
+        Ok(Response::from_execution(result))
+    }
+}
@@ -0,0 +1,214 @@
+//! Function invocation events
+//!
+//! Every invocation is described by an [`Event`]. Handlers generated by the compiler
+//! deserialize the `data` and `context` fields; the remaining fields describe where the
+//! event came from and are available to handlers that care.
+
+use chrono::{DateTime, Utc};
+use jsonschema::JSONSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Where an event originated
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventSource {
+    Http,
+    Schedule,
+    Manual,
+    Webhook { provider: String },
+}
+
+/// Event delivered to a function handler
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    #[serde(default = "Uuid::new_v4")]
+    pub id: Uuid,
+    #[serde(default = "default_source")]
+    pub source: EventSource,
+    #[serde(default = "Utc::now")]
+    pub timestamp: DateTime<Utc>,
+    #[serde(default)]
+    pub data: serde_json::Value,
+    #[serde(default)]
+    pub context: HashMap<String, String>,
+}
+
+fn default_source() -> EventSource {
+    EventSource::Manual
+}
+
+/// A single schema violation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationIssue {
+    /// JSON pointer into the event data, empty for the root
+    pub path: String,
+    pub message: String,
+}
+
+#[derive(Error, Debug)]
+pub enum EventError {
+    #[error("Invalid event JSON: {0}")]
+    InvalidJson(#[from] serde_json::Error),
+
+    #[error("Invalid event schema: {0}")]
+    InvalidSchema(String),
+
+    #[error("Event failed schema validation at {}", format_paths(.issues))]
+    Validation { issues: Vec<ValidationIssue> },
+}
+
+fn format_paths(issues: &[ValidationIssue]) -> String {
+    issues.iter()
+        .map(|i| format!("'{}' ({})", if i.path.is_empty() { "/" } else { &i.path }, i.message))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl Event {
+    fn with_source(source: EventSource, data: serde_json::Value) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            source,
+            timestamp: Utc::now(),
+            data,
+            context: HashMap::new(),
+        }
+    }
+
+    /// Create a manually triggered event
+    pub fn new(data: serde_json::Value) -> Self {
+        Self::manual(data)
+    }
+
+    pub fn manual(data: serde_json::Value) -> Self {
+        Self::with_source(EventSource::Manual, data)
+    }
+
+    pub fn http(data: serde_json::Value) -> Self {
+        Self::with_source(EventSource::Http, data)
+    }
+
+    pub fn schedule(data: serde_json::Value) -> Self {
+        Self::with_source(EventSource::Schedule, data)
+    }
+
+    pub fn webhook(provider: impl Into<String>, data: serde_json::Value) -> Self {
+        Self::with_source(EventSource::Webhook { provider: provider.into() }, data)
+    }
+
+    pub fn with_context(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.context.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn with_timestamp(mut self, timestamp: DateTime<Utc>) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    /// Parse an event, validating its data against `schema` when one is given.
+    ///
+    /// Missing `id`, `source` and `timestamp` fields are filled in, so a bare
+    /// `{"data": ...}` document is accepted.
+    pub fn from_json(json: &str, schema: Option<&serde_json::Value>) -> Result<Self, EventError> {
+        let event: Event = serde_json::from_str(json)?;
+        if let Some(schema) = schema {
+            event.validate(schema)?;
+        }
+        Ok(event)
+    }
+
+    /// Validate the event data against a JSON Schema
+    pub fn validate(&self, schema: &serde_json::Value) -> Result<(), EventError> {
+        let compiled = compile_schema(schema)?;
+        let result = compiled.validate(&self.data);
+        if let Err(errors) = result {
+            let issues = errors
+                .map(|e| ValidationIssue {
+                    path: e.instance_path.to_string(),
+                    message: e.to_string(),
+                })
+                .collect();
+            return Err(EventError::Validation { issues });
+        }
+        Ok(())
+    }
+}
+
+impl Default for Event {
+    fn default() -> Self {
+        Self::manual(serde_json::json!({}))
+    }
+}
+
+/// Compile a JSON Schema, reporting why it is unusable
+pub fn compile_schema(schema: &serde_json::Value) -> Result<JSONSchema, EventError> {
+    JSONSchema::compile(schema).map_err(|e| EventError::InvalidSchema(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schema() -> serde_json::Value {
+        json!({
+            "type": "object",
+            "required": ["name"],
+            "properties": {
+                "name": { "type": "string" },
+                "age": { "type": "integer", "minimum": 0 }
+            }
+        })
+    }
+
+    #[test]
+    fn test_schema_pass() {
+        let event = Event::from_json(r#"{"data": {"name": "ada", "age": 36}}"#, Some(&schema())).unwrap();
+        assert_eq!(event.source, EventSource::Manual);
+        assert_eq!(event.data["name"], "ada");
+    }
+
+    #[test]
+    fn test_schema_fail_lists_paths() {
+        let err = Event::from_json(r#"{"data": {"name": 7, "age": -1}}"#, Some(&schema())).unwrap_err();
+        let EventError::Validation { issues } = &err else {
+            panic!("unexpected error: {err}");
+        };
+
+        let mut paths: Vec<&str> = issues.iter().map(|i| i.path.as_str()).collect();
+        paths.sort();
+        assert_eq!(paths, vec!["/age", "/name"]);
+        assert!(err.to_string().contains("/name"));
+    }
+
+    #[test]
+    fn test_invalid_schema_and_json() {
+        assert!(matches!(
+            Event::default().validate(&json!({"type": 12})),
+            Err(EventError::InvalidSchema(_))
+        ));
+        assert!(matches!(Event::from_json("not json", None), Err(EventError::InvalidJson(_))));
+    }
+
+    #[test]
+    fn test_builder_round_trips() {
+        let events = vec![
+            Event::http(json!({"path": "/hello"})).with_context("method", "GET"),
+            Event::schedule(json!(null)),
+            Event::manual(json!([1, 2, 3])),
+            Event::webhook("github", json!({"action": "opened"})).with_context("delivery", "abc"),
+        ];
+
+        for event in events {
+            let json = serde_json::to_string(&event).unwrap();
+            assert_eq!(Event::from_json(&json, None).unwrap(), event);
+        }
+
+        let json = serde_json::to_value(Event::webhook("stripe", json!({}))).unwrap();
+        assert_eq!(json["source"], json!({"type": "webhook", "provider": "stripe"}));
+    }
+}
@@ -7,25 +7,72 @@ pub mod engine;
 pub mod context;
 pub mod event;
 pub mod response;
+pub mod scheduler;
+pub mod store;
+
+pub use talkpp_support::{DefaultBroker, ServiceBroker, TraceStep};
+pub use talkpp_wrappers::{LogSink, LogStream};
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+use talkpp_compiler::{CompilationArtifact, Provenance};
+use talkpp_executor::RuntimeType;
+use tracing::Instrument;
+use talkpp_wrappers::{Language, WrapperFactory, DEFAULT_DETECTION_THRESHOLD};
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
 /// Main runtime engine
 pub struct Runtime {
     engine_id: Uuid,
     context: context::RuntimeContext,
+    store: store::FunctionStore,
+    scheduler: scheduler::Scheduler,
+    detection_threshold: f32,
+    /// Cancellation tokens of running and queued invocations, by event id
+    in_flight: Mutex<HashMap<Uuid, CancellationToken>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FunctionMetadata {
     pub id: Uuid,
     pub name: String,
+    /// Left empty, the language is detected from the code on deploy
     pub language: String,
+    /// Confidence the language was detected with, if the deployer did not name it
+    #[serde(default)]
+    pub language_confidence: Option<f32>,
     pub version: String,
     pub created_at: chrono::DateTime<chrono::Utc>,
+    /// JSON Schema that event data must satisfy before the function is invoked
+    #[serde(default)]
+    pub event_schema: Option<serde_json::Value>,
+    #[serde(default)]
+    pub concurrency: scheduler::ConcurrencyLimits,
+    /// Source and compiler settings the code was generated from, when it was deployed
+    /// from a compilation artifact
+    #[serde(default)]
+    pub provenance: Option<Provenance>,
+}
+
+impl FunctionMetadata {
+    /// Metadata for a new function at version 1.0.0 with no schema or concurrency limits
+    pub fn new(name: impl Into<String>, language: impl Into<String>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            name: name.into(),
+            language: language.into(),
+            language_confidence: None,
+            version: "1.0.0".to_string(),
+            created_at: chrono::Utc::now(),
+            event_schema: None,
+            concurrency: scheduler::ConcurrencyLimits::default(),
+            provenance: None,
+        }
+    }
 }
 
 impl Runtime {
@@ -34,31 +81,196 @@ impl Runtime {
         Ok(Self {
             engine_id: Uuid::new_v4(),
             context: context::RuntimeContext::new()?,
+            store: store::FunctionStore::in_memory(),
+            scheduler: scheduler::Scheduler::default(),
+            detection_threshold: DEFAULT_DETECTION_THRESHOLD,
+            in_flight: Mutex::default(),
+        })
+    }
+
+    /// Create a runtime whose deployed functions persist in a sled database at `path`
+    pub fn with_persistence(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self {
+            engine_id: Uuid::new_v4(),
+            context: context::RuntimeContext::new()?,
+            store: store::FunctionStore::open(path)?,
+            scheduler: scheduler::Scheduler::default(),
+            detection_threshold: DEFAULT_DETECTION_THRESHOLD,
+            in_flight: Mutex::default(),
         })
     }
 
-    /// Deploy a compiled function to the runtime
-    pub async fn deploy(&mut self, code: &str, metadata: FunctionMetadata) -> Result<Uuid> {
+    /// Limit the number of invocations running at once across all functions
+    pub fn with_global_concurrency(mut self, limit: usize) -> Self {
+        self.scheduler = scheduler::Scheduler::new(limit);
+        self
+    }
+
+    /// Confidence below which a function deployed without a language is rejected
+    /// instead of run as the language its code most resembles
+    pub fn with_detection_threshold(mut self, min_confidence: f32) -> Self {
+        self.detection_threshold = min_confidence;
+        self
+    }
+
+    /// Deploy a compiled function to the runtime using the process runtime
+    pub async fn deploy(&self, code: &str, metadata: FunctionMetadata) -> Result<Uuid> {
+        self.deploy_with_runtime(code, metadata, RuntimeType::Process).await
+    }
+
+    /// Deploy compiled code using the process runtime, recording its provenance with the
+    /// function. Without a language in `metadata`, the compiler's target is used.
+    pub async fn deploy_artifact(&self, artifact: &CompilationArtifact, mut metadata: FunctionMetadata) -> Result<Uuid> {
+        if metadata.language.trim().is_empty() {
+            metadata.language = format!("{:?}", artifact.provenance.target_language).to_lowercase();
+        }
+        metadata.provenance = Some(artifact.provenance.clone());
+        self.deploy(&artifact.code, metadata).await
+    }
+
+    /// Deploy a function, validating it with the wrapper for its language. Without a
+    /// language in `metadata`, the one detected from `code` is used and recorded.
+    pub async fn deploy_with_runtime(&self, code: &str, mut metadata: FunctionMetadata, runtime_type: RuntimeType) -> Result<Uuid> {
         tracing::info!("Deploying function: {}", metadata.name);
-        
-        // TODO: Implement deployment logic
-        
-        Ok(metadata.id)
+
+        let language = if metadata.language.trim().is_empty() {
+            let (language, confidence) = WrapperFactory::detect(code, self.detection_threshold)?;
+            tracing::info!("Detected {} for function {} with confidence {:.2}", language.name(), metadata.name, confidence);
+            metadata.language = language.name().to_string();
+            metadata.language_confidence = Some(confidence);
+            language
+        } else {
+            Language::from_name(&metadata.language)
+                .ok_or_else(|| anyhow::anyhow!("Unsupported language: {}", metadata.language))?
+        };
+
+        WrapperFactory::create_wrapper(language)?.validate(code)?;
+        if let Some(schema) = &metadata.event_schema {
+            event::compile_schema(schema)?;
+        }
+
+        let id = metadata.id;
+        self.store.insert(store::DeployedFunction {
+            metadata,
+            code: code.to_string(),
+            language,
+            runtime_type,
+        })?;
+
+        Ok(id)
+    }
+
+    /// Remove a deployed function, returning whether it existed
+    pub fn undeploy(&self, function_id: Uuid) -> Result<bool> {
+        tracing::info!("Undeploying function: {}", function_id);
+        self.scheduler.forget(&function_id);
+        Ok(self.store.remove(&function_id)?.is_some())
     }
 
     /// Execute a deployed function
+    ///
+    /// Events that fail the function's schema are rejected with an
+    /// [`event::EventError::Validation`]. Invocations beyond the function's concurrency
+    /// limits queue and may fail with a [`scheduler::SchedulerError`]. See [`engine`] for
+    /// how the event is handed to the function, and [`Runtime::cancel_execution`] for
+    /// stopping it by the event's id.
     pub async fn execute(&self, function_id: Uuid, event: event::Event) -> Result<response::Response> {
-        tracing::info!("Executing function: {}", function_id);
-        
-        // TODO: Implement execution logic
-        
-        Ok(response::Response::success("Function executed successfully"))
+        self.invoke(function_id, event, None).await
+    }
+
+    /// Execute a deployed function, streaming its output to `sink` as it runs. Only the
+    /// process and container runtimes stream; WASM output arrives with the response.
+    pub async fn execute_with_logs(&self, function_id: Uuid, event: event::Event, sink: LogSink) -> Result<response::Response> {
+        self.invoke(function_id, event, Some(sink)).await
+    }
+
+    async fn invoke(&self, function_id: Uuid, event: event::Event, log_sink: Option<LogSink>) -> Result<response::Response> {
+        let span = tracing::info_span!("execute", function_id = %function_id, source_hash = tracing::field::Empty);
+        async move {
+            tracing::info!("Executing function: {} on engine {}", function_id, self.engine_id);
+
+            let function = self.store.get(&function_id)
+                .ok_or_else(|| anyhow::anyhow!("Function not found: {}", function_id))?;
+            let source_hash = function.metadata.provenance.as_ref().map(|p| p.source_hash.clone());
+            if let Some(hash) = &source_hash {
+                tracing::Span::current().record("source_hash", hash.as_str());
+            }
+
+            if let Some(schema) = &function.metadata.event_schema {
+                event.validate(schema)?;
+            }
+
+            let cancel = CancellationToken::new();
+            let _in_flight = InFlight::track(&self.in_flight, event.id, cancel.clone())?;
+            let _permit = tokio::select! {
+                permit = self.scheduler.acquire(function_id, &function.metadata.concurrency) => permit?,
+                _ = cancel.cancelled() => return Ok(response::Response::cancelled()),
+            };
+            let mut response = engine::ExecutionEngine::invoke(&function, &event, &self.context, log_sink, cancel).await?;
+            if let Some(hash) = source_hash {
+                response.metadata.insert(response::SOURCE_HASH_KEY.to_string(), hash);
+            }
+            Ok(response)
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// Stop the invocation of the event with id `invocation_id`, returning whether it was
+    /// still queued or running. Its response is marked `cancelled` and carries the output
+    /// written until then. Cancelling a finished invocation does nothing.
+    pub fn cancel_execution(&self, invocation_id: Uuid) -> bool {
+        match self.in_flight.lock().unwrap().get(&invocation_id) {
+            Some(cancel) => {
+                tracing::info!("Cancelling invocation {}", invocation_id);
+                cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Ids of the events whose invocations are queued or running
+    pub fn in_flight(&self) -> Vec<Uuid> {
+        self.in_flight.lock().unwrap().keys().copied().collect()
     }
 
     /// List all deployed functions
     pub fn list_functions(&self) -> Vec<FunctionMetadata> {
-        // TODO: Implement function listing
-        vec![]
+        self.store.list()
+    }
+
+    /// A deployed function with its code and runtime type
+    pub fn get_function(&self, function_id: Uuid) -> Option<store::DeployedFunction> {
+        self.store.get(&function_id)
+    }
+
+    /// Running, queued, completed and rejected invocation counts per function
+    pub fn runtime_stats(&self) -> scheduler::RuntimeStats {
+        self.scheduler.stats()
+    }
+}
+
+/// Registers an invocation's cancellation token for as long as it is held
+struct InFlight<'a> {
+    invocations: &'a Mutex<HashMap<Uuid, CancellationToken>>,
+    id: Uuid,
+}
+
+impl<'a> InFlight<'a> {
+    fn track(invocations: &'a Mutex<HashMap<Uuid, CancellationToken>>, id: Uuid, cancel: CancellationToken) -> Result<Self> {
+        let mut running = invocations.lock().unwrap();
+        if running.contains_key(&id) {
+            anyhow::bail!("Invocation {} is already running", id);
+        }
+        running.insert(id, cancel);
+        Ok(Self { invocations, id })
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.invocations.lock().unwrap().remove(&self.id);
     }
 }
 
@@ -66,4 +278,249 @@ impl Default for Runtime {
     fn default() -> Self {
         Self::new().expect("Failed to create runtime")
     }
-} 
\ No newline at end of file
+} 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(name: &str, language: &str) -> FunctionMetadata {
+        FunctionMetadata {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            language: language.to_string(),
+            language_confidence: None,
+            version: "1.0.0".to_string(),
+            created_at: chrono::Utc::now(),
+            event_schema: None,
+            concurrency: scheduler::ConcurrencyLimits::default(),
+            provenance: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_deploy_and_invoke_python_and_bash() {
+        let runtime = Runtime::new().unwrap();
+
+        let python = r#"
+import json, sys
+event = json.load(sys.stdin)
+print(json.dumps({"success": True, "message": "hello", "data": {"name": event["data"]["name"]}}))
+"#;
+        let bash = r#"echo "bash saw ${TALKPP_EVENT}""#;
+
+        let py_id = runtime.deploy(python, metadata("greet", "python")).await.unwrap();
+        let sh_id = runtime.deploy(bash, metadata("echo", "bash")).await.unwrap();
+        assert_eq!(runtime.list_functions().len(), 2);
+
+        let event = event::Event::new(serde_json::json!({"name": "talk"}));
+
+        let response = runtime.execute(py_id, event.clone()).await.unwrap();
+        assert!(response.success, "{:?}", response);
+        assert_eq!(response.message, "hello");
+        assert_eq!(response.data["name"], "talk");
+
+        let response = runtime.execute(sh_id, event).await.unwrap();
+        assert!(response.success, "{:?}", response);
+        assert!(response.output.contains(r#""name":"talk""#));
+    }
+
+    #[tokio::test]
+    async fn test_execute_with_logs_streams_output() {
+        let runtime = Runtime::new().unwrap();
+        let id = runtime.deploy("echo first; echo second >&2", FunctionMetadata::new("chatty", "bash")).await.unwrap();
+
+        let lines = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = lines.clone();
+        let sink = LogSink::new(move |stream, line| seen.lock().unwrap().push((stream, line.to_string())));
+        let response = runtime.execute_with_logs(id, event::Event::default(), sink).await.unwrap();
+
+        assert!(response.success, "{:?}", response);
+        let mut lines = lines.lock().unwrap().clone();
+        lines.sort_by_key(|(stream, _)| *stream == LogStream::Stderr);
+        assert_eq!(lines, vec![(LogStream::Stdout, "first".to_string()), (LogStream::Stderr, "second".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_execution_stops_running_invocation() {
+        let runtime = std::sync::Arc::new(Runtime::new().unwrap());
+        let id = runtime.deploy("echo started\nsleep 30\necho unreachable", metadata("sleepy", "bash")).await.unwrap();
+        let event = event::Event::default();
+        let invocation_id = event.id;
+
+        let (started_tx, started_rx) = tokio::sync::oneshot::channel();
+        let started_tx = std::sync::Mutex::new(Some(started_tx));
+        let sink = LogSink::new(move |_, _| {
+            if let Some(tx) = started_tx.lock().unwrap().take() {
+                let _ = tx.send(());
+            }
+        });
+        let invocation = tokio::spawn({
+            let runtime = runtime.clone();
+            async move { runtime.execute_with_logs(id, event, sink).await }
+        });
+
+        started_rx.await.unwrap();
+        assert_eq!(runtime.in_flight(), vec![invocation_id]);
+        assert!(runtime.cancel_execution(invocation_id));
+        let response = invocation.await.unwrap().unwrap();
+        assert!(response.cancelled && !response.success, "{:?}", response);
+        assert_eq!(response.output, "started\n");
+
+        // Once the invocation is over, cancelling it does nothing
+        assert!(runtime.in_flight().is_empty());
+        assert!(!runtime.cancel_execution(invocation_id));
+        let quick = runtime.deploy("echo ok", metadata("quick", "bash")).await.unwrap();
+        let event = event::Event::default();
+        let response = runtime.execute(quick, event.clone()).await.unwrap();
+        assert!(!runtime.cancel_execution(event.id));
+        assert!(response.success && !response.cancelled, "{:?}", response);
+    }
+
+    #[tokio::test]
+    async fn test_failed_execution_is_reported() {
+        let runtime = Runtime::new().unwrap();
+        let id = runtime.deploy("exit 3", metadata("fail", "bash")).await.unwrap();
+
+        let response = runtime.execute(id, event::Event::default()).await.unwrap();
+        assert!(!response.success);
+    }
+
+    #[tokio::test]
+    async fn test_deploy_rejects_invalid_code() {
+        let runtime = Runtime::new().unwrap();
+        assert!(runtime.deploy("def broken(:\n", metadata("bad", "python")).await.is_err());
+        assert!(runtime.deploy("print(1)", metadata("cobol", "cobol")).await.is_err());
+        assert!(runtime.list_functions().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_undeploy_and_persistence() {
+        let dir = tempfile::tempdir().unwrap();
+        let id = {
+            let runtime = Runtime::with_persistence(dir.path().join("functions")).unwrap();
+            let id = runtime.deploy("echo ok", metadata("kept", "bash")).await.unwrap();
+            runtime.deploy("echo gone", metadata("dropped", "bash")).await.unwrap();
+            let dropped = runtime.list_functions()[1].id;
+            assert!(runtime.undeploy(dropped).unwrap());
+            assert!(!runtime.undeploy(dropped).unwrap());
+            id
+        };
+
+        let runtime = Runtime::with_persistence(dir.path().join("functions")).unwrap();
+        let functions = runtime.list_functions();
+        assert_eq!(functions.len(), 1);
+        assert_eq!(functions[0].id, id);
+        assert!(runtime.execute(Uuid::new_v4(), event::Event::default()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_deploy_detects_missing_language() {
+        let runtime = Runtime::new().unwrap();
+        let code = "import sys\n\ndef main():\n    print('detected')\n\nif __name__ == '__main__':\n    main()\n";
+        let id = runtime.deploy(code, metadata("unlabeled", "")).await.unwrap();
+
+        let function = runtime.get_function(id).unwrap();
+        assert_eq!(function.language, Language::Python);
+        assert_eq!(function.metadata.language, "python");
+        assert!(function.metadata.language_confidence.unwrap() > 0.9);
+        let response = runtime.execute(id, event::Event::default()).await.unwrap();
+        assert!(response.output.contains("detected"), "{:?}", response);
+
+        // Named languages are taken as given
+        let id = runtime.deploy("echo labeled", metadata("labeled", "bash")).await.unwrap();
+        assert_eq!(runtime.get_function(id).unwrap().metadata.language_confidence, None);
+
+        let err = runtime.deploy("COUNT=3", metadata("ambiguous", " ")).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(talkpp_wrappers::WrapperError::LanguageUndetected { .. })));
+        let lenient = Runtime::new().unwrap().with_detection_threshold(0.2);
+        assert!(lenient.deploy("COUNT=3", metadata("ambiguous", "")).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_execute_rejects_events_failing_schema() {
+        let runtime = Runtime::new().unwrap();
+        let mut meta = metadata("typed", "bash");
+        meta.event_schema = Some(serde_json::json!({
+            "type": "object",
+            "required": ["count"],
+            "properties": { "count": { "type": "integer" } }
+        }));
+        let id = runtime.deploy("echo ran", meta).await.unwrap();
+
+        let err = runtime.execute(id, event::Event::http(serde_json::json!({"count": "many"}))).await.unwrap_err();
+        match err.downcast_ref::<event::EventError>() {
+            Some(event::EventError::Validation { issues }) => assert_eq!(issues[0].path, "/count"),
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        let response = runtime.execute(id, event::Event::http(serde_json::json!({"count": 2}))).await.unwrap();
+        assert!(response.output.contains("ran"));
+
+        let mut bad = metadata("bad-schema", "bash");
+        bad.event_schema = Some(serde_json::json!({"type": 5}));
+        assert!(runtime.deploy("echo", bad).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_execute_rejects_when_function_overloaded() {
+        let runtime = std::sync::Arc::new(Runtime::new().unwrap());
+        let mut meta = metadata("slow", "bash");
+        meta.concurrency = scheduler::ConcurrencyLimits {
+            max_concurrency: 2,
+            max_queued: 1,
+            queue_timeout_ms: 10_000,
+        };
+        let id = runtime.deploy("sleep 0.3", meta).await.unwrap();
+
+        let mut tasks = tokio::task::JoinSet::new();
+        for _ in 0..6 {
+            let runtime = runtime.clone();
+            tasks.spawn(async move { runtime.execute(id, event::Event::default()).await });
+        }
+
+        let mut overloaded = 0;
+        while let Some(result) = tasks.join_next().await {
+            match result.unwrap() {
+                Ok(response) => assert!(response.success, "{:?}", response),
+                Err(e) => {
+                    assert!(matches!(e.downcast_ref(), Some(scheduler::SchedulerError::Overloaded { .. })));
+                    overloaded += 1;
+                }
+            }
+        }
+
+        assert_eq!(overloaded, 3);
+        let stats = &runtime.runtime_stats().functions[&id];
+        assert_eq!((stats.completed, stats.rejected, stats.running), (3, 3, 0));
+    }
+
+    #[tokio::test]
+    async fn test_provenance_survives_deploy_and_execute() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = "send welcome message";
+        let artifact = talkpp_compiler::Compiler::with_config(talkpp_compiler::CompilerConfig {
+            target_language: talkpp_compiler::TargetLanguage::Bash,
+            ..talkpp_compiler::CompilerConfig::default()
+        }).compile(source).unwrap();
+        assert_eq!(artifact.provenance.source_hash, Provenance::hash_source(source));
+
+        let id = {
+            let runtime = Runtime::with_persistence(dir.path().join("functions")).unwrap();
+            runtime.deploy_artifact(&artifact, metadata("welcome", "")).await.unwrap()
+        };
+
+        let runtime = Runtime::with_persistence(dir.path().join("functions")).unwrap();
+        let function = runtime.get_function(id).unwrap();
+        assert_eq!(function.language, Language::Bash);
+        assert_eq!(function.metadata.provenance.as_ref(), Some(&artifact.provenance));
+
+        let response = runtime.execute(id, event::Event::default()).await.unwrap();
+        assert_eq!(response.metadata[response::SOURCE_HASH_KEY], artifact.provenance.source_hash);
+
+        // Functions deployed from plain code carry no provenance
+        let plain = runtime.deploy("echo plain", metadata("plain", "bash")).await.unwrap();
+        let response = runtime.execute(plain, event::Event::default()).await.unwrap();
+        assert!(response.metadata.is_empty());
+    }
+}
@@ -0,0 +1,105 @@
+//! Function invocation responses
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use talkpp_executor::{ExecutionResult, ExecutionStatus};
+
+/// Metadata key holding the source hash of the function that produced a response
+pub const SOURCE_HASH_KEY: &str = "source_hash";
+
+/// Result of invoking a deployed function
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Response {
+    pub success: bool,
+    pub data: serde_json::Value,
+    pub message: String,
+    /// Raw stdout captured from the function
+    #[serde(default)]
+    pub output: String,
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub execution_time_ms: u64,
+    /// Set when the invocation was cancelled; `output` is what it wrote until then
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cancelled: bool,
+    /// About the invocation rather than its result, such as [`SOURCE_HASH_KEY`]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub metadata: HashMap<String, String>,
+}
+
+impl Response {
+    pub fn success(message: impl Into<String>) -> Self {
+        Self {
+            success: true,
+            data: serde_json::json!({}),
+            message: message.into(),
+            output: String::new(),
+            error: None,
+            execution_time_ms: 0,
+            cancelled: false,
+            metadata: HashMap::new(),
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        let message = message.into();
+        Self {
+            success: false,
+            data: serde_json::json!({}),
+            error: Some(message.clone()),
+            message,
+            output: String::new(),
+            execution_time_ms: 0,
+            cancelled: false,
+            metadata: HashMap::new(),
+        }
+    }
+
+    /// An invocation cancelled before it started
+    pub fn cancelled() -> Self {
+        Self { cancelled: true, ..Self::error("Function execution cancelled") }
+    }
+
+    /// Translate an executor result.
+    ///
+    /// Generated handlers print a `{"success", "message", "data"}` object as their last
+    /// line of stdout; when present it becomes the response body, otherwise the raw
+    /// output is returned as a string.
+    pub fn from_execution(result: ExecutionResult) -> Self {
+        let handler_response = result.output
+            .lines()
+            .rev()
+            .find(|l| !l.trim().is_empty())
+            .and_then(|l| serde_json::from_str::<serde_json::Value>(l).ok())
+            .filter(|v| v.is_object());
+
+        let (success, message, data) = match handler_response {
+            Some(body) => (
+                result.success && body["success"].as_bool().unwrap_or(true),
+                body["message"].as_str().unwrap_or_default().to_string(),
+                body.get("data").cloned().unwrap_or(body),
+            ),
+            None => (
+                result.success,
+                match result.status {
+                    ExecutionStatus::Succeeded => "Function executed successfully",
+                    ExecutionStatus::Cancelled => "Function execution cancelled",
+                    _ => "Function execution failed",
+                }.to_string(),
+                serde_json::Value::String(result.output.trim().to_string()),
+            ),
+        };
+
+        Self {
+            success,
+            data,
+            message,
+            output: result.output,
+            error: result.error,
+            execution_time_ms: result.execution_time_ms,
+            cancelled: result.status == ExecutionStatus::Cancelled,
+            metadata: HashMap::new(),
+        }
+    }
+}
@@ -0,0 +1,359 @@
+//! Concurrency limits and per-function queueing
+//!
+//! Every invocation must hold both a global slot and a slot for its function before it
+//! runs. Invocations that cannot start immediately wait in a bounded per-function queue
+//! until a deadline; when the queue is full they are rejected straight away.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use uuid::Uuid;
+
+/// Default number of invocations running at once across all functions
+pub const DEFAULT_GLOBAL_CONCURRENCY: usize = 64;
+
+/// Per-function limits, set at deploy time through [`crate::FunctionMetadata`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConcurrencyLimits {
+    pub max_concurrency: usize,
+    /// Invocations allowed to wait once `max_concurrency` is reached
+    pub max_queued: usize,
+    /// How long a queued invocation waits before it is dropped
+    pub queue_timeout_ms: u64,
+}
+
+impl Default for ConcurrencyLimits {
+    fn default() -> Self {
+        Self {
+            max_concurrency: 10,
+            max_queued: 100,
+            queue_timeout_ms: 30_000,
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum SchedulerError {
+    #[error("Function {function_id} is overloaded: {queued} invocations already queued")]
+    Overloaded { function_id: Uuid, queued: u64 },
+
+    #[error("Function {function_id} invocation timed out after waiting {waited_ms}ms in the queue")]
+    Timeout { function_id: Uuid, waited_ms: u64 },
+}
+
+/// Counters for a single function
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FunctionStats {
+    pub running: u64,
+    pub queued: u64,
+    pub completed: u64,
+    pub rejected: u64,
+    pub timed_out: u64,
+}
+
+/// Snapshot of scheduler state, as returned by [`crate::Runtime::runtime_stats`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuntimeStats {
+    pub global_limit: usize,
+    pub global_available: usize,
+    pub functions: HashMap<Uuid, FunctionStats>,
+}
+
+struct FunctionSlot {
+    /// The limits the semaphore was sized for, replaced together when a redeploy changes them
+    gate: Mutex<(ConcurrencyLimits, Arc<Semaphore>)>,
+    running: AtomicU64,
+    queued: AtomicU64,
+    completed: AtomicU64,
+    rejected: AtomicU64,
+    timed_out: AtomicU64,
+}
+
+impl FunctionSlot {
+    fn new(limits: &ConcurrencyLimits) -> Self {
+        Self {
+            gate: Mutex::new((limits.clone(), Self::semaphore(limits))),
+            running: AtomicU64::new(0),
+            queued: AtomicU64::new(0),
+            completed: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+            timed_out: AtomicU64::new(0),
+        }
+    }
+
+    fn semaphore(limits: &ConcurrencyLimits) -> Arc<Semaphore> {
+        Arc::new(Semaphore::new(limits.max_concurrency.max(1)))
+    }
+
+    /// The semaphore for `limits`, rebuilt when the concurrency limit has changed.
+    ///
+    /// Invocations already running or queued keep the old semaphore, so for a moment after
+    /// a change both old and new invocations may run.
+    fn semaphore_for(&self, limits: &ConcurrencyLimits) -> Arc<Semaphore> {
+        let mut gate = self.gate.lock().unwrap();
+        if gate.0.max_concurrency != limits.max_concurrency {
+            gate.1 = Self::semaphore(limits);
+        }
+        gate.0 = limits.clone();
+        gate.1.clone()
+    }
+
+    fn stats(&self) -> FunctionStats {
+        FunctionStats {
+            running: self.running.load(Ordering::SeqCst),
+            queued: self.queued.load(Ordering::SeqCst),
+            completed: self.completed.load(Ordering::SeqCst),
+            rejected: self.rejected.load(Ordering::SeqCst),
+            timed_out: self.timed_out.load(Ordering::SeqCst),
+        }
+    }
+}
+
+/// Held for the duration of an invocation; releases both slots on drop
+pub struct ExecutionPermit {
+    _global: OwnedSemaphorePermit,
+    _function: OwnedSemaphorePermit,
+    slot: Arc<FunctionSlot>,
+}
+
+impl Drop for ExecutionPermit {
+    fn drop(&mut self) {
+        self.slot.running.fetch_sub(1, Ordering::SeqCst);
+        self.slot.completed.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Decrements the queue counter however the wait ends
+struct QueueGuard<'a>(&'a AtomicU64);
+
+impl Drop for QueueGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+pub struct Scheduler {
+    global_limit: usize,
+    global: Arc<Semaphore>,
+    functions: Mutex<HashMap<Uuid, Arc<FunctionSlot>>>,
+}
+
+impl Scheduler {
+    pub fn new(global_limit: usize) -> Self {
+        let global_limit = global_limit.max(1);
+        Self {
+            global_limit,
+            global: Arc::new(Semaphore::new(global_limit)),
+            functions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn slot(&self, function_id: Uuid, limits: &ConcurrencyLimits) -> (Arc<FunctionSlot>, Arc<Semaphore>) {
+        let slot = self.functions.lock().unwrap()
+            .entry(function_id)
+            .or_insert_with(|| Arc::new(FunctionSlot::new(limits)))
+            .clone();
+        let semaphore = slot.semaphore_for(limits);
+        (slot, semaphore)
+    }
+
+    /// Wait for a slot to run `function_id`.
+    ///
+    /// Fails with [`SchedulerError::Overloaded`] when the function's queue is full and
+    /// with [`SchedulerError::Timeout`] when the queue deadline passes.
+    pub async fn acquire(&self, function_id: Uuid, limits: &ConcurrencyLimits) -> Result<ExecutionPermit, SchedulerError> {
+        let (slot, semaphore) = self.slot(function_id, limits);
+
+        let immediate = semaphore.clone().try_acquire_owned().ok()
+            .and_then(|function| self.global.clone().try_acquire_owned().ok().map(|global| (function, global)));
+
+        let (function, global) = match immediate {
+            Some(permits) => permits,
+            None => {
+                let queued = slot.queued.fetch_add(1, Ordering::SeqCst);
+                let _queue = QueueGuard(&slot.queued);
+                if queued >= limits.max_queued as u64 {
+                    slot.rejected.fetch_add(1, Ordering::SeqCst);
+                    return Err(SchedulerError::Overloaded { function_id, queued });
+                }
+
+                let timeout = Duration::from_millis(limits.queue_timeout_ms);
+                let wait = async {
+                    let function = semaphore.acquire_owned().await;
+                    let global = self.global.clone().acquire_owned().await;
+                    (function, global)
+                };
+
+                match tokio::time::timeout(timeout, wait).await {
+                    Ok((Ok(function), Ok(global))) => (function, global),
+                    _ => {
+                        slot.timed_out.fetch_add(1, Ordering::SeqCst);
+                        return Err(SchedulerError::Timeout {
+                            function_id,
+                            waited_ms: limits.queue_timeout_ms,
+                        });
+                    }
+                }
+            }
+        };
+
+        slot.running.fetch_add(1, Ordering::SeqCst);
+        Ok(ExecutionPermit {
+            _global: global,
+            _function: function,
+            slot,
+        })
+    }
+
+    /// Drop the counters for an undeployed function
+    pub fn forget(&self, function_id: &Uuid) {
+        self.functions.lock().unwrap().remove(function_id);
+    }
+
+    pub fn stats(&self) -> RuntimeStats {
+        RuntimeStats {
+            global_limit: self.global_limit,
+            global_available: self.global.available_permits(),
+            functions: self.functions.lock().unwrap()
+                .iter()
+                .map(|(id, slot)| (*id, slot.stats()))
+                .collect(),
+        }
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new(DEFAULT_GLOBAL_CONCURRENCY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    /// Run `invocations` concurrent calls of a slow mock function, returning the peak
+    /// number running at once and the errors observed
+    async fn hammer(scheduler: Arc<Scheduler>, limits: ConcurrencyLimits, invocations: usize, work: Duration) -> (usize, Vec<SchedulerError>) {
+        let function_id = Uuid::new_v4();
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let mut tasks = tokio::task::JoinSet::new();
+        for _ in 0..invocations {
+            let (scheduler, limits, running, peak) = (scheduler.clone(), limits.clone(), running.clone(), peak.clone());
+            tasks.spawn(async move {
+                let _permit = scheduler.acquire(function_id, &limits).await?;
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(work).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                Ok::<_, SchedulerError>(())
+            });
+        }
+
+        let mut errors = Vec::new();
+        while let Some(result) = tasks.join_next().await {
+            if let Err(e) = result.unwrap() {
+                errors.push(e);
+            }
+        }
+
+        let stats = scheduler.stats().functions[&function_id].clone();
+        assert_eq!(stats.running, 0);
+        assert_eq!(stats.queued, 0);
+        (peak.load(Ordering::SeqCst), errors)
+    }
+
+    #[tokio::test]
+    async fn test_limit_respected_and_overflow_rejected() {
+        let scheduler = Arc::new(Scheduler::new(64));
+        let limits = ConcurrencyLimits {
+            max_concurrency: 3,
+            max_queued: 5,
+            queue_timeout_ms: 10_000,
+        };
+
+        let (peak, errors) = hammer(scheduler, limits, 20, Duration::from_millis(50)).await;
+        assert_eq!(peak, 3);
+        assert_eq!(errors.len(), 12);
+        assert!(errors.iter().all(|e| matches!(e, SchedulerError::Overloaded { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_global_limit_applies_across_functions() {
+        let scheduler = Arc::new(Scheduler::new(2));
+        let limits = ConcurrencyLimits {
+            max_concurrency: 10,
+            max_queued: 100,
+            queue_timeout_ms: 10_000,
+        };
+
+        let (peak, errors) = hammer(scheduler.clone(), limits, 8, Duration::from_millis(20)).await;
+        assert_eq!(peak, 2);
+        assert!(errors.is_empty());
+        assert_eq!(scheduler.stats().global_available, 2);
+    }
+
+    #[tokio::test]
+    async fn test_queued_invocations_time_out() {
+        let scheduler = Arc::new(Scheduler::new(64));
+        let limits = ConcurrencyLimits {
+            max_concurrency: 1,
+            max_queued: 10,
+            queue_timeout_ms: 20,
+        };
+
+        let (_, errors) = hammer(scheduler.clone(), limits, 4, Duration::from_millis(200)).await;
+        assert_eq!(errors.len(), 3);
+        assert!(errors.iter().all(|e| matches!(e, SchedulerError::Timeout { .. })));
+
+        let stats = scheduler.stats().functions.into_values().next().unwrap();
+        assert_eq!(stats.completed, 1);
+        assert_eq!(stats.timed_out, 3);
+        assert_eq!(stats.rejected, 0);
+    }
+
+    #[tokio::test]
+    async fn test_changed_limits_apply_to_later_invocations() {
+        let scheduler = Scheduler::new(64);
+        let function_id = Uuid::new_v4();
+        let limits = |max_concurrency| ConcurrencyLimits {
+            max_concurrency,
+            max_queued: 10,
+            queue_timeout_ms: 20,
+        };
+
+        let first = scheduler.acquire(function_id, &limits(1)).await.unwrap();
+        assert!(matches!(
+            scheduler.acquire(function_id, &limits(1)).await,
+            Err(SchedulerError::Timeout { .. })
+        ));
+
+        // Raised on redeploy: new invocations get the larger limit
+        let mut raised = Vec::new();
+        for _ in 0..3 {
+            raised.push(scheduler.acquire(function_id, &limits(3)).await.unwrap());
+        }
+        assert!(matches!(
+            scheduler.acquire(function_id, &limits(3)).await,
+            Err(SchedulerError::Timeout { .. })
+        ));
+        drop((first, raised));
+
+        // Lowered again
+        let _only = scheduler.acquire(function_id, &limits(1)).await.unwrap();
+        assert!(matches!(
+            scheduler.acquire(function_id, &limits(1)).await,
+            Err(SchedulerError::Timeout { .. })
+        ));
+
+        let stats = &scheduler.stats().functions[&function_id];
+        assert_eq!((stats.running, stats.completed, stats.timed_out), (1, 4, 3));
+    }
+}
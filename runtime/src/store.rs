@@ -0,0 +1,81 @@
+//! Deployed function storage
+
+use crate::FunctionMetadata;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::RwLock;
+use talkpp_executor::RuntimeType;
+use talkpp_wrappers::Language;
+use uuid::Uuid;
+
+/// A function as stored by the runtime
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeployedFunction {
+    pub metadata: FunctionMetadata,
+    pub code: String,
+    pub language: Language,
+    pub runtime_type: RuntimeType,
+}
+
+/// In-memory function registry with optional sled persistence
+pub struct FunctionStore {
+    functions: RwLock<HashMap<Uuid, DeployedFunction>>,
+    db: Option<sled::Db>,
+}
+
+impl FunctionStore {
+    pub fn in_memory() -> Self {
+        Self {
+            functions: RwLock::new(HashMap::new()),
+            db: None,
+        }
+    }
+
+    /// Open a persistent store, loading every previously deployed function
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let db = sled::open(path)?;
+        let mut functions = HashMap::new();
+        for entry in db.iter() {
+            let (_, value) = entry?;
+            let function: DeployedFunction = serde_json::from_slice(&value)?;
+            functions.insert(function.metadata.id, function);
+        }
+
+        Ok(Self {
+            functions: RwLock::new(functions),
+            db: Some(db),
+        })
+    }
+
+    pub fn insert(&self, function: DeployedFunction) -> Result<()> {
+        if let Some(db) = &self.db {
+            db.insert(function.metadata.id.as_bytes(), serde_json::to_vec(&function)?)?;
+            db.flush()?;
+        }
+        self.functions.write().unwrap().insert(function.metadata.id, function);
+        Ok(())
+    }
+
+    pub fn get(&self, id: &Uuid) -> Option<DeployedFunction> {
+        self.functions.read().unwrap().get(id).cloned()
+    }
+
+    pub fn remove(&self, id: &Uuid) -> Result<Option<DeployedFunction>> {
+        if let Some(db) = &self.db {
+            db.remove(id.as_bytes())?;
+            db.flush()?;
+        }
+        Ok(self.functions.write().unwrap().remove(id))
+    }
+
+    pub fn list(&self) -> Vec<FunctionMetadata> {
+        let mut functions: Vec<FunctionMetadata> = self.functions.read().unwrap()
+            .values()
+            .map(|f| f.metadata.clone())
+            .collect();
+        functions.sort_by_key(|f| f.created_at);
+        functions
+    }
+}
@@ -0,0 +1,133 @@
+//! Service broker for running instrumented generated code against mocks
+//!
+//! Rust functions compiled with `InstrumentationMode::DryRun` or `Trace` take a
+//! [`ServiceBroker`]. Handing them a [`MockBroker`] answers their service calls from a
+//! [`MockRegistry`] and keeps the calls and trace steps they report, so generated code
+//! can be checked with the same mocks as an interpreted simulation.
+
+use crate::mock::{MockRegistry, RecordedCall};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use talkpp_support::{ServiceBroker, TraceStep};
+
+#[derive(Default)]
+struct BrokerLog {
+    calls: Vec<RecordedCall>,
+    steps: Vec<TraceStep>,
+    call_counts: HashMap<(String, String), usize>,
+}
+
+/// Broker that answers every call from a mock registry
+pub struct MockBroker {
+    mocks: MockRegistry,
+    log: Mutex<BrokerLog>,
+}
+
+impl MockBroker {
+    pub fn new(mocks: MockRegistry) -> Self {
+        Self {
+            mocks,
+            log: Mutex::new(BrokerLog::default()),
+        }
+    }
+
+    /// Calls made so far; `params` holds the serialized request
+    pub fn calls(&self) -> Vec<RecordedCall> {
+        self.log.lock().unwrap().calls.clone()
+    }
+
+    /// Steps reported so far, in execution order
+    pub fn steps(&self) -> Vec<TraceStep> {
+        self.log.lock().unwrap().steps.clone()
+    }
+}
+
+#[async_trait]
+impl ServiceBroker for MockBroker {
+    async fn call(&self, service: &str, operation: &str, request: Value) -> Result<Value> {
+        let mut log = self.log.lock().unwrap();
+        let count = log.call_counts.entry((service.to_lowercase(), operation.to_string())).or_default();
+        *count += 1;
+        let result = self.mocks.respond(service, operation, *count).into_result();
+
+        log.calls.push(RecordedCall {
+            service: service.to_string(),
+            operation: operation.to_string(),
+            params: request,
+            mocked: true,
+            success: result.is_ok(),
+        });
+        result.map_err(|e| anyhow::anyhow!("{} {} failed: {}", service, operation, e))
+    }
+
+    fn trace(&self, step: TraceStep) {
+        self.log.lock().unwrap().steps.push(step);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{Failure, MockDefinition};
+
+    /// Output of the compiler's trace-mode golden test, kept in sync by that test
+    #[allow(dead_code)]
+    mod generated {
+        include!("../../compiler/testdata/trace_handler.rs.golden");
+    }
+
+    fn event(data: Value) -> generated::Event {
+        generated::Event {
+            data,
+            context: HashMap::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generated_handler_reports_each_statement() {
+        let broker = MockBroker::new(MockRegistry::new());
+        let data = serde_json::json!({"user": {"email": "ada@example.com"}, "order": {"id": 7}});
+        let response = generated::handler(event(data), &broker).await.unwrap();
+        assert!(response.success, "{}", response.message);
+
+        let steps = broker.steps();
+        let summaries: Vec<&str> = steps.iter().map(|s| s.summary.as_str()).collect();
+        assert_eq!(
+            summaries,
+            vec![
+                "user_email: event.user.email",
+                "greeting: \"Welcome aboard\"",
+                "send greeting using SendGrid",
+                "store event.order using Postgres",
+            ]
+        );
+        assert_eq!((steps[2].start_line, steps[2].start_col), (3, 1));
+
+        let calls = broker.calls();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].operation, "send");
+        assert_eq!(calls[0].params["to"], "ada@example.com");
+        assert_eq!(calls[1].params["record"], serde_json::json!({"id": 7}));
+    }
+
+    #[tokio::test]
+    async fn test_mock_failures_reach_generated_code() {
+        let mocks = MockRegistry::new().with_mock(MockDefinition {
+            service: "SendGrid".to_string(),
+            operation: None,
+            response: None,
+            failures: vec![Failure::Error { message: "down".to_string() }],
+        });
+        let broker = MockBroker::new(mocks);
+        let data = serde_json::json!({"user": {"email": "ada@example.com"}});
+        let response = generated::handler(event(data), &broker).await.unwrap();
+
+        assert!(!response.success);
+        assert_eq!(response.message, "Failed to send email");
+        assert_eq!(broker.steps().len(), 3);
+        assert!(!broker.calls()[0].success);
+    }
+}
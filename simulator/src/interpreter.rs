@@ -0,0 +1,255 @@
+//! Direct interpretation of Talk++ programs for dry-runs
+//!
+//! The interpreter walks the parsed AST instead of running generated code, so a
+//! simulation needs no toolchain and can record each decision it makes. Conditions are
+//! evaluated with the same semantics as the generated handlers: an event condition such
+//! as `new user registers` matches when the input's `type` field is
+//! `new_user_registers`.
+//!
+//! A failed service call ends the run with an error response, mirroring the early
+//! return in generated handlers.
+
+use crate::mock::{MockRegistry, RecordedCall};
+use crate::trace::{ExecutionTrace, StepKind};
+use crate::SimulationConfig;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use talkpp_compiler::ast::*;
+
+/// Result of interpreting a program
+#[derive(Debug, Clone)]
+pub struct Outcome {
+    pub success: bool,
+    pub output: Value,
+    pub trace: ExecutionTrace,
+    pub calls: Vec<RecordedCall>,
+    pub errors: Vec<String>,
+}
+
+/// Reason the program stopped early
+struct Halt {
+    message: String,
+}
+
+pub struct Interpreter<'a> {
+    config: &'a SimulationConfig,
+    mocks: &'a MockRegistry,
+    input: Value,
+    variables: serde_json::Map<String, Value>,
+    trace: ExecutionTrace,
+    calls: Vec<RecordedCall>,
+    call_counts: HashMap<(String, String), usize>,
+    /// Time spent in mocked calls, including injected latency
+    simulated: Duration,
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(config: &'a SimulationConfig, mocks: &'a MockRegistry, input: Value) -> Self {
+        let input = if input.is_null() { json!({}) } else { input };
+        Self {
+            config,
+            mocks,
+            input,
+            variables: serde_json::Map::new(),
+            trace: ExecutionTrace::new(),
+            calls: Vec::new(),
+            call_counts: HashMap::new(),
+            simulated: Duration::ZERO,
+        }
+    }
+
+    pub fn run(mut self, program: &Program) -> Outcome {
+        self.trace.record(StepKind::ParseInput { input: self.input.clone() }, 0, Default::default());
+
+        let result = program.statements.iter().try_for_each(|s| self.statement(s, 0));
+
+        let (success, output, errors) = match result {
+            Ok(()) => (
+                true,
+                json!({
+                    "success": true,
+                    "message": "Function executed successfully",
+                    "data": Value::Object(self.variables.clone()),
+                }),
+                vec![],
+            ),
+            Err(halt) => (
+                false,
+                json!({
+                    "success": false,
+                    "message": halt.message.clone(),
+                    "data": {},
+                }),
+                vec![halt.message],
+            ),
+        };
+        self.trace.record(StepKind::Output { value: output.clone() }, 0, Default::default());
+
+        Outcome {
+            success,
+            output,
+            trace: self.trace,
+            calls: self.calls,
+            errors,
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement, depth: usize) -> Result<(), Halt> {
+        match statement {
+            Statement::Conditional(cond) => self.conditional(cond, depth)?,
+            Statement::Action(action) => self.action(action, depth)?,
+            Statement::Assignment(assign) => {
+                let started = Instant::now();
+                let value = self.evaluate(&assign.value);
+                self.variables.insert(assign.variable.clone(), value.clone());
+                self.trace.record(
+                    StepKind::Assignment { variable: assign.variable.clone(), value },
+                    depth,
+                    started.elapsed(),
+                );
+            }
+            Statement::Comment(_) => {}
+        }
+        Ok(())
+    }
+
+    fn conditional(&mut self, cond: &ConditionalStatement, depth: usize) -> Result<(), Halt> {
+        let started = Instant::now();
+        let outcome = self.condition(&cond.condition);
+        self.trace.record(
+            StepKind::Condition { expr: cond.condition.to_string(), outcome },
+            depth,
+            started.elapsed(),
+        );
+
+        let branch = if outcome {
+            Some(&cond.then_body)
+        } else {
+            cond.else_body.as_ref()
+        };
+        for statement in branch.into_iter().flatten() {
+            self.statement(statement, depth + 1)?;
+        }
+        Ok(())
+    }
+
+    fn action(&mut self, action: &ActionStatement, depth: usize) -> Result<(), Halt> {
+        let started = Instant::now();
+        let operation = action.action.to_string();
+        let target = action.target.as_ref().map(Expression::to_string);
+
+        let Some(service) = &action.service else {
+            self.trace.record(StepKind::Action { action: operation, target }, depth, started.elapsed());
+            return Ok(());
+        };
+
+        let request = json!({
+            "operation": operation,
+            "target": target,
+            "value": action.target.as_ref().map(|t| self.evaluate(t)),
+            "parameters": action.parameters.iter()
+                .map(|(key, expr)| (key.clone(), self.evaluate(expr)))
+                .collect::<serde_json::Map<_, _>>(),
+        });
+
+        // Live services are never contacted during a dry-run; without mocking the call
+        // is recorded but has no response.
+        let (mocked, result, latency) = if self.config.mock_external_calls {
+            let count = self.call_counts.entry((service.name.to_lowercase(), operation.clone())).or_default();
+            *count += 1;
+            let outcome = self.mocks.respond(&service.name, &operation, *count);
+            let latency = Duration::from_millis(outcome.latency_ms);
+            (true, outcome.into_result(), latency)
+        } else {
+            (false, Ok(Value::Null), Duration::ZERO)
+        };
+
+        self.simulated += latency;
+        let response = match &result {
+            Ok(body) => body.clone(),
+            Err(message) => json!({"success": false, "error": message}),
+        };
+
+        self.calls.push(RecordedCall {
+            service: service.name.clone(),
+            operation: operation.clone(),
+            params: request.clone(),
+            mocked,
+            success: result.is_ok(),
+        });
+        self.trace.record(
+            StepKind::ServiceCall { service: service.name.clone(), mocked, request, response },
+            depth,
+            started.elapsed() + latency,
+        );
+
+        if self.simulated > Duration::from_secs(self.config.timeout_seconds) {
+            return Err(Halt {
+                message: format!("Simulation timed out after {}s in {}.{}", self.config.timeout_seconds, service.name, operation),
+            });
+        }
+
+        result.map(|_| ()).map_err(|e| Halt {
+            message: format!("{} {} failed: {}", service.name, operation, e),
+        })
+    }
+
+    fn condition(&self, condition: &Condition) -> bool {
+        match condition {
+            Condition::Event(event) => {
+                let expected = format!("{}_{}", event.subject.replace(' ', "_"), event.action);
+                self.input.get("type").and_then(Value::as_str) == Some(expected.as_str())
+            }
+            Condition::Comparison(comp) => {
+                let left = self.evaluate(&comp.left);
+                let right = self.evaluate(&comp.right);
+                compare(&left, &comp.operator, &right)
+            }
+            Condition::Logical(logical) => match logical.operator {
+                LogicalOperator::And => self.condition(&logical.left) && self.condition(&logical.right),
+                LogicalOperator::Or => self.condition(&logical.left) || self.condition(&logical.right),
+            },
+        }
+    }
+
+    /// Evaluate an expression; identifiers resolve to variables first, then input fields,
+    /// with a bare `event` standing for the whole input
+    fn evaluate(&self, expr: &Expression) -> Value {
+        match expr {
+            Expression::Identifier(name) => self.variables.get(name)
+                .or_else(|| self.input.get(name))
+                .or_else(|| (name == "event").then_some(&self.input))
+                .cloned()
+                .unwrap_or(Value::Null),
+            Expression::String(value) => json!(value),
+            Expression::Integer(value) => json!(value),
+            Expression::Float(value) => json!(value),
+            Expression::Boolean(value) => json!(value),
+            Expression::Property(prop) => self.evaluate(&prop.object)
+                .get(&prop.property)
+                .cloned()
+                .unwrap_or(Value::Null),
+            Expression::FunctionCall(_) => Value::Null,
+        }
+    }
+}
+
+fn compare(left: &Value, operator: &ComparisonOperator, right: &Value) -> bool {
+    let ordering = match (left.as_f64(), right.as_f64()) {
+        (Some(l), Some(r)) => l.partial_cmp(&r),
+        _ => match (left.as_str(), right.as_str()) {
+            (Some(l), Some(r)) => Some(l.cmp(r)),
+            _ => None,
+        },
+    };
+
+    match operator {
+        ComparisonOperator::Equal => left == right || ordering == Some(std::cmp::Ordering::Equal),
+        ComparisonOperator::NotEqual => !(left == right || ordering == Some(std::cmp::Ordering::Equal)),
+        ComparisonOperator::GreaterThan => ordering == Some(std::cmp::Ordering::Greater),
+        ComparisonOperator::LessThan => ordering == Some(std::cmp::Ordering::Less),
+        ComparisonOperator::GreaterEqual => matches!(ordering, Some(std::cmp::Ordering::Greater | std::cmp::Ordering::Equal)),
+        ComparisonOperator::LessEqual => matches!(ordering, Some(std::cmp::Ordering::Less | std::cmp::Ordering::Equal)),
+    }
+}
@@ -3,18 +3,23 @@
 //! This crate provides dry-run simulation and testing capabilities
 //! for Talk++ functions before deployment.
 
+pub mod broker;
+pub mod interpreter;
 pub mod mock;
 pub mod trace;
 pub mod validation;
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use talkpp_compiler::ast::Program;
 use uuid::Uuid;
 
 /// Simulation engine
 pub struct Simulator {
     id: Uuid,
     trace_enabled: bool,
+    mocks: mock::MockRegistry,
+    assertions: Option<validation::ValidationSpec>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -23,6 +28,9 @@ pub struct SimulationConfig {
     pub trace_execution: bool,
     pub validate_outputs: bool,
     pub timeout_seconds: u64,
+    /// Event data the simulated function receives
+    #[serde(default)]
+    pub input: serde_json::Value,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -32,6 +40,12 @@ pub struct SimulationResult {
     pub trace: Option<trace::ExecutionTrace>,
     pub output: serde_json::Value,
     pub errors: Vec<String>,
+    /// Every service call the program made, in order
+    #[serde(default)]
+    pub calls: Vec<mock::RecordedCall>,
+    /// Assertion results, present when `validate_outputs` is set and a spec was given
+    #[serde(default)]
+    pub validation: Option<validation::ValidationReport>,
 }
 
 impl Default for SimulationConfig {
@@ -41,6 +55,7 @@ impl Default for SimulationConfig {
             trace_execution: true,
             validate_outputs: true,
             timeout_seconds: 30,
+            input: serde_json::Value::Null,
         }
     }
 }
@@ -51,36 +66,91 @@ impl Simulator {
         Self {
             id: Uuid::new_v4(),
             trace_enabled: true,
+            mocks: mock::MockRegistry::default(),
+            assertions: None,
         }
     }
 
-    /// Simulate execution of compiled Talk++ code
+    /// Use `mocks` to answer service calls when `mock_external_calls` is enabled
+    pub fn with_mocks(mut self, mocks: mock::MockRegistry) -> Self {
+        self.mocks = mocks;
+        self
+    }
+
+    /// Check every simulation result against `spec` when `validate_outputs` is enabled
+    pub fn with_assertions(mut self, spec: validation::ValidationSpec) -> Self {
+        self.assertions = Some(spec);
+        self
+    }
+
+    /// A broker answering generated code's service calls from this simulator's mocks
+    pub fn mock_broker(&self) -> broker::MockBroker {
+        broker::MockBroker::new(self.mocks.clone())
+    }
+
+    /// Simulate execution of a Talk++ program by interpreting it against `config.input`
     pub async fn simulate(&self, code: &str, config: SimulationConfig) -> Result<SimulationResult> {
         tracing::info!("Starting simulation with ID: {}", self.id);
         
         let start_time = std::time::Instant::now();
         
-        // TODO: Implement simulation logic
-        let trace = if config.trace_execution {
-            Some(trace::ExecutionTrace::new())
+        let program = match validation::parse_program(code) {
+            Ok(program) => program,
+            Err(e) => {
+                return Ok(SimulationResult {
+                    success: false,
+                    execution_time_ms: start_time.elapsed().as_millis() as u64,
+                    trace: None,
+                    output: serde_json::Value::Null,
+                    errors: vec![e],
+                    calls: vec![],
+                    validation: None,
+                });
+            }
+        };
+
+        Ok(self.run(&program, config, start_time))
+    }
+
+    /// Simulate execution of an already parsed program, such as one assembled statement
+    /// by statement
+    pub async fn simulate_program(&self, program: &Program, config: SimulationConfig) -> Result<SimulationResult> {
+        tracing::info!("Starting simulation with ID: {}", self.id);
+        Ok(self.run(program, config, std::time::Instant::now()))
+    }
+
+    fn run(&self, program: &Program, config: SimulationConfig, start_time: std::time::Instant) -> SimulationResult {
+        let outcome = interpreter::Interpreter::new(&config, &self.mocks, config.input.clone()).run(program);
+        let trace = if config.trace_execution && self.trace_enabled {
+            Some(outcome.trace)
         } else {
             None
         };
         
         let execution_time = start_time.elapsed().as_millis() as u64;
         
-        Ok(SimulationResult {
-            success: true,
+        let mut result = SimulationResult {
+            success: outcome.success,
             execution_time_ms: execution_time,
             trace,
-            output: serde_json::json!({"message": "Simulation completed"}),
-            errors: vec![],
-        })
+            output: outcome.output,
+            errors: outcome.errors,
+            calls: outcome.calls,
+            validation: None,
+        };
+
+        if config.validate_outputs {
+            if let Some(spec) = &self.assertions {
+                result.validation = Some(validation::validate(&result, spec));
+            }
+        }
+
+        result
     }
 
     /// Validate function signature and dependencies
     pub fn validate(&self, code: &str) -> Result<Vec<String>> {
-        // TODO: Implement validation logic
+        validation::parse_program(code).map_err(|e| anyhow::anyhow!(e))?;
         Ok(vec!["Validation passed".to_string()])
     }
 }
@@ -89,4 +159,160 @@ impl Default for Simulator {
     fn default() -> Self {
         Self::new()
     }
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use trace::StepKind;
+
+    const PROGRAM: &str = "if new user registers then validate email using SendGrid else process signup";
+
+    async fn run(input: serde_json::Value) -> SimulationResult {
+        let config = SimulationConfig {
+            input,
+            ..SimulationConfig::default()
+        };
+        Simulator::new().simulate(PROGRAM, config).await.unwrap()
+    }
+
+    fn condition_outcome(result: &SimulationResult) -> bool {
+        let trace = result.trace.as_ref().unwrap();
+        match trace.find(|k| matches!(k, StepKind::Condition { .. })).next().map(|s| &s.kind) {
+            Some(StepKind::Condition { expr, outcome }) => {
+                assert_eq!(expr, "new user registers");
+                *outcome
+            }
+            other => panic!("no condition step: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_condition_true_takes_then_branch() {
+        let result = run(serde_json::json!({"type": "new_user_registers", "email": "a@b.c"})).await;
+        assert!(result.success);
+        assert!(condition_outcome(&result));
+
+        let trace = result.trace.unwrap();
+        let call = trace.find(|k| matches!(k, StepKind::ServiceCall { .. })).next().unwrap();
+        assert_eq!(call.depth, 1);
+        match &call.kind {
+            StepKind::ServiceCall { service, mocked, request, .. } => {
+                assert_eq!(service, "SendGrid");
+                assert!(mocked);
+                assert_eq!(request["value"], "a@b.c");
+            }
+            _ => unreachable!(),
+        }
+        assert!(matches!(trace.steps.first().unwrap().kind, StepKind::ParseInput { .. }));
+        assert!(matches!(trace.steps.last().unwrap().kind, StepKind::Output { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_condition_false_takes_else_branch() {
+        let result = run(serde_json::json!({"type": "order_placed"})).await;
+        assert!(!condition_outcome(&result));
+
+        let trace = result.trace.unwrap();
+        assert_eq!(trace.find(|k| matches!(k, StepKind::ServiceCall { .. })).count(), 0);
+        assert!(trace.render_tree().contains("process signup"));
+    }
+
+    #[tokio::test]
+    async fn test_trace_serializes_to_json() {
+        let result = run(serde_json::json!({"type": "new_user_registers"})).await;
+        let json = serde_json::to_value(&result).unwrap();
+        let steps = json["trace"]["steps"].as_array().unwrap();
+        assert_eq!(steps[1]["kind"]["type"], "condition");
+        assert_eq!(steps[1]["kind"]["outcome"], true);
+
+        let back: SimulationResult = serde_json::from_value(json).unwrap();
+        assert_eq!(back.trace, result.trace);
+    }
+
+    #[tokio::test]
+    async fn test_parse_errors_fail_simulation() {
+        let result = Simulator::new().simulate("if @", SimulationConfig::default()).await.unwrap();
+        assert!(!result.success);
+        assert_eq!(result.errors.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_failure_injection_takes_failure_branch() {
+        let mocks = mock::MockRegistry::new().with_mock(mock::MockDefinition {
+            service: "SendGrid".to_string(),
+            operation: Some("validate".to_string()),
+            response: None,
+            failures: vec![mock::Failure::Error { message: "invalid address".to_string() }],
+        });
+        let config = SimulationConfig {
+            input: serde_json::json!({"type": "new_user_registers", "email": "nope"}),
+            ..SimulationConfig::default()
+        };
+
+        let result = Simulator::new().with_mocks(mocks).simulate(PROGRAM, config).await.unwrap();
+        assert!(!result.success);
+        assert_eq!(result.output["success"], false);
+        assert!(result.errors[0].contains("invalid address"));
+
+        assert_eq!(result.calls.len(), 1);
+        assert_eq!(result.calls[0].service, "SendGrid");
+        assert_eq!(result.calls[0].params["value"], "nope");
+        assert!(!result.calls[0].success);
+    }
+
+    #[tokio::test]
+    async fn test_strict_mocks_fail_unmatched_calls() {
+        let config = SimulationConfig {
+            input: serde_json::json!({"type": "new_user_registers"}),
+            ..SimulationConfig::default()
+        };
+        let strict = Simulator::new().with_mocks(mock::MockRegistry::new().strict(true));
+        let result = strict.simulate(PROGRAM, config.clone()).await.unwrap();
+        assert!(!result.success);
+        assert!(result.errors[0].contains("No mock defined"));
+
+        let lenient = Simulator::new().simulate(PROGRAM, config).await.unwrap();
+        assert!(lenient.success);
+        assert!(lenient.calls[0].success);
+    }
+
+    #[tokio::test]
+    async fn test_injected_latency_counts_against_timeout() {
+        let mocks = mock::MockRegistry::new().with_mock(mock::MockDefinition {
+            service: "SendGrid".to_string(),
+            operation: None,
+            response: None,
+            failures: vec![mock::Failure::Latency { ms: 5_000 }],
+        });
+        let config = SimulationConfig {
+            input: serde_json::json!({"type": "new_user_registers"}),
+            timeout_seconds: 2,
+            ..SimulationConfig::default()
+        };
+
+        let result = Simulator::new().with_mocks(mocks).simulate(PROGRAM, config).await.unwrap();
+        assert!(!result.success);
+        assert!(result.errors[0].contains("timed out"));
+        let step = result.trace.unwrap().steps.into_iter().find(|s| matches!(s.kind, StepKind::ServiceCall { .. })).unwrap();
+        assert!(step.duration_us >= 5_000_000);
+    }
+
+    #[tokio::test]
+    async fn test_assertions_run_when_validating_outputs() {
+        let spec = validation::ValidationSpec {
+            expect_success: Some(true),
+            ..Default::default()
+        };
+        let simulator = Simulator::new().with_assertions(spec);
+
+        let result = simulator.simulate(PROGRAM, SimulationConfig::default()).await.unwrap();
+        assert!(result.validation.unwrap().passed);
+
+        let config = SimulationConfig {
+            validate_outputs: false,
+            ..SimulationConfig::default()
+        };
+        assert!(simulator.simulate(PROGRAM, config).await.unwrap().validation.is_none());
+    }
+}
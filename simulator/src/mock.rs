@@ -0,0 +1,217 @@
+//! Mocked responses for external service calls
+//!
+//! A [`MockRegistry`] maps a service name and operation to a canned response, with
+//! optional failure injections. Registries are plain data and are usually loaded from a
+//! JSON or YAML file:
+//!
+//! ```yaml
+//! strict: true
+//! mocks:
+//!   - service: SendGrid
+//!     operation: validate
+//!     response: { valid: true }
+//!     failures:
+//!       - type: nth_call
+//!         n: 2
+//!         message: rate limited
+//! ```
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::Path;
+
+/// Response returned for a mocked call when nothing more specific is configured
+pub fn default_response(service: &str, operation: &str) -> Value {
+    json!({
+        "success": true,
+        "service": service,
+        "operation": operation,
+    })
+}
+
+/// A failure injected into a mocked service
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Failure {
+    /// Every call fails
+    Error { message: String },
+    /// Calls succeed but take this long; counts against the simulation timeout
+    Latency { ms: u64 },
+    /// Only the `n`th matching call (1-based) fails
+    NthCall { n: usize, message: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MockDefinition {
+    pub service: String,
+    /// Operation to match, such as `validate`; matches every operation when absent
+    #[serde(default)]
+    pub operation: Option<String>,
+    #[serde(default)]
+    pub response: Option<Value>,
+    #[serde(default)]
+    pub failures: Vec<Failure>,
+}
+
+/// What a mocked call produced
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockOutcome {
+    /// Response body, or the injected error message
+    pub result: Result<Value, String>,
+    pub latency_ms: u64,
+    /// Whether a definition matched the call
+    pub matched: bool,
+}
+
+impl MockOutcome {
+    /// The response body, treating a body with `"success": false` as a failure
+    pub fn into_result(self) -> Result<Value, String> {
+        match self.result {
+            Ok(body) if body.get("success").and_then(Value::as_bool) == Some(false) => Err(
+                body.get("error").and_then(Value::as_str).unwrap_or("service reported failure").to_string(),
+            ),
+            other => other,
+        }
+    }
+}
+
+/// A service call made during simulation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedCall {
+    pub service: String,
+    pub operation: String,
+    pub params: Value,
+    pub mocked: bool,
+    pub success: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MockRegistry {
+    #[serde(default)]
+    pub mocks: Vec<MockDefinition>,
+    /// Fail calls that match no definition instead of returning a default success
+    #[serde(default)]
+    pub strict: bool,
+}
+
+impl MockRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load a registry from a `.json`, `.yaml` or `.yml` file
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("yaml") | Some("yml") => Ok(serde_yaml::from_str(&content)?),
+            _ => Ok(serde_json::from_str(&content)?),
+        }
+    }
+
+    pub fn with_mock(mut self, mock: MockDefinition) -> Self {
+        self.mocks.push(mock);
+        self
+    }
+
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Find the definition for a call, preferring an exact operation match
+    pub fn find(&self, service: &str, operation: &str) -> Option<&MockDefinition> {
+        let service_matches = |m: &&MockDefinition| m.service.eq_ignore_ascii_case(service);
+        self.mocks.iter()
+            .filter(service_matches)
+            .find(|m| m.operation.as_deref().is_some_and(|op| op.eq_ignore_ascii_case(operation)))
+            .or_else(|| self.mocks.iter().filter(service_matches).find(|m| m.operation.is_none()))
+    }
+
+    /// Resolve the `call_number`th (1-based) call to `service`/`operation`
+    pub fn respond(&self, service: &str, operation: &str, call_number: usize) -> MockOutcome {
+        let Some(mock) = self.find(service, operation) else {
+            let result = if self.strict {
+                Err(format!("No mock defined for {}.{}", service, operation))
+            } else {
+                Ok(default_response(service, operation))
+            };
+            return MockOutcome { result, latency_ms: 0, matched: false };
+        };
+
+        let mut latency_ms = 0;
+        let mut error = None;
+        for failure in &mock.failures {
+            match failure {
+                Failure::Error { message } => error = Some(message.clone()),
+                Failure::Latency { ms } => latency_ms += ms,
+                Failure::NthCall { n, message } if *n == call_number => error = Some(message.clone()),
+                Failure::NthCall { .. } => {}
+            }
+        }
+
+        let result = match error {
+            Some(message) => Err(message),
+            None => Ok(mock.response.clone().unwrap_or_else(|| default_response(service, operation))),
+        };
+        MockOutcome { result, latency_ms, matched: true }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> MockRegistry {
+        MockRegistry::new()
+            .with_mock(MockDefinition {
+                service: "SendGrid".to_string(),
+                operation: Some("validate".to_string()),
+                response: Some(json!({"valid": true})),
+                failures: vec![Failure::NthCall { n: 2, message: "rate limited".to_string() }],
+            })
+            .with_mock(MockDefinition {
+                service: "SendGrid".to_string(),
+                operation: None,
+                response: None,
+                failures: vec![Failure::Latency { ms: 250 }],
+            })
+    }
+
+    #[test]
+    fn test_operation_match_and_nth_call_failure() {
+        let registry = registry();
+        assert_eq!(registry.respond("sendgrid", "validate", 1).result, Ok(json!({"valid": true})));
+        assert_eq!(registry.respond("SendGrid", "validate", 2).result, Err("rate limited".to_string()));
+        assert!(registry.respond("SendGrid", "validate", 3).result.is_ok());
+
+        let fallback = registry.respond("SendGrid", "send", 1);
+        assert_eq!(fallback.latency_ms, 250);
+        assert!(fallback.matched);
+    }
+
+    #[test]
+    fn test_unmatched_calls_respect_strict_flag() {
+        let lenient = registry().respond("Twilio", "send", 1);
+        assert!(!lenient.matched);
+        assert!(lenient.result.is_ok());
+
+        assert!(registry().strict(true).respond("Twilio", "send", 1).result.is_err());
+    }
+
+    #[test]
+    fn test_load_yaml_and_json() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let yaml = dir.path().join("mocks.yaml");
+        std::fs::write(&yaml, "strict: true\nmocks:\n  - service: SendGrid\n    failures:\n      - type: error\n        message: down\n").unwrap();
+        let loaded = MockRegistry::from_file(&yaml).unwrap();
+        assert!(loaded.strict);
+        assert_eq!(loaded.mocks[0].failures, vec![Failure::Error { message: "down".to_string() }]);
+
+        let json = dir.path().join("mocks.json");
+        std::fs::write(&json, serde_json::to_string(&registry()).unwrap()).unwrap();
+        assert_eq!(MockRegistry::from_file(&json).unwrap(), registry());
+    }
+}
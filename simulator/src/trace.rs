@@ -0,0 +1,90 @@
+//! Execution traces recorded during simulation
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::time::Duration;
+
+/// What happened at a single step of a simulated execution
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StepKind {
+    /// The input event handed to the function
+    ParseInput { input: serde_json::Value },
+    Condition { expr: String, outcome: bool },
+    ServiceCall {
+        service: String,
+        mocked: bool,
+        request: serde_json::Value,
+        response: serde_json::Value,
+    },
+    /// An action without an external service, such as `process order`
+    Action { action: String, target: Option<String> },
+    Assignment { variable: String, value: serde_json::Value },
+    Output { value: serde_json::Value },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraceStep {
+    pub kind: StepKind,
+    /// Nesting level, 0 for top-level statements
+    pub depth: usize,
+    pub timestamp: DateTime<Utc>,
+    pub duration_us: u64,
+}
+
+/// Ordered record of every step taken by a simulation
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExecutionTrace {
+    pub steps: Vec<TraceStep>,
+}
+
+impl ExecutionTrace {
+    pub fn new() -> Self {
+        Self { steps: Vec::new() }
+    }
+
+    pub fn record(&mut self, kind: StepKind, depth: usize, duration: Duration) {
+        self.steps.push(TraceStep {
+            kind,
+            depth,
+            timestamp: Utc::now(),
+            duration_us: duration.as_micros() as u64,
+        });
+    }
+
+    /// Steps matching a predicate, in execution order
+    pub fn find<'a>(&'a self, predicate: impl Fn(&StepKind) -> bool + 'a) -> impl Iterator<Item = &'a TraceStep> + 'a {
+        self.steps.iter().filter(move |s| predicate(&s.kind))
+    }
+
+    /// Render the trace as an indented tree, one step per line
+    pub fn render_tree(&self) -> String {
+        let mut out = String::new();
+        for step in &self.steps {
+            let indent = "  ".repeat(step.depth);
+            let _ = writeln!(out, "{}├─ {} ({}µs)", indent, describe(&step.kind), step.duration_us);
+        }
+        out
+    }
+}
+
+fn describe(kind: &StepKind) -> String {
+    match kind {
+        StepKind::ParseInput { input } => format!("input {}", input),
+        StepKind::Condition { expr, outcome } => format!("if {} → {}", expr, outcome),
+        StepKind::ServiceCall { service, mocked, request, response } => format!(
+            "call {}{} {} → {}",
+            service,
+            if *mocked { " [mock]" } else { "" },
+            request,
+            response
+        ),
+        StepKind::Action { action, target } => match target {
+            Some(target) => format!("{} {}", action, target),
+            None => action.clone(),
+        },
+        StepKind::Assignment { variable, value } => format!("{} = {}", variable, value),
+        StepKind::Output { value } => format!("output {}", value),
+    }
+}
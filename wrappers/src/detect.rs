@@ -0,0 +1,319 @@
+//! Language detection for unlabeled code
+//!
+//! Each line is checked against a table of keywords and idioms, every signal counting
+//! once per snippet. JavaScript idioms count towards TypeScript too, so TypeScript only
+//! wins on evidence of its own, such as type annotations or interfaces.
+
+use crate::Language;
+
+/// Languages detection chooses between, in tie-breaking order
+const CANDIDATES: [Language; 6] = [
+    Language::Python,
+    Language::JavaScript,
+    Language::TypeScript,
+    Language::Bash,
+    Language::Rust,
+    Language::Go,
+];
+
+/// Score at which the evidence alone gives about two-thirds confidence
+const EVIDENCE_SCALE: f32 = 2.5;
+
+enum Pattern {
+    Prefix(&'static str),
+    Contains(&'static str),
+    AnyOf(&'static [&'static str]),
+    Line(&'static [&'static str]),
+    Custom(fn(&str) -> bool),
+}
+
+impl Pattern {
+    fn matches(&self, line: &str) -> bool {
+        match self {
+            Pattern::Prefix(prefix) => line.starts_with(prefix),
+            Pattern::Contains(needle) => line.contains(needle),
+            Pattern::AnyOf(needles) => needles.iter().any(|needle| line.contains(needle)),
+            Pattern::Line(lines) => lines.contains(&line),
+            Pattern::Custom(matches) => matches(line),
+        }
+    }
+}
+
+const PY: &[Language] = &[Language::Python];
+const JS: &[Language] = &[Language::JavaScript, Language::TypeScript];
+const TS: &[Language] = &[Language::TypeScript];
+const SH: &[Language] = &[Language::Bash];
+const RS: &[Language] = &[Language::Rust];
+const GO: &[Language] = &[Language::Go];
+
+/// Evidence for the languages it names, matched against trimmed lines
+struct Signal {
+    languages: &'static [Language],
+    weight: f32,
+    pattern: Pattern,
+}
+
+const fn signal(languages: &'static [Language], weight: f32, pattern: Pattern) -> Signal {
+    Signal { languages, weight, pattern }
+}
+
+const SIGNALS: &[Signal] = &[
+    // Python
+    signal(PY, 3.0, Pattern::Custom(|l| l.starts_with("def ") && l.ends_with(':'))),
+    signal(PY, 3.0, Pattern::Custom(|l| l.starts_with("from ") && l.contains(" import "))),
+    signal(PY, 2.0, Pattern::Custom(|l| l.starts_with("import ") && !l.contains(['"', '\'', '(', '{', ';']))),
+    signal(PY, 2.5, Pattern::Custom(|l| {
+        l.ends_with(':')
+            && ["if ", "for ", "while ", "with ", "class ", "try", "except", "else", "elif "].iter().any(|k| l.starts_with(k))
+    })),
+    signal(PY, 3.0, Pattern::Prefix("elif ")),
+    signal(PY, 3.0, Pattern::Contains("__name__")),
+    signal(PY, 1.5, Pattern::Custom(|l| l.contains("print(") && !l.contains("print!("))),
+    signal(PY, 1.0, Pattern::Contains("self.")),
+    signal(PY, 1.0, Pattern::AnyOf(&["True", "False", " None"])),
+    signal(PY, 1.0, Pattern::AnyOf(&["(f\"", " f\"", "(f'", " f'"])),
+    signal(PY, 1.0, Pattern::AnyOf(&["lambda ", ".append(", ".items()", " not in ", " is not "])),
+    // JavaScript and TypeScript
+    signal(JS, 2.5, Pattern::Contains("console.")),
+    signal(JS, 2.5, Pattern::AnyOf(&["===", "!=="])),
+    signal(JS, 3.0, Pattern::AnyOf(&["require(", "module.exports", "exports."])),
+    signal(JS, 3.0, Pattern::Custom(|l| l.starts_with("import ") && l.contains(" from ") && l.contains(['"', '\'']))),
+    signal(JS, 2.0, Pattern::AnyOf(&["export default", "export const", "export function", "export async"])),
+    signal(JS, 2.0, Pattern::Custom(|l| l.contains("function") && l.contains('('))),
+    signal(JS, 1.0, Pattern::Custom(|l| l.starts_with("const ") && !l.contains(": "))),
+    signal(JS, 2.0, Pattern::AnyOf(&["JSON.", "document.", "window.", "new Promise", "undefined"])),
+    signal(JS, 1.5, Pattern::AnyOf(&["process.env", "process.argv", "process.stdin", ".then(", ".forEach("])),
+    signal(JS, 2.0, Pattern::Custom(|l| l.contains('`') && l.contains("${"))),
+    // TypeScript
+    signal(TS, 3.0, Pattern::Custom(|l| l.starts_with("interface ") || l.starts_with("export interface "))),
+    signal(TS, 3.0, Pattern::Custom(|l| (l.starts_with("type ") || l.starts_with("export type ")) && l.contains(" = "))),
+    signal(TS, 3.0, Pattern::Prefix("import type ")),
+    signal(TS, 2.0, Pattern::AnyOf(&[": string", ": number", ": boolean", ": void", ": any", ": unknown", ": never"])),
+    signal(TS, 2.0, Pattern::AnyOf(&["string[]", "number[]", "Promise<", "Record<", "Array<"])),
+    signal(TS, 2.0, Pattern::AnyOf(&[" as const", " as unknown", "readonly ", "?: "])),
+    signal(TS, 1.0, Pattern::AnyOf(&["private ", "public ", "implements "])),
+    // Bash
+    signal(SH, 2.0, Pattern::Prefix("echo ")),
+    signal(SH, 1.5, Pattern::Prefix("printf ")),
+    signal(SH, 3.0, Pattern::Line(&["fi", "done", "esac", "fi;", "done;"])),
+    signal(SH, 3.0, Pattern::Custom(|l| l.ends_with("; then") || l == "then")),
+    signal(SH, 3.0, Pattern::Custom(|l| l.ends_with("; do") || l == "do")),
+    signal(SH, 3.0, Pattern::Custom(|l| l.starts_with("case ") && l.ends_with(" in"))),
+    signal(SH, 3.0, Pattern::Prefix("set -")),
+    signal(SH, 1.5, Pattern::Contains("$(")),
+    signal(SH, 1.0, Pattern::Contains("${")),
+    signal(SH, 2.0, Pattern::AnyOf(&["[[ ", "if [ ", "while [ "])),
+    signal(SH, 2.5, Pattern::Custom(|l| {
+        l.strip_prefix("export ")
+            .and_then(|rest| rest.split_whitespace().next())
+            .is_some_and(|word| word.contains('='))
+    })),
+    signal(SH, 2.0, Pattern::Prefix("local ")),
+    signal(SH, 2.0, Pattern::AnyOf(&["\"$@\"", "$1", "$#", "$?"])),
+    signal(SH, 1.0, Pattern::Custom(shell_assignment)),
+    signal(SH, 2.0, Pattern::AnyOf(&["| grep", "| awk", "| sed", "| xargs", "| wc", "| sort", "| head", "| tail"])),
+    signal(SH, 2.0, Pattern::AnyOf(&[">&2", "2>&1", "/dev/null"])),
+    signal(SH, 1.5, Pattern::Prefix("exit ")),
+    // Rust
+    signal(RS, 3.0, Pattern::Custom(|l| {
+        ["fn ", "pub fn ", "async fn ", "pub async fn ", "pub(crate) fn "].iter().any(|k| l.starts_with(k))
+    })),
+    signal(RS, 3.0, Pattern::Prefix("let mut ")),
+    signal(RS, 3.0, Pattern::Custom(has_macro_call)),
+    signal(RS, 3.0, Pattern::Custom(|l| l.starts_with("use ") && l.contains("::") && l.ends_with(';'))),
+    signal(RS, 3.0, Pattern::Custom(|l| l.starts_with("impl ") || l.starts_with("impl<"))),
+    signal(RS, 2.5, Pattern::Prefix("#[")),
+    signal(RS, 3.0, Pattern::AnyOf(&[".unwrap()", ".expect(", "?;"])),
+    signal(RS, 2.0, Pattern::AnyOf(&["&str", "&mut ", "&self"])),
+    signal(RS, 2.0, Pattern::AnyOf(&["::new(", "String::from", "::<"])),
+    signal(RS, 2.0, Pattern::AnyOf(&["Vec<", "Option<", "Result<", "HashMap<"])),
+    signal(RS, 2.0, Pattern::Custom(|l| l.starts_with("struct ") || l.starts_with("pub struct "))),
+    signal(RS, 2.0, Pattern::Custom(|l| l.starts_with("match ") && l.ends_with('{'))),
+    signal(RS, 1.5, Pattern::AnyOf(&["Some(", "Ok(", "Err("])),
+    // Go
+    signal(GO, 4.0, Pattern::Prefix("package ")),
+    signal(GO, 3.0, Pattern::Prefix("func ")),
+    signal(GO, 2.0, Pattern::Contains(":=")),
+    signal(GO, 3.0, Pattern::Contains("fmt.")),
+    signal(GO, 3.0, Pattern::Custom(|l| l == "import (" || l.starts_with("import \""))),
+    signal(GO, 3.0, Pattern::Contains("err != nil")),
+    signal(GO, 3.0, Pattern::Custom(|l| l.starts_with("type ") && (l.ends_with("struct {") || l.ends_with("interface {")))),
+    signal(GO, 2.0, Pattern::Prefix("defer ")),
+    signal(GO, 1.5, Pattern::AnyOf(&["go func", "chan ", "<-"])),
+    signal(GO, 1.5, Pattern::Contains("nil")),
+    signal(GO, 2.0, Pattern::AnyOf(&["[]string", "[]int", "[]byte", "map[string]", "make("])),
+];
+
+/// `NAME=value`, with nothing between the name and the `=`
+fn shell_assignment(line: &str) -> bool {
+    let Some((name, value)) = line.split_once('=') else {
+        return false;
+    };
+    !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && !value.starts_with(['=', ' '])
+}
+
+/// `name!(` or `name![`, as in `println!(` and `vec![`
+fn has_macro_call(line: &str) -> bool {
+    line.match_indices('!').any(|(i, _)| {
+        let before = line[..i].chars().next_back();
+        let after = line[i + 1..].chars().next();
+        before.is_some_and(|c| c.is_ascii_alphanumeric() || c == '_') && matches!(after, Some('(' | '['))
+    })
+}
+
+/// The language a `#!` line runs the script with
+fn shebang_language(line: &str) -> Option<Language> {
+    let mut words = line.strip_prefix("#!")?.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|word| !word.starts_with('-'))?;
+    }
+    if program.starts_with("python") {
+        Some(Language::Python)
+    } else if ["ts-node", "tsx", "deno", "bun"].contains(&program) {
+        Some(Language::TypeScript)
+    } else if program.starts_with("node") {
+        Some(Language::JavaScript)
+    } else if ["bash", "sh", "zsh", "ksh", "dash"].contains(&program) {
+        Some(Language::Bash)
+    } else if ["rust-script", "cargo"].contains(&program) {
+        Some(Language::Rust)
+    } else if ["go", "gorun"].contains(&program) {
+        Some(Language::Go)
+    } else {
+        None
+    }
+}
+
+/// Guess the language of `code` and how confident the guess is, from 0 to 1.
+///
+/// A shebang line settles the question outright. Otherwise confidence combines the
+/// winner's share of all the evidence with how much evidence there is, so a snippet
+/// that matches a single idiom never scores highly. `None` when nothing matched.
+pub fn detect_language(code: &str) -> Option<(Language, f32)> {
+    let mut lines = code.lines().map(str::trim).filter(|line| !line.is_empty()).peekable();
+    if let Some(language) = lines.peek().and_then(|line| shebang_language(line)) {
+        return Some((language, 1.0));
+    }
+
+    let mut seen = vec![false; SIGNALS.len()];
+    for line in lines {
+        if ["//", "/*", "*", "--"].iter().any(|comment| line.starts_with(comment))
+            || (line.starts_with('#') && !line.starts_with("#["))
+        {
+            continue;
+        }
+        for (i, signal) in SIGNALS.iter().enumerate() {
+            if !seen[i] && signal.pattern.matches(line) {
+                seen[i] = true;
+            }
+        }
+    }
+
+    let mut scores = [0.0f32; CANDIDATES.len()];
+    for signal in SIGNALS.iter().zip(&seen).filter(|(_, seen)| **seen).map(|(signal, _)| signal) {
+        for language in signal.languages {
+            let index = CANDIDATES.iter().position(|candidate| candidate == language).unwrap();
+            scores[index] += signal.weight;
+        }
+    }
+
+    let (best, best_score) = scores
+        .iter()
+        .enumerate()
+        .fold((0, 0.0), |(best, best_score), (i, &score)| if score > best_score { (i, score) } else { (best, best_score) });
+    if best_score == 0.0 {
+        return None;
+    }
+
+    // JavaScript and TypeScript share most of their evidence, so neither counts against the other
+    let sibling = match CANDIDATES[best] {
+        Language::JavaScript => Some(Language::TypeScript),
+        Language::TypeScript => Some(Language::JavaScript),
+        _ => None,
+    };
+    let total: f32 = CANDIDATES
+        .iter()
+        .zip(scores)
+        .filter(|(language, _)| Some(**language) != sibling)
+        .map(|(_, score)| score)
+        .sum();
+    let share = best_score / total;
+    let evidence = 1.0 - (-best_score / EVIDENCE_SCALE).exp();
+    Some((CANDIDATES[best], share * evidence))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Snippets as code generation and MCP tools tend to produce them
+    const CORPUS: &[(Language, &str)] = &[
+        (Language::Python, "import json\nimport sys\n\ndata = json.load(sys.stdin)\nprint(data['name'])\n"),
+        (Language::Python, "def fib(n):\n    if n < 2:\n        return n\n    return fib(n - 1) + fib(n - 2)\n\nprint(fib(10))\n"),
+        (Language::Python, "from collections import Counter\n\nwords = open('input.txt').read().split()\nfor word, count in Counter(words).most_common(5):\n    print(f\"{word}: {count}\")\n"),
+        (Language::Python, "class Stack:\n    def __init__(self):\n        self.items = []\n\n    def push(self, item):\n        self.items.append(item)\n"),
+        (Language::Python, "if __name__ == \"__main__\":\n    main()\n"),
+        (Language::Python, "try:\n    value = int(raw)\nexcept ValueError:\n    value = None\n"),
+        (Language::Python, "squares = [x * x for x in range(10) if x % 2 == 0]\nprint(sum(squares))\n"),
+        (Language::JavaScript, "const fs = require('fs');\nconst input = fs.readFileSync(0, 'utf8');\nconsole.log(input.toUpperCase());\n"),
+        (Language::JavaScript, "function greet(name) {\n  return `Hello, ${name}!`;\n}\nconsole.log(greet('world'));\n"),
+        (Language::JavaScript, "const total = items.reduce((sum, item) => sum + item.price, 0);\nif (total === 0) {\n  console.warn('empty cart');\n}\n"),
+        (Language::JavaScript, "module.exports = async function handler(event) {\n  return { statusCode: 200, body: JSON.stringify(event) };\n};\n"),
+        (Language::JavaScript, "fetch(url)\n  .then((res) => res.json())\n  .then((data) => console.log(data));\n"),
+        (Language::JavaScript, "import express from 'express';\nconst app = express();\napp.get('/', (req, res) => res.send('ok'));\napp.listen(3000);\n"),
+        (Language::TypeScript, "interface User {\n  id: number;\n  name: string;\n}\n\nconst user: User = { id: 1, name: 'Ada' };\nconsole.log(user.name);\n"),
+        (Language::TypeScript, "function add(a: number, b: number): number {\n  return a + b;\n}\n"),
+        (Language::TypeScript, "type Status = 'active' | 'inactive';\nexport function toggle(status: Status): Status {\n  return status === 'active' ? 'inactive' : 'active';\n}\n"),
+        (Language::TypeScript, "import type { Request } from 'express';\n\nexport async function handler(req: Request): Promise<void> {\n  console.log(req.body);\n}\n"),
+        (Language::TypeScript, "class Counter {\n  private count = 0;\n  increment(): void {\n    this.count++;\n  }\n}\n"),
+        (Language::TypeScript, "const names: string[] = ['a', 'b'];\nconst lengths = names.map((n) => n.length) as const;\n"),
+        (Language::Bash, "#!/usr/bin/env bash\nset -euo pipefail\necho \"starting\"\n"),
+        (Language::Bash, "for file in *.log; do\n  gzip \"$file\"\ndone\n"),
+        (Language::Bash, "if [ -z \"$1\" ]; then\n  echo \"usage: $0 <name>\" >&2\n  exit 1\nfi\n"),
+        (Language::Bash, "COUNT=$(ls | wc -l)\necho \"There are $COUNT files\"\n"),
+        (Language::Bash, "grep -r TODO src | awk -F: '{print $1}' | sort | uniq\n"),
+        (Language::Bash, "case \"$1\" in\n  start) echo starting ;;\n  stop) echo stopping ;;\nesac\n"),
+        (Language::Bash, "export PATH=\"$HOME/bin:$PATH\"\nmkdir -p \"${OUT_DIR}\"\n"),
+        (Language::Rust, "fn main() {\n    println!(\"Hello, world!\");\n}\n"),
+        (Language::Rust, "use std::collections::HashMap;\n\nfn count(words: &[&str]) -> HashMap<&str, usize> {\n    let mut counts = HashMap::new();\n    for w in words {\n        *counts.entry(*w).or_insert(0) += 1;\n    }\n    counts\n}\n"),
+        (Language::Rust, "#[derive(Debug, Clone)]\npub struct Point {\n    x: f64,\n    y: f64,\n}\n"),
+        (Language::Rust, "impl Point {\n    pub fn norm(&self) -> f64 {\n        (self.x * self.x + self.y * self.y).sqrt()\n    }\n}\n"),
+        (Language::Rust, "let input = std::fs::read_to_string(\"in.txt\").unwrap();\nlet numbers: Vec<i64> = input.lines().map(|l| l.parse().unwrap()).collect();\n"),
+        (Language::Rust, "match value {\n    Some(v) => println!(\"{}\", v),\n    None => eprintln!(\"missing\"),\n}\n"),
+        (Language::Go, "package main\n\nimport \"fmt\"\n\nfunc main() {\n\tfmt.Println(\"hello\")\n}\n"),
+        (Language::Go, "func divide(a, b float64) (float64, error) {\n\tif b == 0 {\n\t\treturn 0, errors.New(\"divide by zero\")\n\t}\n\treturn a / b, nil\n}\n"),
+        (Language::Go, "type Server struct {\n\tAddr string\n\tPort int\n}\n"),
+        (Language::Go, "data, err := os.ReadFile(path)\nif err != nil {\n\tlog.Fatal(err)\n}\n"),
+        (Language::Go, "results := make(chan int)\ngo func() {\n\tresults <- compute()\n}()\nfmt.Println(<-results)\n"),
+        (Language::Go, "import (\n\t\"net/http\"\n)\n\nfunc handler(w http.ResponseWriter, r *http.Request) {\n\tdefer r.Body.Close()\n}\n"),
+    ];
+
+    #[test]
+    fn test_detection_accuracy_on_labeled_corpus() {
+        let misses: Vec<String> = CORPUS
+            .iter()
+            .filter_map(|(expected, code)| match detect_language(code) {
+                Some((language, _)) if language == *expected => None,
+                other => Some(format!("expected {:?}, got {:?} for {:?}", expected, other, code)),
+            })
+            .collect();
+        let accuracy = 1.0 - misses.len() as f32 / CORPUS.len() as f32;
+        assert!(accuracy > 0.9, "accuracy {:.2}:\n{}", accuracy, misses.join("\n"));
+    }
+
+    #[test]
+    fn test_confidence_reflects_evidence() {
+        assert_eq!(detect_language("#!/usr/bin/env python3\nx = 1\n"), Some((Language::Python, 1.0)));
+        assert_eq!(detect_language("#!/bin/sh\n"), Some((Language::Bash, 1.0)));
+        assert_eq!(detect_language(""), None);
+        assert_eq!(detect_language("hello there\n"), None);
+
+        let (_, weak) = detect_language("COUNT=3\n").unwrap();
+        let (_, strong) = detect_language(CORPUS[32].1).unwrap();
+        assert!(weak < 0.5 && strong > 0.9, "weak {} strong {}", weak, strong);
+    }
+}
@@ -0,0 +1,74 @@
+//! Wrapper error types
+
+use crate::Language;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// A single compiler diagnostic with its source position
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub message: String,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+}
+
+/// A construct rejected by a static safety policy
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyFinding {
+    pub line: usize,
+    pub construct: String,
+    pub reason: String,
+}
+
+#[derive(Error, Debug)]
+pub enum WrapperError {
+    #[error("Syntax error{}: {message}", line.map(|l| format!(" at line {}", l)).unwrap_or_default())]
+    SyntaxError { line: Option<usize>, message: String },
+
+    #[error("Compilation failed: {}", diagnostics.iter().map(|d| d.message.as_str()).collect::<Vec<_>>().join("; "))]
+    CompileError { diagnostics: Vec<Diagnostic> },
+
+    #[error("Policy violation: {}", findings.iter().map(|f| format!("line {}: {} ({})", f.line, f.construct, f.reason)).collect::<Vec<_>>().join("; "))]
+    PolicyViolation { findings: Vec<PolicyFinding> },
+
+    #[error(
+        "Could not detect the language{}; specify it explicitly",
+        best.map(|(language, confidence)| format!(
+            " (best guess {} at {:.2}, below {:.2})", language.name(), confidence, min_confidence
+        )).unwrap_or_default()
+    )]
+    LanguageUndetected { best: Option<(Language, f32)>, min_confidence: f32 },
+
+    #[error("{feature} is not supported on {platform}")]
+    UnsupportedOnPlatform { feature: String, platform: &'static str },
+
+    /// The execution left more bytes in its working directory than it may
+    #[error("Execution wrote {used} bytes to its working directory, over the quota of {limit}")]
+    QuotaExceeded { used: u64, limit: u64 },
+
+    #[error("Interpreter not found: {name}")]
+    InterpreterNotFound { name: String },
+
+    #[error("IO error: {source}")]
+    IoError {
+        #[from]
+        source: std::io::Error,
+    },
+}
+
+impl WrapperError {
+    /// `feature` is unavailable on the platform this binary was built for
+    pub fn unsupported(feature: impl Into<String>) -> Self {
+        Self::UnsupportedOnPlatform {
+            feature: feature.into(),
+            platform: std::env::consts::OS,
+        }
+    }
+
+    pub fn syntax(line: Option<usize>, message: impl Into<String>) -> Self {
+        Self::SyntaxError {
+            line,
+            message: message.into(),
+        }
+    }
+}
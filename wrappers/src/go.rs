@@ -0,0 +1,181 @@
+//! Go language wrapper
+//!
+//! Sources are written to `main.go` in a scratch directory, checked with `go vet`, and
+//! run with `go run`. Children that don't inherit the parent environment get a shared
+//! build cache under the system temp directory, since the go tool refuses to build
+//! without one.
+
+use crate::error::{Diagnostic, WrapperError};
+use crate::{process, ExecutionOutput, ExecutionRequest, LanguageWrapper, ResourceLimits};
+use anyhow::Result;
+use async_trait::async_trait;
+use std::path::PathBuf;
+use tokio::process::Command;
+
+/// Runs Go source with the `go` tool
+pub struct GoWrapper {
+    go: PathBuf,
+    cache_dir: PathBuf,
+    limits: ResourceLimits,
+}
+
+impl GoWrapper {
+    pub fn new() -> Result<Self> {
+        let go = which::which("go")
+            .map_err(|_| WrapperError::InterpreterNotFound { name: "go".to_string() })?;
+        Ok(Self {
+            go,
+            cache_dir: std::env::temp_dir().join("talkpp-go-cache"),
+            limits: ResourceLimits::default(),
+        })
+    }
+
+    pub fn with_limits(mut self, limits: ResourceLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn with_cache_dir(mut self, cache_dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = cache_dir.into();
+        self
+    }
+
+    /// Write `code` as `main.go` in a fresh directory
+    fn write_main(code: &str) -> Result<tempfile::TempDir> {
+        let dir = tempfile::tempdir()?;
+        std::fs::write(dir.path().join("main.go"), code)?;
+        Ok(dir)
+    }
+}
+
+#[async_trait]
+impl LanguageWrapper for GoWrapper {
+    async fn execute_with(&self, mut request: ExecutionRequest) -> Result<ExecutionOutput> {
+        let source = Self::write_main(&request.code)?;
+
+        if !request.inherit_env {
+            let cache = self.cache_dir.to_string_lossy().into_owned();
+            request.env.entry("GOCACHE".to_string()).or_insert_with(|| cache.clone());
+            request.env.entry("GOPATH".to_string()).or_insert(cache);
+        }
+
+        let mut command = Command::new(&self.go);
+        command.arg("run").arg(source.path().join("main.go"));
+
+        process::run_sandboxed(command, &request, &self.limits).await
+    }
+
+    fn validate(&self, code: &str) -> Result<()> {
+        let source = Self::write_main(code)?;
+        let output = std::process::Command::new(&self.go)
+            .args(["vet", "main.go"])
+            .current_dir(source.path())
+            .output()?;
+
+        if output.status.success() {
+            return Ok(());
+        }
+
+        let diagnostics = parse_diagnostics(&String::from_utf8_lossy(&output.stderr));
+        Err(WrapperError::CompileError { diagnostics }.into())
+    }
+
+    fn version(&self) -> String {
+        std::process::Command::new(&self.go)
+            .arg("version")
+            .output()
+            .ok()
+            .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+            .filter(|v| !v.is_empty())
+            .unwrap_or_else(|| "unknown".to_string())
+    }
+}
+
+/// Parse `./main.go:3:5: undefined: foo` lines from `go vet` or the compiler
+fn parse_diagnostics(stderr: &str) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = stderr
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim().trim_start_matches("vet: ");
+            let (location, message) = line.split_once(".go:")
+                .and_then(|(_, rest)| rest.split_once(": "))?;
+            let mut position = location.split(':');
+            Some(Diagnostic {
+                message: message.to_string(),
+                line: position.next()?.parse().ok(),
+                column: position.next().and_then(|c| c.parse().ok()),
+            })
+        })
+        .collect();
+
+    if diagnostics.is_empty() {
+        diagnostics.push(Diagnostic {
+            message: stderr.trim().to_string(),
+            line: None,
+            column: None,
+        });
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wrapper() -> Option<GoWrapper> {
+        let wrapper = GoWrapper::new().ok();
+        if wrapper.is_none() {
+            eprintln!("skipping: go is not installed");
+        }
+        wrapper
+    }
+
+    #[test]
+    fn test_parse_vet_diagnostics() {
+        let diagnostics = parse_diagnostics(
+            "# command-line-arguments\nvet: ./main.go:4:2: declared and not used: x\n./main.go:7:9: undefined: missing\n",
+        );
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].line, Some(4));
+        assert_eq!(diagnostics[0].column, Some(2));
+        assert_eq!(diagnostics[0].message, "declared and not used: x");
+        assert_eq!(diagnostics[1].message, "undefined: missing");
+
+        let fallback = parse_diagnostics("go: cannot find GOROOT directory\n");
+        assert_eq!(fallback[0].line, None);
+    }
+
+    #[tokio::test]
+    async fn test_stdin_and_env_round_trip() {
+        let Some(go) = wrapper() else { return };
+        let code = r#"package main
+
+import (
+	"fmt"
+	"io"
+	"os"
+)
+
+func main() {
+	input, _ := io.ReadAll(os.Stdin)
+	fmt.Println(os.Getenv("GREETING"), string(input))
+}
+"#;
+
+        let request = ExecutionRequest::new(code).with_env("GREETING", "hello").with_stdin("world");
+        let output = go.execute_with(request).await.unwrap();
+        assert_eq!(output.stdout.trim(), "hello world", "{}", output.stderr);
+    }
+
+    #[test]
+    fn test_validate_reports_vet_errors() {
+        let Some(go) = wrapper() else { return };
+        assert!(go.validate("package main\n\nfunc main() {}\n").is_ok());
+
+        let err = go.validate("package main\n\nfunc main() {\n\tx := 1\n}\n").unwrap_err();
+        match err.downcast_ref::<WrapperError>() {
+            Some(WrapperError::CompileError { diagnostics }) => assert_eq!(diagnostics[0].line, Some(4)),
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+}
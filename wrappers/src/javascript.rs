@@ -0,0 +1,78 @@
+//! JavaScript language wrapper
+
+use crate::error::WrapperError;
+use crate::{process, ExecutionOutput, ExecutionRequest, LanguageWrapper, ResourceLimits};
+use anyhow::Result;
+use async_trait::async_trait;
+use std::path::PathBuf;
+use tokio::process::Command;
+
+/// Executes JavaScript under Node.js
+pub struct JavaScriptWrapper {
+    node: PathBuf,
+    limits: ResourceLimits,
+}
+
+impl JavaScriptWrapper {
+    pub fn new() -> Result<Self> {
+        let node = which::which("node")
+            .map_err(|_| WrapperError::InterpreterNotFound { name: "node".to_string() })?;
+        Ok(Self {
+            node,
+            limits: ResourceLimits::default(),
+        })
+    }
+
+    pub fn with_limits(mut self, limits: ResourceLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+}
+
+#[async_trait]
+impl LanguageWrapper for JavaScriptWrapper {
+    async fn execute_with(&self, request: ExecutionRequest) -> Result<ExecutionOutput> {
+        let source = process::write_source(&request.code, ".js")?;
+
+        let mut command = Command::new(&self.node);
+        command.arg(source.path());
+
+        process::run_sandboxed(command, &request, &self.limits).await
+    }
+
+    fn validate(&self, code: &str) -> Result<()> {
+        let source = process::write_source(code, ".js")?;
+        let output = std::process::Command::new(&self.node)
+            .arg("--check")
+            .arg(source.path())
+            .output()?;
+
+        if output.status.success() {
+            return Ok(());
+        }
+
+        // node reports `<path>:<line>` on the first line and the error on a later one
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let line = stderr
+            .lines()
+            .next()
+            .and_then(|l| l.rsplit(':').next())
+            .and_then(|n| n.trim().parse().ok());
+        let message = stderr
+            .lines()
+            .find(|l| l.contains("Error"))
+            .unwrap_or(stderr.trim())
+            .to_string();
+
+        Err(WrapperError::syntax(line, message).into())
+    }
+
+    fn version(&self) -> String {
+        std::process::Command::new(&self.node)
+            .arg("--version")
+            .output()
+            .ok()
+            .map(|o| format!("node {}", String::from_utf8_lossy(&o.stdout).trim()))
+            .unwrap_or_else(|| "unknown".to_string())
+    }
+}
@@ -5,16 +5,210 @@
 
 pub mod python;
 pub mod javascript;
+pub mod typescript;
 pub mod bash;
 pub mod rust;
+pub mod go;
+pub mod detect;
+pub mod error;
+pub mod platform;
+pub mod process;
+pub mod workdir;
 
 use anyhow::Result;
+use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+pub use detect::detect_language;
+pub use error::WrapperError;
+
+/// Captured result of running code through a wrapper
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutionOutput {
+    pub stdout: String,
+    pub stderr: String,
+    /// `None` when the process was killed by a signal or timeout
+    pub exit_code: Option<i32>,
+    pub duration: Duration,
+    pub timed_out: bool,
+    /// Set when stdout or stderr exceeded `max_output_bytes`
+    pub truncated: bool,
+    /// Set when the request was cancelled before the process exited; the output is
+    /// whatever it wrote until then
+    #[serde(default)]
+    pub cancelled: bool,
+}
+
+impl ExecutionOutput {
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0) && !self.timed_out && !self.cancelled
+    }
+}
+
+/// Resource limits applied to wrapper subprocesses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResourceLimits {
+    pub timeout: Duration,
+    pub max_output_bytes: usize,
+    /// RLIMIT_CPU on unix, the job's per-process user time limit on Windows
+    pub max_cpu_seconds: Option<u64>,
+    /// RLIMIT_AS on unix, the job's per-process committed memory limit on Windows
+    pub max_memory_bytes: Option<u64>,
+}
+
+impl Default for ResourceLimits {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            max_output_bytes: 1024 * 1024,
+            max_cpu_seconds: None,
+            max_memory_bytes: None,
+        }
+    }
+}
+
+/// Output stream a log line was written to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+}
+
+/// Receives a child's output line by line while it runs. Output is still captured in
+/// full for the [`ExecutionOutput`].
+#[derive(Clone)]
+pub struct LogSink(Arc<LogFn>);
+
+type LogFn = dyn Fn(LogStream, &str) + Send + Sync;
+
+impl LogSink {
+    pub fn new(f: impl Fn(LogStream, &str) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(f))
+    }
+
+    pub fn send(&self, stream: LogStream, line: &str) {
+        (self.0)(stream, line)
+    }
+
+    /// Splits raw chunks of `stream` into lines for this sink
+    pub fn lines(&self, stream: LogStream) -> LineForwarder {
+        LineForwarder {
+            sink: self.clone(),
+            stream,
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl std::fmt::Debug for LogSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("LogSink")
+    }
+}
+
+/// Buffers output until a newline, so lines split across reads arrive whole
+pub struct LineForwarder {
+    sink: LogSink,
+    stream: LogStream,
+    pending: Vec<u8>,
+}
+
+impl LineForwarder {
+    pub fn push(&mut self, chunk: &[u8]) {
+        self.pending.extend_from_slice(chunk);
+        while let Some(end) = self.pending.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            self.sink.send(self.stream, String::from_utf8_lossy(&line[..end]).trim_end_matches('\r'));
+        }
+    }
+
+    /// Send any trailing output that didn't end in a newline
+    pub fn finish(self) {
+        if !self.pending.is_empty() {
+            self.sink.send(self.stream, &String::from_utf8_lossy(&self.pending));
+        }
+    }
+}
+
+/// Everything a wrapper needs to run a piece of code.
+///
+/// The parent environment is not inherited unless `inherit_env` is set, so host
+/// secrets never leak into generated code by accident.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExecutionRequest {
+    pub code: String,
+    pub args: Vec<String>,
+    pub stdin: Option<Vec<u8>>,
+    pub env: HashMap<String, String>,
+    pub working_dir: Option<PathBuf>,
+    pub inherit_env: bool,
+    /// Where to stream output as it is produced
+    #[serde(skip)]
+    pub log_sink: Option<LogSink>,
+    /// Kills the process, and anything it started, when cancelled
+    #[serde(skip)]
+    pub cancel: Option<CancellationToken>,
+}
+
+impl ExecutionRequest {
+    pub fn new(code: impl Into<String>) -> Self {
+        Self {
+            code: code.into(),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
+
+    pub fn with_stdin(mut self, stdin: impl Into<Vec<u8>>) -> Self {
+        self.stdin = Some(stdin.into());
+        self
+    }
+
+    pub fn with_env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn with_working_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.working_dir = Some(dir.into());
+        self
+    }
+
+    pub fn inheriting_env(mut self) -> Self {
+        self.inherit_env = true;
+        self
+    }
+
+    pub fn with_log_sink(mut self, sink: LogSink) -> Self {
+        self.log_sink = Some(sink);
+        self
+    }
+
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+}
 
 /// Language wrapper trait
-pub trait LanguageWrapper {
+#[async_trait]
+pub trait LanguageWrapper: Send + Sync {
     /// Execute code in the target language
-    async fn execute(&self, code: &str, args: &[String]) -> Result<String>;
+    async fn execute(&self, code: &str, args: &[String]) -> Result<ExecutionOutput> {
+        self.execute_with(ExecutionRequest::new(code).with_args(args.to_vec())).await
+    }
+
+    /// Execute a full request with stdin, environment, and working directory
+    async fn execute_with(&self, request: ExecutionRequest) -> Result<ExecutionOutput>;
     
     /// Validate code syntax
     fn validate(&self, code: &str) -> Result<()>;
@@ -23,7 +217,7 @@ pub trait LanguageWrapper {
     fn version(&self) -> String;
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Language {
     Python,
     JavaScript,
@@ -35,28 +229,177 @@ pub enum Language {
     CSharp,
 }
 
+impl Language {
+    /// Parse a language name as used in function metadata (`"python"`, `"ts"`, ...)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "python" | "py" => Some(Self::Python),
+            "javascript" | "js" | "node" => Some(Self::JavaScript),
+            "typescript" | "ts" => Some(Self::TypeScript),
+            "bash" | "sh" | "shell" => Some(Self::Bash),
+            "rust" | "rs" => Some(Self::Rust),
+            "go" | "golang" => Some(Self::Go),
+            "java" => Some(Self::Java),
+            "csharp" | "c#" | "cs" => Some(Self::CSharp),
+            _ => None,
+        }
+    }
+
+    /// Name `from_name` parses back to this language
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Python => "python",
+            Self::JavaScript => "javascript",
+            Self::TypeScript => "typescript",
+            Self::Bash => "bash",
+            Self::Rust => "rust",
+            Self::Go => "go",
+            Self::Java => "java",
+            Self::CSharp => "csharp",
+        }
+    }
+}
+
+/// Detection confidence below which `WrapperFactory::create_for_code` asks for an
+/// explicit language
+pub const DEFAULT_DETECTION_THRESHOLD: f32 = 0.5;
+
 /// Wrapper factory for creating language-specific wrappers
 pub struct WrapperFactory;
 
 impl WrapperFactory {
     /// Create a wrapper for the specified language
-    pub fn create_wrapper(language: Language) -> Result<Box<dyn LanguageWrapper + Send + Sync>> {
+    pub fn create_wrapper(language: Language) -> Result<Box<dyn LanguageWrapper>> {
+        Self::create_wrapper_with_limits(language, ResourceLimits::default())
+    }
+
+    /// Create a wrapper for the specified language that runs code under `limits`
+    pub fn create_wrapper_with_limits(language: Language, limits: ResourceLimits) -> Result<Box<dyn LanguageWrapper>> {
         match language {
-            Language::Python => Ok(Box::new(python::PythonWrapper::new()?)),
-            Language::JavaScript => Ok(Box::new(javascript::JavaScriptWrapper::new()?)),
-            Language::Bash => Ok(Box::new(bash::BashWrapper::new()?)),
-            Language::Rust => Ok(Box::new(rust::RustWrapper::new()?)),
+            Language::Python => Ok(Box::new(python::PythonWrapper::new()?.with_limits(limits))),
+            Language::JavaScript => Ok(Box::new(javascript::JavaScriptWrapper::new()?.with_limits(limits))),
+            Language::TypeScript => Ok(Box::new(typescript::TypeScriptWrapper::new()?.with_limits(limits))),
+            Language::Bash => Ok(Box::new(bash::BashWrapper::new()?.with_limits(limits))),
+            Language::Rust => Ok(Box::new(rust::RustWrapper::new()?.with_limits(limits))),
+            Language::Go => Ok(Box::new(go::GoWrapper::new()?.with_limits(limits))),
             _ => Err(anyhow::anyhow!("Language not supported: {:?}", language)),
         }
     }
 
+    /// Create a wrapper for the language detected in `code`
+    pub fn create_for_code(code: &str) -> Result<Box<dyn LanguageWrapper>> {
+        Self::create_for_code_with_threshold(code, DEFAULT_DETECTION_THRESHOLD)
+    }
+
+    /// Create a wrapper for the language detected in `code`, failing with
+    /// [`WrapperError::LanguageUndetected`] if detection is less confident than `min_confidence`
+    pub fn create_for_code_with_threshold(code: &str, min_confidence: f32) -> Result<Box<dyn LanguageWrapper>> {
+        let (language, _) = Self::detect(code, min_confidence)?;
+        Self::create_wrapper(language)
+    }
+
+    /// The language of `code` and the confidence it was detected with, if at least `min_confidence`
+    pub fn detect(code: &str, min_confidence: f32) -> Result<(Language, f32), WrapperError> {
+        match detect_language(code) {
+            Some((language, confidence)) if confidence >= min_confidence => Ok((language, confidence)),
+            best => Err(WrapperError::LanguageUndetected { best, min_confidence }),
+        }
+    }
+
     /// Get all supported languages
     pub fn supported_languages() -> Vec<Language> {
         vec![
             Language::Python,
             Language::JavaScript,
+            Language::TypeScript,
             Language::Bash,
             Language::Rust,
+            Language::Go,
         ]
     }
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAMS: &[(Language, &str)] = &[
+        (
+            Language::Python,
+            "import os, sys\nprint(os.environ.get('GREETING'), sys.stdin.read().strip())\n",
+        ),
+        (
+            Language::JavaScript,
+            "const input = require('fs').readFileSync(0, 'utf8').trim();\nconsole.log(process.env.GREETING, input);\n",
+        ),
+        (
+            Language::Bash,
+            "read -r input\necho \"$GREETING $input\"\n",
+        ),
+        (
+            Language::Rust,
+            "use std::io::Read;\nfn main() {\n    let mut input = String::new();\n    std::io::stdin().read_to_string(&mut input).unwrap();\n    println!(\"{} {}\", std::env::var(\"GREETING\").unwrap(), input.trim());\n}\n",
+        ),
+    ];
+
+    #[tokio::test]
+    async fn test_env_and_stdin_echoed_across_languages() {
+        // Windows machines may have no bash, and PowerShell is only an opt-in fallback
+        for (language, code) in PROGRAMS.iter().filter(|(language, _)| cfg!(unix) || *language != Language::Bash) {
+            let wrapper = WrapperFactory::create_wrapper(*language).unwrap();
+            let request = ExecutionRequest::new(*code)
+                .with_env("GREETING", "hello")
+                .with_stdin("world\n");
+
+            let output = wrapper.execute_with(request).await.unwrap();
+            assert_eq!(output.stdout.trim(), "hello world", "{:?}: {}", language, output.stderr);
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_create_for_code_requires_confident_detection() {
+        let code = "name=world\necho \"hello $name\"\n";
+        let output = WrapperFactory::create_for_code(code).unwrap().execute(code, &[]).await.unwrap();
+        assert_eq!(output.stdout.trim(), "hello world");
+
+        let err = WrapperFactory::create_for_code("COUNT=3\n").err().unwrap();
+        match err.downcast_ref::<WrapperError>() {
+            Some(WrapperError::LanguageUndetected { best: Some((Language::Bash, _)), .. }) => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert!(WrapperFactory::create_for_code_with_threshold("COUNT=3\n", 0.3).is_ok());
+        assert!(WrapperFactory::create_for_code("").is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_log_sink_receives_lines_as_written() {
+        let lines = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = lines.clone();
+        let sink = LogSink::new(move |stream, line| seen.lock().unwrap().push((stream, line.to_string())));
+
+        let wrapper = WrapperFactory::create_wrapper(Language::Bash).unwrap();
+        let code = "printf 'one\\ntw'; sleep 0.1; printf 'o\\n'; echo oops >&2; printf 'tail'";
+        let output = wrapper.execute_with(ExecutionRequest::new(code).with_log_sink(sink)).await.unwrap();
+
+        assert_eq!(output.stdout, "one\ntwo\ntail");
+        let lines = lines.lock().unwrap();
+        let stdout: Vec<&str> = lines.iter().filter(|(s, _)| *s == LogStream::Stdout).map(|(_, l)| l.as_str()).collect();
+        assert_eq!(stdout, vec!["one", "two", "tail"]);
+        assert!(lines.contains(&(LogStream::Stderr, "oops".to_string())));
+    }
+
+    #[tokio::test]
+    async fn test_parent_env_not_inherited_by_default() {
+        std::env::set_var("TALKPP_HOST_SECRET", "leaked");
+        let wrapper = WrapperFactory::create_wrapper(Language::Python).unwrap();
+        let code = "import os\nprint(os.environ.get('TALKPP_HOST_SECRET', 'absent'))\n";
+
+        let output = wrapper.execute_with(ExecutionRequest::new(code)).await.unwrap();
+        assert_eq!(output.stdout.trim(), "absent");
+
+        let output = wrapper.execute_with(ExecutionRequest::new(code).inheriting_env()).await.unwrap();
+        assert_eq!(output.stdout.trim(), "leaked");
+    }
+}
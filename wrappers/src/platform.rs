@@ -0,0 +1,291 @@
+//! Operating-system specifics of running wrapper subprocesses
+//!
+//! Limits and cleanup differ by platform:
+//!
+//! - **Unix:** the child leads its own process group, CPU time and address space are
+//!   capped with `setrlimit` before `exec`, and a timeout kills the whole group.
+//! - **Windows:** the child is assigned to a Job Object right after it starts. The job
+//!   caps per-process user time and committed memory, and closing or terminating it
+//!   kills every process in it. Anything the child starts before it is assigned escapes
+//!   the job.
+//! - **Elsewhere:** no limits are applied beyond the wall-clock timeout, and only the
+//!   child itself is killed.
+
+use crate::ResourceLimits;
+use std::path::{Path, PathBuf};
+use tokio::process::{Child, Command};
+
+/// PATH given to children that don't inherit the parent environment
+#[cfg(not(windows))]
+pub const DEFAULT_PATH: &str = "/usr/local/bin:/usr/bin:/bin";
+#[cfg(windows)]
+pub const DEFAULT_PATH: &str = r"C:\Windows\System32;C:\Windows;C:\Windows\System32\WindowsPowerShell\v1.0";
+
+/// Parent variables kept for children that don't inherit the environment. Windows
+/// programs, PowerShell included, fail to start without them.
+#[cfg(windows)]
+const PRESERVED_VARS: &[&str] = &["SystemRoot", "SystemDrive", "windir", "ComSpec", "PATHEXT", "TEMP", "TMP"];
+#[cfg(not(windows))]
+const PRESERVED_VARS: &[&str] = &[];
+
+/// The environment a child starts from when it doesn't inherit the parent's
+pub fn base_env() -> Vec<(String, String)> {
+    let mut env = vec![("PATH".to_string(), DEFAULT_PATH.to_string())];
+    env.extend(PRESERVED_VARS.iter().filter_map(|name| Some((name.to_string(), std::env::var(name).ok()?))));
+    env
+}
+
+/// A path in the form the platform's programs accept as a working directory or mount.
+///
+/// Repeated and trailing separators are dropped everywhere. On Windows, `/` becomes
+/// `\` and the verbatim `\\?\` prefix `canonicalize` adds is removed, since neither
+/// `cmd` nor PowerShell accept it as a current directory; see [`normalize_windows_path`].
+pub fn normalize_path(path: &Path) -> PathBuf {
+    if cfg!(windows) {
+        PathBuf::from(normalize_windows_path(&path.to_string_lossy()))
+    } else {
+        path.components().collect()
+    }
+}
+
+/// Windows form of `path`: backslash separators, no repeated or trailing separators,
+/// and `\\?\C:\x` or `\\?\UNC\server\share\x` written as `C:\x` or `\\server\share\x`
+pub fn normalize_windows_path(path: &str) -> String {
+    let path = path.replace('/', "\\");
+    let (prefix, rest) = if let Some(rest) = path.strip_prefix(r"\\?\UNC\") {
+        (r"\\", rest)
+    } else if let Some(rest) = path.strip_prefix(r"\\?\") {
+        ("", rest)
+    } else if let Some(rest) = path.strip_prefix(r"\\") {
+        (r"\\", rest)
+    } else if let Some(rest) = path.strip_prefix('\\') {
+        ("\\", rest)
+    } else {
+        ("", path.as_str())
+    };
+
+    let mut normalized = prefix.to_string();
+    normalized.push_str(&rest.split('\\').filter(|part| !part.is_empty()).collect::<Vec<_>>().join("\\"));
+    // A bare drive needs its separator to mean the drive's root
+    if normalized.len() == 2 && normalized.ends_with(':') {
+        normalized.push('\\');
+    }
+    normalized
+}
+
+/// CPU and memory caps for one child process, applied the way the platform allows.
+///
+/// Call [`prepare`](Self::prepare) before spawning, [`attach`](Self::attach) right
+/// after, and [`kill`](Self::kill) to stop the child and everything it started.
+pub struct ProcessLimits {
+    cpu_seconds: Option<u64>,
+    memory_bytes: Option<u64>,
+    #[cfg(windows)]
+    job: Option<windows::JobObject>,
+}
+
+impl ProcessLimits {
+    pub fn new(limits: &ResourceLimits) -> Self {
+        Self {
+            cpu_seconds: limits.max_cpu_seconds,
+            memory_bytes: limits.max_memory_bytes,
+            #[cfg(windows)]
+            job: None,
+        }
+    }
+
+    /// Configure a command before it is spawned. On unix this starts a new process
+    /// group and sets `RLIMIT_CPU` and `RLIMIT_AS` in the child.
+    pub fn prepare(&self, command: &mut Command) {
+        #[cfg(unix)]
+        unix::prepare(command, self.cpu_seconds, self.memory_bytes);
+        #[cfg(not(unix))]
+        let _ = command;
+    }
+
+    /// Bind a spawned child to the limits. On Windows this places it in a new Job
+    /// Object, which kills the child's processes when the limits are dropped.
+    pub fn attach(&mut self, child: &Child) -> std::io::Result<()> {
+        #[cfg(windows)]
+        {
+            self.job = Some(windows::JobObject::for_child(child, self.cpu_seconds, self.memory_bytes)?);
+        }
+        #[cfg(not(windows))]
+        let _ = child;
+        Ok(())
+    }
+
+    /// Kill the child and, where the platform tracks them, the processes it started
+    pub async fn kill(&self, child: &mut Child) {
+        #[cfg(unix)]
+        unix::kill_group(child);
+        #[cfg(windows)]
+        if let Some(job) = &self.job {
+            job.terminate();
+        }
+
+        let _ = child.kill().await;
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use tokio::process::{Child, Command};
+
+    pub(super) fn prepare(command: &mut Command, cpu_seconds: Option<u64>, memory_bytes: Option<u64>) {
+        command.process_group(0);
+
+        if cpu_seconds.is_none() && memory_bytes.is_none() {
+            return;
+        }
+
+        // SAFETY: only async-signal-safe setrlimit calls run between fork and exec
+        unsafe {
+            command.pre_exec(move || {
+                if let Some(seconds) = cpu_seconds {
+                    set_rlimit(libc::RLIMIT_CPU, seconds)?;
+                }
+                if let Some(bytes) = memory_bytes {
+                    set_rlimit(libc::RLIMIT_AS, bytes)?;
+                }
+                Ok(())
+            });
+        }
+    }
+
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    type RlimitResource = libc::__rlimit_resource_t;
+    #[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+    type RlimitResource = libc::c_int;
+
+    fn set_rlimit(resource: RlimitResource, value: u64) -> std::io::Result<()> {
+        let limit = libc::rlimit {
+            rlim_cur: value as libc::rlim_t,
+            rlim_max: value as libc::rlim_t,
+        };
+        // SAFETY: `limit` is a valid rlimit struct for the duration of the call
+        if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub(super) fn kill_group(child: &Child) {
+        if let Some(pid) = child.id() {
+            // SAFETY: the child leads its own process group, so pgid == pid
+            unsafe {
+                libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::io;
+    use tokio::process::Child;
+    use windows_sys::Win32::Foundation::{CloseHandle, HANDLE};
+    use windows_sys::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, JobObjectExtendedLimitInformation, SetInformationJobObject,
+        TerminateJobObject, JOBOBJECT_EXTENDED_LIMIT_INFORMATION, JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+        JOB_OBJECT_LIMIT_PROCESS_MEMORY, JOB_OBJECT_LIMIT_PROCESS_TIME,
+    };
+
+    /// An unnamed Job Object that kills its processes when closed
+    pub(super) struct JobObject(HANDLE);
+
+    // SAFETY: job handles may be used and closed from any thread
+    unsafe impl Send for JobObject {}
+    unsafe impl Sync for JobObject {}
+
+    impl JobObject {
+        pub(super) fn for_child(child: &Child, cpu_seconds: Option<u64>, memory_bytes: Option<u64>) -> io::Result<Self> {
+            let process = child.raw_handle()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "child has already exited"))?;
+
+            // SAFETY: a null name and null security attributes create an unnamed job
+            let handle = unsafe { CreateJobObjectW(std::ptr::null(), std::ptr::null()) };
+            if handle == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let job = Self(handle);
+
+            // SAFETY: all-zero is a valid JOBOBJECT_EXTENDED_LIMIT_INFORMATION
+            let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = unsafe { std::mem::zeroed() };
+            info.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+            if let Some(seconds) = cpu_seconds {
+                info.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_PROCESS_TIME;
+                // In 100ns ticks
+                info.BasicLimitInformation.PerProcessUserTimeLimit = seconds.saturating_mul(10_000_000).min(i64::MAX as u64) as i64;
+            }
+            if let Some(bytes) = memory_bytes {
+                info.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_PROCESS_MEMORY;
+                info.ProcessMemoryLimit = bytes.min(usize::MAX as u64) as usize;
+            }
+
+            // SAFETY: `info` outlives the call and its size is passed alongside it;
+            // `process` is the live child's handle
+            unsafe {
+                if SetInformationJobObject(
+                    job.0,
+                    JobObjectExtendedLimitInformation,
+                    &info as *const _ as *const std::ffi::c_void,
+                    std::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+                ) == 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                if AssignProcessToJobObject(job.0, process as HANDLE) == 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            Ok(job)
+        }
+
+        pub(super) fn terminate(&self) {
+            // SAFETY: the handle stays open until drop
+            unsafe {
+                TerminateJobObject(self.0, 1);
+            }
+        }
+    }
+
+    impl Drop for JobObject {
+        fn drop(&mut self) {
+            // SAFETY: the handle is owned and closed exactly once
+            unsafe {
+                CloseHandle(self.0);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_windows_paths_are_normalized() {
+        assert_eq!(normalize_windows_path("C:/Users/talk/work/"), r"C:\Users\talk\work");
+        assert_eq!(normalize_windows_path(r"C:\Users\\talk\.\"), r"C:\Users\talk\.");
+        assert_eq!(normalize_windows_path(r"\\?\C:\Users\talk"), r"C:\Users\talk");
+        assert_eq!(normalize_windows_path(r"\\?\UNC\server\share\dir"), r"\\server\share\dir");
+        assert_eq!(normalize_windows_path("//server/share/dir"), r"\\server\share\dir");
+        assert_eq!(normalize_windows_path(r"\\?\C:\"), r"C:\");
+        assert_eq!(normalize_windows_path("C:"), r"C:\");
+        assert_eq!(normalize_windows_path(r"\temp\x"), r"\temp\x");
+        assert_eq!(normalize_windows_path(r"relative/dir"), r"relative\dir");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_paths_are_normalized() {
+        assert_eq!(normalize_path(Path::new("/tmp//talk/work/")), PathBuf::from("/tmp/talk/work"));
+        assert_eq!(normalize_path(Path::new("work/./out")), PathBuf::from("work/out"));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_native_paths_are_normalized() {
+        assert_eq!(normalize_path(Path::new(r"\\?\C:\Users\talk\")), PathBuf::from(r"C:\Users\talk"));
+    }
+}
@@ -0,0 +1,131 @@
+//! Sandboxed subprocess execution shared by the process-based wrappers
+
+use crate::platform::{self, ProcessLimits};
+use crate::{ExecutionOutput, ExecutionRequest, LineForwarder, LogStream, ResourceLimits};
+use anyhow::Result;
+use std::io::Write;
+use std::process::Stdio;
+use std::time::Instant;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::process::Command;
+use tracing::{info, warn};
+
+pub use crate::platform::DEFAULT_PATH;
+
+/// Write source code to a temporary file with the given extension
+pub fn write_source(code: &str, suffix: &str) -> Result<tempfile::NamedTempFile> {
+    let mut file = tempfile::Builder::new().suffix(suffix).tempfile()?;
+    file.write_all(code.as_bytes())?;
+    file.flush()?;
+    Ok(file)
+}
+
+/// Apply the environment, working directory, and arguments of a request to a command
+pub fn prepare_command(command: &mut Command, request: &ExecutionRequest) {
+    if !request.inherit_env {
+        command.env_clear();
+        command.envs(platform::base_env());
+    }
+    command.envs(&request.env).args(&request.args);
+
+    if let Some(dir) = &request.working_dir {
+        command.current_dir(platform::normalize_path(dir));
+    }
+}
+
+/// Run a prepared command under the given limits, capturing stdout and stderr separately.
+///
+/// The child is placed in its own process group on unix, or a Job Object on Windows, so
+/// that a timeout, or cancelling the request's token, kills anything it spawned. See
+/// [`platform`] for how limits differ.
+pub async fn run_sandboxed(mut command: Command, request: &ExecutionRequest, limits: &ResourceLimits) -> Result<ExecutionOutput> {
+    prepare_command(&mut command, request);
+    command
+        .stdin(if request.stdin.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    let mut process_limits = ProcessLimits::new(limits);
+    process_limits.prepare(&mut command);
+
+    let start = Instant::now();
+    let mut child = command.spawn()?;
+    process_limits.attach(&child)?;
+
+    if let (Some(data), Some(mut stdin)) = (request.stdin.clone(), child.stdin.take()) {
+        // Feed stdin concurrently so a child that never reads can't deadlock us
+        tokio::spawn(async move {
+            let _ = stdin.write_all(&data).await;
+        });
+    }
+
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+    let forward = |stream| request.log_sink.as_ref().map(|sink| sink.lines(stream));
+    let stdout_task = tokio::spawn(read_capped(stdout, limits.max_output_bytes, forward(LogStream::Stdout)));
+    let stderr_task = tokio::spawn(read_capped(stderr, limits.max_output_bytes, forward(LogStream::Stderr)));
+
+    let cancellation = async {
+        match &request.cancel {
+            Some(cancel) => cancel.cancelled().await,
+            None => std::future::pending().await,
+        }
+    };
+    // A child that has exited wins over a timeout or cancellation arriving alongside it
+    let (exit_code, timed_out, cancelled) = tokio::select! {
+        biased;
+        status = child.wait() => (status?.code(), false, false),
+        _ = tokio::time::sleep(limits.timeout) => {
+            warn!("Process exceeded timeout of {:?}, killing process group", limits.timeout);
+            process_limits.kill(&mut child).await;
+            (None, true, false)
+        }
+        _ = cancellation => {
+            info!("Execution cancelled, killing process group");
+            process_limits.kill(&mut child).await;
+            (None, false, true)
+        }
+    };
+
+    let (stdout, stdout_truncated) = stdout_task.await??;
+    let (stderr, stderr_truncated) = stderr_task.await??;
+
+    Ok(ExecutionOutput {
+        stdout,
+        stderr,
+        exit_code,
+        duration: start.elapsed(),
+        timed_out,
+        truncated: stdout_truncated || stderr_truncated,
+        cancelled,
+    })
+}
+
+/// Read a stream to completion, keeping at most `max_bytes`; the rest is drained and dropped.
+/// Everything read, including the dropped part, is passed to `forward`.
+async fn read_capped<R: AsyncRead + Unpin>(mut reader: R, max_bytes: usize, mut forward: Option<LineForwarder>) -> Result<(String, bool)> {
+    let mut captured = Vec::new();
+    let mut truncated = false;
+    let mut buf = [0u8; 8192];
+
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        if let Some(forward) = &mut forward {
+            forward.push(&buf[..n]);
+        }
+        let remaining = max_bytes.saturating_sub(captured.len());
+        if n > remaining {
+            truncated = true;
+        }
+        captured.extend_from_slice(&buf[..n.min(remaining)]);
+    }
+
+    if let Some(forward) = forward {
+        forward.finish();
+    }
+    Ok((String::from_utf8_lossy(&captured).into_owned(), truncated))
+}
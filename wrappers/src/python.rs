@@ -0,0 +1,160 @@
+//! Python language wrapper
+
+use crate::error::WrapperError;
+use crate::{process, ExecutionOutput, ExecutionRequest, LanguageWrapper, ResourceLimits};
+use anyhow::Result;
+use async_trait::async_trait;
+use std::path::PathBuf;
+use tokio::process::Command;
+
+/// Executes Python source in a subprocess of the configured interpreter
+pub struct PythonWrapper {
+    interpreter: PathBuf,
+    limits: ResourceLimits,
+}
+
+impl PythonWrapper {
+    /// Locate `python3` (falling back to `python`) on the PATH
+    pub fn new() -> Result<Self> {
+        let interpreter = which::which("python3")
+            .or_else(|_| which::which("python"))
+            .map_err(|_| WrapperError::InterpreterNotFound { name: "python3".to_string() })?;
+        Ok(Self::with_interpreter(interpreter))
+    }
+
+    pub fn with_interpreter(interpreter: impl Into<PathBuf>) -> Self {
+        Self {
+            interpreter: interpreter.into(),
+            limits: ResourceLimits::default(),
+        }
+    }
+
+    pub fn with_limits(mut self, limits: ResourceLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+}
+
+#[async_trait]
+impl LanguageWrapper for PythonWrapper {
+    async fn execute_with(&self, request: ExecutionRequest) -> Result<ExecutionOutput> {
+        let source = process::write_source(&request.code, ".py")?;
+
+        let mut command = Command::new(&self.interpreter);
+        command.arg(source.path());
+
+        process::run_sandboxed(command, &request, &self.limits).await
+    }
+
+    fn validate(&self, code: &str) -> Result<()> {
+        let source = process::write_source(code, ".py")?;
+        let output = std::process::Command::new(&self.interpreter)
+            .args(["-m", "py_compile"])
+            .arg(source.path())
+            .output()?;
+
+        if output.status.success() {
+            return Ok(());
+        }
+
+        Err(parse_syntax_error(&String::from_utf8_lossy(&output.stderr)).into())
+    }
+
+    fn version(&self) -> String {
+        std::process::Command::new(&self.interpreter)
+            .arg("--version")
+            .output()
+            .ok()
+            .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+            .filter(|v| !v.is_empty())
+            .unwrap_or_else(|| "unknown".to_string())
+    }
+}
+
+/// Map `py_compile` output such as `File "x.py", line 3` / `SyntaxError: invalid syntax`
+fn parse_syntax_error(stderr: &str) -> WrapperError {
+    let line = stderr
+        .lines()
+        .rev()
+        .filter_map(|l| l.split(", line ").nth(1))
+        .find_map(|rest| rest.split(|c: char| !c.is_ascii_digit()).next()?.parse().ok());
+
+    let message = stderr
+        .lines()
+        .rev()
+        .find(|l| l.contains("Error"))
+        .map(|l| l.trim().trim_start_matches("Sorry: ").to_string())
+        .unwrap_or_else(|| stderr.trim().to_string());
+
+    WrapperError::syntax(line, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn wrapper(limits: ResourceLimits) -> PythonWrapper {
+        PythonWrapper::new().expect("python3 available").with_limits(limits)
+    }
+
+    #[tokio::test]
+    async fn test_infinite_loop_killed_by_timeout() {
+        let python = wrapper(ResourceLimits {
+            timeout: Duration::from_millis(500),
+            ..ResourceLimits::default()
+        });
+
+        let output = python.execute("while True:\n    pass\n", &[]).await.unwrap();
+        assert!(output.timed_out);
+        assert!(output.exit_code.is_none());
+        assert!(output.duration < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_stderr_captured_on_exception() {
+        let python = wrapper(ResourceLimits::default());
+
+        let output = python.execute("print('before')\n1 / 0\n", &[]).await.unwrap();
+        assert_eq!(output.stdout.trim(), "before");
+        assert!(output.stderr.contains("ZeroDivisionError"));
+        assert_eq!(output.exit_code, Some(1));
+        assert!(!output.timed_out);
+    }
+
+    #[tokio::test]
+    async fn test_large_output_truncated() {
+        let python = wrapper(ResourceLimits {
+            max_output_bytes: 1024,
+            ..ResourceLimits::default()
+        });
+
+        let output = python.execute("print('x' * 100000)\n", &[]).await.unwrap();
+        assert!(output.truncated);
+        assert_eq!(output.stdout.len(), 1024);
+        assert_eq!(output.exit_code, Some(0));
+    }
+
+    #[tokio::test]
+    async fn test_args_passed_to_script() {
+        let python = wrapper(ResourceLimits::default());
+
+        let output = python
+            .execute("import sys\nprint(','.join(sys.argv[1:]))\n", &["a".to_string(), "b".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(output.stdout.trim(), "a,b");
+    }
+
+    #[test]
+    fn test_validate_reports_syntax_error_line() {
+        let python = wrapper(ResourceLimits::default());
+        assert!(python.validate("x = 1\n").is_ok());
+
+        let err = python.validate("x = 1\ndef broken(:\n    pass\n").unwrap_err();
+        match err.downcast_ref::<WrapperError>() {
+            Some(WrapperError::SyntaxError { line, .. }) => assert_eq!(*line, Some(2)),
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+}
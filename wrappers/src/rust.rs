@@ -0,0 +1,494 @@
+//! Rust language wrapper
+//!
+//! Sources are built with cargo into a per-content-hash directory, so running the same
+//! generated function again reuses the compiled binary. Dependencies can be declared in an
+//! embedded manifest header at the top of the file:
+//!
+//! ```text
+//! //! [dependencies]
+//! //! serde_json = "1"
+//! ```
+//!
+//! Only `[dependencies]` may be declared, on crates from the allowlist and by version.
+//! Build dependencies, target tables, patches and `path`/`git` sources could run code on
+//! the host while building, so they are refused.
+
+use crate::error::{Diagnostic, WrapperError};
+use crate::{process, ExecutionOutput, ExecutionRequest, LanguageWrapper, ResourceLimits};
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::process::Command;
+use tracing::{debug, info};
+
+const PACKAGE_NAME: &str = "talkpp_fn";
+const LAST_USED_MARKER: &str = ".last_used";
+
+/// Keys a dependency may set; any other, such as `path`, `git`, `package` or `registry`,
+/// would build something other than the allowed crate
+const DEPENDENCY_KEYS: &[&str] = &["version", "features", "default-features"];
+
+/// Compiles Rust source with cargo and runs the cached binary
+pub struct RustWrapper {
+    cargo: PathBuf,
+    cache_dir: PathBuf,
+    /// Holds the cache directory used when the user has none, removed with the wrapper
+    private_cache: Option<tempfile::TempDir>,
+    limits: ResourceLimits,
+    allowed_dependencies: HashSet<String>,
+    max_cache_bytes: u64,
+    compilations: AtomicUsize,
+}
+
+impl RustWrapper {
+    /// A wrapper caching builds in `talkpp/rust` under the user's cache directory
+    /// (`$XDG_CACHE_HOME` or `~/.cache`). Without one, or when other users could write to
+    /// it, builds are cached in a new private directory removed with the wrapper.
+    pub fn new() -> Result<Self> {
+        let cargo = which::which("cargo")
+            .map_err(|_| WrapperError::InterpreterNotFound { name: "cargo".to_string() })?;
+        // A shared, predictable directory could be seeded with binaries by another user
+        let user_cache = std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+            .and_then(|base| private_cache_dir(&base));
+        let (cache_dir, private_cache) = match user_cache {
+            Some(cache_dir) => (cache_dir, None),
+            None => {
+                let mut builder = tempfile::Builder::new();
+                builder.prefix("talkpp-rust-cache-");
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+                    builder.permissions(std::fs::Permissions::from_mode(0o700));
+                }
+                let private_cache = builder.tempdir()?;
+                (private_cache.path().to_path_buf(), Some(private_cache))
+            }
+        };
+        Ok(Self {
+            cargo,
+            cache_dir,
+            private_cache,
+            limits: ResourceLimits::default(),
+            allowed_dependencies: HashSet::new(),
+            max_cache_bytes: 2 * 1024 * 1024 * 1024,
+            compilations: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn with_limits(mut self, limits: ResourceLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Cache builds in `cache_dir`, which should be writable by trusted users only
+    pub fn with_cache_dir(mut self, cache_dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = cache_dir.into();
+        self.private_cache = None;
+        self
+    }
+
+    /// Crates that embedded manifests may depend on
+    pub fn with_allowed_dependencies<I, S>(mut self, crates: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.allowed_dependencies = crates.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn with_max_cache_bytes(mut self, bytes: u64) -> Self {
+        self.max_cache_bytes = bytes;
+        self
+    }
+
+    /// Number of cargo builds performed by this wrapper
+    pub fn compilation_count(&self) -> usize {
+        self.compilations.load(Ordering::Relaxed)
+    }
+
+    /// Remove every cached build, keeping the cache directory itself
+    pub fn clear_cache(&self) -> Result<()> {
+        if !self.cache_dir.exists() {
+            return Ok(());
+        }
+        for entry in std::fs::read_dir(&self.cache_dir)? {
+            std::fs::remove_dir_all(entry?.path())?;
+        }
+        Ok(())
+    }
+
+    fn toolchain_version(&self) -> String {
+        std::process::Command::new(&self.cargo)
+            .arg("--version")
+            .output()
+            .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+            .unwrap_or_default()
+    }
+
+    /// Write the cargo project for `code` and return its directory
+    fn prepare_project(&self, code: &str) -> Result<PathBuf> {
+        let manifest = EmbeddedManifest::parse(code)?;
+        for dependency in &manifest.dependency_names {
+            if !self.allowed_dependencies.contains(dependency) {
+                bail!("Dependency not allowed: {}", dependency);
+            }
+        }
+
+        let mut hasher = Sha256::new();
+        hasher.update(self.toolchain_version());
+        hasher.update(&manifest.dependencies);
+        hasher.update(code);
+        let project_dir = self.cache_dir.join(hex::encode(hasher.finalize()));
+
+        if !project_dir.join("Cargo.toml").exists() {
+            std::fs::create_dir_all(project_dir.join("src"))?;
+            std::fs::write(
+                project_dir.join("Cargo.toml"),
+                format!(
+                    "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[workspace]\n\n{}\n",
+                    PACKAGE_NAME, manifest.dependencies
+                ),
+            )?;
+            std::fs::write(project_dir.join("src").join("main.rs"), code)?;
+        }
+
+        Ok(project_dir)
+    }
+
+    /// Each build keeps its own target directory, whatever `CARGO_TARGET_DIR` or cargo
+    /// config say, so the binary is where `binary_path` looks and builds stay separate
+    fn target_dir(project_dir: &Path) -> PathBuf {
+        project_dir.join("target")
+    }
+
+    fn binary_path(project_dir: &Path) -> PathBuf {
+        Self::target_dir(project_dir).join("release").join(PACKAGE_NAME)
+    }
+
+    /// Evict least recently used builds until the cache fits in `max_cache_bytes`
+    fn evict(&self, keep: &Path) -> Result<()> {
+        let mut entries = Vec::new();
+        for entry in std::fs::read_dir(&self.cache_dir)? {
+            let path = entry?.path();
+            if path == keep {
+                continue;
+            }
+            let last_used = std::fs::metadata(path.join(LAST_USED_MARKER))
+                .and_then(|m| m.modified())
+                .unwrap_or(std::time::UNIX_EPOCH);
+            entries.push((last_used, dir_size(&path), path));
+        }
+
+        let mut total: u64 = dir_size(keep) + entries.iter().map(|(_, size, _)| size).sum::<u64>();
+        entries.sort_by_key(|(last_used, _, _)| *last_used);
+
+        for (_, size, path) in entries {
+            if total <= self.max_cache_bytes {
+                break;
+            }
+            debug!("Evicting cached Rust build {}", path.display());
+            std::fs::remove_dir_all(&path)?;
+            total = total.saturating_sub(size);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LanguageWrapper for RustWrapper {
+    async fn execute_with(&self, request: ExecutionRequest) -> Result<ExecutionOutput> {
+        let project_dir = self.prepare_project(&request.code)?;
+        let binary = Self::binary_path(&project_dir);
+
+        if !binary.exists() {
+            info!("Compiling Rust function in {}", project_dir.display());
+            self.compilations.fetch_add(1, Ordering::Relaxed);
+
+            let build = Command::new(&self.cargo)
+                .args(["build", "--release", "--quiet", "--target-dir"])
+                .arg(Self::target_dir(&project_dir))
+                .current_dir(&project_dir)
+                .output()
+                .await?;
+
+            if !build.status.success() {
+                return Ok(ExecutionOutput {
+                    stdout: String::new(),
+                    stderr: String::from_utf8_lossy(&build.stderr).into_owned(),
+                    exit_code: build.status.code(),
+                    duration: std::time::Duration::ZERO,
+                    timed_out: false,
+                    truncated: false,
+                    cancelled: false,
+                });
+            }
+            self.evict(&project_dir)?;
+        }
+
+        std::fs::write(project_dir.join(LAST_USED_MARKER), [])?;
+        process::run_sandboxed(Command::new(&binary), &request, &self.limits).await
+    }
+
+    fn validate(&self, code: &str) -> Result<()> {
+        let project_dir = self.prepare_project(code)?;
+        let output = std::process::Command::new(&self.cargo)
+            .args(["check", "--quiet", "--message-format=json", "--target-dir"])
+            .arg(Self::target_dir(&project_dir))
+            .current_dir(&project_dir)
+            .output()?;
+
+        if output.status.success() {
+            return Ok(());
+        }
+
+        let diagnostics = parse_diagnostics(&String::from_utf8_lossy(&output.stdout));
+        Err(WrapperError::CompileError { diagnostics }.into())
+    }
+
+    fn version(&self) -> String {
+        let version = self.toolchain_version();
+        if version.is_empty() { "unknown".to_string() } else { version }
+    }
+}
+
+/// The `[dependencies]` declared in leading `//!` comment lines
+#[derive(Debug, Default)]
+struct EmbeddedManifest {
+    /// The `[dependencies]` table as written to the generated Cargo.toml, serialized
+    /// from the parsed header rather than copied from it
+    dependencies: String,
+    dependency_names: Vec<String>,
+}
+
+impl EmbeddedManifest {
+    /// Parse the header as TOML, refusing any table but `[dependencies]` and any
+    /// dependency source but a version
+    fn parse(code: &str) -> Result<Self> {
+        let header: String = code
+            .lines()
+            .map_while(|l| l.trim_start().strip_prefix("//!"))
+            .map(|line| format!("{}\n", line.trim()))
+            .collect();
+        let mut header: toml::Table = header.parse().map_err(|e| anyhow!("Invalid embedded manifest: {}", e))?;
+        if let Some(table) = header.keys().find(|key| *key != "dependencies") {
+            bail!("Embedded manifests may only declare [dependencies], not [{}]", table);
+        }
+
+        let dependencies = match header.remove("dependencies") {
+            Some(toml::Value::Table(dependencies)) => dependencies,
+            Some(_) => bail!("[dependencies] in an embedded manifest must be a table"),
+            None => return Ok(Self::default()),
+        };
+        for (name, spec) in &dependencies {
+            match spec {
+                toml::Value::String(_) => {}
+                toml::Value::Table(spec) => {
+                    if let Some(key) = spec.keys().find(|key| !DEPENDENCY_KEYS.contains(&key.as_str())) {
+                        bail!("Dependency {} may not set `{}`", name, key);
+                    }
+                }
+                _ => bail!("Dependency {} must be a version or a table", name),
+            }
+        }
+
+        let dependency_names = dependencies.keys().cloned().collect();
+        let mut manifest = toml::Table::new();
+        manifest.insert("dependencies".to_string(), toml::Value::Table(dependencies));
+        Ok(Self { dependencies: toml::to_string(&manifest)?, dependency_names })
+    }
+}
+
+/// `talkpp/rust` under `base`, created if missing so only the current user can access
+/// it; `None` if it can't be created, or if it or `talkpp` belongs to another user or is
+/// writable by others
+fn private_cache_dir(base: &Path) -> Option<PathBuf> {
+    let dir = base.join("talkpp").join("rust");
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(&dir).ok()?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        // SAFETY: geteuid has no preconditions and cannot fail
+        let uid = unsafe { libc::geteuid() };
+        for path in [&dir, &base.join("talkpp")] {
+            let metadata = std::fs::symlink_metadata(path).ok()?;
+            if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o022 != 0 {
+                return None;
+            }
+        }
+    }
+    Some(dir)
+}
+
+/// Extract error diagnostics from `cargo --message-format=json` output
+fn parse_diagnostics(stdout: &str) -> Vec<Diagnostic> {
+    stdout
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .filter(|msg| msg["reason"] == "compiler-message" && msg["message"]["level"] == "error")
+        .map(|msg| {
+            let message = &msg["message"];
+            let span = message["spans"].as_array()
+                .and_then(|spans| spans.iter().find(|s| s["is_primary"] == true));
+            Diagnostic {
+                message: message["message"].as_str().unwrap_or_default().to_string(),
+                line: span.and_then(|s| s["line_start"].as_u64()).map(|n| n as usize),
+                column: span.and_then(|s| s["column_start"].as_u64()).map(|n| n as usize),
+            }
+        })
+        .collect()
+}
+
+fn dir_size(path: &Path) -> u64 {
+    std::fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .map(|e| match e.metadata() {
+                    Ok(m) if m.is_dir() => dir_size(&e.path()),
+                    Ok(m) => m.len(),
+                    Err(_) => 0,
+                })
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wrapper(cache_dir: &Path) -> RustWrapper {
+        RustWrapper::new().expect("cargo available").with_cache_dir(cache_dir)
+    }
+
+    #[tokio::test]
+    async fn test_second_execution_skips_compilation() {
+        let cache = tempfile::tempdir().unwrap();
+        let rust = wrapper(cache.path());
+        let code = "fn main() { println!(\"cached\"); }\n";
+
+        let first = rust.execute(code, &[]).await.unwrap();
+        let second = rust.execute(code, &[]).await.unwrap();
+
+        assert_eq!(first.stdout.trim(), "cached");
+        assert_eq!(second.stdout.trim(), "cached");
+        assert_eq!(rust.compilation_count(), 1);
+
+        rust.clear_cache().unwrap();
+        rust.execute(code, &[]).await.unwrap();
+        assert_eq!(rust.compilation_count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_configured_target_dir_is_ignored() {
+        // Cargo reads config from the directories above the project
+        let root = tempfile::tempdir().unwrap();
+        let shared = root.path().join("shared-target");
+        std::fs::create_dir(root.path().join(".cargo")).unwrap();
+        std::fs::write(
+            root.path().join(".cargo").join("config.toml"),
+            format!("[build]\ntarget-dir = {:?}\n", shared.to_string_lossy()),
+        ).unwrap();
+        let rust = wrapper(&root.path().join("cache"));
+
+        let output = rust.execute("fn main() { println!(\"own target\"); }\n", &[]).await.unwrap();
+        assert_eq!(output.stdout.trim(), "own target");
+        assert!(!shared.exists());
+    }
+
+    #[tokio::test]
+    async fn test_disallowed_dependency_rejected() {
+        let cache = tempfile::tempdir().unwrap();
+        let rust = wrapper(cache.path()).with_allowed_dependencies(["serde_json"]);
+        let code = "//! [dependencies]\n//! reqwest = \"0.11\"\nfn main() {}\n";
+
+        let err = rust.execute(code, &[]).await.unwrap_err();
+        assert!(err.to_string().contains("reqwest"));
+    }
+
+    #[test]
+    fn test_embedded_manifest_parsed() {
+        let manifest = EmbeddedManifest::parse("//! [dependencies]\n//! serde_json = \"1\"\n//! rand = { version = \"0.8\" }\nfn main() {}\n").unwrap();
+        assert_eq!(manifest.dependency_names, vec!["serde_json", "rand"]);
+        assert!(manifest.dependencies.starts_with("[dependencies]\n"));
+    }
+
+    #[test]
+    fn test_manifest_tables_and_sources_outside_the_allowlist_rejected() {
+        let cache = tempfile::tempdir().unwrap();
+        let rust = wrapper(cache.path()).with_allowed_dependencies(["serde_json"]);
+
+        for (header, expected) in [
+            ("[dependencies.evil]\nversion = \"1\"", "Dependency not allowed: evil"),
+            ("[dependencies]\nserde_json = \"1\"\n[target.'cfg(unix)'.dependencies]\nevil = \"1\"", "not [target]"),
+            ("[build-dependencies]\nevil = \"1\"", "not [build-dependencies]"),
+            ("[patch.crates-io]\nserde_json = { path = \"/tmp/evil\" }", "not [patch]"),
+            ("[dependencies]\nserde_json = { path = \"/tmp/evil\" }", "may not set `path`"),
+            ("[dependencies.serde_json]\ngit = \"https://example.com/evil\"", "may not set `git`"),
+            ("[dependencies]\nserde_json = { package = \"evil\", version = \"1\" }", "may not set `package`"),
+        ] {
+            let code = format!("{}\nfn main() {{}}\n", header.lines().map(|l| format!("//! {}\n", l)).collect::<String>());
+            let err = rust.prepare_project(&code).unwrap_err();
+            assert!(err.to_string().contains(expected), "{}: {}", header, err);
+        }
+        assert_eq!(std::fs::read_dir(cache.path()).unwrap().count(), 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_cache_dir_is_private_to_the_user() {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode();
+
+        let base = tempfile::tempdir().unwrap();
+        let dir = private_cache_dir(base.path()).unwrap();
+        assert_eq!(dir, base.path().join("talkpp").join("rust"));
+        assert_eq!(mode(&dir) & 0o077, 0, "{:o}", mode(&dir));
+        assert_eq!(private_cache_dir(base.path()), Some(dir.clone()));
+
+        // A directory others can write to may already hold their binaries
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o777)).unwrap();
+        assert_eq!(private_cache_dir(base.path()), None);
+    }
+
+    #[test]
+    fn test_validate_reports_structured_diagnostics() {
+        let cache = tempfile::tempdir().unwrap();
+        let rust = wrapper(cache.path());
+
+        let err = rust.validate("fn main() {\n    let x: u32 = \"nope\";\n}\n").unwrap_err();
+        match err.downcast_ref::<WrapperError>() {
+            Some(WrapperError::CompileError { diagnostics }) => {
+                assert_eq!(diagnostics[0].line, Some(2));
+                assert!(diagnostics[0].column.is_some());
+                assert!(diagnostics[0].message.contains("mismatched types"));
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cache_eviction_keeps_latest_build() {
+        let cache = tempfile::tempdir().unwrap();
+        let rust = wrapper(cache.path()).with_max_cache_bytes(1);
+
+        rust.execute("fn main() { println!(\"one\"); }\n", &[]).await.unwrap();
+        rust.execute("fn main() { println!(\"two\"); }\n", &[]).await.unwrap();
+
+        assert_eq!(std::fs::read_dir(cache.path()).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,257 @@
+//! TypeScript language wrapper
+//!
+//! Sources are type-checked and transpiled with whichever of `tsc`, `esbuild`, or the
+//! `swc` CLI is installed, then executed through [`JavaScriptWrapper`].
+
+use crate::error::{Diagnostic, WrapperError};
+use crate::javascript::JavaScriptWrapper;
+use crate::{process, ExecutionOutput, ExecutionRequest, LanguageWrapper, ResourceLimits};
+use anyhow::Result;
+use async_trait::async_trait;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone)]
+enum Transpiler {
+    Tsc(PathBuf),
+    Esbuild(PathBuf),
+    Swc(PathBuf),
+}
+
+impl Transpiler {
+    fn detect() -> Option<Self> {
+        which::which("tsc").map(Self::Tsc)
+            .or_else(|_| which::which("esbuild").map(Self::Esbuild))
+            .or_else(|_| which::which("swc").map(Self::Swc))
+            .ok()
+    }
+
+    fn path(&self) -> &Path {
+        match self {
+            Self::Tsc(p) | Self::Esbuild(p) | Self::Swc(p) => p,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Tsc(_) => "tsc",
+            Self::Esbuild(_) => "esbuild",
+            Self::Swc(_) => "swc",
+        }
+    }
+}
+
+/// Transpiles TypeScript to JavaScript and runs it under Node.js
+pub struct TypeScriptWrapper {
+    transpiler: Transpiler,
+    javascript: JavaScriptWrapper,
+}
+
+impl TypeScriptWrapper {
+    pub fn new() -> Result<Self> {
+        let transpiler = Transpiler::detect()
+            .ok_or_else(|| WrapperError::InterpreterNotFound { name: "tsc, esbuild, or swc".to_string() })?;
+        Ok(Self {
+            transpiler,
+            javascript: JavaScriptWrapper::new()?,
+        })
+    }
+
+    pub fn with_limits(mut self, limits: ResourceLimits) -> Self {
+        self.javascript = self.javascript.with_limits(limits);
+        self
+    }
+
+    /// Transpile TypeScript source to CommonJS JavaScript
+    pub fn transpile(&self, code: &str) -> Result<String> {
+        let source = process::write_source(code, ".ts")?;
+        let out_dir = tempfile::tempdir()?;
+        let out_file = out_dir.path().join("out.js");
+
+        let mut command = std::process::Command::new(self.transpiler.path());
+        match &self.transpiler {
+            Transpiler::Tsc(_) => {
+                command
+                    .args(["--target", "es2020", "--module", "commonjs", "--noEmitOnError", "false", "--outDir"])
+                    .arg(out_dir.path())
+                    .arg(source.path());
+            }
+            Transpiler::Esbuild(_) => {
+                command
+                    .arg(source.path())
+                    .args(["--format=cjs", "--platform=node", "--target=es2020"])
+                    .arg(format!("--outfile={}", out_file.display()));
+            }
+            Transpiler::Swc(_) => {
+                command
+                    .arg("compile")
+                    .arg(source.path())
+                    .args(["--config", "module.type=commonjs", "--out-file"])
+                    .arg(&out_file);
+            }
+        }
+        let output = command.output()?;
+
+        // tsc names its output after the input file and exits non-zero on type errors
+        let emitted = match &self.transpiler {
+            Transpiler::Tsc(_) => std::fs::read_dir(out_dir.path())?
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .find(|p| p.extension().is_some_and(|ext| ext == "js")),
+            _ => Some(out_file).filter(|p| p.exists()),
+        };
+
+        match emitted {
+            Some(path) => Ok(std::fs::read_to_string(path)?),
+            None => Err(WrapperError::CompileError {
+                diagnostics: parse_diagnostics(&String::from_utf8_lossy(&output.stdout), &String::from_utf8_lossy(&output.stderr)),
+            }.into()),
+        }
+    }
+
+    fn check(&self, code: &str) -> Result<()> {
+        let source = process::write_source(code, ".ts")?;
+        let output = match &self.transpiler {
+            Transpiler::Tsc(tsc) => std::process::Command::new(tsc)
+                .args(["--noEmit", "--strict", "--target", "es2020", "--module", "commonjs"])
+                .arg(source.path())
+                .output()?,
+            // Without tsc we can only check that the source parses
+            _ => return self.transpile(code).map(|_| ()),
+        };
+
+        if output.status.success() {
+            return Ok(());
+        }
+
+        Err(WrapperError::CompileError {
+            diagnostics: parse_diagnostics(&String::from_utf8_lossy(&output.stdout), &String::from_utf8_lossy(&output.stderr)),
+        }.into())
+    }
+}
+
+#[async_trait]
+impl LanguageWrapper for TypeScriptWrapper {
+    async fn execute_with(&self, mut request: ExecutionRequest) -> Result<ExecutionOutput> {
+        self.check(&request.code)?;
+        request.code = self.transpile(&request.code)?;
+        self.javascript.execute_with(request).await
+    }
+
+    fn validate(&self, code: &str) -> Result<()> {
+        self.check(code)
+    }
+
+    fn version(&self) -> String {
+        let transpiler = std::process::Command::new(self.transpiler.path())
+            .arg("--version")
+            .output()
+            .ok()
+            .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+            .unwrap_or_else(|| "unknown".to_string());
+        format!("{} {}, {}", self.transpiler.name(), transpiler, self.javascript.version())
+    }
+}
+
+/// Parse `file.ts(2,7): error TS2322: ...` (tsc) and `file.ts:2:7: ERROR: ...` (esbuild/swc)
+fn parse_diagnostics(stdout: &str, stderr: &str) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = stdout
+        .lines()
+        .chain(stderr.lines())
+        .filter_map(|line| {
+            let line = line.trim();
+            if let Some((location, message)) = line.split_once("): error ") {
+                let (_, position) = location.rsplit_once('(')?;
+                let (row, col) = position.split_once(',')?;
+                return Some(Diagnostic {
+                    message: message.to_string(),
+                    line: row.parse().ok(),
+                    column: col.parse().ok(),
+                });
+            }
+
+            let (location, message) = line.split_once(": ERROR: ").or_else(|| line.split_once(": error: "))?;
+            let mut parts = location.rsplitn(3, ':');
+            let column = parts.next()?.parse().ok();
+            let row = parts.next()?.parse().ok();
+            Some(Diagnostic {
+                message: message.to_string(),
+                line: row,
+                column,
+            })
+        })
+        .collect();
+
+    if diagnostics.is_empty() {
+        diagnostics.push(Diagnostic {
+            message: format!("{}{}", stdout.trim(), stderr.trim()),
+            line: None,
+            column: None,
+        });
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wrapper() -> Option<TypeScriptWrapper> {
+        let wrapper = TypeScriptWrapper::new().ok();
+        if wrapper.is_none() {
+            eprintln!("skipping: no TypeScript transpiler installed");
+        }
+        wrapper
+    }
+
+    #[test]
+    fn test_parse_tsc_diagnostics() {
+        let diagnostics = parse_diagnostics(
+            "/tmp/a.ts(2,7): error TS2322: Type 'string' is not assignable to type 'number'.\n",
+            "",
+        );
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line, Some(2));
+        assert_eq!(diagnostics[0].column, Some(7));
+        assert!(diagnostics[0].message.starts_with("TS2322"));
+    }
+
+    #[test]
+    fn test_parse_esbuild_diagnostics() {
+        let diagnostics = parse_diagnostics("", "/tmp/a.ts:3:14: ERROR: Expected \";\" but found \"x\"\n");
+        assert_eq!(diagnostics[0].line, Some(3));
+        assert_eq!(diagnostics[0].column, Some(14));
+    }
+
+    #[tokio::test]
+    async fn test_interface_and_async_function() {
+        let Some(ts) = wrapper() else { return };
+        let code = r#"
+interface Greeting { name: string; excited: boolean }
+
+async function greet(g: Greeting): Promise<string> {
+    return `hello ${g.name}${g.excited ? "!" : ""}`;
+}
+
+greet({ name: "ts", excited: true }).then((msg: string) => console.log(msg));
+"#;
+
+        let output = ts.execute(code, &[]).await.unwrap();
+        assert_eq!(output.stdout.trim(), "hello ts!", "{}", output.stderr);
+    }
+
+    #[tokio::test]
+    async fn test_type_error_never_executes() {
+        let Some(ts) = wrapper() else { return };
+        if !matches!(ts.transpiler, Transpiler::Tsc(_)) {
+            return;
+        }
+        let code = "const count: number = \"three\";\nconsole.log('ran');\n";
+
+        assert!(ts.validate(code).is_err());
+        let err = ts.execute(code, &[]).await.unwrap_err();
+        match err.downcast_ref::<WrapperError>() {
+            Some(WrapperError::CompileError { diagnostics }) => assert_eq!(diagnostics[0].line, Some(1)),
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,257 @@
+//! Per-execution working directories
+//!
+//! Every execution runs in a fresh directory under a configured root, which is both its
+//! current directory and its `OUTPUT_DIR`. Files left there are the execution's outputs.
+//! The directory is removed when its [`ExecutionDir`] is dropped, so timeouts and panics
+//! clean up too; only [`ExecutionDir::finish`] can keep a failed execution's directory,
+//! and kept directories are swept once their retention has passed.
+
+use crate::error::WrapperError;
+use crate::ExecutionRequest;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+/// Environment variable naming the directory an execution should write its outputs to
+pub const OUTPUT_DIR_VAR: &str = "OUTPUT_DIR";
+
+/// Prefix of directories kept after a failed execution, followed by the unix time they
+/// may be removed after
+const RETAINED_PREFIX: &str = "failed-";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkdirConfig {
+    /// Directory the per-execution directories are created in
+    pub root: PathBuf,
+    /// Bytes an execution may leave in its directory
+    pub max_bytes: u64,
+    /// Keep the directories of failed executions this long, for debugging
+    pub retain_failed: Option<Duration>,
+}
+
+impl Default for WorkdirConfig {
+    fn default() -> Self {
+        Self {
+            root: std::env::temp_dir().join("talkpp-executions"),
+            max_bytes: 64 * 1024 * 1024,
+            retain_failed: None,
+        }
+    }
+}
+
+/// A file an execution left in its directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputFile {
+    /// Path within the directory, `/`-separated
+    pub name: String,
+    pub bytes: Vec<u8>,
+}
+
+/// The working directory of one execution, removed on drop
+pub struct ExecutionDir {
+    dir: tempfile::TempDir,
+    config: WorkdirConfig,
+}
+
+impl ExecutionDir {
+    /// Create a fresh directory under `config.root`, first sweeping retained directories
+    /// whose retention has passed
+    pub fn create(config: &WorkdirConfig) -> Result<Self> {
+        std::fs::create_dir_all(&config.root)?;
+        sweep_retained(&config.root)?;
+        let dir = tempfile::Builder::new().prefix("exec-").tempdir_in(&config.root)?;
+        Ok(Self { dir, config: config.clone() })
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// `request` run in this directory, with `OUTPUT_DIR` pointing at it
+    pub fn apply(&self, request: ExecutionRequest) -> ExecutionRequest {
+        request
+            .with_working_dir(self.path())
+            .with_env(OUTPUT_DIR_VAR, self.path().to_string_lossy())
+    }
+
+    /// Bytes in the directory's files. Symlinks count as nothing, whatever they point at.
+    pub fn usage(&self) -> std::io::Result<u64> {
+        let mut total = 0;
+        walk(self.path(), &mut |_, metadata| {
+            total += metadata.len();
+            Ok(())
+        })?;
+        Ok(total)
+    }
+
+    /// Fail with [`WrapperError::QuotaExceeded`] if the execution left more than the
+    /// configured bytes behind
+    pub fn check_quota(&self) -> Result<(), WrapperError> {
+        let used = self.usage()?;
+        if used > self.config.max_bytes {
+            return Err(WrapperError::QuotaExceeded { used, limit: self.config.max_bytes });
+        }
+        Ok(())
+    }
+
+    /// Every file in the directory, sorted by name. Symlinks are only read when they
+    /// resolve to a file inside the directory.
+    pub fn outputs(&self) -> Result<Vec<OutputFile>> {
+        let root = self.path().canonicalize()?;
+        let mut files = Vec::new();
+        walk(self.path(), &mut |path, _| {
+            files.push(OutputFile { name: relative_name(self.path(), path), bytes: std::fs::read(path)? });
+            Ok(())
+        })?;
+        for link in symlinks(self.path())? {
+            match link.canonicalize() {
+                Ok(target) if target.starts_with(&root) && target.is_file() => {
+                    files.push(OutputFile { name: relative_name(self.path(), &link), bytes: std::fs::read(&target)? });
+                }
+                _ => warn!("Not capturing {}, which links outside the execution directory", link.display()),
+            }
+        }
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(files)
+    }
+
+    /// Remove the directory, unless the execution failed and failed executions are
+    /// retained; then the directory is kept and its new path returned
+    pub fn finish(self, failed: bool) -> Result<Option<PathBuf>> {
+        let Some(retention) = self.config.retain_failed.filter(|_| failed) else {
+            self.dir.close()?;
+            return Ok(None);
+        };
+        let expires = (SystemTime::now() + retention).duration_since(UNIX_EPOCH)?.as_secs();
+        let name = self.path().file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let retained = self.config.root.join(format!("{}{}-{}", RETAINED_PREFIX, expires, name));
+        std::fs::rename(self.dir.keep(), &retained)?;
+        info!("Keeping the directory of a failed execution at {} for {:?}", retained.display(), retention);
+        Ok(Some(retained))
+    }
+}
+
+/// Remove retained directories under `root` whose retention has passed, returning how
+/// many were removed
+pub fn sweep_retained(root: &Path) -> Result<usize> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let mut removed = 0;
+    for entry in std::fs::read_dir(root)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let expires = name.strip_prefix(RETAINED_PREFIX)
+            .and_then(|rest| rest.split_once('-'))
+            .and_then(|(expires, _)| expires.parse::<u64>().ok());
+        if expires.is_some_and(|expires| expires <= now) {
+            std::fs::remove_dir_all(entry.path())?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// Call `visit` with every regular file under `dir`, without following symlinks
+fn walk(dir: &Path, visit: &mut dyn FnMut(&Path, &std::fs::Metadata) -> std::io::Result<()>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.path().symlink_metadata()?;
+        if metadata.is_dir() {
+            walk(&entry.path(), visit)?;
+        } else if metadata.is_file() {
+            visit(&entry.path(), &metadata)?;
+        }
+    }
+    Ok(())
+}
+
+/// Every symlink under `dir`, without following any
+fn symlinks(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut links = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let metadata = path.symlink_metadata()?;
+        if metadata.is_symlink() {
+            links.push(path);
+        } else if metadata.is_dir() {
+            links.extend(symlinks(&path)?);
+        }
+    }
+    Ok(links)
+}
+
+fn relative_name(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    relative.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(root: &Path) -> WorkdirConfig {
+        WorkdirConfig { root: root.to_path_buf(), max_bytes: 1024, retain_failed: None }
+    }
+
+    #[test]
+    fn test_outputs_and_quota() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = ExecutionDir::create(&config(root.path())).unwrap();
+        std::fs::create_dir(dir.path().join("reports")).unwrap();
+        std::fs::write(dir.path().join("reports/summary.md"), "# Done").unwrap();
+        std::fs::write(dir.path().join("config.json"), "{}").unwrap();
+
+        let names: Vec<String> = dir.outputs().unwrap().into_iter().map(|file| file.name).collect();
+        assert_eq!(names, vec!["config.json", "reports/summary.md"]);
+        assert!(dir.check_quota().is_ok());
+
+        std::fs::write(dir.path().join("big.bin"), vec![0u8; 2048]).unwrap();
+        match dir.check_quota() {
+            Err(WrapperError::QuotaExceeded { used, limit }) => assert_eq!((used, limit), (2056, 1024)),
+            other => panic!("expected a quota violation, got {:?}", other),
+        }
+
+        let path = dir.path().to_path_buf();
+        assert_eq!(dir.finish(false).unwrap(), None);
+        assert!(!path.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks_out_of_the_directory_are_not_followed() {
+        let root = tempfile::tempdir().unwrap();
+        let secret = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(secret.path(), "host secret").unwrap();
+        let dir = ExecutionDir::create(&config(root.path())).unwrap();
+        std::fs::write(dir.path().join("out.txt"), "result").unwrap();
+        std::os::unix::fs::symlink(secret.path(), dir.path().join("stolen.txt")).unwrap();
+        std::os::unix::fs::symlink("out.txt", dir.path().join("latest.txt")).unwrap();
+
+        let outputs = dir.outputs().unwrap();
+        let names: Vec<&str> = outputs.iter().map(|file| file.name.as_str()).collect();
+        assert_eq!(names, vec!["latest.txt", "out.txt"]);
+        assert!(outputs.iter().all(|file| file.bytes == b"result"));
+    }
+
+    #[test]
+    fn test_failed_directories_retained_until_swept() {
+        let root = tempfile::tempdir().unwrap();
+        let retaining = WorkdirConfig { retain_failed: Some(Duration::from_secs(3600)), ..config(root.path()) };
+
+        let succeeded = ExecutionDir::create(&retaining).unwrap();
+        assert_eq!(succeeded.finish(false).unwrap(), None);
+
+        let failed = ExecutionDir::create(&retaining).unwrap();
+        std::fs::write(failed.path().join("trace.log"), "boom").unwrap();
+        let retained = failed.finish(true).unwrap().expect("failed directory kept");
+        assert_eq!(std::fs::read_to_string(retained.join("trace.log")).unwrap(), "boom");
+
+        // Only directories whose retention has passed are swept
+        let expired = root.path().join(format!("{}0-exec-old", RETAINED_PREFIX));
+        std::fs::create_dir(&expired).unwrap();
+        ExecutionDir::create(&retaining).unwrap();
+        assert!(!expired.exists());
+        assert!(retained.exists());
+    }
+}